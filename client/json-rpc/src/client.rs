@@ -40,6 +40,17 @@ impl JsonRpcBatch {
         Ok(())
     }
 
+    /// Submits transactions of a single sender with consecutive sequence numbers, which are
+    /// accepted either all or none
+    pub fn add_submit_batch_request(&mut self, transactions: &[SignedTransaction]) -> Result<()> {
+        let txn_payloads: Vec<_> = transactions
+            .iter()
+            .map(|transaction| Ok(Value::String(hex::encode(lcs::to_bytes(transaction)?))))
+            .collect::<Result<_>>()?;
+        self.add_request("submit_batch".to_string(), vec![Value::Array(txn_payloads)]);
+        Ok(())
+    }
+
     pub fn add_get_account_state_request(&mut self, address: AccountAddress) {
         self.add_request(
             "get_account_state".to_string(),
@@ -47,6 +58,18 @@ impl JsonRpcBatch {
         );
     }
 
+    /// Requests the account state as of a past `version`, which must not have been pruned yet
+    pub fn add_get_account_state_at_version_request(
+        &mut self,
+        address: AccountAddress,
+        version: u64,
+    ) {
+        self.add_request(
+            "get_account_state".to_string(),
+            vec![Value::String(address.to_string()), json!(version)],
+        );
+    }
+
     pub fn add_get_metadata_request(&mut self, version: Option<u64>) {
         self.add_request("get_metadata".to_string(), vec![json!(version)]);
     }
@@ -90,6 +113,20 @@ impl JsonRpcBatch {
         );
     }
 
+    /// Requests the events emitted up to `version`
+    pub fn add_get_events_at_version_request(
+        &mut self,
+        event_key: String,
+        start: u64,
+        limit: u64,
+        version: u64,
+    ) {
+        self.add_request(
+            "get_events".to_string(),
+            vec![json!(event_key), json!(start), json!(limit), json!(version)],
+        );
+    }
+
     pub fn add_get_state_proof_request(&mut self, known_version: u64) {
         self.add_request("get_state_proof".to_string(), vec![json!(known_version)]);
     }
@@ -113,6 +150,32 @@ impl JsonRpcBatch {
     pub fn add_get_network_status_request(&mut self) {
         self.add_request("get_network_status".to_string(), vec![]);
     }
+
+    pub fn add_get_parked_transactions_request(&mut self, address: AccountAddress) {
+        self.add_request(
+            "get_parked_transactions".to_string(),
+            vec![json!(address.to_string())],
+        );
+    }
+
+    pub fn add_get_expired_transactions_request(&mut self, address: AccountAddress) {
+        self.add_request(
+            "get_expired_transactions".to_string(),
+            vec![json!(address.to_string())],
+        );
+    }
+
+    pub fn add_get_account_states_request(
+        &mut self,
+        limit: u64,
+        page_token: Option<String>,
+        version: Option<u64>,
+    ) {
+        self.add_request(
+            "get_account_states".to_string(),
+            vec![json!(limit), json!(page_token), json!(version)],
+        );
+    }
 }
 
 #[derive(Clone)]
@@ -0,0 +1,134 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers to locate and decode the events of the Libra framework.
+//!
+//! The payment event streams of an account are derived from its address, so their keys can be
+//! computed without fetching the account first. Raw event payloads, e.g., from a
+//! `ContractEvent` in a transaction with proof, can be decoded into the typed events mirroring
+//! their stdlib definitions.
+
+use anyhow::Result;
+use libra_types::{
+    account_address::AccountAddress,
+    account_config::{
+        BurnEvent, CancelBurnEvent, MintEvent, NewBlockEvent, NewEpochEvent, PreburnEvent,
+        ReceivedPaymentEvent, SentPaymentEvent, UpgradeEvent,
+    },
+    contract_event::ContractEvent,
+    event::EventKey,
+};
+use move_core_types::{language_storage::TypeTag, move_resource::MoveResource};
+use std::convert::TryFrom;
+
+/// Salts of the event handles created by `LibraAccount::make_account`, in creation order.
+const RECEIVED_PAYMENT_EVENTS_SALT: u64 = 0;
+const SENT_PAYMENT_EVENTS_SALT: u64 = 1;
+
+/// Returns the key of the `ReceivedPaymentEvent` stream of an account.
+///
+/// Only valid for accounts created through `LibraAccount`, which creates the event handles of
+/// an account before any other.
+pub fn received_payment_events_key(address: &AccountAddress) -> EventKey {
+    EventKey::new_from_address(address, RECEIVED_PAYMENT_EVENTS_SALT)
+}
+
+/// Returns the key of the `SentPaymentEvent` stream of an account. The same caveat as for
+/// `received_payment_events_key` applies.
+pub fn sent_payment_events_key(address: &AccountAddress) -> EventKey {
+    EventKey::new_from_address(address, SENT_PAYMENT_EVENTS_SALT)
+}
+
+/// An event of the Libra framework, decoded from its raw payload
+#[derive(Debug)]
+pub enum LibraEvent {
+    Burn(BurnEvent),
+    CancelBurn(CancelBurnEvent),
+    Mint(MintEvent),
+    NewBlock(NewBlockEvent),
+    NewEpoch(NewEpochEvent),
+    Preburn(PreburnEvent),
+    ReceivedPayment(ReceivedPaymentEvent),
+    SentPayment(SentPaymentEvent),
+    Upgrade(UpgradeEvent),
+    /// Event whose type is not defined by the framework
+    Unknown(TypeTag, Vec<u8>),
+}
+
+impl LibraEvent {
+    /// Decodes the LCS encoded payload of an event of the given type.
+    pub fn decode(type_tag: &TypeTag, event_data: &[u8]) -> Result<Self> {
+        let struct_tag = match type_tag {
+            TypeTag::Struct(struct_tag) => struct_tag,
+            _ => return Ok(LibraEvent::Unknown(type_tag.clone(), event_data.to_vec())),
+        };
+        let event = if *struct_tag == BurnEvent::struct_tag() {
+            LibraEvent::Burn(BurnEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == CancelBurnEvent::struct_tag() {
+            LibraEvent::CancelBurn(CancelBurnEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == MintEvent::struct_tag() {
+            LibraEvent::Mint(MintEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == NewBlockEvent::struct_tag() {
+            LibraEvent::NewBlock(NewBlockEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == NewEpochEvent::struct_tag() {
+            LibraEvent::NewEpoch(NewEpochEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == PreburnEvent::struct_tag() {
+            LibraEvent::Preburn(PreburnEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == ReceivedPaymentEvent::struct_tag() {
+            LibraEvent::ReceivedPayment(ReceivedPaymentEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == SentPaymentEvent::struct_tag() {
+            LibraEvent::SentPayment(SentPaymentEvent::try_from_bytes(event_data)?)
+        } else if *struct_tag == UpgradeEvent::struct_tag() {
+            LibraEvent::Upgrade(UpgradeEvent::try_from_bytes(event_data)?)
+        } else {
+            LibraEvent::Unknown(type_tag.clone(), event_data.to_vec())
+        };
+        Ok(event)
+    }
+}
+
+impl TryFrom<&ContractEvent> for LibraEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(event: &ContractEvent) -> Result<Self> {
+        Self::decode(event.type_tag(), event.event_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_core_types::identifier::Identifier;
+
+    #[test]
+    fn test_payment_event_keys() {
+        let address = AccountAddress::random();
+        let received = received_payment_events_key(&address);
+        let sent = sent_payment_events_key(&address);
+        assert_ne!(received, sent);
+        assert_eq!(received.get_creator_address(), address);
+        assert_eq!(sent.get_creator_address(), address);
+    }
+
+    #[test]
+    fn test_decode() {
+        let receiver = AccountAddress::random();
+        let sent = SentPaymentEvent::new(10, Identifier::new("LBR").unwrap(), receiver, vec![1]);
+        let type_tag = TypeTag::Struct(SentPaymentEvent::struct_tag());
+        match LibraEvent::decode(&type_tag, &lcs::to_bytes(&sent).unwrap()).unwrap() {
+            LibraEvent::SentPayment(event) => {
+                assert_eq!(event.amount(), 10);
+                assert_eq!(event.receiver(), receiver);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // payloads not matching their type are rejected
+        assert!(LibraEvent::decode(&type_tag, &[0u8; 3]).is_err());
+
+        match LibraEvent::decode(&TypeTag::U64, &[1, 2]).unwrap() {
+            LibraEvent::Unknown(TypeTag::U64, data) => assert_eq!(data, vec![1, 2]),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+}
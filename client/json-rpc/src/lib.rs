@@ -3,6 +3,7 @@
 
 mod blocking;
 mod client;
+pub mod events;
 mod response;
 
 pub use blocking::JsonRpcClient;
@@ -2,8 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::views::{
-    AccountStateWithProofView, AccountView, BlockMetadata, CurrencyInfoView, EventView,
-    StateProofView, TransactionView,
+    AccountStatePageView, AccountStateWithProofView, AccountView, BlockMetadata, CurrencyInfoView,
+    EventView, ExpiredTransactionView, ParkedTransactionView, StateProofView, TransactionView,
 };
 use anyhow::{ensure, format_err, Error, Result};
 
@@ -23,6 +23,9 @@ pub enum JsonRpcResponse {
     CurrenciesResponse(Vec<CurrencyInfoView>),
     AccountStateWithProofResponse(AccountStateWithProofView),
     NetworkStatusResponse(Number),
+    ParkedTransactionsResponse(Vec<ParkedTransactionView>),
+    ExpiredTransactionsResponse(Vec<ExpiredTransactionView>),
+    AccountStatesResponse(AccountStatePageView),
     UnknownResponse(Value),
 }
 
@@ -31,10 +34,11 @@ impl TryFrom<(String, Value)> for JsonRpcResponse {
 
     fn try_from((method, value): (String, Value)) -> Result<JsonRpcResponse> {
         match method.as_str() {
-            "submit" => {
+            "submit" | "submit_batch" => {
                 ensure!(
                     value == Value::Null,
-                    "received unexpected payload for submit: {}",
+                    "received unexpected payload for {}: {}",
+                    method,
                     value
                 );
                 Ok(JsonRpcResponse::SubmissionResponse)
@@ -91,6 +95,18 @@ impl TryFrom<(String, Value)> for JsonRpcResponse {
                     connected_peers_count,
                 ))
             }
+            "get_parked_transactions" => {
+                let txns: Vec<ParkedTransactionView> = serde_json::from_value(value)?;
+                Ok(JsonRpcResponse::ParkedTransactionsResponse(txns))
+            }
+            "get_expired_transactions" => {
+                let txns: Vec<ExpiredTransactionView> = serde_json::from_value(value)?;
+                Ok(JsonRpcResponse::ExpiredTransactionsResponse(txns))
+            }
+            "get_account_states" => {
+                let page: AccountStatePageView = serde_json::from_value(value)?;
+                Ok(JsonRpcResponse::AccountStatesResponse(page))
+            }
             _ => Ok(JsonRpcResponse::UnknownResponse(value)),
         }
     }
@@ -174,6 +190,26 @@ impl ResponseAsView for TransactionView {
     }
 }
 
+impl ResponseAsView for ParkedTransactionView {
+    fn vec_from_response(response: JsonRpcResponse) -> Result<Vec<Self>> {
+        if let JsonRpcResponse::ParkedTransactionsResponse(txns) = response {
+            Ok(txns)
+        } else {
+            Self::unexpected_response_error::<Vec<Self>>(response)
+        }
+    }
+}
+
+impl ResponseAsView for ExpiredTransactionView {
+    fn vec_from_response(response: JsonRpcResponse) -> Result<Vec<Self>> {
+        if let JsonRpcResponse::ExpiredTransactionsResponse(txns) = response {
+            Ok(txns)
+        } else {
+            Self::unexpected_response_error::<Vec<Self>>(response)
+        }
+    }
+}
+
 impl ResponseAsView for StateProofView {
     fn from_response(response: JsonRpcResponse) -> Result<Self> {
         if let JsonRpcResponse::StateProofResponse(view) = response {
@@ -193,3 +229,13 @@ impl ResponseAsView for AccountStateWithProofView {
         }
     }
 }
+
+impl ResponseAsView for AccountStatePageView {
+    fn from_response(response: JsonRpcResponse) -> Result<Self> {
+        if let JsonRpcResponse::AccountStatesResponse(page) = response {
+            Ok(page)
+        } else {
+            Self::unexpected_response_error::<Self>(response)
+        }
+    }
+}
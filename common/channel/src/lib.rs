@@ -4,7 +4,8 @@
 #![forbid(unsafe_code)]
 
 //! Provides an mpsc (multi-producer single-consumer) channel wrapped in an
-//! [`IntGauge`](libra_metrics::IntGauge)
+//! [`IntGauge`](libra_metrics::IntGauge). Channels which do not need to be
+//! instrumented can be created without a gauge with [`new_unmetered`].
 
 use futures::{
     channel::mpsc,
@@ -49,10 +50,10 @@ impl<T> WithEntryTimestamp<T> {
     }
 }
 
-/// Similar to `mpsc::Sender`, but with an `IntGauge`
+/// Similar to `mpsc::Sender`, but with an optional `IntGauge`
 pub struct Sender<T> {
     inner: mpsc::Sender<WithEntryTimestamp<T>>,
-    gauge: IntGauge,
+    gauge: Option<IntGauge>,
 }
 
 impl<T> Clone for Sender<T> {
@@ -64,10 +65,10 @@ impl<T> Clone for Sender<T> {
     }
 }
 
-/// Similar to `mpsc::Receiver`, but with an `IntGauge`
+/// Similar to `mpsc::Receiver`, but with an optional `IntGauge`
 pub struct Receiver<T> {
     inner: mpsc::Receiver<WithEntryTimestamp<T>>,
-    gauge: IntGauge,
+    gauge: Option<IntGauge>,
     timeout: Duration,
 }
 
@@ -81,12 +82,12 @@ impl<T> Sink<T> for Sender<T> {
     }
 
     fn start_send(mut self: Pin<&mut Self>, msg: T) -> Result<(), Self::Error> {
-        self.gauge.inc();
+        self.inc_gauge();
         (*self)
             .inner
             .start_send(WithEntryTimestamp::new(msg))
             .map_err(|e| {
-                self.gauge.dec();
+                self.dec_gauge();
                 e
             })?;
         Ok(())
@@ -103,15 +104,27 @@ impl<T> Sink<T> for Sender<T> {
 
 impl<T> Sender<T> {
     pub fn try_send(&mut self, msg: T) -> Result<(), mpsc::SendError> {
-        self.gauge.inc();
+        self.inc_gauge();
         (*self)
             .inner
             .try_send(WithEntryTimestamp::new(msg))
             .map_err(|e| {
-                self.gauge.dec();
+                self.dec_gauge();
                 e.into_send_error()
             })
     }
+
+    fn inc_gauge(&self) {
+        if let Some(gauge) = &self.gauge {
+            gauge.inc();
+        }
+    }
+
+    fn dec_gauge(&self) {
+        if let Some(gauge) = &self.gauge {
+            gauge.dec();
+        }
+    }
 }
 
 impl<T> FusedStream for Receiver<T>
@@ -135,7 +148,9 @@ where
         loop {
             match Pin::new(&mut self.inner).poll_next(cx) {
                 Poll::Ready(Some(msg)) => {
-                    self.gauge.dec();
+                    if let Some(gauge) = &self.gauge {
+                        gauge.dec();
+                    }
                     // If the message times out, it gets dropped
                     if Instant::now().duration_since(msg.entry_time) > self.timeout {
                         warn!("Message dropped due to timeout: {:?}", msg.value);
@@ -166,6 +181,20 @@ pub fn new_with_timeout<T>(
     timeout: Duration,
 ) -> (Sender<T>, Receiver<T>) {
     gauge.set(0);
+    new_with_optional_gauge(size, Some(gauge.clone()), timeout)
+}
+
+/// Same as `new`, but without any metrics attached to the channel. This is meant for
+/// short-lived or internal channels where keeping a gauge up to date isn't worth it.
+pub fn new_unmetered<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    new_with_optional_gauge(size, None, MAX_TIMEOUT)
+}
+
+fn new_with_optional_gauge<T>(
+    size: usize,
+    gauge: Option<IntGauge>,
+    timeout: Duration,
+) -> (Sender<T>, Receiver<T>) {
     let (sender, receiver) = mpsc::channel(size);
     (
         Sender {
@@ -174,7 +203,7 @@ pub fn new_with_timeout<T>(
         },
         Receiver {
             inner: receiver,
-            gauge: gauge.clone(),
+            gauge,
             timeout,
         },
     )
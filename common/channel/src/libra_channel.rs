@@ -6,7 +6,11 @@
 //! What makes it different from existing mpsc channels is that we have full control
 //! over how the internal queueing in the channel happens and how we schedule messages
 //! to be sent out from this channel.
-//! Internally, it uses the `PerKeyQueue` to store messages
+//! Internally, it uses the `PerKeyQueue` to store messages.
+//! Besides bounding the number of messages per key, a channel created with
+//! `new_with_byte_limit` also bounds the total size of the messages per key, a channel
+//! created with `new_with_priorities` delivers the messages of its higher priority keys first,
+//! and `select_all` merges several receivers into one stream that can be used in `select!`.
 use crate::message_queues::{PerKeyQueue, QueueStyle};
 use anyhow::{ensure, Result};
 use futures::{
@@ -21,11 +25,11 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
+    time::Instant,
 };
 
 /// SharedState is a data structure private to this module which is
 /// shared by the sender and receiver.
-#[derive(Debug)]
 struct SharedState<K: Eq + Hash + Clone, M> {
     /// The internal queue of messages in this Channel
     internal_queue: PerKeyQueue<K, (M, Option<oneshot::Sender<ElementStatus<M>>>)>,
@@ -35,6 +39,9 @@ struct SharedState<K: Eq + Hash + Clone, M> {
     /// the Receiver task to process the next item.
     waker: Option<Waker>,
 
+    /// Computes the size of a message when the channel is bounded by bytes
+    size_fn: Option<fn(&M) -> usize>,
+
     /// A boolean which tracks whether the receiver has dropped
     receiver_dropped: bool,
     /// A boolean which tracks whether the stream has terminated
@@ -43,6 +50,18 @@ struct SharedState<K: Eq + Hash + Clone, M> {
     stream_terminated: bool,
 }
 
+impl<K: Eq + Hash + Clone, M> Debug for SharedState<K, M> {
+    fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
+        f.debug_struct("SharedState")
+            .field("internal_queue", &self.internal_queue)
+            .field("bounded_by_bytes", &self.size_fn.is_some())
+            .field("waker", &self.waker)
+            .field("receiver_dropped", &self.receiver_dropped)
+            .field("stream_terminated", &self.stream_terminated)
+            .finish()
+    }
+}
+
 /// The sending end of the libra_channel.
 #[derive(Debug)]
 pub struct Sender<K: Eq + Hash + Clone, M> {
@@ -83,6 +102,12 @@ impl<K: Eq + Hash + Clone, M> Sender<K, M> {
         self.push_with_feedback(key, message, None)
     }
 
+    /// Same as `push`, but returns the number of messages dropped because the queue of `key` was
+    /// full, which may include `message` itself depending on the queue style.
+    pub fn push_counting_drops(&mut self, key: K, message: M) -> Result<usize> {
+        self.push_inner(key, message, None, None)
+    }
+
     /// Same as `push`, but this function also accepts a oneshot::Sender over which the sender can
     /// be notified when the message eventually gets delivered or dropped.
     pub fn push_with_feedback(
@@ -91,19 +116,47 @@ impl<K: Eq + Hash + Clone, M> Sender<K, M> {
         message: M,
         status_ch: Option<oneshot::Sender<ElementStatus<M>>>,
     ) -> Result<()> {
+        self.push_inner(key, message, status_ch, None).map(|_| ())
+    }
+
+    /// Same as `push`, but if the channel was created with `QueueStyle::DEADLINE`, the message
+    /// is dropped instead of being delivered if it is still queued once `deadline` has passed.
+    pub fn push_with_deadline(&mut self, key: K, message: M, deadline: Instant) -> Result<()> {
+        self.push_inner(key, message, None, Some(deadline))
+            .map(|_| ())
+    }
+
+    fn push_inner(
+        &mut self,
+        key: K,
+        message: M,
+        status_ch: Option<oneshot::Sender<ElementStatus<M>>>,
+        deadline: Option<Instant>,
+    ) -> Result<usize> {
         let mut shared_state = self.shared_state.lock().unwrap();
         ensure!(!shared_state.receiver_dropped, "Channel is closed");
-        let dropped = shared_state.internal_queue.push(key, (message, status_ch));
-        // If this or an existing message had to be dropped because of the queue being full, we
-        // notify the corresponding status channel if it was registered.
-        if let Some((dropped_val, Some(dropped_status_ch))) = dropped {
-            // Ignore errors.
-            let _err = dropped_status_ch.send(ElementStatus::Dropped(dropped_val));
-        }
+        let size = shared_state.size_fn.map_or(0, |size_fn| size_fn(&message));
+        let dropped =
+            shared_state
+                .internal_queue
+                .push_entry(key, (message, status_ch), size, deadline);
+        // If this or existing messages had to be dropped because of the queue being full, we
+        // notify the corresponding status channels if they were registered.
+        let num_dropped = dropped.len();
+        notify_dropped(dropped);
         if let Some(w) = shared_state.waker.take() {
             w.wake();
         }
-        Ok(())
+        Ok(num_dropped)
+    }
+}
+
+fn notify_dropped<M>(dropped: Vec<(M, Option<oneshot::Sender<ElementStatus<M>>>)>) {
+    for (dropped_val, dropped_status_ch) in dropped {
+        if let Some(dropped_status_ch) = dropped_status_ch {
+            // Ignore errors.
+            let _err = dropped_status_ch.send(ElementStatus::Dropped(dropped_val));
+        }
     }
 }
 
@@ -152,7 +205,9 @@ impl<K: Eq + Hash + Clone, M> Stream for Receiver<K, M> {
     /// it sets the waker passed to it by the scheduler/executor and returns Pending
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut shared_state = self.shared_state.lock().unwrap();
-        if let Some((val, status_ch)) = shared_state.internal_queue.pop() {
+        let (next, expired) = shared_state.internal_queue.pop_with_expired();
+        notify_dropped(expired);
+        if let Some((val, status_ch)) = next {
             if let Some(status_ch) = status_ch {
                 let _err = status_ch.send(ElementStatus::Dequeued);
             }
@@ -175,14 +230,121 @@ impl<K: Eq + Hash + Clone, M> FusedStream for Receiver<K, M> {
     }
 }
 
+/// A stream which yields the messages of several libra_channel receivers. The receivers are
+/// polled in a round-robin fashion, so that a busy receiver cannot starve the others. The
+/// stream terminates once all the underlying receivers have terminated.
+pub struct SelectAll<K: Eq + Hash + Clone, M> {
+    receivers: Vec<Receiver<K, M>>,
+    /// Index of the receiver to poll first on the next call to poll_next
+    next: usize,
+}
+
+impl<K: Eq + Hash + Clone, M> Stream for SelectAll<K, M> {
+    type Item = M;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let num_receivers = self.receivers.len();
+        let mut all_terminated = true;
+        for offset in 0..num_receivers {
+            let idx = (self.next + offset) % num_receivers;
+            let receiver = &mut self.receivers[idx];
+            if receiver.is_terminated() {
+                continue;
+            }
+            match Pin::new(receiver).poll_next(cx) {
+                Poll::Ready(Some(msg)) => {
+                    self.next = (idx + 1) % num_receivers;
+                    return Poll::Ready(Some(msg));
+                }
+                Poll::Ready(None) => {}
+                Poll::Pending => all_terminated = false,
+            }
+        }
+        if all_terminated {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, M> FusedStream for SelectAll<K, M> {
+    fn is_terminated(&self) -> bool {
+        self.receivers.iter().all(FusedStream::is_terminated)
+    }
+}
+
+/// Merges the given receivers into a single `SelectAll` stream.
+pub fn select_all<K: Eq + Hash + Clone, M>(
+    receivers: impl IntoIterator<Item = Receiver<K, M>>,
+) -> SelectAll<K, M> {
+    SelectAll {
+        receivers: receivers.into_iter().collect(),
+        next: 0,
+    }
+}
+
 /// Create a new Libra Channel and returns the two ends of the channel.
 pub fn new<K: Eq + Hash + Clone, M>(
     queue_style: QueueStyle,
     max_queue_size_per_key: NonZeroUsize,
     counters: Option<&'static IntCounterVec>,
+) -> (Sender<K, M>, Receiver<K, M>) {
+    new_with_queue(
+        PerKeyQueue::new(queue_style, max_queue_size_per_key, counters),
+        None,
+    )
+}
+
+/// Create a new Libra Channel which, in addition to the number of messages, bounds the total
+/// size of the messages queued per key to `max_bytes_per_key`. The size of each message is
+/// computed with `size_fn` when it is pushed.
+pub fn new_with_byte_limit<K: Eq + Hash + Clone, M>(
+    queue_style: QueueStyle,
+    max_queue_size_per_key: NonZeroUsize,
+    max_bytes_per_key: NonZeroUsize,
+    size_fn: fn(&M) -> usize,
+    counters: Option<&'static IntCounterVec>,
+) -> (Sender<K, M>, Receiver<K, M>) {
+    new_with_queue(
+        PerKeyQueue::new_with_byte_limit(
+            queue_style,
+            max_queue_size_per_key,
+            max_bytes_per_key,
+            counters,
+        ),
+        Some(size_fn),
+    )
+}
+
+/// Create a new Libra Channel whose messages are delivered by decreasing priority of their key,
+/// as computed by `key_priority`: the keys of a same priority are served in a round-robin
+/// fashion, and the messages of a key are only delivered once no key of higher priority has
+/// pending messages.
+pub fn new_with_priorities<K: Eq + Hash + Clone, M>(
+    queue_style: QueueStyle,
+    max_queue_size_per_key: NonZeroUsize,
+    key_priority: impl Fn(&K) -> u8 + Send + 'static,
+    counters: Option<&'static IntCounterVec>,
+) -> (Sender<K, M>, Receiver<K, M>) {
+    new_with_queue(
+        PerKeyQueue::new_with_priorities(
+            queue_style,
+            max_queue_size_per_key,
+            Box::new(key_priority),
+            counters,
+        ),
+        None,
+    )
+}
+
+fn new_with_queue<K: Eq + Hash + Clone, M>(
+    internal_queue: PerKeyQueue<K, (M, Option<oneshot::Sender<ElementStatus<M>>>)>,
+    size_fn: Option<fn(&M) -> usize>,
 ) -> (Sender<K, M>, Receiver<K, M>) {
     let shared_state = Arc::new(Mutex::new(SharedState {
-        internal_queue: PerKeyQueue::new(queue_style, max_queue_size_per_key, counters),
+        internal_queue,
+        size_fn,
         waker: None,
         receiver_dropped: false,
         stream_terminated: false,
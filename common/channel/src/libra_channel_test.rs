@@ -9,7 +9,10 @@ use futures::{
     stream::{FusedStream, StreamExt},
 };
 use libra_types::account_address::AccountAddress;
-use std::{num::NonZeroUsize, time::Duration};
+use std::{
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
 use tokio::{runtime::Runtime, time::delay_for};
 
 #[test]
@@ -32,6 +35,22 @@ fn test_send_recv_order() {
     block_on(task);
 }
 
+#[test]
+fn test_push_counting_drops() {
+    let (mut sender, mut receiver) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(2).unwrap(), None);
+    assert_eq!(sender.push_counting_drops(0, 0).unwrap(), 0);
+    assert_eq!(sender.push_counting_drops(0, 1).unwrap(), 0);
+    // The queue of the key is full, so that the newest message is dropped.
+    assert_eq!(sender.push_counting_drops(0, 2).unwrap(), 1);
+    assert_eq!(sender.push_counting_drops(1, 3).unwrap(), 0);
+    block_on(async move {
+        assert_eq!(receiver.select_next_some().await, 0);
+        assert_eq!(receiver.select_next_some().await, 3);
+        assert_eq!(receiver.select_next_some().await, 1);
+    });
+}
+
 #[test]
 fn test_empty() {
     let (_, mut receiver) =
@@ -157,3 +176,126 @@ fn test_feedback_on_drop() {
     };
     block_on(task);
 }
+
+#[test]
+fn test_byte_limit_lifo() {
+    let (mut sender, mut receiver) = libra_channel::new_with_byte_limit(
+        QueueStyle::LIFO,
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        Vec::len,
+        None,
+    );
+    sender.push(0, vec![1; 3]).unwrap();
+    sender.push(0, vec![2; 3]).unwrap();
+    // Evicts the oldest message to stay within 8 bytes
+    let (status_tx, status_rx) = oneshot::channel();
+    sender
+        .push_with_feedback(0, vec![3; 3], Some(status_tx))
+        .unwrap();
+    // A message larger than the limit can never be queued
+    sender.push(0, vec![4; 9]).unwrap();
+    sender.push(0, vec![5; 5]).unwrap();
+    let task = async move {
+        assert_eq!(receiver.select_next_some().await, vec![5; 5]);
+        assert_eq!(receiver.select_next_some().await, vec![3; 3]);
+        assert_eq!(ElementStatus::Dequeued, status_rx.await.unwrap());
+        assert_eq!(receiver.select_next_some().now_or_never(), None);
+    };
+    block_on(task);
+}
+
+#[test]
+fn test_byte_limit_fifo() {
+    let (mut sender, mut receiver) = libra_channel::new_with_byte_limit(
+        QueueStyle::FIFO,
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        Vec::len,
+        None,
+    );
+    sender.push(0, vec![1; 5]).unwrap();
+    let (status_tx, status_rx) = oneshot::channel();
+    sender
+        .push_with_feedback(0, vec![2; 5], Some(status_tx))
+        .unwrap();
+    // Keys are bounded independently
+    sender.push(1, vec![3; 5]).unwrap();
+    let task = async move {
+        assert_eq!(ElementStatus::Dropped(vec![2; 5]), status_rx.await.unwrap());
+        assert_eq!(receiver.select_next_some().await, vec![1; 5]);
+        assert_eq!(receiver.select_next_some().await, vec![3; 5]);
+        assert_eq!(receiver.select_next_some().now_or_never(), None);
+    };
+    block_on(task);
+}
+
+#[test]
+fn test_select_all() {
+    let (mut sender_a, receiver_a) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(10).unwrap(), None);
+    let (mut sender_b, receiver_b) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(10).unwrap(), None);
+    let mut receiver = libra_channel::select_all(vec![receiver_a, receiver_b]);
+    sender_a.push(0, 'a').unwrap();
+    sender_a.push(0, 'b').unwrap();
+    sender_b.push(0, 'x').unwrap();
+    sender_b.push(0, 'y').unwrap();
+    let task = async move {
+        // Receivers are polled in a round-robin fashion
+        assert_eq!(receiver.select_next_some().await, 'a');
+        assert_eq!(receiver.select_next_some().await, 'x');
+        assert_eq!(receiver.select_next_some().await, 'b');
+        assert_eq!(receiver.select_next_some().await, 'y');
+        assert_eq!(receiver.select_next_some().now_or_never(), None);
+        // The combined stream only terminates once every sender is dropped
+        drop(sender_a);
+        assert_eq!(receiver.next().now_or_never(), None);
+        assert!(!receiver.is_terminated());
+        drop(sender_b);
+        assert_eq!(receiver.next().await, None);
+        assert!(receiver.is_terminated());
+    };
+    block_on(task);
+}
+
+#[test]
+fn test_deadline() {
+    let (mut sender, mut receiver) =
+        libra_channel::new(QueueStyle::DEADLINE, NonZeroUsize::new(10).unwrap(), None);
+    let now = Instant::now();
+    sender
+        .push_with_deadline(0, 'a', now - Duration::from_secs(1))
+        .unwrap();
+    sender
+        .push_with_deadline(0, 'b', now + Duration::from_secs(60))
+        .unwrap();
+    sender.push(0, 'c').unwrap();
+    let task = async move {
+        // 'a' expired before it was dequeued
+        assert_eq!(receiver.select_next_some().await, 'b');
+        assert_eq!(receiver.select_next_some().await, 'c');
+        assert_eq!(receiver.select_next_some().now_or_never(), None);
+    };
+    block_on(task);
+}
+
+#[test]
+fn test_priorities() {
+    let (mut sender, mut receiver) = libra_channel::new_with_priorities(
+        QueueStyle::FIFO,
+        NonZeroUsize::new(10).unwrap(),
+        |key: &char| if *key == 'h' { 1 } else { 0 },
+        None,
+    );
+    sender.push('l', 1).unwrap();
+    sender.push('l', 2).unwrap();
+    sender.push('h', 3).unwrap();
+    let task = async move {
+        assert_eq!(receiver.select_next_some().await, 3);
+        assert_eq!(receiver.select_next_some().await, 1);
+        assert_eq!(receiver.select_next_some().await, 2);
+        assert_eq!(receiver.select_next_some().now_or_never(), None);
+    };
+    block_on(task);
+}
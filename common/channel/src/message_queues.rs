@@ -3,10 +3,11 @@
 
 use libra_metrics::IntCounterVec;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     fmt::{Debug, Formatter, Result},
     hash::Hash,
     num::NonZeroUsize,
+    time::Instant,
 };
 
 /// QueueStyle is an enum which can be used as a configuration option for
@@ -15,11 +16,14 @@ use std::{
 /// With LIFO, oldest messages are dropped.
 /// With FIFO, newest messages are dropped.
 /// With KLAST, oldest messages are dropped, but remaining are retrieved in FIFO order
+/// With DEADLINE, newest messages are dropped like FIFO, and messages whose deadline
+/// has already passed by the time they are dequeued are dropped instead of being returned
 #[derive(Clone, Copy, Debug)]
 pub enum QueueStyle {
     LIFO,
     FIFO,
     KLAST,
+    DEADLINE,
 }
 
 /// PerKeyQueue maintains a queue of messages per key. It
@@ -30,23 +34,70 @@ pub enum QueueStyle {
 /// of the key's queue and returned. This happens in a round-robin
 /// fashion among keys.
 /// If there are no messages, in any of the queues, `None` is returned.
+/// Optionally, each key's queue can also be bounded by the total size in bytes
+/// of the messages it holds, as reported by the caller on `push_sized`.
+/// Optionally, keys can also be given a priority, in which case `pop` only
+/// round-robins among the keys of the highest priority with pending messages.
 pub(crate) struct PerKeyQueue<K: Eq + Hash + Clone, T> {
     /// QueueStyle for the messages stored per key
     queue_style: QueueStyle,
     /// per_key_queue maintains a map from a Key to a queue
     /// of all the messages from that Key. A Key is
     /// represented by AccountAddress
-    per_key_queue: HashMap<K, VecDeque<T>>,
-    /// This is a (round-robin)queue of Keys which have pending messages
-    /// This queue will be used for performing round robin among
-    /// Keys for choosing the next message
-    round_robin_queue: VecDeque<K>,
+    per_key_queue: HashMap<K, KeyQueue<T>>,
+    /// This is a (round-robin)queue of Keys which have pending messages, per priority
+    /// These queues will be used for performing round robin among
+    /// Keys of the highest priority for choosing the next message
+    round_robin_queues: BTreeMap<u8, VecDeque<K>>,
+    /// Computes the priority of a key, all keys having priority 0 if None
+    key_priority: Option<Box<dyn Fn(&K) -> u8 + Send>>,
     /// Maximum number of messages to store per key
     max_queue_size: NonZeroUsize,
+    /// Maximum number of bytes to store per key, if the queue is also bounded by size
+    max_bytes: Option<NonZeroUsize>,
     counters: Option<&'static IntCounterVec>,
 }
 
-// TODO potentially add `per_key_queue` and `round_robin_queue`
+/// A message along with the bookkeeping needed to enforce the queue's limits.
+struct Entry<T> {
+    message: T,
+    size: usize,
+    deadline: Option<Instant>,
+}
+
+/// The queue of a single key along with the number of bytes it currently holds.
+struct KeyQueue<T> {
+    messages: VecDeque<Entry<T>>,
+    bytes: usize,
+}
+
+impl<T> KeyQueue<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::with_capacity(capacity),
+            bytes: 0,
+        }
+    }
+
+    fn push_back(&mut self, entry: Entry<T>) {
+        self.bytes += entry.size;
+        self.messages.push_back(entry);
+    }
+
+    fn pop_front(&mut self) -> Option<Entry<T>> {
+        let entry = self.messages.pop_front()?;
+        self.bytes -= entry.size;
+        Some(entry)
+    }
+
+    fn pop_back(&mut self) -> Option<Entry<T>> {
+        let entry = self.messages.pop_back()?;
+        self.bytes -= entry.size;
+        Some(entry)
+    }
+}
+
+// TODO potentially add `per_key_queue` and `round_robin_queues`
 impl<K: Eq + Hash + Clone, T> Debug for PerKeyQueue<K, T> {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(
@@ -54,8 +105,9 @@ impl<K: Eq + Hash + Clone, T> Debug for PerKeyQueue<K, T> {
             "PerKeyQueue {{\n\
              queue_style: {:?},\n\
              max_queue_size: {},\n\
+             max_bytes: {:?},\n\
              }}",
-            self.queue_style, self.max_queue_size
+            self.queue_style, self.max_queue_size, self.max_bytes
         )
     }
 }
@@ -71,23 +123,60 @@ impl<K: Eq + Hash + Clone, T> PerKeyQueue<K, T> {
         Self {
             queue_style,
             max_queue_size: max_queue_size_per_key,
+            max_bytes: None,
             per_key_queue: HashMap::new(),
-            round_robin_queue: VecDeque::new(),
+            round_robin_queues: BTreeMap::new(),
+            key_priority: None,
             counters,
         }
     }
 
+    /// Create a new PerKeyQueue which serves the keys of higher `key_priority` first:
+    /// the messages of lower priority keys are only popped once no higher priority
+    /// key has pending messages
+    pub(crate) fn new_with_priorities(
+        queue_style: QueueStyle,
+        max_queue_size_per_key: NonZeroUsize,
+        key_priority: Box<dyn Fn(&K) -> u8 + Send>,
+        counters: Option<&'static IntCounterVec>,
+    ) -> Self {
+        Self {
+            key_priority: Some(key_priority),
+            ..Self::new(queue_style, max_queue_size_per_key, counters)
+        }
+    }
+
+    fn priority(&self, key: &K) -> u8 {
+        self.key_priority
+            .as_ref()
+            .map_or(0, |priority| priority(key))
+    }
+
+    /// Create a new PerKeyQueue which, in addition to max_queue_size_per_key,
+    /// bounds the total size of the messages stored per key to max_bytes_per_key
+    pub(crate) fn new_with_byte_limit(
+        queue_style: QueueStyle,
+        max_queue_size_per_key: NonZeroUsize,
+        max_bytes_per_key: NonZeroUsize,
+        counters: Option<&'static IntCounterVec>,
+    ) -> Self {
+        Self {
+            max_bytes: Some(max_bytes_per_key),
+            ..Self::new(queue_style, max_queue_size_per_key, counters)
+        }
+    }
+
     /// Given a key, pops the message from its queue and returns the message
     /// It also returns a boolean indicating whether the keys queue is empty
     /// after popping the message
-    fn pop_from_key_queue(&mut self, key: &K) -> (Option<T>, bool) {
+    fn pop_from_key_queue(&mut self, key: &K) -> (Option<Entry<T>>, bool) {
         if let Some(q) = self.per_key_queue.get_mut(key) {
             // Extract message from the key's queue
             let retval = match self.queue_style {
-                QueueStyle::FIFO | QueueStyle::KLAST => q.pop_front(),
+                QueueStyle::FIFO | QueueStyle::KLAST | QueueStyle::DEADLINE => q.pop_front(),
                 QueueStyle::LIFO => q.pop_back(),
             };
-            (retval, q.is_empty())
+            (retval, q.messages.is_empty())
         } else {
             (None, true)
         }
@@ -97,63 +186,144 @@ impl<K: Eq + Hash + Clone, T> PerKeyQueue<K, T> {
     /// add the key to round_robin_queue if it didnt already exist.
     /// Returns Some(T) if the new or an existing element was dropped. Returns None otherwise.
     pub(crate) fn push(&mut self, key: K, message: T) -> Option<T> {
+        // A message of size 0 never trips the byte limit, so at most one message is dropped.
+        self.push_sized(key, message, 0).pop()
+    }
+
+    /// Same as `push`, but the message accounts for `size` bytes against the byte limit of
+    /// the key's queue, if there is one. Since a large message may evict several smaller ones,
+    /// all the dropped messages are returned.
+    pub(crate) fn push_sized(&mut self, key: K, message: T, size: usize) -> Vec<T> {
+        self.push_entry(key, message, size, None)
+    }
+
+    /// Same as `push_sized`, but with DEADLINE, the message is dropped rather than returned
+    /// by `pop` once `deadline` has passed. The deadline is ignored for other queue styles.
+    pub(crate) fn push_entry(
+        &mut self,
+        key: K,
+        message: T,
+        size: usize,
+        deadline: Option<Instant>,
+    ) -> Vec<T> {
         if let Some(c) = self.counters.as_ref() {
             c.with_label_values(&["enqueued"]).inc();
         }
         let max_queue_size = self.max_queue_size.get();
+        let max_bytes = self.max_bytes.map(NonZeroUsize::get);
         let key_message_queue = self
             .per_key_queue
             .entry(key.clone())
-            .or_insert_with(|| VecDeque::with_capacity(max_queue_size));
-        // Add the key to our round-robin queue if it's not already there
-        if key_message_queue.is_empty() {
-            self.round_robin_queue.push_back(key);
-        }
-        // Push the message to the actual key message queue
-        if key_message_queue.len() == max_queue_size {
-            if let Some(c) = self.counters.as_ref() {
-                c.with_label_values(&["dropped"]).inc();
+            .or_insert_with(|| KeyQueue::with_capacity(max_queue_size));
+        let was_empty = key_message_queue.messages.is_empty();
+        let mut dropped = Vec::new();
+
+        let fits_in_bytes = |queue: &KeyQueue<T>| match max_bytes {
+            Some(max_bytes) => queue.bytes + size <= max_bytes,
+            None => true,
+        };
+        let oversized = max_bytes.map_or(false, |max_bytes| size > max_bytes);
+        let is_full =
+            key_message_queue.messages.len() == max_queue_size || !fits_in_bytes(key_message_queue);
+        let drops_newest = matches!(self.queue_style, QueueStyle::FIFO | QueueStyle::DEADLINE);
+        if oversized || (is_full && drops_newest) {
+            // Drop the newest message for FIFO, or any message that can never fit
+            dropped.push(message);
+        } else {
+            // Drop the oldest messages for LIFO
+            if key_message_queue.messages.len() == max_queue_size {
+                dropped.extend(key_message_queue.pop_front().map(|entry| entry.message));
             }
-            match self.queue_style {
-                // Drop the newest message for FIFO
-                QueueStyle::FIFO => Some(message),
-                // Drop the oldest message for LIFO
-                QueueStyle::LIFO | QueueStyle::KLAST => {
-                    let oldest = key_message_queue.pop_front();
-                    key_message_queue.push_back(message);
-                    oldest
+            while !fits_in_bytes(key_message_queue) {
+                match key_message_queue.pop_front() {
+                    Some(oldest) => dropped.push(oldest.message),
+                    None => break,
                 }
             }
-        } else {
-            key_message_queue.push_back(message);
-            None
+            key_message_queue.push_back(Entry {
+                message,
+                size,
+                deadline,
+            });
+        }
+
+        // Add the key to the round-robin queue of its priority if it's not already there
+        if was_empty && !key_message_queue.messages.is_empty() {
+            let priority = self.priority(&key);
+            self.round_robin_queues
+                .entry(priority)
+                .or_insert_with(VecDeque::new)
+                .push_back(key);
         }
+        if !dropped.is_empty() {
+            if let Some(c) = self.counters.as_ref() {
+                c.with_label_values(&["dropped"])
+                    .inc_by(dropped.len() as i64);
+            }
+        }
+        dropped
     }
 
-    /// pop a message from the appropriate queue in per_key_queue
-    /// remove the key from the round_robin_queue if it has no more messages
+    /// pop a message from the appropriate queue in per_key_queue, among the keys of the
+    /// highest priority with pending messages
+    /// remove the key from its round_robin_queue if it has no more messages
     pub(crate) fn pop(&mut self) -> Option<T> {
-        let key = match self.round_robin_queue.pop_front() {
-            Some(v) => v,
-            _ => {
-                return None;
+        self.pop_with_expired().0
+    }
+
+    /// Same as `pop`, but also returns the messages which were dropped on the way because
+    /// their deadline had passed, so that their senders can be notified.
+    pub(crate) fn pop_with_expired(&mut self) -> (Option<T>, Vec<T>) {
+        let mut expired = Vec::new();
+        // Only read the clock if there is a deadline to compare against
+        let mut now = None;
+        loop {
+            let (priority, round_robin_queue) = match self.round_robin_queues.iter_mut().next_back()
+            {
+                Some((priority, round_robin_queue)) => (*priority, round_robin_queue),
+                None => {
+                    return (None, expired);
+                }
+            };
+            let key = match round_robin_queue.pop_front() {
+                Some(v) => v,
+                _ => {
+                    return (None, expired);
+                }
+            };
+            if round_robin_queue.is_empty() {
+                self.round_robin_queues.remove(&priority);
+            }
+            let (entry, is_q_empty) = self.pop_from_key_queue(&key);
+            if !is_q_empty {
+                self.round_robin_queues
+                    .entry(priority)
+                    .or_insert_with(VecDeque::new)
+                    .push_back(key);
+            }
+            let entry = match entry {
+                Some(entry) => entry,
+                None => return (None, expired),
+            };
+            if let (QueueStyle::DEADLINE, Some(deadline)) = (self.queue_style, entry.deadline) {
+                if deadline <= *now.get_or_insert_with(Instant::now) {
+                    if let Some(c) = self.counters.as_ref() {
+                        c.with_label_values(&["dropped"]).inc();
+                    }
+                    expired.push(entry.message);
+                    continue;
+                }
             }
-        };
-        let (message, is_q_empty) = self.pop_from_key_queue(&key);
-        if !is_q_empty {
-            self.round_robin_queue.push_back(key);
-        }
-        if message.is_some() {
             if let Some(c) = self.counters.as_ref() {
                 c.with_label_values(&["dequeued"]).inc();
             }
+            return (Some(entry.message), expired);
         }
-        message
     }
 
     /// Clears all the pending messages and cleans up the queue from the previous metadata.
     pub(crate) fn clear(&mut self) {
         self.per_key_queue.clear();
-        self.round_robin_queue.clear();
+        self.round_robin_queues.clear();
     }
 }
@@ -3,7 +3,10 @@
 
 use crate::message_queues::{PerKeyQueue, QueueStyle};
 use libra_types::account_address::AccountAddress;
-use std::num::NonZeroUsize;
+use std::{
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
 
 /// This represents a proposal message from a validator
 #[derive(Debug, PartialEq)]
@@ -437,3 +440,76 @@ fn test_message_queue_clear() {
     );
     assert_eq!(q.pop().unwrap().msg, "msg3".to_string());
 }
+
+#[test]
+fn test_byte_limit() {
+    let mut q = PerKeyQueue::new_with_byte_limit(
+        QueueStyle::KLAST,
+        NonZeroUsize::new(3).unwrap(),
+        NonZeroUsize::new(10).unwrap(),
+        None,
+    );
+    let validator = AccountAddress::new([0u8; AccountAddress::LENGTH]);
+
+    assert!(q.push_sized(validator, "msg1", 4).is_empty());
+    assert!(q.push_sized(validator, "msg2", 4).is_empty());
+    // Evicts both older messages to stay within 10 bytes
+    assert_eq!(q.push_sized(validator, "msg3", 8), vec!["msg1", "msg2"]);
+    // Never fits, regardless of the queue style
+    assert_eq!(q.push_sized(validator, "msg4", 11), vec!["msg4"]);
+    assert_eq!(q.pop(), Some("msg3"));
+    assert_eq!(q.pop(), None);
+
+    // Once drained, the full byte budget is available again
+    assert!(q.push_sized(validator, "msg5", 10).is_empty());
+    assert_eq!(q.pop(), Some("msg5"));
+}
+
+#[test]
+fn test_deadline() {
+    let mut q = PerKeyQueue::new(QueueStyle::DEADLINE, NonZeroUsize::new(3).unwrap(), None);
+    let validator = AccountAddress::new([0u8; AccountAddress::LENGTH]);
+    let now = Instant::now();
+    let expired = now - Duration::from_secs(1);
+    let live = now + Duration::from_secs(60);
+
+    assert!(q.push_entry(validator, "msg1", 0, Some(expired)).is_empty());
+    assert!(q.push_entry(validator, "msg2", 0, Some(live)).is_empty());
+    assert!(q.push_entry(validator, "msg3", 0, None).is_empty());
+    // Newest messages are dropped once full, as with FIFO
+    assert_eq!(q.push_entry(validator, "msg4", 0, Some(live)), vec!["msg4"]);
+
+    assert_eq!(q.pop_with_expired(), (Some("msg2"), vec!["msg1"]));
+    assert_eq!(q.pop_with_expired(), (Some("msg3"), vec![]));
+    assert_eq!(q.pop_with_expired(), (None, vec![]));
+
+    // Only expired messages left
+    assert!(q.push_entry(validator, "msg5", 0, Some(expired)).is_empty());
+    assert_eq!(q.pop(), None);
+}
+
+#[test]
+fn test_priorities() {
+    // Keys 0 and 1 are bulk traffic, key 2 preempts them
+    let mut q = PerKeyQueue::new_with_priorities(
+        QueueStyle::FIFO,
+        NonZeroUsize::new(3).unwrap(),
+        Box::new(|key: &u8| if *key == 2 { 1 } else { 0 }),
+        None,
+    );
+    q.push(0, "bulk1");
+    q.push(1, "bulk2");
+    q.push(0, "bulk3");
+    q.push(2, "urgent1");
+    q.push(2, "urgent2");
+
+    assert_eq!(q.pop(), Some("urgent1"));
+    assert_eq!(q.pop(), Some("urgent2"));
+    // Keys of a same priority are still served in a round-robin fashion
+    assert_eq!(q.pop(), Some("bulk1"));
+    q.push(2, "urgent3");
+    assert_eq!(q.pop(), Some("urgent3"));
+    assert_eq!(q.pop(), Some("bulk2"));
+    assert_eq!(q.pop(), Some("bulk3"));
+    assert_eq!(q.pop(), None);
+}
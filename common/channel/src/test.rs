@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{new_test, new_test_with_timeout, TEST_COUNTER};
+use crate::{new_test, new_test_with_timeout, new_unmetered, TEST_COUNTER};
 use futures::{
     executor::block_on,
     task::{noop_waker, Context, Poll},
@@ -22,6 +22,18 @@ fn test_send() {
     assert_eq!(TEST_COUNTER.get(), 0);
 }
 
+#[test]
+fn test_send_unmetered() {
+    let (mut tx, mut rx) = new_unmetered(1);
+    block_on(tx.send(42)).unwrap();
+    assert!(tx.try_send(43).is_ok());
+    assert!(tx.try_send(44).unwrap_err().is_full());
+    assert_eq!(block_on(rx.next()), Some(42));
+    assert_eq!(block_on(rx.next()), Some(43));
+    drop(tx);
+    assert_eq!(block_on(rx.next()), None);
+}
+
 // Fork the unit tests into separate processes to avoid the conflict that these tests executed in
 // multiple threads may manipulate TEST_COUNTER at the same time.
 rusty_fork_test! {
@@ -7,10 +7,16 @@ use backtrace::Backtrace;
 use libra_logger::prelude::*;
 use serde::Serialize;
 use std::{
+    cell::Cell,
     panic::{self, PanicInfo},
     process, thread, time,
 };
 
+thread_local! {
+    /// Whether the panics of the current thread are caught and recovered from by the caller.
+    static RECOVERABLE_PANICS: Cell<bool> = Cell::new(false);
+}
+
 #[derive(Debug, Serialize)]
 pub struct CrashInfo {
     details: String,
@@ -28,6 +34,20 @@ pub fn setup_panic_handler() {
     }));
 }
 
+/// Runs `f`, whose panics are logged but do not exit the process, so that the caller can catch
+/// them with `std::panic::catch_unwind` and recover, e.g., by restarting an actor.
+pub fn with_recoverable_panics<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            RECOVERABLE_PANICS.with(|recoverable| recoverable.set(self.0));
+        }
+    }
+
+    let _restore = Restore(RECOVERABLE_PANICS.with(|recoverable| recoverable.replace(true)));
+    f()
+}
+
 // Formats and logs panic information
 fn handle_panic(panic_info: &PanicInfo<'_>) {
     // The Display formatter for a PanicInfo contains the message, payload and location.
@@ -37,6 +57,10 @@ fn handle_panic(panic_info: &PanicInfo<'_>) {
     let info = CrashInfo { details, backtrace };
     crit!("{}", toml::to_string_pretty(&info).unwrap());
 
+    if RECOVERABLE_PANICS.with(Cell::get) {
+        return;
+    }
+
     // Provide some time to save the log to disk
     thread::sleep(time::Duration::from_millis(100));
 
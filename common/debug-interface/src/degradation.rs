@@ -0,0 +1,299 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Degradation of a node under resource pressure.
+//!
+//! The resources used by the node are checked periodically against their limits. On every check
+//! where the usage crosses a limit, the node sheds load in one more [`DegradationMode`], in the
+//! order of [`DegradationMode::ORDER`]:
+//! 1. the public network stops accepting new connections,
+//! 2. mempool shrinks to a fraction of its capacity,
+//! 3. JSON-RPC is throttled.
+//!
+//! On every check where the usage is back below `recovery_percent` of every limit, the last mode
+//! entered is left, so that the node recovers in the reverse order. Every transition is logged and
+//! counted in `libra_node_degradation_transitions`, and `GET /degradation` on the node debug
+//! service reports the modes currently active.
+//!
+//! Components which shed load register a [`Degradable`] participant for their mode. Components
+//! which only need to check whether their mode is active can call [`is_active`] instead.
+
+use libra_logger::prelude::*;
+use libra_metrics::{
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, IntCounterVec, IntGauge,
+    IntGaugeVec,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// A way for the node to shed load.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DegradationMode {
+    /// The public network stops accepting new connections.
+    PausePublicAccepts,
+    /// Mempool accepts fewer transactions.
+    ShrinkMempool,
+    /// JSON-RPC serves fewer requests.
+    ThrottleJsonRpc,
+}
+
+impl DegradationMode {
+    /// The modes in the order they are entered under pressure.
+    pub const ORDER: [DegradationMode; 3] = [
+        DegradationMode::PausePublicAccepts,
+        DegradationMode::ShrinkMempool,
+        DegradationMode::ThrottleJsonRpc,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DegradationMode::PausePublicAccepts => "pause_public_accepts",
+            DegradationMode::ShrinkMempool => "shrink_mempool",
+            DegradationMode::ThrottleJsonRpc => "throttle_json_rpc",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ORDER
+            .iter()
+            .position(|mode| *mode == self)
+            .expect("every mode is ordered")
+    }
+}
+
+/// A component which sheds load in a degradation mode.
+pub trait Degradable: Send + Sync {
+    fn mode(&self) -> DegradationMode;
+
+    /// Starts shedding load. Must not block.
+    fn degrade(&self);
+
+    /// Stops shedding load. Must not block.
+    fn recover(&self);
+}
+
+/// Limits of the resources used by the node, `None` meaning unlimited.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceLimits {
+    /// Resident memory of the process, in bytes.
+    pub max_memory_bytes: Option<u64>,
+    /// Size of the files in the storage directory, in bytes.
+    pub max_disk_bytes: Option<u64>,
+    /// The node recovers once its usage falls below this percentage of every limit.
+    pub recovery_percent: u64,
+}
+
+impl ResourceLimits {
+    fn is_exceeded(&self, usage: &ResourceUsage) -> bool {
+        exceeds(usage.memory_bytes, self.max_memory_bytes, 100)
+            || exceeds(usage.disk_bytes, self.max_disk_bytes, 100)
+    }
+
+    fn is_recovered(&self, usage: &ResourceUsage) -> bool {
+        !exceeds(
+            usage.memory_bytes,
+            self.max_memory_bytes,
+            self.recovery_percent,
+        ) && !exceeds(usage.disk_bytes, self.max_disk_bytes, self.recovery_percent)
+    }
+}
+
+/// Whether `usage` is at least `percent` of `limit`. Unknown usages never exceed their limit.
+fn exceeds(usage: Option<u64>, limit: Option<u64>, percent: u64) -> bool {
+    match (usage, limit) {
+        (Some(usage), Some(limit)) => {
+            u128::from(usage) * 100 >= u128::from(limit) * u128::from(percent)
+        }
+        _ => false,
+    }
+}
+
+/// Resources used by the node, `None` if unknown.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    pub memory_bytes: Option<u64>,
+    pub disk_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DegradationStatus {
+    /// Modes currently active, in the order they were entered.
+    pub active: Vec<DegradationMode>,
+    /// Usage measured on the last check.
+    pub usage: ResourceUsage,
+}
+
+#[derive(Default)]
+struct Degradation {
+    participants: Vec<Arc<dyn Degradable>>,
+    usage: ResourceUsage,
+}
+
+/// Number of modes active, i.e., the first `LEVEL` modes of `DegradationMode::ORDER`.
+static LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+static DEGRADATION: Lazy<Mutex<Degradation>> = Lazy::new(|| Mutex::new(Degradation::default()));
+
+static LIBRA_NODE_DEGRADATION_LEVEL: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "libra_node_degradation_level",
+        "Number of degradation modes active, under resource pressure"
+    )
+    .unwrap()
+});
+
+static LIBRA_NODE_DEGRADATION_TRANSITIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_node_degradation_transitions",
+        "Number of times a degradation mode was entered or left",
+        &["mode", "transition"]
+    )
+    .unwrap()
+});
+
+static LIBRA_NODE_RESOURCE_USAGE_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_node_resource_usage_bytes",
+        "Resources used by the node, as checked against their limits",
+        &["resource"]
+    )
+    .unwrap()
+});
+
+/// Registers a participant. If its mode is already active, the participant degrades right away.
+pub fn register(participant: Arc<dyn Degradable>) {
+    let mut degradation = DEGRADATION.lock().unwrap();
+    if is_active(participant.mode()) {
+        participant.degrade();
+    }
+    degradation.participants.push(participant);
+}
+
+/// Whether the node currently sheds load in `mode`.
+pub fn is_active(mode: DegradationMode) -> bool {
+    LEVEL.load(Ordering::Acquire) > mode.index()
+}
+
+pub fn status() -> DegradationStatus {
+    let degradation = DEGRADATION.lock().unwrap();
+    DegradationStatus {
+        active: DegradationMode::ORDER[..LEVEL.load(Ordering::Acquire)].to_vec(),
+        usage: degradation.usage,
+    }
+}
+
+/// Checks `usage` against `limits`, entering the next mode if a limit is crossed, or leaving the
+/// last mode entered once the usage has recovered.
+pub fn update(limits: &ResourceLimits, usage: ResourceUsage) {
+    let mut degradation = DEGRADATION.lock().unwrap();
+    degradation.usage = usage;
+    for (resource, bytes) in &[("memory", usage.memory_bytes), ("disk", usage.disk_bytes)] {
+        if let Some(bytes) = bytes {
+            LIBRA_NODE_RESOURCE_USAGE_BYTES
+                .with_label_values(&[*resource])
+                .set(*bytes as i64);
+        }
+    }
+
+    let level = LEVEL.load(Ordering::Acquire);
+    if limits.is_exceeded(&usage) && level < DegradationMode::ORDER.len() {
+        let mode = DegradationMode::ORDER[level];
+        warn!(
+            "Resource limits exceeded, usage: {:?}, limits: {:?}; entering degradation mode {}",
+            usage,
+            limits,
+            mode.as_str()
+        );
+        LEVEL.store(level + 1, Ordering::Release);
+        for participant in participants(&degradation, mode) {
+            participant.degrade();
+        }
+        record_transition(mode, "enter", level + 1);
+    } else if limits.is_recovered(&usage) && level > 0 {
+        let mode = DegradationMode::ORDER[level - 1];
+        info!(
+            "Resource usage recovered, usage: {:?}, limits: {:?}; leaving degradation mode {}",
+            usage,
+            limits,
+            mode.as_str()
+        );
+        LEVEL.store(level - 1, Ordering::Release);
+        for participant in participants(&degradation, mode) {
+            participant.recover();
+        }
+        record_transition(mode, "leave", level - 1);
+    }
+}
+
+fn participants(
+    degradation: &Degradation,
+    mode: DegradationMode,
+) -> impl Iterator<Item = &Arc<dyn Degradable>> {
+    degradation
+        .participants
+        .iter()
+        .filter(move |participant| participant.mode() == mode)
+}
+
+fn record_transition(mode: DegradationMode, transition: &str, level: usize) {
+    LIBRA_NODE_DEGRADATION_TRANSITIONS
+        .with_label_values(&[mode.as_str(), transition])
+        .inc();
+    LIBRA_NODE_DEGRADATION_LEVEL.set(level as i64);
+}
+
+/// Spawns a thread checking the resources used by the node every `check_interval`, the disk usage
+/// being the size of the files in `storage_dir`. Only the resources with a limit are measured.
+pub fn start_monitor(limits: ResourceLimits, storage_dir: PathBuf, check_interval: Duration) {
+    thread::Builder::new()
+        .name("resource-monitor".to_string())
+        .spawn(move || loop {
+            let usage = ResourceUsage {
+                memory_bytes: limits.max_memory_bytes.and_then(|_| memory_usage()),
+                disk_bytes: limits.max_disk_bytes.and_then(|_| disk_usage(&storage_dir)),
+            };
+            update(&limits, usage);
+            thread::sleep(check_interval);
+        })
+        .expect("Failed to spawn the resource monitor");
+}
+
+/// Resident memory of the process, as reported by `/proc/self/status` on Linux.
+fn memory_usage() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Total size of the files under `dir`. The entries removed while walking the directory, e.g.,
+/// by a compaction of the database, are skipped.
+fn disk_usage(dir: &Path) -> Option<u64> {
+    let mut total = 0;
+    let mut dirs = vec![fs::read_dir(dir).ok()?];
+    while let Some(entries) = dirs.pop() {
+        for entry in entries.filter_map(Result::ok) {
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => {
+                    if let Ok(entries) = fs::read_dir(entry.path()) {
+                        dirs.push(entries);
+                    }
+                }
+                Ok(metadata) => total += metadata.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    Some(total)
+}
@@ -0,0 +1,131 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Draining of a node ahead of a restart.
+//!
+//! Components which need to wind down before the process can be killed register a
+//! [`Drainable`] participant. Once an operator starts draining (`POST /drain` on the node debug
+//! service), every participant is asked to stop taking on new work, and `GET /drain` reports
+//! when all of them are done and it is safe to stop the process. If the node isn't restarted
+//! after all, `POST /drain/cancel` has the participants resume their work.
+//!
+//! The node is safe to stop once every participant reports it is done, e.g., mempool once the
+//! client submissions it was processing were answered, and consensus while the node isn't the
+//! proposer of the upcoming rounds.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// A component which takes part in draining the node.
+pub trait Drainable: Send + Sync {
+    /// Name reported in the drain status while the participant is still draining.
+    fn name(&self) -> &'static str;
+
+    /// Stops taking on new work. Must not block.
+    fn begin_drain(&self);
+
+    /// Whether the in-flight work of this participant has completed.
+    fn is_drained(&self) -> bool;
+
+    /// Resumes taking on new work after the drain was cancelled. Must not block.
+    fn end_drain(&self);
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DrainStatus {
+    pub draining: bool,
+    pub elapsed_ms: u64,
+    /// Participants which are still draining.
+    pub pending: Vec<String>,
+    pub safe_to_stop: bool,
+}
+
+#[derive(Default)]
+struct DrainCoordinator {
+    participants: Vec<Arc<dyn Drainable>>,
+    started_at: Option<Instant>,
+}
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+static COORDINATOR: Lazy<Mutex<DrainCoordinator>> =
+    Lazy::new(|| Mutex::new(DrainCoordinator::default()));
+
+/// Registers a participant. If the node is already draining, the participant is asked to drain
+/// right away.
+pub fn register(participant: Arc<dyn Drainable>) {
+    let mut coordinator = COORDINATOR.lock().unwrap();
+    if coordinator.started_at.is_some() {
+        participant.begin_drain();
+    }
+    coordinator.participants.push(participant);
+}
+
+/// Whether the node has started draining. Components which only need to refuse new work can
+/// check this instead of registering a participant.
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Acquire)
+}
+
+/// Starts draining the node. Calling this again while draining is a no-op.
+pub fn start() -> DrainStatus {
+    {
+        let mut coordinator = COORDINATOR.lock().unwrap();
+        if coordinator.started_at.is_none() {
+            coordinator.started_at = Some(Instant::now());
+            DRAINING.store(true, Ordering::Release);
+            for participant in coordinator.participants.iter() {
+                participant.begin_drain();
+            }
+        }
+    }
+    status()
+}
+
+/// Cancels the drain, if any, and has every participant resume its work.
+pub fn cancel() -> DrainStatus {
+    {
+        let mut coordinator = COORDINATOR.lock().unwrap();
+        if coordinator.started_at.take().is_some() {
+            DRAINING.store(false, Ordering::Release);
+            for participant in coordinator.participants.iter() {
+                participant.end_drain();
+            }
+        }
+    }
+    status()
+}
+
+pub fn status() -> DrainStatus {
+    let coordinator = COORDINATOR.lock().unwrap();
+    let started_at = match coordinator.started_at {
+        Some(started_at) => started_at,
+        None => {
+            return DrainStatus {
+                draining: false,
+                elapsed_ms: 0,
+                pending: vec![],
+                safe_to_stop: false,
+            }
+        }
+    };
+    let pending: Vec<_> = coordinator
+        .participants
+        .iter()
+        .filter(|participant| !participant.is_drained())
+        .map(|participant| participant.name().to_string())
+        .collect();
+    DrainStatus {
+        draining: true,
+        elapsed_ms: started_at.elapsed().as_millis() as u64,
+        safe_to_stop: pending.is_empty(),
+        pending,
+    }
+}
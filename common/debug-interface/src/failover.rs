@@ -0,0 +1,52 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Failover between a validator and its hot standby.
+//!
+//! A standby runs the whole validator stack, keeping its network connections and its state in
+//! sync, but its SafetyRules refuses to sign votes, proposals and timeouts. To fail over, an
+//! operator demotes the active validator (`POST /failover/demote` on its node debug service),
+//! which stops signing and returns the state of its SafetyRules, signed with the consensus key of
+//! the validator, and then promotes the standby (`POST /failover/promote` with that state), which
+//! checks the signature and imports the state before signing, so that the two instances never
+//! sign in the same round.
+//!
+//! SafetyRules persists the demotion, so a demoted validator stays a standby when restarted, until
+//! it is promoted. The failover requests change who signs for the validator, so the node debug
+//! service only accepts them from the loopback interface, whatever address it listens on.
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// The component which can demote and promote the node, i.e., consensus.
+pub trait FailoverHandler: Send + Sync {
+    /// Stops signing, and returns the state to hand over to the standby being promoted.
+    fn demote(&self) -> Result<Value, String>;
+
+    /// Resumes signing, once the state handed over by the demoted validator is imported.
+    fn promote(&self, handover: Value) -> Result<(), String>;
+}
+
+static HANDLER: Lazy<Mutex<Option<Arc<dyn FailoverHandler>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Registers the handler of the failover requests, replacing the previous one, if any.
+pub fn register(handler: Arc<dyn FailoverHandler>) {
+    *HANDLER.lock().unwrap() = Some(handler);
+}
+
+fn handler() -> Result<Arc<dyn FailoverHandler>, String> {
+    HANDLER
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| "This node does not support failover".to_string())
+}
+
+pub fn demote() -> Result<Value, String> {
+    handler()?.demote()
+}
+
+pub fn promote(handover: Value) -> Result<(), String> {
+    handler()?.promote(handover)
+}
@@ -3,11 +3,14 @@
 
 #![forbid(unsafe_code)]
 
-use crate::json_log::JsonLogEntry;
+use crate::{degradation::DegradationStatus, drain::DrainStatus, json_log::JsonLogEntry};
 use anyhow::Result;
 use reqwest::blocking;
 use std::collections::HashMap;
 
+pub mod degradation;
+pub mod drain;
+pub mod failover;
 pub mod json_log;
 pub mod libra_trace;
 pub mod node_debug_service;
@@ -58,6 +61,65 @@ impl NodeDebugClient {
 
         Ok(response.json()?)
     }
+
+    /// Starts draining the node ahead of a restart.
+    pub fn start_drain(&mut self) -> Result<DrainStatus> {
+        let response = self.client.post(&format!("{}/drain", self.addr)).send()?;
+
+        Ok(response.json()?)
+    }
+
+    /// Cancels the drain of the node, which resumes its work.
+    pub fn cancel_drain(&mut self) -> Result<DrainStatus> {
+        let response = self
+            .client
+            .post(&format!("{}/drain/cancel", self.addr))
+            .send()?;
+
+        Ok(response.json()?)
+    }
+
+    pub fn get_drain_status(&mut self) -> Result<DrainStatus> {
+        let response = self.client.get(&format!("{}/drain", self.addr)).send()?;
+
+        Ok(response.json()?)
+    }
+
+    /// Returns the degradation modes the node currently sheds load in.
+    pub fn get_degradation_status(&mut self) -> Result<DegradationStatus> {
+        let response = self
+            .client
+            .get(&format!("{}/degradation", self.addr))
+            .send()?;
+
+        Ok(response.json()?)
+    }
+
+    /// Demotes the node to a standby, and returns the state to hand over to the standby promoted
+    /// in its place.
+    pub fn demote(&mut self) -> Result<serde_json::Value> {
+        let response = self
+            .client
+            .post(&format!("{}/failover/demote", self.addr))
+            .send()?;
+
+        response
+            .json::<std::result::Result<_, String>>()?
+            .map_err(anyhow::Error::msg)
+    }
+
+    /// Promotes the standby node with the state handed over by the demoted node.
+    pub fn promote(&mut self, handover: &serde_json::Value) -> Result<()> {
+        let response = self
+            .client
+            .post(&format!("{}/failover/promote", self.addr))
+            .json(handover)
+            .send()?;
+
+        response
+            .json::<std::result::Result<_, String>>()?
+            .map_err(anyhow::Error::msg)
+    }
 }
 
 /// Implement default utility client for AsyncNodeDebugInterface
@@ -110,4 +172,25 @@ impl AsyncNodeDebugClient {
 
         Ok(response.json().await?)
     }
+
+    /// Starts draining the node ahead of a restart.
+    pub async fn start_drain(&mut self) -> Result<DrainStatus> {
+        let response = self
+            .client
+            .post(&format!("{}/drain", self.addr))
+            .send()
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    pub async fn get_drain_status(&mut self) -> Result<DrainStatus> {
+        let response = self
+            .client
+            .get(&format!("{}/drain", self.addr))
+            .send()
+            .await?;
+
+        Ok(response.json().await?)
+    }
 }
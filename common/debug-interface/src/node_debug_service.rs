@@ -3,10 +3,10 @@
 
 //! Debug interface to access information in a specific node.
 
-use crate::json_log;
+use crate::{degradation, drain, failover, json_log};
 use std::net::SocketAddr;
 use tokio::runtime::{Builder, Runtime};
-use warp::Filter;
+use warp::{Filter, Rejection, Reply};
 
 #[derive(Debug)]
 pub struct NodeDebugService {
@@ -22,18 +22,98 @@ impl NodeDebugService {
             .build()
             .expect("[rpc] failed to create runtime");
 
-        // GET /metrics
-        let metrics =
-            warp::path("metrics").map(|| warp::reply::json(&libra_metrics::get_all_metrics()));
+        let server = runtime.enter(move || warp::serve(routes()).bind(address));
+        runtime.handle().spawn(server);
 
-        // GET /evnets
-        let events = warp::path("events").map(|| warp::reply::json(&json_log::pop_last_entries()));
+        Self { runtime }
+    }
+}
 
-        let routes = warp::get().and(metrics.or(events));
+fn routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    // GET /metrics
+    let metrics =
+        warp::path("metrics").map(|| warp::reply::json(&libra_metrics::get_all_metrics()));
 
-        let server = runtime.enter(move || warp::serve(routes).bind(address));
-        runtime.handle().spawn(server);
+    // GET /evnets
+    let events = warp::path("events").map(|| warp::reply::json(&json_log::pop_last_entries()));
 
-        Self { runtime }
+    // GET /drain
+    let drain_status = warp::path!("drain").map(|| warp::reply::json(&drain::status()));
+
+    // GET /degradation
+    let degradation_status =
+        warp::path("degradation").map(|| warp::reply::json(&degradation::status()));
+
+    // POST /drain
+    let drain_start = warp::path!("drain")
+        .and(local_only())
+        .map(|| warp::reply::json(&drain::start()));
+
+    // POST /drain/cancel
+    let drain_cancel = warp::path!("drain" / "cancel")
+        .and(local_only())
+        .map(|| warp::reply::json(&drain::cancel()));
+
+    // POST /failover/demote
+    let demote = warp::path!("failover" / "demote")
+        .and(local_only())
+        .map(|| warp::reply::json(&failover::demote()));
+
+    // POST /failover/promote
+    let promote = warp::path!("failover" / "promote")
+        .and(local_only())
+        .and(warp::body::json())
+        .map(|handover| warp::reply::json(&failover::promote(handover)));
+
+    warp::get()
+        .and(metrics.or(events).or(drain_status).or(degradation_status))
+        .or(warp::post().and(drain_start.or(drain_cancel).or(demote).or(promote)))
+}
+
+/// Rejects the requests which don't come from the loopback interface.
+fn local_only() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(|remote: Option<SocketAddr>| async move {
+            match remote {
+                Some(remote) if remote.ip().is_loopback() => Ok(()),
+                _ => Err(warp::reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drain::DrainStatus;
+    use warp::http::StatusCode;
+
+    async fn post(path: &str, remote: &str) -> warp::http::Response<warp::hyper::body::Bytes> {
+        warp::test::request()
+            .method("POST")
+            .path(path)
+            .remote_addr(remote.parse().unwrap())
+            .reply(&routes())
+            .await
+    }
+
+    #[tokio::test]
+    async fn drain_from_loopback_only() {
+        for path in &["/drain", "/drain/cancel", "/drain/start"] {
+            let response = post(path, "10.0.0.1:40000").await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+        assert!(!drain::is_draining());
+
+        let response = post("/drain", "127.0.0.1:40000").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: DrainStatus = serde_json::from_slice(response.body()).unwrap();
+        assert!(status.draining);
+
+        let response = post("/drain/cancel", "127.0.0.1:40000").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: DrainStatus = serde_json::from_slice(response.body()).unwrap();
+        assert!(!status.draining);
+        assert!(!drain::is_draining());
     }
 }
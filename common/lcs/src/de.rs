@@ -43,6 +43,33 @@ where
     deserializer.end().map(move |_| t)
 }
 
+/// Deserializes a `&[u8]` which starts with an instance of type `T`, returning it with the bytes
+/// following it, e.g., the fields appended to `T` by a newer version of a protocol.
+///
+/// ```
+/// use libra_canonical_serialization::from_bytes_prefix;
+///
+/// let bytes = vec![0x01, 0x00, 0x02];
+/// let (value, rest): (u16, _) = from_bytes_prefix(&bytes).unwrap();
+///
+/// assert_eq!(value, 1);
+/// assert_eq!(rest, &[0x02]);
+/// ```
+pub fn from_bytes_prefix<'a, T>(bytes: &'a [u8]) -> Result<(T, &'a [u8])>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(bytes);
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.input))
+}
+
+/// Returns the index of the variant of the enum serialized at the start of `bytes`, without
+/// deserializing the enum, e.g., to recognize the variants unknown to this version of the enum.
+pub fn peek_variant_index(bytes: &[u8]) -> Result<u32> {
+    Deserializer::new(bytes).parse_u32_from_uleb128()
+}
+
 /// Perform a stateful deserialization from a `&[u8]` using the provided `seed`.
 pub fn from_bytes_seed<'a, T>(seed: T, bytes: &'a [u8]) -> Result<T::Value>
 where
@@ -282,6 +282,6 @@ pub mod test_helpers;
 /// Variable length sequences in LCS are limited to max length of 2^31
 pub const MAX_SEQUENCE_LENGTH: usize = 1 << 31;
 
-pub use de::{from_bytes, from_bytes_seed};
+pub use de::{from_bytes, from_bytes_prefix, from_bytes_seed, peek_variant_index};
 pub use error::{Error, Result};
 pub use ser::{is_human_readable, serialize_into, serialized_size, to_bytes};
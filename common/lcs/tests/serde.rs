@@ -5,7 +5,8 @@
 #![allow(clippy::unit_arg)]
 
 use libra_canonical_serialization::{
-    from_bytes, serialized_size, to_bytes, Error, MAX_SEQUENCE_LENGTH,
+    from_bytes, from_bytes_prefix, peek_variant_index, serialized_size, to_bytes, Error,
+    MAX_SEQUENCE_LENGTH,
 };
 use proptest::prelude::*;
 use proptest_derive::Arbitrary;
@@ -58,6 +59,23 @@ fn test_enum() {
     is_same(s);
 }
 
+#[test]
+fn test_prefix() {
+    let mut bytes = to_bytes(&E::Tuple(1, 2)).unwrap();
+    assert_eq!(peek_variant_index(&bytes), Ok(2));
+    bytes.extend_from_slice(&[7, 8]);
+    assert_eq!(from_bytes::<E>(&bytes), Err(Error::RemainingInput));
+    let (e, rest): (E, _) = from_bytes_prefix(&bytes).unwrap();
+    assert_eq!(e, E::Tuple(1, 2));
+    assert_eq!(rest, &[7, 8]);
+
+    // variants unknown to `E`
+    let bytes = vec![0x80, 0x01, 0x00];
+    assert_eq!(peek_variant_index(&bytes), Ok(128));
+    assert!(from_bytes_prefix::<E>(&bytes).is_err());
+    assert_eq!(peek_variant_index(&[]), Err(Error::Eof));
+}
+
 #[derive(Arbitrary, Debug, Deserialize, Serialize, PartialEq)]
 struct S {
     int: u16,
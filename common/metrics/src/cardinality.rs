@@ -0,0 +1,61 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Every distinct value of a label creates a new time series in Prometheus. Labels taking dynamic
+//! values, like peer ids, may grow unbounded over the lifetime of a node, so their values are
+//! passed through a `CardinalityGuard`, which keeps the first values it sees and replaces the
+//! following ones with `OTHER_LABEL_VALUE`.
+
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::{collections::HashSet, sync::Mutex};
+
+/// Label value standing for all the values over the limit of a guard.
+pub const OTHER_LABEL_VALUE: &str = "other";
+
+/// Default number of distinct values of a guarded label, large enough for the peers of a node.
+pub const DEFAULT_MAX_LABEL_VALUES: usize = 256;
+
+/// Number of label values replaced by `OTHER_LABEL_VALUE`, by guarded label.
+static LABEL_OVERFLOWS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_metrics_label_overflows",
+        "Number of label values replaced by the 'other' bucket, by guarded label",
+        &["label"]
+    )
+    .unwrap()
+});
+
+/// Caps the number of distinct values a label takes.
+pub struct CardinalityGuard {
+    name: &'static str,
+    max_values: usize,
+    values: Mutex<HashSet<String>>,
+}
+
+impl CardinalityGuard {
+    /// `name` identifies the guarded label in `libra_metrics_label_overflows`, e.g.
+    /// "libra_network_rpc_latency_seconds/peer_id".
+    pub fn new(name: &'static str, max_values: usize) -> Self {
+        Self {
+            name,
+            max_values,
+            values: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns `value` if it was seen before or the limit has not been reached yet, and
+    /// `OTHER_LABEL_VALUE` otherwise.
+    pub fn label<'a>(&self, value: &'a str) -> &'a str {
+        let mut values = self.values.lock().unwrap();
+        if values.contains(value) {
+            return value;
+        }
+        if values.len() < self.max_values {
+            values.insert(value.to_string());
+            return value;
+        }
+        LABEL_OVERFLOWS.with_label_values(&[self.name]).inc();
+        OTHER_LABEL_VALUE
+    }
+}
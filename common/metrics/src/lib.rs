@@ -4,11 +4,13 @@
 #![forbid(unsafe_code)]
 #![recursion_limit = "128"]
 
+pub mod cardinality;
 pub mod counters;
 mod json_encoder;
 mod json_metrics;
 pub mod metric_server;
 mod public_metrics;
+pub mod push_gateway;
 
 mod op_counters;
 pub use op_counters::{DurationHistogram, OpMetrics};
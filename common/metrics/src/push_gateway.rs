@@ -0,0 +1,80 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Push mode export of the metrics, for nodes that can't be scraped by Prometheus, e.g., behind a
+//! NAT. The metrics are periodically pushed to a Prometheus push gateway, which Prometheus scrapes
+//! instead.
+
+use anyhow::{ensure, Result};
+use hyper::{Body, Client, Method, Request};
+use libra_logger::prelude::*;
+use prometheus::{Encoder, TextEncoder};
+use std::{thread, time::Duration};
+use tokio::runtime;
+
+/// Returns the URL the metrics of `instance` of `job` are pushed to, following the grouping key
+/// conventions of the push gateway.
+fn push_url(gateway: &str, job: &str, instance: &str) -> String {
+    format!(
+        "{}/metrics/job/{}/instance/{}",
+        gateway.trim_end_matches('/'),
+        job,
+        instance
+    )
+}
+
+async fn push_metrics(client: &Client<hyper::client::HttpConnector>, url: &str) -> Result<()> {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+
+    // PUT replaces all the metrics of the grouping key, so that metrics no longer reported by the
+    // node don't linger in the gateway
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(url)
+        .header(hyper::header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))?;
+    let response = client.request(request).await?;
+    ensure!(
+        response.status().is_success(),
+        "push gateway returned {}",
+        response.status()
+    );
+    Ok(())
+}
+
+/// Launches a background thread which pushes all the metrics to the push gateway at `gateway`
+/// (e.g., http://pushgateway:9091) every `interval_ms`, grouped by `job` and `instance`.
+/// Failed pushes are logged and retried at the next interval.
+pub fn push_metrics_periodically(gateway: String, job: &str, instance: &str, interval_ms: u64) {
+    let url = push_url(&gateway, job, instance);
+    info!("Pushing metrics to {}", url);
+    thread::spawn(move || {
+        let mut rt = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("Failed to create push gateway runtime");
+        let client = Client::new();
+        loop {
+            if let Err(e) = rt.block_on(push_metrics(&client, &url)) {
+                warn!("Failed to push metrics to {}: {}", url, e);
+            }
+            thread::sleep(Duration::from_millis(interval_ms));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_url() {
+        assert_eq!(
+            push_url("http://localhost:9091/", "libra_node", "a1b2"),
+            "http://localhost:9091/metrics/job/libra_node/instance/a1b2"
+        );
+    }
+}
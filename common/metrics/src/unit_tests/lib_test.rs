@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::super::*;
+use crate::cardinality::{CardinalityGuard, OTHER_LABEL_VALUE};
 use assert_approx_eq::assert_approx_eq;
 use once_cell::sync::Lazy;
 use prometheus::{core::Collector, proto::MetricFamily, Counter, IntCounter, Opts, Registry};
@@ -66,3 +67,14 @@ fn get_all_metrics_test() {
     }
 }
 }
+
+#[test]
+fn cardinality_guard_test() {
+    let guard = CardinalityGuard::new("cardinality_guard_test", 2);
+    assert_eq!(guard.label("a"), "a");
+    assert_eq!(guard.label("b"), "b");
+    assert_eq!(guard.label("c"), OTHER_LABEL_VALUE);
+    // values seen before the limit was reached keep their own bucket
+    assert_eq!(guard.label("a"), "a");
+    assert_eq!(guard.label("d"), OTHER_LABEL_VALUE);
+}
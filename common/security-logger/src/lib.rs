@@ -57,6 +57,12 @@ pub enum SecurityEvent {
     /// Network discovery received an invalid DiscoveryMsg
     InvalidDiscoveryMsg,
 
+    /// Network DHT received an invalid peer record
+    InvalidDhtRecord,
+
+    /// Network peer exchange received an invalid PexMsg
+    InvalidPexMsg,
+
     /// Error for testing
     #[cfg(test)]
     TestError,
@@ -20,6 +20,9 @@ pub const VALIDATOR_NETWORK_KEY: &str = "validator_network";
 
 /// Definitions of global data items (e.g., as held in secure storage)
 pub const EPOCH: &str = "epoch";
+pub const HANDOVER_EPOCH: &str = "handover_epoch";
+pub const HANDOVER_ROUND: &str = "handover_round";
 pub const LAST_VOTED_ROUND: &str = "last_voted_round";
 pub const PREFERRED_ROUND: &str = "preferred_round";
+pub const STANDBY: &str = "standby";
 pub const WAYPOINT: &str = "waypoint";
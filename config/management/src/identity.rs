@@ -0,0 +1,250 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::Error,
+    verify::{
+        compute_genesis, ed25519_from_storage, validator_account, validator_config, write_assert,
+        write_break, x25519_from_storage,
+    },
+    SingleBackend,
+};
+use libra_config::config::HANDSHAKE_VERSION;
+use libra_crypto::{ed25519::Ed25519PublicKey, x25519};
+use libra_global_constants::{CONSENSUS_KEY, FULLNODE_NETWORK_KEY, VALIDATOR_NETWORK_KEY};
+use libra_network_address::{NetworkAddress, Protocol, RawNetworkAddress};
+use libra_secure_storage::BoxedStorage;
+use libra_temppath::TempPath;
+use libra_types::{account_address::AccountAddress, validator_config::ValidatorConfig};
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::{self, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+const VALIDATOR_NETWORK_ADDRESS: &str = "validator_network_address";
+const FULLNODE_NETWORK_ADDRESS: &str = "fullnode_network_address";
+
+/// Exports the identity of a node, i.e., its public keys and the addresses it should advertise,
+/// and cross-checks it against the validator config registered on-chain
+#[derive(Debug, StructOpt)]
+pub struct VerifyIdentity {
+    #[structopt(flatten)]
+    backend: SingleBackend,
+    /// The genesis containing the registered validator config.
+    #[structopt(long)]
+    genesis_path: PathBuf,
+    /// The base validator network address, without the ln-noise-ik
+    /// and ln-handshake protocols. Defaults to the base of the
+    /// registered address.
+    #[structopt(long, verbatim_doc_comment)]
+    validator_address: Option<NetworkAddress>,
+    /// The base full node network address, see validator_address.
+    #[structopt(long)]
+    fullnode_address: Option<NetworkAddress>,
+}
+
+impl VerifyIdentity {
+    pub fn execute(self) -> Result<IdentityBundle, Error> {
+        let storage: BoxedStorage = self.backend.backend.try_into()?;
+        storage
+            .available()
+            .map_err(|e| Error::LocalStorageUnavailable(e.to_string()))?;
+
+        let account = validator_account(&storage)?;
+        let consensus_key = ed25519_from_storage(CONSENSUS_KEY, &storage)
+            .map_err(|e| Error::LocalStorageReadError(CONSENSUS_KEY, e))?;
+        let validator_network_key = x25519_from_storage(VALIDATOR_NETWORK_KEY, &storage)
+            .map_err(|e| Error::LocalStorageReadError(VALIDATOR_NETWORK_KEY, e))?;
+        let fullnode_network_key = x25519_from_storage(FULLNODE_NETWORK_KEY, &storage)
+            .map_err(|e| Error::LocalStorageReadError(FULLNODE_NETWORK_KEY, e))?;
+
+        let db_path = TempPath::new();
+        let (db_rw, _) = compute_genesis(&self.genesis_path, db_path.path())?;
+        let onchain_config = validator_config(account, db_rw.reader)?;
+        let onchain_validator_address =
+            decode_address(&onchain_config.validator_network_address, "validator")?;
+        let onchain_fullnode_address =
+            decode_address(&onchain_config.full_node_network_address, "full node")?;
+
+        let expected_validator_address = expected_address(
+            self.validator_address,
+            &onchain_validator_address,
+            validator_network_key,
+        )?;
+        let expected_fullnode_address = expected_address(
+            self.fullnode_address,
+            &onchain_fullnode_address,
+            fullnode_network_key,
+        )?;
+
+        Ok(IdentityBundle {
+            account,
+            consensus_key,
+            validator_network_key,
+            fullnode_network_key,
+            expected_validator_address,
+            expected_fullnode_address,
+            onchain_config,
+            onchain_validator_address,
+            onchain_fullnode_address,
+        })
+    }
+}
+
+/// The identity of a node, from its secure storage, alongside its validator config registered
+/// on-chain.
+#[derive(Debug)]
+pub struct IdentityBundle {
+    pub account: AccountAddress,
+    pub consensus_key: Ed25519PublicKey,
+    pub validator_network_key: x25519::PublicKey,
+    pub fullnode_network_key: x25519::PublicKey,
+    /// The addresses the node should advertise, carrying its network identity keys.
+    pub expected_validator_address: NetworkAddress,
+    pub expected_fullnode_address: NetworkAddress,
+    pub onchain_config: ValidatorConfig,
+    pub onchain_validator_address: NetworkAddress,
+    pub onchain_fullnode_address: NetworkAddress,
+}
+
+impl IdentityBundle {
+    /// Returns the names of the keys and addresses of the node which don't match the ones
+    /// registered on-chain.
+    pub fn mismatches(&self) -> Vec<&'static str> {
+        self.checks()
+            .into_iter()
+            .filter(|(_, matches)| !matches)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn checks(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            (
+                CONSENSUS_KEY,
+                self.consensus_key == self.onchain_config.consensus_public_key,
+            ),
+            (
+                VALIDATOR_NETWORK_KEY,
+                self.validator_network_key
+                    == self.onchain_config.validator_network_identity_public_key,
+            ),
+            (
+                FULLNODE_NETWORK_KEY,
+                self.fullnode_network_key
+                    == self.onchain_config.full_node_network_identity_public_key,
+            ),
+            (
+                VALIDATOR_NETWORK_ADDRESS,
+                self.expected_validator_address == self.onchain_validator_address,
+            ),
+            (
+                FULLNODE_NETWORK_ADDRESS,
+                self.expected_fullnode_address == self.onchain_fullnode_address,
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for IdentityBundle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buffer = String::new();
+        writeln!(buffer, "Identity of {}:", self.account)?;
+        write_break(&mut buffer);
+        writeln!(buffer, "{} - {}", CONSENSUS_KEY, self.consensus_key)?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            VALIDATOR_NETWORK_KEY, self.validator_network_key
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            FULLNODE_NETWORK_KEY, self.fullnode_network_key
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            VALIDATOR_NETWORK_ADDRESS, self.expected_validator_address
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            FULLNODE_NETWORK_ADDRESS, self.expected_fullnode_address
+        )?;
+
+        write_break(&mut buffer);
+        writeln!(buffer, "Registered on-chain")?;
+        write_break(&mut buffer);
+        writeln!(
+            buffer,
+            "{} - {}",
+            CONSENSUS_KEY, self.onchain_config.consensus_public_key
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            VALIDATOR_NETWORK_KEY, self.onchain_config.validator_network_identity_public_key
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            FULLNODE_NETWORK_KEY, self.onchain_config.full_node_network_identity_public_key
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            VALIDATOR_NETWORK_ADDRESS, self.onchain_validator_address
+        )?;
+        writeln!(
+            buffer,
+            "{} - {}",
+            FULLNODE_NETWORK_ADDRESS, self.onchain_fullnode_address
+        )?;
+
+        write_break(&mut buffer);
+        for (name, matches) in self.checks() {
+            write_assert(&mut buffer, name, matches);
+        }
+        write_break(&mut buffer);
+        write!(f, "{}", buffer)
+    }
+}
+
+fn decode_address(address: &RawNetworkAddress, name: &str) -> Result<NetworkAddress, Error> {
+    NetworkAddress::try_from(address).map_err(|e| {
+        Error::UnexpectedError(format!(
+            "Unable to parse the registered {} address: {}",
+            name, e
+        ))
+    })
+}
+
+/// The address advertised with `network_key`: `base_address`, or the base of the registered
+/// address, followed by the ln-noise-ik and ln-handshake protocols.
+fn expected_address(
+    base_address: Option<NetworkAddress>,
+    onchain_address: &NetworkAddress,
+    network_key: x25519::PublicKey,
+) -> Result<NetworkAddress, Error> {
+    let base_address = match base_address {
+        Some(base_address) => base_address,
+        None => {
+            let base_protos: Vec<_> = onchain_address
+                .as_slice()
+                .iter()
+                .take_while(|proto| !matches!(proto, Protocol::NoiseIK(_)))
+                .cloned()
+                .collect();
+            NetworkAddress::try_from(base_protos).map_err(|e| {
+                Error::UnexpectedError(format!(
+                    "Registered address {} has no base address: {}",
+                    onchain_address, e
+                ))
+            })?
+        }
+    };
+    Ok(base_address.append_prod_protos(network_key, HANDSHAKE_VERSION))
+}
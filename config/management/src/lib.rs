@@ -5,6 +5,7 @@
 
 mod error;
 mod genesis;
+mod identity;
 mod key;
 mod layout;
 mod secure_backend;
@@ -54,6 +55,8 @@ pub enum Command {
     ValidatorConfig(crate::validator_config::ValidatorConfig),
     #[structopt(about = "Verifies and prints the current configuration state")]
     Verify(crate::verify::Verify),
+    #[structopt(about = "Exports the identity of a node and verifies it against the chain")]
+    VerifyIdentity(crate::identity::VerifyIdentity),
 }
 
 #[derive(Debug, PartialEq)]
@@ -66,6 +69,7 @@ pub enum CommandName {
     SetLayout,
     ValidatorConfig,
     Verify,
+    VerifyIdentity,
 }
 
 impl From<&Command> for CommandName {
@@ -79,6 +83,7 @@ impl From<&Command> for CommandName {
             Command::SetLayout(_) => CommandName::SetLayout,
             Command::ValidatorConfig(_) => CommandName::ValidatorConfig,
             Command::Verify(_) => CommandName::Verify,
+            Command::VerifyIdentity(_) => CommandName::VerifyIdentity,
         }
     }
 }
@@ -94,6 +99,7 @@ impl std::fmt::Display for CommandName {
             CommandName::SetLayout => "set-layout",
             CommandName::ValidatorConfig => "validator-config",
             CommandName::Verify => "verify",
+            CommandName::VerifyIdentity => "verify-identity",
         };
         write!(f, "{}", name)
     }
@@ -110,6 +116,7 @@ impl Command {
             Command::SetLayout(_) => self.set_layout().unwrap().to_string(),
             Command::ValidatorConfig(_) => format!("{:?}", self.validator_config().unwrap()),
             Command::Verify(_) => self.verify().unwrap(),
+            Command::VerifyIdentity(_) => self.verify_identity().unwrap().to_string(),
         }
     }
 
@@ -200,6 +207,17 @@ impl Command {
             ))
         }
     }
+
+    pub fn verify_identity(self) -> Result<crate::identity::IdentityBundle, Error> {
+        if let Command::VerifyIdentity(verify_identity) = self {
+            verify_identity.execute()
+        } else {
+            Err(Error::UnexpectedCommand(
+                CommandName::VerifyIdentity,
+                CommandName::from(&self),
+            ))
+        }
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -329,6 +347,29 @@ pub mod tests {
         assert!(contents.is_empty());
         file.read_to_end(&mut contents).unwrap();
         assert!(!contents.is_empty());
+
+        // Step 5) Verify the identities of the operators against genesis
+
+        for ns in [alice_ns, bob_ns, carol_ns].iter() {
+            let identity = helper.verify_identity(ns, genesis_path.path()).unwrap();
+            assert!(identity.mismatches().is_empty());
+        }
+
+        // A rotated network key no longer matches the registered key and address
+        helper
+            .storage(alice_ns.into())
+            .rotate_key(libra_global_constants::VALIDATOR_NETWORK_KEY)
+            .unwrap();
+        let identity = helper
+            .verify_identity(alice_ns, genesis_path.path())
+            .unwrap();
+        assert_eq!(
+            identity.mismatches(),
+            vec![
+                libra_global_constants::VALIDATOR_NETWORK_KEY,
+                "validator_network_address"
+            ]
+        );
     }
 
     #[test]
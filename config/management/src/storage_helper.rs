@@ -256,4 +256,28 @@ impl StorageHelper {
         let command = Command::from_iter(args.split_whitespace());
         command.verify()
     }
+
+    pub fn verify_identity(
+        &self,
+        namespace: &str,
+        genesis_path: &Path,
+    ) -> Result<crate::identity::IdentityBundle, Error> {
+        let args = format!(
+            "
+                management
+                verify-identity
+                --backend backend={backend};\
+                    path={path};\
+                    namespace={ns}
+                --genesis-path {genesis_path}
+            ",
+            backend = crate::secure_backend::DISK,
+            path = self.path_string(),
+            ns = namespace,
+            genesis_path = genesis_path.to_str().expect("Unable to parse genesis_path"),
+        );
+
+        let command = Command::from_iter(args.split_whitespace());
+        command.verify_identity()
+    }
 }
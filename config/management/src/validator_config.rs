@@ -16,6 +16,7 @@ use libra_types::{
 };
 use std::{
     convert::{TryFrom, TryInto},
+    sync::Arc,
     time::Duration,
 };
 use structopt::StructOpt;
@@ -96,7 +97,7 @@ impl ValidatorConfig {
                 Error::LocalStorageSigningError("validator-config", OPERATOR_KEY, e.to_string())
             })?;
         let signed_txn = SignedTransaction::new(raw_transaction, operator_key, signature);
-        let txn = Transaction::UserTransaction(signed_txn);
+        let txn = Transaction::UserTransaction(Arc::new(signed_txn));
 
         // Step 3) Submit to remote storage
 
@@ -91,12 +91,12 @@ impl Verify {
     }
 }
 
-fn write_assert(buffer: &mut String, name: &str, value: bool) {
+pub(crate) fn write_assert(buffer: &mut String, name: &str, value: bool) {
     let value = if value { "match" } else { "MISMATCH" };
     writeln!(buffer, "{} - {}", name, value).unwrap();
 }
 
-fn write_break(buffer: &mut String) {
+pub(crate) fn write_break(buffer: &mut String) {
     writeln!(
         buffer,
         "====================================================================================",
@@ -205,7 +205,7 @@ fn compare_genesis(
 
 /// Compute the ledger given a genesis writeset transaction and return access to that ledger and
 /// the waypoint for that state.
-fn compute_genesis(
+pub(crate) fn compute_genesis(
     genesis_path: &PathBuf,
     db_path: &Path,
 ) -> Result<(DbReaderWriter, Waypoint), Error> {
@@ -229,7 +229,7 @@ fn compute_genesis(
 }
 
 /// Read from the ledger the validator config from the validator set for the specified account
-fn validator_config(
+pub(crate) fn validator_config(
     validator_account: AccountAddress,
     reader: Arc<dyn DbReader>,
 ) -> Result<ValidatorConfig, Error> {
@@ -253,7 +253,7 @@ fn validator_config(
     Ok(info.config().clone())
 }
 
-fn validator_account(storage: &BoxedStorage) -> Result<AccountAddress, Error> {
+pub(crate) fn validator_account(storage: &BoxedStorage) -> Result<AccountAddress, Error> {
     let account = storage
         .get(libra_global_constants::OPERATOR_ACCOUNT)
         .map_err(|e| {
@@ -275,7 +275,7 @@ fn validator_account(storage: &BoxedStorage) -> Result<AccountAddress, Error> {
     })
 }
 
-fn ed25519_from_storage(
+pub(crate) fn ed25519_from_storage(
     key_name: &'static str,
     storage: &BoxedStorage,
 ) -> Result<Ed25519PublicKey, String> {
@@ -285,7 +285,7 @@ fn ed25519_from_storage(
         .map_err(|e| e.to_string())
 }
 
-fn x25519_from_storage(
+pub(crate) fn x25519_from_storage(
     key_name: &'static str,
     storage: &BoxedStorage,
 ) -> Result<x25519::PublicKey, String> {
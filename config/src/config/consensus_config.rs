@@ -14,6 +14,11 @@ pub struct ConsensusConfig {
     pub round_initial_timeout_ms: u64,
     pub proposer_type: ConsensusProposerType,
     pub safety_rules: SafetyRulesConfig,
+    // Log the timeline of every round as a structured log record
+    pub log_round_timeline: bool,
+    // Start as a hot standby of the validator, which follows consensus without signing anything
+    // until promoted
+    pub standby: bool,
 }
 
 impl Default for ConsensusConfig {
@@ -28,6 +33,8 @@ impl Default for ConsensusConfig {
             max_pruned_blocks_in_mem: 10000,
             round_initial_timeout_ms: 1000,
             safety_rules: SafetyRulesConfig::default(),
+            log_round_timeline: false,
+            standby: false,
         }
     }
 }
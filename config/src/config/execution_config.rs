@@ -21,6 +21,10 @@ pub struct ExecutionConfig {
     pub genesis: Option<Transaction>,
     pub genesis_file_location: PathBuf,
     pub service: ExecutionCorrectnessService,
+    /// Whether a full node also executes the transactions it syncs with the candidate VM the node
+    /// was built with, logging the outputs which differ from the ones of the VM without affecting
+    /// them. Ignored by validators.
+    pub shadow_vm: bool,
 }
 
 impl std::fmt::Debug for ExecutionConfig {
@@ -33,8 +37,8 @@ impl std::fmt::Debug for ExecutionConfig {
         }
         write!(
             f,
-            ", genesis_file_location: {:?} }}",
-            self.genesis_file_location
+            ", genesis_file_location: {:?}, shadow_vm: {:?} }}",
+            self.genesis_file_location, self.shadow_vm
         )?;
         self.service.fmt(f)
     }
@@ -46,6 +50,7 @@ impl Default for ExecutionConfig {
             genesis: None,
             genesis_file_location: PathBuf::new(),
             service: ExecutionCorrectnessService::Thread,
+            shadow_vm: false,
         }
     }
 }
@@ -16,8 +16,16 @@ pub struct MempoolConfig {
     pub capacity: usize,
     // max number of transactions per user in Mempool
     pub capacity_per_user: usize,
+    // max number of parked transactions, i.e., waiting for transactions with lower sequence
+    // numbers, in Mempool
+    pub parking_lot_capacity: usize,
+    // max number of parked transactions per user in Mempool
+    pub parking_lot_capacity_per_user: usize,
+    // max number of transactions which expired without being committed remembered for clients
+    pub expired_transactions_capacity: usize,
     pub system_transaction_timeout_secs: u64,
     pub system_transaction_gc_interval_ms: u64,
+    pub admission_control: AdmissionControlConfig,
 }
 
 impl Default for MempoolConfig {
@@ -32,8 +40,38 @@ impl Default for MempoolConfig {
             max_broadcasts_per_peer: 25,
             capacity: 1_000_000,
             capacity_per_user: 100,
+            parking_lot_capacity: 1_000_000,
+            parking_lot_capacity_per_user: 100,
+            expired_transactions_capacity: 100_000,
             system_transaction_timeout_secs: 86400,
             system_transaction_gc_interval_ms: 180_000,
+            admission_control: AdmissionControlConfig::default(),
+        }
+    }
+}
+
+/// Checks performed by full nodes on incoming transactions before accepting them into mempool,
+/// and thus before forwarding them upstream. Validators ignore this config.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdmissionControlConfig {
+    pub enabled: bool,
+    pub verify_signature: bool,
+    // reject transactions whose sender can't afford max_gas_amount * gas_unit_price
+    pub check_balance: bool,
+    // max distance between the sequence number of a transaction and the one of its sender
+    pub max_sequence_number_gap: Option<u64>,
+    pub min_gas_unit_price: u64,
+}
+
+impl Default for AdmissionControlConfig {
+    fn default() -> AdmissionControlConfig {
+        AdmissionControlConfig {
+            enabled: false,
+            verify_signature: true,
+            check_balance: true,
+            max_sequence_number_gap: Some(100),
+            min_gas_unit_price: 0,
         }
     }
 }
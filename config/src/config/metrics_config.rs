@@ -10,6 +10,9 @@ pub struct MetricsConfig {
     pub collection_interval_ms: u64,
     pub dir: PathBuf,
     pub enabled: bool,
+    /// URL of a Prometheus push gateway to push the metrics to, for nodes that can't be scraped
+    pub push_gateway: Option<String>,
+    pub push_interval_ms: u64,
     #[serde(skip)]
     data_dir: PathBuf,
 }
@@ -21,6 +24,8 @@ impl Default for MetricsConfig {
             data_dir: PathBuf::from("/opt/libra/data"),
             enabled: false,
             dir: PathBuf::from("metrics"),
+            push_gateway: None,
+            push_interval_ms: 15_000,
         }
     }
 }
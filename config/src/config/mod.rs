@@ -34,6 +34,8 @@ mod mempool_config;
 pub use mempool_config::*;
 mod network_config;
 pub use network_config::*;
+mod resource_limits_config;
+pub use resource_limits_config::*;
 mod secure_backend_config;
 pub use secure_backend_config::*;
 mod state_sync_config;
@@ -46,7 +48,7 @@ mod upstream_config;
 pub use upstream_config::*;
 mod test_config;
 use crate::network_id::NetworkId;
-use libra_types::waypoint::Waypoint;
+use libra_types::{chain_id::ChainId, waypoint::Waypoint};
 pub use test_config::*;
 
 /// Config pulls in configuration information from the config file.
@@ -78,6 +80,8 @@ pub struct NodeConfig {
     #[serde(default)]
     pub mempool: MempoolConfig,
     #[serde(default)]
+    pub resource_limits: ResourceLimitsConfig,
+    #[serde(default)]
     pub state_sync: StateSyncConfig,
     #[serde(default)]
     pub storage: StorageConfig,
@@ -95,6 +99,12 @@ pub struct BaseConfig {
     data_dir: PathBuf,
     pub role: RoleType,
     pub waypoint: WaypointConfig,
+    /// The chain the node belongs to. Features only meant for test networks, e.g., the noise
+    /// keylog, require it to be set to a chain other than mainnet.
+    pub chain_id: Option<ChainId>,
+    /// How long a subsystem waits at startup for the subsystems it depends on to be ready,
+    /// e.g., consensus for state sync to catch up to the waypoint.
+    pub startup_timeout_ms: u64,
 }
 
 impl Default for BaseConfig {
@@ -103,6 +113,8 @@ impl Default for BaseConfig {
             data_dir: PathBuf::from("/opt/libra/data/commmon"),
             role: RoleType::Validator,
             waypoint: WaypointConfig::None,
+            chain_id: None,
+            startup_timeout_ms: 30 * 60 * 1000,
         }
     }
 }
@@ -197,6 +209,7 @@ impl NodeConfig {
             logger: self.logger.clone(),
             metrics: self.metrics.clone(),
             mempool: self.mempool.clone(),
+            resource_limits: self.resource_limits.clone(),
             state_sync: self.state_sync.clone(),
             storage: self.storage.clone(),
             test: None,
@@ -8,7 +8,7 @@ use crate::{
     utils,
 };
 use anyhow::{anyhow, ensure, Result};
-use libra_crypto::{x25519, Uniform};
+use libra_crypto::{ed25519::Ed25519PrivateKey, x25519, Uniform};
 use libra_network_address::NetworkAddress;
 use libra_types::{transaction::authenticator::AuthenticationKey, PeerId};
 use rand::{
@@ -16,7 +16,12 @@ use rand::{
     Rng, SeedableRng,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, convert::TryFrom, path::PathBuf, string::ToString};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    path::PathBuf,
+    string::ToString,
+};
 
 const NETWORK_PEERS_DEFAULT: &str = "network_peers.config.toml";
 const SEED_PEERS_DEFAULT: &str = "seed_peers.toml";
@@ -37,8 +42,18 @@ pub struct NetworkConfig {
     pub listen_address: NetworkAddress,
     // The address that this node advertises to other nodes for the discovery protocol.
     pub advertised_address: NetworkAddress,
+    // Other addresses advertised along with `advertised_address`, e.g., the IPv4 address of a
+    // node whose `advertised_address` is its IPv6 address.
+    pub additional_advertised_addresses: Vec<NetworkAddress>,
+    // Whether a `listen_address` on `/ip6/::` also accepts IPv4 connections, whatever the default
+    // of the host.
+    pub dual_stack: bool,
     pub discovery_interval_ms: u64,
     pub connectivity_check_interval_ms: u64,
+    // Delay after which the next address of a peer is dialed while the dials of its previous
+    // addresses are pending, keeping the first connection established. The addresses are dialed
+    // one at a time if unset.
+    pub dial_stagger_ms: Option<u64>,
     // If the network uses remote authentication, only trusted peers are allowed to connect.
     // Otherwise, any node can connect.
     // TODO(philiphayes): rename this flag. should reflect `AuthenticationMode` in
@@ -46,6 +61,18 @@ pub struct NetworkConfig {
     pub enable_remote_authentication: bool,
     // Enable this network to use either gossip discovery or onchain discovery.
     pub discovery_method: DiscoveryMethod,
+    // Key-value metadata advertised to other peers by gossip discovery, e.g. the URL of the
+    // JSON-RPC endpoint of this node. Subject to the size limits of the discovery protocol.
+    pub discovery_metadata: BTreeMap<String, String>,
+    // Whether gossip discovery only advertises the addresses which a connected peer managed to
+    // dial back. Addresses with a DNS name are advertised as is.
+    pub verify_advertised_addresses: bool,
+    // Whether to map the listen port on the NAT gateway with NAT-PMP, and advertise the external
+    // address with gossip discovery, for full nodes run behind a home router.
+    pub nat_port_mapping: bool,
+    // Address on which to serve as a relay for the peers which can't accept inbound connections,
+    // e.g., behind a NAT. These peers listen on `/<relay_address>/p2p-circuit`.
+    pub relay_address: Option<NetworkAddress>,
     // network peers are the nodes allowed to connect when the network is started in authenticated
     // mode.
     #[serde(skip)]
@@ -55,8 +82,42 @@ pub struct NetworkConfig {
     #[serde(skip)]
     pub seed_peers: SeedPeersConfig,
     pub seed_peers_file: PathBuf,
+    // File to which the trusted peers are persisted on every update, along with the epoch of the
+    // validator set they came from, so that they are trusted right away on restart, before
+    // storage is open.
+    pub trusted_peers_file: Option<PathBuf>,
+    // File to which the addresses at which the peers are reached are persisted, so that they are
+    // dialed right away on restart instead of bootstrapping from the seed peers.
+    pub address_book_file: Option<PathBuf>,
+    // Interval of the probes of the latency protocol, which measures the latency and jitter between
+    // the validators, exported as metrics. The protocol is disabled if unset.
+    pub latency_probe_interval_ms: Option<u64>,
     pub identity: Identity,
+    // Key signing the notes of gossip discovery, whose X25519 counterpart must be the identity
+    // key. Generated along with an identity stored in the config; an identity from storage signs
+    // with its key in storage instead.
+    pub discovery_signing_key: Option<KeyPair<Ed25519PrivateKey>>,
     pub network_id: NetworkId,
+    // Export the keys of every Noise session, so that captured traffic can be decrypted while
+    // debugging the protocol. Only honored by nodes built with the noise-keylog feature, whose
+    // base config names a chain other than mainnet.
+    pub enable_noise_keylog: bool,
+    // File to which the keys are appended when enable_noise_keylog is set. Can also be set
+    // through the LIBRA_NOISE_KEYLOG_FILE environment variable.
+    pub noise_keylog_file: Option<PathBuf>,
+    // Advertise the messaging protocol V2 in the handshakes, exchanging the handshake extensions
+    // (compression codecs, protocol encodings and observed address) with the peers which support
+    // them. The nodes which predate V2 can't decode such a handshake, so this is only enabled once
+    // every node of the network is upgraded.
+    pub enable_handshake_extensions: bool,
+    // Listeners accepting the peers of other networks, e.g., the full nodes of a validator, on
+    // this network instead of running a separate network for them.
+    pub additional_listeners: Vec<AdditionalListenerConfig>,
+    // Budgets of the resources of this network, and of each of its additional listeners, so that
+    // a flood of traffic on one network can't starve the other networks of the node.
+    pub resource_quota: ResourceQuotaConfig,
+    // Socket options of the TCP connections of this network.
+    pub tcp: TcpConfig,
 }
 
 impl Default for NetworkConfig {
@@ -71,15 +132,32 @@ impl NetworkConfig {
             network_id,
             listen_address: "/ip4/0.0.0.0/tcp/6180".parse().unwrap(),
             advertised_address: "/ip4/127.0.0.1/tcp/6180".parse().unwrap(),
+            additional_advertised_addresses: Vec::new(),
+            dual_stack: false,
             discovery_interval_ms: 1000,
             connectivity_check_interval_ms: 5000,
+            dial_stagger_ms: Some(250),
             enable_remote_authentication: true,
             discovery_method: DiscoveryMethod::Gossip,
+            discovery_metadata: BTreeMap::new(),
+            verify_advertised_addresses: false,
+            nat_port_mapping: false,
+            relay_address: None,
             identity: Identity::None,
+            discovery_signing_key: None,
             network_peers_file: PathBuf::new(),
             network_peers: NetworkPeersConfig::default(),
             seed_peers_file: PathBuf::new(),
             seed_peers: SeedPeersConfig::default(),
+            trusted_peers_file: None,
+            address_book_file: None,
+            latency_probe_interval_ms: None,
+            enable_noise_keylog: false,
+            noise_keylog_file: None,
+            enable_handshake_extensions: false,
+            additional_listeners: Vec::new(),
+            resource_quota: ResourceQuotaConfig::default(),
+            tcp: TcpConfig::default(),
         };
         config.prepare_identity();
         config
@@ -94,15 +172,32 @@ impl NetworkConfig {
             network_id: self.network_id.clone(),
             listen_address: self.listen_address.clone(),
             advertised_address: self.advertised_address.clone(),
+            additional_advertised_addresses: self.additional_advertised_addresses.clone(),
+            dual_stack: self.dual_stack,
             discovery_interval_ms: self.discovery_interval_ms,
             connectivity_check_interval_ms: self.connectivity_check_interval_ms,
+            dial_stagger_ms: self.dial_stagger_ms,
             enable_remote_authentication: self.enable_remote_authentication,
             discovery_method: self.discovery_method,
+            discovery_metadata: self.discovery_metadata.clone(),
+            verify_advertised_addresses: self.verify_advertised_addresses,
+            nat_port_mapping: self.nat_port_mapping,
+            relay_address: self.relay_address.clone(),
             identity: Identity::None,
+            discovery_signing_key: None,
             network_peers_file: self.network_peers_file.clone(),
             network_peers: self.network_peers.clone(),
             seed_peers_file: self.seed_peers_file.clone(),
             seed_peers: self.seed_peers.clone(),
+            trusted_peers_file: self.trusted_peers_file.clone(),
+            address_book_file: self.address_book_file.clone(),
+            latency_probe_interval_ms: self.latency_probe_interval_ms,
+            enable_noise_keylog: self.enable_noise_keylog,
+            noise_keylog_file: self.noise_keylog_file.clone(),
+            enable_handshake_extensions: self.enable_handshake_extensions,
+            additional_listeners: self.additional_listeners.clone(),
+            resource_quota: self.resource_quota.clone(),
+            tcp: self.tcp.clone(),
         }
     }
 
@@ -123,6 +218,17 @@ impl NetworkConfig {
         if self.listen_address.to_string().is_empty() {
             self.listen_address = utils::get_local_ip().ok_or_else(|| anyhow!("No local IP"))?;
         }
+        for listener in &mut self.additional_listeners {
+            ensure!(
+                listener.network_id != self.network_id,
+                "Additional listeners must be for another network than {:?}",
+                self.network_id
+            );
+            if !listener.network_peers_file.as_os_str().is_empty() {
+                let path = root_dir.full_path(&listener.network_peers_file);
+                listener.network_peers = NetworkPeersConfig::load_config(&path)?;
+            }
+        }
 
         if network_role.is_validator() {
             ensure!(
@@ -144,11 +250,12 @@ impl NetworkConfig {
             Identity::FromStorage(_) => (),
             Identity::None => {
                 let mut rng = StdRng::from_seed(OsRng.gen());
-                let key = x25519::PrivateKey::generate(&mut rng);
+                let (signing_key, key) = generate_identity_key(&mut rng);
                 let peer_id = AuthenticationKey::try_from(key.public_key().as_slice())
                     .unwrap()
                     .derived_address();
                 self.identity = Identity::from_config(key, peer_id);
+                self.discovery_signing_key = Some(KeyPair::load(signing_key));
             }
             Identity::FromConfig(config) => {
                 let pubkey = config.keypair.public_key();
@@ -192,7 +299,7 @@ impl NetworkConfig {
     }
 
     pub fn random_with_peer_id(&mut self, rng: &mut StdRng, peer_id: Option<PeerId>) {
-        let identity_key = x25519::PrivateKey::generate(rng);
+        let (signing_key, identity_key) = generate_identity_key(rng);
         let peer_id = if let Some(peer_id) = peer_id {
             peer_id
         } else {
@@ -201,6 +308,7 @@ impl NetworkConfig {
                 .derived_address()
         };
         self.identity = Identity::from_config(identity_key, peer_id);
+        self.discovery_signing_key = Some(KeyPair::load(signing_key));
     }
 
     #[cfg(any(test, feature = "fuzzing"))]
@@ -209,6 +317,93 @@ impl NetworkConfig {
     }
 }
 
+/// Generates an Ed25519 signing key, and the identity key which is its X25519 counterpart.
+fn generate_identity_key(rng: &mut StdRng) -> (Ed25519PrivateKey, x25519::PrivateKey) {
+    let signing_key = Ed25519PrivateKey::generate(rng);
+    let identity_key = x25519::PrivateKey::from_ed25519_private_bytes(&signing_key.to_bytes())
+        .expect("An Ed25519 key should convert to an X25519 key");
+    (signing_key, identity_key)
+}
+
+/// A listener of a network accepting the peers of another network, which share the resources of
+/// the network but only get to use its mempool, state sync and health checker protocols.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdditionalListenerConfig {
+    pub network_id: NetworkId,
+    pub listen_address: NetworkAddress,
+    // If the listener uses remote authentication, only its network peers are allowed to connect.
+    // Otherwise, any node can connect.
+    #[serde(default)]
+    pub enable_remote_authentication: bool,
+    #[serde(skip)]
+    pub network_peers: NetworkPeersConfig,
+    #[serde(default)]
+    pub network_peers_file: PathBuf,
+}
+
+/// Budgets of the resources of a network. Unset limits are unbounded.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceQuotaConfig {
+    // Worker threads of the runtime running the network, one per core if not set.
+    pub runtime_threads: Option<usize>,
+    // Inbound connections beyond this number are closed, before their handshake on the listener
+    // they connected to.
+    pub max_inbound_connections: Option<usize>,
+    // Inbound connections from an IP address beyond this number are closed before their handshake.
+    pub max_inbound_connections_per_ip: Option<usize>,
+    // Inbound messages are dropped while the ones received and not yet processed by the network
+    // add up to this number of bytes.
+    pub max_inbound_message_bytes: Option<usize>,
+    // New RPCs are rejected while the requests of the inbound and outbound RPCs in flight add up to
+    // this number of bytes.
+    pub max_inflight_rpc_bytes: Option<usize>,
+    // Bytes sent per second by all the connections of the network, including the overhead of the
+    // protocols, beyond which the connections wait.
+    pub max_outbound_bytes_per_sec: Option<u64>,
+    // Bytes received per second by all the connections of the network, including the overhead of
+    // the protocols, beyond which the connections stop reading.
+    pub max_inbound_bytes_per_sec: Option<u64>,
+}
+
+/// Socket options of the TCP connections of a network. Unset options keep the defaults of the
+/// host.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TcpConfig {
+    // Whether to set TCP_NODELAY, so that small messages aren't delayed to be coalesced.
+    pub nodelay: bool,
+    // Idle time after which keepalive probes are sent. Keepalives are disabled if not set.
+    pub keepalive_idle_ms: Option<u64>,
+    // Interval between the keepalive probes, if keepalives are enabled.
+    pub keepalive_interval_ms: Option<u64>,
+    // Sizes of the socket buffers. High-latency links, e.g., across continents, need buffers of
+    // at least their bandwidth times their round-trip time.
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+    // Whether the listeners set SO_REUSEPORT, so that a hot-standby node can listen on the same
+    // port, and take it over when this one exits.
+    pub reuse_port: bool,
+    // Network interface the listeners are bound to, e.g., `eth1` on a multi-homed host, or any
+    // interface if not set. Requires CAP_NET_RAW, and is only supported on Linux.
+    pub bind_device: Option<String>,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive_idle_ms: None,
+            keepalive_interval_ms: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            reuse_port: false,
+            bind_device: None,
+        }
+    }
+}
+
 // This is separated to another config so that it can be written to its own file
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct SeedPeersConfig {
@@ -246,7 +441,7 @@ impl std::fmt::Debug for NetworkPeersConfig {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct NetworkPeerInfo {
     #[serde(rename = "ni")]
     pub identity_public_key: x25519::PublicKey,
@@ -258,6 +453,8 @@ pub enum DiscoveryMethod {
     // default until we can deprecate
     Gossip,
     Onchain,
+    // Announces the node, and discovers its peers, over mDNS on the local network
+    Mdns,
     None,
 }
 
@@ -428,6 +625,104 @@ mod test {
         assert_ne!(config.advertised_address.to_string(), "");
     }
 
+    #[test]
+    fn test_load_additional_listeners() {
+        let (mut config, path) = generate_config();
+        let root_dir = RootPath::new_path(path.path());
+        let mut listener = AdditionalListenerConfig {
+            network_id: config.network_id.clone(),
+            listen_address: "/ip4/0.0.0.0/tcp/6181".parse().unwrap(),
+            enable_remote_authentication: false,
+            network_peers: NetworkPeersConfig::default(),
+            network_peers_file: PathBuf::new(),
+        };
+
+        // A listener must be for another network
+        config.additional_listeners = vec![listener.clone()];
+        config.load(&root_dir, RoleType::FullNode).unwrap_err();
+
+        listener.network_id = NetworkId::vfn_network();
+        config.additional_listeners = vec![listener.clone()];
+        config.load(&root_dir, RoleType::FullNode).unwrap();
+        assert_eq!(config.additional_listeners, vec![listener]);
+    }
+
+    #[test]
+    fn test_parse_resource_quota() {
+        let quota: ResourceQuotaConfig =
+            toml::from_str("runtime_threads = 2\nmax_inbound_connections = 100\n").unwrap();
+        assert_eq!(quota.runtime_threads, Some(2));
+        assert_eq!(quota.max_inbound_connections, Some(100));
+        assert_eq!(quota.max_inbound_connections_per_ip, None);
+        assert_eq!(quota.max_inbound_message_bytes, None);
+        assert_eq!(quota.max_inflight_rpc_bytes, None);
+
+        let quota: ResourceQuotaConfig = toml::from_str(
+            "max_outbound_bytes_per_sec = 1000000\nmax_inbound_bytes_per_sec = 2000000\n\
+             max_inflight_rpc_bytes = 64000000\nmax_inbound_connections_per_ip = 8\n",
+        )
+        .unwrap();
+        assert_eq!(quota.max_inflight_rpc_bytes, Some(64_000_000));
+        assert_eq!(quota.max_inbound_connections_per_ip, Some(8));
+        assert_eq!(quota.max_outbound_bytes_per_sec, Some(1_000_000));
+        assert_eq!(quota.max_inbound_bytes_per_sec, Some(2_000_000));
+
+        toml::from_str::<ResourceQuotaConfig>("max_outbound_connections = 1\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_tcp_config() {
+        let tcp: TcpConfig = toml::from_str("").unwrap();
+        assert_eq!(tcp, TcpConfig::default());
+        assert!(tcp.nodelay);
+
+        let tcp: TcpConfig = toml::from_str(
+            "keepalive_idle_ms = 30000\nkeepalive_interval_ms = 5000\n\
+             recv_buffer_size = 8388608\nsend_buffer_size = 8388608\n",
+        )
+        .unwrap();
+        assert!(tcp.nodelay);
+        assert_eq!(tcp.keepalive_idle_ms, Some(30_000));
+        assert_eq!(tcp.keepalive_interval_ms, Some(5_000));
+        assert_eq!(tcp.recv_buffer_size, Some(8_388_608));
+        assert_eq!(tcp.send_buffer_size, Some(8_388_608));
+        assert!(!tcp.reuse_port);
+
+        let tcp: TcpConfig = toml::from_str("reuse_port = true\nbind_device = \"eth1\"\n").unwrap();
+        assert!(tcp.reuse_port);
+        assert_eq!(tcp.bind_device.as_deref(), Some("eth1"));
+
+        toml::from_str::<TcpConfig>("keepalive = true\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_verify_dns_seed_peers() {
+        let pubkey = "080e287879c918794170e258bfaddd75acac5b3e350419044655e4983a487120";
+        let addrs = vec![
+            format!(
+                "/dns4/seed.example.com/tcp/6180/ln-noise-ik/{}/ln-handshake/0",
+                pubkey
+            ),
+            format!(
+                "/dns/seed.example.com/tcp/6180/ws/ln-noise-ik/{}/ln-handshake/0",
+                pubkey
+            ),
+        ];
+        let mut seed_peers = SeedPeersConfig::default();
+        seed_peers.seed_peers.insert(
+            PeerId::random(),
+            addrs.iter().map(|addr| addr.parse().unwrap()).collect(),
+        );
+        seed_peers.verify_libranet_addrs().unwrap();
+
+        // The transport upgrade protocols are still required
+        seed_peers.seed_peers.insert(
+            PeerId::random(),
+            vec!["/dns4/seed.example.com/tcp/6180".parse().unwrap()],
+        );
+        seed_peers.verify_libranet_addrs().unwrap_err();
+    }
+
     fn generate_config() -> (NetworkConfig, TempPath) {
         let temp_dir = TempPath::new();
         temp_dir.create_as_dir().expect("error creating tempdir");
@@ -0,0 +1,45 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// Limits of the resources used by the node, beyond which it sheds load: it pauses the inbound
+/// connections of the public network, then shrinks mempool, then throttles JSON-RPC, one step per
+/// check, and recovers in the reverse order once the usage falls back below the limits.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceLimitsConfig {
+    /// Resident memory of the node in bytes, unlimited if unset
+    pub max_memory_bytes: Option<u64>,
+    /// Size in bytes of the files in the storage directory, unlimited if unset
+    pub max_disk_bytes: Option<u64>,
+    /// The node recovers once its usage falls below this percentage of every limit
+    pub recovery_percent: u64,
+    pub check_interval_ms: u64,
+    /// Capacity of mempool while shrunk, as a percentage of its configured capacity
+    pub shrunk_mempool_capacity_percent: u64,
+    /// Requests served per second by JSON-RPC while throttled
+    pub throttled_rpc_requests_per_sec: u64,
+}
+
+pub const DEFAULT_THROTTLED_RPC_REQUESTS_PER_SEC: u64 = 100;
+
+impl Default for ResourceLimitsConfig {
+    fn default() -> ResourceLimitsConfig {
+        ResourceLimitsConfig {
+            max_memory_bytes: None,
+            max_disk_bytes: None,
+            recovery_percent: 90,
+            check_interval_ms: 10_000,
+            shrunk_mempool_capacity_percent: 50,
+            throttled_rpc_requests_per_sec: DEFAULT_THROTTLED_RPC_REQUESTS_PER_SEC,
+        }
+    }
+}
+
+impl ResourceLimitsConfig {
+    /// Whether any limit is set, i.e., the node ever sheds load.
+    pub fn is_enabled(&self) -> bool {
+        self.max_memory_bytes.is_some() || self.max_disk_bytes.is_some()
+    }
+}
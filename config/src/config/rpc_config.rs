@@ -3,15 +3,23 @@
 
 use crate::utils;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{collections::BTreeMap, net::SocketAddr};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct RpcConfig {
     pub address: SocketAddr,
+    pub quotas: RpcQuotaConfig,
+    /// Number of account states per second served by `get_account_states` to all consumers
+    pub max_account_states_per_sec: u64,
+    /// Enables the methods reserved to the operators of the node, e.g., `get_network_topology`,
+    /// which must not be exposed publicly
+    pub enable_admin_methods: bool,
+    pub staleness: RpcStalenessConfig,
 }
 
 pub const DEFAULT_JSON_RPC_PORT: u16 = 8080;
+pub const DEFAULT_MAX_ACCOUNT_STATES_PER_SEC: u64 = 10_000;
 
 impl Default for RpcConfig {
     fn default() -> RpcConfig {
@@ -19,10 +27,86 @@ impl Default for RpcConfig {
             address: format!("0.0.0.0:{}", DEFAULT_JSON_RPC_PORT)
                 .parse()
                 .unwrap(),
+            quotas: RpcQuotaConfig::default(),
+            max_account_states_per_sec: DEFAULT_MAX_ACCOUNT_STATES_PER_SEC,
+            enable_admin_methods: false,
+            staleness: RpcStalenessConfig::default(),
         }
     }
 }
 
+/// Per-API-key accounting of JSON-RPC usage. When enabled, every request must carry an API key
+/// listed in `api_keys` in its `X-API-Key` header, and requests beyond the key's quota for the
+/// current window are rejected with HTTP 429.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcQuotaConfig {
+    pub enabled: bool,
+    /// Length of the window over which quotas apply and usage is reported
+    pub window_secs: u64,
+    pub api_keys: BTreeMap<String, ApiKeyQuota>,
+    /// Weight of heavy methods, e.g., `get_transactions`. Methods not listed weigh 1.
+    pub method_weights: BTreeMap<String, u64>,
+}
+
+impl Default for RpcQuotaConfig {
+    fn default() -> RpcQuotaConfig {
+        RpcQuotaConfig {
+            enabled: false,
+            window_secs: 60,
+            api_keys: BTreeMap::new(),
+            method_weights: BTreeMap::new(),
+        }
+    }
+}
+
+/// Limits of a single API key per window. Unset limits are unbounded.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKeyQuota {
+    pub max_requests: Option<u64>,
+    pub max_weight: Option<u64>,
+    pub max_result_bytes: Option<u64>,
+}
+
+/// Guards against serving the outdated state of a node lagging behind the chain, e.g., balances on
+/// which wallets would act. Once the timestamp of the latest ledger info of the node is more than
+/// `max_staleness_secs` old, each method is served according to its policy. Disabled if
+/// `max_staleness_secs` is unset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcStalenessConfig {
+    pub max_staleness_secs: Option<u64>,
+    /// Policy of the methods not listed in `method_policies`
+    pub default_policy: StalenessPolicy,
+    pub method_policies: BTreeMap<String, StalenessPolicy>,
+}
+
+impl Default for RpcStalenessConfig {
+    fn default() -> RpcStalenessConfig {
+        RpcStalenessConfig {
+            max_staleness_secs: None,
+            default_policy: StalenessPolicy::Warn,
+            // submitting a transaction doesn't read the state of the node
+            method_policies: vec![("submit".to_string(), StalenessPolicy::Ignore)]
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// How a method is served while the state of the node is stale
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalenessPolicy {
+    /// Served as usual
+    Ignore,
+    /// Served with a `stale` field in the response
+    Warn,
+    /// Fails with a stale state error
+    Reject,
+}
+
 impl RpcConfig {
     pub fn randomize_ports(&mut self) {
         self.address.set_port(utils::get_available_port());
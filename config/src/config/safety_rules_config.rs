@@ -34,6 +34,9 @@ impl SafetyRulesConfig {
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum SafetyRulesService {
+    /// This runs safety rules in a simulated enclave, behind the same message-passing boundary and
+    /// attestation check as an enclave (TEE) backed deployment
+    Enclave,
     /// This runs safety rules in the same thread as event processor
     Local,
     /// This is the production, separate service approach
@@ -14,6 +14,9 @@ pub struct StorageConfig {
     pub grpc_max_receive_len: Option<i32>,
     /// None disables pruning. The windows is in number of versions, consider system tps
     /// (transaction per second) when calculating proper window.
+    ///
+    /// Only the account state out of the window is pruned: the transactions and the events of
+    /// every version, and their indices by account and by event key, are kept in full.
     pub prune_window: Option<u64>,
     #[serde(skip)]
     data_dir: PathBuf,
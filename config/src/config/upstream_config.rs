@@ -3,7 +3,7 @@
 
 use libra_types::PeerId;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 /// In general, a network ID is a PeerId that this node uses to uniquely identify a network it belongs to.
 /// This is equivalent to the `peer_id` field in the NetworkConfig of this NodeConfig
@@ -20,6 +20,17 @@ pub struct UpstreamConfig {
     // optional fallback network id. Used to as a failover if preferred upstream peers are not available
     // TODO replace PeerId with `NetworkConfig` to contain actual info needed to build fallback_network
     pub fallback_networks: Vec<UpstreamNetworkId>,
+    // If set, each transaction is forwarded to a single healthy upstream peer, picked by weight,
+    // instead of to all of them. All transactions of a sender go to the same peer, and move to
+    // another one if it becomes unhealthy
+    pub load_balancing: bool,
+    // relative share of transactions forwarded to each upstream peer when load balancing. Peers
+    // missing from this map have weight 1. Peers with weight 0 only receive transactions when no
+    // peer with a positive weight is healthy
+    pub upstream_weights: HashMap<PeerId, u64>,
+    // optional number of broadcasts an upstream peer may leave unacknowledged before it's deemed
+    // unhealthy, on top of being disconnected by the network health checker
+    pub max_unacked_broadcasts: Option<usize>,
 }
 
 impl UpstreamConfig {
@@ -31,6 +42,11 @@ impl UpstreamConfig {
     pub fn is_primary_upstream_peer(&self, peer: PeerNetworkId) -> bool {
         self.primary_networks.contains(&peer.network_id()) || self.upstream_peers.contains(&peer)
     }
+
+    /// Returns the load balancing weight of upstream peer `peer`
+    pub fn upstream_weight(&self, peer: PeerNetworkId) -> u64 {
+        *self.upstream_weights.get(&peer.peer_id()).unwrap_or(&1)
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -37,6 +37,15 @@ impl NetworkId {
             name: name.to_string(),
         })
     }
+
+    /// Short name of the network, e.g., to label metrics. Private networks go by their name.
+    pub fn as_str(&self) -> &str {
+        match self {
+            NetworkId::Validator => "Validator",
+            NetworkId::Public => "Public",
+            NetworkId::Private(info) => info.name.as_str(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -9,8 +9,12 @@ use crate::{
 use anyhow::{bail, ensure, format_err};
 use libra_crypto::{ed25519::Ed25519Signature, hash::CryptoHash, HashValue};
 use libra_types::{
-    block_info::BlockInfo, block_metadata::BlockMetadata, epoch_state::EpochState,
-    ledger_info::LedgerInfo, transaction::Version, validator_signer::ValidatorSigner,
+    block_info::BlockInfo,
+    block_metadata::{BlockMetadata, BlockMetadataExtension},
+    epoch_state::EpochState,
+    ledger_info::LedgerInfo,
+    transaction::Version,
+    validator_signer::ValidatorSigner,
     validator_verifier::ValidatorVerifier,
 };
 use mirai_annotations::debug_checked_verify_eq;
@@ -87,6 +91,10 @@ impl Block {
         self.block_data.payload()
     }
 
+    pub fn extension(&self) -> Option<&BlockMetadataExtension> {
+        self.block_data.extension()
+    }
+
     pub fn quorum_cert(&self) -> &QuorumCert {
         self.block_data.quorum_cert()
     }
@@ -198,7 +206,8 @@ impl Block {
         match self.block_data.block_type() {
             BlockType::Genesis => bail!("We should not accept genesis from others"),
             BlockType::NilBlock => self.quorum_cert().verify(validator),
-            BlockType::Proposal { author, .. } => {
+            BlockType::Proposal { author, .. }
+            | BlockType::ProposalWithExtension { author, .. } => {
                 let signature = self
                     .signature
                     .as_ref()
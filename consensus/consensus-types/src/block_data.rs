@@ -10,6 +10,7 @@ use libra_crypto::hash::HashValue;
 use libra_crypto_derive::{CryptoHasher, LCSCryptoHash};
 use libra_types::{
     block_info::BlockInfo,
+    block_metadata::BlockMetadataExtension,
     ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
 };
 use mirai_annotations::*;
@@ -20,6 +21,7 @@ use std::collections::BTreeMap;
 pub enum BlockType {
     Proposal {
         /// T of the block (e.g. one or more transaction(s)
+        #[serde(deserialize_with = "crate::common::deserialize_payload")]
         payload: Payload,
         /// Author of the block that can be validated by the author's public key and the signature
         author: Author,
@@ -32,6 +34,14 @@ pub enum BlockType {
     /// from the previous epoch.  The genesis block is used as the the first root block of the
     /// BlockTree for all epochs.
     Genesis,
+    /// A proposal whose author attached a metadata extension, passed to the VM in the block
+    /// prologue. A variant of its own leaves the encoding of the other proposals unchanged.
+    ProposalWithExtension {
+        #[serde(deserialize_with = "crate::common::deserialize_payload")]
+        payload: Payload,
+        author: Author,
+        extension: BlockMetadataExtension,
+    },
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, CryptoHasher, LCSCryptoHash)]
@@ -70,10 +80,10 @@ pub struct BlockData {
 
 impl BlockData {
     pub fn author(&self) -> Option<Author> {
-        if let BlockType::Proposal { author, .. } = self.block_type {
-            Some(author)
-        } else {
-            None
+        match self.block_type {
+            BlockType::Proposal { author, .. }
+            | BlockType::ProposalWithExtension { author, .. } => Some(author),
+            _ => None,
         }
     }
 
@@ -90,8 +100,16 @@ impl BlockData {
     }
 
     pub fn payload(&self) -> Option<&Payload> {
-        if let BlockType::Proposal { payload, .. } = &self.block_type {
-            Some(payload)
+        match &self.block_type {
+            BlockType::Proposal { payload, .. }
+            | BlockType::ProposalWithExtension { payload, .. } => Some(payload),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> Option<&BlockMetadataExtension> {
+        if let BlockType::ProposalWithExtension { extension, .. } = &self.block_type {
+            Some(extension)
         } else {
             None
         }
@@ -188,4 +206,29 @@ impl BlockData {
             block_type: BlockType::Proposal { payload, author },
         }
     }
+
+    /// Attaches a metadata extension to a proposal. Must be called before the proposal is signed.
+    pub fn with_extension(mut self, extension: Option<BlockMetadataExtension>) -> Self {
+        self.block_type = match (self.block_type, extension) {
+            (BlockType::Proposal { payload, author }, Some(extension))
+            | (
+                BlockType::ProposalWithExtension {
+                    payload, author, ..
+                },
+                Some(extension),
+            ) => BlockType::ProposalWithExtension {
+                payload,
+                author,
+                extension,
+            },
+            (
+                BlockType::ProposalWithExtension {
+                    payload, author, ..
+                },
+                None,
+            ) => BlockType::Proposal { payload, author },
+            (block_type, _) => block_type,
+        };
+        self
+    }
 }
@@ -9,7 +9,10 @@ use crate::{
     quorum_cert::QuorumCert,
 };
 use libra_crypto::hash::{CryptoHash, HashValue};
-use libra_types::{validator_signer::ValidatorSigner, validator_verifier::ValidatorVerifier};
+use libra_types::{
+    transaction::SharedTransactionStore, validator_signer::ValidatorSigner,
+    validator_verifier::ValidatorVerifier,
+};
 use std::{collections::BTreeMap, panic, sync::Arc};
 
 #[test]
@@ -98,6 +101,30 @@ fn test_block_relation() {
     assert_eq!(cloned_block.round(), next_block.round());
 }
 
+#[test]
+fn test_payload_shared_on_deserialization() {
+    let signer = ValidatorSigner::random(None);
+    let store = SharedTransactionStore::global();
+    let payload: Vec<_> = random_payload(3)
+        .into_iter()
+        .map(|txn| store.intern_arc(txn))
+        .collect();
+    let block = Block::new_proposal(
+        payload.clone(),
+        1,
+        get_current_timestamp().as_micros() as u64,
+        certificate_for_genesis(),
+        &signer,
+    );
+
+    // the transactions of a received block are the copies shared with the local node
+    let received: Block = lcs::from_bytes(&lcs::to_bytes(&block).unwrap()).unwrap();
+    assert_eq!(received, block);
+    for (txn, received_txn) in payload.iter().zip(received.payload().unwrap()) {
+        assert!(Arc::ptr_eq(txn, received_txn));
+    }
+}
+
 // Ensure that blocks that extend from the same QuorumCertificate but with different signatures
 // have different block ids.
 #[test]
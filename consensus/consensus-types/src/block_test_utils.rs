@@ -22,6 +22,7 @@ use libra_types::{
 use proptest::prelude::*;
 use std::{
     collections::BTreeMap,
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -280,13 +281,13 @@ pub fn random_payload(count: usize) -> Payload {
     let signer = ValidatorSigner::random(None);
     (0..count)
         .map(|i| {
-            get_test_signed_txn(
+            Arc::new(get_test_signed_txn(
                 address,
                 i as u64,
                 signer.private_key(),
                 signer.public_key(),
                 None,
-            )
+            ))
         })
         .collect()
 }
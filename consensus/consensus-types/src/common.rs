@@ -1,7 +1,12 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{SharedTransactionStore, SignedTransaction},
+};
+use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
 
 /// The round of a block is a consensus-internal counter, which starts with 0 and increases
 /// monotonically. It is used for the protocol safety and liveness (please see the detailed
@@ -10,5 +15,19 @@ pub type Round = u64;
 /// Author refers to the author's account address
 pub type Author = AccountAddress;
 
-/// The payload in block.
-pub type Payload = Vec<SignedTransaction>;
+/// The payload in block. The transactions are the copies shared with mempool and the other blocks,
+/// see [`SharedTransactionStore`].
+pub type Payload = Vec<Arc<SignedTransaction>>;
+
+/// Deserializes a payload into the shared copies of its transactions, so that the blocks received
+/// from the other validators do not duplicate the transactions of the local mempool.
+pub(crate) fn deserialize_payload<'de, D>(deserializer: D) -> Result<Payload, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let store = SharedTransactionStore::global();
+    Ok(Vec::<SignedTransaction>::deserialize(deserializer)?
+        .into_iter()
+        .map(|txn| store.intern(txn))
+        .collect())
+}
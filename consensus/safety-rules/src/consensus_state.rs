@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use consensus_types::common::Round;
+use libra_crypto_derive::{CryptoHasher, LCSCryptoHash};
 use libra_types::waypoint::Waypoint;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
@@ -9,12 +10,15 @@ use std::fmt::{Display, Formatter};
 /// Public representation of the internal state of SafetyRules for monitoring / debugging purposes.
 /// This does not include sensitive data like private keys.
 /// @TODO add hash of ledger info (waypoint)
-#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(
+    Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, CryptoHasher, LCSCryptoHash,
+)]
 pub struct ConsensusState {
     epoch: u64,
     last_voted_round: Round,
     preferred_round: Round,
     waypoint: Waypoint,
+    standby: bool,
 }
 
 impl Display for ConsensusState {
@@ -26,8 +30,9 @@ impl Display for ConsensusState {
              \tlast_voted_round = {},\n\
              \tpreferred_round = {}\n\
              \twaypoint = {}\n\
+             \tstandby = {}\n\
              ]",
-            self.epoch, self.last_voted_round, self.preferred_round, self.waypoint,
+            self.epoch, self.last_voted_round, self.preferred_round, self.waypoint, self.standby,
         )
     }
 }
@@ -38,12 +43,14 @@ impl ConsensusState {
         last_voted_round: Round,
         preferred_round: Round,
         waypoint: Waypoint,
+        standby: bool,
     ) -> Self {
         Self {
             epoch,
             last_voted_round,
             preferred_round,
             waypoint,
+            standby,
         }
     }
 
@@ -67,4 +74,9 @@ impl ConsensusState {
     pub fn waypoint(&self) -> Waypoint {
         self.waypoint
     }
+
+    /// Whether SafetyRules is in standby, and does not sign anything until promoted
+    pub fn standby(&self) -> bool {
+        self.standby
+    }
 }
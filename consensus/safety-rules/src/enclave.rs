@@ -0,0 +1,223 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This runs SafetyRules, along with its persistent storage and the consensus private key, inside
+//! of an enclave (TEE). Consensus can only reach it through a message-passing boundary, carrying
+//! the same serialized requests as the serializer and process services, and only after the
+//! enclave attested to the code it runs and to the consensus key it holds. As the key never
+//! leaves the enclave, a compromised host cannot sign conflicting votes with it.
+//!
+//! Hardware backed enclaves implement `TEnclave` and provide an `AttestationVerifier` for their
+//! attestation reports. `SimulatedEnclave` keeps the enclave in process and is meant for testing
+//! the boundary, it offers no more guarantees than the serializer service.
+
+use crate::{
+    persistent_safety_storage::PersistentSafetyStorage,
+    serializer::{SafetyRulesInput, SerializerClient, SerializerService, TSerializerClient},
+    Error, SafetyRules,
+};
+use consensus_types::common::Author;
+use libra_crypto::{
+    ed25519::{Ed25519PublicKey, Ed25519Signature},
+    hash::{CryptoHash, HashValue},
+    traits::Signature,
+};
+use libra_crypto_derive::{CryptoHasher, LCSCryptoHash};
+use libra_types::validator_signer::ValidatorSigner;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Measurement reported by `SimulatedEnclave`.
+pub const SIMULATED_ENCLAVE_MEASUREMENT: &[u8] = b"SafetyRules::SimulatedEnclave";
+
+/// Claims of an enclave about the code it runs and the consensus key it holds, bound to the nonce
+/// of the attestation request.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, CryptoHasher, LCSCryptoHash)]
+pub struct AttestationReport {
+    measurement: HashValue,
+    public_key: Ed25519PublicKey,
+    nonce: Vec<u8>,
+}
+
+impl AttestationReport {
+    pub fn new(measurement: HashValue, public_key: Ed25519PublicKey, nonce: Vec<u8>) -> Self {
+        Self {
+            measurement,
+            public_key,
+            nonce,
+        }
+    }
+
+    pub fn measurement(&self) -> HashValue {
+        self.measurement
+    }
+
+    pub fn public_key(&self) -> &Ed25519PublicKey {
+        &self.public_key
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+}
+
+/// An attestation report along with its proof of origin. Hardware backed enclaves put the quote
+/// of their platform in `evidence`, the simulated enclave leaves it empty.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Attestation {
+    report: AttestationReport,
+    signature: Ed25519Signature,
+    evidence: Vec<u8>,
+}
+
+impl Attestation {
+    pub fn new(report: AttestationReport, signature: Ed25519Signature, evidence: Vec<u8>) -> Self {
+        Self {
+            report,
+            signature,
+            evidence,
+        }
+    }
+
+    pub fn report(&self) -> &AttestationReport {
+        &self.report
+    }
+
+    pub fn signature(&self) -> &Ed25519Signature {
+        &self.signature
+    }
+
+    pub fn evidence(&self) -> &[u8] {
+        &self.evidence
+    }
+}
+
+/// The boundary of an enclave running SafetyRules.
+pub trait TEnclave: Send + Sync {
+    /// Returns an attestation bound to `nonce`.
+    fn attest(&mut self, nonce: Vec<u8>) -> Result<Attestation, Error>;
+
+    /// Handles a serialized `SafetyRulesInput` and returns the serialized result.
+    fn call(&mut self, input: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+/// Decides whether an enclave may be trusted with the consensus key.
+pub trait AttestationVerifier: Send + Sync {
+    fn verify(&self, attestation: &Attestation, nonce: &[u8]) -> Result<(), Error>;
+}
+
+/// Accepts attestations of enclaves running the expected code and holding the expected consensus
+/// key, signed with that key. Hardware backed verifiers additionally check the evidence.
+pub struct MeasurementVerifier {
+    measurement: HashValue,
+    public_key: Ed25519PublicKey,
+}
+
+impl MeasurementVerifier {
+    pub fn new(measurement: HashValue, public_key: Ed25519PublicKey) -> Self {
+        Self {
+            measurement,
+            public_key,
+        }
+    }
+}
+
+impl AttestationVerifier for MeasurementVerifier {
+    fn verify(&self, attestation: &Attestation, nonce: &[u8]) -> Result<(), Error> {
+        let report = attestation.report();
+        if report.nonce() != nonce {
+            return Err(Error::InvalidAttestation("stale nonce".into()));
+        }
+        if report.measurement() != self.measurement {
+            return Err(Error::InvalidAttestation(format!(
+                "unexpected measurement {}",
+                report.measurement()
+            )));
+        }
+        if report.public_key() != &self.public_key {
+            return Err(Error::InvalidAttestation("unexpected consensus key".into()));
+        }
+        attestation
+            .signature()
+            .verify(&report.hash(), &self.public_key)
+            .map_err(|e| Error::InvalidAttestation(format!("{}", e)))
+    }
+}
+
+/// An in process enclave, running SafetyRules behind the same boundary as a hardware backed one.
+pub struct SimulatedEnclave {
+    service: SerializerService,
+    signer: ValidatorSigner,
+}
+
+impl SimulatedEnclave {
+    pub fn new(author: Author, storage: PersistentSafetyStorage) -> Self {
+        let consensus_key = storage
+            .consensus_key()
+            .expect("Unable to retrieve consensus private key");
+        let signer = ValidatorSigner::new(author, consensus_key);
+        let service = SerializerService::new(SafetyRules::new(author, storage));
+        Self { service, signer }
+    }
+
+    pub fn measurement() -> HashValue {
+        HashValue::sha3_256_of(SIMULATED_ENCLAVE_MEASUREMENT)
+    }
+}
+
+impl TEnclave for SimulatedEnclave {
+    fn attest(&mut self, nonce: Vec<u8>) -> Result<Attestation, Error> {
+        let report = AttestationReport::new(Self::measurement(), self.signer.public_key(), nonce);
+        let signature = self.signer.sign_message(report.hash());
+        Ok(Attestation::new(report, signature, vec![]))
+    }
+
+    fn call(&mut self, input: Vec<u8>) -> Result<Vec<u8>, Error> {
+        self.service.handle_message(input)
+    }
+}
+
+/// Owns an enclave that passed attestation, and hands out clients to it.
+pub struct EnclaveService {
+    enclave: Arc<RwLock<Box<dyn TEnclave>>>,
+}
+
+impl EnclaveService {
+    /// Attests `enclave` with a fresh nonce, and fails if `verifier` does not accept it.
+    pub fn new(
+        mut enclave: Box<dyn TEnclave>,
+        verifier: &dyn AttestationVerifier,
+    ) -> Result<Self, Error> {
+        let nonce = lcs::to_bytes(
+            &SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time is before the UNIX epoch")
+                .as_nanos(),
+        )?;
+        let attestation = enclave.attest(nonce.clone())?;
+        verifier.verify(&attestation, &nonce)?;
+        Ok(Self {
+            enclave: Arc::new(RwLock::new(enclave)),
+        })
+    }
+
+    pub fn client(&self) -> SerializerClient {
+        SerializerClient::new_client(Box::new(EnclaveClient {
+            enclave: self.enclave.clone(),
+        }))
+    }
+}
+
+struct EnclaveClient {
+    enclave: Arc<RwLock<Box<dyn TEnclave>>>,
+}
+
+impl TSerializerClient for EnclaveClient {
+    fn request(&mut self, input: SafetyRulesInput) -> Result<Vec<u8>, Error> {
+        let input_message = lcs::to_bytes(&input)?;
+        self.enclave.write().unwrap().call(input_message)
+    }
+}
@@ -20,6 +20,9 @@ pub enum Error {
     #[error("Internal error: {:?}", error)]
     InternalError { error: String },
 
+    #[error("Invalid enclave attestation: {0}")]
+    InvalidAttestation(String),
+
     #[error("Invalid proposal: {}", {0})]
     InvalidProposal(String),
 
@@ -57,6 +60,28 @@ pub enum Error {
     #[error("Serialization error: {0}")]
     SerializationError(String),
 
+    /// The handover is older than the last one handed over by or to this instance
+    #[error(
+        "Handover at epoch {:?} round {:?} is older than the last handover, at epoch {:?} round {:?}",
+        epoch,
+        last_voted_round,
+        last_handover_epoch,
+        last_handover_round
+    )]
+    StaleHandover {
+        epoch: u64,
+        last_voted_round: Round,
+        last_handover_epoch: u64,
+        last_handover_round: Round,
+    },
+
+    /// The handover was not produced by the demotion of an instance with the same consensus key
+    #[error("Invalid handover: {0}")]
+    InvalidHandover(String),
+
+    #[error("SafetyRules is in standby and does not sign")]
+    Standby,
+
     #[error("Waypoint mismatch: {0}")]
     WaypointMismatch(String),
 }
@@ -0,0 +1,55 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{ConsensusState, Error};
+use libra_crypto::{
+    ed25519::{Ed25519PublicKey, Ed25519Signature},
+    hash::CryptoHash,
+    traits::Signature,
+};
+use libra_types::validator_signer::ValidatorSigner;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The state a demoted instance of SafetyRules hands over to the instance taking over, signed with
+/// the consensus key of the validator, so that only a state produced by `demote` is imported.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Handover {
+    state: ConsensusState,
+    signature: Ed25519Signature,
+}
+
+impl Display for Handover {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Handover: [{}]", self.state)
+    }
+}
+
+impl Handover {
+    pub fn new(state: ConsensusState, signer: &ValidatorSigner) -> Self {
+        let signature = signer.sign_message(state.hash());
+        Self { state, signature }
+    }
+
+    /// The state of the demoted instance
+    pub fn state(&self) -> &ConsensusState {
+        &self.state
+    }
+
+    pub fn signature(&self) -> &Ed25519Signature {
+        &self.signature
+    }
+
+    /// Verifies that the handover was signed with the consensus key `public_key`, by an instance
+    /// which was in standby when taking it.
+    pub fn verify(&self, public_key: &Ed25519PublicKey) -> Result<(), Error> {
+        if !self.state.standby() {
+            return Err(Error::InvalidHandover(
+                "The state was not taken in standby".into(),
+            ));
+        }
+        self.signature
+            .verify(&self.state.hash(), public_key)
+            .map_err(|e| Error::InvalidHandover(format!("{}", e)))
+    }
+}
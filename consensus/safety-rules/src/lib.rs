@@ -5,7 +5,9 @@
 
 mod consensus_state;
 mod counters;
+mod enclave;
 mod error;
+mod handover;
 mod local_client;
 mod persistent_safety_storage;
 mod process;
@@ -18,9 +20,18 @@ mod t_safety_rules;
 mod thread;
 
 pub use crate::{
-    consensus_state::ConsensusState, counters::COUNTERS, error::Error,
-    persistent_safety_storage::PersistentSafetyStorage, process::Process,
-    safety_rules::SafetyRules, safety_rules_manager::SafetyRulesManager,
+    consensus_state::ConsensusState,
+    counters::COUNTERS,
+    enclave::{
+        Attestation, AttestationReport, AttestationVerifier, MeasurementVerifier, SimulatedEnclave,
+        TEnclave,
+    },
+    error::Error,
+    handover::Handover,
+    persistent_safety_storage::PersistentSafetyStorage,
+    process::Process,
+    safety_rules::SafetyRules,
+    safety_rules_manager::SafetyRulesManager,
     t_safety_rules::TSafetyRules,
 };
 
@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{ConsensusState, Error, SafetyRules, TSafetyRules};
+use crate::{ConsensusState, Error, Handover, SafetyRules, TSafetyRules};
 use consensus_types::{
     block::Block, block_data::BlockData, quorum_cert::QuorumCert, timeout::Timeout, vote::Vote,
     vote_proposal::VoteProposal,
@@ -50,4 +50,12 @@ impl TSafetyRules for LocalClient {
     fn sign_timeout(&mut self, timeout: &Timeout) -> Result<Ed25519Signature, Error> {
         self.internal.write().unwrap().sign_timeout(timeout)
     }
+
+    fn demote(&mut self) -> Result<Handover, Error> {
+        self.internal.write().unwrap().demote()
+    }
+
+    fn promote(&mut self, handover: &Handover) -> Result<(), Error> {
+        self.internal.write().unwrap().promote(handover)
+    }
 }
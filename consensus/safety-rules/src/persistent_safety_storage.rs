@@ -4,8 +4,13 @@
 use anyhow::Result;
 use consensus_types::common::Round;
 use libra_crypto::ed25519::Ed25519PrivateKey;
-use libra_global_constants::{CONSENSUS_KEY, EPOCH, LAST_VOTED_ROUND, PREFERRED_ROUND, WAYPOINT};
-use libra_secure_storage::{BoxedStorage, CryptoStorage, InMemoryStorage, KVStorage, Value};
+use libra_global_constants::{
+    CONSENSUS_KEY, EPOCH, HANDOVER_EPOCH, HANDOVER_ROUND, LAST_VOTED_ROUND, PREFERRED_ROUND,
+    STANDBY, WAYPOINT,
+};
+use libra_secure_storage::{
+    BoxedStorage, CryptoStorage, Error as StorageError, InMemoryStorage, KVStorage, Value,
+};
 use libra_types::waypoint::Waypoint;
 use std::str::FromStr;
 
@@ -45,6 +50,9 @@ impl PersistentSafetyStorage {
         internal_store.set(EPOCH, Value::U64(1))?;
         internal_store.set(LAST_VOTED_ROUND, Value::U64(0))?;
         internal_store.set(PREFERRED_ROUND, Value::U64(0))?;
+        internal_store.set(STANDBY, Value::U64(0))?;
+        internal_store.set(HANDOVER_EPOCH, Value::U64(0))?;
+        internal_store.set(HANDOVER_ROUND, Value::U64(0))?;
         internal_store.set(WAYPOINT, Value::String(waypoint.to_string()))?;
         Ok(())
     }
@@ -96,6 +104,32 @@ impl PersistentSafetyStorage {
         Ok(())
     }
 
+    /// Whether SafetyRules is in standby. It is persisted, so that a demoted validator does not
+    /// resume signing when restarted.
+    pub fn standby(&self) -> Result<bool> {
+        Ok(self.u64_or_zero(STANDBY)? != 0)
+    }
+
+    pub fn set_standby(&mut self, standby: bool) -> Result<()> {
+        self.internal_store
+            .set(STANDBY, Value::U64(standby as u64))?;
+        Ok(())
+    }
+
+    /// The epoch and last voted round of the latest state handed over by or to this instance.
+    pub fn handover(&self) -> Result<(u64, Round)> {
+        Ok((
+            self.u64_or_zero(HANDOVER_EPOCH)?,
+            self.u64_or_zero(HANDOVER_ROUND)?,
+        ))
+    }
+
+    pub fn set_handover(&mut self, epoch: u64, round: Round) -> Result<()> {
+        self.internal_store.set(HANDOVER_EPOCH, Value::U64(epoch))?;
+        self.internal_store.set(HANDOVER_ROUND, Value::U64(round))?;
+        Ok(())
+    }
+
     pub fn waypoint(&self) -> Result<Waypoint> {
         let waypoint = self
             .internal_store
@@ -110,6 +144,16 @@ impl PersistentSafetyStorage {
             .set(WAYPOINT, Value::String(waypoint.to_string()))?;
         Ok(())
     }
+
+    /// Stores initialized before the failover values existed don't have them, which stands for
+    /// an active instance that never handed over.
+    fn u64_or_zero(&self, key: &str) -> Result<u64> {
+        match self.internal_store.get(key) {
+            Ok(response) => Ok(response.value.u64()?),
+            Err(StorageError::KeyNotSet(_)) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -124,11 +168,29 @@ mod tests {
         assert_eq!(storage.epoch().unwrap(), 1);
         assert_eq!(storage.last_voted_round().unwrap(), 0);
         assert_eq!(storage.preferred_round().unwrap(), 0);
+        assert!(!storage.standby().unwrap());
+        assert_eq!(storage.handover().unwrap(), (0, 0));
         storage.set_epoch(9).unwrap();
         storage.set_last_voted_round(8).unwrap();
         storage.set_preferred_round(1).unwrap();
         assert_eq!(storage.epoch().unwrap(), 9);
         assert_eq!(storage.last_voted_round().unwrap(), 8);
         assert_eq!(storage.preferred_round().unwrap(), 1);
+        storage.set_standby(true).unwrap();
+        storage.set_handover(9, 7).unwrap();
+        assert!(storage.standby().unwrap());
+        assert_eq!(storage.handover().unwrap(), (9, 7));
+    }
+
+    #[test]
+    fn test_missing_failover_values() {
+        let private_key = ValidatorSigner::from_int(0).private_key().clone();
+        let mut internal_store = BoxedStorage::from(InMemoryStorage::new());
+        internal_store
+            .import_private_key(CONSENSUS_KEY, private_key)
+            .unwrap();
+        let storage = PersistentSafetyStorage::new(internal_store);
+        assert!(!storage.standby().unwrap());
+        assert_eq!(storage.handover().unwrap(), (0, 0));
     }
 }
@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{test_utils, ConsensusState, Error, SafetyRulesManager, TSafetyRules};
+use crate::{test_utils, ConsensusState, Error, Handover, SafetyRulesManager, TSafetyRules};
 use consensus_types::{
     block::Block, block_data::BlockData, quorum_cert::QuorumCert, timeout::Timeout, vote::Vote,
     vote_proposal::VoteProposal,
@@ -91,4 +91,12 @@ impl TSafetyRules for ProcessClientWrapper {
     fn sign_timeout(&mut self, timeout: &Timeout) -> Result<Ed25519Signature, Error> {
         self.safety_rules.sign_timeout(timeout)
     }
+
+    fn demote(&mut self) -> Result<Handover, Error> {
+        self.safety_rules.demote()
+    }
+
+    fn promote(&mut self, handover: &Handover) -> Result<(), Error> {
+        self.safety_rules.promote(handover)
+    }
 }
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    consensus_state::ConsensusState, error::Error,
+    consensus_state::ConsensusState, error::Error, handover::Handover,
     persistent_safety_storage::PersistentSafetyStorage, t_safety_rules::TSafetyRules, COUNTERS,
 };
 use consensus_types::{
@@ -128,6 +128,15 @@ impl SafetyRules {
         }
     }
 
+    /// This checks that SafetyRules is not in standby, before signing anything
+    fn verify_active(&self) -> Result<(), Error> {
+        if self.persistent_storage.standby()? {
+            Err(Error::Standby)
+        } else {
+            Ok(())
+        }
+    }
+
     /// This checkes whether the author of one proposal is the validator signer
     fn verify_author(&self, author: Option<Author>) -> Result<(), Error> {
         let validator_signer_author = &self.validator_signer.author();
@@ -149,6 +158,7 @@ impl TSafetyRules for SafetyRules {
             self.persistent_storage.last_voted_round()?,
             self.persistent_storage.preferred_round()?,
             self.persistent_storage.waypoint()?,
+            self.persistent_storage.standby()?,
         ))
     }
 
@@ -179,6 +189,7 @@ impl TSafetyRules for SafetyRules {
         debug!("Incoming vote proposal to sign.");
         let proposed_block = vote_proposal.block();
 
+        self.verify_active()?;
         self.verify_epoch(proposed_block.epoch())?;
 
         let last_voted_round = self.persistent_storage.last_voted_round()?;
@@ -236,6 +247,7 @@ impl TSafetyRules for SafetyRules {
 
     fn sign_proposal(&mut self, block_data: BlockData) -> Result<Block, Error> {
         debug!("Incoming proposal to sign.");
+        self.verify_active()?;
         self.verify_author(block_data.author())?;
         self.verify_epoch(block_data.epoch())?;
         let last_voted_round = self.persistent_storage.last_voted_round()?;
@@ -281,6 +293,7 @@ impl TSafetyRules for SafetyRules {
         debug!("Incoming timeout message for round {}", timeout.round());
         COUNTERS.requested_sign_timeout.inc();
 
+        self.verify_active()?;
         self.verify_epoch(timeout.epoch())?;
 
         let preferred_round = self.persistent_storage.preferred_round()?;
@@ -308,4 +321,53 @@ impl TSafetyRules for SafetyRules {
         debug!("Successfully signed timeout message.");
         Ok(signature)
     }
+
+    /// The standby state is persisted before the state is handed over, so that this instance
+    /// doesn't sign again, even when restarted, unless it is promoted.
+    fn demote(&mut self) -> Result<Handover, Error> {
+        self.persistent_storage.set_standby(true)?;
+        let state = self.consensus_state()?;
+        self.persistent_storage
+            .set_handover(state.epoch(), state.last_voted_round())?;
+        Ok(Handover::new(state, &self.validator_signer))
+    }
+
+    /// The rounds of the handover only matter in the epoch it was taken in, as starting an epoch
+    /// resets them, so a handover from another epoch is rejected: the demoted instance has to be
+    /// demoted again once both instances are in the same epoch. A handover older than the last one
+    /// handed over by or to this instance is rejected as well, so that a stale handover can't be
+    /// replayed. Only a handover signed with the consensus key of this instance, by an instance in
+    /// standby, is imported, so that the rounds can't be forged to stop the validator from voting.
+    fn promote(&mut self, handover: &Handover) -> Result<(), Error> {
+        handover.verify(&self.validator_signer.public_key())?;
+        let handover = handover.state();
+        let epoch = self.persistent_storage.epoch()?;
+        if handover.epoch() != epoch {
+            return Err(Error::IncorrectEpoch(handover.epoch(), epoch));
+        }
+        let (last_handover_epoch, last_handover_round) = self.persistent_storage.handover()?;
+        if (handover.epoch(), handover.last_voted_round())
+            < (last_handover_epoch, last_handover_round)
+        {
+            return Err(Error::StaleHandover {
+                epoch: handover.epoch(),
+                last_voted_round: handover.last_voted_round(),
+                last_handover_epoch,
+                last_handover_round,
+            });
+        }
+
+        if handover.last_voted_round() > self.persistent_storage.last_voted_round()? {
+            self.persistent_storage
+                .set_last_voted_round(handover.last_voted_round())?;
+        }
+        if handover.preferred_round() > self.persistent_storage.preferred_round()? {
+            self.persistent_storage
+                .set_preferred_round(handover.preferred_round())?;
+        }
+        self.persistent_storage
+            .set_handover(handover.epoch(), handover.last_voted_round())?;
+        self.persistent_storage.set_standby(false)?;
+        Ok(())
+    }
 }
@@ -2,6 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    enclave::{
+        AttestationVerifier, EnclaveService, MeasurementVerifier, SimulatedEnclave, TEnclave,
+    },
     local_client::LocalClient,
     persistent_safety_storage::PersistentSafetyStorage,
     process::ProcessService,
@@ -13,6 +16,7 @@ use crate::{
 };
 use consensus_types::common::Author;
 use libra_config::config::{NodeConfig, SafetyRulesService};
+use libra_crypto::PrivateKey;
 use libra_secure_storage::{config, BoxedStorage};
 use std::{
     convert::TryInto,
@@ -49,6 +53,7 @@ pub fn extract_service_inputs(config: &mut NodeConfig) -> (Author, PersistentSaf
 }
 
 enum SafetyRulesWrapper {
+    Enclave(EnclaveService),
     Local(Arc<RwLock<SafetyRules>>),
     Process(ProcessService),
     Serializer(Arc<RwLock<SerializerService>>),
@@ -71,6 +76,7 @@ impl SafetyRulesManager {
         let (author, storage) = extract_service_inputs(config);
         let sr_config = &config.consensus.safety_rules;
         match sr_config.service {
+            SafetyRulesService::Enclave => Self::new_enclave(author, storage),
             SafetyRulesService::Local => Self::new_local(author, storage),
             SafetyRulesService::Serializer => Self::new_serializer(author, storage),
             SafetyRulesService::Thread => Self::new_thread(author, storage),
@@ -78,6 +84,30 @@ impl SafetyRulesManager {
         }
     }
 
+    /// Runs SafetyRules in a simulated enclave, which must attest to holding the consensus key
+    /// found in `storage`.
+    pub fn new_enclave(author: Author, storage: PersistentSafetyStorage) -> Self {
+        let public_key = storage
+            .consensus_key()
+            .expect("Unable to retrieve consensus private key")
+            .public_key();
+        let verifier = MeasurementVerifier::new(SimulatedEnclave::measurement(), public_key);
+        let enclave = SimulatedEnclave::new(author, storage);
+        Self::new_enclave_with(Box::new(enclave), &verifier)
+    }
+
+    /// Runs SafetyRules in the given enclave, once `verifier` accepted its attestation.
+    pub fn new_enclave_with(
+        enclave: Box<dyn TEnclave>,
+        verifier: &dyn AttestationVerifier,
+    ) -> Self {
+        let enclave_service =
+            EnclaveService::new(enclave, verifier).expect("Enclave attestation failed");
+        Self {
+            internal_safety_rules: SafetyRulesWrapper::Enclave(enclave_service),
+        }
+    }
+
     pub fn new_local(author: Author, storage: PersistentSafetyStorage) -> Self {
         let safety_rules = SafetyRules::new(author, storage);
         Self {
@@ -118,6 +148,7 @@ impl SafetyRulesManager {
 
     pub fn client(&self) -> Box<dyn TSafetyRules + Send + Sync> {
         match &self.internal_safety_rules {
+            SafetyRulesWrapper::Enclave(enclave) => Box::new(enclave.client()),
             SafetyRulesWrapper::Local(safety_rules) => {
                 Box::new(LocalClient::new(safety_rules.clone()))
             }
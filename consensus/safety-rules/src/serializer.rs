@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{ConsensusState, Error, SafetyRules, TSafetyRules};
+use crate::{ConsensusState, Error, Handover, SafetyRules, TSafetyRules};
 use consensus_types::{
     block::Block, block_data::BlockData, quorum_cert::QuorumCert, timeout::Timeout, vote::Vote,
     vote_proposal::VoteProposal,
@@ -19,6 +19,8 @@ pub enum SafetyRulesInput {
     ConstructAndSignVote(Box<VoteProposal>),
     SignProposal(Box<BlockData>),
     SignTimeout(Box<Timeout>),
+    Demote,
+    Promote(Box<Handover>),
 }
 
 pub struct SerializerService {
@@ -46,6 +48,8 @@ impl SerializerService {
             SafetyRulesInput::SignTimeout(timeout) => {
                 lcs::to_bytes(&self.internal.sign_timeout(&timeout))
             }
+            SafetyRulesInput::Demote => lcs::to_bytes(&self.internal.demote()),
+            SafetyRulesInput::Promote(handover) => lcs::to_bytes(&self.internal.promote(&handover)),
         };
 
         Ok(output?)
@@ -103,6 +107,16 @@ impl TSafetyRules for SerializerClient {
         let response = self.request(SafetyRulesInput::SignTimeout(Box::new(timeout.clone())))?;
         lcs::from_bytes(&response)?
     }
+
+    fn demote(&mut self) -> Result<Handover, Error> {
+        let response = self.request(SafetyRulesInput::Demote)?;
+        lcs::from_bytes(&response)?
+    }
+
+    fn promote(&mut self, handover: &Handover) -> Result<(), Error> {
+        let response = self.request(SafetyRulesInput::Promote(Box::new(handover.clone())))?;
+        lcs::from_bytes(&response)?
+    }
 }
 
 pub trait TSerializerClient: Send + Sync {
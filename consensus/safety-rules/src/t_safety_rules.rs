@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{ConsensusState, Error};
+use crate::{ConsensusState, Error, Handover};
 use consensus_types::{
     block::Block, block_data::BlockData, quorum_cert::QuorumCert, timeout::Timeout, vote::Vote,
     vote_proposal::VoteProposal,
@@ -35,4 +35,15 @@ pub trait TSafetyRules {
     /// As the holder of the private key, SafetyRules also signs what is effectively a
     /// timeout message. This returns the signature for that timeout message.
     fn sign_timeout(&mut self, timeout: &Timeout) -> Result<Ed25519Signature, Error>;
+
+    /// Puts SafetyRules in standby, where it keeps learning about quorum certificates but no
+    /// longer signs votes, proposals or timeouts, and returns its state to hand over to the
+    /// instance taking over. The last voted round of the returned state is final, as nothing is
+    /// signed until this instance is promoted again. The state is signed with the consensus key.
+    fn demote(&mut self) -> Result<Handover, Error>;
+
+    /// Takes SafetyRules out of standby, once the state handed over by the demoted instance is
+    /// imported, so that this instance never signs in a round the demoted one already voted in.
+    /// The handover must be signed with the consensus key of this instance.
+    fn promote(&mut self, handover: &Handover) -> Result<(), Error>;
 }
@@ -0,0 +1,50 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    enclave::{EnclaveService, MeasurementVerifier, SimulatedEnclave},
+    test_utils,
+    tests::suite,
+    Error, SafetyRulesManager, TSafetyRules,
+};
+use libra_crypto::hash::HashValue;
+use libra_types::validator_signer::ValidatorSigner;
+
+#[test]
+fn test() {
+    suite::run_test_suite(safety_rules);
+}
+
+#[test]
+fn test_attestation() {
+    let signer = ValidatorSigner::from_int(0);
+    let other_signer = ValidatorSigner::from_int(1);
+    let enclave = || {
+        let storage = test_utils::test_storage(&signer);
+        Box::new(SimulatedEnclave::new(signer.author(), storage))
+    };
+
+    let verifier = MeasurementVerifier::new(SimulatedEnclave::measurement(), signer.public_key());
+    assert!(EnclaveService::new(enclave(), &verifier).is_ok());
+
+    let verifier = MeasurementVerifier::new(HashValue::zero(), signer.public_key());
+    assert!(matches!(
+        EnclaveService::new(enclave(), &verifier),
+        Err(Error::InvalidAttestation(_))
+    ));
+
+    let verifier =
+        MeasurementVerifier::new(SimulatedEnclave::measurement(), other_signer.public_key());
+    assert!(matches!(
+        EnclaveService::new(enclave(), &verifier),
+        Err(Error::InvalidAttestation(_))
+    ));
+}
+
+fn safety_rules() -> (Box<dyn TSafetyRules>, ValidatorSigner) {
+    let signer = ValidatorSigner::from_int(0);
+    let storage = test_utils::test_storage(&signer);
+    let safety_rules_manager = SafetyRulesManager::new_enclave(signer.author(), storage);
+    let safety_rules = safety_rules_manager.client();
+    (safety_rules, signer)
+}
@@ -1,7 +1,10 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{test_utils, tests::suite, SafetyRules, TSafetyRules};
+use crate::{test_utils, tests::suite, Error, PersistentSafetyStorage, SafetyRules, TSafetyRules};
+use consensus_types::timeout::Timeout;
+use libra_secure_storage::{BoxedStorage, OnDiskStorage};
+use libra_temppath::TempPath;
 use libra_types::validator_signer::ValidatorSigner;
 
 #[test]
@@ -15,3 +18,25 @@ fn safety_rules() -> (Box<dyn TSafetyRules>, ValidatorSigner) {
     let safety_rules = Box::new(SafetyRules::new(signer.author(), storage));
     (safety_rules, signer)
 }
+
+#[test]
+fn test_standby_survives_restart() {
+    let signer = ValidatorSigner::from_int(0);
+    let waypoint = test_utils::validator_signers_to_waypoints(&[&signer]);
+    let storage_path = TempPath::new();
+    storage_path.create_as_file().unwrap();
+
+    let storage = BoxedStorage::from(OnDiskStorage::new(storage_path.path().to_path_buf()));
+    let storage =
+        PersistentSafetyStorage::initialize(storage, signer.private_key().clone(), waypoint);
+    let mut safety_rules = SafetyRules::new(signer.author(), storage);
+    safety_rules.demote().unwrap();
+
+    let storage = BoxedStorage::from(OnDiskStorage::new(storage_path.path().to_path_buf()));
+    let mut safety_rules = SafetyRules::new(signer.author(), PersistentSafetyStorage::new(storage));
+    assert!(safety_rules.consensus_state().unwrap().standby());
+    assert_eq!(
+        safety_rules.sign_timeout(&Timeout::new(1, 1)),
+        Err(Error::Standby)
+    );
+}
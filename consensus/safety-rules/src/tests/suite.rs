@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{test_utils, Error, TSafetyRules};
+use crate::{test_utils, ConsensusState, Error, Handover, TSafetyRules};
 use consensus_types::{
     block::{block_test_utils::random_payload, Block},
     common::Round,
@@ -67,6 +67,8 @@ pub fn run_test_suite(func: Callback) {
     test_sign_proposal_with_bad_signer(func);
     test_sign_proposal_with_invalid_qc(func);
     test_sign_proposal_with_early_preferred_round(func);
+    test_standby_handover(func);
+    test_forged_handover(func);
 }
 
 fn test_bad_execution_output(func: Callback) {
@@ -600,3 +602,132 @@ fn test_sign_proposal_with_early_preferred_round(func: Callback) {
         Error::InvalidQuorumCertificate("Preferred round too early".into())
     );
 }
+
+fn test_standby_handover(func: Callback) {
+    let (mut active, signer) = func();
+    let (mut standby, _) = func();
+
+    let (proof, genesis_qc) = make_genesis(&signer);
+    let round = genesis_qc.certified_block().round();
+
+    let p0 = test_utils::make_proposal_with_qc(round + 1, genesis_qc, &signer);
+    let p1 = make_proposal_with_parent(round + 2, &p0, None, &signer);
+    let timeout = Timeout::new(p0.block().epoch(), p0.block().round());
+
+    active.initialize(&proof).unwrap();
+    standby.initialize(&proof).unwrap();
+    standby.demote().unwrap();
+
+    // The standby keeps up with quorum certificates, but does not sign
+    active.update(p0.block().quorum_cert()).unwrap();
+    standby.update(p0.block().quorum_cert()).unwrap();
+    active.construct_and_sign_vote(&p0).unwrap();
+    assert_eq!(standby.construct_and_sign_vote(&p0), Err(Error::Standby));
+    assert_eq!(standby.sign_timeout(&timeout), Err(Error::Standby));
+
+    // Hand over from the active instance to the standby
+    let handover = active.demote().unwrap();
+    assert!(handover.state().standby());
+    assert_eq!(handover.state().last_voted_round(), p0.block().round());
+    assert_eq!(active.construct_and_sign_vote(&p1), Err(Error::Standby));
+    standby.promote(&handover).unwrap();
+
+    // The promoted instance never votes again in the rounds of the demoted one
+    assert_eq!(
+        standby.construct_and_sign_vote(&p0),
+        Err(Error::OldProposal {
+            last_voted_round: p0.block().round(),
+            proposal_round: p0.block().round(),
+        })
+    );
+    standby.update(p1.block().quorum_cert()).unwrap();
+    standby.construct_and_sign_vote(&p1).unwrap();
+    assert!(!standby.consensus_state().unwrap().standby());
+
+    // Hand back over to the first instance, which can't be handed the older state again
+    let hand_back = standby.demote().unwrap();
+    assert_eq!(hand_back.state().last_voted_round(), p1.block().round());
+    active.promote(&hand_back).unwrap();
+    assert_eq!(
+        standby.promote(&handover),
+        Err(Error::StaleHandover {
+            epoch: handover.state().epoch(),
+            last_voted_round: p0.block().round(),
+            last_handover_epoch: hand_back.state().epoch(),
+            last_handover_round: p1.block().round(),
+        })
+    );
+    assert_eq!(standby.sign_timeout(&timeout), Err(Error::Standby));
+
+    // A handover from another epoch is rejected
+    let (mut lagging, _) = func();
+    lagging.initialize(&proof).unwrap();
+    lagging.demote().unwrap();
+    let epoch = lagging.consensus_state().unwrap().epoch();
+    let waypoint = handover.state().waypoint();
+    let later_handover = Handover::new(
+        ConsensusState::new(epoch + 1, 0, 0, waypoint, true),
+        &signer,
+    );
+    assert_eq!(
+        lagging.promote(&later_handover),
+        Err(Error::IncorrectEpoch(epoch + 1, epoch))
+    );
+    let earlier_handover = Handover::new(
+        ConsensusState::new(epoch - 1, 0, 0, waypoint, true),
+        &signer,
+    );
+    assert_eq!(
+        lagging.promote(&earlier_handover),
+        Err(Error::IncorrectEpoch(epoch - 1, epoch))
+    );
+    assert_eq!(lagging.sign_timeout(&timeout), Err(Error::Standby));
+}
+
+fn test_forged_handover(func: Callback) {
+    let (mut active, signer) = func();
+    let (mut standby, _) = func();
+
+    let (proof, genesis_qc) = make_genesis(&signer);
+    let round = genesis_qc.certified_block().round();
+    let p0 = test_utils::make_proposal_with_qc(round + 1, genesis_qc, &signer);
+
+    active.initialize(&proof).unwrap();
+    standby.initialize(&proof).unwrap();
+    standby.demote().unwrap();
+    active.update(p0.block().quorum_cert()).unwrap();
+    active.construct_and_sign_vote(&p0).unwrap();
+
+    // A state which was not taken in standby is rejected, even when signed with the consensus key
+    let active_state = active.consensus_state().unwrap();
+    let not_standby = Handover::new(active_state, &signer);
+    assert!(matches!(
+        standby.promote(&not_standby),
+        Err(Error::InvalidHandover(_))
+    ));
+
+    // A handover signed with another key is rejected
+    let handover = active.demote().unwrap();
+    let other_signer = ValidatorSigner::random([7; 32]);
+    let forged = Handover::new(handover.state().clone(), &other_signer);
+    assert!(matches!(
+        standby.promote(&forged),
+        Err(Error::InvalidHandover(_))
+    ));
+
+    // A handover whose last voted round was lowered after signing is rejected
+    let mut bytes = lcs::to_bytes(&handover).unwrap();
+    bytes[8..16].copy_from_slice(&0u64.to_le_bytes());
+    let tampered: Handover = lcs::from_bytes(&bytes).unwrap();
+    assert_eq!(tampered.state().last_voted_round(), 0);
+    assert!(matches!(
+        standby.promote(&tampered),
+        Err(Error::InvalidHandover(_))
+    ));
+
+    // None of them took the standby out of standby, unlike the genuine handover
+    let timeout = Timeout::new(p0.block().epoch(), p0.block().round() + 1);
+    assert_eq!(standby.sign_timeout(&timeout), Err(Error::Standby));
+    standby.promote(&handover).unwrap();
+    standby.sign_timeout(&timeout).unwrap();
+}
@@ -4,14 +4,17 @@
 use crate::{
     counters,
     epoch_manager::EpochManager,
+    failover::ConsensusFailover,
     network::NetworkTask,
     network_interface::{ConsensusNetworkEvents, ConsensusNetworkSender},
     persistent_liveness_storage::StorageWriteProxy,
     state_computer::ExecutionProxy,
+    state_replication::BlockMetadataExtensionProvider,
     txn_manager::MempoolProxy,
     util::time_service::ClockTimeService,
 };
 use channel::libra_channel;
+use debug_interface::{drain, failover};
 use execution_correctness::ExecutionCorrectnessManager;
 use futures::channel::mpsc;
 use libra_config::config::NodeConfig;
@@ -23,7 +26,9 @@ use std::{boxed::Box, sync::Arc};
 use storage_interface::DbReader;
 use tokio::runtime::{self, Runtime};
 
-/// Helper function to start consensus based on configuration and return the runtime
+/// Helper function to start consensus based on configuration and return the runtime.
+/// `extension_provider` supplies the metadata extension of the blocks proposed by this node, if
+/// enabled on chain.
 pub fn start_consensus(
     node_config: &mut NodeConfig,
     network_sender: ConsensusNetworkSender,
@@ -32,6 +37,7 @@ pub fn start_consensus(
     consensus_to_mempool_sender: mpsc::Sender<ConsensusRequest>,
     libra_db: Arc<dyn DbReader>,
     reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
+    extension_provider: Option<Arc<dyn BlockMetadataExtensionProvider>>,
 ) -> Runtime {
     let runtime = runtime::Builder::new()
         .thread_name("consensus-")
@@ -50,6 +56,9 @@ pub fn start_consensus(
 
     let (timeout_sender, timeout_receiver) = channel::new(1_024, &counters::PENDING_ROUND_TIMEOUTS);
     let (self_sender, self_receiver) = channel::new(1_024, &counters::PENDING_SELF_MESSAGES);
+    let (failover_sender, failover_receiver) =
+        channel::new(16, &counters::PENDING_FAILOVER_REQUESTS);
+    failover::register(Arc::new(ConsensusFailover::new(failover_sender)));
 
     let epoch_mgr = EpochManager::new(
         node_config,
@@ -60,12 +69,20 @@ pub fn start_consensus(
         txn_manager,
         state_computer,
         storage,
+        extension_provider,
     );
+    // Consensus is drained once the node isn't the proposer of the upcoming rounds.
+    drain::register(Arc::new(epoch_mgr.proposer_duty()));
 
     let (network_task, network_receiver) = NetworkTask::new(network_events, self_receiver);
 
     runtime.spawn(network_task.start());
-    runtime.spawn(epoch_mgr.start(timeout_receiver, network_receiver, reconfig_events));
+    runtime.spawn(epoch_mgr.start(
+        timeout_receiver,
+        network_receiver,
+        reconfig_events,
+        failover_receiver,
+    ));
 
     debug!("Consensus started.");
     runtime
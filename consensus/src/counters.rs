@@ -2,8 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use libra_metrics::{
-    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
-    DurationHistogram, Histogram, IntCounter, IntCounterVec, IntGauge,
+    cardinality::{CardinalityGuard, DEFAULT_MAX_LABEL_VALUES},
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, DurationHistogram, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -57,6 +59,15 @@ pub static PREFERRED_BLOCK_ROUND: Lazy<IntGauge> = Lazy::new(|| {
     .unwrap()
 });
 
+/// This counter is set to 1 while this validator is a standby, which doesn't sign anything.
+pub static STANDBY: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "libra_consensus_standby",
+        "This counter is set to 1 while this validator is a standby, which doesn't sign anything."
+    )
+    .unwrap()
+});
+
 /// This counter is set to the last round reported by the local round_state.
 pub static CURRENT_ROUND: Lazy<IntGauge> = Lazy::new(|| {
     register_int_gauge!(
@@ -347,6 +358,44 @@ pub static CREATION_TO_RECEIVAL_S: Lazy<DurationHistogram> = Lazy::new(|| {
     DurationHistogram::new(register_histogram!("libra_consensus_creation_to_receival_s", "Duration between block generation time until the moment it is received and ready for execution.").unwrap())
 });
 
+/////////////////////////
+// ROUND TIMELINE COUNTERS
+/////////////////////////
+
+/// Histogram of the time from the start of a round to each event of its timeline, as observed
+/// locally. event can be:
+/// proposal_received: the proposal of the round is received (including by its proposer)
+/// vote_sent: this node votes for the proposal of the round
+/// qc_formed: the proposal of the round is certified
+/// committed: the proposal of the round is committed
+pub static ROUND_TIMELINE_S: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "libra_consensus_round_timeline_s",
+        "Histogram of the time from the start of a round to each event of its timeline",
+        &["event"]
+    )
+    .unwrap()
+});
+
+/// Histogram of the time from the start of a round to the reception of its proposal, by proposer
+pub static PROPOSAL_DELAY_BY_PROPOSER_S: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "libra_consensus_proposal_delay_by_proposer_s",
+        "Histogram of the time from the start of a round to the reception of its proposal, by proposer",
+        &["proposer"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the proposer label of `PROPOSAL_DELAY_BY_PROPOSER_S`, as validators come
+/// and go across epochs
+pub static PROPOSAL_DELAY_PROPOSERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_consensus_proposal_delay_by_proposer_s/proposer",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
 ////////////////////////////////////
 // PROPSOSAL/VOTE TIMESTAMP COUNTERS
 ////////////////////////////////////
@@ -400,6 +449,27 @@ pub static VOTE_FAILURE_WAIT_S: Lazy<DurationHistogram> = Lazy::new(|| {
     DurationHistogram::new(register_histogram!("libra_consensus_vote_failure_wait_s", "Histogram of time waited for failing to have the ability to vote (both those that waited and didn't wait) while trying to follow timestamp rules").unwrap())
 });
 
+/// Offset of the timestamp of the last proposal of every proposer relative to our clock when the
+/// proposal is received, i.e., the skew of the clock of the proposer minus the delay of the
+/// proposal. Proposals ahead of our clock make us wait to vote, or not vote at all, so that a
+/// proposer whose offset stands out has a drifting clock, and so do we if all the proposers do.
+pub static PROPOSAL_TIMESTAMP_OFFSET_BY_PROPOSER_US: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_consensus_proposal_timestamp_offset_by_proposer_us",
+        "Offset of the timestamp of the last proposal of the proposer relative to our clock when received",
+        &["proposer"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the proposer label of `PROPOSAL_TIMESTAMP_OFFSET_BY_PROPOSER_US`
+pub static PROPOSAL_TIMESTAMP_OFFSET_PROPOSERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_consensus_proposal_timestamp_offset_by_proposer_us/proposer",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
 ///////////////////
 // CHANNEL COUNTERS
 ///////////////////
@@ -412,6 +482,15 @@ pub static PENDING_SELF_MESSAGES: Lazy<IntGauge> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Count of the pending failover requests of the operator
+pub static PENDING_FAILOVER_REQUESTS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "libra_consensus_pending_failover_requests",
+        "Count of the pending failover requests of the operator"
+    )
+    .unwrap()
+});
+
 /// Count of the pending outbound round timeouts
 pub static PENDING_ROUND_TIMEOUTS: Lazy<IntGauge> = Lazy::new(|| {
     register_int_gauge!(
@@ -0,0 +1,56 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Draining of consensus ahead of a restart of the node.
+//!
+//! The proposer of a round is elected by all the validators, so the node can't hand its duty over
+//! to another one. Instead, consensus reports to the drain coordinator of the node whether the
+//! node is the proposer of one of the upcoming rounds: stopping it while it isn't doesn't cost the
+//! other validators a timeout round.
+
+use consensus_types::common::Round;
+use debug_interface::drain::Drainable;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Number of rounds after the current one in which the node must not be the proposer for
+/// consensus to be drained.
+pub const PROPOSER_LOOKAHEAD: Round = 3;
+
+/// Whether the node is the proposer of the current round or of one of the `PROPOSER_LOOKAHEAD`
+/// next ones, maintained by the `RoundManager` of the current epoch. The node isn't proposing
+/// until its first round started.
+#[derive(Clone, Default)]
+pub struct ProposerDuty {
+    proposes_soon: Arc<AtomicBool>,
+}
+
+impl ProposerDuty {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn proposes_soon(&self) -> bool {
+        self.proposes_soon.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn update(&self, proposes_soon: bool) {
+        self.proposes_soon.store(proposes_soon, Ordering::Release);
+    }
+}
+
+impl Drainable for ProposerDuty {
+    fn name(&self) -> &'static str {
+        "consensus"
+    }
+
+    fn begin_drain(&self) {}
+
+    fn is_drained(&self) -> bool {
+        !self.proposes_soon()
+    }
+
+    fn end_drain(&self) {}
+}
@@ -4,18 +4,21 @@
 use crate::{
     block_storage::BlockStore,
     counters,
+    drain::ProposerDuty,
+    failover::FailoverRequest,
     liveness::{
         leader_reputation::{ActiveInactiveHeuristic, LeaderReputation, LibraDBBackend},
         proposal_generator::ProposalGenerator,
         proposer_election::ProposerElection,
         rotating_proposer_election::{choose_leader, RotatingProposer},
         round_state::{ExponentialTimeInterval, RoundState},
+        round_timeline::RoundTimeline,
     },
     network::{IncomingBlockRetrievalRequest, NetworkReceivers, NetworkSender},
     network_interface::{ConsensusMsg, ConsensusNetworkSender},
     persistent_liveness_storage::{LedgerRecoveryData, PersistentLivenessStorage, RecoveryData},
     round_manager::{RecoveryManager, RoundManager, UnverifiedEvent, VerifiedEvent},
-    state_replication::{StateComputer, TxnManager},
+    state_replication::{BlockMetadataExtensionProvider, StateComputer, TxnManager},
     util::time_service::TimeService,
 };
 use anyhow::{anyhow, bail, ensure, Context};
@@ -32,7 +35,7 @@ use libra_types::{
     account_address::AccountAddress,
     epoch_change::EpochChangeProof,
     epoch_state::EpochState,
-    on_chain_config::{OnChainConfigPayload, ValidatorSet},
+    on_chain_config::{BlockMetadataExtensionConfig, OnChainConfigPayload, ValidatorSet},
 };
 use network::protocols::network::Event;
 use safety_rules::SafetyRulesManager;
@@ -80,6 +83,13 @@ pub struct EpochManager {
     storage: Arc<dyn PersistentLivenessStorage>,
     safety_rules_manager: SafetyRulesManager,
     processor: Option<RoundProcessor>,
+    extension_provider: Option<Arc<dyn BlockMetadataExtensionProvider>>,
+    // Config of block metadata extensions in the current epoch, None if they're disabled
+    extension_config: Option<BlockMetadataExtensionConfig>,
+    // Whether SafetyRules still has to be put in standby, when the first RoundManager starts
+    start_in_standby: bool,
+    // Shared with the RoundManager of every epoch, and reported to the drain coordinator
+    proposer_duty: ProposerDuty,
 }
 
 impl EpochManager {
@@ -92,9 +102,11 @@ impl EpochManager {
         txn_manager: Box<dyn TxnManager>,
         state_computer: Arc<dyn StateComputer>,
         storage: Arc<dyn PersistentLivenessStorage>,
+        extension_provider: Option<Arc<dyn BlockMetadataExtensionProvider>>,
     ) -> Self {
         let author = config::peer_id(node_config.validator_network.as_ref().unwrap());
         let config = node_config.consensus.clone();
+        let start_in_standby = config.standby;
         let safety_rules_manager = SafetyRulesManager::new(node_config);
         Self {
             author,
@@ -108,9 +120,18 @@ impl EpochManager {
             storage,
             safety_rules_manager,
             processor: None,
+            extension_provider,
+            extension_config: None,
+            start_in_standby,
+            proposer_duty: ProposerDuty::new(),
         }
     }
 
+    /// Whether the node is the proposer of one of the upcoming rounds, across the epochs.
+    pub fn proposer_duty(&self) -> ProposerDuty {
+        self.proposer_duty.clone()
+    }
+
     fn epoch_state(&self) -> &EpochState {
         match self
             .processor
@@ -285,17 +306,36 @@ impl EpochManager {
         safety_rules
             .initialize(&proofs)
             .expect("Unable to initialize SafetyRules");
+        // SafetyRules persists its standby state, so it stays in standby until promoted, across
+        // epochs and restarts alike
+        if std::mem::take(&mut self.start_in_standby) {
+            info!("Start as a standby, SafetyRules does not sign until promoted");
+            safety_rules
+                .demote()
+                .expect("Unable to put SafetyRules in standby");
+        } else if consensus_state.standby() {
+            info!("SafetyRules is still in standby, it does not sign until promoted");
+        }
+        let standby = safety_rules
+            .consensus_state()
+            .expect("Unable to retrieve ConsensusState from SafetyRules")
+            .standby();
+        counters::STANDBY.set(standby as i64);
 
         info!("Create ProposalGenerator");
         // txn manager is required both by proposal generator (to pull the proposers)
         // and by event processor (to update their status).
-        let proposal_generator = ProposalGenerator::new(
+        let mut proposal_generator = ProposalGenerator::new(
             self.author,
             block_store.clone(),
             self.txn_manager.clone(),
             self.time_service.clone(),
             self.config.max_block_size,
         );
+        if let (Some(provider), Some(config)) = (&self.extension_provider, &self.extension_config) {
+            proposal_generator =
+                proposal_generator.with_extension_provider(provider.clone(), config.clone());
+        }
 
         info!("Create RoundState");
         let round_state =
@@ -321,6 +361,9 @@ impl EpochManager {
             self.txn_manager.clone(),
             self.storage.clone(),
             self.time_service.clone(),
+            self.extension_config.clone(),
+            RoundTimeline::new(self.time_service.clone(), self.config.log_round_timeline),
+            self.proposer_duty.clone(),
         );
         processor.start(last_vote).await;
         self.processor = Some(RoundProcessor::Normal(processor));
@@ -360,6 +403,8 @@ impl EpochManager {
             epoch: payload.epoch(),
             verifier: (&validator_set).into(),
         };
+        // extensions are disabled until the config is published
+        self.extension_config = payload.get().ok();
 
         match self.storage.start() {
             LivenessStorageData::RecoveryData(initial_data) => {
@@ -472,11 +517,39 @@ impl EpochManager {
         }
     }
 
+    fn process_failover_request(&mut self, request: FailoverRequest) -> anyhow::Result<()> {
+        let delivered = match (self.processor_mut(), request) {
+            (RoundProcessor::Normal(p), FailoverRequest::Demote(callback)) => {
+                let result = p.demote();
+                if result.is_ok() {
+                    counters::STANDBY.set(1);
+                }
+                callback.send(result).is_ok()
+            }
+            (RoundProcessor::Normal(p), FailoverRequest::Promote(handover, callback)) => {
+                let result = p.promote(&handover);
+                if result.is_ok() {
+                    counters::STANDBY.set(0);
+                }
+                callback.send(result).is_ok()
+            }
+            (RoundProcessor::Recovery(_), FailoverRequest::Demote(callback)) => callback
+                .send(Err(anyhow!("Consensus is recovering, retry later")))
+                .is_ok(),
+            (RoundProcessor::Recovery(_), FailoverRequest::Promote(_, callback)) => callback
+                .send(Err(anyhow!("Consensus is recovering, retry later")))
+                .is_ok(),
+        };
+        ensure!(delivered, "[EpochManager] Failover requester is gone");
+        Ok(())
+    }
+
     pub async fn start(
         mut self,
         mut round_timeout_sender_rx: channel::Receiver<Round>,
         mut network_receivers: NetworkReceivers,
         mut reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
+        mut failover_requests: channel::Receiver<FailoverRequest>,
     ) {
         // initial start of the processor
         if let Some(payload) = reconfig_events.next().await {
@@ -503,6 +576,10 @@ impl EpochManager {
                     idle_duration = pre_select_instant.elapsed();
                     self.process_local_timeout(round).await
                 }
+                request = failover_requests.select_next_some() => {
+                    idle_duration = pre_select_instant.elapsed();
+                    self.process_failover_request(request)
+                }
             };
             if let Err(e) = result {
                 error!("{:?}", e);
@@ -0,0 +1,60 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Demotion and promotion of the validator by its operator, to fail over to a hot standby.
+//!
+//! The requests of the node debug service are forwarded to the `EpochManager`, which hands them
+//! to the SafetyRules of the current `RoundManager`, so that they are serialized with the signing
+//! of votes, proposals and timeouts.
+
+use anyhow::Result;
+use debug_interface::failover::FailoverHandler;
+use futures::{channel::oneshot, executor::block_on};
+use safety_rules::Handover;
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// A request of the operator to the `EpochManager`.
+pub enum FailoverRequest {
+    /// Stop signing, and reply with the state to hand over.
+    Demote(oneshot::Sender<Result<Handover>>),
+    /// Import the state handed over by the demoted validator and start signing.
+    Promote(Handover, oneshot::Sender<Result<()>>),
+}
+
+/// Forwards the failover requests of the node debug service to the `EpochManager`.
+pub struct ConsensusFailover {
+    sender: Mutex<channel::Sender<FailoverRequest>>,
+}
+
+impl ConsensusFailover {
+    pub fn new(sender: channel::Sender<FailoverRequest>) -> Self {
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T>>) -> FailoverRequest,
+    ) -> Result<T> {
+        let (callback, response) = oneshot::channel();
+        self.sender.lock().unwrap().try_send(request(callback))?;
+        block_on(response)?
+    }
+}
+
+impl FailoverHandler for ConsensusFailover {
+    fn demote(&self) -> std::result::Result<Value, String> {
+        let handover = self
+            .request(FailoverRequest::Demote)
+            .map_err(|e| format!("{:#}", e))?;
+        serde_json::to_value(handover).map_err(|e| e.to_string())
+    }
+
+    fn promote(&self, handover: Value) -> std::result::Result<(), String> {
+        let handover: Handover = serde_json::from_value(handover).map_err(|e| e.to_string())?;
+        self.request(|callback| FailoverRequest::Promote(handover, callback))
+            .map_err(|e| format!("{:#}", e))
+    }
+}
@@ -15,7 +15,9 @@
 mod block_storage;
 mod consensusdb;
 mod counters;
+mod drain;
 mod epoch_manager;
+mod failover;
 mod liveness;
 mod network;
 #[cfg(test)]
@@ -38,4 +40,5 @@ pub mod network_interface;
 
 #[cfg(feature = "fuzzing")]
 pub use round_manager::round_manager_fuzzing;
+pub use state_replication::BlockMetadataExtensionProvider;
 pub use util::config_subscription::gen_consensus_reconfig_subscription;
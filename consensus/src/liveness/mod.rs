@@ -6,6 +6,7 @@ pub(crate) mod proposal_generator;
 pub(crate) mod proposer_election;
 pub(crate) mod rotating_proposer_election;
 pub(crate) mod round_state;
+pub(crate) mod round_timeline;
 
 #[cfg(test)]
 mod leader_reputation_test;
@@ -4,7 +4,7 @@
 use crate::{
     block_storage::BlockReader,
     counters,
-    state_replication::TxnManager,
+    state_replication::{BlockMetadataExtensionProvider, TxnManager},
     util::time_service::{wait_if_possible, TimeService, WaitingError, WaitingSuccess},
 };
 use anyhow::{bail, ensure, format_err, Context};
@@ -15,6 +15,9 @@ use consensus_types::{
     quorum_cert::QuorumCert,
 };
 use libra_logger::prelude::*;
+use libra_types::{
+    block_metadata::BlockMetadataExtension, on_chain_config::BlockMetadataExtensionConfig,
+};
 use std::{
     sync::{Arc, Mutex},
     time::{Duration, Instant},
@@ -47,6 +50,12 @@ pub struct ProposalGenerator {
     max_block_size: u64,
     // Last round that a proposal was generated
     last_round_generated: Mutex<Round>,
+    // Supplies the metadata extension of the proposed blocks, along with the on-chain config
+    // enabling it
+    extension_provider: Option<(
+        Arc<dyn BlockMetadataExtensionProvider>,
+        BlockMetadataExtensionConfig,
+    )>,
 }
 
 impl ProposalGenerator {
@@ -64,9 +73,21 @@ impl ProposalGenerator {
             time_service,
             max_block_size,
             last_round_generated: Mutex::new(0),
+            extension_provider: None,
         }
     }
 
+    /// Attaches the extensions supplied by `provider` to the generated proposals, as long as
+    /// they're allowed by `config`.
+    pub fn with_extension_provider(
+        mut self,
+        provider: Arc<dyn BlockMetadataExtensionProvider>,
+        config: BlockMetadataExtensionConfig,
+    ) -> Self {
+        self.extension_provider = Some((provider, config));
+        self
+    }
+
     pub fn author(&self) -> Author {
         self.author
     }
@@ -209,13 +230,26 @@ impl ProposalGenerator {
             .await
             .context("Fail to retrieve txn")?;
 
+        let timestamp_usecs = block_timestamp.as_micros() as u64;
         Ok(BlockData::new_proposal(
             txns,
             self.author,
             round,
-            block_timestamp.as_micros() as u64,
+            timestamp_usecs,
             hqc.as_ref().clone(),
-        ))
+        )
+        .with_extension(self.get_extension(round, timestamp_usecs)))
+    }
+
+    fn get_extension(&self, round: Round, timestamp_usecs: u64) -> Option<BlockMetadataExtension> {
+        let (provider, config) = self.extension_provider.as_ref()?;
+        let extension = provider.get_extension(round, timestamp_usecs)?;
+        // other validators would not vote for a proposal with an invalid extension
+        if let Err(e) = config.verify(&extension) {
+            error!("Dropping the metadata extension of round {}: {}", round, e);
+            return None;
+        }
+        Some(extension)
     }
 
     fn ensure_highest_quorum_cert(&self, round: Round) -> anyhow::Result<Arc<QuorumCert>> {
@@ -4,14 +4,21 @@
 use crate::{
     block_storage::BlockReader,
     liveness::proposal_generator::ProposalGenerator,
+    state_replication::BlockMetadataExtensionProvider,
     test_utils::{build_empty_tree, MockTransactionManager, TreeInserter},
     util::mock_time_service::SimulatedTimeService,
 };
-use consensus_types::block::{
-    block_test_utils::{certificate_for_genesis, gen_test_certificate},
-    Block,
+use consensus_types::{
+    block::{
+        block_test_utils::{certificate_for_genesis, gen_test_certificate},
+        Block,
+    },
+    common::Round,
+};
+use libra_types::{
+    block_metadata::BlockMetadataExtension, on_chain_config::BlockMetadataExtensionConfig,
+    validator_signer::ValidatorSigner,
 };
-use libra_types::validator_signer::ValidatorSigner;
 use std::{
     sync::Arc,
     time::{Duration, Instant},
@@ -21,6 +28,18 @@ fn minute_from_now() -> Instant {
     Instant::now() + Duration::new(60, 0)
 }
 
+/// Attaches the round to the proposals, with the version given by the parity of the round.
+struct RoundExtensionProvider;
+
+impl BlockMetadataExtensionProvider for RoundExtensionProvider {
+    fn get_extension(&self, round: Round, _timestamp_usecs: u64) -> Option<BlockMetadataExtension> {
+        Some(BlockMetadataExtension::new(
+            (round % 2) as u8,
+            round.to_le_bytes().to_vec(),
+        ))
+    }
+}
+
 #[tokio::test]
 async fn test_proposal_generation_empty_tree() {
     let signer = ValidatorSigner::random(None);
@@ -183,3 +202,39 @@ async fn test_empty_proposal_after_reconfiguration() {
         .await;
     assert!(err_proposal.is_err());
 }
+
+#[tokio::test]
+async fn test_proposal_generation_extension() {
+    let signer = ValidatorSigner::random(None);
+    let block_store = build_empty_tree();
+    let mut proposal_generator = ProposalGenerator::new(
+        signer.author(),
+        block_store.clone(),
+        Box::new(MockTransactionManager::new(None)),
+        Arc::new(SimulatedTimeService::new()),
+        1,
+    )
+    .with_extension_provider(
+        Arc::new(RoundExtensionProvider),
+        BlockMetadataExtensionConfig {
+            max_size: 8,
+            supported_versions: vec![1],
+        },
+    );
+
+    let proposal = proposal_generator
+        .generate_proposal(1, minute_from_now())
+        .await
+        .unwrap();
+    assert_eq!(
+        proposal.extension(),
+        Some(&BlockMetadataExtension::new(1, 1u64.to_le_bytes().to_vec()))
+    );
+
+    // Extensions not allowed by the on-chain config are dropped
+    let proposal = proposal_generator
+        .generate_proposal(2, minute_from_now())
+        .await
+        .unwrap();
+    assert_eq!(proposal.extension(), None);
+}
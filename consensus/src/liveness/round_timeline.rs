@@ -0,0 +1,168 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{counters, util::time_service::TimeService};
+use consensus_types::common::{Author, Round};
+use libra_logger::{prelude::*, StructuredLogEntry};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+#[cfg(test)]
+#[path = "round_timeline_test.rs"]
+mod round_timeline_test;
+
+/// Rounds that haven't been committed after this many newer rounds started are given up on.
+const MAX_PENDING_ROUNDS: u64 = 10;
+
+/// Events of the timeline of a round, in the order they are expected to happen.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoundEvent {
+    ProposalReceived,
+    VoteSent,
+    QcFormed,
+    Committed,
+}
+
+impl RoundEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            RoundEvent::ProposalReceived => "proposal_received",
+            RoundEvent::VoteSent => "vote_sent",
+            RoundEvent::QcFormed => "qc_formed",
+            RoundEvent::Committed => "committed",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RoundRecord {
+    start: Duration,
+    proposer: Option<Author>,
+    // time since the start of the round of each event, in the order they happened
+    events: Vec<(RoundEvent, Duration)>,
+}
+
+impl RoundRecord {
+    fn has(&self, event: RoundEvent) -> bool {
+        self.events.iter().any(|(e, _)| *e == event)
+    }
+}
+
+/// RoundTimeline records when the events of each round (proposal received, vote sent, QC formed,
+/// commit) happen relative to the start of the round, as observed by this node.
+/// Each event is exported as a histogram when it happens, and the whole timeline of a round is
+/// optionally logged as a structured log record once the round is committed or given up on.
+pub struct RoundTimeline {
+    time_service: Arc<dyn TimeService>,
+    log_rounds: bool,
+    rounds: BTreeMap<Round, RoundRecord>,
+}
+
+impl RoundTimeline {
+    pub fn new(time_service: Arc<dyn TimeService>, log_rounds: bool) -> Self {
+        Self {
+            time_service,
+            log_rounds,
+            rounds: BTreeMap::new(),
+        }
+    }
+
+    /// Starts the timeline of `round`, giving up on the rounds that are too old to be committed.
+    pub fn new_round(&mut self, round: Round) {
+        let start = self.time_service.get_current_timestamp();
+        self.rounds.entry(round).or_insert_with(|| RoundRecord {
+            start,
+            ..RoundRecord::default()
+        });
+        let stale = self
+            .rounds
+            .range(..round.saturating_sub(MAX_PENDING_ROUNDS))
+            .map(|(round, _)| *round)
+            .collect::<Vec<_>>();
+        for round in stale {
+            self.finish(round);
+        }
+    }
+
+    pub fn proposal_received(&mut self, round: Round, proposer: Author) {
+        if let Some(elapsed) = self.record(round, RoundEvent::ProposalReceived) {
+            counters::PROPOSAL_DELAY_BY_PROPOSER_S
+                .with_label_values(&[
+                    counters::PROPOSAL_DELAY_PROPOSERS.label(&proposer.short_str())
+                ])
+                .observe(elapsed.as_secs_f64());
+            if let Some(record) = self.rounds.get_mut(&round) {
+                record.proposer = Some(proposer);
+            }
+        }
+    }
+
+    pub fn vote_sent(&mut self, round: Round) {
+        self.record(round, RoundEvent::VoteSent);
+    }
+
+    /// Records the certification of the proposal of `round`.
+    pub fn qc_formed(&mut self, round: Round) {
+        self.record(round, RoundEvent::QcFormed);
+    }
+
+    /// Records the commit of the proposal of `round`, along with the certified proposals of the
+    /// preceding rounds, which are committed by the same ledger info, and closes their timelines.
+    pub fn committed(&mut self, round: Round) {
+        let rounds = self
+            .rounds
+            .range(..=round)
+            .map(|(round, _)| *round)
+            .collect::<Vec<_>>();
+        for r in rounds {
+            let certified = self
+                .rounds
+                .get(&r)
+                .map_or(false, |record| record.has(RoundEvent::QcFormed));
+            if r == round || certified {
+                self.record(r, RoundEvent::Committed);
+            }
+            self.finish(r);
+        }
+    }
+
+    /// Returns the time since the start of `round` of the events recorded so far.
+    #[cfg(test)]
+    pub fn events(&self, round: Round) -> Vec<(RoundEvent, Duration)> {
+        self.rounds
+            .get(&round)
+            .map_or_else(Vec::new, |record| record.events.clone())
+    }
+
+    /// Records the first occurrence of `event` in `round`, and returns the time since the start
+    /// of the round. Events of rounds that did not start locally are ignored.
+    fn record(&mut self, round: Round, event: RoundEvent) -> Option<Duration> {
+        let now = self.time_service.get_current_timestamp();
+        let record = self.rounds.get_mut(&round)?;
+        if record.has(event) {
+            return None;
+        }
+        let elapsed = now.checked_sub(record.start).unwrap_or_default();
+        record.events.push((event, elapsed));
+        counters::ROUND_TIMELINE_S
+            .with_label_values(&[event.as_str()])
+            .observe(elapsed.as_secs_f64());
+        Some(elapsed)
+    }
+
+    fn finish(&mut self, round: Round) {
+        let record = match self.rounds.remove(&round) {
+            Some(record) => record,
+            None => return,
+        };
+        if !self.log_rounds {
+            return;
+        }
+        let mut entry = StructuredLogEntry::new_named("consensus_round_timeline")
+            .data("round", round)
+            .data("proposer", record.proposer.map(|author| author.short_str()));
+        for (event, elapsed) in record.events {
+            entry = entry.data(event.as_str(), elapsed.as_millis() as u64);
+        }
+        send_struct_log!(entry);
+    }
+}
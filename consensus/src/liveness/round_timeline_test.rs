@@ -0,0 +1,69 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    liveness::round_timeline::{RoundEvent, RoundTimeline},
+    util::{mock_time_service::SimulatedTimeService, time_service::TimeService},
+};
+use futures::executor::block_on;
+use libra_types::account_address::AccountAddress;
+use std::{sync::Arc, time::Duration};
+
+fn advance(time_service: &SimulatedTimeService, millis: u64) {
+    block_on(time_service.sleep(Duration::from_millis(millis)));
+}
+
+#[test]
+fn test_round_timeline() {
+    let time_service = SimulatedTimeService::new();
+    let mut timeline = RoundTimeline::new(Arc::new(time_service.clone()), true);
+
+    timeline.new_round(1);
+    advance(&time_service, 10);
+    timeline.proposal_received(1, AccountAddress::random());
+    advance(&time_service, 20);
+    timeline.vote_sent(1);
+    // only the first occurrence of an event is recorded
+    timeline.vote_sent(1);
+    advance(&time_service, 30);
+    timeline.qc_formed(1);
+    // events of rounds that did not start locally are ignored
+    timeline.qc_formed(0);
+    assert_eq!(
+        timeline.events(1),
+        vec![
+            (RoundEvent::ProposalReceived, Duration::from_millis(10)),
+            (RoundEvent::VoteSent, Duration::from_millis(30)),
+            (RoundEvent::QcFormed, Duration::from_millis(60)),
+        ]
+    );
+    assert!(timeline.events(0).is_empty());
+
+    // committing round 3 closes the timelines of the previous rounds
+    timeline.new_round(2);
+    timeline.new_round(3);
+    timeline.new_round(4);
+    timeline.committed(3);
+    assert!(timeline.events(1).is_empty());
+    assert!(timeline.events(3).is_empty());
+    advance(&time_service, 5);
+    timeline.proposal_received(4, AccountAddress::random());
+    assert_eq!(
+        timeline.events(4),
+        vec![(RoundEvent::ProposalReceived, Duration::from_millis(5))]
+    );
+}
+
+#[test]
+fn test_stale_rounds() {
+    let time_service = SimulatedTimeService::new();
+    let mut timeline = RoundTimeline::new(Arc::new(time_service), false);
+
+    timeline.new_round(1);
+    timeline.vote_sent(1);
+    timeline.new_round(11);
+    assert!(!timeline.events(1).is_empty());
+    // rounds that can't be committed anymore are given up on
+    timeline.new_round(12);
+    assert!(timeline.events(1).is_empty());
+}
@@ -3,7 +3,7 @@
 
 use crate::{
     counters,
-    network_interface::{ConsensusMsg, ConsensusNetworkEvents, ConsensusNetworkSender},
+    network_interface::{ConsensusMsg, ConsensusNetworkSender},
 };
 use anyhow::{anyhow, ensure};
 use bytes::Bytes;
@@ -22,7 +22,11 @@ use libra_types::{
     account_address::AccountAddress, epoch_change::EpochChangeProof,
     validator_verifier::ValidatorVerifier,
 };
-use network::protocols::{network::Event, rpc::error::RpcError};
+use network::{
+    protocols::{network::Event, rpc::error::RpcError},
+    ProtocolId,
+};
+use network_api::{EventStream, MessageSender};
 use std::{
     mem::{discriminant, Discriminant},
     num::NonZeroUsize,
@@ -48,11 +52,12 @@ pub struct NetworkReceivers {
     pub block_retrieval: libra_channel::Receiver<AccountAddress, IncomingBlockRetrievalRequest>,
 }
 
-/// Implements the actual networking support for all consensus messaging.
+/// Implements the actual networking support for all consensus messaging, through any
+/// [`MessageSender`] of consensus messages, e.g., a simulated network in tests.
 #[derive(Clone)]
-pub struct NetworkSender {
+pub struct NetworkSender<TSender = network::protocols::network::NetworkSender<ConsensusMsg>> {
     author: Author,
-    network_sender: ConsensusNetworkSender,
+    network_sender: ConsensusNetworkSender<TSender>,
     // Self sender and self receivers provide a shortcut for sending the messages to itself.
     // (self sending is not supported by the networking API).
     // Note that we do not support self rpc requests as it might cause infinite recursive calls.
@@ -60,10 +65,13 @@ pub struct NetworkSender {
     validators: ValidatorVerifier,
 }
 
-impl NetworkSender {
+impl<TSender> NetworkSender<TSender>
+where
+    TSender: MessageSender<ConsensusMsg, Protocol = ProtocolId> + Clone,
+{
     pub fn new(
         author: Author,
-        network_sender: ConsensusNetworkSender,
+        network_sender: ConsensusNetworkSender<TSender>,
         self_sender: channel::Sender<anyhow::Result<Event<ConsensusMsg>>>,
         validators: ValidatorVerifier,
     ) -> Self {
@@ -217,11 +225,15 @@ pub struct NetworkTask {
 }
 
 impl NetworkTask {
-    /// Establishes the initial connections with the peers and returns the receivers.
-    pub fn new(
-        network_events: ConsensusNetworkEvents,
+    /// Establishes the initial connections with the peers and returns the receivers. The events
+    /// are received from any [`EventStream`], e.g., the `ConsensusNetworkEvents` of the network.
+    pub fn new<TError>(
+        network_events: impl EventStream<ConsensusMsg, RpcError, TError> + Send + Unpin + 'static,
         self_receiver: channel::Receiver<anyhow::Result<Event<ConsensusMsg>>>,
-    ) -> (NetworkTask, NetworkReceivers) {
+    ) -> (NetworkTask, NetworkReceivers)
+    where
+        TError: Into<anyhow::Error> + 'static,
+    {
         let (consensus_messages_tx, consensus_messages) = libra_channel::new(
             QueueStyle::LIFO,
             NonZeroUsize::new(1).unwrap(),
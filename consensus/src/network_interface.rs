@@ -14,15 +14,13 @@ use consensus_types::{
 };
 use libra_types::{epoch_change::EpochChangeProof, PeerId};
 use network::{
-    error::NetworkError,
     peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
-    protocols::{
-        network::{NetworkEvents, NetworkSender},
-        rpc::error::RpcError,
-    },
+    priority::ProtocolPriority,
+    protocols::network::{NetworkEvents, NetworkSender},
     validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
     ProtocolId,
 };
+use network_api::MessageSender;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -64,9 +62,12 @@ pub type ConsensusNetworkEvents = NetworkEvents<ConsensusMsg>;
 /// remote, to finally receiving the response and deserializing. It therefore
 /// makes the most sense to make the rpc call on a separate async task, which
 /// requires the `ConsensusNetworkSender` to be `Clone` and `Send`.
+///
+/// The messages are sent through any [`MessageSender`], e.g., a simulated network in tests, see
+/// [`ConsensusNetworkSender::from_sender`].
 #[derive(Clone)]
-pub struct ConsensusNetworkSender {
-    network_sender: NetworkSender<ConsensusMsg>,
+pub struct ConsensusNetworkSender<TSender = NetworkSender<ConsensusMsg>> {
+    network_sender: TSender,
 }
 
 /// Create a new Sender that only sends for the `CONSENSUS_DIRECT_SEND_PROTOCOL` and
@@ -79,6 +80,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![ProtocolId::ConsensusRpc],
             vec![ProtocolId::ConsensusDirectSend],
+            ProtocolPriority::High,
             QueueStyle::LIFO,
             NETWORK_CHANNEL_SIZE,
             Some(&counters::PENDING_CONSENSUS_NETWORK_EVENTS),
@@ -96,9 +98,16 @@ impl ConsensusNetworkSender {
         peer_mgr_reqs_tx: PeerManagerRequestSender,
         connection_reqs_tx: ConnectionRequestSender,
     ) -> Self {
-        Self {
-            network_sender: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
-        }
+        Self::from_sender(NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx))
+    }
+}
+
+impl<TSender> ConsensusNetworkSender<TSender>
+where
+    TSender: MessageSender<ConsensusMsg, Protocol = ProtocolId>,
+{
+    pub fn from_sender(network_sender: TSender) -> Self {
+        Self { network_sender }
     }
 
     /// Send a single message to the destination peer using the `CONSENSUS_DIRECT_SEND_PROTOCOL`
@@ -107,7 +116,7 @@ impl ConsensusNetworkSender {
         &mut self,
         recipient: PeerId,
         message: ConsensusMsg,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<(), TSender::Error> {
         let protocol = ProtocolId::ConsensusDirectSend;
         self.network_sender.send_to(recipient, protocol, message)
     }
@@ -118,7 +127,7 @@ impl ConsensusNetworkSender {
         &mut self,
         recipients: impl Iterator<Item = PeerId>,
         message: ConsensusMsg,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<(), TSender::Error> {
         let protocol = ProtocolId::ConsensusDirectSend;
         self.network_sender
             .send_to_many(recipients, protocol, message)
@@ -130,7 +139,7 @@ impl ConsensusNetworkSender {
         recipient: PeerId,
         message: ConsensusMsg,
         timeout: Duration,
-    ) -> Result<ConsensusMsg, RpcError> {
+    ) -> Result<ConsensusMsg, TSender::Error> {
         let protocol = ProtocolId::ConsensusRpc;
         self.network_sender
             .send_rpc(recipient, protocol, message, timeout)
@@ -23,7 +23,7 @@ use network::{
         conn_notifs_channel, ConnectionRequestSender, PeerManagerNotification, PeerManagerRequest,
         PeerManagerRequestSender,
     },
-    protocols::rpc::InboundRpcRequest,
+    protocols::{rpc::InboundRpcRequest, wire::handshake::v1::Encoding},
     ProtocolId,
 };
 use std::{
@@ -125,7 +125,7 @@ impl NetworkPlayground {
                 // but because the rpc call blocks and depends on the message
                 // delivery, we'd have to spawn the sending behaviour on a
                 // separate task, which is inconvenient.
-                PeerManagerRequest::SendRpc(dst, outbound_req) => {
+                PeerManagerRequest::SendRpc(dst, outbound_req, _) => {
                     let dst_twin_ids = author_to_twin_ids.read().unwrap().get_twin_ids(dst);
 
                     let dst_twin_id = match dst_twin_ids.iter().find(|dst_twin_id| {
@@ -154,7 +154,11 @@ impl NetworkPlayground {
                     node_consensus_tx
                         .push(
                             (src_twin_id.author, ProtocolId::ConsensusRpc),
-                            PeerManagerNotification::RecvRpc(src_twin_id.author, inbound_req),
+                            PeerManagerNotification::RecvRpc(
+                                src_twin_id.author,
+                                inbound_req,
+                                Encoding::Lcs,
+                            ),
                         )
                         .unwrap();
                 }
@@ -218,7 +222,7 @@ impl NetworkPlayground {
 
         // copy message data
         let msg_copy = match &msg_notif {
-            PeerManagerNotification::RecvMessage(src, msg) => {
+            PeerManagerNotification::RecvMessage(src, msg, _) => {
                 let msg: ConsensusMsg = lcs::from_bytes(&msg.mdata).unwrap();
                 (*src, msg)
             }
@@ -258,7 +262,7 @@ impl NetworkPlayground {
             // Convert PeerManagerRequest to corresponding PeerManagerNotification,
             // and extract destination peer
             let (dst, msg) = match &net_req {
-                PeerManagerRequest::SendMessage(dst_inner, msg_inner) => {
+                PeerManagerRequest::SendMessage(dst_inner, msg_inner, _) => {
                     (*dst_inner, msg_inner.clone())
                 }
                 msg_inner => panic!(
@@ -273,8 +277,11 @@ impl NetworkPlayground {
                 let src_twin_id_copy = src_twin_id;
                 let dst_twin_id_copy = *dst_twin_id;
 
-                let msg_notif =
-                    PeerManagerNotification::RecvMessage(src_twin_id.author, msg.clone());
+                let msg_notif = PeerManagerNotification::RecvMessage(
+                    src_twin_id.author,
+                    msg.clone(),
+                    Encoding::Lcs,
+                );
 
                 // Deliver and copy message it if it's not dropped
                 if !self.is_message_dropped(&src_twin_id_copy, &dst_twin_id_copy) {
@@ -365,7 +372,7 @@ impl NetworkPlayground {
             // Convert PeerManagerRequest to corresponding PeerManagerNotification,
             // and extract destination peer
             let (dst, msg) = match &net_req {
-                PeerManagerRequest::SendMessage(dst_inner, msg_inner) => {
+                PeerManagerRequest::SendMessage(dst_inner, msg_inner, _) => {
                     (*dst_inner, msg_inner.clone())
                 }
                 msg_inner => panic!(
@@ -377,8 +384,11 @@ impl NetworkPlayground {
             let dst_twin_ids = self.get_twin_ids(dst);
 
             for dst_twin_id in dst_twin_ids.iter() {
-                let msg_notif =
-                    PeerManagerNotification::RecvMessage(src_twin_id.author, msg.clone());
+                let msg_notif = PeerManagerNotification::RecvMessage(
+                    src_twin_id.author,
+                    msg.clone(),
+                    Encoding::Lcs,
+                );
 
                 // Deliver and copy message it if it's not dropped
                 if !self.is_message_dropped(&src_twin_id, &dst_twin_id) {
@@ -435,8 +445,71 @@ mod tests {
     use consensus_types::block_retrieval::{
         BlockRetrievalRequest, BlockRetrievalResponse, BlockRetrievalStatus,
     };
+    use futures::{
+        channel::oneshot,
+        future::{self, BoxFuture},
+    };
     use libra_crypto::HashValue;
     use libra_types::validator_verifier::random_validator_verifier;
+    use network::{error::NetworkError, protocols::network::Event};
+    use network_api::MessageSender;
+
+    /// Sender of a simulated network, which records the messages sent through it and answers
+    /// every rpc with an empty block retrieval response.
+    #[derive(Clone, Default)]
+    struct MockSender {
+        sent: Arc<Mutex<Vec<(PeerId, ProtocolId, ConsensusMsg)>>>,
+    }
+
+    impl MessageSender<ConsensusMsg> for MockSender {
+        type Protocol = ProtocolId;
+        type Error = NetworkError;
+
+        fn send_to(
+            &mut self,
+            recipient: PeerId,
+            protocol: ProtocolId,
+            message: ConsensusMsg,
+        ) -> Result<(), NetworkError> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((recipient, protocol, message));
+            Ok(())
+        }
+
+        fn send_to_many<I>(
+            &mut self,
+            recipients: I,
+            protocol: ProtocolId,
+            message: ConsensusMsg,
+        ) -> Result<(), NetworkError>
+        where
+            I: Iterator<Item = PeerId>,
+        {
+            for recipient in recipients {
+                self.send_to(recipient, protocol, message.clone())?;
+            }
+            Ok(())
+        }
+
+        fn send_rpc(
+            &mut self,
+            recipient: PeerId,
+            protocol: ProtocolId,
+            req_msg: ConsensusMsg,
+            _timeout: Duration,
+        ) -> BoxFuture<'_, Result<ConsensusMsg, NetworkError>> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((recipient, protocol, req_msg));
+            let response = BlockRetrievalResponse::new(BlockRetrievalStatus::IdNotFound, vec![]);
+            Box::pin(future::ready(Ok(ConsensusMsg::BlockRetrievalResponse(
+                Box::new(response),
+            ))))
+        }
+    }
 
     #[test]
     fn test_network_api() {
@@ -616,4 +689,96 @@ mod tests {
             assert_eq!(response.status(), BlockRetrievalStatus::IdNotFound);
         });
     }
+
+    #[test]
+    fn test_mock_network() {
+        let mut runtime = consensus_runtime();
+        let (signers, validator_verifier) = random_validator_verifier(3, None, false);
+        let peers: Vec<_> = signers.iter().map(|signer| signer.author()).collect();
+
+        // consensus runs against a simulated network rather than the network crate
+        let mock_sender = MockSender::default();
+        let (self_sender, self_receiver) = channel::new_test(8);
+        let mut node = NetworkSender::new(
+            peers[0],
+            ConsensusNetworkSender::from_sender(mock_sender.clone()),
+            self_sender,
+            validator_verifier,
+        );
+        let (mut events_tx, events_rx) =
+            mpsc::channel::<Result<Event<ConsensusMsg>, NetworkError>>(8);
+        let (task, mut receivers) = NetworkTask::new(events_rx, self_receiver);
+        runtime.handle().spawn(task.start());
+
+        let vote_msg = VoteMsg::new(
+            Vote::new(
+                VoteData::new(BlockInfo::random(1), BlockInfo::random(0)),
+                peers[0],
+                placeholder_ledger_info(),
+                &signers[0],
+            ),
+            test_utils::placeholder_sync_info(),
+        );
+        timed_block_on(&mut runtime, async {
+            // a broadcast reaches the other validators through the simulated network, and self
+            node.broadcast_vote(vote_msg.clone()).await;
+            let recipients: HashSet<_> = mock_sender
+                .sent
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|(recipient, protocol, msg)| {
+                    assert_eq!(protocol, ProtocolId::ConsensusDirectSend);
+                    match msg {
+                        ConsensusMsg::VoteMsg(v) => assert_eq!(*v, vote_msg),
+                        _ => panic!("unexpected messages"),
+                    }
+                    recipient
+                })
+                .collect();
+            assert_eq!(recipients, peers[1..].iter().copied().collect());
+            let (author, _) = receivers.consensus_messages.next().await.unwrap();
+            assert_eq!(author, peers[0]);
+
+            // the messages received from the simulated network are dispatched to consensus
+            events_tx
+                .send(Ok(Event::Message((
+                    peers[1],
+                    ConsensusMsg::VoteMsg(Box::new(vote_msg.clone())),
+                ))))
+                .await
+                .unwrap();
+            let (author, msg) = receivers.consensus_messages.next().await.unwrap();
+            assert_eq!(author, peers[1]);
+            match msg {
+                ConsensusMsg::VoteMsg(v) => assert_eq!(*v, vote_msg),
+                _ => panic!("unexpected messages"),
+            }
+
+            // and so are its rpc requests
+            let request = BlockRetrievalRequest::new(HashValue::zero(), 1);
+            let (response_tx, _response_rx) = oneshot::channel();
+            events_tx
+                .send(Ok(Event::RpcRequest((
+                    peers[1],
+                    ConsensusMsg::BlockRetrievalRequest(Box::new(request.clone())),
+                    response_tx,
+                ))))
+                .await
+                .unwrap();
+            let incoming = receivers.block_retrieval.next().await.unwrap();
+            assert_eq!(incoming.req, request);
+
+            // while the rpcs of consensus go through the simulated network
+            let response = node
+                .request_block(request, peers[1], Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), BlockRetrievalStatus::IdNotFound);
+            let sent = mock_sender.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].0, peers[1]);
+            assert_eq!(sent[0].1, ProtocolId::ConsensusRpc);
+        });
+    }
 }
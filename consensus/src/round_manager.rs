@@ -3,7 +3,7 @@
 
 use std::{sync::Arc, time::Duration};
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{ensure, format_err, Context, Result};
 use termion::color::*;
 
 use consensus_types::{
@@ -23,20 +23,20 @@ use libra_crypto::hash::TransactionAccumulatorHasher;
 use libra_logger::prelude::*;
 use libra_security_logger::{security_log, SecurityEvent};
 use libra_types::{
-    epoch_state::EpochState, proof::AccumulatorExtensionProof,
-    validator_verifier::ValidatorVerifier,
+    epoch_state::EpochState, on_chain_config::BlockMetadataExtensionConfig,
+    proof::AccumulatorExtensionProof, validator_verifier::ValidatorVerifier,
 };
-#[cfg(test)]
-use safety_rules::ConsensusState;
-use safety_rules::TSafetyRules;
+use safety_rules::{ConsensusState, Handover, TSafetyRules};
 
 use crate::{
     block_storage::{BlockReader, BlockRetriever, BlockStore, VoteReceptionResult},
     counters,
+    drain::{ProposerDuty, PROPOSER_LOOKAHEAD},
     liveness::{
         proposal_generator::ProposalGenerator,
         proposer_election::ProposerElection,
         round_state::{NewRoundEvent, NewRoundReason, RoundState},
+        round_timeline::RoundTimeline,
     },
     network::{IncomingBlockRetrievalRequest, NetworkSender},
     network_interface::ConsensusMsg,
@@ -190,6 +190,10 @@ pub struct RoundManager {
     txn_manager: Box<dyn TxnManager>,
     storage: Arc<dyn PersistentLivenessStorage>,
     time_service: Arc<dyn TimeService>,
+    // Bounds the metadata extension of proposals, None if extensions are disabled in this epoch
+    extension_config: Option<BlockMetadataExtensionConfig>,
+    round_timeline: RoundTimeline,
+    proposer_duty: ProposerDuty,
 }
 
 impl RoundManager {
@@ -204,6 +208,9 @@ impl RoundManager {
         txn_manager: Box<dyn TxnManager>,
         storage: Arc<dyn PersistentLivenessStorage>,
         time_service: Arc<dyn TimeService>,
+        extension_config: Option<BlockMetadataExtensionConfig>,
+        round_timeline: RoundTimeline,
+        proposer_duty: ProposerDuty,
     ) -> Self {
         counters::BLOCK_RETRIEVAL_COUNT.get();
         counters::STATE_SYNC_COUNT.get();
@@ -219,6 +226,9 @@ impl RoundManager {
             network,
             storage,
             time_service,
+            extension_config,
+            round_timeline,
+            proposer_duty,
         }
     }
 
@@ -242,6 +252,7 @@ impl RoundManager {
         debug!("Processing {}", new_round_event);
         counters::CURRENT_ROUND.set(new_round_event.round as i64);
         counters::ROUND_TIMEOUT_MS.set(new_round_event.timeout.as_millis() as i64);
+        self.round_timeline.new_round(new_round_event.round);
         match new_round_event.reason {
             NewRoundReason::QCReady => {
                 counters::QC_ROUNDS_COUNT.inc();
@@ -250,10 +261,13 @@ impl RoundManager {
                 counters::TIMEOUT_ROUNDS_COUNT.inc();
             }
         };
-        if !self
-            .proposer_election
-            .is_valid_proposer(self.proposal_generator.author(), new_round_event.round)
-        {
+        let author = self.proposal_generator.author();
+        let round = new_round_event.round;
+        self.proposer_duty.update(
+            (round..=round + PROPOSER_LOOKAHEAD)
+                .any(|round| self.proposer_election.is_valid_proposer(author, round)),
+        );
+        if !self.proposer_election.is_valid_proposer(author, round) {
             return;
         }
         let proposal_msg = match self.generate_proposal(new_round_event).await {
@@ -446,6 +460,10 @@ impl RoundManager {
     /// This function is called only after all the dependencies of the given QC have been retrieved.
     async fn process_certificates(&mut self) -> anyhow::Result<()> {
         let sync_info = self.block_store.sync_info();
+        self.round_timeline
+            .qc_formed(sync_info.highest_quorum_cert().certified_block().round());
+        self.round_timeline
+            .committed(self.block_store.root().round());
         self.safety_rules.update(sync_info.highest_quorum_cert())?;
         let consensus_state = self.safety_rules.consensus_state()?;
         counters::PREFERRED_BLOCK_ROUND.set(consensus_state.preferred_round() as i64);
@@ -471,14 +489,39 @@ impl RoundManager {
                 .expect("Proposal should be verified having an author"),
             proposal,
         );
+        if let Some(extension) = proposal.extension() {
+            self.extension_config
+                .as_ref()
+                .ok_or_else(|| format_err!("metadata extensions are disabled"))
+                .and_then(|config| config.verify(extension))
+                .with_context(|| {
+                    format!(
+                        "[RoundManager] Invalid metadata extension in proposal {}",
+                        proposal
+                    )
+                })?;
+        }
+
+        if let Some(author) = proposal.author() {
+            self.round_timeline
+                .proposal_received(proposal.round(), author);
+        }
 
         debug!("RoundManager: process_proposed_block {}", proposal);
 
+        let received_at = duration_since_epoch();
         if let Some(time_to_receival) =
-            duration_since_epoch().checked_sub(Duration::from_micros(proposal.timestamp_usecs()))
+            received_at.checked_sub(Duration::from_micros(proposal.timestamp_usecs()))
         {
             counters::CREATION_TO_RECEIVAL_S.observe_duration(time_to_receival);
         }
+        if let Some(author) = proposal.author() {
+            counters::PROPOSAL_TIMESTAMP_OFFSET_BY_PROPOSER_US
+                .with_label_values(&[
+                    counters::PROPOSAL_TIMESTAMP_OFFSET_PROPOSERS.label(&author.short_str())
+                ])
+                .set(proposal.timestamp_usecs() as i64 - received_at.as_micros() as i64);
+        }
 
         let proposal_round = proposal.round();
 
@@ -486,6 +529,7 @@ impl RoundManager {
             .execute_and_vote(proposal)
             .await
             .context("[RoundManager] Process proposal")?;
+        self.round_timeline.vote_sent(proposal_round);
 
         let recipients = self
             .proposer_election
@@ -532,7 +576,7 @@ impl RoundManager {
                 match waiting_error {
                     WaitingError::MaxWaitExceeded => {
                         error!(
-                                "Waiting until proposal block timestamp usecs {:?} would exceed the round duration {:?}, hence will not vote for this round",
+                                "Waiting until proposal block timestamp usecs {:?} would exceed the round duration {:?}, hence will not vote for this round. The clock of the proposer may be ahead of ours, see the clock skews of the peers and the NTP status of this node",
                                 block_timestamp_us,
                                 current_round_deadline);
                         counters::VOTE_FAILURE_WAIT_S.observe_duration(Duration::new(0, 0));
@@ -545,7 +589,7 @@ impl RoundManager {
                         wait_duration,
                     } => {
                         error!(
-                                "Even after waiting for {:?}, proposal block timestamp usecs {:?} >= current timestamp usecs {:?}, will not vote for this round",
+                                "Even after waiting for {:?}, proposal block timestamp usecs {:?} >= current timestamp usecs {:?}, will not vote for this round. The clock of the proposer may be ahead of ours, see the clock skews of the peers and the NTP status of this node",
                                 wait_duration,
                                 block_timestamp_us,
                                 current_duration_since_epoch);
@@ -774,6 +818,26 @@ impl RoundManager {
         self.process_new_round_event(new_round_event).await;
     }
 
+    /// Stops signing, and returns the state of SafetyRules to hand over to the standby promoted in
+    /// place of this validator.
+    pub fn demote(&mut self) -> anyhow::Result<Handover> {
+        let handover = self
+            .safety_rules
+            .demote()
+            .context("[RoundManager] SafetyRules fails to demote")?;
+        info!("Demoted to standby, handing over {}", handover);
+        Ok(handover)
+    }
+
+    /// Imports the state handed over by the demoted validator, and starts signing.
+    pub fn promote(&mut self, handover: &Handover) -> anyhow::Result<()> {
+        self.safety_rules
+            .promote(handover)
+            .context("[RoundManager] SafetyRules fails to promote")?;
+        info!("Promoted from standby, taking over {}", handover);
+        Ok(())
+    }
+
     /// Inspect the current consensus state.
     #[cfg(test)]
     pub fn consensus_state(&mut self) -> ConsensusState {
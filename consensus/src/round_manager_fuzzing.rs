@@ -3,10 +3,12 @@
 
 use crate::{
     block_storage::BlockStore,
+    drain::ProposerDuty,
     liveness::{
         proposal_generator::ProposalGenerator,
         rotating_proposer_election::RotatingProposer,
         round_state::{ExponentialTimeInterval, NewRoundEvent, NewRoundReason, RoundState},
+        round_timeline::RoundTimeline,
     },
     network::NetworkSender,
     network_interface::ConsensusNetworkSender,
@@ -158,7 +160,10 @@ fn create_node_for_fuzzing() -> RoundManager {
         network,
         Box::new(MockTransactionManager::new(None)),
         storage,
-        time_service,
+        time_service.clone(),
+        None,
+        RoundTimeline::new(time_service, false),
+        ProposerDuty::new(),
     )
 }
 
@@ -3,11 +3,13 @@
 
 use crate::{
     block_storage::{BlockReader, BlockStore},
+    drain::ProposerDuty,
     liveness::{
         proposal_generator::ProposalGenerator,
         proposer_election::ProposerElection,
         rotating_proposer_election::RotatingProposer,
         round_state::{ExponentialTimeInterval, RoundState},
+        round_timeline::RoundTimeline,
     },
     network::{IncomingBlockRetrievalRequest, NetworkSender},
     network_interface::{ConsensusMsg, ConsensusNetworkEvents, ConsensusNetworkSender},
@@ -26,6 +28,7 @@ use consensus_types::{
         block_test_utils::{certificate_for_genesis, gen_test_certificate},
         Block,
     },
+    block_data::BlockData,
     block_retrieval::{BlockRetrievalRequest, BlockRetrievalStatus},
     common::{Author, Payload},
     proposal_msg::ProposalMsg,
@@ -43,6 +46,7 @@ use futures::{
 use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_secure_storage::BoxedStorage;
 use libra_types::{
+    block_metadata::BlockMetadataExtension,
     epoch_state::EpochState,
     ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
     validator_signer::ValidatorSigner,
@@ -211,7 +215,10 @@ impl NodeSetup {
             network,
             Box::new(MockTransactionManager::new(None)),
             storage.clone(),
-            time_service,
+            time_service.clone(),
+            None,
+            RoundTimeline::new(time_service, false),
+            ProposerDuty::new(),
         );
         block_on(round_manager.start(last_vote_sent));
         Self {
@@ -311,6 +318,16 @@ fn new_round_on_quorum_cert() {
     });
 }
 
+#[test]
+fn proposer_duty_on_new_round() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.handle().clone());
+    let nodes = NodeSetup::create_nodes(&mut playground, runtime.handle().clone(), 2);
+    // nodes[0] is the proposer of every round
+    assert!(nodes[0].round_manager.proposer_duty.proposes_soon());
+    assert!(!nodes[1].round_manager.proposer_duty.proposes_soon());
+}
+
 #[test]
 /// If the proposal is valid, a vote should be sent
 fn vote_on_successful_proposal() {
@@ -334,7 +351,10 @@ fn vote_on_successful_proposal() {
         assert_eq!(vote_msg.vote().vote_data().proposed().id(), proposal_id);
         let consensus_state = node.round_manager.consensus_state();
         let waypoint = consensus_state.waypoint();
-        assert_eq!(consensus_state, ConsensusState::new(1, 1, 0, waypoint));
+        assert_eq!(
+            consensus_state,
+            ConsensusState::new(1, 1, 0, waypoint, false)
+        );
     });
 }
 
@@ -488,6 +508,36 @@ fn no_vote_on_invalid_proposer() {
     });
 }
 
+#[test]
+/// We don't vote for proposals carrying a metadata extension when extensions are disabled
+fn no_vote_on_disabled_extension() {
+    let mut runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.handle().clone());
+    let mut node = NodeSetup::create_nodes(&mut playground, runtime.handle().clone(), 1)
+        .pop()
+        .unwrap();
+    let genesis_qc = certificate_for_genesis();
+    let block_with_extension = Block::new_proposal_from_block_data(
+        BlockData::new_proposal(vec![], node.signer.author(), 1, 1, genesis_qc.clone())
+            .with_extension(Some(BlockMetadataExtension::new(1, vec![42]))),
+        &node.signer,
+    );
+    let block = Block::new_proposal(vec![], 1, 1, genesis_qc, &node.signer);
+    let block_id = block.id();
+    timed_block_on(&mut runtime, async {
+        // clear the message queue
+        node.next_proposal().await;
+
+        node.round_manager
+            .process_proposal(block_with_extension)
+            .await
+            .unwrap_err();
+        node.round_manager.process_proposal(block).await.unwrap();
+        let vote_msg = node.next_vote().await;
+        assert_eq!(vote_msg.vote().vote_data().proposed().id(), block_id);
+    });
+}
+
 #[test]
 /// We allow to 'skip' round if proposal carries timeout certificate for next round
 fn new_round_on_timeout_certificate() {
@@ -668,7 +718,7 @@ fn recover_on_restart() {
     let waypoint = consensus_state.waypoint();
     assert_eq!(
         consensus_state,
-        ConsensusState::new(1, num_proposals, 0, waypoint)
+        ConsensusState::new(1, num_proposals, 0, waypoint, false)
     );
     for (block, _) in data {
         assert_eq!(node.block_store.block_exists(block.id()), true);
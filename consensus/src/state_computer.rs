@@ -7,7 +7,10 @@ use consensus_types::block::Block;
 use executor_types::{BlockExecutor, StateComputeResult};
 use libra_crypto::HashValue;
 use libra_logger::prelude::*;
-use libra_types::{ledger_info::LedgerInfoWithSignatures, transaction::Transaction};
+use libra_types::{
+    block_metadata::BlockMetadataWithExtension, ledger_info::LedgerInfoWithSignatures,
+    transaction::Transaction,
+};
 use state_synchronizer::StateSyncClient;
 use std::{
     boxed::Box,
@@ -35,13 +38,20 @@ impl ExecutionProxy {
     }
 
     fn transactions_from_block(block: &Block) -> Vec<Transaction> {
-        let mut transactions = vec![Transaction::BlockMetadata(block.into())];
+        let block_metadata = match block.extension() {
+            Some(extension) => Transaction::BlockMetadataWithExtension(
+                BlockMetadataWithExtension::new(block.into(), extension.clone()),
+            ),
+            None => Transaction::BlockMetadata(block.into()),
+        };
+        let mut transactions = vec![block_metadata];
         transactions.extend(
             block
                 .payload()
                 .unwrap_or(&vec![])
                 .iter()
-                .map(|txn| Transaction::UserTransaction(txn.clone())),
+                .cloned()
+                .map(Transaction::UserTransaction),
         );
         transactions
     }
@@ -2,10 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::Result;
-use consensus_types::{block::Block, common::Payload};
+use consensus_types::{
+    block::Block,
+    common::{Payload, Round},
+};
 use executor_types::StateComputeResult;
 use libra_crypto::HashValue;
-use libra_types::ledger_info::LedgerInfoWithSignatures;
+use libra_types::{block_metadata::BlockMetadataExtension, ledger_info::LedgerInfoWithSignatures};
 
 /// Retrieves and updates the status of transactions on demand (e.g., via talking with Mempool)
 #[async_trait::async_trait]
@@ -61,3 +64,10 @@ pub trait StateComputer: Send + Sync {
     /// can assume there were no modifications to the storage made.
     async fn sync_to(&self, target: LedgerInfoWithSignatures) -> Result<()>;
 }
+
+/// Supplies the metadata extension attached to the blocks proposed by this validator. Only used
+/// when extensions are enabled by the on-chain `BlockMetadataExtensionConfig`.
+pub trait BlockMetadataExtensionProvider: Send + Sync {
+    /// Returns the extension of the proposal for `round`, if any.
+    fn get_extension(&self, round: Round, timestamp_usecs: u64) -> Option<BlockMetadataExtension>;
+}
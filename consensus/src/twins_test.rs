@@ -110,11 +110,18 @@ impl SMRNode {
             txn_manager,
             state_computer,
             storage.clone(),
+            None,
         );
         let (network_task, network_receiver) = NetworkTask::new(network_events, self_receiver);
+        let (_failover_sender, failover_receiver) = channel::new_test(1);
 
         runtime.spawn(network_task.start());
-        runtime.spawn(epoch_mgr.start(timeout_receiver, network_receiver, reconfig_events));
+        runtime.spawn(epoch_mgr.start(
+            timeout_receiver,
+            network_receiver,
+            reconfig_events,
+            failover_receiver,
+        ));
         Self {
             config,
             smr_id,
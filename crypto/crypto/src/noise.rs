@@ -633,6 +633,13 @@ impl NoiseSession {
         self.remote_public_key
     }
 
+    /// obtain the keys used to encrypt (write) and decrypt (read) messages of this session.
+    /// This is only meant to export the keys of sessions on test networks, to debug the protocol.
+    #[cfg(feature = "noise-keylog")]
+    pub fn keys(&self) -> (&[u8], &[u8]) {
+        (&self.write_key, &self.read_key)
+    }
+
     /// encrypts a message for the other peers (post-handshake)
     /// the function encrypts in place, and returns the authentication tag as result
     pub fn write_message_in_place<'a>(
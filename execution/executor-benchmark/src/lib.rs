@@ -1,6 +1,10 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod replay;
+
+pub use replay::{run_replay, ReplayReport};
+
 use executor::{db_bootstrapper::bootstrap_db_if_empty, Executor};
 use executor_types::BlockExecutor;
 use libra_config::{config::NodeConfig, utils::get_genesis_txn};
@@ -262,6 +266,7 @@ impl TransactionExecutor {
 
 fn create_storage_service_and_executor(
     config: &NodeConfig,
+    genesis_txn: &Transaction,
 ) -> (Arc<dyn DbReader>, Executor<LibraVM>) {
     let (db, db_rw) = DbReaderWriter::wrap(
         LibraDB::open(
@@ -271,7 +276,7 @@ fn create_storage_service_and_executor(
         )
         .expect("DB should open."),
     );
-    bootstrap_db_if_empty::<LibraVM>(&db_rw, get_genesis_txn(config).unwrap()).unwrap();
+    bootstrap_db_if_empty::<LibraVM>(&db_rw, genesis_txn).unwrap();
 
     let _handle = start_storage_service_with_db(config, db.clone());
     let executor = Executor::new(StorageClient::new(&config.storage.address).into());
@@ -292,7 +297,8 @@ pub fn run_benchmark(
         config.storage.dir = path;
     }
 
-    let (db, executor) = create_storage_service_and_executor(&config);
+    let (db, executor) =
+        create_storage_service_and_executor(&config, get_genesis_txn(&config).unwrap());
     let parent_block_id = executor.committed_block_id();
 
     let (block_sender, block_receiver) = mpsc::sync_channel(50 /* bound */);
@@ -349,11 +355,13 @@ fn create_transaction(
 
     let signature = private_key.sign_message(&raw_txn.hash());
     let signed_txn = SignedTransaction::new(raw_txn, public_key, signature);
-    Transaction::UserTransaction(signed_txn)
+    Transaction::UserTransaction(Arc::new(signed_txn))
 }
 
 #[cfg(test)]
 mod tests {
+    use libra_temppath::TempPath;
+
     #[test]
     fn test_benchmark() {
         super::run_benchmark(
@@ -364,4 +372,28 @@ mod tests {
             None,       /* db_dir */
         );
     }
+
+    #[test]
+    fn test_replay() {
+        let snapshot_dir = TempPath::new();
+        snapshot_dir.create_as_dir().unwrap();
+        super::run_benchmark(
+            25,                                   /* num_accounts */
+            10_000_000,                           /* init_account_balance */
+            5,                                    /* block_size */
+            5,                                    /* num_transfer_blocks */
+            Some(snapshot_dir.path().to_owned()), /* db_dir */
+        );
+
+        let report = super::run_replay(
+            snapshot_dir.path().to_owned(),
+            None,  /* db_dir */
+            100,   /* num_blocks */
+            5,     /* max_block_size */
+            false, /* pause_before_replay */
+        );
+        // 5 blocks minting the accounts, then 5 blocks of transfers
+        assert_eq!(report.num_blocks, 10);
+        assert_eq!(report.num_txns, 50);
+    }
 }
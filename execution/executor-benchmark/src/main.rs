@@ -20,6 +20,19 @@ struct Opt {
 
     #[structopt(long, parse(from_os_str))]
     db_dir: Option<PathBuf>,
+
+    /// Replays the blocks of the DB snapshot in this directory into `db_dir` instead of generating
+    /// transactions, capping the blocks at `block_size` transactions.
+    #[structopt(long, parse(from_os_str))]
+    replay_snapshot: Option<PathBuf>,
+
+    /// Number of blocks of the snapshot to replay.
+    #[structopt(long, default_value = "1000")]
+    num_replay_blocks: usize,
+
+    /// Waits for enter before the replay, to attach a profiler to the process.
+    #[structopt(long)]
+    pause_before_replay: bool,
 }
 
 fn main() {
@@ -32,11 +45,21 @@ fn main() {
         .build_global()
         .expect("Failed to build rayon global thread pool.");
 
-    executor_benchmark::run_benchmark(
-        opt.num_accounts,
-        opt.init_account_balance,
-        opt.block_size,
-        opt.num_transfer_blocks,
-        opt.db_dir,
-    );
+    if let Some(snapshot_dir) = opt.replay_snapshot {
+        executor_benchmark::run_replay(
+            snapshot_dir,
+            opt.db_dir,
+            opt.num_replay_blocks,
+            opt.block_size,
+            opt.pause_before_replay,
+        );
+    } else {
+        executor_benchmark::run_benchmark(
+            opt.num_accounts,
+            opt.init_account_balance,
+            opt.block_size,
+            opt.num_transfer_blocks,
+            opt.db_dir,
+        );
+    }
 }
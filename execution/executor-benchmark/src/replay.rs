@@ -0,0 +1,325 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replay of the blocks committed in a DB snapshot through the executor and the VM, to measure the
+//! performance of the execution stack on a reproducible workload.
+//!
+//! The replay bootstraps a fresh DB with the genesis transaction of the snapshot, then executes and
+//! commits the transactions of the snapshot block by block, checking that every transaction
+//! produces the `TransactionInfo` committed in the snapshot. The blocks are delimited by the
+//! `BlockMetadata` transactions of the snapshot, and capped at `max_block_size` transactions, e.g.,
+//! for the snapshots written by [`run_benchmark`](crate::run_benchmark), which have none.
+//!
+//! The time spent in every phase is logged for every block, and summed up in the returned
+//! [`ReplayReport`]. To profile the replay, e.g., into a flamegraph, pass `pause_before_replay`
+//! and attach a profiler to the printed pid before resuming the replay, e.g.,
+//! `perf record -F 99 -g -p <pid>` then `perf script | inferno-collapse-perf | inferno-flamegraph`.
+
+use crate::create_storage_service_and_executor;
+use executor_types::BlockExecutor;
+use libra_crypto::hash::{CryptoHash, HashValue};
+use libra_logger::prelude::*;
+use libra_types::{
+    block_info::BlockInfo,
+    ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
+    transaction::{Transaction, TransactionInfo, Version},
+};
+use libradb::LibraDB;
+use std::{
+    cmp::min,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    io::stdin,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use storage_interface::DbReader;
+
+/// Number of transactions read from the snapshot at once.
+const READ_BATCH_SIZE: u64 = 1000;
+
+/// Time spent in every phase of a replay.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayReport {
+    pub num_blocks: usize,
+    pub num_txns: usize,
+    /// Reading the transactions from the snapshot.
+    pub read: Duration,
+    /// Executing the blocks, including `vm_execute`.
+    pub execute: Duration,
+    /// Executing the transactions in the VM.
+    pub vm_execute: Duration,
+    /// Committing the blocks, including `save_transactions`.
+    pub commit: Duration,
+    /// Writing the transactions to the DB.
+    pub save_transactions: Duration,
+}
+
+impl ReplayReport {
+    fn total(&self) -> Duration {
+        self.read + self.execute + self.commit
+    }
+
+    /// Transactions replayed per second, excluding the reads from the snapshot.
+    pub fn tps(&self) -> u128 {
+        let time = self.execute + self.commit;
+        if time.as_nanos() == 0 {
+            0
+        } else {
+            self.num_txns as u128 * 1_000_000_000 / time.as_nanos()
+        }
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Replayed {} blocks, {} transactions. TPS: {}.",
+            self.num_blocks,
+            self.num_txns,
+            self.tps()
+        )?;
+        let total = self.total().as_secs_f64().max(std::f64::EPSILON);
+        for (phase, time) in &[
+            ("read", self.read),
+            ("execute", self.execute),
+            ("  vm_execute", self.vm_execute),
+            ("commit", self.commit),
+            ("  save_transactions", self.save_transactions),
+        ] {
+            writeln!(
+                f,
+                "{:<20} {:>10} ms {:>6.1}%",
+                phase,
+                time.as_millis(),
+                time.as_secs_f64() * 100.0 / total
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the transactions committed in a snapshot, block by block.
+struct SnapshotReader {
+    db: LibraDB,
+    ledger_version: Version,
+    next_version: Version,
+    pending: VecDeque<(Transaction, TransactionInfo)>,
+}
+
+impl SnapshotReader {
+    fn new(db: LibraDB) -> Self {
+        let ledger_version = db
+            .get_latest_version()
+            .expect("Failed to read the snapshot.");
+        Self {
+            db,
+            ledger_version,
+            next_version: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn genesis_txn(&mut self) -> Transaction {
+        assert_eq!(self.next_version, 0);
+        let (genesis_txn, _) = self.next_txn().expect("The snapshot is empty.");
+        genesis_txn
+    }
+
+    /// Returns the next block of at most `max_block_size` transactions, with their info in the
+    /// snapshot, or `None` at the end of the snapshot.
+    fn next_block(&mut self, max_block_size: usize) -> Option<Vec<(Transaction, TransactionInfo)>> {
+        let mut block = vec![];
+        while block.len() < max_block_size {
+            if self.pending.is_empty() && !self.read_batch() {
+                break;
+            }
+            if !block.is_empty() {
+                if let Some((Transaction::BlockMetadata(_), _))
+                | Some((Transaction::BlockMetadataWithExtension(_), _)) = self.pending.front()
+                {
+                    break;
+                }
+            }
+            block.push(self.pending.pop_front().unwrap());
+        }
+        if block.is_empty() {
+            None
+        } else {
+            Some(block)
+        }
+    }
+
+    fn next_txn(&mut self) -> Option<(Transaction, TransactionInfo)> {
+        if self.pending.is_empty() && !self.read_batch() {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+
+    /// Reads the next batch of transactions, returning false at the end of the snapshot.
+    fn read_batch(&mut self) -> bool {
+        if self.next_version > self.ledger_version {
+            return false;
+        }
+        let batch_size = min(READ_BATCH_SIZE, self.ledger_version - self.next_version + 1);
+        let txn_list = self
+            .db
+            .get_transactions(self.next_version, batch_size, self.ledger_version, false)
+            .expect("Failed to read the snapshot.");
+        self.next_version += txn_list.transactions.len() as u64;
+        self.pending.extend(
+            txn_list
+                .transactions
+                .into_iter()
+                .zip(txn_list.proof.transaction_infos().iter().cloned()),
+        );
+        true
+    }
+}
+
+/// Total time spent by the executor in `op` so far, as timed by its `executor_duration` histogram.
+fn executor_time(op: &str) -> Duration {
+    let seconds: f64 = prometheus::gather()
+        .iter()
+        .filter(|family| family.get_name() == "executor_duration")
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| {
+            metric
+                .get_label()
+                .iter()
+                .any(|label| label.get_name() == "op" && label.get_value() == op)
+        })
+        .map(|metric| metric.get_histogram().get_sample_sum())
+        .sum();
+    Duration::from_secs_f64(seconds)
+}
+
+/// Replays at most `num_blocks` blocks of the snapshot in `snapshot_dir` into a fresh DB in
+/// `db_dir`, or in a temporary directory.
+pub fn run_replay(
+    snapshot_dir: PathBuf,
+    db_dir: Option<PathBuf>,
+    num_blocks: usize,
+    max_block_size: usize,
+    pause_before_replay: bool,
+) -> ReplayReport {
+    let mut snapshot = SnapshotReader::new(
+        LibraDB::open(
+            &snapshot_dir,
+            true, /* readonly */
+            None, /* pruner */
+        )
+        .expect("Snapshot DB should open."),
+    );
+
+    let (mut config, _genesis_key) = config_builder::test_config();
+    if let Some(path) = db_dir {
+        config.storage.dir = path;
+    }
+    let (_db, mut executor) = create_storage_service_and_executor(&config, &snapshot.genesis_txn());
+    let mut parent_block_id = executor.committed_block_id();
+    // the genesis transaction ends epoch 0
+    let mut epoch = 1;
+
+    if pause_before_replay {
+        println!(
+            "Replaying from pid {}: attach a profiler, e.g., `perf record -F 99 -g -p {}`, \
+             then press enter.",
+            std::process::id(),
+            std::process::id(),
+        );
+        stdin()
+            .read_line(&mut String::new())
+            .expect("Failed to read stdin.");
+    }
+
+    let mut report = ReplayReport::default();
+    while report.num_blocks < num_blocks {
+        let read_start = Instant::now();
+        let block = match snapshot.next_block(max_block_size) {
+            Some(block) => block,
+            None => break,
+        };
+        let read_time = read_start.elapsed();
+
+        let block_id = match &block[0].0 {
+            Transaction::BlockMetadata(block_metadata) => block_metadata.id(),
+            Transaction::BlockMetadataWithExtension(block_metadata) => {
+                block_metadata.metadata().id()
+            }
+            _ => HashValue::random(),
+        };
+        let (transactions, txn_infos): (Vec<_>, Vec<_>) = block.into_iter().unzip();
+        let num_txns = transactions.len();
+        let vm_execute_before = executor_time("vm_execute_block_time_s");
+        let save_transactions_before = executor_time("storage_save_transactions_time_s");
+
+        let execute_start = Instant::now();
+        let output = executor
+            .execute_block((block_id, transactions), parent_block_id)
+            .unwrap();
+        let execute_time = execute_start.elapsed();
+
+        let expected_txn_info_hashes: Vec<_> = txn_infos.iter().map(CryptoHash::hash).collect();
+        assert_eq!(
+            output.transaction_info_hashes(),
+            &expected_txn_info_hashes,
+            "Block ending at version {} diverged from the snapshot.",
+            output.version(),
+        );
+
+        let commit_start = Instant::now();
+        let block_info = BlockInfo::new(
+            epoch,
+            0, /* round, doesn't matter */
+            block_id,
+            output.root_hash(),
+            output.version(),
+            0, /* timestamp_usecs, doesn't matter */
+            output.epoch_state().clone(),
+        );
+        let ledger_info = LedgerInfo::new(
+            block_info,
+            HashValue::zero(), /* consensus_data_hash, doesn't matter */
+        );
+        executor
+            .commit_blocks(
+                vec![block_id],
+                LedgerInfoWithSignatures::new(ledger_info, BTreeMap::new() /* signatures */),
+            )
+            .unwrap();
+        let commit_time = commit_start.elapsed();
+
+        if output.epoch_state().is_some() {
+            epoch += 1;
+        }
+        parent_block_id = block_id;
+
+        let vm_execute_time = executor_time("vm_execute_block_time_s") - vm_execute_before;
+        let save_transactions_time =
+            executor_time("storage_save_transactions_time_s") - save_transactions_before;
+        info!(
+            "Version: {}. read time: {} ms. execute time: {} ms (vm: {} ms). \
+             commit time: {} ms (save: {} ms).",
+            output.version(),
+            read_time.as_millis(),
+            execute_time.as_millis(),
+            vm_execute_time.as_millis(),
+            commit_time.as_millis(),
+            save_transactions_time.as_millis(),
+        );
+
+        report.num_blocks += 1;
+        report.num_txns += num_txns;
+        report.read += read_time;
+        report.execute += execute_time;
+        report.vm_execute += vm_execute_time;
+        report.commit += commit_time;
+        report.save_transactions += save_transactions_time;
+    }
+
+    println!("{}", report);
+    report
+}
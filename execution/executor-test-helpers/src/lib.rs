@@ -17,6 +17,7 @@ use libra_types::{
     transaction::{Script, Transaction},
     validator_signer::ValidatorSigner,
 };
+use std::sync::Arc;
 
 pub fn gen_block_id(index: u8) -> HashValue {
     HashValue::new([index; HashValue::LENGTH])
@@ -79,11 +80,11 @@ pub fn get_test_signed_transaction(
     public_key: Ed25519PublicKey,
     program: Option<Script>,
 ) -> Transaction {
-    Transaction::UserTransaction(get_test_signed_txn(
+    Transaction::UserTransaction(Arc::new(get_test_signed_txn(
         sender,
         sequence_number,
         &private_key,
         public_key,
         program,
-    ))
+    )))
 }
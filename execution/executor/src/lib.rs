@@ -10,6 +10,7 @@ mod mock_vm;
 mod speculation_cache;
 
 pub mod db_bootstrapper;
+pub mod shadow_vm;
 
 use anyhow::{bail, ensure, format_err, Result};
 use debug_interface::prelude::*;
@@ -348,7 +349,8 @@ where
                     // maybe other writeset transactions).
                     match transaction {
                         Transaction::WaypointWriteSet(_) => (),
-                        Transaction::BlockMetadata(_) => {
+                        Transaction::BlockMetadata(_)
+                        | Transaction::BlockMetadataWithExtension(_) => {
                             bail!("Write set should be a subset of read set.")
                         }
                         Transaction::UserTransaction(txn) => match txn.payload() {
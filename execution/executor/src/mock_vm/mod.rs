@@ -25,7 +25,7 @@ use libra_types::{
 use libra_vm::VMExecutor;
 use move_core_types::{language_storage::TypeTag, move_resource::MoveResource};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug)]
 enum MockVMTransaction {
@@ -318,24 +318,24 @@ fn encode_transaction(sender: AccountAddress, program: Script) -> Transaction {
     );
 
     let privkey = Ed25519PrivateKey::generate_for_testing();
-    Transaction::UserTransaction(
+    Transaction::UserTransaction(Arc::new(
         raw_transaction
             .sign(&privkey, privkey.public_key())
             .expect("Failed to sign raw transaction.")
             .into_inner(),
-    )
+    ))
 }
 
 pub fn encode_reconfiguration_transaction(sender: AccountAddress) -> Transaction {
     let raw_transaction = RawTransaction::new_write_set(sender, 0, WriteSet::default());
 
     let privkey = Ed25519PrivateKey::generate_for_testing();
-    Transaction::UserTransaction(
+    Transaction::UserTransaction(Arc::new(
         raw_transaction
             .sign(&privkey, privkey.public_key())
             .expect("Failed to sign raw transaction.")
             .into_inner(),
-    )
+    ))
 }
 
 fn decode_transaction(txn: &SignedTransaction) -> MockVMTransaction {
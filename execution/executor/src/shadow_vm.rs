@@ -0,0 +1,253 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A VM cross-checking the execution of the VM of the node against a candidate VM, e.g., a new
+//! build of the VM, so that an upgrade of the VM can be validated against live traffic before it
+//! is deployed.
+//!
+//! [`ShadowVM`] executes every block with both VMs on the same state, and returns the outputs of
+//! the VM of the node, whatever the candidate VM does: the transactions whose status, gas, write
+//! set or events differ are only logged, and counted in `shadow_vm_divergences` of the executor
+//! counters. A panic of the candidate VM is logged and counted in `shadow_vm_panics` too.
+//!
+//! The candidate VM doubles the execution time of the blocks, so that it is meant for full nodes,
+//! whose execution isn't on the critical path of consensus.
+
+use crate::OP_COUNTERS;
+use libra_crypto::hash::CryptoHash;
+use libra_logger::prelude::*;
+use libra_state_view::StateView;
+use libra_types::{
+    transaction::{Transaction, TransactionOutput},
+    vm_error::VMStatus,
+};
+use libra_vm::VMExecutor;
+use std::{
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+};
+
+/// VM returning the outputs of `V`, and reporting the ones which differ from those of the
+/// candidate VM `C`.
+pub struct ShadowVM<V, C> {
+    phantom: PhantomData<(V, C)>,
+}
+
+impl<V: VMExecutor, C: VMExecutor> VMExecutor for ShadowVM<V, C> {
+    fn execute_block(
+        transactions: Vec<Transaction>,
+        state_view: &dyn StateView,
+    ) -> Result<Vec<TransactionOutput>, VMStatus> {
+        let result = V::execute_block(transactions.clone(), state_view);
+
+        let candidate_result = {
+            let _timer = OP_COUNTERS.timer("shadow_vm_execute_block_time_s");
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                crash_handler::with_recoverable_panics(|| {
+                    C::execute_block(transactions.clone(), state_view)
+                })
+            }))
+        };
+        match candidate_result {
+            Ok(candidate_result) => {
+                let divergences = compare_results(&transactions, &result, &candidate_result);
+                OP_COUNTERS.inc_by("shadow_vm_divergences", divergences);
+            }
+            Err(_) => {
+                error!(
+                    "[shadow vm] The candidate VM panicked executing a block of {} transactions",
+                    transactions.len()
+                );
+                OP_COUNTERS.inc("shadow_vm_panics");
+            }
+        }
+
+        result
+    }
+}
+
+/// Logs the transactions of the block whose outputs differ between the results, and returns their
+/// number, or 1 if the results of the whole block differ.
+fn compare_results(
+    transactions: &[Transaction],
+    result: &Result<Vec<TransactionOutput>, VMStatus>,
+    candidate_result: &Result<Vec<TransactionOutput>, VMStatus>,
+) -> usize {
+    match (result, candidate_result) {
+        (Ok(outputs), Ok(candidate_outputs)) if outputs.len() == candidate_outputs.len() => {
+            let mut divergences = 0;
+            for ((txn, output), candidate_output) in
+                transactions.iter().zip(outputs).zip(candidate_outputs)
+            {
+                let diff = diff_outputs(output, candidate_output);
+                if !diff.is_empty() {
+                    warn!(
+                        "[shadow vm] The candidate VM diverged on the {} of transaction {}: \
+                         {:?} != {:?}",
+                        diff.join(", "),
+                        txn.hash(),
+                        output,
+                        candidate_output
+                    );
+                    divergences += 1;
+                }
+            }
+            divergences
+        }
+        (Err(status), Err(candidate_status)) if status == candidate_status => 0,
+        _ => {
+            warn!(
+                "[shadow vm] The candidate VM diverged on a block of {} transactions: {} != {}",
+                transactions.len(),
+                summarize(result),
+                summarize(candidate_result)
+            );
+            1
+        }
+    }
+}
+
+/// The parts of the outputs which differ.
+fn diff_outputs(
+    output: &TransactionOutput,
+    candidate_output: &TransactionOutput,
+) -> Vec<&'static str> {
+    let mut diff = vec![];
+    if output.status() != candidate_output.status() {
+        diff.push("status");
+    }
+    if output.gas_used() != candidate_output.gas_used() {
+        diff.push("gas used");
+    }
+    if output.write_set() != candidate_output.write_set() {
+        diff.push("write set");
+    }
+    if output.events() != candidate_output.events() {
+        diff.push("events");
+    }
+    diff
+}
+
+fn summarize(result: &Result<Vec<TransactionOutput>, VMStatus>) -> String {
+    match result {
+        Ok(outputs) => format!("{} outputs", outputs.len()),
+        Err(status) => format!("error {:?}", status),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_vm::{encode_mint_transaction, MockVM};
+    use libra_types::{
+        access_path::AccessPath, account_address::AccountAddress, vm_error::StatusCode,
+        write_set::WriteSet,
+    };
+
+    struct MockStateView;
+
+    impl StateView for MockStateView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn multi_get(&self, access_paths: &[AccessPath]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+            Ok(vec![None; access_paths.len()])
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    /// The mock VM, with buggy gas metering.
+    struct DoubleGasVM;
+
+    impl VMExecutor for DoubleGasVM {
+        fn execute_block(
+            transactions: Vec<Transaction>,
+            state_view: &dyn StateView,
+        ) -> Result<Vec<TransactionOutput>, VMStatus> {
+            Ok(MockVM::execute_block(transactions, state_view)?
+                .into_iter()
+                .map(|output| {
+                    TransactionOutput::new(
+                        output.write_set().clone(),
+                        output.events().to_vec(),
+                        output.gas_used() * 2 + 1,
+                        output.status().clone(),
+                    )
+                })
+                .collect())
+        }
+    }
+
+    struct PanickingVM;
+
+    impl VMExecutor for PanickingVM {
+        fn execute_block(
+            _transactions: Vec<Transaction>,
+            _state_view: &dyn StateView,
+        ) -> Result<Vec<TransactionOutput>, VMStatus> {
+            panic!("candidate VM bug")
+        }
+    }
+
+    fn block() -> Vec<Transaction> {
+        (0..3)
+            .map(|i| encode_mint_transaction(AccountAddress::random(), 100 + i))
+            .collect()
+    }
+
+    #[test]
+    fn returns_the_outputs_of_the_node_vm() {
+        let txns = block();
+        let expected = MockVM::execute_block(txns.clone(), &MockStateView).unwrap();
+
+        let outputs =
+            ShadowVM::<MockVM, DoubleGasVM>::execute_block(txns.clone(), &MockStateView).unwrap();
+        assert_eq!(outputs, expected);
+
+        let outputs = ShadowVM::<MockVM, PanickingVM>::execute_block(txns, &MockStateView).unwrap();
+        assert_eq!(outputs, expected);
+    }
+
+    #[test]
+    fn divergences() {
+        let txns = block();
+        let result = MockVM::execute_block(txns.clone(), &MockStateView);
+        assert_eq!(compare_results(&txns, &result, &result), 0);
+
+        let candidate_result = DoubleGasVM::execute_block(txns.clone(), &MockStateView);
+        assert_eq!(compare_results(&txns, &result, &candidate_result), 3);
+
+        let candidate_result = Err(VMStatus::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR));
+        assert_eq!(compare_results(&txns, &result, &candidate_result), 1);
+        assert_eq!(
+            compare_results(&txns, &candidate_result, &candidate_result),
+            0
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let output = TransactionOutput::new(
+            WriteSet::default(),
+            vec![],
+            10,
+            crate::mock_vm::KEEP_STATUS.clone(),
+        );
+        assert!(diff_outputs(&output, &output).is_empty());
+
+        let candidate_output = TransactionOutput::new(
+            WriteSet::default(),
+            vec![],
+            11,
+            crate::mock_vm::DISCARD_STATUS.clone(),
+        );
+        assert_eq!(
+            diff_outputs(&output, &candidate_output),
+            vec!["status", "gas used"]
+        );
+    }
+}
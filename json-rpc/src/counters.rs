@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use libra_metrics::{register_int_gauge_vec, IntGaugeVec};
+use libra_metrics::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
 use once_cell::sync::Lazy;
 
 /// Cumulative number of valid requests that the JSON RPC client service receives
@@ -11,7 +11,7 @@ pub static REQUESTS: Lazy<IntGaugeVec> = Lazy::new(|| {
         "Cumulative number of requests that JSON RPC client service receives",
         &[
             "type",   // type of request, matches JSON RPC method name (e.g. "submit", "get_account_state")
-            "result", // result of request: "success", "fail"
+            "result", // result of request: "success", "fail", "stale" (rejected as the state is stale)
         ]
     )
     .unwrap()
@@ -28,3 +28,16 @@ pub static INVALID_REQUESTS: Lazy<IntGaugeVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+/// Cumulative usage of the JSON RPC client service per API key, when quotas are enabled
+pub static API_KEY_USAGE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_client_service_api_key_usage",
+        "Cumulative usage of the JSON RPC client service per API key",
+        &[
+            "api_key", // API key from the node config, so the number of values stays bounded
+            "type",    // unit of usage: "requests", "weight", "result_bytes"
+        ]
+    )
+    .unwrap()
+});
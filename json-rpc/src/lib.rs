@@ -10,7 +10,11 @@
 //!
 //! Module organization:
 //! ├── methods.rs        # contains all available JSON RPC method handlers
+//! ├── quota.rs          # per-API-key usage metering and quotas
+//! ├── rate_limit.rs     # node-wide rate limiting of heavy methods
 //! ├── runtime.rs        # implementation of JSON RPC protocol over HTTP
+//! ├── schema.rs         # OpenRPC document of the API, generated from the method handlers
+//! ├── staleness.rs      # staleness guards of a node lagging behind the chain
 //! ├── tests.rs          # tests
 
 #[macro_use]
@@ -18,11 +22,19 @@ mod util;
 
 mod counters;
 mod methods;
+mod quota;
+mod rate_limit;
 mod runtime;
+mod schema;
+mod staleness;
 
 pub use libra_json_rpc_types::{errors, views};
 
-pub use runtime::{bootstrap, bootstrap_from_config};
+pub use quota::{Usage, UsageExporter};
+pub use runtime::{
+    bootstrap, bootstrap_from_config, bootstrap_with_usage_exporter, BootstrapOptions,
+};
+pub use schema::{API_VERSION, SCHEMA_PATH};
 
 #[cfg(any(feature = "fuzzing", test))]
 /// Fuzzer for JSON RPC service
@@ -4,9 +4,13 @@
 //! Module contains RPC method handlers for Full Node JSON-RPC interface
 use crate::{
     errors::JsonRpcError,
+    rate_limit::RateLimiter,
+    schema::{optional_param, param, SchemaBuilder},
     views::{
-        AccountStateWithProofView, AccountView, BlockMetadata, CurrencyInfoView, EventView,
-        StateProofView, TransactionView,
+        AccountStatePageView, AccountStateWithProofView, AccountView, BlockMetadata,
+        ConnectedPeerView, CurrencyInfoView, EventView, ExpiredTransactionView,
+        KeyedAccountStateView, ParkedTransactionView, StateProofView, TimeSyncStatusView,
+        TransactionView,
     },
 };
 use anyhow::{ensure, format_err, Error, Result};
@@ -14,8 +18,8 @@ use core::future::Future;
 use debug_interface::prelude::*;
 use futures::{channel::oneshot, SinkExt};
 use libra_config::config::RoleType;
-use libra_crypto::hash::CryptoHash;
-use libra_mempool::MempoolClientSender;
+use libra_crypto::hash::{CryptoHash, HashValue};
+use libra_mempool::{MempoolClientRequest, MempoolClientSender};
 use libra_types::{
     account_address::AccountAddress,
     account_config::{from_currency_code_string, CurrencyInfoResource},
@@ -25,9 +29,9 @@ use libra_types::{
     mempool_status::MempoolStatusCode,
     move_resource::MoveStorage,
     on_chain_config::{OnChainConfig, RegisteredCurrencies},
-    transaction::SignedTransaction,
+    transaction::{SignedTransaction, Transaction},
 };
-use network::counters;
+use network::{connected_peers::ConnectedPeers, counters, time_sync};
 use serde_json::Value;
 use std::{collections::HashMap, convert::TryFrom, ops::Deref, pin::Pin, str::FromStr, sync::Arc};
 use storage_interface::DbReader;
@@ -37,20 +41,57 @@ pub(crate) struct JsonRpcService {
     db: Arc<dyn DbReader>,
     mempool_sender: MempoolClientSender,
     role: RoleType,
+    /// Number of versions for which storage keeps the account state, if it prunes it
+    prune_window: Option<u64>,
+    /// Shared by all the consumers of `get_account_states`
+    account_state_rate_limiter: Arc<RateLimiter>,
+    /// Peers connected to this node, only set if the admin methods are enabled
+    connected_peers: Option<ConnectedPeers>,
 }
 
 impl JsonRpcService {
-    pub fn new(db: Arc<dyn DbReader>, mempool_sender: MempoolClientSender, role: RoleType) -> Self {
+    pub fn new(
+        db: Arc<dyn DbReader>,
+        mempool_sender: MempoolClientSender,
+        role: RoleType,
+        prune_window: Option<u64>,
+        max_account_states_per_sec: u64,
+        connected_peers: Option<ConnectedPeers>,
+    ) -> Self {
         Self {
             db,
             mempool_sender,
             role,
+            prune_window,
+            account_state_rate_limiter: Arc::new(RateLimiter::new(max_account_states_per_sec)),
+            connected_peers,
         }
     }
 
     pub fn get_latest_ledger_info(&self) -> Result<LedgerInfoWithSignatures> {
         self.db.get_latest_ledger_info()
     }
+
+    /// Checks that the account state at `version` can be served, i.e., `version` is not ahead of
+    /// `latest_version` and has not been pruned yet.
+    fn ensure_state_available(&self, version: u64, latest_version: u64) -> Result<()> {
+        ensure!(
+            version <= latest_version,
+            "version {} is greater than latest version {}",
+            version,
+            latest_version
+        );
+        if let Some(prune_window) = self.prune_window {
+            let earliest_version = latest_version.saturating_sub(prune_window);
+            if version < earliest_version {
+                return Err(Error::new(JsonRpcError::state_pruned(
+                    version,
+                    earliest_version,
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 type RpcHandler =
@@ -64,15 +105,23 @@ pub(crate) struct JsonRpcRequest {
 }
 
 impl JsonRpcRequest {
-    /// Returns the request parameter at the given index. Note: this method should not panic with
-    /// an out of bounds error, as the number of request parameters has already been checked.
+    /// Returns the request parameter at the given index, or null for an omitted optional parameter.
     fn get_param(&self, index: usize) -> Value {
-        self.params[index].clone()
+        self.params.get(index).cloned().unwrap_or(Value::Null)
     }
 
     fn version(&self) -> u64 {
         self.ledger_info.ledger_info().version()
     }
+
+    /// Returns the version passed at the given index, defaulting to the latest version if the
+    /// parameter is null or omitted.
+    fn get_version_param(&self, index: usize) -> Result<u64> {
+        match self.get_param(index) {
+            Value::Null => Ok(self.version()),
+            version => Ok(serde_json::from_value(version)?),
+        }
+    }
 }
 
 /// Submits transaction to full node
@@ -84,7 +133,10 @@ async fn submit(mut service: JsonRpcService, request: JsonRpcRequest) -> Result<
     let (req_sender, callback) = oneshot::channel();
     service
         .mempool_sender
-        .send((transaction, req_sender))
+        .send(MempoolClientRequest::SubmitTransaction(
+            transaction,
+            req_sender,
+        ))
         .await?;
     let (mempool_status, vm_status) = callback.await??;
 
@@ -97,18 +149,48 @@ async fn submit(mut service: JsonRpcService, request: JsonRpcRequest) -> Result<
     }
 }
 
-/// Returns account state (AccountView) by given address
+/// Submits a batch of transactions of a single sender with consecutive sequence numbers to full
+/// node, which accepts either all of them or none
+async fn submit_batch(mut service: JsonRpcService, request: JsonRpcRequest) -> Result<()> {
+    let txn_payloads: Vec<String> = serde_json::from_value(request.get_param(0))?;
+    let transactions = txn_payloads
+        .into_iter()
+        .map(|txn_payload| Ok(lcs::from_bytes(&hex::decode(txn_payload)?)?))
+        .collect::<Result<Vec<SignedTransaction>>>()?;
+
+    let (req_sender, callback) = oneshot::channel();
+    service
+        .mempool_sender
+        .send(MempoolClientRequest::SubmitTransactionBatch(
+            transactions,
+            req_sender,
+        ))
+        .await?;
+    let (mempool_status, vm_status) = callback.await??;
+
+    if let Some(vm_error) = vm_status {
+        Err(Error::new(JsonRpcError::vm_error(vm_error)))
+    } else if mempool_status.code == MempoolStatusCode::Accepted {
+        Ok(())
+    } else {
+        Err(Error::new(JsonRpcError::mempool_error(mempool_status)?))
+    }
+}
+
+/// Returns account state (AccountView) by given address, as of the given version if specified
 async fn get_account_state(
     service: JsonRpcService,
     request: JsonRpcRequest,
 ) -> Result<Option<AccountView>> {
     let address: String = serde_json::from_value(request.get_param(0))?;
     let account_address = AccountAddress::from_str(&address)?;
+    let version = request.get_version_param(1)?;
+    service.ensure_state_available(version, request.version())?;
     let response = service
         .db
-        .get_account_state_with_proof_by_version(account_address, request.version())?
+        .get_account_state_with_proof_by_version(account_address, version)?
         .0;
-    let currency_info = currencies_info(service, request).await?;
+    let currency_info = get_currencies_at_version(&service, version)?;
     let currencies: Vec<_> = currency_info
         .into_iter()
         .map(|info| from_currency_code_string(&info.code))
@@ -245,16 +327,22 @@ async fn get_account_transaction(
     }
 }
 
-/// Returns events by given access path
+/// Returns events by given access path, emitted up to the given version if specified
 async fn get_events(service: JsonRpcService, request: JsonRpcRequest) -> Result<Vec<EventView>> {
     let raw_event_key: String = serde_json::from_value(request.get_param(0))?;
     let start: u64 = serde_json::from_value(request.get_param(1))?;
     let limit: u64 = serde_json::from_value(request.get_param(2))?;
+    let req_version = request.get_version_param(3)?;
+    ensure!(
+        req_version <= request.version(),
+        "version {} is greater than latest version {}",
+        req_version,
+        request.version()
+    );
 
     let event_key = EventKey::try_from(&hex::decode(raw_event_key)?[..])?;
     let events_with_proof = service.db.get_events(&event_key, start, true, limit)?;
 
-    let req_version = request.version();
     let events = events_with_proof
         .into_iter()
         .filter(|(version, _event)| version <= &req_version)
@@ -267,10 +355,17 @@ async fn get_events(service: JsonRpcService, request: JsonRpcRequest) -> Result<
 async fn currencies_info(
     service: JsonRpcService,
     request: JsonRpcRequest,
+) -> Result<Vec<CurrencyInfoView>> {
+    get_currencies_at_version(&service, request.version())
+}
+
+fn get_currencies_at_version(
+    service: &JsonRpcService,
+    version: u64,
 ) -> Result<Vec<CurrencyInfoView>> {
     let raw_data = service.db.deref().batch_fetch_resources_by_version(
         vec![RegisteredCurrencies::CONFIG_ID.access_path()],
-        request.version(),
+        version,
     )?;
     ensure!(raw_data.len() == 1, "invalid storage result");
     let currencies = RegisteredCurrencies::from_bytes(&raw_data[0])?;
@@ -284,7 +379,7 @@ async fn currencies_info(
     for raw_data in service
         .db
         .deref()
-        .batch_fetch_resources_by_version(access_paths, request.version())?
+        .batch_fetch_resources_by_version(access_paths, version)?
     {
         let currency_info = CurrencyInfoResource::try_from_bytes(&raw_data)?;
         currencies.push(CurrencyInfoView::from(currency_info));
@@ -319,6 +414,14 @@ async fn get_account_state_with_proof(
         serde_json::from_value::<u64>(request.get_param(1)).unwrap_or_else(|_| request.version());
     let ledger_version =
         serde_json::from_value::<u64>(request.get_param(2)).unwrap_or_else(|_| request.version());
+    ensure!(
+        version <= ledger_version && ledger_version <= request.version(),
+        "invalid versions: version {}, ledger version {}, latest version {}",
+        version,
+        ledger_version,
+        request.version()
+    );
+    service.ensure_state_available(version, request.version())?;
 
     let account_state_with_proof =
         service
@@ -329,6 +432,108 @@ async fn get_account_state_with_proof(
     )?)
 }
 
+/// Returns a page of at most `limit` account states at `version`, ordered by the hash of their
+/// address, starting from `page_token`. Iterating over all the account states is rate limited by
+/// the node, regardless of the quotas of the client.
+async fn get_account_states(
+    service: JsonRpcService,
+    request: JsonRpcRequest,
+) -> Result<AccountStatePageView> {
+    let limit: u64 = serde_json::from_value(request.get_param(0))?;
+    let page_token = match request.get_param(1) {
+        Value::Null => None,
+        page_token => {
+            let page_token: String = serde_json::from_value(page_token)?;
+            Some(HashValue::from_hex(&page_token)?)
+        }
+    };
+    let version = request.get_version_param(2)?;
+    service.ensure_state_available(version, request.version())?;
+
+    if !service.account_state_rate_limiter.try_acquire(limit) {
+        return Err(Error::new(JsonRpcError::quota_exceeded(
+            "max_account_states_per_sec".to_string(),
+        )));
+    }
+
+    let page = service
+        .db
+        .get_account_state_page(version, page_token, limit)?;
+    Ok(AccountStatePageView {
+        version,
+        account_states: page
+            .account_states
+            .into_iter()
+            .map(KeyedAccountStateView::try_from)
+            .collect::<Result<_>>()?,
+        next_page_token: page.next_page_token.map(|key| key.to_hex()),
+    })
+}
+
+/// Returns the transactions of an account parked in the mempool of this node, i.e., waiting for
+/// transactions with lower sequence numbers, ordered by sequence number
+async fn get_parked_transactions(
+    mut service: JsonRpcService,
+    request: JsonRpcRequest,
+) -> Result<Vec<ParkedTransactionView>> {
+    let address: String = serde_json::from_value(request.get_param(0))?;
+    let account_address = AccountAddress::from_str(&address)?;
+
+    let (req_sender, callback) = oneshot::channel();
+    service
+        .mempool_sender
+        .send(MempoolClientRequest::GetParkedTransactions(
+            account_address,
+            req_sender,
+        ))
+        .await?;
+    let parked_transactions = callback.await?;
+
+    Ok(parked_transactions
+        .into_iter()
+        .map(|(txn, parked_duration)| {
+            let txn = Transaction::UserTransaction(Arc::new(txn));
+            ParkedTransactionView {
+                hash: txn.hash().to_string(),
+                transaction: txn.into(),
+                parked_duration_ms: parked_duration.as_millis() as u64,
+            }
+        })
+        .collect())
+}
+
+/// Returns the transactions of an account which expired in the mempool of this node without being
+/// committed, i.e., which will never be committed, ordered by sequence number
+async fn get_expired_transactions(
+    mut service: JsonRpcService,
+    request: JsonRpcRequest,
+) -> Result<Vec<ExpiredTransactionView>> {
+    let address: String = serde_json::from_value(request.get_param(0))?;
+    let account_address = AccountAddress::from_str(&address)?;
+
+    let (req_sender, callback) = oneshot::channel();
+    service
+        .mempool_sender
+        .send(MempoolClientRequest::GetExpiredTransactions(
+            account_address,
+            req_sender,
+        ))
+        .await?;
+    let expired_transactions = callback.await?;
+
+    Ok(expired_transactions
+        .into_iter()
+        .map(|(txn, block_timestamp)| {
+            let txn = Transaction::UserTransaction(Arc::new(txn));
+            ExpiredTransactionView {
+                hash: txn.hash().to_string(),
+                transaction: txn.into(),
+                block_timestamp_usecs: block_timestamp.as_micros() as u64,
+            }
+        })
+        .collect())
+}
+
 /// Returns the number of peers this node is connected to
 async fn get_network_status(service: JsonRpcService, _request: JsonRpcRequest) -> Result<u64> {
     let blah = counters::LIBRA_NETWORK_PEERS
@@ -336,14 +541,61 @@ async fn get_network_status(service: JsonRpcService, _request: JsonRpcRequest) -
     Ok(blah.get() as u64)
 }
 
+/// Returns the peers this node is connected to, on all its networks. This is an admin method,
+/// which is not found unless enabled in the config of the node
+async fn get_network_topology(
+    service: JsonRpcService,
+    _request: JsonRpcRequest,
+) -> Result<Vec<ConnectedPeerView>> {
+    let connected_peers = service
+        .connected_peers
+        .ok_or_else(JsonRpcError::method_not_found)?;
+    Ok(connected_peers
+        .snapshot()
+        .into_iter()
+        .map(|peer| ConnectedPeerView {
+            peer_id: peer.peer_id.to_string(),
+            role: peer.role.as_str().to_string(),
+            network_id: peer.network_id.as_str().to_string(),
+            address: peer.address.to_string(),
+            rtt_ms: peer.rtt.map(|rtt| rtt.as_millis() as u64),
+            synced_version: peer.synced_version,
+            clock_skew_ms: peer.clock_skew_usecs.map(|skew| skew / 1000),
+        })
+        .collect())
+}
+
+/// Returns the quality of the time synchronization of this node: the synchronization status of
+/// its clock, and the skew of the clocks of its peers. This is an admin method, which is not found
+/// unless enabled in the config of the node
+async fn get_time_sync_status(
+    service: JsonRpcService,
+    _request: JsonRpcRequest,
+) -> Result<TimeSyncStatusView> {
+    let connected_peers = service
+        .connected_peers
+        .ok_or_else(JsonRpcError::method_not_found)?;
+    let peers = connected_peers.snapshot();
+    Ok(TimeSyncStatusView {
+        ntp_synchronized: time_sync::ntp_synchronized(),
+        median_peer_clock_skew_ms: time_sync::median_clock_skew_usecs(&peers)
+            .map(|skew| skew / 1000),
+        num_peers_measured: peers
+            .iter()
+            .filter(|peer| peer.clock_skew_usecs.is_some())
+            .count() as u64,
+    })
+}
+
 /// Builds registry of all available RPC methods
 /// To register new RPC method, add it via `register_rpc_method!` macros call
 /// Note that RPC method name will equal to name of function
 pub(crate) fn build_registry() -> RpcRegistry {
     let mut registry = RpcRegistry::new();
     register_rpc_method!(registry, "submit", submit, 1);
+    register_rpc_method!(registry, "submit_batch", submit_batch, 1);
     register_rpc_method!(registry, "get_metadata", get_metadata, 1);
-    register_rpc_method!(registry, "get_account_state", get_account_state, 1);
+    register_rpc_method!(registry, "get_account_state", get_account_state, 1, 1);
     register_rpc_method!(registry, "get_transactions", get_transactions, 3);
     register_rpc_method!(
         registry,
@@ -351,7 +603,7 @@ pub(crate) fn build_registry() -> RpcRegistry {
         get_account_transaction,
         3
     );
-    register_rpc_method!(registry, "get_events", get_events, 3);
+    register_rpc_method!(registry, "get_events", get_events, 3, 1);
     register_rpc_method!(registry, "get_currencies", currencies_info, 0);
 
     register_rpc_method!(registry, "get_state_proof", get_state_proof, 1);
@@ -359,9 +611,121 @@ pub(crate) fn build_registry() -> RpcRegistry {
         registry,
         "get_account_state_with_proof",
         get_account_state_with_proof,
-        3
+        1,
+        2
     );
     register_rpc_method!(registry, "get_network_status", get_network_status, 0);
+    register_rpc_method!(
+        registry,
+        "get_parked_transactions",
+        get_parked_transactions,
+        1
+    );
+    register_rpc_method!(
+        registry,
+        "get_expired_transactions",
+        get_expired_transactions,
+        1
+    );
+    register_rpc_method!(registry, "get_account_states", get_account_states, 1, 2);
+    register_rpc_method!(registry, "get_network_topology", get_network_topology, 0);
+    register_rpc_method!(registry, "get_time_sync_status", get_time_sync_status, 0);
 
     registry
 }
+
+/// Builds the OpenRPC document of the methods of `build_registry`, which lists the admin methods
+/// only if they are enabled
+/// To document new RPC method, add it along with its parameters
+pub(crate) fn build_schema(admin_methods: bool) -> Value {
+    let mut schema = SchemaBuilder::new();
+    schema
+        .method("submit", submit, vec![param::<String>("data")])
+        .method(
+            "submit_batch",
+            submit_batch,
+            vec![param::<Vec<String>>("data")],
+        )
+        .method(
+            "get_metadata",
+            get_metadata,
+            vec![param::<Option<u64>>("version")],
+        )
+        .method(
+            "get_account_state",
+            get_account_state,
+            vec![
+                param::<String>("account"),
+                optional_param::<Option<u64>>("version"),
+            ],
+        )
+        .method(
+            "get_transactions",
+            get_transactions,
+            vec![
+                param::<u64>("start_version"),
+                param::<u64>("limit"),
+                param::<bool>("include_events"),
+            ],
+        )
+        .method(
+            "get_account_transaction",
+            get_account_transaction,
+            vec![
+                param::<String>("account"),
+                param::<u64>("sequence"),
+                param::<bool>("include_events"),
+            ],
+        )
+        .method(
+            "get_events",
+            get_events,
+            vec![
+                param::<String>("key"),
+                param::<u64>("start"),
+                param::<u64>("limit"),
+                optional_param::<Option<u64>>("version"),
+            ],
+        )
+        .method("get_currencies", currencies_info, vec![])
+        .method(
+            "get_state_proof",
+            get_state_proof,
+            vec![param::<u64>("known_version")],
+        )
+        .method(
+            "get_account_state_with_proof",
+            get_account_state_with_proof,
+            vec![
+                param::<String>("account"),
+                optional_param::<Option<u64>>("version"),
+                optional_param::<Option<u64>>("ledger_version"),
+            ],
+        )
+        .method("get_network_status", get_network_status, vec![])
+        .method(
+            "get_parked_transactions",
+            get_parked_transactions,
+            vec![param::<String>("account")],
+        )
+        .method(
+            "get_expired_transactions",
+            get_expired_transactions,
+            vec![param::<String>("account")],
+        )
+        .method(
+            "get_account_states",
+            get_account_states,
+            vec![
+                param::<u64>("limit"),
+                optional_param::<Option<String>>("page_token"),
+                optional_param::<Option<u64>>("version"),
+            ],
+        );
+    if admin_methods {
+        schema
+            .method("get_network_topology", get_network_topology, vec![])
+            .method("get_time_sync_status", get_time_sync_status, vec![]);
+    }
+    schema.build()
+}
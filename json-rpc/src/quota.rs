@@ -0,0 +1,263 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-API-key usage metering and quotas for hosted JSON-RPC endpoints
+//!
+//! Usage is accounted in fixed windows of `RpcQuotaConfig::window_secs`. Request counts and
+//! method weights are charged when a request is admitted, while result bytes are charged once
+//! the response is serialized, so the byte quota only rejects requests after it has been reached.
+//! At the end of each window, the usage of every key is handed to the `UsageExporter`.
+
+use crate::{counters, errors::JsonRpcError};
+use debug_interface::prelude::*;
+use libra_config::config::RpcQuotaConfig;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Usage of a single API key over a window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub weight: u64,
+    pub result_bytes: u64,
+}
+
+/// Hook receiving the usage of every API key at the end of each window, e.g., to feed a billing
+/// pipeline.
+pub trait UsageExporter: Send + Sync {
+    fn export(&self, window_end_unix_secs: u64, usage: &HashMap<String, Usage>);
+}
+
+/// Default exporter, which publishes usage as `rpc_usage` events on the node debug interface.
+pub struct EventUsageExporter;
+
+impl UsageExporter for EventUsageExporter {
+    fn export(&self, window_end_unix_secs: u64, usage: &HashMap<String, Usage>) {
+        for (api_key, usage) in usage {
+            event!("rpc_usage",
+                "api_key": api_key,
+                "window_end": window_end_unix_secs,
+                "requests": usage.requests,
+                "weight": usage.weight,
+                "result_bytes": usage.result_bytes,
+            );
+        }
+    }
+}
+
+/// Why a request was not admitted
+#[derive(Debug, PartialEq)]
+pub enum QuotaError {
+    MissingApiKey,
+    InvalidApiKey,
+    Exceeded(&'static str),
+}
+
+impl From<QuotaError> for JsonRpcError {
+    fn from(err: QuotaError) -> Self {
+        match err {
+            QuotaError::MissingApiKey => JsonRpcError::missing_api_key(),
+            QuotaError::InvalidApiKey => JsonRpcError::invalid_api_key(),
+            QuotaError::Exceeded(limit) => JsonRpcError::quota_exceeded(limit.to_string()),
+        }
+    }
+}
+
+struct Window {
+    start: Instant,
+    usage: HashMap<String, Usage>,
+}
+
+pub(crate) struct QuotaManager {
+    config: RpcQuotaConfig,
+    window: Mutex<Window>,
+    exporter: Arc<dyn UsageExporter>,
+}
+
+impl QuotaManager {
+    pub fn new(config: RpcQuotaConfig, exporter: Arc<dyn UsageExporter>) -> Self {
+        Self {
+            config,
+            window: Mutex::new(Window {
+                start: Instant::now(),
+                usage: HashMap::new(),
+            }),
+            exporter,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Checks that `api_key` may issue requests for `methods` (several for batches) and charges
+    /// them against its quota.
+    pub fn admit(&self, api_key: Option<&str>, methods: &[&str]) -> Result<(), QuotaError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let (api_key, quota) = self
+            .config
+            .api_keys
+            .get_key_value(api_key.ok_or(QuotaError::MissingApiKey)?)
+            .ok_or(QuotaError::InvalidApiKey)?;
+        let weight: u64 = methods
+            .iter()
+            .map(|method| *self.config.method_weights.get(*method).unwrap_or(&1))
+            .sum();
+
+        let mut window = self.current_window();
+        let usage = window.usage.entry(api_key.clone()).or_default();
+        let exceeds = |limit: Option<u64>, value: u64| limit.map_or(false, |limit| value > limit);
+        if exceeds(quota.max_requests, usage.requests + methods.len() as u64) {
+            return Err(QuotaError::Exceeded("max_requests"));
+        }
+        if exceeds(quota.max_weight, usage.weight + weight) {
+            return Err(QuotaError::Exceeded("max_weight"));
+        }
+        // The size of the result is not known yet, so only reject once the quota is used up.
+        if exceeds(quota.max_result_bytes, usage.result_bytes) {
+            return Err(QuotaError::Exceeded("max_result_bytes"));
+        }
+        usage.requests += methods.len() as u64;
+        usage.weight += weight;
+        counters::API_KEY_USAGE
+            .with_label_values(&[api_key, "requests"])
+            .inc_by(methods.len() as i64);
+        counters::API_KEY_USAGE
+            .with_label_values(&[api_key, "weight"])
+            .inc_by(weight as i64);
+        Ok(())
+    }
+
+    /// Charges the size of a response to `api_key`.
+    pub fn record_result_bytes(&self, api_key: Option<&str>, result_bytes: usize) {
+        let api_key = match api_key {
+            Some(api_key) if self.config.enabled && self.config.api_keys.contains_key(api_key) => {
+                api_key
+            }
+            _ => return,
+        };
+        let mut window = self.current_window();
+        window
+            .usage
+            .entry(api_key.to_string())
+            .or_default()
+            .result_bytes += result_bytes as u64;
+        counters::API_KEY_USAGE
+            .with_label_values(&[api_key, "result_bytes"])
+            .inc_by(result_bytes as i64);
+    }
+
+    /// Returns the current window, rolling over to a new one and exporting the usage of the
+    /// previous one if it has ended.
+    fn current_window(&self) -> MutexGuard<Window> {
+        let mut window = self.window.lock().unwrap();
+        let length = Duration::from_secs(self.config.window_secs);
+        if window.start.elapsed() >= length {
+            let usage = std::mem::replace(&mut window.usage, HashMap::new());
+            window.start = Instant::now();
+            if !usage.is_empty() {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("now > UNIX_EPOCH")
+                    .as_secs();
+                self.exporter.export(now, &usage);
+            }
+        }
+        window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_config::config::ApiKeyQuota;
+
+    #[derive(Default)]
+    struct TestExporter {
+        exported: Mutex<Vec<HashMap<String, Usage>>>,
+    }
+
+    impl UsageExporter for TestExporter {
+        fn export(&self, _window_end_unix_secs: u64, usage: &HashMap<String, Usage>) {
+            self.exported.lock().unwrap().push(usage.clone());
+        }
+    }
+
+    fn quota_config(window_secs: u64) -> RpcQuotaConfig {
+        let mut config = RpcQuotaConfig::default();
+        config.enabled = true;
+        config.window_secs = window_secs;
+        config.api_keys.insert(
+            "key".to_string(),
+            ApiKeyQuota {
+                max_requests: Some(3),
+                max_weight: Some(11),
+                max_result_bytes: Some(100),
+            },
+        );
+        config
+            .method_weights
+            .insert("get_transactions".to_string(), 5);
+        config
+    }
+
+    #[test]
+    fn test_disabled() {
+        let quotas = QuotaManager::new(RpcQuotaConfig::default(), Arc::new(EventUsageExporter));
+        assert_eq!(quotas.admit(None, &["get_metadata"]), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_api_key() {
+        let quotas = QuotaManager::new(quota_config(60), Arc::new(EventUsageExporter));
+        assert_eq!(
+            quotas.admit(None, &["get_metadata"]),
+            Err(QuotaError::MissingApiKey)
+        );
+        assert_eq!(
+            quotas.admit(Some("other"), &["get_metadata"]),
+            Err(QuotaError::InvalidApiKey)
+        );
+    }
+
+    #[test]
+    fn test_quotas() {
+        let quotas = QuotaManager::new(quota_config(60), Arc::new(EventUsageExporter));
+        let key = Some("key");
+        assert_eq!(
+            quotas.admit(key, &["get_transactions", "get_transactions"]),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.admit(key, &["get_transactions"]),
+            Err(QuotaError::Exceeded("max_weight"))
+        );
+        assert_eq!(quotas.admit(key, &["get_metadata"]), Ok(()));
+        assert_eq!(
+            quotas.admit(key, &["get_metadata"]),
+            Err(QuotaError::Exceeded("max_requests"))
+        );
+    }
+
+    #[test]
+    fn test_result_bytes_and_export() {
+        let exporter = Arc::new(TestExporter::default());
+        let quotas = QuotaManager::new(quota_config(0), exporter.clone());
+        let key = Some("key");
+        // A zero-length window rolls over on every access, exporting the previous usage
+        assert_eq!(quotas.admit(key, &["get_metadata"]), Ok(()));
+        quotas.record_result_bytes(key, 150);
+        assert_eq!(quotas.admit(key, &["get_metadata"]), Ok(()));
+
+        let exported = exporter.exported.lock().unwrap();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[0]["key"].requests, 1);
+        assert_eq!(exported[1]["key"].result_bytes, 150);
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Node-wide rate limiting of methods serving large amounts of data, e.g., iterating over all the
+//! account states, so that they can be exposed online without starving the node.
+//!
+//! Unlike quotas, which apply per API key and only when enabled, the limit is shared by all the
+//! consumers of the endpoint.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+struct Bucket {
+    units: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket allowing `rate_per_sec` units per second on average, in bursts of up to one
+/// second worth of units.
+pub(crate) struct RateLimiter {
+    rate_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate_per_sec: u64) -> Self {
+        Self {
+            rate_per_sec,
+            bucket: Mutex::new(Bucket {
+                units: rate_per_sec as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `units` from the bucket, or returns false if there are not enough left.
+    pub fn try_acquire(&self, units: u64) -> bool {
+        self.try_acquire_at(units, Instant::now())
+    }
+
+    fn try_acquire_at(&self, units: u64, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now
+            .checked_duration_since(bucket.last_refill)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        bucket.units = (bucket.units + elapsed.as_secs_f64() * self.rate_per_sec as f64)
+            .min(self.rate_per_sec as f64);
+        bucket.last_refill = now;
+        if bucket.units < units as f64 {
+            return false;
+        }
+        bucket.units -= units as f64;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(10);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(6, start));
+        assert!(limiter.try_acquire_at(4, start));
+        assert!(!limiter.try_acquire_at(1, start));
+
+        // refills at the configured rate
+        let later = start + Duration::from_millis(200);
+        assert!(!limiter.try_acquire_at(3, later));
+        assert!(limiter.try_acquire_at(2, later));
+
+        // never holds more than one second worth of units
+        let much_later = later + Duration::from_secs(60);
+        assert!(!limiter.try_acquire_at(11, much_later));
+        assert!(limiter.try_acquire_at(10, much_later));
+    }
+}
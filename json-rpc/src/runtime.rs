@@ -4,29 +4,123 @@
 use crate::{
     counters,
     errors::JsonRpcError,
-    methods::{build_registry, JsonRpcRequest, JsonRpcService, RpcRegistry},
+    methods::{build_registry, build_schema, JsonRpcRequest, JsonRpcService, RpcRegistry},
+    quota::{EventUsageExporter, QuotaError, QuotaManager, UsageExporter},
+    rate_limit::RateLimiter,
+    schema::SCHEMA_PATH,
+    staleness::{Staleness, StalenessGuard, STALE_FIELD},
 };
+use debug_interface::degradation::{self, DegradationMode};
 use futures::future::join_all;
-use libra_config::config::{NodeConfig, RoleType};
+use libra_config::config::{
+    NodeConfig, RoleType, RpcQuotaConfig, RpcStalenessConfig, StalenessPolicy,
+    DEFAULT_MAX_ACCOUNT_STATES_PER_SEC, DEFAULT_THROTTLED_RPC_REQUESTS_PER_SEC,
+};
 use libra_mempool::MempoolClientSender;
 use libra_types::ledger_info::LedgerInfoWithSignatures;
+use network::connected_peers::ConnectedPeers;
 use serde_json::{map::Map, Value};
 use std::{net::SocketAddr, sync::Arc};
-use storage_interface::DbReader;
+use storage_interface::{DbReader, StatePrunedError};
 use tokio::runtime::{Builder, Runtime};
 use warp::{
+    http::StatusCode,
     reject::{self, Reject},
     Filter,
 };
 
-/// Creates HTTP server (warp-based) that serves JSON RPC requests
+/// Header carrying the API key of the consumer when quotas are enabled
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Options of the JSON RPC server started by `bootstrap`
+pub struct BootstrapOptions {
+    /// Address the server listens to
+    pub address: SocketAddr,
+    pub libra_db: Arc<dyn DbReader>,
+    pub mp_sender: MempoolClientSender,
+    pub role: RoleType,
+    /// Number of versions whose states are kept by the storage, if it prunes them
+    pub prune_window: Option<u64>,
+    pub quota_config: RpcQuotaConfig,
+    pub staleness_config: RpcStalenessConfig,
+    pub max_account_states_per_sec: u64,
+    /// Requests served per second while the node sheds load, see
+    /// `DegradationMode::ThrottleJsonRpc`
+    pub throttled_requests_per_sec: u64,
+    /// The admin methods, e.g., `get_network_topology`, are only served if this is set
+    pub connected_peers: Option<ConnectedPeers>,
+}
+
+impl BootstrapOptions {
+    /// Options of a validator's server listening to `address`, with the default limits, no
+    /// quotas, no pruning and no admin methods
+    pub fn new(
+        address: SocketAddr,
+        libra_db: Arc<dyn DbReader>,
+        mp_sender: MempoolClientSender,
+    ) -> Self {
+        Self {
+            address,
+            libra_db,
+            mp_sender,
+            role: RoleType::Validator,
+            prune_window: None,
+            quota_config: RpcQuotaConfig::default(),
+            staleness_config: RpcStalenessConfig::default(),
+            max_account_states_per_sec: DEFAULT_MAX_ACCOUNT_STATES_PER_SEC,
+            throttled_requests_per_sec: DEFAULT_THROTTLED_RPC_REQUESTS_PER_SEC,
+            connected_peers: None,
+        }
+    }
+
+    /// Options of the server of the node with `config`
+    pub fn from_config(
+        config: &NodeConfig,
+        libra_db: Arc<dyn DbReader>,
+        mp_sender: MempoolClientSender,
+        connected_peers: ConnectedPeers,
+    ) -> Self {
+        Self {
+            address: config.rpc.address,
+            libra_db,
+            mp_sender,
+            role: config.base.role,
+            prune_window: config.storage.prune_window,
+            quota_config: config.rpc.quotas.clone(),
+            staleness_config: config.rpc.staleness.clone(),
+            max_account_states_per_sec: config.rpc.max_account_states_per_sec,
+            throttled_requests_per_sec: config.resource_limits.throttled_rpc_requests_per_sec,
+            connected_peers: Some(connected_peers).filter(|_| config.rpc.enable_admin_methods),
+        }
+    }
+}
+
+/// Creates HTTP server (warp-based) that serves JSON RPC requests, and the OpenRPC document of the
+/// API with GET requests to `/openrpc.json`
 /// Returns handle to corresponding Tokio runtime
-pub fn bootstrap(
-    address: SocketAddr,
-    libra_db: Arc<dyn DbReader>,
-    mp_sender: MempoolClientSender,
-    role: RoleType,
+pub fn bootstrap(options: BootstrapOptions) -> Runtime {
+    bootstrap_with_usage_exporter(options, Arc::new(EventUsageExporter))
+}
+
+/// Same as `bootstrap`, but the per-API-key usage is handed to `usage_exporter` at the end of
+/// each quota window, e.g., for billing.
+pub fn bootstrap_with_usage_exporter(
+    options: BootstrapOptions,
+    usage_exporter: Arc<dyn UsageExporter>,
 ) -> Runtime {
+    let BootstrapOptions {
+        address,
+        libra_db,
+        mp_sender,
+        role,
+        prune_window,
+        quota_config,
+        staleness_config,
+        max_account_states_per_sec,
+        throttled_requests_per_sec,
+        connected_peers,
+    } = options;
+
     let runtime = Builder::new()
         .thread_name("rpc-")
         .threaded_scheduler()
@@ -35,16 +129,35 @@ pub fn bootstrap(
         .expect("[rpc] failed to create runtime");
 
     let registry = Arc::new(build_registry());
-    let service = JsonRpcService::new(libra_db, mp_sender, role);
+    let schema = Arc::new(build_schema(connected_peers.is_some()));
+    let service = JsonRpcService::new(
+        libra_db,
+        mp_sender,
+        role,
+        prune_window,
+        max_account_states_per_sec,
+        connected_peers,
+    );
+    let quotas = Arc::new(QuotaManager::new(quota_config, usage_exporter));
+    let staleness = Arc::new(StalenessGuard::new(staleness_config));
+    let throttle = Arc::new(RateLimiter::new(throttled_requests_per_sec));
 
     let handler = warp::any()
         .and(warp::path::end())
         .and(warp::post())
         .and(warp::header::exact("content-type", "application/json"))
+        .and(warp::header::optional::<String>(API_KEY_HEADER))
         .and(warp::body::json())
         .and(warp::any().map(move || service.clone()))
         .and(warp::any().map(move || Arc::clone(&registry)))
+        .and(warp::any().map(move || Arc::clone(&quotas)))
+        .and(warp::any().map(move || Arc::clone(&staleness)))
+        .and(warp::any().map(move || Arc::clone(&throttle)))
         .and_then(rpc_endpoint);
+    let schema_handler = warp::path(SCHEMA_PATH)
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(schema.as_ref()));
 
     // Ensure that we actually bind to the socket first before spawning the
     // server tasks. This helps in tests to prevent races where a client attempts
@@ -53,7 +166,7 @@ pub fn bootstrap(
     //
     // Note: we need to enter the runtime context first to actually bind, since
     //       tokio TcpListener can only be bound inside a tokio context.
-    let server = runtime.enter(move || warp::serve(handler).bind(address));
+    let server = runtime.enter(move || warp::serve(handler.or(schema_handler)).bind(address));
     runtime.handle().spawn(server);
     runtime
 }
@@ -63,22 +176,56 @@ pub fn bootstrap_from_config(
     config: &NodeConfig,
     libra_db: Arc<dyn DbReader>,
     mp_sender: MempoolClientSender,
+    connected_peers: ConnectedPeers,
 ) -> Runtime {
-    bootstrap(config.rpc.address, libra_db, mp_sender, config.base.role)
+    bootstrap(BootstrapOptions::from_config(
+        config,
+        libra_db,
+        mp_sender,
+        connected_peers,
+    ))
 }
 
 /// JSON RPC entry point
 /// Handles all incoming rpc requests
 /// Performs routing based on methods defined in `registry`
 async fn rpc_endpoint(
+    api_key: Option<String>,
     data: Value,
     service: JsonRpcService,
     registry: Arc<RpcRegistry>,
+    quotas: Arc<QuotaManager>,
+    staleness: Arc<StalenessGuard>,
+    throttle: Arc<RateLimiter>,
 ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    if degradation::is_active(DegradationMode::ThrottleJsonRpc) && !throttle.try_acquire(1) {
+        counters::INVALID_REQUESTS
+            .with_label_values(&["throttled"])
+            .inc();
+        return Ok(reject_with_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            JsonRpcError::throttled(),
+        ));
+    }
+    let api_key = api_key.as_deref();
+    if quotas.is_enabled() {
+        if let Err(err) = quotas.admit(api_key, &request_methods(&data)) {
+            // Only a request over quota may succeed once retried, in the next window.
+            let (label, status) = match err {
+                QuotaError::MissingApiKey => ("missing_api_key", StatusCode::UNAUTHORIZED),
+                QuotaError::InvalidApiKey => ("invalid_api_key", StatusCode::FORBIDDEN),
+                QuotaError::Exceeded(_) => ("quota_exceeded", StatusCode::TOO_MANY_REQUESTS),
+            };
+            counters::INVALID_REQUESTS.with_label_values(&[label]).inc();
+            return Ok(reject_with_status(status, JsonRpcError::from(err)));
+        }
+    }
+
     // take snapshot of latest version of DB to be used across all requests, especially for batched requests
     let ledger_info = service
         .get_latest_ledger_info()
         .map_err(|_| reject::custom(DatabaseError))?;
+    let stale = staleness.check(&ledger_info);
     if let Value::Array(requests) = data {
         // batch API call
         let futures = requests.into_iter().map(|req| {
@@ -87,24 +234,62 @@ async fn rpc_endpoint(
                 service.clone(),
                 Arc::clone(&registry),
                 ledger_info.clone(),
+                Arc::clone(&staleness),
+                stale,
             )
         });
-        let responses = join_all(futures).await;
-        Ok(Box::new(warp::reply::json(&Value::Array(responses))))
+        let responses = Value::Array(join_all(futures).await);
+        record_result_bytes(&quotas, api_key, &responses);
+        Ok(Box::new(warp::reply::json(&responses)))
     } else {
         // single API call
-        let resp = rpc_request_handler(data, service, registry, ledger_info).await;
+        let resp =
+            rpc_request_handler(data, service, registry, ledger_info, staleness, stale).await;
+        record_result_bytes(&quotas, api_key, &resp);
         Ok(Box::new(warp::reply::json(&resp)))
     }
 }
 
+/// Rejects a request with `err`, and HTTP `status`.
+fn reject_with_status(status: StatusCode, err: JsonRpcError) -> Box<dyn warp::Reply> {
+    let mut response = Map::new();
+    response.insert("jsonrpc".to_string(), Value::String("2.0".to_string()));
+    response.insert("id".to_string(), Value::Null);
+    response.insert("error".to_string(), err.serialize());
+    Box::new(warp::reply::with_status(
+        warp::reply::json(&response),
+        status,
+    ))
+}
+
+/// Returns the names of the methods called by a single or batch request, for quota accounting.
+/// Malformed requests are accounted for as well, under an empty name.
+fn request_methods(data: &Value) -> Vec<&str> {
+    let method = |request: &Value| request.get("method").and_then(Value::as_str).unwrap_or("");
+    match data {
+        Value::Array(requests) => requests.iter().map(method).collect(),
+        request => vec![method(request)],
+    }
+}
+
+fn record_result_bytes(quotas: &QuotaManager, api_key: Option<&str>, response: &Value) {
+    if quotas.is_enabled() {
+        if let Ok(bytes) = serde_json::to_vec(response) {
+            quotas.record_result_bytes(api_key, bytes.len());
+        }
+    }
+}
+
 /// Handler of single RPC request
 /// Performs validation and executes corresponding rpc handler
+/// If `stale` is set, the method is served according to its policy in `staleness`
 async fn rpc_request_handler(
     req: Value,
     service: JsonRpcService,
     registry: Arc<RpcRegistry>,
     ledger_info: LedgerInfoWithSignatures,
+    staleness: Arc<StalenessGuard>,
+    stale: Option<Staleness>,
 ) -> Value {
     let request: Map<String, Value>;
     let mut response = Map::new();
@@ -177,26 +362,44 @@ async fn rpc_request_handler(
     // get rpc handler
     match request.get("method") {
         Some(Value::String(name)) => match registry.get(name) {
-            Some(handler) => match handler(service, request_params).await {
-                Ok(result) => {
-                    response.insert("result".to_string(), result);
-                    counters::REQUESTS
-                        .with_label_values(&[name, "success"])
-                        .inc();
+            Some(handler) => {
+                let stale = stale.map(|stale| (stale, staleness.policy(name)));
+                if let Some((stale, StalenessPolicy::Reject)) = stale {
+                    response.insert("error".to_string(), stale.error().serialize());
+                    counters::REQUESTS.with_label_values(&[name, "stale"]).inc();
+                    return Value::Object(response);
                 }
-                Err(err) => {
-                    // check for custom error
-                    if let Some(custom_error) = err.downcast_ref::<JsonRpcError>() {
-                        response.insert("error".to_string(), custom_error.clone().serialize());
-                    } else {
-                        response.insert(
-                            "error".to_string(),
-                            JsonRpcError::internal_error(err.to_string()).serialize(),
-                        );
+                match handler(service, request_params).await {
+                    Ok(result) => {
+                        response.insert("result".to_string(), result);
+                        counters::REQUESTS
+                            .with_label_values(&[name, "success"])
+                            .inc();
+                    }
+                    Err(err) => {
+                        // check for custom error
+                        if let Some(custom_error) = err.downcast_ref::<JsonRpcError>() {
+                            response.insert("error".to_string(), custom_error.clone().serialize());
+                        } else if let Some(pruned) = err.downcast_ref::<StatePrunedError>() {
+                            // storage pruned the state while it was read
+                            response.insert(
+                                "error".to_string(),
+                                JsonRpcError::state_pruned(pruned.version, pruned.earliest_version)
+                                    .serialize(),
+                            );
+                        } else {
+                            response.insert(
+                                "error".to_string(),
+                                JsonRpcError::internal_error(err.to_string()).serialize(),
+                            );
+                        }
+                        counters::REQUESTS.with_label_values(&[name, "fail"]).inc();
                     }
-                    counters::REQUESTS.with_label_values(&[name, "fail"]).inc();
                 }
-            },
+                if let Some((stale, StalenessPolicy::Warn)) = stale {
+                    response.insert(STALE_FIELD.to_string(), stale.warning());
+                }
+            }
             None => {
                 response.insert(
                     "error".to_string(),
@@ -0,0 +1,100 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenRPC document of the JSON-RPC API, generated from the types of the method handlers
+//!
+//! Specification: https://spec.open-rpc.org
+
+use crate::methods::{JsonRpcRequest, JsonRpcService};
+use anyhow::Result;
+use core::future::Future;
+use libra_json_rpc_types::schema::{Definitions, JsonSchema};
+use serde_json::{json, Value};
+
+/// Version of the JSON-RPC API, following semver: bumped on every change of the methods or of
+/// the views, with a new major version if the change is not backward compatible
+pub const API_VERSION: &str = "1.1.0";
+
+/// Path of the endpoint serving the OpenRPC document
+pub const SCHEMA_PATH: &str = "openrpc.json";
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+/// Parameter of a method
+pub(crate) struct Param {
+    name: &'static str,
+    /// Whether the parameter may be omitted, i.e., it is a trailing optional parameter
+    optional: bool,
+    schema: fn(&mut Definitions) -> Value,
+}
+
+/// Parameter which must always be given, though it may be nullable
+pub(crate) fn param<T: JsonSchema>(name: &'static str) -> Param {
+    Param {
+        name,
+        optional: false,
+        schema: T::schema,
+    }
+}
+
+/// Trailing parameter which may be omitted
+pub(crate) fn optional_param<T: JsonSchema>(name: &'static str) -> Param {
+    Param {
+        name,
+        optional: true,
+        schema: T::schema,
+    }
+}
+
+pub(crate) struct SchemaBuilder {
+    methods: Vec<Value>,
+    definitions: Definitions,
+}
+
+impl SchemaBuilder {
+    pub fn new() -> Self {
+        Self {
+            methods: vec![],
+            definitions: Definitions::new(),
+        }
+    }
+
+    /// Adds the method `name`, whose result schema is the one of the result of `handler`
+    pub fn method<F, Fut, T>(&mut self, name: &str, _handler: F, params: Vec<Param>) -> &mut Self
+    where
+        F: FnOnce(JsonRpcService, JsonRpcRequest) -> Fut,
+        Fut: Future<Output = Result<T>>,
+        T: JsonSchema,
+    {
+        let params: Vec<_> = params
+            .into_iter()
+            .map(|param| {
+                json!({
+                    "name": param.name,
+                    "required": !param.optional,
+                    "schema": (param.schema)(&mut self.definitions),
+                })
+            })
+            .collect();
+        let result = T::schema(&mut self.definitions);
+        self.methods.push(json!({
+            "name": name,
+            "paramStructure": "by-position",
+            "params": params,
+            "result": { "name": "result", "schema": result },
+        }));
+        self
+    }
+
+    pub fn build(&mut self) -> Value {
+        json!({
+            "openrpc": OPENRPC_VERSION,
+            "info": {
+                "title": "Libra JSON-RPC API",
+                "version": API_VERSION,
+            },
+            "methods": self.methods,
+            "components": { "schemas": self.definitions },
+        })
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Staleness guards of a node lagging behind the chain
+//!
+//! The state of the node is stale once the timestamp of its latest ledger info is more than
+//! `RpcStalenessConfig::max_staleness_secs` old. Requests are checked against the ledger info
+//! snapshot they are served from, and each method is then served, flagged or rejected according
+//! to its `StalenessPolicy`.
+
+use crate::errors::JsonRpcError;
+use libra_config::config::{RpcStalenessConfig, StalenessPolicy};
+use libra_types::ledger_info::LedgerInfoWithSignatures;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the field flagging the responses served from a stale state
+pub(crate) const STALE_FIELD: &str = "stale";
+
+/// Staleness of the ledger info a request is served from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Staleness {
+    ledger_timestamp_usecs: u64,
+    max_staleness_secs: u64,
+}
+
+impl Staleness {
+    /// Value of the `stale` field of the responses served anyway
+    pub fn warning(&self) -> Value {
+        json!({
+            "ledger_timestamp_usecs": self.ledger_timestamp_usecs,
+            "max_staleness_secs": self.max_staleness_secs,
+        })
+    }
+
+    pub fn error(&self) -> JsonRpcError {
+        JsonRpcError::stale_state(self.ledger_timestamp_usecs, self.max_staleness_secs)
+    }
+}
+
+pub(crate) struct StalenessGuard {
+    config: RpcStalenessConfig,
+}
+
+impl StalenessGuard {
+    pub fn new(config: RpcStalenessConfig) -> Self {
+        Self { config }
+    }
+
+    /// Returns the staleness of `ledger_info` if it is more than `max_staleness_secs` old, as of
+    /// `now_usecs`
+    pub fn check_at(
+        &self,
+        ledger_info: &LedgerInfoWithSignatures,
+        now_usecs: u64,
+    ) -> Option<Staleness> {
+        let max_staleness_secs = self.config.max_staleness_secs?;
+        let ledger_timestamp_usecs = ledger_info.ledger_info().timestamp_usecs();
+        let max_staleness_usecs = max_staleness_secs.saturating_mul(1_000_000);
+        if now_usecs.saturating_sub(ledger_timestamp_usecs) > max_staleness_usecs {
+            Some(Staleness {
+                ledger_timestamp_usecs,
+                max_staleness_secs,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Same as `check_at`, as of the current time
+    pub fn check(&self, ledger_info: &LedgerInfoWithSignatures) -> Option<Staleness> {
+        let now_usecs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the UNIX epoch")
+            .as_micros() as u64;
+        self.check_at(ledger_info, now_usecs)
+    }
+
+    pub fn policy(&self, method: &str) -> StalenessPolicy {
+        self.config
+            .method_policies
+            .get(method)
+            .copied()
+            .unwrap_or(self.config.default_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_crypto::HashValue;
+    use libra_types::{block_info::BlockInfo, ledger_info::LedgerInfo};
+    use std::collections::BTreeMap;
+
+    fn ledger_info(timestamp_usecs: u64) -> LedgerInfoWithSignatures {
+        LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                BlockInfo::new(
+                    0,
+                    0,
+                    HashValue::zero(),
+                    HashValue::zero(),
+                    0,
+                    timestamp_usecs,
+                    None,
+                ),
+                HashValue::zero(),
+            ),
+            BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn test_disabled() {
+        let guard = StalenessGuard::new(RpcStalenessConfig::default());
+        assert_eq!(guard.check_at(&ledger_info(0), std::u64::MAX), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let mut config = RpcStalenessConfig::default();
+        config.max_staleness_secs = Some(10);
+        let guard = StalenessGuard::new(config);
+        let ledger_info = ledger_info(5_000_000);
+        assert_eq!(guard.check_at(&ledger_info, 0), None);
+        assert_eq!(guard.check_at(&ledger_info, 15_000_000), None);
+        let staleness = guard.check_at(&ledger_info, 15_000_001).unwrap();
+        assert_eq!(
+            staleness.warning(),
+            json!({"ledger_timestamp_usecs": 5_000_000, "max_staleness_secs": 10})
+        );
+        assert_eq!(staleness.error().data, Some(staleness.warning()));
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut config = RpcStalenessConfig::default();
+        config.default_policy = StalenessPolicy::Reject;
+        config
+            .method_policies
+            .insert("get_metadata".to_string(), StalenessPolicy::Warn);
+        let guard = StalenessGuard::new(config);
+        assert_eq!(guard.policy("submit"), StalenessPolicy::Ignore);
+        assert_eq!(guard.policy("get_metadata"), StalenessPolicy::Warn);
+        assert_eq!(guard.policy("get_account_state"), StalenessPolicy::Reject);
+    }
+}
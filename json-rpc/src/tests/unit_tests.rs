@@ -3,18 +3,28 @@
 
 use crate::{
     errors::{JsonRpcError, ServerCode},
+    methods::{build_registry, build_schema},
     tests::utils::{test_bootstrap, MockLibraDB},
+    BootstrapOptions,
 };
 use futures::{channel::mpsc::channel, StreamExt};
-use libra_config::utils;
+use libra_config::{
+    config::{
+        ApiKeyQuota, RpcQuotaConfig, RpcStalenessConfig, StalenessPolicy,
+        DEFAULT_MAX_ACCOUNT_STATES_PER_SEC,
+    },
+    utils,
+};
 use libra_crypto::{ed25519::Ed25519PrivateKey, hash::CryptoHash, HashValue, PrivateKey, Uniform};
 use libra_json_rpc_client::{
     views::{
-        AccountStateWithProofView, BlockMetadata, BytesView, EventView, StateProofView,
-        TransactionDataView, TransactionView,
+        AccountStatePageView, AccountStateWithProofView, BlockMetadata, BytesView, EventView,
+        ParkedTransactionView, StateProofView, TimeSyncStatusView, TransactionDataView,
+        TransactionView,
     },
     JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse, ResponseAsView,
 };
+use libra_mempool::MempoolClientRequest;
 use libra_proptest_helpers::ValueGenerator;
 use libra_types::{
     account_address::AccountAddress,
@@ -31,13 +41,14 @@ use libra_types::{
 };
 use libradb::test_helper::arb_blocks_to_commit;
 use move_core_types::language_storage::TypeTag;
+use network::connected_peers::ConnectedPeers;
 use proptest::prelude::*;
 use std::{
     collections::{BTreeMap, HashMap},
     convert::TryFrom,
     str::FromStr,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use storage_interface::DbReader;
 use tokio::runtime::Runtime;
@@ -190,7 +201,7 @@ fn test_transaction_submission() {
     // future that mocks shared mempool execution
     runtime.spawn(async move {
         let validator = MockVMValidator;
-        while let Some((txn, cb)) = mp_events.next().await {
+        while let Some(MempoolClientRequest::SubmitTransaction(txn, cb)) = mp_events.next().await {
             let vm_status = validator.validate_transaction(txn).unwrap().status();
             let result = if vm_status.is_some() {
                 (MempoolStatus::new(MempoolStatusCode::VmError), vm_status)
@@ -235,6 +246,137 @@ fn test_transaction_submission() {
     }
 }
 
+#[test]
+fn test_transaction_batch_submission() {
+    let (mp_sender, mut mp_events) = channel(1);
+    let mock_db = mock_db();
+    let port = utils::get_available_port();
+    let address = format!("0.0.0.0:{}", port);
+    let mut runtime = test_bootstrap(address.parse().unwrap(), Arc::new(mock_db), mp_sender);
+    let client = JsonRpcAsyncClient::new(
+        reqwest::Url::from_str(format!("http://{}:{}", "127.0.0.1", port).as_str())
+            .expect("invalid url"),
+    );
+
+    // future that mocks shared mempool execution, which only accepts consecutive batches
+    runtime.spawn(async move {
+        while let Some(MempoolClientRequest::SubmitTransactionBatch(txns, cb)) =
+            mp_events.next().await
+        {
+            let consecutive = txns
+                .windows(2)
+                .all(|txns| txns[1].sequence_number() == txns[0].sequence_number() + 1);
+            let result = if consecutive {
+                (MempoolStatus::new(MempoolStatusCode::Accepted), None)
+            } else {
+                (
+                    MempoolStatus::new(MempoolStatusCode::InvalidSeqNumber),
+                    None,
+                )
+            };
+            cb.send(Ok(result)).unwrap();
+        }
+    });
+
+    let sender = AccountAddress::new([9; AccountAddress::LENGTH]);
+    let privkey = Ed25519PrivateKey::generate_for_testing();
+    let mut batch_submission = move |sequence_numbers: &[u64]| {
+        let txns: Vec<_> = sequence_numbers
+            .iter()
+            .map(|seq| get_test_signed_txn(sender, *seq, &privkey, privkey.public_key(), None))
+            .collect();
+        let mut batch = JsonRpcBatch::default();
+        batch.add_submit_batch_request(&txns).unwrap();
+        runtime.block_on(client.execute(batch)).unwrap()
+    };
+
+    assert!(
+        batch_submission(&[0, 1, 2])[0].as_ref().unwrap() == &JsonRpcResponse::SubmissionResponse
+    );
+    let response = &batch_submission(&[0, 2])[0];
+    let error = response
+        .as_ref()
+        .unwrap_err()
+        .downcast_ref::<JsonRpcError>()
+        .unwrap();
+    assert_eq!(error.code, ServerCode::MempoolInvalidSeqNumber as i16);
+}
+
+#[test]
+fn test_get_parked_transactions() {
+    let (mp_sender, mut mp_events) = channel(1);
+    let mock_db = mock_db();
+    let port = utils::get_available_port();
+    let address = format!("0.0.0.0:{}", port);
+    let mut runtime = test_bootstrap(address.parse().unwrap(), Arc::new(mock_db), mp_sender);
+    let client = JsonRpcAsyncClient::new(
+        reqwest::Url::from_str(format!("http://{}:{}", "127.0.0.1", port).as_str())
+            .expect("invalid url"),
+    );
+
+    // future that mocks shared mempool, in which the transactions of `sender` with sequence
+    // numbers 2 and 3 are parked, waiting for the one with sequence number 1
+    let sender = AccountAddress::new([9; AccountAddress::LENGTH]);
+    let privkey = Ed25519PrivateKey::generate_for_testing();
+    let parked_txns: Vec<_> = [2, 3]
+        .iter()
+        .map(|seq| get_test_signed_txn(sender, *seq, &privkey, privkey.public_key(), None))
+        .collect();
+    let mempool_parked_txns = parked_txns.clone();
+    runtime.spawn(async move {
+        while let Some(MempoolClientRequest::GetParkedTransactions(address, cb)) =
+            mp_events.next().await
+        {
+            let parked = if address == sender {
+                mempool_parked_txns
+                    .iter()
+                    .map(|txn| (txn.clone(), Duration::from_millis(1500)))
+                    .collect()
+            } else {
+                vec![]
+            };
+            cb.send(parked).unwrap();
+        }
+    });
+
+    let mut get_parked_transactions = move |address| {
+        let mut batch = JsonRpcBatch::default();
+        batch.add_get_parked_transactions_request(address);
+        let result = runtime
+            .block_on(client.execute(batch))
+            .unwrap()
+            .remove(0)
+            .unwrap();
+        ParkedTransactionView::vec_from_response(result).unwrap()
+    };
+
+    let views = get_parked_transactions(sender);
+    assert_eq!(views.len(), parked_txns.len());
+    for (view, txn) in views.iter().zip(parked_txns) {
+        assert_eq!(view.parked_duration_ms, 1500);
+        let txn = Transaction::UserTransaction(Arc::new(txn));
+        assert_eq!(view.hash, txn.hash().to_string());
+        match &view.transaction {
+            TransactionDataView::UserTransaction {
+                sender: view_sender,
+                sequence_number,
+                ..
+            } => {
+                assert_eq!(view_sender, &sender.to_string());
+                assert_eq!(
+                    *sequence_number,
+                    txn.as_signed_user_txn().unwrap().sequence_number()
+                );
+            }
+            _ => panic!("wrong type"),
+        }
+    }
+
+    // accounts with no parked transactions
+    let other = AccountAddress::new([7; AccountAddress::LENGTH]);
+    assert!(get_parked_transactions(other).is_empty());
+}
+
 // TODO: Once account configs are published in the mock DB this test can be turned back on
 //#[test]
 //fn test_get_account_state() {
@@ -350,6 +492,34 @@ fn test_get_events() {
     );
 }
 
+#[test]
+fn test_get_events_at_version() {
+    let (mock_db, client, mut runtime) = create_database_client_and_runtime(1);
+
+    let (first_event_version, first_event) = mock_db.events[0].clone();
+    let event_key = hex::encode(first_event.key().as_bytes());
+
+    let mut batch = JsonRpcBatch::default();
+    batch.add_get_events_at_version_request(
+        event_key.clone(),
+        first_event.sequence_number(),
+        first_event.sequence_number() + 10,
+        first_event_version,
+    );
+    let result = execute_batch_and_get_first_response(&client, &mut runtime, batch);
+    let events = EventView::vec_from_response(result).unwrap();
+    assert!(!events.is_empty());
+    assert!(events
+        .iter()
+        .all(|event| event.transaction_version <= first_event_version));
+
+    // versions past the latest ledger version are rejected
+    let mut batch = JsonRpcBatch::default();
+    batch.add_get_events_at_version_request(event_key, 0, 10, mock_db.version + 1);
+    let responses = runtime.block_on(client.execute(batch)).unwrap();
+    assert!(responses[0].is_err());
+}
+
 #[test]
 fn test_get_transactions() {
     let (mock_db, client, mut runtime) = create_database_client_and_runtime(1);
@@ -403,6 +573,15 @@ fn test_get_transactions() {
                     }
                     _ => panic!("Returned value doesn't match!"),
                 },
+                Transaction::BlockMetadataWithExtension(t) => match view.transaction {
+                    TransactionDataView::BlockMetadata { timestamp_usecs } => {
+                        assert_eq!(
+                            t.metadata().clone().into_inner().unwrap().1,
+                            timestamp_usecs
+                        );
+                    }
+                    _ => panic!("Returned value doesn't match!"),
+                },
                 Transaction::WaypointWriteSet(_) => match view.transaction {
                     TransactionDataView::WriteSet { .. } => {}
                     _ => panic!("Returned value doesn't match!"),
@@ -521,6 +700,156 @@ fn test_get_account_state_with_proof_null_versions() {
     assert_eq!(received_proof.version, expected_proof.version);
 }
 
+#[test]
+fn test_get_account_state_with_proof_invalid_versions() {
+    let (mock_db, client, mut runtime) = create_database_client_and_runtime(1);
+
+    let account = get_first_account_from_mock_db(&mock_db);
+    let mut batch = JsonRpcBatch::default();
+    // version past the latest version
+    batch.add_get_account_state_with_proof_request(account, Some(mock_db.version + 1), None);
+    // version past the ledger version
+    batch.add_get_account_state_with_proof_request(account, Some(1), Some(0));
+
+    let responses = runtime.block_on(client.execute(batch)).unwrap();
+    assert!(responses.iter().all(|response| response.is_err()));
+}
+
+#[test]
+fn test_get_pruned_account_state() {
+    let mock_db = mock_db();
+    assert!(mock_db.version > 0);
+    let account = get_first_account_from_mock_db(&mock_db);
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let mut options = BootstrapOptions::new(
+        address.parse().unwrap(),
+        Arc::new(mock_db.clone()),
+        channel(1).0,
+    );
+    options.prune_window = Some(0);
+    let _runtime = crate::bootstrap(options);
+    let request = serde_json::json!({"jsonrpc": "2.0", "method": "get_account_state", "params": [format!("{:x}", account), 0], "id": 1});
+    let resp = reqwest::blocking::Client::new()
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    let data: JsonMap = resp.json().unwrap();
+    let error: JsonRpcError = serde_json::from_value(data.get("error").unwrap().clone()).unwrap();
+    assert_eq!(error.code, ServerCode::StatePruned as i16);
+    assert_eq!(
+        error.data,
+        Some(serde_json::json!({"version": 0, "earliest_version": mock_db.version}))
+    );
+}
+
+#[test]
+fn test_get_account_state_at_past_version() {
+    let mock_db = mock_db();
+    assert!(mock_db.version > 0);
+    let account = get_first_account_from_mock_db(&mock_db);
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let mut options = BootstrapOptions::new(
+        address.parse().unwrap(),
+        Arc::new(mock_db.clone()),
+        channel(1).0,
+    );
+    options.prune_window = Some(mock_db.version);
+    let _runtime = crate::bootstrap(options);
+    let request = serde_json::json!({"jsonrpc": "2.0", "method": "get_account_state_with_proof", "params": [format!("{:x}", account), 0, mock_db.version], "id": 1});
+    let resp = reqwest::blocking::Client::new()
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    let data: JsonMap = resp.json().unwrap();
+    assert!(data.get("error").is_none());
+    let received_proof: AccountStateWithProofView =
+        serde_json::from_value(data.get("result").unwrap().clone()).unwrap();
+    let expected_blob = get_first_state_proof_from_mock_db(&mock_db).blob.unwrap();
+    let account_blob: AccountStateBlob =
+        lcs::from_bytes(&received_proof.blob.unwrap().into_bytes().unwrap()).unwrap();
+    assert_eq!(account_blob, expected_blob);
+}
+
+#[test]
+fn test_stale_state() {
+    let mut mock_db = mock_db();
+    // the latest ledger info is an hour old
+    mock_db.timestamp -= 3600 * 1_000_000;
+    let account = get_first_account_from_mock_db(&mock_db);
+    let mut staleness_config = RpcStalenessConfig::default();
+    staleness_config.max_staleness_secs = Some(60);
+    staleness_config.default_policy = StalenessPolicy::Reject;
+    staleness_config
+        .method_policies
+        .insert("get_metadata".to_string(), StalenessPolicy::Warn);
+    staleness_config
+        .method_policies
+        .insert("get_currencies".to_string(), StalenessPolicy::Ignore);
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let mut options = BootstrapOptions::new(
+        address.parse().unwrap(),
+        Arc::new(mock_db.clone()),
+        channel(1).0,
+    );
+    options.staleness_config = staleness_config;
+    let _runtime = crate::bootstrap(options);
+    let request = serde_json::json!([
+        {"jsonrpc": "2.0", "method": "get_metadata", "params": [], "id": 1},
+        {"jsonrpc": "2.0", "method": "get_currencies", "params": [], "id": 2},
+        {"jsonrpc": "2.0", "method": "get_account_state", "params": [format!("{:x}", account)], "id": 3},
+    ]);
+    let resp = reqwest::blocking::Client::new()
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    let responses: Vec<JsonMap> = resp.json().unwrap();
+    let warning = serde_json::json!({
+        "ledger_timestamp_usecs": mock_db.timestamp,
+        "max_staleness_secs": 60,
+    });
+
+    // served with a warning
+    assert!(responses[0].get("result").is_some());
+    assert_eq!(responses[0].get("stale"), Some(&warning));
+    // served as usual
+    assert!(responses[1].get("result").is_some());
+    assert_eq!(responses[1].get("stale"), None);
+    // rejected
+    let error: JsonRpcError =
+        serde_json::from_value(responses[2].get("error").unwrap().clone()).unwrap();
+    assert_eq!(error.code, ServerCode::StaleState as i16);
+    assert_eq!(error.data, Some(warning));
+}
+
+#[test]
+fn test_missing_api_key() {
+    let (address, _runtime) = bootstrap_with_quotas();
+    let resp = post_with_api_key(&address, None);
+    assert_eq!(resp.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(fetch_error(resp), ServerCode::InvalidApiKey as i16);
+}
+
+#[test]
+fn test_invalid_api_key() {
+    let (address, _runtime) = bootstrap_with_quotas();
+    let resp = post_with_api_key(&address, Some("other"));
+    assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(fetch_error(resp), ServerCode::InvalidApiKey as i16);
+}
+
+#[test]
+fn test_quota_exceeded() {
+    let (address, _runtime) = bootstrap_with_quotas();
+    let resp = post_with_api_key(&address, Some("key"));
+    assert_eq!(resp.status(), reqwest::StatusCode::OK);
+    let resp = post_with_api_key(&address, Some("key"));
+    assert_eq!(resp.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(fetch_error(resp), ServerCode::QuotaExceeded as i16);
+}
+
 #[test]
 fn test_get_account_state_with_proof() {
     let (mock_db, client, mut runtime) = create_database_client_and_runtime(1);
@@ -569,6 +898,53 @@ fn test_get_account_state_with_proof() {
     assert_eq!(txn_info_with_proof, *expected_txn_info_with_proof);
 }
 
+#[test]
+fn test_get_account_states() {
+    let (mock_db, client, mut runtime) = create_database_client_and_runtime(1);
+
+    let mut expected: Vec<_> = mock_db
+        .all_accounts
+        .iter()
+        .map(|(address, blob)| (address.hash(), blob.clone()))
+        .collect();
+    expected.sort_by_key(|(key, _)| *key);
+
+    let mut received = vec![];
+    let mut page_token = None;
+    loop {
+        let mut batch = JsonRpcBatch::default();
+        batch.add_get_account_states_request(3, page_token, None);
+        let result = execute_batch_and_get_first_response(&client, &mut runtime, batch);
+        let page = AccountStatePageView::from_response(result).unwrap();
+        assert_eq!(page.version, mock_db.version);
+        assert!(page.account_states.len() <= 3);
+        for account_state in page.account_states {
+            let blob: AccountStateBlob =
+                lcs::from_bytes(&account_state.blob.into_bytes().unwrap()).unwrap();
+            received.push((HashValue::from_hex(&account_state.key).unwrap(), blob));
+        }
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    assert_eq!(received, expected);
+
+    // version past the latest version
+    let mut batch = JsonRpcBatch::default();
+    batch.add_get_account_states_request(3, None, Some(mock_db.version + 1));
+    let responses = runtime.block_on(client.execute(batch)).unwrap();
+    assert!(responses[0].is_err());
+
+    // more account states than the node serves per second
+    let mut batch = JsonRpcBatch::default();
+    batch.add_get_account_states_request(DEFAULT_MAX_ACCOUNT_STATES_PER_SEC + 1, None, None);
+    let response = runtime.block_on(client.execute(batch)).unwrap().remove(0);
+    let error = response.unwrap_err();
+    let error = error.downcast_ref::<JsonRpcError>().unwrap();
+    assert_eq!(error.code, ServerCode::QuotaExceeded as i16);
+}
+
 #[test]
 fn test_get_state_proof() {
     let (mock_db, client, mut runtime) = create_database_client_and_runtime(1024);
@@ -600,6 +976,121 @@ fn test_get_network_status() {
     }
 }
 
+#[test]
+fn test_get_network_topology() {
+    let request = serde_json::json!({"jsonrpc": "2.0", "method": "get_network_topology", "params": [], "id": 1});
+    let client = reqwest::blocking::Client::new();
+
+    // admin methods are not found unless enabled
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let _runtime = test_bootstrap(address.parse().unwrap(), Arc::new(mock_db()), channel(1).0);
+    let resp = client
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    assert_eq!(fetch_error(resp), -32601);
+
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let mut options =
+        BootstrapOptions::new(address.parse().unwrap(), Arc::new(mock_db()), channel(1).0);
+    options.connected_peers = Some(ConnectedPeers::new());
+    let _runtime = crate::bootstrap(options);
+    let resp = client
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    let data: JsonMap = resp.json().unwrap();
+    // expect no connected peers when no network is running
+    assert_eq!(data.get("result").unwrap(), &serde_json::json!([]));
+
+    let request = serde_json::json!({"jsonrpc": "2.0", "method": "get_time_sync_status", "params": [], "id": 1});
+    let resp = client
+        .post(&format!("http://{}", address))
+        .json(&request)
+        .send()
+        .unwrap();
+    let data: JsonMap = resp.json().unwrap();
+    let status: TimeSyncStatusView =
+        serde_json::from_value(data.get("result").unwrap().clone()).unwrap();
+    assert_eq!(status.median_peer_clock_skew_ms, None);
+    assert_eq!(status.num_peers_measured, 0);
+}
+
+#[test]
+fn test_openrpc_schema() {
+    // every registered method is documented, the admin methods only if enabled
+    let method_names = |schema: &serde_json::Value| {
+        let mut names: Vec<_> = schema["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|method| method["name"].as_str().unwrap().to_string())
+            .collect();
+        names.sort();
+        names
+    };
+    let mut registered: Vec<_> = build_registry().keys().cloned().collect();
+    registered.sort();
+    assert_eq!(method_names(&build_schema(true)), registered);
+    let admin_methods = ["get_network_topology", "get_time_sync_status"];
+    registered.retain(|name| !admin_methods.contains(&name.as_str()));
+    assert_eq!(method_names(&build_schema(false)), registered);
+
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let _runtime = test_bootstrap(address.parse().unwrap(), Arc::new(mock_db()), channel(1).0);
+    let schema: serde_json::Value =
+        reqwest::blocking::get(&format!("http://{}/{}", address, crate::SCHEMA_PATH))
+            .unwrap()
+            .json()
+            .unwrap();
+    assert_eq!(schema["info"]["version"], crate::API_VERSION);
+    assert_eq!(method_names(&schema), method_names(&build_schema(false)));
+
+    // the results reference the schemas of the views
+    let get_transactions = schema["methods"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|method| method["name"] == "get_transactions")
+        .unwrap();
+    assert_eq!(
+        get_transactions["result"]["schema"],
+        serde_json::json!({
+            "type": "array",
+            "items": { "$ref": "#/components/schemas/TransactionView" },
+        })
+    );
+    let definitions = &schema["components"]["schemas"];
+    assert_eq!(
+        definitions["AmountView"],
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "amount": { "type": "integer", "minimum": 0 },
+                "currency": { "type": "string" },
+            },
+            "required": ["amount", "currency"],
+        })
+    );
+    assert_eq!(
+        definitions["EventDataView"]["oneOf"][7],
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "type": { "const": "newepoch" },
+                "epoch": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["type", "epoch"],
+        })
+    );
+    assert_eq!(
+        definitions["AccountRoleView"]["oneOf"][0],
+        serde_json::json!({ "const": "unknown" })
+    );
+}
+
 /// Creates and returns a MockLibraDB, JsonRpcAsyncClient and corresponding server Runtime tuple for
 /// testing. The given channel_buffer specifies the buffer size of the mempool client sender channel.
 fn create_database_client_and_runtime(
@@ -624,6 +1115,40 @@ fn create_database_client_and_runtime(
     (mock_db, client, runtime)
 }
 
+/// Creates a server whose only API key, "key", is allowed a single request per window, and returns
+/// its address along with its runtime.
+fn bootstrap_with_quotas() -> (String, Runtime) {
+    let mut quota_config = RpcQuotaConfig::default();
+    quota_config.enabled = true;
+    quota_config.api_keys.insert(
+        "key".to_string(),
+        ApiKeyQuota {
+            max_requests: Some(1),
+            max_weight: None,
+            max_result_bytes: None,
+        },
+    );
+    let address = format!("0.0.0.0:{}", utils::get_available_port());
+    let mut options =
+        BootstrapOptions::new(address.parse().unwrap(), Arc::new(mock_db()), channel(1).0);
+    options.quota_config = quota_config;
+    let runtime = crate::bootstrap(options);
+    (address, runtime)
+}
+
+/// Sends a `get_metadata` request to the server at `address`, with `api_key` if any.
+fn post_with_api_key(address: &str, api_key: Option<&str>) -> reqwest::blocking::Response {
+    let request =
+        serde_json::json!({"jsonrpc": "2.0", "method": "get_metadata", "params": [], "id": 1});
+    let mut request = reqwest::blocking::Client::new()
+        .post(&format!("http://{}", address))
+        .json(&request);
+    if let Some(api_key) = api_key {
+        request = request.header("x-api-key", api_key);
+    }
+    request.send().unwrap()
+}
+
 /// Returns the first account address stored in the given mock database.
 fn get_first_account_from_mock_db(mock_db: &MockLibraDB) -> AccountAddress {
     *mock_db
@@ -2,8 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{Error, Result};
-use libra_config::config::RoleType;
-use libra_crypto::HashValue;
+use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_mempool::MempoolClientSender;
 use libra_types::{
     account_address::AccountAddress,
@@ -23,7 +22,7 @@ use libra_types::{
     vm_error::StatusCode,
 };
 use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
-use storage_interface::{DbReader, StartupInfo, TreeState};
+use storage_interface::{AccountStatePage, DbReader, StartupInfo, TreeState};
 use tokio::runtime::Runtime;
 
 /// Creates JSON RPC server for a Validator node
@@ -33,7 +32,7 @@ pub fn test_bootstrap(
     libra_db: Arc<dyn DbReader>,
     mp_sender: MempoolClientSender,
 ) -> Runtime {
-    crate::bootstrap(address, libra_db, mp_sender, RoleType::Validator)
+    crate::bootstrap(crate::BootstrapOptions::new(address, libra_db, mp_sender))
 }
 
 /// Lightweight mock of LibraDB
@@ -229,6 +228,32 @@ impl DbReader for MockLibraDB {
         Ok(self.account_state_with_proof[0].clone())
     }
 
+    fn get_account_state_page(
+        &self,
+        _version: Version,
+        page_token: Option<HashValue>,
+        limit: u64,
+    ) -> Result<AccountStatePage> {
+        let start = page_token.unwrap_or_else(HashValue::zero);
+        let mut account_states: Vec<_> = self
+            .all_accounts
+            .iter()
+            .map(|(address, blob)| (address.hash(), blob.clone()))
+            .filter(|(key, _)| *key >= start)
+            .collect();
+        account_states.sort_by_key(|(key, _)| *key);
+        account_states.truncate(limit as usize + 1);
+        let next_page_token = if account_states.len() > limit as usize {
+            account_states.pop().map(|(key, _)| key)
+        } else {
+            None
+        };
+        Ok(AccountStatePage {
+            account_states,
+            next_page_token,
+        })
+    }
+
     fn get_startup_info(&self) -> Result<Option<StartupInfo>> {
         unimplemented!()
     }
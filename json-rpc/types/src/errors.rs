@@ -29,6 +29,17 @@ pub enum ServerCode {
     MempoolInvalidUpdate = -32010,
     MempoolVmError = -32011,
     MempoolUnknownError = -32012,
+
+    // Quota errors - see `RpcQuotaConfig` for specs
+    QuotaExceeded = -32013,
+    InvalidApiKey = -32014,
+
+    // Storage errors
+    StatePruned = -32015,
+    // Staleness errors - see `RpcStalenessConfig` for specs
+    StaleState = -32016,
+    // Degradation errors - see `ResourceLimitsConfig` for specs
+    Throttled = -32017,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -83,6 +94,70 @@ impl JsonRpcError {
         }
     }
 
+    pub fn quota_exceeded(message: String) -> Self {
+        Self {
+            code: ServerCode::QuotaExceeded as i16,
+            message: format!("Server error: Quota exceeded: {}", message),
+            data: None,
+        }
+    }
+
+    /// The node throttles its requests while shedding load under resource pressure.
+    pub fn throttled() -> Self {
+        Self {
+            code: ServerCode::Throttled as i16,
+            message: "Server error: Node is overloaded, retry later".to_string(),
+            data: None,
+        }
+    }
+
+    pub fn missing_api_key() -> Self {
+        Self {
+            code: ServerCode::InvalidApiKey as i16,
+            message: "Server error: Missing API key".to_string(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_api_key() -> Self {
+        Self {
+            code: ServerCode::InvalidApiKey as i16,
+            message: "Server error: Unknown API key".to_string(),
+            data: None,
+        }
+    }
+
+    /// The state at `version` has been pruned: the `data` carries the earliest available version.
+    pub fn state_pruned(version: u64, earliest_version: u64) -> Self {
+        Self {
+            code: ServerCode::StatePruned as i16,
+            message: format!(
+                "Server error: State at version {} has been pruned, earliest available version is {}",
+                version, earliest_version
+            ),
+            data: Some(serde_json::json!({
+                "version": version,
+                "earliest_version": earliest_version,
+            })),
+        }
+    }
+
+    /// The latest ledger info of the node, with timestamp `ledger_timestamp_usecs`, is more than
+    /// `max_staleness_secs` old: the `data` carries both.
+    pub fn stale_state(ledger_timestamp_usecs: u64, max_staleness_secs: u64) -> Self {
+        Self {
+            code: ServerCode::StaleState as i16,
+            message: format!(
+                "Server error: State of the node is stale, latest ledger timestamp is {} usecs",
+                ledger_timestamp_usecs
+            ),
+            data: Some(serde_json::json!({
+                "ledger_timestamp_usecs": ledger_timestamp_usecs,
+                "max_staleness_secs": max_staleness_secs,
+            })),
+        }
+    }
+
     pub fn mempool_error(error: MempoolStatus) -> Result<Self> {
         let code = match error.code {
             MempoolStatusCode::InvalidSeqNumber => ServerCode::MempoolInvalidSeqNumber,
@@ -0,0 +1,374 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON schemas of the views, from which the OpenRPC document of the JSON-RPC API is generated.
+//!
+//! The schemas of the views are declared with the `object_schema!`, `tagged_enum_schema!` and
+//! `enum_schema!` macros next to their field and variant types, which fail to compile unless the
+//! declared fields and variants, and their types, are exactly the ones of the Rust type, so that
+//! the schemas can't drift from the implementation. The views referenced by other views are
+//! collected in the [`Definitions`] of the document, and referenced by name.
+
+use crate::views::{
+    AccountRoleView, AccountStatePageView, AccountStateProofView, AccountStateWithProofView,
+    AccountView, AmountView, BlockMetadata, BytesView, ConnectedPeerView, CurrencyInfoView,
+    EventDataView, EventView, ExpiredTransactionView, KeyedAccountStateView, ParkedTransactionView,
+    ScriptView, StateProofView, TimeSyncStatusView, TransactionDataView, TransactionView,
+};
+use libra_types::vm_error::StatusCode;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Prefix of the references to the schemas of the [`Definitions`] of an OpenRPC document.
+pub const REF_PREFIX: &str = "#/components/schemas/";
+
+/// The schemas of the views, by name.
+pub type Definitions = BTreeMap<String, Value>;
+
+/// A type whose JSON representation has a schema.
+pub trait JsonSchema {
+    /// Returns the schema of the type, adding the schemas of the views it references to
+    /// `definitions`.
+    fn schema(definitions: &mut Definitions) -> Value;
+}
+
+/// Adds the schema of the view `name` to `definitions`, built by `build` unless it is already
+/// there, and returns a reference to it.
+pub fn reference(
+    definitions: &mut Definitions,
+    name: &str,
+    build: impl FnOnce(&mut Definitions) -> Value,
+) -> Value {
+    if !definitions.contains_key(name) {
+        // Reserve the name first, so that recursive views terminate.
+        definitions.insert(name.to_string(), Value::Null);
+        let schema = build(definitions);
+        definitions.insert(name.to_string(), schema);
+    }
+    json!({ "$ref": format!("{}{}", REF_PREFIX, name) })
+}
+
+/// The schema of an object with all of `fields`.
+pub fn object(fields: Vec<(&str, Value)>) -> Value {
+    let required: Vec<_> = fields.iter().map(|(name, _)| json!(name)).collect();
+    let properties: Map<_, _> = fields
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The schema of a variant of an enum tagged by its `tag` field, with all of `fields`.
+fn tagged_variant(tag: &str, name: &str, mut fields: Vec<(&str, Value)>) -> Value {
+    fields.insert(0, (tag, json!({ "const": name })));
+    object(fields)
+}
+
+/// The schema of a variant of an externally tagged enum, i.e., its name for a unit variant, and
+/// an object with the fields of the variant under its name otherwise.
+fn external_variant(name: &str, fields: Option<Vec<(&str, Value)>>) -> Value {
+    match fields {
+        None => json!({ "const": name }),
+        Some(fields) => object(vec![(name, object(fields))]),
+    }
+}
+
+macro_rules! object_schema {
+    ($ty:ident { $($field:ident: $field_ty:ty),* $(,)? }) => {
+        impl JsonSchema for $ty {
+            fn schema(definitions: &mut Definitions) -> Value {
+                #[allow(dead_code)]
+                fn check(value: &$ty) {
+                    let $ty { $($field),* } = value;
+                    $(let _: &$field_ty = $field;)*
+                }
+                reference(definitions, stringify!($ty), |definitions| {
+                    object(vec![$((
+                        stringify!($field),
+                        <$field_ty as JsonSchema>::schema(definitions),
+                    )),*])
+                })
+            }
+        }
+    };
+}
+
+macro_rules! tagged_enum_schema {
+    ($ty:ident, $tag:expr, {
+        $($variant:ident as $name:expr => { $($field:ident: $field_ty:ty),* $(,)? }),* $(,)?
+    }) => {
+        impl JsonSchema for $ty {
+            fn schema(definitions: &mut Definitions) -> Value {
+                #[allow(dead_code)]
+                fn check(value: &$ty) {
+                    match value {
+                        $($ty::$variant { $($field),* } => { $(let _: &$field_ty = $field;)* })*
+                    }
+                }
+                reference(definitions, stringify!($ty), |definitions| {
+                    json!({ "oneOf": vec![$(tagged_variant($tag, $name, vec![$((
+                        stringify!($field),
+                        <$field_ty as JsonSchema>::schema(definitions),
+                    )),*])),*] })
+                })
+            }
+        }
+    };
+}
+
+macro_rules! enum_schema {
+    ($ty:ident, {
+        $($variant:ident as $name:expr $(=> { $($field:ident: $field_ty:ty),* $(,)? })?),* $(,)?
+    }) => {
+        impl JsonSchema for $ty {
+            fn schema(definitions: &mut Definitions) -> Value {
+                #[allow(dead_code)]
+                fn check(value: &$ty) {
+                    match value {
+                        $($ty::$variant $({ $($field),* })? => {
+                            $($(let _: &$field_ty = $field;)*)?
+                        })*
+                    }
+                }
+                reference(definitions, stringify!($ty), |definitions| {
+                    json!({ "oneOf": vec![$(external_variant(
+                        $name,
+                        None $(.or(Some(vec![$((
+                            stringify!($field),
+                            <$field_ty as JsonSchema>::schema(definitions),
+                        )),*])))?,
+                    )),*] })
+                })
+            }
+        }
+    };
+}
+
+impl JsonSchema for () {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "null" })
+    }
+}
+
+impl JsonSchema for bool {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for u64 {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl JsonSchema for i64 {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "integer" })
+    }
+}
+
+impl JsonSchema for String {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn schema(definitions: &mut Definitions) -> Value {
+        json!({ "oneOf": [T::schema(definitions), { "type": "null" }] })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn schema(definitions: &mut Definitions) -> Value {
+        json!({ "type": "array", "items": T::schema(definitions) })
+    }
+}
+
+/// Status codes are serialized as their number.
+impl JsonSchema for StatusCode {
+    fn schema(definitions: &mut Definitions) -> Value {
+        u64::schema(definitions)
+    }
+}
+
+impl JsonSchema for BytesView {
+    fn schema(_definitions: &mut Definitions) -> Value {
+        json!({ "type": "string", "description": "Hex-encoded bytes" })
+    }
+}
+
+object_schema!(AmountView {
+    amount: u64,
+    currency: String,
+});
+
+enum_schema!(AccountRoleView, {
+    Unknown as "unknown",
+    Unhosted as "unhosted",
+    Empty as "empty",
+    ChildVASP as "child_vasp" => { parent_vasp_address: BytesView },
+    ParentVASP as "parent_vasp" => {
+        human_name: String,
+        base_url: String,
+        expiration_time: u64,
+        compliance_key: BytesView,
+    },
+});
+
+object_schema!(AccountView {
+    balances: Vec<AmountView>,
+    sequence_number: u64,
+    authentication_key: BytesView,
+    sent_events_key: BytesView,
+    received_events_key: BytesView,
+    delegated_key_rotation_capability: bool,
+    delegated_withdrawal_capability: bool,
+    role: AccountRoleView,
+});
+
+object_schema!(EventView {
+    key: BytesView,
+    sequence_number: u64,
+    transaction_version: u64,
+    data: EventDataView,
+});
+
+tagged_enum_schema!(EventDataView, "type", {
+    Burn as "burn" => { amount: AmountView, preburn_address: BytesView },
+    CancelBurn as "cancelburn" => { amount: AmountView, preburn_address: BytesView },
+    Mint as "mint" => { amount: AmountView },
+    Preburn as "preburn" => { amount: AmountView, preburn_address: BytesView },
+    ReceivedPayment as "receivedpayment" => {
+        amount: AmountView,
+        sender: BytesView,
+        metadata: BytesView,
+    },
+    SentPayment as "sentpayment" => {
+        amount: AmountView,
+        receiver: BytesView,
+        metadata: BytesView,
+    },
+    Upgrade as "upgrade" => { write_set: BytesView },
+    NewEpoch as "newepoch" => { epoch: u64 },
+    NewBlock as "newblock" => { round: u64, proposer: BytesView, proposed_time: u64 },
+    Unknown as "unknown" => {},
+});
+
+object_schema!(BlockMetadata {
+    version: u64,
+    timestamp: u64,
+});
+
+object_schema!(TransactionView {
+    version: u64,
+    transaction: TransactionDataView,
+    hash: String,
+    events: Vec<EventView>,
+    vm_status: StatusCode,
+    gas_used: u64,
+});
+
+object_schema!(ParkedTransactionView {
+    transaction: TransactionDataView,
+    hash: String,
+    parked_duration_ms: u64,
+});
+
+object_schema!(ExpiredTransactionView {
+    transaction: TransactionDataView,
+    hash: String,
+    block_timestamp_usecs: u64,
+});
+
+object_schema!(ConnectedPeerView {
+    peer_id: String,
+    role: String,
+    network_id: String,
+    address: String,
+    rtt_ms: Option<u64>,
+    synced_version: Option<u64>,
+    clock_skew_ms: Option<i64>,
+});
+
+object_schema!(TimeSyncStatusView {
+    ntp_synchronized: Option<bool>,
+    median_peer_clock_skew_ms: Option<i64>,
+    num_peers_measured: u64,
+});
+
+tagged_enum_schema!(TransactionDataView, "type", {
+    BlockMetadata as "blockmetadata" => { timestamp_usecs: u64 },
+    WriteSet as "writeset" => {},
+    UserTransaction as "user" => {
+        sender: String,
+        signature_scheme: String,
+        signature: String,
+        public_key: String,
+        sequence_number: u64,
+        max_gas_amount: u64,
+        gas_unit_price: u64,
+        gas_currency: String,
+        expiration_time: u64,
+        script_hash: String,
+        script: ScriptView,
+    },
+    UnknownTransaction as "unknown" => {},
+});
+
+tagged_enum_schema!(ScriptView, "type", {
+    PeerToPeer as "peer_to_peer_transaction" => {
+        receiver: String,
+        amount: u64,
+        currency: String,
+        metadata: BytesView,
+        metadata_signature: BytesView,
+    },
+    Mint as "mint_transaction" => {
+        receiver: String,
+        currency: String,
+        auth_key_prefix: BytesView,
+        amount: u64,
+    },
+    Unknown as "unknown_transaction" => {},
+});
+
+object_schema!(CurrencyInfoView {
+    code: String,
+    scaling_factor: u64,
+    fractional_part: u64,
+});
+
+object_schema!(StateProofView {
+    ledger_info_with_signatures: BytesView,
+    epoch_change_proof: BytesView,
+    ledger_consistency_proof: BytesView,
+});
+
+object_schema!(AccountStateWithProofView {
+    version: u64,
+    blob: Option<BytesView>,
+    proof: AccountStateProofView,
+});
+
+object_schema!(AccountStateProofView {
+    ledger_info_to_transaction_info_proof: BytesView,
+    transaction_info: BytesView,
+    transaction_info_to_account_proof: BytesView,
+});
+
+object_schema!(AccountStatePageView {
+    version: u64,
+    account_states: Vec<KeyedAccountStateView>,
+    next_page_token: Option<String>,
+});
+
+object_schema!(KeyedAccountStateView {
+    key: String,
+    blob: BytesView,
+});
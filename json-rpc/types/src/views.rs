@@ -9,7 +9,7 @@ use libra_types::{
         CurrencyInfoResource, MintEvent, NewBlockEvent, NewEpochEvent, PreburnEvent,
         ReceivedPaymentEvent, SentPaymentEvent, UpgradeEvent, LBR_NAME,
     },
-    account_state_blob::AccountStateWithProof,
+    account_state_blob::{AccountStateBlob, AccountStateWithProof},
     contract_event::ContractEvent,
     epoch_change::EpochChangeProof,
     ledger_info::LedgerInfoWithSignatures,
@@ -306,6 +306,52 @@ pub struct TransactionView {
     pub gas_used: u64,
 }
 
+/// Transaction waiting in mempool for transactions of the same sender with lower sequence numbers
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ParkedTransactionView {
+    pub transaction: TransactionDataView,
+    pub hash: String,
+    pub parked_duration_ms: u64,
+}
+
+/// Transaction which expired without being committed, and thus never will be
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ExpiredTransactionView {
+    pub transaction: TransactionDataView,
+    pub hash: String,
+    /// Timestamp of the block the transaction expired at
+    pub block_timestamp_usecs: u64,
+}
+
+/// Peer connected to the node, as returned by `get_network_topology`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ConnectedPeerView {
+    pub peer_id: String,
+    /// Role of the network the peer is connected through, i.e., `validator` or `full_node`
+    pub role: String,
+    pub network_id: String,
+    pub address: String,
+    /// Round-trip time of the last successful health check ping, if any
+    pub rtt_ms: Option<u64>,
+    /// Latest version the peer reported being synced to, if known
+    pub synced_version: Option<u64>,
+    /// Skew of the clock of the peer relative to the clock of the node, positive if ahead, as
+    /// measured by the last successful health check ping, if any
+    pub clock_skew_ms: Option<i64>,
+}
+
+/// Quality of the time synchronization of the node, as returned by `get_time_sync_status`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TimeSyncStatusView {
+    /// Whether the local clock is synchronized, e.g., by NTP, if known
+    pub ntp_synchronized: Option<bool>,
+    /// Median skew of the clocks of the connected peers relative to the clock of the node, if
+    /// measured. A large skew suggests that the clock of the node drifts.
+    pub median_peer_clock_skew_ms: Option<i64>,
+    /// Number of connected peers whose clock skew was measured
+    pub num_peers_measured: u64,
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(tag = "type")]
@@ -374,6 +420,14 @@ impl From<Transaction> for TransactionDataView {
                     timestamp_usecs: x.1,
                 })
             }
+            Transaction::BlockMetadataWithExtension(t) => {
+                t.into_inner()
+                    .0
+                    .into_inner()
+                    .map(|x| TransactionDataView::BlockMetadata {
+                        timestamp_usecs: x.1,
+                    })
+            }
             Transaction::WaypointWriteSet(_) => Ok(TransactionDataView::WriteSet {}),
             Transaction::UserTransaction(t) => {
                 let script_hash = match t.payload() {
@@ -592,3 +646,31 @@ impl TryFrom<AccountStateProof> for AccountStateProofView {
         })
     }
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AccountStatePageView {
+    pub version: u64,
+    pub account_states: Vec<KeyedAccountStateView>,
+    /// Key to pass to get the next page, or None if this is the last page
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct KeyedAccountStateView {
+    /// Hash of the account address, which orders the account states
+    pub key: String,
+    pub blob: BytesView,
+}
+
+impl TryFrom<(HashValue, AccountStateBlob)> for KeyedAccountStateView {
+    type Error = Error;
+
+    fn try_from(
+        (key, blob): (HashValue, AccountStateBlob),
+    ) -> Result<KeyedAccountStateView, Error> {
+        Ok(KeyedAccountStateView {
+            key: key.to_hex(),
+            blob: BytesView::from(&lcs::to_bytes(&blob)?),
+        })
+    }
+}
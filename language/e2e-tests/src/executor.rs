@@ -13,17 +13,33 @@ use libra_crypto::HashValue;
 use libra_state_view::StateView;
 use libra_types::{
     access_path::AccessPath,
-    account_config::{AccountResource, BalanceResource},
+    account_address::AccountAddress,
+    account_config::{association_address, AccountResource, BalanceResource, CORE_CODE_ADDRESS},
     block_metadata::{new_block_event_key, BlockMetadata, NewBlockEvent},
-    on_chain_config::{OnChainConfig, VMPublishingOption, ValidatorSet},
+    contract_event::ContractEvent,
+    move_resource::MoveResource,
+    on_chain_config::{
+        config_address, ConfigurationResource, OnChainConfig, VMPublishingOption, ValidatorSet,
+    },
     transaction::{
         SignedTransaction, Transaction, TransactionOutput, TransactionStatus, VMValidatorResult,
     },
+    validator_info::ValidatorInfo,
     vm_error::{StatusCode, VMStatus},
-    write_set::WriteSet,
+    write_set::{WriteOp, WriteSet, WriteSetMut},
+};
+use libra_vm::{data_cache::StateViewCache, LibraVM, VMExecutor, VMValidator};
+use move_core_types::{
+    gas_schedule::{GasAlgebra, GasUnits},
+    identifier::Identifier,
+    language_storage::{ModuleId, TypeTag},
+};
+use move_vm_runtime::{data_cache::TransactionDataCache, move_vm::MoveVM};
+use move_vm_types::{
+    gas_schedule::{zero_cost_schedule, CostStrategy},
+    values::Value,
 };
-use libra_vm::{LibraVM, VMExecutor, VMValidator};
-use move_core_types::{identifier::Identifier, language_storage::ModuleId};
+use std::sync::Arc;
 use stdlib::{stdlib_modules, transaction_scripts::StdlibScript, StdLibOptions};
 use vm::CompiledModule;
 use vm_genesis::GENESIS_KEYPAIR;
@@ -163,6 +179,16 @@ impl FakeExecutor {
             })
     }
 
+    /// Reads the configuration resource, which holds the current epoch, from this executor's data
+    /// store.
+    pub fn read_configuration_resource(&self) -> ConfigurationResource {
+        let ap = AccessPath::new(config_address(), ConfigurationResource::resource_path());
+        let data_blob = StateView::get(&self.data_store, &ap)
+            .expect("config account must exist in data store")
+            .expect("configuration resource must exist in data store");
+        lcs::from_bytes(data_blob.as_slice()).expect("Failure decoding configuration resource")
+    }
+
     /// Executes the given block of transactions.
     ///
     /// Typical tests will call this method and check that the output matches what was expected.
@@ -174,7 +200,7 @@ impl FakeExecutor {
         LibraVM::execute_block(
             txn_block
                 .into_iter()
-                .map(Transaction::UserTransaction)
+                .map(|txn| Transaction::UserTransaction(Arc::new(txn)))
                 .collect(),
             &self.data_store,
         )
@@ -234,10 +260,25 @@ impl FakeExecutor {
         &self.data_store
     }
 
+    /// Returns the timestamp of the last block, in microseconds.
+    pub fn block_time(&self) -> u64 {
+        self.block_time
+    }
+
     pub fn new_block(&mut self) {
+        self.advance_time(1)
+    }
+
+    /// Executes the prologue of a new block whose timestamp is `microseconds` after the one of the
+    /// last block, so that the on-chain time can be fast-forwarded deterministically.
+    pub fn advance_time(&mut self, microseconds: u64) {
+        assert!(
+            microseconds > 0,
+            "The block timestamps must strictly increase"
+        );
         let validator_set = ValidatorSet::fetch_config(&self.data_store)
             .expect("Unable to retrieve the validator set from storage");
-        self.block_time += 1;
+        self.block_time += microseconds;
         let new_block = BlockMetadata::new(
             HashValue::zero(),
             0,
@@ -256,4 +297,77 @@ impl FakeExecutor {
         assert!(lcs::from_bytes::<NewBlockEvent>(event.event_data()).is_ok());
         self.apply_write_set(output.write_set());
     }
+
+    /// Executes the function `function_name` of the stdlib module `module_name` as `sender`,
+    /// without gas metering, and applies its writes to the data store. Returns the emitted events.
+    ///
+    /// Panics if the execution fails.
+    pub fn exec(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        type_params: Vec<TypeTag>,
+        args: Vec<Value>,
+        sender: &AccountAddress,
+    ) -> Vec<ContractEvent> {
+        let (write_set, events) = {
+            let gas_schedule = zero_cost_schedule();
+            let mut cost_strategy =
+                CostStrategy::system(&gas_schedule, GasUnits::new(std::u64::MAX));
+            let data_cache = StateViewCache::new(&self.data_store);
+            let mut data_store = TransactionDataCache::new(&data_cache);
+            MoveVM::new()
+                .execute_function(
+                    &ModuleId::new(CORE_CODE_ADDRESS, Identifier::new(module_name).unwrap()),
+                    &Identifier::new(function_name).unwrap(),
+                    type_params,
+                    args,
+                    *sender,
+                    &mut data_store,
+                    &mut cost_strategy,
+                )
+                .unwrap_or_else(|e| {
+                    panic!("Error calling {}.{}: {:?}", module_name, function_name, e)
+                });
+            let write_set = data_store
+                .make_write_set()
+                .expect("Failed to generate the write set");
+            (write_set, data_store.event_data().to_vec())
+        };
+        self.apply_write_set(&write_set);
+        events
+    }
+
+    /// Starts a new epoch in a new block, as the association would. Returns the events of the
+    /// reconfiguration.
+    pub fn reconfigure(&mut self) -> Vec<ContractEvent> {
+        // There can be at most one reconfiguration per block.
+        self.new_block();
+        self.exec(
+            "LibraConfig",
+            "reconfigure",
+            vec![],
+            vec![Value::transaction_argument_signer_reference(
+                association_address(),
+            )],
+            &association_address(),
+        )
+    }
+
+    /// Replaces the validator set with `validators` and starts a new epoch, without registering
+    /// the validators. Returns the events of the reconfiguration.
+    ///
+    /// The first validator of the set proposes the following blocks, so it cannot be empty.
+    pub fn rotate_validator_set(&mut self, validators: Vec<ValidatorInfo>) -> Vec<ContractEvent> {
+        assert!(!validators.is_empty(), "The validator set cannot be empty");
+        let validator_set = ValidatorSet::new(validators);
+        let write_set = WriteSetMut::new(vec![(
+            ValidatorSet::CONFIG_ID.access_path(),
+            WriteOp::Value(lcs::to_bytes(&validator_set).unwrap()),
+        )])
+        .freeze()
+        .unwrap();
+        self.apply_write_set(&write_set);
+        self.reconfigure()
+    }
 }
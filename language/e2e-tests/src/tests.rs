@@ -19,8 +19,10 @@ mod mint;
 mod module_publishing;
 mod on_chain_configs;
 mod peer_to_peer;
+mod reconfiguration;
 mod rotate_key;
 mod scripts;
+mod sponsored_transactions;
 mod transaction_builder;
 mod transaction_fees;
 mod validator_set_management;
@@ -9,6 +9,7 @@ use libra_types::{
     transaction::{Module, SignedTransaction, Transaction, TransactionPayload, TransactionStatus},
     vm_error::{StatusCode, VMStatus},
 };
+use std::sync::Arc;
 
 #[test]
 fn move_from_across_blocks() {
@@ -65,8 +66,12 @@ fn move_from_across_blocks() {
 
     // create 2 remove resource transaction over the same resource in one block
     let txns = vec![
-        Transaction::UserTransaction(remove_resource_txn(&sender, 18, vec![module.clone()])),
-        Transaction::UserTransaction(remove_resource_txn(&sender, 19, vec![module])),
+        Transaction::UserTransaction(Arc::new(remove_resource_txn(
+            &sender,
+            18,
+            vec![module.clone()],
+        ))),
+        Transaction::UserTransaction(Arc::new(remove_resource_txn(&sender, 19, vec![module]))),
     ];
     let output = executor
         .execute_transaction_block(txns)
@@ -113,8 +118,12 @@ fn borrow_after_move() {
 
     // create a remove and a borrow resource transaction over the same resource in one block
     let txns = vec![
-        Transaction::UserTransaction(remove_resource_txn(&sender, 14, vec![module.clone()])),
-        Transaction::UserTransaction(borrow_resource_txn(&sender, 15, vec![module])),
+        Transaction::UserTransaction(Arc::new(remove_resource_txn(
+            &sender,
+            14,
+            vec![module.clone()],
+        ))),
+        Transaction::UserTransaction(Arc::new(borrow_resource_txn(&sender, 15, vec![module]))),
     ];
     let output = executor
         .execute_transaction_block(txns)
@@ -161,8 +170,16 @@ fn change_after_move() {
 
     // create a remove and a change resource transaction over the same resource in one block
     let txns = vec![
-        Transaction::UserTransaction(remove_resource_txn(&sender, 14, vec![module.clone()])),
-        Transaction::UserTransaction(change_resource_txn(&sender, 15, vec![module.clone()])),
+        Transaction::UserTransaction(Arc::new(remove_resource_txn(
+            &sender,
+            14,
+            vec![module.clone()],
+        ))),
+        Transaction::UserTransaction(Arc::new(change_resource_txn(
+            &sender,
+            15,
+            vec![module.clone()],
+        ))),
     ];
     let output = executor
         .execute_transaction_block(txns)
@@ -0,0 +1,67 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::executor::FakeExecutor;
+use libra_crypto::{ed25519::Ed25519PrivateKey, PrivateKey, Uniform};
+use libra_types::{
+    account_address::AccountAddress,
+    on_chain_config::{new_epoch_event_key, OnChainConfig, ValidatorSet},
+    validator_info::ValidatorInfo,
+};
+
+#[test]
+fn advance_time() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    executor.new_block();
+    assert_eq!(executor.block_time(), 1);
+
+    // one day later
+    executor.advance_time(86_400_000_000);
+    assert_eq!(executor.block_time(), 86_400_000_001);
+
+    executor.reconfigure();
+    assert_eq!(
+        executor
+            .read_configuration_resource()
+            .last_reconfiguration_time(),
+        86_400_000_002
+    );
+}
+
+#[test]
+fn reconfigure() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let epoch = executor.read_configuration_resource().epoch();
+
+    let events = executor.reconfigure();
+    assert!(events.iter().any(|e| e.key() == &new_epoch_event_key()));
+    assert_eq!(executor.read_configuration_resource().epoch(), epoch + 1);
+
+    executor.reconfigure();
+    assert_eq!(executor.read_configuration_resource().epoch(), epoch + 2);
+}
+
+#[test]
+fn rotate_validator_set() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let epoch = executor.read_configuration_resource().epoch();
+
+    let validators: Vec<_> = (0..4)
+        .map(|_| {
+            ValidatorInfo::new_with_test_network_keys(
+                AccountAddress::random(),
+                Ed25519PrivateKey::generate_for_testing().public_key(),
+                1,
+            )
+        })
+        .collect();
+    let events = executor.rotate_validator_set(validators.clone());
+    assert!(events.iter().any(|e| e.key() == &new_epoch_event_key()));
+    assert_eq!(executor.read_configuration_resource().epoch(), epoch + 1);
+
+    let validator_set = ValidatorSet::fetch_config(executor.get_state_view()).unwrap();
+    assert_eq!(validator_set.payload(), &validators[..]);
+
+    // the blocks of the new epoch are proposed by the new validators
+    executor.new_block();
+}
@@ -0,0 +1,136 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    account::{self, Account, AccountData},
+    assert_prologue_parity, assert_status_eq,
+    executor::FakeExecutor,
+    gas_costs, transaction_status_eq,
+};
+use libra_types::{
+    account_config::{lbr_type_tag, LBR_NAME},
+    transaction::{SignedTransaction, TransactionArgument, TransactionStatus},
+    vm_error::{StatusCode, VMStatus},
+};
+use stdlib::transaction_scripts::StdlibScript;
+use transaction_builder::sponsored::UnsignedSponsoredTransaction;
+
+/// Returns the transfer of `sender` to `receiver`, with a gas price of 1, whose gas is paid by
+/// `gas_payer`.
+fn sponsored_p2p_txn(
+    sender: &Account,
+    receiver: &Account,
+    gas_payer: &Account,
+    seq_num: u64,
+    transfer_amount: u64,
+) -> SignedTransaction {
+    let raw_txn = Account::create_raw_txn_with_args(
+        *sender.address(),
+        StdlibScript::PeerToPeerWithMetadata
+            .compiled_bytes()
+            .into_vec(),
+        vec![lbr_type_tag()],
+        vec![
+            TransactionArgument::Address(*receiver.address()),
+            TransactionArgument::U64(transfer_amount),
+            TransactionArgument::U8Vector(vec![]),
+            TransactionArgument::U8Vector(vec![]),
+        ],
+        seq_num,
+        gas_costs::TXN_RESERVED,
+        1,
+        LBR_NAME.to_owned(),
+    );
+    let mut txn = UnsignedSponsoredTransaction::new(raw_txn, *gas_payer.address());
+    txn.sign_as_sender(&sender.privkey, sender.pubkey.clone())
+        .unwrap();
+    txn.sign_as_gas_payer(&gas_payer.privkey, gas_payer.pubkey.clone())
+        .unwrap();
+    txn.into_signed_transaction().unwrap()
+}
+
+#[test]
+fn gas_payer_pays_gas() {
+    let mut executor = FakeExecutor::from_fresh_genesis();
+    let sender = AccountData::new(1_000_000, 10);
+    let receiver = AccountData::new(100_000, 10);
+    let gas_payer = AccountData::new(1_000_000, 3);
+    executor.add_account_data(&sender);
+    executor.add_account_data(&receiver);
+    executor.add_account_data(&gas_payer);
+
+    let txn = sponsored_p2p_txn(
+        sender.account(),
+        receiver.account(),
+        gas_payer.account(),
+        10,
+        1_000,
+    );
+    assert_eq!(executor.verify_transaction(txn.clone()).status(), None);
+    let output = executor.execute_and_apply(txn);
+    assert_eq!(
+        output.status(),
+        &TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED))
+    );
+    assert!(output.gas_used() > 0);
+
+    // the sender only pays the transfer, and the gas payer pays the gas
+    let balance = |account: &AccountData| {
+        executor
+            .read_balance_resource(account.account(), account::lbr_currency_code())
+            .unwrap()
+            .coin()
+    };
+    assert_eq!(balance(&sender), 1_000_000 - 1_000);
+    assert_eq!(balance(&receiver), 100_000 + 1_000);
+    assert_eq!(balance(&gas_payer), 1_000_000 - output.gas_used());
+
+    // only the sequence number of the sender is bumped
+    let sequence_number = |account: &AccountData| {
+        executor
+            .read_account_resource(account.account())
+            .unwrap()
+            .sequence_number()
+    };
+    assert_eq!(sequence_number(&sender), 11);
+    assert_eq!(sequence_number(&gas_payer), 3);
+}
+
+#[test]
+fn gas_payer_checks() {
+    let mut executor = FakeExecutor::from_fresh_genesis();
+    let sender = AccountData::new(1_000_000, 10);
+    let receiver = AccountData::new(100_000, 10);
+    let poor_gas_payer = AccountData::new(10, 0);
+    executor.add_account_data(&sender);
+    executor.add_account_data(&receiver);
+    executor.add_account_data(&poor_gas_payer);
+
+    // the gas payer must exist
+    let txn = sponsored_p2p_txn(
+        sender.account(),
+        receiver.account(),
+        &Account::new(),
+        10,
+        1_000,
+    );
+    assert_prologue_parity!(
+        executor.verify_transaction(txn.clone()).status(),
+        executor.execute_transaction(txn).status(),
+        VMStatus::new(StatusCode::GAS_PAYER_ACCOUNT_DOES_NOT_EXIST)
+    );
+
+    // the gas payer, not the sender, must be able to pay the gas
+    let txn = sponsored_p2p_txn(
+        sender.account(),
+        receiver.account(),
+        poor_gas_payer.account(),
+        10,
+        1_000,
+    );
+    assert_prologue_parity!(
+        executor.verify_transaction(txn.clone()).status(),
+        executor.execute_transaction(txn).status(),
+        VMStatus::new(StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE)
+    );
+}
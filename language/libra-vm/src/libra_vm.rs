@@ -16,7 +16,7 @@ use libra_types::{
     access_path::AccessPath,
     account_address::AccountAddress,
     account_config,
-    block_metadata::BlockMetadata,
+    block_metadata::{BlockMetadata, BlockMetadataExtension, BlockMetadataWithExtension},
     on_chain_config::{LibraVersion, OnChainConfig, VMConfig},
     transaction::{
         ChangeSet, Module, Script, SignatureCheckedTransaction, SignedTransaction, Transaction,
@@ -493,6 +493,7 @@ impl LibraVM {
         &self,
         remote_cache: &mut StateViewCache<'_>,
         block_metadata: BlockMetadata,
+        extension: Option<BlockMetadataExtension>,
     ) -> VMResult<TransactionOutput> {
         // TODO: How should we setup the metadata here? A couple of thoughts here:
         // 1. We might make the txn_data to be poisoned so that reading anything will result in a panic.
@@ -510,16 +511,25 @@ impl LibraVM {
         let mut data_store = TransactionDataCache::new(remote_cache);
 
         if let Ok((round, timestamp, previous_vote, proposer)) = block_metadata.into_inner() {
-            let args = vec![
+            let mut args = vec![
                 Value::transaction_argument_signer_reference(txn_data.sender),
                 Value::u64(round),
                 Value::u64(timestamp),
                 Value::vector_address(previous_vote),
                 Value::address(proposer),
             ];
+            // the Move side checks the extension against the on-chain config
+            let function_name = match extension {
+                Some(extension) => {
+                    args.push(Value::u8(extension.version()));
+                    args.push(Value::vector_u8(extension.into_data()));
+                    &*BLOCK_PROLOGUE_WITH_EXTENSION
+                }
+                None => &*BLOCK_PROLOGUE,
+            };
             self.move_vm.execute_function(
                 &LIBRA_BLOCK_MODULE,
-                &BLOCK_PROLOGUE,
+                function_name,
                 vec![],
                 args,
                 txn_data.sender(),
@@ -656,7 +666,8 @@ impl LibraVM {
     }
 
     /// Run the prologue of a transaction by calling into `PROLOGUE_NAME` function stored
-    /// in the `ACCOUNT_MODULE` on chain.
+    /// in the `ACCOUNT_MODULE` on chain, or into `SPONSORED_PROLOGUE_NAME` if the gas of the
+    /// transaction is paid by another account than its sender.
     fn run_prologue(
         &self,
         data_store: &mut TransactionDataCache,
@@ -671,20 +682,39 @@ impl LibraVM {
         let txn_gas_price = txn_data.gas_unit_price().get();
         let txn_max_gas_units = txn_data.max_gas_amount().get();
         let txn_expiration_time = txn_data.expiration_time();
-        let _timer = TXN_PROLOGUE_SECONDS.start_timer();
-        self.move_vm
-            .execute_function(
-                &account_config::ACCOUNT_MODULE,
-                &PROLOGUE_NAME,
-                vec![gas_currency_ty],
+        let (function_name, args) = match &txn_data.gas_payer {
+            None => (
+                &*PROLOGUE_NAME,
+                vec![
+                    Value::transaction_argument_signer_reference(txn_data.sender),
+                    Value::u64(txn_sequence_number),
+                    Value::vector_u8(txn_public_key),
+                    Value::u64(txn_gas_price),
+                    Value::u64(txn_max_gas_units),
+                    Value::u64(txn_expiration_time),
+                ],
+            ),
+            Some((gas_payer, gas_payer_public_key)) => (
+                &*SPONSORED_PROLOGUE_NAME,
                 vec![
                     Value::transaction_argument_signer_reference(txn_data.sender),
                     Value::u64(txn_sequence_number),
                     Value::vector_u8(txn_public_key),
+                    Value::address(*gas_payer),
+                    Value::vector_u8(gas_payer_public_key.clone()),
                     Value::u64(txn_gas_price),
                     Value::u64(txn_max_gas_units),
                     Value::u64(txn_expiration_time),
                 ],
+            ),
+        };
+        let _timer = TXN_PROLOGUE_SECONDS.start_timer();
+        self.move_vm
+            .execute_function(
+                &account_config::ACCOUNT_MODULE,
+                function_name,
+                vec![gas_currency_ty],
+                args,
                 txn_data.sender(),
                 data_store,
                 cost_strategy,
@@ -707,18 +737,23 @@ impl LibraVM {
         let txn_gas_price = txn_data.gas_unit_price().get();
         let txn_max_gas_units = txn_data.max_gas_amount().get();
         let gas_remaining = cost_strategy.remaining_gas().get();
-        let _timer = TXN_EPILOGUE_SECONDS.start_timer();
-        self.move_vm.execute_function(
-            &account_config::ACCOUNT_MODULE,
+        let (function_name, args) = epilogue_function_and_args(
             &SUCCESS_EPILOGUE_NAME,
-            vec![gas_currency_ty],
+            &SPONSORED_SUCCESS_EPILOGUE_NAME,
+            txn_data,
             vec![
-                Value::transaction_argument_signer_reference(txn_data.sender),
                 Value::u64(txn_sequence_number),
                 Value::u64(txn_gas_price),
                 Value::u64(txn_max_gas_units),
                 Value::u64(gas_remaining),
             ],
+        );
+        let _timer = TXN_EPILOGUE_SECONDS.start_timer();
+        self.move_vm.execute_function(
+            &account_config::ACCOUNT_MODULE,
+            function_name,
+            vec![gas_currency_ty],
+            args,
             txn_data.sender(),
             data_store,
             cost_strategy,
@@ -740,18 +775,23 @@ impl LibraVM {
         let txn_gas_price = txn_data.gas_unit_price().get();
         let txn_max_gas_units = txn_data.max_gas_amount().get();
         let gas_remaining = cost_strategy.remaining_gas().get();
-        let _timer = TXN_EPILOGUE_SECONDS.start_timer();
-        self.move_vm.execute_function(
-            &account_config::ACCOUNT_MODULE,
+        let (function_name, args) = epilogue_function_and_args(
             &FAILURE_EPILOGUE_NAME,
-            vec![gas_currency_ty],
+            &SPONSORED_FAILURE_EPILOGUE_NAME,
+            txn_data,
             vec![
-                Value::transaction_argument_signer_reference(txn_data.sender),
                 Value::u64(txn_sequence_number),
                 Value::u64(txn_gas_price),
                 Value::u64(txn_max_gas_units),
                 Value::u64(gas_remaining),
             ],
+        );
+        let _timer = TXN_EPILOGUE_SECONDS.start_timer();
+        self.move_vm.execute_function(
+            &account_config::ACCOUNT_MODULE,
+            function_name,
+            vec![gas_currency_ty],
+            args,
             txn_data.sender(),
             data_store,
             cost_strategy,
@@ -841,14 +881,29 @@ impl LibraVM {
                     execute_block_trace_guard.clear();
                     current_block_id = block_metadata.id();
                     trace_code_block!("libra_vm::execute_block_impl", {"block", current_block_id}, execute_block_trace_guard);
-                    result.push(self.process_block_prologue(&mut data_cache, block_metadata)?)
+                    result.push(self.process_block_prologue(
+                        &mut data_cache,
+                        block_metadata,
+                        None,
+                    )?)
+                }
+                TransactionBlock::BlockPrologueWithExtension(block_metadata) => {
+                    let (block_metadata, extension) = block_metadata.into_inner();
+                    execute_block_trace_guard.clear();
+                    current_block_id = block_metadata.id();
+                    trace_code_block!("libra_vm::execute_block_impl", {"block", current_block_id}, execute_block_trace_guard);
+                    result.push(self.process_block_prologue(
+                        &mut data_cache,
+                        block_metadata,
+                        Some(extension),
+                    )?)
                 }
                 TransactionBlock::WaypointWriteSet(change_set) => result.push(
                     self.process_waypoint_change_set(&mut data_cache, change_set)
                         .unwrap_or_else(discard_error_output),
                 ),
                 TransactionBlock::WriteSet(txn) => {
-                    result.push(self.process_writeset_transaction(&mut data_cache, *txn)?)
+                    result.push(self.process_writeset_transaction(&mut data_cache, unshare(txn))?)
                 }
             }
         }
@@ -865,7 +920,7 @@ impl LibraVM {
     fn execute_user_transactions(
         &mut self,
         block_id: HashValue,
-        txn_block: Vec<SignedTransaction>,
+        txn_block: Vec<Arc<SignedTransaction>>,
         data_cache: &mut StateViewCache<'_>,
         state_view: &dyn StateView,
     ) -> VMResult<Vec<TransactionOutput>> {
@@ -876,7 +931,8 @@ impl LibraVM {
             signature_verified_block = txn_block
                 .into_par_iter()
                 .map(|txn| {
-                    txn.check_signature()
+                    unshare(txn)
+                        .check_signature()
                         .map_err(|_| VMStatus::new(StatusCode::INVALID_SIGNATURE))
                 })
                 .collect();
@@ -911,6 +967,12 @@ impl LibraVM {
     }
 }
 
+/// Returns the transaction shared with the other components, e.g., with mempool and consensus, or
+/// a copy of it if they still hold it, since checking its signature consumes it.
+fn unshare(txn: Arc<SignedTransaction>) -> SignedTransaction {
+    Arc::try_unwrap(txn).unwrap_or_else(|txn| txn.as_ref().clone())
+}
+
 pub(crate) fn discard_error_output(err: VMStatus) -> TransactionOutput {
     // Since this transaction will be discarded, no writeset will be included.
     TransactionOutput::new(
@@ -1055,6 +1117,27 @@ impl<'a> LibraVMInternals<'a> {
     }
 }
 
+/// Returns the epilogue to run for the transaction of `txn_data` along with its arguments: the
+/// sponsored epilogue takes the gas payer right after the sender.
+fn epilogue_function_and_args<'a>(
+    epilogue_name: &'a IdentStr,
+    sponsored_epilogue_name: &'a IdentStr,
+    txn_data: &TransactionMetadata,
+    args: Vec<Value>,
+) -> (&'a IdentStr, Vec<Value>) {
+    let sender = Value::transaction_argument_signer_reference(txn_data.sender);
+    match txn_data.gas_payer() {
+        None => (epilogue_name, std::iter::once(sender).chain(args).collect()),
+        Some(gas_payer) => (
+            sponsored_epilogue_name,
+            vec![sender, Value::address(gas_payer)]
+                .into_iter()
+                .chain(args)
+                .collect(),
+        ),
+    }
+}
+
 fn is_governance_txn(sender: AccountAddress, remote_cache: &dyn RemoteCache) -> bool {
     let association_capability_path =
         create_access_path(sender, association_capability_struct_tag());
@@ -1086,10 +1169,11 @@ fn normalize_gas_price(
 /// Transactions divided by transaction flow.
 /// Transaction flows are different across different types of transactions.
 pub enum TransactionBlock {
-    UserTransaction(Vec<SignedTransaction>),
+    UserTransaction(Vec<Arc<SignedTransaction>>),
     WaypointWriteSet(ChangeSet),
     BlockPrologue(BlockMetadata),
-    WriteSet(Box<SignedTransaction>),
+    BlockPrologueWithExtension(BlockMetadataWithExtension),
+    WriteSet(Arc<SignedTransaction>),
 }
 
 pub fn chunk_block_transactions(txns: Vec<Transaction>) -> Vec<TransactionBlock> {
@@ -1104,6 +1188,13 @@ pub fn chunk_block_transactions(txns: Vec<Transaction>) -> Vec<TransactionBlock>
                 }
                 blocks.push(TransactionBlock::BlockPrologue(data));
             }
+            Transaction::BlockMetadataWithExtension(data) => {
+                if !buf.is_empty() {
+                    blocks.push(TransactionBlock::UserTransaction(buf));
+                    buf = vec![];
+                }
+                blocks.push(TransactionBlock::BlockPrologueWithExtension(data));
+            }
             Transaction::WaypointWriteSet(cs) => {
                 if !buf.is_empty() {
                     blocks.push(TransactionBlock::UserTransaction(buf));
@@ -1117,7 +1208,7 @@ pub fn chunk_block_transactions(txns: Vec<Transaction>) -> Vec<TransactionBlock>
                         blocks.push(TransactionBlock::UserTransaction(buf));
                         buf = vec![];
                     }
-                    blocks.push(TransactionBlock::WriteSet(Box::new(txn)));
+                    blocks.push(TransactionBlock::WriteSet(txn));
                 } else {
                     buf.push(txn);
                 }
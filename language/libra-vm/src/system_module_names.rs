@@ -83,10 +83,18 @@ pub static SUCCESS_EPILOGUE_NAME: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("success_epilogue").unwrap());
 pub static FAILURE_EPILOGUE_NAME: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("failure_epilogue").unwrap());
+pub static SPONSORED_PROLOGUE_NAME: Lazy<Identifier> =
+    Lazy::new(|| Identifier::new("sponsored_prologue").unwrap());
+pub static SPONSORED_SUCCESS_EPILOGUE_NAME: Lazy<Identifier> =
+    Lazy::new(|| Identifier::new("sponsored_success_epilogue").unwrap());
+pub static SPONSORED_FAILURE_EPILOGUE_NAME: Lazy<Identifier> =
+    Lazy::new(|| Identifier::new("sponsored_failure_epilogue").unwrap());
 pub static BUMP_SEQUENCE_NUMBER_NAME: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("bump_sequence_number").unwrap());
 pub static BLOCK_PROLOGUE: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("block_prologue").unwrap());
+pub static BLOCK_PROLOGUE_WITH_EXTENSION: Lazy<Identifier> =
+    Lazy::new(|| Identifier::new("block_prologue_with_extension").unwrap());
 pub static DISTRIBUTE_TXN_FEES: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("distribute_transaction_fees").unwrap());
 
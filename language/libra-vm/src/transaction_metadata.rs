@@ -19,6 +19,9 @@ pub struct TransactionMetadata {
     pub gas_unit_price: GasPrice<GasCarrier>,
     pub transaction_size: AbstractMemorySize<GasCarrier>,
     pub expiration_time: Duration,
+    /// The address and the authentication key preimage of the gas payer of a sponsored
+    /// transaction
+    pub gas_payer: Option<(AccountAddress, Vec<u8>)>,
 }
 
 impl TransactionMetadata {
@@ -34,6 +37,15 @@ impl TransactionMetadata {
             gas_unit_price: GasPrice::new(txn.gas_unit_price()),
             transaction_size: AbstractMemorySize::new(txn.raw_txn_bytes_len() as u64),
             expiration_time: txn.expiration_time(),
+            gas_payer: txn
+                .authenticator()
+                .gas_payer()
+                .map(|(gas_payer, authenticator)| {
+                    (
+                        gas_payer,
+                        authenticator.authentication_key_preimage().into_vec(),
+                    )
+                }),
         }
     }
 
@@ -64,6 +76,16 @@ impl TransactionMetadata {
     pub fn expiration_time(&self) -> u64 {
         self.expiration_time.as_secs()
     }
+
+    pub fn gas_payer(&self) -> Option<AccountAddress> {
+        self.gas_payer.as_ref().map(|(gas_payer, _)| *gas_payer)
+    }
+
+    pub fn gas_payer_authentication_key_preimage(&self) -> Option<&[u8]> {
+        self.gas_payer
+            .as_ref()
+            .map(|(_, preimage)| preimage.as_slice())
+    }
 }
 
 impl Default for TransactionMetadata {
@@ -79,6 +101,7 @@ impl Default for TransactionMetadata {
             gas_unit_price: GasPrice::new(0),
             transaction_size: AbstractMemorySize::new(0),
             expiration_time: Duration::new(0, 0),
+            gas_payer: None,
         }
     }
 }
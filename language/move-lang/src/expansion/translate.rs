@@ -710,6 +710,7 @@ fn function(
 
 fn function_(context: &mut Context, pfunction: P::Function) -> (FunctionName, E::Function) {
     let P::Function {
+        attributes: _attributes,
         loc,
         name,
         visibility,
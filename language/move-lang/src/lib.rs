@@ -19,6 +19,7 @@ pub mod shared;
 pub mod test_utils;
 mod to_bytecode;
 pub mod typing;
+pub mod unit_test;
 
 use anyhow::anyhow;
 use codespan::{ByteIndex, Span};
@@ -67,7 +68,7 @@ pub fn move_check_no_report(
     deps: &[String],
     sender_opt: Option<Address>,
 ) -> anyhow::Result<(FilesSourceText, Errors)> {
-    let (files, pprog_and_comments_res) = parse_program(targets, deps)?;
+    let (files, pprog_and_comments_res) = parse_program(targets, deps, false)?;
     let pprog_res = pprog_and_comments_res.map(|(pprog, _)| pprog);
     match check_program(pprog_res, sender_opt) {
         Err(errors) => Ok((files, errors)),
@@ -85,7 +86,7 @@ pub fn move_compile(
     deps: &[String],
     sender_opt: Option<Address>,
 ) -> anyhow::Result<(FilesSourceText, Vec<CompiledUnit>)> {
-    let (files, pprog_and_comments_res) = parse_program(targets, deps)?;
+    let (files, pprog_and_comments_res) = parse_program(targets, deps, false)?;
     let pprog_res = pprog_and_comments_res.map(|(pprog, _)| pprog);
     match compile_program(pprog_res, sender_opt) {
         Err(errors) => errors::report_errors(files, errors),
@@ -99,7 +100,7 @@ pub fn move_compile_no_report(
     deps: &[String],
     sender_opt: Option<Address>,
 ) -> anyhow::Result<(FilesSourceText, Result<Vec<CompiledUnit>, Errors>)> {
-    let (files, pprog_and_comments_res) = parse_program(targets, deps)?;
+    let (files, pprog_and_comments_res) = parse_program(targets, deps, false)?;
     let pprog_res = pprog_and_comments_res.map(|(pprog, _)| pprog);
     Ok(match compile_program(pprog_res, sender_opt) {
         Err(errors) => (files, Err(errors)),
@@ -118,7 +119,7 @@ pub fn move_compile_to_expansion_no_report(
     FilesSourceText,
     Result<(expansion::ast::Program, CommentMap), Errors>,
 )> {
-    let (files, pprog_and_comments_res) = parse_program(targets, deps)?;
+    let (files, pprog_and_comments_res) = parse_program(targets, deps, false)?;
     let res = pprog_and_comments_res.and_then(|(pprog, comment_map)| {
         let (eprog, errors) = expansion::translate::program(pprog, sender_opt);
         check_errors(errors)?;
@@ -127,6 +128,28 @@ pub fn move_compile_to_expansion_no_report(
     Ok((files, res))
 }
 
+/// Move compile, keeping the test functions of the targets, i.e., the functions annotated with
+/// `#[test]`, which are dropped by the other entry points.
+///
+/// Returns the errors instead of reporting them to stderr, along with the test functions of the
+/// targets.
+pub fn move_compile_for_unit_tests(
+    targets: &[String],
+    deps: &[String],
+    sender_opt: Option<Address>,
+) -> anyhow::Result<(
+    FilesSourceText,
+    Result<(Vec<CompiledUnit>, Vec<unit_test::UnitTest>), Errors>,
+)> {
+    let (files, pprog_and_comments_res) = parse_program(targets, deps, true)?;
+    let res = pprog_and_comments_res.and_then(|(pprog, _)| {
+        let tests = unit_test::collect_unit_tests(&pprog.source_definitions, sender_opt)?;
+        let units = compile_program(Ok(pprog), sender_opt)?;
+        Ok((units, tests))
+    });
+    Ok((files, res))
+}
+
 //**************************************************************************************************
 // Utils
 //**************************************************************************************************
@@ -227,9 +250,12 @@ fn compile_program(
 // Parsing
 //**************************************************************************************************
 
+/// Parses the targets and their dependencies, dropping the test functions of the targets unless
+/// `keep_unit_tests` is set. The test functions of the dependencies are always dropped.
 fn parse_program(
     targets: &[String],
     deps: &[String],
+    keep_unit_tests: bool,
 ) -> anyhow::Result<(
     FilesSourceText,
     Result<(parser::ast::Program, CommentMap), Errors>,
@@ -261,6 +287,11 @@ fn parse_program(
         errors.append(&mut es);
     }
 
+    if !keep_unit_tests {
+        unit_test::strip_unit_tests(&mut source_definitions);
+    }
+    unit_test::strip_unit_tests(&mut lib_definitions);
+
     let res = if errors.is_empty() {
         Ok((
             parser::ast::Program {
@@ -155,12 +155,21 @@ pub enum FunctionBody_ {
 }
 pub type FunctionBody = Spanned<FunctionBody_>;
 
+#[derive(PartialEq, Debug, Clone, Copy)]
+// #[test] or #[expected_failure(abort_code = N)], marking a Move unit test
+pub enum FunctionAttribute_ {
+    Test,
+    ExpectedFailure(Option<u64>),
+}
+pub type FunctionAttribute = Spanned<FunctionAttribute_>;
+
 #[derive(PartialEq, Debug)]
 // (public?) foo<T1(: copyable?), ..., TN(: copyable?)>(x1: t1, ..., xn: tn): t1 * ... * tn {
 //    body
 //  }
 // (public?) native foo<T1(: copyable?), ..., TN(: copyable?)>(x1: t1, ..., xn: tn): t1 * ... * tn;
 pub struct Function {
+    pub attributes: Vec<FunctionAttribute>,
     pub loc: Loc,
     pub visibility: FunctionVisibility,
     pub signature: FunctionSignature,
@@ -996,6 +1005,7 @@ impl AstDebug for PragmaProperty_ {
 impl AstDebug for Function {
     fn ast_debug(&self, w: &mut AstWriter) {
         let Function {
+            attributes,
             loc: _loc,
             visibility,
             signature,
@@ -1003,6 +1013,9 @@ impl AstDebug for Function {
             name,
             body,
         } = self;
+        for attribute in attributes {
+            attribute.ast_debug(w);
+        }
         visibility.ast_debug(w);
         if let FunctionBody_::Native = &body.value {
             w.write("native ");
@@ -1021,6 +1034,18 @@ impl AstDebug for Function {
     }
 }
 
+impl AstDebug for FunctionAttribute_ {
+    fn ast_debug(&self, w: &mut AstWriter) {
+        match self {
+            FunctionAttribute_::Test => w.writeln("#[test]"),
+            FunctionAttribute_::ExpectedFailure(None) => w.writeln("#[expected_failure]"),
+            FunctionAttribute_::ExpectedFailure(Some(code)) => {
+                w.writeln(&format!("#[expected_failure(abort_code = {})]", code))
+            }
+        }
+    }
+}
+
 impl AstDebug for FunctionVisibility {
     fn ast_debug(&self, w: &mut AstWriter) {
         match self {
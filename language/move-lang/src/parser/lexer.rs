@@ -46,6 +46,7 @@ pub enum Tok {
     GreaterEqual,
     GreaterGreater,
     Caret,
+    NumSign,
     Abort,
     Acquires,
     As,
@@ -121,6 +122,7 @@ impl fmt::Display for Tok {
             GreaterEqual => ">=",
             GreaterGreater => ">>",
             Caret => "^",
+            NumSign => "#",
             Abort => "abort",
             Acquires => "acquires",
             As => "as",
@@ -409,6 +411,7 @@ fn find_token(file: &'static str, text: &str, start_offset: usize) -> Result<(To
         '/' => (Tok::Slash, 1),
         ';' => (Tok::Semicolon, 1),
         '^' => (Tok::Caret, 1),
+        '#' => (Tok::NumSign, 1),
         '{' => (Tok::LBrace, 1),
         '}' => (Tok::RBrace, 1),
         _ => {
@@ -1280,18 +1280,83 @@ fn parse_optional_type_parameters<'input>(
 // Functions
 //**************************************************************************************************
 
+// Parse a function attribute:
+//      Attribute =
+//          "test"
+//          | "expected_failure" ( "(" "abort_code" "=" <Num> ")" )?
+fn parse_function_attribute<'input>(
+    tokens: &mut Lexer<'input>,
+) -> Result<FunctionAttribute, Error> {
+    let start_loc = tokens.start_loc();
+    let attribute = match (tokens.peek(), tokens.content()) {
+        (Tok::IdentifierValue, "test") => {
+            tokens.advance()?;
+            FunctionAttribute_::Test
+        }
+        (Tok::IdentifierValue, "expected_failure") => {
+            tokens.advance()?;
+            let abort_code = if match_token(tokens, Tok::LParen)? {
+                consume_identifier(tokens, "abort_code")?;
+                consume_token(tokens, Tok::Equal)?;
+                if tokens.peek() != Tok::NumValue {
+                    return Err(unexpected_token_error(tokens, "an abort code"));
+                }
+                let code_loc = current_token_loc(tokens);
+                let code = parse_num(tokens)?;
+                if code > u128::from(std::u64::MAX) {
+                    return Err(vec![(
+                        code_loc,
+                        "Invalid abort code. Abort codes must fit into the type 'u64'".to_string(),
+                    )]);
+                }
+                consume_token(tokens, Tok::RParen)?;
+                Some(code as u64)
+            } else {
+                None
+            };
+            FunctionAttribute_::ExpectedFailure(abort_code)
+        }
+        _ => {
+            return Err(unexpected_token_error(
+                tokens,
+                "'test' or 'expected_failure'",
+            ))
+        }
+    };
+    let end_loc = tokens.previous_end_loc();
+    Ok(spanned(tokens.file_name(), start_loc, end_loc, attribute))
+}
+
+// Parse the attributes of a function:
+//      Attributes = ( "#" "[" Comma<Attribute> "]" )*
+fn parse_function_attributes<'input>(
+    tokens: &mut Lexer<'input>,
+) -> Result<Vec<FunctionAttribute>, Error> {
+    let mut attributes = vec![];
+    while match_token(tokens, Tok::NumSign)? {
+        attributes.extend(parse_comma_list(
+            tokens,
+            Tok::LBracket,
+            Tok::RBracket,
+            parse_function_attribute,
+            "a function attribute",
+        )?);
+    }
+    Ok(attributes)
+}
+
 // Parse a function declaration:
 //      FunctionDecl =
 //          <NativeFunctionDecl>
 //          | <MoveFunctionDecl>
 //      NativeFunctionDecl =
-//          <DocComments> "native" ( "public" )? "fun"
+//          <DocComments> <Attributes> "native" ( "public" )? "fun"
 //          <FunctionDefName> "(" Comma<Parameter> ")"
 //          (":" <Type>)?
 //          ("acquires" <ModuleAccess> ("," <ModuleAccess>)*)?
 //          ";"
 //      MoveFunctionDecl =
-//          <DocComments> ( "public" )? "fun"
+//          <DocComments> <Attributes> ( "public" )? "fun"
 //          <FunctionDefName> "(" Comma<Parameter> ")"
 //          (":" <Type>)?
 //          ("acquires" <ModuleAccess> ("," <ModuleAccess>)*)?
@@ -1300,12 +1365,25 @@ fn parse_optional_type_parameters<'input>(
 //          <Identifier> <OptionalTypeParameters>
 //
 // If the "allow_native" parameter is false, this will only accept Move
-// functions.
+// functions without attributes, i.e., the function of a script.
 fn parse_function_decl<'input>(
     tokens: &mut Lexer<'input>,
     allow_native: bool,
 ) -> Result<Function, Error> {
     tokens.match_doc_comments();
+    let attributes_loc = tokens.start_loc();
+    let attributes = parse_function_attributes(tokens)?;
+    if !allow_native && !attributes.is_empty() {
+        let loc = make_loc(
+            tokens.file_name(),
+            attributes_loc,
+            tokens.previous_end_loc(),
+        );
+        return Err(vec![(
+            loc,
+            "Function attributes can only be declared inside a module".to_string(),
+        )]);
+    }
     let start_loc = tokens.start_loc();
     // Record the source location of the "native" keyword (if there is one).
     let native_opt = if allow_native {
@@ -1393,6 +1471,7 @@ fn parse_function_decl<'input>(
 
     let loc = make_loc(tokens.file_name(), start_loc, tokens.previous_end_loc());
     Ok(Function {
+        attributes,
         loc,
         visibility,
         signature,
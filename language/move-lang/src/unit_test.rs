@@ -0,0 +1,154 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Move unit tests, i.e., the functions of a module annotated with `#[test]`, and optionally with
+//! `#[expected_failure(abort_code = N)]` when they are expected to abort.
+//!
+//! Test functions are only compiled for the unit test runner: every other entry point of the
+//! compiler drops them, so that they are never published.
+
+use crate::{
+    errors::*,
+    parser::ast::{
+        Definition, Function, FunctionAttribute_, FunctionBody_, ModuleDefinition, ModuleMember,
+    },
+    shared::{Address, Identifier},
+};
+use move_ir_types::location::*;
+
+/// A test function of a module.
+#[derive(Debug, Clone)]
+pub struct UnitTest {
+    pub loc: Loc,
+    pub module_address: Address,
+    pub module_name: String,
+    pub function_name: String,
+    /// `Some` if the test is expected to abort, with the expected abort code, if any.
+    pub expected_failure: Option<Option<u64>>,
+}
+
+impl UnitTest {
+    /// The name of the test, as displayed by the runner.
+    pub fn name(&self) -> String {
+        format!(
+            "{}::{}::{}",
+            self.module_address, self.module_name, self.function_name
+        )
+    }
+}
+
+fn is_unit_test(function: &Function) -> bool {
+    !function.attributes.is_empty()
+}
+
+/// Removes the test functions of the modules of `definitions`.
+pub fn strip_unit_tests(definitions: &mut Vec<Definition>) {
+    let strip_module = |module: &mut ModuleDefinition| {
+        module.members.retain(|member| match member {
+            ModuleMember::Function(function) => !is_unit_test(function),
+            _ => true,
+        })
+    };
+    for definition in definitions {
+        match definition {
+            Definition::Module(module) => strip_module(module),
+            Definition::Address(_, _, modules) => modules.iter_mut().for_each(strip_module),
+            Definition::Script(_) => (),
+        }
+    }
+}
+
+/// Collects the test functions of the modules of `definitions`, checking their attributes.
+pub fn collect_unit_tests(
+    definitions: &[Definition],
+    sender_opt: Option<Address>,
+) -> Result<Vec<UnitTest>, Errors> {
+    let mut tests = vec![];
+    let mut errors = vec![];
+    for definition in definitions {
+        let (address, modules) = match definition {
+            Definition::Module(module) => match sender_opt {
+                Some(sender) => (sender, std::slice::from_ref(module)),
+                // Reported by the expansion of the program
+                None => continue,
+            },
+            Definition::Address(_, address, modules) => (*address, &modules[..]),
+            Definition::Script(_) => continue,
+        };
+        for module in modules {
+            for member in &module.members {
+                if let ModuleMember::Function(function) = member {
+                    if is_unit_test(function) {
+                        match unit_test(address, module, function) {
+                            Ok(test) => tests.push(test),
+                            Err(error) => errors.push(error),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(tests)
+    } else {
+        Err(errors)
+    }
+}
+
+fn unit_test(
+    module_address: Address,
+    module: &ModuleDefinition,
+    function: &Function,
+) -> Result<UnitTest, Error> {
+    let mut is_test = false;
+    let mut expected_failure = None;
+    for attribute in &function.attributes {
+        match attribute.value {
+            FunctionAttribute_::Test if is_test => {
+                return Err(vec![(
+                    attribute.loc,
+                    "Duplicate 'test' attribute".to_string(),
+                )])
+            }
+            FunctionAttribute_::Test => is_test = true,
+            FunctionAttribute_::ExpectedFailure(_) if expected_failure.is_some() => {
+                return Err(vec![(
+                    attribute.loc,
+                    "Duplicate 'expected_failure' attribute".to_string(),
+                )])
+            }
+            FunctionAttribute_::ExpectedFailure(code) => expected_failure = Some(code),
+        }
+    }
+    let name = &function.name;
+    if !is_test {
+        let msg = format!(
+            "Invalid attributes of '{}'. Only test functions can be annotated, with a 'test' \
+             attribute",
+            name
+        );
+        return Err(vec![(name.loc(), msg)]);
+    }
+    let signature = &function.signature;
+    if !signature.type_parameters.is_empty() || !signature.parameters.is_empty() {
+        let msg = format!(
+            "Invalid test function '{}'. Test functions cannot take type parameters or parameters",
+            name
+        );
+        return Err(vec![(name.loc(), msg)]);
+    }
+    if let FunctionBody_::Native = function.body.value {
+        let msg = format!(
+            "Invalid test function '{}'. Test functions cannot be native",
+            name
+        );
+        return Err(vec![(name.loc(), msg)]);
+    }
+    Ok(UnitTest {
+        loc: function.loc,
+        module_address,
+        module_name: module.name.value().to_string(),
+        function_name: name.value().to_string(),
+        expected_failure,
+    })
+}
@@ -870,6 +870,53 @@ module LibraAccount {
         Transaction::assert(LibraTransactionTimeout::is_valid_transaction_timestamp(txn_expiration_time), 7);
     }
 
+    // The prologue of a sponsored transaction, whose gas is paid by `gas_payer` instead of its
+    // sender. The runtime runs this instead of prologue for sponsored transactions.
+    // It verifies:
+    // - The sender's and the gas payer's auth keys match the transaction's public keys
+    // - That the gas payer has enough balance to pay for all of the gas
+    // - That the sequence number matches the transaction's sequence key
+    fun sponsored_prologue<Token>(
+        sender: &signer,
+        txn_sequence_number: u64,
+        txn_public_key: vector<u8>,
+        gas_payer: address,
+        gas_payer_public_key: vector<u8>,
+        txn_gas_price: u64,
+        txn_max_gas_units: u64,
+        txn_expiration_time: u64,
+    ) acquires LibraAccount, Balance {
+        let transaction_sender = Signer::address_of(sender);
+
+        // Verify that the transaction sender's account exists
+        Transaction::assert(exists(transaction_sender), 5);
+        Transaction::assert(!account_is_frozen(transaction_sender), 0);
+        Transaction::assert(
+            Hash::sha3_256(txn_public_key) ==
+                *&borrow_global<LibraAccount>(transaction_sender).authentication_key,
+            2
+        );
+
+        // Verify that the gas payer's account exists, and that it signed the transaction
+        Transaction::assert(exists(gas_payer), 8);
+        Transaction::assert(!account_is_frozen(gas_payer), 9);
+        Transaction::assert(
+            Hash::sha3_256(gas_payer_public_key) ==
+                *&borrow_global<LibraAccount>(gas_payer).authentication_key,
+            2
+        );
+
+        // Check that the gas payer has enough balance for all of the gas
+        let max_transaction_fee = txn_gas_price * txn_max_gas_units;
+        Transaction::assert(balance<Token>(gas_payer) >= max_transaction_fee, 6);
+
+        // Check that the transaction sequence number matches the sequence number of the account
+        let sequence_number = borrow_global<LibraAccount>(transaction_sender).sequence_number;
+        Transaction::assert(txn_sequence_number >= sequence_number, 3);
+        Transaction::assert(txn_sequence_number == sequence_number, 4);
+        Transaction::assert(LibraTransactionTimeout::is_valid_transaction_timestamp(txn_expiration_time), 7);
+    }
+
     //  Collects gas and bumps the sequence number for executing a transaction
     fun epilogue<Token>(
         sender: address,
@@ -926,6 +973,64 @@ module LibraAccount {
         epilogue<Token>(sender, transaction_fee_amount, txn_sequence_number);
     }
 
+    // Collects gas from the gas payer and bumps the sequence number of the sender for executing a
+    // sponsored transaction
+    fun sponsored_epilogue<Token>(
+        sender: address,
+        gas_payer: address,
+        transaction_fee_amount: u64,
+        txn_sequence_number: u64,
+    ) acquires LibraAccount, Balance, AccountOperationsCapability {
+        // Bump the sequence number
+        let sender_account = borrow_global_mut<LibraAccount>(sender);
+        sender_account.sequence_number = txn_sequence_number + 1;
+
+        if (transaction_fee_amount > 0) {
+            let gas_payer_balance = borrow_global_mut<Balance<Token>>(gas_payer);
+            let transaction_fee = withdraw_from_balance(gas_payer, gas_payer_balance, transaction_fee_amount);
+            Libra::deposit(&mut borrow_global_mut<Balance<Token>>(0xFEE).coin, transaction_fee);
+        }
+    }
+
+    // The sponsored_success_epilogue is invoked instead of success_epilogue at the end of
+    // successfully executed sponsored transactions.
+    fun sponsored_success_epilogue<Token>(
+        account: &signer,
+        gas_payer: address,
+        txn_sequence_number: u64,
+        txn_gas_price: u64,
+        txn_max_gas_units: u64,
+        gas_units_remaining: u64
+    ) acquires LibraAccount, Balance, AccountOperationsCapability {
+        let sender = Signer::address_of(account);
+
+        // Charge the gas payer for gas
+        let transaction_fee_amount = txn_gas_price * (txn_max_gas_units - gas_units_remaining);
+        Transaction::assert(
+            balance<Token>(gas_payer) >= transaction_fee_amount,
+            6
+        );
+        sponsored_epilogue<Token>(sender, gas_payer, transaction_fee_amount, txn_sequence_number);
+    }
+
+    // The sponsored_failure_epilogue is invoked instead of failure_epilogue at the end of sponsored
+    // transactions when the transaction is aborted during execution or during
+    // `sponsored_success_epilogue`.
+    fun sponsored_failure_epilogue<Token>(
+        account: &signer,
+        gas_payer: address,
+        txn_sequence_number: u64,
+        txn_gas_price: u64,
+        txn_max_gas_units: u64,
+        gas_units_remaining: u64
+    ) acquires LibraAccount, Balance, AccountOperationsCapability {
+        let sender = Signer::address_of(account);
+        // Charge the gas payer for gas
+        let transaction_fee_amount = txn_gas_price * (txn_max_gas_units - gas_units_remaining);
+
+        sponsored_epilogue<Token>(sender, gas_payer, transaction_fee_amount, txn_sequence_number);
+    }
+
     // Bump the sequence number of an account. This function should be used only for bumping the sequence number when
     // a writeset transaction is committed.
     fun bump_sequence_number(signer: &signer) acquires LibraAccount {
@@ -2,6 +2,7 @@ address 0x0 {
 
 module LibraBlock {
     use 0x0::Event;
+    use 0x0::LibraBlockExtension;
     use 0x0::LibraSystem;
     use 0x0::LibraTimestamp;
     use 0x0::Signer;
@@ -55,10 +56,30 @@ module LibraBlock {
         Transaction::assert(Signer::address_of(vm) == 0x0, 33);
 
         process_block_prologue(vm,  round, timestamp, previous_block_votes, proposer);
+        LibraBlockExtension::clear_current(vm);
 
         // TODO(valerini): call regular reconfiguration here LibraSystem2::update_all_validator_info()
     }
 
+    // Set the metadata for the current block, along with the extension attached to it by its
+    // proposer. The runtime runs this instead of block_prologue for blocks with an extension.
+    public fun block_prologue_with_extension(
+        vm: &signer,
+        round: u64,
+        timestamp: u64,
+        previous_block_votes: vector<address>,
+        proposer: address,
+        extension_version: u8,
+        extension_data: vector<u8>
+    ) acquires BlockMetadata {
+        // Can only be invoked by LibraVM privilege.
+        Transaction::assert(Signer::address_of(vm) == 0x0, 33);
+        Transaction::assert(LibraBlockExtension::is_valid(extension_version, &extension_data), 5003);
+
+        process_block_prologue(vm,  round, timestamp, previous_block_votes, proposer);
+        LibraBlockExtension::set_current(vm, extension_version, extension_data);
+    }
+
     // Update the BlockMetadata resource with the new blockmetada coming from the consensus.
     fun process_block_prologue(
         vm: &signer,
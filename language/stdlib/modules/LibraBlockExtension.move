@@ -0,0 +1,102 @@
+address 0x0 {
+
+module LibraBlockExtension {
+    use 0x0::LibraConfig;
+    use 0x0::Signer;
+    use 0x0::Transaction;
+    use 0x0::Vector;
+
+    // Enables the metadata extension of blocks: the proposer of a block may attach a small,
+    // versioned blob to it, which is signed along with the rest of the block.
+    struct LibraBlockExtension {
+      // Maximum size in bytes of the data of an extension
+      max_size: u64,
+      // Versions of the extensions accepted by validators
+      supported_versions: vector<u8>,
+    }
+
+    // Extension attached to the current block, if any
+    resource struct CurrentExtension {
+      has_extension: bool,
+      version: u8,
+      data: vector<u8>,
+    }
+
+    // Not part of genesis: deployments using block metadata extensions call this from their own
+    // genesis, or from a write set transaction.
+    public fun initialize(config_account: &signer, max_size: u64, supported_versions: vector<u8>) {
+      Transaction::assert(Signer::address_of(config_account) == LibraConfig::default_config_address(), 1);
+
+      LibraConfig::publish_new_config<LibraBlockExtension>(
+          config_account,
+          LibraBlockExtension { max_size, supported_versions },
+      );
+      move_to(
+          config_account,
+          CurrentExtension { has_extension: false, version: 0, data: Vector::empty() }
+      );
+    }
+
+    public fun set(account: &signer, max_size: u64, supported_versions: vector<u8>) {
+      LibraConfig::set<LibraBlockExtension>(
+          account,
+          LibraBlockExtension { max_size, supported_versions }
+      );
+    }
+
+    public fun is_enabled(): bool {
+      LibraConfig::is_published<LibraBlockExtension>(LibraConfig::default_config_address())
+    }
+
+    // Whether an extension with the given version and data may be attached to a block
+    public fun is_valid(version: u8, data: &vector<u8>): bool {
+      if (!is_enabled()) return false;
+      let config = LibraConfig::get<LibraBlockExtension>();
+      Vector::length(data) <= config.max_size && Vector::contains(&config.supported_versions, &version)
+    }
+
+    // Record the extension of the current block. Called from the block prologue.
+    public fun set_current(vm: &signer, version: u8, data: vector<u8>) acquires CurrentExtension {
+      // Can only be invoked by LibraVM privilege.
+      Transaction::assert(Signer::address_of(vm) == 0x0, 33);
+
+      let current = borrow_global_mut<CurrentExtension>(LibraConfig::default_config_address());
+      current.has_extension = true;
+      current.version = version;
+      current.data = data;
+    }
+
+    // Forget the extension of the previous block, for blocks without one. Called from the block
+    // prologue.
+    public fun clear_current(vm: &signer) acquires CurrentExtension {
+      // Can only be invoked by LibraVM privilege.
+      Transaction::assert(Signer::address_of(vm) == 0x0, 33);
+
+      let addr = LibraConfig::default_config_address();
+      if (!exists<CurrentExtension>(addr)) return;
+      let current = borrow_global_mut<CurrentExtension>(addr);
+      current.has_extension = false;
+      current.version = 0;
+      current.data = Vector::empty();
+    }
+
+    // Whether the current block has an extension
+    public fun has_extension(): bool acquires CurrentExtension {
+      let addr = LibraConfig::default_config_address();
+      exists<CurrentExtension>(addr) && borrow_global<CurrentExtension>(addr).has_extension
+    }
+
+    // Version of the extension of the current block
+    public fun version(): u8 acquires CurrentExtension {
+      Transaction::assert(has_extension(), 1);
+      borrow_global<CurrentExtension>(LibraConfig::default_config_address()).version
+    }
+
+    // Data of the extension of the current block
+    public fun data(): vector<u8> acquires CurrentExtension {
+      Transaction::assert(has_extension(), 1);
+      *&borrow_global<CurrentExtension>(LibraConfig::default_config_address()).data
+    }
+}
+
+}
@@ -0,0 +1,97 @@
+address 0x0 {
+
+module ValidatorConnectivity {
+    use 0x0::LibraConfig;
+    use 0x0::Signer;
+    use 0x0::Transaction;
+    use 0x0::ValidatorConfig;
+    use 0x0::Vector;
+
+    // Preferences of the operator of a validator for the connections of the validator to the other
+    // validators, applied by the validator network of the validator on every reconfiguration.
+    struct Preferences {
+      validator: address,
+      // Region of the validator, e.g., b"us-west"
+      region: vector<u8>,
+      // Regions of the validators dialed first, the most preferred first
+      preferred_regions: vector<vector<u8>>,
+      // Number of connections to other validators beyond which no more are dialed, 0 for no limit
+      max_connections: u64,
+    }
+
+    struct ValidatorConnectivity {
+      preferences: vector<Preferences>,
+    }
+
+    resource struct CapabilityHolder {
+      cap: LibraConfig::ModifyConfigCapability<ValidatorConnectivity>,
+    }
+
+    // Not part of genesis: deployments tuning the topology of their validator network call this
+    // from their own genesis, or from a write set transaction.
+    public fun initialize(config_account: &signer) {
+      Transaction::assert(Signer::address_of(config_account) == LibraConfig::default_config_address(), 1);
+
+      let cap = LibraConfig::publish_new_config_with_capability<ValidatorConnectivity>(
+          config_account,
+          ValidatorConnectivity { preferences: Vector::empty() },
+      );
+      move_to(config_account, CapabilityHolder { cap })
+    }
+
+    // Replace the preferences of a validator. Only callable by the operator of the validator.
+    public fun set_preferences(
+        operator: &signer,
+        validator_account: address,
+        region: vector<u8>,
+        preferred_regions: vector<vector<u8>>,
+        max_connections: u64,
+    ) acquires CapabilityHolder {
+      Transaction::assert(
+          Signer::address_of(operator) == ValidatorConfig::get_operator(validator_account),
+          22
+      );
+
+      let config = LibraConfig::get<ValidatorConnectivity>();
+      remove_preferences_(&mut config.preferences, validator_account);
+      Vector::push_back(
+          &mut config.preferences,
+          Preferences { validator: validator_account, region, preferred_regions, max_connections }
+      );
+      set_config(config)
+    }
+
+    // Remove the preferences of a validator. Only callable by the operator of the validator.
+    public fun remove_preferences(operator: &signer, validator_account: address) acquires CapabilityHolder {
+      Transaction::assert(
+          Signer::address_of(operator) == ValidatorConfig::get_operator(validator_account),
+          22
+      );
+
+      let config = LibraConfig::get<ValidatorConnectivity>();
+      if (remove_preferences_(&mut config.preferences, validator_account)) set_config(config);
+    }
+
+    fun set_config(config: ValidatorConnectivity) acquires CapabilityHolder {
+      LibraConfig::set_with_capability<ValidatorConnectivity>(
+          &borrow_global<CapabilityHolder>(LibraConfig::default_config_address()).cap,
+          config
+      )
+    }
+
+    // Remove the preferences of the validator, returning whether it had any
+    fun remove_preferences_(preferences: &mut vector<Preferences>, validator: address): bool {
+      let i = 0;
+      let len = Vector::length(preferences);
+      while (i < len) {
+        if (Vector::borrow(preferences, i).validator == validator) {
+          _ = Vector::remove(preferences, i);
+          return true
+        };
+        i = i + 1;
+      };
+      false
+    }
+}
+
+}
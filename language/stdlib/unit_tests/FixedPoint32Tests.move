@@ -0,0 +1,28 @@
+address 0x1 {
+
+module FixedPoint32Tests {
+    use 0x0::FixedPoint32;
+    use 0x0::Transaction;
+
+    #[test]
+    fun multiply_and_divide() {
+        let half = FixedPoint32::create_from_rational(1, 2);
+        Transaction::assert(FixedPoint32::get_raw_value(copy half) == 1 << 31, 0);
+        Transaction::assert(FixedPoint32::multiply_u64(10, copy half) == 5, 1);
+        Transaction::assert(FixedPoint32::divide_u64(10, half) == 20, 2);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 16)]
+    fun create_from_rational_underflow() {
+        FixedPoint32::create_from_rational(1, 1 << 33);
+    }
+
+    #[test]
+    #[expected_failure]
+    fun divide_by_zero() {
+        FixedPoint32::divide_u64(1, FixedPoint32::create_from_raw_value(0));
+    }
+}
+
+}
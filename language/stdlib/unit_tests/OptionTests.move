@@ -0,0 +1,36 @@
+address 0x1 {
+
+module OptionTests {
+    use 0x0::Option;
+    use 0x0::Transaction;
+
+    #[test]
+    fun some_and_none() {
+        let some = Option::some(5);
+        Transaction::assert(Option::is_some(&some), 0);
+        Transaction::assert(Option::contains(&some, &5), 1);
+        Transaction::assert(*Option::borrow(&some) == 5, 2);
+
+        let none = Option::none<u64>();
+        Transaction::assert(Option::is_none(&none), 3);
+        Transaction::assert(Option::get_with_default(&none, 7) == 7, 4);
+    }
+
+    #[test]
+    fun fill_and_extract() {
+        let opt = Option::none();
+        Option::fill(&mut opt, 3);
+        Transaction::assert(Option::extract(&mut opt) == 3, 0);
+        Option::destroy_none(opt);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 99)]
+    fun fill_some() {
+        let opt = Option::some(3);
+        Option::fill(&mut opt, 4);
+        Option::destroy_some(opt);
+    }
+}
+
+}
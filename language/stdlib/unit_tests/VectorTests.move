@@ -0,0 +1,58 @@
+address 0x1 {
+
+module VectorTests {
+    use 0x0::Transaction;
+    use 0x0::Vector;
+
+    #[test]
+    fun push_back_and_pop_back() {
+        let v = Vector::empty();
+        Vector::push_back(&mut v, 1);
+        Vector::push_back(&mut v, 2);
+        Transaction::assert(Vector::length(&v) == 2, 0);
+        Transaction::assert(Vector::pop_back(&mut v) == 2, 1);
+        Transaction::assert(Vector::pop_back(&mut v) == 1, 2);
+        Vector::destroy_empty(v);
+    }
+
+    #[test]
+    fun reverse() {
+        let v = Vector::empty();
+        Vector::push_back(&mut v, 1);
+        Vector::push_back(&mut v, 2);
+        Vector::push_back(&mut v, 3);
+        Vector::reverse(&mut v);
+        Transaction::assert(*Vector::borrow(&v, 0) == 3, 0);
+        Transaction::assert(*Vector::borrow(&v, 1) == 2, 1);
+        Transaction::assert(*Vector::borrow(&v, 2) == 1, 2);
+    }
+
+    #[test]
+    fun index_of() {
+        let v = Vector::singleton(7);
+        Vector::push_back(&mut v, 8);
+        let (found, i) = Vector::index_of(&v, &8);
+        Transaction::assert(found && i == 1, 0);
+        let (found, _) = Vector::index_of(&v, &9);
+        Transaction::assert(!found, 1);
+    }
+
+    #[test]
+    fun remove_preserves_order() {
+        let v = Vector::singleton(1);
+        Vector::push_back(&mut v, 2);
+        Vector::push_back(&mut v, 3);
+        Transaction::assert(Vector::remove(&mut v, 0) == 1, 0);
+        Transaction::assert(*Vector::borrow(&v, 0) == 2, 1);
+        Transaction::assert(*Vector::borrow(&v, 1) == 3, 2);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 10)]
+    fun remove_out_of_bounds() {
+        let v = Vector::singleton(1);
+        Vector::remove(&mut v, 1);
+    }
+}
+
+}
@@ -1,34 +1,42 @@
 #! /bin/bash
 
-TRACE_PATH=$HOME/trace
+TRACE_DIR=$HOME/trace
 
-[ ! -e  "$TRACE_PATH" ] || rm -f "$TRACE_PATH"
-
-export MOVE_VM_TRACE=$TRACE_PATH
+[ ! -e  "$TRACE_DIR" ] || rm -rf "$TRACE_DIR"
+mkdir -p "$TRACE_DIR"
 
 echo "Rebuilding stdlib..."
 pushd ../../stdlib || exit 1
 cargo run
 popd || exit 1
 
+# Every test suite is traced to its own file, and their coverage is aggregated afterwards.
 echo "Running IR testsuite..."
 pushd ../../ir-testsuite || exit 1
-cargo test
+MOVE_VM_TRACE=$TRACE_DIR/ir-testsuite cargo test
 popd || exit 1
 
 echo "Running e2e testsuite..."
 pushd ../../e2e-tests || exit 1
-cargo test -- --skip account_universe
+MOVE_VM_TRACE=$TRACE_DIR/e2e-tests cargo test -- --skip account_universe
 popd || exit 1
 
 echo "Running Move testsuite..."
 pushd ../../move-lang || exit 1
-cargo test
+MOVE_VM_TRACE=$TRACE_DIR/move-lang cargo test
 cargo run --bin move-build -- ../stdlib/modules -m
 popd || exit 1
 
-echo "Converting trace file..."
-cargo run --bin move-trace-conversion -- -f "$TRACE_PATH" -o trace.mvcov
+echo "Converting trace files..."
+rm -f trace.mvcov
+for TRACE_PATH in "$TRACE_DIR"/*; do
+    [ -s "$TRACE_PATH" ] || continue
+    if [ -e trace.mvcov ]; then
+        cargo run --bin move-trace-conversion -- -f "$TRACE_PATH" -o trace.mvcov -u trace.mvcov
+    else
+        cargo run --bin move-trace-conversion -- -f "$TRACE_PATH" -o trace.mvcov
+    fi
+done
 
 echo "Producing coverage summaries..."
 cargo run --bin coverage-summaries -- -t trace.mvcov -s ../../stdlib/staged/stdlib.mv
@@ -37,10 +45,12 @@ echo "==========================================================================
 echo "You can check source coverage for a module by running:"
 echo "> cargo run --bin source-coverage -- -t trace.mvcov -b ../../move-lang/move_build_output/modules/<LOOK_FOR_MODULE_HERE>.mv -s ../../stdlib/modules/<SOURCE_MODULE>.move"
 echo "---------------------------------------------------------------------------"
-echo "You can can also getter a finer-grained coverage summary for each function by running:"
-echo "> cargo run --bin coverage-summaries -- -t trace.mvcov -s ../../stdlib/staged/stdlib.mv"
+echo "You can can also get a finer-grained coverage summary for each function, including the"
+echo "abort branches which are never taken, by running:"
+echo "> cargo run --bin coverage-summaries -- -t trace.mvcov -s ../../stdlib/staged/stdlib.mv -f"
+echo "---------------------------------------------------------------------------"
+echo "or a per-function report in CSV format by running:"
+echo "> cargo run --bin coverage-summaries -- -t trace.mvcov -s ../../stdlib/staged/stdlib.mv -c"
 echo "==========================================================================="
 
-unset MOVE_VM_TRACE
-
 echo "DONE"
@@ -8,6 +8,7 @@ use move_coverage::{
     summary::{self, ModuleSummary, ModuleSummaryOptions},
 };
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
     io::{self, Write},
     path::Path,
@@ -21,10 +22,11 @@ use vm::file_format::CompiledModule;
     about = "Creates a coverage summary from the trace data collected from the Move VM"
 )]
 struct Args {
-    /// The path to the coverage map or trace file
-    #[structopt(long = "input-trace-path", short = "t")]
-    pub input_trace_path: String,
-    /// Whether the passed-in file is a raw trace file or a serialized coverage map
+    /// The paths to the coverage maps or trace files, e.g., of several test suites, whose coverage
+    /// is aggregated
+    #[structopt(long = "input-trace-path", short = "t", required = true)]
+    pub input_trace_paths: Vec<String>,
+    /// Whether the passed-in files are raw trace files or serialized coverage maps
     #[structopt(long = "is-raw-trace", short = "r")]
     pub is_raw_trace_file: bool,
     /// The path to the module binary
@@ -78,15 +80,16 @@ fn format_human_summary<W: Write>(args: &Args, coverage_map: &CoverageMap, summa
 
     let mut total_covered = 0;
     let mut total_instructions = 0;
+    let mut total_uncovered_aborts = 0;
 
     for module in get_modules(&args).iter() {
         let mut summary_options = ModuleSummaryOptions::default();
         summary_options.summarize_function_coverage = args.summarize_functions;
-        let (total, covered) = ModuleSummary::new(summary_options, &module, coverage_map)
-            .summarize_human(summary_writer)
-            .unwrap();
+        let module_summary = ModuleSummary::new(summary_options, &module, coverage_map);
+        let (total, covered) = module_summary.summarize_human(summary_writer).unwrap();
         total_covered += covered;
         total_instructions += total;
+        total_uncovered_aborts += module_summary.uncovered_aborts();
     }
 
     writeln!(summary_writer, "+-------------------------+").unwrap();
@@ -97,10 +100,23 @@ fn format_human_summary<W: Write>(args: &Args, coverage_map: &CoverageMap, summa
     )
     .unwrap();
     writeln!(summary_writer, "+-------------------------+").unwrap();
+    if total_uncovered_aborts > 0 {
+        writeln!(
+            summary_writer,
+            "| Uncovered abort branches: {}",
+            total_uncovered_aborts
+        )
+        .unwrap();
+        writeln!(summary_writer, "+-------------------------+").unwrap();
+    }
 }
 
 fn format_csv_summary<W: Write>(args: &Args, coverage_map: &CoverageMap, summary_writer: &mut W) {
-    writeln!(summary_writer, "ModuleName,FunctionName,Covered,Uncovered").unwrap();
+    writeln!(
+        summary_writer,
+        "ModuleName,FunctionName,Covered,Uncovered,UncoveredAborts"
+    )
+    .unwrap();
 
     for module in get_modules(&args).iter() {
         let mut summary_options = ModuleSummaryOptions::default();
@@ -113,13 +129,23 @@ fn format_csv_summary<W: Write>(args: &Args, coverage_map: &CoverageMap, summary
 
 fn main() {
     let args = Args::from_args();
-    let input_trace_path = Path::new(&args.input_trace_path);
-
-    let coverage_map = if args.is_raw_trace_file {
-        CoverageMap::from_trace_file(&input_trace_path)
-    } else {
-        CoverageMap::from_binary_file(&input_trace_path)
-    };
+    let coverage_map = args
+        .input_trace_paths
+        .iter()
+        .map(|input_trace_path| {
+            let input_trace_path = Path::new(input_trace_path);
+            if args.is_raw_trace_file {
+                CoverageMap::from_trace_file(&input_trace_path)
+            } else {
+                CoverageMap::from_binary_file(&input_trace_path)
+            }
+        })
+        .fold(
+            CoverageMap {
+                module_maps: BTreeMap::new(),
+            },
+            CoverageMap::merge,
+        );
 
     let mut summary_writer: Box<dyn Write> = match &args.summary_path {
         Some(x) => {
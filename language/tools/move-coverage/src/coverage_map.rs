@@ -63,6 +63,28 @@ impl CoverageMap {
         empty_module_map.update_coverage_from_trace_file(filename)
     }
 
+    /// Adds the coverage of `other`, e.g., of another test suite, to this coverage map.
+    pub fn merge(mut self, other: CoverageMap) -> Self {
+        for (key, other_module_map) in other.module_maps {
+            let module_map = self.module_maps.entry(key).or_insert_with(|| {
+                ModuleCoverageMap::new(
+                    other_module_map.module_addr,
+                    other_module_map.module_name.clone(),
+                )
+            });
+            for (func_name, other_func_map) in other_module_map.function_maps {
+                let func_map = module_map
+                    .function_maps
+                    .entry(func_name)
+                    .or_insert_with(FunctionCoverage::new);
+                for (pc, count) in other_func_map {
+                    *func_map.entry(pc).or_insert(0) += count;
+                }
+            }
+        }
+        self
+    }
+
     /// Takes in a file containing a serialized coverage map and returns a coverage map.
     pub fn from_binary_file<P: AsRef<Path>>(filename: P) -> Self {
         let mut bytes = Vec::new();
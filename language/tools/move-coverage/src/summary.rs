@@ -8,9 +8,14 @@ use move_core_types::{identifier::Identifier, language_storage::ModuleId};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
+    fmt,
     io::{self, Write},
 };
-use vm::{access::ModuleAccess, CompiledModule};
+use vm::{
+    access::ModuleAccess,
+    file_format::{Bytecode, CodeOffset},
+    CompiledModule,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModuleSummaryOptions {
@@ -29,6 +34,16 @@ pub struct FunctionSummary {
     pub fn_is_native: bool,
     pub total_number_of_instructions: u64,
     pub covered_instructions: u64,
+    pub uncovered_aborts: Vec<UncoveredAbort>,
+}
+
+/// An `Abort` instruction which was never executed, i.e., an abort branch not covered by the tests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UncoveredAbort {
+    pub code_offset: CodeOffset,
+    /// The abort code, if it is loaded by the instruction right before the `Abort`, as for the
+    /// aborts of `assert`s and of `abort <constant>` expressions.
+    pub abort_code: Option<u64>,
 }
 
 impl Default for ModuleSummaryOptions {
@@ -62,21 +77,35 @@ impl ModuleSummary {
                         fn_is_native: true,
                         total_number_of_instructions: 0,
                         covered_instructions: 0,
+                        uncovered_aborts: vec![],
                     },
                     Some(code_unit) => {
+                        let function_map =
+                            module_map.and_then(|fn_map| fn_map.function_maps.get(&fn_name));
+                        let is_covered = |pc: usize| {
+                            function_map.map_or(false, |map| map.contains_key(&(pc as u64)))
+                        };
                         let total_number_of_instructions = code_unit.code.len() as u64;
-                        let covered_instructions = module_map
-                            .and_then(|fn_map| {
-                                fn_map
-                                    .function_maps
-                                    .get(&fn_name)
-                                    .map(|function_map| function_map.len())
+                        let covered_instructions =
+                            function_map.map_or(0, |function_map| function_map.len()) as u64;
+                        let uncovered_aborts = code_unit
+                            .code
+                            .iter()
+                            .enumerate()
+                            .filter(|(pc, instr)| **instr == Bytecode::Abort && !is_covered(*pc))
+                            .map(|(pc, _)| UncoveredAbort {
+                                code_offset: pc as CodeOffset,
+                                abort_code: match pc.checked_sub(1).map(|pc| &code_unit.code[pc]) {
+                                    Some(Bytecode::LdU64(abort_code)) => Some(*abort_code),
+                                    _ => None,
+                                },
                             })
-                            .unwrap_or(0) as u64;
+                            .collect();
                         FunctionSummary {
                             fn_is_native: false,
                             total_number_of_instructions,
                             covered_instructions,
+                            uncovered_aborts,
                         }
                     }
                 };
@@ -92,6 +121,14 @@ impl ModuleSummary {
         }
     }
 
+    /// Number of abort branches of the module not covered.
+    pub fn uncovered_aborts(&self) -> usize {
+        self.function_summaries
+            .values()
+            .map(|fn_summary| fn_summary.uncovered_aborts.len())
+            .sum()
+    }
+
     /// Summarizes the modules coverage in CSV format
     pub fn summarize_csv<W: Write>(&self, summary_writer: &mut W) -> io::Result<()> {
         let module = format!(
@@ -100,11 +137,11 @@ impl ModuleSummary {
             self.module_name.name()
         );
 
-        let mut format_line = |fn_name, covered, uncovered| {
+        let mut format_line = |fn_name, covered, uncovered, uncovered_aborts| {
             writeln!(
                 summary_writer,
-                "{},{},{},{}",
-                module, fn_name, covered, uncovered
+                "{},{},{},{},{}",
+                module, fn_name, covered, uncovered, uncovered_aborts
             )
         };
 
@@ -117,6 +154,7 @@ impl ModuleSummary {
                 fn_name,
                 fn_summary.covered_instructions,
                 fn_summary.total_number_of_instructions,
+                fn_summary.uncovered_aborts.len(),
             )?;
         }
 
@@ -162,6 +200,9 @@ impl ModuleSummary {
                     "\t\t% coverage: {:.2}",
                     fn_summary.percent_coverage()
                 )?;
+                for uncovered_abort in &fn_summary.uncovered_aborts {
+                    writeln!(summary_writer, "\t\t{}", uncovered_abort)?;
+                }
             }
         }
 
@@ -171,10 +212,32 @@ impl ModuleSummary {
             ">>> % Module coverage: {:.2}",
             covered_percentage
         )?;
+        let uncovered_aborts = self.uncovered_aborts();
+        if uncovered_aborts > 0 {
+            writeln!(
+                summary_writer,
+                ">>> Uncovered abort branches: {}",
+                uncovered_aborts
+            )?;
+        }
         Ok((total_instructions, total_covered))
     }
 }
 
+impl fmt::Display for UncoveredAbort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "uncovered abort branch: code offset {}",
+            self.code_offset
+        )?;
+        if let Some(abort_code) = self.abort_code {
+            write!(f, ", abort code {}", abort_code)?;
+        }
+        Ok(())
+    }
+}
+
 impl FunctionSummary {
     pub fn percent_coverage(&self) -> f64 {
         percent_coverage_for_counts(self.total_number_of_instructions, self.covered_instructions)
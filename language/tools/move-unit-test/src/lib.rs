@@ -0,0 +1,188 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+//! Runner of the unit tests of Move modules, i.e., their functions annotated with `#[test]`.
+//!
+//! The modules under test are compiled along with their test functions and published on top of
+//! the genesis state of a `FakeExecutor`. Every test function is then executed on its own, so that
+//! its writes are not seen by the other tests, and is metered with the gas schedule of the
+//! genesis state.
+//!
+//! A test passes if it returns, or, for a test annotated with
+//! `#[expected_failure(abort_code = N)]`, if it aborts with the code `N`. A test annotated with
+//! `#[expected_failure]` passes if its execution fails for any reason.
+
+use anyhow::{bail, Result};
+use language_e2e_tests::executor::FakeExecutor;
+use libra_types::{
+    on_chain_config::{OnChainConfig, VMConfig},
+    vm_error::StatusCode,
+};
+use libra_vm::data_cache::StateViewCache;
+use move_core_types::{
+    account_address::AccountAddress,
+    gas_schedule::{GasAlgebra, GasUnits},
+    identifier::Identifier,
+    language_storage::ModuleId,
+};
+use move_lang::{
+    compiled_unit::CompiledUnit, errors::report_errors_to_buffer, shared::Address,
+    unit_test::UnitTest,
+};
+use move_vm_runtime::{data_cache::TransactionDataCache, move_vm::MoveVM};
+use move_vm_types::gas_schedule::{zero_cost_schedule, CostStrategy};
+use std::fmt;
+use vm::{errors::VMResult, file_format::CompiledModule};
+
+/// The maximal amount of gas used by a test, unless specified otherwise.
+pub const DEFAULT_MAX_GAS: u64 = 1_000_000;
+
+/// The unit tests of a set of modules, along with the modules compiled with their test functions.
+pub struct TestPlan {
+    modules: Vec<CompiledModule>,
+    tests: Vec<UnitTest>,
+}
+
+impl TestPlan {
+    /// Compiles the unit tests of the modules of `targets`, which may depend on the modules of
+    /// `deps`, e.g., on the standard library.
+    pub fn compile(
+        targets: &[String],
+        deps: &[String],
+        sender_opt: Option<Address>,
+    ) -> Result<Self> {
+        let (files, units_and_tests) =
+            move_lang::move_compile_for_unit_tests(targets, deps, sender_opt)?;
+        let (units, tests) = match units_and_tests {
+            Ok(units_and_tests) => units_and_tests,
+            Err(errors) => bail!(
+                "{}",
+                String::from_utf8_lossy(&report_errors_to_buffer(files, errors))
+            ),
+        };
+        let modules = units
+            .into_iter()
+            .filter_map(|unit| match unit {
+                CompiledUnit::Module { module, .. } => Some(module),
+                CompiledUnit::Script { .. } => None,
+            })
+            .collect();
+        Ok(Self { modules, tests })
+    }
+
+    pub fn tests(&self) -> &[UnitTest] {
+        &self.tests
+    }
+
+    /// Only keeps the tests whose name contains `filter`.
+    pub fn filter(&mut self, filter: &str) {
+        self.tests.retain(|test| test.name().contains(filter))
+    }
+
+    /// Runs every test, with at most `max_gas` gas units each.
+    pub fn run(&self, max_gas: u64) -> Vec<TestResult> {
+        let mut executor = FakeExecutor::from_genesis_file();
+        for module in &self.modules {
+            executor.add_module(&module.self_id(), module);
+        }
+        let state = executor.get_state_view();
+        let gas_schedule = VMConfig::fetch_config(state)
+            .map(|config| config.gas_schedule)
+            .unwrap_or_else(zero_cost_schedule);
+        let move_vm = MoveVM::new();
+
+        self.tests
+            .iter()
+            .map(|test| {
+                let module_address = AccountAddress::new(test.module_address.to_u8());
+                let module_id = ModuleId::new(
+                    module_address,
+                    Identifier::new(test.module_name.as_str()).unwrap(),
+                );
+                let function_name = Identifier::new(test.function_name.as_str()).unwrap();
+
+                let data_cache = StateViewCache::new(state);
+                let mut data_store = TransactionDataCache::new(&data_cache);
+                let mut cost_strategy =
+                    CostStrategy::transaction(&gas_schedule, GasUnits::new(max_gas));
+                let result = move_vm.execute_function(
+                    &module_id,
+                    &function_name,
+                    vec![],
+                    vec![],
+                    module_address,
+                    &mut data_store,
+                    &mut cost_strategy,
+                );
+                TestResult {
+                    name: test.name(),
+                    gas_used: max_gas - cost_strategy.remaining_gas().get(),
+                    outcome: TestOutcome::of(test, result),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+}
+
+impl TestOutcome {
+    fn of(test: &UnitTest, result: VMResult<()>) -> Self {
+        match (test.expected_failure, result) {
+            (None, Ok(())) | (Some(None), Err(_)) => TestOutcome::Passed,
+            (Some(Some(code)), Err(status))
+                if status.major_status == StatusCode::ABORTED
+                    && status.sub_status == Some(code) =>
+            {
+                TestOutcome::Passed
+            }
+            (None, Err(status)) => TestOutcome::Failed(format!("failed with {}", status)),
+            (Some(None), Ok(())) => {
+                TestOutcome::Failed("expected to fail, but succeeded".to_string())
+            }
+            (Some(Some(code)), Ok(())) => TestOutcome::Failed(format!(
+                "expected to abort with code {}, but succeeded",
+                code
+            )),
+            (Some(Some(code)), Err(status)) => TestOutcome::Failed(format!(
+                "expected to abort with code {}, but failed with {}",
+                code, status
+            )),
+        }
+    }
+}
+
+/// The result of a unit test, along with the gas it used.
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub gas_used: u64,
+    pub outcome: TestOutcome,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == TestOutcome::Passed
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.outcome {
+            TestOutcome::Passed => {
+                write!(f, "test {} ... ok (gas used: {})", self.name, self.gas_used)
+            }
+            TestOutcome::Failed(reason) => write!(
+                f,
+                "test {} ... FAILED (gas used: {}): {}",
+                self.name, self.gas_used, reason
+            ),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, Result};
+use move_coverage::coverage_map::{output_map_to_file, CoverageMap};
+use move_lang::{command_line as cli, shared::Address};
+use move_unit_test::TestPlan;
+use std::{env, fs, path::PathBuf};
+use structopt::StructOpt;
+
+/// The environment variable read by the Move VM for the path of its execution trace.
+const MOVE_VM_TRACE_ENV_VAR: &str = "MOVE_VM_TRACE";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Move Unit Test",
+    about = "Runs the unit tests of Move modules, i.e., their functions annotated with #[test]"
+)]
+pub struct Options {
+    /// The source files of the modules to test
+    #[structopt(name = "PATH_TO_SOURCE_FILE")]
+    pub source_files: Vec<String>,
+
+    /// The library files needed as dependencies
+    #[structopt(
+        name = "PATH_TO_DEPENDENCY_FILE",
+        short = cli::DEPENDENCY_SHORT,
+        long = cli::DEPENDENCY,
+    )]
+    pub dependencies: Vec<String>,
+
+    /// Add the modules of the standard library to the dependencies
+    #[structopt(long = "stdlib")]
+    pub stdlib: bool,
+
+    /// The sender address for modules
+    #[structopt(
+        name = "ADDRESS",
+        short = cli::SENDER_SHORT,
+        long = cli::SENDER,
+        parse(try_from_str = cli::parse_address)
+    )]
+    pub sender: Option<Address>,
+
+    /// Only run the tests whose name contains this string
+    #[structopt(long = "filter", short = "f")]
+    pub filter: Option<String>,
+
+    /// The maximal amount of gas used by every test, `move_unit_test::DEFAULT_MAX_GAS` by default
+    #[structopt(long = "max-gas", short = "g", default_value = "1000000")]
+    pub max_gas: u64,
+
+    /// Save the coverage map of the tests to this file. Requires a debug build of the runner, in
+    /// which the Move VM traces its execution
+    #[structopt(long = "coverage", short = "c")]
+    pub coverage: Option<PathBuf>,
+}
+
+pub fn main() -> Result<()> {
+    let Options {
+        source_files,
+        mut dependencies,
+        stdlib,
+        sender,
+        filter,
+        max_gas,
+        coverage,
+    } = Options::from_args();
+
+    if stdlib {
+        dependencies.extend(stdlib::stdlib_files());
+    }
+    let mut plan = TestPlan::compile(&source_files, &dependencies, sender)?;
+    if let Some(filter) = &filter {
+        plan.filter(filter);
+    }
+
+    // The Move VM reads the path of its trace once, before its first execution
+    let trace_path = coverage
+        .as_ref()
+        .map(|coverage| coverage.with_extension("trace"));
+    if let Some(trace_path) = &trace_path {
+        let _ = fs::remove_file(trace_path);
+        env::set_var(MOVE_VM_TRACE_ENV_VAR, trace_path);
+    }
+
+    let results = plan.run(max_gas);
+    for result in &results {
+        println!("{}", result);
+    }
+    let failures = results.iter().filter(|result| !result.passed()).count();
+    println!(
+        "\ntest result: {} passed; {} failed",
+        results.len() - failures,
+        failures
+    );
+
+    if let (Some(coverage), Some(trace_path)) = (coverage, trace_path) {
+        if !trace_path.exists() {
+            bail!(
+                "No execution trace to compute the coverage from: the runner must be a debug build"
+            );
+        }
+        let coverage_map = CoverageMap::from_trace_file(&trace_path);
+        output_map_to_file(&coverage, &coverage_map)?;
+        fs::remove_file(&trace_path)?;
+    }
+
+    if failures > 0 {
+        bail!("{} of {} tests failed", failures, results.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use move_unit_test::{TestPlan, DEFAULT_MAX_GAS};
+use std::path::PathBuf;
+
+const STDLIB_UNIT_TESTS_DIR: &str = "../../stdlib/unit_tests";
+
+#[test]
+fn stdlib_unit_tests() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(STDLIB_UNIT_TESTS_DIR);
+    let targets = vec![path.to_str().unwrap().to_string()];
+
+    let plan = TestPlan::compile(&targets, &stdlib::stdlib_files(), None).unwrap();
+    assert!(!plan.tests().is_empty());
+    let results = plan.run(DEFAULT_MAX_GAS);
+    let failures: Vec<_> = results
+        .iter()
+        .filter(|result| !result.passed())
+        .map(|result| result.to_string())
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    assert!(results.iter().all(|result| result.gas_used > 0));
+}
@@ -3,6 +3,9 @@
 
 #![forbid(unsafe_code)]
 
+pub mod sponsored;
+pub mod write_set;
+
 use libra_types::{
     access_path::AccessPath,
     account_address::AccountAddress,
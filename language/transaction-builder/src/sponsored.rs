@@ -0,0 +1,196 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Construction of sponsored transactions, whose gas is paid by another account than their
+//! sender, e.g., by the operator of a wallet on behalf of its users.
+//!
+//! The sender and the gas payer both sign the raw transaction along with the address of the gas
+//! payer, so that the signature of the sender cannot be replayed with another gas payer:
+//! 1. the sender, or the gas payer, wraps the `RawTransaction` in an
+//!    `UnsignedSponsoredTransaction`;
+//! 2. the transaction, serialized with LCS, is carried to both parties, who sign it with
+//!    `sign_as_sender` and `sign_as_gas_payer`;
+//! 3. once both signatures are added, `into_signed_transaction` returns the transaction to
+//!    submit.
+//!
+//! The prologue checks the authentication keys of both accounts, and the epilogue withdraws the
+//! transaction fee from the balance of the gas payer.
+
+use anyhow::{ensure, format_err, Result};
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature},
+    hash::CryptoHash,
+    traits::{Signature, SigningKey},
+    HashValue,
+};
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{
+        authenticator::TransactionAuthenticator, RawTransaction, SignedTransaction,
+        SponsoredRawTransaction,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// A transaction whose gas is paid by `gas_payer`, collecting the signatures of its sender and of
+/// its gas payer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsignedSponsoredTransaction {
+    txn: SponsoredRawTransaction,
+    sender_authenticator: Option<TransactionAuthenticator>,
+    gas_payer_authenticator: Option<TransactionAuthenticator>,
+}
+
+impl UnsignedSponsoredTransaction {
+    pub fn new(raw_txn: RawTransaction, gas_payer: AccountAddress) -> Self {
+        Self {
+            txn: SponsoredRawTransaction::new(raw_txn, gas_payer),
+            sender_authenticator: None,
+            gas_payer_authenticator: None,
+        }
+    }
+
+    pub fn raw_txn(&self) -> &RawTransaction {
+        self.txn.raw_txn()
+    }
+
+    pub fn gas_payer(&self) -> AccountAddress {
+        self.txn.gas_payer()
+    }
+
+    /// The message signed by both the sender and the gas payer.
+    pub fn signing_message(&self) -> HashValue {
+        self.txn.hash()
+    }
+
+    /// Signs the transaction with the key of its sender.
+    pub fn sign_as_sender(
+        &mut self,
+        private_key: &Ed25519PrivateKey,
+        public_key: Ed25519PublicKey,
+    ) -> Result<()> {
+        let authenticator = self.authenticator(private_key, public_key)?;
+        self.sender_authenticator = Some(authenticator);
+        Ok(())
+    }
+
+    /// Signs the transaction with the key of its gas payer.
+    pub fn sign_as_gas_payer(
+        &mut self,
+        private_key: &Ed25519PrivateKey,
+        public_key: Ed25519PublicKey,
+    ) -> Result<()> {
+        let authenticator = self.authenticator(private_key, public_key)?;
+        self.gas_payer_authenticator = Some(authenticator);
+        Ok(())
+    }
+
+    fn authenticator(
+        &self,
+        private_key: &Ed25519PrivateKey,
+        public_key: Ed25519PublicKey,
+    ) -> Result<TransactionAuthenticator> {
+        let signature: Ed25519Signature = private_key.sign_message(&self.signing_message());
+        signature.verify(&self.signing_message(), &public_key)?;
+        Ok(TransactionAuthenticator::ed25519(public_key, signature))
+    }
+
+    /// Returns the transaction to submit, once signed by both the sender and the gas payer.
+    pub fn into_signed_transaction(self) -> Result<SignedTransaction> {
+        let sender_authenticator = self
+            .sender_authenticator
+            .ok_or_else(|| format_err!("Missing the signature of the sender"))?;
+        let gas_payer_authenticator = self
+            .gas_payer_authenticator
+            .ok_or_else(|| format_err!("Missing the signature of the gas payer"))?;
+        let gas_payer = self.txn.gas_payer();
+        let raw_txn = self.txn.into_raw_transaction();
+        ensure!(
+            raw_txn.sender() != gas_payer,
+            "The gas payer of a sponsored transaction cannot be its sender"
+        );
+        let txn = SignedTransaction::new_sponsored(
+            raw_txn,
+            sender_authenticator,
+            gas_payer,
+            gas_payer_authenticator,
+        );
+        Ok(txn.check_signature()?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_crypto::{PrivateKey, Uniform};
+    use libra_types::{account_config::LBR_NAME, transaction::Script};
+    use std::time::Duration;
+
+    fn raw_txn(sender: AccountAddress) -> RawTransaction {
+        RawTransaction::new_script(
+            sender,
+            0,
+            Script::new(vec![], vec![], vec![]),
+            1_000,
+            1,
+            LBR_NAME.to_owned(),
+            Duration::from_secs(u64::max_value()),
+        )
+    }
+
+    #[test]
+    fn test_sponsored_transaction() {
+        let sender_key = Ed25519PrivateKey::generate_for_testing();
+        let gas_payer_key = Ed25519PrivateKey::generate_for_testing();
+        let sender = AccountAddress::random();
+        let gas_payer = AccountAddress::random();
+
+        let mut txn = UnsignedSponsoredTransaction::new(raw_txn(sender), gas_payer);
+        txn.sign_as_sender(&sender_key, sender_key.public_key())
+            .unwrap();
+        assert!(txn.clone().into_signed_transaction().is_err());
+
+        // the transaction is carried to the gas payer
+        let bytes = lcs::to_bytes(&txn).unwrap();
+        let mut txn: UnsignedSponsoredTransaction = lcs::from_bytes(&bytes).unwrap();
+        txn.sign_as_gas_payer(&gas_payer_key, gas_payer_key.public_key())
+            .unwrap();
+        let signed_txn = txn.into_signed_transaction().unwrap();
+        assert_eq!(signed_txn.sender(), sender);
+        assert_eq!(signed_txn.gas_payer(), gas_payer);
+        assert_eq!(
+            signed_txn.authenticator().public_key_bytes(),
+            sender_key.public_key().to_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn test_signatures_bind_gas_payer() {
+        let sender_key = Ed25519PrivateKey::generate_for_testing();
+        let gas_payer_key = Ed25519PrivateKey::generate_for_testing();
+        let sender = AccountAddress::random();
+
+        let mut txn = UnsignedSponsoredTransaction::new(raw_txn(sender), AccountAddress::random());
+        txn.sign_as_sender(&sender_key, sender_key.public_key())
+            .unwrap();
+        txn.sign_as_gas_payer(&gas_payer_key, gas_payer_key.public_key())
+            .unwrap();
+
+        // the signatures cannot be replayed with another gas payer
+        let forged = SignedTransaction::new_sponsored(
+            raw_txn(sender),
+            txn.sender_authenticator.clone().unwrap(),
+            AccountAddress::random(),
+            txn.gas_payer_authenticator.clone().unwrap(),
+        );
+        assert!(forged.check_signature().is_err());
+
+        // the sender cannot pay its own gas
+        let mut txn = UnsignedSponsoredTransaction::new(raw_txn(sender), sender);
+        txn.sign_as_sender(&sender_key, sender_key.public_key())
+            .unwrap();
+        txn.sign_as_gas_payer(&sender_key, sender_key.public_key())
+            .unwrap();
+        assert!(txn.into_signed_transaction().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed construction of write-set transactions, which modify the state directly for governance
+//! and emergency operations, e.g., upgrading the stdlib or fixing a resource.
+//!
+//! Write-set transactions are sent by the association account, whose authentication key must be
+//! the one of a `MultiEd25519PublicKey`:
+//! 1. the operators build the `ChangeSet` with a `WriteSetBuilder`, and wrap it in an
+//!    `UnsignedWriteSetTransaction`;
+//! 2. they validate it against the current state with `dry_run`;
+//! 3. the transaction, serialized with LCS, is carried to every signer, who signs it offline with
+//!    `UnsignedWriteSetTransaction::sign`;
+//! 4. the signatures are added with `add_signature`, and once the threshold of the key is reached,
+//!    `into_signed_transaction` returns the transaction to submit.
+//!
+//! A `ChangeSet` built by a `WriteSetBuilder` can also be applied without being signed, as the
+//! `Transaction::WaypointWriteSet` of a genesis.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519Signature},
+    hash::CryptoHash,
+    multi_ed25519::{MultiEd25519PublicKey, MultiEd25519Signature},
+    traits::{Signature, SigningKey},
+    HashValue,
+};
+use libra_state_view::StateView;
+use libra_types::{
+    access_path::AccessPath,
+    account_address::AccountAddress,
+    account_config::{association_address, AccountResource},
+    transaction::{
+        authenticator::AuthenticationKey, ChangeSet, RawTransaction, SignedTransaction,
+        TransactionPayload,
+    },
+    write_set::{WriteOp, WriteSetMut},
+};
+use move_core_types::move_resource::MoveResource;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use stdlib::StdLibOptions;
+use vm::{access::ModuleAccess, file_format::CompiledModule};
+
+/// Builds the `ChangeSet` of a write-set transaction, rejecting two writes to the same access
+/// path.
+#[derive(Debug, Default)]
+pub struct WriteSetBuilder {
+    writes: BTreeMap<AccessPath, WriteOp>,
+}
+
+impl WriteSetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write(&mut self, access_path: AccessPath, op: WriteOp) -> Result<&mut Self> {
+        ensure!(
+            !self.writes.contains_key(&access_path),
+            "Duplicate write to {}",
+            access_path
+        );
+        self.writes.insert(access_path, op);
+        Ok(self)
+    }
+
+    /// Publish `module`, or replace the module with the same id.
+    pub fn publish_module(&mut self, module: &CompiledModule) -> Result<&mut Self> {
+        let mut bytes = vec![];
+        module
+            .serialize(&mut bytes)
+            .map_err(|e| anyhow!("Failed to serialize module {}: {:?}", module.self_id(), e))?;
+        self.write(
+            AccessPath::code_access_path(&module.self_id()),
+            WriteOp::Value(bytes),
+        )
+    }
+
+    /// Publish all the modules of the stdlib.
+    pub fn publish_stdlib(&mut self, option: StdLibOptions) -> Result<&mut Self> {
+        for module in stdlib::stdlib_modules(option) {
+            self.publish_module(module.as_inner())?;
+        }
+        Ok(self)
+    }
+
+    /// Store `resource` under `address`, replacing the existing one, if any.
+    pub fn set_resource<T: MoveResource + Serialize>(
+        &mut self,
+        address: AccountAddress,
+        resource: &T,
+    ) -> Result<&mut Self> {
+        self.write(
+            AccessPath::new(address, T::resource_path()),
+            WriteOp::Value(lcs::to_bytes(resource)?),
+        )
+    }
+
+    /// Remove the resource of type `T` stored under `address`.
+    pub fn remove_resource<T: MoveResource>(
+        &mut self,
+        address: AccountAddress,
+    ) -> Result<&mut Self> {
+        self.write(
+            AccessPath::new(address, T::resource_path()),
+            WriteOp::Deletion,
+        )
+    }
+
+    pub fn build(self) -> Result<ChangeSet> {
+        ensure!(!self.writes.is_empty(), "Empty write set");
+        let write_set = WriteSetMut::new(self.writes.into_iter().collect()).freeze()?;
+        Ok(ChangeSet::new(write_set, vec![]))
+    }
+}
+
+/// How a write of a write-set transaction changes the current state.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WriteEffect {
+    Create,
+    Modify,
+    Delete,
+    Unchanged,
+}
+
+/// A write-set transaction of the association account, collecting the signatures of the keys of
+/// its `MultiEd25519PublicKey`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsignedWriteSetTransaction {
+    raw_txn: RawTransaction,
+    public_key: MultiEd25519PublicKey,
+    signatures: Vec<(Ed25519Signature, u8)>,
+}
+
+impl UnsignedWriteSetTransaction {
+    /// Wraps `change_set` in the write-set transaction with `sequence_number` of the association
+    /// account, whose authentication key is the one of `public_key`.
+    pub fn new(
+        sequence_number: u64,
+        change_set: ChangeSet,
+        public_key: MultiEd25519PublicKey,
+    ) -> Self {
+        Self {
+            raw_txn: RawTransaction::new_change_set(
+                association_address(),
+                sequence_number,
+                change_set,
+            ),
+            public_key,
+            signatures: vec![],
+        }
+    }
+
+    pub fn raw_txn(&self) -> &RawTransaction {
+        &self.raw_txn
+    }
+
+    /// The message signed by every signer.
+    pub fn signing_message(&self) -> HashValue {
+        self.raw_txn.hash()
+    }
+
+    /// Signs the transaction with one of the keys of its `MultiEd25519PublicKey`, on the machine
+    /// of the signer.
+    pub fn sign(&self, private_key: &Ed25519PrivateKey) -> Ed25519Signature {
+        private_key.sign_message(&self.signing_message())
+    }
+
+    /// Adds the `signature` of the key at `index` in the `MultiEd25519PublicKey`.
+    pub fn add_signature(&mut self, index: u8, signature: Ed25519Signature) -> Result<()> {
+        let public_key = self
+            .public_key
+            .public_keys()
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("No public key at index {}", index))?;
+        ensure!(
+            self.signatures.iter().all(|(_, i)| *i != index),
+            "Already signed with the key at index {}",
+            index
+        );
+        signature.verify(&self.signing_message(), public_key)?;
+        self.signatures.push((signature, index));
+        Ok(())
+    }
+
+    /// Number of signatures still missing to reach the threshold of the key.
+    pub fn missing_signatures(&self) -> usize {
+        (*self.public_key.threshold() as usize).saturating_sub(self.signatures.len())
+    }
+
+    /// Returns the transaction to submit, once the threshold of the key is reached.
+    pub fn into_signed_transaction(self) -> Result<SignedTransaction> {
+        ensure!(
+            self.missing_signatures() == 0,
+            "{} signatures missing",
+            self.missing_signatures()
+        );
+        let signature = MultiEd25519Signature::new(self.signatures)?;
+        let txn = SignedTransaction::new_multisig(self.raw_txn, self.public_key, signature);
+        Ok(txn.check_signature()?.into_inner())
+    }
+
+    /// Checks the transaction against the current state in `state_view`, as the prologue of the
+    /// write-set transactions would, and returns the effect of every write.
+    pub fn dry_run(&self, state_view: &dyn StateView) -> Result<Vec<(AccessPath, WriteEffect)>> {
+        let sender = self.raw_txn.sender();
+        let account_blob = state_view
+            .get(&AccessPath::new(sender, AccountResource::resource_path()))?
+            .ok_or_else(|| anyhow!("No account at {}", sender))?;
+        let account: AccountResource = lcs::from_bytes(&account_blob)?;
+        ensure!(
+            account.sequence_number() == self.raw_txn.sequence_number(),
+            "Sequence number {} of the transaction, expected {}",
+            self.raw_txn.sequence_number(),
+            account.sequence_number()
+        );
+        ensure!(
+            account.authentication_key()
+                == AuthenticationKey::multi_ed25519(&self.public_key).as_ref(),
+            "The authentication key of {} is not the one of the signing keys",
+            sender
+        );
+
+        let change_set = match self.raw_txn.clone().into_payload() {
+            TransactionPayload::WriteSet(change_set) => change_set,
+            _ => bail!("Not a write-set transaction"),
+        };
+        let mut effects = vec![];
+        for (access_path, op) in change_set.write_set().iter() {
+            if access_path.path.first() == Some(&AccessPath::CODE_TAG) {
+                if let WriteOp::Value(bytes) = op {
+                    let module = CompiledModule::deserialize(bytes)
+                        .map_err(|e| anyhow!("Invalid module at {}: {:?}", access_path, e))?;
+                    ensure!(
+                        AccessPath::code_access_path(&module.self_id()) == *access_path,
+                        "Module {} published at {}",
+                        module.self_id(),
+                        access_path
+                    );
+                }
+            }
+            let effect = match (state_view.get(access_path)?, op) {
+                (None, WriteOp::Deletion) => bail!("Deletion of the missing {}", access_path),
+                (None, WriteOp::Value(_)) => WriteEffect::Create,
+                (Some(_), WriteOp::Deletion) => WriteEffect::Delete,
+                (Some(current), WriteOp::Value(bytes)) if current == *bytes => {
+                    WriteEffect::Unchanged
+                }
+                (Some(_), WriteOp::Value(_)) => WriteEffect::Modify,
+            };
+            effects.push((access_path.clone(), effect));
+        }
+        Ok(effects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_types::event::{EventHandle, EventKey};
+    use std::{collections::HashMap, convert::TryFrom};
+
+    #[derive(Default)]
+    struct FakeStateView(HashMap<AccessPath, Vec<u8>>);
+
+    impl StateView for FakeStateView {
+        fn get(&self, access_path: &AccessPath) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.get(access_path).cloned())
+        }
+
+        fn multi_get(&self, access_paths: &[AccessPath]) -> Result<Vec<Option<Vec<u8>>>> {
+            access_paths.iter().map(|ap| self.get(ap)).collect()
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    fn account(sequence_number: u64, authentication_key: Vec<u8>) -> AccountResource {
+        AccountResource::new(
+            sequence_number,
+            authentication_key,
+            None,
+            None,
+            EventHandle::new(EventKey::new([0; EventKey::LENGTH]), 0),
+            EventHandle::new(EventKey::new([1; EventKey::LENGTH]), 0),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_write_set_transaction() {
+        let private_keys: Vec<_> = (0..3u8)
+            .map(|i| Ed25519PrivateKey::try_from(&[i + 1; 32][..]).unwrap())
+            .collect();
+        let public_key =
+            MultiEd25519PublicKey::new(private_keys.iter().map(|k| k.into()).collect(), 2).unwrap();
+        let auth_key = AuthenticationKey::multi_ed25519(&public_key).to_vec();
+
+        let mut state = FakeStateView::default();
+        let account_path = AccessPath::new(association_address(), AccountResource::resource_path());
+        state.0.insert(
+            account_path.clone(),
+            lcs::to_bytes(&account(7, auth_key.clone())).unwrap(),
+        );
+
+        let mut builder = WriteSetBuilder::new();
+        builder
+            .set_resource(association_address(), &account(8, auth_key))
+            .unwrap();
+        assert!(builder
+            .remove_resource::<AccountResource>(association_address())
+            .is_err());
+        let change_set = builder.build().unwrap();
+
+        // the sequence number must be the one of the association account
+        let txn = UnsignedWriteSetTransaction::new(6, change_set.clone(), public_key.clone());
+        assert!(txn.dry_run(&state).is_err());
+        let mut txn = UnsignedWriteSetTransaction::new(7, change_set, public_key);
+        assert_eq!(
+            txn.dry_run(&state).unwrap(),
+            vec![(account_path, WriteEffect::Modify)]
+        );
+
+        // the transaction is carried to the offline signers
+        let bytes = lcs::to_bytes(&txn).unwrap();
+        let offline_txn: UnsignedWriteSetTransaction = lcs::from_bytes(&bytes).unwrap();
+        let signature = offline_txn.sign(&private_keys[2]);
+        assert!(txn.add_signature(0, signature.clone()).is_err());
+        txn.add_signature(2, signature.clone()).unwrap();
+        assert!(txn.add_signature(2, signature).is_err());
+        assert_eq!(txn.missing_signatures(), 1);
+        assert!(txn.clone().into_signed_transaction().is_err());
+
+        txn.add_signature(0, offline_txn.sign(&private_keys[0]))
+            .unwrap();
+        let signed_txn = txn.into_signed_transaction().unwrap();
+        assert_eq!(signed_txn.sender(), association_address());
+        assert_eq!(signed_txn.sequence_number(), 7);
+    }
+}
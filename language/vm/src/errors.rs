@@ -35,6 +35,8 @@ pub const ESEQUENCE_NUMBER_TOO_NEW: u64 = 4; // transaction sequence number is t
 pub const EACCOUNT_DOES_NOT_EXIST: u64 = 5; // transaction sender's account does not exist
 pub const ECANT_PAY_GAS_DEPOSIT: u64 = 6; // insufficient balance to pay for gas deposit
 pub const ETRANSACTION_EXPIRED: u64 = 7; // transaction expiration time exceeds block time.
+pub const EGAS_PAYER_DOES_NOT_EXIST: u64 = 8; // gas payer's account does not exist
+pub const EGAS_PAYER_FROZEN: u64 = 9; // gas payer's account is frozen
 
 /// Generic error codes. These codes don't have any special meaning for the VM, but they are useful
 /// conventions for debugging
@@ -86,6 +88,10 @@ pub fn convert_prologue_runtime_error(err: &VMStatus, txn_sender: &AccountAddres
                 VMStatus::new(StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE)
             }
             Some(ETRANSACTION_EXPIRED) => VMStatus::new(StatusCode::TRANSACTION_EXPIRED),
+            Some(EGAS_PAYER_DOES_NOT_EXIST) => {
+                VMStatus::new(StatusCode::GAS_PAYER_ACCOUNT_DOES_NOT_EXIST)
+            }
+            Some(EGAS_PAYER_FROZEN) => VMStatus::new(StatusCode::GAS_PAYER_ACCOUNT_FROZEN),
             // This should never happen...
             _ => err.clone(),
         }
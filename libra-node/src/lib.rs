@@ -4,3 +4,4 @@
 #![forbid(unsafe_code)]
 
 pub mod main_node;
+pub mod startup;
@@ -24,6 +24,10 @@ struct Args {
     #[structopt(short = "d", long)]
     /// Disable logging
     no_logging: bool,
+    #[structopt(long)]
+    /// Dial the seed peers of the node, report their reachability and protocol compatibility,
+    /// and exit instead of starting the node
+    check_seed_peers: bool,
 }
 
 #[global_allocator]
@@ -45,6 +49,11 @@ fn main() {
         libra_logger::init_struct_log_from_env().expect("Failed to initialize structured logging");
     }
 
+    if args.check_seed_peers {
+        let healthy = libra_node::main_node::check_seed_peers(&mut config);
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+
     if config.metrics.enabled {
         for network in &config.full_node_networks {
             let peer_id = config::peer_id(&network);
@@ -57,6 +66,21 @@ fn main() {
         }
     }
 
+    if let Some(push_gateway) = config.metrics.push_gateway.clone() {
+        let instance = config
+            .validator_network
+            .as_ref()
+            .or_else(|| config.full_node_networks.first())
+            .map(|network| config::peer_id(network).to_string())
+            .expect("Node has no network to identify it in the push gateway");
+        libra_metrics::push_gateway::push_metrics_periodically(
+            push_gateway,
+            "libra_node",
+            &instance,
+            config.metrics.push_interval_ms,
+        );
+    }
+
     let _node_handle = libra_node::main_node::setup_environment(&mut config);
 
     let term = Arc::new(AtomicBool::new(false));
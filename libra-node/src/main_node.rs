@@ -1,31 +1,56 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::startup::{StartupGraph, Subsystem};
 use backup_service::start_backup_service;
 use consensus::{consensus_provider::start_consensus, gen_consensus_reconfig_subscription};
-use debug_interface::node_debug_service::NodeDebugService;
-use executor::{db_bootstrapper::bootstrap_db_if_empty, Executor};
+use debug_interface::{
+    degradation::{self, Degradable, DegradationMode, ResourceLimits},
+    drain::{self, Drainable},
+    node_debug_service::NodeDebugService,
+};
+use executor::{db_bootstrapper::bootstrap_db_if_empty, shadow_vm::ShadowVM, Executor};
 use executor_types::ChunkExecutor;
 use futures::{channel::mpsc::channel, executor::block_on};
 use libra_config::{
-    config::{DiscoveryMethod, NetworkConfig, NodeConfig, RoleType},
+    config::{DiscoveryMethod, Identity, NetworkConfig, NodeConfig, RoleType},
+    network_id::NetworkId,
     utils::get_genesis_txn,
 };
+use libra_crypto::{x25519, ValidCryptoMaterial};
 use libra_json_rpc::bootstrap_from_config as bootstrap_rpc;
 use libra_logger::prelude::*;
 use libra_mempool::gen_mempool_reconfig_subscription;
 use libra_metrics::metric_server;
-use libra_secure_storage::config;
-use libra_types::waypoint::Waypoint;
+use libra_secure_storage::{config, BoxedStorage, CryptoStorage};
+use libra_types::{chain_id::ChainId, waypoint::Waypoint};
 use libra_vm::LibraVM;
 use libradb::LibraDB;
-use network::validator_network::network_builder::{AuthenticationMode, NetworkBuilder};
-use network_simple_onchain_discovery::{
-    gen_simple_discovery_reconfig_subscription, ConfigurationChangeListener,
+use network::{
+    address_book::{FileAddressBook, DEFAULT_ADDRESS_STALENESS},
+    connected_peers::ConnectedPeers,
+    connection_limits::InboundConnectionGate,
+    noise::{NoiseKeyProvider, NoiseKeyProviderError, NoiseKeylog},
+    preflight::SeedPeerChecker,
+    quota::QuotaLimits,
+    validator_network::network_builder::{AuthenticationMode, NetworkBuilder},
+    ConnectivityRequest, ProtocolId,
 };
+use network_simple_onchain_discovery::gen_simple_discovery_reconfig_subscription;
 use onchain_discovery::builder::OnchainDiscoveryBuilder;
 use state_synchronizer::StateSynchronizer;
-use std::{boxed::Box, collections::HashMap, net::ToSocketAddrs, sync::Arc, thread, time::Instant};
+use std::{
+    boxed::Box,
+    collections::HashMap,
+    convert::TryFrom,
+    net::ToSocketAddrs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 use storage_interface::{DbReader, DbReaderWriter};
 use storage_service::start_storage_service_with_db;
 use tokio::runtime::{Builder, Runtime};
@@ -33,6 +58,14 @@ use tokio::runtime::{Builder, Runtime};
 const AC_SMP_CHANNEL_BUFFER_SIZE: usize = 1_024;
 const INTRA_NODE_CHANNEL_BUFFER_SIZE: usize = 1;
 
+/// Application protocols offered to the peers of the additional listeners of a network, which
+/// take part in neither its consensus nor its discovery.
+const ADDITIONAL_LISTENER_PROTOCOLS: [ProtocolId; 3] = [
+    ProtocolId::StateSynchronizerDirectSend,
+    ProtocolId::MempoolDirectSend,
+    ProtocolId::HealthCheckerRpc,
+];
+
 pub struct LibraHandle {
     _rpc: Runtime,
     _mempool: Runtime,
@@ -43,8 +76,95 @@ pub struct LibraHandle {
     _backup: Runtime,
 }
 
-fn setup_chunk_executor(db: DbReaderWriter) -> Box<dyn ChunkExecutor> {
-    Box::new(Executor::<LibraVM>::new(db))
+/// Puts a network in maintenance mode once the node starts draining, so that it stops dialing
+/// new peers while the in-flight work winds down.
+struct NetworkDrain {
+    conn_mgr_reqs_tx: Mutex<channel::Sender<ConnectivityRequest>>,
+    in_maintenance: AtomicBool,
+}
+
+impl NetworkDrain {
+    fn try_enter_maintenance(&self) -> bool {
+        if self.in_maintenance.load(Ordering::Acquire) {
+            return true;
+        }
+        match self
+            .conn_mgr_reqs_tx
+            .lock()
+            .unwrap()
+            .try_send(ConnectivityRequest::SetMaintenanceMode(true))
+        {
+            Ok(()) => {
+                self.in_maintenance.store(true, Ordering::Release);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to put network in maintenance mode: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Drainable for NetworkDrain {
+    fn name(&self) -> &'static str {
+        "network"
+    }
+
+    fn begin_drain(&self) {
+        self.try_enter_maintenance();
+    }
+
+    fn is_drained(&self) -> bool {
+        // Retry in case the request could not be queued when draining started.
+        self.try_enter_maintenance()
+    }
+
+    fn end_drain(&self) {
+        if !self.in_maintenance.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        if let Err(e) = self
+            .conn_mgr_reqs_tx
+            .lock()
+            .unwrap()
+            .try_send(ConnectivityRequest::SetMaintenanceMode(false))
+        {
+            warn!("Failed to take network out of maintenance mode: {:?}", e);
+        }
+    }
+}
+
+/// Stops accepting new connections on the public network while the node sheds load.
+struct PublicNetworkAccepts {
+    gate: InboundConnectionGate,
+}
+
+impl Degradable for PublicNetworkAccepts {
+    fn mode(&self) -> DegradationMode {
+        DegradationMode::PausePublicAccepts
+    }
+
+    fn degrade(&self) {
+        self.gate.pause();
+    }
+
+    fn recover(&self) {
+        self.gate.resume();
+    }
+}
+
+/// The VM which the shadow VM of full nodes compares `LibraVM` against. A build validating an
+/// upgrade of the VM points it at the new VM, e.g., at a `libra-vm` dependency on its revision,
+/// renamed in `Cargo.toml`.
+type CandidateVM = LibraVM;
+
+fn setup_chunk_executor(db: DbReaderWriter, shadow_vm: bool) -> Box<dyn ChunkExecutor> {
+    if shadow_vm {
+        Box::new(Executor::<ShadowVM<LibraVM, CandidateVM>>::new(db))
+    } else {
+        Box::new(Executor::<LibraVM>::new(db))
+    }
 }
 
 fn setup_debug_interface(config: &NodeConfig) -> NodeDebugService {
@@ -60,31 +180,166 @@ fn setup_debug_interface(config: &NodeConfig) -> NodeDebugService {
     NodeDebugService::new(addr)
 }
 
+/// Fetches the network identity key of a network from the secure storage of its identity, see
+/// `Identity::FromStorage`.
+struct StorageKeyProvider {
+    storage: Mutex<BoxedStorage>,
+    key_name: String,
+}
+
+impl StorageKeyProvider {
+    fn from_config(config: &NetworkConfig) -> Option<Self> {
+        match &config.identity {
+            Identity::FromStorage(identity) => Some(Self {
+                storage: Mutex::new((&identity.backend).into()),
+                key_name: identity.key_name.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl NoiseKeyProvider for StorageKeyProvider {
+    fn private_key(&self) -> Result<x25519::PrivateKey, NoiseKeyProviderError> {
+        let key = self
+            .storage
+            .lock()
+            .unwrap()
+            .export_private_key(&self.key_name)
+            .map_err(anyhow::Error::new)?;
+        Ok(
+            x25519::PrivateKey::from_ed25519_private_bytes(&key.to_bytes())
+                .map_err(anyhow::Error::new)?,
+        )
+    }
+}
+
 // TODO(abhayb): Move to network crate (similar to consensus).
 pub fn setup_network(
     config: &mut NetworkConfig,
     role: RoleType,
     libra_db: Arc<dyn DbReader>,
     waypoint: Waypoint,
+    chain_id: Option<ChainId>,
 ) -> (Runtime, NetworkBuilder) {
-    let runtime = Builder::new()
-        .thread_name("network-")
+    // every network runs on its own runtime, so that a flood of traffic on one network doesn't
+    // starve the tasks of the other networks
+    let mut runtime_builder = Builder::new();
+    runtime_builder
+        .thread_name(format!("network-{}", config.network_id.as_str()))
         .threaded_scheduler()
-        .enable_all()
+        .enable_all();
+    if let Some(runtime_threads) = config.resource_quota.runtime_threads {
+        runtime_builder.core_threads(runtime_threads);
+    }
+    let runtime = runtime_builder
         .build()
         .expect("Failed to start runtime. Won't be able to start networking.");
 
+    let key_provider = StorageKeyProvider::from_config(config);
     let identity_key = config::identity_key(config);
     let peer_id = config::peer_id(config);
 
-    let mut network_builder = NetworkBuilder::new(
-        runtime.handle().clone(),
-        config.network_id.clone(),
-        peer_id,
-        role,
-        config.listen_address.clone(),
-    );
+    let executor = runtime.handle().clone();
+    let listen_addresses = vec![config.listen_address.clone()];
+    let mut network_builder = match (role, &config.network_id) {
+        (RoleType::Validator, NetworkId::Validator) => {
+            NetworkBuilder::validator_defaults(executor, peer_id, listen_addresses)
+        }
+        (RoleType::FullNode, NetworkId::Public) => {
+            NetworkBuilder::public_fullnode_defaults(executor, peer_id, listen_addresses)
+        }
+        (RoleType::FullNode, network_id)
+            if *network_id == NetworkId::vfn_network() && config.enable_remote_authentication =>
+        {
+            NetworkBuilder::vfn_defaults(executor, peer_id, listen_addresses)
+        }
+        _ => NetworkBuilder::new(
+            executor,
+            config.network_id.clone(),
+            peer_id,
+            role,
+            listen_addresses,
+        ),
+    };
+    if let (RoleType::FullNode, NetworkId::Public) = (role, &config.network_id) {
+        degradation::register(Arc::new(PublicNetworkAccepts {
+            gate: network_builder.inbound_connection_gate(),
+        }));
+    }
     network_builder.add_connection_monitoring();
+    if let (RoleType::Validator, Some(probe_interval_ms)) = (role, config.latency_probe_interval_ms)
+    {
+        network_builder.add_latency_measurement(Duration::from_millis(probe_interval_ms));
+    }
+    network_builder.dual_stack(config.dual_stack);
+    network_builder
+        .tcp_nodelay(config.tcp.nodelay)
+        .tcp_buffer_sizes(config.tcp.recv_buffer_size, config.tcp.send_buffer_size);
+    if config.tcp.reuse_port {
+        network_builder.tcp_reuse_port(true);
+    }
+    if let Some(device) = &config.tcp.bind_device {
+        network_builder.tcp_bind_device(device.clone());
+    }
+    if let Some(idle_ms) = config.tcp.keepalive_idle_ms {
+        network_builder.tcp_keepalive(
+            Duration::from_millis(idle_ms),
+            config.tcp.keepalive_interval_ms.map(Duration::from_millis),
+        );
+    }
+    network_builder.quota_limits(QuotaLimits {
+        max_inbound_connections: config.resource_quota.max_inbound_connections,
+        max_inbound_message_bytes: config.resource_quota.max_inbound_message_bytes,
+        max_inflight_rpc_bytes: config.resource_quota.max_inflight_rpc_bytes,
+    });
+    if let Some(max_inbound_connections) = config.resource_quota.max_inbound_connections {
+        network_builder.max_inbound_connections(max_inbound_connections);
+    }
+    if let Some(max_inbound_connections_per_ip) =
+        config.resource_quota.max_inbound_connections_per_ip
+    {
+        network_builder.max_inbound_connections_per_ip(max_inbound_connections_per_ip);
+    }
+    if let Some(max_outbound_bytes_per_sec) = config.resource_quota.max_outbound_bytes_per_sec {
+        network_builder.max_outbound_bytes_per_sec(max_outbound_bytes_per_sec);
+    }
+    if let Some(max_inbound_bytes_per_sec) = config.resource_quota.max_inbound_bytes_per_sec {
+        network_builder.max_inbound_bytes_per_sec(max_inbound_bytes_per_sec);
+    }
+    if let Some(noise_keylog) = NoiseKeylog::from_config(
+        config.enable_noise_keylog,
+        config.noise_keylog_file.as_deref(),
+        chain_id,
+    ) {
+        network_builder.noise_keylog(noise_keylog);
+    }
+    network_builder.enable_handshake_extensions(config.enable_handshake_extensions);
+
+    for listener in &config.additional_listeners {
+        // the listeners authenticate with the identity of the network
+        let key = x25519::PrivateKey::try_from(identity_key.to_bytes().as_slice())
+            .expect("identity key should be valid");
+        let (authentication_mode, trusted_peers) = if listener.enable_remote_authentication {
+            (
+                AuthenticationMode::Mutual(key),
+                listener.network_peers.peers.clone(),
+            )
+        } else {
+            (AuthenticationMode::ServerOnly(key), HashMap::new())
+        };
+        info!(
+            "network setup: additional listener for {:?} on {}",
+            listener.network_id, listener.listen_address
+        );
+        network_builder.add_listener(
+            listener.network_id.clone(),
+            listener.listen_address.clone(),
+            authentication_mode,
+            trusted_peers,
+            ADDITIONAL_LISTENER_PROTOCOLS.to_vec(),
+        );
+    }
 
     if config.enable_remote_authentication {
         // Sanity check seed peer addresses.
@@ -110,26 +365,90 @@ pub fn setup_network(
 
         network_builder
             .advertised_address(config.advertised_address.clone())
-            .authentication_mode(AuthenticationMode::Mutual(identity_key))
             .trusted_peers(trusted_peers)
             .seed_peers(seed_peers)
             .connectivity_check_interval_ms(config.connectivity_check_interval_ms)
-            // TODO:  Why is the connectivity manager related to remote_authentication?
-            .add_connectivity_manager();
+            .dial_stagger(config.dial_stagger_ms.map(Duration::from_millis));
+        if let Some(trusted_peers_file) = &config.trusted_peers_file {
+            network_builder.trusted_peers_file(trusted_peers_file.clone());
+        }
+        if let Some(address_book_file) = &config.address_book_file {
+            network_builder.address_book(
+                Box::new(FileAddressBook::new(address_book_file.clone())),
+                DEFAULT_ADDRESS_STALENESS,
+            );
+        }
+        // TODO:  Why is the connectivity manager related to remote_authentication?
+        network_builder.add_connectivity_manager();
+
+        if let Some(conn_mgr_reqs_tx) = network_builder.conn_mgr_reqs_tx() {
+            drain::register(Arc::new(NetworkDrain {
+                conn_mgr_reqs_tx: Mutex::new(conn_mgr_reqs_tx),
+                in_maintenance: AtomicBool::new(false),
+            }));
+        }
     } else {
         // Even if a network end-point operates without remote authentication, it might want to prove
         // its identity to another peer it connects to. For this, we use TCP + Noise but without
         // enforcing a trusted peers set.
-        network_builder
-            .authentication_mode(AuthenticationMode::ServerOnly(identity_key))
-            .advertised_address(config.advertised_address.clone());
+        network_builder.advertised_address(config.advertised_address.clone());
+    }
+    let authentication_mode: fn(x25519::PrivateKey) -> AuthenticationMode =
+        if config.enable_remote_authentication {
+            AuthenticationMode::Mutual
+        } else {
+            AuthenticationMode::ServerOnly
+        };
+    // a key in secure storage can be refreshed from the storage through the handle of the network
+    match key_provider {
+        Some(key_provider) => {
+            network_builder
+                .authentication_mode_from_provider(authentication_mode, Arc::new(key_provider))
+                .expect("Unable to read key");
+        }
+        None => {
+            network_builder.authentication_mode(authentication_mode(identity_key));
+        }
+    }
+    for advertised_address in &config.additional_advertised_addresses {
+        network_builder.advertised_address(advertised_address.clone());
+    }
+    if let Some(relay_address) = &config.relay_address {
+        // The node itself is still reachable without the relay.
+        if let Err(err) = network_builder.add_relay_server(relay_address.clone()) {
+            warn!(
+                "Not serving as a relay on network {}: {}",
+                config.network_id.as_str(),
+                err
+            );
+        }
     }
 
     match config.discovery_method {
         DiscoveryMethod::Gossip => {
+            let signing_key = config::discovery_signing_key(config);
+            if config.nat_port_mapping {
+                // The node is still reachable on its other addresses without a gateway.
+                if let Err(err) = network_builder.add_nat_port_mapping(None) {
+                    warn!(
+                        "Not mapping the listen port of network {} on the NAT gateway: {}",
+                        config.network_id.as_str(),
+                        err
+                    );
+                }
+            }
             network_builder
                 .discovery_interval_ms(config.discovery_interval_ms)
-                .add_gossip_discovery();
+                .discovery_metadata(config.discovery_metadata.clone())
+                .verify_advertised_addresses(config.verify_advertised_addresses)
+                .add_gossip_discovery(signing_key)
+                .unwrap_or_else(|err| {
+                    panic!(
+                        "Invalid configuration of network {}: {}",
+                        config.network_id.as_str(),
+                        err
+                    )
+                });
         }
         DiscoveryMethod::Onchain => {
             let (network_tx, discovery_events) =
@@ -148,12 +467,93 @@ pub fn setup_network(
             );
             onchain_discovery_builder.start(runtime.handle());
         }
+        DiscoveryMethod::Mdns => {
+            network_builder
+                .discovery_interval_ms(config.discovery_interval_ms)
+                .add_mdns_discovery()
+                .unwrap_or_else(|err| {
+                    panic!(
+                        "Invalid configuration of network {}: {}",
+                        config.network_id.as_str(),
+                        err
+                    )
+                });
+        }
         DiscoveryMethod::None => {}
     }
 
     (runtime, network_builder)
 }
 
+/// Application protocols the node runs on a network with the given role, which the seed peers of
+/// the network are expected to support.
+fn expected_protocols(role: RoleType, config: &NetworkConfig) -> Vec<ProtocolId> {
+    let mut protocols = vec![
+        ProtocolId::StateSynchronizerDirectSend,
+        ProtocolId::MempoolDirectSend,
+        ProtocolId::HealthCheckerRpc,
+    ];
+    if role == RoleType::Validator {
+        protocols.push(ProtocolId::ConsensusRpc);
+        protocols.push(ProtocolId::ConsensusDirectSend);
+    }
+    match config.discovery_method {
+        DiscoveryMethod::Gossip => {
+            protocols.push(ProtocolId::DiscoveryDirectSend);
+            protocols.push(ProtocolId::DialBackRpc);
+        }
+        DiscoveryMethod::Onchain => protocols.push(ProtocolId::OnchainDiscoveryRpc),
+        DiscoveryMethod::Mdns | DiscoveryMethod::None => {}
+    }
+    protocols
+}
+
+/// Dials the seed peers of every network of the node, prints the result for each of their
+/// addresses, and returns whether they are all reachable and compatible.
+pub fn check_seed_peers(node_config: &mut NodeConfig) -> bool {
+    let mut runtime = Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .expect("Failed to start runtime. Won't be able to check seed peers.");
+
+    let mut network_configs: Vec<(RoleType, &mut NetworkConfig)> = node_config
+        .full_node_networks
+        .iter_mut()
+        .map(|network_config| (RoleType::FullNode, network_config))
+        .collect();
+    if let Some(network_config) = node_config.validator_network.as_mut() {
+        network_configs.push((RoleType::Validator, network_config));
+    }
+
+    let mut healthy = true;
+    for (role, network_config) in network_configs {
+        let peer_id = config::peer_id(network_config);
+        let checker = SeedPeerChecker::new(
+            peer_id,
+            config::identity_key(network_config),
+            network_config.network_id.clone(),
+            expected_protocols(role, network_config),
+        );
+        println!(
+            "Seed peers of {} network {:?} (peer_id: {}):",
+            role,
+            network_config.network_id,
+            peer_id.short_str()
+        );
+        let checks =
+            runtime.block_on(checker.check_seed_peers(&network_config.seed_peers.seed_peers));
+        if checks.is_empty() {
+            println!("  none");
+        }
+        for check in checks {
+            healthy &= check.is_ok();
+            println!("  {}", check);
+        }
+    }
+    healthy
+}
+
 pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
     crash_handler::setup_panic_handler();
 
@@ -164,6 +564,15 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         .build_global()
         .expect("Building rayon global thread pool should work.");
 
+    let startup =
+        StartupGraph::for_node(Duration::from_millis(node_config.base.startup_timeout_ms));
+    debug!("Startup order: {:?}", startup.startup_order());
+    let wait_for_dependencies = |subsystem| {
+        startup
+            .wait_for_dependencies(subsystem)
+            .unwrap_or_else(|err| panic!("Startup failed: {}", err))
+    };
+
     let mut instant = Instant::now();
     let (libra_db, db_rw) = DbReaderWriter::wrap(
         LibraDB::open(
@@ -187,18 +596,29 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         "Storage service started in {} ms",
         instant.elapsed().as_millis()
     );
+    startup.mark_ready(Subsystem::Storage);
 
     instant = Instant::now();
-    let chunk_executor = setup_chunk_executor(db_rw.clone());
+    let shadow_vm = node_config.execution.shadow_vm;
+    if shadow_vm && node_config.base.role.is_validator() {
+        warn!("Not running the shadow VM on a validator");
+    }
+    let chunk_executor = setup_chunk_executor(
+        db_rw.clone(),
+        shadow_vm && !node_config.base.role.is_validator(),
+    );
     debug!(
         "ChunkExecutor setup in {} ms",
         instant.elapsed().as_millis()
     );
     let mut network_runtimes = vec![];
+    let mut network_builders = vec![];
     let mut state_sync_network_handles = vec![];
     let mut mempool_network_handles = vec![];
     let mut consensus_network_handles = None;
     let mut reconfig_subscriptions = vec![];
+    // shared by every network, and reported by the JSON-RPC endpoint
+    let connected_peers = ConnectedPeers::new();
 
     let (mempool_reconfig_subscription, mempool_reconfig_events) =
         gen_mempool_reconfig_subscription();
@@ -209,6 +629,7 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
     reconfig_subscriptions.push(consensus_reconfig_subscription);
 
     let waypoint = config::waypoint(&node_config.base.waypoint);
+    let chain_id = node_config.base.chain_id;
 
     // Gather all network configs into a single vector.
     // TODO:  consider explicitly encoding the role in the NetworkConfig
@@ -221,12 +642,21 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         network_configs.push((RoleType::Validator, network_config));
     }
 
-    // Instantiate every network and collect the requisite endpoints for state_sync, mempool, and consensus.
+    // Instantiate every network and collect the requisite endpoints for state_sync, mempool, and
+    // consensus. The networks only start dialing once every handler is registered and running,
+    // so that none of them misses the peers connected early.
+    wait_for_dependencies(Subsystem::Network);
     for (role, network_config) in network_configs {
         // Perform common instantiation steps
-        let (runtime, mut network_builder) =
-            setup_network(network_config, role, Arc::clone(&db_rw.reader), waypoint);
+        let (runtime, mut network_builder) = setup_network(
+            network_config,
+            role,
+            Arc::clone(&db_rw.reader),
+            waypoint,
+            chain_id,
+        );
         let peer_id = network_builder.peer_id();
+        network_builder.connected_peers(connected_peers.clone());
 
         // Create the endpoints to connect the Network to StateSynchronizer.
         let (state_sync_sender, state_sync_events) =
@@ -250,17 +680,14 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
                 }
 
                 // Set up to listen for network configuration changes from StateSync.
-                // TODO:  move this inside network_builder.
-                if let Some(conn_mgr_reqs_tx) = network_builder.conn_mgr_reqs_tx() {
+                if network_builder.conn_mgr_reqs_tx().is_some() {
                     let (simple_discovery_reconfig_subscription, simple_discovery_reconfig_rx) =
                         gen_simple_discovery_reconfig_subscription();
                     reconfig_subscriptions.push(simple_discovery_reconfig_subscription);
-                    let network_config_listener =
-                        ConfigurationChangeListener::new(conn_mgr_reqs_tx, RoleType::Validator);
-                    runtime
-                        .handle()
-                        .spawn(network_config_listener.start(simple_discovery_reconfig_rx));
-                };
+                    network_builder
+                        .add_onchain_discovery(simple_discovery_reconfig_rx)
+                        .expect("The ConnectivityManager is enabled");
+                }
 
                 consensus_network_handles = Some(consensus::network_interface::add_to_network(
                     &mut network_builder,
@@ -270,19 +697,21 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
             RoleType::FullNode => (),
         }
 
-        // Start the network and cache the runtime so it does not go out of scope.
-        // TODO:  move all 'start' commands to a second phase at the end of setup_environment.  Target is to have one pass to wire the pieces together and a second pass to start processing in an appropriate order.
-        let _listen_addr = network_builder.build();
+        // Cache the runtime so it does not go out of scope. The network is started once its
+        // handlers are.
         network_runtimes.push(runtime);
-        debug!("Network started for peer_id: {}", peer_id);
+        network_builders.push((network_config.network_id.clone(), network_builder));
     }
 
+    startup.mark_ready(Subsystem::Network);
+
     // TODO set up on-chain discovery network based on UpstreamConfig.fallback_network
     // and pass network handles to mempool/state sync
 
     // for state sync to send requests to mempool
     let (state_sync_to_mempool_sender, state_sync_requests) =
         channel(INTRA_NODE_CHANNEL_BUFFER_SIZE);
+    wait_for_dependencies(Subsystem::StateSync);
     let state_synchronizer = StateSynchronizer::bootstrap(
         state_sync_network_handles,
         state_sync_to_mempool_sender,
@@ -291,15 +720,15 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         &node_config,
         waypoint,
         reconfig_subscriptions,
+        connected_peers.clone(),
     );
     let (mp_client_sender, mp_client_events) = channel(AC_SMP_CHANNEL_BUFFER_SIZE);
 
-    let rpc_runtime = bootstrap_rpc(&node_config, libra_db.clone(), mp_client_sender);
-
     let mut consensus_runtime = None;
     let (consensus_to_mempool_sender, consensus_requests) = channel(INTRA_NODE_CHANNEL_BUFFER_SIZE);
 
     instant = Instant::now();
+    wait_for_dependencies(Subsystem::Mempool);
     let mempool = libra_mempool::bootstrap(
         node_config,
         Arc::clone(&db_rw.reader),
@@ -310,6 +739,39 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         mempool_reconfig_events,
     );
     debug!("Mempool started in {} ms", instant.elapsed().as_millis());
+    startup.mark_ready(Subsystem::Mempool);
+
+    // Every handler of the networks is running: start the networks.
+    for (network_id, network_builder) in network_builders {
+        let peer_id = network_builder.peer_id();
+        let _network_handle = network_builder.build().unwrap_or_else(|err| {
+            panic!(
+                "Invalid configuration of network {}: {}",
+                network_id.as_str(),
+                err
+            )
+        });
+        debug!("Network started for peer_id: {}", peer_id);
+    }
+
+    // State sync is ready once it caught up to the waypoint, which it does in the background.
+    let readiness = startup.readiness();
+    let state_sync_initialized = state_synchronizer.wait_until_initialized();
+    thread::spawn(move || {
+        block_on(state_sync_initialized).expect("State synchronizer initialization failure");
+        debug!("State synchronizer initialization complete.");
+        readiness.mark_ready(Subsystem::StateSync);
+    });
+
+    // JSON-RPC only binds once storage is open and mempool is able to accept transactions.
+    wait_for_dependencies(Subsystem::JsonRpc);
+    let rpc_runtime = bootstrap_rpc(
+        &node_config,
+        libra_db.clone(),
+        mp_client_sender,
+        connected_peers,
+    );
+    startup.mark_ready(Subsystem::JsonRpc);
 
     // Note: We need to start network provider before consensus, because the consensus
     // initialization is blocked on state synchronizer to sync to the initial root ledger
@@ -326,9 +788,7 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         // TODO: Note that we need the networking layer to be able to discover & connect to the
         // peers with potentially outdated network identity public keys.
         debug!("Wait until state synchronizer is initialized");
-        block_on(state_synchronizer.wait_until_initialized())
-            .expect("State synchronizer initialization failure");
-        debug!("State synchronizer initialization complete.");
+        wait_for_dependencies(Subsystem::Consensus);
 
         // Initialize and start consensus.
         instant = Instant::now();
@@ -340,12 +800,27 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
             consensus_to_mempool_sender,
             libra_db,
             consensus_reconfig_events,
+            None,
         ));
         debug!("Consensus started in {} ms", instant.elapsed().as_millis());
+        startup.mark_ready(Subsystem::Consensus);
     }
 
     let debug_if = setup_debug_interface(&node_config);
 
+    let resource_limits = &node_config.resource_limits;
+    if resource_limits.is_enabled() {
+        degradation::start_monitor(
+            ResourceLimits {
+                max_memory_bytes: resource_limits.max_memory_bytes,
+                max_disk_bytes: resource_limits.max_disk_bytes,
+                recovery_percent: resource_limits.recovery_percent,
+            },
+            node_config.storage.dir(),
+            Duration::from_millis(resource_limits.check_interval_ms),
+        );
+    }
+
     let metrics_port = node_config.debug_interface.metrics_server_port;
     let metric_host = node_config.debug_interface.address.clone();
     thread::spawn(move || metric_server::start_server(metric_host, metrics_port, false));
@@ -0,0 +1,281 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Explicit startup ordering of the node's subsystems.
+//!
+//! Every subsystem declares the subsystems it depends on in a [`StartupGraph`]. Before starting
+//! a subsystem, the node blocks until all its dependencies have signaled that they are ready,
+//! e.g., JSON-RPC only binds once storage is open and mempool is accepting transactions, and
+//! consensus only starts once state sync has caught up to the waypoint.
+//!
+//! The subsystems signal their readiness from wherever they become ready, in any order, and a
+//! subsystem waits on all its dependencies at once. The wait is bounded by the startup timeout of
+//! the node, past which it fails with the dependencies which never became ready.
+
+use anyhow::{bail, Result};
+use libra_logger::prelude::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How often to log the dependencies a subsystem is still waiting on.
+const READINESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Subsystem {
+    Storage,
+    Network,
+    StateSync,
+    Mempool,
+    JsonRpc,
+    Consensus,
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Set of subsystems which have signaled readiness, shared by everything participating in the
+/// startup sequence.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    inner: Arc<(Mutex<HashSet<Subsystem>>, Condvar)>,
+}
+
+impl Readiness {
+    pub fn mark_ready(&self, subsystem: Subsystem) {
+        let (ready, cvar) = &*self.inner;
+        ready.lock().unwrap().insert(subsystem);
+        cvar.notify_all();
+        info!("Subsystem {} is ready", subsystem);
+    }
+
+    pub fn is_ready(&self, subsystem: Subsystem) -> bool {
+        self.inner.0.lock().unwrap().contains(&subsystem)
+    }
+
+    /// Blocks until all of `subsystems` are ready, or fails with the ones still pending once
+    /// `timeout` elapsed.
+    fn wait_for(
+        &self,
+        waiting: Subsystem,
+        subsystems: &BTreeSet<Subsystem>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let (ready, cvar) = &*self.inner;
+        let mut ready = ready.lock().unwrap();
+        let start = Instant::now();
+        loop {
+            let pending: Vec<_> = subsystems
+                .iter()
+                .filter(|subsystem| !ready.contains(*subsystem))
+                .collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                bail!(
+                    "{} timed out after {} ms waiting on {:?}",
+                    waiting,
+                    elapsed.as_millis(),
+                    pending
+                );
+            }
+            info!(
+                "{} waiting on {:?} ({} ms elapsed)",
+                waiting,
+                pending,
+                elapsed.as_millis()
+            );
+            let wait = std::cmp::min(READINESS_LOG_INTERVAL, timeout - elapsed);
+            ready = cvar.wait_timeout(ready, wait).unwrap().0;
+        }
+    }
+}
+
+/// Dependency graph between subsystems. Edges point from a subsystem to the subsystems which
+/// need to be ready before it can start.
+pub struct StartupGraph {
+    dependencies: BTreeMap<Subsystem, BTreeSet<Subsystem>>,
+    readiness: Readiness,
+    timeout: Duration,
+}
+
+impl StartupGraph {
+    /// A graph whose subsystems wait at most `timeout` for their dependencies.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            dependencies: BTreeMap::new(),
+            readiness: Readiness::default(),
+            timeout,
+        }
+    }
+
+    /// The startup order of a node: storage comes first, the networks are wired to their
+    /// handlers before they start dialing, JSON-RPC binds once storage and mempool are up, and
+    /// consensus waits for state sync to catch up to the waypoint.
+    pub fn for_node(timeout: Duration) -> Self {
+        let mut graph = Self::new(timeout);
+        graph
+            .add_subsystem(Subsystem::Storage)
+            .add_dependency(Subsystem::Network, Subsystem::Storage)
+            .add_dependency(Subsystem::StateSync, Subsystem::Storage)
+            .add_dependency(Subsystem::StateSync, Subsystem::Network)
+            .add_dependency(Subsystem::Mempool, Subsystem::Storage)
+            .add_dependency(Subsystem::Mempool, Subsystem::Network)
+            .add_dependency(Subsystem::JsonRpc, Subsystem::Storage)
+            .add_dependency(Subsystem::JsonRpc, Subsystem::Mempool)
+            .add_dependency(Subsystem::Consensus, Subsystem::StateSync)
+            .add_dependency(Subsystem::Consensus, Subsystem::Mempool);
+        graph
+    }
+
+    pub fn add_subsystem(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.dependencies.entry(subsystem).or_default();
+        self
+    }
+
+    pub fn add_dependency(&mut self, subsystem: Subsystem, depends_on: Subsystem) -> &mut Self {
+        self.add_subsystem(depends_on);
+        self.dependencies
+            .entry(subsystem)
+            .or_default()
+            .insert(depends_on);
+        self
+    }
+
+    pub fn dependencies(&self, subsystem: Subsystem) -> impl Iterator<Item = &Subsystem> {
+        self.dependencies.get(&subsystem).into_iter().flatten()
+    }
+
+    /// Returns the subsystems in an order in which each one comes after all its dependencies.
+    /// Panics if the dependencies contain a cycle.
+    pub fn startup_order(&self) -> Vec<Subsystem> {
+        let mut order = Vec::with_capacity(self.dependencies.len());
+        let mut remaining = self.dependencies.clone();
+        while !remaining.is_empty() {
+            let started: BTreeSet<_> = order.iter().cloned().collect();
+            let next: Vec<_> = remaining
+                .iter()
+                .filter(|(_, deps)| deps.is_subset(&started))
+                .map(|(subsystem, _)| *subsystem)
+                .collect();
+            assert!(
+                !next.is_empty(),
+                "Startup dependencies contain a cycle among {:?}",
+                remaining.keys().collect::<Vec<_>>()
+            );
+            for subsystem in next {
+                remaining.remove(&subsystem);
+                order.push(subsystem);
+            }
+        }
+        order
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    pub fn mark_ready(&self, subsystem: Subsystem) {
+        self.readiness.mark_ready(subsystem);
+    }
+
+    /// Blocks until every dependency of `subsystem` has signaled readiness, or fails with the
+    /// dependencies still pending after the startup timeout.
+    pub fn wait_for_dependencies(&self, subsystem: Subsystem) -> Result<()> {
+        match self.dependencies.get(&subsystem) {
+            Some(dependencies) => self
+                .readiness
+                .wait_for(subsystem, dependencies, self.timeout),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn test_node_startup_order() {
+        let graph = StartupGraph::for_node(TIMEOUT);
+        let order = graph.startup_order();
+        let position = |s| order.iter().position(|x| *x == s).unwrap();
+        for subsystem in order.iter() {
+            for dependency in graph.dependencies(*subsystem) {
+                assert!(position(*dependency) < position(*subsystem));
+            }
+        }
+        assert_eq!(order[0], Subsystem::Storage);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cycle() {
+        let mut graph = StartupGraph::new(TIMEOUT);
+        graph
+            .add_dependency(Subsystem::Network, Subsystem::Storage)
+            .add_dependency(Subsystem::Storage, Subsystem::Network);
+        graph.startup_order();
+    }
+
+    #[test]
+    fn test_wait_for_dependencies() {
+        let graph = StartupGraph::for_node(TIMEOUT);
+        graph.mark_ready(Subsystem::Storage);
+        graph.mark_ready(Subsystem::Network);
+        // Mempool's dependencies are all ready
+        graph.wait_for_dependencies(Subsystem::Mempool).unwrap();
+
+        let readiness = graph.readiness();
+        let handle = thread::spawn(move || {
+            readiness.mark_ready(Subsystem::Mempool);
+        });
+        graph.wait_for_dependencies(Subsystem::JsonRpc).unwrap();
+        handle.join().unwrap();
+        assert!(graph.readiness().is_ready(Subsystem::Mempool));
+        assert!(!graph.readiness().is_ready(Subsystem::Consensus));
+    }
+
+    #[test]
+    fn test_out_of_order_readiness() {
+        let graph = StartupGraph::for_node(TIMEOUT);
+        graph.mark_ready(Subsystem::Storage);
+        graph.mark_ready(Subsystem::Network);
+
+        // Consensus' dependencies become ready in the opposite order of the startup order.
+        let readiness = graph.readiness();
+        let handle = thread::spawn(move || {
+            readiness.mark_ready(Subsystem::Mempool);
+            readiness.mark_ready(Subsystem::StateSync);
+        });
+        graph.wait_for_dependencies(Subsystem::Consensus).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let graph = StartupGraph::for_node(Duration::from_millis(50));
+        graph.mark_ready(Subsystem::Storage);
+        graph.mark_ready(Subsystem::Network);
+        graph.mark_ready(Subsystem::Mempool);
+
+        let error = graph
+            .wait_for_dependencies(Subsystem::Consensus)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Consensus timed out"));
+        assert!(error.contains("StateSync"));
+        assert!(!error.contains("Mempool"));
+    }
+}
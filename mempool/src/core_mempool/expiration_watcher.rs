@@ -0,0 +1,85 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Records the transactions which expired without being committed
+//!
+//! Once the timestamp of a block passes the expiration time of a transaction, the transaction
+//! can't be committed in any later block, so clients can be told that it will never commit rather
+//! than waiting for it. Only the latest `capacity` expired transactions are remembered.
+
+use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::Duration,
+};
+
+pub(crate) struct ExpirationWatcher {
+    capacity: usize,
+    // expired transactions of each account with the block timestamp they expired at, and the id
+    // of the record, by sequence number
+    expired: HashMap<AccountAddress, BTreeMap<u64, (u64, SignedTransaction, Duration)>>,
+    // records in the order they were added, oldest first, so that the oldest is forgotten first
+    order: VecDeque<(u64, AccountAddress, u64)>,
+    next_id: u64,
+}
+
+impl ExpirationWatcher {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            expired: HashMap::new(),
+            order: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// records that `txn` expired as of the block timestamp `block_time`, replacing any earlier
+    /// record of the same sender and sequence number
+    pub(crate) fn record(&mut self, txn: SignedTransaction, block_time: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.order.len() >= self.capacity {
+            if let Some((id, address, sequence_number)) = self.order.pop_front() {
+                self.forget(id, address, sequence_number);
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let (address, sequence_number) = (txn.sender(), txn.sequence_number());
+        self.order.push_back((id, address, sequence_number));
+        self.expired
+            .entry(address)
+            .or_default()
+            .insert(sequence_number, (id, txn, block_time));
+    }
+
+    /// removes the record `id`, unless it was replaced already
+    fn forget(&mut self, id: u64, address: AccountAddress, sequence_number: u64) {
+        if let Some(txns) = self.expired.get_mut(&address) {
+            if txns
+                .get(&sequence_number)
+                .map(|(record_id, _, _)| *record_id)
+                == Some(id)
+            {
+                txns.remove(&sequence_number);
+            }
+            if txns.is_empty() {
+                self.expired.remove(&address);
+            }
+        }
+    }
+
+    /// returns the expired transactions of `address` with the block timestamp they expired at,
+    /// ordered by sequence number
+    pub(crate) fn get(&self, address: &AccountAddress) -> Vec<(SignedTransaction, Duration)> {
+        self.expired
+            .get(address)
+            .map(|txns| {
+                txns.values()
+                    .map(|(_, txn, block_time)| (txn.clone(), *block_time))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
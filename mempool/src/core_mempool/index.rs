@@ -9,7 +9,7 @@ use std::{
     collections::{btree_set::Iter, BTreeMap, BTreeSet},
     iter::Rev,
     ops::Bound,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 pub type AccountTransactions = BTreeMap<u64, MempoolTransaction>;
@@ -247,30 +247,57 @@ impl TimelineIndex {
 /// e.g. transactions that can't be included in next block
 /// (because their sequence number is too high)
 /// we keep separate index to be able to efficiently evict them when Mempool is full
+///
+/// It's represented as Map <(Address, sequence_number), parking time>
 pub struct ParkingLotIndex {
-    data: BTreeSet<TxnPointer>,
+    data: BTreeMap<TxnPointer, Instant>,
 }
 
 impl ParkingLotIndex {
     pub(crate) fn new() -> Self {
         Self {
-            data: BTreeSet::new(),
+            data: BTreeMap::new(),
         }
     }
 
     /// add transaction to index
-    pub(crate) fn insert(&mut self, txn: &MempoolTransaction) {
-        self.data.insert(TxnPointer::from(txn));
+    /// returns whether the transaction was not parked yet
+    pub(crate) fn insert(&mut self, txn: &MempoolTransaction) -> bool {
+        let mut is_new = false;
+        self.data.entry(TxnPointer::from(txn)).or_insert_with(|| {
+            is_new = true;
+            Instant::now()
+        });
+        is_new
     }
 
     /// remove transaction from index
-    pub(crate) fn remove(&mut self, txn: &MempoolTransaction) {
-        self.data.remove(&TxnPointer::from(txn));
+    /// returns for how long the transaction was parked, if it was
+    pub(crate) fn remove(&mut self, txn: &MempoolTransaction) -> Option<Duration> {
+        self.data
+            .remove(&TxnPointer::from(txn))
+            .map(|parked_at| parked_at.elapsed())
+    }
+
+    /// returns for how long the transaction has been parked, if it is
+    pub(crate) fn parked_duration(&self, txn: &MempoolTransaction) -> Option<Duration> {
+        self.data
+            .get(&TxnPointer::from(txn))
+            .map(|parked_at| parked_at.elapsed())
     }
 
     /// returns random "non-ready" transaction (with highest sequence number for that account)
     pub(crate) fn pop(&mut self) -> Option<TxnPointer> {
-        self.data.iter().rev().next().cloned()
+        self.data.keys().rev().next().cloned()
+    }
+
+    /// returns the parked transactions of `address` with for how long they have been parked,
+    /// ordered by sequence number
+    pub(crate) fn account_txns(&self, address: AccountAddress) -> Vec<(u64, Duration)> {
+        self.data
+            .range((address, 0)..=(address, u64::max_value()))
+            .map(|((_, sequence_number), parked_at)| (*sequence_number, parked_at.elapsed()))
+            .collect()
     }
 
     pub(crate) fn size(&self) -> usize {
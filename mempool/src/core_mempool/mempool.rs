@@ -5,6 +5,7 @@
 //! agreed upon.
 use crate::{
     core_mempool::{
+        expiration_watcher::ExpirationWatcher,
         index::TxnPointer,
         transaction::{MempoolTransaction, TimelineState},
         transaction_store::TransactionStore,
@@ -18,11 +19,12 @@ use libra_logger::prelude::*;
 use libra_types::{
     account_address::AccountAddress,
     mempool_status::{MempoolStatus, MempoolStatusCode},
-    transaction::SignedTransaction,
+    transaction::{SharedTransactionStore, SignedTransaction},
 };
 use std::{
     cmp::max,
     collections::HashSet,
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -37,6 +39,8 @@ pub struct Mempool {
     // by consensus
     pub(crate) metrics_cache: TtlCache<(AccountAddress, u64), SystemTime>,
     pub system_transaction_timeout: Duration,
+    // transactions which expired without being committed, for clients waiting for them
+    expired_transactions: ExpirationWatcher,
 }
 
 impl Mempool {
@@ -48,6 +52,9 @@ impl Mempool {
             system_transaction_timeout: Duration::from_secs(
                 config.mempool.system_transaction_timeout_secs,
             ),
+            expired_transactions: ExpirationWatcher::new(
+                config.mempool.expired_transactions_capacity,
+            ),
         }
     }
 
@@ -82,6 +89,11 @@ impl Mempool {
             if sequence_number >= current_seq_number {
                 self.transactions
                     .reject_transaction(&sender, sequence_number);
+            } else {
+                // stale rejection of an already committed transaction: the cached sequence
+                // number is still accurate, and the ready transactions of the account rely on it
+                self.sequence_number_cache
+                    .insert(*sender, current_seq_number);
             }
         } else {
             // update current cached sequence number for account
@@ -143,7 +155,7 @@ impl Mempool {
         }
 
         let txn_info = MempoolTransaction::new(
-            txn,
+            SharedTransactionStore::global().intern(txn),
             expiration_time,
             gas_amount,
             rankin_score,
@@ -156,6 +168,84 @@ impl Mempool {
         status
     }
 
+    /// Used to add a batch of transactions of a single sender with consecutive sequence numbers
+    /// to the Mempool, each with its gas amount, ranking score and whether it is a governance
+    /// transaction
+    /// Either the whole batch is accepted, or none of it is and the status tells why
+    pub(crate) fn add_txn_batch(
+        &mut self,
+        txns: Vec<(SignedTransaction, u64, u64, bool)>,
+        db_sequence_number: u64,
+        timeline_state: TimelineState,
+    ) -> MempoolStatus {
+        let (sender, first_sequence_number) = match txns.first() {
+            Some((txn, ..)) => (txn.sender(), txn.sequence_number()),
+            None => {
+                return MempoolStatus::new(MempoolStatusCode::UnknownStatus)
+                    .with_message("empty transaction batch".to_string())
+            }
+        };
+        for (idx, (txn, ..)) in txns.iter().enumerate() {
+            if txn.sender() != sender {
+                return MempoolStatus::new(MempoolStatusCode::UnknownStatus).with_message(format!(
+                    "transaction {} of the batch is sent by {}, not {}",
+                    idx,
+                    txn.sender(),
+                    sender,
+                ));
+            }
+            if txn.sequence_number() != first_sequence_number + idx as u64 {
+                return MempoolStatus::new(MempoolStatusCode::InvalidSeqNumber).with_message(
+                    format!(
+                        "transaction {} of the batch has sequence number {}, expected {}",
+                        idx,
+                        txn.sequence_number(),
+                        first_sequence_number + idx as u64,
+                    ),
+                );
+            }
+        }
+
+        let cached_value = self.sequence_number_cache.get(&sender);
+        let sequence_number =
+            cached_value.map_or(db_sequence_number, |value| max(*value, db_sequence_number));
+        if first_sequence_number < sequence_number {
+            return MempoolStatus::new(MempoolStatusCode::InvalidSeqNumber).with_message(format!(
+                "batch sequence number is {}, current sequence number is  {}",
+                first_sequence_number, sequence_number,
+            ));
+        }
+        if let Some(status) = self.transactions.check_batch(
+            &sender,
+            first_sequence_number,
+            txns.len(),
+            sequence_number,
+        ) {
+            OP_COUNTERS.inc(&format!("insert_batch.{:?}", status));
+            return status;
+        }
+
+        // the checks above guarantee that each transaction of the batch is accepted
+        for (txn, gas_amount, rankin_score, is_governance_txn) in txns {
+            let status = self.add_txn(
+                txn,
+                gas_amount,
+                rankin_score,
+                db_sequence_number,
+                timeline_state,
+                is_governance_txn,
+            );
+            if status.code != MempoolStatusCode::Accepted {
+                error!(
+                    "[Mempool] transaction of a checked batch rejected: {:?}",
+                    status
+                );
+                return status;
+            }
+        }
+        MempoolStatus::new(MempoolStatusCode::Accepted)
+    }
+
     /// Fetches next block of transactions for consensus
     /// `batch_size` - size of requested block
     /// `seen_txns` - transactions that were sent to Consensus but were not committed yet
@@ -165,7 +255,7 @@ impl Mempool {
         &mut self,
         batch_size: u64,
         mut seen: HashSet<TxnPointer>,
-    ) -> Vec<SignedTransaction> {
+    ) -> Vec<Arc<SignedTransaction>> {
         let mut result = vec![];
         // Helper DS. Helps to mitigate scenarios where account submits several transactions
         // with increasing gas price (e.g. user submits transactions with sequence number 1, 2
@@ -229,6 +319,11 @@ impl Mempool {
         block
     }
 
+    /// Sets the maximum number of transactions in Mempool, see `MempoolConfig::capacity`
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.transactions.set_capacity(capacity);
+    }
+
     /// periodic core mempool garbage collection
     /// removes all expired transactions
     /// clears expired entries in metrics cache and sequence number cache
@@ -240,8 +335,29 @@ impl Mempool {
     }
 
     /// Garbage collection based on client-specified expiration time
+    /// The expired transactions are recorded, as they can't be committed in any later block
     pub(crate) fn gc_by_expiration_time(&mut self, block_time: Duration) {
-        self.transactions.gc_by_expiration_time(block_time);
+        for txn in self.transactions.gc_by_expiration_time(block_time) {
+            self.expired_transactions.record(txn, block_time);
+        }
+    }
+
+    /// Returns the parked transactions of `address`, i.e., waiting for transactions with lower
+    /// sequence numbers, with for how long they have been parked
+    pub(crate) fn get_parked_transactions(
+        &self,
+        address: AccountAddress,
+    ) -> Vec<(SignedTransaction, Duration)> {
+        self.transactions.get_parked_transactions(address)
+    }
+
+    /// Returns the transactions of `address` which expired without being committed, with the
+    /// block timestamp they expired at, ordered by sequence number
+    pub(crate) fn get_expired_transactions(
+        &self,
+        address: &AccountAddress,
+    ) -> Vec<(SignedTransaction, Duration)> {
+        self.expired_transactions.get(address)
     }
 
     /// Read `count` transactions from timeline since `timeline_id`
@@ -1,6 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod expiration_watcher;
 mod index;
 mod mempool;
 mod transaction;
@@ -2,11 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 #[derive(Clone)]
 pub struct MempoolTransaction {
-    pub txn: SignedTransaction,
+    /// The copy of the transaction shared with consensus, see [`SharedTransactionStore`].
+    ///
+    /// [`SharedTransactionStore`]: libra_types::transaction::SharedTransactionStore
+    pub txn: Arc<SignedTransaction>,
     // system expiration time of transaction. It should be removed from mempool by that time
     pub expiration_time: Duration,
     pub gas_amount: u64,
@@ -17,7 +20,7 @@ pub struct MempoolTransaction {
 
 impl MempoolTransaction {
     pub(crate) fn new(
-        txn: SignedTransaction,
+        txn: Arc<SignedTransaction>,
         expiration_time: Duration,
         gas_amount: u64,
         ranking_score: u64,
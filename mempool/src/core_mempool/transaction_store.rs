@@ -12,6 +12,7 @@ use crate::{
     OP_COUNTERS,
 };
 use anyhow::{format_err, Result};
+use debug_interface::prelude::*;
 use libra_config::config::MempoolConfig;
 use libra_logger::prelude::*;
 use libra_types::{
@@ -22,6 +23,7 @@ use libra_types::{
 use std::{
     collections::HashMap,
     ops::Bound,
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -45,6 +47,8 @@ pub struct TransactionStore {
     // configuration
     capacity: usize,
     capacity_per_user: usize,
+    parking_lot_capacity: usize,
+    parking_lot_capacity_per_user: usize,
 }
 
 impl TransactionStore {
@@ -65,15 +69,24 @@ impl TransactionStore {
             // configuration
             capacity: config.capacity,
             capacity_per_user: config.capacity_per_user,
+            parking_lot_capacity: config.parking_lot_capacity,
+            parking_lot_capacity_per_user: config.parking_lot_capacity_per_user,
         }
     }
 
+    /// sets the maximum number of transactions, e.g., to shrink Mempool under resource pressure
+    /// the transactions beyond the new capacity are kept, but no more are accepted until GC or
+    /// commits free up space
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
     /// fetch transaction by account address + sequence_number
     pub(crate) fn get(
         &self,
         address: &AccountAddress,
         sequence_number: u64,
-    ) -> Option<SignedTransaction> {
+    ) -> Option<Arc<SignedTransaction>> {
         if let Some(txn) = self
             .transactions
             .get(&address)
@@ -115,6 +128,12 @@ impl TransactionStore {
 
         self.clean_committed_transactions(&address, current_sequence_number);
 
+        if self.would_be_parked(&address, sequence_number, current_sequence_number) {
+            if let Some(status) = self.check_parking_lot_capacity(&address, 1) {
+                return status;
+            }
+        }
+
         if let Some(txns) = self.transactions.get_mut(&address) {
             // capacity check
             if txns.len() >= self.capacity_per_user {
@@ -137,6 +156,62 @@ impl TransactionStore {
         MempoolStatus::new(MempoolStatusCode::Accepted)
     }
 
+    /// checks if a batch of `count` transactions of `address` with consecutive sequence numbers,
+    /// starting at `first_sequence_number`, can be inserted as a whole (without inserting it)
+    /// returns the status to reject the batch with otherwise
+    pub(crate) fn check_batch(
+        &self,
+        address: &AccountAddress,
+        first_sequence_number: u64,
+        count: usize,
+        current_sequence_number: u64,
+    ) -> Option<MempoolStatus> {
+        let account_txns = self.transactions.get(address);
+        // replacing a transaction can fail halfway through the batch, so it can't overlap the
+        // transactions already in Mempool
+        let end_sequence_number = first_sequence_number + count as u64;
+        if account_txns.map_or(false, |txns| {
+            txns.range(first_sequence_number..end_sequence_number)
+                .next()
+                .is_some()
+        }) {
+            return Some(
+                MempoolStatus::new(MempoolStatusCode::InvalidUpdate).with_message(format!(
+                    "batch of sequence numbers {} to {} overlaps transactions in mempool",
+                    first_sequence_number,
+                    end_sequence_number - 1,
+                )),
+            );
+        }
+
+        if self.system_ttl_index.size() + count > self.capacity {
+            return Some(
+                MempoolStatus::new(MempoolStatusCode::MempoolIsFull).with_message(format!(
+                    "mempool size: {}, batch size: {}, capacity: {}",
+                    self.system_ttl_index.size(),
+                    count,
+                    self.capacity,
+                )),
+            );
+        }
+
+        let txns_len = account_txns.map_or(0, |txns| txns.range(current_sequence_number..).count());
+        if txns_len + count > self.capacity_per_user {
+            return Some(
+                MempoolStatus::new(MempoolStatusCode::TooManyTransactions).with_message(format!(
+                    "txns length: {} batch size: {} capacity per user: {}",
+                    txns_len, count, self.capacity_per_user,
+                )),
+            );
+        }
+
+        // either the whole batch is ready or the whole batch is parked
+        if self.would_be_parked(address, first_sequence_number, current_sequence_number) {
+            return self.check_parking_lot_capacity(address, count);
+        }
+        None
+    }
+
     fn track_indices(&self) {
         OP_COUNTERS.set("txn.system_ttl_index", self.system_ttl_index.size());
         OP_COUNTERS.set("txn.parking_lot_index", self.parking_lot_index.size());
@@ -157,6 +232,9 @@ impl TransactionStore {
                     .get_mut(&address)
                     .and_then(|txns| txns.remove(&sequence_number))
                 {
+                    if let Some(parked_duration) = self.parking_lot_index.parked_duration(&txn) {
+                        log_parking_lot_event("evicted", &txn, parked_duration);
+                    }
                     self.index_remove(&txn);
                 }
             }
@@ -190,6 +268,50 @@ impl TransactionStore {
         false
     }
 
+    /// check if a transaction would be parked upon insertion (without inserting it), i.e., if any
+    /// transaction between the current sequence number and its own is missing
+    fn would_be_parked(
+        &self,
+        address: &AccountAddress,
+        tx_sequence_number: u64,
+        curr_sequence_number: u64,
+    ) -> bool {
+        if tx_sequence_number <= curr_sequence_number {
+            return false;
+        }
+        let present = self.transactions.get(address).map_or(0, |txns| {
+            txns.range(curr_sequence_number..tx_sequence_number).count()
+        });
+        (present as u64) < tx_sequence_number - curr_sequence_number
+    }
+
+    /// returns the status to reject `count` transactions with if the parking lot can't take them
+    fn check_parking_lot_capacity(
+        &self,
+        address: &AccountAddress,
+        count: usize,
+    ) -> Option<MempoolStatus> {
+        let parked_txns = self.parking_lot_index.account_txns(*address).len();
+        if parked_txns + count > self.parking_lot_capacity_per_user {
+            return Some(
+                MempoolStatus::new(MempoolStatusCode::TooManyTransactions).with_message(format!(
+                    "parked txns length: {} parking lot capacity per user: {}",
+                    parked_txns, self.parking_lot_capacity_per_user,
+                )),
+            );
+        }
+        if self.parking_lot_index.size() + count > self.parking_lot_capacity {
+            return Some(
+                MempoolStatus::new(MempoolStatusCode::MempoolIsFull).with_message(format!(
+                    "parking lot size: {}, capacity: {}",
+                    self.parking_lot_index.size(),
+                    self.parking_lot_capacity,
+                )),
+            );
+        }
+        None
+    }
+
     /// check if transaction is already present in Mempool
     /// e.g. given request is update
     /// we allow increase in gas price to speed up process
@@ -228,6 +350,9 @@ impl TransactionStore {
             let mut sequence_number = current_sequence_number;
             while let Some(txn) = txns.get_mut(&sequence_number) {
                 self.priority_index.insert(txn);
+                if let Some(parked_duration) = self.parking_lot_index.remove(txn) {
+                    log_parking_lot_event("ready", txn, parked_duration);
+                }
 
                 if txn.timeline_state == TimelineState::NotReady {
                     self.timeline_index.insert(txn);
@@ -240,7 +365,9 @@ impl TransactionStore {
                 match txn.timeline_state {
                     TimelineState::Ready(_) => {}
                     _ => {
-                        self.parking_lot_index.insert(&txn);
+                        if self.parking_lot_index.insert(&txn) {
+                            OP_COUNTERS.inc("parking_lot.parked");
+                        }
                         parking_lot_txns += 1;
                     }
                 }
@@ -315,7 +442,7 @@ impl TransactionStore {
                 .get_mut(&address)
                 .and_then(|txns| txns.get(&sequence_number))
             {
-                batch.push((id, txn.txn.clone()));
+                batch.push((id, txn.txn.as_ref().clone()));
                 if let TimelineState::Ready(timeline_id) = txn.timeline_state {
                     last_timeline_id = timeline_id;
                 }
@@ -341,7 +468,7 @@ impl TransactionStore {
                         .get_mut(&address)
                         .and_then(|txns| txns.get(&sequence_number))
                     {
-                        return Some((timeline_id, txn.txn.clone()));
+                        return Some((timeline_id, txn.txn.as_ref().clone()));
                     }
                 }
                 None
@@ -359,11 +486,12 @@ impl TransactionStore {
     }
 
     /// GC old transactions based on client-specified expiration time
-    pub(crate) fn gc_by_expiration_time(&mut self, block_time: Duration) {
-        self.gc(block_time, false);
+    /// returns the expired transactions
+    pub(crate) fn gc_by_expiration_time(&mut self, block_time: Duration) -> Vec<SignedTransaction> {
+        self.gc(block_time, false)
     }
 
-    fn gc(&mut self, now: Duration, by_system_ttl: bool) {
+    fn gc(&mut self, now: Duration, by_system_ttl: bool) -> Vec<SignedTransaction> {
         let (index_name, index) = if by_system_ttl {
             ("gc.system_ttl_index", &mut self.system_ttl_index)
         } else {
@@ -371,26 +499,67 @@ impl TransactionStore {
         };
         OP_COUNTERS.inc(index_name);
 
+        let mut removed = vec![];
         for key in index.gc(now) {
             if let Some(txns) = self.transactions.get_mut(&key.address) {
                 // mark all following transactions as non-ready
-                for (_, t) in txns.range((Bound::Excluded(key.sequence_number), Bound::Unbounded)) {
+                for (_, t) in
+                    txns.range_mut((Bound::Excluded(key.sequence_number), Bound::Unbounded))
+                {
                     self.parking_lot_index.insert(&t);
                     self.priority_index.remove(&t);
                     self.timeline_index.remove(&t);
+                    // so that they are put back in the timeline once they are ready again
+                    if let TimelineState::Ready(_) = t.timeline_state {
+                        t.timeline_state = TimelineState::NotReady;
+                    }
                 }
                 if let Some(txn) = txns.remove(&key.sequence_number) {
                     let is_active = self.priority_index.contains(&txn);
                     let status = if is_active { "active" } else { "parked" };
                     OP_COUNTERS.inc(&format!("{}.{}", index_name, status));
+                    if let Some(parked_duration) = self.parking_lot_index.parked_duration(&txn) {
+                        log_parking_lot_event("expired", &txn, parked_duration);
+                    }
                     self.index_remove(&txn);
+                    removed.push(txn.txn.as_ref().clone());
                 }
             }
         }
         self.track_indices();
+        removed
     }
 
     pub(crate) fn iter_queue(&self) -> PriorityQueueIter {
         self.priority_index.iter()
     }
+
+    /// Returns the parked transactions of `address` with for how long they have been parked,
+    /// ordered by sequence number
+    pub(crate) fn get_parked_transactions(
+        &self,
+        address: AccountAddress,
+    ) -> Vec<(SignedTransaction, Duration)> {
+        self.parking_lot_index
+            .account_txns(address)
+            .into_iter()
+            .filter_map(|(sequence_number, parked_duration)| {
+                self.get(&address, sequence_number)
+                    .map(|txn| (txn.as_ref().clone(), parked_duration))
+            })
+            .collect()
+    }
+}
+
+/// records a parked transaction leaving the parking lot, either because it became ready, or
+/// because it was evicted or expired
+fn log_parking_lot_event(event: &'static str, txn: &MempoolTransaction, parked_duration: Duration) {
+    OP_COUNTERS.inc(&format!("parking_lot.{}", event));
+    OP_COUNTERS.observe_duration(&format!("parking_lot.{}.duration", event), parked_duration);
+    event!("mempool_parking_lot",
+        "event": event,
+        "sender": txn.get_sender().to_string(),
+        "sequence_number": txn.get_sequence_number(),
+        "parked_ms": parked_duration.as_millis() as u64,
+    );
 }
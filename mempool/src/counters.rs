@@ -58,3 +58,13 @@ pub static SHARED_MEMPOOL_TRANSACTION_BROADCAST: Lazy<IntCounter> = Lazy::new(||
     )
     .unwrap()
 });
+
+/// Counter of transactions rejected by the admission control of full nodes
+pub static ADMISSION_CONTROL_REJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_mempool_admission_control_rejections",
+        "Number of transactions rejected by the admission control of full nodes",
+        &["status"] // status code the transaction was rejected with
+    )
+    .unwrap()
+});
@@ -59,12 +59,15 @@
 /// This module provides mocks of shared mempool for tests.
 #[cfg(any(test, feature = "fuzzing"))]
 mod tests;
+#[cfg(any(test, feature = "fuzzing"))]
+pub use shared_mempool::fuzzing;
 pub use shared_mempool::{
     bootstrap, network,
     types::{
         gen_mempool_reconfig_subscription, CommitNotification, CommitResponse,
-        CommittedTransaction, ConsensusRequest, ConsensusResponse, MempoolClientSender,
-        SubmissionStatus, TransactionExclusion,
+        CommittedTransaction, ConsensusRequest, ConsensusResponse, ExpiredTransaction,
+        MempoolClientRequest, MempoolClientSender, ParkedTransaction, SubmissionStatus,
+        TransactionExclusion,
     },
 };
 #[cfg(feature = "fuzzing")]
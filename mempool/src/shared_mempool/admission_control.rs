@@ -0,0 +1,100 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Admission control of full nodes
+//!
+//! Full nodes forward the transactions in their mempool upstream, so anything they accept ends up
+//! being validated by validators. These cheap sanity checks against the local state reject
+//! transactions that are bound to fail before they are forwarded, so that full nodes can't be used
+//! to amplify spam towards validators.
+
+use crate::counters;
+use libra_config::config::AdmissionControlConfig;
+use libra_types::{
+    account_config::from_currency_code_string,
+    account_state::AccountState,
+    transaction::SignedTransaction,
+    vm_error::{StatusCode, VMStatus},
+};
+use std::convert::TryFrom;
+use storage_interface::DbReader;
+
+/// Checks that `txn`, whose sender is at `account_sequence_number`, may be accepted.
+pub(crate) fn admit(
+    config: &AdmissionControlConfig,
+    db: &dyn DbReader,
+    txn: &SignedTransaction,
+    account_sequence_number: u64,
+) -> Result<(), VMStatus> {
+    check(config, db, txn, account_sequence_number).map_err(|status| {
+        counters::ADMISSION_CONTROL_REJECTIONS
+            .with_label_values(&[&format!("{:?}", status.major_status)])
+            .inc();
+        status
+    })
+}
+
+fn check(
+    config: &AdmissionControlConfig,
+    db: &dyn DbReader,
+    txn: &SignedTransaction,
+    account_sequence_number: u64,
+) -> Result<(), VMStatus> {
+    if txn.gas_unit_price() < config.min_gas_unit_price {
+        return Err(
+            VMStatus::new(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND).with_message(format!(
+                "gas unit price {} is below the minimum {} accepted by this node",
+                txn.gas_unit_price(),
+                config.min_gas_unit_price
+            )),
+        );
+    }
+    if let Some(max_gap) = config.max_sequence_number_gap {
+        if txn.sequence_number() > account_sequence_number.saturating_add(max_gap) {
+            return Err(VMStatus::new(StatusCode::SEQUENCE_NUMBER_TOO_NEW));
+        }
+    }
+    if config.check_balance {
+        check_balance(txn, get_gas_balance(db, txn)?)?;
+    }
+    if config.verify_signature && txn.clone().check_signature().is_err() {
+        return Err(VMStatus::new(StatusCode::INVALID_SIGNATURE));
+    }
+    Ok(())
+}
+
+/// Returns the balance of the gas payer of `txn`, i.e., of its sender unless it is sponsored, in the
+/// currency it pays gas with.
+fn get_gas_balance(db: &dyn DbReader, txn: &SignedTransaction) -> Result<u64, VMStatus> {
+    let internal_error = |e: anyhow::Error| {
+        VMStatus::new(StatusCode::RESOURCE_DOES_NOT_EXIST).with_message(e.to_string())
+    };
+    let missing_account_status = if txn.is_sponsored() {
+        StatusCode::GAS_PAYER_ACCOUNT_DOES_NOT_EXIST
+    } else {
+        StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST
+    };
+    let blob = db
+        .get_latest_account_state(txn.gas_payer())
+        .map_err(internal_error)?
+        .ok_or_else(|| VMStatus::new(missing_account_status))?;
+    let currency_code = from_currency_code_string(txn.gas_currency_code())
+        .map_err(|_| VMStatus::new(StatusCode::CURRENCY_INFO_DOES_NOT_EXIST))?;
+    let balances = AccountState::try_from(&blob)
+        .and_then(|state| state.get_balance_resources(&[currency_code.clone()]))
+        .map_err(internal_error)?;
+    Ok(balances
+        .get(&currency_code)
+        .map_or(0, |balance| balance.coin()))
+}
+
+/// Checks that `balance` covers the maximum fee of `txn`.
+pub(crate) fn check_balance(txn: &SignedTransaction, balance: u64) -> Result<(), VMStatus> {
+    let max_fee = txn.max_gas_amount().checked_mul(txn.gas_unit_price());
+    match max_fee {
+        Some(max_fee) if max_fee <= balance => Ok(()),
+        _ => Err(VMStatus::new(
+            StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE,
+        )),
+    }
+}
@@ -6,27 +6,32 @@
 use crate::{
     core_mempool::{CoreMempool, TimelineState},
     counters,
-    network::{MempoolNetworkEvents, MempoolSyncMsg},
+    network::MempoolSyncMsg,
     shared_mempool::{
         tasks,
-        types::{notify_subscribers, SharedMempool, SharedMempoolNotification},
+        types::{
+            notify_subscribers, MempoolClientRequest, SharedMempool, SharedMempoolNotification,
+        },
     },
-    CommitNotification, ConsensusRequest, SubmissionStatus,
+    CommitNotification, ConsensusRequest,
+};
+use ::network::{
+    error::NetworkError,
+    protocols::{network::Event, rpc::error::RpcError},
 };
-use ::network::protocols::network::Event;
-use anyhow::Result;
 use bounded_executor::BoundedExecutor;
 use channel::libra_channel;
 use debug_interface::prelude::*;
 use futures::{
-    channel::{mpsc, oneshot},
+    channel::mpsc,
     stream::{select_all, FuturesUnordered},
     StreamExt,
 };
 use libra_config::config::{NodeConfig, PeerNetworkId, UpstreamNetworkId};
 use libra_logger::prelude::*;
 use libra_security_logger::{security_log, SecurityEvent};
-use libra_types::{on_chain_config::OnChainConfigPayload, transaction::SignedTransaction};
+use libra_types::on_chain_config::OnChainConfigPayload;
+use network_api::EventStream;
 use std::{
     ops::Deref,
     sync::{Arc, Mutex},
@@ -35,21 +40,20 @@ use std::{
 use tokio::{runtime::Handle, time::interval};
 use vm_validator::vm_validator::TransactionValidation;
 
-/// Coordinator that handles inbound network events and outbound txn broadcasts.
-pub(crate) async fn coordinator<V>(
+/// Coordinator that handles inbound network events and outbound txn broadcasts. The events are
+/// received from any [`EventStream`], e.g., the `MempoolNetworkEvents` of the network.
+pub(crate) async fn coordinator<V, TEvents>(
     mut smp: SharedMempool<V>,
     executor: Handle,
-    network_events: Vec<(UpstreamNetworkId, MempoolNetworkEvents)>,
-    mut client_events: mpsc::Receiver<(
-        SignedTransaction,
-        oneshot::Sender<Result<SubmissionStatus>>,
-    )>,
+    network_events: Vec<(UpstreamNetworkId, TEvents)>,
+    mut client_events: mpsc::Receiver<MempoolClientRequest>,
     mut consensus_requests: mpsc::Receiver<ConsensusRequest>,
     mut state_sync_requests: mpsc::Receiver<CommitNotification>,
     mut mempool_reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
     node_config: NodeConfig,
 ) where
     V: TransactionValidation,
+    TEvents: EventStream<MempoolSyncMsg, RpcError, NetworkError> + Unpin,
 {
     let smp_events: Vec<_> = network_events
         .into_iter()
@@ -69,15 +73,34 @@ pub(crate) async fn coordinator<V>(
 
     loop {
         ::futures::select! {
-            (mut msg, callback) = client_events.select_next_some() => {
-                trace_event!("mempool::client_event", {"txn", msg.sender(), msg.sequence_number()});
-                bounded_executor
-                .spawn(tasks::process_client_transaction_submission(
-                    smp.clone(),
-                    msg,
-                    callback,
-                ))
-                .await;
+            request = client_events.select_next_some() => {
+                match request {
+                    MempoolClientRequest::SubmitTransaction(mut msg, callback) => {
+                        trace_event!("mempool::client_event", {"txn", msg.sender(), msg.sequence_number()});
+                        bounded_executor
+                        .spawn(tasks::process_client_transaction_submission(
+                            smp.clone(),
+                            msg,
+                            callback,
+                        ))
+                        .await;
+                    }
+                    MempoolClientRequest::SubmitTransactionBatch(msgs, callback) => {
+                        bounded_executor
+                        .spawn(tasks::process_client_transaction_batch_submission(
+                            smp.clone(),
+                            msgs,
+                            callback,
+                        ))
+                        .await;
+                    }
+                    MempoolClientRequest::GetParkedTransactions(address, callback) => {
+                        tasks::process_parked_transactions_request(&mempool, address, callback);
+                    }
+                    MempoolClientRequest::GetExpiredTransactions(address, callback) => {
+                        tasks::process_expired_transactions_request(&mempool, address, callback);
+                    }
+                }
             },
             msg = consensus_requests.select_next_some() => {
                 tasks::process_consensus_request(&mempool, msg).await;
@@ -0,0 +1,184 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzing of the handlers of shared mempool network messages
+
+use crate::{
+    core_mempool::{CoreMempool, TimelineState},
+    network::{MempoolNetworkSender, MempoolSyncMsg},
+    shared_mempool::{
+        peer_manager::PeerManager,
+        tasks,
+        types::{SharedMempool, DEFAULT_MIN_BROADCAST_RECIPIENT_COUNT},
+    },
+};
+use channel::{libra_channel, message_queues::QueueStyle};
+use futures::executor::block_on;
+use libra_config::config::{NodeConfig, PeerNetworkId, RoleType};
+use libra_proptest_helpers::ValueGenerator;
+use libra_types::{transaction::SignedTransaction, PeerId};
+use network::peer_manager::{ConnectionRequestSender, PeerManagerRequestSender};
+use proptest::{arbitrary::any, collection::vec, prop_oneof, strategy::Strategy};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, RwLock},
+};
+use storage_interface::mock::MockDbReader;
+use vm_validator::mocks::mock_vm_validator::MockVMValidator;
+
+const MAX_MESSAGES: usize = 8;
+const MAX_BATCH_SIZE: usize = 4;
+const MAX_TIMELINE_ID: u64 = 16;
+
+#[test]
+fn test_mempool_network_messages_fuzzer() {
+    let mut gen = ValueGenerator::new();
+    let data = generate_corpus(&mut gen);
+    fuzzer(&data);
+}
+
+/// Generates a sequence of shared mempool messages, each tagged with whether it comes from the
+/// upstream peer or from a downstream peer of the full node receiving them.
+pub fn generate_corpus(gen: &mut ValueGenerator) -> Vec<u8> {
+    let messages = gen.generate(vec(
+        (any::<bool>(), arb_mempool_sync_msg()),
+        1..MAX_MESSAGES,
+    ));
+    lcs::to_bytes(&messages).expect("failed to serialize mempool messages")
+}
+
+fn arb_request_id() -> impl Strategy<Value = String> {
+    // broadcasts are identified by the range of timeline IDs they were read from, so that acks
+    // may match actual broadcasts
+    prop_oneof![
+        (0..MAX_TIMELINE_ID, 0..MAX_TIMELINE_ID)
+            .prop_map(|(start, end)| format!("{}_{}", start, end)),
+        any::<String>(),
+    ]
+}
+
+/// Generates the shared mempool messages exchanged by the peers.
+pub fn arb_mempool_sync_msg() -> impl Strategy<Value = MempoolSyncMsg> {
+    prop_oneof![
+        (
+            arb_request_id(),
+            vec(any::<SignedTransaction>(), 0..MAX_BATCH_SIZE)
+        )
+            .prop_map(|(request_id, transactions)| {
+                MempoolSyncMsg::BroadcastTransactionsRequest {
+                    request_id,
+                    transactions,
+                }
+            }),
+        (
+            arb_request_id(),
+            vec(0..MAX_BATCH_SIZE as u64 + 1, 0..MAX_BATCH_SIZE),
+            any::<bool>(),
+        )
+            .prop_map(|(request_id, retry_txns, backoff)| {
+                MempoolSyncMsg::BroadcastTransactionsResponse {
+                    request_id,
+                    retry_txns,
+                    backoff,
+                }
+            }),
+    ]
+}
+
+/// Delivers the messages encoded in `data` to the shared mempool of a full node, as its
+/// coordinator would, and broadcasts to the upstream peer of the node after each of them.
+pub fn fuzzer(data: &[u8]) {
+    let messages: Vec<(bool, MempoolSyncMsg)> = match lcs::from_bytes(data) {
+        Ok(messages) => messages,
+        Err(_) => {
+            // should not throw error or panic on invalid fuzzer inputs
+            if cfg!(test) {
+                panic!();
+            }
+            return;
+        }
+    };
+
+    let upstream_network = PeerId::random();
+    let downstream_network = PeerId::random();
+    let upstream_peer = PeerNetworkId(upstream_network, PeerId::random());
+    let downstream_peer = PeerNetworkId(downstream_network, PeerId::random());
+
+    let mut config = NodeConfig::random();
+    config.base.role = RoleType::FullNode;
+    config.upstream.primary_networks = vec![upstream_network];
+    config.mempool.shared_mempool_batch_size = MAX_BATCH_SIZE;
+
+    let (network_reqs_tx, _network_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (connection_reqs_tx, _connection_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let network_sender = MempoolNetworkSender::new(
+        PeerManagerRequestSender::new(network_reqs_tx),
+        ConnectionRequestSender::new(connection_reqs_tx),
+    );
+    let mut network_senders = HashMap::new();
+    network_senders.insert(upstream_network, network_sender.clone());
+    network_senders.insert(downstream_network, network_sender);
+
+    let peer_manager = Arc::new(PeerManager::new(
+        config.upstream.clone(),
+        DEFAULT_MIN_BROADCAST_RECIPIENT_COUNT,
+    ));
+    peer_manager.add_peer(upstream_peer);
+    peer_manager.add_peer(downstream_peer);
+
+    let mut smp = SharedMempool {
+        mempool: Arc::new(Mutex::new(CoreMempool::new(&config))),
+        config: config.mempool.clone(),
+        network_senders,
+        db: Arc::new(MockDbReader),
+        validator: Arc::new(RwLock::new(MockVMValidator)),
+        peer_manager: peer_manager.clone(),
+        subscribers: vec![],
+    };
+
+    for (from_upstream, message) in messages {
+        let peer = if from_upstream {
+            upstream_peer
+        } else {
+            downstream_peer
+        };
+        match message {
+            MempoolSyncMsg::BroadcastTransactionsRequest {
+                request_id,
+                transactions,
+            } => {
+                let timeline_state = if from_upstream {
+                    TimelineState::NonQualified
+                } else {
+                    TimelineState::NotReady
+                };
+                block_on(tasks::process_transaction_broadcast(
+                    smp.clone(),
+                    transactions,
+                    request_id,
+                    timeline_state,
+                    peer,
+                ));
+            }
+            MempoolSyncMsg::BroadcastTransactionsResponse {
+                request_id,
+                retry_txns,
+                backoff,
+            } => {
+                tasks::process_broadcast_ack(
+                    &smp.mempool,
+                    peer,
+                    request_id,
+                    retry_txns,
+                    backoff,
+                    false,
+                    peer_manager.clone(),
+                );
+            }
+        }
+        tasks::broadcast_single_peer(upstream_peer, true, &mut smp);
+    }
+}
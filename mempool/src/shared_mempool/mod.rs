@@ -1,6 +1,9 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub(crate) mod admission_control;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
 pub mod network;
 mod runtime;
 pub(crate) mod types;
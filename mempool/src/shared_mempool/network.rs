@@ -7,12 +7,13 @@ use crate::counters;
 use channel::message_queues::QueueStyle;
 use libra_types::{transaction::SignedTransaction, PeerId};
 use network::{
-    error::NetworkError,
     peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
     protocols::network::{NetworkEvents, NetworkSender},
     validator_network::network_builder::NetworkBuilder,
     ProtocolId,
 };
+use network_api::MessageSender;
 use serde::{Deserialize, Serialize};
 
 /// Container for exchanging transactions with other Mempools
@@ -55,9 +56,12 @@ pub type MempoolNetworkEvents = NetworkEvents<MempoolSyncMsg>;
 /// remote, to finally receiving the response and deserializing. It therefore
 /// makes the most sense to make the rpc call on a separate async task, which
 /// requires the `MempoolNetworkSender` to be `Clone` and `Send`.
+///
+/// The messages are sent through any [`MessageSender`], e.g., a simulated network in tests, see
+/// [`MempoolNetworkSender::from_sender`].
 #[derive(Clone)]
-pub struct MempoolNetworkSender {
-    inner: NetworkSender<MempoolSyncMsg>,
+pub struct MempoolNetworkSender<TSender = NetworkSender<MempoolSyncMsg>> {
+    inner: TSender,
 }
 
 /// Create a new Sender that only sends for the `MEMPOOL_DIRECT_SEND_PROTOCOL` ProtocolId and a
@@ -70,6 +74,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![],
             vec![ProtocolId::MempoolDirectSend],
+            ProtocolPriority::Low,
             QueueStyle::KLAST,
             max_broadcasts_per_peer,
             Some(&counters::PENDING_MEMPOOL_NETWORK_EVENTS),
@@ -86,9 +91,16 @@ impl MempoolNetworkSender {
         peer_mgr_reqs_tx: PeerManagerRequestSender,
         connection_reqs_tx: ConnectionRequestSender,
     ) -> Self {
-        Self {
-            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
-        }
+        Self::from_sender(NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx))
+    }
+}
+
+impl<TSender> MempoolNetworkSender<TSender>
+where
+    TSender: MessageSender<MempoolSyncMsg, Protocol = ProtocolId>,
+{
+    pub fn from_sender(inner: TSender) -> Self {
+        Self { inner }
     }
 
     /// Send a single message to the destination peer using the `MEMPOOL_DIRECT_SEND_PROTOCOL`
@@ -97,7 +109,7 @@ impl MempoolNetworkSender {
         &mut self,
         recipient: PeerId,
         message: MempoolSyncMsg,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<(), TSender::Error> {
         let protocol = ProtocolId::MempoolDirectSend;
         self.inner.send_to(recipient, protocol, message)
     }
@@ -2,8 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use libra_config::config::{PeerNetworkId, UpstreamConfig};
+use libra_types::account_address::AccountAddress;
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
     sync::Mutex,
 };
 
@@ -63,26 +65,35 @@ impl PeerManager {
             .expect("failed to acquire peer_info lock");
         let is_new_peer = !peer_info.contains_key(&peer);
         if self.is_upstream_peer(peer) {
-            peer_info
-                .entry(peer)
-                .or_insert(PeerSyncState {
-                    timeline_id: 0,
-                    is_alive: true,
-                    broadcast_info: BroadcastInfo::new(),
-                })
-                .is_alive = true;
+            let state = peer_info.entry(peer).or_insert(PeerSyncState {
+                timeline_id: 0,
+                is_alive: true,
+                broadcast_info: BroadcastInfo::new(),
+            });
+            if !state.is_alive {
+                // broadcasts sent over the previous connection will never be ACK'ed, retry them
+                let broadcast_info = &mut state.broadcast_info;
+                for (_batch_id, batch) in broadcast_info.sent_batches.drain() {
+                    broadcast_info.total_retry_txns.extend(batch);
+                }
+            }
+            state.is_alive = true;
         }
         is_new_peer
     }
 
     pub fn disable_peer(&self, peer: PeerNetworkId) {
-        if let Some(state) = self
-            .peer_info
-            .lock()
-            .expect("failed to get peer info lock")
-            .get_mut(&peer)
-        {
-            state.is_alive = false;
+        let mut peer_info = self.peer_info.lock().expect("failed to get peer info lock");
+        let was_healthy = match peer_info.get_mut(&peer) {
+            Some(state) => {
+                let was_healthy = self.is_healthy(state);
+                state.is_alive = false;
+                was_healthy
+            }
+            None => return,
+        };
+        if was_healthy {
+            self.fail_over(&mut peer_info, peer);
         }
     }
 
@@ -104,6 +115,7 @@ impl PeerManager {
             .expect("failed to acquire peer_info lock");
 
         let sync_state = peer_info.get_mut(&peer).expect("missing peer sync state");
+        let was_healthy = self.is_healthy(sync_state);
         sync_state
             .broadcast_info
             .sent_batches
@@ -120,6 +132,21 @@ impl PeerManager {
             .collect::<BTreeSet<_>>();
 
         sync_state.broadcast_info.total_retry_txns = gc_retry_txns;
+
+        if was_healthy && !self.is_healthy(sync_state) {
+            self.fail_over(&mut peer_info, peer);
+        }
+    }
+
+    /// Moves the timeline position of `peer` forward, without broadcasting anything
+    pub fn update_peer_timeline(&self, peer: PeerNetworkId, timeline_id: u64) {
+        let mut peer_info = self
+            .peer_info
+            .lock()
+            .expect("failed to acquire peer_info lock");
+        if let Some(sync_state) = peer_info.get_mut(&peer) {
+            sync_state.timeline_id = std::cmp::max(sync_state.timeline_id, timeline_id);
+        }
     }
 
     pub fn process_broadcast_ack(
@@ -134,7 +161,11 @@ impl PeerManager {
             .lock()
             .expect("failed to acquire peer_info lock");
 
-        let sync_state = peer_info.get_mut(&peer).expect("missing peer sync state");
+        // ignore ACKs from peers this node does not broadcast to, e.g. downstream peers
+        let sync_state = match peer_info.get_mut(&peer) {
+            Some(sync_state) => sync_state,
+            None => return,
+        };
 
         if let Some(batch) = sync_state.broadcast_info.sent_batches.remove(&batch_id) {
             // convert retry_txns from index within a batch to actual timeline ID of txn
@@ -166,11 +197,7 @@ impl PeerManager {
             .lock()
             .expect("failed to acquire lock")
             .get(&peer)
-            .expect("missing peer")
-            .broadcast_info
-            .sent_batches
-            .get(batch_id)
-            .cloned()
+            .and_then(|state| state.broadcast_info.sent_batches.get(batch_id).cloned())
     }
 
     pub fn is_upstream_peer(&self, peer: PeerNetworkId) -> bool {
@@ -199,16 +226,105 @@ impl PeerManager {
             return true;
         }
 
-        let no_live_primaries = self
+        let peer_info = self
             .peer_info
             .lock()
-            .expect("failed to acquire peer info lock")
+            .expect("failed to acquire peer info lock");
+        self.is_fallback_picked(&peer_info)
+    }
+
+    // fallback peers are picked if k-policy is on and no primary peer is healthy
+    // TODO change from sending to k fallback peers instead of sending to all fallback peers
+    fn is_fallback_picked(&self, peer_info: &PeerInfo) -> bool {
+        let no_healthy_primaries = peer_info
             .iter()
-            .find(|(peer, state)| self.is_primary_upstream_peer(**peer) && state.is_alive)
+            .find(|(peer, state)| self.is_primary_upstream_peer(**peer) && self.is_healthy(state))
             .is_none();
+        no_healthy_primaries && self.min_broadcast_recipient_count > 0
+    }
+
+    /// A peer is healthy if it is connected, i.e., it was not disconnected by the network
+    /// health checker, and does not lag behind on ACKs
+    pub fn is_healthy(&self, state: &PeerSyncState) -> bool {
+        state.is_alive
+            && self
+                .upstream_config
+                .max_unacked_broadcasts
+                .map_or(true, |max| state.broadcast_info.sent_batches.len() < max)
+    }
+
+    /// Returns the peers among which transactions are load balanced, with their weight, or None
+    /// if load balancing is disabled
+    pub fn load_balancing_recipients(&self) -> Option<Vec<(PeerNetworkId, u64)>> {
+        if !self.upstream_config.load_balancing {
+            return None;
+        }
+        let peer_info = self
+            .peer_info
+            .lock()
+            .expect("failed to acquire peer info lock");
+        let fallback_picked = self.is_fallback_picked(&peer_info);
+        let recipients = peer_info
+            .iter()
+            .filter(|(peer, state)| {
+                (self.is_primary_upstream_peer(**peer) || fallback_picked) && self.is_healthy(state)
+            })
+            .map(|(peer, _state)| (*peer, self.upstream_config.upstream_weight(*peer)))
+            .collect();
+        Some(recipients)
+    }
 
-        // for fallback peers, broadcast if k-policy is on
-        // TODO change from sending to k fallback peers instead of sending to all fallback peers
-        no_live_primaries && self.min_broadcast_recipient_count > 0
+    // Makes the peers taking over the transactions of `failed_peer` re-read the timeline from
+    // the oldest transaction it may not have received
+    fn fail_over(&self, peer_info: &mut PeerInfo, failed_peer: PeerNetworkId) {
+        if !self.upstream_config.load_balancing {
+            return;
+        }
+        let failed_state = &peer_info[&failed_peer];
+        let unacked_timeline_id = failed_state
+            .broadcast_info
+            .sent_batches
+            .values()
+            .flatten()
+            .chain(failed_state.broadcast_info.total_retry_txns.iter())
+            .map(|timeline_id| timeline_id.saturating_sub(1))
+            .min();
+        let rewind_timeline_id = unacked_timeline_id.map_or(failed_state.timeline_id, |id| {
+            std::cmp::min(id, failed_state.timeline_id)
+        });
+        for (peer, state) in peer_info.iter_mut() {
+            if *peer != failed_peer {
+                state.timeline_id = std::cmp::min(state.timeline_id, rewind_timeline_id);
+            }
+        }
     }
 }
+
+/// Picks the recipient of the transactions of `sender` among the load balancing `recipients`,
+/// using weighted rendezvous hashing: a sender only moves to another peer when the one it is
+/// assigned to stops being a recipient
+pub(crate) fn pick_recipient(
+    recipients: &[(PeerNetworkId, u64)],
+    sender: AccountAddress,
+) -> Option<PeerNetworkId> {
+    // peers with weight 0 are only picked if no other peer is available
+    let has_weighted_recipients = recipients.iter().any(|(_peer, weight)| *weight > 0);
+    recipients
+        .iter()
+        .filter(|(_peer, weight)| *weight > 0 || !has_weighted_recipients)
+        .map(|(peer, weight)| {
+            let mut hasher = DefaultHasher::new();
+            (peer, sender).hash(&mut hasher);
+            // uniform in (0, 1)
+            let hash = ((hasher.finish() >> 12) as f64 + 0.5) / (1u64 << 52) as f64;
+            let score = std::cmp::max(*weight, 1) as f64 / -hash.ln();
+            (peer, score)
+        })
+        .max_by(|(peer_a, score_a), (peer_b, score_b)| {
+            score_a
+                .partial_cmp(score_b)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| peer_a.peer_id().cmp(&peer_b.peer_id()))
+        })
+        .map(|(peer, _score)| *peer)
+}
@@ -7,18 +7,22 @@ use crate::{
     shared_mempool::{
         coordinator::{coordinator, gc_coordinator},
         peer_manager::PeerManager,
-        types::{SharedMempool, SharedMempoolNotification, DEFAULT_MIN_BROADCAST_RECIPIENT_COUNT},
+        tasks,
+        types::{
+            MempoolClientRequest, SharedMempool, SharedMempoolNotification,
+            DEFAULT_MIN_BROADCAST_RECIPIENT_COUNT,
+        },
     },
-    CommitNotification, ConsensusRequest, SubmissionStatus,
+    CommitNotification, ConsensusRequest,
 };
-use anyhow::Result;
 use channel::libra_channel;
-use futures::channel::{
-    mpsc::{self, Receiver, UnboundedSender},
-    oneshot,
+use debug_interface::{
+    degradation::{self, Degradable, DegradationMode},
+    drain::{self, Drainable},
 };
+use futures::channel::mpsc::{self, Receiver, UnboundedSender};
 use libra_config::config::NodeConfig;
-use libra_types::{on_chain_config::OnChainConfigPayload, transaction::SignedTransaction, PeerId};
+use libra_types::{on_chain_config::OnChainConfigPayload, PeerId};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex, RwLock},
@@ -27,6 +31,49 @@ use storage_interface::DbReader;
 use tokio::runtime::{Builder, Handle, Runtime};
 use vm_validator::vm_validator::{TransactionValidation, VMValidator};
 
+/// Shrinks Mempool to a fraction of its capacity while the node sheds load under resource pressure
+struct MempoolShrink {
+    mempool: Arc<Mutex<CoreMempool>>,
+    capacity: usize,
+    shrunk_capacity: usize,
+}
+
+impl Degradable for MempoolShrink {
+    fn mode(&self) -> DegradationMode {
+        DegradationMode::ShrinkMempool
+    }
+
+    fn degrade(&self) {
+        self.mempool
+            .lock()
+            .unwrap()
+            .set_capacity(self.shrunk_capacity);
+    }
+
+    fn recover(&self) {
+        self.mempool.lock().unwrap().set_capacity(self.capacity);
+    }
+}
+
+/// Mempool is drained once the client submissions it's processing were answered. The new ones
+/// are rejected from the start of the drain.
+struct MempoolDrain;
+
+impl Drainable for MempoolDrain {
+    fn name(&self) -> &'static str {
+        "mempool"
+    }
+
+    fn begin_drain(&self) {}
+
+    fn is_drained(&self) -> bool {
+        tasks::in_flight_submissions() == 0
+    }
+
+    // the submissions are accepted again as soon as the node isn't draining
+    fn end_drain(&self) {}
+}
+
 /// bootstrap of SharedMempool
 /// creates separate Tokio Runtime that runs following routines:
 ///   - outbound_sync_task (task that periodically broadcasts transactions to peers)
@@ -39,7 +86,7 @@ pub(crate) fn start_shared_mempool<V>(
     // First element in tuple is the network ID
     // See `NodeConfig::is_upstream_peer` for the definition of network ID
     mempool_network_handles: Vec<(PeerId, MempoolNetworkSender, MempoolNetworkEvents)>,
-    client_events: mpsc::Receiver<(SignedTransaction, oneshot::Sender<Result<SubmissionStatus>>)>,
+    client_events: mpsc::Receiver<MempoolClientRequest>,
     consensus_requests: mpsc::Receiver<ConsensusRequest>,
     state_sync_requests: mpsc::Receiver<CommitNotification>,
     mempool_reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
@@ -66,9 +113,15 @@ pub(crate) fn start_shared_mempool<V>(
         network_senders.insert(network_id, network_sender);
     }
 
+    let mut mempool_config = config.mempool.clone();
+    // Admission control only guards what full nodes forward upstream.
+    if config.base.role.is_validator() {
+        mempool_config.admission_control.enabled = false;
+    }
+
     let smp = SharedMempool {
         mempool: mempool.clone(),
-        config: config.mempool.clone(),
+        config: mempool_config,
         network_senders,
         db,
         validator,
@@ -100,7 +153,7 @@ pub fn bootstrap(
     // The first element in the tuple is the ID of the network that this network is a handle to
     // See `NodeConfig::is_upstream_peer` for the definition of network ID
     mempool_network_handles: Vec<(PeerId, MempoolNetworkSender, MempoolNetworkEvents)>,
-    client_events: Receiver<(SignedTransaction, oneshot::Sender<Result<SubmissionStatus>>)>,
+    client_events: Receiver<MempoolClientRequest>,
     consensus_requests: Receiver<ConsensusRequest>,
     state_sync_requests: Receiver<CommitNotification>,
     mempool_reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
@@ -112,6 +165,16 @@ pub fn bootstrap(
         .build()
         .expect("[shared mempool] failed to create runtime");
     let mempool = Arc::new(Mutex::new(CoreMempool::new(&config)));
+    if config.resource_limits.is_enabled() {
+        degradation::register(Arc::new(MempoolShrink {
+            mempool: Arc::clone(&mempool),
+            capacity: config.mempool.capacity,
+            shrunk_capacity: config.mempool.capacity
+                * config.resource_limits.shrunk_mempool_capacity_percent as usize
+                / 100,
+        }));
+    }
+    drain::register(Arc::new(MempoolDrain));
     let vm_validator = Arc::new(RwLock::new(VMValidator::new(Arc::clone(&db))));
     start_shared_mempool(
         runtime.handle(),
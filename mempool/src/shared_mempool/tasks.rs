@@ -8,8 +8,12 @@ use crate::{
     counters,
     network::{MempoolNetworkSender, MempoolSyncMsg},
     shared_mempool::{
-        peer_manager::PeerManager,
-        types::{notify_subscribers, ScheduledBroadcast, SharedMempool, SharedMempoolNotification},
+        admission_control,
+        peer_manager::{pick_recipient, PeerManager},
+        types::{
+            notify_subscribers, ExpiredTransaction, ParkedTransaction, ScheduledBroadcast,
+            SharedMempool, SharedMempoolNotification,
+        },
     },
     CommitNotification, CommitResponse, CommittedTransaction, ConsensusRequest, ConsensusResponse,
     SubmissionStatus,
@@ -19,6 +23,7 @@ use futures::{channel::oneshot, stream::FuturesUnordered};
 use libra_config::config::PeerNetworkId;
 use libra_logger::prelude::*;
 use libra_types::{
+    account_address::AccountAddress,
     mempool_status::{MempoolStatus, MempoolStatusCode},
     on_chain_config::OnChainConfigPayload,
     transaction::SignedTransaction,
@@ -32,7 +37,10 @@ use std::{
     cmp,
     collections::HashSet,
     ops::Deref,
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::runtime::Handle;
@@ -70,7 +78,11 @@ pub(crate) fn execute_broadcast<V>(
 
 /// broadcasts txns to `peer` if alive
 /// Returns whether the next broadcast scheduled for this peer should be in backpressure mode or not
-fn broadcast_single_peer<V>(peer: PeerNetworkId, backoff: bool, smp: &mut SharedMempool<V>) -> bool
+pub(crate) fn broadcast_single_peer<V>(
+    peer: PeerNetworkId,
+    backoff: bool,
+    smp: &mut SharedMempool<V>,
+) -> bool
 where
     V: TransactionValidation,
 {
@@ -79,7 +91,7 @@ where
     let (timeline_id, retry_txns_id, next_backoff) = if peer_manager.is_picked_peer(peer) {
         let state = peer_manager.get_peer_state(peer);
         let next_backoff = state.broadcast_info.backoff_mode;
-        if state.is_alive {
+        if peer_manager.is_healthy(&state) {
             (
                 state.timeline_id,
                 state
@@ -112,7 +124,8 @@ where
     // first populate batch with retriable txns, to prioritize resending them
     let retry_txns = mempool.filter_read_timeline(retry_txns_id);
     // pad the batch with new txns from fresh timeline read, if batch has space
-    let (new_txns, new_timeline_id) = if retry_txns.len() < smp.config.shared_mempool_batch_size {
+    let (mut new_txns, new_timeline_id) = if retry_txns.len() < smp.config.shared_mempool_batch_size
+    {
         mempool.read_timeline(
             timeline_id,
             smp.config.shared_mempool_batch_size - retry_txns.len(),
@@ -120,8 +133,14 @@ where
     } else {
         (vec![], timeline_id)
     };
+    // when load balancing, only forward the new txns whose sender is assigned to this peer
+    if let Some(recipients) = peer_manager.load_balancing_recipients() {
+        new_txns.retain(|(_id, txn)| pick_recipient(&recipients, txn.sender()) == Some(peer));
+    }
 
     if new_txns.is_empty() && retry_txns.is_empty() {
+        // the txns read may all have been assigned to other peers
+        peer_manager.update_peer_timeline(peer, new_timeline_id);
         return next_backoff;
     }
 
@@ -200,6 +219,30 @@ fn send_mempool_sync_msg(
 // tasks processing txn submission //
 // =============================== //
 
+/// Number of client submissions being processed, which have to be answered before the node can
+/// be stopped once it drains
+static IN_FLIGHT_SUBMISSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a client submission as in flight until it's dropped
+struct InFlightSubmission;
+
+impl InFlightSubmission {
+    fn new() -> Self {
+        IN_FLIGHT_SUBMISSIONS.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for InFlightSubmission {
+    fn drop(&mut self) {
+        IN_FLIGHT_SUBMISSIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub(crate) fn in_flight_submissions() -> usize {
+    IN_FLIGHT_SUBMISSIONS.load(Ordering::SeqCst)
+}
+
 /// processes transactions directly submitted by client
 pub(crate) async fn process_client_transaction_submission<V>(
     smp: SharedMempool<V>,
@@ -208,6 +251,20 @@ pub(crate) async fn process_client_transaction_submission<V>(
 ) where
     V: TransactionValidation,
 {
+    let _in_flight = InFlightSubmission::new();
+    // Stop accepting new transactions once the node is being drained ahead of a restart.
+    if debug_interface::drain::is_draining() {
+        if callback
+            .send(Err(format_err!(
+                "[shared mempool] node is draining, not accepting transactions"
+            )))
+            .is_err()
+        {
+            error!("[shared mempool] failed to reject transaction submission of draining node");
+        }
+        return;
+    }
+
     let mut statuses =
         process_incoming_transactions(&smp, vec![transaction], TimelineState::NotReady).await;
     log_txn_process_results(&statuses, None);
@@ -227,6 +284,65 @@ pub(crate) async fn process_client_transaction_submission<V>(
     }
 }
 
+/// processes a batch of transactions of a single sender directly submitted by client, which is
+/// added to the local mempool as a whole or not at all
+pub(crate) async fn process_client_transaction_batch_submission<V>(
+    smp: SharedMempool<V>,
+    transactions: Vec<SignedTransaction>,
+    callback: oneshot::Sender<Result<SubmissionStatus>>,
+) where
+    V: TransactionValidation,
+{
+    let _in_flight = InFlightSubmission::new();
+    let status = if debug_interface::drain::is_draining() {
+        Err(format_err!(
+            "[shared mempool] node is draining, not accepting transactions"
+        ))
+    } else {
+        process_incoming_transaction_batch(&smp, transactions).await
+    };
+    if let Ok(status) = &status {
+        log_txn_process_results(std::slice::from_ref(status), None);
+    }
+
+    if let Err(e) = callback
+        .send(status)
+        .map_err(|_| format_err!("[shared mempool] timeout on callback send to AC endpoint"))
+    {
+        error!("[shared mempool] failed to send back transaction batch submission result to AC endpoint with error: {:?}", e);
+    }
+}
+
+/// returns the parked transactions of an account to a client
+pub(crate) fn process_parked_transactions_request(
+    mempool: &Mutex<CoreMempool>,
+    address: AccountAddress,
+    callback: oneshot::Sender<Vec<ParkedTransaction>>,
+) {
+    let parked_transactions = mempool
+        .lock()
+        .expect("[shared mempool] failed to acquire mempool lock")
+        .get_parked_transactions(address);
+    if callback.send(parked_transactions).is_err() {
+        error!("[shared mempool] failed to send back parked transactions to client endpoint");
+    }
+}
+
+/// returns the transactions of an account which expired without being committed to a client
+pub(crate) fn process_expired_transactions_request(
+    mempool: &Mutex<CoreMempool>,
+    address: AccountAddress,
+    callback: oneshot::Sender<Vec<ExpiredTransaction>>,
+) {
+    let expired_transactions = mempool
+        .lock()
+        .expect("[shared mempool] failed to acquire mempool lock")
+        .get_expired_transactions(&address);
+    if callback.send(expired_transactions).is_err() {
+        error!("[shared mempool] failed to send back expired transactions to client endpoint");
+    }
+}
+
 /// processes transactions from other nodes
 pub(crate) async fn process_transaction_broadcast<V>(
     mut smp: SharedMempool<V>,
@@ -306,6 +422,20 @@ where
             .filter_map(|(idx, t)| {
                 if let Ok(sequence_number) = seq_numbers[idx] {
                     if t.sequence_number() >= sequence_number {
+                        if smp.config.admission_control.enabled {
+                            if let Err(status) = admission_control::admit(
+                                &smp.config.admission_control,
+                                smp.db.as_ref(),
+                                &t,
+                                sequence_number,
+                            ) {
+                                statuses.push((
+                                    MempoolStatus::new(MempoolStatusCode::VmError),
+                                    Some(status),
+                                ));
+                                return None;
+                            }
+                        }
                         return Some((t, sequence_number));
                     } else {
                         statuses.push((
@@ -372,6 +502,85 @@ where
     statuses
 }
 
+/// validates a batch of transactions of a single sender and submits it to the local mempool
+/// returns the status of the first transaction of the batch which is rejected, if any
+async fn process_incoming_transaction_batch<V>(
+    smp: &SharedMempool<V>,
+    transactions: Vec<SignedTransaction>,
+) -> Result<SubmissionStatus>
+where
+    V: TransactionValidation,
+{
+    let sender = match transactions.first() {
+        Some(transaction) => transaction.sender(),
+        None => return Err(format_err!("[shared mempool] empty transaction batch")),
+    };
+    if transactions.iter().any(|t| t.sender() != sender) {
+        return Err(format_err!(
+            "[shared mempool] transaction batch of more than one sender"
+        ));
+    }
+
+    let sequence_number = match get_account_sequence_number(smp.db.as_ref(), sender) {
+        Ok(sequence_number) => sequence_number,
+        Err(_) => {
+            return Ok((
+                MempoolStatus::new(MempoolStatusCode::VmError),
+                Some(
+                    VMStatus::new(RESOURCE_DOES_NOT_EXIST)
+                        .with_message("[shared mempool] failed to get account state".to_string()),
+                ),
+            ))
+        }
+    };
+    if transactions[0].sequence_number() < sequence_number {
+        return Ok((
+            MempoolStatus::new(MempoolStatusCode::VmError),
+            Some(VMStatus::new(SEQUENCE_NUMBER_TOO_OLD)),
+        ));
+    }
+
+    let mut batch = vec![];
+    for transaction in transactions {
+        if smp.config.admission_control.enabled {
+            if let Err(status) = admission_control::admit(
+                &smp.config.admission_control,
+                smp.db.as_ref(),
+                &transaction,
+                sequence_number,
+            ) {
+                return Ok((MempoolStatus::new(MempoolStatusCode::VmError), Some(status)));
+            }
+        }
+        let validation_result = smp
+            .validator
+            .read()
+            .unwrap()
+            .validate_transaction(transaction.clone())?;
+        if let Some(validation_status) = validation_result.status() {
+            return Ok((
+                MempoolStatus::new(MempoolStatusCode::VmError),
+                Some(validation_status),
+            ));
+        }
+        let gas_amount = transaction.max_gas_amount();
+        batch.push((
+            transaction,
+            gas_amount,
+            validation_result.score(),
+            validation_result.is_governance_txn(),
+        ));
+    }
+
+    let mempool_status = smp
+        .mempool
+        .lock()
+        .expect("[shared mempool] failed to acquire mempool lock")
+        .add_txn_batch(batch, sequence_number, TimelineState::NotReady);
+    notify_subscribers(SharedMempoolNotification::NewTransactions, &smp.subscribers);
+    Ok((mempool_status, None))
+}
+
 // TODO update counters to ID peers using PeerNetworkId
 fn log_txn_process_results(results: &[SubmissionStatus], sender: Option<PeerId>) {
     let sender = match sender {
@@ -472,7 +681,7 @@ pub(crate) async fn process_consensus_request(mempool: &Mutex<CoreMempool>, req:
                 .iter()
                 .map(|txn| (txn.sender, txn.sequence_number))
                 .collect();
-            let mut txns;
+            let txns;
             {
                 let mut mempool = mempool.lock().expect("failed to acquire mempool lock");
                 // gc before pulling block as extra protection against txns that may expire in consensus
@@ -483,9 +692,7 @@ pub(crate) async fn process_consensus_request(mempool: &Mutex<CoreMempool>, req:
                 mempool.gc_by_expiration_time(curr_time);
                 txns = mempool.get_block(block_size, exclude_transactions);
             }
-            let transactions = txns.drain(..).map(SignedTransaction::into).collect();
-
-            (ConsensusResponse::GetBlockResponse(transactions), callback)
+            (ConsensusResponse::GetBlockResponse(txns), callback)
         }
         ConsensusRequest::RejectNotification(transactions, callback) => {
             // handle rejected txns
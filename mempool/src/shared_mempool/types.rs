@@ -28,7 +28,7 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex, RwLock},
     task::Waker,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use storage_interface::DbReader;
 use subscription_service::ReconfigSubscription;
@@ -146,8 +146,8 @@ pub enum ConsensusRequest {
 pub enum ConsensusResponse {
     /// block to submit to consensus
     GetBlockResponse(
-        // transactions in block
-        Vec<SignedTransaction>,
+        // transactions in block, shared with mempool
+        Vec<Arc<SignedTransaction>>,
     ),
     /// ACK for commit notification
     CommitResponse(),
@@ -190,9 +190,33 @@ pub struct TransactionExclusion {
 /// Submission Status is represented as combination of vm_validator internal status and core mempool insertion status
 pub type SubmissionStatus = (MempoolStatus, Option<VMStatus>);
 
-/// sender type: used to enqueue new transactions to shared mempool by client endpoints
-pub type MempoolClientSender =
-    mpsc::Sender<(SignedTransaction, oneshot::Sender<Result<SubmissionStatus>>)>;
+/// Parked transaction, i.e., waiting for transactions with lower sequence numbers, with for how
+/// long it has been parked
+pub type ParkedTransaction = (SignedTransaction, Duration);
+
+/// Transaction which expired without being committed, with the timestamp of the block it expired
+/// at, i.e., which will never be committed
+pub type ExpiredTransaction = (SignedTransaction, Duration);
+
+/// Request from a client endpoint to shared mempool
+pub enum MempoolClientRequest {
+    /// enqueues a new transaction
+    SubmitTransaction(SignedTransaction, oneshot::Sender<Result<SubmissionStatus>>),
+    /// enqueues a batch of transactions of a single sender with consecutive sequence numbers,
+    /// either all of them or none
+    SubmitTransactionBatch(
+        Vec<SignedTransaction>,
+        oneshot::Sender<Result<SubmissionStatus>>,
+    ),
+    /// fetches the parked transactions of an account, ordered by sequence number
+    GetParkedTransactions(AccountAddress, oneshot::Sender<Vec<ParkedTransaction>>),
+    /// fetches the transactions of an account which expired without being committed, ordered by
+    /// sequence number
+    GetExpiredTransactions(AccountAddress, oneshot::Sender<Vec<ExpiredTransaction>>),
+}
+
+/// sender type: used to send requests to shared mempool by client endpoints
+pub type MempoolClientSender = mpsc::Sender<MempoolClientRequest>;
 
 /// On-chain configs that mempool subscribes to for reconfiguration
 const MEMPOOL_SUBSCRIBED_CONFIGS: &[ConfigID] = &[LibraVersion::CONFIG_ID, VMConfig::CONFIG_ID];
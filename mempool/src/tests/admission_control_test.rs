@@ -0,0 +1,91 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    shared_mempool::admission_control::{admit, check_balance},
+    tests::common::TestTransaction,
+};
+use libra_config::config::AdmissionControlConfig;
+use libra_types::{
+    transaction::{authenticator::TransactionAuthenticator, SignedTransaction},
+    vm_error::{StatusCode, VMStatus},
+};
+use storage_interface::mock::MockDbReader;
+
+fn config() -> AdmissionControlConfig {
+    // the mock db has no balances
+    AdmissionControlConfig {
+        enabled: true,
+        check_balance: false,
+        ..AdmissionControlConfig::default()
+    }
+}
+
+fn major_status(result: Result<(), VMStatus>) -> StatusCode {
+    result.unwrap_err().major_status
+}
+
+#[test]
+fn test_admit() {
+    let txn = TestTransaction::new(0, 5, 1).make_signed_transaction();
+    assert!(admit(&config(), &MockDbReader, &txn, 0).is_ok());
+}
+
+#[test]
+fn test_min_gas_unit_price() {
+    let config = AdmissionControlConfig {
+        min_gas_unit_price: 2,
+        ..config()
+    };
+    let txn = TestTransaction::new(0, 0, 1).make_signed_transaction();
+    assert_eq!(
+        major_status(admit(&config, &MockDbReader, &txn, 0)),
+        StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND
+    );
+    let txn = TestTransaction::new(0, 0, 2).make_signed_transaction();
+    assert!(admit(&config, &MockDbReader, &txn, 0).is_ok());
+}
+
+#[test]
+fn test_sequence_number_gap() {
+    let config = AdmissionControlConfig {
+        max_sequence_number_gap: Some(10),
+        ..config()
+    };
+    let txn = TestTransaction::new(0, 15, 1).make_signed_transaction();
+    assert_eq!(
+        major_status(admit(&config, &MockDbReader, &txn, 4)),
+        StatusCode::SEQUENCE_NUMBER_TOO_NEW
+    );
+    assert!(admit(&config, &MockDbReader, &txn, 5).is_ok());
+}
+
+#[test]
+fn test_signature() {
+    let txn = TestTransaction::new(0, 0, 1).make_signed_transaction();
+    let other = TestTransaction::new(0, 1, 1).make_signed_transaction();
+    let (public_key, signature) = match other.authenticator() {
+        TransactionAuthenticator::Ed25519 {
+            public_key,
+            signature,
+        } => (public_key, signature),
+        _ => unreachable!(),
+    };
+    // signature of another transaction
+    let forged = SignedTransaction::new(txn.into_raw_transaction(), public_key, signature);
+    assert_eq!(
+        major_status(admit(&config(), &MockDbReader, &forged, 0)),
+        StatusCode::INVALID_SIGNATURE
+    );
+}
+
+#[test]
+fn test_balance() {
+    // max fee of 100 gas units at 3 per unit
+    let txn = TestTransaction::new(0, 0, 3).make_signed_transaction();
+    assert!(check_balance(&txn, 300).is_ok());
+    assert_eq!(
+        major_status(check_balance(&txn, 299)),
+        StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE
+    );
+}
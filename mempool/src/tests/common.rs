@@ -174,7 +174,7 @@ impl ConsensusMock {
             ))
             .cloned()
             .collect();
-        block
+        block.into_iter().map(|txn| txn.as_ref().clone()).collect()
     }
 }
 
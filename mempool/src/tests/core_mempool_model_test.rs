@@ -0,0 +1,349 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks core mempool against a simple model of its semantics, on random sequences of
+//! insertions, commits, rejections, expirations and block requests.
+
+use crate::{
+    core_mempool::{CoreMempool, TimelineState, TxnPointer},
+    tests::common::TestTransaction,
+};
+use libra_config::config::NodeConfig;
+use libra_types::mempool_status::MempoolStatusCode;
+use proptest::{collection::vec, prelude::*};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    time::Duration,
+};
+
+const NUM_ACCOUNTS: usize = 3;
+const MAX_SEQUENCE_NUMBER: u64 = 6;
+
+#[derive(Clone, Debug)]
+enum Operation {
+    Insert {
+        account: usize,
+        sequence_number: u64,
+        gas_price: u64,
+        expiration_time: u64,
+    },
+    Commit {
+        account: usize,
+        sequence_number: u64,
+    },
+    Reject {
+        account: usize,
+        sequence_number: u64,
+    },
+    Expire {
+        block_time: u64,
+    },
+    GetBlock {
+        batch_size: u64,
+    },
+}
+
+fn arb_operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        6 => (0..NUM_ACCOUNTS, 0..MAX_SEQUENCE_NUMBER, 1..4u64, 1..5u64).prop_map(
+            |(account, sequence_number, gas_price, expiration_time)| Operation::Insert {
+                account,
+                sequence_number,
+                gas_price,
+                expiration_time: expiration_time * 10,
+            }
+        ),
+        2 => (0..NUM_ACCOUNTS, 0..MAX_SEQUENCE_NUMBER).prop_map(|(account, sequence_number)| {
+            Operation::Commit {
+                account,
+                sequence_number,
+            }
+        }),
+        1 => (0..NUM_ACCOUNTS, 0..MAX_SEQUENCE_NUMBER).prop_map(|(account, sequence_number)| {
+            Operation::Reject {
+                account,
+                sequence_number,
+            }
+        }),
+        1 => (0..60u64).prop_map(|block_time| Operation::Expire { block_time }),
+        2 => (1..8u64).prop_map(|batch_size| Operation::GetBlock { batch_size }),
+    ]
+}
+
+#[derive(Clone, Default)]
+struct ModelAccount {
+    // sequence number of the account in storage
+    committed_sequence_number: u64,
+    // sequence number of the account as cached by mempool, if any
+    sequence_number: Option<u64>,
+    // (gas price, expiration time) of the transactions of the account, by sequence number
+    txns: BTreeMap<u64, (u64, u64)>,
+}
+
+impl ModelAccount {
+    /// sequence numbers of the transactions that can be included in the next block, in order
+    fn ready(&self) -> Vec<u64> {
+        let mut ready = vec![];
+        if let Some(mut sequence_number) = self.sequence_number {
+            while self.txns.contains_key(&sequence_number) {
+                ready.push(sequence_number);
+                sequence_number += 1;
+            }
+        }
+        ready
+    }
+}
+
+struct Model {
+    capacity: usize,
+    accounts: Vec<ModelAccount>,
+}
+
+impl Model {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            accounts: vec![ModelAccount::default(); NUM_ACCOUNTS],
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.accounts.iter().map(|account| account.txns.len()).sum()
+    }
+
+    fn ready(&self) -> BTreeSet<TxnPointer> {
+        self.accounts
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, account)| {
+                account.ready().into_iter().map(move |sequence_number| {
+                    (TestTransaction::get_address(idx), sequence_number)
+                })
+            })
+            .collect()
+    }
+
+    fn parked(&self) -> BTreeSet<TxnPointer> {
+        let ready = self.ready();
+        self.accounts
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, account)| {
+                account.txns.keys().map(move |sequence_number| {
+                    (TestTransaction::get_address(idx), *sequence_number)
+                })
+            })
+            .filter(|txn| !ready.contains(txn))
+            .collect()
+    }
+
+    fn insert(
+        &mut self,
+        idx: usize,
+        sequence_number: u64,
+        gas_price: u64,
+        expiration_time: u64,
+    ) -> MempoolStatusCode {
+        let account = &mut self.accounts[idx];
+        let current = account
+            .sequence_number
+            .map_or(account.committed_sequence_number, |cached| {
+                cached.max(account.committed_sequence_number)
+            });
+        account.sequence_number = Some(current);
+        if sequence_number < current {
+            return MempoolStatusCode::InvalidSeqNumber;
+        }
+        // the gas price of a transaction can only be bumped
+        if let Some((current_gas_price, current_expiration_time)) =
+            account.txns.get(&sequence_number)
+        {
+            if *current_expiration_time != expiration_time || *current_gas_price >= gas_price {
+                return MempoolStatusCode::InvalidUpdate;
+            }
+            account
+                .txns
+                .insert(sequence_number, (gas_price, expiration_time));
+            return MempoolStatusCode::Accepted;
+        }
+
+        // when full, only transactions that would be ready may evict a parked transaction
+        let is_ready =
+            sequence_number == current || account.ready().last() == Some(&(sequence_number - 1));
+        if self.size() >= self.capacity && is_ready {
+            if let Some((address, evicted)) = self.parked().into_iter().next_back() {
+                let evicted_idx = (0..NUM_ACCOUNTS)
+                    .find(|idx| TestTransaction::get_address(*idx) == address)
+                    .unwrap();
+                self.accounts[evicted_idx].txns.remove(&evicted);
+            }
+        }
+        if self.size() >= self.capacity {
+            return MempoolStatusCode::MempoolIsFull;
+        }
+        self.accounts[idx]
+            .txns
+            .insert(sequence_number, (gas_price, expiration_time));
+        MempoolStatusCode::Accepted
+    }
+
+    fn commit(&mut self, idx: usize, sequence_number: u64) {
+        let account = &mut self.accounts[idx];
+        let current = account.sequence_number.take().unwrap_or_default();
+        let new_sequence_number = current.max(sequence_number + 1);
+        account.sequence_number = Some(new_sequence_number);
+        account.committed_sequence_number =
+            account.committed_sequence_number.max(sequence_number + 1);
+        account.txns = account.txns.split_off(&new_sequence_number);
+    }
+
+    fn reject(&mut self, idx: usize, sequence_number: u64) {
+        let account = &mut self.accounts[idx];
+        let current = account.sequence_number.take().unwrap_or_default();
+        if sequence_number >= current {
+            account.txns.clear();
+        } else {
+            account.sequence_number = Some(current);
+        }
+    }
+
+    fn expire(&mut self, block_time: u64) {
+        for account in self.accounts.iter_mut() {
+            account
+                .txns
+                .retain(|_, (_, expiration_time)| *expiration_time >= block_time);
+        }
+    }
+}
+
+fn check_invariants(pool: &mut CoreMempool, model: &Model) {
+    // transactions ready for broadcast are exactly the ones ready for consensus
+    let (timeline, _) = pool.read_timeline(0, usize::max_value());
+    let timeline: BTreeSet<_> = timeline
+        .iter()
+        .map(|(_, txn)| (txn.sender(), txn.sequence_number()))
+        .collect();
+    assert_eq!(timeline, model.ready());
+
+    for idx in 0..NUM_ACCOUNTS {
+        let parked: BTreeSet<_> = pool
+            .get_parked_transactions(TestTransaction::get_address(idx))
+            .iter()
+            .map(|(txn, _)| (txn.sender(), txn.sequence_number()))
+            .collect();
+        let expected: BTreeSet<_> = model
+            .parked()
+            .into_iter()
+            .filter(|(address, _)| *address == TestTransaction::get_address(idx))
+            .collect();
+        assert_eq!(parked, expected);
+    }
+}
+
+fn check_block(pool: &mut CoreMempool, model: &Model, batch_size: u64) {
+    let block = pool.get_block(batch_size, HashSet::new());
+    let ready = model.ready();
+    assert_eq!(block.len(), ready.len().min(batch_size as usize));
+
+    // each account contributes the first of its ready transactions, in order, with their latest
+    // gas price
+    for (idx, account) in model.accounts.iter().enumerate() {
+        let address = TestTransaction::get_address(idx);
+        let included: Vec<_> = block
+            .iter()
+            .filter(|txn| txn.sender() == address)
+            .map(|txn| {
+                assert_eq!(txn.gas_unit_price(), account.txns[&txn.sequence_number()].0);
+                txn.sequence_number()
+            })
+            .collect();
+        assert_eq!(included[..], account.ready()[..included.len()]);
+    }
+
+    // the block starts with the best paying transaction that can be executed right away
+    if let Some(first) = block.first() {
+        let best_gas_price = model
+            .accounts
+            .iter()
+            .filter_map(|account| {
+                let sequence_number = account.ready().first().cloned()?;
+                Some(account.txns[&sequence_number].0)
+            })
+            .max();
+        assert_eq!(Some(first.gas_unit_price()), best_gas_price);
+    }
+}
+
+fn run(capacity: usize, operations: Vec<Operation>) {
+    let mut config = NodeConfig::random();
+    config.mempool.capacity = capacity;
+    let mut pool = CoreMempool::new(&config);
+    let mut model = Model::new(capacity);
+
+    for operation in operations {
+        match operation {
+            Operation::Insert {
+                account,
+                sequence_number,
+                gas_price,
+                expiration_time,
+            } => {
+                let txn = TestTransaction::new(account, sequence_number, gas_price)
+                    .make_signed_transaction_with_expiration_time(Duration::from_secs(
+                        expiration_time,
+                    ));
+                let db_sequence_number = model.accounts[account].committed_sequence_number;
+                let status = pool.add_txn(
+                    txn,
+                    0,
+                    gas_price,
+                    db_sequence_number,
+                    TimelineState::NotReady,
+                    false,
+                );
+                let expected = model.insert(account, sequence_number, gas_price, expiration_time);
+                assert_eq!(status.code, expected, "{:?}", status);
+            }
+            Operation::Commit {
+                account,
+                sequence_number,
+            } => {
+                pool.remove_transaction(
+                    &TestTransaction::get_address(account),
+                    sequence_number,
+                    false,
+                );
+                model.commit(account, sequence_number);
+            }
+            Operation::Reject {
+                account,
+                sequence_number,
+            } => {
+                pool.remove_transaction(
+                    &TestTransaction::get_address(account),
+                    sequence_number,
+                    true,
+                );
+                model.reject(account, sequence_number);
+            }
+            Operation::Expire { block_time } => {
+                pool.gc_by_expiration_time(Duration::from_secs(block_time));
+                model.expire(block_time);
+            }
+            Operation::GetBlock { batch_size } => check_block(&mut pool, &model, batch_size),
+        }
+        check_invariants(&mut pool, &model);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(100))]
+
+    #[test]
+    fn test_core_mempool_model(
+        capacity in NUM_ACCOUNTS..3 * MAX_SEQUENCE_NUMBER as usize,
+        operations in vec(arb_operation(), 1..50),
+    ) {
+        run(capacity, operations);
+    }
+}
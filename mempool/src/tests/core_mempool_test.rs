@@ -9,9 +9,13 @@ use crate::{
     },
 };
 use libra_config::config::NodeConfig;
-use libra_types::transaction::SignedTransaction;
+use libra_types::{
+    mempool_status::MempoolStatusCode,
+    transaction::{SharedTransactionStore, SignedTransaction},
+};
 use std::{
     collections::HashSet,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
@@ -194,7 +198,7 @@ fn test_system_ttl() {
     // gc routine should clear transaction from first insert but keep last one
     mempool.gc();
     let batch = mempool.get_block(1, HashSet::new());
-    assert_eq!(vec![transaction.make_signed_transaction()], batch);
+    assert_eq!(vec![Arc::new(transaction.make_signed_transaction())], batch);
 }
 
 #[test]
@@ -209,7 +213,24 @@ fn test_commit_callback() {
     // transaction 5 got back from consensus
     pool.remove_transaction(&TestTransaction::get_address(1), 5, false);
     // verify that we can execute transaction 6
-    assert_eq!(pool.get_block(1, HashSet::new())[0], txns[0]);
+    assert_eq!(*pool.get_block(1, HashSet::new())[0], txns[0]);
+}
+
+#[test]
+fn test_get_block_shares_transactions() {
+    // the blocks pulled by consensus share the copy of the transactions held by mempool
+    let mut pool = setup_mempool().0;
+    let txns = add_txns_to_mempool(&mut pool, vec![TestTransaction::new(1, 0, 1)]);
+    let block = pool.get_block(1, HashSet::new());
+    assert_eq!(*block[0], txns[0]);
+    assert!(Arc::ptr_eq(
+        &block[0],
+        &pool.get_block(1, HashSet::new())[0]
+    ));
+    assert!(Arc::ptr_eq(
+        &block[0],
+        &SharedTransactionStore::global().get(&txns[0]).unwrap()
+    ));
 }
 
 #[test]
@@ -303,6 +324,23 @@ fn test_capacity() {
     assert!(add_txn(&mut pool, TestTransaction::new(1, 2, 1)).is_ok());
 }
 
+#[test]
+fn test_set_capacity() {
+    let mut config = NodeConfig::random();
+    config.mempool.capacity = 2;
+    let mut pool = CoreMempool::new(&config);
+    add_txn(&mut pool, TestTransaction::new(1, 0, 1)).unwrap();
+
+    // shrinking keeps the transactions beyond the new capacity, but rejects new ones
+    pool.set_capacity(1);
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 1, 1)).is_err());
+    assert_eq!(pool.get_block(10, HashSet::new()).len(), 1);
+
+    // restoring the capacity accepts them again
+    pool.set_capacity(config.mempool.capacity);
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 1, 1)).is_ok());
+}
+
 #[test]
 fn test_parking_lot_eviction() {
     let mut config = NodeConfig::random();
@@ -361,6 +399,102 @@ fn test_parking_lot_evict_only_for_ready_txn_insertion() {
     }
 }
 
+#[test]
+fn test_parking_lot_capacity() {
+    let mut config = NodeConfig::random();
+    config.mempool.parking_lot_capacity = 3;
+    config.mempool.parking_lot_capacity_per_user = 2;
+    let mut pool = CoreMempool::new(&config);
+
+    // per user limit
+    for seq in &[2, 3] {
+        add_txn(&mut pool, TestTransaction::new(1, *seq, 1)).unwrap();
+    }
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 5, 1)).is_err());
+    // ready transactions don't count against the parking lot
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+
+    // global limit
+    add_txn(&mut pool, TestTransaction::new(0, 2, 1)).unwrap();
+    assert!(add_txn(&mut pool, TestTransaction::new(2, 1, 1)).is_err());
+
+    // unparking frees space
+    add_txn(&mut pool, TestTransaction::new(1, 0, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 1, 1)).unwrap();
+    assert!(pool
+        .get_parked_transactions(TestTransaction::get_address(1))
+        .is_empty());
+    add_txn(&mut pool, TestTransaction::new(2, 1, 1)).unwrap();
+
+    let parked: Vec<_> = pool
+        .get_parked_transactions(TestTransaction::get_address(2))
+        .into_iter()
+        .map(|(txn, _)| txn.sequence_number())
+        .collect();
+    assert_eq!(parked, vec![1]);
+}
+
+#[test]
+fn test_add_txn_batch() {
+    let mut config = NodeConfig::random();
+    config.mempool.capacity_per_user = 4;
+    let mut pool = CoreMempool::new(&config);
+    let add_batch = |pool: &mut CoreMempool, txns: Vec<TestTransaction>| {
+        let batch = txns
+            .iter()
+            .map(|txn| {
+                let txn = txn.make_signed_transaction();
+                let gas_price = txn.gas_unit_price();
+                (txn, 0, gas_price, false)
+            })
+            .collect();
+        pool.add_txn_batch(batch, 0, TimelineState::NotReady).code
+    };
+
+    // the batch must be of consecutive transactions of a single sender
+    let status = add_batch(
+        &mut pool,
+        vec![TestTransaction::new(1, 0, 1), TestTransaction::new(1, 2, 1)],
+    );
+    assert_eq!(status, MempoolStatusCode::InvalidSeqNumber);
+    let status = add_batch(
+        &mut pool,
+        vec![TestTransaction::new(1, 0, 1), TestTransaction::new(2, 1, 1)],
+    );
+    assert_eq!(status, MempoolStatusCode::UnknownStatus);
+    assert!(pool.get_block(10, HashSet::new()).is_empty());
+
+    let status = add_batch(
+        &mut pool,
+        vec![
+            TestTransaction::new(1, 0, 1),
+            TestTransaction::new(1, 1, 1),
+            TestTransaction::new(1, 2, 1),
+        ],
+    );
+    assert_eq!(status, MempoolStatusCode::Accepted);
+    let mut block: Vec<_> = pool
+        .get_block(10, HashSet::new())
+        .iter()
+        .map(SignedTransaction::sequence_number)
+        .collect();
+    block.sort();
+    assert_eq!(block, vec![0, 1, 2]);
+
+    // a batch which doesn't fit is rejected as a whole
+    let status = add_batch(
+        &mut pool,
+        vec![TestTransaction::new(1, 3, 1), TestTransaction::new(1, 4, 1)],
+    );
+    assert_eq!(status, MempoolStatusCode::TooManyTransactions);
+    let status = add_batch(
+        &mut pool,
+        vec![TestTransaction::new(1, 2, 2), TestTransaction::new(1, 3, 1)],
+    );
+    assert_eq!(status, MempoolStatusCode::InvalidUpdate);
+    assert_eq!(pool.get_block(10, HashSet::new()).len(), 3);
+}
+
 #[test]
 fn test_gc_ready_transaction() {
     let mut pool = setup_mempool().0;
@@ -397,6 +531,40 @@ fn test_gc_ready_transaction() {
     assert_eq!(timeline[0].sequence_number(), 0);
 }
 
+#[test]
+fn test_expired_transactions() {
+    let mut config = NodeConfig::random();
+    config.mempool.expired_transactions_capacity = 2;
+    let mut pool = CoreMempool::new(&config);
+    for seq in 0..3 {
+        let txn = TestTransaction::new(1, seq, 1)
+            .make_signed_transaction_with_expiration_time(Duration::from_secs(seq));
+        pool.add_txn(txn, 0, 1, 0, TimelineState::NotReady, false);
+    }
+    add_txn(&mut pool, TestTransaction::new(1, 3, 1)).unwrap();
+
+    // only the transactions which expired as of the block timestamp are recorded
+    pool.gc_by_expiration_time(Duration::from_secs(1));
+    let expired: Vec<_> = pool
+        .get_expired_transactions(&TestTransaction::get_address(1))
+        .into_iter()
+        .map(|(txn, block_time)| (txn.sequence_number(), block_time))
+        .collect();
+    assert_eq!(expired, vec![(0, Duration::from_secs(1))]);
+
+    // only the latest expired transactions are remembered
+    pool.gc_by_expiration_time(Duration::from_secs(3));
+    let expired: Vec<_> = pool
+        .get_expired_transactions(&TestTransaction::get_address(1))
+        .into_iter()
+        .map(|(txn, _)| txn.sequence_number())
+        .collect();
+    assert_eq!(expired, vec![1, 2]);
+    assert!(pool
+        .get_expired_transactions(&TestTransaction::get_address(0))
+        .is_empty());
+}
+
 #[test]
 fn test_clean_stuck_transactions() {
     let mut pool = setup_mempool().0;
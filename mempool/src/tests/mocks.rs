@@ -5,11 +5,11 @@ use crate::{
     core_mempool::{CoreMempool, TimelineState},
     network::{MempoolNetworkEvents, MempoolNetworkSender},
     shared_mempool::start_shared_mempool,
-    CommitNotification, ConsensusRequest, SubmissionStatus,
+    CommitNotification, ConsensusRequest, MempoolClientSender,
 };
 use anyhow::{format_err, Result};
 use channel::{self, libra_channel, message_queues::QueueStyle};
-use futures::channel::{mpsc, oneshot};
+use futures::channel::mpsc;
 use libra_config::{
     config::{NetworkConfig, NodeConfig},
     network_id::NetworkId,
@@ -30,7 +30,7 @@ use vm_validator::mocks::mock_vm_validator::MockVMValidator;
 pub struct MockSharedMempool {
     _runtime: Runtime,
     /// sender from admission control to shared mempool
-    pub ac_client: mpsc::Sender<(SignedTransaction, oneshot::Sender<Result<SubmissionStatus>>)>,
+    pub ac_client: MempoolClientSender,
     /// mempool
     pub mempool: Arc<Mutex<CoreMempool>>,
     /// sender from consensus to shared mempool
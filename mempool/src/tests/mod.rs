@@ -1,9 +1,13 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(test)]
+mod admission_control_test;
 #[cfg(test)]
 mod common;
 #[cfg(test)]
+mod core_mempool_model_test;
+#[cfg(test)]
 mod core_mempool_test;
 #[cfg(test)]
 mod shared_mempool_test;
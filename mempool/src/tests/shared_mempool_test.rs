@@ -28,9 +28,10 @@ use libra_network_address::NetworkAddress;
 use libra_types::{transaction::SignedTransaction, PeerId};
 use network::{
     peer_manager::{
-        conn_notifs_channel, ConnectionNotification, ConnectionRequestSender,
+        conn_notifs_channel, ConnectionInfo, ConnectionNotification, ConnectionRequestSender,
         PeerManagerNotification, PeerManagerRequest, PeerManagerRequestSender,
     },
+    protocols::wire::handshake::v1::Encoding,
     DisconnectReason, ProtocolId,
 };
 use std::{
@@ -322,7 +323,7 @@ impl SharedMempoolNetwork {
         let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
         let network_req = block_on(network_reqs_rx.next()).unwrap();
 
-        if let PeerManagerRequest::SendMessage(peer_id, msg) = network_req {
+        if let PeerManagerRequest::SendMessage(peer_id, msg, _) = network_req {
             let sync_msg = lcs::from_bytes(&msg.mdata).unwrap();
             if let MempoolSyncMsg::BroadcastTransactionsRequest { transactions, .. } = sync_msg {
                 // send it to peer
@@ -330,7 +331,7 @@ impl SharedMempoolNetwork {
                 receiver_network_notif_tx
                     .push(
                         (*peer, ProtocolId::MempoolDirectSend),
-                        PeerManagerNotification::RecvMessage(*peer, msg),
+                        PeerManagerNotification::RecvMessage(*peer, msg, Encoding::Lcs),
                     )
                     .unwrap();
 
@@ -362,7 +363,7 @@ impl SharedMempoolNetwork {
         let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
         let network_req = block_on(network_reqs_rx.next()).unwrap();
 
-        if let PeerManagerRequest::SendMessage(peer_id, msg) = network_req {
+        if let PeerManagerRequest::SendMessage(peer_id, msg, _) = network_req {
             let sync_msg = lcs::from_bytes(&msg.mdata).unwrap();
             if let MempoolSyncMsg::BroadcastTransactionsResponse { .. } = sync_msg {
                 // send it to peer
@@ -370,7 +371,7 @@ impl SharedMempoolNetwork {
                 receiver_network_notif_tx
                     .push(
                         (*peer, ProtocolId::MempoolDirectSend),
-                        PeerManagerNotification::RecvMessage(*peer, msg),
+                        PeerManagerNotification::RecvMessage(*peer, msg, Encoding::Lcs),
                     )
                     .unwrap();
 
@@ -412,7 +413,7 @@ fn test_basic_flow() {
     // A discovers new peer B
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     for seq in 0..3 {
@@ -444,7 +445,7 @@ fn test_metric_cache_ignore_shared_txns() {
     // Let peer_a discover new peer_b.
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     for txn in txns.iter().take(3) {
         // Let peer_a share txns with peer_b
@@ -468,7 +469,7 @@ fn test_interruption_in_sync() {
     // A discovers first peer
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     // make sure first txn delivered to first peer
     assert_eq!(*peer_b, smp.deliver_message(&peer_a, 1, true).1);
@@ -476,7 +477,7 @@ fn test_interruption_in_sync() {
     // A discovers second peer
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_c, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_c, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     // make sure first txn delivered to second peer
     assert_eq!(*peer_c, smp.deliver_message(&peer_a, 1, true).1);
@@ -505,7 +506,7 @@ fn test_interruption_in_sync() {
     // A reconnects to B
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // B should receive transaction 2
@@ -525,7 +526,7 @@ fn test_ready_transactions() {
     // first message delivery
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     smp.deliver_message(&peer_a, 1, true);
 
@@ -547,11 +548,11 @@ fn test_broadcast_self_transactions() {
     // A and B discover each other
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     smp.send_connection_event(
         &peer_b,
-        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // A sends txn to B
@@ -583,11 +584,11 @@ fn test_broadcast_dependencies() {
     // A and B discover each other
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     smp.send_connection_event(
         &peer_b,
-        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // B receives 0
@@ -611,11 +612,11 @@ fn test_broadcast_updated_transaction() {
     // A and B discover each other
     smp.send_connection_event(
         &peer_a,
-        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_b, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     smp.send_connection_event(
         &peer_b,
-        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(*peer_a, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // B receives 0
@@ -752,7 +753,7 @@ fn test_broadcast_ack_single_account_single_peer() {
     // FN discovers new peer V
     smp.send_connection_event(
         &full_node,
-        ConnectionNotification::NewPeer(validator, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(validator, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // deliver messages until FN mempool is empty
@@ -797,7 +798,7 @@ fn test_broadcast_ack_multiple_accounts_single_peer() {
     // full node discovers new validator peer
     smp.send_connection_event(
         &full_node,
-        ConnectionNotification::NewPeer(validator, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(validator, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // deliver message
@@ -859,11 +860,11 @@ fn test_k_policy_broadcast_no_fallback() {
     // fn_0 discovers primary and fallback upstream peers
     smp.send_connection_event(
         &fn_0,
-        ConnectionNotification::NewPeer(v_0, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(v_0, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
     smp.send_connection_event(
         &fn_0_fallback_network_id,
-        ConnectionNotification::NewPeer(fn_1, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(fn_1, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // add txn to fn_0
@@ -907,7 +908,7 @@ fn test_k_policy_broadcast_not_enough_fallbacks() {
     // fn_0 discovers primary peer but no fallback peers available
     smp.send_connection_event(
         &fn_0,
-        ConnectionNotification::NewPeer(v_0, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(v_0, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     // add txn to fn_0
@@ -920,6 +921,59 @@ fn test_k_policy_broadcast_not_enough_fallbacks() {
     smp.assert_no_message_sent(&fn_0_fallback_network_id);
 }
 
+#[test]
+fn test_load_balancing_failover() {
+    let v_0 = PeerId::random();
+    let v_1 = PeerId::random();
+    let fn_0 = PeerId::random();
+
+    // fn_0 balances its txns between v_0 and v_1
+    let mut fn_0_config = NodeConfig::default();
+    fn_0_config.mempool.shared_mempool_batch_size = 1;
+    fn_0_config.upstream.load_balancing = true;
+    for validator in &[v_0, v_1] {
+        fn_0_config
+            .upstream
+            .upstream_peers
+            .insert(PeerNetworkId(fn_0, *validator));
+    }
+
+    let mut smp = SharedMempoolNetwork::default();
+    init_single_shared_mempool(&mut smp, v_0, NodeConfig::default());
+    init_single_shared_mempool(&mut smp, v_1, NodeConfig::default());
+    init_single_shared_mempool(&mut smp, fn_0, fn_0_config);
+    for validator in &[v_0, v_1] {
+        smp.send_connection_event(
+            &fn_0,
+            ConnectionNotification::NewPeer(
+                *validator,
+                NetworkAddress::mock(),
+                ConnectionInfo::mock(),
+            ),
+        );
+    }
+
+    // the txn is only forwarded to the peer its sender is assigned to
+    smp.add_txns(&fn_0, vec![TestTransaction::new(1, 0, 1)]);
+    let (txns, assigned_peer) = smp.deliver_message(&fn_0, 1, true);
+    assert_eq!(txns.len(), 1);
+    smp.assert_no_message_sent(&fn_0);
+
+    // once that peer is lost, txns of the same sender fail over to the other peer
+    smp.send_connection_event(
+        &fn_0,
+        ConnectionNotification::LostPeer(
+            assigned_peer,
+            NetworkAddress::mock(),
+            DisconnectReason::ConnectionLost,
+        ),
+    );
+    smp.add_txns(&fn_0, vec![TestTransaction::new(1, 1, 1)]);
+    let (txns, peer) = smp.deliver_message(&fn_0, 1, false);
+    assert_ne!(peer, assigned_peer);
+    assert_eq!(txns.get(0).unwrap().sequence_number(), 1);
+}
+
 #[test]
 fn test_rebroadcast_mempool_is_full() {
     let (mut smp, val, full_node) = SharedMempoolNetwork::bootstrap_vfn_network(3, Some(5), None);
@@ -932,7 +986,7 @@ fn test_rebroadcast_mempool_is_full() {
     // FN discovers new peer V
     smp.send_connection_event(
         &full_node,
-        ConnectionNotification::NewPeer(val, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(val, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     let (txns, _recipient) = smp.deliver_message(&full_node, 1, true);
@@ -992,7 +1046,7 @@ fn test_rebroadcast_too_many_txns() {
     // FN discovers new peer V
     smp.send_connection_event(
         &full_node,
-        ConnectionNotification::NewPeer(val, NetworkAddress::mock()),
+        ConnectionNotification::NewPeer(val, NetworkAddress::mock(), ConnectionInfo::mock()),
     );
 
     let (txns, _recipient) = smp.deliver_message(&full_node, 1, true);
@@ -0,0 +1,102 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stable API of the network to its applications, e.g., consensus, mempool and state sync: the
+//! events the applications receive from the network, and the senders of their messages.
+//!
+//! The applications compile against these traits rather than the implementation of the network
+//! in the `network` crate, so that another implementation, e.g., a simulated network in tests, can
+//! be swapped in. Any `Stream` of [`Event`]s is an [`EventStream`], and the `NetworkSender` of the
+//! `network` crate is a [`MessageSender`].
+
+use bytes::Bytes;
+use futures::{
+    channel::oneshot,
+    future::BoxFuture,
+    stream::{FusedStream, Stream},
+};
+use libra_types::PeerId;
+use std::time::Duration;
+
+/// Events received by the applications from the network.
+///
+/// The inbound direct-send messages and RPC requests are deserialized into the type `TMessage`,
+/// which encapsulates all the messages and RPCs received by the application. The responses of the
+/// RPC requests fail with the `TRpcError` of the network implementation.
+#[derive(Debug)]
+pub enum Event<TMessage, TRpcError> {
+    /// New inbound direct-send message from peer.
+    Message((PeerId, TMessage)),
+    /// New inbound rpc request. The request is fulfilled by sending the
+    /// serialized response `Bytes` over the `oneshot::Sender`, where the network
+    /// layer will handle sending the response over-the-wire.
+    RpcRequest((PeerId, TMessage, oneshot::Sender<Result<Bytes, TRpcError>>)),
+    /// Peer which we have a newly established connection with.
+    NewPeer(PeerId),
+    /// Peer with which we've lost our connection.
+    LostPeer(PeerId),
+}
+
+/// impl PartialEq for simpler testing
+impl<TMessage: PartialEq, TRpcError> PartialEq for Event<TMessage, TRpcError> {
+    fn eq(&self, other: &Event<TMessage, TRpcError>) -> bool {
+        use Event::*;
+        match (self, other) {
+            (Message((pid1, msg1)), Message((pid2, msg2))) => pid1 == pid2 && msg1 == msg2,
+            // ignore oneshot::Sender in comparison
+            (RpcRequest((pid1, msg1, _)), RpcRequest((pid2, msg2, _))) => {
+                pid1 == pid2 && msg1 == msg2
+            }
+            (NewPeer(pid1), NewPeer(pid2)) => pid1 == pid2,
+            (LostPeer(pid1), LostPeer(pid2)) => pid1 == pid2,
+            _ => false,
+        }
+    }
+}
+
+/// The events of an application, as received from the network, or from a simulated network in
+/// tests. Implemented by every `Stream` of [`Event`]s.
+pub trait EventStream<TMessage, TRpcError, TError>:
+    Stream<Item = Result<Event<TMessage, TRpcError>, TError>> + FusedStream
+{
+}
+
+impl<TMessage, TRpcError, TError, TStream> EventStream<TMessage, TRpcError, TError> for TStream where
+    TStream: Stream<Item = Result<Event<TMessage, TRpcError>, TError>> + FusedStream
+{
+}
+
+/// Sends the messages of an application to its peers, serializing them into `TMessage`.
+pub trait MessageSender<TMessage> {
+    /// Identifier of the protocol of a message, e.g., the `ProtocolId` of the `network` crate.
+    type Protocol: Copy + Send;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Sends a direct-send message to a single recipient.
+    fn send_to(
+        &mut self,
+        recipient: PeerId,
+        protocol: Self::Protocol,
+        message: TMessage,
+    ) -> Result<(), Self::Error>;
+
+    /// Sends a direct-send message to many recipients.
+    fn send_to_many<I>(
+        &mut self,
+        recipients: I,
+        protocol: Self::Protocol,
+        message: TMessage,
+    ) -> Result<(), Self::Error>
+    where
+        I: Iterator<Item = PeerId>;
+
+    /// Sends an rpc request to a single recipient, and waits for its response, of the same
+    /// message type, until `timeout`.
+    fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        protocol: Self::Protocol,
+        req_msg: TMessage,
+        timeout: Duration,
+    ) -> BoxFuture<'_, Result<TMessage, Self::Error>>;
+}
@@ -20,12 +20,12 @@ use futures::{
     stream::{FuturesUnordered, StreamExt},
 };
 use libra_types::PeerId;
-use network::protocols::{
-    network::{
+use network::{
+    error::NetworkError,
+    protocols::network::{
         dummy::{setup_network, DummyMsg, DummyNetworkSender},
         Event,
     },
-    rpc::error::RpcError,
 };
 use std::time::Duration;
 
@@ -121,7 +121,7 @@ async fn send_rpc(
     mut sender: DummyNetworkSender,
     recipient: PeerId,
     req_msg: DummyMsg,
-) -> Result<DummyMsg, RpcError> {
+) -> Result<DummyMsg, NetworkError> {
     sender
         .send_rpc(recipient, req_msg, Duration::from_secs(15))
         .await
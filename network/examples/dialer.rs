@@ -0,0 +1,98 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A dialer connecting to the `echo_listener` example over Noise, sending it a DirectSend message
+//! and checking that it echoes a RPC request, built with the public API of the network crate.
+//!
+//! Run it with
+//!
+//! `cargo run -p network --example dialer <address printed by the echo listener>`
+//!
+//! e.g., `/ip4/127.0.0.1/tcp/6180/ln-noise-ik/<public key>/ln-handshake/0`. It exits with an error
+//! if the listener cannot be reached or does not echo the request.
+
+use bytes::Bytes;
+use channel::message_queues::QueueStyle;
+use libra_config::{config::RoleType, network_id::NetworkId};
+use libra_crypto::{x25519, Uniform};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use network::{
+    priority::ProtocolPriority,
+    validator_network::network_builder::{AuthenticationMode, NetworkBuilder},
+    ProtocolId,
+};
+use std::{env, time::Duration};
+use tokio::runtime::Runtime;
+
+/// The protocols of the `echo_listener` example.
+const ECHO_RPC_PROTOCOL: ProtocolId = ProtocolId::ConsensusRpc;
+const ECHO_DIRECT_SEND_PROTOCOL: ProtocolId = ProtocolId::ConsensusDirectSend;
+
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() {
+    ::libra_logger::Logger::new().init();
+    let listener_addr: NetworkAddress = env::args()
+        .nth(1)
+        .expect("Usage: dialer <listener address>")
+        .parse()
+        .expect("Invalid listener address");
+    // The echo listener derives its peer id from its key, which its address carries.
+    let listener_pubkey = listener_addr
+        .find_noise_proto()
+        .expect("The listener address lacks the /ln-noise-ik/<public key> of the listener");
+    let listener_peer_id = PeerId::from_identity_public_key(listener_pubkey);
+
+    let mut runtime = Runtime::new().unwrap();
+    let key = x25519::PrivateKey::generate(&mut rand::rngs::OsRng);
+    let mut network_builder = NetworkBuilder::new(
+        runtime.handle().clone(),
+        NetworkId::Public,
+        PeerId::default(),
+        RoleType::FullNode,
+        vec!["/ip4/127.0.0.1/tcp/0".parse().unwrap()],
+    );
+    network_builder.authentication_mode(AuthenticationMode::ServerOnly(key));
+    let (mut sender, _events, mut connection_reqs_tx, _connection_notifs) = network_builder
+        .add_protocol_handler(
+            vec![ECHO_RPC_PROTOCOL],
+            vec![ECHO_DIRECT_SEND_PROTOCOL],
+            ProtocolPriority::Normal,
+            QueueStyle::FIFO,
+            128,
+            None,
+        );
+    network_builder
+        .build()
+        .expect("Failed to build the network");
+
+    runtime.block_on(async move {
+        connection_reqs_tx
+            .dial_peer(listener_peer_id, listener_addr.clone())
+            .await
+            .expect("Failed to dial the listener");
+        println!("Connected to {} at {}", listener_peer_id, listener_addr);
+
+        sender
+            .send_to(
+                listener_peer_id,
+                ECHO_DIRECT_SEND_PROTOCOL,
+                Bytes::from_static(b"hello from the dialer"),
+            )
+            .expect("Failed to send the DirectSend message");
+
+        let request = Bytes::from_static(b"echo");
+        let response = sender
+            .send_rpc(
+                listener_peer_id,
+                ECHO_RPC_PROTOCOL,
+                request.clone(),
+                RPC_TIMEOUT,
+            )
+            .await
+            .expect("RPC request failed");
+        assert_eq!(response, request, "The listener did not echo the request");
+        println!("The listener echoed the RPC request");
+    });
+}
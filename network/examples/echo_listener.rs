@@ -0,0 +1,89 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A listener echoing the RPC requests of the peers connecting to it, and printing their
+//! DirectSend messages, built with the public API of the network crate.
+//!
+//! Run it with
+//!
+//! `cargo run -p network --example echo_listener [<listen address, /ip4/127.0.0.1/tcp/6180 by default>]`
+//!
+//! then run the `dialer` example with the full address it prints, including its Noise public key.
+
+use channel::message_queues::QueueStyle;
+use futures::StreamExt;
+use libra_config::{config::RoleType, network_id::NetworkId};
+use libra_crypto::{x25519, Uniform};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use network::{
+    peer_manager::PeerManagerNotification,
+    priority::ProtocolPriority,
+    validator_network::network_builder::{AuthenticationMode, NetworkBuilder},
+    ProtocolId,
+};
+use std::env;
+use tokio::runtime::Runtime;
+
+/// The examples borrow the protocols of consensus: any `ProtocolId` supported by both peers works.
+const ECHO_RPC_PROTOCOL: ProtocolId = ProtocolId::ConsensusRpc;
+const ECHO_DIRECT_SEND_PROTOCOL: ProtocolId = ProtocolId::ConsensusDirectSend;
+
+fn main() {
+    ::libra_logger::Logger::new().init();
+    let listen_addr: NetworkAddress = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/ip4/127.0.0.1/tcp/6180".to_string())
+        .parse()
+        .expect("Invalid listen address");
+
+    let mut runtime = Runtime::new().unwrap();
+    // A full node accepting the connections of any peer, whose peer id is derived from its key.
+    let key = x25519::PrivateKey::generate(&mut rand::rngs::OsRng);
+    let mut network_builder = NetworkBuilder::new(
+        runtime.handle().clone(),
+        NetworkId::Public,
+        PeerId::default(),
+        RoleType::FullNode,
+        vec![listen_addr],
+    );
+    network_builder.authentication_mode(AuthenticationMode::ServerOnly(key));
+    let (_sender, mut events, _connection_reqs_tx, _connection_notifs) = network_builder
+        .add_protocol_handler(
+            vec![ECHO_RPC_PROTOCOL],
+            vec![ECHO_DIRECT_SEND_PROTOCOL],
+            ProtocolPriority::Normal,
+            QueueStyle::FIFO,
+            128,
+            None,
+        );
+    let listen_addr = network_builder
+        .build()
+        .expect("Failed to build the network")
+        .listen_addrs()[0]
+        .clone();
+    println!("Listening on {}", listen_addr);
+
+    runtime.block_on(async move {
+        while let Some(notification) = events.next().await {
+            match notification {
+                PeerManagerNotification::RecvRpc(peer_id, request, _) => {
+                    println!(
+                        "Echoing RPC request of {} bytes from {}",
+                        request.data.len(),
+                        peer_id
+                    );
+                    // The dialer may have given up on the response already.
+                    let _ = request.res_tx.send(Ok(request.data));
+                }
+                PeerManagerNotification::RecvMessage(peer_id, message, _) => {
+                    println!(
+                        "Received DirectSend message from {}: {}",
+                        peer_id,
+                        String::from_utf8_lossy(&message.mdata)
+                    );
+                }
+            }
+        }
+    });
+}
@@ -19,8 +19,13 @@ use std::time::Duration;
 pub mod and_then;
 pub mod boxed;
 pub mod memory;
+pub mod or;
+pub mod relay;
 pub mod tcp;
 pub mod timeout;
+#[cfg(unix)]
+pub mod uds;
+pub mod websocket;
 
 /// Origin of how a Connection was established.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -135,6 +140,20 @@ pub trait TransportExt: Transport {
         and_then::AndThen::new(self, f)
     }
 
+    /// Combines this [`Transport`] with `other`, listening on and dialing every address with this
+    /// transport, or with `other` if this transport does not support the address, i.e., fails
+    /// with an [`InvalidInput`](std::io::ErrorKind::InvalidInput) error.
+    ///
+    /// The connections of either transport are yielded as an [`Either`](futures::future::Either)
+    /// of their outputs.
+    fn or<T>(self, other: T) -> or::OrTransport<Self, T>
+    where
+        Self: Sized,
+        T: Transport,
+    {
+        or::OrTransport::new(self, other)
+    }
+
     /// Wraps a [`Transport`] with a timeout to the
     /// [Inbound](Transport::Inbound) and [Outbound](Transport::Outbound)
     /// connection futures.
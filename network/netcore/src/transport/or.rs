@@ -0,0 +1,144 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transport which listens on and dials the addresses of either of two transports.
+
+use crate::transport::Transport;
+use futures::{
+    future::{Either, Future, FutureExt, TryFutureExt},
+    stream::{Stream, StreamExt, TryStreamExt},
+};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use std::{io, pin::Pin};
+
+/// See the [or](crate::transport::TransportExt::or) method for more information.
+#[derive(Debug, Clone)]
+pub struct OrTransport<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> OrTransport<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+/// Whether `err` is the error of a transport which does not support the address.
+fn is_unsupported_addr(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::InvalidInput
+}
+
+impl<A, B> Transport for OrTransport<A, B>
+where
+    A: Transport<Error = io::Error>,
+    A::Output: Send + 'static,
+    A::Listener: 'static,
+    A::Inbound: 'static,
+    A::Outbound: 'static,
+    B: Transport<Error = io::Error>,
+    B::Output: Send + 'static,
+    B::Listener: 'static,
+    B::Inbound: 'static,
+    B::Outbound: 'static,
+{
+    type Output = Either<A::Output, B::Output>;
+    type Error = io::Error;
+    type Listener =
+        Pin<Box<dyn Stream<Item = io::Result<(Self::Inbound, NetworkAddress)>> + Send + 'static>>;
+    type Inbound = Pin<Box<dyn Future<Output = io::Result<Self::Output>> + Send + 'static>>;
+    type Outbound = Pin<Box<dyn Future<Output = io::Result<Self::Output>> + Send + 'static>>;
+
+    fn listen_on(
+        &self,
+        addr: NetworkAddress,
+    ) -> Result<(Self::Listener, NetworkAddress), Self::Error> {
+        match self.first.listen_on(addr.clone()) {
+            Ok((listener, listen_addr)) => {
+                let listener: Self::Listener = listener
+                    .map_ok(|(inbound, dialer_addr)| {
+                        (inbound.map_ok(Either::Left).boxed(), dialer_addr)
+                    })
+                    .boxed();
+                Ok((listener, listen_addr))
+            }
+            Err(err) if is_unsupported_addr(&err) => {
+                let (listener, listen_addr) = self.second.listen_on(addr)?;
+                let listener: Self::Listener = listener
+                    .map_ok(|(inbound, dialer_addr)| {
+                        (inbound.map_ok(Either::Right).boxed(), dialer_addr)
+                    })
+                    .boxed();
+                Ok((listener, listen_addr))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn dial(&self, peer_id: PeerId, addr: NetworkAddress) -> Result<Self::Outbound, Self::Error> {
+        match self.first.dial(peer_id, addr.clone()) {
+            Ok(outbound) => Ok(outbound.map_ok(Either::Left).boxed()),
+            Err(err) if is_unsupported_addr(&err) => {
+                let outbound = self.second.dial(peer_id, addr)?;
+                Ok(outbound.map_ok(Either::Right).boxed())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{memory::MemoryTransport, tcp::TcpTransport, TransportExt};
+    use futures::{
+        future::join,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    async fn listen_and_dial<T>(transport: &T, addr: NetworkAddress) -> io::Result<()>
+    where
+        T: Transport<Error = io::Error>,
+        T::Output: futures::io::AsyncRead + futures::io::AsyncWrite + Unpin,
+    {
+        let (listener, addr) = transport.listen_on(addr)?;
+        let dial = transport.dial(PeerId::random(), addr)?;
+        let listener = listener.into_future().then(|(maybe_result, _stream)| {
+            let (incoming, _addr) = maybe_result.unwrap().unwrap();
+            incoming
+        });
+
+        let (outbound, inbound) = join(dial, listener).await;
+        let (mut outbound, mut inbound) = (outbound?, inbound?);
+        outbound.write_all(b"Earth").await?;
+        let mut buf = [0; 5];
+        inbound.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Earth");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn listen_and_dial_either_transport() {
+        let t = TcpTransport::default().or(MemoryTransport);
+
+        listen_and_dial(&t, "/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .await
+            .unwrap();
+        listen_and_dial(&t, "/memory/0".parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn unsupported_multiaddrs() {
+        let t = TcpTransport::default().or(MemoryTransport);
+
+        let result = t.listen_on("/dns4/example.com/tcp/22".parse().unwrap());
+        assert!(result.is_err());
+
+        let peer_id = PeerId::random();
+        let result = t.dial(peer_id, "/ln-handshake/0".parse().unwrap());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,1086 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Relay Transport
+//!
+//! Lets a peer which can't accept inbound connections, e.g., behind a symmetric NAT, be dialed
+//! through a publicly reachable relay, at a `/<relay>/p2p-circuit` address, where `<relay>` is
+//! the `/ip{4,6}/<addr>/tcp/<port>` or `/dns{,4,6}/<name>/tcp/<port>` address of the
+//! [`RelayServer`].
+//!
+//! The relay only splices raw byte streams: the protocols above the base transport, e.g., Noise,
+//! run end-to-end between the two real endpoints, so the relay can neither read nor forge their
+//! traffic.
+//!
+//! The protocol between the clients and the relay is made of u16 length-prefixed frames, the
+//! first one of each connection to the relay being a request:
+//!
+//! * A peer listening on a circuit address keeps a control connection to the relay, on which it
+//!   sends a `Reserve` request with its `PeerId`. The connection is then authenticated by a
+//!   [`RelayAuthenticator`], e.g., with the Noise handshake of the network, and the peer repeats
+//!   its request over the authenticated connection. The relay only reserves the authenticated
+//!   `PeerId`, answers `Ok`, and then sends an `Incoming` frame with a random 128-bit circuit id
+//!   for every dialer of the peer, until the reservation expires with an `Expired` frame, after
+//!   which the peer reserves again.
+//! * A dialer sends a `Connect` request with the `PeerId` of the peer it dials. The relay asks
+//!   the peer to accept the circuit, and answers `Ok` once it did, or an error code otherwise.
+//! * The peer accepts a circuit by opening a new connection to the relay, on which it sends an
+//!   `Accept` request with the circuit id. The connection is authenticated as the control ones,
+//!   and the peer repeats its request over the authenticated connection. The relay only hands the
+//!   circuit to the peer which reserved the dialed `PeerId`, answers `Ok` over the authenticated
+//!   connection, and then splices the underlying connection with the dialer connection.
+//!
+//! A `PeerId` can thus only be reserved by its owner, whose last reservation gets the circuits.
+//! The reservations are limited overall and per source IP address, so that a single host can't
+//! take all of them with made-up identities, and expire so that the abandoned ones are released.
+
+use crate::{
+    framing::{read_u16frame, write_u16frame},
+    transport::Transport,
+};
+use bytes::BytesMut;
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture, Future, FutureExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    stream::{self, Stream, StreamExt},
+};
+use libra_network_address::{parse_circuit, NetworkAddress, Protocol};
+use libra_types::PeerId;
+use rand::Rng;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::{TryFrom, TryInto},
+    io,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{delay_for, timeout};
+
+/// Maximum number of peers with a reservation on a relay.
+pub const MAX_RESERVATIONS: usize = 256;
+/// Maximum number of reservations from the same source IP address.
+pub const MAX_RESERVATIONS_PER_SOURCE: usize = 8;
+/// Lifetime of a reservation, after which the peer reserves again.
+pub const RESERVATION_TTL: Duration = Duration::from_secs(3600);
+/// Maximum number of circuits, pending or spliced, through a relay.
+pub const MAX_CIRCUITS: usize = 1024;
+/// Time for a client of the relay to send its request, and for a peer to accept a circuit.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time to wait before reserving again after the control connection to the relay failed.
+const RESERVE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+const RESERVE: u8 = 0;
+const CONNECT: u8 = 1;
+const ACCEPT: u8 = 2;
+const INCOMING: u8 = 3;
+const EXPIRED: u8 = 4;
+
+/// Status codes of the answers of the relay.
+const OK: u8 = 0;
+const NO_RESERVATION: u8 = 1;
+const RELAY_FULL: u8 = 2;
+const ACCEPT_TIMEOUT: u8 = 3;
+const SOURCE_FULL: u8 = 4;
+const UNKNOWN_CIRCUIT: u8 = 5;
+
+/// Authentication of the control connections of the reservations, and of the connections
+/// accepting the circuits, so that a peer can only reserve, and accept the circuits of, its own
+/// `PeerId` on a relay.
+pub trait RelayAuthenticator<S>: Clone + Send + Sync + 'static {
+    type Output: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Authenticates the peer to the relay on `socket`, a connection to the relay.
+    fn authenticate(&self, socket: S) -> BoxFuture<'static, io::Result<Self::Output>>;
+
+    /// Authenticates the peer on `socket`, an inbound connection of the relay, returning its
+    /// `PeerId`.
+    fn authenticate_peer(
+        &self,
+        socket: S,
+    ) -> BoxFuture<'static, io::Result<(Self::Output, PeerId)>>;
+
+    /// Returns the connection under the authenticated `output`, once both ends flushed the last
+    /// frame they send over `output` and read the last frame they receive over it.
+    fn into_socket(output: Self::Output) -> S;
+}
+
+/// The first frame sent to the relay on each connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Request {
+    Reserve(PeerId),
+    Connect(PeerId),
+    Accept(u128),
+}
+
+impl Request {
+    fn encode(self) -> Vec<u8> {
+        let (kind, arg) = match self {
+            Request::Reserve(peer_id) => (RESERVE, peer_id.to_vec()),
+            Request::Connect(peer_id) => (CONNECT, peer_id.to_vec()),
+            Request::Accept(circuit_id) => (ACCEPT, circuit_id.to_be_bytes().to_vec()),
+        };
+        let mut message = vec![kind];
+        message.extend_from_slice(&arg);
+        message
+    }
+
+    fn decode(message: &[u8]) -> io::Result<Self> {
+        let request = match message.split_first() {
+            Some((&RESERVE, peer_id)) => PeerId::try_from(peer_id).map(Request::Reserve).ok(),
+            Some((&CONNECT, peer_id)) => PeerId::try_from(peer_id).map(Request::Connect).ok(),
+            Some((&ACCEPT, circuit_id)) => decode_circuit_id(circuit_id).map(Request::Accept),
+            _ => None,
+        };
+        request.ok_or_else(|| invalid_data_error("Invalid relay request"))
+    }
+}
+
+/// The frames sent by the relay on the control connection of a reservation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Notification {
+    Incoming(u128),
+    Expired,
+}
+
+impl Notification {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Notification::Incoming(circuit_id) => {
+                let mut message = vec![INCOMING];
+                message.extend_from_slice(&circuit_id.to_be_bytes());
+                message
+            }
+            Notification::Expired => vec![EXPIRED],
+        }
+    }
+
+    fn decode(message: &[u8]) -> io::Result<Self> {
+        let notification = match message.split_first() {
+            Some((&INCOMING, circuit_id)) => {
+                decode_circuit_id(circuit_id).map(Notification::Incoming)
+            }
+            Some((&EXPIRED, [])) => Some(Notification::Expired),
+            _ => None,
+        };
+        notification.ok_or_else(|| invalid_data_error("Invalid relay notification"))
+    }
+}
+
+fn decode_circuit_id(bytes: &[u8]) -> Option<u128> {
+    bytes.try_into().ok().map(u128::from_be_bytes)
+}
+
+fn invalid_data_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_addr_error(addr: &NetworkAddress) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid NetworkAddress: '{}'", addr),
+    )
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(socket: &mut S, frame: &[u8]) -> io::Result<()> {
+    write_u16frame(socket, frame).await?;
+    socket.flush().await
+}
+
+async fn read_frame<S: AsyncRead + Unpin>(socket: &mut S) -> io::Result<BytesMut> {
+    let mut buf = BytesMut::new();
+    read_u16frame(socket, &mut buf).await?;
+    Ok(buf)
+}
+
+async fn timeout_io<T>(fut: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    timeout(REQUEST_TIMEOUT, fut)
+        .await
+        .unwrap_or_else(|err| Err(io::Error::new(io::ErrorKind::TimedOut, err)))
+}
+
+/// The IP address of the client of the relay at `addr`, if any.
+fn source_ip(addr: &NetworkAddress) -> Option<IpAddr> {
+    match addr.as_slice().first() {
+        Some(Protocol::Ip4(addr)) => Some(IpAddr::V4(*addr)),
+        Some(Protocol::Ip6(addr)) => Some(IpAddr::V6(*addr)),
+        _ => None,
+    }
+}
+
+fn is_circuit(addr: &NetworkAddress) -> bool {
+    addr.as_slice().contains(&Protocol::P2pCircuit)
+}
+
+/// Parses the address of the relay of a `/<relay>/p2p-circuit` address, with no protocol after
+/// the circuit.
+fn parse_relay_addr(addr: &NetworkAddress) -> Option<NetworkAddress> {
+    match parse_circuit(addr.as_slice()) {
+        Some((relay_protos, [])) => NetworkAddress::try_from(relay_protos.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+/////////////////////
+// Relay transport //
+/////////////////////
+
+type BoxedIoFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'static>>;
+
+/// Transport which dials and listens on `/<relay>/p2p-circuit` addresses through a relay, and on
+/// the other addresses through the inner transport, which also connects to the relay.
+#[derive(Clone, Debug)]
+pub struct RelayTransport<T, A> {
+    inner: T,
+    /// The `PeerId` reserved on the relay when listening on a circuit address.
+    peer_id: PeerId,
+    /// The authentication of the reservations of `peer_id`.
+    auth: A,
+}
+
+impl<T, A> RelayTransport<T, A> {
+    pub fn new(inner: T, peer_id: PeerId, auth: A) -> Self {
+        Self {
+            inner,
+            peer_id,
+            auth,
+        }
+    }
+}
+
+impl<T, A> Transport for RelayTransport<T, A>
+where
+    T: Transport<Error = io::Error> + Clone + Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Listener: Send + 'static,
+    T::Inbound: Send + 'static,
+    T::Outbound: Send + 'static,
+    A: RelayAuthenticator<T::Output>,
+{
+    type Output = T::Output;
+    type Error = io::Error;
+    type Listener =
+        Pin<Box<dyn Stream<Item = io::Result<(Self::Inbound, NetworkAddress)>> + Send + 'static>>;
+    type Inbound = BoxedIoFuture<T::Output>;
+    type Outbound = BoxedIoFuture<T::Output>;
+
+    fn listen_on(
+        &self,
+        addr: NetworkAddress,
+    ) -> Result<(Self::Listener, NetworkAddress), Self::Error> {
+        if !is_circuit(&addr) {
+            let (listener, listen_addr) = self.inner.listen_on(addr)?;
+            let listener = listener
+                .map(|result| result.map(|(inbound, addr)| (inbound.boxed(), addr)))
+                .boxed();
+            return Ok((listener, listen_addr));
+        }
+
+        let relay_addr = parse_relay_addr(&addr).ok_or_else(|| invalid_addr_error(&addr))?;
+        let listener = circuit_listener(
+            self.inner.clone(),
+            self.auth.clone(),
+            self.peer_id,
+            relay_addr,
+            addr.clone(),
+        );
+        Ok((listener, addr))
+    }
+
+    fn dial(&self, peer_id: PeerId, addr: NetworkAddress) -> Result<Self::Outbound, Self::Error> {
+        if !is_circuit(&addr) {
+            return Ok(self.inner.dial(peer_id, addr)?.boxed());
+        }
+
+        let relay_addr = parse_relay_addr(&addr).ok_or_else(|| invalid_addr_error(&addr))?;
+        let connect = self.inner.dial(peer_id, relay_addr)?;
+        let f = async move {
+            let mut socket = connect.await?;
+            write_frame(&mut socket, &Request::Connect(peer_id).encode()).await?;
+            let answer = timeout_io(read_frame(&mut socket)).await?;
+            match answer.as_ref() {
+                [OK] => Ok(socket),
+                [NO_RESERVATION] => Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("Peer {} has no reservation on the relay", peer_id),
+                )),
+                [RELAY_FULL] => Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "The relay has too many circuits",
+                )),
+                [ACCEPT_TIMEOUT] => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Peer {} didn't accept the circuit", peer_id),
+                )),
+                _ => Err(invalid_data_error("Invalid relay answer")),
+            }
+        };
+        Ok(f.boxed())
+    }
+}
+
+/// State of the listener of a circuit address.
+enum ListenState<S> {
+    /// Reserving, after a delay if the previous control connection failed.
+    Reserve { delay: bool },
+    /// Waiting for the circuits on the control connection.
+    Reserved(S),
+}
+
+/// The stream of the circuits to `peer_id` through the relay at `relay_addr`, each reported
+/// with the circuit address `listen_addr`. The reservation is renewed whenever the control
+/// connection fails, after yielding the error, and right away when it expires.
+fn circuit_listener<T, A>(
+    inner: T,
+    auth: A,
+    peer_id: PeerId,
+    relay_addr: NetworkAddress,
+    listen_addr: NetworkAddress,
+) -> Pin<Box<dyn Stream<Item = io::Result<(BoxedIoFuture<T::Output>, NetworkAddress)>> + Send>>
+where
+    T: Transport<Error = io::Error> + Clone + Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Outbound: Send + 'static,
+    A: RelayAuthenticator<T::Output>,
+{
+    stream::unfold(ListenState::Reserve { delay: false }, move |mut state| {
+        let inner = inner.clone();
+        let auth = auth.clone();
+        let relay_addr = relay_addr.clone();
+        let listen_addr = listen_addr.clone();
+        async move {
+            loop {
+                let mut control = match state {
+                    ListenState::Reserve { delay } => {
+                        if delay {
+                            delay_for(RESERVE_RETRY_INTERVAL).await;
+                        }
+                        match reserve(&inner, &auth, peer_id, relay_addr.clone()).await {
+                            Ok(control) => control,
+                            Err(err) => {
+                                return Some((Err(err), ListenState::Reserve { delay: true }))
+                            }
+                        }
+                    }
+                    ListenState::Reserved(control) => control,
+                };
+                match read_frame(&mut control)
+                    .await
+                    .and_then(|frame| Notification::decode(&frame))
+                {
+                    Ok(Notification::Incoming(circuit_id)) => {
+                        let inbound = accept(inner, auth, peer_id, relay_addr, circuit_id).boxed();
+                        return Some((Ok((inbound, listen_addr)), ListenState::Reserved(control)));
+                    }
+                    Ok(Notification::Expired) => state = ListenState::Reserve { delay: false },
+                    Err(err) => return Some((Err(err), ListenState::Reserve { delay: true })),
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Opens a control connection to the relay at `relay_addr`, authenticated by `auth`, reserving
+/// `peer_id`.
+async fn reserve<T, A>(
+    inner: &T,
+    auth: &A,
+    peer_id: PeerId,
+    relay_addr: NetworkAddress,
+) -> io::Result<A::Output>
+where
+    T: Transport<Error = io::Error>,
+    T::Output: AsyncRead + AsyncWrite + Unpin,
+    A: RelayAuthenticator<T::Output>,
+{
+    let mut control = inner.dial(peer_id, relay_addr)?.await?;
+    write_frame(&mut control, &Request::Reserve(peer_id).encode()).await?;
+    let mut control = timeout_io(auth.authenticate(control)).await?;
+    // the request is repeated over the authenticated connection, where it can't be replayed
+    write_frame(&mut control, &Request::Reserve(peer_id).encode()).await?;
+    let answer = timeout_io(read_frame(&mut control)).await?;
+    match answer.as_ref() {
+        [OK] => Ok(control),
+        [RELAY_FULL] => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "The relay has too many reservations",
+        )),
+        [SOURCE_FULL] => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "The relay has too many reservations from this address",
+        )),
+        _ => Err(invalid_data_error("Invalid relay answer")),
+    }
+}
+
+/// Accepts the circuit `circuit_id` of `peer_id` on the relay at `relay_addr`, over a connection
+/// authenticated by `auth`, returning the connection spliced with the dialer.
+async fn accept<T, A>(
+    inner: T,
+    auth: A,
+    peer_id: PeerId,
+    relay_addr: NetworkAddress,
+    circuit_id: u128,
+) -> io::Result<T::Output>
+where
+    T: Transport<Error = io::Error>,
+    T::Output: AsyncRead + AsyncWrite + Unpin,
+    A: RelayAuthenticator<T::Output>,
+{
+    let mut socket = inner.dial(peer_id, relay_addr)?.await?;
+    write_frame(&mut socket, &Request::Accept(circuit_id).encode()).await?;
+    let mut socket = timeout_io(auth.authenticate(socket)).await?;
+    write_frame(&mut socket, &Request::Accept(circuit_id).encode()).await?;
+    let answer = timeout_io(read_frame(&mut socket)).await?;
+    match answer.as_ref() {
+        [OK] => Ok(A::into_socket(socket)),
+        [UNKNOWN_CIRCUIT] => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "The relay has no such circuit of this peer",
+        )),
+        _ => Err(invalid_data_error("Invalid relay answer")),
+    }
+}
+
+//////////////////
+// Relay server //
+//////////////////
+
+struct Reservation {
+    /// The id of the reservation, as a peer may reserve again before its previous control
+    /// connection is closed.
+    id: u64,
+    /// The IP address of the peer.
+    source: Option<IpAddr>,
+    /// The sender of the circuit ids to the control connection.
+    circuit_ids: mpsc::UnboundedSender<u128>,
+}
+
+/// A dialer waiting for the peer with the reservation of `peer_id` to accept its circuit.
+struct PendingCircuit<S> {
+    peer_id: PeerId,
+    dialer: oneshot::Sender<S>,
+}
+
+struct Circuits<S> {
+    reservations: HashMap<PeerId, Reservation>,
+    /// Number of reservations per source IP address.
+    sources: HashMap<Option<IpAddr>, usize>,
+    /// The dialers waiting for the peers to accept their circuits, by circuit id.
+    pending: HashMap<u128, PendingCircuit<S>>,
+    /// Number of circuits, pending or spliced.
+    circuits: usize,
+    next_reservation_id: u64,
+}
+
+impl<S> Circuits<S> {
+    /// Registers the circuit of `dialer` to `peer_id`, returning its id. The ids are random, so
+    /// that they can't be guessed by the clients of the relay which didn't receive them.
+    fn add_pending(&mut self, peer_id: PeerId, dialer: oneshot::Sender<S>) -> u128 {
+        let mut rng = rand::thread_rng();
+        loop {
+            if let Entry::Vacant(entry) = self.pending.entry(rng.gen()) {
+                let circuit_id = *entry.key();
+                entry.insert(PendingCircuit { peer_id, dialer });
+                return circuit_id;
+            }
+        }
+    }
+
+    /// Reserves `peer_id` for the peer at `source`, replacing its previous reservation, and
+    /// returns the id of the reservation, or the status code of the refusal.
+    fn reserve(
+        &mut self,
+        peer_id: PeerId,
+        source: Option<IpAddr>,
+        circuit_ids: mpsc::UnboundedSender<u128>,
+    ) -> Result<u64, u8> {
+        let replaced_source = self.reservations.get(&peer_id).map(|r| r.source);
+        if replaced_source.is_none() && self.reservations.len() >= MAX_RESERVATIONS {
+            return Err(RELAY_FULL);
+        }
+        let from_source = self.sources.get(&source).copied().unwrap_or(0);
+        if replaced_source != Some(source) && from_source >= MAX_RESERVATIONS_PER_SOURCE {
+            return Err(SOURCE_FULL);
+        }
+
+        self.next_reservation_id += 1;
+        let id = self.next_reservation_id;
+        let reservation = Reservation {
+            id,
+            source,
+            circuit_ids,
+        };
+        if let Some(replaced) = self.reservations.insert(peer_id, reservation) {
+            self.release_source(replaced.source);
+        }
+        *self.sources.entry(source).or_insert(0) += 1;
+        Ok(id)
+    }
+
+    /// Removes the reservation `id` of `peer_id`, unless it was already replaced.
+    fn unreserve(&mut self, peer_id: &PeerId, id: u64) {
+        if self.reservations.get(peer_id).map(|r| r.id) == Some(id) {
+            if let Some(reservation) = self.reservations.remove(peer_id) {
+                self.release_source(reservation.source);
+            }
+        }
+    }
+
+    fn release_source(&mut self, source: Option<IpAddr>) {
+        if let Entry::Occupied(mut count) = self.sources.entry(source) {
+            *count.get_mut() -= 1;
+            if *count.get() == 0 {
+                count.remove();
+            }
+        }
+    }
+}
+
+/// Relay splicing the connections of the dialers of `/<relay>/p2p-circuit` addresses with the
+/// connections accepted by the peers with a reservation, which are authenticated by `auth`.
+pub struct RelayServer<T: Transport, A> {
+    listener: T::Listener,
+    auth: A,
+    circuits: Arc<Mutex<Circuits<T::Output>>>,
+    /// Lifetime of the reservations, `RESERVATION_TTL` unless changed by the tests.
+    reservation_ttl: Duration,
+}
+
+impl<T, A> RelayServer<T, A>
+where
+    T: Transport<Error = io::Error>,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Inbound: Send + 'static,
+    A: RelayAuthenticator<T::Output>,
+{
+    /// Listens for the clients of the relay on `addr` of `transport`, returning the actual
+    /// listening address.
+    pub fn listen_on(
+        transport: &T,
+        addr: NetworkAddress,
+        auth: A,
+    ) -> io::Result<(Self, NetworkAddress)> {
+        let (listener, listen_addr) = transport.listen_on(addr)?;
+        Ok((
+            Self {
+                listener,
+                auth,
+                circuits: Arc::new(Mutex::new(Circuits {
+                    reservations: HashMap::new(),
+                    sources: HashMap::new(),
+                    pending: HashMap::new(),
+                    circuits: 0,
+                    next_reservation_id: 0,
+                })),
+                reservation_ttl: RESERVATION_TTL,
+            },
+            listen_addr,
+        ))
+    }
+
+    /// Serves the clients of the relay. The failures of a client are only reported to the client
+    /// itself, or its peer, by the answers of the relay.
+    pub async fn start(mut self) {
+        while let Some(incoming) = self.listener.next().await {
+            if let Ok((inbound, addr)) = incoming {
+                let context = ReservationContext {
+                    source: source_ip(&addr),
+                    auth: self.auth.clone(),
+                    ttl: self.reservation_ttl,
+                };
+                let circuits = self.circuits.clone();
+                tokio::spawn(serve(inbound, context, circuits).map(|_| ()));
+            }
+        }
+    }
+}
+
+/// What the relay needs to serve a reservation or accept request.
+struct ReservationContext<A> {
+    /// The IP address of the client.
+    source: Option<IpAddr>,
+    auth: A,
+    ttl: Duration,
+}
+
+async fn serve<S, A>(
+    inbound: impl Future<Output = io::Result<S>>,
+    context: ReservationContext<A>,
+    circuits: Arc<Mutex<Circuits<S>>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    A: RelayAuthenticator<S>,
+{
+    let mut socket = inbound.await?;
+    let request = timeout_io(read_frame(&mut socket)).await?;
+    match Request::decode(&request)? {
+        Request::Reserve(peer_id) => serve_reservation(socket, peer_id, context, circuits).await,
+        Request::Connect(peer_id) => {
+            let reservation = {
+                let mut circuits = circuits.lock().unwrap();
+                match circuits.reservations.get(&peer_id) {
+                    Some(_) if circuits.circuits >= MAX_CIRCUITS => Err(RELAY_FULL),
+                    Some(reservation) => {
+                        let circuit_ids = reservation.circuit_ids.clone();
+                        circuits.circuits += 1;
+                        Ok(circuit_ids)
+                    }
+                    None => Err(NO_RESERVATION),
+                }
+            };
+            match reservation {
+                Ok(circuit_ids) => {
+                    let result = serve_connect(socket, peer_id, circuit_ids, &circuits).await;
+                    circuits.lock().unwrap().circuits -= 1;
+                    result
+                }
+                Err(code) => write_frame(&mut socket, &[code]).await,
+            }
+        }
+        Request::Accept(circuit_id) => serve_accept(socket, circuit_id, context, circuits).await,
+    }
+}
+
+/// Authenticates the connection `socket` accepting the circuit `circuit_id`, and hands it over
+/// to the dialer of the circuit if the authenticated peer has the reservation it dialed.
+async fn serve_accept<S, A>(
+    socket: S,
+    circuit_id: u128,
+    context: ReservationContext<A>,
+    circuits: Arc<Mutex<Circuits<S>>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    A: RelayAuthenticator<S>,
+{
+    let (mut socket, remote_peer_id) = timeout_io(context.auth.authenticate_peer(socket)).await?;
+    let request = timeout_io(read_frame(&mut socket)).await?;
+    if Request::decode(&request)? != Request::Accept(circuit_id) {
+        return Err(invalid_data_error("Invalid relay request"));
+    }
+
+    // the circuits of another peer are left pending, and the peer is answered as if they didn't
+    // exist; the dialer is gone if the circuit is unknown
+    let dialer = {
+        let mut circuits = circuits.lock().unwrap();
+        match circuits.pending.entry(circuit_id) {
+            Entry::Occupied(pending) if pending.get().peer_id == remote_peer_id => {
+                Some(pending.remove().dialer)
+            }
+            _ => None,
+        }
+    };
+    match dialer {
+        Some(dialer) => {
+            write_frame(&mut socket, &[OK]).await?;
+            // the dialer is gone if its sender dropped
+            let _ = dialer.send(A::into_socket(socket));
+            Ok(())
+        }
+        None => write_frame(&mut socket, &[UNKNOWN_CIRCUIT]).await,
+    }
+}
+
+/// Authenticates the control connection `socket` as the one of `peer_id`, and sends it the
+/// circuits of `peer_id` until it is closed, replaced by another reservation, or expired.
+async fn serve_reservation<S, A>(
+    socket: S,
+    peer_id: PeerId,
+    context: ReservationContext<A>,
+    circuits: Arc<Mutex<Circuits<S>>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    A: RelayAuthenticator<S>,
+{
+    let (mut control, remote_peer_id) = timeout_io(context.auth.authenticate_peer(socket)).await?;
+    if remote_peer_id != peer_id {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Peer {} can't reserve {}", remote_peer_id, peer_id),
+        ));
+    }
+    let request = timeout_io(read_frame(&mut control)).await?;
+    if Request::decode(&request)? != Request::Reserve(peer_id) {
+        return Err(invalid_data_error("Invalid relay request"));
+    }
+
+    let (circuit_ids_tx, mut circuit_ids) = mpsc::unbounded();
+    let reservation_id = circuits
+        .lock()
+        .unwrap()
+        .reserve(peer_id, context.source, circuit_ids_tx);
+    let (mut reader, mut writer) = control.split();
+    let reservation_id = match reservation_id {
+        Ok(reservation_id) => reservation_id,
+        Err(code) => return write_frame(&mut writer, &[code]).await,
+    };
+
+    let result: io::Result<()> = async {
+        write_frame(&mut writer, &[OK]).await?;
+        // the peer sends nothing more on the control connection, so reading only completes once
+        // it is closed
+        let mut buf = [0u8; 1];
+        let mut closed = reader.read(&mut buf).fuse();
+        let mut expired = delay_for(context.ttl).fuse();
+        loop {
+            futures::select! {
+                circuit_id = circuit_ids.next() => match circuit_id {
+                    Some(circuit_id) => {
+                        let incoming = Notification::Incoming(circuit_id).encode();
+                        write_frame(&mut writer, &incoming).await?
+                    }
+                    None => return Ok(()),
+                },
+                _ = closed => return Ok(()),
+                _ = expired => {
+                    return write_frame(&mut writer, &Notification::Expired.encode()).await
+                }
+            }
+        }
+    }
+    .await;
+
+    circuits.lock().unwrap().unreserve(&peer_id, reservation_id);
+    result
+}
+
+/// Asks `peer_id`, through the control connection of its reservation `circuit_ids`, to accept
+/// a circuit, and splices it with the dialer `socket`.
+async fn serve_connect<S>(
+    mut socket: S,
+    peer_id: PeerId,
+    circuit_ids: mpsc::UnboundedSender<u128>,
+    circuits: &Mutex<Circuits<S>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (accepted_tx, accepted_rx) = oneshot::channel();
+    let circuit_id = circuits.lock().unwrap().add_pending(peer_id, accepted_tx);
+    let _ = circuit_ids.unbounded_send(circuit_id);
+
+    let accepted = timeout(REQUEST_TIMEOUT, accepted_rx).await;
+    circuits.lock().unwrap().pending.remove(&circuit_id);
+    match accepted {
+        Ok(Ok(acceptor)) => {
+            write_frame(&mut socket, &[OK]).await?;
+            splice(socket, acceptor).await
+        }
+        _ => write_frame(&mut socket, &[ACCEPT_TIMEOUT]).await,
+    }
+}
+
+/// Copies the bytes received on each of `a` and `b` to the other, until both are closed.
+async fn splice<S>(a: S, b: S) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut a_reader, mut a_writer) = a.split();
+    let (mut b_reader, mut b_writer) = b.split();
+    let a_to_b = async {
+        let result = futures::io::copy(&mut a_reader, &mut b_writer).await;
+        b_writer.close().await?;
+        result
+    };
+    let b_to_a = async {
+        let result = futures::io::copy(&mut b_reader, &mut a_writer).await;
+        a_writer.close().await?;
+        result
+    };
+    let (a_to_b, b_to_a) = future::join(a_to_b, b_to_a).await;
+    a_to_b.and(b_to_a).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{
+        tcp::{TcpSocket, TcpTransport},
+        ConnectionOrigin, TransportExt,
+    };
+    use futures::future::join;
+
+    /// Authentication of the tests, where the peers claim their `PeerId`.
+    #[derive(Clone, Debug)]
+    struct ClaimedPeerId(PeerId);
+
+    impl<S> RelayAuthenticator<S> for ClaimedPeerId
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        type Output = S;
+
+        fn authenticate(&self, mut socket: S) -> BoxFuture<'static, io::Result<S>> {
+            let peer_id = self.0;
+            async move {
+                write_frame(&mut socket, &peer_id.to_vec()).await?;
+                Ok(socket)
+            }
+            .boxed()
+        }
+
+        fn authenticate_peer(&self, mut socket: S) -> BoxFuture<'static, io::Result<(S, PeerId)>> {
+            async move {
+                let frame = read_frame(&mut socket).await?;
+                let peer_id = PeerId::try_from(frame.as_ref())
+                    .map_err(|_| invalid_data_error("Invalid PeerId"))?;
+                Ok((socket, peer_id))
+            }
+            .boxed()
+        }
+
+        fn into_socket(output: S) -> S {
+            output
+        }
+    }
+
+    fn circuit_addr(relay_addr: &NetworkAddress) -> NetworkAddress {
+        format!("{}/p2p-circuit", relay_addr).parse().unwrap()
+    }
+
+    /// Starts a relay on localhost whose reservations expire after `reservation_ttl`.
+    fn start_relay(reservation_ttl: Duration) -> io::Result<NetworkAddress> {
+        let (mut relay, relay_addr) = RelayServer::listen_on(
+            &TcpTransport::default(),
+            "/ip4/127.0.0.1/tcp/0".parse().unwrap(),
+            ClaimedPeerId(PeerId::random()),
+        )?;
+        relay.reservation_ttl = reservation_ttl;
+        tokio::spawn(relay.start());
+        Ok(relay_addr)
+    }
+
+    /// Reserves a random `PeerId` on the relay at `relay_addr`, returning the control connection.
+    async fn reserve_random(relay_addr: &NetworkAddress) -> io::Result<TcpSocket> {
+        let peer_id = PeerId::random();
+        let auth = ClaimedPeerId(peer_id);
+        reserve(&TcpTransport::default(), &auth, peer_id, relay_addr.clone()).await
+    }
+
+    #[test]
+    fn encoding() {
+        let peer_id = PeerId::random();
+        for request in &[
+            Request::Reserve(peer_id),
+            Request::Connect(peer_id),
+            Request::Accept(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10),
+        ] {
+            assert_eq!(Request::decode(&request.encode()).unwrap(), *request);
+        }
+        assert!(Request::decode(&[ACCEPT, 1, 2]).is_err());
+        assert!(Request::decode(&[INCOMING]).is_err());
+        for notification in &[Notification::Incoming(42), Notification::Expired] {
+            assert_eq!(
+                Notification::decode(&notification.encode()).unwrap(),
+                *notification
+            );
+        }
+        assert!(Notification::decode(&[EXPIRED, 0]).is_err());
+    }
+
+    /// Dials a listener through a relay whose reservations expire after `reservation_ttl`, after
+    /// `dial_delay`.
+    async fn check_dial_through_relay(
+        reservation_ttl: Duration,
+        dial_delay: Duration,
+    ) -> io::Result<()> {
+        let relay_addr = start_relay(reservation_ttl)?;
+
+        let listener_id = PeerId::random();
+        let auth = ClaimedPeerId(listener_id);
+        let t = RelayTransport::new(TcpTransport::default(), listener_id, auth).and_then(
+            |mut out, _addr, origin| async move {
+                match origin {
+                    ConnectionOrigin::Inbound => {
+                        out.write_all(b"Earth").await?;
+                        let mut buf = [0; 3];
+                        out.read_exact(&mut buf).await?;
+                        assert_eq!(&buf, b"Air");
+                    }
+                    ConnectionOrigin::Outbound => {
+                        let mut buf = [0; 5];
+                        out.read_exact(&mut buf).await?;
+                        assert_eq!(&buf, b"Earth");
+                        out.write_all(b"Air").await?;
+                    }
+                }
+                Ok(())
+            },
+        );
+
+        let (mut listener, addr) = t.listen_on(circuit_addr(&relay_addr))?;
+        assert_eq!(addr, circuit_addr(&relay_addr));
+        // dialing a peer without a reservation fails
+        let result = t.dial(PeerId::random(), addr.clone())?.await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+
+        // the listener reserves on its first poll, so it is polled along with the dial, which
+        // retries until the reservation is made
+        let dial = async {
+            delay_for(dial_delay).await;
+            loop {
+                match t.dial(listener_id, addr.clone())?.await {
+                    Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                        delay_for(Duration::from_millis(10)).await
+                    }
+                    result => return result,
+                }
+            }
+        };
+        let accept = async {
+            let (incoming, incoming_addr) = listener.next().await.unwrap()?;
+            assert_eq!(incoming_addr, addr);
+            incoming.await
+        };
+        let (outgoing, incoming) = join(dial, accept).await;
+        outgoing?;
+        incoming?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dial_through_relay() -> io::Result<()> {
+        check_dial_through_relay(RESERVATION_TTL, Duration::from_millis(0)).await
+    }
+
+    #[tokio::test]
+    async fn dial_after_reservation_expiry() -> io::Result<()> {
+        // the listener reserves again right away when its reservation expires
+        check_dial_through_relay(Duration::from_millis(100), Duration::from_millis(300)).await
+    }
+
+    #[tokio::test]
+    async fn reservation_of_another_peer() -> io::Result<()> {
+        let relay_addr = start_relay(RESERVATION_TTL)?;
+
+        let peer_id = PeerId::random();
+        let impostor = ClaimedPeerId(PeerId::random());
+        let t = RelayTransport::new(TcpTransport::default(), peer_id, impostor);
+        let (mut listener, addr) = t.listen_on(circuit_addr(&relay_addr))?;
+        assert!(listener.next().await.unwrap().is_err());
+
+        let result = t.dial(peer_id, addr)?.await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accept_circuit_of_another_peer() -> io::Result<()> {
+        let relay_addr = start_relay(RESERVATION_TTL)?;
+
+        let peer_id = PeerId::random();
+        let auth = ClaimedPeerId(peer_id);
+        let mut control =
+            reserve(&TcpTransport::default(), &auth, peer_id, relay_addr.clone()).await?;
+        let t = RelayTransport::new(TcpTransport::default(), peer_id, auth.clone());
+        let dial = t.dial(peer_id, circuit_addr(&relay_addr))?;
+
+        let accepting = async {
+            let frame = read_frame(&mut control).await?;
+            let circuit_id = match Notification::decode(&frame)? {
+                Notification::Incoming(circuit_id) => circuit_id,
+                notification => panic!("Unexpected notification {:?}", notification),
+            };
+
+            // another peer, which learnt the circuit id, can't accept the circuit
+            let other_peer_id = PeerId::random();
+            let result = accept(
+                TcpTransport::default(),
+                ClaimedPeerId(other_peer_id),
+                other_peer_id,
+                relay_addr.clone(),
+                circuit_id,
+            )
+            .await;
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+            // which is left to the peer with the reservation
+            let mut socket = accept(
+                TcpTransport::default(),
+                auth.clone(),
+                peer_id,
+                relay_addr.clone(),
+                circuit_id,
+            )
+            .await?;
+            socket.write_all(b"Fire").await?;
+            Ok::<_, io::Error>(socket)
+        };
+        let (outgoing, incoming) = join(dial, accepting).await;
+        let (mut outgoing, _incoming) = (outgoing?, incoming?);
+        let mut buf = [0; 4];
+        outgoing.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Fire");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reservations_per_source() -> io::Result<()> {
+        let relay_addr = start_relay(RESERVATION_TTL)?;
+
+        let mut controls = Vec::new();
+        for _ in 0..MAX_RESERVATIONS_PER_SOURCE {
+            controls.push(reserve_random(&relay_addr).await?);
+        }
+        let result = reserve_random(&relay_addr).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+
+        // closing a control connection releases its reservation
+        drop(controls.pop());
+        loop {
+            match reserve_random(&relay_addr).await {
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    delay_for(Duration::from_millis(10)).await
+                }
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn reservation_expiry() -> io::Result<()> {
+        let relay_addr = start_relay(Duration::from_millis(100))?;
+
+        let peer_id = PeerId::random();
+        let auth = ClaimedPeerId(peer_id);
+        let t = RelayTransport::new(TcpTransport::default(), peer_id, auth.clone());
+        let mut control =
+            reserve(&TcpTransport::default(), &auth, peer_id, relay_addr.clone()).await?;
+        let frame = read_frame(&mut control).await?;
+        assert_eq!(Notification::decode(&frame)?, Notification::Expired);
+        // the relay closes the control connection once the reservation is removed
+        assert!(read_frame(&mut control).await.is_err());
+
+        let result = t.dial(peer_id, circuit_addr(&relay_addr))?.await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_multiaddrs() {
+        let peer_id = PeerId::random();
+        let t = RelayTransport::new(TcpTransport::default(), peer_id, ClaimedPeerId(peer_id));
+
+        let result = t.listen_on("/memory/0/p2p-circuit".parse().unwrap());
+        assert!(result.is_err());
+        let result = t.listen_on(
+            "/ip4/127.0.0.1/tcp/1/p2p-circuit/ln-handshake/0"
+                .parse()
+                .unwrap(),
+        );
+        assert!(result.is_err());
+
+        let peer_id = PeerId::random();
+        let result = t.dial(
+            peer_id,
+            "/ip4/127.0.0.1/tcp/1/ws/p2p-circuit".parse().unwrap(),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -11,11 +11,14 @@ use futures::{
 };
 use libra_network_address::{parse_dns_tcp, parse_ip_tcp, IpFilter, NetworkAddress};
 use libra_types::PeerId;
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::{
     convert::TryFrom,
     fmt::Debug,
     io,
-    net::SocketAddr,
+    net::{Ipv4Addr, SocketAddr},
     pin::Pin,
     task::{Context, Poll},
     time::Duration,
@@ -34,8 +37,23 @@ pub struct TcpTransport {
     /// Keep alive duration to set for opened sockets, or `None` to keep default.
     #[allow(clippy::option_option)]
     pub keepalive: Option<Option<Duration>>,
+    /// Interval between the keep alive probes to set for opened sockets, or `None` to keep
+    /// default. Only set on Linux, Android and macOS, the other platforms keep their default.
+    pub keepalive_interval: Option<Duration>,
     /// `TCP_NODELAY` to set for opened sockets, or `None` to keep default.
     pub nodelay: Option<bool>,
+    /// `IPV6_V6ONLY` to set for sockets listening on IPv6 addresses, or `None` to keep default.
+    /// With `Some(false)`, a socket listening on `/ip6/::/tcp/<port>` also accepts the IPv4
+    /// connections to the port (dual-stack).
+    pub ipv6_only: Option<bool>,
+    /// `SO_REUSEPORT` to set for listening sockets, or `None` to keep default. With `Some(true)`,
+    /// other processes may listen on the same port too, e.g., a hot standby taking over the port.
+    /// Only supported on Linux, Android and macOS.
+    pub reuse_port: Option<bool>,
+    /// Network interface to bind the listening sockets to with `SO_BINDTODEVICE`, e.g., `eth1`
+    /// on a multi-homed host, or `None` to accept the connections of any interface. Only
+    /// supported on Linux and Android, where it requires `CAP_NET_RAW`.
+    pub bind_device: Option<String>,
 }
 
 impl TcpTransport {
@@ -56,12 +74,47 @@ impl TcpTransport {
             stream.set_keepalive(keepalive)?;
         }
 
+        if let Some(interval) = self.keepalive_interval {
+            set_keepalive_interval(stream, interval)?;
+        }
+
         if let Some(nodelay) = self.nodelay {
             stream.set_nodelay(nodelay)?;
         }
 
         Ok(())
     }
+
+    fn bind(&self, addr: SocketAddr) -> io::Result<::std::net::TcpListener> {
+        let ipv6_only = match addr {
+            SocketAddr::V6(_) => self.ipv6_only,
+            SocketAddr::V4(_) => None,
+        };
+        if ipv6_only.is_none() && self.reuse_port.is_none() && self.bind_device.is_none() {
+            return ::std::net::TcpListener::bind(addr);
+        }
+
+        let domain = match addr {
+            SocketAddr::V4(_) => Domain::ipv4(),
+            SocketAddr::V6(_) => Domain::ipv6(),
+        };
+        let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+        if let Some(ipv6_only) = ipv6_only {
+            socket.set_only_v6(ipv6_only)?;
+        }
+        // as `std::net::TcpListener::bind` does
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        if let Some(reuse_port) = self.reuse_port {
+            set_reuse_port(&socket, reuse_port)?;
+        }
+        if let Some(device) = &self.bind_device {
+            bind_device(&socket, device)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(128)?;
+        Ok(socket.into_tcp_listener())
+    }
 }
 
 impl Transport for TcpTransport {
@@ -81,7 +134,7 @@ impl Transport for TcpTransport {
             return Err(invalid_addr_error(&addr));
         }
 
-        let listener = ::std::net::TcpListener::bind((ipaddr, port))?;
+        let listener = self.bind(SocketAddr::new(ipaddr, port))?;
         let listener = TcpListener::try_from(listener)?;
         let listen_addr = NetworkAddress::from(listener.local_addr()?);
 
@@ -115,6 +168,81 @@ impl Transport for TcpTransport {
     }
 }
 
+/// Sets `TCP_KEEPINTVL`, which neither tokio nor socket2 expose, rounded up to whole seconds.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn set_keepalive_interval(stream: &TcpStream, interval: Duration) -> io::Result<()> {
+    let secs = interval.as_secs() + u64::from(interval.subsec_nanos() > 0);
+    let secs = libc::c_int::try_from(secs.max(1)).unwrap_or(libc::c_int::max_value());
+    setsockopt(
+        stream.as_raw_fd(),
+        libc::IPPROTO_TCP,
+        libc::TCP_KEEPINTVL,
+        &secs.to_ne_bytes(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn set_keepalive_interval(_stream: &TcpStream, _interval: Duration) -> io::Result<()> {
+    Ok(())
+}
+
+/// Sets `SO_REUSEPORT`, which socket2 only exposes behind a feature.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn set_reuse_port(socket: &Socket, reuse_port: bool) -> io::Result<()> {
+    setsockopt(
+        socket.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_REUSEPORT,
+        &libc::c_int::from(reuse_port).to_ne_bytes(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn set_reuse_port(_socket: &Socket, _reuse_port: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+/// Sets `SO_BINDTODEVICE`, so that the socket only uses the network interface `device`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+    setsockopt(
+        socket.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_BINDTODEVICE,
+        device.as_bytes(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, _device: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_BINDTODEVICE is not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn setsockopt(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &[u8]) -> io::Result<()> {
+    // Safe as the option value outlives the call, and its length is the one passed.
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value.as_ptr() as *const libc::c_void,
+            value.len() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
 /// Try to lookup the dns name, then filter addrs according to the `IpFilter`.
 fn resolve_with_filter<'a>(
     ip_filter: IpFilter,
@@ -172,6 +300,20 @@ fn invalid_addr_error(addr: &NetworkAddress) -> io::Error {
     )
 }
 
+/// Returns the IPv4 address of the IPv4 peers of dual-stack sockets, which are reported with
+/// their IPv4-mapped IPv6 address `::ffff:<ipv4>`.
+fn unmap_ipv4(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(addr) => match addr.ip().octets() {
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                SocketAddr::new(Ipv4Addr::new(a, b, c, d).into(), addr.port())
+            }
+            _ => SocketAddr::V6(addr),
+        },
+        addr => addr,
+    }
+}
+
 #[must_use = "streams do nothing unless polled"]
 pub struct TcpListenerStream {
     inner: TcpListener,
@@ -188,7 +330,7 @@ impl Stream for TcpListenerStream {
                     return Poll::Ready(Some(Err(e)));
                 }
                 let dialer_addr = match socket.peer_addr() {
-                    Ok(addr) => NetworkAddress::from(addr),
+                    Ok(addr) => NetworkAddress::from(unmap_ipv4(addr)),
                     Err(e) => return Poll::Ready(Some(Err(e))),
                 };
                 Poll::Ready(Some(Ok((
@@ -312,6 +454,97 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn dual_stack_listen_and_dial() -> Result<(), ::std::io::Error> {
+        let t = TcpTransport {
+            ipv6_only: Some(false),
+            ..TcpTransport::default()
+        };
+
+        let (listener, addr) = t.listen_on("/ip6/::/tcp/0".parse().unwrap())?;
+        let port = match parse_ip_tcp(addr.as_slice()) {
+            Some(((_, port), _)) => port,
+            None => panic!("unexpected listen address: {}", addr),
+        };
+
+        // an IPv4 peer connects, and is reported with its IPv4 address
+        let dial = t.dial(
+            PeerId::random(),
+            format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap(),
+        )?;
+        let listener = listener.into_future().map(|(maybe_result, _stream)| {
+            let (_incoming, dialer_addr) = maybe_result.unwrap().unwrap();
+            dialer_addr
+        });
+
+        let (outgoing, dialer_addr) = join(dial, listener).await;
+        assert!(outgoing.is_ok());
+        assert!(dialer_addr.to_string().starts_with("/ip4/127.0.0.1/tcp/"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn socket_options() -> Result<(), ::std::io::Error> {
+        let t = TcpTransport {
+            nodelay: Some(true),
+            keepalive: Some(Some(Duration::from_secs(60))),
+            keepalive_interval: Some(Duration::from_secs(10)),
+            recv_buffer_size: Some(1 << 16),
+            send_buffer_size: Some(1 << 16),
+            ..TcpTransport::default()
+        };
+
+        let mut listener = TcpListener::bind("127.0.0.1:0").await?;
+        let dial = TcpStream::connect(listener.local_addr()?);
+        let (outgoing, incoming) = join(dial, listener.accept()).await;
+        for stream in &[outgoing?, incoming?.0] {
+            t.apply_config(stream)?;
+            assert!(stream.nodelay()?);
+            assert_eq!(stream.keepalive()?, Some(Duration::from_secs(60)));
+            // the kernel may round the buffer sizes, e.g., Linux doubles them
+            assert!(stream.recv_buffer_size()? >= 1 << 16);
+            assert!(stream.send_buffer_size()? >= 1 << 16);
+        }
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    #[tokio::test]
+    async fn reuse_port() -> Result<(), ::std::io::Error> {
+        let t = TcpTransport {
+            reuse_port: Some(true),
+            ..TcpTransport::default()
+        };
+
+        // a standby listens on the port of the active listener
+        let (_active, addr) = t.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())?;
+        let (_standby, standby_addr) = t.listen_on(addr.clone())?;
+        assert_eq!(standby_addr, addr);
+
+        // but not without SO_REUSEPORT
+        assert!(TcpTransport::default().listen_on(addr).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bind_unknown_device() {
+        let t = TcpTransport {
+            bind_device: Some("no-such-device".to_string()),
+            ..TcpTransport::default()
+        };
+        assert!(t
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn unmap_ipv4_addrs() {
+        let mapped: SocketAddr = "[::ffff:10.0.0.1]:6180".parse().unwrap();
+        assert_eq!(unmap_ipv4(mapped), "10.0.0.1:6180".parse().unwrap());
+        let v6: SocketAddr = "[::1]:6180".parse().unwrap();
+        assert_eq!(unmap_ipv4(v6), v6);
+    }
+
     #[test]
     fn unsupported_multiaddrs() {
         let t = TcpTransport::default();
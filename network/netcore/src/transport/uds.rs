@@ -0,0 +1,240 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unix Domain Socket Transport
+//!
+//! Lets co-located processes, like a validator and its full node or a sidecar, connect without
+//! going through loopback TCP. Only the processes allowed by the filesystem permissions on the
+//! socket and its directory can connect, so operators should listen in a directory only readable
+//! by the users of those processes.
+use crate::{compat::IoCompat, transport::Transport};
+use futures::{
+    future::{self, Future, FutureExt},
+    io::{AsyncRead, AsyncWrite},
+    stream::Stream,
+};
+use libra_network_address::{parse_unix, NetworkAddress, Protocol};
+use libra_types::PeerId;
+use std::{
+    fs, io,
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Transport to build Unix domain socket connections
+///
+/// Listens on and dials `/unix/<path>` addresses.
+#[derive(Debug, Clone, Default)]
+pub struct UdsTransport;
+
+impl Transport for UdsTransport {
+    type Output = UdsSocket;
+    type Error = io::Error;
+    type Listener = UdsListenerStream;
+    type Inbound = future::Ready<io::Result<UdsSocket>>;
+    type Outbound = Pin<Box<dyn Future<Output = io::Result<UdsSocket>> + Send + 'static>>;
+
+    fn listen_on(
+        &self,
+        addr: NetworkAddress,
+    ) -> Result<(Self::Listener, NetworkAddress), Self::Error> {
+        let (path, addr_suffix) =
+            parse_unix(addr.as_slice()).ok_or_else(|| invalid_addr_error(&addr))?;
+        if !addr_suffix.is_empty() {
+            return Err(invalid_addr_error(&addr));
+        }
+
+        remove_stale_socket(path.as_ref())?;
+        let listener = UnixListener::bind(path)?;
+        let listen_addr = NetworkAddress::from(Protocol::Unix(path.clone()));
+
+        Ok((
+            UdsListenerStream {
+                inner: listener,
+                listen_addr: listen_addr.clone(),
+            },
+            listen_addr,
+        ))
+    }
+
+    fn dial(&self, _peer_id: PeerId, addr: NetworkAddress) -> Result<Self::Outbound, Self::Error> {
+        let (path, _addr_suffix) =
+            parse_unix(addr.as_slice()).ok_or_else(|| invalid_addr_error(&addr))?;
+        let path = path.clone();
+
+        let f = async move { UnixStream::connect(path).await.map(UdsSocket::new) };
+        Ok(f.boxed())
+    }
+}
+
+/// Removes the socket left at `path` by a listener which is gone, e.g., after a crash, as binding
+/// to an existing path fails. A socket which still accepts connections is left alone.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => Ok(()),
+                Err(_) => fs::remove_file(path),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid_addr_error(addr: &NetworkAddress) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid NetworkAddress: '{}'", addr),
+    )
+}
+
+#[must_use = "streams do nothing unless polled"]
+pub struct UdsListenerStream {
+    inner: UnixListener,
+    /// Dialers are usually unnamed sockets, so they are all reported with the listening address.
+    listen_addr: NetworkAddress,
+}
+
+impl Stream for UdsListenerStream {
+    type Item = io::Result<(future::Ready<io::Result<UdsSocket>>, NetworkAddress)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.inner.incoming()).poll_next(context) {
+            Poll::Ready(Some(Ok(socket))) => Poll::Ready(Some(Ok((
+                future::ready(Ok(UdsSocket::new(socket))),
+                self.listen_addr.clone(),
+            )))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A wrapper around a tokio UnixStream, so that closing it shuts down its write half, as for
+/// `TcpSocket`.
+#[derive(Debug)]
+pub struct UdsSocket {
+    inner: IoCompat<UnixStream>,
+}
+
+impl UdsSocket {
+    fn new(socket: UnixStream) -> Self {
+        Self {
+            inner: IoCompat::new(socket),
+        }
+    }
+}
+
+impl AsyncRead for UdsSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(context, buf)
+    }
+}
+
+impl AsyncWrite for UdsSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(context, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(context)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{ConnectionOrigin, TransportExt};
+    use futures::{
+        future::join,
+        io::{AsyncReadExt, AsyncWriteExt},
+        stream::StreamExt,
+    };
+    use libra_network_address::UnixPath;
+    use libra_temppath::TempPath;
+    use std::convert::TryFrom;
+
+    fn unix_addr(temppath: &TempPath) -> NetworkAddress {
+        let path = temppath.path().to_str().unwrap().to_owned();
+        NetworkAddress::from(Protocol::Unix(UnixPath::try_from(path).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn simple_listen_and_dial() -> Result<(), ::std::io::Error> {
+        let t = UdsTransport.and_then(|mut out, _addr, origin| async move {
+            match origin {
+                ConnectionOrigin::Inbound => {
+                    out.write_all(b"Earth").await?;
+                    let mut buf = [0; 3];
+                    out.read_exact(&mut buf).await?;
+                    assert_eq!(&buf, b"Air");
+                }
+                ConnectionOrigin::Outbound => {
+                    let mut buf = [0; 5];
+                    out.read_exact(&mut buf).await?;
+                    assert_eq!(&buf, b"Earth");
+                    out.write_all(b"Air").await?;
+                }
+            }
+            Ok(())
+        });
+
+        let temppath = TempPath::new();
+        let (listener, addr) = t.listen_on(unix_addr(&temppath))?;
+        assert_eq!(addr, unix_addr(&temppath));
+        let peer_id = PeerId::random();
+        let dial = t.dial(peer_id, addr)?;
+        let listener = listener.into_future().then(|(maybe_result, _stream)| {
+            let (incoming, _addr) = maybe_result.unwrap().unwrap();
+            incoming.map(Result::unwrap)
+        });
+
+        let (outgoing, _incoming) = join(dial, listener).await;
+        assert!(outgoing.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn listen_on_stale_socket() {
+        let temppath = TempPath::new();
+        let (listener, _addr) = UdsTransport.listen_on(unix_addr(&temppath)).unwrap();
+
+        // the socket is still accepting connections
+        assert!(UdsTransport.listen_on(unix_addr(&temppath)).is_err());
+
+        // the socket file is left behind by the listener
+        drop(listener);
+        assert!(temppath.path().exists());
+        assert!(UdsTransport.listen_on(unix_addr(&temppath)).is_ok());
+    }
+
+    #[test]
+    fn unsupported_multiaddrs() {
+        let t = UdsTransport;
+
+        let result = t.listen_on("/memory/0".parse().unwrap());
+        assert!(result.is_err());
+        let result = t.listen_on("/unix/foo.sock/ln-handshake/0".parse().unwrap());
+        assert!(result.is_err());
+
+        let peer_id = PeerId::random();
+        let result = t.dial(peer_id, "/ip4/127.0.0.1/tcp/22".parse().unwrap());
+        assert!(result.is_err());
+    }
+}
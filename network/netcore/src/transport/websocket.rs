@@ -0,0 +1,304 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! WebSocket Transport
+//!
+//! Carries a byte stream over binary WebSocket messages on top of a TCP connection, so that peers
+//! which can only open WebSockets, like browsers, can connect to a node. Every write is sent as
+//! one binary message, and the payloads of the binary messages received are read back to back.
+use crate::{
+    compat::IoCompat,
+    transport::{
+        tcp::{TcpSocket, TcpTransport},
+        Transport,
+    },
+};
+use futures::{
+    future::{Future, FutureExt, TryFutureExt},
+    io::{AsyncRead, AsyncWrite},
+    ready,
+    sink::Sink,
+    stream::{Stream, StreamExt, TryStreamExt},
+};
+use libra_network_address::{parse_dns_tcp_ws, parse_ip_tcp_ws, NetworkAddress, Protocol};
+use libra_types::PeerId;
+use std::{
+    cmp, fmt, io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio_tungstenite::{
+    accept_async, client_async,
+    tungstenite::{Error as WsError, Message},
+    WebSocketStream,
+};
+
+/// Transport to build WebSocket connections, over the TCP connections of `tcp`.
+///
+/// Listens on and dials `/ip4/<addr>/tcp/<port>/ws` or `/ip6/<addr>/tcp/<port>/ws` addresses,
+/// and also dials `/dns{,4,6}/<name>/tcp/<port>/ws` addresses, whose name is resolved by `tcp` on
+/// every dial.
+#[derive(Debug, Clone, Default)]
+pub struct WebSocketTransport {
+    pub tcp: TcpTransport,
+}
+
+impl Transport for WebSocketTransport {
+    type Output = WebSocketSocket;
+    type Error = io::Error;
+    type Listener =
+        Pin<Box<dyn Stream<Item = io::Result<(Self::Inbound, NetworkAddress)>> + Send + 'static>>;
+    type Inbound = Pin<Box<dyn Future<Output = io::Result<WebSocketSocket>> + Send + 'static>>;
+    type Outbound = Pin<Box<dyn Future<Output = io::Result<WebSocketSocket>> + Send + 'static>>;
+
+    fn listen_on(
+        &self,
+        addr: NetworkAddress,
+    ) -> Result<(Self::Listener, NetworkAddress), Self::Error> {
+        let tcp_addr = parse_tcp_addr(&addr)?;
+        let (listener, listen_addr) = self.tcp.listen_on(tcp_addr)?;
+
+        let listener = listener
+            .map_ok(|(fut_socket, dialer_addr)| {
+                let fut_upgrade = fut_socket.and_then(|socket| {
+                    accept_async(IoCompat::new(socket))
+                        .map_ok(WebSocketSocket::new)
+                        .map_err(into_io_error)
+                });
+                (fut_upgrade.boxed(), dialer_addr)
+            })
+            .boxed();
+
+        Ok((listener, listen_addr.push(Protocol::Ws)))
+    }
+
+    fn dial(&self, peer_id: PeerId, addr: NetworkAddress) -> Result<Self::Outbound, Self::Error> {
+        let protos = addr.as_slice();
+        let url = if let Some(((ipaddr, port), _addr_suffix)) = parse_ip_tcp_ws(protos) {
+            format!("ws://{}/", SocketAddr::new(ipaddr, port))
+        } else if let Some(((_ip_filter, dns_name, port), _addr_suffix)) = parse_dns_tcp_ws(protos)
+        {
+            format!("ws://{}:{}/", dns_name, port)
+        } else {
+            return Err(invalid_addr_error(&addr));
+        };
+        let fut_socket = self.tcp.dial(peer_id, parse_tcp_addr(&addr)?)?;
+
+        let fut_upgrade = fut_socket.and_then(|socket| {
+            client_async(url, IoCompat::new(socket))
+                .map_ok(|(stream, _response)| WebSocketSocket::new(stream))
+                .map_err(into_io_error)
+        });
+        Ok(fut_upgrade.boxed())
+    }
+}
+
+/// Returns the `/ip{4,6}/<addr>/tcp/<port>` or `/dns{,4,6}/<name>/tcp/<port>` address of the TCP
+/// connection carrying the WebSockets of `addr`, which must not have any trailing protocols.
+fn parse_tcp_addr(addr: &NetworkAddress) -> io::Result<NetworkAddress> {
+    let protos = addr.as_slice();
+    let addr_suffix = parse_ip_tcp_ws(protos)
+        .map(|x| x.1)
+        .or_else(|| parse_dns_tcp_ws(protos).map(|x| x.1));
+    match addr_suffix {
+        Some(addr_suffix) if addr_suffix.is_empty() => {
+            Ok(NetworkAddress::new(protos[..2].to_vec()))
+        }
+        _ => Err(invalid_addr_error(addr)),
+    }
+}
+
+fn invalid_addr_error(addr: &NetworkAddress) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid NetworkAddress: '{}'", addr),
+    )
+}
+
+fn into_io_error(err: WsError) -> io::Error {
+    match err {
+        WsError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+/// A byte stream over a WebSocket.
+pub struct WebSocketSocket {
+    inner: WebSocketStream<IoCompat<TcpSocket>>,
+    /// Payload of the last binary message received, read up to `read_pos`.
+    read_buf: Vec<u8>,
+    read_pos: usize,
+}
+
+impl WebSocketSocket {
+    fn new(inner: WebSocketStream<IoCompat<TcpSocket>>) -> Self {
+        Self {
+            inner,
+            read_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+impl fmt::Debug for WebSocketSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebSocketSocket")
+            .field("buffered", &(self.read_buf.len() - self.read_pos))
+            .finish()
+    }
+}
+
+impl AsyncRead for WebSocketSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        while this.read_pos == this.read_buf.len() {
+            match ready!(this.inner.poll_next_unpin(context)) {
+                Some(Ok(Message::Binary(payload))) => {
+                    this.read_buf = payload;
+                    this.read_pos = 0;
+                }
+                // pings are answered by the WebSocket itself
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected WebSocket text message",
+                    )))
+                }
+                Some(Ok(Message::Close(_)))
+                | Some(Err(WsError::ConnectionClosed))
+                | Some(Err(WsError::AlreadyClosed))
+                | None => return Poll::Ready(Ok(0)),
+                Some(Err(err)) => return Poll::Ready(Err(into_io_error(err))),
+            }
+        }
+
+        let len = cmp::min(buf.len(), this.read_buf.len() - this.read_pos);
+        buf[..len].copy_from_slice(&this.read_buf[this.read_pos..this.read_pos + len]);
+        this.read_pos += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for WebSocketSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(Pin::new(&mut self.inner).poll_ready(context)).map_err(into_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(into_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(context)
+            .map_err(into_io_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        match ready!(Pin::new(&mut self.inner).poll_close(context)) {
+            Ok(()) | Err(WsError::ConnectionClosed) | Err(WsError::AlreadyClosed) => {
+                Poll::Ready(Ok(()))
+            }
+            Err(err) => Poll::Ready(Err(into_io_error(err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{ConnectionOrigin, TransportExt};
+    use futures::{
+        future::join,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    #[tokio::test]
+    async fn simple_listen_and_dial() -> Result<(), ::std::io::Error> {
+        let t = WebSocketTransport::default().and_then(|mut out, _addr, origin| async move {
+            match origin {
+                ConnectionOrigin::Inbound => {
+                    out.write_all(b"Earth").await?;
+                    out.flush().await?;
+                    let mut buf = [0; 3];
+                    out.read_exact(&mut buf).await?;
+                    assert_eq!(&buf, b"Air");
+                }
+                ConnectionOrigin::Outbound => {
+                    // read the message in two parts
+                    let mut buf = [0; 2];
+                    out.read_exact(&mut buf).await?;
+                    assert_eq!(&buf, b"Ea");
+                    let mut buf = [0; 3];
+                    out.read_exact(&mut buf).await?;
+                    assert_eq!(&buf, b"rth");
+                    out.write_all(b"Air").await?;
+                    out.flush().await?;
+                }
+            }
+            Ok(())
+        });
+
+        let (listener, addr) = t.listen_on("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())?;
+        assert!(parse_ip_tcp_ws(addr.as_slice()).is_some());
+        let peer_id = PeerId::random();
+        let dial = t.dial(peer_id, addr)?;
+        let listener = listener.into_future().then(|(maybe_result, _stream)| {
+            let (incoming, _addr) = maybe_result.unwrap().unwrap();
+            incoming.map(Result::unwrap)
+        });
+
+        let (outgoing, _incoming) = join(dial, listener).await;
+        assert!(outgoing.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_multiaddrs() {
+        let t = WebSocketTransport::default();
+
+        let result = t.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap());
+        assert!(result.is_err());
+
+        let peer_id = PeerId::random();
+        let result = t.dial(peer_id, "/memory/22".parse().unwrap());
+        assert!(result.is_err());
+        let result = t.dial(peer_id, "/dns/example.com/tcp/22".parse().unwrap());
+        assert!(result.is_err());
+        let result = t.listen_on("/dns/localhost/tcp/0/ws".parse().unwrap());
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn dial_dns_name() -> Result<(), ::std::io::Error> {
+        let t = WebSocketTransport::default();
+
+        let (listener, addr) = t.listen_on("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())?;
+        let ((_, port), _) = parse_ip_tcp_ws(addr.as_slice()).unwrap();
+        let dns_addr = format!("/dns4/localhost/tcp/{}/ws", port).parse().unwrap();
+        let dial = t.dial(PeerId::random(), dns_addr)?;
+        let listener = listener.into_future().then(|(maybe_result, _stream)| {
+            let (incoming, _addr) = maybe_result.unwrap().unwrap();
+            incoming
+        });
+
+        let (outgoing, incoming) = join(dial, listener).await;
+        let (mut outgoing, mut incoming) = (outgoing?, incoming?);
+        outgoing.write_all(b"Earth").await?;
+        outgoing.flush().await?;
+        let mut buf = [0; 5];
+        incoming.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Earth");
+        Ok(())
+    }
+}
@@ -16,6 +16,7 @@ use std::{
     iter::IntoIterator,
     net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     num,
+    path::Path,
     str::FromStr,
     string::ToString,
 };
@@ -139,6 +140,12 @@ pub enum Protocol {
     // probably need to move network wire into its own crate to avoid circular
     // dependency b/w network and types.
     Handshake(u8),
+    // WebSocket over the preceding `/ip{4,6}/<addr>/tcp/<port>`, takes no argument
+    Ws,
+    Unix(UnixPath),
+    // Relayed connection through the relay at the preceding `/ip{4,6}/<addr>/tcp/<port>` or
+    // `/dns{,4,6}/<name>/tcp/<port>`, takes no argument
+    P2pCircuit,
 }
 
 /// A minimally parsed DNS name. We don't really do any checking other than
@@ -162,6 +169,17 @@ pub enum Protocol {
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct DnsName(String);
 
+/// The filesystem path of a Unix domain socket. We only enforce that it is not
+/// an empty string and that it does not contain any NUL characters, which the
+/// OS rejects.
+///
+/// As '/' characters are our protocol delimiter, the human-readable
+/// representation of a path percent-encodes its '/' characters as "%2F" and
+/// its '%' characters as "%25", e.g., `/unix/%2Fvar%2Frun%2Flibra.sock` for the
+/// socket at `/var/run/libra.sock`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct UnixPath(String);
+
 /// Possible errors when parsing a human-readable [`NetworkAddress`].
 #[derive(Error, Debug)]
 pub enum ParseError {
@@ -194,6 +212,15 @@ pub enum ParseError {
 
     #[error("dns name is too long: len: {0} bytes, max len: 255 bytes")]
     DnsNameTooLong(usize),
+
+    #[error("unix socket path cannot be empty")]
+    EmptyUnixPathString,
+
+    #[error("unix socket path cannot contain NUL characters")]
+    InvalidUnixPathCharacter,
+
+    #[error("unix socket path must only escape '/' as '%2F' and '%' as '%25'")]
+    InvalidUnixPathEncoding,
 }
 
 #[derive(Error, Debug)]
@@ -322,6 +349,7 @@ impl NetworkAddress {
     /// `"/dns4/<domain>/tcp/<port>"` or
     /// `"/dns6/<domain>/tcp/<port>"` or
     /// `"/dns/<domain>/tcp/<port>"` or
+    /// `"/ip4/<addr>/tcp/<port>/ws"`, `"/dns4/<domain>/tcp/<port>/ws"`, etc. or
     /// cfg!(test) `"/memory/<port>"`
     ///
     /// followed by transport upgrade handshake protocols:
@@ -352,6 +380,35 @@ impl NetworkAddress {
         })
     }
 
+    /// Replace the public key of every `/ln-noise-ik/<pubkey>` protocol, e.g., after the network
+    /// identity key of the peer listening on this address was rotated.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// use libra_crypto::{traits::ValidCryptoMaterialStringExt, x25519};
+    /// use libra_network_address::NetworkAddress;
+    /// use std::str::FromStr;
+    ///
+    /// let pubkey_str = "080e287879c918794170e258bfaddd75acac5b3e350419044655e4983a487120";
+    /// let pubkey = x25519::PublicKey::from_encoded_string(pubkey_str).unwrap();
+    /// let addr_str = "/ip4/1.2.3.4/tcp/6180/ln-noise-ik/d83fc5a5ee66a4a6ae4d6a2da4f9c5bb3b9c62ba5eb22b4b5d32c4e6d7ea5a6c/ln-handshake/0";
+    /// let addr = NetworkAddress::from_str(addr_str).unwrap();
+    /// let addr = addr.rotate_noise_public_key(&pubkey);
+    /// assert_eq!(addr.find_noise_proto(), Some(pubkey));
+    /// ```
+    pub fn rotate_noise_public_key(self, network_pubkey: &x25519::PublicKey) -> Self {
+        Self::new(
+            self.0
+                .into_iter()
+                .map(|proto| match proto {
+                    Protocol::NoiseIK(_) => Protocol::NoiseIK(*network_pubkey),
+                    proto => proto,
+                })
+                .collect(),
+        )
+    }
+
     #[cfg(any(test, feature = "fuzzing"))]
     pub fn mock() -> Self {
         NetworkAddress::new(vec![Protocol::Memory(1234)])
@@ -500,12 +557,28 @@ pub fn arb_libranet_addr() -> impl Strategy<Value = NetworkAddress> {
             .prop_map(|(addr, port)| vec![Protocol::Ip4(addr), Protocol::Tcp(port)]),
         any::<(Ipv6Addr, u16)>()
             .prop_map(|(addr, port)| vec![Protocol::Ip6(addr), Protocol::Tcp(port)]),
+        any::<(Ipv4Addr, u16)>().prop_map(|(addr, port)| vec![
+            Protocol::Ip4(addr),
+            Protocol::Tcp(port),
+            Protocol::Ws
+        ]),
         any::<(DnsName, u16)>()
             .prop_map(|(name, port)| vec![Protocol::Dns(name), Protocol::Tcp(port)]),
         any::<(DnsName, u16)>()
             .prop_map(|(name, port)| vec![Protocol::Dns4(name), Protocol::Tcp(port)]),
         any::<(DnsName, u16)>()
             .prop_map(|(name, port)| vec![Protocol::Dns6(name), Protocol::Tcp(port)]),
+        any::<(DnsName, u16)>().prop_map(|(name, port)| vec![
+            Protocol::Dns4(name),
+            Protocol::Tcp(port),
+            Protocol::Ws
+        ]),
+        any::<UnixPath>().prop_map(|path| vec![Protocol::Unix(path)]),
+        any::<(Ipv4Addr, u16)>().prop_map(|(addr, port)| vec![
+            Protocol::Ip4(addr),
+            Protocol::Tcp(port),
+            Protocol::P2pCircuit
+        ]),
     ];
     let arb_libranet_protos = any::<(x25519::PublicKey, u8)>()
         .prop_map(|(pubkey, hs)| vec![Protocol::NoiseIK(pubkey), Protocol::Handshake(hs)]);
@@ -541,6 +614,9 @@ impl fmt::Display for Protocol {
                     .expect("ValidCryptoMaterialStringExt::to_encoded_string is infallible")
             ),
             Handshake(version) => write!(f, "/ln-handshake/{}", version),
+            Ws => write!(f, "/ws"),
+            Unix(path) => write!(f, "/unix/{}", path),
+            P2pCircuit => write!(f, "/p2p-circuit"),
         }
     }
 }
@@ -571,6 +647,9 @@ impl Protocol {
                 args.next().ok_or(ParseError::UnexpectedEnd)?,
             )?),
             "ln-handshake" => Protocol::Handshake(parse_one(args)?),
+            "ws" => Protocol::Ws,
+            "unix" => Protocol::Unix(parse_one(args)?),
+            "p2p-circuit" => Protocol::P2pCircuit,
             unknown => return Err(ParseError::UnknownProtocolType(unknown.to_string())),
         };
         Ok(protocol)
@@ -677,6 +756,117 @@ impl Arbitrary for DnsName {
     }
 }
 
+//////////////
+// UnixPath //
+//////////////
+
+impl UnixPath {
+    fn validate(s: &str) -> Result<(), ParseError> {
+        if s.is_empty() {
+            Err(ParseError::EmptyUnixPathString)
+        } else if s.contains('\0') {
+            Err(ParseError::InvalidUnixPathCharacter)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decodes the percent-encoded '/' and '%' characters of a human-readable
+    /// path.
+    fn decode(s: &str) -> Result<String, ParseError> {
+        let mut path = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '%' => match (chars.next(), chars.next()) {
+                    (Some('2'), Some('F')) | (Some('2'), Some('f')) => path.push('/'),
+                    (Some('2'), Some('5')) => path.push('%'),
+                    _ => return Err(ParseError::InvalidUnixPathEncoding),
+                },
+                '/' => return Err(ParseError::InvalidUnixPathEncoding),
+                c => path.push(c),
+            }
+        }
+        Ok(path)
+    }
+}
+
+impl Into<String> for UnixPath {
+    fn into(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for UnixPath {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+impl AsRef<Path> for UnixPath {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl TryFrom<String> for UnixPath {
+    type Error = ParseError;
+
+    /// Takes the path itself, not its percent-encoded representation.
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        UnixPath::validate(s.as_str()).map(|_| UnixPath(s))
+    }
+}
+
+impl FromStr for UnixPath {
+    type Err = ParseError;
+
+    /// Parses the percent-encoded representation of a path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UnixPath::try_from(UnixPath::decode(s)?)
+    }
+}
+
+impl fmt::Display for UnixPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '/' => f.write_str("%2F")?,
+                '%' => f.write_str("%25")?,
+                c => fmt::Write::write_char(f, c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for UnixPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename = "UnixPath")]
+        struct DeserializeWrapper(String);
+
+        let wrapper = DeserializeWrapper::deserialize(deserializer)?;
+        let path = UnixPath::try_from(wrapper.0).map_err(de::Error::custom)?;
+        Ok(path)
+    }
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+impl Arbitrary for UnixPath {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        // generate arbitrary unicode strings, including '/' and '%' characters
+        // to exercise the encoding, but without control characters
+        r"[^\pC]{1,100}".prop_map(UnixPath).boxed()
+    }
+}
+
 /////////////
 // Parsing //
 /////////////
@@ -709,6 +899,23 @@ pub fn parse_ip_tcp(protos: &[Protocol]) -> Option<((IpAddr, u16), &[Protocol])>
     }
 }
 
+/// parse the `&[Protocol]` into the `"/ip4/<addr>/tcp/<port>/ws"` or
+/// `"/ip6/<addr>/tcp/<port>/ws"` prefix and unparsed `&[Protocol]` suffix.
+pub fn parse_ip_tcp_ws(protos: &[Protocol]) -> Option<((IpAddr, u16), &[Protocol])> {
+    use Protocol::*;
+
+    if protos.len() < 3 {
+        return None;
+    }
+
+    let (prefix, suffix) = protos.split_at(3);
+    match prefix {
+        [Ip4(ip), Tcp(port), Ws] => Some(((IpAddr::V4(*ip), *port), suffix)),
+        [Ip6(ip), Tcp(port), Ws] => Some(((IpAddr::V6(*ip), *port), suffix)),
+        _ => None,
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum IpFilter {
     Any,
@@ -745,6 +952,38 @@ pub fn parse_dns_tcp(protos: &[Protocol]) -> Option<((IpFilter, &DnsName, u16),
     }
 }
 
+/// parse the `&[Protocol]` into the `"/dns/<domain>/tcp/<port>/ws"`,
+/// `"/dns4/<domain>/tcp/<port>/ws"`, or `"/dns6/<domain>/tcp/<port>/ws"` prefix
+/// and unparsed `&[Protocol]` suffix.
+pub fn parse_dns_tcp_ws(protos: &[Protocol]) -> Option<((IpFilter, &DnsName, u16), &[Protocol])> {
+    match parse_dns_tcp(protos) {
+        Some((dns_tcp, [Protocol::Ws, suffix @ ..])) => Some((dns_tcp, suffix)),
+        _ => None,
+    }
+}
+
+/// parse the `&[Protocol]` into the `"/<relay>/p2p-circuit"` prefix, where
+/// `<relay>` is `"/ip{4,6}/<addr>/tcp/<port>"` or `"/dns{,4,6}/<name>/tcp/<port>"`,
+/// and unparsed `&[Protocol]` suffix. Returns the `<relay>` protocols.
+pub fn parse_circuit(protos: &[Protocol]) -> Option<(&[Protocol], &[Protocol])> {
+    let relay_suffix = parse_ip_tcp(protos)
+        .map(|x| x.1)
+        .or_else(|| parse_dns_tcp(protos).map(|x| x.1))?;
+    match relay_suffix {
+        [Protocol::P2pCircuit, suffix @ ..] => Some((&protos[..2], suffix)),
+        _ => None,
+    }
+}
+
+/// parse the `&[Protocol]` into the `"/unix/<path>"` prefix and unparsed
+/// `&[Protocol]` suffix.
+pub fn parse_unix(protos: &[Protocol]) -> Option<(&UnixPath, &[Protocol])> {
+    match protos.split_first() {
+        Some((Protocol::Unix(path), suffix)) => Some((path, suffix)),
+        _ => None,
+    }
+}
+
 /// parse the `&[Protocol]` into the `"/ln-noise-ik/<pubkey>"` prefix and
 /// unparsed `&[Protocol]` suffix.
 pub fn parse_noise_ik(protos: &[Protocol]) -> Option<(&x25519::PublicKey, &[Protocol])> {
@@ -769,13 +1008,21 @@ pub fn parse_handshake(protos: &[Protocol]) -> Option<(u8, &[Protocol])> {
 fn parse_libranet_protos(protos: &[Protocol]) -> Option<&[Protocol]> {
     // parse base transport layer
     // ---
-    // parse_ip_tcp
+    // parse_circuit
+    // <or> parse_ip_tcp_ws
+    // <or> parse_ip_tcp
+    // <or> parse_dns_tcp_ws
     // <or> parse_dns_tcp
+    // <or> parse_unix
     // <or> cfg!(test) parse_memory
 
-    let transport_suffix = parse_ip_tcp(protos)
+    let transport_suffix = parse_circuit(protos)
         .map(|x| x.1)
+        .or_else(|| parse_ip_tcp_ws(protos).map(|x| x.1))
+        .or_else(|| parse_ip_tcp(protos).map(|x| x.1))
+        .or_else(|| parse_dns_tcp_ws(protos).map(|x| x.1))
         .or_else(|| parse_dns_tcp(protos).map(|x| x.1))
+        .or_else(|| parse_unix(protos).map(|x| x.1))
         .or_else(|| {
             if cfg!(test) {
                 parse_memory(protos).map(|x| x.1)
@@ -859,6 +1106,18 @@ mod test {
                 "/dns/example.com/tcp/80",
                 vec![Dns(DnsName("example.com".to_owned())), Tcp(80)],
             ),
+            (
+                "/ip4/12.34.56.78/tcp/1234/p2p-circuit",
+                vec![Ip4(Ipv4Addr::new(12, 34, 56, 78)), Tcp(1234), P2pCircuit],
+            ),
+            (
+                "/ip6/::1/tcp/8080/ws",
+                vec![Ip6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), Tcp(8080), Ws],
+            ),
+            (
+                "/unix/%2Fvar%2Frun%2Flibra%25.sock",
+                vec![Unix(UnixPath("/var/run/libra%.sock".to_owned()))],
+            ),
             (
                 &noise_addr_str,
                 vec![
@@ -894,6 +1153,11 @@ mod test {
             "/ip4/1.1.1.1.",
             "/ip4/1.1.1.1.1",
             "/ip4/1.1.1.999.1",
+            "/unix",
+            "/unix/",
+            "/unix/%2",
+            "/unix/%2Fvar%2Frun%2Flibra.sock%",
+            "/unix/foo%20bar",
         ];
 
         for &addr_str in &test_cases {
@@ -953,6 +1217,28 @@ mod test {
         assert_eq!(None, parse_ip_tcp(addr.as_slice()));
     }
 
+    #[test]
+    fn test_parse_ip_tcp_ws() {
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123/ws").unwrap();
+        let expected_suffix: &[Protocol] = &[];
+        assert_eq!(
+            parse_ip_tcp_ws(addr.as_slice()).unwrap(),
+            ((IpAddr::from_str("1.2.3.4").unwrap(), 123), expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/ip6/::1/tcp/123/ws/ln-handshake/0").unwrap();
+        let expected_suffix: &[Protocol] = &[Protocol::Handshake(0)];
+        assert_eq!(
+            parse_ip_tcp_ws(addr.as_slice()).unwrap(),
+            ((IpAddr::from_str("::1").unwrap(), 123), expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123").unwrap();
+        assert_eq!(None, parse_ip_tcp_ws(addr.as_slice()));
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/ws/tcp/123").unwrap();
+        assert_eq!(None, parse_ip_tcp_ws(addr.as_slice()));
+    }
+
     #[test]
     fn test_parse_dns_tcp() {
         let dns_name = DnsName::from_str("example.com").unwrap();
@@ -988,6 +1274,74 @@ mod test {
         assert_eq!(None, parse_dns_tcp(addr.as_slice()));
     }
 
+    #[test]
+    fn test_parse_dns_tcp_ws() {
+        let dns_name = DnsName::from_str("example.com").unwrap();
+        let addr = NetworkAddress::from_str("/dns4/example.com/tcp/123/ws").unwrap();
+        let expected_suffix: &[Protocol] = &[];
+        assert_eq!(
+            parse_dns_tcp_ws(addr.as_slice()).unwrap(),
+            ((IpFilter::OnlyIp4, &dns_name, 123), expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/dns/example.com/tcp/123/ws/ln-handshake/0").unwrap();
+        let expected_suffix: &[Protocol] = &[Protocol::Handshake(0)];
+        assert_eq!(
+            parse_dns_tcp_ws(addr.as_slice()).unwrap(),
+            ((IpFilter::Any, &dns_name, 123), expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/dns/example.com/tcp/123").unwrap();
+        assert_eq!(None, parse_dns_tcp_ws(addr.as_slice()));
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123/ws").unwrap();
+        assert_eq!(None, parse_dns_tcp_ws(addr.as_slice()));
+    }
+
+    #[test]
+    fn test_parse_circuit() {
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123/p2p-circuit").unwrap();
+        let expected_suffix: &[Protocol] = &[];
+        assert_eq!(
+            parse_circuit(addr.as_slice()).unwrap(),
+            (&addr.as_slice()[..2], expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/dns/example.com/tcp/123/p2p-circuit/ln-handshake/0")
+            .unwrap();
+        let expected_suffix: &[Protocol] = &[Protocol::Handshake(0)];
+        assert_eq!(
+            parse_circuit(addr.as_slice()).unwrap(),
+            (&addr.as_slice()[..2], expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123").unwrap();
+        assert_eq!(None, parse_circuit(addr.as_slice()));
+        let addr = NetworkAddress::from_str("/ip4/1.2.3.4/tcp/123/ws/p2p-circuit").unwrap();
+        assert_eq!(None, parse_circuit(addr.as_slice()));
+    }
+
+    #[test]
+    fn test_parse_unix() {
+        let path = UnixPath::try_from("/var/run/libra.sock".to_owned()).unwrap();
+        let addr = NetworkAddress::from_str("/unix/%2Fvar%2Frun%2Flibra.sock").unwrap();
+        let expected_suffix: &[Protocol] = &[];
+        assert_eq!(
+            parse_unix(addr.as_slice()).unwrap(),
+            (&path, expected_suffix)
+        );
+
+        let addr =
+            NetworkAddress::from_str("/unix/%2Fvar%2Frun%2Flibra.sock/ln-handshake/0").unwrap();
+        let expected_suffix: &[Protocol] = &[Protocol::Handshake(0)];
+        assert_eq!(
+            parse_unix(addr.as_slice()).unwrap(),
+            (&path, expected_suffix)
+        );
+
+        let addr = NetworkAddress::from_str("/tcp/999/memory/123").unwrap();
+        assert_eq!(None, parse_unix(addr.as_slice()));
+    }
+
     #[test]
     fn test_parse_noise_ik() {
         let pubkey_str = "080e287879c918794170e258bfaddd75acac5b3e350419044655e4983a487120";
@@ -159,7 +159,7 @@ where
 
     fn handle_connection_notif(&mut self, notif: ConnectionNotification) {
         match notif {
-            ConnectionNotification::NewPeer(peer_id, _addr) => {
+            ConnectionNotification::NewPeer(peer_id, _addr, _) => {
                 trace!("connected to new peer: {}", peer_id.short_str());
                 // Add peer to connected peer list.
                 self.connected_peers.insert(peer_id);
@@ -169,6 +169,7 @@ where
                 // Remove peer from connected peer list.
                 self.connected_peers.remove(&peer_id);
             }
+            ConnectionNotification::TrustedPeersUpdated(_) => {}
         }
     }
 
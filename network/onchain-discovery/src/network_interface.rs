@@ -6,10 +6,12 @@ use crate::types::{OnchainDiscoveryMsg, QueryDiscoverySetRequest, QueryDiscovery
 use channel::{libra_channel, message_queues::QueueStyle};
 use libra_types::PeerId;
 use network::{
+    error::NetworkError,
     peer_manager::{
         ConnectionNotification, ConnectionRequestSender, PeerManagerNotification,
         PeerManagerRequestSender,
     },
+    priority::ProtocolPriority,
     protocols::{network::NetworkSender, rpc::error::RpcError},
     validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
     ProtocolId,
@@ -59,7 +61,7 @@ impl OnchainDiscoveryNetworkSender {
         recipient: PeerId,
         req_msg: QueryDiscoverySetRequest,
         timeout: Duration,
-    ) -> Result<Box<QueryDiscoverySetResponse>, RpcError> {
+    ) -> Result<Box<QueryDiscoverySetResponse>, NetworkError> {
         let protocol = ProtocolId::OnchainDiscoveryRpc;
         let req_msg_enum = OnchainDiscoveryMsg::QueryDiscoverySetRequest(req_msg);
 
@@ -71,7 +73,7 @@ impl OnchainDiscoveryNetworkSender {
         let res_msg = match res_msg_enum {
             OnchainDiscoveryMsg::QueryDiscoverySetResponse(res_msg) => res_msg,
             OnchainDiscoveryMsg::QueryDiscoverySetRequest(_) => {
-                return Err(RpcError::InvalidRpcResponse);
+                return Err(RpcError::InvalidRpcResponse.into());
             }
         };
         Ok(res_msg)
@@ -87,6 +89,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![ProtocolId::OnchainDiscoveryRpc],
             vec![],
+            ProtocolPriority::Normal,
             QueueStyle::LIFO,
             NETWORK_CHANNEL_SIZE,
             // Some(&counters::PENDING_CONSENSUS_NETWORK_EVENTS),
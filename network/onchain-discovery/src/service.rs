@@ -16,7 +16,11 @@ use futures::{
 };
 use libra_logger::prelude::*;
 use libra_types::PeerId;
-use network::{peer_manager::PeerManagerNotification, protocols::rpc::error::RpcError, ProtocolId};
+use network::{
+    peer_manager::PeerManagerNotification,
+    protocols::{network::decoding::Decoded, rpc::error::RpcError, wire::handshake::v1::Encoding},
+    ProtocolId,
+};
 use std::{sync::Arc, task::Context};
 use storage_interface::DbReader;
 use tokio::runtime::Handle;
@@ -55,7 +59,7 @@ impl OnchainDiscoveryService {
 
         while let Some(event) = self.peer_mgr_notifs_rx.next().await {
             match event {
-                PeerManagerNotification::RecvRpc(peer_id, rpc_req) => {
+                PeerManagerNotification::RecvRpc(peer_id, rpc_req, encoding) => {
                     let peer_id_short = peer_id.short_str();
                     trace!("received inbound rpc from peer: {}", peer_id_short);
                     if let Err(err) = self.handle_inbound_rpc(
@@ -63,6 +67,7 @@ impl OnchainDiscoveryService {
                         rpc_req.protocol,
                         rpc_req.data,
                         rpc_req.res_tx,
+                        encoding,
                     ) {
                         warn!(
                             "error handling peer's inbound rpc request: peer: {}, err: {:?}",
@@ -70,7 +75,7 @@ impl OnchainDiscoveryService {
                         );
                     }
                 }
-                PeerManagerNotification::RecvMessage(peer_id, msg) => {
+                PeerManagerNotification::RecvMessage(peer_id, msg, _) => {
                     warn!(
                         "unexpected direct-send message from network: peer: {}, msg: {:?}",
                         peer_id.short_str(),
@@ -88,6 +93,7 @@ impl OnchainDiscoveryService {
         protocol: ProtocolId,
         data: Bytes,
         res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+        encoding: Encoding,
     ) -> anyhow::Result<()> {
         ensure!(
             protocol == ProtocolId::OnchainDiscoveryRpc,
@@ -95,8 +101,13 @@ impl OnchainDiscoveryService {
             protocol
         );
 
-        let req_msg: OnchainDiscoveryMsg =
-            lcs::from_bytes(data.as_ref()).context("failed to deserialize rpc")?;
+        let req_msg: OnchainDiscoveryMsg = match encoding
+            .decode(protocol.decoding(), data.as_ref())
+            .context("failed to deserialize rpc")?
+        {
+            Decoded::Message(req_msg) => req_msg,
+            Decoded::UnknownVariant(index) => bail!("unknown rpc variant from peer: {}", index),
+        };
 
         let req_msg = match req_msg {
             OnchainDiscoveryMsg::QueryDiscoverySetRequest(req_msg) => req_msg,
@@ -115,6 +126,7 @@ impl OnchainDiscoveryService {
                 peer_id,
                 req_msg,
                 res_tx,
+                encoding,
             ))
             .map(|_| ())
             .map_err(|_| {
@@ -128,6 +140,7 @@ async fn handle_query_discovery_set_request(
     peer_id: PeerId,
     req_msg: QueryDiscoverySetRequest,
     mut res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+    encoding: Encoding,
 ) {
     let mut f_query_storage = storage_query_discovery_set_async(libra_db, req_msg).fuse();
     let mut f_rpc_cancel = future::poll_fn(|cx: &mut Context<'_>| res_tx.poll_canceled(cx)).fuse();
@@ -144,7 +157,7 @@ async fn handle_query_discovery_set_request(
             };
 
             let res_msg = OnchainDiscoveryMsg::QueryDiscoverySetResponse(res_msg);
-            let res_bytes = match lcs::to_bytes(&res_msg) {
+            let res_bytes = match encoding.encode(&res_msg) {
                 Ok(res_bytes) => res_bytes,
                 Err(err) => {
                     error!("failed to serialize response message: err: {:?}, res_msg: {:?}", err, res_msg);
@@ -28,10 +28,13 @@ use libradb::LibraDB;
 use network::{
     connectivity_manager::{ConnectivityRequest, DiscoverySource},
     peer_manager::{
-        ConnectionNotification, ConnectionRequestSender, PeerManagerNotification,
+        ConnectionInfo, ConnectionNotification, ConnectionRequestSender, PeerManagerNotification,
         PeerManagerRequest, PeerManagerRequestSender,
     },
-    protocols::rpc::{InboundRpcRequest, OutboundRpcRequest},
+    protocols::{
+        rpc::{InboundRpcRequest, OutboundRpcRequest},
+        wire::handshake::v1::Encoding,
+    },
     ProtocolId,
 };
 use std::{
@@ -78,7 +81,7 @@ impl MockOnchainDiscoveryNetworkSender {
         self.peer_mgr_notifs_tx
             .push_with_feedback(
                 (recipient, ProtocolId::OnchainDiscoveryRpc),
-                PeerManagerNotification::RecvRpc(recipient, inbound_rpc_req),
+                PeerManagerNotification::RecvRpc(recipient, inbound_rpc_req, Encoding::Lcs),
                 Some(delivered_tx),
             )
             .unwrap();
@@ -96,7 +99,7 @@ impl MockOnchainDiscoveryNetworkSender {
 
     async fn new_peer(&mut self, peer_id: PeerId) {
         let addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/1234").unwrap();
-        let notif = ConnectionNotification::NewPeer(peer_id, addr);
+        let notif = ConnectionNotification::NewPeer(peer_id, addr, ConnectionInfo::mock());
         self.send_connection_notif(peer_id, notif).await;
     }
 
@@ -118,6 +121,7 @@ impl MockOnchainDiscoveryNetworkSender {
                     res_tx,
                     ..
                 },
+                _,
             ) => (
                 protocol,
                 PeerManagerNotification::RecvRpc(
@@ -127,6 +131,7 @@ impl MockOnchainDiscoveryNetworkSender {
                         data,
                         res_tx,
                     },
+                    Encoding::Lcs,
                 ),
             ),
             _ => panic!("Unexpected request, expected SendRpc: {:?}", outbound_req),
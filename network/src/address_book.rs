@@ -0,0 +1,178 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistence of the addresses at which the peers of a network were last reached.
+//!
+//! The addresses discovered at runtime, e.g., by gossip, are otherwise lost on restart, so that a
+//! full node must bootstrap from its seed peers every time. When the network has an
+//! [`AddressBook`], the connectivity manager records the addresses it successfully connects to
+//! in it, and dials the addresses loaded from it on startup, after the addresses of all the other
+//! discovery sources. Addresses not reached for longer than the staleness of the address book are
+//! pruned, both on load and on every update.
+
+use anyhow::{Context, Result};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Default period after which an address not reached again is pruned from the address book.
+pub const DEFAULT_ADDRESS_STALENESS: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// An address at which a peer was reached.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct KnownAddress {
+    pub addr: NetworkAddress,
+    /// Seconds since the Unix epoch at which the peer was last reached at `addr`.
+    pub last_seen_secs: u64,
+}
+
+/// The known addresses of the peers of a network, the most recently reached first.
+pub type KnownAddresses = HashMap<PeerId, Vec<KnownAddress>>;
+
+/// Store of the addresses at which the peers of a network were reached.
+pub trait AddressBook: Send {
+    /// Loads the stored addresses, empty if nothing was stored yet.
+    fn load(&self) -> Result<KnownAddresses>;
+
+    /// Replaces the stored addresses with `addresses`.
+    fn store(&self, addresses: &KnownAddresses) -> Result<()>;
+}
+
+/// An [`AddressBook`] persisted to a file.
+pub struct FileAddressBook {
+    path: PathBuf,
+}
+
+impl FileAddressBook {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl AddressBook for FileAddressBook {
+    fn load(&self) -> Result<KnownAddresses> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read address book from {:?}", self.path))
+            }
+        };
+        lcs::from_bytes(&bytes)
+            .with_context(|| format!("Failed to parse address book from {:?}", self.path))
+    }
+
+    /// Replaces the content of the file atomically, so that a crash never leaves a truncated file
+    /// behind.
+    fn store(&self, addresses: &KnownAddresses) -> Result<()> {
+        let bytes = lcs::to_bytes(addresses)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, &bytes)
+            .with_context(|| format!("Failed to write address book to {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write address book to {:?}", self.path))
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_secs()
+}
+
+/// Records that `peer_id` was reached at `addr` at `now_secs`, moving `addr` first.
+pub fn record(
+    addresses: &mut KnownAddresses,
+    peer_id: PeerId,
+    addr: NetworkAddress,
+    now_secs: u64,
+) {
+    let known = addresses.entry(peer_id).or_default();
+    known.retain(|known_addr| known_addr.addr != addr);
+    known.insert(
+        0,
+        KnownAddress {
+            addr,
+            last_seen_secs: now_secs,
+        },
+    );
+}
+
+/// Removes the addresses not reached within `staleness` of `now_secs`, and the peers left without
+/// addresses. Returns the number of addresses removed.
+pub fn prune_stale(addresses: &mut KnownAddresses, now_secs: u64, staleness: Duration) -> usize {
+    let oldest = now_secs.saturating_sub(staleness.as_secs());
+    let mut pruned = 0;
+    addresses.retain(|_, known| {
+        let len = known.len();
+        known.retain(|known_addr| known_addr.last_seen_secs >= oldest);
+        pruned += len - known.len();
+        !known.is_empty()
+    });
+    pruned
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_temppath::TempPath;
+
+    #[test]
+    fn record_and_prune() {
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+        let addr_1: NetworkAddress = "/ip4/127.0.0.1/tcp/9090".parse().unwrap();
+        let addr_2: NetworkAddress = "/ip4/127.0.0.1/tcp/9091".parse().unwrap();
+        let mut addresses = KnownAddresses::new();
+        record(&mut addresses, peer_a, addr_1.clone(), 10);
+        record(&mut addresses, peer_a, addr_2.clone(), 20);
+        record(&mut addresses, peer_b, addr_1.clone(), 20);
+        record(&mut addresses, peer_a, addr_1.clone(), 30);
+        assert_eq!(
+            addresses[&peer_a],
+            vec![
+                KnownAddress {
+                    addr: addr_1,
+                    last_seen_secs: 30,
+                },
+                KnownAddress {
+                    addr: addr_2,
+                    last_seen_secs: 20,
+                },
+            ]
+        );
+
+        assert_eq!(prune_stale(&mut addresses, 35, Duration::from_secs(10)), 2);
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(addresses[&peer_a].len(), 1);
+    }
+
+    #[test]
+    fn file_address_book() {
+        let path = TempPath::new();
+        let address_book = FileAddressBook::new(path.path().to_path_buf());
+        assert!(address_book.load().unwrap().is_empty());
+
+        let mut addresses = KnownAddresses::new();
+        record(
+            &mut addresses,
+            PeerId::random(),
+            "/ip4/127.0.0.1/tcp/9090".parse().unwrap(),
+            now_secs(),
+        );
+        address_book.store(&addresses).unwrap();
+        assert_eq!(address_book.load().unwrap(), addresses);
+
+        fs::write(path.path(), b"garbage").unwrap();
+        assert!(address_book.load().is_err());
+    }
+}
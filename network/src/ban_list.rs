@@ -0,0 +1,224 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The peers, and the IP prefixes, banned from a network for a while, e.g., after consensus
+//! detects a peer equivocating.
+//!
+//! Bans are requested with [`ConnectivityRequest::BanPeer`] and
+//! [`ConnectivityRequest::BanIpPrefix`]: the connectivity manager disconnects the banned peers
+//! and stops dialing them, and the peer manager closes the connections they establish, until
+//! their ban expires.
+//!
+//! [`ConnectivityRequest::BanPeer`]: crate::connectivity_manager::ConnectivityRequest::BanPeer
+//! [`ConnectivityRequest::BanIpPrefix`]: crate::connectivity_manager::ConnectivityRequest::BanIpPrefix
+
+use anyhow::{ensure, format_err, Error};
+use libra_network_address::{NetworkAddress, Protocol};
+use libra_types::PeerId;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// A range of IP addresses sharing their first `len` bits, e.g., `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl IpPrefix {
+    pub fn new(addr: IpAddr, len: u8) -> Result<Self, Error> {
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        ensure!(
+            len <= max_len,
+            "Invalid length {} of IP prefix {}",
+            len,
+            addr
+        );
+        Ok(Self { addr, len })
+    }
+
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) => {
+                Self::matches(&prefix.octets(), &addr.octets(), self.len)
+            }
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) => {
+                Self::matches(&prefix.octets(), &addr.octets(), self.len)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the first `len` bits of `prefix` and `addr` are equal.
+    fn matches(prefix: &[u8], addr: &[u8], len: u8) -> bool {
+        let (bytes, bits) = ((len / 8) as usize, len % 8);
+        if prefix[..bytes] != addr[..bytes] {
+            return false;
+        }
+        bits == 0 || {
+            let mask = 0xffu8 << (8 - bits);
+            prefix[bytes] & mask == addr[bytes] & mask
+        }
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let addr: IpAddr = parts.next().unwrap_or_default().parse()?;
+        let len = parts
+            .next()
+            .ok_or_else(|| format_err!("Missing length of IP prefix {}", s))?
+            .parse()?;
+        Self::new(addr, len)
+    }
+}
+
+impl fmt::Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bans {
+    /// Banned peers, until when
+    peers: HashMap<PeerId, Instant>,
+    /// Banned IP prefixes, until when
+    ip_prefixes: HashMap<IpPrefix, Instant>,
+}
+
+/// The bans of a network. Cloning it returns a handle to the same bans.
+#[derive(Clone, Debug, Default)]
+pub struct BanList {
+    inner: Arc<RwLock<Bans>>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bans `peer_id` for `duration`, or until the end of its current ban if later.
+    pub fn ban_peer(&self, peer_id: PeerId, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bans = self.inner.write().unwrap();
+        let entry = bans.peers.entry(peer_id).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    /// Bans the peers connecting from an IP address in `prefix` for `duration`, or until the end
+    /// of its current ban if later.
+    pub fn ban_ip_prefix(&self, prefix: IpPrefix, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bans = self.inner.write().unwrap();
+        let entry = bans.ip_prefixes.entry(prefix).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    pub fn is_peer_banned(&self, peer_id: &PeerId) -> bool {
+        let now = Instant::now();
+        self.inner
+            .read()
+            .unwrap()
+            .peers
+            .get(peer_id)
+            .map_or(false, |until| *until > now)
+    }
+
+    /// Whether `addr` is an IP address in a banned prefix. Addresses naming a host are only
+    /// resolved when dialed, so that they are never banned.
+    pub fn is_address_banned(&self, addr: &NetworkAddress) -> bool {
+        let ip_addr = match addr.as_slice().first() {
+            Some(Protocol::Ip4(addr)) => IpAddr::V4(*addr),
+            Some(Protocol::Ip6(addr)) => IpAddr::V6(*addr),
+            _ => return false,
+        };
+        let now = Instant::now();
+        self.inner
+            .read()
+            .unwrap()
+            .ip_prefixes
+            .iter()
+            .any(|(prefix, until)| *until > now && prefix.contains(&ip_addr))
+    }
+
+    /// Whether `peer_id`, connected at `addr`, is banned.
+    pub fn is_banned(&self, peer_id: &PeerId, addr: &NetworkAddress) -> bool {
+        self.is_peer_banned(peer_id) || self.is_address_banned(addr)
+    }
+
+    /// Forgets the expired bans.
+    pub(crate) fn prune(&self) {
+        let now = Instant::now();
+        let mut bans = self.inner.write().unwrap();
+        bans.peers.retain(|_, until| *until > now);
+        bans.ip_prefixes.retain(|_, until| *until > now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ip_prefix() {
+        let prefix: IpPrefix = "10.1.0.0/16".parse().unwrap();
+        assert!(prefix.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!prefix.contains(&"10.2.2.3".parse().unwrap()));
+        assert!(!prefix.contains(&"::1".parse().unwrap()));
+
+        let prefix: IpPrefix = "192.168.0.128/25".parse().unwrap();
+        assert!(prefix.contains(&"192.168.0.200".parse().unwrap()));
+        assert!(!prefix.contains(&"192.168.0.100".parse().unwrap()));
+        assert_eq!(prefix.to_string(), "192.168.0.128/25");
+
+        let prefix: IpPrefix = "2001:db8::/32".parse().unwrap();
+        assert!(prefix.contains(&"2001:db8::1".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<IpPrefix>()
+            .unwrap()
+            .contains(&"1.2.3.4".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpPrefix>().is_err());
+        assert!("10.0.0.0".parse::<IpPrefix>().is_err());
+    }
+
+    #[test]
+    fn bans_expire() {
+        let ban_list = BanList::new();
+        let peer_id = PeerId::random();
+        let addr: NetworkAddress = "/ip4/10.1.2.3/tcp/6180".parse().unwrap();
+        assert!(!ban_list.is_banned(&peer_id, &addr));
+
+        ban_list.ban_peer(peer_id, Duration::from_secs(3600));
+        assert!(ban_list.clone().is_peer_banned(&peer_id));
+        assert!(!ban_list.is_peer_banned(&PeerId::random()));
+
+        ban_list.ban_ip_prefix("10.1.0.0/16".parse().unwrap(), Duration::from_secs(3600));
+        assert!(ban_list.is_banned(&PeerId::random(), &addr));
+        let dns_addr: NetworkAddress = "/dns4/example.com/tcp/6180".parse().unwrap();
+        assert!(!ban_list.is_address_banned(&dns_addr));
+
+        // a shorter ban does not cut an ongoing one short
+        ban_list.ban_peer(peer_id, Duration::from_secs(0));
+        assert!(ban_list.is_peer_banned(&peer_id));
+
+        let other_peer_id = PeerId::random();
+        ban_list.ban_peer(other_peer_id, Duration::from_secs(0));
+        assert!(!ban_list.is_peer_banned(&other_peer_id));
+        ban_list.prune();
+        assert_eq!(ban_list.inner.read().unwrap().peers.len(), 1);
+    }
+}
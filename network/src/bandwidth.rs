@@ -0,0 +1,306 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Caps on the bandwidth of a network, e.g., for full nodes on metered or residential links.
+//!
+//! The `BandwidthManager` of a network holds a token bucket of bytes per direction, shared by all
+//! the connections of the network and of its additional listeners. Every connection established by
+//! the `ThrottledTransport` reads and writes its socket only while the bucket of the direction
+//! holds bytes, and waits for the bucket to refill otherwise, so that TCP pushes back on the peers.
+//! The bytes are refilled at the configured rate, up to one second of traffic.
+//!
+//! The bytes are counted on the base transport, i.e., including the Noise and framing overhead.
+
+use futures::{
+    future::{Future, FutureExt, TryFutureExt},
+    io::{AsyncRead, AsyncWrite},
+    ready,
+    stream::{Stream, StreamExt, TryStreamExt},
+};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use netcore::transport::Transport;
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, Delay};
+
+#[derive(Debug)]
+struct ByteBucket {
+    bytes_per_sec: u64,
+    /// Negative when concurrent connections consumed more bytes than were available.
+    bytes: f64,
+    refilled_at: Instant,
+}
+
+impl ByteBucket {
+    fn new(bytes_per_sec: u64, now: Instant) -> Self {
+        Self {
+            bytes_per_sec,
+            bytes: bytes_per_sec as f64,
+            refilled_at: now,
+        }
+    }
+
+    /// The bytes available, or how long until some are.
+    fn available(&mut self, now: Instant) -> Result<usize, Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        let rate = self.bytes_per_sec as f64;
+        self.bytes = (self.bytes + elapsed.as_secs_f64() * rate).min(rate);
+        self.refilled_at = now;
+        if self.bytes >= 1.0 {
+            Ok(self.bytes as usize)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.bytes) / rate))
+        }
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.bytes -= bytes as f64;
+    }
+}
+
+/// The bandwidth of one direction, unlimited if `None`.
+#[derive(Clone, Debug, Default)]
+struct BandwidthLimit(Option<Arc<Mutex<ByteBucket>>>);
+
+impl BandwidthLimit {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self(
+            bytes_per_sec
+                .filter(|bytes_per_sec| *bytes_per_sec > 0)
+                .map(|bytes_per_sec| {
+                    Arc::new(Mutex::new(ByteBucket::new(bytes_per_sec, Instant::now())))
+                }),
+        )
+    }
+
+    /// Polls for the bytes available, waiting on `delay` for the bucket to refill.
+    fn poll_available(&self, delay: &mut Option<Delay>, cx: &mut Context) -> Poll<usize> {
+        let bucket = match &self.0 {
+            Some(bucket) => bucket,
+            None => return Poll::Ready(usize::MAX),
+        };
+        loop {
+            if let Some(pending_delay) = delay {
+                ready!(Pin::new(pending_delay).poll(cx));
+                *delay = None;
+            }
+            match bucket.lock().unwrap().available(Instant::now()) {
+                Ok(bytes) => return Poll::Ready(bytes),
+                Err(wait) => *delay = Some(delay_for(wait)),
+            }
+        }
+    }
+
+    fn consume(&self, bytes: usize) {
+        if let Some(bucket) = &self.0 {
+            bucket.lock().unwrap().consume(bytes);
+        }
+    }
+}
+
+/// The bandwidth of a network. Cloning it returns a handle to the same bandwidth.
+#[derive(Clone, Debug, Default)]
+pub struct BandwidthManager {
+    outbound: BandwidthLimit,
+    inbound: BandwidthLimit,
+}
+
+impl BandwidthManager {
+    /// Caps the traffic of the network to the given bytes per second, `None` meaning unlimited.
+    pub fn new(
+        max_outbound_bytes_per_sec: Option<u64>,
+        max_inbound_bytes_per_sec: Option<u64>,
+    ) -> Self {
+        Self {
+            outbound: BandwidthLimit::new(max_outbound_bytes_per_sec),
+            inbound: BandwidthLimit::new(max_inbound_bytes_per_sec),
+        }
+    }
+
+    pub fn throttle<T>(&self, socket: T) -> ThrottledSocket<T> {
+        ThrottledSocket {
+            socket,
+            bandwidth: self.clone(),
+            read_delay: None,
+            write_delay: None,
+        }
+    }
+}
+
+/// A socket reading and writing within the bandwidth of its network.
+pub struct ThrottledSocket<T> {
+    socket: T,
+    bandwidth: BandwidthManager,
+    read_delay: Option<Delay>,
+    write_delay: Option<Delay>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for ThrottledSocket<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThrottledSocket")
+            .field("socket", &self.socket)
+            .finish()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ThrottledSocket<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let available = ready!(this
+            .bandwidth
+            .inbound
+            .poll_available(&mut this.read_delay, cx));
+        let len = buf.len().min(available);
+        let bytes = ready!(Pin::new(&mut this.socket).poll_read(cx, &mut buf[..len]))?;
+        this.bandwidth.inbound.consume(bytes);
+        Poll::Ready(Ok(bytes))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for ThrottledSocket<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let available = ready!(this
+            .bandwidth
+            .outbound
+            .poll_available(&mut this.write_delay, cx));
+        let len = buf.len().min(available);
+        let bytes = ready!(Pin::new(&mut this.socket).poll_write(cx, &buf[..len]))?;
+        this.bandwidth.outbound.consume(bytes);
+        Poll::Ready(Ok(bytes))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().socket).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().socket).poll_close(cx)
+    }
+}
+
+/// A transport whose connections share the bandwidth of a `BandwidthManager`.
+#[derive(Clone, Debug)]
+pub struct ThrottledTransport<T> {
+    transport: T,
+    bandwidth: BandwidthManager,
+}
+
+impl<T> ThrottledTransport<T> {
+    pub fn new(transport: T, bandwidth: BandwidthManager) -> Self {
+        Self {
+            transport,
+            bandwidth,
+        }
+    }
+}
+
+impl<T> Transport for ThrottledTransport<T>
+where
+    T: Transport,
+    T::Output: Send + 'static,
+    T::Inbound: Send + 'static,
+    T::Outbound: Send + 'static,
+    T::Listener: Send + 'static,
+{
+    type Output = ThrottledSocket<T::Output>;
+    type Error = T::Error;
+    type Inbound =
+        Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send + 'static>>;
+    type Outbound =
+        Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send + 'static>>;
+    type Listener = Pin<
+        Box<
+            dyn Stream<Item = Result<(Self::Inbound, NetworkAddress), Self::Error>>
+                + Send
+                + 'static,
+        >,
+    >;
+
+    fn listen_on(
+        &self,
+        addr: NetworkAddress,
+    ) -> Result<(Self::Listener, NetworkAddress), Self::Error> {
+        let (listener, listen_addr) = self.transport.listen_on(addr)?;
+        let bandwidth = self.bandwidth.clone();
+        let listener = listener
+            .map_ok(move |(inbound, addr)| {
+                let bandwidth = bandwidth.clone();
+                let inbound: Self::Inbound = inbound
+                    .map_ok(move |socket| bandwidth.throttle(socket))
+                    .boxed();
+                (inbound, addr)
+            })
+            .boxed();
+        Ok((listener, listen_addr))
+    }
+
+    fn dial(&self, peer_id: PeerId, addr: NetworkAddress) -> Result<Self::Outbound, Self::Error> {
+        let bandwidth = self.bandwidth.clone();
+        Ok(self
+            .transport
+            .dial(peer_id, addr)?
+            .map_ok(move |socket| bandwidth.throttle(socket))
+            .boxed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use memsocket::MemorySocket;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn byte_bucket() {
+        let now = Instant::now();
+        let mut bucket = ByteBucket::new(1000, now);
+        assert_eq!(bucket.available(now), Ok(1000));
+        bucket.consume(1500);
+        // the bucket owes 500 bytes, so that the next byte is available in 501ms
+        let wait = bucket.available(now).unwrap_err();
+        assert!(wait > Duration::from_millis(500) && wait <= Duration::from_millis(501));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(bucket.available(later), Ok(500));
+        // the bucket holds at most one second of traffic
+        assert_eq!(bucket.available(later + Duration::from_secs(10)), Ok(1000));
+    }
+
+    #[test]
+    fn throttled_socket() {
+        let mut rt = Runtime::new().unwrap();
+        let (a, b) = MemorySocket::new_pair();
+        let bandwidth = BandwidthManager::new(Some(10_000), None);
+        let mut a = bandwidth.throttle(a);
+        let mut b = BandwidthManager::default().throttle(b);
+
+        let start = Instant::now();
+        let data = vec![7u8; 15_000];
+        let read = rt.block_on(async move {
+            let writer = async move {
+                a.write_all(&data).await.unwrap();
+                a.close().await.unwrap();
+            };
+            let reader = async move {
+                let mut read = Vec::new();
+                b.read_to_end(&mut read).await.unwrap();
+                read
+            };
+            futures::future::join(writer, reader).await.1
+        });
+        assert_eq!(read, vec![7u8; 15_000]);
+        // after the burst of 10_000 bytes, the other 5_000 bytes take half a second
+        assert!(start.elapsed() >= Duration::from_millis(490));
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion of the panics of the network actors into errors, so that a bug in the handling of a
+//! single event restarts the actor rather than silently terminating it, or the whole process.
+//!
+//! The actors run every iteration of their event loop with `catch_panic`, and restart from the
+//! state they can reconstruct when it returns an `ActorPanic`. Every restart is counted in
+//! `libra_network_actor_restarts`, by actor.
+
+use crate::counters;
+use futures::future::{poll_fn, Future};
+use libra_logger::prelude::*;
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    task::Poll,
+};
+use thiserror::Error;
+
+/// The panic of an actor while it was handling an event.
+#[derive(Debug, Error)]
+#[error("{actor} panicked: {message}")]
+pub struct ActorPanic {
+    pub actor: &'static str,
+    pub message: String,
+}
+
+/// Runs `future`, an iteration of the event loop of `actor`, converting its panic into an error
+/// which is logged and counted as a restart of the actor.
+pub async fn catch_panic<F: Future>(
+    actor: &'static str,
+    future: F,
+) -> Result<F::Output, ActorPanic> {
+    futures::pin_mut!(future);
+    let result = poll_fn(|cx| {
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            crash_handler::with_recoverable_panics(|| future.as_mut().poll(cx))
+        })) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    })
+    .await;
+    result.map_err(|payload| {
+        let error = ActorPanic {
+            actor,
+            message: panic_message(payload.as_ref()),
+        };
+        error!("{}, restarting it", error);
+        counters::LIBRA_NETWORK_ACTOR_RESTARTS
+            .with_label_values(&[actor])
+            .inc();
+        error
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn panics_to_errors() {
+        assert_eq!(block_on(catch_panic("test", async { 42 })).unwrap(), 42);
+
+        let error = block_on(catch_panic("test", async {
+            futures::future::ready(()).await;
+            panic!("bug {}", 42);
+        }))
+        .unwrap_err();
+        assert_eq!(error.actor, "test");
+        assert_eq!(error.message, "bug 42");
+        assert_eq!(
+            counters::LIBRA_NETWORK_ACTOR_RESTARTS
+                .with_label_values(&["test"])
+                .get(),
+            1
+        );
+    }
+}
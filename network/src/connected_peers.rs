@@ -0,0 +1,154 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A view of the peers connected to this node, across all its networks, which operators use to
+//! build maps of the network topology, e.g., through the JSON-RPC endpoint of the node.
+//!
+//! Every network sharing a [`ConnectedPeers`] handle records its peers when they connect and
+//! forgets them when they disconnect. The health checker of the network records the round-trip
+//! time of its pings and the skew of the clocks of the peers, and state sync the versions the peers
+//! reported being synced to.
+
+use libra_config::{config::RoleType, network_id::NetworkId};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// A peer connected to this node.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectedPeerInfo {
+    pub peer_id: PeerId,
+    pub network_id: NetworkId,
+    /// Role of the network the peer is connected through
+    pub role: RoleType,
+    pub address: NetworkAddress,
+    /// Round-trip time of the last successful ping of the peer
+    pub rtt: Option<Duration>,
+    /// Latest version the peer reported being synced to
+    pub synced_version: Option<u64>,
+    /// Skew of the clock of the peer relative to ours, in microseconds, measured by the last
+    /// successful ping of the peer. Positive if the clock of the peer is ahead.
+    pub clock_skew_usecs: Option<i64>,
+}
+
+/// The peers connected to this node. Cloning it returns a handle to the same view.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectedPeers {
+    inner: Arc<RwLock<HashMap<PeerId, ConnectedPeerInfo>>>,
+}
+
+impl ConnectedPeers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the connected peers, ordered by network and peer id.
+    pub fn snapshot(&self) -> Vec<ConnectedPeerInfo> {
+        let mut peers: Vec<_> = self.inner.read().unwrap().values().cloned().collect();
+        peers.sort_by(|a, b| {
+            (a.network_id.as_str(), a.peer_id).cmp(&(b.network_id.as_str(), b.peer_id))
+        });
+        peers
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<ConnectedPeerInfo> {
+        self.inner.read().unwrap().get(peer_id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records the latest version `peer_id` reported being synced to, if it is connected.
+    pub fn set_synced_version(&self, peer_id: &PeerId, version: u64) {
+        if let Some(peer) = self.inner.write().unwrap().get_mut(peer_id) {
+            peer.synced_version = Some(version);
+        }
+    }
+
+    pub(crate) fn insert(
+        &self,
+        peer_id: PeerId,
+        network_id: NetworkId,
+        role: RoleType,
+        address: NetworkAddress,
+    ) {
+        self.inner.write().unwrap().insert(
+            peer_id,
+            ConnectedPeerInfo {
+                peer_id,
+                network_id,
+                role,
+                address,
+                rtt: None,
+                synced_version: None,
+                clock_skew_usecs: None,
+            },
+        );
+    }
+
+    /// Forgets `peer_id` if it is still connected at `address`, i.e., unless the lost connection
+    /// was replaced by another one.
+    pub(crate) fn remove(&self, peer_id: &PeerId, address: &NetworkAddress) {
+        let mut peers = self.inner.write().unwrap();
+        if peers.get(peer_id).map(|peer| &peer.address) == Some(address) {
+            peers.remove(peer_id);
+        }
+    }
+
+    pub(crate) fn set_rtt(&self, peer_id: &PeerId, rtt: Duration) {
+        if let Some(peer) = self.inner.write().unwrap().get_mut(peer_id) {
+            peer.rtt = Some(rtt);
+        }
+    }
+
+    pub(crate) fn set_clock_skew(&self, peer_id: &PeerId, clock_skew_usecs: i64) {
+        if let Some(peer) = self.inner.write().unwrap().get_mut(peer_id) {
+            peer.clock_skew_usecs = Some(clock_skew_usecs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn connect_and_disconnect() {
+        let connected_peers = ConnectedPeers::new();
+        let peer_id = PeerId::random();
+        let address: NetworkAddress = "/ip4/127.0.0.1/tcp/6180".parse().unwrap();
+        connected_peers.insert(
+            peer_id,
+            NetworkId::Validator,
+            RoleType::Validator,
+            address.clone(),
+        );
+        connected_peers.set_rtt(&peer_id, Duration::from_millis(20));
+        connected_peers.set_clock_skew(&peer_id, -1_500);
+        connected_peers.set_synced_version(&peer_id, 42);
+        // versions of peers which are not connected are dropped
+        connected_peers.set_synced_version(&PeerId::random(), 7);
+
+        let peers = connected_peers.clone().snapshot();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].rtt, Some(Duration::from_millis(20)));
+        assert_eq!(peers[0].synced_version, Some(42));
+        assert_eq!(peers[0].clock_skew_usecs, Some(-1_500));
+
+        // the loss of a replaced connection is ignored
+        let other_address: NetworkAddress = "/ip4/127.0.0.1/tcp/6181".parse().unwrap();
+        connected_peers.remove(&peer_id, &other_address);
+        assert_eq!(connected_peers.len(), 1);
+        connected_peers.remove(&peer_id, &address);
+        assert!(connected_peers.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Limits of the inbound connections of a listener, enforced as soon as a connection is accepted,
+//! before the Noise handshake, so that a flood of connections cannot make a public full node spend
+//! its CPU on handshakes, nor exhaust its file descriptors.
+//!
+//! A connection counts against the limits from its acceptance until it closes, whether its
+//! handshake is still pending or it is established. The connections beyond the limits are closed
+//! right away, and counted in `libra_network_rejected_inbound_connections`, by reason.
+//!
+//! The acceptance of inbound connections can also be paused altogether through an
+//! [`InboundConnectionGate`], e.g., while the node sheds load under resource pressure. The
+//! connections already established are kept.
+
+use crate::counters;
+use libra_network_address::{NetworkAddress, Protocol};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Limits of the inbound connections of a listener, `None` meaning unlimited.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InboundConnectionLimits {
+    pub max_inbound_connections: Option<usize>,
+    pub max_inbound_connections_per_ip: Option<usize>,
+}
+
+/// Pauses the acceptance of the inbound connections of a network. Cloning it returns a handle to
+/// the same gate.
+#[derive(Clone, Debug, Default)]
+pub struct InboundConnectionGate {
+    paused: Arc<AtomicBool>,
+}
+
+impl InboundConnectionGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Closes the inbound connections right after they are accepted, until resumed.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug, Default)]
+struct InboundConnections {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Counts the inbound connections of a listener against its limits.
+#[derive(Clone, Debug)]
+pub struct InboundConnectionLimiter {
+    limits: InboundConnectionLimits,
+    gate: InboundConnectionGate,
+    connections: Arc<Mutex<InboundConnections>>,
+}
+
+impl InboundConnectionLimiter {
+    pub fn new(limits: InboundConnectionLimits, gate: InboundConnectionGate) -> Self {
+        Self {
+            limits,
+            gate,
+            connections: Arc::new(Mutex::new(InboundConnections::default())),
+        }
+    }
+
+    /// Counts a connection accepted from `addr` until the returned permit is dropped, or returns
+    /// `None` if the connection exceeds the limits or the gate is paused. The connections from
+    /// addresses without an IP, e.g., in memory, only count against the total limit.
+    pub fn try_accept(&self, addr: &NetworkAddress) -> Option<InboundConnectionPermit> {
+        if self.gate.is_paused() {
+            counters::LIBRA_NETWORK_REJECTED_INBOUND_CONNECTIONS
+                .with_label_values(&["paused"])
+                .inc();
+            return None;
+        }
+        let ip_addr = ip_addr(addr);
+        let mut connections = self.connections.lock().unwrap();
+        if self
+            .limits
+            .max_inbound_connections
+            .map_or(false, |max| connections.total >= max)
+        {
+            counters::LIBRA_NETWORK_REJECTED_INBOUND_CONNECTIONS
+                .with_label_values(&["max_inbound_connections"])
+                .inc();
+            return None;
+        }
+        if let Some(ip_addr) = ip_addr {
+            let ip_connections = connections.per_ip.get(&ip_addr).copied().unwrap_or(0);
+            if self
+                .limits
+                .max_inbound_connections_per_ip
+                .map_or(false, |max| ip_connections >= max)
+            {
+                counters::LIBRA_NETWORK_REJECTED_INBOUND_CONNECTIONS
+                    .with_label_values(&["max_inbound_connections_per_ip"])
+                    .inc();
+                return None;
+            }
+            connections.per_ip.insert(ip_addr, ip_connections + 1);
+        }
+        connections.total += 1;
+        Some(InboundConnectionPermit {
+            connections: self.connections.clone(),
+            ip_addr,
+        })
+    }
+
+    /// Number of inbound connections currently counted.
+    pub fn connections(&self) -> usize {
+        self.connections.lock().unwrap().total
+    }
+}
+
+/// The count of an inbound connection against the limits of its listener, released when dropped.
+#[derive(Debug)]
+pub struct InboundConnectionPermit {
+    connections: Arc<Mutex<InboundConnections>>,
+    ip_addr: Option<IpAddr>,
+}
+
+impl Drop for InboundConnectionPermit {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+        connections.total -= 1;
+        if let Some(ip_addr) = self.ip_addr {
+            if let Some(ip_connections) = connections.per_ip.get_mut(&ip_addr) {
+                *ip_connections -= 1;
+                if *ip_connections == 0 {
+                    connections.per_ip.remove(&ip_addr);
+                }
+            }
+        }
+    }
+}
+
+fn ip_addr(addr: &NetworkAddress) -> Option<IpAddr> {
+    match addr.as_slice().first() {
+        Some(Protocol::Ip4(addr)) => Some(IpAddr::V4(*addr)),
+        Some(Protocol::Ip6(addr)) => Some(IpAddr::V6(*addr)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let limiter = InboundConnectionLimiter::new(
+            InboundConnectionLimits {
+                max_inbound_connections: Some(3),
+                max_inbound_connections_per_ip: Some(2),
+            },
+            InboundConnectionGate::new(),
+        );
+        let addr1: NetworkAddress = "/ip4/10.0.0.1/tcp/6180".parse().unwrap();
+        let addr2: NetworkAddress = "/ip6/::1/tcp/6180".parse().unwrap();
+
+        let first = limiter.try_accept(&addr1).unwrap();
+        let second = limiter.try_accept(&addr1).unwrap();
+        assert!(limiter.try_accept(&addr1).is_none());
+        let third = limiter.try_accept(&addr2).unwrap();
+        assert!(limiter.try_accept(&addr2).is_none());
+        assert_eq!(limiter.connections(), 3);
+
+        // closing a connection makes room for its IP again
+        drop(first);
+        assert_eq!(limiter.connections(), 2);
+        let fourth = limiter.try_accept(&addr1).unwrap();
+        drop((second, third, fourth));
+        assert_eq!(limiter.connections(), 0);
+
+        // addresses without an IP only count against the total limit
+        let memory_addr: NetworkAddress = "/memory/1234".parse().unwrap();
+        let permits: Vec<_> = (0..3)
+            .map(|_| limiter.try_accept(&memory_addr).unwrap())
+            .collect();
+        assert!(limiter.try_accept(&memory_addr).is_none());
+        drop(permits);
+
+        // unlimited limiters never reject connections
+        let limiter = InboundConnectionLimiter::new(
+            InboundConnectionLimits::default(),
+            InboundConnectionGate::new(),
+        );
+        let permits: Vec<_> = (0..100)
+            .map(|_| limiter.try_accept(&addr1).unwrap())
+            .collect();
+        assert_eq!(limiter.connections(), 100);
+        drop(permits);
+    }
+
+    #[test]
+    fn gate() {
+        let gate = InboundConnectionGate::new();
+        let limiter =
+            InboundConnectionLimiter::new(InboundConnectionLimits::default(), gate.clone());
+        let addr: NetworkAddress = "/ip4/10.0.0.1/tcp/6180".parse().unwrap();
+        let permit = limiter.try_accept(&addr).unwrap();
+
+        // the established connections are kept while paused
+        gate.pause();
+        assert!(limiter.try_accept(&addr).is_none());
+        assert_eq!(limiter.connections(), 1);
+
+        gate.resume();
+        let other_permit = limiter.try_accept(&addr).unwrap();
+        drop((permit, other_permit));
+    }
+}
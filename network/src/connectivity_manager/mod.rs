@@ -9,12 +9,16 @@
 //! Consensus actor informs the ConnectivityManager of eligible nodes.
 //!
 //! Different discovery sources notify the ConnectivityManager of updates to
-//! peers' addresses. Currently, there are 3 discovery sources (ordered by
+//! peers' addresses. Currently, there are 7 discovery sources (ordered by
 //! decreasing dial priority, i.e., first is highest priority):
 //!
 //! 1. Onchain discovery protocol
 //! 2. Gossip discovery protocol
-//! 3. Seed peers from config
+//! 3. DHT discovery protocol
+//! 4. Seed peers from config
+//! 5. mDNS discovery of the peers on the local network
+//! 6. Peer exchange with the connected peers
+//! 7. Addresses at which the peers were reached on previous runs
 //!
 //! In other words, if a we have some addresses discovered via onchain discovery
 //! and some seed addresses from our local config, we will try the onchain
@@ -26,13 +30,72 @@
 //! absolutely important that we maintain connectivity with all peers and heal
 //! any partitions asap, as we aren't currently gossiping consensus messages or
 //! using a relay protocol.
+//!
+//! Once enabled with [`ConnectivityManager::with_parallel_dials`], the addresses of a peer are
+//! raced instead, Happy Eyeballs style: the next address is dialed after a small stagger, or as
+//! soon as the previous dial fails, the first successful connection is kept and the other dials
+//! are canceled. A peer advertising, e.g., an IPv6 and an IPv4 address, or addresses in several
+//! datacenters, is then reached as fast as its best address allows, rather than after the
+//! backoff of every unreachable address before it.
+//!
+//! Addresses may name a host instead of an IP address, e.g.,
+//! `/dns4/<name>/tcp/<port>`. They are dialed as they are, and the name is
+//! resolved by the transport on every dial attempt, so that a peer whose IP
+//! address changes, as in cloud deployments, is reached at its new address on
+//! the next dial, without any change to the config.
+//!
+//! The trusted and seed peers from the config can be reloaded at runtime, e.g.,
+//! by the node operator, with [`ConnectivityRequest::ReloadPeers`]. The peers
+//! which are no longer trusted are disconnected and the new ones are dialed
+//! right away, without waiting for the next connectivity check.
+//!
+//! Peers, or the IP prefixes they connect from, can be banned for a while, e.g., by
+//! consensus after detecting an equivocation, with [`ConnectivityRequest::BanPeer`] and
+//! [`ConnectivityRequest::BanIpPrefix`]. Banned peers are disconnected right away and aren't
+//! dialed until their ban expires. The [`BanList`] is shared with the peer manager, which closes
+//! the connections of banned peers.
+//!
+//! The updates of the eligible peers, i.e., of the trusted peers, are persisted to the trusted
+//! peers file of the network, if any, and their diffs are sent to the connection event listeners,
+//! see [`trusted_peers`](crate::trusted_peers).
+//!
+//! The operators of the validators may publish connectivity preferences on chain, received with
+//! [`ConnectivityRequest::UpdatePreferences`]: the eligible peers in the preferred regions of the
+//! node are dialed first, and no more peers are dialed once the node has as many connections as
+//! its preferences allow.
+//!
+//! The applications learn that a peer is unreachable, e.g., from send failures and RPC timeouts,
+//! faster than the health checker pings, and report it with a [`ReachabilityReporter`]. After
+//! [`UNREACHABLE_HINTS_THRESHOLD`] reports within [`REACHABILITY_HINTS_WINDOW`], the connection
+//! with the peer is closed and the peer is redialed as soon as it is lost, at its next address.
+//!
+//! The addresses at which the peers are successfully reached are recorded in the [`AddressBook`]
+//! of the network, if any, and loaded from it on startup, so that a node doesn't have to
+//! bootstrap from its seed peers on every restart, see [`address_book`](crate::address_book).
+//!
+//! Once enabled with [`ConnectivityRequest::EnablePeerExchange`], the peer exchange protocol is
+//! asked for more peers after every connectivity check which leaves the node short on outbound
+//! candidates, i.e., on eligible peers with known addresses which it isn't connected to, see
+//! [`pex`](crate::protocols::pex). It is served the addresses known to the ConnectivityManager
+//! with [`ConnectivityRequest::GetKnownAddresses`].
+//!
+//! The decisions of the actor, i.e., which peers to dial or disconnect, are made by its
+//! [`policy`], a synchronous core which neither awaits nor reads the clock.
+//!
+//! If the handling of an event panics, the actor cancels its queued dials, forgets
+//! their backoff, and checks its connectivity again, as on startup.
 
 use crate::{
+    address_book::{self, AddressBook, KnownAddresses},
+    ban_list::{BanList, IpPrefix},
+    catch_panic::catch_panic,
     common::NetworkPublicKeys,
+    network_events::{NetworkEvent, NetworkEvents},
     peer_manager::{self, conn_notifs_channel, ConnectionRequestSender, PeerManagerError},
+    trusted_peers::{PersistedTrustedPeers, TrustedPeersDiff},
 };
 use futures::{
-    channel::oneshot,
+    channel::{mpsc, oneshot},
     future::{BoxFuture, FutureExt},
     stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
 };
@@ -40,28 +103,34 @@ use libra_logger::prelude::*;
 use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
 use num_variants::NumVariants;
+use policy::{ConnectivityPolicy, Dial};
 use std::{
-    cmp::min,
-    collections::HashMap,
-    fmt,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 use tokio::time;
 
+mod policy;
 #[cfg(test)]
 mod test;
 
+const ACTOR: &str = "connectivity_manager";
+
+/// Number of reports of a connected peer being unreachable after which its connection is closed.
+pub const UNREACHABLE_HINTS_THRESHOLD: usize = 3;
+/// Period over which the reports of a peer being unreachable are counted.
+pub const REACHABILITY_HINTS_WINDOW: Duration = Duration::from_secs(60);
+
 /// The ConnectivityManager actor.
 pub struct ConnectivityManager<TTicker, TBackoff> {
     /// PeerId of this node.
     self_peer_id: PeerId,
     /// Nodes which are eligible to join the network.
     eligible: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
-    /// PeerId and address of remote peers to which this peer is connected.
-    connected: HashMap<PeerId, NetworkAddress>,
-    /// Addresses of peers received from discovery sources.
-    peer_addresses: PeerAddresses,
+    /// Decides which peers to dial or disconnect, and tracks the connections and queued dials.
+    policy: ConnectivityPolicy<TBackoff>,
     /// Ticker to trigger connectivity checks to provide the guarantees stated above.
     ticker: TTicker,
     /// Channel to send connection requests to PeerManager.
@@ -73,16 +142,58 @@ pub struct ConnectivityManager<TTicker, TBackoff> {
     /// Peers queued to be dialed, potentially with some delay. The dial can be canceled by
     /// sending over (or dropping) the associated oneshot sender.
     dial_queue: HashMap<PeerId, oneshot::Sender<()>>,
-    /// The state of any currently executing dials. Used to keep track of what
-    /// the next dial delay and dial address should be for a given peer.
-    dial_states: HashMap<PeerId, DialState<TBackoff>>,
-    /// Backoff strategy.
-    backoff_strategy: TBackoff,
-    /// Maximum delay b/w 2 consecutive attempts to connect with a disconnected peer.
-    max_delay_ms: u64,
     /// A local counter incremented on receiving an incoming message. Printing this in debugging
     /// allows for easy debugging.
     event_id: u32,
+    /// Peers and IP prefixes which must not be connected, shared with the peer manager.
+    ban_list: BanList,
+    /// File to which the eligible peers are persisted on every update.
+    trusted_peers_file: Option<PathBuf>,
+    /// Channel over which the diffs of the updates of the eligible peers are sent to the
+    /// connection event listeners.
+    trusted_peers_updates_tx: Option<channel::Sender<TrustedPeersDiff>>,
+    /// Number of reports of the connected peers being unreachable, since the start of the window.
+    reachability_hints: HashMap<PeerId, (usize, Instant)>,
+    /// Peers disconnected for being unreachable, redialed as soon as they are lost.
+    unreachable: HashSet<PeerId>,
+    /// Store of the addresses at which the peers were reached, if any.
+    address_book: Option<Box<dyn AddressBook>>,
+    /// Addresses at which the peers were reached, as persisted to the address book.
+    known_addresses: KnownAddresses,
+    /// Period after which an address not reached again is pruned from the address book.
+    address_staleness: Duration,
+    /// Channel over which peer exchange is asked for more peers, if enabled.
+    peer_exchange_tx: Option<mpsc::Sender<()>>,
+    /// Delay after which the next address of a peer is dialed while the previous dials are
+    /// pending, if the addresses of the peers are raced.
+    dial_stagger: Option<Duration>,
+    /// Publisher of the bans, if any.
+    events: Option<NetworkEvents>,
+}
+
+/// Preferences of the operator of this node for the peers it dials, e.g., published on chain.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectivityPreferences {
+    /// Regions of the peers dialed first, the most preferred first.
+    pub preferred_regions: Vec<String>,
+    /// Number of connections beyond which no more peers are dialed, `None` for no limit.
+    pub max_connections: Option<usize>,
+    /// Regions of the peers which published one.
+    pub peer_regions: HashMap<PeerId, String>,
+}
+
+impl ConnectivityPreferences {
+    /// Rank of `peer_id` in the dial order, lower ranks being dialed first.
+    fn rank(&self, peer_id: &PeerId) -> usize {
+        self.peer_regions
+            .get(peer_id)
+            .and_then(|region| {
+                self.preferred_regions
+                    .iter()
+                    .position(|preferred| preferred == region)
+            })
+            .unwrap_or_else(|| self.preferred_regions.len())
+    }
 }
 
 /// Different sources for peer addresses, ordered by priority (Onchain=highest,
@@ -92,7 +203,45 @@ pub struct ConnectivityManager<TTicker, TBackoff> {
 pub enum DiscoverySource {
     OnChain,
     Gossip,
+    Dht,
     Config,
+    Mdns,
+    Pex,
+    AddressBook,
+}
+
+/// Why an application reports a peer as unreachable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReachabilityHint {
+    /// A message to the peer could not be sent.
+    SendFailed,
+    /// The peer did not respond in time, e.g., to an RPC.
+    Timeout,
+}
+
+/// Reports to the [`ConnectivityManager`] the peers which the applications fail to reach.
+#[derive(Clone)]
+pub struct ReachabilityReporter(channel::Sender<ConnectivityRequest>);
+
+impl ReachabilityReporter {
+    pub fn new(conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>) -> Self {
+        Self(conn_mgr_reqs_tx)
+    }
+
+    /// Reports that `peer_id` is unreachable, without waiting: the report is dropped if the
+    /// [`ConnectivityManager`] is busy.
+    pub fn report_unreachable(&mut self, peer_id: PeerId, hint: ReachabilityHint) {
+        if let Err(err) = self
+            .0
+            .try_send(ConnectivityRequest::ReportUnreachable(peer_id, hint))
+        {
+            debug!(
+                "Dropped the report of peer {} being unreachable: {}",
+                peer_id.short_str(),
+                err
+            );
+        }
+    }
 }
 
 /// Requests received by the [`ConnectivityManager`] manager actor from upstream modules.
@@ -100,20 +249,42 @@ pub enum DiscoverySource {
 pub enum ConnectivityRequest {
     /// Request to update known addresses of peer with id `PeerId` to given list.
     UpdateAddresses(DiscoverySource, HashMap<PeerId, Vec<NetworkAddress>>),
-    /// Update set of nodes eligible to join the network.
-    UpdateEligibleNodes(HashMap<PeerId, NetworkPublicKeys>),
+    /// Update set of nodes eligible to join the network, from the validator set of the given epoch.
+    UpdateEligibleNodes(u64, HashMap<PeerId, NetworkPublicKeys>),
+    /// Replace the preferences of the operator for the peers to dial.
+    UpdatePreferences(ConnectivityPreferences),
     /// Gets current size of dial queue. This is useful in tests.
     GetDialQueueSize(oneshot::Sender<usize>),
+    /// Enter or leave maintenance mode.
+    SetMaintenanceMode(bool),
+    /// Atomically replace the set of nodes eligible to join the network, i.e., the trusted peers,
+    /// and the addresses of the seed peers from the config, then check connectivity right away.
+    ReloadPeers(
+        HashMap<PeerId, NetworkPublicKeys>,
+        HashMap<PeerId, Vec<NetworkAddress>>,
+    ),
+    /// Disconnect `peer_id` right away, then neither dial it nor accept its connections for
+    /// `duration`.
+    BanPeer { peer_id: PeerId, duration: Duration },
+    /// Disconnect the peers connected from an IP address in `prefix` right away, then neither
+    /// dial such addresses nor accept connections from them for `duration`.
+    BanIpPrefix {
+        prefix: IpPrefix,
+        duration: Duration,
+    },
+    /// An application failed to reach `PeerId`, see [`ReachabilityReporter`].
+    ReportUnreachable(PeerId, ReachabilityHint),
+    /// Gets the addresses of the known peers, which aren't banned, e.g., to share them with the
+    /// connected peers.
+    GetKnownAddresses(oneshot::Sender<HashMap<PeerId, Vec<NetworkAddress>>>),
+    /// Ask for more peers over `requests_tx` whenever fewer than `min_outbound_candidates`
+    /// eligible peers with known addresses aren't connected.
+    EnablePeerExchange {
+        min_outbound_candidates: usize,
+        requests_tx: mpsc::Sender<()>,
+    },
 }
 
-/// The set of `NetworkAddress`'s for all peers.
-struct PeerAddresses(HashMap<PeerId, Addresses>);
-
-/// A set of `NetworkAddress`'s for a single peer, bucketed by DiscoverySource in
-/// priority order.
-#[derive(Clone, Default)]
-struct Addresses([Vec<NetworkAddress>; DiscoverySource::NUM_VARIANTS]);
-
 #[derive(Debug)]
 enum DialResult {
     Success,
@@ -121,17 +292,6 @@ enum DialResult {
     Failed(PeerManagerError),
 }
 
-/// The state needed to compute the next dial delay and dial addr for a given
-/// peer.
-#[derive(Debug, Clone)]
-struct DialState<TBackoff> {
-    /// The current state of this peer's backoff delay.
-    backoff: TBackoff,
-    /// The index of the next address to dial. Index of an address in the peer's
-    /// `peer_addresses` entry.
-    addr_idx: usize,
-}
-
 impl<TTicker, TBackoff> ConnectivityManager<TTicker, TBackoff>
 where
     TTicker: Stream + FusedStream + Unpin + 'static,
@@ -148,46 +308,102 @@ where
         requests_rx: channel::Receiver<ConnectivityRequest>,
         backoff_strategy: TBackoff,
         max_delay_ms: u64,
+        ban_list: BanList,
+        trusted_peers_file: Option<PathBuf>,
+        trusted_peers_updates_tx: Option<channel::Sender<TrustedPeersDiff>>,
     ) -> Self {
-        // Ensure seed peers doesn't contain our own address (we want to avoid
-        // pointless self-dials).
-        let peer_addresses = PeerAddresses(
-            seed_peers
-                .into_iter()
-                .filter(|(peer_id, _)| peer_id != &self_peer_id)
-                .map(|(peer_id, seed_addrs)| {
-                    (
-                        peer_id,
-                        Addresses::from_addrs(DiscoverySource::Config, seed_addrs),
-                    )
-                })
-                .collect(),
-        );
-
-        info!(
-            "[{}] ConnectivityManager init: num_seed_peers: {}, peer addresses: {}",
-            self_peer_id.short_str(),
-            peer_addresses.0.len(),
-            peer_addresses,
-        );
-
         Self {
             self_peer_id,
             eligible,
-            connected: HashMap::new(),
-            peer_addresses,
+            policy: ConnectivityPolicy::new(
+                self_peer_id,
+                seed_peers,
+                backoff_strategy,
+                max_delay_ms,
+            ),
             ticker,
             connection_reqs_tx,
             connection_notifs_rx,
             requests_rx,
             dial_queue: HashMap::new(),
-            dial_states: HashMap::new(),
-            backoff_strategy,
-            max_delay_ms,
             event_id: 0,
+            ban_list,
+            trusted_peers_file,
+            trusted_peers_updates_tx,
+            reachability_hints: HashMap::new(),
+            unreachable: HashSet::new(),
+            address_book: None,
+            known_addresses: HashMap::new(),
+            address_staleness: address_book::DEFAULT_ADDRESS_STALENESS,
+            peer_exchange_tx: None,
+            dial_stagger: None,
+            events: None,
         }
     }
 
+    /// Publishes the banned peers to `events`.
+    pub fn with_events(mut self, events: NetworkEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Races the addresses of the peers, starting the dial of the next address after `stagger`,
+    /// or as soon as the previous dial fails, and keeping the first successful connection.
+    pub fn with_parallel_dials(mut self, stagger: Duration) -> Self {
+        self.dial_stagger = Some(stagger);
+        self
+    }
+
+    /// Records the addresses at which the peers are reached in `address_book`, and dials the
+    /// addresses already in it, pruning those not reached within `staleness`.
+    pub fn with_address_book(
+        mut self,
+        address_book: Box<dyn AddressBook>,
+        staleness: Duration,
+    ) -> Self {
+        match address_book.load() {
+            Ok(known_addresses) => self.known_addresses = known_addresses,
+            // The address book is only a cache of the discovery sources.
+            Err(err) => error!(
+                "[{}] Ignoring the address book: {:?}",
+                self.self_peer_id.short_str(),
+                err
+            ),
+        }
+        self.address_book = Some(address_book);
+        self.address_staleness = staleness;
+        let pruned = address_book::prune_stale(
+            &mut self.known_addresses,
+            address_book::now_secs(),
+            staleness,
+        );
+        if pruned > 0 {
+            self.store_known_addresses();
+        }
+        info!(
+            "[{}] Loaded the addresses of {} peers from the address book, pruned {} stale addresses",
+            self.self_peer_id.short_str(),
+            self.known_addresses.len(),
+            pruned
+        );
+        let address_map = self
+            .known_addresses
+            .iter()
+            .map(|(peer_id, known)| {
+                (
+                    *peer_id,
+                    known
+                        .iter()
+                        .map(|known_addr| known_addr.addr.clone())
+                        .collect(),
+                )
+            })
+            .collect();
+        self.policy
+            .update_addresses(DiscoverySource::AddressBook, address_map);
+        self
+    }
+
     /// Starts the [`ConnectivityManager`] actor.
     pub async fn start(mut self) {
         // The ConnectivityManager actor is interested in 3 kinds of events:
@@ -203,267 +419,458 @@ where
 
         trace!("Starting connection manager");
         loop {
-            self.event_id = self.event_id.wrapping_add(1);
-            ::futures::select! {
-                _ = self.ticker.select_next_some() => {
-                    trace!("Event Id: {}, type: Tick", self.event_id);
-                    self.check_connectivity(&mut pending_dials).await;
-                },
-                req = self.requests_rx.select_next_some() => {
-                    trace!("Event Id: {}, type: ConnectivityRequest, req: {:?}", self.event_id, req);
-                    self.handle_request(req);
-                },
-                notif = self.connection_notifs_rx.select_next_some() => {
-                    trace!("Event Id: {}, type: peer_manager::ConnectionNotification, notif: {:?}", self.event_id, notif);
-                    self.handle_control_notification(notif);
-                },
-                peer_id = pending_dials.select_next_some() => {
-                    trace!("Event Id: {}, type: Dial complete, peer: {}", self.event_id, peer_id.short_str());
-                    self.dial_queue.remove(&peer_id);
-                },
-                complete => {
+            match catch_panic(ACTOR, self.handle_next_event(&mut pending_dials)).await {
+                Ok(true) => {}
+                Ok(false) => {
                     crit!("Connectivity manager actor terminated");
                     break;
                 }
+                Err(_) => self.restart(&mut pending_dials).await,
             }
         }
     }
 
-    /// Disconnect from all peers that are no longer eligible.
-    ///
-    /// For instance, a validator might leave the validator set after a
-    /// reconfiguration. If we are currently connected to this validator, calling
-    /// this function will close our connection to it.
-    async fn close_stale_connections(&mut self) {
-        let eligible = self.eligible.read().unwrap().clone();
-        let stale_connections: Vec<_> = self
-            .connected
-            .keys()
-            .filter(|peer_id| !eligible.contains_key(peer_id))
-            .cloned()
-            .collect();
-        for p in stale_connections.into_iter() {
-            info!("Should no longer be connected to peer: {}", p.short_str());
-            // Close existing connection.
-            if let Err(e) = self.connection_reqs_tx.disconnect_peer(p).await {
-                info!(
-                    "Failed to disconnect from peer: {}. Error: {:?}",
-                    p.short_str(),
-                    e
+    /// Waits for the next event and handles it. Returns false once all the event streams ended.
+    async fn handle_next_event(
+        &mut self,
+        pending_dials: &mut FuturesUnordered<BoxFuture<'static, PeerId>>,
+    ) -> bool {
+        self.event_id = self.event_id.wrapping_add(1);
+        ::futures::select! {
+            _ = self.ticker.select_next_some() => {
+                trace!("Event Id: {}, type: Tick", self.event_id);
+                self.check_connectivity(pending_dials).await;
+            },
+            req = self.requests_rx.select_next_some() => {
+                trace!("Event Id: {}, type: ConnectivityRequest, req: {:?}", self.event_id, req);
+                let check_connectivity = matches!(
+                    req,
+                    ConnectivityRequest::ReloadPeers(..)
+                        | ConnectivityRequest::BanPeer { .. }
+                        | ConnectivityRequest::BanIpPrefix { .. }
                 );
-            }
+                self.handle_request(req).await;
+                if check_connectivity {
+                    self.check_connectivity(pending_dials).await;
+                }
+            },
+            notif = self.connection_notifs_rx.select_next_some() => {
+                trace!("Event Id: {}, type: peer_manager::ConnectionNotification, notif: {:?}", self.event_id, notif);
+                let redial = match &notif {
+                    peer_manager::ConnectionNotification::LostPeer(peer_id, _, _) => {
+                        self.unreachable.remove(peer_id)
+                    }
+                    _ => false,
+                };
+                self.handle_control_notification(notif);
+                if redial {
+                    self.check_connectivity(pending_dials).await;
+                }
+            },
+            peer_id = pending_dials.select_next_some() => {
+                trace!("Event Id: {}, type: Dial complete, peer: {}", self.event_id, peer_id.short_str());
+                self.dial_queue.remove(&peer_id);
+                self.policy.dial_complete(&peer_id);
+            },
+            complete => return false,
         }
+        true
     }
 
-    /// Cancel all pending dials to peers that are no longer eligible.
-    ///
-    /// For instance, a validator might leave the validator set after a
-    /// reconfiguration. If there is a pending dial to this validator, calling
-    /// this function will remove it from the dial queue.
-    async fn cancel_stale_dials(&mut self) {
-        let eligible = self.eligible.read().unwrap().clone();
-        let stale_dials: Vec<_> = self
-            .dial_queue
-            .keys()
-            .filter(|peer_id| !eligible.contains_key(peer_id))
-            .cloned()
-            .collect();
-        for p in stale_dials.into_iter() {
-            self.dial_queue.remove(&p);
-        }
+    /// Restarts the actor after a panic: the eligible peers, their addresses and the connected
+    /// peers are kept, while the dials which may have been left half-queued are dropped.
+    async fn restart(&mut self, pending_dials: &mut FuturesUnordered<BoxFuture<'static, PeerId>>) {
+        // Dropping the senders of the dial queue cancels the pending dials.
+        self.dial_queue.clear();
+        self.policy.forget_dials();
+        self.check_connectivity(pending_dials).await;
     }
 
-    async fn dial_eligible_peers<'a>(
-        &'a mut self,
-        pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, PeerId>>,
+    /// Queues `dial`, to be carried out after its delay unless canceled.
+    fn queue_dial(
+        &mut self,
+        dial: Dial,
+        pending_dials: &mut FuturesUnordered<BoxFuture<'static, PeerId>>,
     ) {
-        let eligible = self.eligible.read().unwrap().clone();
-        let to_connect: Vec<_> = self
-            .peer_addresses
-            .0
-            .iter()
-            .filter(|(peer_id, addrs)| {
-                eligible.contains_key(peer_id)  // The node is eligible to be dialed.
-                    && self.connected.get(peer_id).is_none() // The node is not already connected.
-                    && self.dial_queue.get(peer_id).is_none() // There is no pending dial to this node.
-                    && !addrs.is_empty() // There is an address to dial.
-            })
-            .collect();
-
-        // We tune max delay depending on the number of peers to which we're not connected. This
-        // ensures that if we're disconnected from a large fraction of peers, we keep the retry
-        // window smaller.
-        let max_delay = Duration::from_millis(
-            (self.max_delay_ms as f64
-                * (1.0
-                    - ((self.dial_queue.len() + to_connect.len()) as f64
-                        / eligible
-                            .iter()
-                            .filter(|(peer_id, _)| self.peer_addresses.0.contains_key(&peer_id))
-                            .count() as f64))) as u64,
-        );
-
-        // The initial dial state; it has zero dial delay and uses the first
-        // address.
-        let init_dial_state = DialState::new(self.backoff_strategy.clone());
-
-        for (p, addrs) in to_connect.into_iter() {
-            let mut connction_reqs_tx = self.connection_reqs_tx.clone();
-            let peer_id = *p;
-            let dial_state = self
-                .dial_states
-                .entry(peer_id)
-                .or_insert_with(|| init_dial_state.clone());
-
-            // Choose the next addr to dial for this peer. Currently, we just
-            // round-robin the selection, i.e., try the sequence:
-            // addr[0], .., addr[len-1], addr[0], ..
-            let addr = dial_state.next_addr(&addrs).clone();
+        let Dial {
+            peer_id,
+            addr,
+            delay: dial_delay,
+            fallback_addrs,
+        } = dial;
+        let connction_reqs_tx = self.connection_reqs_tx.clone();
+        let mut addrs = vec![addr.clone()];
+        let stagger = self.dial_stagger.unwrap_or_default();
+        if self.dial_stagger.is_some() {
+            addrs.extend(fallback_addrs);
+        }
+        let now = Instant::now();
+        let f_delay = time::delay_for(dial_delay);
 
-            // Using the DialState's backoff strategy, compute the delay until
-            // the next dial attempt for this peer.
-            let now = Instant::now();
-            let dial_delay = dial_state.next_backoff_delay(max_delay);
-            let f_delay = time::delay_for(dial_delay);
+        let (cancel_tx, cancel_rx) = oneshot::channel();
 
-            let (cancel_tx, cancel_rx) = oneshot::channel();
+        info!(
+            "Create dial future: peer: {}, at address: {}, after delay: {:?}",
+            peer_id.short_str(),
+            addr,
+            dial_delay,
+        );
 
+        // Create future which completes by either dialing after calculated
+        // delay or on cancellation.
+        let f = async move {
             info!(
-                "Create dial future: peer: {}, at address: {}, after delay: {:?}",
+                "Dial future: dialing peer: {}, at address: {}, after delay: {:?}",
                 peer_id.short_str(),
                 addr,
-                dial_delay,
+                f_delay
+                    .deadline()
+                    .duration_since(tokio::time::Instant::from_std(now))
             );
-
-            // Create future which completes by either dialing after calculated
-            // delay or on cancellation.
-            let f = async move {
-                info!(
-                    "Dial future: dialing peer: {}, at address: {}, after delay: {:?}",
-                    peer_id.short_str(),
-                    addr,
-                    f_delay
-                        .deadline()
-                        .duration_since(tokio::time::Instant::from_std(now))
-                );
-                // We dial after a delay. The dial can be canceled by sending to or dropping
-                // `cancel_rx`.
-                let dial_result = ::futures::select! {
-                    _ = f_delay.fuse() => {
-                        info!("Dialing peer: {}, at addr: {}", peer_id.short_str(), addr);
-                        match connction_reqs_tx.dial_peer(peer_id, addr.clone()).await {
-                            Ok(_) => DialResult::Success,
-                            Err(e) => DialResult::Failed(e),
-                        }
-                    },
-                    _ = cancel_rx.fuse() => {
-                        DialResult::Cancelled
-                    },
-                };
-                log_dial_result(peer_id, addr, dial_result);
-                // Send peer_id as future result so it can be removed from dial queue.
-                peer_id
+            // We dial after a delay. The dial can be canceled by sending to or dropping
+            // `cancel_rx`.
+            let (addr, dial_result) = ::futures::select! {
+                _ = f_delay.fuse() => {
+                    race_dials(connction_reqs_tx, peer_id, addrs, stagger).await
+                },
+                _ = cancel_rx.fuse() => {
+                    (addr, DialResult::Cancelled)
+                },
             };
-            pending_dials.push(f.boxed());
-            self.dial_queue.insert(peer_id, cancel_tx);
-        }
+            log_dial_result(peer_id, addr, dial_result);
+            // Send peer_id as future result so it can be removed from dial queue.
+            peer_id
+        };
+        pending_dials.push(f.boxed());
+        self.dial_queue.insert(peer_id, cancel_tx);
     }
 
-    // Note: We do not check that the connections to older incarnations of a node are broken, and
-    // instead rely on the node moving to a new epoch to break connections made from older
-    // incarnations.
+    /// Carries out the decisions of the policy: cancels the dials to and disconnects from the
+    /// peers which are no longer eligible, or are banned, then dials the peers which are eligible
+    /// but are neither connected nor queued for dialing in the future.
     async fn check_connectivity<'a>(
         &'a mut self,
         pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, PeerId>>,
     ) {
-        // Cancel dials to peers that are no longer eligible.
-        self.cancel_stale_dials().await;
-        // Disconnect from connected peers that are no longer eligible.
-        self.close_stale_connections().await;
-        // Dial peers which are eligible but are neither connected nor queued for dialing in the
-        // future.
-        self.dial_eligible_peers(pending_dials).await;
+        self.ban_list.prune();
+        let eligible = self.eligible.read().unwrap().clone();
+        let actions = self.policy.check(&eligible, &self.ban_list);
+        for p in actions.cancel {
+            // Dropping the oneshot sender cancels the queued dial.
+            self.dial_queue.remove(&p);
+        }
+        for p in actions.disconnect {
+            info!("Should no longer be connected to peer: {}", p.short_str());
+            // Close existing connection.
+            if let Err(e) = self.connection_reqs_tx.disconnect_peer(p).await {
+                info!(
+                    "Failed to disconnect from peer: {}. Error: {:?}",
+                    p.short_str(),
+                    e
+                );
+            }
+        }
+        for dial in actions.dial {
+            self.queue_dial(dial, pending_dials);
+        }
+        if actions.request_peers {
+            self.request_peer_exchange(&eligible);
+        }
     }
 
-    fn handle_request(&mut self, req: ConnectivityRequest) {
+    /// Asks peer exchange for more peers. The request is dropped if one is already pending.
+    fn request_peer_exchange(&mut self, eligible: &HashMap<PeerId, NetworkPublicKeys>) {
+        if let Some(requests_tx) = self.peer_exchange_tx.as_mut() {
+            debug!(
+                "[{}] Requesting more peers: {} outbound candidates",
+                self.self_peer_id.short_str(),
+                self.policy
+                    .num_outbound_candidates(eligible, &self.ban_list)
+            );
+            let _ = requests_tx.try_send(());
+        }
+    }
+
+    async fn handle_request(&mut self, req: ConnectivityRequest) {
         match req {
             ConnectivityRequest::UpdateAddresses(src, address_map) => {
-                // Keep track of if any peer's addresses have actually changed, so
-                // we can log without too much spam.
-                let mut have_any_changed = false;
-                let self_peer_id = self.self_peer_id.short_str();
-
-                for (peer_id, addrs) in address_map {
-                    // Do not include self_peer_id in the address list for dialing
-                    // to avoid pointless self-dials.
-                    if peer_id == self.self_peer_id {
-                        continue;
-                    }
-
-                    // Update peer's addresses
-                    let curr_addrs = self.peer_addresses.0.entry(peer_id).or_default();
-                    if curr_addrs.update(src, addrs) {
-                        // At least one peer's addresses have actually changed.
-                        have_any_changed = true;
-
-                        // Ensure that the next dial attempt starts from the first
-                        // address if the addresses have actually changed.
-                        if let Some(dial_state) = self.dial_states.get_mut(&peer_id) {
-                            dial_state.reset_addr();
-                        }
-
-                        // Log the change to this peer's addresses.
-                        let peer_id = peer_id.short_str();
-                        let addrs = curr_addrs;
-                        info!(
-                            "[{}] addresses updated for peer: {}, update src: {:?}, addrs: {}",
-                            self_peer_id, peer_id, src, addrs,
-                        );
-                    }
+                self.policy.update_addresses(src, address_map);
+            }
+            ConnectivityRequest::UpdateEligibleNodes(epoch, nodes) => {
+                trace!("Received updated list of eligible nodes of epoch {}", epoch);
+                self.update_eligible(Some(epoch), nodes);
+            }
+            ConnectivityRequest::UpdatePreferences(preferences) => {
+                info!(
+                    "[{}] Updating connectivity preferences: preferred regions: {:?}, max \
+                     connections: {:?}",
+                    self.self_peer_id.short_str(),
+                    preferences.preferred_regions,
+                    preferences.max_connections,
+                );
+                self.policy.set_preferences(preferences);
+            }
+            ConnectivityRequest::GetDialQueueSize(sender) => {
+                sender.send(self.policy.num_queued()).unwrap();
+            }
+            ConnectivityRequest::SetMaintenanceMode(maintenance_mode) => {
+                info!(
+                    "[{}] {} maintenance mode",
+                    self.self_peer_id.short_str(),
+                    if maintenance_mode {
+                        "Entering"
+                    } else {
+                        "Leaving"
+                    },
+                );
+                self.policy.set_maintenance_mode(maintenance_mode);
+                if maintenance_mode {
+                    // Dropping the oneshot senders cancels the queued dials.
+                    self.dial_queue.clear();
                 }
-
-                // Only log the total state if anything has actually changed.
-                if have_any_changed {
-                    let peer_addresses = &self.peer_addresses;
-                    info!(
-                        "[{}] current addresses: update src: {:?}, all peer addresses: {}",
-                        self_peer_id, src, peer_addresses,
-                    );
+            }
+            ConnectivityRequest::ReloadPeers(trusted_peers, seed_peers) => {
+                info!(
+                    "[{}] Reloading peers: num_trusted_peers: {}, num_seed_peers: {}",
+                    self.self_peer_id.short_str(),
+                    trusted_peers.len(),
+                    seed_peers.len(),
+                );
+                self.update_eligible(None, trusted_peers);
+                // Forget the seed addresses of the peers which are no longer seed peers.
+                let mut address_map: HashMap<_, _> = self
+                    .policy
+                    .known_peers()
+                    .into_iter()
+                    .map(|peer_id| (peer_id, Vec::new()))
+                    .collect();
+                address_map.extend(seed_peers);
+                self.policy
+                    .update_addresses(DiscoverySource::Config, address_map);
+            }
+            ConnectivityRequest::BanPeer { peer_id, duration } => {
+                info!(
+                    "[{}] Banning peer: {} for {:?}",
+                    self.self_peer_id.short_str(),
+                    peer_id.short_str(),
+                    duration,
+                );
+                self.ban_list.ban_peer(peer_id, duration);
+                if let Some(events) = &self.events {
+                    events.publish(NetworkEvent::PeerBanned { peer_id, duration });
                 }
             }
-            ConnectivityRequest::UpdateEligibleNodes(nodes) => {
-                trace!("Received updated list of eligible nodes",);
-                *self.eligible.write().unwrap() = nodes;
+            ConnectivityRequest::BanIpPrefix { prefix, duration } => {
+                info!(
+                    "[{}] Banning IP prefix: {} for {:?}",
+                    self.self_peer_id.short_str(),
+                    prefix,
+                    duration,
+                );
+                self.ban_list.ban_ip_prefix(prefix, duration);
             }
-            ConnectivityRequest::GetDialQueueSize(sender) => {
-                sender.send(self.dial_queue.len()).unwrap();
+            ConnectivityRequest::ReportUnreachable(peer_id, hint) => {
+                self.handle_reachability_hint(peer_id, hint).await;
+            }
+            ConnectivityRequest::GetKnownAddresses(sender) => {
+                let _ = sender.send(self.policy.known_addresses(&self.ban_list));
+            }
+            ConnectivityRequest::EnablePeerExchange {
+                min_outbound_candidates,
+                requests_tx,
+            } => {
+                info!(
+                    "[{}] Enabling peer exchange: min outbound candidates: {}",
+                    self.self_peer_id.short_str(),
+                    min_outbound_candidates,
+                );
+                self.peer_exchange_tx = Some(requests_tx);
+                self.policy.enable_peer_exchange(min_outbound_candidates);
+            }
+        }
+    }
+
+    /// Closes the connection with `peer_id` once it was reported unreachable often enough, to
+    /// redial it at its next address as soon as the connection is lost.
+    async fn handle_reachability_hint(&mut self, peer_id: PeerId, hint: ReachabilityHint) {
+        let addr = match self.policy.connected_addr(&peer_id) {
+            Some(addr) if !self.unreachable.contains(&peer_id) => addr.clone(),
+            // The peer is already being redialed, or disconnected.
+            _ => return,
+        };
+        let now = Instant::now();
+        let (num_hints, window_start) = self.reachability_hints.entry(peer_id).or_insert((0, now));
+        if now.duration_since(*window_start) > REACHABILITY_HINTS_WINDOW {
+            *num_hints = 0;
+            *window_start = now;
+        }
+        *num_hints += 1;
+        if *num_hints < UNREACHABLE_HINTS_THRESHOLD {
+            return;
+        }
+        self.reachability_hints.remove(&peer_id);
+
+        info!(
+            "[{}] Peer {} at {} is unreachable ({:?}), reconnecting",
+            self.self_peer_id.short_str(),
+            peer_id.short_str(),
+            addr,
+            hint,
+        );
+        // Redial the peer at the address following the unreachable one.
+        self.policy.redial_after(peer_id, &addr);
+        match self.connection_reqs_tx.disconnect_peer(peer_id).await {
+            Ok(()) => {
+                self.unreachable.insert(peer_id);
+            }
+            Err(err) => info!(
+                "Failed to disconnect from peer: {}. Error: {:?}",
+                peer_id.short_str(),
+                err
+            ),
+        }
+    }
+
+    /// Replaces the eligible peers, persisting them and notifying the connection event listeners
+    /// of their changes.
+    fn update_eligible(
+        &mut self,
+        epoch: Option<u64>,
+        eligible: HashMap<PeerId, NetworkPublicKeys>,
+    ) {
+        let diff = TrustedPeersDiff::new(epoch, &self.eligible.read().unwrap(), &eligible);
+        if let Some(path) = &self.trusted_peers_file {
+            let trusted_peers = PersistedTrustedPeers {
+                epoch,
+                peers: eligible.clone(),
+            };
+            if let Err(err) = trusted_peers.store(path) {
+                error!(
+                    "[{}] Failed to persist the trusted peers: {:?}",
+                    self.self_peer_id.short_str(),
+                    err
+                );
+            }
+        }
+        *self.eligible.write().unwrap() = eligible;
+        if diff.is_empty() {
+            return;
+        }
+        info!(
+            "[{}] Trusted peers updated: epoch: {:?}, added: {}, removed: {}, key changed: {}",
+            self.self_peer_id.short_str(),
+            epoch,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.key_changed.len(),
+        );
+        if let Some(trusted_peers_updates_tx) = &mut self.trusted_peers_updates_tx {
+            if let Err(err) = trusted_peers_updates_tx.try_send(diff) {
+                warn!(
+                    "[{}] Failed to notify the trusted peers update: {}",
+                    self.self_peer_id.short_str(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Records that `peer_id` was reached at `addr` in the address book, if `addr` is one of its
+    /// known addresses, i.e., if it was dialed rather than it connected to us.
+    fn record_known_address(&mut self, peer_id: PeerId, addr: &NetworkAddress) {
+        if self.address_book.is_none() {
+            return;
+        }
+        if !self.policy.is_known_address(&peer_id, addr) {
+            return;
+        }
+        let now_secs = address_book::now_secs();
+        address_book::record(&mut self.known_addresses, peer_id, addr.clone(), now_secs);
+        address_book::prune_stale(&mut self.known_addresses, now_secs, self.address_staleness);
+        self.store_known_addresses();
+    }
+
+    fn store_known_addresses(&self) {
+        if let Some(address_book) = &self.address_book {
+            if let Err(err) = address_book.store(&self.known_addresses) {
+                error!(
+                    "[{}] Failed to persist the address book: {:?}",
+                    self.self_peer_id.short_str(),
+                    err
+                );
             }
         }
     }
 
     fn handle_control_notification(&mut self, notif: peer_manager::ConnectionNotification) {
         match notif {
-            peer_manager::ConnectionNotification::NewPeer(peer_id, addr) => {
-                self.connected.insert(peer_id, addr);
+            peer_manager::ConnectionNotification::NewPeer(peer_id, addr, _) => {
+                self.record_known_address(peer_id, &addr);
+                self.policy.peer_connected(peer_id, addr);
                 // Cancel possible queued dial to this peer.
-                self.dial_states.remove(&peer_id);
                 self.dial_queue.remove(&peer_id);
             }
             peer_manager::ConnectionNotification::LostPeer(peer_id, addr, _reason) => {
-                match self.connected.get(&peer_id) {
-                    Some(curr_addr) if *curr_addr == addr => {
-                        // Remove node from connected peers list.
-                        self.connected.remove(&peer_id);
-                    }
-                    _ => {
-                        debug!(
-                            "Ignoring stale lost peer event for peer: {}, addr: {}",
-                            peer_id.short_str(),
-                            addr
-                        );
-                    }
+                if self.policy.peer_lost(&peer_id, &addr) {
+                    self.reachability_hints.remove(&peer_id);
+                } else {
+                    debug!(
+                        "Ignoring stale lost peer event for peer: {}, addr: {}",
+                        peer_id.short_str(),
+                        addr
+                    );
                 }
             }
+            // Sent by this actor.
+            peer_manager::ConnectionNotification::TrustedPeersUpdated(_) => {}
+        }
+    }
+}
+
+/// Dials `peer_id` at each of `addrs` in turn, starting the next dial after `stagger`, or as soon as
+/// the previous one fails, until one succeeds or finds the peer already connected. The pending
+/// dials are then canceled, and their connections closed by the peer manager. Returns the address
+/// and result of the dial which ended the race, or of the last failed one.
+async fn race_dials(
+    connection_reqs_tx: ConnectionRequestSender,
+    peer_id: PeerId,
+    addrs: Vec<NetworkAddress>,
+    stagger: Duration,
+) -> (NetworkAddress, DialResult) {
+    let mut addrs = addrs.into_iter();
+    let mut dials = FuturesUnordered::new();
+    loop {
+        if let Some(addr) = addrs.next() {
+            let mut connection_reqs_tx = connection_reqs_tx.clone();
+            dials.push(async move {
+                info!("Dialing peer: {}, at addr: {}", peer_id.short_str(), addr);
+                let dial_result = match connection_reqs_tx.dial_peer(peer_id, addr.clone()).await {
+                    Ok(_) => DialResult::Success,
+                    Err(e) => DialResult::Failed(e),
+                };
+                (addr, dial_result)
+            });
+        }
+
+        // Wait for a dial to complete, or for the stagger to elapse if an address is left.
+        let completed = if addrs.as_slice().is_empty() {
+            dials.next().await
+        } else {
+            ::futures::select! {
+                completed = dials.select_next_some() => Some(completed),
+                _ = time::delay_for(stagger).fuse() => None,
+            }
+        };
+        if let Some((addr, dial_result)) = completed {
+            match dial_result {
+                DialResult::Success | DialResult::Failed(PeerManagerError::AlreadyConnected(_)) => {
+                    return (addr, dial_result);
+                }
+                _ if dials.is_empty() && addrs.as_slice().is_empty() => {
+                    return (addr, dial_result);
+                }
+                _ => log_dial_result(peer_id, addr, dial_result),
+            }
         }
     }
 }
@@ -499,102 +906,3 @@ fn log_dial_result(peer_id: PeerId, addr: NetworkAddress, dial_result: DialResul
         },
     }
 }
-
-impl fmt::Display for PeerAddresses {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Write the normal HashMap-style debug format, but shorten the peer_id's
-        // so the output isn't as noisy.
-        f.debug_map()
-            .entries(
-                self.0
-                    .iter()
-                    .map(|(peer_id, addrs)| (peer_id.short_str(), addrs)),
-            )
-            .finish()
-    }
-}
-
-impl fmt::Debug for PeerAddresses {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)
-    }
-}
-
-impl Addresses {
-    fn new() -> Self {
-        Default::default()
-    }
-
-    fn from_addrs(src: DiscoverySource, src_addrs: Vec<NetworkAddress>) -> Self {
-        let mut addrs = Self::new();
-        addrs.update(src, src_addrs);
-        addrs
-    }
-
-    fn len(&self) -> usize {
-        self.0.iter().map(Vec::len).sum()
-    }
-
-    fn is_empty(&self) -> bool {
-        self.len() == 0
-    }
-
-    /// Update the addresses for the `DiscoverySource` bucket. Return `true` if
-    /// the addresses have actually changed.
-    fn update(&mut self, src: DiscoverySource, addrs: Vec<NetworkAddress>) -> bool {
-        let src_idx = src as u8 as usize;
-        if self.0[src_idx] != addrs {
-            self.0[src_idx] = addrs;
-            true
-        } else {
-            false
-        }
-    }
-
-    fn get(&self, idx: usize) -> Option<&NetworkAddress> {
-        self.0.iter().flatten().nth(idx)
-    }
-}
-
-impl fmt::Display for Addresses {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Write without the typical "Addresses(..)" around the output to reduce
-        // debug noise.
-        write!(f, "{:?}", self.0)
-    }
-}
-
-impl fmt::Debug for Addresses {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)
-    }
-}
-
-impl<TBackoff> DialState<TBackoff>
-where
-    TBackoff: Iterator<Item = Duration> + Clone,
-{
-    fn new(backoff: TBackoff) -> Self {
-        Self {
-            backoff,
-            addr_idx: 0,
-        }
-    }
-
-    fn reset_addr(&mut self) {
-        self.addr_idx = 0;
-    }
-
-    fn next_addr<'a>(&mut self, addrs: &'a Addresses) -> &'a NetworkAddress {
-        assert!(!addrs.is_empty());
-
-        let addr_idx = self.addr_idx;
-        self.addr_idx = self.addr_idx.wrapping_add(1);
-
-        addrs.get(addr_idx % addrs.len()).unwrap()
-    }
-
-    fn next_backoff_delay(&mut self, max_delay: Duration) -> Duration {
-        min(max_delay, self.backoff.next().unwrap_or(max_delay))
-    }
-}
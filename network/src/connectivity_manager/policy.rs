@@ -0,0 +1,887 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The synchronous core of the [`ConnectivityManager`](super::ConnectivityManager).
+//!
+//! The [`ConnectivityPolicy`] decides which peers to dial, which queued dials to cancel, and which
+//! peers to disconnect, from the eligible peers, their known addresses, the connections, the
+//! queued dials and their backoff. It neither awaits nor reads the clock: the bans are checked as
+//! of the check, and the actor carries out the returned [`Actions`] and reports back the
+//! connections and the completed dials. Policy changes, e.g., to the dial priorities or budgets,
+//! go here, where they can be tested over every combination of peer states.
+
+use super::{ConnectivityPreferences, DiscoverySource};
+use crate::{ban_list::BanList, common::NetworkPublicKeys};
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use num_variants::NumVariants;
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    fmt,
+    time::Duration,
+};
+
+/// The bans consulted by the policy, as of the check.
+pub(crate) trait BanCheck {
+    fn is_peer_banned(&self, peer_id: &PeerId) -> bool;
+
+    fn is_address_banned(&self, addr: &NetworkAddress) -> bool;
+
+    fn is_banned(&self, peer_id: &PeerId, addr: &NetworkAddress) -> bool {
+        self.is_peer_banned(peer_id) || self.is_address_banned(addr)
+    }
+}
+
+impl BanCheck for BanList {
+    fn is_peer_banned(&self, peer_id: &PeerId) -> bool {
+        BanList::is_peer_banned(self, peer_id)
+    }
+
+    fn is_address_banned(&self, addr: &NetworkAddress) -> bool {
+        BanList::is_address_banned(self, addr)
+    }
+}
+
+/// A dial to queue: `peer_id` is dialed at `addr` after `delay`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Dial {
+    pub peer_id: PeerId,
+    pub addr: NetworkAddress,
+    pub delay: Duration,
+    /// The other addresses of the peer, which aren't banned, in the order in which they follow
+    /// `addr`, to race against it.
+    pub fallback_addrs: Vec<NetworkAddress>,
+}
+
+/// What the actor has to do after a connectivity check.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(crate) struct Actions {
+    /// Queued dials to cancel, of the peers which are no longer eligible, or are banned.
+    pub cancel: Vec<PeerId>,
+    /// Connected peers to disconnect, which are no longer eligible, or are banned.
+    pub disconnect: Vec<PeerId>,
+    /// Dials to queue, in dial order.
+    pub dial: Vec<Dial>,
+    /// Whether to ask peer exchange for more peers.
+    pub request_peers: bool,
+}
+
+/// The set of `NetworkAddress`'s for all peers.
+struct PeerAddresses(HashMap<PeerId, Addresses>);
+
+/// A set of `NetworkAddress`'s for a single peer, bucketed by DiscoverySource in
+/// priority order.
+#[derive(Clone, Default)]
+struct Addresses([Vec<NetworkAddress>; DiscoverySource::NUM_VARIANTS]);
+
+/// The state needed to compute the next dial delay and dial addr for a given
+/// peer.
+#[derive(Debug, Clone)]
+struct DialState<TBackoff> {
+    /// The current state of this peer's backoff delay.
+    backoff: TBackoff,
+    /// The index of the next address to dial. Index of an address in the peer's
+    /// `peer_addresses` entry.
+    addr_idx: usize,
+}
+
+/// The decisions of the [`ConnectivityManager`](super::ConnectivityManager), and the state they
+/// are made from.
+pub(crate) struct ConnectivityPolicy<TBackoff> {
+    /// PeerId of this node.
+    self_peer_id: PeerId,
+    /// PeerId and address of remote peers to which this peer is connected.
+    connected: HashMap<PeerId, NetworkAddress>,
+    /// Addresses of peers received from discovery sources.
+    peer_addresses: PeerAddresses,
+    /// Peers queued to be dialed, until their dial completes or is canceled.
+    queued: HashSet<PeerId>,
+    /// The state of any currently executing dials. Used to keep track of what
+    /// the next dial delay and dial address should be for a given peer.
+    dial_states: HashMap<PeerId, DialState<TBackoff>>,
+    /// Backoff strategy.
+    backoff_strategy: TBackoff,
+    /// Maximum delay b/w 2 consecutive attempts to connect with a disconnected peer.
+    max_delay_ms: u64,
+    /// While in maintenance mode, no new dials are made. Existing connections are left untouched.
+    maintenance_mode: bool,
+    /// Preferences of the operator of this node for the peers it dials.
+    preferences: ConnectivityPreferences,
+    /// Number of outbound candidates below which peer exchange is asked for more peers, if
+    /// enabled.
+    min_outbound_candidates: Option<usize>,
+}
+
+impl<TBackoff> ConnectivityPolicy<TBackoff>
+where
+    TBackoff: Iterator<Item = Duration> + Clone,
+{
+    pub fn new(
+        self_peer_id: PeerId,
+        seed_peers: HashMap<PeerId, Vec<NetworkAddress>>,
+        backoff_strategy: TBackoff,
+        max_delay_ms: u64,
+    ) -> Self {
+        // Ensure seed peers doesn't contain our own address (we want to avoid
+        // pointless self-dials).
+        let peer_addresses = PeerAddresses(
+            seed_peers
+                .into_iter()
+                .filter(|(peer_id, _)| peer_id != &self_peer_id)
+                .map(|(peer_id, seed_addrs)| {
+                    (
+                        peer_id,
+                        Addresses::from_addrs(DiscoverySource::Config, seed_addrs),
+                    )
+                })
+                .collect(),
+        );
+
+        info!(
+            "[{}] ConnectivityManager init: num_seed_peers: {}, peer addresses: {}",
+            self_peer_id.short_str(),
+            peer_addresses.0.len(),
+            peer_addresses,
+        );
+
+        Self {
+            self_peer_id,
+            connected: HashMap::new(),
+            peer_addresses,
+            queued: HashSet::new(),
+            dial_states: HashMap::new(),
+            backoff_strategy,
+            max_delay_ms,
+            maintenance_mode: false,
+            preferences: ConnectivityPreferences::default(),
+            min_outbound_candidates: None,
+        }
+    }
+
+    /// Decides which queued dials to cancel and which peers to disconnect, because they are no
+    /// longer `eligible` or are banned, then, unless in maintenance mode, which peers to dial and
+    /// whether to ask for more peers. The dials returned are recorded as queued.
+    //
+    // Note: We do not check that the connections to older incarnations of a node are broken, and
+    // instead rely on the node moving to a new epoch to break connections made from older
+    // incarnations.
+    pub fn check(
+        &mut self,
+        eligible: &HashMap<PeerId, NetworkPublicKeys>,
+        bans: &impl BanCheck,
+    ) -> Actions {
+        let mut cancel: Vec<_> = self
+            .queued
+            .iter()
+            .filter(|peer_id| !eligible.contains_key(peer_id) || bans.is_peer_banned(peer_id))
+            .cloned()
+            .collect();
+        cancel.sort();
+        for peer_id in &cancel {
+            self.queued.remove(peer_id);
+        }
+
+        let mut disconnect: Vec<_> = self
+            .connected
+            .iter()
+            .filter(|(peer_id, addr)| {
+                !eligible.contains_key(peer_id) || bans.is_banned(peer_id, addr)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        disconnect.sort();
+
+        if self.maintenance_mode {
+            return Actions {
+                cancel,
+                disconnect,
+                ..Actions::default()
+            };
+        }
+        let dial = self.dial_eligible_peers(eligible, bans);
+        let request_peers = self
+            .min_outbound_candidates
+            .map_or(false, |min_candidates| {
+                self.num_outbound_candidates(eligible, bans) < min_candidates
+            });
+        Actions {
+            cancel,
+            disconnect,
+            dial,
+            request_peers,
+        }
+    }
+
+    /// Dials the peers which are eligible but are neither connected nor queued for dialing.
+    fn dial_eligible_peers(
+        &mut self,
+        eligible: &HashMap<PeerId, NetworkPublicKeys>,
+        bans: &impl BanCheck,
+    ) -> Vec<Dial> {
+        let connected = &self.connected;
+        let queued = &self.queued;
+        let mut to_connect: Vec<_> = self
+            .peer_addresses
+            .0
+            .iter()
+            .filter(|(peer_id, addrs)| {
+                eligible.contains_key(peer_id)  // The node is eligible to be dialed.
+                    && !connected.contains_key(peer_id) // The node is not already connected.
+                    && !queued.contains(peer_id) // There is no pending dial to this node.
+                    && !addrs.is_empty() // There is an address to dial.
+                    && !bans.is_peer_banned(peer_id) // The node is not banned.
+            })
+            .collect();
+
+        // We tune max delay depending on the number of peers to which we're not connected. This
+        // ensures that if we're disconnected from a large fraction of peers, we keep the retry
+        // window smaller.
+        let max_delay = Duration::from_millis(
+            (self.max_delay_ms as f64
+                * (1.0
+                    - ((queued.len() + to_connect.len()) as f64
+                        / eligible
+                            .iter()
+                            .filter(|(peer_id, _)| self.peer_addresses.0.contains_key(&peer_id))
+                            .count() as f64))) as u64,
+        );
+
+        // Dial the peers of the preferred regions first, and only as many as the preferences allow.
+        let preferences = &self.preferences;
+        to_connect.sort_by_key(|(peer_id, _)| (preferences.rank(peer_id), **peer_id));
+        if let Some(max_connections) = preferences.max_connections {
+            to_connect.truncate(max_connections.saturating_sub(connected.len() + queued.len()));
+        }
+
+        // The initial dial state; it has zero dial delay and uses the first
+        // address.
+        let init_dial_state = DialState::new(self.backoff_strategy.clone());
+
+        let mut dials = Vec::new();
+        for (peer_id, addrs) in to_connect {
+            let dial_state = self
+                .dial_states
+                .entry(*peer_id)
+                .or_insert_with(|| init_dial_state.clone());
+
+            // Choose the next addr to dial for this peer. Currently, we just
+            // round-robin the selection, i.e., try the sequence:
+            // addr[0], .., addr[len-1], addr[0], ..
+            let addr = dial_state.next_addr(&addrs).clone();
+            // Addresses in a banned IP prefix are skipped, and retried on the next check.
+            if bans.is_address_banned(&addr) {
+                continue;
+            }
+
+            // Using the DialState's backoff strategy, compute the delay until
+            // the next dial attempt for this peer.
+            let delay = dial_state.next_backoff_delay(max_delay);
+            let fallback_addrs = dial_state.fallback_addrs(&addrs, &addr, bans);
+            dials.push(Dial {
+                peer_id: *peer_id,
+                addr,
+                delay,
+                fallback_addrs,
+            });
+        }
+        for dial in &dials {
+            self.queued.insert(dial.peer_id);
+        }
+        dials
+    }
+
+    /// Number of eligible peers with known addresses which aren't connected, nor banned.
+    pub fn num_outbound_candidates(
+        &self,
+        eligible: &HashMap<PeerId, NetworkPublicKeys>,
+        bans: &impl BanCheck,
+    ) -> usize {
+        self.peer_addresses
+            .0
+            .iter()
+            .filter(|(peer_id, addrs)| {
+                eligible.contains_key(peer_id)
+                    && !self.connected.contains_key(peer_id)
+                    && !addrs.is_empty()
+                    && !bans.is_peer_banned(peer_id)
+            })
+            .count()
+    }
+
+    /// Replaces the addresses of the peers of `address_map` from `src`. Returns whether any
+    /// changed.
+    pub fn update_addresses(
+        &mut self,
+        src: DiscoverySource,
+        address_map: HashMap<PeerId, Vec<NetworkAddress>>,
+    ) -> bool {
+        // Keep track of if any peer's addresses have actually changed, so
+        // we can log without too much spam.
+        let mut have_any_changed = false;
+        let self_peer_id = self.self_peer_id.short_str();
+
+        for (peer_id, addrs) in address_map {
+            // Do not include self_peer_id in the address list for dialing
+            // to avoid pointless self-dials.
+            if peer_id == self.self_peer_id {
+                continue;
+            }
+
+            // Update peer's addresses
+            let curr_addrs = self.peer_addresses.0.entry(peer_id).or_default();
+            if curr_addrs.update(src, addrs) {
+                // At least one peer's addresses have actually changed.
+                have_any_changed = true;
+
+                // Ensure that the next dial attempt starts from the first
+                // address if the addresses have actually changed.
+                if let Some(dial_state) = self.dial_states.get_mut(&peer_id) {
+                    dial_state.reset_addr();
+                }
+
+                // Log the change to this peer's addresses.
+                let peer_id = peer_id.short_str();
+                let addrs = curr_addrs;
+                info!(
+                    "[{}] addresses updated for peer: {}, update src: {:?}, addrs: {}",
+                    self_peer_id, peer_id, src, addrs,
+                );
+            }
+        }
+
+        // Only log the total state if anything has actually changed.
+        if have_any_changed {
+            let peer_addresses = &self.peer_addresses;
+            info!(
+                "[{}] current addresses: update src: {:?}, all peer addresses: {}",
+                self_peer_id, src, peer_addresses,
+            );
+        }
+        have_any_changed
+    }
+
+    /// Records the connection with `peer_id` at `addr`, which ends its queued dial and backoff.
+    pub fn peer_connected(&mut self, peer_id: PeerId, addr: NetworkAddress) {
+        self.connected.insert(peer_id, addr);
+        self.dial_states.remove(&peer_id);
+        self.queued.remove(&peer_id);
+    }
+
+    /// Records the loss of the connection with `peer_id` at `addr`. Returns false if the peer was
+    /// connected at another address, i.e., the loss is stale.
+    pub fn peer_lost(&mut self, peer_id: &PeerId, addr: &NetworkAddress) -> bool {
+        match self.connected.get(peer_id) {
+            Some(curr_addr) if curr_addr == addr => {
+                self.connected.remove(peer_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records that the queued dial of `peer_id` completed, whether it succeeded or not.
+    pub fn dial_complete(&mut self, peer_id: &PeerId) {
+        self.queued.remove(peer_id);
+    }
+
+    /// Forgets the queued dials and their backoff, e.g., after a panic left them half-queued.
+    pub fn forget_dials(&mut self) {
+        self.queued.clear();
+        self.dial_states.clear();
+    }
+
+    /// Enters or leaves maintenance mode. Entering it cancels all the queued dials.
+    pub fn set_maintenance_mode(&mut self, maintenance_mode: bool) {
+        self.maintenance_mode = maintenance_mode;
+        if maintenance_mode {
+            self.queued.clear();
+        }
+    }
+
+    pub fn set_preferences(&mut self, preferences: ConnectivityPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Asks for more peers on the checks which leave fewer than `min_outbound_candidates`.
+    pub fn enable_peer_exchange(&mut self, min_outbound_candidates: usize) {
+        self.min_outbound_candidates = Some(min_outbound_candidates);
+    }
+
+    /// Makes the next dial of `peer_id` start over from its initial backoff, at the address
+    /// following `addr`, e.g., when `addr` turned out to be unreachable.
+    pub fn redial_after(&mut self, peer_id: PeerId, addr: &NetworkAddress) {
+        let mut dial_state = DialState::new(self.backoff_strategy.clone());
+        if let Some(addrs) = self.peer_addresses.0.get(&peer_id) {
+            if let Some(addr_idx) = (0..addrs.len()).find(|idx| addrs.get(*idx) == Some(addr)) {
+                dial_state.addr_idx = addr_idx + 1;
+            }
+        }
+        self.dial_states.insert(peer_id, dial_state);
+    }
+
+    pub fn connected_addr(&self, peer_id: &PeerId) -> Option<&NetworkAddress> {
+        self.connected.get(peer_id)
+    }
+
+    pub fn num_queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Whether `addr` is one of the known addresses of `peer_id`.
+    pub fn is_known_address(&self, peer_id: &PeerId, addr: &NetworkAddress) -> bool {
+        self.peer_addresses
+            .0
+            .get(peer_id)
+            .map_or(false, |addrs| addrs.contains(addr))
+    }
+
+    /// The peers with addresses from any source, current or past.
+    pub fn known_peers(&self) -> Vec<PeerId> {
+        self.peer_addresses.0.keys().cloned().collect()
+    }
+
+    /// The addresses of the known peers, which aren't banned.
+    pub fn known_addresses(&self, bans: &impl BanCheck) -> HashMap<PeerId, Vec<NetworkAddress>> {
+        self.peer_addresses
+            .0
+            .iter()
+            .filter(|(peer_id, addrs)| !addrs.is_empty() && !bans.is_peer_banned(peer_id))
+            .map(|(peer_id, addrs)| (*peer_id, addrs.to_vec()))
+            .collect()
+    }
+}
+
+impl fmt::Display for PeerAddresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write the normal HashMap-style debug format, but shorten the peer_id's
+        // so the output isn't as noisy.
+        f.debug_map()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|(peer_id, addrs)| (peer_id.short_str(), addrs)),
+            )
+            .finish()
+    }
+}
+
+impl fmt::Debug for PeerAddresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Addresses {
+    fn new() -> Self {
+        Default::default()
+    }
+
+    fn from_addrs(src: DiscoverySource, src_addrs: Vec<NetworkAddress>) -> Self {
+        let mut addrs = Self::new();
+        addrs.update(src, src_addrs);
+        addrs
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(Vec::len).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Update the addresses for the `DiscoverySource` bucket. Return `true` if
+    /// the addresses have actually changed.
+    fn update(&mut self, src: DiscoverySource, addrs: Vec<NetworkAddress>) -> bool {
+        let src_idx = src as u8 as usize;
+        if self.0[src_idx] != addrs {
+            self.0[src_idx] = addrs;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<&NetworkAddress> {
+        self.0.iter().flatten().nth(idx)
+    }
+
+    fn contains(&self, addr: &NetworkAddress) -> bool {
+        self.0.iter().flatten().any(|known_addr| known_addr == addr)
+    }
+
+    /// The distinct addresses of all the sources, in priority order.
+    fn to_vec(&self) -> Vec<NetworkAddress> {
+        let mut addrs: Vec<NetworkAddress> = Vec::new();
+        for addr in self.0.iter().flatten() {
+            if !addrs.contains(addr) {
+                addrs.push(addr.clone());
+            }
+        }
+        addrs
+    }
+}
+
+impl fmt::Display for Addresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write without the typical "Addresses(..)" around the output to reduce
+        // debug noise.
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl fmt::Debug for Addresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl<TBackoff> DialState<TBackoff>
+where
+    TBackoff: Iterator<Item = Duration> + Clone,
+{
+    fn new(backoff: TBackoff) -> Self {
+        Self {
+            backoff,
+            addr_idx: 0,
+        }
+    }
+
+    fn reset_addr(&mut self) {
+        self.addr_idx = 0;
+    }
+
+    fn next_addr<'a>(&mut self, addrs: &'a Addresses) -> &'a NetworkAddress {
+        assert!(!addrs.is_empty());
+
+        let addr_idx = self.addr_idx;
+        self.addr_idx = self.addr_idx.wrapping_add(1);
+
+        addrs.get(addr_idx % addrs.len()).unwrap()
+    }
+
+    /// The distinct addresses other than `addr` which aren't banned, in the order in which they
+    /// follow the last address returned by [`Self::next_addr`].
+    fn fallback_addrs(
+        &self,
+        addrs: &Addresses,
+        addr: &NetworkAddress,
+        bans: &impl BanCheck,
+    ) -> Vec<NetworkAddress> {
+        let mut fallback_addrs: Vec<NetworkAddress> = Vec::new();
+        for idx in 0..addrs.len() {
+            let fallback_addr = addrs
+                .get(self.addr_idx.wrapping_add(idx) % addrs.len())
+                .unwrap();
+            if fallback_addr != addr
+                && !fallback_addrs.contains(fallback_addr)
+                && !bans.is_address_banned(fallback_addr)
+            {
+                fallback_addrs.push(fallback_addr.clone());
+            }
+        }
+        fallback_addrs
+    }
+
+    fn next_backoff_delay(&mut self, max_delay: Duration) -> Duration {
+        min(max_delay, self.backoff.next().unwrap_or(max_delay))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_crypto::{x25519, Uniform};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::iter;
+
+    const BACKOFF: Duration = Duration::from_millis(10);
+
+    #[derive(Default)]
+    struct TestBans {
+        peers: Vec<PeerId>,
+        addrs: Vec<NetworkAddress>,
+    }
+
+    impl BanCheck for TestBans {
+        fn is_peer_banned(&self, peer_id: &PeerId) -> bool {
+            self.peers.contains(peer_id)
+        }
+
+        fn is_address_banned(&self, addr: &NetworkAddress) -> bool {
+            self.addrs.contains(addr)
+        }
+    }
+
+    fn addr(idx: usize) -> NetworkAddress {
+        format!("/ip4/10.0.0.{}/tcp/6180", idx).parse().unwrap()
+    }
+
+    fn eligible(peers: &[PeerId]) -> HashMap<PeerId, NetworkPublicKeys> {
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let keys = NetworkPublicKeys {
+            identity_public_key: x25519::PrivateKey::generate(&mut rng).public_key(),
+        };
+        peers
+            .iter()
+            .map(|peer_id| (*peer_id, keys.clone()))
+            .collect()
+    }
+
+    fn policy<TBackoff>(
+        seed_peers: HashMap<PeerId, Vec<NetworkAddress>>,
+        backoff: TBackoff,
+    ) -> ConnectivityPolicy<TBackoff>
+    where
+        TBackoff: Iterator<Item = Duration> + Clone,
+    {
+        ConnectivityPolicy::new(PeerId::random(), seed_peers, backoff, 1000 /* ms */)
+    }
+
+    fn dialed(actions: &Actions) -> Vec<(PeerId, NetworkAddress)> {
+        actions
+            .dial
+            .iter()
+            .map(|dial| (dial.peer_id, dial.addr.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn single_peer_decisions() {
+        let peer_id = PeerId::random();
+        // Every combination of the states of a peer.
+        for case in 0..(1 << 7) {
+            let is = |bit: u32| case & (1 << bit) != 0;
+            let (is_eligible, has_addr, is_connected, is_queued) = (is(0), is(1), is(2), is(3));
+            let (is_peer_banned, is_addr_banned, in_maintenance) = (is(4), is(5), is(6));
+
+            let mut seed_peers = HashMap::new();
+            if has_addr {
+                seed_peers.insert(peer_id, vec![addr(1)]);
+            }
+            let mut policy = policy(seed_peers, iter::repeat(BACKOFF));
+            policy.enable_peer_exchange(1);
+            policy.set_maintenance_mode(in_maintenance);
+            if is_connected {
+                policy.peer_connected(peer_id, addr(1));
+            }
+            if is_queued {
+                policy.queued.insert(peer_id);
+            }
+            let eligible = if is_eligible {
+                eligible(&[peer_id])
+            } else {
+                HashMap::new()
+            };
+            let bans = TestBans {
+                peers: if is_peer_banned {
+                    vec![peer_id]
+                } else {
+                    vec![]
+                },
+                addrs: if is_addr_banned {
+                    vec![addr(1)]
+                } else {
+                    vec![]
+                },
+            };
+
+            let actions = policy.check(&eligible, &bans);
+
+            let should_cancel = is_queued && (!is_eligible || is_peer_banned);
+            let should_disconnect =
+                is_connected && (!is_eligible || is_peer_banned || is_addr_banned);
+            let should_dial = !in_maintenance
+                && is_eligible
+                && has_addr
+                && !is_connected
+                && !is_queued
+                && !is_peer_banned
+                && !is_addr_banned;
+            let is_candidate = is_eligible && has_addr && !is_connected && !is_peer_banned;
+            assert_eq!(
+                actions.cancel,
+                if should_cancel { vec![peer_id] } else { vec![] },
+                "case {:07b}",
+                case
+            );
+            assert_eq!(
+                actions.disconnect,
+                if should_disconnect {
+                    vec![peer_id]
+                } else {
+                    vec![]
+                },
+                "case {:07b}",
+                case
+            );
+            assert_eq!(
+                dialed(&actions),
+                if should_dial {
+                    vec![(peer_id, addr(1))]
+                } else {
+                    vec![]
+                },
+                "case {:07b}",
+                case
+            );
+            assert_eq!(
+                actions.request_peers,
+                !in_maintenance && !is_candidate,
+                "case {:07b}",
+                case
+            );
+            assert_eq!(
+                policy.num_queued(),
+                usize::from((is_queued && !should_cancel) || should_dial),
+                "case {:07b}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn dial_budget() {
+        for num_connected in 0..=2 {
+            for num_queued in 0..=2 {
+                for num_candidates in 0..=3 {
+                    let max_connections = iter::once(None).chain((0..=6).map(Some));
+                    for max_connections in max_connections {
+                        let connected: Vec<_> =
+                            (0..num_connected).map(|_| PeerId::random()).collect();
+                        let queued: Vec<_> = (0..num_queued).map(|_| PeerId::random()).collect();
+                        let candidates: Vec<_> =
+                            (0..num_candidates).map(|_| PeerId::random()).collect();
+                        let all: Vec<_> = connected
+                            .iter()
+                            .chain(&queued)
+                            .chain(&candidates)
+                            .cloned()
+                            .collect();
+                        let seed_peers = all
+                            .iter()
+                            .map(|peer_id| (*peer_id, vec![addr(1)]))
+                            .collect();
+                        let mut policy = policy(seed_peers, iter::repeat(BACKOFF));
+                        for peer_id in &connected {
+                            policy.peer_connected(*peer_id, addr(1));
+                        }
+                        policy.queued.extend(queued.iter().cloned());
+                        // Every other candidate is in the preferred region.
+                        let peer_regions = candidates
+                            .iter()
+                            .enumerate()
+                            .filter(|(idx, _)| idx % 2 == 1)
+                            .map(|(_, peer_id)| (*peer_id, "preferred".to_string()))
+                            .collect();
+                        policy.set_preferences(ConnectivityPreferences {
+                            preferred_regions: vec!["preferred".to_string()],
+                            max_connections,
+                            peer_regions,
+                        });
+
+                        let actions = policy.check(&eligible(&all), &TestBans::default());
+
+                        let budget = max_connections.map_or(num_candidates, |max_connections| {
+                            max_connections.saturating_sub(num_connected + num_queued)
+                        });
+                        let mut expected: Vec<_> = candidates
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, peer_id)| (idx % 2 == 0, *peer_id))
+                            .collect();
+                        expected.sort();
+                        let expected: Vec<_> = expected
+                            .into_iter()
+                            .take(budget)
+                            .map(|(_, peer_id)| (peer_id, addr(1)))
+                            .collect();
+                        assert_eq!(dialed(&actions), expected);
+                        assert!(actions.cancel.is_empty());
+                        assert!(actions.disconnect.is_empty());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_and_addresses() {
+        let peer_id = PeerId::random();
+        let connected: Vec<_> = (0..3).map(|_| PeerId::random()).collect();
+        let mut seed_peers: HashMap<_, _> = connected
+            .iter()
+            .map(|peer_id| (*peer_id, vec![addr(1)]))
+            .collect();
+        seed_peers.insert(peer_id, vec![addr(1), addr(2), addr(3)]);
+        let backoff = vec![BACKOFF, 2 * BACKOFF, 3 * BACKOFF].into_iter();
+        let mut policy = policy(seed_peers, backoff);
+        for peer_id in &connected {
+            policy.peer_connected(*peer_id, addr(1));
+        }
+        let mut all = connected.clone();
+        all.push(peer_id);
+        let eligible = eligible(&all);
+        let bans = TestBans::default();
+        let check = |policy: &mut ConnectivityPolicy<_>| {
+            let actions = policy.check(&eligible, &bans);
+            assert_eq!(actions.dial.len(), 1);
+            let dial = actions.dial[0].clone();
+            assert_eq!(dial.peer_id, peer_id);
+            policy.dial_complete(&peer_id);
+            (dial.addr, dial.delay)
+        };
+
+        // The addresses are dialed in turn, with the delays of the backoff, capped by the max
+        // delay, which is reduced by the fraction of the peers not connected.
+        let max_delay = Duration::from_millis(750);
+        assert_eq!(check(&mut policy), (addr(1), BACKOFF));
+        assert_eq!(check(&mut policy), (addr(2), 2 * BACKOFF));
+        assert_eq!(check(&mut policy), (addr(3), 3 * BACKOFF));
+        assert_eq!(check(&mut policy), (addr(1), max_delay));
+
+        // New addresses of a higher priority source are dialed from the first one.
+        let mut address_map = HashMap::new();
+        address_map.insert(peer_id, vec![addr(4), addr(5)]);
+        address_map.insert(policy.self_peer_id, vec![addr(6)]);
+        assert!(policy.update_addresses(DiscoverySource::Gossip, address_map.clone()));
+        assert!(!policy.update_addresses(DiscoverySource::Gossip, address_map));
+        assert!(policy.connected_addr(&policy.self_peer_id).is_none());
+        assert!(!policy.known_peers().contains(&policy.self_peer_id));
+        assert_eq!(check(&mut policy), (addr(4), max_delay));
+
+        // Connecting resets the backoff, and an unreachable address is skipped on the redial.
+        policy.peer_connected(peer_id, addr(5));
+        assert!(!policy.peer_lost(&peer_id, &addr(4)));
+        policy.redial_after(peer_id, &addr(5));
+        assert!(policy.peer_lost(&peer_id, &addr(5)));
+        assert_eq!(check(&mut policy), (addr(1), BACKOFF));
+    }
+
+    #[test]
+    fn fallback_addresses() {
+        let peer_id = PeerId::random();
+        let mut seed_peers = HashMap::new();
+        seed_peers.insert(peer_id, vec![addr(1), addr(2), addr(3), addr(2)]);
+        let mut policy = policy(seed_peers, iter::repeat(BACKOFF));
+        let eligible = eligible(&[peer_id]);
+        let bans = TestBans {
+            addrs: vec![addr(3)],
+            ..TestBans::default()
+        };
+        let mut check = || {
+            let actions = policy.check(&eligible, &bans);
+            assert_eq!(actions.dial.len(), 1);
+            policy.dial_complete(&peer_id);
+            let dial = actions.dial[0].clone();
+            (dial.addr, dial.fallback_addrs)
+        };
+
+        // The other distinct addresses which aren't banned are raced, in the order in which they
+        // follow the dialed address.
+        assert_eq!(check(), (addr(1), vec![addr(2)]));
+        assert_eq!(check(), (addr(2), vec![addr(1)]));
+    }
+}
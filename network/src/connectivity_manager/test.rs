@@ -3,6 +3,7 @@
 
 use super::*;
 use crate::{
+    address_book::FileAddressBook,
     peer::DisconnectReason,
     peer_manager::{conn_notifs_channel, ConnectionRequest},
 };
@@ -12,6 +13,7 @@ use futures::SinkExt;
 use libra_crypto::{test_utils::TEST_SEED, x25519, Uniform};
 use libra_logger::info;
 use libra_network_address::NetworkAddress;
+use libra_temppath::TempPath;
 use rand::{rngs::StdRng, SeedableRng};
 use std::{io, num::NonZeroUsize};
 use tokio::runtime::Runtime;
@@ -26,6 +28,23 @@ fn setup_conn_mgr(
     conn_notifs_channel::Sender,
     channel::Sender<ConnectivityRequest>,
     channel::Sender<()>,
+) {
+    setup_conn_mgr_with_persistence(rt, eligible_peers, seed_peers, None, None, None, None)
+}
+
+fn setup_conn_mgr_with_persistence(
+    rt: &mut Runtime,
+    eligible_peers: Vec<PeerId>,
+    seed_peers: HashMap<PeerId, Vec<NetworkAddress>>,
+    trusted_peers_file: Option<PathBuf>,
+    trusted_peers_updates_tx: Option<channel::Sender<TrustedPeersDiff>>,
+    address_book: Option<Box<dyn AddressBook>>,
+    dial_stagger: Option<Duration>,
+) -> (
+    libra_channel::Receiver<PeerId, ConnectionRequest>,
+    conn_notifs_channel::Sender,
+    channel::Sender<ConnectivityRequest>,
+    channel::Sender<()>,
 ) {
     let self_peer_id = PeerId::random();
     let (connection_reqs_tx, connection_reqs_rx) =
@@ -46,7 +65,7 @@ fn setup_conn_mgr(
         })
         .collect::<HashMap<_, _>>();
 
-    let conn_mgr = {
+    let mut conn_mgr = {
         ConnectivityManager::new(
             self_peer_id,
             Arc::new(RwLock::new(eligible_peers)),
@@ -57,8 +76,17 @@ fn setup_conn_mgr(
             conn_mgr_reqs_rx,
             FixedInterval::from_millis(100),
             300, /* ms */
+            BanList::new(),
+            trusted_peers_file,
+            trusted_peers_updates_tx,
         )
     };
+    if let Some(address_book) = address_book {
+        conn_mgr = conn_mgr.with_address_book(address_book, Duration::from_secs(3600));
+    }
+    if let Some(dial_stagger) = dial_stagger {
+        conn_mgr = conn_mgr.with_parallel_dials(dial_stagger);
+    }
     rt.spawn(conn_mgr.start());
     (
         connection_reqs_rx,
@@ -159,7 +187,11 @@ async fn expect_dial_request(
         send_notification_await_delivery(
             connection_notifs_tx,
             peer_id,
-            peer_manager::ConnectionNotification::NewPeer(peer_id, address),
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_id,
+                address,
+                peer_manager::ConnectionInfo::mock(),
+            ),
         )
         .await;
     }
@@ -290,6 +322,82 @@ fn connect_to_seeds_on_startup() {
     rt.block_on(f_peer_mgr);
 }
 
+#[test]
+// Tests that the DNS name of a seed address is handed unresolved to the peer manager on every
+// dial, so that the name is resolved again after a failed dial or a lost connection.
+fn redial_dns_seed_addr() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let seed_addr = NetworkAddress::from_str("/dns4/seed.example.com/tcp/9090").unwrap();
+    let seed_peers = vec![(seed_peer_id, vec![seed_addr.clone()])]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let eligible_peers = vec![seed_peer_id];
+
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+
+    let f_peer_mgr = async move {
+        info!("Waiting to receive dial request to seed peer");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr.clone(),
+            Err(PeerManagerError::IoError(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            ))),
+        )
+        .await;
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        info!("Waiting to receive dial request to seed peer");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // The seed peer moves to another IP address.
+        info!("Sending lost peer notification for seed peer");
+        send_notification_await_delivery(
+            &mut connection_notifs_tx,
+            seed_peer_id,
+            peer_manager::ConnectionNotification::LostPeer(
+                seed_peer_id,
+                seed_addr.clone(),
+                DisconnectReason::ConnectionLost,
+            ),
+        )
+        .await;
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        info!("Waiting to receive dial request to seed peer");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr);
+}
+
 #[test]
 fn addr_change() {
     ::libra_logger::Logger::new().environment_only(true).init();
@@ -527,7 +635,7 @@ fn disconnect() {
         // Send request to make other peer ineligible.
         info!("Sending request to make other peer ineligible");
         conn_mgr_reqs_tx
-            .send(ConnectivityRequest::UpdateEligibleNodes(HashMap::new()))
+            .send(ConnectivityRequest::UpdateEligibleNodes(1, HashMap::new()))
             .await
             .unwrap();
 
@@ -614,7 +722,7 @@ fn retry_on_failure() {
         // Send request to make other peer ineligible.
         info!("Sending request to make other peer ineligible");
         conn_mgr_reqs_tx
-            .send(ConnectivityRequest::UpdateEligibleNodes(HashMap::new()))
+            .send(ConnectivityRequest::UpdateEligibleNodes(1, HashMap::new()))
             .await
             .unwrap();
 
@@ -704,7 +812,11 @@ fn no_op_requests() {
         send_notification_await_delivery(
             &mut connection_notifs_tx,
             other_peer_id,
-            peer_manager::ConnectionNotification::NewPeer(other_peer_id, other_address.clone()),
+            peer_manager::ConnectionNotification::NewPeer(
+                other_peer_id,
+                other_address.clone(),
+                peer_manager::ConnectionInfo::mock(),
+            ),
         )
         .await;
 
@@ -715,7 +827,7 @@ fn no_op_requests() {
         // Send request to make other peer ineligible.
         info!("Sending request to make other peer ineligible");
         conn_mgr_reqs_tx
-            .send(ConnectivityRequest::UpdateEligibleNodes(HashMap::new()))
+            .send(ConnectivityRequest::UpdateEligibleNodes(1, HashMap::new()))
             .await
             .unwrap();
 
@@ -755,6 +867,73 @@ fn no_op_requests() {
     rt.block_on(events_f);
 }
 
+#[test]
+fn maintenance_mode() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let other_peer_id = PeerId::random();
+    let eligible_peers = vec![other_peer_id];
+    let seed_peers = HashMap::new();
+    info!("Other peer_id is {}", other_peer_id.short_str());
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+
+    let events_f = async move {
+        let other_address = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+
+        info!("Entering maintenance mode");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::SetMaintenanceMode(true))
+            .await
+            .unwrap();
+
+        // Send address of other peer.
+        info!("Sending address of other peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                DiscoverySource::Gossip,
+                [(other_peer_id, vec![other_address.clone()])]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            ))
+            .await
+            .unwrap();
+
+        // Trigger connectivity checks. The second tick can only be sent once the first one has
+        // been handled.
+        info!("Sending ticks to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        ticker_tx.send(()).await.unwrap();
+
+        // Nothing is dialed while in maintenance mode.
+        assert_eq!(0, get_dial_queue_size(&mut conn_mgr_reqs_tx).await);
+
+        info!("Leaving maintenance mode");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::SetMaintenanceMode(false))
+            .await
+            .unwrap();
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        // Peer manager receives a request to connect to the other peer.
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            other_peer_id,
+            other_address.clone(),
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(events_f);
+}
+
 #[test]
 fn backoff_on_failure() {
     ::libra_logger::Logger::new().environment_only(true).init();
@@ -773,6 +952,7 @@ fn backoff_on_failure() {
         info!("Sending list of eligible peers");
         conn_mgr_reqs_tx
             .send(ConnectivityRequest::UpdateEligibleNodes(
+                1,
                 [(peer_a, peer_a_keys), (peer_b, peer_b_keys)]
                     .iter()
                     .cloned()
@@ -811,7 +991,11 @@ fn backoff_on_failure() {
         send_notification_await_delivery(
             &mut connection_notifs_tx,
             peer_b,
-            peer_manager::ConnectionNotification::NewPeer(peer_b, peer_b_address.clone()),
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_b,
+                peer_b_address.clone(),
+                peer_manager::ConnectionInfo::mock(),
+            ),
         )
         .await;
 
@@ -1103,3 +1287,541 @@ fn multiple_addrs_shrinking() {
     };
     rt.block_on(f_peer_mgr);
 }
+
+// Tests that reloading the trusted and seed peers disconnects the peers which are no longer
+// trusted and dials the new seed peers, without waiting for a connectivity check.
+#[test]
+fn reload_peers() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let old_peer_id = PeerId::random();
+    let old_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+    let seed_peers = vec![(old_peer_id, vec![old_addr.clone()])]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let eligible_peers = vec![old_peer_id];
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+
+    let events_f = async move {
+        // Peer manager receives a request to connect to the old seed peer on startup.
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            old_peer_id,
+            old_addr.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // Replace the old seed peer with a new one.
+        let (new_peer_id, new_peer_keys) = gen_peer();
+        let new_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap();
+        info!("Sending request to reload peers");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::ReloadPeers(
+                [(new_peer_id, new_peer_keys)].iter().cloned().collect(),
+                [(new_peer_id, vec![new_addr.clone()])]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            ))
+            .await
+            .unwrap();
+
+        // Peer manager receives a request to disconnect from the old peer, then to connect to
+        // the new one, without any tick.
+        info!("Waiting to receive disconnect request");
+        expect_disconnect_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            old_peer_id,
+            old_addr,
+            Ok(()),
+        )
+        .await;
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            new_peer_id,
+            new_addr,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn ban_peer() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let seed_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+    let seed_peers = vec![(seed_peer_id, vec![seed_addr.clone()])]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let eligible_peers = vec![seed_peer_id];
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+
+    let events_f = async move {
+        // Peer manager receives a request to connect to the seed peer on startup.
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // Peer manager receives a request to disconnect from the banned peer, without any tick.
+        info!("Sending request to ban the seed peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::BanPeer {
+                peer_id: seed_peer_id,
+                duration: Duration::from_secs(3600),
+            })
+            .await
+            .unwrap();
+        info!("Waiting to receive disconnect request");
+        expect_disconnect_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            seed_peer_id,
+            seed_addr,
+            Ok(()),
+        )
+        .await;
+
+        // The banned peer isn't dialed again. The second tick is only received once the first
+        // one is handled.
+        info!("Sending ticks to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        ticker_tx.send(()).await.unwrap();
+        assert_eq!(0, get_dial_queue_size(&mut conn_mgr_reqs_tx).await);
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn peer_exchange() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let seed_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+    let seed_peers = vec![(seed_peer_id, vec![seed_addr.clone()])]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let eligible_peers = vec![seed_peer_id];
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+
+    let events_f = async move {
+        // Peer manager receives a request to connect to the seed peer on startup.
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // Once connected to the only eligible peer, peer exchange is asked for more peers on the
+        // next connectivity check.
+        info!("Enabling peer exchange");
+        let (requests_tx, mut requests_rx) = mpsc::channel(1);
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::EnablePeerExchange {
+                min_outbound_candidates: 1,
+                requests_tx,
+            })
+            .await
+            .unwrap();
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        requests_rx.next().await.unwrap();
+
+        // The known addresses are served to peer exchange.
+        let (known_tx, known_rx) = oneshot::channel();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::GetKnownAddresses(known_tx))
+            .await
+            .unwrap();
+        let mut expected = HashMap::new();
+        expected.insert(seed_peer_id, vec![seed_addr]);
+        assert_eq!(known_rx.await.unwrap(), expected);
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn persist_and_notify_trusted_peers() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let trusted_peers_file = TempPath::new();
+    let (trusted_peers_updates_tx, mut trusted_peers_updates_rx) = channel::new_test(8);
+    let (_connection_reqs_rx, _connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr_with_persistence(
+            &mut rt,
+            vec![],
+            HashMap::new(),
+            Some(trusted_peers_file.path().to_path_buf()),
+            Some(trusted_peers_updates_tx),
+            None,
+            None,
+        );
+
+    let events_f = async move {
+        let (peer_a, peer_a_keys) = gen_peer();
+        let eligible: HashMap<_, _> = vec![(peer_a, peer_a_keys.clone())].into_iter().collect();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateEligibleNodes(
+                5,
+                eligible.clone(),
+            ))
+            .await
+            .unwrap();
+        let diff = trusted_peers_updates_rx.next().await.unwrap();
+        assert_eq!(diff.epoch, Some(5));
+        assert_eq!(diff.added, eligible);
+        assert!(diff.removed.is_empty() && diff.key_changed.is_empty());
+        assert_eq!(
+            PersistedTrustedPeers::load(trusted_peers_file.path()).unwrap(),
+            Some(PersistedTrustedPeers {
+                epoch: Some(5),
+                peers: eligible,
+            })
+        );
+
+        // An update leaving the peers unchanged is persisted, but not notified.
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateEligibleNodes(
+                6,
+                vec![(peer_a, peer_a_keys)].into_iter().collect(),
+            ))
+            .await
+            .unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateEligibleNodes(7, HashMap::new()))
+            .await
+            .unwrap();
+        let diff = trusted_peers_updates_rx.next().await.unwrap();
+        assert_eq!(diff.epoch, Some(7));
+        assert_eq!(diff.removed, vec![peer_a].into_iter().collect());
+        assert_eq!(
+            PersistedTrustedPeers::load(trusted_peers_file.path()).unwrap(),
+            Some(PersistedTrustedPeers {
+                epoch: Some(7),
+                peers: HashMap::new(),
+            })
+        );
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn connectivity_preferences() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let (eu_peer_id, us_peer_id, other_peer_id) =
+        (PeerId::random(), PeerId::random(), PeerId::random());
+    let eligible_peers = vec![eu_peer_id, us_peer_id, other_peer_id];
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, HashMap::new());
+
+    let events_f = async move {
+        info!("Sending connectivity preferences");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdatePreferences(
+                ConnectivityPreferences {
+                    preferred_regions: vec!["us".to_string()],
+                    max_connections: Some(1),
+                    peer_regions: vec![
+                        (eu_peer_id, "eu".to_string()),
+                        (us_peer_id, "us".to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+            ))
+            .await
+            .unwrap();
+        let us_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                DiscoverySource::OnChain,
+                vec![
+                    (
+                        eu_peer_id,
+                        vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()],
+                    ),
+                    (us_peer_id, vec![us_addr.clone()]),
+                    (
+                        other_peer_id,
+                        vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9092").unwrap()],
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            ))
+            .await
+            .unwrap();
+
+        // Only the peer of the preferred region is dialed.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            us_peer_id,
+            us_addr,
+            Ok(()),
+        )
+        .await;
+
+        // No other peer is dialed once connected to as many peers as the preferences allow. The
+        // second tick is only received once the first one is handled.
+        info!("Sending ticks to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        ticker_tx.send(()).await.unwrap();
+        assert_eq!(0, get_dial_queue_size(&mut conn_mgr_reqs_tx).await);
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn reconnect_unreachable_peer() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let seed_addr_1 = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+    let seed_addr_2 = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+    let seed_peers = vec![(seed_peer_id, vec![seed_addr_1.clone(), seed_addr_2.clone()])]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let eligible_peers = vec![seed_peer_id];
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr(&mut rt, eligible_peers, seed_peers);
+    let mut reporter = ReachabilityReporter::new(conn_mgr_reqs_tx.clone());
+
+    let events_f = async move {
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr_1.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // The connection is closed once the peer is reported unreachable often enough.
+        info!("Reporting the seed peer as unreachable");
+        for _ in 0..UNREACHABLE_HINTS_THRESHOLD {
+            assert_eq!(0, get_dial_queue_size(&mut conn_mgr_reqs_tx).await);
+            reporter.report_unreachable(seed_peer_id, ReachabilityHint::Timeout);
+        }
+        info!("Waiting to receive disconnect request");
+        expect_disconnect_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            seed_peer_id,
+            seed_addr_1,
+            Ok(()),
+        )
+        .await;
+
+        // The peer is redialed at its next address, without any tick.
+        info!("Waiting to receive dial request to the next address");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr_2,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn persist_and_load_address_book() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let (peer_a, stale_peer) = (PeerId::random(), PeerId::random());
+    let addr_a = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+    let stale_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+    let now_secs = address_book::now_secs();
+    let path = TempPath::new();
+    let mut known_addresses = KnownAddresses::new();
+    address_book::record(&mut known_addresses, peer_a, addr_a.clone(), now_secs - 60);
+    address_book::record(
+        &mut known_addresses,
+        stale_peer,
+        stale_addr,
+        now_secs - 7200,
+    );
+    FileAddressBook::new(path.path().to_path_buf())
+        .store(&known_addresses)
+        .unwrap();
+
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr_with_persistence(
+            &mut rt,
+            vec![peer_a, stale_peer],
+            HashMap::new(),
+            None,
+            None,
+            Some(Box::new(FileAddressBook::new(path.path().to_path_buf()))),
+            None,
+        );
+
+    let events_f = async move {
+        // The stale address is pruned on startup, so only the other peer is dialed.
+        info!("Waiting to receive dial request to the address from the address book");
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            peer_a,
+            addr_a.clone(),
+            Ok(()),
+        )
+        .await;
+        assert_eq!(0, get_dial_queue_size(&mut conn_mgr_reqs_tx).await);
+
+        // The successful dial refreshes the address in the address book.
+        let known_addresses = FileAddressBook::new(path.path().to_path_buf())
+            .load()
+            .unwrap();
+        assert_eq!(known_addresses.len(), 1);
+        let known = &known_addresses[&peer_a];
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].addr, addr_a);
+        assert!(known[0].last_seen_secs >= now_secs);
+    };
+    rt.block_on(events_f);
+}
+
+async fn expect_dial(
+    connection_reqs_rx: &mut libra_channel::Receiver<PeerId, ConnectionRequest>,
+    peer_id: PeerId,
+    address: &NetworkAddress,
+) -> oneshot::Sender<Result<(), PeerManagerError>> {
+    match connection_reqs_rx.next().await.unwrap() {
+        ConnectionRequest::DialPeer(p, addr, response_tx) => {
+            assert_eq!(peer_id, p);
+            assert_eq!(address, &addr);
+            response_tx
+        }
+        _ => panic!("unexpected request to peer manager"),
+    }
+}
+
+// Tests that the addresses of a peer are raced: the next address is dialed after the stagger while
+// the first dial is pending, and the pending dial is canceled once the peer is reached.
+#[test]
+fn parallel_dials() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let other_peer_id = PeerId::random();
+    let addr_1 = NetworkAddress::from_str("/ip6/::1/tcp/9091").unwrap();
+    let addr_2 = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+    let seed_peers: HashMap<_, _> = vec![(other_peer_id, vec![addr_1.clone(), addr_2.clone()])]
+        .into_iter()
+        .collect();
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr_with_persistence(
+            &mut rt,
+            vec![other_peer_id],
+            seed_peers,
+            None,
+            None,
+            None,
+            Some(Duration::from_millis(50)),
+        );
+
+    let events_f = async move {
+        // The first address doesn't respond.
+        let response_tx_1 = expect_dial(&mut connection_reqs_rx, other_peer_id, &addr_1).await;
+
+        // The second address is dialed after the stagger, and is reached.
+        info!("Waiting to receive the dial of the second address");
+        let response_tx_2 = expect_dial(&mut connection_reqs_rx, other_peer_id, &addr_2).await;
+        response_tx_2.send(Ok(())).unwrap();
+        send_notification_await_delivery(
+            &mut connection_notifs_tx,
+            other_peer_id,
+            peer_manager::ConnectionNotification::NewPeer(
+                other_peer_id,
+                addr_2,
+                peer_manager::ConnectionInfo::mock(),
+            ),
+        )
+        .await;
+        while get_dial_queue_size(&mut conn_mgr_reqs_tx).await > 0 {}
+
+        // The dial of the first address was canceled.
+        assert!(response_tx_1.is_canceled());
+    };
+    rt.block_on(events_f);
+}
+
+// Tests that the next address of a peer is dialed as soon as the dial of the previous one fails,
+// without waiting for the stagger.
+#[test]
+fn parallel_dials_failure() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let other_peer_id = PeerId::random();
+    let addr_1 = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+    let addr_2 = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+    let seed_peers: HashMap<_, _> = vec![(other_peer_id, vec![addr_1.clone(), addr_2.clone()])]
+        .into_iter()
+        .collect();
+    let (mut connection_reqs_rx, mut connection_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr_with_persistence(
+            &mut rt,
+            vec![other_peer_id],
+            seed_peers,
+            None,
+            None,
+            None,
+            Some(Duration::from_secs(3600)),
+        );
+
+    let events_f = async move {
+        let response_tx_1 = expect_dial(&mut connection_reqs_rx, other_peer_id, &addr_1).await;
+        response_tx_1
+            .send(Err(PeerManagerError::IoError(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            ))))
+            .unwrap();
+
+        expect_dial_request(
+            &mut connection_reqs_rx,
+            &mut connection_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            other_peer_id,
+            addr_2,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(events_f);
+}
@@ -1,11 +1,25 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::peer_manager::request_trace;
 use libra_metrics::{
-    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, HistogramVec,
-    IntCounterVec, IntGauge, IntGaugeVec, OpMetrics,
+    cardinality::{CardinalityGuard, DEFAULT_MAX_LABEL_VALUES, OTHER_LABEL_VALUE},
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, DurationHistogram, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    OpMetrics,
 };
+use libra_types::PeerId;
 use once_cell::sync::Lazy;
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Gauge, Opts,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 // some type labels
 pub const REQUEST_LABEL: &str = "request";
@@ -69,6 +83,199 @@ pub static LIBRA_NETWORK_RPC_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Caps the values of the peer_id label of `LIBRA_NETWORK_RPC_LATENCY`
+pub static LIBRA_NETWORK_RPC_LATENCY_PEERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_network_rpc_latency_seconds/peer_id",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
+/// Messages of the forward-compatible protocols ignored for their variants unknown to this version
+pub static LIBRA_NETWORK_UNKNOWN_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_unknown_messages",
+        "Libra network messages of unknown variants ignored",
+        &["protocol_id"]
+    )
+    .unwrap()
+});
+
+/// Skew of the clock of every peer relative to ours, estimated from the health checker pings
+pub static LIBRA_NETWORK_PEER_CLOCK_SKEW_USECS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_network_peer_clock_skew_usecs",
+        "Skew of the clock of the peer relative to ours, positive if ahead, in microseconds",
+        &["peer_id"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the peer_id label of `LIBRA_NETWORK_PEER_CLOCK_SKEW_USECS`
+pub static LIBRA_NETWORK_PEER_CLOCK_SKEW_PEERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_network_peer_clock_skew_usecs/peer_id",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
+/// Round-trip time of the successful health checker pings of every peer
+pub static LIBRA_NETWORK_PEER_RTT: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "libra_network_peer_rtt_seconds",
+        "Round-trip time of the successful health checker pings of the peer",
+        &["peer_id"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the peer_id label of `LIBRA_NETWORK_PEER_RTT`
+pub static LIBRA_NETWORK_PEER_RTT_PEERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_network_peer_rtt_seconds/peer_id",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
+/// Latencies between the pairs of peers, measured by the latency protocol
+pub static LIBRA_NETWORK_LATENCY_MATRIX_USECS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_network_latency_matrix_usecs",
+        "One-way latency, or its jitter, from a peer to another, in microseconds",
+        &["from_peer_id", "to_peer_id", "stat"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the peer_id labels of `LIBRA_NETWORK_LATENCY_MATRIX_USECS`
+pub static LIBRA_NETWORK_LATENCY_MATRIX_PEERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_network_latency_matrix_usecs/peer_id",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
+/// Number of peers in the routing table of the DHT
+pub static LIBRA_NETWORK_DHT_PEERS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "libra_network_dht_peers",
+        "Number of peers in the routing table of the DHT"
+    )
+    .unwrap()
+});
+
+/// Histogram of idle time of spent in event processing loop
+pub static EVENT_PROCESSING_LOOP_IDLE_DURATION_S: Lazy<DurationHistogram> = Lazy::new(|| {
+    DurationHistogram::new(
+        register_histogram!(
+            "simple_onchain_discovery_event_processing_loop_idle_duration_s",
+            "Histogram of idle time of spent in event processing loop"
+        )
+        .unwrap(),
+    )
+});
+
+/// Histogram of busy time of spent in event processing loop
+pub static EVENT_PROCESSING_LOOP_BUSY_DURATION_S: Lazy<DurationHistogram> = Lazy::new(|| {
+    DurationHistogram::new(
+        register_histogram!(
+            "simple_onchain_discovery_event_processing_loop_busy_duration_s",
+            "Histogram of busy time of spent in event processing loop"
+        )
+        .unwrap(),
+    )
+});
+
+/// Whether the local clock is synchronized, e.g., by NTP: 1 if synchronized, 0 if not, -1 if
+/// unknown
+pub static LIBRA_NTP_SYNCHRONIZED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "libra_ntp_synchronized",
+        "Whether the local clock is synchronized (1), not synchronized (0), or unknown (-1)"
+    )
+    .unwrap()
+});
+
+/// Inbound messages exceeding the rate limit of their peer, dropped or delayed
+pub static LIBRA_NETWORK_RATE_LIMITED_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_rate_limited_messages",
+        "Number of inbound messages exceeding the rate limit of their peer",
+        &["peer_id", "protocol_id", "policy"]
+    )
+    .unwrap()
+});
+
+/// Inbound connections closed to make room for more desirable peers, by network
+pub static LIBRA_NETWORK_EVICTED_CONNECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_evicted_connections",
+        "Number of inbound connections closed to make room for more desirable peers",
+        &["network_id"]
+    )
+    .unwrap()
+});
+
+/// Peers in each state of the lifecycle of their connections, see `peer_manager::lifecycle`
+pub static LIBRA_NETWORK_PEER_CONNECTION_STATES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_network_peer_connection_states",
+        "Number of peers in each state of the lifecycle of their connections",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Time the peers spent in a state of the lifecycle of their connections, by state
+pub static LIBRA_NETWORK_PEER_CONNECTION_STATE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "libra_network_peer_connection_state_duration_seconds",
+        "Time spent by the peers in a state of the lifecycle of their connections",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Events of the lifecycle of the connections of the peers which their state doesn't allow
+pub static LIBRA_NETWORK_PEER_INVALID_TRANSITIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_peer_invalid_transitions",
+        "Number of events of the lifecycle of the connections of the peers which their state \
+         doesn't allow",
+        &["state", "event"]
+    )
+    .unwrap()
+});
+
+/// Inbound connections closed before their handshake, as beyond the limits of their listener
+pub static LIBRA_NETWORK_REJECTED_INBOUND_CONNECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_rejected_inbound_connections",
+        "Number of inbound connections closed before their handshake, by exceeded limit",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+/// Handshakes without common protocols, by network and mismatch
+pub static LIBRA_NETWORK_PROTOCOL_MISMATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_protocol_mismatches",
+        "Number of handshakes with peers sharing no messaging protocol, no application protocol, \
+         or only the health checker",
+        &["network_id", "mismatch"]
+    )
+    .unwrap()
+});
+
+/// Caps the values of the peer_id label of `LIBRA_NETWORK_RATE_LIMITED_MESSAGES`
+pub static LIBRA_NETWORK_RATE_LIMITED_PEERS: Lazy<CardinalityGuard> = Lazy::new(|| {
+    CardinalityGuard::new(
+        "libra_network_rate_limited_messages/peer_id",
+        DEFAULT_MAX_LABEL_VALUES,
+    )
+});
+
 pub static LIBRA_NETWORK_DIRECT_SEND_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "libra_network_direct_send_messages",
@@ -87,6 +294,179 @@ pub static LIBRA_NETWORK_DIRECT_SEND_BYTES: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Durations of the spans of the requests, see `request_trace`
+pub static LIBRA_NETWORK_SPAN_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "libra_network_span_duration_seconds",
+        "Duration of the spans of the requests, by span",
+        &["span"]
+    )
+    .unwrap()
+});
+
+/// Bytes of the messages exchanged with the peers, as framed on the wire. The network of the peer
+/// tells its role, e.g., the peers of the Validator network are validators.
+pub static LIBRA_NETWORK_TRAFFIC_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_traffic_bytes",
+        "Bytes of the messages exchanged with the peers, by protocol, network and direction",
+        &["protocol_id", "network_id", "direction"]
+    )
+    .unwrap()
+});
+
+/// Messages exchanged with the peers, labeled like `LIBRA_NETWORK_TRAFFIC_BYTES`
+pub static LIBRA_NETWORK_TRAFFIC_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_traffic_messages",
+        "Messages exchanged with the peers, by protocol, network and direction",
+        &["protocol_id", "network_id", "direction"]
+    )
+    .unwrap()
+});
+
+/// Number of peers broken down in the metrics of `LIBRA_NETWORK_PEER_TRAFFIC`.
+pub const MAX_TRAFFIC_PEERS: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct PeerTrafficTotals {
+    sent_bytes: u64,
+    sent_messages: u64,
+    received_bytes: u64,
+    received_messages: u64,
+}
+
+impl PeerTrafficTotals {
+    fn bytes(&self) -> u64 {
+        self.sent_bytes + self.received_bytes
+    }
+
+    fn add(&mut self, other: &PeerTrafficTotals) {
+        self.sent_bytes += other.sent_bytes;
+        self.sent_messages += other.sent_messages;
+        self.received_bytes += other.received_bytes;
+        self.received_messages += other.received_messages;
+    }
+}
+
+/// Traffic with every connected peer. Only the `max_peers` peers which exchanged the most bytes
+/// are broken down when the metrics are collected, the traffic of the others being summed up
+/// under `OTHER_LABEL_VALUE`, so that the number of series stays bounded whatever the churn of the
+/// peers.
+#[derive(Clone)]
+pub struct PeerTraffic {
+    totals: Arc<Mutex<HashMap<PeerId, PeerTrafficTotals>>>,
+    max_peers: usize,
+    bytes: IntGaugeVec,
+    messages: IntGaugeVec,
+}
+
+impl PeerTraffic {
+    fn new(max_peers: usize) -> Self {
+        let labels = &["peer_id", "direction"];
+        Self {
+            totals: Arc::default(),
+            max_peers,
+            bytes: IntGaugeVec::new(
+                Opts::new(
+                    "libra_network_peer_traffic_bytes",
+                    "Bytes of the messages exchanged with the peers exchanging the most",
+                ),
+                labels,
+            )
+            .unwrap(),
+            messages: IntGaugeVec::new(
+                Opts::new(
+                    "libra_network_peer_traffic_messages",
+                    "Messages exchanged with the peers exchanging the most bytes",
+                ),
+                labels,
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn record_sent(&self, peer_id: PeerId, bytes: usize) {
+        let mut totals = self.totals.lock().unwrap();
+        let totals = totals.entry(peer_id).or_default();
+        totals.sent_bytes += bytes as u64;
+        totals.sent_messages += 1;
+    }
+
+    pub fn record_received(&self, peer_id: PeerId, bytes: usize) {
+        let mut totals = self.totals.lock().unwrap();
+        let totals = totals.entry(peer_id).or_default();
+        totals.received_bytes += bytes as u64;
+        totals.received_messages += 1;
+    }
+
+    /// Forgets the traffic of `peer_id`, once disconnected.
+    pub fn remove(&self, peer_id: &PeerId) {
+        self.totals.lock().unwrap().remove(peer_id);
+    }
+
+    fn set(&self, peer_label: &str, totals: &PeerTrafficTotals) {
+        for (direction, bytes, messages) in &[
+            (SENT_LABEL, totals.sent_bytes, totals.sent_messages),
+            (
+                RECEIVED_LABEL,
+                totals.received_bytes,
+                totals.received_messages,
+            ),
+        ] {
+            self.bytes
+                .with_label_values(&[peer_label, *direction])
+                .set(*bytes as i64);
+            self.messages
+                .with_label_values(&[peer_label, *direction])
+                .set(*messages as i64);
+        }
+    }
+}
+
+impl Collector for PeerTraffic {
+    fn desc(&self) -> Vec<&Desc> {
+        self.bytes
+            .desc()
+            .into_iter()
+            .chain(self.messages.desc())
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut peers: Vec<_> = self
+            .totals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(peer_id, totals)| (*peer_id, *totals))
+            .collect();
+        peers.sort_by_key(|(_, totals)| Reverse(totals.bytes()));
+        // The peers which fell out of the top are dropped from the series.
+        self.bytes.reset();
+        self.messages.reset();
+        let mut others = PeerTrafficTotals::default();
+        for (peer_id, totals) in peers.iter().take(self.max_peers) {
+            self.set(&peer_id.short_str(), totals);
+        }
+        for (_, totals) in peers.iter().skip(self.max_peers) {
+            others.add(totals);
+        }
+        if peers.len() > self.max_peers {
+            self.set(OTHER_LABEL_VALUE, &others);
+        }
+        let mut families = self.bytes.collect();
+        families.extend(self.messages.collect());
+        families
+    }
+}
+
+pub static LIBRA_NETWORK_PEER_TRAFFIC: Lazy<PeerTraffic> = Lazy::new(|| {
+    let collector = PeerTraffic::new(MAX_TRAFFIC_PEERS);
+    prometheus::register(Box::new(collector.clone())).unwrap();
+    collector
+});
+
 /// Counters(queued,dequeued,dropped) related to inbound network notifications for RPCs and
 /// DirectSends.
 pub static PENDING_NETWORK_NOTIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
@@ -117,6 +497,46 @@ pub static PENDING_HEALTH_CHECKER_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::ne
     ).unwrap()
 });
 
+/// Counter of pending network events to the latency prober.
+pub static PENDING_LATENCY_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pending_latency_network_events",
+        "Counters(queued,dequeued,dropped) related to pending network notifications to the latency prober",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Counter of pending network events to the DHT.
+pub static PENDING_DHT_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pending_dht_network_events",
+        "Counters(queued,dequeued,dropped) related to pending network notifications to the DHT",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Counter of pending network events to peer exchange.
+pub static PENDING_PEX_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pending_pex_network_events",
+        "Counters(queued,dequeued,dropped) related to pending network notifications to peer exchange",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Counter of pending network events to dial-back.
+pub static PENDING_DIAL_BACK_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pending_dial_back_network_events",
+        "Counters(queued,dequeued,dropped) related to pending network notifications to dial-back",
+        &["state"]
+    )
+    .unwrap()
+});
+
 /// Counter of pending network events to Discovery.
 pub static PENDING_DISCOVERY_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
@@ -137,6 +557,74 @@ pub static PENDING_PEER_MANAGER_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Age of the oldest request of applications not yet written to the wire, computed when the
+/// metrics are collected so that it keeps growing while a request is stuck.
+#[derive(Clone)]
+pub struct OldestInFlightRequestAge {
+    gauge: Gauge,
+}
+
+impl Collector for OldestInFlightRequestAge {
+    fn desc(&self) -> Vec<&Desc> {
+        self.gauge.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let age = request_trace::oldest_in_flight_age().unwrap_or_default();
+        self.gauge.set(age.as_secs_f64());
+        self.gauge.collect()
+    }
+}
+
+pub static LIBRA_NETWORK_OLDEST_IN_FLIGHT_REQUEST_AGE: Lazy<OldestInFlightRequestAge> =
+    Lazy::new(|| {
+        let collector = OldestInFlightRequestAge {
+            gauge: Gauge::new(
+                "libra_network_oldest_in_flight_request_age_seconds",
+                "Age of the oldest PeerManagerRequest not yet written to the wire",
+            )
+            .unwrap(),
+        };
+        prometheus::register(Box::new(collector.clone())).unwrap();
+        collector
+    });
+
+pub static LIBRA_NETWORK_QUOTA_USAGE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_network_quota_usage",
+        "Amount of a resource reserved by the peers of a network",
+        &["network_id", "resource"]
+    )
+    .unwrap()
+});
+
+pub static LIBRA_NETWORK_QUOTA_LIMIT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "libra_network_quota_limit",
+        "Limit of a resource for the peers of a network, if any",
+        &["network_id", "resource"]
+    )
+    .unwrap()
+});
+
+pub static LIBRA_NETWORK_QUOTA_REJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_quota_rejections",
+        "Number of reservations of a resource rejected because the quota of a network was exhausted",
+        &["network_id", "resource"]
+    )
+    .unwrap()
+});
+
+pub static LIBRA_NETWORK_ACTOR_RESTARTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "libra_network_actor_restarts",
+        "Number of restarts of a network actor after a panic while handling an event",
+        &["actor"]
+    )
+    .unwrap()
+});
+
 pub static OP_COUNTERS: Lazy<OpMetrics> = Lazy::new(|| OpMetrics::new_and_registered("network"));
 
 ///
@@ -147,6 +635,10 @@ pub static OP_COUNTERS: Lazy<OpMetrics> = Lazy::new(|| OpMetrics::new_and_regist
 pub static PENDING_CONNECTIVITY_MANAGER_REQUESTS: Lazy<IntGauge> =
     Lazy::new(|| OP_COUNTERS.gauge("pending_connectivity_manager_requests"));
 
+/// Counter of pending updates of the trusted peers to notify the connection event listeners of
+pub static PENDING_TRUSTED_PEERS_UPDATES: Lazy<IntGauge> =
+    Lazy::new(|| OP_COUNTERS.gauge("pending_trusted_peers_updates"));
+
 /// Counter of pending Connection Handler notifications to PeerManager.
 pub static PENDING_CONNECTION_HANDLER_NOTIFICATIONS: Lazy<IntGauge> =
     Lazy::new(|| OP_COUNTERS.gauge("pending_connection_handler_notifications"));
@@ -182,3 +674,52 @@ pub static PENDING_PEER_DIRECT_SEND_NOTIFICATIONS: &str = "pending_peer_direct_s
 
 /// Counter of pending connection notifications from Peer to NetworkProvider.
 pub static PENDING_PEER_NETWORK_NOTIFICATIONS: &str = "pending_peer_network_notifications";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sent_bytes(traffic: &PeerTraffic) -> HashMap<String, i64> {
+        let families = traffic.collect();
+        families[0]
+            .get_metric()
+            .iter()
+            .filter(|metric| metric.get_label()[0].get_value() == SENT_LABEL)
+            .map(|metric| {
+                (
+                    metric.get_label()[1].get_value().to_string(),
+                    metric.get_gauge().get_value() as i64,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn peer_traffic_top_peers() {
+        let traffic = PeerTraffic::new(2);
+        let peers: Vec<_> = (0..4).map(|_| PeerId::random()).collect();
+        for (i, peer_id) in peers.iter().enumerate() {
+            traffic.record_sent(*peer_id, 100 * (i + 1));
+            traffic.record_received(*peer_id, 1);
+        }
+
+        // the two peers which exchanged the most are broken down, the others summed up
+        let expected: HashMap<_, _> = vec![
+            (peers[3].short_str(), 400),
+            (peers[2].short_str(), 300),
+            (OTHER_LABEL_VALUE.to_string(), 300),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(sent_bytes(&traffic), expected);
+
+        // a disconnected peer leaves the series
+        traffic.remove(&peers[3]);
+        traffic.remove(&peers[0]);
+        let expected: HashMap<_, _> =
+            vec![(peers[2].short_str(), 300), (peers[1].short_str(), 200)]
+                .into_iter()
+                .collect();
+        assert_eq!(sent_bytes(&traffic), expected);
+    }
+}
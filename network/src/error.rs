@@ -1,130 +1,125 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::peer_manager::PeerManagerError;
+use crate::{
+    peer_manager::PeerManagerError, protocols::rpc::error::RpcError, DisconnectReason, ProtocolId,
+};
+use anyhow::anyhow;
 use futures::channel::{mpsc, oneshot};
-use libra_types::validator_verifier::VerifyError;
+use libra_types::{validator_verifier::VerifyError, PeerId};
 use std::io;
 use thiserror::Error;
 
-/// Errors propagated from the network module.
+/// Errors returned by the network API to applications.
+///
+/// `NotConnected`, `Timeout`, `QueueFull`, `MemoryBudgetExceeded` and `PeerDisconnected` are
+/// transient, and the operation may be retried (possibly with another peer); the other variants are
+/// not expected to go away by retrying.
 #[derive(Debug, Error)]
-#[error("{inner}")]
-pub struct NetworkError {
-    inner: anyhow::Error,
-}
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Error)]
-pub enum NetworkErrorKind {
-    #[error("IO error")]
-    IoError,
-
-    #[error("Lcs error")]
-    LcsError,
+pub enum NetworkError {
+    #[error("Not connected with peer {0}")]
+    NotConnected(PeerId),
 
-    #[error("Error parsing protobuf message")]
-    ProtobufParseError,
-
-    #[error("Invalid signature error")]
-    SignatureError,
+    #[error("Operation timed out")]
+    Timeout,
 
-    #[error("Error sending on mpsc channel")]
-    MpscSendError,
+    #[error("Protocol {0:?} is not supported by the peer")]
+    ProtocolNotSupported(ProtocolId),
 
-    #[error("Oneshot channel unexpectedly dropped")]
-    OneshotCanceled,
+    #[error("Request dropped by a full queue")]
+    QueueFull,
 
-    #[error("Error setting timeout")]
-    TimerError,
+    #[error("In-flight RPC memory budget exhausted")]
+    MemoryBudgetExceeded,
 
-    #[error("Operation timed out")]
-    TimedOut,
+    #[error("Peer disconnected: {reason:?}")]
+    PeerDisconnected { reason: DisconnectReason },
 
-    #[error("Unknown tokio::time Error variant")]
-    UnknownTimerError,
+    #[error("Network is shutting down")]
+    ShuttingDown,
 
-    #[error("PeerManager error")]
-    PeerManagerError,
+    #[error("Lcs error: {0}")]
+    LcsError(#[from] lcs::Error),
 
-    #[error("Parsing error")]
-    ParsingError,
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
 
-    #[error("Peer not connected")]
-    NotConnected,
+    #[error("Error: {0:?}")]
+    Error(#[from] anyhow::Error),
 }
 
-impl From<NetworkErrorKind> for NetworkError {
-    fn from(kind: NetworkErrorKind) -> NetworkError {
-        NetworkError {
-            inner: anyhow::Error::new(kind),
+impl NetworkError {
+    /// Whether the failed operation may succeed if retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            NetworkError::NotConnected(_)
+            | NetworkError::Timeout
+            | NetworkError::QueueFull
+            | NetworkError::MemoryBudgetExceeded
+            | NetworkError::PeerDisconnected { .. } => true,
+            _ => false,
         }
     }
 }
 
-impl From<anyhow::Error> for NetworkError {
-    fn from(inner: anyhow::Error) -> NetworkError {
-        NetworkError { inner }
-    }
-}
-
-impl From<io::Error> for NetworkError {
-    fn from(err: io::Error) -> NetworkError {
-        anyhow::Error::new(err)
-            .context(NetworkErrorKind::IoError)
-            .into()
-    }
-}
-
-impl From<lcs::Error> for NetworkError {
-    fn from(err: lcs::Error) -> NetworkError {
-        anyhow::Error::new(err)
-            .context(NetworkErrorKind::LcsError)
-            .into()
-    }
-}
-
 impl From<VerifyError> for NetworkError {
     fn from(err: VerifyError) -> NetworkError {
-        anyhow::Error::new(err)
-            .context(NetworkErrorKind::SignatureError)
-            .into()
+        NetworkError::Error(anyhow!(err))
     }
 }
 
 impl From<mpsc::SendError> for NetworkError {
     fn from(err: mpsc::SendError) -> NetworkError {
-        anyhow::Error::new(err)
-            .context(NetworkErrorKind::MpscSendError)
-            .into()
+        if err.is_full() {
+            NetworkError::QueueFull
+        } else {
+            NetworkError::ShuttingDown
+        }
     }
 }
 
 impl From<oneshot::Canceled> for NetworkError {
-    fn from(err: oneshot::Canceled) -> NetworkError {
-        anyhow::Error::new(err)
-            .context(NetworkErrorKind::OneshotCanceled)
-            .into()
+    fn from(_err: oneshot::Canceled) -> NetworkError {
+        NetworkError::ShuttingDown
     }
 }
 
 impl From<PeerManagerError> for NetworkError {
     fn from(err: PeerManagerError) -> NetworkError {
         match err {
-            PeerManagerError::IoError(_) => anyhow::Error::new(err)
-                .context(NetworkErrorKind::IoError)
-                .into(),
-            PeerManagerError::NotConnected(_) => anyhow::Error::new(err)
-                .context(NetworkErrorKind::NotConnected)
-                .into(),
-            err => anyhow::Error::new(err)
-                .context(NetworkErrorKind::PeerManagerError)
-                .into(),
+            PeerManagerError::NotConnected(peer_id) => NetworkError::NotConnected(peer_id),
+            PeerManagerError::IoError(err) => NetworkError::IoError(err),
+            PeerManagerError::LcsError(err) => NetworkError::LcsError(err),
+            PeerManagerError::MpscSendError(err) => err.into(),
+            PeerManagerError::ShuttingDownPeer | PeerManagerError::OneshotSenderDropped => {
+                NetworkError::ShuttingDown
+            }
+            err => NetworkError::Error(anyhow!(err)),
+        }
+    }
+}
+
+impl From<RpcError> for NetworkError {
+    fn from(err: RpcError) -> NetworkError {
+        match err {
+            RpcError::NotConnected(peer_id) => NetworkError::NotConnected(peer_id),
+            RpcError::TimedOut => NetworkError::Timeout,
+            RpcError::ProtocolNotSupported(protocol) => {
+                NetworkError::ProtocolNotSupported(protocol)
+            }
+            RpcError::TooManyPending(_) => NetworkError::QueueFull,
+            RpcError::MemoryBudgetExceeded(_) => NetworkError::MemoryBudgetExceeded,
+            RpcError::PeerDisconnected(reason) => NetworkError::PeerDisconnected { reason },
+            RpcError::IoError(err) => NetworkError::IoError(err),
+            RpcError::LcsError(err) => NetworkError::LcsError(err),
+            RpcError::MpscSendError(err) => err.into(),
+            err => NetworkError::Error(anyhow!(err)),
         }
     }
 }
 
 impl From<tokio::time::Elapsed> for NetworkError {
     fn from(_err: tokio::time::Elapsed) -> NetworkError {
-        NetworkErrorKind::TimedOut.into()
+        NetworkError::Timeout
     }
 }
@@ -0,0 +1,162 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Eviction of the inbound connections of a network when a more desirable peer connects while the
+//! inbound connection quota of the network is exhausted, see [`quota`](crate::quota).
+//!
+//! The peer manager asks its [`EvictionPolicy`] which active inbound connection of the network,
+//! if any, to close to make room for the new connection, and closes the new connection otherwise.
+//! The [`DefaultEvictionPolicy`] prefers the trusted peers of the network, then the peers with
+//! the highest [`PeerScores`], and among equally desirable peers evicts the most recently
+//! connected one, so that long-lived connections are kept.
+
+use crate::common::NetworkPublicKeys;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+/// An active inbound connection which may be evicted.
+#[derive(Clone, Debug)]
+pub struct EvictionCandidate {
+    pub peer_id: PeerId,
+    pub addr: NetworkAddress,
+    /// When the connection was established.
+    pub connected_at: Instant,
+}
+
+/// Chooses the connections evicted to make room for more desirable peers.
+pub trait EvictionPolicy: fmt::Debug + Send + Sync {
+    /// Returns the peer among `candidates`, the active inbound connections of the network, whose
+    /// connection is closed to accept a new connection from `peer_id`, or `None` to close the new
+    /// connection instead.
+    fn select_victim(&self, peer_id: &PeerId, candidates: &[EvictionCandidate]) -> Option<PeerId>;
+}
+
+/// Scores of the peers, e.g., reported by the applications according to the usefulness of the
+/// peers, shared with the eviction policy. Peers without a score have a score of 0.
+#[derive(Clone, Debug, Default)]
+pub struct PeerScores(Arc<RwLock<HashMap<PeerId, i64>>>);
+
+impl PeerScores {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `delta` to the score of `peer_id`.
+    pub fn adjust(&self, peer_id: PeerId, delta: i64) {
+        let mut scores = self.0.write().unwrap();
+        let score = scores.entry(peer_id).or_insert(0);
+        *score = score.saturating_add(delta);
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> i64 {
+        self.0.read().unwrap().get(peer_id).copied().unwrap_or(0)
+    }
+
+    /// Forgets the score of `peer_id`.
+    pub fn remove(&self, peer_id: &PeerId) {
+        self.0.write().unwrap().remove(peer_id);
+    }
+}
+
+/// Evicts the least desirable connection, by trust, then score, then recency, if the new peer is
+/// strictly more desirable.
+#[derive(Debug)]
+pub struct DefaultEvictionPolicy {
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    peer_scores: PeerScores,
+}
+
+impl DefaultEvictionPolicy {
+    pub fn new(
+        trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+        peer_scores: PeerScores,
+    ) -> Self {
+        Self {
+            trusted_peers,
+            peer_scores,
+        }
+    }
+
+    fn desirability(&self, peer_id: &PeerId) -> (bool, i64) {
+        (
+            self.trusted_peers.read().unwrap().contains_key(peer_id),
+            self.peer_scores.get(peer_id),
+        )
+    }
+}
+
+impl EvictionPolicy for DefaultEvictionPolicy {
+    fn select_victim(&self, peer_id: &PeerId, candidates: &[EvictionCandidate]) -> Option<PeerId> {
+        let victim = candidates.iter().min_by_key(|candidate| {
+            (
+                self.desirability(&candidate.peer_id),
+                Reverse(candidate.connected_at),
+            )
+        })?;
+        if self.desirability(peer_id) > self.desirability(&victim.peer_id) {
+            Some(victim.peer_id)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_crypto::{x25519, Uniform};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::Duration;
+
+    #[test]
+    fn default_policy() {
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let (trusted, old, recent, new) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        let trusted_peers = vec![(
+            trusted,
+            NetworkPublicKeys {
+                identity_public_key: x25519::PrivateKey::generate(&mut rng).public_key(),
+            },
+        )]
+        .into_iter()
+        .collect();
+        let peer_scores = PeerScores::new();
+        let policy =
+            DefaultEvictionPolicy::new(Arc::new(RwLock::new(trusted_peers)), peer_scores.clone());
+
+        let now = Instant::now();
+        let candidate = |peer_id, age| EvictionCandidate {
+            peer_id,
+            addr: "/memory/1234".parse().unwrap(),
+            connected_at: now - Duration::from_secs(age),
+        };
+        let candidates = vec![candidate(old, 60), candidate(recent, 1)];
+
+        // peers as desirable as the connected ones are rejected
+        assert_eq!(policy.select_victim(&new, &candidates), None);
+        // the most recent of the least desirable connections is evicted
+        assert_eq!(policy.select_victim(&trusted, &candidates), Some(recent));
+        peer_scores.adjust(recent, 5);
+        assert_eq!(policy.select_victim(&trusted, &candidates), Some(old));
+        peer_scores.adjust(new, 1);
+        assert_eq!(policy.select_victim(&new, &candidates), Some(old));
+
+        // trusted peers are never evicted for untrusted ones
+        peer_scores.adjust(new, 100);
+        assert_eq!(policy.select_victim(&new, &[candidate(trusted, 1)]), None);
+        peer_scores.remove(&new);
+        assert_eq!(peer_scores.get(&new), 0);
+    }
+}
@@ -14,11 +14,14 @@
 use crate::{
     counters,
     peer::{Peer, PeerHandle, PeerNotification},
-    peer_manager::TransportNotification,
+    peer_manager::{request_trace::RequestTrace, TransportNotification},
+    priority::ProtocolPriorities,
     protocols::{
         direct_send::{DirectSend, DirectSendNotification, DirectSendRequest, Message},
         rpc::{InboundRpcRequest, OutboundRpcRequest, Rpc, RpcNotification},
     },
+    quota::ResourceBudget,
+    rate_limit::InboundRateLimits,
     transport::Connection,
     validator_network, ProtocolId,
 };
@@ -37,9 +40,9 @@ use tokio::runtime::Handle;
 #[derive(Debug)]
 pub enum NetworkRequest {
     /// Send an RPC request to peer.
-    SendRpc(OutboundRpcRequest),
+    SendRpc(OutboundRpcRequest, RequestTrace),
     /// Fire-and-forget style message send to peer.
-    SendMessage(Message),
+    SendMessage(Message, RequestTrace),
 }
 
 /// Notifications that [`NetworkProvider`] sends to consumers of its API. The
@@ -69,6 +72,10 @@ where
         max_concurrent_reqs: usize,
         max_concurrent_notifs: usize,
         channel_size: usize,
+        inbound_message_budget: ResourceBudget,
+        inflight_rpc_budget: ResourceBudget,
+        inbound_rate_limits: InboundRateLimits,
+        protocol_priorities: ProtocolPriorities,
     ) -> (
         libra_channel::Sender<ProtocolId, NetworkRequest>,
         libra_channel::Receiver<ProtocolId, NetworkNotification>,
@@ -110,6 +117,8 @@ where
             peer_notifs_tx,
             peer_rpc_notifs_tx,
             peer_ds_notifs_tx,
+            inbound_message_budget,
+            inbound_rate_limits,
         );
         executor.spawn(peer.start());
 
@@ -132,6 +141,7 @@ where
             Duration::from_millis(validator_network::network_builder::INBOUND_RPC_TIMEOUT_MS),
             validator_network::network_builder::MAX_CONCURRENT_OUTBOUND_RPCS,
             validator_network::network_builder::MAX_CONCURRENT_INBOUND_RPCS,
+            inflight_rpc_budget,
         );
         executor.spawn(rpc.start());
 
@@ -159,9 +169,11 @@ where
         executor.spawn(ds.start());
 
         // TODO: Add label for peer.
-        let (requests_tx, requests_rx) = libra_channel::new(
+        // The requests of the protocols of higher priority preempt the others.
+        let (requests_tx, requests_rx) = libra_channel::new_with_priorities(
             QueueStyle::FIFO,
             NonZeroUsize::new(channel_size).expect("libra_channel cannot be of size 0"),
+            move |protocol: &ProtocolId| protocol_priorities.get(*protocol) as u8,
             Some(&counters::PENDING_NETWORK_REQUESTS),
         );
         // TODO: Add label for peer.
@@ -224,12 +236,12 @@ where
     async fn handle_network_request(
         peer_id: PeerId,
         req: NetworkRequest,
-        mut rpc_reqs_tx: channel::Sender<OutboundRpcRequest>,
+        mut rpc_reqs_tx: channel::Sender<(OutboundRpcRequest, RequestTrace)>,
         mut ds_reqs_tx: channel::Sender<DirectSendRequest>,
     ) {
         match req {
-            NetworkRequest::SendRpc(req) => {
-                if let Err(e) = rpc_reqs_tx.send(req).await {
+            NetworkRequest::SendRpc(req, trace) => {
+                if let Err(e) = rpc_reqs_tx.send((req, trace)).await {
                     error!(
                         "Failed to send RPC to peer: {}. Error: {:?}",
                         peer_id.short_str(),
@@ -237,14 +249,17 @@ where
                     );
                 }
             }
-            NetworkRequest::SendMessage(msg) => {
+            NetworkRequest::SendMessage(msg, trace) => {
                 counters::LIBRA_NETWORK_DIRECT_SEND_MESSAGES
                     .with_label_values(&["sent"])
                     .inc();
                 counters::LIBRA_NETWORK_DIRECT_SEND_BYTES
                     .with_label_values(&["sent"])
                     .observe(msg.mdata.len() as f64);
-                if let Err(e) = ds_reqs_tx.send(DirectSendRequest::SendMessage(msg)).await {
+                if let Err(e) = ds_reqs_tx
+                    .send(DirectSendRequest::SendMessage(msg, trace))
+                    .await
+                {
                     error!(
                         "Failed to send DirectSend to peer: {}. Error: {:?}",
                         peer_id.short_str(),
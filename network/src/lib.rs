@@ -10,12 +10,31 @@
 pub use common::NetworkPublicKeys;
 pub use interface::NetworkProvider;
 
+pub mod address_book;
+pub mod ban_list;
+pub mod bandwidth;
+pub mod catch_panic;
 pub mod common;
+pub mod connected_peers;
+pub mod connection_limits;
 pub mod connectivity_manager;
 pub mod error;
+pub mod eviction;
 pub mod interface;
+pub mod mdns;
+pub mod nat;
+pub mod network_events;
+pub mod network_info;
+pub mod observed_addrs;
+pub mod onchain_discovery;
 pub mod peer_manager;
+pub mod preflight;
+pub mod priority;
 pub mod protocols;
+pub mod quota;
+pub mod rate_limit;
+pub mod time_sync;
+pub mod trusted_peers;
 pub mod validator_network;
 
 pub mod counters;
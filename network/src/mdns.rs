@@ -0,0 +1,374 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discovery of the peers on the local network with multicast DNS (RFC 6762), so that the nodes of
+//! a local test network, or of a small LAN deployment, find each other without any seed peer.
+//!
+//! The [`MdnsDiscovery`] actor periodically announces the advertised addresses of the node as an
+//! unsolicited mDNS response, with a TXT record named `<peer id>._libranet._udp.local` listing the
+//! network id and the addresses. It listens for the announcements of the other nodes of the same
+//! network, and sends their addresses to the [`ConnectivityManager`] as
+//! [`DiscoverySource::Mdns`]. Only the announcements are implemented, not the mDNS queries: a node
+//! learns about its peers at the next announcement interval.
+//!
+//! The announcements are neither authenticated nor encrypted, so anyone on the local network can
+//! announce addresses: the peers are still authenticated by the Noise handshakes when dialed.
+//!
+//! [`ConnectivityManager`]: crate::connectivity_manager::ConnectivityManager
+
+use crate::connectivity_manager::{ConnectivityRequest, DiscoverySource};
+use futures::{
+    sink::SinkExt,
+    stream::{self, FusedStream, Stream, StreamExt},
+};
+use libra_config::network_id::NetworkId;
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    str::FromStr,
+};
+use tokio::net::UdpSocket;
+
+/// Multicast group of mDNS.
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+/// Port of mDNS.
+pub const MDNS_PORT: u16 = 5353;
+/// Suffix of the names of the records announcing the nodes.
+const SERVICE_NAME: [&str; 3] = ["_libranet", "_udp", "local"];
+/// Type of the DNS TXT records.
+const TYPE_TXT: u16 = 16;
+/// Class IN, with the cache-flush bit set, as the records are unique to the announcing node.
+const CLASS_IN_FLUSH: u16 = 0x8001;
+/// Time-to-live of the announcements, in seconds.
+const TTL_SECS: u32 = 120;
+/// Maximum size of an mDNS message.
+const MAX_MESSAGE_SIZE: usize = 9000;
+
+/// The addresses of a node, as announced over mDNS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Announcement {
+    pub peer_id: PeerId,
+    pub network_id: String,
+    pub addrs: Vec<NetworkAddress>,
+}
+
+impl Announcement {
+    /// Encodes the announcement as an unsolicited mDNS response, with a single TXT record.
+    /// Returns `None` if an entry of the TXT record exceeds 255 bytes.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let mut message = Vec::new();
+        // header: id, flags (response, authoritative), no question, 1 answer, no other record
+        for field in &[0u16, 0x8400, 0, 1, 0, 0] {
+            message.extend_from_slice(&field.to_be_bytes());
+        }
+        let peer_id = String::from(&self.peer_id);
+        for label in std::iter::once(peer_id.as_str()).chain(SERVICE_NAME.iter().copied()) {
+            message.push(label.len() as u8);
+            message.extend_from_slice(label.as_bytes());
+        }
+        message.push(0);
+        message.extend_from_slice(&TYPE_TXT.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN_FLUSH.to_be_bytes());
+        message.extend_from_slice(&TTL_SECS.to_be_bytes());
+
+        let mut rdata = Vec::new();
+        let entries = std::iter::once(format!("net={}", self.network_id))
+            .chain(self.addrs.iter().map(|addr| format!("addr={}", addr)));
+        for entry in entries {
+            let len = u8::try_from(entry.len()).ok()?;
+            rdata.push(len);
+            rdata.extend_from_slice(entry.as_bytes());
+        }
+        message.extend_from_slice(&u16::try_from(rdata.len()).ok()?.to_be_bytes());
+        message.extend_from_slice(&rdata);
+        Some(message)
+    }
+
+    /// Decodes the first announcement of a node in an mDNS message, if any. The other records,
+    /// e.g., of other services, are skipped.
+    pub fn decode(message: &[u8]) -> Option<Self> {
+        let mut reader = Reader { message, pos: 12 };
+        let field = |idx: usize| -> Option<u16> {
+            message
+                .get(idx..idx + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let (questions, answers) = (field(4)?, field(6)?);
+        for _ in 0..questions {
+            reader.name()?;
+            reader.take(4)?;
+        }
+        for _ in 0..answers {
+            let name = reader.name()?;
+            let rtype = reader.u16()?;
+            let _class = reader.u16()?;
+            reader.take(4)?;
+            let rdlen = reader.u16()? as usize;
+            let rdata = reader.take(rdlen)?;
+            if rtype != TYPE_TXT
+                || name.len() != SERVICE_NAME.len() + 1
+                || name[1..]
+                    .iter()
+                    .zip(SERVICE_NAME.iter())
+                    .any(|(a, b)| a != b)
+            {
+                continue;
+            }
+            let peer_id = match PeerId::from_str(&name[0]) {
+                Ok(peer_id) => peer_id,
+                Err(_) => continue,
+            };
+            return Some(Self::from_txt(peer_id, rdata));
+        }
+        None
+    }
+
+    fn from_txt(peer_id: PeerId, rdata: &[u8]) -> Self {
+        let mut announcement = Self {
+            peer_id,
+            network_id: String::new(),
+            addrs: Vec::new(),
+        };
+        let mut reader = Reader {
+            message: rdata,
+            pos: 0,
+        };
+        while let Some(len) = reader.take(1) {
+            let entry = match reader.take(len[0] as usize) {
+                Some(entry) => String::from_utf8_lossy(entry),
+                None => break,
+            };
+            if entry.starts_with("net=") {
+                announcement.network_id = entry["net=".len()..].to_string();
+            } else if entry.starts_with("addr=") {
+                // Addresses unknown to this version are skipped.
+                if let Ok(addr) = NetworkAddress::from_str(&entry["addr=".len()..]) {
+                    announcement.addrs.push(addr);
+                }
+            }
+        }
+        announcement
+    }
+}
+
+/// Reads the fields of a DNS message.
+struct Reader<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.message.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a name, following its compression pointers, which may only point backwards.
+    fn name(&mut self) -> Option<Vec<String>> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut end = None;
+        loop {
+            let len = *self.message.get(pos)? as usize;
+            if len & 0xc0 == 0xc0 {
+                let pointer = (len & 0x3f) << 8 | *self.message.get(pos + 1)? as usize;
+                if pointer >= pos {
+                    return None;
+                }
+                end.get_or_insert(pos + 2);
+                pos = pointer;
+            } else if len == 0 {
+                self.pos = end.unwrap_or(pos + 1);
+                return Some(labels);
+            } else {
+                let label = self.message.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+        }
+    }
+}
+
+/// Binds a socket to the mDNS port, shared with the other nodes running on the host, and joins
+/// the mDNS multicast group.
+pub fn bind_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    // The other nodes of a local test network run on this host.
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into_udp_socket())
+}
+
+/// The actor announcing the addresses of this node, and discovering its peers, over mDNS.
+pub struct MdnsDiscovery<TTicker> {
+    announcement: Announcement,
+    socket: UdpSocket,
+    /// Ticker to trigger the announcements.
+    ticker: TTicker,
+    conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    /// Addresses of the peers discovered.
+    discovered: HashMap<PeerId, Vec<NetworkAddress>>,
+}
+
+impl<TTicker> MdnsDiscovery<TTicker>
+where
+    TTicker: Stream + FusedStream + Unpin,
+{
+    pub fn new(
+        peer_id: PeerId,
+        network_id: &NetworkId,
+        addrs: Vec<NetworkAddress>,
+        socket: UdpSocket,
+        ticker: TTicker,
+        conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    ) -> Self {
+        Self {
+            announcement: Announcement {
+                peer_id,
+                network_id: network_id.as_str().to_string(),
+                addrs,
+            },
+            socket,
+            ticker,
+            conn_mgr_reqs_tx,
+            discovered: HashMap::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        let message = match self.announcement.encode() {
+            Some(message) => message,
+            None => {
+                error!("The advertised addresses are too long to be announced over mDNS");
+                return;
+            }
+        };
+        let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+        let (recv_half, mut send_half) = self.socket.split();
+        let mut received = stream::unfold(recv_half, |mut recv_half| async move {
+            let mut buf = vec![0; MAX_MESSAGE_SIZE];
+            let res = recv_half.recv_from(&mut buf).await.map(|(len, _)| {
+                buf.truncate(len);
+                buf
+            });
+            Some((res, recv_half))
+        })
+        .boxed()
+        .fuse();
+        loop {
+            futures::select! {
+                _ = self.ticker.select_next_some() => {
+                    if let Err(err) = send_half.send_to(&message, &group).await {
+                        warn!("Failed to announce the addresses over mDNS: {}", err);
+                    }
+                }
+                res = received.select_next_some() => {
+                    match res {
+                        Ok(buf) => {
+                            if let Some(announcement) = Announcement::decode(&buf) {
+                                Self::handle_announcement(
+                                    &self.announcement,
+                                    &mut self.discovered,
+                                    &mut self.conn_mgr_reqs_tx,
+                                    announcement,
+                                ).await;
+                            }
+                        }
+                        Err(err) => warn!("Failed to receive an mDNS message: {}", err),
+                    }
+                }
+                complete => break,
+            }
+        }
+        crit!("mDNS discovery actor terminated");
+    }
+
+    async fn handle_announcement(
+        own: &Announcement,
+        discovered: &mut HashMap<PeerId, Vec<NetworkAddress>>,
+        conn_mgr_reqs_tx: &mut channel::Sender<ConnectivityRequest>,
+        announcement: Announcement,
+    ) {
+        if announcement.peer_id == own.peer_id
+            || announcement.network_id != own.network_id
+            || discovered.get(&announcement.peer_id) == Some(&announcement.addrs)
+        {
+            return;
+        }
+        info!(
+            "Discovered peer {} over mDNS at {:?}",
+            announcement.peer_id.short_str(),
+            announcement.addrs
+        );
+        discovered.insert(announcement.peer_id, announcement.addrs);
+        let update =
+            ConnectivityRequest::UpdateAddresses(DiscoverySource::Mdns, discovered.clone());
+        if let Err(err) = conn_mgr_reqs_tx.send(update).await {
+            warn!("Failed to send the addresses discovered over mDNS: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn announcement() -> Announcement {
+        Announcement {
+            peer_id: PeerId::random(),
+            network_id: "vfn".to_string(),
+            addrs: vec![
+                NetworkAddress::from_str("/ip4/192.168.1.2/tcp/6180").unwrap(),
+                NetworkAddress::from_str("/dns4/node.local/tcp/6180").unwrap(),
+            ],
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let announcement = announcement();
+        let message = announcement.encode().unwrap();
+        assert_eq!(Announcement::decode(&message), Some(announcement));
+        // truncated messages fail to decode, without panicking
+        for len in 0..message.len() {
+            assert_eq!(Announcement::decode(&message[..len]), None);
+        }
+    }
+
+    #[test]
+    fn decode_compressed_name() {
+        let announcement = announcement();
+        let message = announcement.encode().unwrap();
+        // An A record for `_libranet._udp.local` precedes the TXT record, whose name then points
+        // to the service name of the A record.
+        let mut compressed = message[..12].to_vec();
+        compressed[7] = 2;
+        let service = &message[12 + 33..];
+        let service_len = SERVICE_NAME
+            .iter()
+            .map(|label| label.len() + 1)
+            .sum::<usize>()
+            + 1;
+        compressed.extend_from_slice(&service[..service_len]);
+        compressed.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 2]);
+        compressed.extend_from_slice(&message[12..12 + 33]);
+        compressed.extend_from_slice(&[0xc0, 12]);
+        compressed.extend_from_slice(&service[service_len..]);
+        assert_eq!(Announcement::decode(&compressed), Some(announcement));
+    }
+}
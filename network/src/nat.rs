@@ -0,0 +1,415 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Port mapping on the NAT gateway with NAT-PMP (RFC 6886), so that the full nodes run at home,
+//! behind the NAT of a router, are dialable without configuring the router manually.
+//!
+//! The [`PortMapper`] actor asks the gateway for its external IPv4 address and for a mapping of the
+//! TCP listen port of the node, and renews the lease at half of its lifetime. It sends the
+//! resulting address, `/ip4/<external address>/tcp/<external port>` followed by the Noise and
+//! handshake protocols of the node, to the discovery protocol, which advertises it in addition to
+//! the other addresses of the node. It sends `None` once the mapping is lost, e.g., when the
+//! gateway stops answering, and retries on the next renewal.
+//!
+//! The mapping isn't deleted when the node stops: it expires at the end of its lease.
+//!
+//! UPnP IGD isn't supported, as it requires SSDP discovery and SOAP over HTTP. Most home routers
+//! support NAT-PMP, or PCP (RFC 6887), whose servers usually answer the NAT-PMP requests too.
+
+use futures::channel::mpsc;
+use libra_crypto::x25519;
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use std::{
+    fs, io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    net::UdpSocket,
+    time::{delay_for, timeout},
+};
+
+/// Port of the NAT-PMP server of the gateway.
+pub const NAT_PMP_PORT: u16 = 5351;
+/// Version of NAT-PMP.
+const VERSION: u8 = 0;
+/// Opcode of the requests of the external address.
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+/// Opcode of the requests of TCP mappings.
+const OP_MAP_TCP: u8 = 2;
+/// Bit set in the opcode of the responses.
+const OP_RESPONSE: u8 = 128;
+/// Lifetime of the mappings requested, in seconds, as recommended by RFC 6886.
+pub const MAPPING_LIFETIME_SECS: u32 = 7200;
+/// Delay before the first retransmission of a request, doubled for every retransmission.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Number of transmissions of a request before giving up.
+const MAX_TRANSMISSIONS: u32 = 5;
+/// Delay before requesting the mapping again after a failure.
+const FAILURE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Size of the largest NAT-PMP response.
+const MAX_RESPONSE_SIZE: usize = 16;
+/// Flag of the routes through a gateway in `/proc/net/route`.
+const RTF_GATEWAY: u32 = 0x2;
+
+#[derive(Debug, Error)]
+pub enum NatPmpError {
+    #[error("NAT-PMP I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("The NAT gateway didn't answer")]
+    Timeout,
+
+    #[error("The NAT gateway refused the request with result code {0}")]
+    Refused(u16),
+}
+
+/// A NAT-PMP response, successful or not.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Response {
+    ExternalAddress {
+        result: u16,
+        external_ip: Ipv4Addr,
+    },
+    Mapping {
+        result: u16,
+        internal_port: u16,
+        external_port: u16,
+        lifetime_secs: u32,
+    },
+}
+
+impl Response {
+    /// Decodes a response to the request of `opcode`. Returns `None` if the message is malformed
+    /// or answers another request.
+    pub fn decode(message: &[u8], opcode: u8) -> Option<Self> {
+        let u16_at = |pos: usize| {
+            Some(u16::from_be_bytes([
+                *message.get(pos)?,
+                *message.get(pos + 1)?,
+            ]))
+        };
+        let u32_at = |pos: usize| {
+            Some(u32::from_be_bytes([
+                *message.get(pos)?,
+                *message.get(pos + 1)?,
+                *message.get(pos + 2)?,
+                *message.get(pos + 3)?,
+            ]))
+        };
+        if *message.get(0)? != VERSION || *message.get(1)? != OP_RESPONSE | opcode {
+            return None;
+        }
+        let result = u16_at(2)?;
+        // The epoch of the gateway, at 4..8, isn't used: the mapping is renewed anyway.
+        match opcode {
+            OP_EXTERNAL_ADDRESS => Some(Response::ExternalAddress {
+                result,
+                external_ip: Ipv4Addr::from(u32_at(8)?),
+            }),
+            OP_MAP_TCP => Some(Response::Mapping {
+                result,
+                internal_port: u16_at(8)?,
+                external_port: u16_at(10)?,
+                lifetime_secs: u32_at(12)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes the request of the external address of the gateway.
+pub fn encode_external_address_request() -> [u8; 2] {
+    [VERSION, OP_EXTERNAL_ADDRESS]
+}
+
+/// Encodes the request of a mapping of the TCP `internal_port`, preferably on the same external
+/// port. A lifetime of 0 deletes the mapping.
+pub fn encode_mapping_request(internal_port: u16, lifetime_secs: u32) -> [u8; 12] {
+    let mut message = [0; 12];
+    message[0] = VERSION;
+    message[1] = OP_MAP_TCP;
+    // 2..4 are reserved
+    message[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    message[6..8].copy_from_slice(&internal_port.to_be_bytes());
+    message[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+    message
+}
+
+/// Parses the IPv4 gateway of the default route from a routing table in the format of
+/// `/proc/net/route`.
+pub fn parse_default_gateway(route_table: &str) -> Option<Ipv4Addr> {
+    route_table.lines().skip(1).find_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let destination = u32::from_str_radix(fields.get(1)?, 16).ok()?;
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+        if destination == 0 && flags & RTF_GATEWAY != 0 {
+            // The addresses are printed in the byte order of the host.
+            Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+        } else {
+            None
+        }
+    })
+}
+
+/// The IPv4 gateway of the default route of the host, if known. Only available on Linux.
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    parse_default_gateway(&fs::read_to_string("/proc/net/route").ok()?)
+}
+
+/// The actor mapping the listen port of this node on the NAT gateway, and sending the resulting
+/// external address to discovery.
+pub struct PortMapper {
+    gateway: SocketAddrV4,
+    pubkey: x25519::PublicKey,
+    handshake_version: u8,
+    external_addrs_tx: mpsc::UnboundedSender<Option<NetworkAddress>>,
+}
+
+impl PortMapper {
+    pub fn new(
+        gateway: SocketAddrV4,
+        pubkey: x25519::PublicKey,
+        handshake_version: u8,
+        external_addrs_tx: mpsc::UnboundedSender<Option<NetworkAddress>>,
+    ) -> Self {
+        Self {
+            gateway,
+            pubkey,
+            handshake_version,
+            external_addrs_tx,
+        }
+    }
+
+    /// Maps the TCP `internal_port` until the actor is dropped.
+    pub async fn start(self, internal_port: u16) {
+        let mut socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                error!("Failed to bind the NAT-PMP socket: {}", err);
+                return;
+            }
+        };
+        let mut external_addr = None;
+        loop {
+            let delay = match self.map_port(&mut socket, internal_port).await {
+                Ok((addr, lifetime_secs)) => {
+                    if external_addr.as_ref() != Some(&addr) {
+                        info!("Mapped the listen port on the NAT gateway to {}", addr);
+                        external_addr = Some(addr.clone());
+                        if self.external_addrs_tx.unbounded_send(Some(addr)).is_err() {
+                            break;
+                        }
+                    }
+                    Duration::from_secs(u64::from(lifetime_secs / 2).max(1))
+                }
+                Err(err) => {
+                    warn!("Failed to map the listen port on the NAT gateway: {}", err);
+                    if external_addr.take().is_some()
+                        && self.external_addrs_tx.unbounded_send(None).is_err()
+                    {
+                        break;
+                    }
+                    FAILURE_RETRY_INTERVAL
+                }
+            };
+            delay_for(delay).await;
+        }
+        crit!("NAT port mapping actor terminated");
+    }
+
+    /// Requests the external address of the gateway and the mapping of `internal_port`. Returns
+    /// the external address of the node and the lifetime of the mapping.
+    async fn map_port(
+        &self,
+        socket: &mut UdpSocket,
+        internal_port: u16,
+    ) -> Result<(NetworkAddress, u32), NatPmpError> {
+        let external_ip = match self
+            .request(
+                socket,
+                &encode_external_address_request(),
+                OP_EXTERNAL_ADDRESS,
+            )
+            .await?
+        {
+            Response::ExternalAddress {
+                result: 0,
+                external_ip,
+            } => external_ip,
+            Response::ExternalAddress { result, .. } | Response::Mapping { result, .. } => {
+                return Err(NatPmpError::Refused(result))
+            }
+        };
+        let request = encode_mapping_request(internal_port, MAPPING_LIFETIME_SECS);
+        let (external_port, lifetime_secs) =
+            match self.request(socket, &request, OP_MAP_TCP).await? {
+                Response::Mapping {
+                    result: 0,
+                    external_port,
+                    lifetime_secs,
+                    ..
+                } => (external_port, lifetime_secs),
+                Response::ExternalAddress { result, .. } | Response::Mapping { result, .. } => {
+                    return Err(NatPmpError::Refused(result))
+                }
+            };
+        let addr = NetworkAddress::from(SocketAddr::from((external_ip, external_port)))
+            .append_prod_protos(self.pubkey, self.handshake_version);
+        Ok((addr, lifetime_secs))
+    }
+
+    /// Sends `request` to the gateway until it answers, doubling the delay between the
+    /// retransmissions.
+    async fn request(
+        &self,
+        socket: &mut UdpSocket,
+        request: &[u8],
+        opcode: u8,
+    ) -> Result<Response, NatPmpError> {
+        let mut delay = INITIAL_RETRY_DELAY;
+        for _ in 0..MAX_TRANSMISSIONS {
+            socket
+                .send_to(request, &SocketAddr::V4(self.gateway))
+                .await?;
+            let mut buf = [0; MAX_RESPONSE_SIZE];
+            let received = timeout(delay, async {
+                loop {
+                    let (len, from) = socket.recv_from(&mut buf).await?;
+                    // Only the responses of the gateway to this request are accepted.
+                    if from == SocketAddr::V4(self.gateway) {
+                        if let Some(response) = Response::decode(&buf[..len], opcode) {
+                            return Ok::<_, io::Error>(response);
+                        }
+                    }
+                }
+            })
+            .await;
+            match received {
+                Ok(response) => return Ok(response?),
+                Err(_) => delay *= 2,
+            }
+        }
+        Err(NatPmpError::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::stream::StreamExt;
+    use libra_config::config::HANDSHAKE_VERSION;
+    use libra_crypto::Uniform;
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn encode_requests() {
+        assert_eq!(encode_external_address_request(), [0, 0]);
+        assert_eq!(
+            encode_mapping_request(6180, 7200),
+            [0, 2, 0, 0, 0x18, 0x24, 0x18, 0x24, 0, 0, 0x1c, 0x20]
+        );
+    }
+
+    #[test]
+    fn decode_responses() {
+        let external_address = [0, 128, 0, 0, 0, 0, 0, 9, 203, 0, 113, 7];
+        assert_eq!(
+            Response::decode(&external_address, OP_EXTERNAL_ADDRESS),
+            Some(Response::ExternalAddress {
+                result: 0,
+                external_ip: Ipv4Addr::new(203, 0, 113, 7),
+            })
+        );
+        let mapping = [
+            0, 130, 0, 0, 0, 0, 0, 9, 0x18, 0x24, 0x18, 0x25, 0, 0, 0x0e, 0x10,
+        ];
+        assert_eq!(
+            Response::decode(&mapping, OP_MAP_TCP),
+            Some(Response::Mapping {
+                result: 0,
+                internal_port: 6180,
+                external_port: 6181,
+                lifetime_secs: 3600,
+            })
+        );
+        // responses to the other requests are ignored
+        assert_eq!(Response::decode(&mapping, OP_EXTERNAL_ADDRESS), None);
+        // truncated messages fail to decode, without panicking
+        for len in 0..mapping.len() {
+            assert_eq!(Response::decode(&mapping[..len], OP_MAP_TCP), None);
+        }
+    }
+
+    #[test]
+    fn default_gateway_from_route_table() {
+        let route_table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0
+";
+        let expected = if cfg!(target_endian = "little") {
+            Ipv4Addr::new(192, 168, 1, 1)
+        } else {
+            Ipv4Addr::new(1, 1, 168, 192)
+        };
+        assert_eq!(parse_default_gateway(route_table), Some(expected));
+        // no default route
+        let without_default_route: Vec<_> = route_table.lines().take(2).collect();
+        assert_eq!(
+            parse_default_gateway(&without_default_route.join("\n")),
+            None
+        );
+    }
+
+    #[test]
+    fn map_port() {
+        let mut rt = Runtime::new().unwrap();
+        let pubkey = x25519::PrivateKey::generate(&mut StdRng::from_seed([0u8; 32])).public_key();
+        rt.block_on(async move {
+            let mut gateway = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let gateway_addr = match gateway.local_addr().unwrap() {
+                SocketAddr::V4(addr) => addr,
+                addr => panic!("Unexpected gateway address: {}", addr),
+            };
+            let (external_addrs_tx, mut external_addrs_rx) = mpsc::unbounded();
+            let mapper =
+                PortMapper::new(gateway_addr, pubkey, HANDSHAKE_VERSION, external_addrs_tx);
+            tokio::spawn(mapper.start(6180));
+
+            // the gateway drops the first request, which is retransmitted
+            let mut buf = [0; MAX_RESPONSE_SIZE];
+            let (len, _) = gateway.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &encode_external_address_request());
+            let (len, from) = gateway.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &encode_external_address_request());
+            gateway
+                .send_to(&[0, 128, 0, 0, 0, 0, 0, 9, 203, 0, 113, 7], &from)
+                .await
+                .unwrap();
+            let (len, from) = gateway.recv_from(&mut buf).await.unwrap();
+            assert_eq!(
+                &buf[..len],
+                &encode_mapping_request(6180, MAPPING_LIFETIME_SECS)
+            );
+            gateway
+                .send_to(
+                    &[
+                        0, 130, 0, 0, 0, 0, 0, 9, 0x18, 0x24, 0x18, 0x25, 0, 0, 0x1c, 0x20,
+                    ],
+                    &from,
+                )
+                .await
+                .unwrap();
+
+            let expected =
+                NetworkAddress::from(SocketAddr::from((Ipv4Addr::new(203, 0, 113, 7), 6181)))
+                    .append_prod_protos(pubkey, HANDSHAKE_VERSION);
+            assert_eq!(external_addrs_rx.next().await.unwrap(), Some(expected));
+        });
+    }
+}
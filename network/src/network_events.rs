@@ -0,0 +1,140 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A firehose of the structured events of a network, e.g., for operators streaming them into
+//! their logging pipeline rather than parsing the debug logs.
+//!
+//! Subscribers are added with [`NetworkBuilder::add_event_subscriber`] and receive every event
+//! published once they subscribed. The events are broadcast without back-pressure on the
+//! network: a subscriber lagging more than [`EVENTS_CAPACITY`] events behind misses the oldest
+//! ones, and is told how many with a `RecvError::Lagged`.
+//!
+//! [`NetworkBuilder::add_event_subscriber`]:
+//! crate::validator_network::network_builder::NetworkBuilder::add_event_subscriber
+
+use crate::{peer::DisconnectReason, ProtocolId};
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use netcore::transport::ConnectionOrigin;
+use std::{fmt, time::Duration};
+use tokio::sync::broadcast;
+
+/// Number of events kept for a lagging subscriber.
+pub const EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkEvent {
+    /// A dial of `peer_id` at `address` started.
+    DialStarted {
+        peer_id: PeerId,
+        address: NetworkAddress,
+    },
+    /// The dial of `peer_id` at `address` failed, before its handshake completed.
+    DialFailed {
+        peer_id: PeerId,
+        address: NetworkAddress,
+        error: String,
+    },
+    /// The handshake of a connection failed, e.g., as the other end shares no protocol with us.
+    /// The peer is only known beforehand for the outbound connections.
+    HandshakeFailed {
+        peer_id: Option<PeerId>,
+        address: NetworkAddress,
+        origin: ConnectionOrigin,
+        reason: String,
+    },
+    PeerConnected {
+        peer_id: PeerId,
+        address: NetworkAddress,
+        origin: ConnectionOrigin,
+    },
+    PeerDisconnected {
+        peer_id: PeerId,
+        address: NetworkAddress,
+        reason: DisconnectReason,
+    },
+    PeerBanned {
+        peer_id: PeerId,
+        duration: Duration,
+    },
+    /// Messages of `protocol` with `peer_id` dropped as the queue of the peer and protocol was
+    /// full.
+    MessagesDropped {
+        peer_id: PeerId,
+        protocol: ProtocolId,
+        count: usize,
+    },
+}
+
+/// The publisher of the events of a network. Cloning it returns a handle to the same publisher.
+#[derive(Clone)]
+pub struct NetworkEvents {
+    sender: broadcast::Sender<NetworkEvent>,
+}
+
+impl NetworkEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Returns a receiver of the events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn publish(&self, event: NetworkEvent) {
+        // The event is dropped if nobody subscribed.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for NetworkEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for NetworkEvents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NetworkEvents").finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::sync::broadcast::RecvError;
+
+    fn banned(peer_id: PeerId) -> NetworkEvent {
+        NetworkEvent::PeerBanned {
+            peer_id,
+            duration: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn subscribers() {
+        let events = NetworkEvents::new();
+        // nobody subscribed yet
+        events.publish(banned(PeerId::random()));
+
+        let mut first = events.subscribe();
+        let mut second = events.clone().subscribe();
+        let peer_id = PeerId::random();
+        events.publish(banned(peer_id));
+        assert_eq!(first.try_recv().unwrap(), banned(peer_id));
+        assert_eq!(second.try_recv().unwrap(), banned(peer_id));
+        assert!(first.try_recv().is_err());
+
+        // a lagging subscriber misses the oldest events
+        for _ in 0..=EVENTS_CAPACITY {
+            events.publish(banned(peer_id));
+        }
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            assert!(matches!(first.recv().await, Err(RecvError::Lagged(1))));
+            assert_eq!(first.recv().await.unwrap(), banned(peer_id));
+        });
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A queryable view of the connections of a network, returned by
+//! [`NetworkHandle::network_info`], so that the applications don't have to rebuild it from the
+//! connection notifications.
+//!
+//! The PeerManager records the connection with every peer once it is accepted, and forgets it
+//! once it is closed, while a connection replaced, e.g., on a simultaneous dial, is updated in
+//! place. The round-trip times of the peers are the ones of the pings of the health checker of the
+//! network, if it was added.
+//!
+//! [`NetworkHandle::network_info`]:
+//! crate::validator_network::network_handle::NetworkHandle::network_info
+
+use crate::{
+    protocols::{
+        health_checker::{PeerRtts, RttStats},
+        wire::handshake::v1::SupportedProtocols,
+    },
+    transport::ConnectionMetadata,
+};
+use libra_config::network_id::NetworkId;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use netcore::transport::ConnectionOrigin;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// The connection with a peer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerConnectionInfo {
+    pub peer_id: PeerId,
+    /// Network the connection was established for, e.g., by an additional listener
+    pub network_id: NetworkId,
+    /// Address of the peer, the one dialed if outbound
+    pub address: NetworkAddress,
+    pub origin: ConnectionOrigin,
+    /// Application protocols negotiated on the connection
+    pub application_protocols: SupportedProtocols,
+    /// When the connection was accepted by the PeerManager
+    pub connected_at: Instant,
+    /// Round-trip times of the pings of the peer, if it answered any since it connected
+    pub rtt: Option<RttStats>,
+}
+
+impl PeerConnectionInfo {
+    /// How long the connection has been established.
+    pub fn age(&self) -> Duration {
+        self.connected_at.elapsed()
+    }
+}
+
+/// The connections of a network. Cloning it returns a handle to the same view.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkInfo {
+    connections: Arc<RwLock<HashMap<PeerId, (ConnectionMetadata, Instant)>>>,
+    rtts: PeerRtts,
+}
+
+impl NetworkInfo {
+    /// A view of the connections whose round-trip times are the ones recorded in `rtts`.
+    pub fn new(rtts: PeerRtts) -> Self {
+        Self {
+            connections: Arc::default(),
+            rtts,
+        }
+    }
+
+    /// Returns the connections with the peers, ordered by peer id.
+    pub fn peers(&self) -> Vec<PeerConnectionInfo> {
+        let mut peers: Vec<_> = self
+            .connections
+            .read()
+            .unwrap()
+            .values()
+            .map(|(conn_meta, connected_at)| self.info(conn_meta, *connected_at))
+            .collect();
+        peers.sort_by_key(|peer| peer.peer_id);
+        peers
+    }
+
+    /// Returns the connection with `peer_id`, if it is connected.
+    pub fn peer(&self, peer_id: &PeerId) -> Option<PeerConnectionInfo> {
+        self.connections
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .map(|(conn_meta, connected_at)| self.info(conn_meta, *connected_at))
+    }
+
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        self.connections.read().unwrap().contains_key(peer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records the connection `conn_meta`, replacing any other connection with its peer.
+    pub(crate) fn insert(&self, conn_meta: ConnectionMetadata) {
+        self.connections
+            .write()
+            .unwrap()
+            .insert(conn_meta.peer_id(), (conn_meta, Instant::now()));
+    }
+
+    pub(crate) fn remove(&self, peer_id: &PeerId) {
+        self.connections.write().unwrap().remove(peer_id);
+    }
+
+    fn info(&self, conn_meta: &ConnectionMetadata, connected_at: Instant) -> PeerConnectionInfo {
+        PeerConnectionInfo {
+            peer_id: conn_meta.peer_id(),
+            network_id: conn_meta.network_id().clone(),
+            address: conn_meta.addr().clone(),
+            origin: conn_meta.origin(),
+            application_protocols: conn_meta.application_protocols().clone(),
+            connected_at,
+            rtt: self.rtts.get(&conn_meta.peer_id()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        protocols::wire::handshake::v1::MessagingProtocolVersion, transport::ConnectionId,
+        ProtocolId,
+    };
+
+    fn conn_meta(peer_id: PeerId, origin: ConnectionOrigin) -> ConnectionMetadata {
+        ConnectionMetadata::new(
+            peer_id,
+            ConnectionId::from(0),
+            NetworkAddress::mock(),
+            origin,
+            MessagingProtocolVersion::V1,
+            [ProtocolId::ConsensusRpc].iter().into(),
+            NetworkId::Validator,
+        )
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let rtts = PeerRtts::new();
+        let network_info = NetworkInfo::new(rtts.clone());
+        let peer_id = PeerId::random();
+        assert_eq!(network_info.peer(&peer_id), None);
+
+        network_info.insert(conn_meta(peer_id, ConnectionOrigin::Inbound));
+        rtts.sample(peer_id, Duration::from_millis(20));
+        let peers = network_info.clone().peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].peer_id, peer_id);
+        assert_eq!(peers[0].origin, ConnectionOrigin::Inbound);
+        assert!(peers[0]
+            .application_protocols
+            .contains(ProtocolId::ConsensusRpc));
+        assert_eq!(
+            peers[0].rtt.map(|rtt| rtt.last),
+            Some(Duration::from_millis(20))
+        );
+
+        // a replaced connection is updated in place
+        network_info.insert(conn_meta(peer_id, ConnectionOrigin::Outbound));
+        assert_eq!(network_info.len(), 1);
+        assert_eq!(
+            network_info.peer(&peer_id).unwrap().origin,
+            ConnectionOrigin::Outbound
+        );
+
+        network_info.remove(&peer_id);
+        assert!(!network_info.is_connected(&peer_id));
+        assert!(network_info.is_empty());
+    }
+}
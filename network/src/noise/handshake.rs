@@ -10,12 +10,18 @@
 //!
 //! [stream]: network::noise::stream
 
-use crate::noise::stream::NoiseStream;
+use crate::noise::{keylog::NoiseKeylog, stream::NoiseStream};
 use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use libra_config::config::NetworkPeerInfo;
 use libra_crypto::{noise, x25519};
 use libra_types::PeerId;
 use netcore::transport::ConnectionOrigin;
+use rand::{
+    rngs::{OsRng, StdRng},
+    SeedableRng,
+};
+#[cfg(any(test, feature = "fuzzing"))]
+use std::sync::Mutex;
 use std::{
     collections::HashMap,
     convert::TryFrom as _,
@@ -127,10 +133,26 @@ impl HandshakeAuthMode {
 pub struct NoiseUpgrader {
     /// The validator's own peer id.
     self_peer_id: PeerId,
-    /// Config for executing Noise handshakes. Includes our static private key.
-    noise_config: noise::NoiseConfig,
+    /// Config for executing Noise handshakes. Includes our static private key, which may be
+    /// rotated while the sessions established with the previous key stay alive.
+    noise_config: RwLock<Arc<noise::NoiseConfig>>,
     /// Handshake authentication can be either mutual or server-only authentication.
     auth_mode: HandshakeAuthMode,
+    /// If set, the keys of every session are exported to this keylog.
+    keylog: Option<Arc<NoiseKeylog>>,
+    /// (For tests) If set, the source of the ephemeral keys and of the anti-replay timestamps of
+    /// the handshakes, instead of the OS and the clock.
+    #[cfg(any(test, feature = "fuzzing"))]
+    deterministic: Option<Mutex<DeterministicSource>>,
+}
+
+/// (For tests) Seeded source of the randomness and the time of the handshakes, so that their
+/// transcripts are reproducible.
+#[cfg(any(test, feature = "fuzzing"))]
+struct DeterministicSource {
+    rng: StdRng,
+    /// The timestamp of the last handshake, incremented by every handshake.
+    timestamp: u64,
 }
 
 impl NoiseUpgrader {
@@ -138,8 +160,85 @@ impl NoiseUpgrader {
     pub fn new(peer_id: PeerId, key: x25519::PrivateKey, auth_mode: HandshakeAuthMode) -> Self {
         Self {
             self_peer_id: peer_id,
-            noise_config: noise::NoiseConfig::new(key),
+            noise_config: RwLock::new(Arc::new(noise::NoiseConfig::new(key))),
             auth_mode,
+            keylog: None,
+            #[cfg(any(test, feature = "fuzzing"))]
+            deterministic: None,
+        }
+    }
+
+    /// (For tests) Draw the ephemeral keys of the handshakes from a generator seeded with `seed`,
+    /// and send the consecutive anti-replay timestamps after `timestamp`, so that the same
+    /// handshakes produce the same bytes on the wire.
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub fn with_deterministic_source(mut self, seed: [u8; 32], timestamp: u64) -> Self {
+        self.deterministic = Some(Mutex::new(DeterministicSource {
+            rng: StdRng::from_seed(seed),
+            timestamp,
+        }));
+        self
+    }
+
+    /// Export the keys of every session established by this upgrader to `keylog`.
+    pub fn with_keylog(mut self, keylog: Arc<NoiseKeylog>) -> Self {
+        self.keylog = Some(keylog);
+        self
+    }
+
+    /// The static public key of the handshakes from now on.
+    pub fn public_key(&self) -> x25519::PublicKey {
+        self.noise_config().public_key()
+    }
+
+    /// Use `key` as the static private key of the handshakes from now on. The handshakes in
+    /// progress complete with the previous key, and the sessions already established are not
+    /// affected.
+    pub fn set_identity_key(&self, key: x25519::PrivateKey) {
+        *self.noise_config.write().unwrap() = Arc::new(noise::NoiseConfig::new(key));
+    }
+
+    /// The config of a new handshake, which keeps using it even if the key is rotated meanwhile.
+    fn noise_config(&self) -> Arc<noise::NoiseConfig> {
+        self.noise_config.read().unwrap().clone()
+    }
+
+    /// The generator of the ephemeral key of a new handshake.
+    fn rng(&self) -> io::Result<StdRng> {
+        #[cfg(any(test, feature = "fuzzing"))]
+        {
+            if let Some(deterministic) = &self.deterministic {
+                let rng = &mut deterministic.lock().unwrap().rng;
+                return StdRng::from_rng(rng).map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+            }
+        }
+        StdRng::from_rng(OsRng).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    /// The anti-replay timestamp of a new handshake, in milliseconds since the unix epoch.
+    fn timestamp(&self) -> u64 {
+        #[cfg(any(test, feature = "fuzzing"))]
+        {
+            if let Some(deterministic) = &self.deterministic {
+                let mut deterministic = deterministic.lock().unwrap();
+                deterministic.timestamp += 1;
+                return deterministic.timestamp;
+            }
+        }
+        time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .expect("system clock should work")
+            .as_millis() as u64
+    }
+
+    fn log_session(
+        &self,
+        origin: ConnectionOrigin,
+        public_key: x25519::PublicKey,
+        session: &noise::NoiseSession,
+    ) {
+        if let Some(keylog) = &self.keylog {
+            keylog.log_session(origin, public_key, session);
         }
     }
 
@@ -220,19 +319,16 @@ impl NoiseUpgrader {
 
         // craft 8-byte payload as current timestamp (in milliseconds)
         let payload = {
-            let now: u64 = time::SystemTime::now()
-                .duration_since(time::UNIX_EPOCH)
-                .expect("system clock should work")
-                .as_millis() as u64; // (TIMESTAMP_SIZE)
+            let now: u64 = self.timestamp(); // (TIMESTAMP_SIZE)
 
             // e.g. [157, 126, 253, 97, 114, 1, 0, 0]
             now.to_le_bytes()
         };
 
         // craft first handshake message  (-> e, es, s, ss)
-        let mut rng = rand::rngs::OsRng;
-        let initiator_state = self
-            .noise_config
+        let noise_config = self.noise_config();
+        let mut rng = self.rng()?;
+        let initiator_state = noise_config
             .initiate_connection(
                 &mut rng,
                 &prologue_msg,
@@ -252,10 +348,14 @@ impl NoiseUpgrader {
 
         // parse the server's response
         // TODO: security logging here? (mimoo)
-        let (_, session) = self
-            .noise_config
+        let (_, session) = noise_config
             .finalize_connection(initiator_state, &server_response)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        self.log_session(
+            ConnectionOrigin::Outbound,
+            noise_config.public_key(),
+            &session,
+        );
 
         // finalize the connection
         Ok(NoiseStream::new(socket, session))
@@ -300,7 +400,8 @@ impl NoiseUpgrader {
         })?;
 
         // verify that this is indeed our public key
-        if self_expected_public_key != self.noise_config.public_key().as_slice() {
+        let noise_config = self.noise_config();
+        if self_expected_public_key != noise_config.public_key().as_slice() {
             // TODO: security logging (mimoo)
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -313,8 +414,7 @@ impl NoiseUpgrader {
 
         // parse it
         let (prologue, client_init_message) = client_message.split_at(Self::PROLOGUE_SIZE);
-        let (remote_public_key, handshake_state, payload) = self
-            .noise_config
+        let (remote_public_key, handshake_state, payload) = noise_config
             .parse_client_init_message(&prologue, &client_init_message)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
@@ -405,15 +505,19 @@ impl NoiseUpgrader {
         }
 
         // construct the response
-        let mut rng = rand::rngs::OsRng;
+        let mut rng = self.rng()?;
         let mut server_response = [0u8; Self::SERVER_MESSAGE_SIZE];
-        let session = self
-            .noise_config
+        let session = noise_config
             .respond_to_client(&mut rng, handshake_state, None, &mut server_response)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
         // send the response
         socket.write_all(&server_response).await?;
+        self.log_session(
+            ConnectionOrigin::Inbound,
+            noise_config.public_key(),
+            &session,
+        );
 
         // finalize the connection
         Ok((NoiseStream::new(socket, session), remote_peer_id))
@@ -523,4 +627,66 @@ mod test {
     fn test_handshake_mutual_auth() {
         test_handshake_success(true /* is_mutual_auth */);
     }
+
+    #[test]
+    fn test_handshake_identity_key_rotation() {
+        let ((client, _), (server, server_public)) = build_peers(false /* is_mutual_auth */);
+        let mut rng = ::rand::rngs::StdRng::from_seed([1u8; 32]);
+        let new_server_private = x25519::PrivateKey::generate(&mut rng);
+        let new_server_public = new_server_private.public_key();
+        server.set_identity_key(new_server_private);
+        assert_eq!(server.public_key(), new_server_public);
+
+        let handshake = |server_public_key| {
+            let (dialer_socket, listener_socket) = MemorySocket::new_pair();
+            block_on(join(
+                client.upgrade_outbound(dialer_socket, server_public_key),
+                server.upgrade_inbound(listener_socket),
+            ))
+        };
+
+        // dialers expecting the previous key are rejected
+        let (client_session, server_session) = handshake(server_public);
+        assert!(client_session.is_err());
+        assert!(server_session.is_err());
+
+        let (client_session, server_session) = handshake(new_server_public);
+        assert_eq!(
+            client_session.unwrap().get_remote_static(),
+            new_server_public
+        );
+        assert!(server_session.is_ok());
+    }
+
+    #[cfg(feature = "noise-keylog")]
+    #[test]
+    fn test_handshake_keylog() {
+        use libra_temppath::TempPath;
+        use libra_types::chain_id::ChainId;
+
+        let client_keylog = TempPath::new();
+        let server_keylog = TempPath::new();
+        let open = |path: &TempPath| {
+            Arc::new(NoiseKeylog::open(path.path(), Some(ChainId::test())).unwrap())
+        };
+
+        let ((client, _), (server, server_public)) = build_peers(false /* is_mutual_auth */);
+        let client = client.with_keylog(open(&client_keylog));
+        let server = server.with_keylog(open(&server_keylog));
+        perform_handshake(client, server, server_public);
+
+        let read_entry = |path: &TempPath| -> Vec<String> {
+            let keylog = std::fs::read_to_string(path.path()).unwrap();
+            let lines: Vec<_> = keylog.lines().collect();
+            assert_eq!(lines.len(), 1);
+            lines[0].split(' ').map(str::to_string).collect()
+        };
+        let client_entry = read_entry(&client_keylog);
+        let server_entry = read_entry(&server_keylog);
+        assert_eq!(client_entry[1], "outbound");
+        assert_eq!(server_entry[1], "inbound");
+        // each side's write key is the other side's read key
+        assert_eq!(client_entry[4], server_entry[5]);
+        assert_eq!(client_entry[5], server_entry[4]);
+    }
 }
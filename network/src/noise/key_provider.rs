@@ -0,0 +1,32 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sources of the static private key of the Noise handshakes, i.e., of the network identity key,
+//! so that the key can live in secure storage, e.g., Vault or an HSM-backed backend, rather than
+//! in the config of the node.
+//!
+//! The Noise handshakes perform their Diffie-Hellman operations with the key in memory: the
+//! network fetches the key from its [`NoiseKeyProvider`] when it is built, see
+//! [`NetworkBuilder::authentication_mode_from_provider`], and again whenever its handle refreshes
+//! it, e.g., after the key was rotated in the storage, see [`NetworkHandle::refresh_identity_key`].
+//! The providers are never called on the path of the handshakes, so they may block.
+//!
+//! [`NetworkBuilder::authentication_mode_from_provider`]:
+//! crate::validator_network::network_builder::NetworkBuilder::authentication_mode_from_provider
+//! [`NetworkHandle::refresh_identity_key`]:
+//! crate::validator_network::network_handle::NetworkHandle::refresh_identity_key
+
+use libra_crypto::x25519;
+use thiserror::Error;
+
+/// Failure to fetch the network identity key from a [`NoiseKeyProvider`].
+#[derive(Debug, Error)]
+#[error("Failed to fetch the network identity key: {0}")]
+pub struct NoiseKeyProviderError(#[from] pub anyhow::Error);
+
+/// Provides the network identity key of a node.
+pub trait NoiseKeyProvider: Send + Sync {
+    /// Fetches the current network identity key, which may block, e.g., on a request to the
+    /// storage of the key.
+    fn private_key(&self) -> Result<x25519::PrivateKey, NoiseKeyProviderError>;
+}
@@ -0,0 +1,190 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export of Noise session keys, to decrypt captured traffic while debugging the protocol.
+//!
+//! When enabled, every successful handshake appends one line to the keylog file:
+//!
+//! ```text
+//! <unix_time_ms> <inbound|outbound> <local_static_public_key> <remote_static_public_key> <write_key> <read_key>
+//! ```
+//!
+//! All keys are hex-encoded. Both directions of a session start at nonce 0, so the keys are all
+//! that is needed to decrypt the transport messages following the handshake.
+//!
+//! Anyone with access to the keylog can decrypt and forge the traffic of the node, so this is
+//! only meant for test networks, and fails closed: the node must be built with the
+//! `noise-keylog` feature, the network config must set `enable_noise_keylog`, and the node config
+//! must name a chain other than mainnet. The file is only readable by its owner.
+
+use libra_crypto::{noise::NoiseSession, x25519};
+use libra_logger::prelude::*;
+use libra_types::chain_id::ChainId;
+use netcore::transport::ConnectionOrigin;
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Environment variable overriding the path of the keylog of the node config.
+pub const NOISE_KEYLOG_ENV_VAR: &str = "LIBRA_NOISE_KEYLOG_FILE";
+
+/// Appends the keys of Noise sessions to a file.
+pub struct NoiseKeylog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl NoiseKeylog {
+    /// Opens the keylog at `path`, creating it if needed. Fails unless `chain_id` is set to a
+    /// chain other than mainnet, or if the node is built without the `noise-keylog` feature.
+    pub fn open(path: &Path, chain_id: Option<ChainId>) -> io::Result<Self> {
+        if !cfg!(feature = "noise-keylog") {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "noise keylog requires building with the noise-keylog feature",
+            ));
+        }
+        match chain_id {
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "noise keylog requires an explicit chain id",
+                ))
+            }
+            Some(chain_id) if chain_id.is_mainnet() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "noise keylog is disabled on mainnet",
+                ))
+            }
+            Some(_) => (),
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Returns the keylog of the network config if `enabled`, at the path of the
+    /// `LIBRA_NOISE_KEYLOG_FILE` environment variable or, failing that, of the network config.
+    /// Errors are logged rather than returned, so that a misconfigured keylog never prevents the
+    /// node from starting.
+    pub fn from_config(
+        enabled: bool,
+        config_path: Option<&Path>,
+        chain_id: Option<ChainId>,
+    ) -> Option<Arc<Self>> {
+        if !enabled {
+            return None;
+        }
+        let path = env::var_os(NOISE_KEYLOG_ENV_VAR)
+            .map(PathBuf::from)
+            .or_else(|| config_path.map(Path::to_path_buf))?;
+        match Self::open(&path, chain_id) {
+            Ok(keylog) => {
+                warn!(
+                    "Noise session keys are exported to {:?}: traffic of chain {:?} can be decrypted",
+                    path, chain_id
+                );
+                Some(Arc::new(keylog))
+            }
+            Err(e) => {
+                error!("Unable to enable noise keylog {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Records the keys of a newly established session.
+    pub fn log_session(
+        &self,
+        origin: ConnectionOrigin,
+        local_public_key: x25519::PublicKey,
+        session: &NoiseSession,
+    ) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock should work")
+            .as_millis();
+        let origin = match origin {
+            ConnectionOrigin::Inbound => "inbound",
+            ConnectionOrigin::Outbound => "outbound",
+        };
+        let (write_key, read_key) = session_keys(session);
+        let line = format!(
+            "{} {} {} {} {} {}\n",
+            now,
+            origin,
+            hex::encode(local_public_key.as_slice()),
+            hex::encode(session.get_remote_static().as_slice()),
+            hex::encode(write_key),
+            hex::encode(read_key),
+        );
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            error!("Unable to write to noise keylog {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(feature = "noise-keylog")]
+fn session_keys(session: &NoiseSession) -> (&[u8], &[u8]) {
+    session.keys()
+}
+
+/// Without the `noise-keylog` feature, no keylog can be opened, so no session is ever recorded.
+#[cfg(not(feature = "noise-keylog"))]
+fn session_keys(_session: &NoiseSession) -> (&[u8], &[u8]) {
+    unreachable!("noise keylog opened without the noise-keylog feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_temppath::TempPath;
+
+    #[test]
+    fn test_disabled_on_mainnet() {
+        let path = TempPath::new();
+        assert!(NoiseKeylog::open(path.path(), Some(ChainId::mainnet())).is_err());
+        assert!(!path.path().exists());
+    }
+
+    #[test]
+    fn test_disabled_without_chain_id() {
+        let path = TempPath::new();
+        assert!(NoiseKeylog::open(path.path(), None).is_err());
+        assert!(!path.path().exists());
+    }
+
+    #[test]
+    fn test_disabled_unless_enabled() {
+        let path = TempPath::new();
+        assert!(
+            NoiseKeylog::from_config(false, Some(path.path()), Some(ChainId::test())).is_none()
+        );
+        assert!(!path.path().exists());
+    }
+
+    #[test]
+    fn test_requires_feature() {
+        let path = TempPath::new();
+        assert_eq!(
+            NoiseKeylog::open(path.path(), Some(ChainId::test())).is_ok(),
+            cfg!(feature = "noise-keylog")
+        );
+    }
+}
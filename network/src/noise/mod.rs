@@ -90,9 +90,15 @@
 //! [crypto]: ../libra_crypto/noise/index.html
 
 pub mod handshake;
+pub mod key_provider;
+pub mod keylog;
+pub mod relay;
 pub mod stream;
 
 #[cfg(any(test, feature = "fuzzing"))]
 pub mod fuzzing;
 
 pub use handshake::{AntiReplayTimestamps, HandshakeAuthMode, NoiseUpgrader};
+pub use key_provider::{NoiseKeyProvider, NoiseKeyProviderError};
+pub use keylog::NoiseKeylog;
+pub use relay::NoiseRelayAuth;
@@ -0,0 +1,155 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authentication of the reservations, and of the circuits accepted, on a relay, see
+//! [`netcore::transport::relay`].
+//!
+//! The relay sends its static public key, and the peer reserving a `PeerId`, or accepting one of
+//! its circuits, runs the Noise IK handshake as a client with the relay. The relay thus
+//! authenticates the `PeerId` of the peer, which must be derived from its identity key, as for the
+//! inbound connections of a server-only network. The relay itself isn't authenticated: it only
+//! learns the identity of the peer. The spliced connections leave the Noise session once the
+//! circuit is accepted, as the peers run their own handshake end-to-end through the relay.
+
+use crate::noise::{handshake::NoiseUpgrader, stream::NoiseStream, HandshakeAuthMode};
+use bytes::BytesMut;
+use futures::{
+    future::{BoxFuture, FutureExt},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+};
+use libra_crypto::x25519;
+use libra_types::PeerId;
+use netcore::{
+    framing::{read_u16frame, write_u16frame},
+    transport::relay::RelayAuthenticator,
+};
+use std::{convert::TryFrom, io, sync::Arc};
+
+/// Authenticates the reservations on a relay with the Noise IK handshake.
+#[derive(Clone)]
+pub struct NoiseRelayAuth {
+    upgrader: Arc<NoiseUpgrader>,
+}
+
+impl NoiseRelayAuth {
+    /// Authenticates the peer `peer_id`, or the relay, with the identity key `key`.
+    pub fn new(peer_id: PeerId, key: x25519::PrivateKey) -> Self {
+        Self {
+            upgrader: Arc::new(NoiseUpgrader::new(
+                peer_id,
+                key,
+                HandshakeAuthMode::ServerOnly,
+            )),
+        }
+    }
+}
+
+impl std::fmt::Debug for NoiseRelayAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "NoiseRelayAuth({})", self.upgrader.public_key())
+    }
+}
+
+impl<S> RelayAuthenticator<S> for NoiseRelayAuth
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = NoiseStream<S>;
+
+    fn authenticate(&self, mut socket: S) -> BoxFuture<'static, io::Result<NoiseStream<S>>> {
+        let upgrader = self.upgrader.clone();
+        async move {
+            let mut relay_public_key = BytesMut::new();
+            read_u16frame(&mut socket, &mut relay_public_key).await?;
+            let relay_public_key = x25519::PublicKey::try_from(relay_public_key.as_ref())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            upgrader.upgrade_outbound(socket, relay_public_key).await
+        }
+        .boxed()
+    }
+
+    fn authenticate_peer(
+        &self,
+        mut socket: S,
+    ) -> BoxFuture<'static, io::Result<(NoiseStream<S>, PeerId)>> {
+        let upgrader = self.upgrader.clone();
+        async move {
+            write_u16frame(&mut socket, upgrader.public_key().as_slice()).await?;
+            socket.flush().await?;
+            upgrader.upgrade_inbound(socket).await
+        }
+        .boxed()
+    }
+
+    fn into_socket(output: NoiseStream<S>) -> S {
+        output.into_socket()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{future::try_join, stream::StreamExt};
+    use libra_crypto::{test_utils::TEST_SEED, traits::Uniform};
+    use libra_network_address::NetworkAddress;
+    use netcore::transport::{
+        relay::{RelayServer, RelayTransport},
+        tcp::TcpTransport,
+        Transport,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::Duration;
+    use tokio::{runtime::Runtime, time::delay_for};
+
+    /// Reserves `peer_id` on a relay with the identity key `key`, and dials it through the relay.
+    fn dial_through_relay(peer_id: PeerId, key: x25519::PrivateKey) -> io::Result<()> {
+        let mut rng = StdRng::from_seed(TEST_SEED);
+        let relay_key = x25519::PrivateKey::generate(&mut rng);
+        let relay_auth = NoiseRelayAuth::new(PeerId::random(), relay_key);
+
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (relay, relay_addr) = RelayServer::listen_on(
+                &TcpTransport::default(),
+                "/ip4/127.0.0.1/tcp/0".parse().unwrap(),
+                relay_auth,
+            )?;
+            tokio::spawn(relay.start());
+
+            let auth = NoiseRelayAuth::new(peer_id, key);
+            let t = RelayTransport::new(TcpTransport::default(), peer_id, auth);
+            let addr: NetworkAddress = format!("{}/p2p-circuit", relay_addr).parse().unwrap();
+            let (mut listener, _addr) = t.listen_on(addr.clone())?;
+
+            let dial = async {
+                loop {
+                    match t.dial(peer_id, addr.clone())?.await {
+                        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                            delay_for(Duration::from_millis(10)).await
+                        }
+                        result => return result,
+                    }
+                }
+            };
+            let accept = async {
+                let (incoming, _addr) = listener.next().await.unwrap()?;
+                incoming.await
+            };
+            try_join(dial, accept).await.map(|_| ())
+        })
+    }
+
+    #[test]
+    fn reserve_own_peer_id() {
+        let key = x25519::PrivateKey::generate(&mut StdRng::from_seed([1; 32]));
+        let peer_id = PeerId::from_identity_public_key(key.public_key());
+        dial_through_relay(peer_id, key).unwrap();
+    }
+
+    #[test]
+    fn reserve_peer_id_of_another_key() {
+        let key = x25519::PrivateKey::generate(&mut StdRng::from_seed([1; 32]));
+        // the relay refuses the reservation, which the listener reports
+        dial_through_relay(PeerId::random(), key).unwrap_err();
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The addresses of this node as observed by its peers, so that a node without any configured
+//! advertised address, e.g., behind a NAT or listening on `0.0.0.0`, learns the address its peers
+//! reach it at.
+//!
+//! During the handshake of a connection, each end reports in its `HandshakeExtensions` the address
+//! it observed for the other end: the dialer reports the address it dialed, and the listener the
+//! address the connection came from. The PeerManager records the observations of the connected
+//! peers in an [`ObservedAddrs`] handle, and forgets them when the peers disconnect.
+//!
+//! A single peer can report any address, so an IP address is only trusted once it is reported by
+//! [`MIN_OBSERVERS`] distinct peers, more than any other IP address. Only the ports reported by
+//! the dialers are meaningful, as the listeners observe the ephemeral ports of the outbound
+//! connections of this node: the port of the consensus address is the one the most dialers
+//! reported, or the bound listen port of the node if none did.
+
+use libra_network_address::{parse_ip_tcp, NetworkAddress};
+use libra_types::PeerId;
+use netcore::transport::ConnectionOrigin;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+};
+
+/// Minimum number of distinct peers which must report an IP address for it to be trusted.
+pub const MIN_OBSERVERS: usize = 3;
+
+/// An address of this node reported by a peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Observation {
+    ip: IpAddr,
+    /// The port the peer dialed, if it dialed this node.
+    port: Option<u16>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    observations: HashMap<PeerId, Observation>,
+    /// The bound TCP listen port of this node, once known.
+    listen_port: Option<u16>,
+}
+
+/// The addresses of this node observed by its connected peers. Cloning it returns a handle to the
+/// same view.
+#[derive(Clone, Debug, Default)]
+pub struct ObservedAddrs {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl ObservedAddrs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the address of this node reported by the most peers, if at least [`MIN_OBSERVERS`]
+    /// of them agree on its IP address, and its port is known.
+    pub fn consensus(&self) -> Option<NetworkAddress> {
+        let inner = self.inner.read().unwrap();
+        let ip = most_common(
+            inner
+                .observations
+                .values()
+                .map(|observation| observation.ip),
+        )
+        .filter(|(_, count)| *count >= MIN_OBSERVERS)?
+        .0;
+        let port = most_common(
+            inner
+                .observations
+                .values()
+                .filter(|observation| observation.ip == ip)
+                .filter_map(|observation| observation.port),
+        )
+        .map(|(port, _)| port)
+        .or(inner.listen_port)?;
+        Some(NetworkAddress::from(SocketAddr::new(ip, port)))
+    }
+
+    /// Sets the bound TCP listen port of this node, the port of the consensus address when no
+    /// dialer reported one.
+    pub(crate) fn set_listen_port(&self, port: u16) {
+        self.inner.write().unwrap().listen_port = Some(port);
+    }
+
+    /// Records the address of this node reported by `peer_id` on a connection of `origin`,
+    /// ignoring the addresses other than IP and TCP, e.g., the address of a relay the connection
+    /// went through.
+    pub(crate) fn insert(&self, peer_id: PeerId, origin: ConnectionOrigin, addr: &NetworkAddress) {
+        let (ip, port) = match parse_ip_tcp(addr.as_slice()) {
+            Some(((ip, port), [])) if !ip.is_unspecified() => (ip, port),
+            _ => return,
+        };
+        let port = match origin {
+            ConnectionOrigin::Inbound => Some(port),
+            ConnectionOrigin::Outbound => None,
+        };
+        self.inner
+            .write()
+            .unwrap()
+            .observations
+            .insert(peer_id, Observation { ip, port });
+    }
+
+    pub(crate) fn remove(&self, peer_id: &PeerId) {
+        self.inner.write().unwrap().observations.remove(peer_id);
+    }
+}
+
+/// Returns the most common item with its count, if it is strictly more common than the others.
+fn most_common<T: Copy + Eq + std::hash::Hash>(
+    items: impl Iterator<Item = T>,
+) -> Option<(T, usize)> {
+    let mut counts = HashMap::new();
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+    match counts.as_slice() {
+        [first, second, ..] if first.1 == second.1 => None,
+        [first, ..] => Some(*first),
+        [] => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(addr: &str) -> NetworkAddress {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn consensus() {
+        let observed_addrs = ObservedAddrs::new();
+        let peers: Vec<_> = (0..5).map(|_| PeerId::random()).collect();
+        let public = addr("/ip4/203.0.113.7/tcp/40000");
+        observed_addrs.insert(peers[0], ConnectionOrigin::Outbound, &public);
+        observed_addrs.insert(peers[1], ConnectionOrigin::Outbound, &public);
+        // the other addresses are ignored
+        observed_addrs.insert(peers[2], ConnectionOrigin::Inbound, &addr("/memory/1234"));
+        observed_addrs.insert(
+            peers[3],
+            ConnectionOrigin::Inbound,
+            &addr("/ip4/0.0.0.0/tcp/1"),
+        );
+        observed_addrs.insert(
+            peers[4],
+            ConnectionOrigin::Outbound,
+            &addr("/ip4/203.0.113.7/tcp/40000/p2p-circuit"),
+        );
+        assert_eq!(observed_addrs.consensus(), None);
+
+        // without any dialer, the port is the listen port
+        observed_addrs.insert(peers[2], ConnectionOrigin::Outbound, &public);
+        assert_eq!(observed_addrs.consensus(), None);
+        observed_addrs.set_listen_port(6180);
+        assert_eq!(
+            observed_addrs.consensus(),
+            Some(addr("/ip4/203.0.113.7/tcp/6180"))
+        );
+
+        // the port reported by a dialer wins over the listen port
+        observed_addrs.insert(
+            peers[3],
+            ConnectionOrigin::Inbound,
+            &addr("/ip4/203.0.113.7/tcp/6190"),
+        );
+        assert_eq!(
+            observed_addrs.consensus(),
+            Some(addr("/ip4/203.0.113.7/tcp/6190"))
+        );
+
+        // a minority of peers can't change the consensus, which is lost along with its observers
+        observed_addrs.insert(
+            peers[4],
+            ConnectionOrigin::Inbound,
+            &addr("/ip4/198.51.100.1/tcp/6180"),
+        );
+        assert_eq!(
+            observed_addrs.consensus(),
+            Some(addr("/ip4/203.0.113.7/tcp/6190"))
+        );
+        observed_addrs.remove(&peers[0]);
+        observed_addrs.remove(&peers[1]);
+        assert_eq!(observed_addrs.consensus(), None);
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-chain discovery of the validators: the [`ConfigurationChangeListener`] receives the
+//! validator set of every epoch on reconfiguration, and sends the network addresses and identity
+//! keys of the validators to the [`ConnectivityManager`], so that the trusted peers of the network
+//! and the peers it dials follow the chain rather than the static config.
+//!
+//! [`ConnectivityManager`]: crate::connectivity_manager::ConnectivityManager
+
+use crate::{
+    common::NetworkPublicKeys,
+    connectivity_manager::{ConnectivityPreferences, ConnectivityRequest, DiscoverySource},
+    counters::{EVENT_PROCESSING_LOOP_BUSY_DURATION_S, EVENT_PROCESSING_LOOP_IDLE_DURATION_S},
+};
+use channel::libra_channel;
+use futures::{sink::SinkExt, StreamExt};
+use libra_config::config::RoleType;
+use libra_crypto::x25519;
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use libra_types::{
+    on_chain_config::{OnChainConfigPayload, ValidatorConnectivityConfig, ValidatorSet},
+    validator_config::ValidatorConfig,
+    PeerId,
+};
+use std::{convert::TryFrom, time::Instant};
+
+/// Listener which converts published  updates from the OnChainConfig to ConnectivityRequests
+/// for the ConnectivityManager.
+pub struct ConfigurationChangeListener {
+    conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    peer_id: PeerId,
+    role: RoleType,
+}
+
+/// Extract the network_address from the provided config, depending on role.
+fn network_address(role: RoleType, config: &ValidatorConfig) -> Result<NetworkAddress, lcs::Error> {
+    match role {
+        RoleType::Validator => NetworkAddress::try_from(&config.validator_network_address),
+        RoleType::FullNode => NetworkAddress::try_from(&config.full_node_network_address),
+    }
+}
+
+/// Extracts the public key from the provided config, depending on role.
+fn public_key(role: RoleType, config: &ValidatorConfig) -> x25519::PublicKey {
+    match role {
+        RoleType::Validator => config.validator_network_identity_public_key,
+        RoleType::FullNode => config.full_node_network_identity_public_key,
+    }
+}
+
+/// Extracts a set of ConnectivityRequests from the ValidatorSet of `epoch` which are appropriate for a network with type role.
+fn extract_updates(role: RoleType, epoch: u64, node_set: ValidatorSet) -> Vec<ConnectivityRequest> {
+    let node_list = node_set.payload().to_vec();
+
+    let mut updates = Vec::new();
+
+    // Collect the set of address updates.
+    let address_map = node_list
+        .iter()
+        .flat_map(|node| match network_address(role, node.config()) {
+            Ok(addr) => Some((*node.account_address(), vec![addr])),
+            Err(e) => {
+                warn!("Cannot parse network address {}", e);
+                None
+            }
+        })
+        .collect();
+
+    let update_address_req =
+        ConnectivityRequest::UpdateAddresses(DiscoverySource::OnChain, address_map);
+
+    updates.push(update_address_req);
+
+    // Collect the set of EligibleNodes
+    updates.push(ConnectivityRequest::UpdateEligibleNodes(
+        epoch,
+        node_list
+            .into_iter()
+            .map(|node| {
+                (
+                    *node.account_address(),
+                    NetworkPublicKeys {
+                        identity_public_key: public_key(role, node.config()),
+                    },
+                )
+            })
+            .collect(),
+    ));
+
+    updates
+}
+
+/// Extracts the preferences published by the operator of `peer_id` for its connections to the
+/// other validators, none if the operator didn't publish any.
+fn extract_preferences(
+    peer_id: PeerId,
+    connectivity: &ValidatorConnectivityConfig,
+) -> ConnectivityPreferences {
+    let region = |region: &[u8]| String::from_utf8_lossy(region).into_owned();
+    let peer_regions = connectivity
+        .preferences
+        .iter()
+        .map(|preferences| (preferences.validator, region(&preferences.region)))
+        .collect();
+    match connectivity.preferences_of(&peer_id) {
+        Some(preferences) => ConnectivityPreferences {
+            preferred_regions: preferences
+                .preferred_regions
+                .iter()
+                .map(|preferred| region(preferred))
+                .collect(),
+            max_connections: match preferences.max_connections {
+                0 => None,
+                max_connections => Some(max_connections as usize),
+            },
+            peer_regions,
+        },
+        None => ConnectivityPreferences {
+            peer_regions,
+            ..ConnectivityPreferences::default()
+        },
+    }
+}
+
+impl ConfigurationChangeListener {
+    /// Creates a new ConfigurationListener for the network of the node `peer_id`
+    pub fn new(
+        conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+        peer_id: PeerId,
+        role: RoleType,
+    ) -> Self {
+        Self {
+            conn_mgr_reqs_tx,
+            peer_id,
+            role,
+        }
+    }
+
+    /// Processes a received OnChainConfigPayload.  Depending on role (Validator or FullNode), parses
+    /// the appropriate configuration changes and passes it to the ConnectionManager channel.
+    async fn process_payload(&mut self, payload: OnChainConfigPayload) {
+        let node_set: ValidatorSet = payload
+            .get()
+            .expect("failed to get ValidatorSet from payload");
+
+        let updates = match self.role {
+            RoleType::Validator => {
+                let mut updates = extract_updates(self.role, payload.epoch(), node_set);
+                // The connectivity preferences are only published for the validator network.
+                let connectivity: ValidatorConnectivityConfig = payload.get().unwrap_or_default();
+                updates.push(ConnectivityRequest::UpdatePreferences(extract_preferences(
+                    self.peer_id,
+                    &connectivity,
+                )));
+                updates
+            }
+            RoleType::FullNode => extract_updates(self.role, payload.epoch(), node_set),
+        };
+
+        info!(
+            "Update {} Network about new Node IDs",
+            self.role.to_string()
+        );
+
+        for update in updates {
+            match self.conn_mgr_reqs_tx.send(update).await {
+                Ok(()) => (),
+                Err(e) => warn!("Failed to send update to ConnectivityManager {}", e),
+            }
+        }
+    }
+
+    /// Starts the listener to wait on reconfiguration events.  Creates an infinite loop.
+    pub async fn start(
+        mut self,
+        mut reconfig_events: libra_channel::Receiver<(), OnChainConfigPayload>,
+    ) {
+        loop {
+            let start_idle_time = Instant::now();
+            let payload = reconfig_events.select_next_some().await;
+            let idle_duration = start_idle_time.elapsed();
+            let start_process_time = Instant::now();
+            self.process_payload(payload).await;
+            let process_duration = start_process_time.elapsed();
+
+            EVENT_PROCESSING_LOOP_IDLE_DURATION_S.observe_duration(idle_duration);
+            EVENT_PROCESSING_LOOP_BUSY_DURATION_S.observe_duration(process_duration);
+        }
+    }
+}
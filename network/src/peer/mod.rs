@@ -6,7 +6,12 @@
 use crate::{
     counters,
     peer_manager::PeerManagerError,
-    protocols::wire::messaging::v1::NetworkMessage,
+    protocols::wire::{
+        handshake::v1::CompressionCodec,
+        messaging::v1::{compression, NetworkMessage},
+    },
+    quota::{QuotaPermit, ResourceBudget},
+    rate_limit::{InboundRateLimits, PeerRateLimiter, RateLimitPolicy},
     transport,
     transport::{Connection, ConnectionMetadata},
     ProtocolId,
@@ -22,7 +27,11 @@ use futures::{
 use libra_logger::prelude::*;
 use libra_types::PeerId;
 use netcore::compat::IoCompat;
-use std::{fmt::Debug, io, time::Duration};
+use std::{
+    fmt::Debug,
+    io,
+    time::{Duration, Instant},
+};
 use stream_ratelimiter::*;
 use tokio::runtime::Handle;
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
@@ -52,7 +61,9 @@ pub enum DisconnectReason {
 
 #[derive(Debug)]
 pub enum PeerNotification {
-    NewMessage(NetworkMessage),
+    /// A message received from the peer, along with its reservation in the inbound message budget
+    /// of the network, which is released once the notification is processed.
+    NewMessage(NetworkMessage, QuotaPermit),
     PeerDisconnected(ConnectionMetadata, DisconnectReason),
 }
 
@@ -76,6 +87,12 @@ pub struct Peer<TSocket> {
     rpc_notifs_tx: channel::Sender<PeerNotification>,
     /// Channel to notify about new inbound DirectSend substreams.
     direct_send_notifs_tx: channel::Sender<PeerNotification>,
+    /// Budget of the network for the inbound messages not yet processed.
+    inbound_message_budget: ResourceBudget,
+    /// Rate limits of the inbound messages of the peer, by protocol.
+    rate_limiter: PeerRateLimiter,
+    /// Codec compressing the payloads sent to the peer, if both ends support compression.
+    compression_codec: Option<CompressionCodec>,
     /// Flag to indicate if the actor is being shut down.
     state: State,
 }
@@ -91,11 +108,14 @@ where
         peer_notifs_tx: channel::Sender<PeerNotification>,
         rpc_notifs_tx: channel::Sender<PeerNotification>,
         direct_send_notifs_tx: channel::Sender<PeerNotification>,
+        inbound_message_budget: ResourceBudget,
+        inbound_rate_limits: InboundRateLimits,
     ) -> Self {
         let Connection {
             metadata: connection_metadata,
             socket,
         } = connection;
+        let compression_codec = connection_metadata.compression_codecs().preferred();
         Self {
             executor,
             connection_metadata,
@@ -104,6 +124,9 @@ where
             peer_notifs_tx,
             rpc_notifs_tx,
             direct_send_notifs_tx,
+            inbound_message_budget,
+            rate_limiter: PeerRateLimiter::new(inbound_rate_limits),
+            compression_codec,
             state: State::Connected,
         }
     }
@@ -132,8 +155,12 @@ where
         // the task:
         // `write_reqs_tx`: Instruction to send a NetworkMessage on the wire.
         // `close_tx`: Instruction to close the underlying connection.
-        let (write_reqs_tx, close_tx) =
-            Self::start_writer_task(&self.executor, self_peer_id, writer);
+        let (write_reqs_tx, close_tx) = Self::start_writer_task(
+            &self.executor,
+            self_peer_id,
+            self.connection_metadata.network_id().as_str().to_string(),
+            writer,
+        );
         // Start main Peer event loop.
         loop {
             match self.state {
@@ -179,6 +206,22 @@ where
                             e
                         );
                     }
+                    // Let the RPC actor fail the pending outbound RPCs with the reason of the
+                    // disconnection. This must not block the shutdown, so the notification is
+                    // dropped if the RPC actor is not keeping up.
+                    if let Err(e) = self
+                        .rpc_notifs_tx
+                        .try_send(PeerNotification::PeerDisconnected(
+                            self.connection_metadata.clone(),
+                            reason,
+                        ))
+                    {
+                        info!(
+                            "Failed to notify RPC actor about disconnection of peer: {}; error: {:?}",
+                            self.peer_id().short_str(),
+                            e
+                        );
+                    }
                     // Send a PeerDisconnected event to upstream.
                     if let Err(e) = self
                         .peer_notifs_tx
@@ -199,6 +242,7 @@ where
                 }
             }
         }
+        counters::LIBRA_NETWORK_PEER_TRAFFIC.remove(&self_peer_id);
     }
 
     // Start a new task on the given executor which is responsible for writing outbound messages on
@@ -211,6 +255,7 @@ where
     fn start_writer_task<T: tokio::io::AsyncWrite + Send + Unpin + 'static>(
         executor: &Handle,
         self_peer_id: PeerId,
+        network_id: String,
         mut writer: FramedWrite<T, LengthDelimitedCodec>,
     ) -> (
         channel::Sender<(
@@ -232,12 +277,18 @@ where
             loop {
                 futures::select! {
                     (message, ack_ch) = write_reqs_rx.select_next_some() => {
+                        let protocol = protocol_label(&message);
+                        let message = lcs::to_bytes(&message)
+                            .expect("Outboung message failed to serialize");
+                        record_traffic(
+                            self_peer_id,
+                            &network_id,
+                            protocol,
+                            counters::SENT_LABEL,
+                            message.len(),
+                        );
                         if let Err(e) = writer
-                            .send(
-                                lcs::to_bytes(&message)
-                                    .expect("Outboung message failed to serialize")
-                                    .into(),
-                            )
+                            .send(message.into())
                             .map_ok(|_| ack_ch.send(Ok(())))
                             .await
                         {
@@ -292,12 +343,48 @@ where
         )>,
     ) -> Result<(), PeerManagerError> {
         trace!("Received message from Peer {}", self.peer_id().short_str(),);
+        // Drop the message if the network is already holding too many inbound messages.
+        let permit = match self.inbound_message_budget.try_reserve(message.len()) {
+            Some(permit) => permit,
+            None => {
+                debug!(
+                    "Dropping message of {} bytes from Peer {}: inbound message quota exhausted",
+                    message.len(),
+                    self.peer_id().short_str()
+                );
+                return Ok(());
+            }
+        };
         // Read inbound message from stream.
+        let wire_len = message.len();
         let message = message.freeze();
-        let message: NetworkMessage = lcs::from_bytes(&message)?;
+        let mut message: NetworkMessage = lcs::from_bytes(&message)?;
+        record_traffic(
+            self.peer_id(),
+            self.connection_metadata.network_id().as_str(),
+            protocol_label(&message),
+            counters::RECEIVED_LABEL,
+            wire_len,
+        );
+        if self.compression_codec.is_some() {
+            message = compression::decompress_message(message)?;
+        }
+        let protocol = match &message {
+            NetworkMessage::RpcRequest(request) => Some(request.protocol_id),
+            NetworkMessage::TracedRpcRequest(traced) => Some(traced.request.protocol_id),
+            NetworkMessage::DirectSendMsg(message) => Some(message.protocol_id),
+            _ => None,
+        };
+        if let Some(protocol) = protocol {
+            if !self.admit_message(protocol).await {
+                return Ok(());
+            }
+        }
         match message {
-            NetworkMessage::RpcRequest(_) | NetworkMessage::RpcResponse(_) => {
-                let notif = PeerNotification::NewMessage(message);
+            NetworkMessage::RpcRequest(_)
+            | NetworkMessage::TracedRpcRequest(_)
+            | NetworkMessage::RpcResponse(_) => {
+                let notif = PeerNotification::NewMessage(message, permit);
                 self.rpc_notifs_tx.send(notif).await.map_err(|err| {
                     warn!("Failed to send notification to RPC actor. Error: {:?}", err);
                     err
@@ -305,7 +392,7 @@ where
                 Ok(())
             }
             NetworkMessage::DirectSendMsg(_) => {
-                let notif = PeerNotification::NewMessage(message);
+                let notif = PeerNotification::NewMessage(message, permit);
                 self.direct_send_notifs_tx
                     .send(notif)
                     .await
@@ -329,6 +416,40 @@ where
         }
     }
 
+    /// Whether to process an inbound message of `protocol`, waiting for the rate limit of the
+    /// peer to allow it in case of back-pressure.
+    async fn admit_message(&mut self, protocol: ProtocolId) -> bool {
+        let mut wait = match self.rate_limiter.try_acquire(protocol, Instant::now()) {
+            Ok(()) => return true,
+            Err(wait) => wait,
+        };
+        let policy = self.rate_limiter.policy();
+        counters::LIBRA_NETWORK_RATE_LIMITED_MESSAGES
+            .with_label_values(&[
+                counters::LIBRA_NETWORK_RATE_LIMITED_PEERS.label(&self.peer_id().short_str()),
+                protocol.as_str(),
+                policy.as_str(),
+            ])
+            .inc();
+        match policy {
+            RateLimitPolicy::Drop => {
+                debug!(
+                    "Dropping message of protocol {} from Peer {}: rate limit exceeded",
+                    protocol,
+                    self.peer_id().short_str()
+                );
+                false
+            }
+            RateLimitPolicy::BackPressure => loop {
+                tokio::time::delay_for(wait).await;
+                match self.rate_limiter.try_acquire(protocol, Instant::now()) {
+                    Ok(()) => return true,
+                    Err(next_wait) => wait = next_wait,
+                }
+            },
+        }
+    }
+
     async fn handle_request<'a>(
         &'a mut self,
         request: PeerRequest,
@@ -344,6 +465,22 @@ where
         );
         match request {
             PeerRequest::SendMessage(message, protocol, channel) => {
+                // The peers on the sessions without traced RPCs are sent the bare requests.
+                let message = match message {
+                    NetworkMessage::TracedRpcRequest(traced)
+                        if !self
+                            .connection_metadata
+                            .messaging_protocol()
+                            .has_traced_rpcs() =>
+                    {
+                        NetworkMessage::RpcRequest(traced.request)
+                    }
+                    message => message,
+                };
+                let message = match self.compression_codec {
+                    Some(codec) => compression::compress_message(codec, message),
+                    None => message,
+                };
                 if let Err(e) = write_reqs_tx.send((message, channel)).await {
                     error!(
                         "Failed to send message for protocol {:?} to peer: {:?}. Error: {:?}",
@@ -366,6 +503,39 @@ where
     }
 }
 
+/// Label of the protocol of `message` in the traffic metrics. The responses to RPCs and the control
+/// messages don't carry the protocol they belong to.
+fn protocol_label(message: &NetworkMessage) -> &'static str {
+    match message {
+        NetworkMessage::RpcRequest(request) => request.protocol_id.as_str(),
+        NetworkMessage::TracedRpcRequest(traced) => traced.request.protocol_id.as_str(),
+        NetworkMessage::DirectSendMsg(message) => message.protocol_id.as_str(),
+        NetworkMessage::RpcResponse(_) => "rpc_response",
+        NetworkMessage::Error(_) | NetworkMessage::Ping(_) | NetworkMessage::Pong(_) => "control",
+    }
+}
+
+/// Accounts a message of `bytes` on the wire exchanged with `peer_id` in the traffic metrics.
+fn record_traffic(
+    peer_id: PeerId,
+    network_id: &str,
+    protocol: &str,
+    direction: &str,
+    bytes: usize,
+) {
+    counters::LIBRA_NETWORK_TRAFFIC_BYTES
+        .with_label_values(&[protocol, network_id, direction])
+        .inc_by(bytes as i64);
+    counters::LIBRA_NETWORK_TRAFFIC_MESSAGES
+        .with_label_values(&[protocol, network_id, direction])
+        .inc();
+    if direction == counters::SENT_LABEL {
+        counters::LIBRA_NETWORK_PEER_TRAFFIC.record_sent(peer_id, bytes);
+    } else {
+        counters::LIBRA_NETWORK_PEER_TRAFFIC.record_received(peer_id, bytes);
+    }
+}
+
 pub struct PeerHandle {
     peer_id: PeerId,
     sender: channel::Sender<PeerRequest>,
@@ -5,12 +5,17 @@ use crate::{
     peer::{DisconnectReason, Peer, PeerHandle, PeerNotification},
     protocols::wire::{
         handshake::v1::MessagingProtocolVersion,
-        messaging::v1::{DirectSendMsg, NetworkMessage},
+        messaging::v1::{
+            DirectSendMsg, NetworkMessage, RpcRequest, TraceContext, TracedRpcRequest,
+        },
     },
+    quota::{NetworkQuota, QuotaLimits},
+    rate_limit::{InboundRateLimits, RateLimit, RateLimitPolicy},
     transport::{Connection, ConnectionId, ConnectionMetadata},
     ProtocolId,
 };
 use futures::{future::join, io::AsyncWriteExt, stream::StreamExt, SinkExt};
+use libra_config::network_id::NetworkId;
 use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
 use memsocket::MemorySocket;
@@ -34,6 +39,21 @@ fn build_test_peer(
     channel::Receiver<PeerNotification>,
     channel::Receiver<PeerNotification>,
     channel::Receiver<PeerNotification>,
+) {
+    build_test_peer_with_rate_limits(executor, origin, InboundRateLimits::default())
+}
+
+fn build_test_peer_with_rate_limits(
+    executor: Handle,
+    origin: ConnectionOrigin,
+    inbound_rate_limits: InboundRateLimits,
+) -> (
+    Peer<MemorySocket>,
+    PeerHandle,
+    MemorySocket,
+    channel::Receiver<PeerNotification>,
+    channel::Receiver<PeerNotification>,
+    channel::Receiver<PeerNotification>,
 ) {
     let (a, b) = MemorySocket::new_pair();
     let peer_id = PeerId::random();
@@ -49,6 +69,7 @@ fn build_test_peer(
             origin,
             MessagingProtocolVersion::V1,
             [].iter().into(),
+            NetworkId::Validator,
         ),
         socket: a,
     };
@@ -60,6 +81,8 @@ fn build_test_peer(
         peer_notifs_tx,
         peer_rpc_notifs_tx,
         peer_direct_send_notifs_tx,
+        NetworkQuota::new(&NetworkId::Validator, QuotaLimits::default()).inbound_message_bytes,
+        inbound_rate_limits,
     );
     let peer_handle = PeerHandle::new(peer_id, peer_req_tx);
 
@@ -130,7 +153,7 @@ fn build_test_connected_peers(
 async fn assert_new_message_event(peer_notifs_rx: &mut channel::Receiver<PeerNotification>) {
     let event = peer_notifs_rx.next().await;
     assert!(
-        matches!(event, Some(PeerNotification::NewMessage(_))),
+        matches!(event, Some(PeerNotification::NewMessage(_, _))),
         "Expected NewMessage event. Received: {:?}",
         event
     );
@@ -192,6 +215,54 @@ fn peer_send_message() {
     rt.block_on(join(server, client));
 }
 
+#[test]
+fn peer_send_traced_rpc_request_without_traced_rpcs() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    // The test peers negotiated MessagingProtocolVersion V1.
+    let (
+        peer,
+        mut peer_handle,
+        connection,
+        _peer_notifs_rx,
+        _peer_rpc_notifs_rx,
+        _peer_direct_send_notifs_rx,
+    ) = build_test_peer(rt.handle().clone(), ConnectionOrigin::Inbound);
+
+    let request = RpcRequest {
+        request_id: 0,
+        protocol_id: ProtocolId::ConsensusRpc,
+        priority: 0,
+        raw_request: Vec::from("hello world"),
+    };
+    let send_msg = NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+        request: request.clone(),
+        trace_context: TraceContext {
+            trace_id: 1,
+            span_id: 2,
+        },
+    });
+
+    let server = async move {
+        // The client should send the bare request.
+        let mut connection = Framed::new(IoCompat::new(connection), LengthDelimitedCodec::new());
+        let msg = connection.next().await.unwrap();
+        let msg: NetworkMessage = lcs::from_bytes(&msg.unwrap().freeze()).unwrap();
+        assert_eq!(msg, NetworkMessage::RpcRequest(request));
+        connection.close().await.unwrap();
+    };
+
+    let client = async move {
+        peer_handle
+            .send_message(send_msg, ProtocolId::ConsensusRpc)
+            .await
+            .unwrap();
+        ManuallyDrop::new(peer_handle);
+    };
+    rt.spawn(peer.start());
+    rt.block_on(join(server, client));
+}
+
 #[test]
 fn peer_recv_message() {
     ::libra_logger::Logger::new().environment_only(true).init();
@@ -230,7 +301,73 @@ fn peer_recv_message() {
             // Wait to receive notification of DirectSendMsg from Peer.
             let received = peer_direct_send_notifs_rx.next().await.unwrap();
             assert!(
-                matches!(received, PeerNotification::NewMessage(received_msg) if received_msg == recv_msg)
+                matches!(received, PeerNotification::NewMessage(received_msg, _) if received_msg == recv_msg)
+            );
+        }
+    };
+    rt.spawn(peer.start());
+    rt.block_on(join(server, client));
+}
+
+#[test]
+fn peer_recv_message_rate_limited() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let mut inbound_rate_limits = InboundRateLimits::default();
+    inbound_rate_limits.protocol_limits.insert(
+        PROTOCOL,
+        RateLimit {
+            messages_per_sec: 0,
+            burst: 2,
+        },
+    );
+    inbound_rate_limits.policy = RateLimitPolicy::Drop;
+    let (
+        peer,
+        _peer_handle,
+        connection,
+        _peer_notifs_rx,
+        _peer_rpc_notifs_rx,
+        mut peer_direct_send_notifs_rx,
+    ) = build_test_peer_with_rate_limits(
+        rt.handle().clone(),
+        ConnectionOrigin::Inbound,
+        inbound_rate_limits,
+    );
+
+    let limited_msg = NetworkMessage::DirectSendMsg(DirectSendMsg {
+        protocol_id: PROTOCOL,
+        priority: 0,
+        raw_msg: Vec::from("hello world"),
+    });
+    let unlimited_msg = NetworkMessage::DirectSendMsg(DirectSendMsg {
+        protocol_id: ProtocolId::ConsensusDirectSend,
+        priority: 0,
+        raw_msg: Vec::from("hello world"),
+    });
+    let expected_msgs = vec![
+        limited_msg.clone(),
+        limited_msg.clone(),
+        unlimited_msg.clone(),
+    ];
+
+    let server = async move {
+        let mut connection = Framed::new(IoCompat::new(connection), LengthDelimitedCodec::new());
+        // Only the first 2 messages of PROTOCOL are allowed, the others are dropped.
+        for msg in vec![limited_msg; 5].into_iter().chain(Some(unlimited_msg)) {
+            connection
+                .send(lcs::to_bytes(&msg).unwrap().into())
+                .await
+                .unwrap();
+        }
+        connection.close().await.unwrap();
+    };
+
+    let client = async move {
+        for expected_msg in expected_msgs {
+            let received = peer_direct_send_notifs_rx.next().await.unwrap();
+            assert!(
+                matches!(received, PeerNotification::NewMessage(received_msg, _) if received_msg == expected_msg)
             );
         }
     };
@@ -23,7 +23,7 @@ pub fn new() -> (Sender, Receiver) {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::peer::DisconnectReason;
+    use crate::{peer::DisconnectReason, peer_manager::ConnectionInfo};
     use futures::{executor::block_on, future::FutureExt, stream::StreamExt};
     use libra_network_address::NetworkAddress;
 
@@ -36,7 +36,11 @@ mod test {
             sender
                 .push(
                     peer_id_a,
-                    ConnectionNotification::NewPeer(peer_id_a, NetworkAddress::mock()),
+                    ConnectionNotification::NewPeer(
+                        peer_id_a,
+                        NetworkAddress::mock(),
+                        ConnectionInfo::mock(),
+                    ),
                 )
                 .unwrap();
             sender
@@ -52,7 +56,11 @@ mod test {
             sender
                 .push(
                     peer_id_a,
-                    ConnectionNotification::NewPeer(peer_id_a, NetworkAddress::mock()),
+                    ConnectionNotification::NewPeer(
+                        peer_id_a,
+                        NetworkAddress::mock(),
+                        ConnectionInfo::mock(),
+                    ),
                 )
                 .unwrap();
             sender
@@ -80,13 +88,21 @@ mod test {
             sender
                 .push(
                     peer_id_a,
-                    ConnectionNotification::NewPeer(peer_id_a, NetworkAddress::mock()),
+                    ConnectionNotification::NewPeer(
+                        peer_id_a,
+                        NetworkAddress::mock(),
+                        ConnectionInfo::mock(),
+                    ),
                 )
                 .unwrap();
             sender
                 .push(
                     peer_id_b,
-                    ConnectionNotification::NewPeer(peer_id_b, NetworkAddress::mock()),
+                    ConnectionNotification::NewPeer(
+                        peer_id_b,
+                        NetworkAddress::mock(),
+                        ConnectionInfo::mock(),
+                    ),
                 )
                 .unwrap();
             // Assert that we receive 2 updates, since they are sent for different peers.
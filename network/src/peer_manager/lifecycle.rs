@@ -0,0 +1,422 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The lifecycle of the connections with the peers, as an explicit state machine.
+//!
+//! Every peer is in one of the [`ConnectionState`]s, and moves between them on the following
+//! events:
+//!
+//! ```text
+//! Disconnected -> Dialing          the PeerManager asks the transport to dial the peer
+//! Disconnected -> Handshaking      an inbound connection authenticates the peer
+//! Dialing      -> Handshaking      the dialed connection is established, the handshakes start
+//! Handshaking  -> Connected        the PeerManager accepts the upgraded connection
+//! Connected    -> Draining         the PeerManager closes the connection, e.g., on request
+//! Draining     -> Disconnected     the connection is closed
+//! Connected    -> Disconnected     the connection is lost
+//! Dialing      -> Disconnected     the dial fails
+//! Handshaking  -> Disconnected     the handshakes fail, or the PeerManager rejects the connection
+//! Draining     -> Dialing          the peer is dialed again while its connection drains
+//! Draining     -> Handshaking      the peer connects again while its connection drains
+//! ```
+//!
+//! The PeerManager moves the peers through their lifecycle as it dials them, accepts, closes and
+//! loses their connections, and the transport as it upgrades their connections. The transitions
+//! are published to the subscribers of [`PeerLifecycles`], and the number of peers in each state
+//! and the time they spend in it are exported as metrics, so that the connections stuck in a
+//! state show up.
+//!
+//! Several connection attempts may be in flight for a peer, e.g., raced dials, or a dial crossing
+//! an inbound connection: a peer is in the state of its most advanced connection. A failed attempt
+//! doesn't move a peer which is connected, nor does the end of a drained connection move a peer
+//! which is being dialed again. The events which the state of a peer doesn't allow, e.g., closing
+//! the connection of a peer which isn't connected, are invariant violations: they are logged and
+//! counted in `libra_network_peer_invalid_transitions`, and leave the state unchanged.
+
+use crate::counters;
+use channel::{libra_channel, message_queues::QueueStyle};
+use libra_logger::prelude::*;
+use libra_types::PeerId;
+use std::{
+    collections::HashMap,
+    mem,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Number of transitions of a peer kept for a lagging subscriber, which misses the oldest first.
+const SUBSCRIPTION_QUEUE_SIZE: usize = 16;
+
+/// The state of the connections with a peer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ConnectionState {
+    /// No connection with the peer, nor any attempt.
+    Disconnected,
+    /// An outbound connection with the peer is being established.
+    Dialing,
+    /// A connection with the peer is established, and its Noise and LibraNet handshakes run.
+    Handshaking,
+    /// The PeerManager accepted a connection with the peer.
+    Connected,
+    /// The connection with the peer is being closed, flushing its pending messages.
+    Draining,
+}
+
+impl ConnectionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Dialing => "dialing",
+            ConnectionState::Handshaking => "handshaking",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Draining => "draining",
+        }
+    }
+}
+
+/// What happened to the connections with a peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum LifecycleEvent {
+    /// The PeerManager asked the transport to dial the peer.
+    DialRequested,
+    /// A connection with the peer is established, and its handshakes start.
+    HandshakeStarted,
+    /// A dial or handshake failed, or the PeerManager rejected the connection.
+    AttemptFailed,
+    /// The PeerManager accepted a connection with the peer.
+    Established,
+    /// The PeerManager closes the connection with the peer.
+    Closing,
+    /// The last connection with the peer is closed.
+    Closed,
+}
+
+impl LifecycleEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            LifecycleEvent::DialRequested => "dial_requested",
+            LifecycleEvent::HandshakeStarted => "handshake_started",
+            LifecycleEvent::AttemptFailed => "attempt_failed",
+            LifecycleEvent::Established => "established",
+            LifecycleEvent::Closing => "closing",
+            LifecycleEvent::Closed => "closed",
+        }
+    }
+}
+
+/// A change of the state of a peer, published to the subscribers of [`PeerLifecycles`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StateTransition {
+    pub peer_id: PeerId,
+    pub from: ConnectionState,
+    pub to: ConnectionState,
+}
+
+/// The state of a peer in `state` after `event`: `Ok(None)` if the event doesn't move the peer,
+/// as another connection attempt is further along, or `Err(())` if `state` doesn't allow it.
+fn next_state(
+    state: ConnectionState,
+    event: LifecycleEvent,
+) -> Result<Option<ConnectionState>, ()> {
+    use ConnectionState::*;
+    use LifecycleEvent::*;
+
+    match (state, event) {
+        (Disconnected, DialRequested) | (Draining, DialRequested) => Ok(Some(Dialing)),
+        (Dialing, DialRequested) | (Handshaking, DialRequested) => Ok(None),
+        (Disconnected, HandshakeStarted)
+        | (Dialing, HandshakeStarted)
+        | (Draining, HandshakeStarted) => Ok(Some(Handshaking)),
+        (Handshaking, HandshakeStarted) | (Connected, HandshakeStarted) => Ok(None),
+        (Dialing, AttemptFailed) | (Handshaking, AttemptFailed) => Ok(Some(Disconnected)),
+        (_, AttemptFailed) => Ok(None),
+        (Handshaking, Established) => Ok(Some(Connected)),
+        (Connected, Established) => Ok(None),
+        (Connected, Closing) => Ok(Some(Draining)),
+        (Draining, Closing) => Ok(None),
+        (Connected, Closed) | (Draining, Closed) => Ok(Some(Disconnected)),
+        (_, Closed) => Ok(None),
+        _ => Err(()),
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// The peers which aren't disconnected, along with their state and when they entered it.
+    states: HashMap<PeerId, (ConnectionState, Instant)>,
+    /// Senders of the transitions to the subscribers.
+    subscribers: Vec<libra_channel::Sender<PeerId, StateTransition>>,
+}
+
+/// The states of the connections with the peers, maintained by the PeerManager and the transport
+/// of a network. Cloning it returns a handle to the same states.
+#[derive(Clone, Default)]
+pub struct PeerLifecycles {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl PeerLifecycles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the state of the connections with `peer_id`.
+    pub fn state(&self, peer_id: &PeerId) -> ConnectionState {
+        self.inner
+            .lock()
+            .unwrap()
+            .states
+            .get(peer_id)
+            .map_or(ConnectionState::Disconnected, |(state, _)| *state)
+    }
+
+    /// Returns the states of the peers which aren't disconnected.
+    pub fn states(&self) -> HashMap<PeerId, ConnectionState> {
+        self.inner
+            .lock()
+            .unwrap()
+            .states
+            .iter()
+            .map(|(peer_id, (state, _))| (*peer_id, *state))
+            .collect()
+    }
+
+    /// Returns a receiver of the transitions of the peers from now on, by peer.
+    pub fn subscribe(&self) -> libra_channel::Receiver<PeerId, StateTransition> {
+        let (transitions_tx, transitions_rx) = libra_channel::new(
+            QueueStyle::KLAST,
+            NonZeroUsize::new(SUBSCRIPTION_QUEUE_SIZE).unwrap(),
+            None,
+        );
+        self.inner.lock().unwrap().subscribers.push(transitions_tx);
+        transitions_rx
+    }
+
+    /// Moves `peer_id` to the state following `event`, if any.
+    pub(crate) fn handle_event(&self, peer_id: PeerId, event: LifecycleEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        let (from, since) = inner
+            .states
+            .get(&peer_id)
+            .copied()
+            .unwrap_or((ConnectionState::Disconnected, Instant::now()));
+        let to = match next_state(from, event) {
+            Ok(Some(to)) => to,
+            Ok(None) => return,
+            Err(()) => {
+                error!(
+                    "Invalid {:?} event for Peer {} in state {:?}",
+                    event,
+                    peer_id.short_str(),
+                    from
+                );
+                counters::LIBRA_NETWORK_PEER_INVALID_TRANSITIONS
+                    .with_label_values(&[from.as_str(), event.as_str()])
+                    .inc();
+                return;
+            }
+        };
+
+        trace!(
+            "Peer {} moves from {:?} to {:?} on {:?}",
+            peer_id.short_str(),
+            from,
+            to,
+            event
+        );
+        if from != ConnectionState::Disconnected {
+            counters::LIBRA_NETWORK_PEER_CONNECTION_STATES
+                .with_label_values(&[from.as_str()])
+                .dec();
+            counters::LIBRA_NETWORK_PEER_CONNECTION_STATE_DURATION
+                .with_label_values(&[from.as_str()])
+                .observe(since.elapsed().as_secs_f64());
+        }
+        if to == ConnectionState::Disconnected {
+            inner.states.remove(&peer_id);
+        } else {
+            counters::LIBRA_NETWORK_PEER_CONNECTION_STATES
+                .with_label_values(&[to.as_str()])
+                .inc();
+            inner.states.insert(peer_id, (to, Instant::now()));
+        }
+
+        // The subscribers which went away are dropped.
+        let transition = StateTransition { peer_id, from, to };
+        inner.subscribers = mem::take(&mut inner.subscribers)
+            .into_iter()
+            .filter_map(|mut subscriber| {
+                subscriber
+                    .push(peer_id, transition)
+                    .ok()
+                    .map(|()| subscriber)
+            })
+            .collect();
+    }
+
+    /// Moves `peer_id` to [`ConnectionState::Handshaking`], and back to
+    /// [`ConnectionState::Disconnected`] if the handshake doesn't complete, i.e., if the returned
+    /// guard is dropped without [`HandshakeGuard::complete`], e.g., on an error or timeout.
+    pub(crate) fn handshake_started(&self, peer_id: PeerId) -> HandshakeGuard {
+        self.handle_event(peer_id, LifecycleEvent::HandshakeStarted);
+        HandshakeGuard {
+            lifecycles: self.clone(),
+            peer_id,
+            completed: false,
+        }
+    }
+
+    /// Moves `peer_id` to [`ConnectionState::Connected`], through
+    /// [`ConnectionState::Handshaking`] if the transport of the connection didn't report its
+    /// handshake.
+    pub(crate) fn established(&self, peer_id: PeerId) {
+        self.handle_event(peer_id, LifecycleEvent::HandshakeStarted);
+        self.handle_event(peer_id, LifecycleEvent::Established);
+    }
+}
+
+/// Fails the connection attempt of a peer when dropped before its handshake completes.
+#[must_use]
+pub(crate) struct HandshakeGuard {
+    lifecycles: PeerLifecycles,
+    peer_id: PeerId,
+    completed: bool,
+}
+
+impl HandshakeGuard {
+    /// Leaves the peer in [`ConnectionState::Handshaking`], until the PeerManager accepts or
+    /// rejects the connection.
+    pub fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for HandshakeGuard {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.lifecycles
+                .handle_event(self.peer_id, LifecycleEvent::AttemptFailed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::{ConnectionState::*, LifecycleEvent::*};
+    use futures::{executor::block_on, future::FutureExt, stream::StreamExt};
+
+    fn transition(peer_id: PeerId, from: ConnectionState, to: ConnectionState) -> StateTransition {
+        StateTransition { peer_id, from, to }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let lifecycles = PeerLifecycles::new();
+        let mut transitions_rx = lifecycles.subscribe();
+        let peer_id = PeerId::random();
+        let mut next_transition = || transitions_rx.next().now_or_never().flatten();
+
+        // A dial, its handshake and the connection.
+        lifecycles.handle_event(peer_id, DialRequested);
+        let handshake = lifecycles.handshake_started(peer_id);
+        assert_eq!(lifecycles.state(&peer_id), Handshaking);
+        handshake.complete();
+        lifecycles.established(peer_id);
+        assert_eq!(
+            lifecycles.states(),
+            [(peer_id, Connected)]
+                .iter()
+                .cloned()
+                .collect::<HashMap<_, _>>()
+        );
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Disconnected, Dialing))
+        );
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Dialing, Handshaking))
+        );
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Handshaking, Connected))
+        );
+
+        // The failed attempts of a connected peer, e.g., an inbound connection crossing the dial,
+        // don't move it.
+        drop(lifecycles.handshake_started(peer_id));
+        lifecycles.handle_event(peer_id, AttemptFailed);
+        assert_eq!(lifecycles.state(&peer_id), Connected);
+        assert_eq!(next_transition(), None);
+
+        // Its connection is closed.
+        lifecycles.handle_event(peer_id, Closing);
+        lifecycles.handle_event(peer_id, Closed);
+        assert_eq!(lifecycles.state(&peer_id), Disconnected);
+        assert!(lifecycles.states().is_empty());
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Connected, Draining))
+        );
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Draining, Disconnected))
+        );
+
+        // A handshake which doesn't complete fails the attempt.
+        drop(lifecycles.handshake_started(peer_id));
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Disconnected, Handshaking))
+        );
+        assert_eq!(
+            next_transition(),
+            Some(transition(peer_id, Handshaking, Disconnected))
+        );
+
+        // Invalid events leave the state unchanged.
+        lifecycles.handle_event(peer_id, Closing);
+        assert_eq!(lifecycles.state(&peer_id), Disconnected);
+        assert_eq!(next_transition(), None);
+    }
+
+    #[test]
+    fn redial_while_draining() {
+        let lifecycles = PeerLifecycles::new();
+        let peer_id = PeerId::random();
+        lifecycles.established(peer_id);
+        lifecycles.handle_event(peer_id, Closing);
+
+        // The end of the drained connection doesn't move the peer being dialed again.
+        lifecycles.handle_event(peer_id, DialRequested);
+        lifecycles.handle_event(peer_id, Closed);
+        assert_eq!(lifecycles.state(&peer_id), Dialing);
+        lifecycles.handle_event(peer_id, AttemptFailed);
+        assert_eq!(lifecycles.state(&peer_id), Disconnected);
+    }
+
+    #[test]
+    fn invalid_events() {
+        assert_eq!(next_state(Connected, DialRequested), Err(()));
+        assert_eq!(next_state(Dialing, Established), Err(()));
+        assert_eq!(next_state(Draining, Established), Err(()));
+        assert_eq!(next_state(Disconnected, Closing), Err(()));
+        assert_eq!(next_state(Handshaking, Closing), Err(()));
+    }
+
+    #[test]
+    fn dropped_subscribers() {
+        let lifecycles = PeerLifecycles::new();
+        drop(lifecycles.subscribe());
+        let mut transitions_rx = lifecycles.subscribe();
+        let peer_id = PeerId::random();
+        lifecycles.handle_event(peer_id, DialRequested);
+        assert_eq!(lifecycles.inner.lock().unwrap().subscribers.len(), 1);
+        assert_eq!(
+            block_on(transitions_rx.next()),
+            Some(transition(peer_id, Disconnected, Dialing))
+        );
+    }
+}
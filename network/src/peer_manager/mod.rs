@@ -10,28 +10,59 @@
 //!  * A main event loop actor which is responsible for handling requests and sending
 //!  notification about new/lost Peers to the rest of the network stack.
 //!  * An actor responsible for dialing and listening for new connections.
+//!  * An actor per additional listener, e.g., accepting the peers of another network with a
+//!  different authentication mode, see [`PeerManager::add_listener`].
+//!
+//! Protocol handlers can be registered after the PeerManager starts, see
+//! [`PeerManager::protocol_handlers_sender`].
+//!
+//! On shutdown, see [`PeerManager::shutdown_sender`], the main event loop aborts the listening
+//! actors, waits for the RPCs in flight to complete, then closes the connections with all the
+//! peers, until the deadline of the shutdown.
+//!
+//! The state of the connection with every peer, from its dial to its close, is tracked by
+//! [`lifecycle::PeerLifecycles`], see [`lifecycle`] for the transitions.
 use crate::{
+    ban_list::BanList,
+    connection_limits::{
+        InboundConnectionGate, InboundConnectionLimiter, InboundConnectionLimits,
+        InboundConnectionPermit,
+    },
     counters,
+    error::NetworkError,
+    eviction::{EvictionCandidate, EvictionPolicy},
     interface::{NetworkNotification, NetworkProvider, NetworkRequest},
+    network_events::{NetworkEvent, NetworkEvents},
+    network_info::NetworkInfo,
+    observed_addrs::ObservedAddrs,
     peer::DisconnectReason,
+    priority::ProtocolPriorities,
     protocols::{
         direct_send::Message,
         rpc::{error::RpcError, InboundRpcRequest, OutboundRpcRequest},
+        wire::handshake::v1::{
+            Encoding, MessagingProtocolVersion, ProtocolEncodings, SupportedProtocols,
+        },
     },
+    quota::{NetworkQuota, QuotaLimits, QuotaPermit},
+    rate_limit::InboundRateLimits,
     transport,
-    transport::{Connection, ConnectionId, ConnectionMetadata},
+    transport::{
+        ApplicationProtocols, Connection, ConnectionId, ConnectionMetadata, HandshakeError,
+    },
+    trusted_peers::TrustedPeersDiff,
     ProtocolId,
 };
 use bytes::Bytes;
 use channel::{self, libra_channel};
 use futures::{
-    channel::oneshot,
-    future::{BoxFuture, FutureExt},
+    channel::{mpsc, oneshot},
+    future::{abortable, AbortHandle, Aborted, BoxFuture, FutureExt},
     io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     sink::SinkExt,
-    stream::{Fuse, FuturesUnordered, StreamExt},
+    stream::{self, Fuse, FuturesUnordered, SelectAll, StreamExt},
 };
-use libra_config::config::RoleType;
+use libra_config::{config::RoleType, network_id::NetworkId};
 use libra_logger::prelude::*;
 use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
@@ -39,34 +70,47 @@ use netcore::transport::{ConnectionOrigin, Transport};
 use std::{
     collections::{hash_map::Entry, HashMap},
     fmt::Debug,
+    io,
     marker::PhantomData,
-    time::Duration,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
-use tokio::runtime::Handle;
+use tokio::{runtime::Handle, task::JoinHandle};
 
 pub mod conn_notifs_channel;
 mod error;
+pub mod lifecycle;
+pub mod request_trace;
 #[cfg(test)]
 mod tests;
 
 pub use self::error::PeerManagerError;
+use self::{
+    lifecycle::{LifecycleEvent, PeerLifecycles},
+    request_trace::RequestTrace,
+};
+
+/// Interval of the checks of the completion of the steps of a shutdown.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
-/// Request received by PeerManager from upstream actors.
+/// Request received by PeerManager from upstream actors, along with its trace.
 #[derive(Debug)]
 pub enum PeerManagerRequest {
     /// Send an RPC request to a remote peer.
-    SendRpc(PeerId, OutboundRpcRequest),
+    SendRpc(PeerId, OutboundRpcRequest, RequestTrace),
     /// Fire-and-forget style message send to a remote peer.
-    SendMessage(PeerId, Message),
+    SendMessage(PeerId, Message, RequestTrace),
 }
 
 /// Notifications sent by PeerManager to upstream actors.
 #[derive(Debug)]
 pub enum PeerManagerNotification {
-    /// A new RPC request has been received from a remote peer.
-    RecvRpc(PeerId, InboundRpcRequest),
-    /// A new message has been received from a remote peer.
-    RecvMessage(PeerId, Message),
+    /// A new RPC request has been received from a remote peer, encoded with the encoding of its
+    /// protocol negotiated on the connection, with which the response must be encoded too.
+    RecvRpc(PeerId, InboundRpcRequest, Encoding),
+    /// A new message has been received from a remote peer, encoded with the encoding of its
+    /// protocol negotiated on the connection.
+    RecvMessage(PeerId, Message, Encoding),
 }
 
 #[derive(Debug)]
@@ -82,9 +126,65 @@ pub enum ConnectionRequest {
 #[derive(Debug, PartialEq, Eq)]
 pub enum ConnectionNotification {
     /// Connection with a new peer has been established.
-    NewPeer(PeerId, NetworkAddress),
+    NewPeer(PeerId, NetworkAddress, ConnectionInfo),
     /// Connection to a peer has been terminated. This could have been triggered from either end.
     LostPeer(PeerId, NetworkAddress, DisconnectReason),
+    /// The trusted peers of the network have been updated. Keyed by our own peer id, so that a
+    /// listener lagging behind only receives the last update.
+    TrustedPeersUpdated(TrustedPeersDiff),
+}
+
+/// What was negotiated on the connection with a new peer, so that the applications can choose
+/// per peer between the full protocol and a degraded behavior.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Network the connection was established for
+    pub network_id: NetworkId,
+    /// Role of the network the connection was established for
+    pub role: RoleType,
+    pub origin: ConnectionOrigin,
+    /// Version of the handshake agreed on with the peer
+    pub messaging_protocol: MessagingProtocolVersion,
+    /// Application protocols supported by both ends of the connection
+    pub application_protocols: SupportedProtocols,
+    /// Encodings of the application protocols negotiated on the connection
+    pub encodings: ProtocolEncodings,
+}
+
+impl ConnectionInfo {
+    pub fn new(conn_meta: &ConnectionMetadata, role: RoleType) -> Self {
+        Self {
+            network_id: conn_meta.network_id().clone(),
+            role,
+            origin: conn_meta.origin(),
+            messaging_protocol: conn_meta.messaging_protocol(),
+            application_protocols: conn_meta.application_protocols().clone(),
+            encodings: conn_meta.encodings().clone(),
+        }
+    }
+
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub fn mock() -> Self {
+        Self {
+            network_id: NetworkId::Validator,
+            role: RoleType::Validator,
+            origin: ConnectionOrigin::Outbound,
+            messaging_protocol: MessagingProtocolVersion::V1,
+            application_protocols: SupportedProtocols::default(),
+            encodings: ProtocolEncodings::default(),
+        }
+    }
+}
+
+/// A protocol handler registered after the PeerManager started, see
+/// [`PeerManager::protocol_handlers_sender`].
+pub struct ProtocolHandlerRegistration {
+    /// The protocols handled.
+    pub protocols: Vec<ProtocolId>,
+    /// Receives the messages of the protocols.
+    pub handler: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    /// Receives the NewPeer/LostPeer notifications.
+    pub connection_event_handler: conn_notifs_channel::Sender,
 }
 
 /// Convenience wrapper which makes it easy to issue communication requests and await the responses
@@ -92,6 +192,10 @@ pub enum ConnectionNotification {
 #[derive(Clone)]
 pub struct PeerManagerRequestSender {
     inner: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerRequest>,
+    /// Publisher of the messages dropped as their queue was full.
+    events: NetworkEvents,
+    /// Encodings negotiated with the peers, which the messages to them are encoded with.
+    encodings: PeerEncodings,
 }
 
 /// Convenience wrapper which makes it easy to issue connection requests and await the responses
@@ -104,7 +208,50 @@ pub struct ConnectionRequestSender {
 impl PeerManagerRequestSender {
     /// Construct a new PeerManagerRequestSender with a raw channel::Sender
     pub fn new(inner: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerRequest>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            events: NetworkEvents::new(),
+            encodings: PeerEncodings::new(),
+        }
+    }
+
+    /// Publishes the direct-send messages dropped as their queue was full to `events`. The RPCs
+    /// dropped fail with [`NetworkError::QueueFull`] instead.
+    pub fn with_events(mut self, events: NetworkEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Looks the encodings of the messages to the peers up in `encodings`, the view of the
+    /// PeerManager. All the messages are encoded with LCS otherwise.
+    pub fn with_peer_encodings(mut self, encodings: PeerEncodings) -> Self {
+        self.encodings = encodings;
+        self
+    }
+
+    /// Returns the encoding of the messages of `protocol` to `peer_id`.
+    pub fn encoding(&self, peer_id: &PeerId, protocol: ProtocolId) -> Encoding {
+        self.encodings.get(peer_id, protocol)
+    }
+
+    fn push_message(
+        &mut self,
+        peer_id: PeerId,
+        protocol: ProtocolId,
+        request: PeerManagerRequest,
+    ) -> Result<(), NetworkError> {
+        let count = self
+            .inner
+            .push_counting_drops((peer_id, protocol), request)
+            .map_err(|_| NetworkError::ShuttingDown)?;
+        if count > 0 {
+            self.events.publish(NetworkEvent::MessagesDropped {
+                peer_id,
+                protocol,
+                count,
+            });
+        }
+        Ok(())
     }
 
     /// Send a fire-and-forget direct-send message to remote peer.
@@ -117,12 +264,19 @@ impl PeerManagerRequestSender {
         peer_id: PeerId,
         protocol: ProtocolId,
         mdata: Bytes,
-    ) -> Result<(), PeerManagerError> {
-        self.inner.push(
-            (peer_id, protocol),
-            PeerManagerRequest::SendMessage(peer_id, Message { protocol, mdata }),
-        )?;
-        Ok(())
+    ) -> Result<(), NetworkError> {
+        let trace = RequestTrace::start();
+        trace!(
+            "Enqueuing {} of message for protocol {:?} to peer {}",
+            trace,
+            protocol,
+            peer_id.short_str()
+        );
+        self.push_message(
+            peer_id,
+            protocol,
+            PeerManagerRequest::SendMessage(peer_id, Message { protocol, mdata }, trace),
+        )
     }
 
     /// Send the _same_ message to many recipients using the direct-send protocol.
@@ -141,16 +295,24 @@ impl PeerManagerRequestSender {
         recipients: impl Iterator<Item = PeerId>,
         protocol: ProtocolId,
         mdata: Bytes,
-    ) -> Result<(), PeerManagerError> {
+    ) -> Result<(), NetworkError> {
         let msg = Message { protocol, mdata };
         for recipient in recipients {
             // We return `Err` early here if the send fails. Since sending will
             // only fail if the queue is unexpectedly shutdown (i.e., receiver
             // dropped early), we know that we can't make further progress if
             // this send fails.
-            self.inner.push(
-                (recipient, protocol),
-                PeerManagerRequest::SendMessage(recipient, msg.clone()),
+            let trace = RequestTrace::start();
+            trace!(
+                "Enqueuing {} of message for protocol {:?} to peer {}",
+                trace,
+                protocol,
+                recipient.short_str()
+            );
+            self.push_message(
+                recipient,
+                protocol,
+                PeerManagerRequest::SendMessage(recipient, msg.clone(), trace),
             )?;
         }
         Ok(())
@@ -163,7 +325,7 @@ impl PeerManagerRequestSender {
         protocol: ProtocolId,
         req: Bytes,
         timeout: Duration,
-    ) -> Result<Bytes, RpcError> {
+    ) -> Result<Bytes, NetworkError> {
         let (res_tx, res_rx) = oneshot::channel();
         let request = OutboundRpcRequest {
             protocol,
@@ -171,11 +333,31 @@ impl PeerManagerRequestSender {
             res_tx,
             timeout,
         };
-        self.inner.push(
-            (peer_id, protocol),
-            PeerManagerRequest::SendRpc(peer_id, request),
-        )?;
-        res_rx.await?
+        // Once the timeout elapses the caller has given up on the response, so there is no point
+        // in having the PeerManager forward the request if it is still queued by then.
+        let deadline = Instant::now() + timeout;
+        let trace = RequestTrace::start();
+        trace!(
+            "Enqueuing {} of rpc for protocol {:?} to peer {}",
+            trace,
+            protocol,
+            peer_id.short_str()
+        );
+        self.inner
+            .push_with_deadline(
+                (peer_id, protocol),
+                PeerManagerRequest::SendRpc(peer_id, request, trace),
+                deadline,
+            )
+            .map_err(|_| NetworkError::ShuttingDown)?;
+        match res_rx.await {
+            Ok(res) => Ok(res?),
+            // The request expired before it could be handed to the rpc layer.
+            Err(oneshot::Canceled) if Instant::now() >= deadline => Err(NetworkError::Timeout),
+            // Otherwise it was dropped on its way to the peer, by a full queue or, rarely, by the
+            // connection going away before the request reached it.
+            Err(oneshot::Canceled) => Err(NetworkError::QueueFull),
+        }
     }
 }
 
@@ -204,6 +386,67 @@ impl ConnectionRequestSender {
     }
 }
 
+/// The encodings negotiated for the application protocols on the connections with the peers,
+/// maintained by the PeerManager, for the applications to encode their messages to a peer with.
+/// Cloning it returns a handle to the same view.
+#[derive(Clone, Debug, Default)]
+pub struct PeerEncodings {
+    inner: Arc<RwLock<HashMap<PeerId, ProtocolEncodings>>>,
+}
+
+impl PeerEncodings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the encoding of the messages of `protocol` on the connection with `peer_id`, LCS
+    /// if it isn't connected.
+    pub fn get(&self, peer_id: &PeerId, protocol: ProtocolId) -> Encoding {
+        self.inner
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .map(|encodings| encodings.get(protocol))
+            .unwrap_or_default()
+    }
+
+    fn insert(&self, peer_id: PeerId, encodings: ProtocolEncodings) {
+        self.inner.write().unwrap().insert(peer_id, encodings);
+    }
+
+    fn remove(&self, peer_id: &PeerId) {
+        self.inner.write().unwrap().remove(peer_id);
+    }
+}
+
+/// When the last message was received from each of the connected peers, maintained by the
+/// PeerManager, e.g., for the health checker to spare the peers which are evidently alive.
+/// Cloning it returns a handle to the same view.
+#[derive(Clone, Debug, Default)]
+pub struct PeerActivity {
+    inner: Arc<RwLock<HashMap<PeerId, Instant>>>,
+}
+
+impl PeerActivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns when the last message was received from `peer_id`, if any since it connected.
+    pub fn last_received(&self, peer_id: &PeerId) -> Option<Instant> {
+        self.inner.read().unwrap().get(peer_id).copied()
+    }
+
+    /// Records that a message was just received from `peer_id`.
+    pub(crate) fn record(&self, peer_id: PeerId) {
+        self.inner.write().unwrap().insert(peer_id, Instant::now());
+    }
+
+    fn remove(&self, peer_id: &PeerId) {
+        self.inner.write().unwrap().remove(peer_id);
+    }
+}
+
 /// Responsible for handling and maintaining connections to other Peers
 pub struct PeerManager<TTransport, TSocket>
 where
@@ -216,10 +459,24 @@ where
     own_peer_id: PeerId,
     /// Our node type.
     role: RoleType,
-    /// Address to listen on for incoming connections.
-    listen_addr: NetworkAddress,
-    /// Connection Listener, listening on `listen_addr`
+    /// Addresses listened on for incoming connections.
+    listen_addrs: Vec<NetworkAddress>,
+    /// Connection Listener, listening on `listen_addrs`
     transport_handler: Option<TransportHandler<TTransport, TSocket>>,
+    /// Additional connection listeners, which only accept inbound connections.
+    listener_handlers: Vec<TransportHandler<TTransport, TSocket>>,
+    /// The running connection listeners, aborted on shutdown.
+    running_transport_handlers: Vec<(AbortHandle, JoinHandle<Result<(), Aborted>>)>,
+    /// Receiver of the deadline of the shutdown, see [`PeerManager::shutdown_sender`].
+    shutdown_rx: Option<oneshot::Receiver<Instant>>,
+    /// Whether the PeerManager is shutting down, closing the new connections.
+    shutting_down: bool,
+    /// Receiver of the protocol handlers registered at runtime, see
+    /// [`PeerManager::protocol_handlers_sender`].
+    protocol_handlers_rx: Option<mpsc::UnboundedReceiver<ProtocolHandlerRegistration>>,
+    /// The application protocols advertised in the handshakes of the transport, extended with the
+    /// protocols of the handlers registered at runtime.
+    application_protocols: Option<ApplicationProtocols>,
     /// Map from PeerId to corresponding Peer object.
     active_peers: HashMap<
         PeerId,
@@ -236,6 +493,9 @@ where
         HashMap<ProtocolId, libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>>,
     /// Channels to send NewPeer/LostPeer notifications to.
     connection_event_handlers: Vec<conn_notifs_channel::Sender>,
+    /// Channels to send NewPeer/LostPeer notifications to, for the connections established for
+    /// the network of an additional listener instead.
+    listener_event_handlers: HashMap<NetworkId, Vec<conn_notifs_channel::Sender>>,
     /// Channel used to send Dial requests to the ConnectionHandler actor
     transport_reqs_tx: channel::Sender<TransportRequest>,
     /// Sender for connection events.
@@ -255,6 +515,40 @@ where
     max_concurrent_network_notifs: usize,
     /// Size of channels between different actors.
     channel_size: usize,
+    /// Limits of the quota of every network.
+    quota_limits: QuotaLimits,
+    /// Quotas of the networks of the connections, by network.
+    quotas: HashMap<NetworkId, NetworkQuota>,
+    /// Reservations of the active inbound connections in the quotas of their network, along with
+    /// when the connections were established.
+    inbound_connection_permits: HashMap<ConnectionId, (QuotaPermit, Instant)>,
+    /// Chooses the inbound connections closed to make room for more desirable peers once the
+    /// inbound connection quota of their network is exhausted.
+    eviction_policy: Arc<dyn EvictionPolicy>,
+    /// Limits of the inbound connections of every listener.
+    inbound_connection_limits: InboundConnectionLimits,
+    /// Pauses the acceptance of inbound connections on every listener.
+    inbound_connection_gate: InboundConnectionGate,
+    /// Counts of the active inbound connections against the limits of their listener.
+    listener_connection_permits: HashMap<ConnectionId, InboundConnectionPermit>,
+    /// Peers and IP prefixes whose connections are closed, shared with the connectivity manager.
+    ban_list: BanList,
+    /// Rate limits of the inbound messages of every peer.
+    inbound_rate_limits: InboundRateLimits,
+    /// Priorities of the outbound messages of the protocols, shared with the network builder.
+    protocol_priorities: ProtocolPriorities,
+    /// Encodings negotiated on the connections of the active peers, shared with the applications.
+    peer_encodings: PeerEncodings,
+    /// Addresses of this node reported by the active peers, shared with discovery.
+    observed_addrs: ObservedAddrs,
+    /// When the last message was received from the active peers, shared with the health checker.
+    peer_activity: PeerActivity,
+    /// Connections with the active peers, shared with the network handle.
+    network_info: NetworkInfo,
+    /// States of the connections with the peers, shared with the transport and the applications.
+    lifecycles: PeerLifecycles,
+    /// Publisher of the events of the network, shared with the transport.
+    events: NetworkEvents,
 }
 
 impl<TTransport, TSocket> PeerManager<TTransport, TSocket>
@@ -269,7 +563,7 @@ where
         transport: TTransport,
         own_peer_id: PeerId,
         role: RoleType,
-        listen_addr: NetworkAddress,
+        listen_addrs: Vec<NetworkAddress>,
         requests_rx: libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
         connection_reqs_rx: libra_channel::Receiver<PeerId, ConnectionRequest>,
         upstream_handlers: HashMap<
@@ -280,6 +574,19 @@ where
         channel_size: usize,
         max_concurrent_network_reqs: usize,
         max_concurrent_network_notifs: usize,
+        quota_limits: QuotaLimits,
+        inbound_connection_limits: InboundConnectionLimits,
+        inbound_connection_gate: InboundConnectionGate,
+        eviction_policy: Arc<dyn EvictionPolicy>,
+        ban_list: BanList,
+        inbound_rate_limits: InboundRateLimits,
+        protocol_priorities: ProtocolPriorities,
+        peer_encodings: PeerEncodings,
+        observed_addrs: ObservedAddrs,
+        peer_activity: PeerActivity,
+        network_info: NetworkInfo,
+        lifecycles: PeerLifecycles,
+        events: NetworkEvents,
     ) -> Self {
         let (transport_notifs_tx, transport_notifs_rx) = channel::new(
             channel_size,
@@ -290,20 +597,32 @@ where
         //TODO now that you can only listen on a socket inside of a tokio runtime we'll need to
         // rethink how we init the PeerManager so we don't have to do this funny thing.
         let transport_notifs_tx_clone = transport_notifs_tx.clone();
-        let (transport_handler, listen_addr) = executor.enter(|| {
+        let (transport_handler, listen_addrs) = executor.enter(|| {
             TransportHandler::new(
                 transport,
-                listen_addr,
+                listen_addrs,
                 transport_reqs_rx,
                 transport_notifs_tx_clone,
+                InboundConnectionLimiter::new(
+                    inbound_connection_limits,
+                    inbound_connection_gate.clone(),
+                ),
+                lifecycles.clone(),
+                events.clone(),
             )
         });
         Self {
             executor,
             own_peer_id,
             role,
-            listen_addr,
+            listen_addrs,
             transport_handler: Some(transport_handler),
+            listener_handlers: Vec::new(),
+            running_transport_handlers: Vec::new(),
+            shutdown_rx: None,
+            shutting_down: false,
+            protocol_handlers_rx: None,
+            application_protocols: None,
             active_peers: HashMap::new(),
             requests_rx,
             connection_reqs_rx,
@@ -314,21 +633,117 @@ where
             phantom_transport: PhantomData,
             upstream_handlers,
             connection_event_handlers,
+            listener_event_handlers: HashMap::new(),
             max_concurrent_network_reqs,
             max_concurrent_network_notifs,
             channel_size,
+            quota_limits,
+            quotas: HashMap::new(),
+            inbound_connection_permits: HashMap::new(),
+            eviction_policy,
+            inbound_connection_limits,
+            inbound_connection_gate,
+            listener_connection_permits: HashMap::new(),
+            ban_list,
+            inbound_rate_limits,
+            protocol_priorities,
+            peer_encodings,
+            observed_addrs,
+            peer_activity,
+            network_info,
+            lifecycles,
+            events,
         }
     }
 
-    /// Get the [`NetworkAddress`] we're listening for incoming connections on
-    pub fn listen_addr(&self) -> &NetworkAddress {
-        &self.listen_addr
+    /// Also accept connections on `listen_addr` through `transport`, e.g., to authenticate the
+    /// peers of another network differently, while sharing this PeerManager. Return the address
+    /// actually listened on.
+    ///
+    /// `transport` must establish its connections for `network_id`, which must differ from the
+    /// network of the other connections. The NewPeer/LostPeer notifications of these connections
+    /// are only sent to `connection_event_handlers`, and only the application protocols negotiated
+    /// by `transport` are delivered from them. A peer is connected through one listener at most.
+    pub fn add_listener(
+        &mut self,
+        network_id: NetworkId,
+        transport: TTransport,
+        listen_addr: NetworkAddress,
+        connection_event_handlers: Vec<conn_notifs_channel::Sender>,
+    ) -> NetworkAddress {
+        assert!(
+            !self.listener_event_handlers.contains_key(&network_id),
+            "Network {:?} already has a listener",
+            network_id
+        );
+        // Listeners never dial, so the sender of their dial requests is dropped right away.
+        let (_, transport_reqs_rx) = channel::new(1, &counters::PENDING_PEER_MANAGER_DIAL_REQUESTS);
+        let transport_notifs_tx = self.transport_notifs_tx.clone();
+        let (listener_handler, mut listen_addrs) = self.executor.enter(|| {
+            TransportHandler::new(
+                transport,
+                vec![listen_addr],
+                transport_reqs_rx,
+                transport_notifs_tx,
+                InboundConnectionLimiter::new(
+                    self.inbound_connection_limits,
+                    self.inbound_connection_gate.clone(),
+                ),
+                self.lifecycles.clone(),
+                self.events.clone(),
+            )
+        });
+        let listen_addr = listen_addrs.remove(0);
+        info!("Network {:?} listening on {}", network_id, listen_addr);
+        self.listener_handlers.push(listener_handler);
+        self.listener_event_handlers
+            .insert(network_id, connection_event_handlers);
+        listen_addr
+    }
+
+    /// Get the [`NetworkAddress`]es we're listening for incoming connections on, in the order
+    /// of the addresses given to [`PeerManager::new`].
+    pub fn listen_addrs(&self) -> &[NetworkAddress] {
+        &self.listen_addrs
+    }
+
+    /// Return a sender of the deadline of the shutdown of the PeerManager: on receipt, it closes
+    /// its listeners, waits for the RPCs in flight to complete, then closes the connections with
+    /// all the peers and terminates, giving up on the RPCs and connections left at the deadline.
+    pub fn shutdown_sender(&mut self) -> oneshot::Sender<Instant> {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown_rx = Some(shutdown_rx);
+        shutdown_tx
+    }
+
+    /// Return a sender of the protocol handlers registered after the PeerManager starts, e.g., by
+    /// components started later than the network. On receipt, the protocols are added to the
+    /// `application_protocols` advertised in the handshakes of the transport, and only the
+    /// connections established afterwards negotiate them: the handler receives their messages and
+    /// NewPeer notifications, and may receive LostPeer notifications of older connections.
+    pub fn protocol_handlers_sender(
+        &mut self,
+        application_protocols: ApplicationProtocols,
+    ) -> mpsc::UnboundedSender<ProtocolHandlerRegistration> {
+        let (protocol_handlers_tx, protocol_handlers_rx) = mpsc::unbounded();
+        self.protocol_handlers_rx = Some(protocol_handlers_rx);
+        self.application_protocols = Some(application_protocols);
+        protocol_handlers_tx
     }
 
     /// Start listening on the set address and return a future which runs PeerManager
     pub async fn start(mut self) {
         // Start listening for connections.
         self.start_connection_listener();
+        // Without a shutdown sender, the receiver is canceled right away and never polled again.
+        let mut shutdown_rx = self
+            .shutdown_rx
+            .take()
+            .unwrap_or_else(|| oneshot::channel().1);
+        let mut protocol_handlers_rx = self
+            .protocol_handlers_rx
+            .take()
+            .unwrap_or_else(|| mpsc::unbounded().1);
         loop {
             ::futures::select! {
                 connection_event = self.transport_notifs_rx.select_next_some() => {
@@ -340,6 +755,16 @@ where
                 connection_request = self.connection_reqs_rx.select_next_some() => {
                   self.handle_connection_request(connection_request).await;
                 }
+                registration = protocol_handlers_rx.select_next_some() => {
+                  self.register_protocol_handler(registration);
+                }
+                deadline = shutdown_rx => {
+                  if let Ok(deadline) = deadline {
+                    self.shutdown(deadline).await;
+                    info!("Peer manager actor shut down");
+                    break;
+                  }
+                }
                 complete => {
                   // TODO: This should be ok when running in client mode.
                   crit!("Peer manager actor terminated");
@@ -349,13 +774,45 @@ where
         }
     }
 
+    /// Deliver the messages of the protocols of `registration` to its handler, then advertise the
+    /// protocols, so that the connections negotiating them always find the handler.
+    fn register_protocol_handler(&mut self, registration: ProtocolHandlerRegistration) {
+        info!(
+            "Registering the handler of protocols {:?}",
+            registration.protocols
+        );
+        for protocol in &registration.protocols {
+            self.upstream_handlers
+                .insert(*protocol, registration.handler.clone());
+        }
+        self.connection_event_handlers
+            .push(registration.connection_event_handler);
+        if let Some(application_protocols) = &self.application_protocols {
+            application_protocols.extend(&registration.protocols);
+        }
+    }
+
     fn handle_connection_event(&mut self, event: TransportNotification<TSocket>) {
         trace!("TransportNotification::{:?}", event);
         match event {
-            TransportNotification::NewConnection(conn) => {
+            TransportNotification::NewConnection(conn, listener_permit) => {
                 info!("New connection established: {:?}", conn,);
+                let conn_meta = conn.metadata.clone();
                 // Update libra_network_peer counter.
                 self.add_peer(conn);
+                // An inbound connection counts against the limits of its listener until it closes.
+                if let Some(permit) = listener_permit {
+                    let is_active = self.active_peers.get(&conn_meta.peer_id()).map_or(
+                        false,
+                        |(active_conn_meta, _)| {
+                            active_conn_meta.connection_id() == conn_meta.connection_id()
+                        },
+                    );
+                    if is_active {
+                        self.listener_connection_permits
+                            .insert(conn_meta.connection_id(), permit);
+                    }
+                }
                 counters::LIBRA_NETWORK_PEERS
                     .with_label_values(&[self.role.as_str(), "connected"])
                     .set(self.active_peers.len() as i64);
@@ -368,6 +825,10 @@ where
                     lost_conn_metadata, reason,
                 );
                 let peer_id = lost_conn_metadata.peer_id();
+                self.inbound_connection_permits
+                    .remove(&lost_conn_metadata.connection_id());
+                self.listener_connection_permits
+                    .remove(&lost_conn_metadata.connection_id());
                 // If the active connection with the peer is lost, remove it from `active_peers`.
                 if let Entry::Occupied(entry) = self.active_peers.entry(peer_id) {
                     let (conn_metadata, _) = entry.get();
@@ -397,9 +858,21 @@ where
                 // Notify upstream if there's still no active connection. This might be redundant,
                 // but does not affect correctness.
                 if !self.active_peers.contains_key(&peer_id) {
+                    self.peer_encodings.remove(&peer_id);
+                    self.peer_activity.remove(&peer_id);
+                    self.network_info.remove(&peer_id);
+                    self.observed_addrs.remove(&peer_id);
+                    self.lifecycles
+                        .handle_event(peer_id, LifecycleEvent::Closed);
+                    self.events.publish(NetworkEvent::PeerDisconnected {
+                        peer_id,
+                        address: lost_conn_metadata.addr().clone(),
+                        reason,
+                    });
                     self.send_lostpeer_notification(
                         peer_id,
                         lost_conn_metadata.addr().clone(),
+                        lost_conn_metadata.network_id(),
                         reason,
                     );
                 }
@@ -426,7 +899,26 @@ where
                             requested_peer_id.short_str()
                         );
                     }
+                } else if self.ban_list.is_banned(&requested_peer_id, &addr) {
+                    debug!(
+                        "Peer {} is banned. Not dialing address {}",
+                        requested_peer_id.short_str(),
+                        addr
+                    );
+                    let error = PeerManagerError::Banned(requested_peer_id);
+                    if response_tx.send(Err(error)).is_err() {
+                        warn!(
+                            "Receiver for DialPeer {} dropped",
+                            requested_peer_id.short_str()
+                        );
+                    }
                 } else {
+                    self.lifecycles
+                        .handle_event(requested_peer_id, LifecycleEvent::DialRequested);
+                    self.events.publish(NetworkEvent::DialStarted {
+                        peer_id: requested_peer_id,
+                        address: addr.clone(),
+                    });
                     self.dial_peer(requested_peer_id, addr, response_tx).await;
                 };
             }
@@ -436,6 +928,8 @@ where
                 if let Some((conn_metadata, sender)) = self.active_peers.remove(&peer_id) {
                     // This should trigger a disconnect.
                     drop(sender);
+                    self.lifecycles
+                        .handle_event(peer_id, LifecycleEvent::Closing);
                     // Add to outstanding disconnect requests.
                     self.outstanding_disconnect_requests
                         .insert(conn_metadata.connection_id(), resp_tx);
@@ -458,30 +952,69 @@ where
     async fn handle_request(&mut self, request: PeerManagerRequest) {
         trace!("PeerManagerRequest::{:?}", request);
         match request {
-            PeerManagerRequest::SendMessage(peer_id, msg) => {
+            PeerManagerRequest::SendMessage(peer_id, msg, mut trace) => {
+                trace!("Dequeued {} for peer {}", trace, peer_id.short_str());
+                trace.stage("peer_manager_queue");
                 if let Some((_, sender)) = self.active_peers.get_mut(&peer_id) {
-                    if let Err(err) = sender.push(msg.protocol, NetworkRequest::SendMessage(msg)) {
-                        info!(
+                    let protocol = msg.protocol;
+                    match sender
+                        .push_counting_drops(protocol, NetworkRequest::SendMessage(msg, trace))
+                    {
+                        Ok(0) => {}
+                        Ok(count) => self.events.publish(NetworkEvent::MessagesDropped {
+                            peer_id,
+                            protocol,
+                            count,
+                        }),
+                        Err(err) => info!(
                             "Failed to forward outbound message to downstream actor. Error:
                               {:?}",
                             err
-                        );
+                        ),
                     }
                 } else {
-                    warn!("Peer {} is not connected", peer_id.short_str());
+                    warn!(
+                        "Peer {} is not connected, dropping {}",
+                        peer_id.short_str(),
+                        trace
+                    );
                 }
             }
-            PeerManagerRequest::SendRpc(peer_id, req) => {
-                if let Some((_, sender)) = self.active_peers.get_mut(&peer_id) {
-                    if let Err(err) = sender.push(req.protocol, NetworkRequest::SendRpc(req)) {
-                        info!(
-                            "Failed to forward outbound rpc to downstream actor. Error:
+            PeerManagerRequest::SendRpc(peer_id, req, mut trace) => {
+                trace!("Dequeued {} for peer {}", trace, peer_id.short_str());
+                trace.stage("peer_manager_queue");
+                // Requests that can't be forwarded are failed right away, so that the caller
+                // learns why instead of waiting for the timeout.
+                if let Some((metadata, sender)) = self.active_peers.get_mut(&peer_id) {
+                    if !metadata.application_protocols().contains(req.protocol) {
+                        let _ = req
+                            .res_tx
+                            .send(Err(RpcError::ProtocolNotSupported(req.protocol)));
+                    } else {
+                        let protocol = req.protocol;
+                        match sender
+                            .push_counting_drops(protocol, NetworkRequest::SendRpc(req, trace))
+                        {
+                            Ok(0) => {}
+                            Ok(count) => self.events.publish(NetworkEvent::MessagesDropped {
+                                peer_id,
+                                protocol,
+                                count,
+                            }),
+                            Err(err) => info!(
+                                "Failed to forward outbound rpc to downstream actor. Error:
                             {:?}",
-                            err
-                        );
+                                err
+                            ),
+                        }
                     }
                 } else {
-                    warn!("Peer {} is not connected", peer_id.short_str());
+                    warn!(
+                        "Peer {} is not connected, dropping {}",
+                        peer_id.short_str(),
+                        trace
+                    );
+                    let _ = req.res_tx.send(Err(RpcError::NotConnected(peer_id)));
                 }
             }
         }
@@ -492,7 +1025,118 @@ where
             .transport_handler
             .take()
             .expect("Transport handler already taken");
-        self.executor.spawn(transport_handler.listen());
+        let handlers: Vec<_> = std::iter::once(transport_handler)
+            .chain(self.listener_handlers.drain(..))
+            .collect();
+        for handler in handlers {
+            let (listen, abort_handle) = abortable(handler.listen());
+            self.running_transport_handlers
+                .push((abort_handle, self.executor.spawn(listen)));
+        }
+    }
+
+    /// Shut down until `deadline`: abort the connection listeners, wait for the RPCs in flight to
+    /// complete, then close the connections with all the peers and wait for them to be closed.
+    async fn shutdown(&mut self, deadline: Instant) {
+        info!("Peer manager shutting down");
+        self.shutting_down = true;
+        // Aborting the listeners closes their sockets, and the connections being upgraded.
+        for (abort_handle, join_handle) in self.running_transport_handlers.drain(..) {
+            abort_handle.abort();
+            if let Err(err) = join_handle.await {
+                error!("Connection listener failed: {}", err);
+            }
+        }
+
+        // The RPCs in flight are served by the actors of the peers, while the events of their
+        // connections are still handled here.
+        if !self
+            .handle_connection_events_until(deadline, |peer_manager| {
+                peer_manager.inflight_rpc_bytes() == 0
+            })
+            .await
+        {
+            warn!(
+                "Closing the connections with {} bytes of RPCs still in flight",
+                self.inflight_rpc_bytes()
+            );
+        }
+
+        // Dropping the handles of the peers closes their connections, once their pending messages
+        // are flushed. The disconnections are acknowledged like requested ones.
+        let mut disconnect_acks = Vec::new();
+        for (peer_id, (conn_metadata, peer_handle)) in self.active_peers.drain() {
+            drop(peer_handle);
+            self.lifecycles
+                .handle_event(peer_id, LifecycleEvent::Closing);
+            let (ack_tx, ack_rx) = oneshot::channel();
+            self.outstanding_disconnect_requests
+                .insert(conn_metadata.connection_id(), ack_tx);
+            disconnect_acks.push(ack_rx);
+        }
+        if !self
+            .handle_connection_events_until(deadline, |peer_manager| {
+                peer_manager.outstanding_disconnect_requests.is_empty()
+            })
+            .await
+        {
+            warn!(
+                "Giving up on closing {} connections",
+                self.outstanding_disconnect_requests.len()
+            );
+        }
+        counters::LIBRA_NETWORK_PEERS
+            .with_label_values(&[self.role.as_str(), "connected"])
+            .set(0);
+    }
+
+    /// Handle the connection events until `done` holds, or `deadline` passes. Return whether
+    /// `done` holds.
+    async fn handle_connection_events_until(
+        &mut self,
+        deadline: Instant,
+        done: impl Fn(&Self) -> bool,
+    ) -> bool {
+        let mut deadline = tokio::time::delay_until(deadline.into()).fuse();
+        let mut ticks = tokio::time::interval(SHUTDOWN_CHECK_INTERVAL).fuse();
+        while !done(self) {
+            futures::select! {
+                connection_event = self.transport_notifs_rx.select_next_some() => {
+                    self.handle_connection_event(connection_event);
+                }
+                _ = ticks.select_next_some() => {}
+                _ = deadline => return false,
+            }
+        }
+        true
+    }
+
+    /// Bytes of the inbound and outbound RPCs in flight, over all the networks.
+    fn inflight_rpc_bytes(&self) -> usize {
+        self.quotas
+            .values()
+            .map(|quota| quota.inflight_rpc_bytes.used())
+            .sum()
+    }
+
+    /// Channels to send the NewPeer/LostPeer notifications of the connections established for
+    /// `network_id` to.
+    fn connection_event_handlers_mut(
+        &mut self,
+        network_id: &NetworkId,
+    ) -> &mut Vec<conn_notifs_channel::Sender> {
+        match self.listener_event_handlers.get_mut(network_id) {
+            Some(handlers) => handlers,
+            None => &mut self.connection_event_handlers,
+        }
+    }
+
+    /// Quota of the connections established for `network_id`, created on first use.
+    fn quota(&mut self, network_id: &NetworkId) -> &NetworkQuota {
+        let quota_limits = self.quota_limits;
+        self.quotas
+            .entry(network_id.clone())
+            .or_insert_with(|| NetworkQuota::new(network_id, quota_limits))
     }
 
     /// In the event two peers simultaneously dial each other we need to be able to do
@@ -526,6 +1170,54 @@ where
 
         let mut send_new_peer_notification = true;
 
+        // Close the connections established while shutting down, e.g., upgraded before the
+        // listeners were aborted.
+        if self.shutting_down {
+            info!(
+                "Closing {:?} connection with Peer {}: shutting down",
+                conn_meta.origin(),
+                peer_id.short_str()
+            );
+            self.close_connection(connection);
+            return;
+        }
+
+        // Close the connections of banned peers, before they replace any existing connection.
+        if self.ban_list.is_banned(&peer_id, conn_meta.addr()) {
+            info!(
+                "Closing {:?} connection with banned Peer {} at {}",
+                conn_meta.origin(),
+                peer_id.short_str(),
+                conn_meta.addr()
+            );
+            self.close_connection(connection);
+            return;
+        }
+
+        // Close inbound connections beyond the quota of their network, unless the eviction policy
+        // makes room for them, before they replace any existing connection.
+        let connection_permit = if conn_meta.origin() == ConnectionOrigin::Inbound {
+            let permit = self
+                .quota(conn_meta.network_id())
+                .inbound_connections
+                .try_reserve(1);
+            match permit.or_else(|| self.evict_inbound_connection(&conn_meta)) {
+                Some(permit) => Some(permit),
+                None => {
+                    info!(
+                        "Closing incoming connection with Peer {}: inbound connection quota of \
+                         network {} exhausted",
+                        peer_id.short_str(),
+                        conn_meta.network_id().as_str()
+                    );
+                    self.close_connection(connection);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
         // Check for and handle simultaneous dialing
         if let Entry::Occupied(active_entry) = self.active_peers.entry(peer_id) {
             let (curr_conn_metadata, _) = active_entry.get();
@@ -549,27 +1241,15 @@ where
                     peer_id.short_str()
                 );
                 // Drop the new connection and keep the one already stored in active_peers
-                let drop_fut = async move {
-                    let mut connection = connection;
-                    if let Err(e) = tokio::time::timeout(
-                        transport::TRANSPORT_TIMEOUT,
-                        connection.socket.close(),
-                    )
-                    .await
-                    {
-                        error!(
-                            "Closing connection with Peer {} failed with error: {}",
-                            peer_id.short_str(),
-                            e
-                        );
-                    };
-                };
-                self.executor.spawn(drop_fut);
+                self.close_connection(connection);
                 return;
             }
         }
 
         // Initialize a new network stack for this connection.
+        let quota = self.quota(conn_meta.network_id());
+        let inbound_message_budget = quota.inbound_message_bytes.clone();
+        let inflight_rpc_budget = quota.inflight_rpc_bytes.clone();
         let (network_reqs_tx, network_notifs_rx) = NetworkProvider::start(
             self.executor.clone(),
             connection,
@@ -577,34 +1257,130 @@ where
             self.max_concurrent_network_reqs,
             self.max_concurrent_network_notifs,
             self.channel_size,
+            inbound_message_budget,
+            inflight_rpc_budget,
+            self.inbound_rate_limits.clone(),
+            self.protocol_priorities.clone(),
         );
+        if let Some(permit) = connection_permit {
+            self.inbound_connection_permits
+                .insert(conn_meta.connection_id(), (permit, Instant::now()));
+        }
         // Start background task to handle events (RPCs and DirectSend messages) received from
         // peer.
-        self.spawn_peer_network_events_handler(peer_id, network_notifs_rx);
+        self.spawn_peer_network_events_handler(
+            peer_id,
+            conn_meta.application_protocols().clone(),
+            conn_meta.encodings().clone(),
+            network_notifs_rx,
+        );
         // Save NetworkRequest sender to `active_peers`.
         self.active_peers
             .insert(peer_id, (conn_meta.clone(), network_reqs_tx));
+        self.peer_encodings
+            .insert(peer_id, conn_meta.encodings().clone());
+        match conn_meta.observed_addr() {
+            Some(observed_addr) => {
+                self.observed_addrs
+                    .insert(peer_id, conn_meta.origin(), observed_addr)
+            }
+            None => self.observed_addrs.remove(&peer_id),
+        }
+        self.network_info.insert(conn_meta.clone());
+        self.lifecycles.established(peer_id);
         // Send NewPeer notification to connection event handlers.
         if send_new_peer_notification {
-            for handler in self.connection_event_handlers.iter_mut() {
+            self.events.publish(NetworkEvent::PeerConnected {
+                peer_id,
+                address: conn_meta.addr().clone(),
+                origin: conn_meta.origin(),
+            });
+            for handler in self
+                .connection_event_handlers_mut(conn_meta.network_id())
+                .iter_mut()
+            {
                 handler
                     .push(
                         peer_id,
-                        ConnectionNotification::NewPeer(peer_id, conn_meta.addr().clone()),
+                        ConnectionNotification::NewPeer(
+                            peer_id,
+                            conn_meta.addr().clone(),
+                            ConnectionInfo::new(&conn_meta, self.role),
+                        ),
                     )
                     .unwrap();
             }
         }
     }
 
+    /// Close the active inbound connection of the network of `conn_meta` chosen by the eviction
+    /// policy to make room for the connection `conn_meta`, if any, and return its reservation in
+    /// the quota of the network.
+    fn evict_inbound_connection(&mut self, conn_meta: &ConnectionMetadata) -> Option<QuotaPermit> {
+        let candidates: Vec<_> = self
+            .active_peers
+            .values()
+            .filter(|(active_conn_meta, _)| active_conn_meta.network_id() == conn_meta.network_id())
+            .filter_map(|(active_conn_meta, _)| {
+                self.inbound_connection_permits
+                    .get(&active_conn_meta.connection_id())
+                    .map(|(_, connected_at)| EvictionCandidate {
+                        peer_id: active_conn_meta.peer_id(),
+                        addr: active_conn_meta.addr().clone(),
+                        connected_at: *connected_at,
+                    })
+            })
+            .collect();
+        let victim = self
+            .eviction_policy
+            .select_victim(&conn_meta.peer_id(), &candidates)?;
+        let (victim_conn_meta, peer_handle) = self.active_peers.remove(&victim)?;
+        info!(
+            "Closing connection with Peer {} to make room for Peer {}",
+            victim.short_str(),
+            conn_meta.peer_id().short_str()
+        );
+        // Dropping the handle closes the connection. The LostPeer notification is sent once it's
+        // closed, as the peer no longer has an active connection.
+        drop(peer_handle);
+        self.lifecycles
+            .handle_event(victim, LifecycleEvent::Closing);
+        counters::LIBRA_NETWORK_EVICTED_CONNECTIONS
+            .with_label_values(&[conn_meta.network_id().as_str()])
+            .inc();
+        self.inbound_connection_permits
+            .remove(&victim_conn_meta.connection_id())
+            .map(|(permit, _)| permit)
+    }
+
+    /// Close a connection which wasn't added to the active peers, ending its connection attempt.
+    fn close_connection(&self, mut connection: Connection<TSocket>) {
+        let peer_id = connection.metadata.peer_id();
+        self.lifecycles
+            .handle_event(peer_id, LifecycleEvent::AttemptFailed);
+        let drop_fut = async move {
+            if let Err(e) =
+                tokio::time::timeout(transport::TRANSPORT_TIMEOUT, connection.socket.close()).await
+            {
+                error!(
+                    "Closing connection with Peer {} failed with error: {}",
+                    peer_id.short_str(),
+                    e
+                );
+            };
+        };
+        self.executor.spawn(drop_fut);
+    }
+
     fn send_lostpeer_notification(
         &mut self,
         peer_id: PeerId,
         addr: NetworkAddress,
+        network_id: &NetworkId,
         reason: DisconnectReason,
     ) {
         // Send LostPeer notification to connection event handlers.
-        for handler in self.connection_event_handlers.iter_mut() {
+        for handler in self.connection_event_handlers_mut(network_id).iter_mut() {
             if let Err(e) = handler.push(
                 peer_id,
                 ConnectionNotification::LostPeer(peer_id, addr.clone(), reason),
@@ -631,13 +1407,23 @@ where
     fn spawn_peer_network_events_handler(
         &self,
         peer_id: PeerId,
+        application_protocols: SupportedProtocols,
+        encodings: ProtocolEncodings,
         network_events: libra_channel::Receiver<ProtocolId, NetworkNotification>,
     ) {
         let mut upstream_handlers = self.upstream_handlers.clone();
+        let peer_activity = self.peer_activity.clone();
         self.executor.spawn(network_events.for_each_concurrent(
             self.max_concurrent_network_reqs,
             move |inbound_event| {
-                Self::handle_inbound_event(inbound_event, peer_id, &mut upstream_handlers);
+                peer_activity.record(peer_id);
+                Self::handle_inbound_event(
+                    inbound_event,
+                    peer_id,
+                    &application_protocols,
+                    &encodings,
+                    &mut upstream_handlers,
+                );
                 futures::future::ready(())
             },
         ));
@@ -646,11 +1432,27 @@ where
     fn handle_inbound_event(
         inbound_event: NetworkNotification,
         peer_id: PeerId,
+        application_protocols: &SupportedProtocols,
+        encodings: &ProtocolEncodings,
         upstream_handlers: &mut HashMap<
             ProtocolId,
             libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
         >,
     ) {
+        let protocol = match &inbound_event {
+            NetworkNotification::RecvMessage(msg) => msg.protocol,
+            NetworkNotification::RecvRpc(rpc_req) => rpc_req.protocol,
+        };
+        // Only deliver the protocols negotiated on the connection, so that the peers of a
+        // listener can't reach the handlers of protocols it doesn't offer.
+        if !application_protocols.contains(protocol) {
+            warn!(
+                "Dropping inbound {:?} event from peer {}, which wasn't negotiated",
+                protocol,
+                peer_id.short_str()
+            );
+            return;
+        }
         match inbound_event {
             NetworkNotification::RecvMessage(msg) => {
                 let protocol = msg.protocol;
@@ -658,7 +1460,7 @@ where
                     // Send over libra channel for fairness.
                     if let Err(err) = handler.push(
                         (peer_id, protocol),
-                        PeerManagerNotification::RecvMessage(peer_id, msg),
+                        PeerManagerNotification::RecvMessage(peer_id, msg, encodings.get(protocol)),
                     ) {
                         warn!(
                             "Upstream handler unable to handle messages for protocol: {:?}. Error:
@@ -676,7 +1478,7 @@ where
                     // Send over libra channel for fairness.
                     if let Err(err) = handler.push(
                         (peer_id, protocol),
-                        PeerManagerNotification::RecvRpc(peer_id, rpc_req),
+                        PeerManagerNotification::RecvRpc(peer_id, rpc_req, encodings.get(protocol)),
                     ) {
                         warn!(
                             "Upstream handler unable to handle rpc for protocol: {:?}. Error:
@@ -706,7 +1508,8 @@ pub enum TransportNotification<TSocket>
 where
     TSocket: AsyncRead + AsyncWrite,
 {
-    NewConnection(Connection<TSocket>),
+    /// A new connection, along with its count against the limits of its listener if inbound.
+    NewConnection(Connection<TSocket>, Option<InboundConnectionPermit>),
     Disconnected(ConnectionMetadata, DisconnectReason),
 }
 
@@ -718,9 +1521,16 @@ where
 {
     /// [`Transport`] that is used to establish connections
     transport: TTransport,
-    listener: Fuse<TTransport::Listener>,
+    /// Incoming connections on all the addresses listened on.
+    listener: Fuse<SelectAll<TTransport::Listener>>,
     transport_reqs_rx: channel::Receiver<TransportRequest>,
     transport_notifs_tx: channel::Sender<TransportNotification<TSocket>>,
+    /// Limits of the incoming connections, enforced before their upgrade.
+    inbound_connection_limiter: InboundConnectionLimiter,
+    /// States of the connections with the peers, which the failed dials move back.
+    lifecycles: PeerLifecycles,
+    /// Publisher of the failed dials and handshakes.
+    events: NetworkEvents,
 }
 
 impl<TTransport, TSocket> TransportHandler<TTransport, TSocket>
@@ -733,22 +1543,34 @@ where
 {
     fn new(
         transport: TTransport,
-        listen_addr: NetworkAddress,
+        listen_addrs: Vec<NetworkAddress>,
         transport_reqs_rx: channel::Receiver<TransportRequest>,
         transport_notifs_tx: channel::Sender<TransportNotification<TSocket>>,
-    ) -> (Self, NetworkAddress) {
-        let (listener, listen_addr) = transport
-            .listen_on(listen_addr)
-            .expect("Transport listen on fails");
-        debug!("listening on {:?}", listen_addr);
+        inbound_connection_limiter: InboundConnectionLimiter,
+        lifecycles: PeerLifecycles,
+        events: NetworkEvents,
+    ) -> (Self, Vec<NetworkAddress>) {
+        let (listeners, listen_addrs): (Vec<_>, Vec<_>) = listen_addrs
+            .into_iter()
+            .map(|listen_addr| {
+                let (listener, listen_addr) = transport
+                    .listen_on(listen_addr)
+                    .expect("Transport listen on fails");
+                debug!("listening on {:?}", listen_addr);
+                (listener, listen_addr)
+            })
+            .unzip();
         (
             Self {
                 transport,
-                listener: listener.fuse(),
+                listener: stream::select_all(listeners).fuse(),
                 transport_reqs_rx,
                 transport_notifs_tx,
+                inbound_connection_limiter,
+                lifecycles,
+                events,
             },
-            listen_addr,
+            listen_addrs,
         )
     }
 
@@ -769,7 +1591,18 @@ where
                     match incoming_connection {
                         Ok((upgrade, addr)) => {
                             debug!("Incoming connection from {}", addr);
-                            pending_inbound_connections.push(upgrade.map(|out| (out, addr)));
+                            // Dropping the upgrade closes the connection before its handshake.
+                            match self.inbound_connection_limiter.try_accept(&addr) {
+                                Some(permit) => pending_inbound_connections
+                                    .push(upgrade.map(|out| (out, addr, permit))),
+                                None => {
+                                    info!(
+                                        "Closing incoming connection from {}: inbound connection \
+                                         limits exceeded or acceptance paused",
+                                        addr
+                                    );
+                                }
+                            }
                         }
                         Err(e) => {
                             warn!("Incoming connection error {}", e);
@@ -779,8 +1612,8 @@ where
                 (upgrade, addr, peer_id, response_tx) = pending_outbound_connections.select_next_some() => {
                     self.handle_completed_outbound_upgrade(upgrade, addr, peer_id, response_tx).await;
                 },
-                (upgrade, addr) = pending_inbound_connections.select_next_some() => {
-                    self.handle_completed_inbound_upgrade(upgrade, addr).await;
+                (upgrade, addr, permit) = pending_inbound_connections.select_next_some() => {
+                    self.handle_completed_inbound_upgrade(upgrade, addr, permit).await;
                 },
                 complete => break,
             }
@@ -812,6 +1645,13 @@ where
                             .boxed(),
                     ),
                     Err(error) => {
+                        self.lifecycles
+                            .handle_event(peer_id, LifecycleEvent::AttemptFailed);
+                        self.events.publish(NetworkEvent::DialFailed {
+                            peer_id,
+                            address: addr,
+                            error: error.to_string(),
+                        });
                         if response_tx
                             .send(Err(PeerManagerError::from_transport_error(error)))
                             .is_err()
@@ -838,13 +1678,25 @@ where
         match upgrade {
             Ok(connection) => {
                 let dialed_peer_id = connection.metadata.peer_id();
+                // The dial was canceled while in flight, e.g., as another address of the peer
+                // was reached first: the connection is closed rather than replacing that one.
+                if response_tx.is_canceled() {
+                    debug!(
+                        "Closing the connection with Peer '{}' dialed at '{}': dial canceled",
+                        peer_id.short_str(),
+                        addr
+                    );
+                    self.lifecycles
+                        .handle_event(peer_id, LifecycleEvent::AttemptFailed);
+                    return;
+                }
                 let response = if dialed_peer_id == peer_id {
                     debug!(
                         "Peer '{}' successfully dialed at '{}'",
                         peer_id.short_str(),
                         addr
                     );
-                    let event = TransportNotification::NewConnection(connection);
+                    let event = TransportNotification::NewConnection(connection, None);
                     // Send the new connection to PeerManager
                     self.transport_notifs_tx.send(event).await.unwrap();
                     Ok(())
@@ -856,6 +1708,14 @@ where
                     );
 
                     warn!("{}", e);
+                    self.lifecycles
+                        .handle_event(peer_id, LifecycleEvent::AttemptFailed);
+                    self.events.publish(NetworkEvent::HandshakeFailed {
+                        peer_id: Some(peer_id),
+                        address: addr.clone(),
+                        origin: ConnectionOrigin::Outbound,
+                        reason: e.to_string(),
+                    });
 
                     Err(PeerManagerError::from_transport_error(e))
                 };
@@ -869,6 +1729,21 @@ where
             }
             Err(error) => {
                 error!("Error dialing Peer {} at {}", peer_id.short_str(), addr);
+                self.lifecycles
+                    .handle_event(peer_id, LifecycleEvent::AttemptFailed);
+                self.events.publish(match handshake_error(&error) {
+                    Some(handshake_error) => NetworkEvent::HandshakeFailed {
+                        peer_id: Some(peer_id),
+                        address: addr,
+                        origin: ConnectionOrigin::Outbound,
+                        reason: handshake_error.to_string(),
+                    },
+                    None => NetworkEvent::DialFailed {
+                        peer_id,
+                        address: addr,
+                        error: error.to_string(),
+                    },
+                });
 
                 if response_tx
                     .send(Err(PeerManagerError::from_transport_error(error)))
@@ -887,6 +1762,7 @@ where
         &mut self,
         upgrade: Result<Connection<TSocket>, TTransport::Error>,
         addr: NetworkAddress,
+        permit: InboundConnectionPermit,
     ) {
         match upgrade {
             Ok(connection) => {
@@ -895,13 +1771,27 @@ where
                     connection.metadata.peer_id().short_str(),
                     addr
                 );
-                let event = TransportNotification::NewConnection(connection);
+                let event = TransportNotification::NewConnection(connection, Some(permit));
                 // Send the new connection to PeerManager
                 self.transport_notifs_tx.send(event).await.unwrap();
             }
             Err(e) => {
                 warn!("Connection from {} failed to upgrade {}", addr, e);
+                self.events.publish(NetworkEvent::HandshakeFailed {
+                    peer_id: None,
+                    address: addr,
+                    origin: ConnectionOrigin::Inbound,
+                    reason: e.to_string(),
+                });
             }
         }
     }
 }
+
+/// Returns the LibraNet handshake error which failed the upgrade of a connection with `error`, if
+/// any, to tell the failed handshakes from the failed dials.
+fn handshake_error<E: std::error::Error + 'static>(error: &E) -> Option<&HandshakeError> {
+    (error as &(dyn std::error::Error + 'static))
+        .downcast_ref::<io::Error>()
+        .and_then(HandshakeError::from_io_error)
+}
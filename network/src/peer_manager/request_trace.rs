@@ -0,0 +1,230 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracing of the requests of applications through the network stack.
+//!
+//! Every `PeerManagerRequest` carries a `RequestTrace` with a monotonic id, which is logged when
+//! the request is enqueued by the `PeerManagerRequestSender`, dequeued by the `PeerManager` and
+//! written to the wire, so that a message which was sent but never arrived can be followed through
+//! the logs. The trace is in flight until it is dropped, i.e., once the request was written or
+//! dropped along the way, and the age of the oldest trace in flight is exported to spot stuck
+//! requests.
+//!
+//! The trace is also the root of the spans of the request: the stages it goes through, e.g., the
+//! queue of the PeerManager, are recorded as consecutive spans, and its context is propagated in
+//! the RPC requests so that the span of the handler of the remote node is a child of the span of
+//! the outbound RPC. The spans are logged once finished, with the ids of their trace, so that the
+//! latency of an RPC can be followed across the nodes, and their durations are exported by name.
+
+use crate::{counters, protocols::wire::messaging::v1::TraceContext};
+use libra_logger::prelude::*;
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Enqueue times of the traces in flight, by id. As ids are monotonic, the first entry is the
+/// oldest one.
+static IN_FLIGHT: Lazy<Mutex<BTreeMap<u64, Instant>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug)]
+pub struct RequestTrace {
+    id: u64,
+    /// Context of the root span of the request, in a new trace
+    context: TraceContext,
+    started_at: Instant,
+    /// End of the last stage recorded, where the next one starts
+    stage_started_at: Instant,
+}
+
+impl RequestTrace {
+    /// Assigns the next id to a new request, which is in flight until the trace is dropped.
+    pub fn start() -> Self {
+        Lazy::force(&counters::LIBRA_NETWORK_OLDEST_IN_FLIGHT_REQUEST_AGE);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        IN_FLIGHT.lock().unwrap().insert(id, now);
+        Self {
+            id,
+            context: TraceContext {
+                trace_id: rand::random(),
+                span_id: rand::random(),
+            },
+            started_at: now,
+            stage_started_at: now,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Context of the root span of the request, to propagate to the remote node.
+    pub fn context(&self) -> TraceContext {
+        self.context
+    }
+
+    /// Returns the root span of the request, which started with the trace and ends once dropped,
+    /// e.g., when the response of an RPC was received.
+    pub fn root_span(&self, name: &'static str) -> Span {
+        Span::new(name, self.context, None, self.started_at)
+    }
+
+    /// Records the stage `name` of the request, from the end of the previous stage until now.
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        drop(Span::child_of(name, &self.context).started_at(self.stage_started_at));
+        self.stage_started_at = now;
+    }
+}
+
+impl Drop for RequestTrace {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl fmt::Display for RequestTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request#{}", self.id)
+    }
+}
+
+/// A timed operation of a trace, logged and exported in `LIBRA_NETWORK_SPAN_DURATION` once
+/// dropped.
+#[derive(Debug)]
+pub struct Span {
+    name: &'static str,
+    context: TraceContext,
+    parent_span_id: Option<u64>,
+    started_at: Instant,
+}
+
+impl Span {
+    fn new(
+        name: &'static str,
+        context: TraceContext,
+        parent_span_id: Option<u64>,
+        started_at: Instant,
+    ) -> Self {
+        Self {
+            name,
+            context,
+            parent_span_id,
+            started_at,
+        }
+    }
+
+    /// Starts a span in a new trace, e.g., for an inbound request without context.
+    pub fn root(name: &'static str) -> Self {
+        let context = TraceContext {
+            trace_id: rand::random(),
+            span_id: rand::random(),
+        };
+        Self::new(name, context, None, Instant::now())
+    }
+
+    /// Starts a span child of the span of `parent`, e.g., propagated by a remote node.
+    pub fn child_of(name: &'static str, parent: &TraceContext) -> Self {
+        let context = TraceContext {
+            trace_id: parent.trace_id,
+            span_id: rand::random(),
+        };
+        Self::new(name, context, Some(parent.span_id), Instant::now())
+    }
+
+    /// Context of the span, the parent of its children.
+    pub fn context(&self) -> TraceContext {
+        self.context
+    }
+
+    fn started_at(mut self, started_at: Instant) -> Self {
+        self.started_at = started_at;
+        self
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let duration = self.started_at.elapsed();
+        counters::LIBRA_NETWORK_SPAN_DURATION
+            .with_label_values(&[self.name])
+            .observe(duration.as_secs_f64());
+        debug!(
+            "span {} trace_id={:032x} span_id={:016x} parent_span_id={} duration={:?}",
+            self.name,
+            self.context.trace_id,
+            self.context.span_id,
+            self.parent_span_id
+                .map_or_else(|| "none".to_string(), |id| format!("{:016x}", id)),
+            duration
+        );
+    }
+}
+
+/// Returns how long the oldest request in flight has been, if any.
+pub fn oldest_in_flight_age() -> Option<Duration> {
+    IN_FLIGHT
+        .lock()
+        .unwrap()
+        .values()
+        .next()
+        .map(|enqueued_at| enqueued_at.elapsed())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_in_flight(trace_id: u64) -> bool {
+        IN_FLIGHT.lock().unwrap().contains_key(&trace_id)
+    }
+
+    #[test]
+    fn test_request_trace() {
+        let first = RequestTrace::start();
+        let second = RequestTrace::start();
+        assert!(second.id() > first.id());
+        assert!(is_in_flight(first.id()));
+        assert!(oldest_in_flight_age().is_some());
+
+        let first_id = first.id();
+        drop(first);
+        assert!(!is_in_flight(first_id));
+        assert!(is_in_flight(second.id()));
+    }
+
+    #[test]
+    fn test_spans() {
+        let mut trace = RequestTrace::start();
+        let root = trace.root_span("test_root");
+        assert_eq!(root.context(), trace.context());
+
+        // the spans of the remote handler are in the same trace, children of the propagated span
+        let handler = Span::child_of("test_handler", &trace.context());
+        assert_eq!(handler.context().trace_id, trace.context().trace_id);
+        assert_eq!(handler.parent_span_id, Some(trace.context().span_id));
+        assert_ne!(handler.context().span_id, trace.context().span_id);
+
+        let first_stage_started_at = trace.stage_started_at;
+        trace.stage("test_stage");
+        assert!(trace.stage_started_at >= first_stage_started_at);
+
+        let other = RequestTrace::start();
+        assert_ne!(other.context().trace_id, trace.context().trace_id);
+        assert_eq!(
+            counters::LIBRA_NETWORK_SPAN_DURATION
+                .with_label_values(&["test_stage"])
+                .get_sample_count(),
+            1
+        );
+    }
+}
@@ -2,30 +2,63 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    ban_list::BanList,
+    common::NetworkPublicKeys,
+    connection_limits::{InboundConnectionGate, InboundConnectionLimits},
+    eviction::{DefaultEvictionPolicy, PeerScores},
+    network_events::{NetworkEvent, NetworkEvents},
+    network_info::NetworkInfo,
+    observed_addrs::ObservedAddrs,
     peer::DisconnectReason,
     peer_manager::{
-        conn_notifs_channel, error::PeerManagerError, ConnectionNotification, ConnectionRequest,
+        conn_notifs_channel,
+        error::PeerManagerError,
+        lifecycle::{ConnectionState, PeerLifecycles, StateTransition},
+        request_trace::RequestTrace,
+        ConnectionInfo, ConnectionNotification, ConnectionRequest, PeerActivity, PeerEncodings,
         PeerManager, PeerManagerNotification, PeerManagerRequest, TransportNotification,
     },
-    protocols::wire::{
-        handshake::v1::MessagingProtocolVersion,
-        messaging::v1::{NetworkMessage, Nonce},
+    priority::ProtocolPriorities,
+    protocols::{
+        health_checker::PeerRtts,
+        rpc::{error::RpcError, OutboundRpcRequest},
+        wire::{
+            handshake::v1::{MessagingProtocolVersion, ProtocolEncodings},
+            messaging::v1::{NetworkMessage, Nonce},
+        },
     },
+    quota::QuotaLimits,
+    rate_limit::InboundRateLimits,
     transport,
     transport::{Connection, ConnectionId, ConnectionMetadata},
     ProtocolId,
 };
 use channel::{libra_channel, message_queues::QueueStyle};
-use futures::{channel::oneshot, io::AsyncWriteExt, sink::SinkExt, stream::StreamExt};
-use libra_config::config::RoleType;
+use futures::{
+    channel::oneshot,
+    future::FutureExt,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sink::SinkExt,
+    stream::StreamExt,
+};
+use libra_config::{config::RoleType, network_id::NetworkId};
+use libra_crypto::{x25519, Uniform};
 use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
 use memsocket::MemorySocket;
 use netcore::{
     compat::IoCompat,
-    transport::{boxed::BoxedTransport, memory::MemoryTransport, ConnectionOrigin, TransportExt},
+    transport::{
+        boxed::BoxedTransport, memory::MemoryTransport, ConnectionOrigin, Transport, TransportExt,
+    },
+};
+use std::{
+    collections::HashMap,
+    iter::FromIterator,
+    num::NonZeroUsize,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, iter::FromIterator, num::NonZeroUsize};
 use tokio::runtime::Handle;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
@@ -49,6 +82,7 @@ pub fn build_test_transport(
                     origin,
                     MessagingProtocolVersion::V1,
                     [TEST_PROTOCOL].iter().into(),
+                    NetworkId::Validator,
                 ),
             })
         })
@@ -80,6 +114,29 @@ fn build_test_peer_manager(
     libra_channel::Sender<PeerId, ConnectionRequest>,
     libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerNotification>,
     conn_notifs_channel::Receiver,
+) {
+    build_test_peer_manager_listening_on(
+        executor,
+        peer_id,
+        vec!["/memory/0".parse().unwrap()],
+        InboundConnectionLimits::default(),
+    )
+}
+
+fn build_test_peer_manager_listening_on(
+    executor: Handle,
+    peer_id: PeerId,
+    listen_addrs: Vec<NetworkAddress>,
+    inbound_connection_limits: InboundConnectionLimits,
+) -> (
+    PeerManager<
+        BoxedTransport<Connection<MemorySocket>, impl std::error::Error + Sync + Send + 'static>,
+        MemorySocket,
+    >,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerRequest>,
+    libra_channel::Sender<PeerId, ConnectionRequest>,
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerNotification>,
+    conn_notifs_channel::Receiver,
 ) {
     let (peer_manager_request_tx, peer_manager_request_rx) =
         libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
@@ -94,7 +151,7 @@ fn build_test_peer_manager(
         build_test_transport(),
         peer_id,
         RoleType::Validator,
-        "/memory/0".parse().unwrap(),
+        listen_addrs,
         peer_manager_request_rx,
         connection_reqs_rx,
         HashMap::from_iter([(TEST_PROTOCOL, hello_tx)].iter().cloned()),
@@ -102,6 +159,22 @@ fn build_test_peer_manager(
         1024, /* max concurrent network requests */
         1024, /* max concurrent network notifications */
         1024, /* channel size */
+        QuotaLimits::default(),
+        inbound_connection_limits,
+        InboundConnectionGate::new(),
+        Arc::new(DefaultEvictionPolicy::new(
+            Arc::new(RwLock::new(HashMap::new())),
+            PeerScores::new(),
+        )),
+        BanList::new(),
+        InboundRateLimits::default(),
+        ProtocolPriorities::new(),
+        PeerEncodings::new(),
+        ObservedAddrs::new(),
+        PeerActivity::new(),
+        NetworkInfo::new(PeerRtts::new()),
+        PeerLifecycles::new(),
+        NetworkEvents::new(),
     );
 
     (
@@ -220,6 +293,7 @@ fn create_connection<TSocket: transport::TSocket>(
             origin,
             MessagingProtocolVersion::V1,
             [TEST_PROTOCOL].iter().into(),
+            NetworkId::Validator,
         ),
     }
 }
@@ -544,6 +618,7 @@ fn peer_manager_simultaneous_dial_disconnect_event() {
                 ConnectionOrigin::Inbound,
                 MessagingProtocolVersion::V1,
                 [TEST_PROTOCOL].iter().into(),
+                NetworkId::Validator,
             ),
             DisconnectReason::ConnectionLost,
         );
@@ -578,7 +653,10 @@ fn test_dial_disconnect() {
 
         // Expect NewPeer notification from PeerManager.
         let conn_notif = conn_status_rx.next().await.unwrap();
-        assert!(matches!(conn_notif, ConnectionNotification::NewPeer(_, _)));
+        assert!(matches!(
+            conn_notif,
+            ConnectionNotification::NewPeer(_, _, _)
+        ));
 
         // Send DisconnectPeer request to PeerManager.
         let (disconnect_resp_tx, disconnect_resp_rx) = oneshot::channel();
@@ -598,6 +676,7 @@ fn test_dial_disconnect() {
                 ConnectionOrigin::Outbound,
                 MessagingProtocolVersion::V1,
                 [TEST_PROTOCOL].iter().into(),
+                NetworkId::Validator,
             ),
             DisconnectReason::Requested,
         );
@@ -616,3 +695,660 @@ fn test_dial_disconnect() {
 
     runtime.block_on(test);
 }
+
+#[test]
+fn peer_manager_new_peer_connection_info() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[1]);
+
+    let test = async move {
+        let (_outbound, inbound) = build_test_connection();
+        let addr: NetworkAddress = "/memory/1".parse().unwrap();
+        peer_manager.add_peer(create_connection(
+            inbound,
+            ids[0],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(0),
+        ));
+
+        // The NewPeer notification carries what was negotiated on the connection.
+        let conn_notif = conn_status_rx.next().await.unwrap();
+        assert_eq!(
+            conn_notif,
+            ConnectionNotification::NewPeer(
+                ids[0],
+                addr,
+                ConnectionInfo {
+                    network_id: NetworkId::Validator,
+                    role: RoleType::Validator,
+                    origin: ConnectionOrigin::Inbound,
+                    messaging_protocol: MessagingProtocolVersion::V1,
+                    application_protocols: [TEST_PROTOCOL].iter().into(),
+                    encodings: ProtocolEncodings::default(),
+                },
+            )
+        );
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_connection_lifecycle() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, _conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[1]);
+    let peer_id = ids[0];
+    let mut transitions_rx = peer_manager.lifecycles.subscribe();
+    let connection_metadata = ConnectionMetadata::new(
+        peer_id,
+        ConnectionId::from(0),
+        NetworkAddress::mock(),
+        ConnectionOrigin::Outbound,
+        MessagingProtocolVersion::V1,
+        [TEST_PROTOCOL].iter().into(),
+        NetworkId::Validator,
+    );
+    let transition = move |from, to| StateTransition { peer_id, from, to };
+
+    let test = async move {
+        let (outbound, _inbound) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            outbound,
+            peer_id,
+            NetworkAddress::mock(),
+            ConnectionOrigin::Outbound,
+            ConnectionId::from(0),
+        ));
+        // Connections which aren't upgraded by `LibraNetTransport` go through the handshake state
+        // as they're accepted.
+        assert_eq!(
+            transitions_rx.next().await.unwrap(),
+            transition(ConnectionState::Disconnected, ConnectionState::Handshaking)
+        );
+        assert_eq!(
+            transitions_rx.next().await.unwrap(),
+            transition(ConnectionState::Handshaking, ConnectionState::Connected)
+        );
+
+        // The connection drains once its disconnection is requested, until it's closed.
+        let (disconnect_resp_tx, _disconnect_resp_rx) = oneshot::channel();
+        peer_manager
+            .handle_connection_request(ConnectionRequest::DisconnectPeer(
+                peer_id,
+                disconnect_resp_tx,
+            ))
+            .await;
+        assert_eq!(
+            transitions_rx.next().await.unwrap(),
+            transition(ConnectionState::Connected, ConnectionState::Draining)
+        );
+        peer_manager.handle_connection_event(TransportNotification::Disconnected(
+            connection_metadata,
+            DisconnectReason::Requested,
+        ));
+        assert_eq!(
+            transitions_rx.next().await.unwrap(),
+            transition(ConnectionState::Draining, ConnectionState::Disconnected)
+        );
+        assert_eq!(
+            peer_manager.lifecycles.state(&peer_id),
+            ConnectionState::Disconnected
+        );
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_network_events() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, _conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[1]);
+    let peer_id = ids[0];
+    let mut events_rx = peer_manager.events.subscribe();
+    let connection_metadata = ConnectionMetadata::new(
+        peer_id,
+        ConnectionId::from(0),
+        NetworkAddress::mock(),
+        ConnectionOrigin::Outbound,
+        MessagingProtocolVersion::V1,
+        [TEST_PROTOCOL].iter().into(),
+        NetworkId::Validator,
+    );
+
+    let test = async move {
+        let (outbound, _inbound) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            outbound,
+            peer_id,
+            NetworkAddress::mock(),
+            ConnectionOrigin::Outbound,
+            ConnectionId::from(0),
+        ));
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            NetworkEvent::PeerConnected {
+                peer_id,
+                address: NetworkAddress::mock(),
+                origin: ConnectionOrigin::Outbound,
+            }
+        );
+
+        peer_manager.handle_connection_event(TransportNotification::Disconnected(
+            connection_metadata,
+            DisconnectReason::ConnectionLost,
+        ));
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            NetworkEvent::PeerDisconnected {
+                peer_id,
+                address: NetworkAddress::mock(),
+                reason: DisconnectReason::ConnectionLost,
+            }
+        );
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn test_send_rpc_failures() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[1]);
+
+    let send_rpc = |protocol| {
+        let (res_tx, res_rx) = oneshot::channel();
+        let request = PeerManagerRequest::SendRpc(
+            ids[0],
+            OutboundRpcRequest {
+                protocol,
+                data: vec![].into(),
+                res_tx,
+                timeout: Duration::from_secs(10),
+            },
+            RequestTrace::start(),
+        );
+        (request, res_rx)
+    };
+
+    let test = async move {
+        // The peer is not connected.
+        let (request, res_rx) = send_rpc(TEST_PROTOCOL);
+        peer_manager.handle_request(request).await;
+        let result = res_rx.await.unwrap();
+        assert!(matches!(result, Err(RpcError::NotConnected(peer_id)) if peer_id == ids[0]));
+
+        let (outbound, _inbound) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            outbound,
+            ids[0],
+            NetworkAddress::mock(),
+            ConnectionOrigin::Outbound,
+            ConnectionId::from(0),
+        ));
+        let conn_notif = conn_status_rx.next().await.unwrap();
+        assert!(matches!(
+            conn_notif,
+            ConnectionNotification::NewPeer(_, _, _)
+        ));
+
+        // The peer didn't advertise the protocol in the handshake.
+        let (request, res_rx) = send_rpc(ProtocolId::StateSynchronizerDirectSend);
+        peer_manager.handle_request(request).await;
+        let result = res_rx.await.unwrap();
+        assert!(matches!(
+            result,
+            Err(RpcError::ProtocolNotSupported(
+                ProtocolId::StateSynchronizerDirectSend
+            ))
+        ));
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_listener_connection_events() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[0]);
+    let (listener_status_tx, mut listener_status_rx) = conn_notifs_channel::new();
+    let network_id = NetworkId::vfn_network();
+    peer_manager.add_listener(
+        network_id.clone(),
+        build_test_transport(),
+        "/memory/0".parse().unwrap(),
+        vec![listener_status_tx],
+    );
+
+    let test = async move {
+        // A connection established for the network of the listener.
+        let (mut outbound, inbound) = build_test_connection();
+        let addr = NetworkAddress::mock();
+        peer_manager.add_peer(Connection {
+            socket: inbound,
+            metadata: ConnectionMetadata::new(
+                ids[1],
+                ConnectionId::from(0),
+                addr.clone(),
+                ConnectionOrigin::Inbound,
+                MessagingProtocolVersion::V1,
+                [TEST_PROTOCOL].iter().into(),
+                network_id,
+            ),
+        });
+
+        // Only the handlers of the listener are notified of its peers.
+        assert_eq!(
+            listener_status_rx.next().await.unwrap(),
+            ConnectionNotification::NewPeer(ids[1], addr.clone(), ConnectionInfo::mock())
+        );
+        assert!(conn_status_rx.next().now_or_never().is_none());
+
+        outbound.close().await.unwrap();
+        assert_peer_disconnected_event(
+            ids[1],
+            ConnectionOrigin::Inbound,
+            DisconnectReason::ConnectionLost,
+            &mut peer_manager,
+        )
+        .await;
+        assert_eq!(
+            listener_status_rx.next().await.unwrap(),
+            ConnectionNotification::LostPeer(ids[1], addr, DisconnectReason::ConnectionLost)
+        );
+        assert!(conn_status_rx.next().now_or_never().is_none());
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_inbound_connection_quota() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(4);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[0]);
+    peer_manager.quota_limits = QuotaLimits {
+        max_inbound_connections: Some(1),
+        max_inbound_message_bytes: None,
+        max_inflight_rpc_bytes: None,
+    };
+
+    let test = async move {
+        let addr: NetworkAddress = "/ip6/::1/tcp/8080".parse().unwrap();
+        let (mut outbound1, inbound1) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound1,
+            ids[1],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(0),
+        ));
+        assert_eq!(
+            conn_status_rx.next().await.unwrap(),
+            ConnectionNotification::NewPeer(ids[1], addr.clone(), ConnectionInfo::mock())
+        );
+
+        // The quota of the network is exhausted, so the second inbound connection is closed.
+        let (mut outbound2, inbound2) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound2,
+            ids[2],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(1),
+        ));
+        assert!(ping_pong(&mut outbound2).await.is_err());
+        assert!(conn_status_rx.next().now_or_never().is_none());
+        assert_eq!(
+            peer_manager
+                .quota(&NetworkId::Validator)
+                .inbound_connections
+                .used(),
+            1
+        );
+
+        // Losing the first connection releases its reservation.
+        ping_pong(&mut outbound1).await.unwrap();
+        outbound1.close().await.unwrap();
+        assert_peer_disconnected_event(
+            ids[1],
+            ConnectionOrigin::Inbound,
+            DisconnectReason::ConnectionLost,
+            &mut peer_manager,
+        )
+        .await;
+        assert_eq!(
+            peer_manager
+                .quota(&NetworkId::Validator)
+                .inbound_connections
+                .used(),
+            0
+        );
+
+        let (mut outbound3, inbound3) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound3,
+            ids[3],
+            addr,
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(2),
+        ));
+        ping_pong(&mut outbound3).await.unwrap();
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_inbound_connection_eviction() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(4);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[0]);
+    peer_manager.quota_limits = QuotaLimits {
+        max_inbound_connections: Some(1),
+        max_inbound_message_bytes: None,
+        max_inflight_rpc_bytes: None,
+    };
+    // The peer of the second connection is trusted.
+    let trusted_peers = vec![(
+        ids[2],
+        NetworkPublicKeys {
+            identity_public_key: x25519::PrivateKey::generate_for_testing().public_key(),
+        },
+    )]
+    .into_iter()
+    .collect();
+    peer_manager.eviction_policy = Arc::new(DefaultEvictionPolicy::new(
+        Arc::new(RwLock::new(trusted_peers)),
+        PeerScores::new(),
+    ));
+
+    let test = async move {
+        let addr: NetworkAddress = "/ip6/::1/tcp/8080".parse().unwrap();
+        let (mut outbound1, inbound1) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound1,
+            ids[1],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(0),
+        ));
+        assert_eq!(
+            conn_status_rx.next().await.unwrap(),
+            ConnectionNotification::NewPeer(ids[1], addr.clone(), ConnectionInfo::mock())
+        );
+
+        // The quota of the network is exhausted, so the connection of the untrusted peer is
+        // evicted to accept the connection of the trusted one.
+        let (mut outbound2, inbound2) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound2,
+            ids[2],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(1),
+        ));
+        assert_eq!(
+            conn_status_rx.next().await.unwrap(),
+            ConnectionNotification::NewPeer(ids[2], addr.clone(), ConnectionInfo::mock())
+        );
+        assert_peer_disconnected_event(
+            ids[1],
+            ConnectionOrigin::Inbound,
+            DisconnectReason::Requested,
+            &mut peer_manager,
+        )
+        .await;
+        assert_eq!(
+            conn_status_rx.next().await.unwrap(),
+            ConnectionNotification::LostPeer(ids[1], addr.clone(), DisconnectReason::Requested)
+        );
+        assert!(ping_pong(&mut outbound1).await.is_err());
+        ping_pong(&mut outbound2).await.unwrap();
+        assert_eq!(
+            peer_manager
+                .quota(&NetworkId::Validator)
+                .inbound_connections
+                .used(),
+            1
+        );
+
+        // An untrusted peer doesn't evict the trusted one.
+        let (mut outbound3, inbound3) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound3,
+            ids[3],
+            addr,
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(2),
+        ));
+        assert!(ping_pong(&mut outbound3).await.is_err());
+        assert!(conn_status_rx.next().now_or_never().is_none());
+        ping_pong(&mut outbound2).await.unwrap();
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_banned_connections() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(3);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[0]);
+    let ban_list = peer_manager.ban_list.clone();
+    ban_list.ban_peer(ids[1], Duration::from_secs(3600));
+    ban_list.ban_ip_prefix("10.0.0.0/8".parse().unwrap(), Duration::from_secs(3600));
+
+    let test = async move {
+        // The connections of a banned peer are closed.
+        let (mut outbound1, inbound1) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound1,
+            ids[1],
+            "/ip6/::1/tcp/8080".parse().unwrap(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(0),
+        ));
+        assert!(ping_pong(&mut outbound1).await.is_err());
+
+        // So are the connections from a banned IP prefix.
+        let (mut outbound2, inbound2) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound2,
+            ids[2],
+            "/ip4/10.1.2.3/tcp/8080".parse().unwrap(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(1),
+        ));
+        assert!(ping_pong(&mut outbound2).await.is_err());
+        assert!(conn_status_rx.next().now_or_never().is_none());
+
+        // Banned peers aren't dialed.
+        let (response_tx, response_rx) = oneshot::channel();
+        peer_manager
+            .handle_connection_request(ConnectionRequest::DialPeer(
+                ids[1],
+                "/ip6/::1/tcp/8080".parse().unwrap(),
+                response_tx,
+            ))
+            .await;
+        match response_rx.await.unwrap() {
+            Err(PeerManagerError::Banned(peer_id)) => assert_eq!(peer_id, ids[1]),
+            result => panic!("unexpected dial result: {:?}", result),
+        }
+
+        let addr: NetworkAddress = "/ip6/::1/tcp/8081".parse().unwrap();
+        let (mut outbound3, inbound3) = build_test_connection();
+        peer_manager.add_peer(create_connection(
+            inbound3,
+            ids[2],
+            addr.clone(),
+            ConnectionOrigin::Inbound,
+            ConnectionId::from(2),
+        ));
+        ping_pong(&mut outbound3).await.unwrap();
+        assert_eq!(
+            conn_status_rx.next().await.unwrap(),
+            ConnectionNotification::NewPeer(ids[2], addr, ConnectionInfo::mock())
+        );
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_multiple_listen_addrs() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(1);
+    let (peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, _conn_status_rx) =
+        build_test_peer_manager_listening_on(
+            runtime.handle().clone(),
+            ids[0],
+            vec!["/memory/0".parse().unwrap(), "/memory/0".parse().unwrap()],
+            InboundConnectionLimits::default(),
+        );
+
+    // Every listen address is bound, on its own port.
+    let listen_addrs = peer_manager.listen_addrs();
+    assert_eq!(listen_addrs.len(), 2);
+    assert_ne!(listen_addrs[0], listen_addrs[1]);
+    for listen_addr in listen_addrs {
+        assert!(MemoryTransport::default()
+            .listen_on(listen_addr.clone())
+            .is_err());
+    }
+}
+
+#[test]
+fn peer_manager_inbound_connection_limits() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(1);
+    let (peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager_listening_on(
+            runtime.handle().clone(),
+            ids[0],
+            vec!["/memory/0".parse().unwrap()],
+            InboundConnectionLimits {
+                max_inbound_connections: Some(1),
+                max_inbound_connections_per_ip: None,
+            },
+        );
+    let listen_addr = peer_manager.listen_addrs()[0].clone();
+    runtime.spawn(peer_manager.start());
+
+    let test = async move {
+        let transport = MemoryTransport::default();
+        let mut first = transport
+            .dial(PeerId::random(), listen_addr.clone())
+            .unwrap()
+            .await
+            .unwrap();
+        match conn_status_rx.next().await.unwrap() {
+            ConnectionNotification::NewPeer(_, _, _) => {}
+            notification => panic!("Unexpected notification {:?}", notification),
+        }
+
+        // The limit of the listener is reached, so the second connection is closed right away.
+        let mut second = transport
+            .dial(PeerId::random(), listen_addr.clone())
+            .unwrap()
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1];
+        assert_eq!(second.read(&mut buf).await.unwrap(), 0);
+        assert!(conn_status_rx.next().now_or_never().is_none());
+
+        // Closing the first connection makes room for a new one.
+        first.close().await.unwrap();
+        match conn_status_rx.next().await.unwrap() {
+            ConnectionNotification::LostPeer(_, _, _) => {}
+            notification => panic!("Unexpected notification {:?}", notification),
+        }
+        let _third = transport
+            .dial(PeerId::random(), listen_addr)
+            .unwrap()
+            .await
+            .unwrap();
+        match conn_status_rx.next().await.unwrap() {
+            ConnectionNotification::NewPeer(_, _, _) => {}
+            notification => panic!("Unexpected notification {:?}", notification),
+        }
+    };
+
+    runtime.block_on(test);
+}
+
+#[test]
+fn peer_manager_shutdown() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(1);
+    let (mut peer_manager, _request_tx, _connection_reqs_tx, _hello_rx, mut conn_status_rx) =
+        build_test_peer_manager(runtime.handle().clone(), ids[0]);
+    let listen_addr = peer_manager.listen_addrs()[0].clone();
+    let shutdown_tx = peer_manager.shutdown_sender();
+    let peer_manager = runtime.spawn(peer_manager.start());
+
+    let test = async move {
+        let mut connection = MemoryTransport::default()
+            .dial(PeerId::random(), listen_addr.clone())
+            .unwrap()
+            .await
+            .unwrap();
+        match conn_status_rx.next().await.unwrap() {
+            ConnectionNotification::NewPeer(_, _, _) => {}
+            notification => panic!("Unexpected notification {:?}", notification),
+        }
+
+        shutdown_tx
+            .send(Instant::now() + Duration::from_secs(10))
+            .unwrap();
+        // The connection is closed, and the peer reported lost, before the peer manager terminates.
+        let mut buf = [0u8; 1];
+        assert_eq!(connection.read(&mut buf).await.unwrap(), 0);
+        match conn_status_rx.next().await.unwrap() {
+            ConnectionNotification::LostPeer(_, _, DisconnectReason::Requested) => {}
+            notification => panic!("Unexpected notification {:?}", notification),
+        }
+        peer_manager.await.unwrap();
+
+        // The listener is closed, so its address can be listened on again.
+        assert!(MemoryTransport::default().listen_on(listen_addr).is_ok());
+    };
+
+    runtime.block_on(test);
+}
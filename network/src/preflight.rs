@@ -0,0 +1,277 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pre-flight checks of the seed peers of a network, so that operators can validate the config of
+//! a node before deploying it.
+//!
+//! Every address of every seed peer is dialed with the transport the node would use, going through
+//! the Noise and LibraNet handshakes, and the reachability, the latency and the support of the
+//! application protocols the node expects are reported per address.
+
+use crate::{
+    protocols::wire::handshake::v1::{MessagingProtocolVersion, ProtocolId, SupportedProtocols},
+    transport::{LibraNetTransport, TSocket, LIBRA_TCP_TRANSPORT},
+};
+use futures::future::join_all;
+use libra_config::{config::HANDSHAKE_VERSION, network_id::NetworkId};
+use libra_crypto::x25519;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use netcore::transport::{tcp::TcpTransport, Transport};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    time::{Duration, Instant},
+};
+
+/// A seed peer address which could be dialed.
+#[derive(Debug)]
+pub struct ReachableSeedPeer {
+    /// Time taken to connect and complete the Noise and LibraNet handshakes.
+    pub latency: Duration,
+    pub messaging_protocol: MessagingProtocolVersion,
+    /// Expected application protocols supported by the seed peer.
+    pub supported_protocols: Vec<ProtocolId>,
+    /// Expected application protocols not supported by the seed peer.
+    pub missing_protocols: Vec<ProtocolId>,
+}
+
+impl ReachableSeedPeer {
+    pub fn is_compatible(&self) -> bool {
+        self.missing_protocols.is_empty()
+    }
+}
+
+/// Result of the pre-flight check of one address of a seed peer.
+#[derive(Debug)]
+pub struct SeedPeerCheck {
+    pub peer_id: PeerId,
+    pub addr: NetworkAddress,
+    pub result: io::Result<ReachableSeedPeer>,
+}
+
+impl SeedPeerCheck {
+    /// Whether the address is reachable and supports all the expected protocols.
+    pub fn is_ok(&self) -> bool {
+        match &self.result {
+            Ok(seed_peer) => seed_peer.is_compatible(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for SeedPeerCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.result {
+            Ok(seed_peer) if seed_peer.is_compatible() => write!(
+                f,
+                "OK {} {}: reachable in {}ms, messaging protocol {:?}",
+                self.peer_id.short_str(),
+                self.addr,
+                seed_peer.latency.as_millis(),
+                seed_peer.messaging_protocol
+            ),
+            Ok(seed_peer) => write!(
+                f,
+                "INCOMPATIBLE {} {}: reachable in {}ms, missing protocols {:?}",
+                self.peer_id.short_str(),
+                self.addr,
+                seed_peer.latency.as_millis(),
+                seed_peer.missing_protocols
+            ),
+            Err(err) => write!(
+                f,
+                "UNREACHABLE {} {}: {}",
+                self.peer_id.short_str(),
+                self.addr,
+                err
+            ),
+        }
+    }
+}
+
+/// Dials seed peers on behalf of a node.
+pub struct SeedPeerChecker<TTransport> {
+    transport: LibraNetTransport<TTransport>,
+    expected_protocols: Vec<ProtocolId>,
+}
+
+impl SeedPeerChecker<TcpTransport> {
+    /// Returns a checker dialing over TCP, as the node would, with its identity on the network
+    /// `network_id`.
+    pub fn new(
+        peer_id: PeerId,
+        identity_key: x25519::PrivateKey,
+        network_id: NetworkId,
+        expected_protocols: Vec<ProtocolId>,
+    ) -> Self {
+        Self::with_transport(
+            LIBRA_TCP_TRANSPORT,
+            peer_id,
+            identity_key,
+            network_id,
+            expected_protocols,
+        )
+    }
+}
+
+impl<TTransport> SeedPeerChecker<TTransport>
+where
+    TTransport: Transport<Error = io::Error>,
+    TTransport::Output: TSocket,
+    TTransport::Outbound: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Listener: Send + 'static,
+{
+    pub fn with_transport(
+        base_transport: TTransport,
+        peer_id: PeerId,
+        identity_key: x25519::PrivateKey,
+        network_id: NetworkId,
+        expected_protocols: Vec<ProtocolId>,
+    ) -> Self {
+        // Seed peers are authenticated with the public key in their addresses, so no trusted peers
+        // are needed. Seed peers requiring mutual authentication reject the node if it is not one
+        // of their trusted peers, which is reported as the address being unreachable.
+        let transport = LibraNetTransport::new(
+            base_transport,
+            peer_id,
+            identity_key,
+            None,
+            HANDSHAKE_VERSION,
+            network_id,
+            SupportedProtocols::from(expected_protocols.iter()),
+            None,
+        );
+        Self {
+            transport,
+            expected_protocols,
+        }
+    }
+
+    /// Dials all the addresses of `seed_peers` concurrently, and returns their checks ordered by
+    /// peer.
+    pub async fn check_seed_peers(
+        &self,
+        seed_peers: &HashMap<PeerId, Vec<NetworkAddress>>,
+    ) -> Vec<SeedPeerCheck> {
+        let mut targets: Vec<_> = seed_peers
+            .iter()
+            .flat_map(|(peer_id, addrs)| addrs.iter().map(move |addr| (*peer_id, addr.clone())))
+            .collect();
+        // the sort is stable, so the addresses of a peer keep their order of preference
+        targets.sort_by_key(|(peer_id, _)| *peer_id);
+
+        join_all(targets.into_iter().map(|(peer_id, addr)| async move {
+            let result = self.check_addr(peer_id, addr.clone()).await;
+            SeedPeerCheck {
+                peer_id,
+                addr,
+                result,
+            }
+        }))
+        .await
+    }
+
+    async fn check_addr(
+        &self,
+        peer_id: PeerId,
+        addr: NetworkAddress,
+    ) -> io::Result<ReachableSeedPeer> {
+        let start = Instant::now();
+        let connection = self.transport.dial(peer_id, addr)?.await?;
+        let latency = start.elapsed();
+
+        let negotiated_protocols = connection.metadata.application_protocols();
+        let (supported_protocols, missing_protocols) = self
+            .expected_protocols
+            .iter()
+            .copied()
+            .partition(|protocol| negotiated_protocols.contains(*protocol));
+        Ok(ReachableSeedPeer {
+            latency,
+            messaging_protocol: connection.metadata.messaging_protocol(),
+            supported_protocols,
+            missing_protocols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::stream::StreamExt;
+    use libra_crypto::{test_utils::TEST_SEED, traits::Uniform};
+    use libra_network_address::Protocol;
+    use netcore::transport::memory::MemoryTransport;
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_check_seed_peers() {
+        let mut rt = Runtime::new().unwrap();
+        let mut rng = StdRng::from_seed(TEST_SEED);
+        let seed_key = x25519::PrivateKey::generate(&mut rng);
+        let node_key = x25519::PrivateKey::generate(&mut rng);
+        let wrong_key = x25519::PrivateKey::generate(&mut rng);
+        let seed_peer_id = PeerId::from_identity_public_key(seed_key.public_key());
+        let node_peer_id = PeerId::from_identity_public_key(node_key.public_key());
+
+        // the seed peer supports consensus, but not mempool
+        let seed_transport = LibraNetTransport::new(
+            MemoryTransport,
+            seed_peer_id,
+            seed_key,
+            None,
+            HANDSHAKE_VERSION,
+            NetworkId::Validator,
+            SupportedProtocols::from(
+                [ProtocolId::ConsensusRpc, ProtocolId::HealthCheckerRpc].iter(),
+            ),
+            None,
+        );
+        let (inbounds, seed_addr) = rt.enter(|| {
+            seed_transport
+                .listen_on("/memory/0".parse().unwrap())
+                .unwrap()
+        });
+        rt.spawn(inbounds.for_each_concurrent(None, |inbound| async move {
+            if let Ok((upgrade, _addr)) = inbound {
+                let _ = upgrade.await;
+            }
+        }));
+
+        // an address of the seed peer advertising another public key
+        let mut wrong_key_addr: Vec<_> = seed_addr.as_slice().to_vec();
+        wrong_key_addr[1] = Protocol::NoiseIK(wrong_key.public_key());
+        let wrong_key_addr = NetworkAddress::new(wrong_key_addr);
+
+        let checker = SeedPeerChecker::with_transport(
+            MemoryTransport,
+            node_peer_id,
+            node_key,
+            NetworkId::Validator,
+            vec![ProtocolId::ConsensusRpc, ProtocolId::MempoolDirectSend],
+        );
+        let seed_peers = vec![(seed_peer_id, vec![seed_addr.clone(), wrong_key_addr])]
+            .into_iter()
+            .collect();
+        let checks = rt.block_on(checker.check_seed_peers(&seed_peers));
+
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].addr, seed_addr);
+        let seed_peer = checks[0].result.as_ref().unwrap();
+        assert_eq!(seed_peer.messaging_protocol, MessagingProtocolVersion::V2);
+        assert_eq!(
+            seed_peer.supported_protocols,
+            vec![ProtocolId::ConsensusRpc]
+        );
+        assert_eq!(
+            seed_peer.missing_protocols,
+            vec![ProtocolId::MempoolDirectSend]
+        );
+        assert!(!checks[0].is_ok());
+        assert!(checks[1].result.is_err());
+        assert!(!checks[1].is_ok());
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Priorities of the outbound messages of the application protocols, so that the latency
+//! sensitive protocols, e.g., consensus and the health checker, are not stuck behind a burst of
+//! bulk traffic, e.g., of mempool broadcasts.
+//!
+//! Every protocol handler is registered with a `ProtocolPriority`. The requests to the
+//! PeerManager and the outbound queue of every peer serve the protocols of the highest priority
+//! first, and the protocols of a same priority in a round-robin fashion. A protocol is only served
+//! once no protocol of higher priority has pending messages for the peer.
+
+use crate::ProtocolId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Priority of the outbound messages of a protocol.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ProtocolPriority {
+    /// Bulk traffic, e.g., mempool broadcasts and state sync chunks.
+    Low = 0,
+    Normal = 1,
+    /// Latency sensitive traffic, e.g., consensus messages and health checks.
+    High = 2,
+}
+
+impl Default for ProtocolPriority {
+    fn default() -> Self {
+        ProtocolPriority::Normal
+    }
+}
+
+/// The priorities of the protocols of a network, `ProtocolPriority::Normal` by default. Cloning it
+/// returns a handle to the same priorities, so that the protocols registered after the creation of
+/// a queue are prioritized as well.
+#[derive(Clone, Debug, Default)]
+pub struct ProtocolPriorities(Arc<RwLock<HashMap<ProtocolId, ProtocolPriority>>>);
+
+impl ProtocolPriorities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, protocol: ProtocolId, priority: ProtocolPriority) {
+        self.0.write().unwrap().insert(protocol, priority);
+    }
+
+    pub fn get(&self, protocol: ProtocolId) -> ProtocolPriority {
+        self.0
+            .read()
+            .unwrap()
+            .get(&protocol)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_priorities() {
+        let priorities = ProtocolPriorities::new();
+        let handle = priorities.clone();
+        assert_eq!(
+            handle.get(ProtocolId::ConsensusRpc),
+            ProtocolPriority::Normal
+        );
+        priorities.set(ProtocolId::ConsensusRpc, ProtocolPriority::High);
+        priorities.set(ProtocolId::MempoolDirectSend, ProtocolPriority::Low);
+        assert_eq!(handle.get(ProtocolId::ConsensusRpc), ProtocolPriority::High);
+        assert_eq!(
+            handle.get(ProtocolId::MempoolDirectSend),
+            ProtocolPriority::Low
+        );
+        assert!(ProtocolPriority::High > ProtocolPriority::Low);
+    }
+}
@@ -0,0 +1,530 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Kademlia-style distributed hash table discovering the addresses of the peers of the public
+//! full-node network.
+//!
+//! Unlike gossip [`discovery`](crate::protocols::discovery), which exchanges the full set of known
+//! peers, every node only keeps the records of up to [`BUCKET_SIZE`] peers at each XOR distance
+//! from its own PeerId in its [`RoutingTable`], and only the records of the peers closest to a
+//! target are exchanged, so that the state and the traffic of a node grow with the logarithm of
+//! the size of the network.
+//!
+//! ## Records
+//!
+//! A [`PeerRecord`] carries the addresses of a peer and an epoch, usually a timestamp, so that the
+//! newer record of a peer replaces the older one. Records are signed with an Ed25519 key whose
+//! X25519 counterpart, following the XEdDSA approach, is the network identity key of the peer, so
+//! that the PeerId is bound to the signing key and any node can verify the records it receives
+//! from third parties. Invalid records are dropped.
+//!
+//! ## Protocol
+//!
+//! - On connecting to a peer, the node sends it its own record with a [`DhtMsg::Store`], and looks
+//! itself up with a [`DhtMsg::FindNode`], to learn the peers closest to it.
+//! - Every discovery interval, the node looks a random PeerId up, querying the
+//! [`LOOKUP_PARALLELISM`] connected peers closest to the target.
+//! - A peer answers a [`DhtMsg::FindNode`] with the [`BUCKET_SIZE`] records closest to the target
+//! it knows of, including its own.
+//!
+//! The addresses of the routing table are sent to the [`ConnectivityManager`] on every change,
+//! which dials the closer peers, so that the lookups converge over the following intervals, as the
+//! iterative lookups of Kademlia do.
+//!
+//! ## Future work
+//!
+//! - The record is not re-issued on rotations of the network identity key.
+//! - The peers of a full bucket are not pinged before newcomers are dropped, so that unresponsive
+//! peers are only evicted once their records are replaced.
+//!
+//! [`ConnectivityManager`]: ../../connectivity_manager
+
+use crate::{
+    catch_panic::catch_panic,
+    connectivity_manager::{ConnectivityRequest, DiscoverySource},
+    counters,
+    error::NetworkError,
+    peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
+    protocols::{
+        network::{Event, NetworkEvents, NetworkSender},
+        rpc::error::RpcError,
+    },
+    validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
+    ProtocolId,
+};
+use anyhow::{ensure, Result};
+use bytes::Bytes;
+use channel::message_queues::QueueStyle;
+use futures::{
+    channel::oneshot,
+    sink::SinkExt,
+    stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
+};
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature},
+    x25519, CryptoMaterialError, PrivateKey, Signature, SigningKey,
+};
+use libra_crypto_derive::{CryptoHasher, LCSCryptoHash};
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use libra_security_logger::{security_log, SecurityEvent};
+use libra_types::PeerId;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+#[cfg(test)]
+mod test;
+
+const ACTOR: &str = "dht";
+
+/// Maximum number of records of a routing table at each distance, `k` in Kademlia.
+pub const BUCKET_SIZE: usize = 20;
+/// Number of peers queried by a lookup, `alpha` in Kademlia.
+pub const LOOKUP_PARALLELISM: usize = 3;
+/// Maximum number of addresses in a record.
+pub const MAX_RECORD_ADDRS: usize = 8;
+
+/// Timeout of the DHT RPCs.
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type DhtNetworkEvents = NetworkEvents<DhtMsg>;
+
+/// The interface from the DHT to the network layer.
+#[derive(Clone)]
+pub struct DhtNetworkSender {
+    inner: NetworkSender<DhtMsg>,
+}
+
+pub fn add_to_network(network: &mut NetworkBuilder) -> (DhtNetworkSender, DhtNetworkEvents) {
+    let (sender, receiver, connection_reqs_tx, connection_notifs_rx) = network
+        .add_protocol_handler(
+            vec![ProtocolId::DhtRpc],
+            vec![],
+            ProtocolPriority::Normal,
+            QueueStyle::LIFO,
+            NETWORK_CHANNEL_SIZE,
+            Some(&counters::PENDING_DHT_NETWORK_EVENTS),
+        );
+    (
+        DhtNetworkSender::new(sender, connection_reqs_tx),
+        DhtNetworkEvents::new(receiver, connection_notifs_rx),
+    )
+}
+
+impl DhtNetworkSender {
+    pub fn new(
+        peer_mgr_reqs_tx: PeerManagerRequestSender,
+        connection_reqs_tx: ConnectionRequestSender,
+    ) -> Self {
+        Self {
+            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
+        }
+    }
+
+    /// Send a DHT request to `recipient`, and wait for its response until `timeout`.
+    pub async fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        req_msg: DhtMsg,
+        timeout: Duration,
+    ) -> Result<DhtMsg, NetworkError> {
+        self.inner
+            .send_rpc(recipient, ProtocolId::DhtRpc, req_msg, timeout)
+            .await
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DhtMsg {
+    /// Requests the records closest to the PeerId known to the recipient.
+    FindNode(PeerId),
+    /// The response to a `FindNode`.
+    Nodes(Vec<SignedPeerRecord>),
+    /// Requests the recipient to store the record.
+    Store(SignedPeerRecord),
+    /// The response to a `Store`.
+    Stored,
+}
+
+/// The addresses of a peer.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, CryptoHasher, LCSCryptoHash)]
+pub struct PeerRecord {
+    pub peer_id: PeerId,
+    pub addrs: Vec<NetworkAddress>,
+    /// Monotonically increasing incarnation number, so that peers can issue updates to their
+    /// records and old records can't be replayed. This is usually a timestamp.
+    pub epoch: u64,
+}
+
+/// A [`PeerRecord`] signed by its peer.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SignedPeerRecord {
+    record: PeerRecord,
+    public_key: Ed25519PublicKey,
+    signature: Ed25519Signature,
+}
+
+impl SignedPeerRecord {
+    pub fn sign(
+        record: PeerRecord,
+        signing_key: &Ed25519PrivateKey,
+    ) -> Result<Self, CryptoMaterialError> {
+        let signature = signing_key.sign(&record)?;
+        Ok(Self {
+            record,
+            public_key: signing_key.public_key(),
+            signature,
+        })
+    }
+
+    pub fn record(&self) -> &PeerRecord {
+        &self.record
+    }
+
+    /// Checks that the record is signed by the key of its peer, and within the limits of what
+    /// records may carry.
+    pub fn verify(&self) -> Result<()> {
+        ensure!(
+            self.record.addrs.len() <= MAX_RECORD_ADDRS,
+            "Record has {} addresses, more than the maximum of {}",
+            self.record.addrs.len(),
+            MAX_RECORD_ADDRS
+        );
+        ensure!(
+            signing_peer_id(&self.public_key)? == self.record.peer_id,
+            "Record of peer {} is signed by the key of another peer",
+            self.record.peer_id.short_str()
+        );
+        self.signature
+            .verify_struct_msg(&self.record, &self.public_key)
+    }
+}
+
+/// The PeerId of the node whose network identity key is the X25519 counterpart of `public_key`.
+pub fn signing_peer_id(public_key: &Ed25519PublicKey) -> Result<PeerId> {
+    let identity_key = x25519::PublicKey::from_ed25519_public_bytes(&public_key.to_bytes())?;
+    Ok(PeerId::from_identity_public_key(identity_key))
+}
+
+/// The XOR distance between two PeerIds, ordered as a big-endian integer.
+fn distance(a: &PeerId, b: &PeerId) -> [u8; PeerId::LENGTH] {
+    let mut distance = [0u8; PeerId::LENGTH];
+    for (d, (a, b)) in distance
+        .iter_mut()
+        .zip(a.as_ref().iter().zip(b.as_ref().iter()))
+    {
+        *d = a ^ b;
+    }
+    distance
+}
+
+/// The index of the bucket of the peers at `distance`, i.e., the position of its highest set bit,
+/// or `None` for a peer at distance zero, i.e., the node itself.
+fn bucket_index(distance: &[u8; PeerId::LENGTH]) -> Option<usize> {
+    let leading_zeros = distance
+        .iter()
+        .position(|byte| *byte != 0)
+        .map(|i| i * 8 + distance[i].leading_zeros() as usize)?;
+    Some(PeerId::LENGTH * 8 - 1 - leading_zeros)
+}
+
+/// The records of the peers known to a node, in buckets of up to [`BUCKET_SIZE`] records of the
+/// peers at the same distance from the node, the most recently updated last.
+#[derive(Clone, Debug)]
+pub struct RoutingTable {
+    self_peer_id: PeerId,
+    buckets: Vec<Vec<SignedPeerRecord>>,
+}
+
+impl RoutingTable {
+    pub fn new(self_peer_id: PeerId) -> Self {
+        Self {
+            self_peer_id,
+            buckets: vec![Vec::new(); PeerId::LENGTH * 8],
+        }
+    }
+
+    /// Inserts `signed`, or replaces the record of its peer if `signed` is newer. As in Kademlia,
+    /// the peers of a full bucket are kept over newcomers, as the peers known for longest are the
+    /// likeliest to stay online. Returns whether the table changed.
+    pub fn insert(&mut self, signed: SignedPeerRecord) -> bool {
+        let index = match bucket_index(&distance(&self.self_peer_id, &signed.record.peer_id)) {
+            Some(index) => index,
+            None => return false,
+        };
+        let bucket = &mut self.buckets[index];
+        match bucket
+            .iter()
+            .position(|known| known.record.peer_id == signed.record.peer_id)
+        {
+            Some(pos) if bucket[pos].record.epoch >= signed.record.epoch => false,
+            Some(pos) => {
+                bucket.remove(pos);
+                bucket.push(signed);
+                true
+            }
+            None if bucket.len() >= BUCKET_SIZE => false,
+            None => {
+                bucket.push(signed);
+                true
+            }
+        }
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&SignedPeerRecord> {
+        let index = bucket_index(&distance(&self.self_peer_id, peer_id))?;
+        self.buckets[index]
+            .iter()
+            .find(|known| known.record.peer_id == *peer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the `n` records closest to `target`, the closest first.
+    pub fn closest(&self, target: &PeerId, n: usize) -> Vec<SignedPeerRecord> {
+        let mut records: Vec<_> = self.buckets.iter().flatten().cloned().collect();
+        records.sort_by_key(|signed| distance(target, &signed.record.peer_id));
+        records.truncate(n);
+        records
+    }
+
+    /// The addresses of all the peers of the table.
+    pub fn addresses(&self) -> HashMap<PeerId, Vec<NetworkAddress>> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|signed| (signed.record.peer_id, signed.record.addrs.clone()))
+            .collect()
+    }
+}
+
+/// The actor discovering the peers of the network by running the DHT protocol.
+pub struct Dht<TTicker> {
+    /// The signed record of this node, sent to the peers it connects to.
+    self_record: SignedPeerRecord,
+    /// Ticker to trigger the lookups of random PeerIds.
+    ticker: TTicker,
+    network_tx: DhtNetworkSender,
+    network_rx: DhtNetworkEvents,
+    /// Channel to send the addresses of the routing table to the ConnectivityManager.
+    conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    routing_table: RoutingTable,
+    connected: HashSet<PeerId>,
+    rng: SmallRng,
+}
+
+impl<TTicker> Dht<TTicker>
+where
+    TTicker: Stream + FusedStream + Unpin,
+{
+    /// Create new instance of the [`Dht`] actor, publishing the signed `self_record`.
+    pub fn new(
+        self_record: SignedPeerRecord,
+        ticker: TTicker,
+        network_tx: DhtNetworkSender,
+        network_rx: DhtNetworkEvents,
+        conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    ) -> Self {
+        let routing_table = RoutingTable::new(self_record.record.peer_id);
+        Self {
+            self_record,
+            ticker,
+            network_tx,
+            network_rx,
+            conn_mgr_reqs_tx,
+            routing_table,
+            connected: HashSet::new(),
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        let mut rpcs = FuturesUnordered::new();
+        loop {
+            let next_event = async {
+                futures::select! {
+                    event = self.network_rx.select_next_some() => {
+                        match event {
+                            Ok(Event::NewPeer(peer_id)) => {
+                                self.connected.insert(peer_id);
+                                let self_peer_id = self.self_record.record.peer_id;
+                                for req in vec![
+                                    DhtMsg::Store(self.self_record.clone()),
+                                    DhtMsg::FindNode(self_peer_id),
+                                ] {
+                                    rpcs.push(Self::send_request(
+                                        self.network_tx.clone(),
+                                        peer_id,
+                                        req,
+                                    ));
+                                }
+                            }
+                            Ok(Event::LostPeer(peer_id)) => {
+                                self.connected.remove(&peer_id);
+                            }
+                            Ok(Event::RpcRequest((peer_id, req, res_tx))) => {
+                                self.handle_request(peer_id, req, res_tx).await;
+                            }
+                            Ok(event) => {
+                                warn!("Unexpected DHT network event: {:?}", event);
+                            }
+                            Err(err) => {
+                                warn!("DHT network error: {:?}", err);
+                            }
+                        }
+                    }
+                    _ = self.ticker.select_next_some() => {
+                        let target = PeerId::new(self.rng.gen());
+                        for peer_id in self.closest_connected(&target) {
+                            rpcs.push(Self::send_request(
+                                self.network_tx.clone(),
+                                peer_id,
+                                DhtMsg::FindNode(target),
+                            ));
+                        }
+                    }
+                    res = rpcs.select_next_some() => {
+                        let (peer_id, res) = res;
+                        match res {
+                            Ok(DhtMsg::Nodes(records)) => {
+                                self.handle_records(peer_id, records).await;
+                            }
+                            Ok(DhtMsg::Stored) => {}
+                            Ok(msg) => {
+                                warn!(
+                                    "Unexpected DHT response from peer {}: {:?}",
+                                    peer_id.short_str(),
+                                    msg
+                                );
+                            }
+                            Err(err) => {
+                                debug!(
+                                    "DHT request to peer {} failed: {:?}",
+                                    peer_id.short_str(),
+                                    err
+                                );
+                            }
+                        }
+                    }
+                    complete => return false,
+                }
+                true
+            };
+            match catch_panic(ACTOR, next_event).await {
+                // The routing table is only updated atomically, so there is no state to restore on
+                // panics.
+                Ok(true) | Err(_) => {}
+                Ok(false) => break,
+            }
+        }
+        crit!("DHT actor terminated");
+    }
+
+    /// The connected peers closest to `target`, to query in a lookup.
+    fn closest_connected(&self, target: &PeerId) -> Vec<PeerId> {
+        let mut peers: Vec<_> = self.connected.iter().copied().collect();
+        peers.sort_by_key(|peer_id| distance(target, peer_id));
+        peers.truncate(LOOKUP_PARALLELISM);
+        peers
+    }
+
+    /// The records closest to `target`, including the record of this node.
+    fn closest_records(&self, target: &PeerId) -> Vec<SignedPeerRecord> {
+        let mut records = self.routing_table.closest(target, BUCKET_SIZE);
+        records.push(self.self_record.clone());
+        records.sort_by_key(|signed| distance(target, &signed.record.peer_id));
+        records.truncate(BUCKET_SIZE);
+        records
+    }
+
+    async fn handle_request(
+        &mut self,
+        peer_id: PeerId,
+        req: DhtMsg,
+        res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+    ) {
+        let res = match req {
+            DhtMsg::FindNode(target) => DhtMsg::Nodes(self.closest_records(&target)),
+            DhtMsg::Store(signed) => {
+                self.handle_records(peer_id, vec![signed]).await;
+                DhtMsg::Stored
+            }
+            req => {
+                warn!(
+                    "Unexpected DHT request from peer {}: {:?}",
+                    peer_id.short_str(),
+                    req
+                );
+                return;
+            }
+        };
+        match lcs::to_bytes(&res) {
+            Ok(res) => {
+                let _ = res_tx.send(Ok(res.into()));
+            }
+            Err(err) => warn!(
+                "Unable to serialize the DHT response to peer {}: {}",
+                peer_id.short_str(),
+                err
+            ),
+        }
+    }
+
+    /// Inserts the valid `records` received from `remote_peer` in the routing table, and sends
+    /// the addresses of the table to the ConnectivityManager if it changed.
+    async fn handle_records(&mut self, remote_peer: PeerId, records: Vec<SignedPeerRecord>) {
+        let mut change_detected = false;
+        for signed in records {
+            if let Err(err) = signed.verify() {
+                security_log(SecurityEvent::InvalidDhtRecord)
+                    .error(&err)
+                    .data(&remote_peer)
+                    .log();
+                continue;
+            }
+            if self.routing_table.insert(signed) {
+                change_detected = true;
+            }
+        }
+        if change_detected {
+            counters::LIBRA_NETWORK_DHT_PEERS.set(self.routing_table.len() as i64);
+            self.conn_mgr_reqs_tx
+                .send(ConnectivityRequest::UpdateAddresses(
+                    DiscoverySource::Dht,
+                    self.routing_table.addresses(),
+                ))
+                .await
+                .expect("ConnectivityRequest::UpdateAddresses send");
+        }
+    }
+
+    async fn send_request(
+        mut network_tx: DhtNetworkSender,
+        peer_id: PeerId,
+        req: DhtMsg,
+    ) -> (PeerId, Result<DhtMsg, NetworkError>) {
+        (
+            peer_id,
+            network_tx.send_rpc(peer_id, req, RPC_TIMEOUT).await,
+        )
+    }
+}
+
+/// The current epoch of the record of a node, in milliseconds since the Unix epoch.
+pub fn get_unix_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("System clock reset to before unix epoch")
+        .as_millis() as u64
+}
@@ -0,0 +1,284 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    peer_manager::{self, conn_notifs_channel, PeerManagerNotification, PeerManagerRequest},
+    protocols::{
+        rpc::{InboundRpcRequest, OutboundRpcRequest},
+        wire::handshake::v1::Encoding,
+    },
+};
+use channel::libra_channel;
+use libra_crypto::Uniform;
+use rand::rngs::StdRng;
+use std::{num::NonZeroUsize, str::FromStr};
+use tokio::runtime::Runtime;
+
+/// Returns a signing key, and the PeerId it signs the records of.
+fn signing_key(rng: &mut StdRng) -> (Ed25519PrivateKey, PeerId) {
+    let signing_key = Ed25519PrivateKey::generate(rng);
+    let peer_id = signing_peer_id(&signing_key.public_key()).unwrap();
+    (signing_key, peer_id)
+}
+
+fn signed_record(
+    signing_key: &Ed25519PrivateKey,
+    peer_id: PeerId,
+    addr: &str,
+    epoch: u64,
+) -> SignedPeerRecord {
+    let record = PeerRecord {
+        peer_id,
+        addrs: vec![NetworkAddress::from_str(addr).unwrap()],
+        epoch,
+    };
+    SignedPeerRecord::sign(record, signing_key).unwrap()
+}
+
+fn setup_dht(
+    rt: &mut Runtime,
+    self_record: SignedPeerRecord,
+) -> (
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    channel::Receiver<ConnectivityRequest>,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    conn_notifs_channel::Sender,
+    channel::Sender<()>,
+) {
+    let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (connection_reqs_tx, _) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (conn_mgr_reqs_tx, conn_mgr_reqs_rx) = channel::new_test(1);
+    let (network_notifs_tx, network_notifs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
+    let (ticker_tx, ticker_rx) = channel::new_test(0);
+    let dht = Dht::new(
+        self_record,
+        ticker_rx,
+        DhtNetworkSender::new(
+            PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+            ConnectionRequestSender::new(connection_reqs_tx),
+        ),
+        DhtNetworkEvents::new(network_notifs_rx, connection_notifs_rx),
+        conn_mgr_reqs_tx,
+    );
+    rt.spawn(dht.start());
+    (
+        peer_mgr_reqs_rx,
+        conn_mgr_reqs_rx,
+        network_notifs_tx,
+        connection_notifs_tx,
+        ticker_tx,
+    )
+}
+
+async fn send_new_peer_notification(
+    peer_id: PeerId,
+    connection_notifs_tx: &mut conn_notifs_channel::Sender,
+) {
+    let (delivered_tx, delivered_rx) = oneshot::channel();
+    connection_notifs_tx
+        .push_with_feedback(
+            peer_id,
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_id,
+                NetworkAddress::from_str("/ip6/::1/tcp/8081").unwrap(),
+                peer_manager::ConnectionInfo::mock(),
+            ),
+            Some(delivered_tx),
+        )
+        .unwrap();
+    delivered_rx.await.unwrap();
+}
+
+async fn expect_request(
+    network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+) -> (DhtMsg, OutboundRpcRequest) {
+    let (peer_id, rpc_req) = match network_reqs_rx.next().await.unwrap() {
+        PeerManagerRequest::SendRpc(peer_id, rpc_req, _) => (peer_id, rpc_req),
+        req => panic!("Unexpected PeerManagerRequest: {:?}", req),
+    };
+    assert_eq!(peer_id, expected_peer_id);
+    assert_eq!(rpc_req.protocol, ProtocolId::DhtRpc);
+    (lcs::from_bytes(&rpc_req.data).unwrap(), rpc_req)
+}
+
+fn send_response(rpc_req: OutboundRpcRequest, res: DhtMsg) {
+    rpc_req
+        .res_tx
+        .send(Ok(lcs::to_bytes(&res).unwrap().into()))
+        .unwrap();
+}
+
+async fn expect_address_update(
+    conn_mgr_reqs_rx: &mut channel::Receiver<ConnectivityRequest>,
+    expected_address_map: HashMap<PeerId, Vec<NetworkAddress>>,
+) {
+    match conn_mgr_reqs_rx.next().await.unwrap() {
+        ConnectivityRequest::UpdateAddresses(src, address_map) => {
+            assert_eq!(DiscoverySource::Dht, src);
+            assert_eq!(expected_address_map, address_map);
+        }
+        req => panic!("Unexpected request to connectivity manager: {:?}", req),
+    }
+}
+
+async fn send_inbound_request(
+    peer_id: PeerId,
+    req: DhtMsg,
+    network_notifs_tx: &mut libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+) -> DhtMsg {
+    let (res_tx, res_rx) = oneshot::channel();
+    let inbound_rpc_req = InboundRpcRequest {
+        protocol: ProtocolId::DhtRpc,
+        data: lcs::to_bytes(&req).unwrap().into(),
+        res_tx,
+    };
+    network_notifs_tx
+        .push(
+            (peer_id, ProtocolId::DhtRpc),
+            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req, Encoding::Lcs),
+        )
+        .unwrap();
+    lcs::from_bytes(&res_rx.await.unwrap().unwrap()).unwrap()
+}
+
+#[test]
+fn verify_record() {
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let (key_a, peer_a) = signing_key(&mut rng);
+    let (key_b, _) = signing_key(&mut rng);
+
+    let signed = signed_record(&key_a, peer_a, "/ip4/127.0.0.1/tcp/9090", 1);
+    signed.verify().unwrap();
+
+    // The record of a peer signed by the key of another peer.
+    signed_record(&key_b, peer_a, "/ip4/127.0.0.1/tcp/9090", 1)
+        .verify()
+        .unwrap_err();
+
+    // A record altered after it was signed.
+    let mut altered = signed;
+    altered.record.epoch = 2;
+    altered.verify().unwrap_err();
+}
+
+#[test]
+fn routing_table() {
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let (signing_key, _) = signing_key(&mut rng);
+    let self_peer_id = PeerId::new([0u8; PeerId::LENGTH]);
+    let mut table = RoutingTable::new(self_peer_id);
+    let peer = |first: u8, last: u8| {
+        let mut bytes = [0u8; PeerId::LENGTH];
+        bytes[0] = first;
+        bytes[PeerId::LENGTH - 1] = last;
+        PeerId::new(bytes)
+    };
+    let record = |peer_id, epoch| signed_record(&signing_key, peer_id, "/memory/1", epoch);
+
+    // The records of the farthest peers fill the highest bucket, and newcomers are dropped.
+    for i in 0..BUCKET_SIZE as u8 {
+        assert!(table.insert(record(peer(0x80, i), 1)));
+    }
+    assert!(!table.insert(record(peer(0x80, 0xff), 1)));
+    assert_eq!(table.len(), BUCKET_SIZE);
+
+    // Only newer records of a known peer replace its record.
+    assert!(!table.insert(record(peer(0x80, 0), 1)));
+    assert!(table.insert(record(peer(0x80, 0), 2)));
+    assert_eq!(table.get(&peer(0x80, 0)).unwrap().record().epoch, 2);
+
+    // The node itself is never inserted.
+    assert!(!table.insert(record(self_peer_id, 1)));
+
+    // The closer peers are in other buckets.
+    assert!(table.insert(record(peer(0x01, 0), 1)));
+    assert!(table.insert(record(peer(0, 1), 1)));
+    let closest: Vec<_> = table
+        .closest(&self_peer_id, 3)
+        .iter()
+        .map(|signed| signed.record().peer_id)
+        .collect();
+    assert_eq!(closest, vec![peer(0, 1), peer(0x01, 0), peer(0x80, 0)]);
+}
+
+#[test]
+fn find_node_and_store() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let (self_key, self_peer_id) = signing_key(&mut rng);
+    let (key_a, peer_a) = signing_key(&mut rng);
+    let (key_b, peer_b) = signing_key(&mut rng);
+    let (key_c, peer_c) = signing_key(&mut rng);
+    let self_record = signed_record(&self_key, self_peer_id, "/ip4/127.0.0.1/tcp/9090", 1);
+    let record_b = signed_record(&key_b, peer_b, "/ip4/127.0.0.1/tcp/9091", 1);
+    let record_c = signed_record(&key_c, peer_c, "/ip4/127.0.0.1/tcp/9092", 1);
+
+    let (
+        mut network_reqs_rx,
+        mut conn_mgr_reqs_rx,
+        mut network_notifs_tx,
+        mut connection_notifs_tx,
+        _ticker_tx,
+    ) = setup_dht(&mut rt, self_record.clone());
+
+    let events_f = async move {
+        // On connecting to a peer, the node stores its record at the peer and looks itself up.
+        send_new_peer_notification(peer_a, &mut connection_notifs_tx).await;
+        let mut find_node_req = None;
+        for _ in 0..2 {
+            match expect_request(&mut network_reqs_rx, peer_a).await {
+                (DhtMsg::Store(signed), rpc_req) => {
+                    assert_eq!(signed, self_record);
+                    send_response(rpc_req, DhtMsg::Stored);
+                }
+                (DhtMsg::FindNode(target), rpc_req) => {
+                    assert_eq!(target, self_peer_id);
+                    find_node_req = Some(rpc_req);
+                }
+                (msg, _) => panic!("Unexpected DhtMsg: {:?}", msg),
+            }
+        }
+
+        // The valid records of the response are sent to the connectivity manager, the forged
+        // ones are dropped.
+        let forged = signed_record(&key_a, peer_c, "/ip4/127.0.0.1/tcp/6666", 2);
+        send_response(
+            find_node_req.unwrap(),
+            DhtMsg::Nodes(vec![record_b.clone(), forged]),
+        );
+        let mut expected = HashMap::new();
+        expected.insert(peer_b, record_b.record().addrs.clone());
+        expect_address_update(&mut conn_mgr_reqs_rx, expected.clone()).await;
+
+        // The records stored by the peers are sent too.
+        let res = send_inbound_request(
+            peer_a,
+            DhtMsg::Store(record_c.clone()),
+            &mut network_notifs_tx,
+        )
+        .await;
+        assert!(matches!(res, DhtMsg::Stored));
+        expected.insert(peer_c, record_c.record().addrs.clone());
+        expect_address_update(&mut conn_mgr_reqs_rx, expected).await;
+
+        // The peers looking a target up receive the records of the routing table and of the node.
+        let res =
+            send_inbound_request(peer_a, DhtMsg::FindNode(peer_b), &mut network_notifs_tx).await;
+        let records = match res {
+            DhtMsg::Nodes(records) => records,
+            msg => panic!("Unexpected DhtMsg: {:?}", msg),
+        };
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0], record_b);
+        assert!(records.contains(&record_c));
+        assert!(records.contains(&self_record));
+    };
+    rt.block_on(events_f);
+}
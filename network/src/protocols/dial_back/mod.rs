@@ -0,0 +1,443 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Dial-back verification of the advertised addresses of this node, before gossiping them.
+//!
+//! Nodes frequently advertise wrong or unreachable addresses, e.g., an address behind a NAT, or
+//! one whose port is only bound once the node listens (`/tcp/0`). Such addresses waste the dials
+//! of every peer learning them through [`discovery`](crate::protocols::discovery). With
+//! verification enabled, the node asks a random connected peer to dial each of its advertised
+//! addresses back, and only the addresses reached are put into its discovery notes.
+//!
+//! ## Protocol
+//!
+//! - On each tick, the node sends a [`DialBackMsg::DialBack`] for every advertised address not
+//! verified yet to a random connected peer.
+//! - The peer dials the address over its base transport, without the Noise and LibraNet
+//! handshakes, and answers with a [`DialBackMsg::Result`] telling whether the address was reached
+//! within [`PROBE_TIMEOUT`].
+//! - The verified addresses, in the order they were advertised, are sent to discovery whenever one
+//! more address is verified. The failed ones are retried on the next ticks.
+//!
+//! The addresses with a DNS name are set by the operator and passed through unverified, as the
+//! peers can't tell which host they resolve to.
+//!
+//! ## Abuse
+//!
+//! A peer only dials an address on the host the requester is connected from, i.e., with the same
+//! IP, so that the protocol can't be used to scan or flood third parties. It runs at most
+//! [`MAX_CONCURRENT_PROBES`] probes at a time, and drops the requests beyond.
+
+use crate::{
+    catch_panic::catch_panic,
+    connected_peers::ConnectedPeers,
+    counters,
+    error::NetworkError,
+    peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
+    protocols::{
+        network::{Event, NetworkEvents, NetworkSender},
+        rpc::error::RpcError,
+    },
+    validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
+    ProtocolId,
+};
+use bytes::Bytes;
+use channel::message_queues::QueueStyle;
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture, FutureExt},
+    stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
+};
+use libra_logger::prelude::*;
+use libra_network_address::{NetworkAddress, Protocol};
+use libra_types::PeerId;
+use netcore::transport::Transport;
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, time::Duration};
+
+#[cfg(test)]
+mod test;
+
+const ACTOR: &str = "dial_back";
+
+/// Maximum number of probes a node runs at a time on behalf of its peers.
+pub const MAX_CONCURRENT_PROBES: usize = 8;
+
+/// Time given to a probe to reach the address.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout of the dial-back RPCs, beyond the time given to the probe.
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type DialBackNetworkEvents = NetworkEvents<DialBackMsg>;
+
+/// The interface from dial-back to the network layer.
+#[derive(Clone)]
+pub struct DialBackNetworkSender {
+    inner: NetworkSender<DialBackMsg>,
+}
+
+pub fn add_to_network(
+    network: &mut NetworkBuilder,
+) -> (DialBackNetworkSender, DialBackNetworkEvents) {
+    let (sender, receiver, connection_reqs_tx, connection_notifs_rx) = network
+        .add_protocol_handler(
+            vec![ProtocolId::DialBackRpc],
+            vec![],
+            ProtocolPriority::Normal,
+            QueueStyle::LIFO,
+            NETWORK_CHANNEL_SIZE,
+            Some(&counters::PENDING_DIAL_BACK_NETWORK_EVENTS),
+        );
+    (
+        DialBackNetworkSender::new(sender, connection_reqs_tx),
+        DialBackNetworkEvents::new(receiver, connection_notifs_rx),
+    )
+}
+
+impl DialBackNetworkSender {
+    pub fn new(
+        peer_mgr_reqs_tx: PeerManagerRequestSender,
+        connection_reqs_tx: ConnectionRequestSender,
+    ) -> Self {
+        Self {
+            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
+        }
+    }
+
+    /// Send a dial-back request to `recipient`, and wait for its response until `timeout`.
+    pub async fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        req_msg: DialBackMsg,
+        timeout: Duration,
+    ) -> Result<DialBackMsg, NetworkError> {
+        self.inner
+            .send_rpc(recipient, ProtocolId::DialBackRpc, req_msg, timeout)
+            .await
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DialBackMsg {
+    /// Asks the recipient to dial `addr`, an address advertised by the sender.
+    DialBack { addr: NetworkAddress },
+    /// The response to a `DialBack`: whether the recipient reached the address.
+    Result { reachable: bool },
+}
+
+/// Checks whether an address is reachable.
+pub trait AddressProber: Send + Sync {
+    /// Resolves to true if `addr`, advertised by `peer_id`, was reached.
+    fn probe(&self, peer_id: PeerId, addr: NetworkAddress) -> BoxFuture<'static, bool>;
+}
+
+/// Probes the addresses by dialing them over a base transport, e.g., TCP, and closing the
+/// connection once established.
+pub struct TransportProber<TTransport> {
+    transport: TTransport,
+}
+
+impl<TTransport> TransportProber<TTransport> {
+    pub fn new(transport: TTransport) -> Self {
+        Self { transport }
+    }
+}
+
+impl<TTransport> AddressProber for TransportProber<TTransport>
+where
+    TTransport: Transport + Send + Sync,
+    TTransport::Outbound: Send + 'static,
+{
+    fn probe(&self, peer_id: PeerId, addr: NetworkAddress) -> BoxFuture<'static, bool> {
+        match self.transport.dial(peer_id, addr) {
+            Ok(outbound) => async move {
+                match tokio::time::timeout(PROBE_TIMEOUT, outbound).await {
+                    Ok(Ok(_)) => true,
+                    Ok(Err(_)) | Err(_) => false,
+                }
+            }
+            .boxed(),
+            Err(_) => future::ready(false).boxed(),
+        }
+    }
+}
+
+/// Whether `addr` designates the host of `connected_from`: the same IP, or the in-memory
+/// transport of this process. Addresses with an unbound port (0) are never reachable.
+pub fn is_same_host(addr: &NetworkAddress, connected_from: &NetworkAddress) -> bool {
+    if addr
+        .as_slice()
+        .iter()
+        .any(|proto| matches!(proto, Protocol::Tcp(0) | Protocol::Memory(0)))
+    {
+        return false;
+    }
+    match (addr.as_slice().first(), connected_from.as_slice().first()) {
+        (Some(Protocol::Ip4(ip)), Some(Protocol::Ip4(from))) => ip == from,
+        (Some(Protocol::Ip6(ip)), Some(Protocol::Ip6(from))) => ip == from,
+        (Some(Protocol::Memory(_)), Some(Protocol::Memory(_))) => true,
+        _ => false,
+    }
+}
+
+/// Whether `addr` is passed through unverified, as its host is named by DNS.
+fn is_dns(addr: &NetworkAddress) -> bool {
+    matches!(
+        addr.as_slice().first(),
+        Some(Protocol::Dns(_)) | Some(Protocol::Dns4(_)) | Some(Protocol::Dns6(_))
+    )
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Verification {
+    Unverified,
+    Pending,
+    Verified,
+}
+
+/// The actor probing the addresses of the connected peers on their request, and, if enabled,
+/// verifying the advertised addresses of this node with them.
+pub struct DialBack<TTicker> {
+    /// The advertised addresses of this node, with their verification, if enabled.
+    addrs: Vec<(NetworkAddress, Verification)>,
+    /// Channel over which the verified addresses are sent to discovery, if enabled.
+    verified_addrs_tx: Option<mpsc::UnboundedSender<Vec<NetworkAddress>>>,
+    /// Ticker triggering the verification of the addresses not verified yet.
+    ticker: TTicker,
+    network_tx: DialBackNetworkSender,
+    network_rx: DialBackNetworkEvents,
+    /// The peers of all the networks, with the address they are connected from.
+    connected_peers: ConnectedPeers,
+    connected: HashSet<PeerId>,
+    rng: SmallRng,
+}
+
+impl<TTicker> DialBack<TTicker>
+where
+    TTicker: Stream + FusedStream + Unpin,
+{
+    /// Create a new instance of the [`DialBack`] actor, only probing the addresses of the peers
+    /// connected to `connected_peers` on their request.
+    pub fn new(
+        ticker: TTicker,
+        network_tx: DialBackNetworkSender,
+        network_rx: DialBackNetworkEvents,
+        connected_peers: ConnectedPeers,
+    ) -> Self {
+        Self {
+            addrs: Vec::new(),
+            verified_addrs_tx: None,
+            ticker,
+            network_tx,
+            network_rx,
+            connected_peers,
+            connected: HashSet::new(),
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    /// Verify the advertised addresses `addrs` of this node, and send the verified ones to
+    /// `verified_addrs_tx`.
+    pub fn verify(
+        mut self,
+        addrs: Vec<NetworkAddress>,
+        verified_addrs_tx: mpsc::UnboundedSender<Vec<NetworkAddress>>,
+    ) -> Self {
+        self.addrs = addrs
+            .into_iter()
+            .map(|addr| {
+                let verification = if is_dns(&addr) {
+                    Verification::Verified
+                } else {
+                    Verification::Unverified
+                };
+                (addr, verification)
+            })
+            .collect();
+        self.verified_addrs_tx = Some(verified_addrs_tx);
+        self
+    }
+
+    pub async fn start(mut self, prober: Box<dyn AddressProber>) {
+        if self
+            .addrs
+            .iter()
+            .any(|(_, verification)| *verification == Verification::Verified)
+        {
+            self.send_verified_addrs();
+        }
+        let mut probes = FuturesUnordered::new();
+        let mut rpcs = FuturesUnordered::new();
+        loop {
+            let next_event = async {
+                futures::select! {
+                    event = self.network_rx.select_next_some() => {
+                        match event {
+                            Ok(Event::NewPeer(peer_id)) => {
+                                self.connected.insert(peer_id);
+                            }
+                            Ok(Event::LostPeer(peer_id)) => {
+                                self.connected.remove(&peer_id);
+                            }
+                            Ok(Event::RpcRequest((
+                                peer_id,
+                                DialBackMsg::DialBack { addr },
+                                res_tx,
+                            ))) => {
+                                if probes.len() >= MAX_CONCURRENT_PROBES {
+                                    debug!(
+                                        "Dropping the dial-back request of peer {}: too many probes",
+                                        peer_id.short_str()
+                                    );
+                                } else if self.is_probe_allowed(&peer_id, &addr) {
+                                    let probe = prober.probe(peer_id, addr);
+                                    probes.push(probe.map(move |reachable| (res_tx, reachable)));
+                                } else {
+                                    Self::respond(res_tx, false);
+                                }
+                            }
+                            Ok(event) => {
+                                warn!("Unexpected dial-back network event: {:?}", event);
+                            }
+                            Err(err) => {
+                                warn!("Dial-back network error: {:?}", err);
+                            }
+                        }
+                    }
+                    (res_tx, reachable) = probes.select_next_some() => {
+                        Self::respond(res_tx, reachable);
+                    }
+                    _ = self.ticker.select_next_some() => {
+                        for (idx, peer_id) in self.choose_verifiers() {
+                            let addr = self.addrs[idx].0.clone();
+                            self.addrs[idx].1 = Verification::Pending;
+                            rpcs.push(Self::send_request(
+                                self.network_tx.clone(),
+                                peer_id,
+                                idx,
+                                addr,
+                            ));
+                        }
+                    }
+                    res = rpcs.select_next_some() => {
+                        let (idx, peer_id, res) = res;
+                        self.handle_result(idx, peer_id, res);
+                    }
+                    complete => return false,
+                }
+                true
+            };
+            match catch_panic(ACTOR, next_event).await {
+                // The state of the actor is only updated atomically, so there is no state to
+                // restore on panics.
+                Ok(true) | Err(_) => {}
+                Ok(false) => break,
+            }
+        }
+        crit!("Dial-back actor terminated");
+    }
+
+    /// Whether `addr` may be probed on behalf of `peer_id`, i.e., it designates the host the peer
+    /// is connected from.
+    fn is_probe_allowed(&self, peer_id: &PeerId, addr: &NetworkAddress) -> bool {
+        self.connected.contains(peer_id)
+            && self
+                .connected_peers
+                .get(peer_id)
+                .map_or(false, |peer| is_same_host(addr, &peer.address))
+    }
+
+    fn respond(res_tx: oneshot::Sender<Result<Bytes, RpcError>>, reachable: bool) {
+        match lcs::to_bytes(&DialBackMsg::Result { reachable }) {
+            Ok(res) => {
+                let _ = res_tx.send(Ok(res.into()));
+            }
+            Err(err) => warn!("Unable to serialize the dial-back response: {}", err),
+        }
+    }
+
+    /// A random connected peer for each address to verify.
+    fn choose_verifiers(&mut self) -> Vec<(usize, PeerId)> {
+        let connected: Vec<_> = self.connected.iter().copied().collect();
+        let rng = &mut self.rng;
+        self.addrs
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, verification))| *verification == Verification::Unverified)
+            .filter_map(|(idx, _)| connected.choose(rng).map(|peer_id| (idx, *peer_id)))
+            .collect()
+    }
+
+    fn handle_result(
+        &mut self,
+        idx: usize,
+        peer_id: PeerId,
+        res: Result<DialBackMsg, NetworkError>,
+    ) {
+        let reachable = match res {
+            Ok(DialBackMsg::Result { reachable }) => reachable,
+            Ok(msg) => {
+                warn!(
+                    "Unexpected dial-back response from peer {}: {:?}",
+                    peer_id.short_str(),
+                    msg
+                );
+                false
+            }
+            Err(err) => {
+                debug!(
+                    "Dial-back request to peer {} failed: {:?}",
+                    peer_id.short_str(),
+                    err
+                );
+                false
+            }
+        };
+        let (addr, verification) = &mut self.addrs[idx];
+        if reachable {
+            info!(
+                "Advertised address {} verified by peer {}",
+                addr,
+                peer_id.short_str()
+            );
+            *verification = Verification::Verified;
+            self.send_verified_addrs();
+        } else {
+            debug!(
+                "Advertised address {} not reached by peer {}",
+                addr,
+                peer_id.short_str()
+            );
+            *verification = Verification::Unverified;
+        }
+    }
+
+    fn send_verified_addrs(&mut self) {
+        let verified = self
+            .addrs
+            .iter()
+            .filter(|(_, verification)| *verification == Verification::Verified)
+            .map(|(addr, _)| addr.clone())
+            .collect();
+        if let Some(verified_addrs_tx) = &self.verified_addrs_tx {
+            if verified_addrs_tx.unbounded_send(verified).is_err() {
+                warn!("Discovery stopped receiving the verified addresses");
+            }
+        }
+    }
+
+    async fn send_request(
+        mut network_tx: DialBackNetworkSender,
+        peer_id: PeerId,
+        idx: usize,
+        addr: NetworkAddress,
+    ) -> (usize, PeerId, Result<DialBackMsg, NetworkError>) {
+        let res = network_tx
+            .send_rpc(peer_id, DialBackMsg::DialBack { addr }, RPC_TIMEOUT)
+            .await;
+        (idx, peer_id, res)
+    }
+}
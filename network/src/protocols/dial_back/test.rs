@@ -0,0 +1,290 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    peer_manager::{self, conn_notifs_channel, PeerManagerNotification, PeerManagerRequest},
+    protocols::{
+        rpc::{InboundRpcRequest, OutboundRpcRequest},
+        wire::handshake::v1::Encoding,
+    },
+};
+use channel::libra_channel;
+use futures::sink::SinkExt;
+use libra_config::{config::RoleType, network_id::NetworkId};
+use std::{
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tokio::runtime::Runtime;
+
+fn addr(addr: &str) -> NetworkAddress {
+    NetworkAddress::from_str(addr).unwrap()
+}
+
+/// Reaches the addresses of `reachable`, and records the addresses probed.
+struct FakeProber {
+    reachable: Vec<NetworkAddress>,
+    probed: Arc<Mutex<Vec<NetworkAddress>>>,
+}
+
+impl AddressProber for FakeProber {
+    fn probe(&self, _peer_id: PeerId, addr: NetworkAddress) -> BoxFuture<'static, bool> {
+        let reachable = self.reachable.contains(&addr);
+        self.probed.lock().unwrap().push(addr);
+        future::ready(reachable).boxed()
+    }
+}
+
+struct Setup {
+    peer_mgr_reqs_rx: libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    network_notifs_tx: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    connection_notifs_tx: conn_notifs_channel::Sender,
+    ticker_tx: mpsc::Sender<()>,
+    connected_peers: ConnectedPeers,
+    probed: Arc<Mutex<Vec<NetworkAddress>>>,
+}
+
+fn setup_dial_back(
+    rt: &mut Runtime,
+    reachable: Vec<NetworkAddress>,
+    verify: Option<(
+        Vec<NetworkAddress>,
+        mpsc::UnboundedSender<Vec<NetworkAddress>>,
+    )>,
+) -> Setup {
+    let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (connection_reqs_tx, _) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (network_notifs_tx, network_notifs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
+    let (ticker_tx, ticker_rx) = mpsc::channel(0);
+    let connected_peers = ConnectedPeers::new();
+    let probed = Arc::new(Mutex::new(Vec::new()));
+    let mut dial_back = DialBack::new(
+        ticker_rx,
+        DialBackNetworkSender::new(
+            PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+            ConnectionRequestSender::new(connection_reqs_tx),
+        ),
+        DialBackNetworkEvents::new(network_notifs_rx, connection_notifs_rx),
+        connected_peers.clone(),
+    );
+    if let Some((addrs, verified_addrs_tx)) = verify {
+        dial_back = dial_back.verify(addrs, verified_addrs_tx);
+    }
+    let prober = FakeProber {
+        reachable,
+        probed: probed.clone(),
+    };
+    rt.spawn(dial_back.start(Box::new(prober)));
+    Setup {
+        peer_mgr_reqs_rx,
+        network_notifs_tx,
+        connection_notifs_tx,
+        ticker_tx,
+        connected_peers,
+        probed,
+    }
+}
+
+/// Connects `peer_id` from `address`.
+async fn connect(
+    peer_id: PeerId,
+    address: NetworkAddress,
+    connected_peers: &ConnectedPeers,
+    connection_notifs_tx: &mut conn_notifs_channel::Sender,
+) {
+    connected_peers.insert(
+        peer_id,
+        NetworkId::Public,
+        RoleType::FullNode,
+        address.clone(),
+    );
+    let (delivered_tx, delivered_rx) = oneshot::channel();
+    connection_notifs_tx
+        .push_with_feedback(
+            peer_id,
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_id,
+                address,
+                peer_manager::ConnectionInfo::mock(),
+            ),
+            Some(delivered_tx),
+        )
+        .unwrap();
+    delivered_rx.await.unwrap();
+}
+
+fn send_inbound_request(
+    peer_id: PeerId,
+    addr: NetworkAddress,
+    network_notifs_tx: &mut libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+) -> oneshot::Receiver<Result<Bytes, RpcError>> {
+    let (res_tx, res_rx) = oneshot::channel();
+    let inbound_rpc_req = InboundRpcRequest {
+        protocol: ProtocolId::DialBackRpc,
+        data: lcs::to_bytes(&DialBackMsg::DialBack { addr })
+            .unwrap()
+            .into(),
+        res_tx,
+    };
+    network_notifs_tx
+        .push(
+            (peer_id, ProtocolId::DialBackRpc),
+            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req, Encoding::Lcs),
+        )
+        .unwrap();
+    res_rx
+}
+
+async fn is_reachable(res_rx: oneshot::Receiver<Result<Bytes, RpcError>>) -> bool {
+    match lcs::from_bytes(&res_rx.await.unwrap().unwrap()).unwrap() {
+        DialBackMsg::Result { reachable } => reachable,
+        msg => panic!("Unexpected DialBackMsg: {:?}", msg),
+    }
+}
+
+async fn expect_request(
+    peer_mgr_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+) -> (NetworkAddress, OutboundRpcRequest) {
+    let (peer_id, rpc_req) = match peer_mgr_reqs_rx.next().await.unwrap() {
+        PeerManagerRequest::SendRpc(peer_id, rpc_req, _) => (peer_id, rpc_req),
+        req => panic!("Unexpected PeerManagerRequest: {:?}", req),
+    };
+    assert_eq!(peer_id, expected_peer_id);
+    assert_eq!(rpc_req.protocol, ProtocolId::DialBackRpc);
+    match lcs::from_bytes(&rpc_req.data).unwrap() {
+        DialBackMsg::DialBack { addr } => (addr, rpc_req),
+        msg => panic!("Unexpected DialBackMsg: {:?}", msg),
+    }
+}
+
+fn send_response(rpc_req: OutboundRpcRequest, reachable: bool) {
+    rpc_req
+        .res_tx
+        .send(Ok(lcs::to_bytes(&DialBackMsg::Result { reachable })
+            .unwrap()
+            .into()))
+        .unwrap();
+}
+
+#[test]
+fn same_host() {
+    let from = addr("/ip4/10.0.0.1/tcp/40000");
+    assert!(is_same_host(&addr("/ip4/10.0.0.1/tcp/6180"), &from));
+    assert!(!is_same_host(&addr("/ip4/10.0.0.2/tcp/6180"), &from));
+    assert!(!is_same_host(&addr("/ip4/10.0.0.1/tcp/0"), &from));
+    assert!(!is_same_host(&addr("/ip6/::1/tcp/6180"), &from));
+    assert!(!is_same_host(&addr("/dns4/example.com/tcp/6180"), &from));
+    assert!(is_same_host(
+        &addr("/ip6/::1/tcp/6180"),
+        &addr("/ip6/::1/tcp/40000")
+    ));
+    assert!(is_same_host(&addr("/memory/6180"), &addr("/memory/40000")));
+}
+
+#[test]
+fn probe_on_request() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let peer_a = PeerId::random();
+    let peer_b = PeerId::random();
+    let reachable = addr("/ip4/10.0.0.1/tcp/6180");
+    let mut setup = setup_dial_back(&mut rt, vec![reachable.clone()], None);
+
+    let events_f = async move {
+        connect(
+            peer_a,
+            addr("/ip4/10.0.0.1/tcp/40000"),
+            &setup.connected_peers,
+            &mut setup.connection_notifs_tx,
+        )
+        .await;
+
+        // The addresses on the host of the requester are probed.
+        let res_rx = send_inbound_request(peer_a, reachable.clone(), &mut setup.network_notifs_tx);
+        assert!(is_reachable(res_rx).await);
+        let res_rx = send_inbound_request(
+            peer_a,
+            addr("/ip4/10.0.0.1/tcp/6181"),
+            &mut setup.network_notifs_tx,
+        );
+        assert!(!is_reachable(res_rx).await);
+
+        // The other hosts, the unbound ports and the unknown peers are refused without probing.
+        for (peer_id, refused) in vec![
+            (peer_a, "/ip4/10.0.0.2/tcp/6180"),
+            (peer_a, "/ip4/10.0.0.1/tcp/0"),
+            (peer_b, "/ip4/10.0.0.1/tcp/6180"),
+        ] {
+            let res_rx = send_inbound_request(peer_id, addr(refused), &mut setup.network_notifs_tx);
+            assert!(!is_reachable(res_rx).await);
+        }
+        assert_eq!(
+            *setup.probed.lock().unwrap(),
+            vec![reachable, addr("/ip4/10.0.0.1/tcp/6181")]
+        );
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn verify_advertised_addrs() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let peer_a = PeerId::random();
+    let first = addr("/ip4/10.0.0.9/tcp/6180");
+    let second = addr("/ip4/10.0.0.9/tcp/6181");
+    let named = addr("/dns4/example.com/tcp/6180");
+    let (verified_addrs_tx, mut verified_addrs_rx) = mpsc::unbounded();
+    let mut setup = setup_dial_back(
+        &mut rt,
+        vec![],
+        Some((
+            vec![first.clone(), second.clone(), named.clone()],
+            verified_addrs_tx,
+        )),
+    );
+
+    let events_f = async move {
+        // The addresses named by DNS are passed through.
+        assert_eq!(verified_addrs_rx.next().await.unwrap(), vec![named.clone()]);
+
+        connect(
+            peer_a,
+            addr("/ip4/10.0.0.1/tcp/40000"),
+            &setup.connected_peers,
+            &mut setup.connection_notifs_tx,
+        )
+        .await;
+        setup.ticker_tx.send(()).await.unwrap();
+        let (req_addr, first_req) = expect_request(&mut setup.peer_mgr_reqs_rx, peer_a).await;
+        assert_eq!(req_addr, first);
+        let (req_addr, second_req) = expect_request(&mut setup.peer_mgr_reqs_rx, peer_a).await;
+        assert_eq!(req_addr, second);
+        send_response(second_req, false);
+        send_response(first_req, true);
+
+        // Only the verified addresses are sent, in the order they are advertised.
+        assert_eq!(
+            verified_addrs_rx.next().await.unwrap(),
+            vec![first.clone(), named.clone()]
+        );
+
+        // The failed addresses are retried on the next tick.
+        setup.ticker_tx.send(()).await.unwrap();
+        let (req_addr, rpc_req) = expect_request(&mut setup.peer_mgr_reqs_rx, peer_a).await;
+        assert_eq!(req_addr, second);
+        send_response(rpc_req, true);
+        assert_eq!(
+            verified_addrs_rx.next().await.unwrap(),
+            vec![first, second, named]
+        );
+    };
+    rt.block_on(events_f);
+}
@@ -22,6 +22,7 @@
 use crate::{
     counters,
     peer::{PeerHandle, PeerNotification},
+    peer_manager::request_trace::RequestTrace,
     protocols::wire::messaging::v1::{DirectSendMsg, NetworkMessage, Priority},
     ProtocolId,
 };
@@ -33,10 +34,10 @@ use std::fmt::Debug;
 #[cfg(test)]
 mod test;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Debug)]
 pub enum DirectSendRequest {
     /// A request to send out a message.
-    SendMessage(Message),
+    SendMessage(Message, RequestTrace),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -131,7 +132,8 @@ impl DirectSend {
     async fn handle_peer_notification(&mut self, notif: PeerNotification) {
         trace!("PeerNotification::{:?}", notif);
         match notif {
-            PeerNotification::NewMessage(message) => {
+            // the message is held against the quota of the network until it is forwarded
+            PeerNotification::NewMessage(message, _permit) => {
                 let peer_id = self.peer_handle.peer_id();
                 if let NetworkMessage::DirectSendMsg(message) = message {
                     let protocol = message.protocol_id;
@@ -170,7 +172,7 @@ impl DirectSend {
     async fn handle_direct_send_request(&mut self, req: DirectSendRequest) {
         trace!("DirectSendRequest::{:?}", req);
         match req {
-            DirectSendRequest::SendMessage(msg) => {
+            DirectSendRequest::SendMessage(msg, trace) => {
                 let protocol_id = msg.protocol;
                 // If send to PeerHandle fails, simply drop the message on the floor;
                 let msg_len = msg.mdata.len();
@@ -188,6 +190,11 @@ impl DirectSend {
                     .await;
                 match send_result {
                     Ok(()) => {
+                        trace!(
+                            "Wrote {} to peer: {}",
+                            trace,
+                            self.peer_handle.peer_id().short_str()
+                        );
                         counters::LIBRA_NETWORK_DIRECT_SEND_MESSAGES
                             .with_label_values(&["sent"])
                             .inc();
@@ -197,7 +204,8 @@ impl DirectSend {
                     }
                     Err(e) => {
                         warn!(
-                            "Failed to send message for protocol: {:?} to peer: {}. Error: {:?}",
+                            "Failed to send {} for protocol: {:?} to peer: {}. Error: {:?}",
+                            trace,
                             protocol_id,
                             self.peer_handle.peer_id().short_str(),
                             e
@@ -4,11 +4,12 @@
 use crate::{
     counters,
     peer::{PeerHandle, PeerNotification, PeerRequest},
-    peer_manager::PeerManagerError,
+    peer_manager::{request_trace::RequestTrace, PeerManagerError},
     protocols::{
         direct_send::{DirectSend, DirectSendNotification, DirectSendRequest, Message},
         wire::messaging::v1::{DirectSendMsg, NetworkMessage, Priority},
     },
+    quota::QuotaPermit,
     ProtocolId,
 };
 use bytes::Bytes;
@@ -99,24 +100,26 @@ fn test_inbound_msg() {
     let f_substream = async move {
         debug!("Sending first message");
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(NetworkMessage::DirectSendMsg(
-                DirectSendMsg {
+            .send(PeerNotification::NewMessage(
+                NetworkMessage::DirectSendMsg(DirectSendMsg {
                     protocol_id: PROTOCOL_1,
                     priority: Priority::default(),
                     raw_msg: MESSAGE_1.clone(),
-                },
-            )))
+                }),
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         debug!("Sending second message");
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(NetworkMessage::DirectSendMsg(
-                DirectSendMsg {
+            .send(PeerNotification::NewMessage(
+                NetworkMessage::DirectSendMsg(DirectSendMsg {
                     protocol_id: PROTOCOL_2,
                     priority: Priority::default(),
                     raw_msg: MESSAGE_2.clone(),
-                },
-            )))
+                }),
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
     };
@@ -143,10 +146,13 @@ fn test_outbound_msg() {
 
     // Fake the dialer NetworkProvider
     let f_network_provider = async move {
-        let msg_sent = DirectSendRequest::SendMessage(Message {
-            protocol: PROTOCOL_1,
-            mdata: Bytes::from(MESSAGE_1.clone()),
-        });
+        let msg_sent = DirectSendRequest::SendMessage(
+            Message {
+                protocol: PROTOCOL_1,
+                mdata: Bytes::from(MESSAGE_1.clone()),
+            },
+            RequestTrace::start(),
+        );
         debug!("Sending message");
         ds_requests_tx.send(msg_sent).await.unwrap();
     };
@@ -181,18 +187,24 @@ fn test_send_failure() {
     let f_network_provider = async move {
         // Request DirectSend to send the first message
         ds_requests_tx
-            .send(DirectSendRequest::SendMessage(Message {
-                protocol: PROTOCOL_1,
-                mdata: Bytes::from(MESSAGE_1.clone()),
-            }))
+            .send(DirectSendRequest::SendMessage(
+                Message {
+                    protocol: PROTOCOL_1,
+                    mdata: Bytes::from(MESSAGE_1.clone()),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
         // Request DirectSend to send the second message
         ds_requests_tx
-            .send(DirectSendRequest::SendMessage(Message {
-                protocol: PROTOCOL_1,
-                mdata: Bytes::from(MESSAGE_2.clone()),
-            }))
+            .send(DirectSendRequest::SendMessage(
+                Message {
+                    protocol: PROTOCOL_1,
+                    mdata: Bytes::from(MESSAGE_2.clone()),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
     };
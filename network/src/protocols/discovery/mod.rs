@@ -8,12 +8,12 @@
 //! ## Implementation
 //!
 //! The discovery module is implemented as a stand-alone actor in the Network sub-system of the
-//! Libra stack. The actor participates in discovery by periodically sending its observed state of
-//! the network to a randomly chosen peer. Other peers are also expected to be running the same
-//! protocol. Therefore, in expectation, every peer expects to hear from 1 other peer in each
-//! round. On hearing from the remote peer, the local discovery module tries to reconcile its state
-//! to reflect any changes. In addition to updating its state, it also passes on new information to
-//! the [`ConnectivityManager`] module.
+//! Libra stack. The actor participates in discovery by periodically sending a digest of its
+//! observed state of the network to a randomly chosen peer. Other peers are also expected to be
+//! running the same protocol. Therefore, in expectation, every peer expects to hear from 1 other
+//! peer in each round. On hearing from the remote peer, the local discovery module tries to
+//! reconcile its state to reflect any changes. In addition to updating its state, it also passes
+//! on new information to the [`ConnectivityManager`] module.
 //!
 //! For the initial bootstrap of a node, it sends the discovery message to a randomly chosen seed
 //! peer in each round. The message only contains the identity of this peer unless it learns more
@@ -24,6 +24,52 @@
 //!
 //! TODO: We need to handle to case of peers who may no longer be a part of the network.
 //!
+//! ## Digests
+//!
+//! The digest only carries the PeerId and epoch of each known note, rather than the notes
+//! themselves. The receiver replies with the notes the sender lacks or has outdated, and asks for
+//! the notes it lacks or has outdated itself, which the sender then sends back. Once the peers
+//! agree, a round only exchanges the digest, whose size is small compared to the notes with their
+//! addresses and signatures.
+//!
+//! ## Signed notes
+//!
+//! A note is signed by its peer, over its PeerId, addresses, epoch and metadata, with an Ed25519
+//! key whose X25519 counterpart, following the XEdDSA approach, is the network identity key of the
+//! peer: the key its PeerId derives from, or its trusted key in mutually authenticated networks.
+//! The epoch of a note is the time at which it was issued, so that:
+//! - a note is only replaced by a newer note of the same peer,
+//! - notes older than [`NOTE_TTL_MS`] are dropped, and peers re-issue their note every half TTL.
+//!
+//! Notes which are not signed by their peer, or have expired, are dropped, so that a peer can
+//! neither spoof the addresses of another peer nor propagate stale addresses.
+//!
+//! ## Metadata
+//!
+//! Peers may attach a small key-value map to their note, e.g. the URL of their JSON-RPC endpoint,
+//! a hint of their region, or their capabilities. It is part of the `PeerInfo` of the note, and
+//! bounded by [`MAX_METADATA_ENTRIES`], [`MAX_METADATA_KEY_SIZE`] and [`MAX_METADATA_VALUE_SIZE`]:
+//! notes exceeding these limits are dropped. The metadata of the known peers can be queried
+//! through a [`PeerMetadata`] handle.
+//!
+//! ## Identity key rotation
+//!
+//! When the network identity key of the node is rotated, the actor re-issues its note with its
+//! addresses carrying the new public key, so that the other peers dial it with the new key. The
+//! signing key isn't rotated along with it, so the peers reject the note once they no longer trust
+//! the previous key.
+//!
+//! ## Observed addresses
+//!
+//! When no advertised address is configured, the actor advertises the address of this node
+//! observed by its peers instead, once enough of them agree on it, see
+//! [`observed_addrs`](crate::observed_addrs). It is checked on every tick.
+//!
+//! ## Panics
+//!
+//! If the handling of an event panics, the actor keeps the notes it knows, and sends their
+//! addresses to the [`ConnectivityManager`] again, in case the panic interrupted their update.
+//!
 //! ## Future work
 //!
 //! - Currently, we do not try to detect/punish nodes which are just lurking (without contributing
@@ -34,21 +80,31 @@
 //! [`ConnectivityManager`]: ../../connectivity_manager
 
 use crate::{
+    catch_panic::catch_panic,
+    common::NetworkPublicKeys,
     connectivity_manager::{ConnectivityRequest, DiscoverySource},
     counters,
     error::NetworkError,
+    observed_addrs::ObservedAddrs,
     peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
     protocols::network::{Event, NetworkEvents, NetworkSender},
     validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
     ProtocolId,
 };
+use anyhow::{ensure, Result};
 use bytes::Bytes;
 use channel::message_queues::QueueStyle;
 use futures::{
+    channel::mpsc,
     sink::SinkExt,
     stream::{FusedStream, Stream, StreamExt},
 };
-use libra_config::config::RoleType;
+use libra_config::config::{RoleType, HANDSHAKE_VERSION};
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature},
+    x25519, PrivateKey, Signature, SigningKey,
+};
 use libra_crypto_derive::{CryptoHasher, LCSCryptoHash};
 use libra_logger::prelude::*;
 use libra_network_address::NetworkAddress;
@@ -58,14 +114,95 @@ use rand::{rngs::SmallRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryInto,
+    sync::{Arc, RwLock},
     time::SystemTime,
 };
 
 #[cfg(test)]
 mod test;
 
+const ACTOR: &str = "discovery";
+
+/// Age after which notes are dropped. Peers re-issue their note every half TTL.
+pub const NOTE_TTL_MS: u64 = 10 * 60 * 1000 /* 10 minutes */;
+/// Maximum number of entries in the metadata of a note.
+pub const MAX_METADATA_ENTRIES: usize = 16;
+/// Maximum size in bytes of a key of the metadata of a note.
+pub const MAX_METADATA_KEY_SIZE: usize = 64;
+/// Maximum size in bytes of a value of the metadata of a note.
+pub const MAX_METADATA_VALUE_SIZE: usize = 256;
+
+/// Key-value metadata advertised by a peer in its note.
+pub type DiscoveryMetadata = BTreeMap<String, String>;
+
+/// Checks that `metadata` is within the limits of what notes may carry.
+pub fn verify_metadata(metadata: &DiscoveryMetadata) -> Result<()> {
+    ensure!(
+        metadata.len() <= MAX_METADATA_ENTRIES,
+        "Metadata has {} entries, more than the maximum of {}",
+        metadata.len(),
+        MAX_METADATA_ENTRIES
+    );
+    for (key, value) in metadata {
+        ensure!(
+            !key.is_empty() && key.len() <= MAX_METADATA_KEY_SIZE,
+            "Metadata key {:?} is empty or longer than {} bytes",
+            key,
+            MAX_METADATA_KEY_SIZE
+        );
+        ensure!(
+            value.len() <= MAX_METADATA_VALUE_SIZE,
+            "Value of metadata key {:?} is longer than {} bytes",
+            key,
+            MAX_METADATA_VALUE_SIZE
+        );
+    }
+    Ok(())
+}
+
+/// Metadata advertised by the peers known to discovery, including this node. Cloning it returns
+/// a handle to the same view, which applications use to pick peers based on their metadata.
+#[derive(Clone, Debug, Default)]
+pub struct PeerMetadata {
+    inner: Arc<RwLock<HashMap<PeerId, DiscoveryMetadata>>>,
+}
+
+impl PeerMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metadata advertised by `peer_id`, if its note is known.
+    pub fn get(&self, peer_id: &PeerId) -> Option<DiscoveryMetadata> {
+        self.inner.read().unwrap().get(peer_id).cloned()
+    }
+
+    /// Returns the value of `key` in the metadata advertised by `peer_id`.
+    pub fn get_value(&self, peer_id: &PeerId, key: &str) -> Option<String> {
+        self.inner
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .and_then(|metadata| metadata.get(key).cloned())
+    }
+
+    /// Returns the peers advertising `key`, along with its value.
+    pub fn peers_with_key(&self, key: &str) -> Vec<(PeerId, String)> {
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(peer_id, metadata)| Some((*peer_id, metadata.get(key)?.clone())))
+            .collect()
+    }
+
+    fn insert(&self, peer_id: PeerId, metadata: DiscoveryMetadata) {
+        self.inner.write().unwrap().insert(peer_id, metadata);
+    }
+}
+
 /// The interface from Network to Discovery module.
 ///
 /// `DiscoveryNetworkEvents` is a `Stream` of `PeerManagerNotification` where the
@@ -96,6 +233,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![],
             vec![ProtocolId::DiscoveryDirectSend],
+            ProtocolPriority::Normal,
             QueueStyle::LIFO,
             NETWORK_CHANNEL_SIZE,
             Some(&counters::PENDING_DISCOVERY_NETWORK_EVENTS),
@@ -126,8 +264,7 @@ impl DiscoveryNetworkSender {
 
 /// The actor running the discovery protocol.
 pub struct Discovery<TTicker> {
-    /// Note for self, which is prefixed with an underscore as this is not used but is in
-    /// preparation for logic that changes the advertised Note while the validator is running.
+    /// Note for self, re-issued when the advertised addresses change while the node is running.
     note: Note,
     /// PeerId for self.
     peer_id: PeerId,
@@ -151,6 +288,29 @@ pub struct Discovery<TTicker> {
     conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
     /// Random-number generator.
     rng: SmallRng,
+    /// Metadata of the known peers, shared with applications.
+    peer_metadata: PeerMetadata,
+    /// The new public keys of the network identity of this node, when it is rotated.
+    identity_key_updates: mpsc::UnboundedReceiver<x25519::PublicKey>,
+    /// The public key of the network identity of this node, once rotated.
+    identity_key: Option<x25519::PublicKey>,
+    /// The advertised addresses of this node, as they are verified by dial-back.
+    verified_addrs: mpsc::UnboundedReceiver<Vec<NetworkAddress>>,
+    /// The advertised addresses of this node, without its external address.
+    self_addrs: Vec<NetworkAddress>,
+    /// The external addresses of this node, as mapped on the NAT gateway.
+    external_addrs: mpsc::UnboundedReceiver<Option<NetworkAddress>>,
+    /// The current external address of this node, advertised after its other addresses.
+    external_addr: Option<NetworkAddress>,
+    /// The addresses of this node observed by its peers, with the public key of its network
+    /// identity at creation, if advertised instead of the given addresses.
+    observed_addrs: Option<(ObservedAddrs, x25519::PublicKey)>,
+    /// The current consensus of the observed addresses, advertised instead of the given ones.
+    observed_addr: Option<NetworkAddress>,
+    /// Key signing the notes of this node.
+    signing_key: Ed25519PrivateKey,
+    /// Trusted keys of the peers, which may sign their notes in mutually authenticated networks.
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
 }
 
 impl<TTicker> Discovery<TTicker>
@@ -161,16 +321,28 @@ where
         self_peer_id: PeerId,
         role: RoleType,
         self_addrs: Vec<NetworkAddress>,
+        self_metadata: DiscoveryMetadata,
+        signing_key: Ed25519PrivateKey,
+        trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
         ticker: TTicker,
         network_reqs_tx: DiscoveryNetworkSender,
         network_notifs_rx: DiscoveryNetworkEvents,
         conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+        peer_metadata: PeerMetadata,
     ) -> Self {
         // TODO(philiphayes): wire through config
         let dns_seed_addr = b"example.com";
 
         let epoch = get_unix_epoch();
-        let self_note = Note::new(self_peer_id, self_addrs, dns_seed_addr, epoch);
+        let self_note = Note::new(
+            self_peer_id,
+            self_addrs.clone(),
+            self_metadata,
+            dns_seed_addr,
+            epoch,
+            &signing_key,
+        );
+        peer_metadata.insert(self_peer_id, self_note.metadata().clone());
 
         let known_peers = vec![(self_peer_id, self_note.clone())]
             .into_iter()
@@ -188,9 +360,62 @@ where
             network_notifs_rx,
             conn_mgr_reqs_tx,
             rng: SmallRng::from_entropy(),
+            peer_metadata,
+            identity_key_updates: mpsc::unbounded().1,
+            identity_key: None,
+            verified_addrs: mpsc::unbounded().1,
+            self_addrs,
+            external_addrs: mpsc::unbounded().1,
+            external_addr: None,
+            observed_addrs: None,
+            observed_addr: None,
+            signing_key,
+            trusted_peers,
         }
     }
 
+    /// Re-advertise the addresses of this node with the new public keys of its network identity
+    /// received from `identity_key_updates`.
+    pub fn identity_key_updates(
+        mut self,
+        identity_key_updates: mpsc::UnboundedReceiver<x25519::PublicKey>,
+    ) -> Self {
+        self.identity_key_updates = identity_key_updates;
+        self
+    }
+
+    /// Advertise the addresses of this node received from `verified_addrs`, as they are verified
+    /// by [`dial_back`](crate::protocols::dial_back), instead of the ones given at creation.
+    pub fn verified_addrs(
+        mut self,
+        verified_addrs: mpsc::UnboundedReceiver<Vec<NetworkAddress>>,
+    ) -> Self {
+        self.verified_addrs = verified_addrs;
+        self
+    }
+
+    /// Advertise the external address of this node received from `external_addrs`, as mapped on
+    /// the NAT gateway by the [`PortMapper`](crate::nat::PortMapper), in addition to the other
+    /// addresses. `None` withdraws it.
+    pub fn external_addrs(
+        mut self,
+        external_addrs: mpsc::UnboundedReceiver<Option<NetworkAddress>>,
+    ) -> Self {
+        self.external_addrs = external_addrs;
+        self
+    }
+
+    /// Advertise the consensus of the addresses of this node observed by its peers, carrying
+    /// `pubkey`, instead of the addresses given at creation, once there is one.
+    pub fn observed_addrs(
+        mut self,
+        observed_addrs: ObservedAddrs,
+        pubkey: x25519::PublicKey,
+    ) -> Self {
+        self.observed_addrs = Some((observed_addrs, pubkey));
+        self
+    }
+
     // Starts the main event loop for the discovery actor. We bootstrap by first dialing all the
     // seed peers, and then entering the event handling loop. Messages are received from:
     // - a ticker to trigger discovery message send to a random connected peer
@@ -203,42 +428,171 @@ where
 
         debug!("Starting Discovery actor event loop");
         loop {
-            futures::select! {
-                notif = self.network_notifs_rx.select_next_some() => {
-                    self.handle_network_event(notif).await;
-                },
-                _ = self.ticker.select_next_some() => {
-                    self.handle_tick();
-                }
-                complete => {
+            match catch_panic(ACTOR, self.handle_next_event()).await {
+                Ok(true) => {}
+                Ok(false) => {
                     crit!("Discovery actor terminated");
                     break;
                 }
+                Err(_) => self.restart().await,
+            }
+        }
+    }
+
+    /// Waits for the next event and handles it. Returns false once all the event streams ended.
+    async fn handle_next_event(&mut self) -> bool {
+        futures::select! {
+            notif = self.network_notifs_rx.select_next_some() => {
+                self.handle_network_event(notif).await;
+            },
+            _ = self.ticker.select_next_some() => {
+                self.handle_tick().await;
+            }
+            pubkey = self.identity_key_updates.select_next_some() => {
+                self.handle_identity_key_update(pubkey);
+            }
+            addrs = self.verified_addrs.select_next_some() => {
+                self.handle_verified_addrs(addrs);
             }
+            addr = self.external_addrs.select_next_some() => {
+                self.handle_external_addr(addr);
+            }
+            complete => return false,
         }
+        true
+    }
+
+    /// Restarts the actor after a panic, from the notes it knows.
+    async fn restart(&mut self) {
+        if let Err(err) = self
+            .conn_mgr_reqs_tx
+            .send(self.update_addresses_request())
+            .await
+        {
+            warn!("Failed to send the addresses of the known peers: {:?}", err);
+        }
+        self.record_num_discovery_notes();
+    }
+
+    fn update_addresses_request(&self) -> ConnectivityRequest {
+        ConnectivityRequest::UpdateAddresses(
+            DiscoverySource::Gossip,
+            self.known_peers
+                .iter()
+                .map(|(peer_id, note)| (*peer_id, note.addrs().clone()))
+                .collect(),
+        )
     }
 
     // Handles a clock "tick" by:
-    // 1. Selecting a random peer to send state to.
-    // 2. Compose the msg to send.
-    // 3. Spawn off a new task to push the msg to the peer.
-    fn handle_tick(&mut self) {
+    // 1. Dropping the expired notes, and re-issuing the note of self every half TTL.
+    // 2. Selecting a random peer to send state to.
+    // 3. Compose the digest of the known notes.
+    // 4. Push the digest to the peer.
+    async fn handle_tick(&mut self) {
         debug!("Discovery interval tick");
-        // On each tick, we choose a random neighbor and push our state to it.
+        self.update_observed_addr();
+        self.expire_notes().await;
+        // On each tick, we choose a random neighbor and push the digest of our state to it.
         if let Some(peer) = self.choose_random_neighbor() {
-            // We clone `peer_mgr_reqs_tx` member of Self, since using `self` inside fut below
-            // triggers some lifetime errors.
-            let mut sender = self.network_reqs_tx.clone();
-            // Compose discovery msg to send.
-            let msg = self.compose_discovery_msg();
-            if let Err(err) = sender.send_to(peer, msg) {
-                warn!(
-                    "Failed to send discovery msg to {}; error: {:?}",
-                    peer.short_str(),
-                    err
-                );
-            };
+            let msg = self.compose_digest();
+            self.send_msg(peer, msg);
+        }
+    }
+
+    // Drops the notes of the other peers which expired, and re-issues the note of self once it is
+    // older than half the TTL, so that it never expires at the other peers.
+    async fn expire_notes(&mut self) {
+        let now = get_unix_epoch();
+        let self_peer_id = self.peer_id;
+        let num_notes = self.known_peers.len();
+        self.known_peers
+            .retain(|peer_id, note| *peer_id == self_peer_id || !note.is_expired(now));
+        let expired = num_notes != self.known_peers.len();
+        if expired {
+            self.conn_mgr_reqs_tx
+                .send(self.update_addresses_request())
+                .await
+                .expect("ConnectivityRequest::UpdateAddresses send");
+            self.record_num_discovery_notes();
+        }
+        if self.note.epoch().saturating_add(NOTE_TTL_MS / 2) <= now {
+            self.reissue_note(self.note.addrs().clone(), now);
+        }
+    }
+
+    // Issues a new note for self, whose addresses carry the new public key of the network identity
+    // of this node. It is pushed to the other peers on the next ticks.
+    fn handle_identity_key_update(&mut self, pubkey: x25519::PublicKey) {
+        info!("Advertising the new network identity key: {}", pubkey);
+        self.identity_key = Some(pubkey);
+        self.reissue_self_note();
+    }
+
+    // Issues a new note for self with the newly verified addresses.
+    fn handle_verified_addrs(&mut self, addrs: Vec<NetworkAddress>) {
+        info!("Advertising the verified addresses: {:?}", addrs);
+        self.self_addrs = addrs;
+        self.reissue_self_note();
+    }
+
+    // Issues a new note for self with the new external address, or without it if withdrawn.
+    fn handle_external_addr(&mut self, addr: Option<NetworkAddress>) {
+        if addr == self.external_addr {
+            return;
+        }
+        info!("Advertising the external address: {:?}", addr);
+        self.external_addr = addr;
+        self.reissue_self_note();
+    }
+
+    // Issues a new note for self with the new consensus of the observed addresses, if any. The
+    // last consensus is kept when there is none, e.g., when its observers disconnected.
+    fn update_observed_addr(&mut self) {
+        let addr = match &self.observed_addrs {
+            Some((observed_addrs, pubkey)) => match observed_addrs.consensus() {
+                Some(addr) => addr.append_prod_protos(*pubkey, HANDSHAKE_VERSION),
+                None => return,
+            },
+            None => return,
+        };
+        if self.observed_addr.as_ref() == Some(&addr) {
+            return;
         }
+        info!("Advertising the address observed by the peers: {}", addr);
+        self.observed_addr = Some(addr);
+        self.reissue_self_note();
+    }
+
+    // Issues a new note for self with its advertised addresses and external address, carrying
+    // the current public key of the network identity of this node.
+    fn reissue_self_note(&mut self) {
+        let self_addrs = match &self.observed_addr {
+            Some(observed_addr) => std::slice::from_ref(observed_addr),
+            None => self.self_addrs.as_slice(),
+        };
+        let addrs = self_addrs.iter().chain(self.external_addr.iter()).cloned();
+        let addrs = match self.identity_key {
+            Some(pubkey) => addrs
+                .map(|addr| addr.rotate_noise_public_key(&pubkey))
+                .collect(),
+            None => addrs.collect(),
+        };
+        self.reissue_note(addrs, max(self.note.epoch() + 1, get_unix_epoch()));
+    }
+
+    // Issues a new note for self, signed with the signing key of this node.
+    fn reissue_note(&mut self, addrs: Vec<NetworkAddress>, epoch: u64) {
+        let note = Note::new(
+            self.peer_id,
+            addrs,
+            self.note.metadata().clone(),
+            &self.dns_seed_addr,
+            epoch,
+            &self.signing_key,
+        );
+        self.known_peers.insert(self.peer_id, note.clone());
+        self.note = note;
     }
 
     async fn handle_network_event(&mut self, event: Result<Event<DiscoveryMsg>, NetworkError>) {
@@ -254,8 +608,12 @@ where
                         // Remove peer from connected peer list.
                         self.connected_peers.remove(&peer_id);
                     }
-                    Event::Message((peer_id, msg)) => {
-                        self.reconcile(peer_id, msg.notes).await;
+                    Event::Message((peer_id, DiscoveryMsg::Digest(digest))) => {
+                        self.handle_digest(peer_id, digest);
+                    }
+                    Event::Message((peer_id, DiscoveryMsg::Notes { notes, wanted })) => {
+                        self.send_wanted_notes(peer_id, wanted);
+                        self.reconcile(peer_id, notes).await;
                         self.record_num_discovery_notes();
                     }
                     Event::RpcRequest(req) => {
@@ -281,18 +639,98 @@ where
         }
     }
 
-    // Creates DiscoveryMsg to be sent to some remote peer.
-    fn compose_discovery_msg(&self) -> DiscoveryMsg {
-        let notes = self.known_peers.values().cloned().collect::<Vec<_>>();
-        DiscoveryMsg { notes }
+    // Creates the digest of the known notes to be sent to some remote peer.
+    fn compose_digest(&self) -> DiscoveryMsg {
+        let digest = self
+            .known_peers
+            .iter()
+            .map(|(peer_id, note)| (*peer_id, note.epoch()))
+            .collect::<Vec<_>>();
+        DiscoveryMsg::Digest(digest)
+    }
+
+    // Replies to the digest of a remote peer with the notes it lacks or has outdated, and asks for
+    // the unexpired notes it has which we lack or have outdated.
+    fn handle_digest(&mut self, remote_peer: PeerId, digest: Vec<(PeerId, u64)>) {
+        let now = get_unix_epoch();
+        let remote_epochs: HashMap<_, _> = digest.into_iter().collect();
+        let notes = self
+            .known_peers
+            .iter()
+            .filter(|(peer_id, note)| {
+                remote_epochs
+                    .get(*peer_id)
+                    .map_or(true, |epoch| *epoch < note.epoch())
+            })
+            .map(|(_, note)| note.clone())
+            .collect::<Vec<_>>();
+        let wanted = remote_epochs
+            .iter()
+            .filter(|(peer_id, epoch)| {
+                !is_expired(**epoch, now)
+                    && self
+                        .known_peers
+                        .get(*peer_id)
+                        .map_or(true, |note| note.epoch() < **epoch)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+        if !notes.is_empty() || !wanted.is_empty() {
+            self.send_msg(remote_peer, DiscoveryMsg::Notes { notes, wanted });
+        }
+    }
+
+    // Sends the notes a remote peer asked for in reply to our digest.
+    fn send_wanted_notes(&mut self, remote_peer: PeerId, wanted: Vec<PeerId>) {
+        let notes = wanted
+            .into_iter()
+            .collect::<HashSet<_>>()
+            .iter()
+            .filter_map(|peer_id| self.known_peers.get(peer_id).cloned())
+            .collect::<Vec<_>>();
+        if !notes.is_empty() {
+            self.send_msg(
+                remote_peer,
+                DiscoveryMsg::Notes {
+                    notes,
+                    wanted: vec![],
+                },
+            );
+        }
+    }
+
+    fn send_msg(&mut self, peer: PeerId, msg: DiscoveryMsg) {
+        if let Err(err) = self.network_reqs_tx.send_to(peer, msg) {
+            warn!(
+                "Failed to send discovery msg to {}; error: {:?}",
+                peer.short_str(),
+                err
+            );
+        }
     }
 
     // Updates local state by reconciling with notes received from some remote peer.
     async fn reconcile(&mut self, remote_peer: PeerId, remote_notes: Vec<Note>) {
         let mut change_detected = false;
+        let now = get_unix_epoch();
         // If a peer is previously unknown, or has a newer epoch number, we update its
         // corresponding entry in the map.
-        for mut note in remote_notes {
+        for note in remote_notes {
+            if let Err(err) = note.verify(&self.trusted_peers.read().unwrap()) {
+                security_log(SecurityEvent::InvalidDiscoveryMsg)
+                    .error(&err)
+                    .data(&note.peer_id)
+                    .log();
+                continue;
+            }
+            if note.is_expired(now) {
+                debug!(
+                    "Received expired note for peer: {} from peer: {}",
+                    note.peer_id.short_str(),
+                    remote_peer.short_str()
+                );
+                continue;
+            }
             match self.known_peers.get_mut(&note.peer_id) {
                 // If we know about this peer, and receive the same or an older epoch, we do
                 // nothing.
@@ -331,17 +769,16 @@ where
                                 .log();
                             continue;
                         }
-                        note = Note::new(
-                            self.peer_id,
+                        self.reissue_note(
                             self.note.addrs().clone(),
-                            &self.dns_seed_addr,
                             max(note.epoch() + 1, get_unix_epoch()),
                         );
-                        self.note = note.clone();
-                    } else {
-                        change_detected = true;
+                        continue;
                     }
+                    change_detected = true;
                     // Update internal state of the peer with new Note.
+                    self.peer_metadata
+                        .insert(note.peer_id, note.metadata().clone());
                     self.known_peers.insert(note.peer_id, note);
                 }
             }
@@ -349,14 +786,7 @@ where
 
         if change_detected {
             self.conn_mgr_reqs_tx
-                .send(ConnectivityRequest::UpdateAddresses(
-                    DiscoverySource::Gossip,
-                    self.known_peers
-                        .clone()
-                        .iter()
-                        .map(|(peer_id, note)| (*peer_id, note.addrs().clone()))
-                        .collect(),
-                ))
+                .send(self.update_addresses_request())
                 .await
                 .expect("ConnectivityRequest::UpdateAddresses send");
         }
@@ -379,10 +809,18 @@ where
     }
 }
 
-/// A Discovery message contains notes collected from other peers within the system.
+/// A Discovery message either contains the digest of the notes known to the sender, or notes
+/// collected from other peers within the system.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct DiscoveryMsg {
-    notes: Vec<Note>,
+pub enum DiscoveryMsg {
+    /// The PeerId and epoch of each note known to the sender.
+    Digest(Vec<(PeerId, u64)>),
+    /// Notes the receiver lacks or has outdated, along with the peers whose notes the sender lacks
+    /// or has outdated, which the receiver should send back.
+    Notes {
+        notes: Vec<Note>,
+        wanted: Vec<PeerId>,
+    },
 }
 
 /// A `Note` contains a validator's signed `PeerInfo` as well as a signed
@@ -396,25 +834,84 @@ pub struct Note {
     peer_info: PeerInfo,
     /// The validator node's signed `FullNodePayload`.
     full_node_info: FullNodeInfo,
+    /// Key signing the note, whose X25519 counterpart is the network identity key of the peer.
+    public_key: Ed25519PublicKey,
+    /// Signature of the [`SignedPeerInfo`] of the note.
+    signature: Ed25519Signature,
 }
 
 impl Note {
-    fn new(peer_id: PeerId, addrs: Vec<NetworkAddress>, dns_seed_addr: &[u8], epoch: u64) -> Self {
+    fn new(
+        peer_id: PeerId,
+        addrs: Vec<NetworkAddress>,
+        metadata: DiscoveryMetadata,
+        dns_seed_addr: &[u8],
+        epoch: u64,
+        signing_key: &Ed25519PrivateKey,
+    ) -> Self {
+        let peer_info = PeerInfo {
+            addrs,
+            epoch,
+            metadata,
+        };
+        let signature = signing_key
+            .sign(&SignedPeerInfo {
+                peer_id,
+                peer_info: peer_info.clone(),
+            })
+            .expect("Failed to sign the discovery note");
         Self {
             peer_id,
-            peer_info: PeerInfo { addrs, epoch },
+            peer_info,
             full_node_info: FullNodeInfo {
                 dns_seed_addr: dns_seed_addr.to_vec(),
                 epoch,
             },
+            public_key: signing_key.public_key(),
+            signature,
         }
     }
 
+    /// Checks that the note is signed by the network identity of its peer, i.e., the identity key
+    /// its PeerId derives from or its key in `trusted_peers`, and within the limits of what notes
+    /// may carry.
+    fn verify(&self, trusted_peers: &HashMap<PeerId, NetworkPublicKeys>) -> Result<()> {
+        verify_metadata(self.metadata())?;
+        let identity_key =
+            x25519::PublicKey::from_ed25519_public_bytes(&self.public_key.to_bytes())?;
+        let trusted_key = trusted_peers
+            .get(&self.peer_id)
+            .map(|keys| keys.identity_public_key);
+        ensure!(
+            PeerId::from_identity_public_key(identity_key) == self.peer_id
+                || trusted_key == Some(identity_key),
+            "Note of peer {} is signed by the key of another peer",
+            self.peer_id.short_str()
+        );
+        self.signature.verify_struct_msg(
+            &SignedPeerInfo {
+                peer_id: self.peer_id,
+                peer_info: self.peer_info.clone(),
+            },
+            &self.public_key,
+        )
+    }
+
+    /// Whether the note was issued more than [`NOTE_TTL_MS`] before `now`.
+    fn is_expired(&self, now: u64) -> bool {
+        is_expired(self.epoch(), now)
+    }
+
     /// Shortcut to the addrs embedded within the Note
     fn addrs(&self) -> &Vec<NetworkAddress> {
         &self.peer_info.addrs
     }
 
+    /// Shortcut to the metadata embedded within the Note
+    fn metadata(&self) -> &DiscoveryMetadata {
+        &self.peer_info.metadata
+    }
+
     /// The current implementation derives epoch from the PeerInfo.
     fn epoch(&self) -> u64 {
         self.peer_info.epoch
@@ -430,6 +927,15 @@ pub struct PeerInfo {
     /// updates to their `PeerInfo` and prevent attackers from propagating old
     /// `PeerInfo`s. This is usually a timestamp.
     epoch: u64,
+    /// Key-value metadata advertised by this peer.
+    metadata: DiscoveryMetadata,
+}
+
+/// The `PeerInfo` of a peer, along with its id, as signed by the peer.
+#[derive(Deserialize, Serialize, CryptoHasher, LCSCryptoHash)]
+struct SignedPeerInfo {
+    peer_id: PeerId,
+    peer_info: PeerInfo,
 }
 
 /// Discovery information relevant to public full nodes and clients.
@@ -444,6 +950,11 @@ pub struct FullNodeInfo {
     epoch: u64,
 }
 
+/// Whether a note issued at `epoch` is older than [`NOTE_TTL_MS`] at `now`.
+fn is_expired(epoch: u64, now: u64) -> bool {
+    epoch.saturating_add(NOTE_TTL_MS) < now
+}
+
 fn get_unix_epoch() -> u64 {
     // TODO: Currently, SystemTime::now() in Rust is not guaranteed to use a monotonic clock.
     // At the moment, it's unclear how to do this in a platform-agnostic way. For Linux, we
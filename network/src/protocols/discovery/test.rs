@@ -3,19 +3,19 @@
 
 use super::*;
 use crate::{
-    error::NetworkErrorKind,
     peer_manager::{
         self, conn_notifs_channel, ConnectionRequestSender, PeerManagerNotification,
         PeerManagerRequest,
     },
-    protocols::direct_send::Message,
+    protocols::{direct_send::Message, wire::handshake::v1::Encoding},
     ProtocolId,
 };
-use anyhow::anyhow;
 use channel::{libra_channel, message_queues::QueueStyle};
 use futures::channel::oneshot;
 use libra_config::config::RoleType;
+use libra_crypto::Uniform;
 use libra_network_address::NetworkAddress;
+use rand::rngs::StdRng;
 use std::{num::NonZeroUsize, str::FromStr};
 use tokio::runtime::Runtime;
 
@@ -28,15 +28,68 @@ fn get_raw_message(msg: DiscoveryMsg) -> Message {
 
 fn parse_raw_message(msg: Message) -> Result<DiscoveryMsg, NetworkError> {
     assert_eq!(msg.protocol, ProtocolId::DiscoveryDirectSend);
-    let msg: DiscoveryMsg = lcs::from_bytes(&msg.mdata)
-        .map_err(|err| anyhow!(err).context(NetworkErrorKind::ParsingError))?;
+    let msg: DiscoveryMsg = lcs::from_bytes(&msg.mdata)?;
     Ok(msg)
 }
 
+/// Returns the signing key generated from `seed`, and the PeerId whose identity key is its X25519
+/// counterpart.
+fn signing_key(seed: u8) -> (Ed25519PrivateKey, PeerId) {
+    let signing_key = Ed25519PrivateKey::generate(&mut StdRng::from_seed([seed; 32]));
+    let identity_key =
+        x25519::PublicKey::from_ed25519_public_bytes(&signing_key.public_key().to_bytes()).unwrap();
+    (signing_key, PeerId::from_identity_public_key(identity_key))
+}
+
+fn note(
+    signing_key: &Ed25519PrivateKey,
+    peer_id: PeerId,
+    addrs: Vec<NetworkAddress>,
+    epoch: u64,
+) -> Note {
+    Note::new(
+        peer_id,
+        addrs,
+        DiscoveryMetadata::new(),
+        b"example.com",
+        epoch,
+        signing_key,
+    )
+}
+
 fn setup_discovery(
     rt: &mut Runtime,
     peer_id: PeerId,
+    signing_key: Ed25519PrivateKey,
     addrs: Vec<NetworkAddress>,
+    metadata: DiscoveryMetadata,
+    peer_metadata: PeerMetadata,
+) -> (
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    channel::Receiver<ConnectivityRequest>,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    conn_notifs_channel::Sender,
+    channel::Sender<()>,
+) {
+    setup_discovery_with_identity_key_updates(
+        rt,
+        peer_id,
+        signing_key,
+        addrs,
+        metadata,
+        peer_metadata,
+        mpsc::unbounded().1,
+    )
+}
+
+fn setup_discovery_with_identity_key_updates(
+    rt: &mut Runtime,
+    peer_id: PeerId,
+    signing_key: Ed25519PrivateKey,
+    addrs: Vec<NetworkAddress>,
+    metadata: DiscoveryMetadata,
+    peer_metadata: PeerMetadata,
+    identity_key_updates: mpsc::UnboundedReceiver<x25519::PublicKey>,
 ) -> (
     libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
     channel::Receiver<ConnectivityRequest>,
@@ -59,6 +112,9 @@ fn setup_discovery(
             peer_id,
             role,
             addrs,
+            metadata,
+            signing_key,
+            Arc::new(RwLock::new(HashMap::new())),
             ticker_rx,
             DiscoveryNetworkSender::new(
                 PeerManagerRequestSender::new(peer_mgr_reqs_tx),
@@ -66,7 +122,9 @@ fn setup_discovery(
             ),
             DiscoveryNetworkEvents::new(network_notifs_rx, connection_notifs_rx),
             conn_mgr_reqs_tx,
+            peer_metadata,
         )
+        .identity_key_updates(identity_key_updates)
     };
     rt.spawn(discovery.start());
     (
@@ -93,6 +151,45 @@ async fn expect_address_update(
     }
 }
 
+async fn send_msg(
+    network_notifs_tx: &mut libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    peer_id: PeerId,
+    msg: DiscoveryMsg,
+) {
+    let (delivered_tx, delivered_rx) = oneshot::channel();
+    network_notifs_tx
+        .push_with_feedback(
+            (peer_id, ProtocolId::DiscoveryDirectSend),
+            PeerManagerNotification::RecvMessage(peer_id, get_raw_message(msg), Encoding::Lcs),
+            Some(delivered_tx),
+        )
+        .unwrap();
+    delivered_rx.await.unwrap();
+}
+
+async fn expect_msg(
+    network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+) -> DiscoveryMsg {
+    match network_reqs_rx.select_next_some().await {
+        PeerManagerRequest::SendMessage(peer_id, raw_msg, _) => {
+            assert_eq!(peer_id, expected_peer_id);
+            parse_raw_message(raw_msg).unwrap()
+        }
+        req => panic!("Unexpected request to peer manager: {:?}", req),
+    }
+}
+
+async fn expect_notes(
+    network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+) -> (Vec<Note>, Vec<PeerId>) {
+    match expect_msg(network_reqs_rx, expected_peer_id).await {
+        DiscoveryMsg::Notes { notes, wanted } => (notes, wanted),
+        msg => panic!("Unexpected DiscoveryMsg: {:?}", msg),
+    }
+}
+
 #[test]
 // Test behavior on receipt of an inbound DiscoveryMsg.
 fn inbound() {
@@ -100,39 +197,46 @@ fn inbound() {
     let mut rt = Runtime::new().unwrap();
 
     // Setup self.
-    let self_peer_id = PeerId::random();
+    let (self_key, self_peer_id) = signing_key(0);
     let self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
 
     // Setup other peer.
     let other_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap()];
-    let other_peer_id = PeerId::random();
+    let (other_key, other_peer_id) = signing_key(1);
 
     // Setup new peer to be added later.
     let new_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/7070").unwrap()];
-    let new_peer_id = PeerId::random();
+    let (new_key, new_peer_id) = signing_key(2);
 
     // Setup discovery.
-    let (_, mut conn_mgr_reqs_rx, mut network_notifs_tx, _, _) =
-        setup_discovery(&mut rt, self_peer_id, self_addrs.clone());
+    let (_, mut conn_mgr_reqs_rx, mut network_notifs_tx, _, _) = setup_discovery(
+        &mut rt,
+        self_peer_id,
+        self_key,
+        self_addrs.clone(),
+        DiscoveryMetadata::new(),
+        PeerMetadata::new(),
+    );
 
     // Fake connectivity manager and dialer.
     let f_network = async move {
         // Send a message from other peer containing their discovery note.
-        let other_note = Note::new(
-            other_peer_id,
-            other_addrs.clone(),
-            b"example.com",
-            100, /* epoch */
-        );
-        let msg = DiscoveryMsg {
+        let epoch = get_unix_epoch();
+        let other_note = note(&other_key, other_peer_id, other_addrs.clone(), epoch);
+        let msg = DiscoveryMsg::Notes {
             notes: vec![other_note],
+            wanted: vec![],
         };
         let msg_key = (other_peer_id, ProtocolId::DiscoveryDirectSend);
         let (delivered_tx, delivered_rx) = oneshot::channel();
         network_notifs_tx
             .push_with_feedback(
                 msg_key,
-                PeerManagerNotification::RecvMessage(other_peer_id, get_raw_message(msg)),
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
                 Some(delivered_tx),
             )
             .unwrap();
@@ -153,30 +257,25 @@ fn inbound() {
 
         // Send a message from other peer containing their updated discovery note
         // and another peer's new note.
-        let new_note = Note::new(
-            new_peer_id,
-            new_addrs.clone(),
-            b"example.com",
-            200, /* epoch */
-        );
+        let new_note = note(&new_key, new_peer_id, new_addrs.clone(), epoch + 1);
 
         // Update other peer's note.
         let other_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/1234").unwrap()];
-        let other_note = Note::new(
-            other_peer_id,
-            other_addrs.clone(),
-            b"example.com",
-            300, /* epoch */
-        );
+        let other_note = note(&other_key, other_peer_id, other_addrs.clone(), epoch + 2);
 
-        let msg = DiscoveryMsg {
+        let msg = DiscoveryMsg::Notes {
             notes: vec![new_note, other_note],
+            wanted: vec![],
         };
         let (delivered_tx, delivered_rx) = oneshot::channel();
         network_notifs_tx
             .push_with_feedback(
                 msg_key,
-                PeerManagerNotification::RecvMessage(other_peer_id, get_raw_message(msg)),
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
                 Some(delivered_tx),
             )
             .unwrap();
@@ -206,7 +305,7 @@ fn outbound() {
     let mut rt = Runtime::new().unwrap();
 
     // Setup self peer.
-    let peer_id = PeerId::random();
+    let (self_key, peer_id) = signing_key(0);
     let addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
 
     // Setup other peer.
@@ -217,10 +316,17 @@ fn outbound() {
     let (
         mut network_reqs_rx,
         _conn_mgr_req_rx,
-        _network_notifs_tx,
+        mut network_notifs_tx,
         mut connection_notifs_tx,
         mut ticker_tx,
-    ) = setup_discovery(&mut rt, peer_id, addrs.clone());
+    ) = setup_discovery(
+        &mut rt,
+        peer_id,
+        self_key,
+        addrs.clone(),
+        DiscoveryMetadata::new(),
+        PeerMetadata::new(),
+    );
 
     // Fake connectivity manager and dialer.
     let f_network = async move {
@@ -229,7 +335,11 @@ fn outbound() {
         connection_notifs_tx
             .push_with_feedback(
                 other_peer_id,
-                peer_manager::ConnectionNotification::NewPeer(other_peer_id, other_peer_addr),
+                peer_manager::ConnectionNotification::NewPeer(
+                    other_peer_id,
+                    other_peer_addr,
+                    peer_manager::ConnectionInfo::mock(),
+                ),
                 Some(delivered_tx),
             )
             .unwrap();
@@ -238,21 +348,263 @@ fn outbound() {
         // Trigger outbound msg.
         ticker_tx.send(()).await.unwrap();
 
-        // Check request sent as message over network.
-        match network_reqs_rx.select_next_some().await {
-            PeerManagerRequest::SendMessage(peer, raw_msg) => {
-                assert_eq!(peer, other_peer_id);
-                let msg = parse_raw_message(raw_msg).unwrap();
-                // Receive DiscoveryMsg from actor. The message should contain only a note for the
-                // sending peer since it doesn't yet have the note for the other peer.
-                assert_eq!(1, msg.notes.len());
-                assert_eq!(peer_id, msg.notes[0].peer_id);
-                assert_eq!(&addrs, msg.notes[0].addrs());
+        // Check digest sent as message over network. The digest should contain only the note for
+        // the sending peer since it doesn't yet have the note for the other peer.
+        let epoch = match expect_msg(&mut network_reqs_rx, other_peer_id).await {
+            DiscoveryMsg::Digest(digest) => {
+                assert_eq!(1, digest.len());
+                assert_eq!(peer_id, digest[0].0);
+                digest[0].1
             }
-            req => {
-                panic!("Unexpected request to peer manager: {:?}", req);
+            msg => panic!("Unexpected DiscoveryMsg: {:?}", msg),
+        };
+
+        // The other peer asks for the note, which is sent back.
+        send_msg(
+            &mut network_notifs_tx,
+            other_peer_id,
+            DiscoveryMsg::Notes {
+                notes: vec![],
+                wanted: vec![peer_id],
+            },
+        )
+        .await;
+        let (notes, wanted) = expect_notes(&mut network_reqs_rx, other_peer_id).await;
+        assert!(wanted.is_empty());
+        assert_eq!(1, notes.len());
+        assert_eq!(peer_id, notes[0].peer_id);
+        assert_eq!(epoch, notes[0].epoch());
+        assert_eq!(&addrs, notes[0].addrs());
+    };
+
+    rt.block_on(f_network);
+}
+
+#[test]
+// Test that only the notes missing or outdated at either peer are exchanged in reply to a digest.
+fn digest() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let (self_key, self_peer_id) = signing_key(0);
+    let self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+
+    // Setup the remote peer exchanging digests with self, and the peers it knows of.
+    let (_, remote_peer_id) = signing_key(1);
+    let remote_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap();
+    let (other_key, other_peer_id) = signing_key(2);
+    let other_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/7070").unwrap()];
+    let (new_key, new_peer_id) = signing_key(3);
+    let new_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/6060").unwrap()];
+    let (_, expired_peer_id) = signing_key(4);
+
+    // Setup discovery.
+    let (
+        mut network_reqs_rx,
+        mut conn_mgr_reqs_rx,
+        mut network_notifs_tx,
+        mut connection_notifs_tx,
+        mut ticker_tx,
+    ) = setup_discovery(
+        &mut rt,
+        self_peer_id,
+        self_key,
+        self_addrs.clone(),
+        DiscoveryMetadata::new(),
+        PeerMetadata::new(),
+    );
+
+    let f_network = async move {
+        let (delivered_tx, delivered_rx) = oneshot::channel();
+        connection_notifs_tx
+            .push_with_feedback(
+                remote_peer_id,
+                peer_manager::ConnectionNotification::NewPeer(
+                    remote_peer_id,
+                    remote_addr,
+                    peer_manager::ConnectionInfo::mock(),
+                ),
+                Some(delivered_tx),
+            )
+            .unwrap();
+        delivered_rx.await.unwrap();
+
+        // Self learns the note of the other peer.
+        let epoch = get_unix_epoch();
+        let other_note = note(&other_key, other_peer_id, other_addrs.clone(), epoch);
+        send_msg(
+            &mut network_notifs_tx,
+            remote_peer_id,
+            DiscoveryMsg::Notes {
+                notes: vec![other_note],
+                wanted: vec![],
+            },
+        )
+        .await;
+        expect_address_update(
+            &mut conn_mgr_reqs_rx,
+            [
+                (other_peer_id, other_addrs.clone()),
+                (self_peer_id, self_addrs.clone()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .await;
+
+        // Get the epoch of the note of self from its digest.
+        ticker_tx.send(()).await.unwrap();
+        let self_epoch = match expect_msg(&mut network_reqs_rx, remote_peer_id).await {
+            DiscoveryMsg::Digest(digest) => {
+                digest
+                    .into_iter()
+                    .find(|(peer_id, _)| *peer_id == self_peer_id)
+                    .unwrap()
+                    .1
             }
+            msg => panic!("Unexpected DiscoveryMsg: {:?}", msg),
+        };
+
+        // The remote peer has an older note of the other peer, and the notes of a new peer and of
+        // an expired peer. It receives the note of the other peer, and is asked for the note of the
+        // new peer only.
+        send_msg(
+            &mut network_notifs_tx,
+            remote_peer_id,
+            DiscoveryMsg::Digest(vec![
+                (self_peer_id, self_epoch),
+                (other_peer_id, epoch - 1),
+                (new_peer_id, epoch + 1),
+                (expired_peer_id, epoch - NOTE_TTL_MS - 1),
+            ]),
+        )
+        .await;
+        let (notes, wanted) = expect_notes(&mut network_reqs_rx, remote_peer_id).await;
+        assert_eq!(1, notes.len());
+        assert_eq!(other_peer_id, notes[0].peer_id);
+        assert_eq!(vec![new_peer_id], wanted);
+
+        // The remote peer sends the note of the new peer.
+        let new_note = note(&new_key, new_peer_id, new_addrs.clone(), epoch + 1);
+        send_msg(
+            &mut network_notifs_tx,
+            remote_peer_id,
+            DiscoveryMsg::Notes {
+                notes: vec![new_note],
+                wanted: vec![],
+            },
+        )
+        .await;
+        expect_address_update(
+            &mut conn_mgr_reqs_rx,
+            [
+                (new_peer_id, new_addrs),
+                (other_peer_id, other_addrs),
+                (self_peer_id, self_addrs),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .await;
+
+        // Once both peers agree, nothing is sent in reply to the digest: the next message is the
+        // reply to an empty digest, which carries every known note.
+        let agreed = vec![
+            (self_peer_id, self_epoch),
+            (other_peer_id, epoch),
+            (new_peer_id, epoch + 1),
+        ];
+        send_msg(
+            &mut network_notifs_tx,
+            remote_peer_id,
+            DiscoveryMsg::Digest(agreed),
+        )
+        .await;
+        send_msg(
+            &mut network_notifs_tx,
+            remote_peer_id,
+            DiscoveryMsg::Digest(vec![]),
+        )
+        .await;
+        let (notes, wanted) = expect_notes(&mut network_reqs_rx, remote_peer_id).await;
+        assert!(wanted.is_empty());
+        assert_eq!(3, notes.len());
+    };
+    rt.block_on(f_network);
+}
+
+#[test]
+// Test that discovery actor advertises its addresses with its new identity key once rotated.
+fn identity_key_rotation() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let mut rng = StdRng::from_seed([0u8; 32]);
+
+    // Setup self peer.
+    let (self_key, peer_id) = signing_key(0);
+    let addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090")
+        .unwrap()
+        .append_prod_protos(x25519::PrivateKey::generate(&mut rng).public_key(), 0)];
+    let new_pubkey = x25519::PrivateKey::generate(&mut rng).public_key();
+
+    // Setup other peer.
+    let other_peer_id = PeerId::random();
+    let other_peer_addr = NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap();
+
+    // Setup discovery.
+    let (identity_key_updates_tx, identity_key_updates_rx) = mpsc::unbounded();
+    let (
+        mut network_reqs_rx,
+        _conn_mgr_req_rx,
+        mut network_notifs_tx,
+        mut connection_notifs_tx,
+        _ticker_tx,
+    ) = setup_discovery_with_identity_key_updates(
+        &mut rt,
+        peer_id,
+        self_key,
+        addrs.clone(),
+        DiscoveryMetadata::new(),
+        PeerMetadata::new(),
+        identity_key_updates_rx,
+    );
+
+    let f_network = async move {
+        let (delivered_tx, delivered_rx) = oneshot::channel();
+        connection_notifs_tx
+            .push_with_feedback(
+                other_peer_id,
+                peer_manager::ConnectionNotification::NewPeer(
+                    other_peer_id,
+                    other_peer_addr,
+                    peer_manager::ConnectionInfo::mock(),
+                ),
+                Some(delivered_tx),
+            )
+            .unwrap();
+        delivered_rx.await.unwrap();
+
+        identity_key_updates_tx.unbounded_send(new_pubkey).unwrap();
+        let expected_addrs = vec![addrs[0].clone().rotate_noise_public_key(&new_pubkey)];
+        // The update may be handled after the first digest.
+        for _ in 0..10 {
+            send_msg(
+                &mut network_notifs_tx,
+                other_peer_id,
+                DiscoveryMsg::Digest(vec![]),
+            )
+            .await;
+            let (notes, _) = expect_notes(&mut network_reqs_rx, other_peer_id).await;
+            assert_eq!(1, notes.len());
+            if notes[0].addrs() == &expected_addrs {
+                return;
+            }
+            assert_eq!(&addrs, notes[0].addrs());
         }
+        panic!("The new identity key was never advertised");
     };
 
     rt.block_on(f_network);
@@ -264,7 +616,7 @@ fn old_note_higher_epoch() {
     let mut rt = Runtime::new().unwrap();
 
     // Setup self peer.
-    let peer_id = PeerId::random();
+    let (self_key, peer_id) = signing_key(0);
     let addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
 
     // Setup other peer.
@@ -272,8 +624,15 @@ fn old_note_higher_epoch() {
     let other_peer_id = PeerId::random();
 
     // Setup discovery.
-    let (mut network_reqs_rx, _, mut network_notifs_tx, mut connection_notifs_tx, mut ticker_tx) =
-        setup_discovery(&mut rt, peer_id, addrs);
+    let (mut network_reqs_rx, _, mut network_notifs_tx, mut connection_notifs_tx, _) =
+        setup_discovery(
+            &mut rt,
+            peer_id,
+            self_key,
+            addrs,
+            DiscoveryMetadata::new(),
+            PeerMetadata::new(),
+        );
 
     // Fake connectivity manager and dialer.
     let f_network = async move {
@@ -285,6 +644,7 @@ fn old_note_higher_epoch() {
                 peer_manager::ConnectionNotification::NewPeer(
                     other_peer_id,
                     other_peer_addrs[0].clone(),
+                    peer_manager::ConnectionInfo::mock(),
                 ),
                 Some(delivered_tx),
             )
@@ -295,39 +655,44 @@ fn old_note_higher_epoch() {
         // current note.
         let old_self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap()];
         let old_epoch = get_unix_epoch() + 1_000_000;
-        let old_note = Note::new(peer_id, old_self_addrs.clone(), b"example.com", old_epoch);
-        let msg = DiscoveryMsg {
+        let old_note = note(
+            &signing_key(0).0,
+            peer_id,
+            old_self_addrs.clone(),
+            old_epoch,
+        );
+        let msg = DiscoveryMsg::Notes {
             notes: vec![old_note],
+            wanted: vec![],
         };
         let msg_key = (other_peer_id, ProtocolId::DiscoveryDirectSend);
         let (delivered_tx, delivered_rx) = oneshot::channel();
         network_notifs_tx
             .push_with_feedback(
                 msg_key,
-                PeerManagerNotification::RecvMessage(other_peer_id, get_raw_message(msg)),
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
                 Some(delivered_tx),
             )
             .unwrap();
         delivered_rx.await.unwrap();
 
-        // Trigger outbound msg.
-        ticker_tx.send(()).await.unwrap();
-
-        // Check request sent as message over network.
-        match network_reqs_rx.select_next_some().await {
-            PeerManagerRequest::SendMessage(peer, raw_msg) => {
-                assert_eq!(peer, other_peer_id);
-                let msg = parse_raw_message(raw_msg).unwrap();
-                // Receive DiscoveryMsg from actor. The message should contain only a note for the
-                // sending peer since it doesn't yet have the note for the other peer.
-                assert_eq!(1, msg.notes.len());
-                assert_eq!(peer_id, msg.notes[0].peer_id);
-                assert!(msg.notes[0].epoch() > old_epoch);
-            }
-            req => {
-                panic!("Unexpected request to peer manager: {:?}", req);
-            }
-        }
+        // The other peer lacks every note, so it receives the note of this node in reply to its
+        // digest.
+        send_msg(
+            &mut network_notifs_tx,
+            other_peer_id,
+            DiscoveryMsg::Digest(vec![]),
+        )
+        .await;
+        let (notes, wanted) = expect_notes(&mut network_reqs_rx, other_peer_id).await;
+        assert!(wanted.is_empty());
+        assert_eq!(1, notes.len());
+        assert_eq!(peer_id, notes[0].peer_id);
+        assert!(notes[0].epoch() > old_epoch);
     };
     rt.block_on(f_network);
 }
@@ -338,7 +703,7 @@ fn old_note_max_epoch() {
     let mut rt = Runtime::new().unwrap();
 
     // Setup self.
-    let peer_id = PeerId::random();
+    let (self_key, peer_id) = signing_key(0);
     let addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
 
     // Setup other.
@@ -346,8 +711,15 @@ fn old_note_max_epoch() {
     let other_peer_id = PeerId::random();
 
     // Setup discovery.
-    let (mut network_reqs_rx, _, mut network_notifs_tx, mut connection_notifs_tx, mut ticker_tx) =
-        setup_discovery(&mut rt, peer_id, addrs);
+    let (mut network_reqs_rx, _, mut network_notifs_tx, mut connection_notifs_tx, _) =
+        setup_discovery(
+            &mut rt,
+            peer_id,
+            self_key,
+            addrs,
+            DiscoveryMetadata::new(),
+            PeerMetadata::new(),
+        );
 
     // Fake connectivity manager and dialer.
     let f_network = async move {
@@ -359,6 +731,7 @@ fn old_note_max_epoch() {
                 peer_manager::ConnectionNotification::NewPeer(
                     other_peer_id,
                     other_peer_addrs[0].clone(),
+                    peer_manager::ConnectionInfo::mock(),
                 ),
                 Some(delivered_tx),
             )
@@ -368,39 +741,280 @@ fn old_note_max_epoch() {
         // Send DiscoveryMsg consisting of the this node's older note which has u64::MAX epoch.
         let old_self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9091").unwrap()];
         let old_epoch = std::u64::MAX;
-        let old_note = Note::new(peer_id, old_self_addrs.clone(), b"example.com", old_epoch);
-        let msg = DiscoveryMsg {
+        let old_note = note(
+            &signing_key(0).0,
+            peer_id,
+            old_self_addrs.clone(),
+            old_epoch,
+        );
+        let msg = DiscoveryMsg::Notes {
             notes: vec![old_note],
+            wanted: vec![],
         };
         let msg_key = (other_peer_id, ProtocolId::DiscoveryDirectSend);
         let (delivered_tx, delivered_rx) = oneshot::channel();
         network_notifs_tx
             .push_with_feedback(
                 msg_key,
-                PeerManagerNotification::RecvMessage(other_peer_id, get_raw_message(msg)),
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
                 Some(delivered_tx),
             )
             .unwrap();
         delivered_rx.await.unwrap();
 
-        // Trigger outbound msg.
-        ticker_tx.send(()).await.unwrap();
+        // The other peer lacks every note, so it receives the note of this node in reply to its
+        // digest.
+        send_msg(
+            &mut network_notifs_tx,
+            other_peer_id,
+            DiscoveryMsg::Digest(vec![]),
+        )
+        .await;
+        let (notes, wanted) = expect_notes(&mut network_reqs_rx, other_peer_id).await;
+        assert!(wanted.is_empty());
+        assert_eq!(1, notes.len());
+        assert_eq!(peer_id, notes[0].peer_id);
+        assert!(notes[0].epoch() < old_epoch);
+    };
+    rt.block_on(f_network);
+}
 
-        // Check request sent as message over network.
-        match network_reqs_rx.select_next_some().await {
-            PeerManagerRequest::SendMessage(peer, raw_msg) => {
-                assert_eq!(peer, other_peer_id);
-                let msg = parse_raw_message(raw_msg).unwrap();
-                // Receive DiscoveryMsg from actor. The message should contain only a note for the
-                // sending peer since it doesn't yet have the note for the other peer.
-                assert_eq!(1, msg.notes.len());
-                assert_eq!(peer_id, msg.notes[0].peer_id);
-                assert!(msg.notes[0].epoch() < old_epoch);
-            }
-            req => {
-                panic!("Unexpected request to peer manager: {:?}", req);
-            }
-        }
+#[test]
+fn metadata_limits() {
+    let entry = |key_size: usize, value_size: usize| ("k".repeat(key_size), "v".repeat(value_size));
+    let valid: DiscoveryMetadata = (0..MAX_METADATA_ENTRIES)
+        .map(|i| (format!("{}", i), "v".repeat(MAX_METADATA_VALUE_SIZE)))
+        .collect();
+    assert!(verify_metadata(&valid).is_ok());
+
+    let mut too_many = valid;
+    too_many.insert("extra".to_string(), String::new());
+    assert!(verify_metadata(&too_many).is_err());
+
+    for (key_size, value_size) in &[(0, 1), (MAX_METADATA_KEY_SIZE + 1, 1)] {
+        let metadata = vec![entry(*key_size, *value_size)].into_iter().collect();
+        assert!(verify_metadata(&metadata).is_err());
+    }
+    let metadata = vec![entry(MAX_METADATA_KEY_SIZE, MAX_METADATA_VALUE_SIZE + 1)]
+        .into_iter()
+        .collect();
+    assert!(verify_metadata(&metadata).is_err());
+}
+
+#[test]
+// Test that the metadata of received notes is exposed to applications, unless it exceeds limits.
+fn inbound_metadata() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let (self_key, self_peer_id) = signing_key(0);
+    let self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+    let self_metadata: DiscoveryMetadata = vec![("region".to_string(), "eu".to_string())]
+        .into_iter()
+        .collect();
+
+    // Setup other peer, advertising its JSON-RPC endpoint.
+    let other_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap()];
+    let (other_key, other_peer_id) = signing_key(1);
+    let other_metadata: DiscoveryMetadata =
+        vec![("json_rpc".to_string(), "http://127.0.0.1:8081".to_string())]
+            .into_iter()
+            .collect();
+
+    // Setup a peer with oversized metadata.
+    let big_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/7070").unwrap()];
+    let (big_key, big_peer_id) = signing_key(2);
+    let big_metadata: DiscoveryMetadata = vec![(
+        "json_rpc".to_string(),
+        "v".repeat(MAX_METADATA_VALUE_SIZE + 1),
+    )]
+    .into_iter()
+    .collect();
+
+    // Setup discovery.
+    let peer_metadata = PeerMetadata::new();
+    let (_, mut conn_mgr_reqs_rx, mut network_notifs_tx, _, _) = setup_discovery(
+        &mut rt,
+        self_peer_id,
+        self_key,
+        self_addrs.clone(),
+        self_metadata,
+        peer_metadata.clone(),
+    );
+    assert_eq!(
+        peer_metadata.get_value(&self_peer_id, "region"),
+        Some("eu".to_string())
+    );
+
+    let f_network = async move {
+        let epoch = get_unix_epoch();
+        let other_note = Note::new(
+            other_peer_id,
+            other_addrs.clone(),
+            other_metadata,
+            b"example.com",
+            epoch,
+            &other_key,
+        );
+        let big_note = Note::new(
+            big_peer_id,
+            big_addrs,
+            big_metadata,
+            b"example.com",
+            epoch,
+            &big_key,
+        );
+        let msg = DiscoveryMsg::Notes {
+            notes: vec![big_note, other_note],
+            wanted: vec![],
+        };
+        let msg_key = (other_peer_id, ProtocolId::DiscoveryDirectSend);
+        let (delivered_tx, delivered_rx) = oneshot::channel();
+        network_notifs_tx
+            .push_with_feedback(
+                msg_key,
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
+                Some(delivered_tx),
+            )
+            .unwrap();
+        delivered_rx.await.unwrap();
+
+        // The note with oversized metadata is dropped.
+        expect_address_update(
+            &mut conn_mgr_reqs_rx,
+            [(other_peer_id, other_addrs), (self_peer_id, self_addrs)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .await;
+    };
+    rt.block_on(f_network);
+
+    assert_eq!(
+        peer_metadata.peers_with_key("json_rpc"),
+        vec![(other_peer_id, "http://127.0.0.1:8081".to_string())]
+    );
+    assert!(peer_metadata.get(&big_peer_id).is_none());
+}
+
+#[test]
+fn verify_note() {
+    let addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+    let (key, peer_id) = signing_key(0);
+    let no_trusted_peers = HashMap::new();
+
+    // The note of a peer whose PeerId derives from its identity key.
+    let derived_note = note(&key, peer_id, addrs.clone(), get_unix_epoch());
+    assert!(derived_note.verify(&no_trusted_peers).is_ok());
+
+    // The note of a peer whose PeerId is unrelated to its identity key, e.g., a validator, is only
+    // valid if its identity key is trusted.
+    let other_peer_id = PeerId::random();
+    let other_note = note(&key, other_peer_id, addrs.clone(), get_unix_epoch());
+    assert!(other_note.verify(&no_trusted_peers).is_err());
+    let identity_public_key =
+        x25519::PublicKey::from_ed25519_public_bytes(&key.public_key().to_bytes()).unwrap();
+    let trusted_peers = [(
+        other_peer_id,
+        NetworkPublicKeys {
+            identity_public_key,
+        },
+    )]
+    .iter()
+    .cloned()
+    .collect();
+    assert!(other_note.verify(&trusted_peers).is_ok());
+
+    // A note altered after it was signed is invalid.
+    let mut altered_note = derived_note;
+    altered_note.peer_info.epoch += 1;
+    assert!(altered_note.verify(&no_trusted_peers).is_err());
+
+    // A note signed by another key is invalid.
+    let forged_note = note(&signing_key(1).0, peer_id, addrs, get_unix_epoch());
+    assert!(forged_note.verify(&no_trusted_peers).is_err());
+}
+
+#[test]
+// Forged and expired notes are dropped on receipt.
+fn inbound_invalid_notes() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let (self_key, self_peer_id) = signing_key(0);
+    let self_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+
+    // Setup other peers.
+    let (other_key, other_peer_id) = signing_key(1);
+    let other_addrs = vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/8080").unwrap()];
+    let (_, forged_peer_id) = signing_key(2);
+    let (expired_key, expired_peer_id) = signing_key(3);
+
+    // Setup discovery.
+    let (_, mut conn_mgr_reqs_rx, mut network_notifs_tx, _, _) = setup_discovery(
+        &mut rt,
+        self_peer_id,
+        self_key,
+        self_addrs.clone(),
+        DiscoveryMetadata::new(),
+        PeerMetadata::new(),
+    );
+
+    let f_network = async move {
+        let epoch = get_unix_epoch();
+        let other_note = note(&other_key, other_peer_id, other_addrs.clone(), epoch);
+        // The other peer claims the address of another peer for itself.
+        let forged_note = note(
+            &other_key,
+            forged_peer_id,
+            vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/7070").unwrap()],
+            epoch,
+        );
+        let expired_note = note(
+            &expired_key,
+            expired_peer_id,
+            vec![NetworkAddress::from_str("/ip4/127.0.0.1/tcp/6060").unwrap()],
+            epoch - NOTE_TTL_MS - 1,
+        );
+        let msg = DiscoveryMsg::Notes {
+            notes: vec![forged_note, expired_note, other_note],
+            wanted: vec![],
+        };
+        let msg_key = (other_peer_id, ProtocolId::DiscoveryDirectSend);
+        let (delivered_tx, delivered_rx) = oneshot::channel();
+        network_notifs_tx
+            .push_with_feedback(
+                msg_key,
+                PeerManagerNotification::RecvMessage(
+                    other_peer_id,
+                    get_raw_message(msg),
+                    Encoding::Lcs,
+                ),
+                Some(delivered_tx),
+            )
+            .unwrap();
+        delivered_rx.await.unwrap();
+
+        // Only the valid note of the other peer is accepted.
+        expect_address_update(
+            &mut conn_mgr_reqs_rx,
+            [(other_peer_id, other_addrs), (self_peer_id, self_addrs)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .await;
     };
     rt.block_on(f_network);
 }
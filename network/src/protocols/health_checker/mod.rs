@@ -11,6 +11,22 @@
 //! disconnect from the peer. It relies on ConnectivityManager or the remote peer to re-establish
 //! the connection.
 //!
+//! The Pong also carries the time of the remote peer when replying, from which the HealthChecker
+//! estimates the skew of the clock of the peer relative to ours, give or take half the round-trip
+//! time. The skews are exported as metrics and reported in the view of the connected peers, as
+//! consensus rejects the proposals whose timestamps are ahead of our clock.
+//!
+//! The round-trip times of the pings are exported as a histogram per peer, and summarized in
+//! [`PeerRtts`], e.g., for consensus to account for the latency of the links in its timeouts.
+//!
+//! With a [`PingPolicy`], the HealthChecker adapts to the activity of the peers, so that large
+//! fanouts aren't pinged needlessly: the peers which sent a message recently, as recorded in
+//! [`PeerActivity`], are evidently alive and aren't pinged, and the idle peers which keep
+//! answering are pinged less and less often, back to every round once a ping fails.
+//!
+//! If the handling of an event panics, the HealthChecker forgets the ping failures of the connected
+//! peers, which may have been left half-updated, and carries on with the next round.
+//!
 //! Future Work
 //! -----------
 //! We can make a few other improvements to the health checker. These are:
@@ -18,13 +34,17 @@
 //! - Use successful inbound pings as a sign of remote note being healthy
 //! - Ping a peer only in periods of no application-level communication with the peer
 use crate::{
+    catch_panic::catch_panic,
+    connected_peers::ConnectedPeers,
     counters,
     error::NetworkError,
-    peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    peer_manager::{ConnectionRequestSender, PeerActivity, PeerManagerRequestSender},
+    priority::ProtocolPriority,
     protocols::{
         network::{Event, NetworkEvents, NetworkSender},
         rpc::error::RpcError,
     },
+    time_sync,
     validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
     ProtocolId,
 };
@@ -39,11 +59,18 @@ use libra_security_logger::{security_log, SecurityEvent};
 use libra_types::PeerId;
 use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, time::Duration};
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 #[cfg(test)]
 mod test;
 
+const ACTOR: &str = "health_checker";
+
 /// The interface from Network to HealthChecker layer.
 ///
 /// `HealthCheckerNetworkEvents` is a `Stream` of `PeerManagerNotification` where the
@@ -72,6 +99,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![ProtocolId::HealthCheckerRpc],
             vec![],
+            ProtocolPriority::High,
             QueueStyle::LIFO,
             NETWORK_CHANNEL_SIZE,
             Some(&counters::PENDING_HEALTH_CHECKER_NETWORK_EVENTS),
@@ -102,7 +130,7 @@ impl HealthCheckerNetworkSender {
         recipient: PeerId,
         req_msg: HealthCheckerMsg,
         timeout: Duration,
-    ) -> Result<HealthCheckerMsg, RpcError> {
+    ) -> Result<HealthCheckerMsg, NetworkError> {
         let protocol = ProtocolId::HealthCheckerRpc;
         self.inner
             .send_rpc(recipient, protocol, req_msg, timeout)
@@ -123,8 +151,137 @@ pub enum HealthCheckerMsg {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Ping(u32);
 
+/// The nonce of the Ping, and the time of the replying peer, in microseconds since the Unix epoch.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Pong(u32);
+pub struct Pong(u32, u64);
+
+/// The round-trip times of the successful pings of a peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RttStats {
+    /// Round-trip time of the last ping.
+    pub last: Duration,
+    /// Shortest round-trip time, i.e., the closest to the propagation delay of the link.
+    pub min: Duration,
+    /// Smoothed round-trip time, estimated like TCP does (RFC 6298).
+    pub smoothed: Duration,
+    /// Smoothed mean deviation of the round-trip times from `smoothed`.
+    pub variation: Duration,
+    /// Number of successful pings.
+    pub samples: u64,
+}
+
+impl RttStats {
+    /// The stats after a new sample of the round-trip time.
+    fn sample(prev: Option<RttStats>, rtt: Duration) -> Self {
+        match prev {
+            None => Self {
+                last: rtt,
+                min: rtt,
+                smoothed: rtt,
+                variation: rtt / 2,
+                samples: 1,
+            },
+            Some(prev) => {
+                let deviation = if rtt > prev.smoothed {
+                    rtt - prev.smoothed
+                } else {
+                    prev.smoothed - rtt
+                };
+                Self {
+                    last: rtt,
+                    min: cmp::min(prev.min, rtt),
+                    smoothed: (prev.smoothed * 7 + rtt) / 8,
+                    variation: (prev.variation * 3 + deviation) / 4,
+                    samples: prev.samples + 1,
+                }
+            }
+        }
+    }
+
+    /// A timeout which the round-trip times are unlikely to exceed, i.e., the retransmission
+    /// timeout of TCP.
+    pub fn timeout(&self) -> Duration {
+        self.smoothed + self.variation * 4
+    }
+}
+
+/// The round-trip times of the pings of the connected peers. Cloning it returns a handle to the
+/// same stats.
+#[derive(Clone, Debug, Default)]
+pub struct PeerRtts {
+    inner: Arc<RwLock<HashMap<PeerId, RttStats>>>,
+}
+
+impl PeerRtts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The round-trip times of `peer_id`, if it was pinged successfully since it connected.
+    pub fn get(&self, peer_id: &PeerId) -> Option<RttStats> {
+        self.inner.read().unwrap().get(peer_id).copied()
+    }
+
+    /// Returns the round-trip times of all the peers, ordered by peer id.
+    pub fn snapshot(&self) -> Vec<(PeerId, RttStats)> {
+        let mut rtts: Vec<_> = self
+            .inner
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(peer_id, stats)| (*peer_id, *stats))
+            .collect();
+        rtts.sort_by_key(|(peer_id, _)| *peer_id);
+        rtts
+    }
+
+    /// Records a new sample of the round-trip time of `peer_id`, and returns the stats.
+    pub(crate) fn sample(&self, peer_id: PeerId, rtt: Duration) -> RttStats {
+        let mut inner = self.inner.write().unwrap();
+        let stats = RttStats::sample(inner.get(&peer_id).copied(), rtt);
+        inner.insert(peer_id, stats);
+        stats
+    }
+
+    /// Forgets the round-trip times of `peer_id`.
+    fn remove(&self, peer_id: &PeerId) {
+        self.inner.write().unwrap().remove(peer_id);
+    }
+}
+
+/// How the health checker adapts the pings of the peers to their activity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PingPolicy {
+    /// The peers which sent a message within this window aren't pinged.
+    pub activity_window: Duration,
+    /// Factor by which the number of rounds between the pings of an idle peer grows after each of
+    /// its successful pings.
+    pub backoff_factor: u64,
+    /// Maximum number of rounds between the pings of an idle peer.
+    pub max_backoff_rounds: u64,
+}
+
+impl Default for PingPolicy {
+    fn default() -> Self {
+        Self {
+            activity_window: Duration::from_secs(10),
+            backoff_factor: 2,
+            max_backoff_rounds: 16,
+        }
+    }
+}
+
+impl PingPolicy {
+    /// The number of rounds until the next ping of a peer after a successful one, given the
+    /// number of rounds before it, if the previous ping was successful too.
+    fn backoff_rounds(&self, prev_rounds: Option<u64>) -> u64 {
+        prev_rounds
+            .unwrap_or(1)
+            .saturating_mul(self.backoff_factor)
+            .min(self.max_backoff_rounds)
+            .max(1)
+    }
+}
 
 /// The actor performing health checks by running the Ping protocol
 pub struct HealthChecker<TTicker> {
@@ -148,6 +305,17 @@ pub struct HealthChecker<TTicker> {
     ping_failures_tolerated: u64,
     /// Counter incremented in each round of health checks
     round: u64,
+    /// View of the connected peers in which the round-trip time of the pings, and the clock skews
+    /// they measure, are recorded
+    connected_peers: Option<ConnectedPeers>,
+    /// Round-trip times of the successful pings of the connected peers
+    rtts: PeerRtts,
+    /// How the pings adapt to the activity of the peers, recorded in `activity`, if they do
+    ping_policy: Option<PingPolicy>,
+    activity: PeerActivity,
+    /// Map from the idle peers which answered their last ping to the round of their next ping,
+    /// and the number of rounds before it
+    backoffs: HashMap<PeerId, (u64, u64)>,
 }
 
 impl<TTicker> HealthChecker<TTicker>
@@ -171,87 +339,133 @@ where
             ping_timeout,
             ping_failures_tolerated,
             round: 0,
+            connected_peers: None,
+            rtts: PeerRtts::new(),
+            ping_policy: None,
+            activity: PeerActivity::new(),
+            backoffs: HashMap::new(),
         }
     }
 
+    /// Records the round-trip time of the successful pings, and the clock skews they measure, in
+    /// `connected_peers`.
+    pub fn report_rtt(mut self, connected_peers: ConnectedPeers) -> Self {
+        self.connected_peers = Some(connected_peers);
+        self
+    }
+
+    /// Summarizes the round-trip times of the successful pings of the connected peers in `rtts`.
+    pub fn record_rtts(mut self, rtts: PeerRtts) -> Self {
+        self.rtts = rtts;
+        self
+    }
+
+    /// Adapts the pings of the peers to their activity recorded in `activity` according to
+    /// `ping_policy`, rather than pinging them all alike.
+    pub fn adaptive(mut self, ping_policy: PingPolicy, activity: PeerActivity) -> Self {
+        self.ping_policy = Some(ping_policy);
+        self.activity = activity;
+        self
+    }
+
     pub async fn start(mut self) {
         let mut tick_handlers = FuturesUnordered::new();
         loop {
-            futures::select! {
-                event = self.network_rx.select_next_some() => {
-                    match event {
-                        Ok(Event::NewPeer(peer_id)) => {
-                            self.connected.insert(peer_id, (self.round, 0));
-                        },
-                        Ok(Event::LostPeer(peer_id)) => {
-                            self.connected.remove(&peer_id);
-                        },
-                        Ok(Event::RpcRequest((peer_id, msg, res_tx))) => {
-                            match msg {
-                            HealthCheckerMsg::Ping(ping) => self.handle_ping_request(peer_id, ping, res_tx),
-                            _ => security_log(SecurityEvent::InvalidHealthCheckerMsg)
-                                .error("Unexpected rpc message")
-                                    .data(&msg)
-                                    .data(&peer_id)
-                                    .log(),
-                            };
-                        }
-                        Ok(Event::Message(_)) => {
-                            security_log(SecurityEvent::InvalidNetworkEventHC)
-                                .error("Unexpected network event")
-                                .data(&event)
-                                .log();
-                            debug_assert!(false, "Unexpected network event");
-                        },
-                        Err(err) => {
-                            security_log(SecurityEvent::InvalidNetworkEventHC)
-                                .error(&err)
-                                .log();
-                            debug_assert!(false, "Unexpected network error");
+            let next_event = async {
+                futures::select! {
+                    event = self.network_rx.select_next_some() => {
+                        match event {
+                            Ok(Event::NewPeer(peer_id)) => {
+                                self.connected.insert(peer_id, (self.round, 0));
+                            },
+                            Ok(Event::LostPeer(peer_id)) => {
+                                self.connected.remove(&peer_id);
+                                self.rtts.remove(&peer_id);
+                                self.backoffs.remove(&peer_id);
+                            },
+                            Ok(Event::RpcRequest((peer_id, msg, res_tx))) => {
+                                match msg {
+                                HealthCheckerMsg::Ping(ping) => self.handle_ping_request(peer_id, ping, res_tx),
+                                _ => security_log(SecurityEvent::InvalidHealthCheckerMsg)
+                                    .error("Unexpected rpc message")
+                                        .data(&msg)
+                                        .data(&peer_id)
+                                        .log(),
+                                };
+                            }
+                            Ok(Event::Message(_)) => {
+                                security_log(SecurityEvent::InvalidNetworkEventHC)
+                                    .error("Unexpected network event")
+                                    .data(&event)
+                                    .log();
+                                debug_assert!(false, "Unexpected network event");
+                            },
+                            Err(err) => {
+                                security_log(SecurityEvent::InvalidNetworkEventHC)
+                                    .error(&err)
+                                    .log();
+                                debug_assert!(false, "Unexpected network error");
+                            }
                         }
                     }
-                }
-                _ = self.ticker.select_next_some() => {
-                    self.round += 1;
-                    debug!("Tick: Round number: {}", self.round);
-                    match self.sample_random_peer() {
-                        Some(peer_id) => {
-                            debug!("Will ping: {}", peer_id.short_str());
-
-                            let nonce = self.sample_nonce();
-
-                            tick_handlers.push(
-                                Self::ping_peer(
-                                    self.network_tx.clone(),
-                                    peer_id,
-                                    self.round,
-                                    nonce,
-                                    self.ping_timeout.clone()));
-                        }
-                        None => {
-                            debug!("No connected peer to ping");
+                    _ = self.ticker.select_next_some() => {
+                        self.round += 1;
+                        debug!("Tick: Round number: {}", self.round);
+                        time_sync::update_ntp_synchronized_metric();
+                        match self.sample_random_peer() {
+                            Some(peer_id) => {
+                                debug!("Will ping: {}", peer_id.short_str());
+
+                                let nonce = self.sample_nonce();
+
+                                tick_handlers.push(
+                                    Self::ping_peer(
+                                        self.network_tx.clone(),
+                                        peer_id,
+                                        self.round,
+                                        nonce,
+                                        self.ping_timeout.clone()));
+                            }
+                            None => {
+                                debug!("No connected peer due for a ping");
+                            }
                         }
                     }
+                    res = tick_handlers.select_next_some() => {
+                        let (peer_id, round, nonce, ping_result) = res;
+                        self.handle_ping_response(peer_id, round, nonce, ping_result).await;
+                    }
+                    complete => return false,
                 }
-                res = tick_handlers.select_next_some() => {
-                    let (peer_id, round, nonce, ping_result) = res;
-                    self.handle_ping_response(peer_id, round, nonce, ping_result).await;
-                }
-                complete => {
-                    break;
-                }
+                true
+            };
+            match catch_panic(ACTOR, next_event).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(_) => self.restart(),
             }
         }
         crit!("Health checker actor terminated");
     }
 
+    /// Restarts the actor after a panic: the connected peers are kept, as if they had just
+    /// connected.
+    fn restart(&mut self) {
+        let round = self.round;
+        for state in self.connected.values_mut() {
+            *state = (round, 0);
+        }
+        self.backoffs.clear();
+    }
+
     fn handle_ping_request(
         &mut self,
         peer_id: PeerId,
         ping: Ping,
         res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
     ) {
-        let message = match lcs::to_bytes(&HealthCheckerMsg::Pong(Pong(ping.0))) {
+        let now = duration_since_epoch().as_micros() as u64;
+        let message = match lcs::to_bytes(&HealthCheckerMsg::Pong(Pong(ping.0, now))) {
             Ok(msg) => msg,
             Err(e) => {
                 warn!("Unable to serialize pong response: {}", e);
@@ -271,13 +485,37 @@ where
         peer_id: PeerId,
         round: u64,
         req_nonce: u32,
-        ping_result: Result<Pong, RpcError>,
+        ping_result: Result<(Pong, Duration, Duration), NetworkError>,
     ) {
         debug!("Got result for ping round: {}", round);
         match ping_result {
-            Ok(pong) => {
+            Ok((pong, sent_at, rtt)) => {
                 if pong.0 == req_nonce {
                     debug!("Ping successful for peer: {}", peer_id.short_str());
+                    let clock_skew_usecs = time_sync::clock_skew_usecs(pong.1, sent_at, rtt);
+                    counters::LIBRA_NETWORK_PEER_CLOCK_SKEW_USECS
+                        .with_label_values(&[counters::LIBRA_NETWORK_PEER_CLOCK_SKEW_PEERS
+                            .label(&peer_id.short_str())])
+                        .set(clock_skew_usecs);
+                    counters::LIBRA_NETWORK_PEER_RTT
+                        .with_label_values(&[
+                            counters::LIBRA_NETWORK_PEER_RTT_PEERS.label(&peer_id.short_str())
+                        ])
+                        .observe(rtt.as_secs_f64());
+                    // The pongs of the peers lost meanwhile are only exported as metrics.
+                    if self.connected.contains_key(&peer_id) {
+                        self.rtts.sample(peer_id, rtt);
+                        if let Some(ping_policy) = &self.ping_policy {
+                            let prev_rounds =
+                                self.backoffs.get(&peer_id).map(|(_, rounds)| *rounds);
+                            let rounds = ping_policy.backoff_rounds(prev_rounds);
+                            self.backoffs.insert(peer_id, (round + rounds, rounds));
+                        }
+                    }
+                    if let Some(connected_peers) = &self.connected_peers {
+                        connected_peers.set_rtt(&peer_id, rtt);
+                        connected_peers.set_clock_skew(&peer_id, clock_skew_usecs);
+                    }
                     // Update last successful ping to current round.
                     self.connected
                         .entry(peer_id)
@@ -303,6 +541,8 @@ where
                     peer_id.short_str(),
                     err
                 );
+                // The peer is pinged every round again.
+                self.backoffs.remove(&peer_id);
                 match self.connected.get_mut(&peer_id) {
                     None => {
                         // If we are no longer connected to the peer, we ignore ping
@@ -340,24 +580,60 @@ where
         round: u64,
         nonce: u32,
         ping_timeout: Duration,
-    ) -> (PeerId, u64, u32, Result<Pong, RpcError>) {
+    ) -> (
+        PeerId,
+        u64,
+        u32,
+        Result<(Pong, Duration, Duration), NetworkError>,
+    ) {
         debug!(
             "Sending Ping request to peer: {} with nonce: {}",
             peer_id.short_str(),
             nonce
         );
+        let sent_at = duration_since_epoch();
+        let start = Instant::now();
         let res_pong_msg = network_tx
             .send_rpc(peer_id, HealthCheckerMsg::Ping(Ping(nonce)), ping_timeout)
             .await
             .and_then(|msg| match msg {
-                HealthCheckerMsg::Pong(res) => Ok(res),
-                _ => Err(RpcError::InvalidRpcResponse),
+                HealthCheckerMsg::Pong(res) => Ok((res, sent_at, start.elapsed())),
+                _ => Err(RpcError::InvalidRpcResponse.into()),
             });
         (peer_id, round, nonce, res_pong_msg)
     }
 
+    /// Samples a peer to ping among the connected peers, or, with a ping policy, among those
+    /// which were idle recently and whose backoff is over. The peers which were active recently
+    /// are considered to have answered a ping in this round.
     fn sample_random_peer(&mut self) -> Option<PeerId> {
-        let peers: Vec<_> = self.connected.keys().cloned().collect();
+        let ping_policy = match &self.ping_policy {
+            Some(ping_policy) => ping_policy,
+            None => {
+                let peers: Vec<_> = self.connected.keys().cloned().collect();
+                return peers.choose(&mut self.rng).cloned();
+            }
+        };
+        let round = self.round;
+        let now = Instant::now();
+        let mut peers = Vec::new();
+        for (peer_id, state) in self.connected.iter_mut() {
+            let active = self
+                .activity
+                .last_received(peer_id)
+                .map_or(false, |received_at| {
+                    now.duration_since(received_at) < ping_policy.activity_window
+                });
+            if active {
+                *state = (round, 0);
+            } else if self
+                .backoffs
+                .get(peer_id)
+                .map_or(true, |(next_round, _)| *next_round <= round)
+            {
+                peers.push(*peer_id);
+            }
+        }
         peers.choose(&mut self.rng).cloned()
     }
 
@@ -365,3 +641,9 @@ where
         self.rng.gen::<u32>()
     }
 }
+
+fn duration_since_epoch() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
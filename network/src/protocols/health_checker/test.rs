@@ -6,7 +6,7 @@ use crate::{
     peer_manager::{
         self, conn_notifs_channel, ConnectionRequest, PeerManagerNotification, PeerManagerRequest,
     },
-    protocols::rpc::InboundRpcRequest,
+    protocols::{rpc::InboundRpcRequest, wire::handshake::v1::Encoding},
     ProtocolId,
 };
 use channel::{libra_channel, message_queues::QueueStyle};
@@ -26,6 +26,20 @@ fn setup_permissive_health_checker(
     libra_channel::Receiver<PeerId, ConnectionRequest>,
     conn_notifs_channel::Sender,
     channel::Sender<()>,
+) {
+    setup_health_checker(rt, ping_failures_tolerated, None)
+}
+
+fn setup_health_checker(
+    rt: &mut Runtime,
+    ping_failures_tolerated: u64,
+    adaptive: Option<(PingPolicy, PeerActivity)>,
+) -> (
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    libra_channel::Receiver<PeerId, ConnectionRequest>,
+    conn_notifs_channel::Sender,
+    channel::Sender<()>,
 ) {
     let (ticker_tx, ticker_rx) = channel::new_test(0);
 
@@ -43,13 +57,16 @@ fn setup_permissive_health_checker(
     );
     let hc_network_rx = HealthCheckerNetworkEvents::new(network_notifs_rx, connection_notifs_rx);
 
-    let health_checker = HealthChecker::new(
+    let mut health_checker = HealthChecker::new(
         ticker_rx,
         hc_network_tx,
         hc_network_rx,
         PING_TIMEOUT,
         ping_failures_tolerated,
     );
+    if let Some((ping_policy, activity)) = adaptive {
+        health_checker = health_checker.adaptive(ping_policy, activity);
+    }
     rt.spawn(health_checker.start());
     (
         peer_mgr_reqs_rx,
@@ -74,10 +91,10 @@ fn setup_strict_health_checker(
 
 async fn expect_ping(
     network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
-) -> (Ping, oneshot::Sender<Result<Bytes, RpcError>>) {
+) -> (PeerId, Ping, oneshot::Sender<Result<Bytes, RpcError>>) {
     let req = network_reqs_rx.next().await.unwrap();
-    let rpc_req = match req {
-        PeerManagerRequest::SendRpc(_peer_id, rpc_req) => rpc_req,
+    let (peer_id, rpc_req) = match req {
+        PeerManagerRequest::SendRpc(peer_id, rpc_req, _) => (peer_id, rpc_req),
         _ => panic!("Unexpected PeerManagerRequest: {:?}", req),
     };
 
@@ -88,7 +105,7 @@ async fn expect_ping(
     assert_eq!(protocol, ProtocolId::HealthCheckerRpc,);
 
     match lcs::from_bytes(&req_data).unwrap() {
-        HealthCheckerMsg::Ping(ping) => (ping, res_tx),
+        HealthCheckerMsg::Ping(ping) => (peer_id, ping, res_tx),
         msg => panic!("Unexpected HealthCheckerMsg: {:?}", msg),
     }
 }
@@ -96,15 +113,15 @@ async fn expect_ping(
 async fn expect_ping_send_ok(
     network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
 ) {
-    let (ping, res_tx) = expect_ping(network_reqs_rx).await;
-    let res_data = lcs::to_bytes(&HealthCheckerMsg::Pong(Pong(ping.0))).unwrap();
+    let (_peer_id, ping, res_tx) = expect_ping(network_reqs_rx).await;
+    let res_data = lcs::to_bytes(&HealthCheckerMsg::Pong(Pong(ping.0, 0))).unwrap();
     res_tx.send(Ok(res_data.into())).unwrap();
 }
 
 async fn expect_ping_send_notok(
     network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
 ) {
-    let (_ping_msg, res_tx) = expect_ping(network_reqs_rx).await;
+    let (_peer_id, _ping_msg, res_tx) = expect_ping(network_reqs_rx).await;
     // This mock ping request must fail.
     res_tx.send(Err(RpcError::TimedOut)).unwrap();
 }
@@ -112,7 +129,7 @@ async fn expect_ping_send_notok(
 async fn expect_ping_timeout(
     network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
 ) {
-    let (_ping_msg, _res_tx) = expect_ping(network_reqs_rx).await;
+    let (_peer_id, _ping_msg, _res_tx) = expect_ping(network_reqs_rx).await;
     // Sleep for ping timeout plus a little bit.
     std::thread::sleep(PING_TIMEOUT + Duration::from_millis(100));
 }
@@ -137,7 +154,7 @@ async fn send_inbound_ping(
     network_notifs_tx
         .push_with_feedback(
             key,
-            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req),
+            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req, Encoding::Lcs),
             Some(delivered_tx),
         )
         .unwrap();
@@ -148,7 +165,11 @@ async fn send_inbound_ping(
 async fn expect_pong(res_rx: oneshot::Receiver<Result<Bytes, RpcError>>) {
     let res_data = res_rx.await.unwrap().unwrap();
     match lcs::from_bytes(&res_data).unwrap() {
-        HealthCheckerMsg::Pong(_) => {}
+        HealthCheckerMsg::Pong(pong) => {
+            // The pong carries the time of the peer.
+            let now = duration_since_epoch().as_micros() as u64;
+            assert!(pong.1 <= now && now - pong.1 < 60_000_000);
+        }
         msg => panic!("Unexpected HealthCheckerMsg: {:?}", msg),
     };
 }
@@ -177,6 +198,7 @@ async fn send_new_peer_notification(
             peer_manager::ConnectionNotification::NewPeer(
                 peer_id,
                 NetworkAddress::from_str("/ip6/::1/tcp/8081").unwrap(),
+                peer_manager::ConnectionInfo::mock(),
             ),
             Some(delivered_tx),
         )
@@ -358,3 +380,89 @@ fn ping_timeout() {
     };
     rt.block_on(events_f);
 }
+
+#[test]
+fn adaptive_pings_skip_active_peers() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let activity = PeerActivity::new();
+    let ping_policy = PingPolicy {
+        activity_window: Duration::from_secs(3600),
+        backoff_factor: 1,
+        max_backoff_rounds: 1,
+    };
+    let (mut network_reqs_rx, _, _, mut connection_notifs_tx, mut ticker_tx) =
+        setup_health_checker(&mut rt, 0, Some((ping_policy, activity.clone())));
+
+    let events_f = async move {
+        let active_peer_id = PeerId::random();
+        let idle_peer_id = PeerId::random();
+        send_new_peer_notification(active_peer_id, &mut connection_notifs_tx).await;
+        send_new_peer_notification(idle_peer_id, &mut connection_notifs_tx).await;
+        activity.record(active_peer_id);
+
+        // Only the idle peer is pinged, every round as its backoff is a single round.
+        for _ in 0..5 {
+            ticker_tx.send(()).await.unwrap();
+            let (peer_id, ping, res_tx) = expect_ping(&mut network_reqs_rx).await;
+            assert_eq!(peer_id, idle_peer_id);
+            let res_data = lcs::to_bytes(&HealthCheckerMsg::Pong(Pong(ping.0, 0))).unwrap();
+            res_tx.send(Ok(res_data.into())).unwrap();
+        }
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn ping_policy_backoff() {
+    let ping_policy = PingPolicy::default();
+    assert_eq!(ping_policy.backoff_rounds(None), 2);
+    assert_eq!(ping_policy.backoff_rounds(Some(2)), 4);
+    assert_eq!(ping_policy.backoff_rounds(Some(8)), 16);
+    assert_eq!(ping_policy.backoff_rounds(Some(16)), 16);
+
+    // Idle peers are pinged every round at most.
+    let ping_policy = PingPolicy {
+        backoff_factor: 0,
+        ..PingPolicy::default()
+    };
+    assert_eq!(ping_policy.backoff_rounds(Some(4)), 1);
+}
+
+#[test]
+fn rtt_stats() {
+    let rtts = PeerRtts::new();
+    let peer_id = PeerId::random();
+    assert_eq!(rtts.get(&peer_id), None);
+
+    let stats = rtts.sample(peer_id, Duration::from_millis(80));
+    assert_eq!(
+        stats,
+        RttStats {
+            last: Duration::from_millis(80),
+            min: Duration::from_millis(80),
+            smoothed: Duration::from_millis(80),
+            variation: Duration::from_millis(40),
+            samples: 1,
+        }
+    );
+    assert_eq!(stats.timeout(), Duration::from_millis(240));
+
+    // The smoothed round-trip time moves an eighth of the way to the new sample, and its variation
+    // a quarter of the way to the deviation from it.
+    let stats = rtts.sample(peer_id, Duration::from_millis(40));
+    assert_eq!(
+        stats,
+        RttStats {
+            last: Duration::from_millis(40),
+            min: Duration::from_millis(40),
+            smoothed: Duration::from_millis(75),
+            variation: Duration::from_millis(40),
+            samples: 2,
+        }
+    );
+    assert_eq!(rtts.clone().snapshot(), vec![(peer_id, stats)]);
+
+    rtts.remove(&peer_id);
+    assert!(rtts.snapshot().is_empty());
+}
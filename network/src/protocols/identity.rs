@@ -3,10 +3,11 @@
 
 //! Protocol used to exchange supported protocol information with a remote.
 
-use crate::protocols::wire::handshake::v1::HandshakeMsg;
+use crate::protocols::wire::handshake::v1::{HandshakeExtensions, HandshakeMsg};
 use bytes::BytesMut;
 use futures::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use netcore::framing::{read_u16frame, write_u16frame};
+use serde::{de::DeserializeOwned, Serialize};
 use std::io;
 
 /// The Handshake exchange protocol.
@@ -17,39 +18,63 @@ pub async fn exchange_handshake<T>(
 where
     T: AsyncRead + AsyncWrite + Unpin,
 {
-    // Send serialized handshake message to remote peer.
-    let msg = lcs::to_bytes(own_handshake).map_err(|e| {
+    exchange_msg(own_handshake, socket, "identity msg").await
+}
+
+/// The exchange of the handshake extensions, following the Handshake exchange on the sessions
+/// whose messaging protocol version has extensions.
+pub async fn exchange_extensions<T>(
+    own_extensions: &HandshakeExtensions,
+    socket: &mut T,
+) -> io::Result<HandshakeExtensions>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    exchange_msg(own_extensions, socket, "handshake extensions").await
+}
+
+/// Sends `own_msg` to the remote as a length-prefixed frame, and reads the one of the remote.
+async fn exchange_msg<M, T>(own_msg: &M, socket: &mut T, name: &str) -> io::Result<M>
+where
+    M: Serialize + DeserializeOwned,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    // Send serialized message to remote peer.
+    let msg = lcs::to_bytes(own_msg).map_err(|e| {
         io::Error::new(
             io::ErrorKind::InvalidData,
-            format!("Failed to serialize identity msg: {}", e),
+            format!("Failed to serialize {}: {}", name, e),
         )
     })?;
     write_u16frame(socket, &msg).await?;
     socket.flush().await?;
 
-    // Read handshake message from the Remote
+    // Read message from the Remote
     let mut response = BytesMut::new();
     read_u16frame(socket, &mut response).await?;
-    let identity = lcs::from_bytes(&response).map_err(|e| {
+    let remote_msg = lcs::from_bytes(&response).map_err(|e| {
         io::Error::new(
             io::ErrorKind::InvalidData,
-            format!("Failed to parse identity msg: {}", e),
+            format!("Failed to parse {}: {}", name, e),
         )
     })?;
-    Ok(identity)
+    Ok(remote_msg)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         protocols::{
-            identity::exchange_handshake,
-            wire::handshake::v1::{HandshakeMsg, MessagingProtocolVersion},
+            identity::{exchange_extensions, exchange_handshake},
+            wire::handshake::v1::{
+                CompressionCodec, HandshakeExtensions, HandshakeMsg, MessagingProtocolVersion,
+            },
         },
         ProtocolId,
     };
     use futures::{executor::block_on, future::join};
     use libra_config::network_id::NetworkId;
+    use libra_network_address::NetworkAddress;
     use memsocket::MemorySocket;
 
     fn build_test_connection() -> (MemorySocket, MemorySocket) {
@@ -107,4 +132,43 @@ mod tests {
 
         block_on(join(server, client));
     }
+
+    #[test]
+    fn extensions_exchange() {
+        let (mut outbound, mut inbound) = build_test_connection();
+
+        let mut server_extensions =
+            HandshakeExtensions::new(&[ProtocolId::ConsensusRpc].iter().into());
+        server_extensions.observed_addr = Some(NetworkAddress::mock());
+        let mut client_extensions = server_extensions.clone();
+        client_extensions.compression_codecs = [CompressionCodec::Lz4].iter().into();
+        client_extensions.observed_addr = None;
+
+        let server_extensions_clone = server_extensions.clone();
+        let client_extensions_clone = client_extensions.clone();
+
+        let server = async move {
+            let extensions = exchange_extensions(&server_extensions, &mut inbound)
+                .await
+                .expect("Extensions exchange fails");
+
+            assert_eq!(
+                lcs::to_bytes(&extensions).unwrap(),
+                lcs::to_bytes(&client_extensions_clone).unwrap()
+            );
+        };
+
+        let client = async move {
+            let extensions = exchange_extensions(&client_extensions, &mut outbound)
+                .await
+                .expect("Extensions exchange fails");
+
+            assert_eq!(
+                lcs::to_bytes(&extensions).unwrap(),
+                lcs::to_bytes(&server_extensions_clone).unwrap()
+            );
+        };
+
+        block_on(join(server, client));
+    }
 }
@@ -0,0 +1,428 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Protocol measuring the latency between the validators, e.g., to evaluate the leader schedule
+//! and the timeouts of consensus on real networks.
+//!
+//! The LatencyProber periodically probes every connected peer with a Probe carrying the time it
+//! was sent, at the priority of consensus, so that the probes are not queued behind bulk traffic.
+//! The peer replies with the time it received the Probe, from which the one-way latency to the
+//! peer is measured: the validators keep their clocks synchronized, as consensus requires, so that
+//! the measure is off by the skew of the clocks at most, see [`time_sync`](crate::time_sync). The
+//! jitter is the smoothed mean deviation of the successive one-way latencies, as in RFC 3550.
+//!
+//! The replies also carry the latencies the peer measured to its own peers, so that every node
+//! aggregates the latencies between all the pairs of validators in its [`LatencyMatrix`], which
+//! is exported as metrics.
+//!
+//! The protocol is opt-in, see
+//! [`NetworkBuilder::add_latency_measurement`](crate::validator_network::network_builder::NetworkBuilder::add_latency_measurement).
+
+use crate::{
+    catch_panic::catch_panic,
+    counters,
+    error::NetworkError,
+    peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
+    protocols::{
+        network::{Event, NetworkEvents, NetworkSender},
+        rpc::error::RpcError,
+    },
+    validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
+    ProtocolId,
+};
+use bytes::Bytes;
+use channel::message_queues::QueueStyle;
+use futures::{
+    channel::oneshot,
+    stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
+};
+use libra_logger::prelude::*;
+use libra_types::PeerId;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod test;
+
+const ACTOR: &str = "latency_prober";
+
+/// Weight of a new sample in the smoothed jitter, as in RFC 3550.
+const JITTER_GAIN: u64 = 16;
+
+pub type LatencyNetworkEvents = NetworkEvents<LatencyMsg>;
+
+/// The interface from the LatencyProber to the network layer.
+#[derive(Clone)]
+pub struct LatencyNetworkSender {
+    inner: NetworkSender<LatencyMsg>,
+}
+
+pub fn add_to_network(
+    network: &mut NetworkBuilder,
+) -> (LatencyNetworkSender, LatencyNetworkEvents) {
+    let (sender, receiver, connection_reqs_tx, connection_notifs_rx) = network
+        .add_protocol_handler(
+            vec![ProtocolId::LatencyRpc],
+            vec![],
+            ProtocolPriority::High,
+            QueueStyle::LIFO,
+            NETWORK_CHANNEL_SIZE,
+            Some(&counters::PENDING_LATENCY_NETWORK_EVENTS),
+        );
+    (
+        LatencyNetworkSender::new(sender, connection_reqs_tx),
+        LatencyNetworkEvents::new(receiver, connection_notifs_rx),
+    )
+}
+
+impl LatencyNetworkSender {
+    pub fn new(
+        peer_mgr_reqs_tx: PeerManagerRequestSender,
+        connection_reqs_tx: ConnectionRequestSender,
+    ) -> Self {
+        Self {
+            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
+        }
+    }
+
+    /// Send a Probe to `recipient`, and wait for its Reply until `timeout`.
+    pub async fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        req_msg: LatencyMsg,
+        timeout: Duration,
+    ) -> Result<LatencyMsg, NetworkError> {
+        self.inner
+            .send_rpc(recipient, ProtocolId::LatencyRpc, req_msg, timeout)
+            .await
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum LatencyMsg {
+    Probe(Probe),
+    Reply(Reply),
+}
+
+/// A nonce, and the time the Probe was sent, in microseconds since the Unix epoch.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Probe {
+    pub nonce: u32,
+    pub sent_at_usecs: u64,
+}
+
+/// The nonce of the Probe, the time the replying peer received it, in microseconds since the Unix
+/// epoch, and the latencies the replying peer measured to its own peers.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Reply {
+    pub nonce: u32,
+    pub received_at_usecs: u64,
+    pub latencies: Vec<(PeerId, LatencyStats)>,
+}
+
+/// The latency measured from a peer to another.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LatencyStats {
+    /// One-way latency of the last Probe, in microseconds.
+    pub one_way_usecs: u64,
+    /// Smoothed mean deviation of the successive one-way latencies, in microseconds.
+    pub jitter_usecs: u64,
+}
+
+impl LatencyStats {
+    /// The stats after a new sample of the one-way latency.
+    fn sample(prev: Option<LatencyStats>, one_way_usecs: u64) -> Self {
+        let jitter_usecs = match prev {
+            None => 0,
+            Some(prev) => {
+                let deviation = if one_way_usecs > prev.one_way_usecs {
+                    one_way_usecs - prev.one_way_usecs
+                } else {
+                    prev.one_way_usecs - one_way_usecs
+                };
+                (prev.jitter_usecs * (JITTER_GAIN - 1) + deviation) / JITTER_GAIN
+            }
+        };
+        Self {
+            one_way_usecs,
+            jitter_usecs,
+        }
+    }
+}
+
+/// The latencies between the pairs of peers known to this node, keyed by the peer which measured
+/// them. Cloning it returns a handle to the same matrix.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyMatrix {
+    inner: Arc<RwLock<HashMap<PeerId, HashMap<PeerId, LatencyStats>>>>,
+}
+
+impl LatencyMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The latency measured from `from` to `to`, if any.
+    pub fn get(&self, from: &PeerId, to: &PeerId) -> Option<LatencyStats> {
+        self.inner
+            .read()
+            .unwrap()
+            .get(from)
+            .and_then(|row| row.get(to))
+            .copied()
+    }
+
+    /// The latencies measured by `from`.
+    pub fn row(&self, from: &PeerId) -> HashMap<PeerId, LatencyStats> {
+        self.inner
+            .read()
+            .unwrap()
+            .get(from)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns all the latencies of the matrix, ordered by the peers measuring and measured.
+    pub fn snapshot(&self) -> Vec<(PeerId, PeerId, LatencyStats)> {
+        let mut latencies: Vec<_> = self
+            .inner
+            .read()
+            .unwrap()
+            .iter()
+            .flat_map(|(from, row)| row.iter().map(move |(to, stats)| (*from, *to, *stats)))
+            .collect();
+        latencies.sort_by_key(|(from, to, _)| (*from, *to));
+        latencies
+    }
+
+    /// Records a new sample of the one-way latency from `from` to `to`, and returns the stats.
+    fn sample(&self, from: PeerId, to: PeerId, one_way_usecs: u64) -> LatencyStats {
+        let mut inner = self.inner.write().unwrap();
+        let row = inner.entry(from).or_default();
+        let stats = LatencyStats::sample(row.get(&to).copied(), one_way_usecs);
+        row.insert(to, stats);
+        stats
+    }
+
+    /// Replaces the latencies measured by `from`.
+    fn set_row(&self, from: PeerId, row: HashMap<PeerId, LatencyStats>) {
+        self.inner.write().unwrap().insert(from, row);
+    }
+
+    /// Forgets the latency from `from` to `to`.
+    fn remove(&self, from: &PeerId, to: &PeerId) {
+        if let Some(row) = self.inner.write().unwrap().get_mut(from) {
+            row.remove(to);
+        }
+    }
+}
+
+/// The actor measuring the latencies to the connected peers by running the Probe protocol.
+pub struct LatencyProber<TTicker> {
+    /// PeerId of this node, whose row of the matrix the prober measures.
+    self_peer_id: PeerId,
+    /// Ticker to trigger the probes of all the connected peers.
+    ticker: TTicker,
+    network_tx: LatencyNetworkSender,
+    network_rx: LatencyNetworkEvents,
+    connected: HashSet<PeerId>,
+    matrix: LatencyMatrix,
+    probe_timeout: Duration,
+    rng: SmallRng,
+}
+
+impl<TTicker> LatencyProber<TTicker>
+where
+    TTicker: Stream + FusedStream + Unpin,
+{
+    /// Create new instance of the [`LatencyProber`] actor, recording the latencies in `matrix`.
+    pub fn new(
+        self_peer_id: PeerId,
+        ticker: TTicker,
+        network_tx: LatencyNetworkSender,
+        network_rx: LatencyNetworkEvents,
+        matrix: LatencyMatrix,
+        probe_timeout: Duration,
+    ) -> Self {
+        Self {
+            self_peer_id,
+            ticker,
+            network_tx,
+            network_rx,
+            connected: HashSet::new(),
+            matrix,
+            probe_timeout,
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        let mut probes = FuturesUnordered::new();
+        loop {
+            let next_event = async {
+                futures::select! {
+                    event = self.network_rx.select_next_some() => {
+                        match event {
+                            Ok(Event::NewPeer(peer_id)) => {
+                                self.connected.insert(peer_id);
+                            }
+                            Ok(Event::LostPeer(peer_id)) => {
+                                self.connected.remove(&peer_id);
+                                self.matrix.remove(&self.self_peer_id, &peer_id);
+                            }
+                            Ok(Event::RpcRequest((peer_id, LatencyMsg::Probe(probe), res_tx))) => {
+                                self.handle_probe(peer_id, probe, res_tx);
+                            }
+                            Ok(event) => {
+                                warn!("Unexpected latency network event: {:?}", event);
+                            }
+                            Err(err) => {
+                                warn!("Latency network error: {:?}", err);
+                            }
+                        }
+                    }
+                    _ = self.ticker.select_next_some() => {
+                        for peer_id in &self.connected {
+                            let nonce = self.rng.gen::<u32>();
+                            probes.push(Self::probe_peer(
+                                self.network_tx.clone(),
+                                *peer_id,
+                                nonce,
+                                self.probe_timeout,
+                            ));
+                        }
+                    }
+                    res = probes.select_next_some() => {
+                        let (peer_id, nonce, sent_at_usecs, reply) = res;
+                        self.handle_reply(peer_id, nonce, sent_at_usecs, reply);
+                    }
+                    complete => return false,
+                }
+                true
+            };
+            match catch_panic(ACTOR, next_event).await {
+                // The matrix is only updated atomically, so there is no state to restore on panics.
+                Ok(true) | Err(_) => {}
+                Ok(false) => break,
+            }
+        }
+        crit!("Latency prober actor terminated");
+    }
+
+    fn handle_probe(
+        &self,
+        peer_id: PeerId,
+        probe: Probe,
+        res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+    ) {
+        let reply = LatencyMsg::Reply(Reply {
+            nonce: probe.nonce,
+            received_at_usecs: now_usecs(),
+            latencies: self.matrix.row(&self.self_peer_id).into_iter().collect(),
+        });
+        match lcs::to_bytes(&reply) {
+            Ok(reply) => {
+                let _ = res_tx.send(Ok(reply.into()));
+            }
+            Err(err) => warn!(
+                "Unable to serialize the latency reply to peer {}: {}",
+                peer_id.short_str(),
+                err
+            ),
+        }
+    }
+
+    fn handle_reply(
+        &self,
+        peer_id: PeerId,
+        nonce: u32,
+        sent_at_usecs: u64,
+        reply: Result<Reply, NetworkError>,
+    ) {
+        let reply = match reply {
+            Ok(reply) if reply.nonce == nonce => reply,
+            Ok(reply) => {
+                warn!(
+                    "Latency reply nonce {} of peer {} doesn't match the probe nonce {}",
+                    reply.nonce,
+                    peer_id.short_str(),
+                    nonce
+                );
+                return;
+            }
+            Err(err) => {
+                debug!(
+                    "Latency probe of peer {} failed: {:?}",
+                    peer_id.short_str(),
+                    err
+                );
+                return;
+            }
+        };
+        // A peer whose clock is behind ours may seem to receive the probe before it was sent.
+        let one_way_usecs = reply.received_at_usecs.saturating_sub(sent_at_usecs);
+        let stats = self
+            .matrix
+            .sample(self.self_peer_id, peer_id, one_way_usecs);
+        update_metrics(&self.self_peer_id, &peer_id, stats);
+
+        let row: HashMap<_, _> = reply
+            .latencies
+            .into_iter()
+            .filter(|(to, _)| *to != peer_id)
+            .collect();
+        for (to, stats) in &row {
+            update_metrics(&peer_id, to, *stats);
+        }
+        self.matrix.set_row(peer_id, row);
+    }
+
+    async fn probe_peer(
+        mut network_tx: LatencyNetworkSender,
+        peer_id: PeerId,
+        nonce: u32,
+        probe_timeout: Duration,
+    ) -> (PeerId, u32, u64, Result<Reply, NetworkError>) {
+        let sent_at_usecs = now_usecs();
+        let probe = LatencyMsg::Probe(Probe {
+            nonce,
+            sent_at_usecs,
+        });
+        let reply = network_tx
+            .send_rpc(peer_id, probe, probe_timeout)
+            .await
+            .and_then(|msg| match msg {
+                LatencyMsg::Reply(reply) => Ok(reply),
+                _ => Err(RpcError::InvalidRpcResponse.into()),
+            });
+        (peer_id, nonce, sent_at_usecs, reply)
+    }
+}
+
+fn update_metrics(from: &PeerId, to: &PeerId, stats: LatencyStats) {
+    let from = from.short_str();
+    let to = to.short_str();
+    let labels = [
+        counters::LIBRA_NETWORK_LATENCY_MATRIX_PEERS.label(&from),
+        counters::LIBRA_NETWORK_LATENCY_MATRIX_PEERS.label(&to),
+    ];
+    counters::LIBRA_NETWORK_LATENCY_MATRIX_USECS
+        .with_label_values(&[labels[0], labels[1], "one_way"])
+        .set(stats.one_way_usecs as i64);
+    counters::LIBRA_NETWORK_LATENCY_MATRIX_USECS
+        .with_label_values(&[labels[0], labels[1], "jitter"])
+        .set(stats.jitter_usecs as i64);
+}
+
+fn now_usecs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
@@ -0,0 +1,180 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    peer_manager::{self, conn_notifs_channel, PeerManagerNotification, PeerManagerRequest},
+    protocols::{rpc::InboundRpcRequest, wire::handshake::v1::Encoding},
+};
+use channel::libra_channel;
+use futures::sink::SinkExt;
+use libra_network_address::NetworkAddress;
+use std::{num::NonZeroUsize, str::FromStr};
+use tokio::runtime::Runtime;
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn setup_latency_prober(
+    rt: &mut Runtime,
+    self_peer_id: PeerId,
+    matrix: LatencyMatrix,
+) -> (
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    conn_notifs_channel::Sender,
+    channel::Sender<()>,
+) {
+    let (ticker_tx, ticker_rx) = channel::new_test(0);
+    let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (connection_reqs_tx, _connection_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (network_notifs_tx, network_notifs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
+
+    let latency_prober = LatencyProber::new(
+        self_peer_id,
+        ticker_rx,
+        LatencyNetworkSender::new(
+            PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+            ConnectionRequestSender::new(connection_reqs_tx),
+        ),
+        LatencyNetworkEvents::new(network_notifs_rx, connection_notifs_rx),
+        matrix,
+        PROBE_TIMEOUT,
+    );
+    rt.spawn(latency_prober.start());
+    (
+        peer_mgr_reqs_rx,
+        network_notifs_tx,
+        connection_notifs_tx,
+        ticker_tx,
+    )
+}
+
+async fn send_new_peer_notification(
+    peer_id: PeerId,
+    connection_notifs_tx: &mut conn_notifs_channel::Sender,
+) {
+    let (delivered_tx, delivered_rx) = oneshot::channel();
+    connection_notifs_tx
+        .push_with_feedback(
+            peer_id,
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_id,
+                NetworkAddress::from_str("/ip6/::1/tcp/8081").unwrap(),
+                peer_manager::ConnectionInfo::mock(),
+            ),
+            Some(delivered_tx),
+        )
+        .unwrap();
+    delivered_rx.await.unwrap();
+}
+
+/// Waits for a Probe to `expected_peer_id`, and replies that it was received `one_way_usecs`
+/// after it was sent, along with `latencies`.
+async fn expect_probe_send_reply(
+    network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+    one_way_usecs: u64,
+    latencies: Vec<(PeerId, LatencyStats)>,
+) {
+    let (peer_id, rpc_req) = match network_reqs_rx.next().await.unwrap() {
+        PeerManagerRequest::SendRpc(peer_id, rpc_req, _) => (peer_id, rpc_req),
+        req => panic!("Unexpected PeerManagerRequest: {:?}", req),
+    };
+    assert_eq!(peer_id, expected_peer_id);
+    assert_eq!(rpc_req.protocol, ProtocolId::LatencyRpc);
+    let probe = match lcs::from_bytes(&rpc_req.data).unwrap() {
+        LatencyMsg::Probe(probe) => probe,
+        msg => panic!("Unexpected LatencyMsg: {:?}", msg),
+    };
+    let reply = LatencyMsg::Reply(Reply {
+        nonce: probe.nonce,
+        received_at_usecs: probe.sent_at_usecs + one_way_usecs,
+        latencies,
+    });
+    rpc_req
+        .res_tx
+        .send(Ok(lcs::to_bytes(&reply).unwrap().into()))
+        .unwrap();
+}
+
+async fn send_inbound_probe(
+    peer_id: PeerId,
+    network_notifs_tx: &mut libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+) -> Reply {
+    let probe = LatencyMsg::Probe(Probe {
+        nonce: 7,
+        sent_at_usecs: now_usecs(),
+    });
+    let (res_tx, res_rx) = oneshot::channel();
+    let inbound_rpc_req = InboundRpcRequest {
+        protocol: ProtocolId::LatencyRpc,
+        data: lcs::to_bytes(&probe).unwrap().into(),
+        res_tx,
+    };
+    network_notifs_tx
+        .push(
+            (peer_id, ProtocolId::LatencyRpc),
+            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req, Encoding::Lcs),
+        )
+        .unwrap();
+    match lcs::from_bytes(&res_rx.await.unwrap().unwrap()).unwrap() {
+        LatencyMsg::Reply(reply) => {
+            assert_eq!(reply.nonce, 7);
+            reply
+        }
+        msg => panic!("Unexpected LatencyMsg: {:?}", msg),
+    }
+}
+
+#[test]
+fn jitter() {
+    let stats = LatencyStats::sample(None, 1_000);
+    assert_eq!(stats.jitter_usecs, 0);
+    let stats = LatencyStats::sample(Some(stats), 2_600);
+    assert_eq!(stats.one_way_usecs, 2_600);
+    assert_eq!(stats.jitter_usecs, 100);
+    let stats = LatencyStats::sample(Some(stats), 1_000);
+    assert_eq!(stats.jitter_usecs, (100 * 15 + 1_600) / 16);
+}
+
+#[test]
+fn probe_and_aggregate() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let (self_peer_id, peer_a, peer_b) = (PeerId::random(), PeerId::random(), PeerId::random());
+    let matrix = LatencyMatrix::new();
+    let (mut network_reqs_rx, mut network_notifs_tx, mut connection_notifs_tx, mut ticker_tx) =
+        setup_latency_prober(&mut rt, self_peer_id, matrix.clone());
+
+    let events_f = async move {
+        send_new_peer_notification(peer_a, &mut connection_notifs_tx).await;
+        ticker_tx.send(()).await.unwrap();
+
+        // The peer reports its own latency to another validator along with its reply.
+        let a_to_b = LatencyStats {
+            one_way_usecs: 3_000,
+            jitter_usecs: 40,
+        };
+        expect_probe_send_reply(&mut network_reqs_rx, peer_a, 5_000, vec![(peer_b, a_to_b)]).await;
+        // The row of the peer is recorded last.
+        while matrix.get(&peer_a, &peer_b).is_none() {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        let self_to_a = LatencyStats {
+            one_way_usecs: 5_000,
+            jitter_usecs: 0,
+        };
+        let mut expected = vec![(self_peer_id, peer_a, self_to_a), (peer_a, peer_b, a_to_b)];
+        expected.sort_by_key(|(from, to, _)| (*from, *to));
+        assert_eq!(matrix.snapshot(), expected);
+
+        // The latencies measured by this node are shared with the peers probing it.
+        let reply = send_inbound_probe(peer_b, &mut network_notifs_tx).await;
+        assert_eq!(reply.latencies, vec![(peer_a, self_to_a)]);
+    };
+    rt.block_on(events_f);
+}
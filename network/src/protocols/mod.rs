@@ -8,7 +8,11 @@ pub mod direct_send;
 pub mod network;
 pub mod rpc;
 
+pub mod dht;
+pub mod dial_back;
 pub mod discovery;
 pub mod health_checker;
 pub mod identity;
+pub mod latency;
+pub mod pex;
 pub mod wire;
@@ -0,0 +1,176 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Forward-compatible decoding of the messages of the protocols, so that the nodes keep talking to
+//! each other while a rolling upgrade extends the messages of a protocol.
+//!
+//! The messages of a protocol are decoded [`Strict`](Decoding::Strict)ly by default: a message
+//! which is not exactly an instance of the local message type fails to decode. A protocol opts in
+//! to [`ForwardCompatible`](Decoding::ForwardCompatible) decoding in
+//! [`ProtocolId::decoding`](crate::ProtocolId::decoding), if losing the parts of its messages
+//! unknown to the local version is semantically safe, i.e., if its messages are best effort:
+//! - a message of a top-level enum variant unknown to the local message type, i.e., added by a
+//!   newer version, is ignored altogether, as if it were lost in transit, and an RPC request of an
+//!   unknown variant is answered with an error;
+//! - the bytes following a message, i.e., fields appended to it by a newer version, are skipped,
+//!   up to a bound, so that a peer cannot make us buffer arbitrary padding.
+//!
+//! Only the top level of a message is forward compatible: the enum variants unknown to the local
+//! version nested in a message still fail its decoding.
+
+use serde::de::{self, DeserializeOwned, Visitor};
+use std::fmt;
+
+/// Maximum number of bytes following a message skipped by the protocols decoded
+/// [`ForwardCompatible`](Decoding::ForwardCompatible)ly.
+pub const MAX_TRAILING_BYTES: usize = 4096;
+
+/// How the messages of a protocol are decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decoding {
+    /// The messages must be instances of the local message type exactly.
+    Strict,
+    /// The messages of top-level enum variants unknown to the local message type are ignored, and
+    /// at most `max_trailing_bytes` bytes following a message are skipped.
+    ForwardCompatible { max_trailing_bytes: usize },
+}
+
+/// A message decoded from the network.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Decoded<TMessage> {
+    Message(TMessage),
+    /// A message of the top-level enum variant of the given index, unknown to the local version of
+    /// the message type, to be ignored.
+    UnknownVariant(u32),
+}
+
+impl Decoding {
+    pub fn decode<TMessage: DeserializeOwned>(
+        self,
+        data: &[u8],
+    ) -> Result<Decoded<TMessage>, lcs::Error> {
+        let max_trailing_bytes = match self {
+            Decoding::Strict => return lcs::from_bytes(data).map(Decoded::Message),
+            Decoding::ForwardCompatible { max_trailing_bytes } => max_trailing_bytes,
+        };
+        match lcs::from_bytes_prefix(data) {
+            Ok((message, rest)) if rest.len() <= max_trailing_bytes => {
+                Ok(Decoded::Message(message))
+            }
+            Ok(_) => Err(lcs::Error::RemainingInput),
+            Err(err) => match (num_variants::<TMessage>(), lcs::peek_variant_index(data)) {
+                (Some(num_variants), Ok(index)) if index as usize >= num_variants => {
+                    Ok(Decoded::UnknownVariant(index))
+                }
+                _ => Err(err),
+            },
+        }
+    }
+}
+
+/// The number of variants of `T` if it is an enum, as declared to its `Deserializer`.
+fn num_variants<T: DeserializeOwned>() -> Option<usize> {
+    match T::deserialize(VariantsProbe) {
+        Err(ProbeError(num_variants)) => num_variants,
+        Ok(_) => None,
+    }
+}
+
+/// A `Deserializer` failing with the number of variants of the enums, and without it otherwise.
+struct VariantsProbe;
+
+#[derive(Debug)]
+struct ProbeError(Option<usize>);
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        ProbeError(None)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for VariantsProbe {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, ProbeError> {
+        Err(ProbeError(None))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, ProbeError> {
+        Err(ProbeError(Some(variants.len())))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    enum OldMsg {
+        Ping(u32),
+        Pong(u32),
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    enum NewMsg {
+        Ping(u32, u64),
+        Pong(u32),
+        Nack,
+    }
+
+    const FORWARD_COMPATIBLE: Decoding = Decoding::ForwardCompatible {
+        max_trailing_bytes: 8,
+    };
+
+    #[test]
+    fn decode() {
+        assert_eq!(num_variants::<OldMsg>(), Some(2));
+        assert_eq!(num_variants::<u64>(), None);
+
+        let ping = lcs::to_bytes(&NewMsg::Ping(1, 2)).unwrap();
+        assert!(Decoding::Strict.decode::<OldMsg>(&ping).is_err());
+        assert_eq!(
+            FORWARD_COMPATIBLE.decode(&ping),
+            Ok(Decoded::Message(OldMsg::Ping(1)))
+        );
+
+        let nack = lcs::to_bytes(&NewMsg::Nack).unwrap();
+        assert!(Decoding::Strict.decode::<OldMsg>(&nack).is_err());
+        assert_eq!(
+            FORWARD_COMPATIBLE.decode::<OldMsg>(&nack),
+            Ok(Decoded::UnknownVariant(2))
+        );
+
+        // known variants which fail to decode, and oversized trailing bytes, are still errors
+        let pong = lcs::to_bytes(&NewMsg::Pong(3)).unwrap();
+        assert!(FORWARD_COMPATIBLE.decode::<OldMsg>(&pong[..2]).is_err());
+        let mut padded = pong.clone();
+        padded.extend_from_slice(&[0; 9]);
+        assert_eq!(
+            FORWARD_COMPATIBLE.decode::<OldMsg>(&padded),
+            Err(lcs::Error::RemainingInput)
+        );
+        assert_eq!(
+            FORWARD_COMPATIBLE.decode(&padded[..pong.len() + 8]),
+            Ok(Decoded::Message(OldMsg::Pong(3)))
+        );
+    }
+}
@@ -6,10 +6,8 @@
 use crate::{
     error::NetworkError,
     peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
-    protocols::{
-        network::{Event, NetworkEvents, NetworkSender},
-        rpc::error::RpcError,
-    },
+    priority::ProtocolPriority,
+    protocols::network::{Event, NetworkEvents, NetworkSender},
     validator_network::network_builder::{
         AuthenticationMode, NetworkBuilder, NETWORK_CHANNEL_SIZE,
     },
@@ -37,6 +35,7 @@ fn add_to_network(network: &mut NetworkBuilder) -> (DummyNetworkSender, DummyNet
         .add_protocol_handler(
             vec![TEST_RPC_PROTOCOL],
             vec![TEST_DIRECT_SEND_PROTOCOL],
+            ProtocolPriority::Normal,
             QueueStyle::LIFO,
             NETWORK_CHANNEL_SIZE,
             None,
@@ -76,7 +75,7 @@ impl DummyNetworkSender {
         recipient: PeerId,
         message: DummyMsg,
         timeout: Duration,
-    ) -> Result<DummyMsg, RpcError> {
+    ) -> Result<DummyMsg, NetworkError> {
         let protocol = TEST_RPC_PROTOCOL;
         self.inner
             .send_rpc(recipient, protocol, message, timeout)
@@ -138,14 +137,14 @@ pub fn setup_network() -> DummyNetwork {
         network_id.clone(),
         listener_peer_id,
         RoleType::Validator,
-        listener_addr,
+        vec![listener_addr],
     );
     network_builder
         .authentication_mode(AuthenticationMode::Mutual(listener_identity_private_key))
         .trusted_peers(trusted_peers.clone())
         .add_connectivity_manager();
     let (listener_sender, mut listener_events) = add_to_network(&mut network_builder);
-    let listener_addr = network_builder.build();
+    let listener_addr = network_builder.build().unwrap().listen_addrs()[0].clone();
 
     // Set up the dialer network
     let mut network_builder = NetworkBuilder::new(
@@ -153,7 +152,7 @@ pub fn setup_network() -> DummyNetwork {
         network_id,
         dialer_peer_id,
         RoleType::Validator,
-        dialer_addr,
+        vec![dialer_addr],
     );
     network_builder
         .authentication_mode(AuthenticationMode::Mutual(dialer_identity_private_key))
@@ -166,7 +165,7 @@ pub fn setup_network() -> DummyNetwork {
         )
         .add_connectivity_manager();
     let (dialer_sender, mut dialer_events) = add_to_network(&mut network_builder);
-    let _dialer_addr = network_builder.build();
+    let _dialer_handle = network_builder.build().unwrap();
 
     // Wait for establishing connection
     let first_dialer_event = block_on(dialer_events.next()).unwrap().unwrap();
@@ -5,26 +5,37 @@
 
 pub use crate::protocols::rpc::error::RpcError;
 use crate::{
+    counters,
     error::NetworkError,
     peer_manager::{
         ConnectionNotification, ConnectionRequestSender, PeerManagerNotification,
         PeerManagerRequestSender,
     },
+    protocols::wire::handshake::v1::Encoding,
     ProtocolId,
 };
-use bytes::Bytes;
+use anyhow::anyhow;
 use channel::libra_channel;
+use decoding::Decoded;
 use futures::{
-    channel::oneshot,
-    stream::{FusedStream, Map, Select, Stream, StreamExt},
+    future::{self, BoxFuture},
+    stream::{FilterMap, FusedStream, Select, Stream, StreamExt},
     task::{Context, Poll},
 };
+use libra_logger::prelude::*;
 use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
 use pin_project::pin_project;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{marker::PhantomData, pin::Pin, time::Duration};
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
+pub mod decoding;
 #[cfg(any(feature = "testing", test))]
 pub mod dummy;
 #[cfg(test)]
@@ -42,54 +53,32 @@ impl<T: DeserializeOwned + Serialize> Message for T {}
 /// messages and RPCs that are received by that consumer.
 ///
 /// [`NetworkNotification`]: crate::interface::NetworkNotification
-#[derive(Debug)]
-pub enum Event<TMessage> {
-    /// New inbound direct-send message from peer.
-    Message((PeerId, TMessage)),
-    /// New inbound rpc request. The request is fulfilled by sending the
-    /// serialized response `Bytes` over the `oneshot::Sender`, where the network
-    /// layer will handle sending the response over-the-wire.
-    RpcRequest((PeerId, TMessage, oneshot::Sender<Result<Bytes, RpcError>>)),
-    /// Peer which we have a newly established connection with.
-    NewPeer(PeerId),
-    /// Peer with which we've lost our connection.
-    LostPeer(PeerId),
-}
-
-/// impl PartialEq for simpler testing
-impl<TMessage: PartialEq> PartialEq for Event<TMessage> {
-    fn eq(&self, other: &Event<TMessage>) -> bool {
-        use Event::*;
-        match (self, other) {
-            (Message((pid1, msg1)), Message((pid2, msg2))) => pid1 == pid2 && msg1 == msg2,
-            // ignore oneshot::Sender in comparison
-            (RpcRequest((pid1, msg1, _)), RpcRequest((pid2, msg2, _))) => {
-                pid1 == pid2 && msg1 == msg2
-            }
-            (NewPeer(pid1), NewPeer(pid2)) => pid1 == pid2,
-            (LostPeer(pid1), LostPeer(pid2)) => pid1 == pid2,
-            _ => false,
-        }
-    }
-}
+pub type Event<TMessage> = network_api::Event<TMessage, RpcError>;
 
 /// A `Stream` of `Event<TMessage>` from the lower network layer to an upper
 /// network application that deserializes inbound network direct-send and rpc
 /// messages into `TMessage`.
 ///
 /// `NetworkEvents` is really just a thin wrapper around a
-/// `channel::Receiver<NetworkNotification>` that deserializes inbound messages.
+/// `channel::Receiver<NetworkNotification>` that deserializes inbound messages, as per the
+/// [`Decoding`](decoding::Decoding) of their protocols.
 #[pin_project]
 pub struct NetworkEvents<TMessage> {
     #[pin]
     event_stream: Select<
-        Map<
+        FilterMap<
             libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerNotification>,
-            fn(PeerManagerNotification) -> Result<Event<TMessage>, NetworkError>,
+            future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
+            fn(
+                PeerManagerNotification,
+            ) -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
         >,
-        Map<
+        FilterMap<
             libra_channel::Receiver<PeerId, ConnectionNotification>,
-            fn(ConnectionNotification) -> Result<Event<TMessage>, NetworkError>,
+            future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
+            fn(
+                ConnectionNotification,
+            ) -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
         >,
     >,
     _marker: PhantomData<TMessage>,
@@ -100,13 +89,19 @@ impl<TMessage: Message> NetworkEvents<TMessage> {
         peer_mgr_notifs_rx: libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerNotification>,
         connection_notifs_rx: libra_channel::Receiver<PeerId, ConnectionNotification>,
     ) -> Self {
-        let data_event_stream = peer_mgr_notifs_rx.map(
+        let data_event_stream = peer_mgr_notifs_rx.filter_map(
             peer_mgr_notif_to_event
-                as fn(PeerManagerNotification) -> Result<Event<TMessage>, NetworkError>,
+                as fn(
+                    PeerManagerNotification,
+                )
+                    -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
         );
-        let control_event_stream = connection_notifs_rx.map(
+        let control_event_stream = connection_notifs_rx.filter_map(
             control_msg_to_event
-                as fn(ConnectionNotification) -> Result<Event<TMessage>, NetworkError>,
+                as fn(
+                    ConnectionNotification,
+                )
+                    -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>>,
         );
         Self {
             event_stream: ::futures::stream::select(data_event_stream, control_event_stream),
@@ -127,28 +122,67 @@ impl<TMessage> Stream for NetworkEvents<TMessage> {
     }
 }
 
+/// The messages are decoded with the encoding negotiated on the connection they were received on,
+/// and the ones of variants unknown to `TMessage` are ignored, for the protocols decoded
+/// forward-compatibly.
 fn peer_mgr_notif_to_event<TMessage: Message>(
     notif: PeerManagerNotification,
-) -> Result<Event<TMessage>, NetworkError> {
-    match notif {
-        PeerManagerNotification::RecvRpc(peer_id, rpc_req) => {
-            let req_msg: TMessage = lcs::from_bytes(&rpc_req.data)?;
-            Ok(Event::RpcRequest((peer_id, req_msg, rpc_req.res_tx)))
+) -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>> {
+    future::ready(match notif {
+        PeerManagerNotification::RecvRpc(peer_id, rpc_req, encoding) => {
+            match encoding.decode(rpc_req.protocol.decoding(), &rpc_req.data) {
+                Ok(Decoded::Message(req_msg)) => {
+                    Some(Ok(Event::RpcRequest((peer_id, req_msg, rpc_req.res_tx))))
+                }
+                Ok(Decoded::UnknownVariant(index)) => {
+                    ignore_unknown_variant(peer_id, rpc_req.protocol, index);
+                    // The rpc layer has timed the request out if the send fails.
+                    let _ = rpc_req.res_tx.send(Err(RpcError::ApplicationError(anyhow!(
+                        "Unknown request variant {}",
+                        index
+                    ))));
+                    None
+                }
+                Err(err) => Some(Err(err.into())),
+            }
         }
-        PeerManagerNotification::RecvMessage(peer_id, msg) => {
-            let msg: TMessage = lcs::from_bytes(&msg.mdata)?;
-            Ok(Event::Message((peer_id, msg)))
+        PeerManagerNotification::RecvMessage(peer_id, msg, encoding) => {
+            match encoding.decode(msg.protocol.decoding(), &msg.mdata) {
+                Ok(Decoded::Message(msg)) => Some(Ok(Event::Message((peer_id, msg)))),
+                Ok(Decoded::UnknownVariant(index)) => {
+                    ignore_unknown_variant(peer_id, msg.protocol, index);
+                    None
+                }
+                Err(err) => Some(Err(err.into())),
+            }
         }
-    }
+    })
 }
 
+fn ignore_unknown_variant(peer_id: PeerId, protocol: ProtocolId, index: u32) {
+    counters::LIBRA_NETWORK_UNKNOWN_MESSAGES
+        .with_label_values(&[protocol.as_str()])
+        .inc();
+    debug!(
+        "Ignoring {} message of unknown variant {} from peer {}",
+        protocol,
+        index,
+        peer_id.short_str()
+    );
+}
+
+/// The updates of the trusted peers are only of interest to the connection event listeners of the
+/// network, not to its applications.
 fn control_msg_to_event<TMessage>(
     notif: ConnectionNotification,
-) -> Result<Event<TMessage>, NetworkError> {
-    match notif {
-        ConnectionNotification::NewPeer(peer_id, _addr) => Ok(Event::NewPeer(peer_id)),
-        ConnectionNotification::LostPeer(peer_id, _addr, _reason) => Ok(Event::LostPeer(peer_id)),
-    }
+) -> future::Ready<Option<Result<Event<TMessage>, NetworkError>>> {
+    future::ready(match notif {
+        ConnectionNotification::NewPeer(peer_id, _addr, _) => Some(Ok(Event::NewPeer(peer_id))),
+        ConnectionNotification::LostPeer(peer_id, _addr, _reason) => {
+            Some(Ok(Event::LostPeer(peer_id)))
+        }
+        ConnectionNotification::TrustedPeersUpdated(_) => None,
+    })
 }
 
 impl<TMessage> FusedStream for NetworkEvents<TMessage> {
@@ -157,6 +191,118 @@ impl<TMessage> FusedStream for NetworkEvents<TMessage> {
     }
 }
 
+impl<TMessage: Message> NetworkEvents<TMessage> {
+    /// Turns this stream into a `PeerContextEvents` stream, along with the `PeerContexts` the
+    /// application attaches its per-connection state to.
+    pub fn with_peer_contexts<C>(self) -> (PeerContextEvents<TMessage, C>, PeerContexts<C>) {
+        let contexts = PeerContexts::new();
+        let events = PeerContextEvents {
+            events: self,
+            contexts: contexts.clone(),
+        };
+        (events, contexts)
+    }
+}
+
+/// Application state attached to the connections with peers, shared between an application and
+/// its `PeerContextEvents` stream.
+///
+/// A context can only be attached to a connected peer, and is dropped as soon as the stream sees
+/// the connection go away: on `LostPeer`, or on the `NewPeer` of a new connection when the
+/// `LostPeer` of the previous one was coalesced by the connection notifications channel. Hence
+/// applications don't leak state for peers whose disconnection they missed.
+pub struct PeerContexts<C> {
+    // `None` for connected peers without context.
+    contexts: Arc<RwLock<HashMap<PeerId, Option<Arc<C>>>>>,
+}
+
+impl<C> Clone for PeerContexts<C> {
+    fn clone(&self) -> Self {
+        Self {
+            contexts: self.contexts.clone(),
+        }
+    }
+}
+
+impl<C> PeerContexts<C> {
+    fn new() -> Self {
+        Self {
+            contexts: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Attaches `context` to the current connection with `peer`, replacing the previous context
+    /// if any. Returns false, dropping `context`, if `peer` is not connected.
+    pub fn attach(&self, peer: PeerId, context: C) -> bool {
+        match self.contexts.write().unwrap().get_mut(&peer) {
+            Some(entry) => {
+                *entry = Some(Arc::new(context));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the context attached to the current connection with `peer`.
+    pub fn get(&self, peer: &PeerId) -> Option<Arc<C>> {
+        self.contexts.read().unwrap().get(peer).cloned().flatten()
+    }
+
+    fn connected(&self, peer: PeerId) {
+        self.contexts.write().unwrap().insert(peer, None);
+    }
+
+    fn disconnected(&self, peer: &PeerId) -> Option<Arc<C>> {
+        self.contexts.write().unwrap().remove(peer).flatten()
+    }
+}
+
+/// A `NetworkEvents` stream returning each event along with the context attached to the
+/// connection with its peer, if any. `LostPeer` events return the context detached from the lost
+/// connection, so that the application can clean it up.
+#[pin_project]
+pub struct PeerContextEvents<TMessage, C> {
+    #[pin]
+    events: NetworkEvents<TMessage>,
+    contexts: PeerContexts<C>,
+}
+
+impl<TMessage, C> Stream for PeerContextEvents<TMessage, C> {
+    type Item = Result<(Event<TMessage>, Option<Arc<C>>), NetworkError>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let contexts = this.contexts;
+        this.events.poll_next(context).map(|item| {
+            item.map(|result| {
+                result.map(|event| {
+                    let peer_context = match &event {
+                        Event::NewPeer(peer_id) => {
+                            contexts.connected(*peer_id);
+                            None
+                        }
+                        Event::LostPeer(peer_id) => contexts.disconnected(peer_id),
+                        Event::Message((peer_id, _)) | Event::RpcRequest((peer_id, _, _)) => {
+                            contexts.get(peer_id)
+                        }
+                    };
+                    (event, peer_context)
+                })
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.events.size_hint()
+    }
+}
+
+impl<TMessage, C> FusedStream for PeerContextEvents<TMessage, C> {
+    fn is_terminated(&self) -> bool {
+        self.events.is_terminated()
+    }
+}
+
 /// `NetworkSender` is the generic interface from upper network applications to
 /// the lower network layer. It provides the full API for network applications,
 /// including sending direct-send messages, sending rpc requests, as well as
@@ -207,6 +353,12 @@ impl<TMessage> NetworkSender<TMessage> {
         self.connection_reqs_tx.disconnect_peer(peer).await?;
         Ok(())
     }
+
+    /// Returns the encoding negotiated with `peer` for `protocol`, which the responses to its rpc
+    /// requests must be encoded with.
+    pub fn encoding(&self, peer: &PeerId, protocol: ProtocolId) -> Encoding {
+        self.peer_mgr_reqs_tx.encoding(peer, protocol)
+    }
 }
 
 impl<TMessage: Message> NetworkSender<TMessage> {
@@ -218,7 +370,7 @@ impl<TMessage: Message> NetworkSender<TMessage> {
         protocol: ProtocolId,
         message: TMessage,
     ) -> Result<(), NetworkError> {
-        let mdata = lcs::to_bytes(&message)?.into();
+        let mdata = self.encoding(&recipient, protocol).encode(&message)?.into();
         self.peer_mgr_reqs_tx.send_to(recipient, protocol, mdata)?;
         Ok(())
     }
@@ -231,10 +383,19 @@ impl<TMessage: Message> NetworkSender<TMessage> {
         protocol: ProtocolId,
         message: TMessage,
     ) -> Result<(), NetworkError> {
-        // Serialize message.
-        let mdata = lcs::to_bytes(&message)?.into();
-        self.peer_mgr_reqs_tx
-            .send_to_many(recipients, protocol, mdata)?;
+        // Serialize the message once per encoding of the recipients.
+        let mut recipients_by_encoding: HashMap<Encoding, Vec<PeerId>> = HashMap::new();
+        for recipient in recipients {
+            recipients_by_encoding
+                .entry(self.encoding(&recipient, protocol))
+                .or_default()
+                .push(recipient);
+        }
+        for (encoding, recipients) in recipients_by_encoding {
+            let mdata = encoding.encode(&message)?.into();
+            self.peer_mgr_reqs_tx
+                .send_to_many(recipients.into_iter(), protocol, mdata)?;
+        }
         Ok(())
     }
 
@@ -247,14 +408,59 @@ impl<TMessage: Message> NetworkSender<TMessage> {
         protocol: ProtocolId,
         req_msg: TMessage,
         timeout: Duration,
-    ) -> Result<TMessage, RpcError> {
+    ) -> Result<TMessage, NetworkError> {
         // serialize request
-        let req_data = lcs::to_bytes(&req_msg)?.into();
+        let encoding = self.encoding(&recipient, protocol);
+        let req_data = encoding.encode(&req_msg)?.into();
         let res_data = self
             .peer_mgr_reqs_tx
             .send_rpc(recipient, protocol, req_data, timeout)
             .await?;
-        let res_msg: TMessage = lcs::from_bytes(&res_data)?;
-        Ok(res_msg)
+        match encoding.decode(protocol.decoding(), &res_data)? {
+            Decoded::Message(res_msg) => Ok(res_msg),
+            Decoded::UnknownVariant(index) => Err(NetworkError::Error(anyhow!(
+                "Unknown {} response variant {}",
+                protocol,
+                index
+            ))),
+        }
+    }
+}
+
+impl<TMessage: Message + Send + 'static> network_api::MessageSender<TMessage>
+    for NetworkSender<TMessage>
+{
+    type Protocol = ProtocolId;
+    type Error = NetworkError;
+
+    fn send_to(
+        &mut self,
+        recipient: PeerId,
+        protocol: ProtocolId,
+        message: TMessage,
+    ) -> Result<(), NetworkError> {
+        Self::send_to(self, recipient, protocol, message)
+    }
+
+    fn send_to_many<I>(
+        &mut self,
+        recipients: I,
+        protocol: ProtocolId,
+        message: TMessage,
+    ) -> Result<(), NetworkError>
+    where
+        I: Iterator<Item = PeerId>,
+    {
+        Self::send_to_many(self, recipients, protocol, message)
+    }
+
+    fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        protocol: ProtocolId,
+        req_msg: TMessage,
+        timeout: Duration,
+    ) -> BoxFuture<'_, Result<TMessage, NetworkError>> {
+        Box::pin(Self::send_rpc(self, recipient, protocol, req_msg, timeout))
     }
 }
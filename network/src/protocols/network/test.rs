@@ -106,3 +106,110 @@ fn test_rpc() {
     let (res_msg, _) = tn.runtime.block_on(join(f_send, f_respond));
     assert_eq!(res_msg.unwrap(), msg);
 }
+
+#[test]
+fn test_peer_contexts() {
+    use crate::{
+        peer::DisconnectReason,
+        peer_manager::{
+            conn_notifs_channel, ConnectionInfo, ConnectionNotification, PeerManagerNotification,
+        },
+        protocols::direct_send::Message,
+        ProtocolId,
+    };
+    use channel::message_queues::QueueStyle;
+    use futures::executor::block_on;
+    use libra_network_address::NetworkAddress;
+    use std::num::NonZeroUsize;
+
+    let (mut peer_mgr_notifs_tx, peer_mgr_notifs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (mut conn_notifs_tx, conn_notifs_rx) = conn_notifs_channel::new();
+    let (mut events, contexts) =
+        NetworkEvents::<DummyMsg>::new(peer_mgr_notifs_rx, conn_notifs_rx).with_peer_contexts();
+    let peer_id = PeerId::random();
+    let protocol = ProtocolId::ConsensusDirectSend;
+    let mut send_msg = |msg: DummyMsg| {
+        let mdata = lcs::to_bytes(&msg).unwrap().into();
+        peer_mgr_notifs_tx
+            .push(
+                (peer_id, protocol),
+                PeerManagerNotification::RecvMessage(
+                    peer_id,
+                    Message { protocol, mdata },
+                    Encoding::Lcs,
+                ),
+            )
+            .unwrap();
+    };
+
+    // Contexts can't be attached to disconnected peers
+    assert!(!contexts.attach(peer_id, "disconnected"));
+
+    conn_notifs_tx
+        .push(
+            peer_id,
+            ConnectionNotification::NewPeer(
+                peer_id,
+                NetworkAddress::mock(),
+                ConnectionInfo::mock(),
+            ),
+        )
+        .unwrap();
+    let (event, context) = block_on(events.next()).unwrap().unwrap();
+    assert_eq!(event, Event::NewPeer(peer_id));
+    assert!(context.is_none());
+    assert!(contexts.attach(peer_id, "connected"));
+
+    // Subsequent events of the peer return its context
+    send_msg(DummyMsg(vec![1]));
+    let (event, context) = block_on(events.next()).unwrap().unwrap();
+    assert_eq!(event, Event::Message((peer_id, DummyMsg(vec![1]))));
+    assert_eq!(*context.unwrap(), "connected");
+
+    // A new connection starts without context, even if the loss of the previous one was missed
+    conn_notifs_tx
+        .push(
+            peer_id,
+            ConnectionNotification::LostPeer(
+                peer_id,
+                NetworkAddress::mock(),
+                DisconnectReason::ConnectionLost,
+            ),
+        )
+        .unwrap();
+    conn_notifs_tx
+        .push(
+            peer_id,
+            ConnectionNotification::NewPeer(
+                peer_id,
+                NetworkAddress::mock(),
+                ConnectionInfo::mock(),
+            ),
+        )
+        .unwrap();
+    let (event, context) = block_on(events.next()).unwrap().unwrap();
+    assert_eq!(event, Event::NewPeer(peer_id));
+    assert!(context.is_none());
+    assert!(contexts.get(&peer_id).is_none());
+
+    // The context of a lost connection is returned with the `LostPeer` event, then dropped
+    assert!(contexts.attach(peer_id, "reconnected"));
+    conn_notifs_tx
+        .push(
+            peer_id,
+            ConnectionNotification::LostPeer(
+                peer_id,
+                NetworkAddress::mock(),
+                DisconnectReason::Requested,
+            ),
+        )
+        .unwrap();
+    let (event, context) = block_on(events.next()).unwrap().unwrap();
+    assert_eq!(event, Event::LostPeer(peer_id));
+    assert_eq!(*context.unwrap(), "reconnected");
+    assert!(contexts.get(&peer_id).is_none());
+    send_msg(DummyMsg(vec![2]));
+    let (_, context) = block_on(events.next()).unwrap().unwrap();
+    assert!(context.is_none());
+}
@@ -0,0 +1,403 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Lightweight peer exchange (PEX), discovering the addresses of more peers on demand.
+//!
+//! Unlike gossip [`discovery`](crate::protocols::discovery), which periodically exchanges the full
+//! set of known peers, a node only asks one connected peer at a time for a bounded random sample
+//! of the addresses it knows of, and only when the [`ConnectivityManager`] is short on outbound
+//! candidates, i.e., on eligible peers with known addresses which it isn't connected to.
+//!
+//! ## Protocol
+//!
+//! - When triggered by the [`ConnectivityManager`], the node sends a [`PexMsg::GetPeers`] to a
+//! random connected peer, asking for up to [`MAX_PEX_PEERS`] peers, optionally of a given role.
+//! - The peer answers with a [`PexMsg::Peers`] carrying a random sample of the addresses known to
+//! its own [`ConnectivityManager`], including its own advertised addresses, and at most
+//! [`MAX_PEX_ADDRS`] addresses per peer. Responses beyond these limits are dropped.
+//! - The addresses received are sent to the [`ConnectivityManager`], as the lowest priority
+//! discovery source but the address book.
+//!
+//! The addresses are not signed: they are hints, and a peer can't be impersonated at an address
+//! sent by a third party, as the address pins the network identity key dialed by Noise.
+//!
+//! ## Roles
+//!
+//! The requests and responses carry the role of their sender, so that the nodes only learn the
+//! roles of the peers they exchange with first-hand. The responses to a request filtered by role
+//! only carry the peers known to have that role.
+//!
+//! [`ConnectivityManager`]: ../../connectivity_manager
+
+use crate::{
+    catch_panic::catch_panic,
+    connectivity_manager::{ConnectivityRequest, DiscoverySource},
+    counters,
+    error::NetworkError,
+    peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
+    protocols::{
+        network::{Event, NetworkEvents, NetworkSender},
+        rpc::error::RpcError,
+    },
+    validator_network::network_builder::{NetworkBuilder, NETWORK_CHANNEL_SIZE},
+    ProtocolId,
+};
+use anyhow::{ensure, Result};
+use bytes::Bytes;
+use channel::message_queues::QueueStyle;
+use futures::{
+    channel::{mpsc, oneshot},
+    sink::SinkExt,
+    stream::{FuturesUnordered, StreamExt},
+};
+use libra_config::config::RoleType;
+use libra_logger::prelude::*;
+use libra_network_address::NetworkAddress;
+use libra_security_logger::{security_log, SecurityEvent};
+use libra_types::PeerId;
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+#[cfg(test)]
+mod test;
+
+const ACTOR: &str = "pex";
+
+/// Maximum number of peers in a response.
+pub const MAX_PEX_PEERS: usize = 32;
+/// Maximum number of addresses of a peer in a response.
+pub const MAX_PEX_ADDRS: usize = 8;
+
+/// Timeout of the PEX RPCs.
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type PexNetworkEvents = NetworkEvents<PexMsg>;
+
+/// The interface from peer exchange to the network layer.
+#[derive(Clone)]
+pub struct PexNetworkSender {
+    inner: NetworkSender<PexMsg>,
+}
+
+pub fn add_to_network(network: &mut NetworkBuilder) -> (PexNetworkSender, PexNetworkEvents) {
+    let (sender, receiver, connection_reqs_tx, connection_notifs_rx) = network
+        .add_protocol_handler(
+            vec![ProtocolId::PexRpc],
+            vec![],
+            ProtocolPriority::Normal,
+            QueueStyle::LIFO,
+            NETWORK_CHANNEL_SIZE,
+            Some(&counters::PENDING_PEX_NETWORK_EVENTS),
+        );
+    (
+        PexNetworkSender::new(sender, connection_reqs_tx),
+        PexNetworkEvents::new(receiver, connection_notifs_rx),
+    )
+}
+
+impl PexNetworkSender {
+    pub fn new(
+        peer_mgr_reqs_tx: PeerManagerRequestSender,
+        connection_reqs_tx: ConnectionRequestSender,
+    ) -> Self {
+        Self {
+            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
+        }
+    }
+
+    /// Send a PEX request to `recipient`, and wait for its response until `timeout`.
+    pub async fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        req_msg: PexMsg,
+        timeout: Duration,
+    ) -> Result<PexMsg, NetworkError> {
+        self.inner
+            .send_rpc(recipient, ProtocolId::PexRpc, req_msg, timeout)
+            .await
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum PexMsg {
+    /// Requests a random sample of up to `max_peers` peers known to the recipient, only of
+    /// `role_filter` if any. `role` is the role of the sender.
+    GetPeers {
+        role: RoleType,
+        role_filter: Option<RoleType>,
+        max_peers: u32,
+    },
+    /// The response to a `GetPeers`. `role` is the role of the sender.
+    Peers { role: RoleType, peers: Vec<PexPeer> },
+}
+
+/// The addresses of a peer.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PexPeer {
+    pub peer_id: PeerId,
+    pub addrs: Vec<NetworkAddress>,
+}
+
+/// Checks that the `peers` of a response to a request for up to `max_peers` peers are within the
+/// limits of what responses may carry.
+pub fn verify_peers(peers: &[PexPeer], max_peers: usize) -> Result<()> {
+    ensure!(
+        peers.len() <= max_peers,
+        "Response has {} peers, more than the {} requested",
+        peers.len(),
+        max_peers
+    );
+    for peer in peers {
+        ensure!(
+            peer.addrs.len() <= MAX_PEX_ADDRS,
+            "Peer {} has {} addresses, more than the maximum of {}",
+            peer.peer_id.short_str(),
+            peer.addrs.len(),
+            MAX_PEX_ADDRS
+        );
+    }
+    Ok(())
+}
+
+/// The actor exchanging the addresses of the known peers with the connected peers on demand.
+pub struct PeerExchange {
+    self_peer_id: PeerId,
+    role: RoleType,
+    /// The advertised addresses of this node, sent along the known peers.
+    self_addrs: Vec<NetworkAddress>,
+    /// The role of the peers requested, if any.
+    role_filter: Option<RoleType>,
+    /// Channel over which the ConnectivityManager requests more outbound candidates.
+    requests_rx: mpsc::Receiver<()>,
+    network_tx: PexNetworkSender,
+    network_rx: PexNetworkEvents,
+    /// Channel to get the known addresses from, and send the discovered ones to, the
+    /// ConnectivityManager.
+    conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    connected: HashSet<PeerId>,
+    /// Roles of the peers, as declared by the peers themselves.
+    roles: HashMap<PeerId, RoleType>,
+    rng: SmallRng,
+}
+
+impl PeerExchange {
+    /// Create new instance of the [`PeerExchange`] actor, requesting the peers of `role_filter`,
+    /// if any, whenever the ConnectivityManager asks for more over `requests_rx`.
+    pub fn new(
+        self_peer_id: PeerId,
+        role: RoleType,
+        self_addrs: Vec<NetworkAddress>,
+        role_filter: Option<RoleType>,
+        requests_rx: mpsc::Receiver<()>,
+        network_tx: PexNetworkSender,
+        network_rx: PexNetworkEvents,
+        conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    ) -> Self {
+        Self {
+            self_peer_id,
+            role,
+            self_addrs,
+            role_filter,
+            requests_rx,
+            network_tx,
+            network_rx,
+            conn_mgr_reqs_tx,
+            connected: HashSet::new(),
+            roles: HashMap::new(),
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        let mut rpcs = FuturesUnordered::new();
+        loop {
+            let next_event = async {
+                futures::select! {
+                    event = self.network_rx.select_next_some() => {
+                        match event {
+                            Ok(Event::NewPeer(peer_id)) => {
+                                self.connected.insert(peer_id);
+                            }
+                            Ok(Event::LostPeer(peer_id)) => {
+                                self.connected.remove(&peer_id);
+                            }
+                            Ok(Event::RpcRequest((peer_id, req, res_tx))) => {
+                                self.handle_request(peer_id, req, res_tx).await;
+                            }
+                            Ok(event) => {
+                                warn!("Unexpected PEX network event: {:?}", event);
+                            }
+                            Err(err) => {
+                                warn!("PEX network error: {:?}", err);
+                            }
+                        }
+                    }
+                    _ = self.requests_rx.select_next_some() => {
+                        let connected: Vec<_> = self.connected.iter().copied().collect();
+                        if let Some(peer_id) = connected.choose(&mut self.rng) {
+                            rpcs.push(Self::send_request(
+                                self.network_tx.clone(),
+                                *peer_id,
+                                PexMsg::GetPeers {
+                                    role: self.role,
+                                    role_filter: self.role_filter,
+                                    max_peers: MAX_PEX_PEERS as u32,
+                                },
+                            ));
+                        }
+                    }
+                    res = rpcs.select_next_some() => {
+                        let (peer_id, res) = res;
+                        match res {
+                            Ok(PexMsg::Peers { role, peers }) => {
+                                self.roles.insert(peer_id, role);
+                                self.handle_peers(peer_id, peers).await;
+                            }
+                            Ok(msg) => {
+                                warn!(
+                                    "Unexpected PEX response from peer {}: {:?}",
+                                    peer_id.short_str(),
+                                    msg
+                                );
+                            }
+                            Err(err) => {
+                                debug!(
+                                    "PEX request to peer {} failed: {:?}",
+                                    peer_id.short_str(),
+                                    err
+                                );
+                            }
+                        }
+                    }
+                    complete => return false,
+                }
+                true
+            };
+            match catch_panic(ACTOR, next_event).await {
+                // The state of the actor is only updated atomically, so there is no state to
+                // restore on panics.
+                Ok(true) | Err(_) => {}
+                Ok(false) => break,
+            }
+        }
+        crit!("PEX actor terminated");
+    }
+
+    async fn handle_request(
+        &mut self,
+        peer_id: PeerId,
+        req: PexMsg,
+        res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+    ) {
+        let res = match req {
+            PexMsg::GetPeers {
+                role,
+                role_filter,
+                max_peers,
+            } => {
+                self.roles.insert(peer_id, role);
+                PexMsg::Peers {
+                    role: self.role,
+                    peers: self
+                        .sample_peers(peer_id, role_filter, max_peers as usize)
+                        .await,
+                }
+            }
+            req => {
+                warn!(
+                    "Unexpected PEX request from peer {}: {:?}",
+                    peer_id.short_str(),
+                    req
+                );
+                return;
+            }
+        };
+        match lcs::to_bytes(&res) {
+            Ok(res) => {
+                let _ = res_tx.send(Ok(res.into()));
+            }
+            Err(err) => warn!(
+                "Unable to serialize the PEX response to peer {}: {}",
+                peer_id.short_str(),
+                err
+            ),
+        }
+    }
+
+    /// A random sample of up to `max_peers` peers known to the ConnectivityManager and of this
+    /// node, only of `role_filter` if any, excluding the `requester`.
+    async fn sample_peers(
+        &mut self,
+        requester: PeerId,
+        role_filter: Option<RoleType>,
+        max_peers: usize,
+    ) -> Vec<PexPeer> {
+        let (known_tx, known_rx) = oneshot::channel();
+        self.conn_mgr_reqs_tx
+            .send(ConnectivityRequest::GetKnownAddresses(known_tx))
+            .await
+            .expect("ConnectivityRequest::GetKnownAddresses send");
+        let mut known = known_rx.await.unwrap_or_default();
+        known.insert(self.self_peer_id, self.self_addrs.clone());
+        self.roles.insert(self.self_peer_id, self.role);
+
+        let roles = &self.roles;
+        let mut peers: Vec<_> = known
+            .into_iter()
+            .filter(|(peer_id, addrs)| {
+                *peer_id != requester
+                    && !addrs.is_empty()
+                    && role_filter.map_or(true, |role| roles.get(peer_id) == Some(&role))
+            })
+            .map(|(peer_id, mut addrs)| {
+                addrs.truncate(MAX_PEX_ADDRS);
+                PexPeer { peer_id, addrs }
+            })
+            .collect();
+        peers.shuffle(&mut self.rng);
+        peers.truncate(max_peers.min(MAX_PEX_PEERS));
+        peers
+    }
+
+    /// Sends the addresses of the valid response of `remote_peer` to the ConnectivityManager.
+    async fn handle_peers(&mut self, remote_peer: PeerId, peers: Vec<PexPeer>) {
+        if let Err(err) = verify_peers(&peers, MAX_PEX_PEERS) {
+            security_log(SecurityEvent::InvalidPexMsg)
+                .error(&err)
+                .data(&remote_peer)
+                .log();
+            return;
+        }
+        let address_map: HashMap<_, _> = peers
+            .into_iter()
+            .filter(|peer| peer.peer_id != self.self_peer_id)
+            .map(|peer| (peer.peer_id, peer.addrs))
+            .collect();
+        if address_map.is_empty() {
+            return;
+        }
+        self.conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                DiscoverySource::Pex,
+                address_map,
+            ))
+            .await
+            .expect("ConnectivityRequest::UpdateAddresses send");
+    }
+
+    async fn send_request(
+        mut network_tx: PexNetworkSender,
+        peer_id: PeerId,
+        req: PexMsg,
+    ) -> (PeerId, Result<PexMsg, NetworkError>) {
+        (
+            peer_id,
+            network_tx.send_rpc(peer_id, req, RPC_TIMEOUT).await,
+        )
+    }
+}
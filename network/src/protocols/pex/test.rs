@@ -0,0 +1,315 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    peer_manager::{self, conn_notifs_channel, PeerManagerNotification, PeerManagerRequest},
+    protocols::{
+        rpc::{InboundRpcRequest, OutboundRpcRequest},
+        wire::handshake::v1::Encoding,
+    },
+};
+use channel::libra_channel;
+use std::{num::NonZeroUsize, str::FromStr};
+use tokio::runtime::Runtime;
+
+fn addrs(addr: &str) -> Vec<NetworkAddress> {
+    vec![NetworkAddress::from_str(addr).unwrap()]
+}
+
+fn setup_pex(
+    rt: &mut Runtime,
+    self_peer_id: PeerId,
+    role_filter: Option<RoleType>,
+) -> (
+    libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    channel::Receiver<ConnectivityRequest>,
+    libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+    conn_notifs_channel::Sender,
+    mpsc::Sender<()>,
+) {
+    let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(8).unwrap(), None);
+    let (connection_reqs_tx, _) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (conn_mgr_reqs_tx, conn_mgr_reqs_rx) = channel::new_test(1);
+    let (network_notifs_tx, network_notifs_rx) =
+        libra_channel::new(QueueStyle::FIFO, NonZeroUsize::new(1).unwrap(), None);
+    let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
+    let (requests_tx, requests_rx) = mpsc::channel(0);
+    let peer_exchange = PeerExchange::new(
+        self_peer_id,
+        RoleType::FullNode,
+        addrs("/ip4/127.0.0.1/tcp/9090"),
+        role_filter,
+        requests_rx,
+        PexNetworkSender::new(
+            PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+            ConnectionRequestSender::new(connection_reqs_tx),
+        ),
+        PexNetworkEvents::new(network_notifs_rx, connection_notifs_rx),
+        conn_mgr_reqs_tx,
+    );
+    rt.spawn(peer_exchange.start());
+    (
+        peer_mgr_reqs_rx,
+        conn_mgr_reqs_rx,
+        network_notifs_tx,
+        connection_notifs_tx,
+        requests_tx,
+    )
+}
+
+async fn send_new_peer_notification(
+    peer_id: PeerId,
+    connection_notifs_tx: &mut conn_notifs_channel::Sender,
+) {
+    let (delivered_tx, delivered_rx) = oneshot::channel();
+    connection_notifs_tx
+        .push_with_feedback(
+            peer_id,
+            peer_manager::ConnectionNotification::NewPeer(
+                peer_id,
+                NetworkAddress::from_str("/ip6/::1/tcp/8081").unwrap(),
+                peer_manager::ConnectionInfo::mock(),
+            ),
+            Some(delivered_tx),
+        )
+        .unwrap();
+    delivered_rx.await.unwrap();
+}
+
+async fn expect_request(
+    network_reqs_rx: &mut libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
+    expected_peer_id: PeerId,
+) -> (PexMsg, OutboundRpcRequest) {
+    let (peer_id, rpc_req) = match network_reqs_rx.next().await.unwrap() {
+        PeerManagerRequest::SendRpc(peer_id, rpc_req, _) => (peer_id, rpc_req),
+        req => panic!("Unexpected PeerManagerRequest: {:?}", req),
+    };
+    assert_eq!(peer_id, expected_peer_id);
+    assert_eq!(rpc_req.protocol, ProtocolId::PexRpc);
+    (lcs::from_bytes(&rpc_req.data).unwrap(), rpc_req)
+}
+
+fn send_response(rpc_req: OutboundRpcRequest, res: PexMsg) {
+    rpc_req
+        .res_tx
+        .send(Ok(lcs::to_bytes(&res).unwrap().into()))
+        .unwrap();
+}
+
+async fn expect_address_update(
+    conn_mgr_reqs_rx: &mut channel::Receiver<ConnectivityRequest>,
+    expected_address_map: HashMap<PeerId, Vec<NetworkAddress>>,
+) {
+    match conn_mgr_reqs_rx.next().await.unwrap() {
+        ConnectivityRequest::UpdateAddresses(src, address_map) => {
+            assert_eq!(DiscoverySource::Pex, src);
+            assert_eq!(expected_address_map, address_map);
+        }
+        req => panic!("Unexpected request to connectivity manager: {:?}", req),
+    }
+}
+
+/// Answers the next request of the known addresses with `known`.
+async fn serve_known_addresses(
+    conn_mgr_reqs_rx: &mut channel::Receiver<ConnectivityRequest>,
+    known: HashMap<PeerId, Vec<NetworkAddress>>,
+) {
+    match conn_mgr_reqs_rx.next().await.unwrap() {
+        ConnectivityRequest::GetKnownAddresses(sender) => sender.send(known).unwrap(),
+        req => panic!("Unexpected request to connectivity manager: {:?}", req),
+    }
+}
+
+fn send_inbound_request(
+    peer_id: PeerId,
+    req: PexMsg,
+    network_notifs_tx: &mut libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>,
+) -> oneshot::Receiver<Result<Bytes, RpcError>> {
+    let (res_tx, res_rx) = oneshot::channel();
+    let inbound_rpc_req = InboundRpcRequest {
+        protocol: ProtocolId::PexRpc,
+        data: lcs::to_bytes(&req).unwrap().into(),
+        res_tx,
+    };
+    network_notifs_tx
+        .push(
+            (peer_id, ProtocolId::PexRpc),
+            PeerManagerNotification::RecvRpc(peer_id, inbound_rpc_req, Encoding::Lcs),
+        )
+        .unwrap();
+    res_rx
+}
+
+fn peer_ids(res: Bytes) -> HashSet<PeerId> {
+    match lcs::from_bytes(&res).unwrap() {
+        PexMsg::Peers { role, peers } => {
+            assert_eq!(role, RoleType::FullNode);
+            peers.into_iter().map(|peer| peer.peer_id).collect()
+        }
+        msg => panic!("Unexpected PexMsg: {:?}", msg),
+    }
+}
+
+#[test]
+fn verify() {
+    let peer = |num_addrs| PexPeer {
+        peer_id: PeerId::random(),
+        addrs: vec![NetworkAddress::from_str("/memory/1").unwrap(); num_addrs],
+    };
+    verify_peers(&[peer(1), peer(MAX_PEX_ADDRS)], 2).unwrap();
+    verify_peers(&[peer(1), peer(1)], 1).unwrap_err();
+    verify_peers(&[peer(MAX_PEX_ADDRS + 1)], 1).unwrap_err();
+}
+
+#[test]
+fn get_peers() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let self_peer_id = PeerId::random();
+    let peer_a = PeerId::random();
+    let peer_b = PeerId::random();
+    let peer_c = PeerId::random();
+
+    let (_network_reqs_rx, mut conn_mgr_reqs_rx, mut network_notifs_tx, _, _requests_tx) =
+        setup_pex(&mut rt, self_peer_id, None);
+
+    let events_f = async move {
+        let mut known = HashMap::new();
+        known.insert(peer_a, addrs("/ip4/127.0.0.1/tcp/9091"));
+        known.insert(peer_b, addrs("/ip4/127.0.0.1/tcp/9092"));
+        known.insert(peer_c, addrs("/ip4/127.0.0.1/tcp/9093"));
+
+        // The requester gets the known peers and this node, but not itself.
+        let res_rx = send_inbound_request(
+            peer_a,
+            PexMsg::GetPeers {
+                role: RoleType::Validator,
+                role_filter: None,
+                max_peers: 8,
+            },
+            &mut network_notifs_tx,
+        );
+        serve_known_addresses(&mut conn_mgr_reqs_rx, known.clone()).await;
+        let expected: HashSet<_> = vec![self_peer_id, peer_b, peer_c].into_iter().collect();
+        assert_eq!(peer_ids(res_rx.await.unwrap().unwrap()), expected);
+
+        // Only up to `max_peers` peers are sent.
+        let res_rx = send_inbound_request(
+            peer_b,
+            PexMsg::GetPeers {
+                role: RoleType::FullNode,
+                role_filter: None,
+                max_peers: 1,
+            },
+            &mut network_notifs_tx,
+        );
+        serve_known_addresses(&mut conn_mgr_reqs_rx, known.clone()).await;
+        assert_eq!(peer_ids(res_rx.await.unwrap().unwrap()).len(), 1);
+
+        // Only the peers known to have the role are sent: peer_a declared itself a validator,
+        // and peer_b and this node are full nodes.
+        let res_rx = send_inbound_request(
+            peer_c,
+            PexMsg::GetPeers {
+                role: RoleType::FullNode,
+                role_filter: Some(RoleType::Validator),
+                max_peers: 8,
+            },
+            &mut network_notifs_tx,
+        );
+        serve_known_addresses(&mut conn_mgr_reqs_rx, known.clone()).await;
+        let expected: HashSet<_> = vec![peer_a].into_iter().collect();
+        assert_eq!(peer_ids(res_rx.await.unwrap().unwrap()), expected);
+
+        let res_rx = send_inbound_request(
+            peer_c,
+            PexMsg::GetPeers {
+                role: RoleType::FullNode,
+                role_filter: Some(RoleType::FullNode),
+                max_peers: 8,
+            },
+            &mut network_notifs_tx,
+        );
+        serve_known_addresses(&mut conn_mgr_reqs_rx, known).await;
+        let expected: HashSet<_> = vec![self_peer_id, peer_b].into_iter().collect();
+        assert_eq!(peer_ids(res_rx.await.unwrap().unwrap()), expected);
+    };
+    rt.block_on(events_f);
+}
+
+#[test]
+fn request_peers() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+    let mut rt = Runtime::new().unwrap();
+    let self_peer_id = PeerId::random();
+    let peer_a = PeerId::random();
+    let peer_b = PeerId::random();
+
+    let (
+        mut network_reqs_rx,
+        mut conn_mgr_reqs_rx,
+        _network_notifs_tx,
+        mut connection_notifs_tx,
+        mut requests_tx,
+    ) = setup_pex(&mut rt, self_peer_id, Some(RoleType::Validator));
+
+    let events_f = async move {
+        send_new_peer_notification(peer_a, &mut connection_notifs_tx).await;
+
+        // Responses beyond the limits are dropped.
+        requests_tx.send(()).await.unwrap();
+        match expect_request(&mut network_reqs_rx, peer_a).await {
+            (
+                PexMsg::GetPeers {
+                    role,
+                    role_filter,
+                    max_peers,
+                },
+                rpc_req,
+            ) => {
+                assert_eq!(role, RoleType::FullNode);
+                assert_eq!(role_filter, Some(RoleType::Validator));
+                assert_eq!(max_peers as usize, MAX_PEX_PEERS);
+                let invalid = PexPeer {
+                    peer_id: peer_b,
+                    addrs: vec![NetworkAddress::from_str("/memory/1").unwrap(); MAX_PEX_ADDRS + 1],
+                };
+                send_response(
+                    rpc_req,
+                    PexMsg::Peers {
+                        role: RoleType::Validator,
+                        peers: vec![invalid],
+                    },
+                );
+            }
+            (msg, _) => panic!("Unexpected PexMsg: {:?}", msg),
+        }
+
+        // The peers of the valid responses are sent to the connectivity manager, but this node.
+        requests_tx.send(()).await.unwrap();
+        let (_, rpc_req) = expect_request(&mut network_reqs_rx, peer_a).await;
+        send_response(
+            rpc_req,
+            PexMsg::Peers {
+                role: RoleType::Validator,
+                peers: vec![
+                    PexPeer {
+                        peer_id: peer_b,
+                        addrs: addrs("/ip4/127.0.0.1/tcp/9092"),
+                    },
+                    PexPeer {
+                        peer_id: self_peer_id,
+                        addrs: addrs("/ip4/127.0.0.1/tcp/6666"),
+                    },
+                ],
+            },
+        );
+        let mut expected = HashMap::new();
+        expected.insert(peer_b, addrs("/ip4/127.0.0.1/tcp/9092"));
+        expect_address_update(&mut conn_mgr_reqs_rx, expected).await;
+    };
+    rt.block_on(events_f);
+}
@@ -3,7 +3,7 @@
 
 //! Rpc protocol errors
 
-use crate::peer_manager::PeerManagerError;
+use crate::{peer_manager::PeerManagerError, DisconnectReason, ProtocolId};
 use anyhow::anyhow;
 use futures::channel::{mpsc, oneshot};
 use libra_types::PeerId;
@@ -24,6 +24,12 @@ pub enum RpcError {
     #[error("Failed to open substream, not connected with peer: {0}")]
     NotConnected(PeerId),
 
+    #[error("Protocol {0:?} is not supported by the peer")]
+    ProtocolNotSupported(ProtocolId),
+
+    #[error("Peer disconnected before responding: {0:?}")]
+    PeerDisconnected(DisconnectReason),
+
     #[error("Received invalid rpc response message")]
     InvalidRpcResponse,
 
@@ -45,6 +51,9 @@ pub enum RpcError {
     #[error("Too many pending RPCs: {0}")]
     TooManyPending(u32),
 
+    #[error("In-flight RPC memory budget exhausted, rejecting request of {0} bytes")]
+    MemoryBudgetExceeded(usize),
+
     #[error("Rpc timed out")]
     TimedOut,
 }
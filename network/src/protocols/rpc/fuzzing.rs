@@ -75,6 +75,7 @@ pub fn fuzzer(data: &[u8]) {
     let f_handle_inbound = rpc::handle_inbound_request_inner(
         notification_tx,
         inbound_request,
+        None,
         PeerHandle::new(MOCK_PEER_ID, peer_reqs_tx),
     )
     .map(|_| io::Result::Ok(()));
@@ -33,6 +33,19 @@
 //! inbound RPCs. For outbound RPCs, we log a warning when the limit is exceeded, but allow the RPC
 //! to proceed.
 //!
+//! The bytes of the requests of the inbound and outbound RPCs are also held against the in-flight
+//! RPC budget of the network, shared by all its peers, until the RPC completes. Once the budget is
+//! exhausted, new outbound RPCs fail with [`RpcError::MemoryBudgetExceeded`], and new inbound RPCs
+//! are declined.
+//!
+//! Tracing:
+//! --------
+//! Outbound RPC requests are sent as `TracedRpcRequest`s carrying the context of the trace of the
+//! request, so that the span of the handling of the request by the remote node links to the span
+//! of the outbound RPC, see [`request_trace`](crate::peer_manager::request_trace). The peers on
+//! the sessions of messaging protocol version V1 are sent the bare `RpcRequest`s, whose handling
+//! starts a new trace.
+//!
 //! State
 //! -------------
 //! * For outbound RPCs, the RPC actors maintains a HashMap from the RequestId to a channel over
@@ -49,9 +62,12 @@ use crate::{
         RESPONSE_LABEL, SENT_LABEL,
     },
     peer::{PeerHandle, PeerNotification},
+    peer_manager::request_trace::{RequestTrace, Span},
     protocols::wire::messaging::v1::{
-        NetworkMessage, Priority, RequestId, RpcRequest, RpcResponse,
+        NetworkMessage, Priority, RequestId, RpcRequest, RpcResponse, TraceContext,
+        TracedRpcRequest,
     },
+    quota::ResourceBudget,
     ProtocolId,
 };
 use bytes::Bytes;
@@ -170,7 +186,7 @@ pub struct Rpc {
     /// Channel to send requests to Peer.
     peer_handle: PeerHandle,
     /// Channel to receive requests from other upstream actors.
-    requests_rx: channel::Receiver<OutboundRpcRequest>,
+    requests_rx: channel::Receiver<(OutboundRpcRequest, RequestTrace)>,
     /// Channel to receive notifications from Peer.
     peer_notifs_rx: channel::Receiver<PeerNotification>,
     /// Channels to send notifictions to upstream actors.
@@ -178,7 +194,8 @@ pub struct Rpc {
     /// The timeout duration for inbound rpc calls.
     inbound_rpc_timeout: Duration,
     /// Channels to send Rpc responses to pending outbound RPC tasks.
-    pending_outbound_rpcs: HashMap<RequestId, (ProtocolId, oneshot::Sender<RpcResponse>)>,
+    pending_outbound_rpcs:
+        HashMap<RequestId, (ProtocolId, oneshot::Sender<Result<RpcResponse, RpcError>>)>,
     /// RequestId to use for next outbound RPC.
     request_id_gen: RequestIdGenerator,
     /// The maximum number of concurrent outbound rpc requests that we will
@@ -187,18 +204,22 @@ pub struct Rpc {
     /// The maximum number of concurrent inbound rpc requests that we will
     /// service before back-pressure kicks in.
     max_concurrent_inbound_rpcs: u32,
+    /// Budget of the bytes of the requests of the rpcs in flight, shared by the peers of the
+    /// network.
+    inflight_rpc_budget: ResourceBudget,
 }
 
 impl Rpc {
     /// Create a new instance of the [`Rpc`] protocol actor.
     pub fn new(
         peer_handle: PeerHandle,
-        requests_rx: channel::Receiver<OutboundRpcRequest>,
+        requests_rx: channel::Receiver<(OutboundRpcRequest, RequestTrace)>,
         peer_notifs_rx: channel::Receiver<PeerNotification>,
         rpc_handler_tx: channel::Sender<RpcNotification>,
         inbound_rpc_timeout: Duration,
         max_concurrent_outbound_rpcs: u32,
         max_concurrent_inbound_rpcs: u32,
+        inflight_rpc_budget: ResourceBudget,
     ) -> Self {
         Self {
             request_id_gen: RequestIdGenerator::new(peer_handle.peer_id()),
@@ -210,6 +231,7 @@ impl Rpc {
             pending_outbound_rpcs: HashMap::new(),
             max_concurrent_outbound_rpcs,
             max_concurrent_inbound_rpcs,
+            inflight_rpc_budget,
         }
     }
 
@@ -226,8 +248,8 @@ impl Rpc {
                     );
                 },
                 maybe_req = self.requests_rx.next() => {
-                    if let Some(req) = maybe_req {
-                        self.handle_outbound_rpc(req, &mut outbound_rpc_tasks).await;
+                    if let Some((req, trace)) = maybe_req {
+                        self.handle_outbound_rpc(req, trace, &mut outbound_rpc_tasks).await;
                     } else {
                         break;
                     }
@@ -254,7 +276,7 @@ impl Rpc {
         inbound_rpc_tasks: &mut InboundRpcTasks,
     ) {
         match notif {
-            PeerNotification::NewMessage(message) => {
+            PeerNotification::NewMessage(message, _permit) => {
                 match message {
                     // This is a response to a pending outbound RPC.
                     NetworkMessage::RpcResponse(response) => {
@@ -262,18 +284,26 @@ impl Rpc {
                     }
                     // This is a new inbound RPC request.
                     NetworkMessage::RpcRequest(request) => {
-                        self.handle_inbound_request(request, inbound_rpc_tasks);
+                        self.handle_inbound_request(request, None, inbound_rpc_tasks);
+                    }
+                    NetworkMessage::TracedRpcRequest(traced) => {
+                        self.handle_inbound_request(
+                            traced.request,
+                            Some(traced.trace_context),
+                            inbound_rpc_tasks,
+                        );
                     }
                     _ => {
                         error!("Received non-RPC message from Peer actor: {:?}", message);
                     }
                 }
             }
-            notif => debug_assert!(
-                false,
-                "Received unexpected event from Peer: {:?}, expected NewMessage",
-                notif
-            ),
+            PeerNotification::PeerDisconnected(_, reason) => {
+                // Fail the pending outbound RPCs right away rather than letting them time out.
+                for (_, (_, response_tx)) in self.pending_outbound_rpcs.drain() {
+                    let _ = response_tx.send(Err(RpcError::PeerDisconnected(reason)));
+                }
+            }
         }
     }
 
@@ -287,7 +317,7 @@ impl Rpc {
                 "Waiting to notify outbound rpc task about inbound response for request_id {}",
                 request_id
             );
-            if let Err(e) = response_tx.send(response) {
+            if let Err(e) = response_tx.send(Ok(response)) {
                 warn!(
                     "Failed to handle inbount RPC response from peer: {} for protocol: {:?}. Error: {:?}",
                     peer_id.short_str(),
@@ -313,6 +343,7 @@ impl Rpc {
     fn handle_inbound_request(
         &mut self,
         request: RpcRequest,
+        trace_context: Option<TraceContext>,
         inbound_rpc_tasks: &mut InboundRpcTasks,
     ) {
         let notification_tx = self.rpc_handler_tx.clone();
@@ -329,12 +360,29 @@ impl Rpc {
             );
             return;
         }
+        let req_len = request.raw_request.len();
+        let permit = match self.inflight_rpc_budget.try_reserve(req_len) {
+            Some(permit) => permit,
+            None => {
+                counters::LIBRA_NETWORK_RPC_MESSAGES
+                    .with_label_values(&[RESPONSE_LABEL, DECLINED_LABEL])
+                    .inc();
+                warn!(
+                    "In-flight RPC memory budget exhausted. Not processing inbound rpc request \
+                     of {} bytes from {}",
+                    req_len, peer_id_str
+                );
+                return;
+            }
+        };
         let timeout = self.inbound_rpc_timeout;
         // Handle request with timeout.
         let f = async move {
+            // The request is in flight until the task completes.
+            let _permit = permit;
             if let Err(err) = tokio::time::timeout(
                 timeout,
-                handle_inbound_request_inner(notification_tx, request, peer_handle),
+                handle_inbound_request_inner(notification_tx, request, trace_context, peer_handle),
             )
             .map_err(Into::<RpcError>::into)
             .map(|r| r.and_then(|x| x))
@@ -363,20 +411,36 @@ impl Rpc {
     async fn handle_outbound_rpc(
         &mut self,
         req: OutboundRpcRequest,
+        trace: RequestTrace,
         outbound_rpc_tasks: &mut OutboundRpcTasks,
     ) {
         // If we already have too many pending RPCs, return error immediately.
         if outbound_rpc_tasks.len() as u32 == self.max_concurrent_outbound_rpcs {
             warn!(
-                "Pending outbound RPCs ({}) exceeding limit ({}).",
+                "Pending outbound RPCs ({}) exceeding limit ({}), dropping {}.",
                 outbound_rpc_tasks.len(),
                 self.max_concurrent_outbound_rpcs,
+                trace,
             );
             let _result = req.res_tx.send(Err(RpcError::TooManyPending(
                 self.max_concurrent_outbound_rpcs,
             )));
             return;
         }
+        let permit = match self.inflight_rpc_budget.try_reserve(req.data.len()) {
+            Some(permit) => permit,
+            None => {
+                warn!(
+                    "In-flight RPC memory budget exhausted, dropping {} of {} bytes.",
+                    trace,
+                    req.data.len(),
+                );
+                let _result = req
+                    .res_tx
+                    .send(Err(RpcError::MemoryBudgetExceeded(req.data.len())));
+                return;
+            }
+        };
 
         // Unpack request.
         let OutboundRpcRequest {
@@ -400,11 +464,20 @@ impl Rpc {
             .insert(request_id, (protocol, response_tx));
 
         let f = async move {
+            // The request is in flight until the task completes.
+            let _permit = permit;
             // Wrap the outbound rpc protocol with the requested timeout window.
             let mut f_rpc_res = tokio::time::timeout(
                 timeout,
                 // Future to run the actual outbound rpc protocol.
-                handle_outbound_rpc_inner(peer_handle, request_id, protocol, req_data, response_rx),
+                handle_outbound_rpc_inner(
+                    peer_handle,
+                    request_id,
+                    protocol,
+                    req_data,
+                    trace,
+                    response_rx,
+                ),
             )
             .map_err(Into::<RpcError>::into)
             .map(|r| r.and_then(|x| x))
@@ -456,19 +529,26 @@ async fn handle_outbound_rpc_inner(
     request_id: RequestId,
     protocol: ProtocolId,
     req_data: Bytes,
-    response_rx: oneshot::Receiver<RpcResponse>,
+    mut trace: RequestTrace,
+    response_rx: oneshot::Receiver<Result<RpcResponse, RpcError>>,
 ) -> Result<Bytes, RpcError> {
     let req_len = req_data.len();
     let peer_id = peer_handle.peer_id();
     let peer_id_str = peer_id.to_string();
+    // The span of the RPC ends once the response is received or the RPC failed.
+    let _span = trace.root_span("outbound_rpc");
+    trace.stage("rpc_queue");
 
     // Create NetworkMessage to be sent over the wire.
-    let request = NetworkMessage::RpcRequest(RpcRequest {
-        request_id,
-        // TODO: Use default priority for now. To be exposed via network API.
-        priority: Priority::default(),
-        protocol_id: protocol,
-        raw_request: Vec::from(req_data.as_ref()),
+    let request = NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+        request: RpcRequest {
+            request_id,
+            // TODO: Use default priority for now. To be exposed via network API.
+            priority: Priority::default(),
+            protocol_id: protocol,
+            raw_request: Vec::from(req_data.as_ref()),
+        },
+        trace_context: trace.context(),
     });
 
     // Send outbound request to peer_handle.
@@ -480,10 +560,25 @@ async fn handle_outbound_rpc_inner(
     let prototol_id_descriptor = protocol.as_str();
     // Start timer to collect RPC latency.
     let timer = counters::LIBRA_NETWORK_RPC_LATENCY
-        .with_label_values(&[REQUEST_LABEL, prototol_id_descriptor, &peer_id_str])
+        .with_label_values(&[
+            REQUEST_LABEL,
+            prototol_id_descriptor,
+            counters::LIBRA_NETWORK_RPC_LATENCY_PEERS.label(&peer_id_str),
+        ])
         .start_timer();
 
     peer_handle.send_message(request, protocol).await?;
+    trace!(
+        "Wrote {} as request_id {} to peer: {:?}",
+        trace,
+        request_id,
+        peer_id_str
+    );
+    // The compression, the serialization and the write of the request on the socket.
+    trace.stage("rpc_write");
+    let wire_span = Span::child_of("rpc_wire", &trace.context());
+    // The request is no longer in flight once written, regardless of the response.
+    drop(trace);
 
     // Collect counters for requests sent.
     counters::LIBRA_NETWORK_RPC_MESSAGES
@@ -499,7 +594,9 @@ async fn handle_outbound_rpc_inner(
         request_id,
         peer_id_str
     );
-    let response = response_rx.await?;
+    let response = response_rx.await??;
+    // The round trip of the request on the wire, including the handling by the remote node.
+    drop(wire_span);
     let latency = timer.stop_and_record();
     trace!(
         "Received response for request_id {} from peer: {:?} \
@@ -524,11 +621,17 @@ async fn handle_outbound_rpc_inner(
 async fn handle_inbound_request_inner(
     mut notification_tx: channel::Sender<RpcNotification>,
     request: RpcRequest,
+    trace_context: Option<TraceContext>,
     mut peer_handle: PeerHandle,
 ) -> Result<(), RpcError> {
     let req_data = request.raw_request;
     let request_id = request.request_id;
     let peer_id = peer_handle.peer_id();
+    // The span of the handling of the request is linked to the span of the RPC of the remote node.
+    let span = match trace_context {
+        Some(context) => Span::child_of("inbound_rpc", &context),
+        None => Span::root("inbound_rpc"),
+    };
 
     trace!(
         "Received inbound request with request_id {} from peer: {:?}",
@@ -550,6 +653,7 @@ async fn handle_inbound_request_inner(
         data: Bytes::from(req_data),
         res_tx,
     });
+    let handler_span = Span::child_of("rpc_handler", &span.context());
     notification_tx.send(notification).await?;
 
     // Wait for response from upper layer.
@@ -559,6 +663,7 @@ async fn handle_inbound_request_inner(
         peer_id.short_str()
     );
     let res_data = res_rx.await??;
+    drop(handler_span);
     let res_len = res_data.len();
 
     // Send response to remote peer.
@@ -572,9 +677,11 @@ async fn handle_inbound_request_inner(
         request_id,
         priority: request.priority,
     };
+    let write_span = Span::child_of("rpc_write", &span.context());
     peer_handle
         .send_message(NetworkMessage::RpcResponse(response), request.protocol_id)
         .await?;
+    drop(write_span);
 
     // Collect counters for sent response.
     counters::LIBRA_NETWORK_RPC_MESSAGES
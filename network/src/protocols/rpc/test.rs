@@ -9,12 +9,18 @@
 use super::{error::RpcError, *};
 use crate::{
     counters::{CANCELED_LABEL, FAILED_LABEL, REQUEST_LABEL, RESPONSE_LABEL},
-    peer::{PeerNotification, PeerRequest},
+    peer::{DisconnectReason, PeerNotification, PeerRequest},
     peer_manager::PeerManagerError,
+    protocols::wire::handshake::v1::MessagingProtocolVersion,
+    quota::{NetworkQuota, QuotaLimits, QuotaPermit, ResourceBudget},
+    transport::{ConnectionId, ConnectionMetadata},
 };
 use anyhow::anyhow;
 use futures::future::join;
+use libra_config::network_id::NetworkId;
+use libra_network_address::NetworkAddress;
 use libra_types::PeerId;
+use netcore::transport::ConnectionOrigin;
 use serial_test::serial;
 use tokio::runtime::{Handle, Runtime};
 
@@ -29,7 +35,21 @@ fn reset_counters() {
 fn start_rpc_actor(
     executor: Handle,
 ) -> (
-    channel::Sender<OutboundRpcRequest>,
+    channel::Sender<(OutboundRpcRequest, RequestTrace)>,
+    channel::Receiver<RpcNotification>,
+    channel::Receiver<PeerRequest>,
+    channel::Sender<PeerNotification>,
+) {
+    let budget =
+        NetworkQuota::new(&NetworkId::Validator, QuotaLimits::default()).inflight_rpc_bytes;
+    start_rpc_actor_with_budget(executor, budget)
+}
+
+fn start_rpc_actor_with_budget(
+    executor: Handle,
+    inflight_rpc_budget: ResourceBudget,
+) -> (
+    channel::Sender<(OutboundRpcRequest, RequestTrace)>,
     channel::Receiver<RpcNotification>,
     channel::Receiver<PeerRequest>,
     channel::Sender<PeerNotification>,
@@ -48,6 +68,7 @@ fn start_rpc_actor(
         Duration::from_secs(1), // 1 second inbound rpc timeout.
         10,                     // max_concurrent_outbound_rpcs
         10,                     // max_concurrent_inbound_rpcs
+        inflight_rpc_budget,
     );
     executor.spawn(rpc.start());
     (rpc_requests_tx, rpc_notifs_rx, peer_reqs_rx, peer_notifs_tx)
@@ -64,10 +85,10 @@ async fn expect_two_requests(
         match peer_rx.next().await.unwrap() {
             PeerRequest::SendMessage(message, protocol, res_tx) => {
                 if protocol == expected_protocol_a {
-                    assert_eq!(message, expected_message_a);
+                    assert_eq!(without_trace_context(message), expected_message_a);
                 } else {
                     assert_eq!(protocol, expected_protocol_b);
-                    assert_eq!(message, expected_message_b);
+                    assert_eq!(without_trace_context(message), expected_message_b);
                 }
                 res_tx.send(Ok(())).unwrap();
             }
@@ -76,6 +97,15 @@ async fn expect_two_requests(
     }
 }
 
+// The outbound requests propagate the trace of the request, which differs on every call.
+fn without_trace_context(message: NetworkMessage) -> NetworkMessage {
+    match message {
+        NetworkMessage::TracedRpcRequest(traced) => NetworkMessage::RpcRequest(traced.request),
+        NetworkMessage::RpcRequest(request) => panic!("Untraced outbound request: {:?}", request),
+        message => message,
+    }
+}
+
 async fn expect_successful_send(
     peer_rx: &mut channel::Receiver<PeerRequest>,
     expected_protocol: ProtocolId,
@@ -85,7 +115,7 @@ async fn expect_successful_send(
     match peer_rx.next().await.unwrap() {
         PeerRequest::SendMessage(message, protocol, res_tx) => {
             assert_eq!(protocol, expected_protocol);
-            assert_eq!(message, expected_message);
+            assert_eq!(without_trace_context(message), expected_message);
             res_tx.send(Ok(())).unwrap();
         }
         req => panic!("Unexpected PeerRequest: {:?}, expected OpenSubstream", req),
@@ -116,7 +146,7 @@ async fn expect_failed_send(
     match peer_rx.next().await.unwrap() {
         PeerRequest::SendMessage(message, protocol, res_tx) => {
             assert_eq!(protocol, expected_protocol);
-            assert_eq!(message, expected_message);
+            assert_eq!(without_trace_context(message), expected_message);
             res_tx
                 .send(Err(PeerManagerError::Error(anyhow!("failed to send"))))
                 .unwrap();
@@ -173,7 +203,10 @@ fn outbound_rpc_success() {
         expect_successful_send(&mut peer_reqs_rx, protocol_id, request).await;
         // Notify about inbound RpcResponse.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(response))
+            .send(PeerNotification::NewMessage(
+                response,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
     };
@@ -182,12 +215,15 @@ fn outbound_rpc_success() {
     let f_send_rpc = async move {
         let (res_tx, res_rx) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id,
-                data: req_data.clone(),
-                res_tx,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data.clone(),
+                    res_tx,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -242,12 +278,18 @@ fn outbound_rpc_concurrent() {
         .await;
         // Send response for second request first.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(response_b))
+            .send(PeerNotification::NewMessage(
+                response_b,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Send response for first request next.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(response_a))
+            .send(PeerNotification::NewMessage(
+                response_a,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
     };
@@ -257,24 +299,30 @@ fn outbound_rpc_concurrent() {
         // Send first request.
         let (res_tx_a, res_rx_a) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id_a,
-                data: req_data_a.clone(),
-                res_tx: res_tx_a,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id_a,
+                    data: req_data_a.clone(),
+                    res_tx: res_tx_a,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
         // Send second request.
         let (res_tx_b, res_rx_b) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id_b,
-                data: req_data_b.clone(),
-                res_tx: res_tx_b,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id_b,
+                    data: req_data_b.clone(),
+                    res_tx: res_tx_b,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -312,12 +360,15 @@ fn outbound_rpc_timeout() {
     let f_send_rpc = async move {
         let (res_tx, res_rx) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id,
-                data: req_data,
-                res_tx,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data,
+                    res_tx,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -330,6 +381,68 @@ fn outbound_rpc_timeout() {
     rt.block_on(f);
 }
 
+// Test that pending outbound rpcs fail with the reason of the disconnection of the peer.
+#[test]
+#[serial]
+fn outbound_rpc_peer_disconnected() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+
+    let mut rt = Runtime::new().unwrap();
+    let (mut rpc_requests_tx, _rpc_notifs_rx, mut peer_reqs_rx, mut peer_notifs_tx) =
+        start_rpc_actor(rt.handle().clone());
+
+    let protocol_id = RPC_PROTOCOL_A;
+    let req_data = Bytes::from_static(b"hello");
+    let message = create_network_request(
+        0, // This is the first request.
+        protocol_id,
+        req_data.clone(),
+    );
+
+    let f = async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        rpc_requests_tx
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data,
+                    res_tx,
+                    timeout: Duration::from_secs(10),
+                },
+                RequestTrace::start(),
+            ))
+            .await
+            .unwrap();
+        expect_successful_send(&mut peer_reqs_rx, protocol_id, message).await;
+
+        // The peer disconnects before responding.
+        let metadata = ConnectionMetadata::new(
+            PeerId::random(),
+            ConnectionId::default(),
+            NetworkAddress::mock(),
+            ConnectionOrigin::Outbound,
+            MessagingProtocolVersion::V1,
+            [protocol_id].iter().into(),
+            NetworkId::Validator,
+        );
+        peer_notifs_tx
+            .send(PeerNotification::PeerDisconnected(
+                metadata,
+                DisconnectReason::ConnectionLost,
+            ))
+            .await
+            .unwrap();
+
+        let result: Result<Bytes, RpcError> = res_rx.await.unwrap();
+        assert!(matches!(
+            result,
+            Err(RpcError::PeerDisconnected(DisconnectReason::ConnectionLost))
+        ));
+    };
+
+    rt.block_on(f);
+}
+
 // Test that outbound rpcs can be canceled immediately after request.
 #[test]
 #[serial]
@@ -347,12 +460,15 @@ fn outbound_cancellation_before_send() {
     // Make an outbound rpc request. listener does not reply with response within timeout.
     let f_send_rpc = async move {
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id,
-                data: req_data.clone(),
-                res_tx,
-                timeout: Duration::from_secs(100), // use a large timeout value.
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data.clone(),
+                    res_tx,
+                    timeout: Duration::from_secs(100), // use a large timeout value.
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -388,12 +504,15 @@ fn outbound_cancellation_before_recv() {
     // Make an outbound rpc request and then cancel before receiving response.
     let f_send_rpc = async move {
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id,
-                data: req_data.clone(),
-                res_tx,
-                timeout: Duration::from_secs(100), // use a large timeout value.
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data.clone(),
+                    res_tx,
+                    timeout: Duration::from_secs(100), // use a large timeout value.
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -436,12 +555,15 @@ fn outbound_rpc_failed_request_delivery() {
     let f_send_rpc = async move {
         let (res_tx, res_rx) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id,
-                data: req_data,
-                res_tx,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: req_data,
+                    res_tx,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -478,7 +600,10 @@ fn inbound_rpc_success() {
 
         // Send inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request))
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Expect response.
@@ -533,12 +658,18 @@ fn inbound_rpc_concurrent() {
 
         // Send first inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request_a))
+            .send(PeerNotification::NewMessage(
+                request_a,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Send second inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request_b))
+            .send(PeerNotification::NewMessage(
+                request_b,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Expect responses.
@@ -595,7 +726,10 @@ fn inbound_rpc_timeout() {
         let request = create_network_request(0 as RequestId, protocol_id, req_data);
         // Send inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request))
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Wait for time greater than inbound_rpc_timeout and check for failure counter.
@@ -633,7 +767,10 @@ fn inbound_rpc_failed_response_delivery() {
         let response = create_network_response(0 as RequestId, expected_resp_data);
         // Send inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request))
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Expect failed response.
@@ -684,7 +821,10 @@ fn inbound_rpc_failed_upstream_delivery() {
         drop(rpc_notifs_rx);
         // Send inbound request to RPC module.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request))
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
         // Failure counter should increase.
@@ -734,7 +874,10 @@ fn concurrent_inbound_outbound() {
         expect_successful_send(&mut peer_reqs_rx, protocol_id_a, request_a).await;
         // Send  notification about inbound RPC.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(request_b))
+            .send(PeerNotification::NewMessage(
+                request_b,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
 
@@ -743,7 +886,10 @@ fn concurrent_inbound_outbound() {
 
         // Notify about response to outbound RPC.
         peer_notifs_tx
-            .send(PeerNotification::NewMessage(response_a))
+            .send(PeerNotification::NewMessage(
+                response_a,
+                QuotaPermit::default(),
+            ))
             .await
             .unwrap();
     };
@@ -753,12 +899,15 @@ fn concurrent_inbound_outbound() {
         // Send first request.
         let (res_tx_a, res_rx_a) = oneshot::channel();
         rpc_requests_tx
-            .send(OutboundRpcRequest {
-                protocol: protocol_id_a,
-                data: req_data_a.clone(),
-                res_tx: res_tx_a,
-                timeout: Duration::from_millis(100),
-            })
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id_a,
+                    data: req_data_a.clone(),
+                    res_tx: res_tx_a,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
             .await
             .unwrap();
 
@@ -778,3 +927,104 @@ fn concurrent_inbound_outbound() {
     let f = join(f_send_rpc, f_mock_peer);
     rt.block_on(f);
 }
+
+// Test that outbound and inbound rpcs are rejected while the in-flight rpc memory budget is
+// exhausted, and accepted again once it is released.
+#[test]
+#[serial]
+fn rpc_memory_budget_exceeded() {
+    ::libra_logger::Logger::new().environment_only(true).init();
+
+    let mut rt = Runtime::new().unwrap();
+    let budget = NetworkQuota::new(
+        &NetworkId::Validator,
+        QuotaLimits {
+            max_inflight_rpc_bytes: Some(8),
+            ..QuotaLimits::default()
+        },
+    )
+    .inflight_rpc_bytes;
+    let (mut rpc_requests_tx, mut rpc_notifs_rx, mut peer_reqs_rx, mut peer_notifs_tx) =
+        start_rpc_actor_with_budget(rt.handle().clone(), budget.clone());
+
+    let protocol_id = RPC_PROTOCOL_A;
+    let f = async move {
+        // The first request is in flight until it times out.
+        let (res_tx, first_res_rx) = oneshot::channel();
+        rpc_requests_tx
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: Bytes::from_static(b"hello"),
+                    res_tx,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
+            .await
+            .unwrap();
+        let request = create_network_request(0, protocol_id, Bytes::from_static(b"hello"));
+        expect_successful_send(&mut peer_reqs_rx, protocol_id, request).await;
+        assert_eq!(budget.used(), 5);
+
+        // The second request would exceed the budget.
+        let (res_tx, res_rx) = oneshot::channel();
+        rpc_requests_tx
+            .send((
+                OutboundRpcRequest {
+                    protocol: protocol_id,
+                    data: Bytes::from_static(b"world"),
+                    res_tx,
+                    timeout: Duration::from_millis(100),
+                },
+                RequestTrace::start(),
+            ))
+            .await
+            .unwrap();
+        match res_rx.await.unwrap() {
+            Err(RpcError::MemoryBudgetExceeded(5)) => {}
+            res => panic!("Unexpected response: {:?}", res),
+        }
+
+        // So would an inbound request, which is declined.
+        let request = create_network_request(0, protocol_id, Bytes::from_static(b"hola!"));
+        peer_notifs_tx
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
+            .await
+            .unwrap();
+        while counters::LIBRA_NETWORK_RPC_MESSAGES
+            .with_label_values(&[RESPONSE_LABEL, DECLINED_LABEL])
+            .get() as u64
+            != 1
+        {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(budget.used(), 5);
+
+        // The budget is released once the first request times out.
+        match first_res_rx.await.unwrap() {
+            Err(RpcError::TimedOut) => {}
+            res => panic!("Unexpected response: {:?}", res),
+        }
+        while budget.used() != 0 {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+
+        // The inbound requests are accepted again.
+        let request = create_network_request(1, protocol_id, Bytes::from_static(b"hola!"));
+        peer_notifs_tx
+            .send(PeerNotification::NewMessage(
+                request,
+                QuotaPermit::default(),
+            ))
+            .await
+            .unwrap();
+        match rpc_notifs_rx.next().await.unwrap() {
+            RpcNotification::RecvRpc(req) => assert_eq!(req.data, Bytes::from_static(b"hola!")),
+        }
+    };
+    rt.block_on(f);
+}
@@ -9,9 +9,29 @@
 //! supported messaging protocol versions to a bit vector representing application protocols
 //! supported over that messaging protocol. On receipt, both ends will determine the highest
 //! intersecting messaging protocol version and use that for the remainder of the session.
+//!
+//! The layout of the `HandshakeMsg` is fixed, as it is decoded strictly by every node. On the
+//! sessions of messaging protocol version V2, both ends then send a serialized and
+//! length-prefixed `HandshakeExtensions` to each other, right after the `HandshakeMsg`:
+//!
+//! * The extensions advertise the compression codecs supported by the node: if both ends support
+//!   a codec, the payloads of the DirectSend and RPC messages of the session are compressed, see
+//!   [`compression`](crate::protocols::wire::messaging::v1::compression).
+//! * The extensions advertise the encodings of the payloads supported by the application
+//!   protocols which support more than LCS. The messages of a protocol are encoded with the newest
+//!   encoding supported by both ends, and with LCS otherwise, so that the protocols migrate to
+//!   cheaper encodings incrementally, as the nodes are upgraded.
+//! * The extensions report the address at which the node observed the other end of the
+//!   connection, for the other end to learn its external address, see
+//!   [`observed_addrs`](crate::observed_addrs).
+//!
+//! The sessions of version V1 are neither compressed nor report any observed address, and all
+//! their protocols use LCS.
 
+use crate::protocols::network::decoding::{Decoded, Decoding, MAX_TRAILING_BYTES};
 use libra_config::network_id::NetworkId;
-use serde::{Deserialize, Serialize};
+use libra_network_address::NetworkAddress;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::BTreeMap, convert::TryInto, fmt, iter::Iterator};
 
 #[cfg(test)]
@@ -20,7 +40,7 @@ mod test;
 /// Unique identifier associated with each application protocol.
 /// New application protocols can be added without bumping up the MessagingProtocolVersion.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Hash, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
 pub enum ProtocolId {
     ConsensusRpc = 0,
     ConsensusDirectSend = 1,
@@ -30,6 +50,10 @@ pub enum ProtocolId {
     HealthCheckerRpc = 5,
     IdentityDirectSend = 6,
     OnchainDiscoveryRpc = 7,
+    LatencyRpc = 8,
+    DhtRpc = 9,
+    PexRpc = 10,
+    DialBackRpc = 11,
 }
 
 impl ProtocolId {
@@ -44,8 +68,42 @@ impl ProtocolId {
             HealthCheckerRpc => "HealthCheckerRpc",
             IdentityDirectSend => "IdentityDirectSend",
             OnchainDiscoveryRpc => "OnchainDiscoveryRpc",
+            LatencyRpc => "LatencyRpc",
+            DhtRpc => "DhtRpc",
+            PexRpc => "PexRpc",
+            DialBackRpc => "DialBackRpc",
         }
     }
+
+    /// How the messages of the protocol are decoded. The best-effort protocols opt in to
+    /// forward-compatible decoding, so that the messages added to them by a newer version are
+    /// ignored by the older nodes during rolling upgrades. The messages of consensus are signed
+    /// and must be understood fully, and the other protocols are internal to the network.
+    pub fn decoding(self) -> Decoding {
+        use ProtocolId::*;
+        match self {
+            MempoolDirectSend
+            | StateSynchronizerDirectSend
+            | DiscoveryDirectSend
+            | HealthCheckerRpc
+            | LatencyRpc
+            | PexRpc
+            | DialBackRpc => Decoding::ForwardCompatible {
+                max_trailing_bytes: MAX_TRAILING_BYTES,
+            },
+            ConsensusRpc | ConsensusDirectSend | IdentityDirectSend | OnchainDiscoveryRpc
+            | DhtRpc => Decoding::Strict,
+        }
+    }
+
+    /// The encodings of the payloads of the protocol supported by this node. The `NetworkSender`
+    /// and `NetworkEvents` encode and decode the messages of the protocol with the encoding
+    /// negotiated on the connection with each peer. A protocol supporting more than LCS must also
+    /// encode its RPC responses with it, see
+    /// [`NetworkSender::encoding`](crate::protocols::network::NetworkSender::encoding).
+    pub fn encodings(self) -> &'static [Encoding] {
+        &[Encoding::Lcs]
+    }
 }
 
 impl fmt::Display for ProtocolId {
@@ -54,9 +112,143 @@ impl fmt::Display for ProtocolId {
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
 pub struct SupportedProtocols(bitvec::BitVec);
 
+/// Compression codecs of the payloads of the messages, see
+/// [`compression`](crate::protocols::wire::messaging::v1::compression).
+/// New codecs can be added without bumping up the MessagingProtocolVersion.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize, Serialize)]
+pub enum CompressionCodec {
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl CompressionCodec {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompressionCodec::Lz4 => "lz4",
+            CompressionCodec::Zstd => "zstd",
+        }
+    }
+}
+
+/// The compression codecs supported by this node, in order of preference.
+pub const SUPPORTED_COMPRESSION_CODECS: [CompressionCodec; 2] =
+    [CompressionCodec::Zstd, CompressionCodec::Lz4];
+
+/// A bit-vector of compression codecs, so that the codecs unknown to a node are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CompressionCodecs(bitvec::BitVec);
+
+impl<'a, T: Iterator<Item = &'a CompressionCodec>> From<T> for CompressionCodecs {
+    fn from(codecs: T) -> Self {
+        let mut bv = bitvec::BitVec::default();
+        codecs.for_each(|codec| bv.set(*codec as u8));
+        Self(bv)
+    }
+}
+
+impl CompressionCodecs {
+    /// Returns whether `codec` is supported.
+    pub fn contains(&self, codec: CompressionCodec) -> bool {
+        self.0.is_set(codec as u8)
+    }
+
+    /// Returns the codec of `SUPPORTED_COMPRESSION_CODECS` preferred by this node among these
+    /// codecs.
+    pub fn preferred(&self) -> Option<CompressionCodec> {
+        SUPPORTED_COMPRESSION_CODECS
+            .iter()
+            .copied()
+            .find(|codec| self.contains(*codec))
+    }
+}
+
+/// Encodings of the payloads of the messages of the application protocols. Every protocol supports
+/// LCS, which is used unless both ends support another encoding of the protocol.
+/// New encodings can be added without bumping up the MessagingProtocolVersion.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Encoding {
+    Lcs = 0,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Lcs => "lcs",
+        }
+    }
+
+    /// Encodes `message` with this encoding.
+    pub fn encode<T: Serialize>(self, message: &T) -> lcs::Result<Vec<u8>> {
+        match self {
+            Encoding::Lcs => lcs::to_bytes(message),
+        }
+    }
+
+    /// Decodes the message `data` encoded with this encoding, as per the `decoding` of its
+    /// protocol.
+    pub fn decode<T: DeserializeOwned>(
+        self,
+        decoding: Decoding,
+        data: &[u8],
+    ) -> lcs::Result<Decoded<T>> {
+        match self {
+            Encoding::Lcs => decoding.decode(data),
+        }
+    }
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Lcs
+    }
+}
+
+/// A bit-vector of encodings, so that the encodings unknown to a node are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Encodings(bitvec::BitVec);
+
+impl<'a, T: Iterator<Item = &'a Encoding>> From<T> for Encodings {
+    fn from(encodings: T) -> Self {
+        let mut bv = bitvec::BitVec::default();
+        encodings.for_each(|encoding| bv.set(*encoding as u8));
+        Self(bv)
+    }
+}
+
+impl Encodings {
+    /// Returns whether `encoding` is supported.
+    pub fn contains(&self, encoding: Encoding) -> bool {
+        self.0.is_set(encoding as u8)
+    }
+
+    /// Returns the newest encoding among these encodings, i.e., of the highest number, if any is
+    /// known to this node.
+    fn newest(&self) -> Option<Encoding> {
+        let last_bit = self.0.last_set_bit()?;
+        (0..=last_bit)
+            .rev()
+            .filter(|i| self.0.is_set(*i))
+            .find_map(|i| lcs::from_bytes(&[i]).ok())
+    }
+}
+
+/// The encodings negotiated for the application protocols of a connection. The protocols without
+/// any negotiated encoding use LCS.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProtocolEncodings(BTreeMap<ProtocolId, Encoding>);
+
+impl ProtocolEncodings {
+    /// Returns the encoding of the messages of `protocol`.
+    pub fn get(&self, protocol: ProtocolId) -> Encoding {
+        self.0.get(&protocol).copied().unwrap_or_default()
+    }
+}
+
 /// The HandshakeMsg contains a mapping from MessagingProtocolVersion suppported by the node to a
 /// bit-vector specifying application-level protocols supported over that version.
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
@@ -65,12 +257,39 @@ pub struct HandshakeMsg {
     pub network_id: NetworkId,
 }
 
+/// The HandshakeExtensions are exchanged after the HandshakeMsg on the sessions of
+/// MessagingProtocolVersion V2. They contain the compression codecs supported by the node, the
+/// encodings supported by the protocols which support more than LCS, and the address at which the
+/// node observed the other end of the connection, if any.
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct HandshakeExtensions {
+    pub compression_codecs: CompressionCodecs,
+    /// Keyed by the `ProtocolId` numbers, so that the protocols unknown to a node are ignored.
+    pub encodings: BTreeMap<u8, Encodings>,
+    pub observed_addr: Option<NetworkAddress>,
+}
+
 /// Enum representing different versions of the Libra network protocol. These should be listed from
 /// old to new, old having the smallest value.
 /// We derive `PartialOrd` since nodes need to find highest intersecting protocol version.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug, Hash, Deserialize, Serialize)]
 pub enum MessagingProtocolVersion {
     V1 = 0,
+    /// V1 followed by the exchange of the `HandshakeExtensions`, and with the `TracedRpcRequest`
+    /// messages.
+    V2 = 1,
+}
+
+impl MessagingProtocolVersion {
+    /// Returns whether the `HandshakeExtensions` are exchanged on the sessions of this version.
+    pub fn has_extensions(self) -> bool {
+        self >= MessagingProtocolVersion::V2
+    }
+
+    /// Returns whether the RPC requests of the sessions of this version may carry their traces.
+    pub fn has_traced_rpcs(self) -> bool {
+        self >= MessagingProtocolVersion::V2
+    }
 }
 
 impl TryInto<Vec<ProtocolId>> for SupportedProtocols {
@@ -99,6 +318,29 @@ impl<'a, T: Iterator<Item = &'a ProtocolId>> From<T> for SupportedProtocols {
 }
 
 impl SupportedProtocols {
+    /// Returns whether `protocol` is supported.
+    pub fn contains(&self, protocol: ProtocolId) -> bool {
+        self.0.is_set(protocol as u8)
+    }
+
+    /// Returns whether no protocol is supported.
+    pub fn is_empty(&self) -> bool {
+        self.0.count_ones() == 0
+    }
+
+    /// Returns whether the health checker is the only supported protocol, e.g., on a connection
+    /// with a peer sharing no application protocol other than the health checker.
+    pub fn is_health_check_only(&self) -> bool {
+        self.contains(ProtocolId::HealthCheckerRpc) && self.0.count_ones() == 1
+    }
+
+    /// Adds `protocols` to the supported protocols.
+    pub fn extend<'a>(&mut self, protocols: impl IntoIterator<Item = &'a ProtocolId>) {
+        protocols
+            .into_iter()
+            .for_each(|protocol| self.0.set(*protocol as u8));
+    }
+
     /// Returns a new SupportedProtocols struct that is an intersection.
     fn intersection(self, other: SupportedProtocols) -> SupportedProtocols {
         SupportedProtocols(self.0 & other.0)
@@ -152,3 +394,54 @@ impl HandshakeMsg {
         None
     }
 }
+
+impl HandshakeExtensions {
+    /// The extensions of a node supporting all of `SUPPORTED_COMPRESSION_CODECS`, advertising the
+    /// encodings of the `application_protocols` which support more than LCS.
+    pub fn new(application_protocols: &SupportedProtocols) -> Self {
+        let protocols: Vec<ProtocolId> = application_protocols
+            .clone()
+            .try_into()
+            .expect("Local protocols are known");
+        Self {
+            compression_codecs: SUPPORTED_COMPRESSION_CODECS.iter().into(),
+            encodings: protocols
+                .into_iter()
+                .filter(|protocol| protocol.encodings() != [Encoding::Lcs])
+                .map(|protocol| (protocol as u8, protocol.encodings().iter().into()))
+                .collect(),
+            observed_addr: None,
+        }
+    }
+
+    /// Returns the compression codecs supported by both nodes, whose payloads are compressed if
+    /// there is any.
+    pub fn find_common_compression_codecs(&self, other: &HandshakeExtensions) -> CompressionCodecs {
+        CompressionCodecs(self.compression_codecs.0.clone() & other.compression_codecs.0.clone())
+    }
+
+    /// Returns the encodings of the common `protocols` negotiated with the other node: the newest
+    /// encoding supported by both nodes, so that both ends agree whatever their preferences.
+    pub fn find_common_encodings(
+        &self,
+        other: &HandshakeExtensions,
+        protocols: &SupportedProtocols,
+    ) -> ProtocolEncodings {
+        ProtocolEncodings(
+            self.encodings
+                .iter()
+                .filter_map(|(id, encodings)| {
+                    // the protocols unknown to this node are skipped
+                    let protocol: ProtocolId = lcs::from_bytes(&[*id]).ok()?;
+                    if !protocols.contains(protocol) {
+                        return None;
+                    }
+                    let other_encodings = other.encodings.get(id)?;
+                    Encodings(encodings.0.clone() & other_encodings.0.clone())
+                        .newest()
+                        .map(|encoding| (protocol, encoding))
+                })
+                .collect(),
+        )
+    }
+}
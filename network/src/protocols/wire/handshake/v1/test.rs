@@ -8,9 +8,35 @@ use super::*;
 fn net_protocol() -> lcs::Result<()> {
     let protocol = MessagingProtocolVersion::V1;
     assert_eq!(lcs::to_bytes(&protocol)?, vec![0x00]);
+    let protocol = MessagingProtocolVersion::V2;
+    assert_eq!(lcs::to_bytes(&protocol)?, vec![0x01]);
     Ok(())
 }
 
+#[test]
+fn extensions_by_version() {
+    assert!(!MessagingProtocolVersion::V1.has_extensions());
+    assert!(MessagingProtocolVersion::V2.has_extensions());
+    assert!(!MessagingProtocolVersion::V1.has_traced_rpcs());
+    assert!(MessagingProtocolVersion::V2.has_traced_rpcs());
+
+    // a node supporting V2 falls back to V1 with the nodes only supporting V1
+    let protocols: SupportedProtocols = [ProtocolId::ConsensusRpc].iter().into();
+    let mut h1 = HandshakeMsg::new(NetworkId::Validator);
+    h1.add(MessagingProtocolVersion::V1, protocols.clone());
+    h1.add(MessagingProtocolVersion::V2, protocols.clone());
+    let mut h2 = HandshakeMsg::new(NetworkId::Validator);
+    h2.add(MessagingProtocolVersion::V1, protocols.clone());
+    assert_eq!(
+        Some((MessagingProtocolVersion::V1, protocols.clone())),
+        h1.find_common_protocols(&h2)
+    );
+    assert_eq!(
+        Some((MessagingProtocolVersion::V2, protocols)),
+        h1.find_common_protocols(&h1)
+    );
+}
+
 #[test]
 fn protocols_to_from_vec() {
     let supported_protocols: SupportedProtocols =
@@ -87,3 +113,105 @@ fn common_protocols() {
         h1.find_common_protocols(&h2)
     );
 }
+
+#[test]
+fn common_compression_codecs() {
+    let h1 = HandshakeExtensions::new(&SupportedProtocols::default());
+    assert_eq!(
+        h1.find_common_compression_codecs(&h1).preferred(),
+        Some(CompressionCodec::Zstd)
+    );
+
+    let mut h2 = HandshakeExtensions::new(&SupportedProtocols::default());
+    h2.compression_codecs = [CompressionCodec::Lz4].iter().into();
+    let common = h1.find_common_compression_codecs(&h2);
+    assert_eq!(common, h2.find_common_compression_codecs(&h1));
+    assert!(!common.contains(CompressionCodec::Zstd));
+    assert_eq!(common.preferred(), Some(CompressionCodec::Lz4));
+
+    // a node without any codec does not compress
+    h2.compression_codecs = CompressionCodecs::default();
+    assert_eq!(h1.find_common_compression_codecs(&h2).preferred(), None);
+
+    // the codecs unknown to a node are ignored
+    let mut codecs = bitvec::BitVec::default();
+    codecs.set(CompressionCodec::Lz4 as u8);
+    codecs.set(200);
+    h2.compression_codecs = CompressionCodecs(codecs);
+    assert_eq!(
+        h1.find_common_compression_codecs(&h2).preferred(),
+        Some(CompressionCodec::Lz4)
+    );
+}
+
+#[test]
+fn common_encodings() {
+    let protocol = ProtocolId::StateSynchronizerDirectSend;
+    let protocols: SupportedProtocols = [protocol].iter().into();
+    let mut h1 = HandshakeExtensions::new(&protocols);
+    h1.encodings
+        .insert(protocol as u8, [Encoding::Lcs].iter().into());
+
+    // the encodings unknown to a node are ignored, and both ends agree
+    let mut h2 = HandshakeExtensions::new(&protocols);
+    let mut encodings = bitvec::BitVec::default();
+    encodings.set(Encoding::Lcs as u8);
+    encodings.set(200);
+    h2.encodings.insert(protocol as u8, Encodings(encodings));
+    let common = h1.find_common_encodings(&h2, &protocols);
+    assert_eq!(common, h2.find_common_encodings(&h1, &protocols));
+    assert_eq!(common.get(protocol), Encoding::Lcs);
+
+    // only the common protocols are negotiated
+    assert_eq!(
+        h1.find_common_encodings(&h2, &SupportedProtocols::default()),
+        ProtocolEncodings::default()
+    );
+
+    // the protocols without common encodings use LCS
+    h2.encodings.clear();
+    let common = h1.find_common_encodings(&h2, &protocols);
+    assert_eq!(common, ProtocolEncodings::default());
+    assert_eq!(common.get(protocol), Encoding::Lcs);
+
+    // the protocols only supporting LCS aren't advertised
+    let h3 = HandshakeExtensions::new(&protocols);
+    assert!(h3.encodings.is_empty());
+}
+
+#[test]
+fn encodings_of_unknown_protocols() {
+    let protocol = ProtocolId::StateSynchronizerDirectSend;
+    let protocols: SupportedProtocols = [protocol].iter().into();
+    let mut h1 = HandshakeExtensions::new(&protocols);
+    h1.encodings
+        .insert(protocol as u8, [Encoding::Lcs].iter().into());
+    h1.encodings.insert(200, [Encoding::Lcs].iter().into());
+
+    // the extensions of a node advertising the encodings of a protocol unknown to this node,
+    // added by a newer version, are decoded, and the unknown protocol is skipped
+    let h2: HandshakeExtensions = lcs::from_bytes(&lcs::to_bytes(&h1).unwrap()).unwrap();
+    assert_eq!(h2.encodings.len(), 2);
+    let common = h1.find_common_encodings(&h2, &protocols);
+    assert_eq!(common, h2.find_common_encodings(&h1, &protocols));
+    assert_eq!(common.get(protocol), Encoding::Lcs);
+    assert_eq!(
+        common,
+        ProtocolEncodings([(protocol, Encoding::Lcs)].iter().copied().collect())
+    );
+}
+
+#[test]
+fn health_check_only_protocols() {
+    assert!(SupportedProtocols::default().is_empty());
+    assert!(!SupportedProtocols::default().is_health_check_only());
+
+    let health_check_only: SupportedProtocols = [ProtocolId::HealthCheckerRpc].iter().into();
+    assert!(!health_check_only.is_empty());
+    assert!(health_check_only.is_health_check_only());
+
+    let protocols: SupportedProtocols = [ProtocolId::HealthCheckerRpc, ProtocolId::ConsensusRpc]
+        .iter()
+        .into();
+    assert!(!protocols.is_health_check_only());
+}
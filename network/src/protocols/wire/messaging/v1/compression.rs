@@ -0,0 +1,238 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compression of the payloads of the DirectSend and RPC messages, e.g., of the state sync chunks
+//! and of the consensus proposals, which compress well.
+//!
+//! When both ends of a connection advertise a common compression codec during the handshake, every
+//! payload of a `DirectSendMsg`, `RpcRequest`, `TracedRpcRequest` or `RpcResponse` sent over the
+//! connection is prefixed with a byte naming its codec, `0` meaning uncompressed. The payloads smaller than
+//! `COMPRESSION_THRESHOLD_BYTES`, or which do not shrink, are sent uncompressed. The receiver
+//! decompresses the payloads of any codec it supports, up to `MAX_DECOMPRESSED_BYTES`.
+
+use crate::protocols::wire::{
+    handshake::v1::CompressionCodec,
+    messaging::v1::{DirectSendMsg, NetworkMessage, RpcRequest, RpcResponse, TracedRpcRequest},
+};
+use libra_logger::prelude::*;
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+};
+
+/// Payloads smaller than this are sent uncompressed.
+pub const COMPRESSION_THRESHOLD_BYTES: usize = 1024;
+/// Payloads decompressing to more than this are rejected, as larger than the maximum frame.
+pub const MAX_DECOMPRESSED_BYTES: usize = 8 * 1024 * 1024;
+
+const UNCOMPRESSED: u8 = 0;
+const ZSTD_LEVEL: i32 = 1;
+
+/// Compresses the payload of `message` with `codec`.
+pub fn compress_message(codec: CompressionCodec, message: NetworkMessage) -> NetworkMessage {
+    match message {
+        NetworkMessage::RpcRequest(request) => NetworkMessage::RpcRequest(RpcRequest {
+            raw_request: compress(codec, &request.raw_request),
+            ..request
+        }),
+        NetworkMessage::TracedRpcRequest(traced) => {
+            NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+                request: RpcRequest {
+                    raw_request: compress(codec, &traced.request.raw_request),
+                    ..traced.request
+                },
+                ..traced
+            })
+        }
+        NetworkMessage::RpcResponse(response) => NetworkMessage::RpcResponse(RpcResponse {
+            raw_response: compress(codec, &response.raw_response),
+            ..response
+        }),
+        NetworkMessage::DirectSendMsg(message) => NetworkMessage::DirectSendMsg(DirectSendMsg {
+            raw_msg: compress(codec, &message.raw_msg),
+            ..message
+        }),
+        message => message,
+    }
+}
+
+/// Decompresses the payload of `message`.
+pub fn decompress_message(message: NetworkMessage) -> io::Result<NetworkMessage> {
+    Ok(match message {
+        NetworkMessage::RpcRequest(request) => NetworkMessage::RpcRequest(RpcRequest {
+            raw_request: decompress(&request.raw_request)?,
+            ..request
+        }),
+        NetworkMessage::TracedRpcRequest(traced) => {
+            NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+                request: RpcRequest {
+                    raw_request: decompress(&traced.request.raw_request)?,
+                    ..traced.request
+                },
+                ..traced
+            })
+        }
+        NetworkMessage::RpcResponse(response) => NetworkMessage::RpcResponse(RpcResponse {
+            raw_response: decompress(&response.raw_response)?,
+            ..response
+        }),
+        NetworkMessage::DirectSendMsg(message) => NetworkMessage::DirectSendMsg(DirectSendMsg {
+            raw_msg: decompress(&message.raw_msg)?,
+            ..message
+        }),
+        message => message,
+    })
+}
+
+/// Returns `payload` prefixed with its codec, compressed with `codec` if worth it.
+pub fn compress(codec: CompressionCodec, payload: &[u8]) -> Vec<u8> {
+    if payload.len() >= COMPRESSION_THRESHOLD_BYTES {
+        let compressed = match codec {
+            CompressionCodec::Lz4 => lz4::block::compress(payload, None, true),
+            CompressionCodec::Zstd => zstd::stream::encode_all(payload, ZSTD_LEVEL),
+        };
+        match compressed {
+            Ok(compressed) if compressed.len() < payload.len() => {
+                let mut prefixed = Vec::with_capacity(1 + compressed.len());
+                prefixed.push(codec as u8);
+                prefixed.extend_from_slice(&compressed);
+                return prefixed;
+            }
+            Ok(_) => {}
+            Err(err) => debug!(
+                "Failed to compress payload with {}: {}",
+                codec.as_str(),
+                err
+            ),
+        }
+    }
+    let mut prefixed = Vec::with_capacity(1 + payload.len());
+    prefixed.push(UNCOMPRESSED);
+    prefixed.extend_from_slice(payload);
+    prefixed
+}
+
+/// Returns the payload of `prefixed`, decompressed according to its codec.
+pub fn decompress(prefixed: &[u8]) -> io::Result<Vec<u8>> {
+    let (codec, payload) = match prefixed.split_first() {
+        Some((codec, payload)) => (*codec, payload),
+        None => {
+            return Err(invalid_data(
+                "Missing compression codec of payload".to_string(),
+            ))
+        }
+    };
+    match codec {
+        UNCOMPRESSED => Ok(payload.to_vec()),
+        codec if codec == CompressionCodec::Lz4 as u8 => {
+            // The size of the decompressed payload prefixes the compressed one.
+            let size = payload
+                .get(..4)
+                .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+                .ok_or_else(|| invalid_data("Truncated lz4 payload".to_string()))?;
+            if size > MAX_DECOMPRESSED_BYTES {
+                return Err(invalid_data(format!(
+                    "lz4 payload decompressing to {} bytes",
+                    size
+                )));
+            }
+            lz4::block::decompress(payload, None)
+        }
+        codec if codec == CompressionCodec::Zstd as u8 => {
+            let mut decompressed = Vec::new();
+            zstd::stream::read::Decoder::new(payload)?
+                .take(MAX_DECOMPRESSED_BYTES as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+            if decompressed.len() > MAX_DECOMPRESSED_BYTES {
+                return Err(invalid_data(
+                    "zstd payload decompressing to too many bytes".to_string(),
+                ));
+            }
+            Ok(decompressed)
+        }
+        codec => Err(invalid_data(format!(
+            "Unsupported compression codec {}",
+            codec
+        ))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let compressible = vec![42u8; 10 * COMPRESSION_THRESHOLD_BYTES];
+        let small = vec![42u8; COMPRESSION_THRESHOLD_BYTES - 1];
+        let random: Vec<u8> = (0..COMPRESSION_THRESHOLD_BYTES * 2)
+            .map(|_| rand::random())
+            .collect();
+        for codec in &[CompressionCodec::Lz4, CompressionCodec::Zstd] {
+            let compressed = compress(*codec, &compressible);
+            assert_eq!(compressed[0], *codec as u8);
+            assert!(compressed.len() < compressible.len() / 10);
+            assert_eq!(decompress(&compressed).unwrap(), compressible);
+
+            // not worth compressing
+            for payload in &[&small, &random] {
+                let prefixed = compress(*codec, payload);
+                assert_eq!(prefixed[0], UNCOMPRESSED);
+                assert_eq!(&decompress(&prefixed).unwrap(), *payload);
+            }
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let message = NetworkMessage::DirectSendMsg(DirectSendMsg {
+            protocol_id: crate::ProtocolId::StateSynchronizerDirectSend,
+            priority: 0,
+            raw_msg: vec![7u8; 4 * COMPRESSION_THRESHOLD_BYTES],
+        });
+        let compressed = compress_message(CompressionCodec::Zstd, message.clone());
+        assert_ne!(compressed, message);
+        assert_eq!(decompress_message(compressed).unwrap(), message);
+
+        let message = NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+            request: RpcRequest {
+                request_id: 0,
+                protocol_id: crate::ProtocolId::ConsensusRpc,
+                priority: 0,
+                raw_request: vec![7u8; 4 * COMPRESSION_THRESHOLD_BYTES],
+            },
+            trace_context: crate::protocols::wire::messaging::v1::TraceContext {
+                trace_id: 1,
+                span_id: 2,
+            },
+        });
+        let compressed = compress_message(CompressionCodec::Lz4, message.clone());
+        assert_ne!(compressed, message);
+        assert_eq!(decompress_message(compressed).unwrap(), message);
+
+        let ping = NetworkMessage::Ping(crate::protocols::wire::messaging::v1::Nonce(1));
+        assert_eq!(compress_message(CompressionCodec::Lz4, ping.clone()), ping);
+    }
+
+    #[test]
+    fn invalid_payloads() {
+        assert!(decompress(&[]).is_err());
+        assert!(decompress(&[200, 1, 2, 3]).is_err());
+        assert!(decompress(&[CompressionCodec::Lz4 as u8, 1]).is_err());
+        assert!(decompress(&[CompressionCodec::Zstd as u8, 1, 2, 3]).is_err());
+
+        // a payload decompressing beyond the limit is rejected
+        let bomb = compress(
+            CompressionCodec::Zstd,
+            &vec![0u8; MAX_DECOMPRESSED_BYTES + 1],
+        );
+        assert!(decompress(&bomb).is_err());
+        let mut bomb = vec![CompressionCodec::Lz4 as u8];
+        bomb.extend_from_slice(&(MAX_DECOMPRESSED_BYTES as u32 + 1).to_le_bytes());
+        assert!(decompress(&bomb).is_err());
+    }
+}
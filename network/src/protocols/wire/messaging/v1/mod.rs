@@ -7,6 +7,8 @@
 use crate::protocols::wire::handshake::v1::{MessagingProtocolVersion, ProtocolId};
 use serde::{Deserialize, Serialize};
 
+pub mod compression;
+
 #[cfg(test)]
 mod test;
 
@@ -20,6 +22,8 @@ pub enum NetworkMessage {
     RpcRequest(RpcRequest),
     RpcResponse(RpcResponse),
     DirectSendMsg(DirectSendMsg),
+    /// Only sent on the sessions of MessagingProtocolVersion V2.
+    TracedRpcRequest(TracedRpcRequest),
 }
 
 /// Enum representing various error codes that can be embedded in NetworkMessage.
@@ -57,6 +61,24 @@ pub struct RpcRequest {
     pub raw_request: Vec<u8>,
 }
 
+/// An RpcRequest carrying the trace of the request on the sender, which the span of the handler
+/// of the request links to. The peers on the sessions of MessagingProtocolVersion V1 are sent the
+/// bare RpcRequest instead.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TracedRpcRequest {
+    pub request: RpcRequest,
+    pub trace_context: TraceContext,
+}
+
+/// Context of a trace propagated to a remote node. The ids are the ones of the W3C trace context
+/// used by OpenTelemetry, so that the spans logged by the nodes can be assembled into traces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    /// Id of the span of the sender, the parent of the spans of the receiver
+    pub span_id: u64,
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct RpcResponse {
     /// RequestId for corresponding request. This is copied as is from the RpcRequest.
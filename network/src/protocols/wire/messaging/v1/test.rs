@@ -37,3 +37,36 @@ fn rpc_request() -> lcs::Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn traced_rpc_request() -> lcs::Result<()> {
+    let message = NetworkMessage::TracedRpcRequest(TracedRpcRequest {
+        request: RpcRequest {
+            request_id: 25,
+            protocol_id: ProtocolId::ConsensusRpc,
+            priority: 0,
+            raw_request: vec![],
+        },
+        trace_context: TraceContext {
+            trace_id: 1,
+            span_id: 2,
+        },
+    });
+    let bytes = lcs::to_bytes(&message)?;
+    assert_eq!(
+        bytes,
+        // [6] -> TracedRpcRequest variant
+        // [25, 0, 0, 0, 0, 0, 0] -> request, with an empty raw_request
+        // [1, 0, ...] -> trace_id, 16 bytes
+        // [2, 0, ...] -> span_id, 8 bytes
+        [
+            &[6, 25, 0, 0, 0, 0, 0, 0, 1][..],
+            &[0; 15][..],
+            &[2][..],
+            &[0; 7][..]
+        ]
+        .concat()
+    );
+    assert_eq!(lcs::from_bytes::<NetworkMessage>(&bytes)?, message);
+    Ok(())
+}
@@ -0,0 +1,186 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Budgets of the resources used by the peers of a network, so that a flood of traffic on one
+//! network of a node, e.g., the public one, cannot starve the other networks of the node.
+//!
+//! Every network, including the network of an additional listener, has its own `NetworkQuota`,
+//! bounding:
+//! * the number of inbound connections, and so the number of tasks spawned for them, beyond which
+//!   new inbound connections are closed, unless a less desirable connection is evicted for them,
+//!   see [`eviction`](crate::eviction);
+//! * the bytes of inbound messages read from the wire and not yet processed by the RPC and
+//!   DirectSend actors, beyond which the messages received are dropped;
+//! * the bytes of the requests of the inbound and outbound RPCs in flight, i.e., from their
+//!   receipt or submission until their response is sent or received, beyond which new RPCs are
+//!   rejected, so that a spike of RPCs cannot exhaust the memory of a small full node.
+//!
+//! The usage of every resource is exported per network, along with its limit and the number of
+//! rejected reservations.
+
+use crate::counters;
+use libra_config::network_id::NetworkId;
+use libra_metrics::{IntCounter, IntGauge};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+pub const INBOUND_CONNECTIONS_RESOURCE: &str = "inbound_connections";
+pub const INBOUND_MESSAGE_BYTES_RESOURCE: &str = "inbound_message_bytes";
+pub const INFLIGHT_RPC_BYTES_RESOURCE: &str = "inflight_rpc_bytes";
+
+/// Limits of the `NetworkQuota` of every network, `None` meaning unlimited.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct QuotaLimits {
+    pub max_inbound_connections: Option<usize>,
+    pub max_inbound_message_bytes: Option<usize>,
+    pub max_inflight_rpc_bytes: Option<usize>,
+}
+
+/// Budgets of the resources of one network.
+#[derive(Clone, Debug)]
+pub struct NetworkQuota {
+    pub inbound_connections: ResourceBudget,
+    pub inbound_message_bytes: ResourceBudget,
+    pub inflight_rpc_bytes: ResourceBudget,
+}
+
+impl NetworkQuota {
+    pub fn new(network_id: &NetworkId, limits: QuotaLimits) -> Self {
+        Self {
+            inbound_connections: ResourceBudget::new(
+                network_id,
+                INBOUND_CONNECTIONS_RESOURCE,
+                limits.max_inbound_connections,
+            ),
+            inbound_message_bytes: ResourceBudget::new(
+                network_id,
+                INBOUND_MESSAGE_BYTES_RESOURCE,
+                limits.max_inbound_message_bytes,
+            ),
+            inflight_rpc_bytes: ResourceBudget::new(
+                network_id,
+                INFLIGHT_RPC_BYTES_RESOURCE,
+                limits.max_inflight_rpc_bytes,
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BudgetInner {
+    limit: Option<usize>,
+    used: AtomicUsize,
+    usage: IntGauge,
+    rejections: IntCounter,
+}
+
+/// Budget of one resource of a network, shared by all its peers.
+#[derive(Clone, Debug)]
+pub struct ResourceBudget(Arc<BudgetInner>);
+
+impl ResourceBudget {
+    fn new(network_id: &NetworkId, resource: &str, limit: Option<usize>) -> Self {
+        let labels = [network_id.as_str(), resource];
+        if let Some(limit) = limit {
+            counters::LIBRA_NETWORK_QUOTA_LIMIT
+                .with_label_values(&labels)
+                .set(limit as i64);
+        }
+        Self(Arc::new(BudgetInner {
+            limit,
+            used: AtomicUsize::new(0),
+            usage: counters::LIBRA_NETWORK_QUOTA_USAGE.with_label_values(&labels),
+            rejections: counters::LIBRA_NETWORK_QUOTA_REJECTIONS.with_label_values(&labels),
+        }))
+    }
+
+    /// Reserves `amount` of the resource until the returned permit is dropped, or returns `None`
+    /// if the reservation would exceed the limit of the budget.
+    pub fn try_reserve(&self, amount: usize) -> Option<QuotaPermit> {
+        let inner = &self.0;
+        let mut used = inner.used.load(Ordering::Relaxed);
+        loop {
+            let new_used = used.saturating_add(amount);
+            if inner.limit.map_or(false, |limit| new_used > limit) {
+                inner.rejections.inc();
+                return None;
+            }
+            match inner.used.compare_exchange_weak(
+                used,
+                new_used,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => used = actual,
+            }
+        }
+        inner.usage.add(amount as i64);
+        Some(QuotaPermit {
+            budget: Some(self.clone()),
+            amount,
+        })
+    }
+
+    /// Amount of the resource currently reserved.
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Relaxed)
+    }
+}
+
+/// A reservation of a resource, released when dropped. The default permit doesn't reserve
+/// anything.
+#[derive(Debug, Default)]
+pub struct QuotaPermit {
+    budget: Option<ResourceBudget>,
+    amount: usize,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        if let Some(budget) = self.budget.take() {
+            budget.0.used.fetch_sub(self.amount, Ordering::Relaxed);
+            budget.0.usage.sub(self.amount as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resource_budget() {
+        let network_id = NetworkId::private_network("quota-test");
+        let quota = NetworkQuota::new(
+            &network_id,
+            QuotaLimits {
+                max_inbound_connections: None,
+                max_inbound_message_bytes: Some(100),
+                max_inflight_rpc_bytes: None,
+            },
+        );
+        let budget = &quota.inbound_message_bytes;
+
+        let first = budget.try_reserve(60).unwrap();
+        assert!(budget.try_reserve(41).is_none());
+        let second = budget.try_reserve(40).unwrap();
+        assert_eq!(budget.used(), 100);
+
+        drop(first);
+        assert_eq!(budget.used(), 40);
+        assert!(budget.try_reserve(61).is_none());
+        drop(second);
+        assert_eq!(budget.used(), 0);
+
+        // unlimited budgets never reject reservations
+        let permits: Vec<_> = (0..1000)
+            .map(|_| quota.inbound_connections.try_reserve(1).unwrap())
+            .collect();
+        assert_eq!(quota.inbound_connections.used(), 1000);
+        drop(permits);
+        assert_eq!(quota.inbound_connections.used(), 0);
+    }
+}
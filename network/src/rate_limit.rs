@@ -0,0 +1,196 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limits of the inbound messages of every peer, by protocol, so that a single peer cannot
+//! monopolize the actors of a protocol, e.g., by flooding us with mempool transactions.
+//!
+//! Every connection gets a token bucket per protocol: a RPC request or a DirectSend message of the
+//! protocol consumes a token, and the tokens are refilled at `messages_per_sec`, up to `burst`.
+//! The RPC responses and the pings are not limited, as we asked for them. The messages received
+//! while the bucket is empty are either dropped, or delay the reading of the following messages of
+//! the peer until a token is available, so that TCP pushes back on the peer.
+//!
+//! Every message exceeding the rate limit of its peer is counted in
+//! `libra_network_rate_limited_messages`, by peer and protocol.
+
+use crate::ProtocolId;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Messages allowed per second, and in a burst.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    pub messages_per_sec: u32,
+    pub burst: u32,
+}
+
+/// What to do with the inbound messages exceeding the rate limit of their peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RateLimitPolicy {
+    /// Drop them.
+    Drop,
+    /// Stop reading the connection of the peer until they are allowed.
+    BackPressure,
+}
+
+impl RateLimitPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateLimitPolicy::Drop => "drop",
+            RateLimitPolicy::BackPressure => "back_pressure",
+        }
+    }
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        RateLimitPolicy::Drop
+    }
+}
+
+/// Rate limits of the inbound messages of every peer of a network, none by default.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct InboundRateLimits {
+    /// Limit of the protocols without their own limit, `None` meaning unlimited.
+    pub default_limit: Option<RateLimit>,
+    /// Limits of specific protocols.
+    pub protocol_limits: HashMap<ProtocolId, RateLimit>,
+    pub policy: RateLimitPolicy,
+}
+
+impl InboundRateLimits {
+    pub fn limit(&self, protocol: ProtocolId) -> Option<RateLimit> {
+        self.protocol_limits
+            .get(&protocol)
+            .copied()
+            .or(self.default_limit)
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+        }
+    }
+
+    /// Consumes a token, or returns how long until one is available.
+    fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens
+            + elapsed.as_secs_f64() * f64::from(self.limit.messages_per_sec))
+        .min(f64::from(self.limit.burst));
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if self.limit.messages_per_sec == 0 {
+            // Never refilled: check again in a while, in case of back-pressure.
+            Err(Duration::from_secs(1))
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / f64::from(self.limit.messages_per_sec),
+            ))
+        }
+    }
+}
+
+/// The token buckets of the connection of one peer, by protocol.
+#[derive(Debug)]
+pub(crate) struct PeerRateLimiter {
+    limits: InboundRateLimits,
+    buckets: HashMap<ProtocolId, TokenBucket>,
+}
+
+impl PeerRateLimiter {
+    pub(crate) fn new(limits: InboundRateLimits) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> RateLimitPolicy {
+        self.limits.policy
+    }
+
+    /// Consumes a token of `protocol`, or returns how long until one is available.
+    pub(crate) fn try_acquire(
+        &mut self,
+        protocol: ProtocolId,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let limit = match self.limits.limit(protocol) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        self.buckets
+            .entry(protocol)
+            .or_insert_with(|| TokenBucket::new(limit, now))
+            .try_acquire(now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_buckets() {
+        let mut limits = InboundRateLimits::default();
+        limits.default_limit = Some(RateLimit {
+            messages_per_sec: 10,
+            burst: 2,
+        });
+        limits.protocol_limits.insert(
+            ProtocolId::MempoolDirectSend,
+            RateLimit {
+                messages_per_sec: 0,
+                burst: 1,
+            },
+        );
+        let mut limiter = PeerRateLimiter::new(limits);
+        let now = Instant::now();
+
+        // the burst is allowed, then one message every 100ms
+        let protocol = ProtocolId::ConsensusRpc;
+        assert_eq!(limiter.try_acquire(protocol, now), Ok(()));
+        assert_eq!(limiter.try_acquire(protocol, now), Ok(()));
+        let wait = limiter.try_acquire(protocol, now).unwrap_err();
+        assert!(wait > Duration::from_millis(99) && wait <= Duration::from_millis(100));
+        let later = now + Duration::from_millis(100);
+        assert_eq!(limiter.try_acquire(protocol, later), Ok(()));
+        assert!(limiter.try_acquire(protocol, later).is_err());
+        // the burst is refilled, but not beyond
+        let much_later = later + Duration::from_secs(10);
+        assert_eq!(limiter.try_acquire(protocol, much_later), Ok(()));
+        assert_eq!(limiter.try_acquire(protocol, much_later), Ok(()));
+        assert!(limiter.try_acquire(protocol, much_later).is_err());
+
+        // every protocol has its own bucket, and can have its own limit
+        assert_eq!(
+            limiter.try_acquire(ProtocolId::ConsensusDirectSend, later),
+            Ok(())
+        );
+        let protocol = ProtocolId::MempoolDirectSend;
+        assert_eq!(limiter.try_acquire(protocol, now), Ok(()));
+        assert!(limiter.try_acquire(protocol, much_later).is_err());
+
+        // no limit by default
+        let mut limiter = PeerRateLimiter::new(InboundRateLimits::default());
+        for _ in 0..1000 {
+            assert_eq!(limiter.try_acquire(protocol, now), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Quality of the time synchronization of this node, as consensus rejects the proposals whose
+//! timestamps are ahead of our clock: a node whose clock drifts either fails to vote, or has its
+//! proposals rejected, with little hint of the cause.
+//!
+//! Two sources are combined:
+//! * the skew of the clock of every connected peer relative to ours, estimated by the health
+//!   checker from its pings. When most peers seem ahead, or behind, our clock is the one drifting;
+//! * the synchronization status of the local clock, as reported by the kernel, which NTP daemons
+//!   keep up-to-date.
+
+use crate::{connected_peers::ConnectedPeerInfo, counters};
+use std::time::Duration;
+
+/// Estimates the skew of the clock of a peer which replied to a ping with its time
+/// `remote_time_usecs`, relative to ours: the ping was sent at `sent_at`, since the Unix epoch,
+/// and the reply received `rtt` later. The peer is assumed to have replied halfway through, so
+/// that the estimate is off by half the round-trip time at most. A positive skew means the clock
+/// of the peer is ahead of ours.
+pub fn clock_skew_usecs(remote_time_usecs: u64, sent_at: Duration, rtt: Duration) -> i64 {
+    let local_time_usecs = (sent_at + rtt / 2).as_micros() as i64;
+    remote_time_usecs as i64 - local_time_usecs
+}
+
+/// Returns the median skew of the clocks of the `peers` whose skew was measured, which estimates
+/// the drift of our own clock, with the opposite sign, when most peers are synchronized.
+pub fn median_clock_skew_usecs(peers: &[ConnectedPeerInfo]) -> Option<i64> {
+    let mut skews: Vec<_> = peers
+        .iter()
+        .filter_map(|peer| peer.clock_skew_usecs)
+        .collect();
+    if skews.is_empty() {
+        return None;
+    }
+    skews.sort();
+    Some(skews[skews.len() / 2])
+}
+
+/// Whether the kernel considers the local clock synchronized, e.g., by an NTP daemon. Returns
+/// `None` if unknown, e.g., on platforms other than Linux.
+#[cfg(target_os = "linux")]
+pub fn ntp_synchronized() -> Option<bool> {
+    // Only reads the state of the clock, as `modes` is zero.
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    match unsafe { libc::adjtimex(&mut timex) } {
+        -1 => None,
+        state => Some(state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn ntp_synchronized() -> Option<bool> {
+    None
+}
+
+/// Exports the synchronization status of the local clock: 1 if synchronized, 0 if not, -1 if
+/// unknown.
+pub(crate) fn update_ntp_synchronized_metric() {
+    counters::LIBRA_NTP_SYNCHRONIZED.set(match ntp_synchronized() {
+        Some(true) => 1,
+        Some(false) => 0,
+        None => -1,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_config::{config::RoleType, network_id::NetworkId};
+    use libra_types::PeerId;
+
+    #[test]
+    fn clock_skew() {
+        let sent_at = Duration::from_secs(1_000);
+        let rtt = Duration::from_millis(20);
+        // the peer replied halfway through the round trip
+        assert_eq!(clock_skew_usecs(1_000_010_000, sent_at, rtt), 0);
+        assert_eq!(clock_skew_usecs(1_002_010_000, sent_at, rtt), 2_000_000);
+        assert_eq!(clock_skew_usecs(999_010_000, sent_at, rtt), -1_000_000);
+    }
+
+    #[test]
+    fn median_clock_skew() {
+        let peer = |clock_skew_usecs| ConnectedPeerInfo {
+            peer_id: PeerId::random(),
+            network_id: NetworkId::Validator,
+            role: RoleType::Validator,
+            address: "/ip4/127.0.0.1/tcp/6180".parse().unwrap(),
+            rtt: None,
+            synced_version: None,
+            clock_skew_usecs,
+        };
+        assert_eq!(median_clock_skew_usecs(&[]), None);
+        assert_eq!(median_clock_skew_usecs(&[peer(None)]), None);
+        assert_eq!(
+            median_clock_skew_usecs(&[peer(Some(-5)), peer(None), peer(Some(300)), peer(Some(7))]),
+            Some(7)
+        );
+    }
+}
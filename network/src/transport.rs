@@ -3,10 +3,15 @@
 
 use crate::{
     common::NetworkPublicKeys,
-    noise::{stream::NoiseStream, HandshakeAuthMode, NoiseUpgrader},
+    counters,
+    noise::{stream::NoiseStream, HandshakeAuthMode, NoiseKeylog, NoiseUpgrader},
+    peer_manager::lifecycle::PeerLifecycles,
     protocols::{
-        identity::exchange_handshake,
-        wire::handshake::v1::{HandshakeMsg, MessagingProtocolVersion, SupportedProtocols},
+        identity::{exchange_extensions, exchange_handshake},
+        wire::handshake::v1::{
+            CompressionCodecs, HandshakeExtensions, HandshakeMsg, MessagingProtocolVersion,
+            ProtocolEncodings, ProtocolId, SupportedProtocols,
+        },
     },
 };
 use futures::{
@@ -17,9 +22,12 @@ use futures::{
 use libra_config::{config::HANDSHAKE_VERSION, network_id::NetworkId};
 use libra_crypto::x25519;
 use libra_logger::prelude::*;
-use libra_network_address::{parse_dns_tcp, parse_ip_tcp, parse_memory, NetworkAddress};
+use libra_network_address::{
+    parse_circuit, parse_dns_tcp, parse_dns_tcp_ws, parse_ip_tcp, parse_ip_tcp_ws, parse_memory,
+    parse_unix, NetworkAddress,
+};
 use libra_types::PeerId;
-use netcore::transport::{tcp, ConnectionOrigin, Transport};
+use netcore::transport::{tcp, websocket, ConnectionOrigin, Transport};
 use std::{
     collections::HashMap,
     convert::TryFrom,
@@ -32,14 +40,22 @@ use std::{
     },
     time::Duration,
 };
+use thiserror::Error;
 use tokio::time::timeout;
 
 /// A timeout for the connection to open and complete all of the upgrade steps.
 pub const TRANSPORT_TIMEOUT: Duration = Duration::from_secs(30);
 
-/// Currently supported messaging protocol version.
-/// TODO: Add ability to support more than one messaging protocol.
-pub const SUPPORTED_MESSAGING_PROTOCOL: MessagingProtocolVersion = MessagingProtocolVersion::V1;
+/// Currently supported messaging protocol versions, advertised with the same application
+/// protocols. V1 is kept for the peers which don't advertise V2.
+pub const SUPPORTED_MESSAGING_PROTOCOLS: [MessagingProtocolVersion; 2] =
+    [MessagingProtocolVersion::V1, MessagingProtocolVersion::V2];
+
+/// The messaging protocol versions advertised unless the handshake extensions are enabled. The
+/// nodes which predate V2 fail to decode a `HandshakeMsg` advertising it, so V2 is only advertised
+/// once every node of the network supports it.
+pub const DEFAULT_MESSAGING_PROTOCOLS: [MessagingProtocolVersion; 1] =
+    [MessagingProtocolVersion::V1];
 
 /// Global connection-id generator.
 static CONNECTION_ID_GENERATOR: ConnectionIdGenerator = ConnectionIdGenerator::new();
@@ -51,8 +67,17 @@ pub const LIBRA_TCP_TRANSPORT: tcp::TcpTransport = tcp::TcpTransport {
     send_buffer_size: None,
     ttl: None,
     keepalive: None,
+    keepalive_interval: None,
     // Use TCP_NODELAY for libra tcp connections.
     nodelay: Some(true),
+    ipv6_only: None,
+    reuse_port: None,
+    bind_device: None,
+};
+
+/// websocket::Transport over `LIBRA_TCP_TRANSPORT`, for peers which can only open WebSockets.
+pub const LIBRA_WS_TRANSPORT: websocket::WebSocketTransport = websocket::WebSocketTransport {
+    tcp: LIBRA_TCP_TRANSPORT,
 };
 
 /// A trait alias for "socket-like" things.
@@ -97,6 +122,14 @@ pub struct ConnectionMetadata {
     origin: ConnectionOrigin,
     messaging_protocol: MessagingProtocolVersion,
     application_protocols: SupportedProtocols,
+    /// Network the connection was established for during the handshake.
+    network_id: NetworkId,
+    /// Compression codecs supported by both ends, none by default.
+    compression_codecs: CompressionCodecs,
+    /// Encodings negotiated for the application protocols, LCS by default.
+    encodings: ProtocolEncodings,
+    /// Address of this node as observed by the peer, if it reported one.
+    observed_addr: Option<NetworkAddress>,
 }
 
 impl ConnectionMetadata {
@@ -107,6 +140,7 @@ impl ConnectionMetadata {
         origin: ConnectionOrigin,
         messaging_protocol: MessagingProtocolVersion,
         application_protocols: SupportedProtocols,
+        network_id: NetworkId,
     ) -> ConnectionMetadata {
         ConnectionMetadata {
             peer_id,
@@ -115,9 +149,28 @@ impl ConnectionMetadata {
             origin,
             messaging_protocol,
             application_protocols,
+            network_id,
+            compression_codecs: CompressionCodecs::default(),
+            encodings: ProtocolEncodings::default(),
+            observed_addr: None,
         }
     }
 
+    pub fn with_compression_codecs(mut self, compression_codecs: CompressionCodecs) -> Self {
+        self.compression_codecs = compression_codecs;
+        self
+    }
+
+    pub fn with_encodings(mut self, encodings: ProtocolEncodings) -> Self {
+        self.encodings = encodings;
+        self
+    }
+
+    pub fn with_observed_addr(mut self, observed_addr: Option<NetworkAddress>) -> Self {
+        self.observed_addr = observed_addr;
+        self
+    }
+
     pub fn peer_id(&self) -> PeerId {
         self.peer_id
     }
@@ -133,6 +186,36 @@ impl ConnectionMetadata {
     pub fn origin(&self) -> ConnectionOrigin {
         self.origin
     }
+
+    pub fn messaging_protocol(&self) -> MessagingProtocolVersion {
+        self.messaging_protocol
+    }
+
+    pub fn application_protocols(&self) -> &SupportedProtocols {
+        &self.application_protocols
+    }
+
+    pub fn network_id(&self) -> &NetworkId {
+        &self.network_id
+    }
+
+    pub fn compression_codecs(&self) -> &CompressionCodecs {
+        &self.compression_codecs
+    }
+
+    pub fn encodings(&self) -> &ProtocolEncodings {
+        &self.encodings
+    }
+
+    pub fn observed_addr(&self) -> Option<&NetworkAddress> {
+        self.observed_addr.as_ref()
+    }
+
+    /// Whether the peer shares no application protocol but the health checker: the connection is
+    /// only kept to track the liveness of the peer.
+    pub fn is_health_check_only(&self) -> bool {
+        self.application_protocols.is_health_check_only()
+    }
 }
 
 /// The `Connection` struct consists of connection metadata and the actual socket for
@@ -143,46 +226,126 @@ pub struct Connection<TSocket> {
     pub metadata: ConnectionMetadata,
 }
 
+/// Reasons for which the LibraNet handshake of a connection fails, carried by the `io::Error`
+/// of the upgrade of the connection, see [`HandshakeError::from_io_error`].
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("network_ids don't match own: {own:?} received: {received:?}")]
+    NetworkIdMismatch { own: NetworkId, received: NetworkId },
+
+    #[error("no matching messaging protocol")]
+    NoCommonMessagingProtocol,
+
+    #[error("no common application protocols, peer supports: {0:?}")]
+    NoCommonApplicationProtocols(SupportedProtocols),
+}
+
+impl HandshakeError {
+    /// Returns the handshake error which failed the upgrade of a connection with `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<&HandshakeError> {
+        err.get_ref().and_then(|err| err.downcast_ref())
+    }
+}
+
+impl From<HandshakeError> for io::Error {
+    fn from(err: HandshakeError) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+}
+
 /// Exchange HandshakeMsg's to try negotiating a set of common supported protocols.
+///
+/// If the negotiated messaging protocol version has extensions, the HandshakeExtensions are then
+/// exchanged to negotiate the compression codecs and encodings of the session, and to learn the
+/// address the peer observed for this end. Otherwise, the session uses none of them.
+///
+/// The connection is closed if the peer shares no application protocol. If the peer only shares
+/// the health checker, the connection is kept as health-check-only, see
+/// [`ConnectionMetadata::is_health_check_only`]. Both cases are counted in
+/// `libra_network_protocol_mismatches`, to detect version mismatches across the network.
 pub async fn perform_handshake<T: TSocket>(
     peer_id: PeerId,
     mut socket: T,
     addr: NetworkAddress,
     origin: ConnectionOrigin,
     own_handshake: &HandshakeMsg,
+    own_extensions: &HandshakeExtensions,
 ) -> io::Result<Connection<T>> {
     let handshake_other = exchange_handshake(&own_handshake, &mut socket).await?;
     if own_handshake.network_id != handshake_other.network_id {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "network_ids don't match own: {:?} received: {:?}",
-                own_handshake.network_id, handshake_other.network_id
-            ),
-        ));
+        return Err(HandshakeError::NetworkIdMismatch {
+            own: own_handshake.network_id.clone(),
+            received: handshake_other.network_id,
+        }
+        .into());
     }
 
+    let count_mismatch = |mismatch: &str| {
+        counters::LIBRA_NETWORK_PROTOCOL_MISMATCHES
+            .with_label_values(&[own_handshake.network_id.as_str(), mismatch])
+            .inc()
+    };
     let intersecting_protocols = own_handshake.find_common_protocols(&handshake_other);
     match intersecting_protocols {
         None => {
             info!("No matching protocols found for connection with peer: {:?}. Handshake received: {:?}",
                   peer_id.short_str(), handshake_other);
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "no matching messaging protocol",
-            ))
+            count_mismatch("messaging_protocol");
+            Err(HandshakeError::NoCommonMessagingProtocol.into())
         }
-        Some((messaging_protocol, application_protocols)) => Ok(Connection {
-            socket,
-            metadata: ConnectionMetadata::new(
+        Some((messaging_protocol, application_protocols)) if application_protocols.is_empty() => {
+            info!(
+                "No common application protocols for connection with peer: {:?}. Handshake received: {:?}",
+                peer_id.short_str(),
+                handshake_other
+            );
+            count_mismatch("application_protocols");
+            let peer_protocols = handshake_other
+                .supported_protocols
+                .get(&messaging_protocol)
+                .cloned()
+                .unwrap_or_default();
+            Err(HandshakeError::NoCommonApplicationProtocols(peer_protocols).into())
+        }
+        Some((messaging_protocol, application_protocols)) => {
+            if application_protocols.is_health_check_only()
+                && !own_handshake
+                    .supported_protocols
+                    .get(&messaging_protocol)
+                    .map_or(false, SupportedProtocols::is_health_check_only)
+            {
+                info!(
+                    "Only the health checker is common with peer: {:?}, keeping the connection as health-check-only. Handshake received: {:?}",
+                    peer_id.short_str(),
+                    handshake_other
+                );
+                count_mismatch("health_check_only");
+            }
+            let metadata = ConnectionMetadata::new(
                 peer_id,
                 CONNECTION_ID_GENERATOR.next(),
                 addr,
                 origin,
                 messaging_protocol,
-                application_protocols,
-            ),
-        }),
+                application_protocols.clone(),
+                own_handshake.network_id.clone(),
+            );
+            let metadata = if messaging_protocol.has_extensions() {
+                let extensions_other = exchange_extensions(own_extensions, &mut socket).await?;
+                metadata
+                    .with_compression_codecs(
+                        own_extensions.find_common_compression_codecs(&extensions_other),
+                    )
+                    .with_encodings(
+                        own_extensions
+                            .find_common_encodings(&extensions_other, &application_protocols),
+                    )
+                    .with_observed_addr(extensions_other.observed_addr)
+            } else {
+                metadata
+            };
+            Ok(Connection { socket, metadata })
+        }
     }
 }
 
@@ -198,11 +361,72 @@ where
     }
 }
 
+/// The application protocols advertised in the handshakes of a transport, shared with the
+/// PeerManager, which extends them when protocol handlers are registered after the network is
+/// built. Only the connections established afterwards negotiate the new protocols.
+#[derive(Clone, Debug, Default)]
+pub struct ApplicationProtocols(Arc<RwLock<SupportedProtocols>>);
+
+impl ApplicationProtocols {
+    pub fn new(protocols: SupportedProtocols) -> Self {
+        Self(Arc::new(RwLock::new(protocols)))
+    }
+
+    pub fn get(&self) -> SupportedProtocols {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Advertises `protocols` too in the handshakes of the new connections.
+    pub fn extend(&self, protocols: &[ProtocolId]) {
+        self.0.write().unwrap().extend(protocols);
+    }
+}
+
+/// Rotates the network identity key of a transport: the handshakes of the new connections use
+/// the new key, while the connections already established keep their Noise sessions.
+#[derive(Clone)]
+pub struct IdentityKeyRotator(Arc<UpgradeContext>);
+
+impl IdentityKeyRotator {
+    /// Uses `key` for the handshakes of the new connections.
+    pub fn rotate(&self, key: x25519::PrivateKey) {
+        self.0.noise.set_identity_key(key);
+    }
+
+    /// The public key of the handshakes of the new connections.
+    pub fn public_key(&self) -> x25519::PublicKey {
+        self.0.noise.public_key()
+    }
+}
+
 /// Common context for performing both inbound and outbound connection upgrades.
 struct UpgradeContext {
     noise: NoiseUpgrader,
     handshake_version: u8,
-    own_handshake: HandshakeMsg,
+    network_id: NetworkId,
+    application_protocols: ApplicationProtocols,
+    /// Whether `MessagingProtocolVersion::V2`, with the handshake extensions, is advertised.
+    handshake_extensions: bool,
+}
+
+impl UpgradeContext {
+    /// The handshake of this end, advertising the current application protocols, and its
+    /// extensions, reporting `observed_addr` as the address of the other end.
+    fn own_handshake(&self, observed_addr: NetworkAddress) -> (HandshakeMsg, HandshakeExtensions) {
+        let application_protocols = self.application_protocols.get();
+        let mut own_handshake = HandshakeMsg::new(self.network_id.clone());
+        let messaging_protocols: &[MessagingProtocolVersion] = if self.handshake_extensions {
+            &SUPPORTED_MESSAGING_PROTOCOLS
+        } else {
+            &DEFAULT_MESSAGING_PROTOCOLS
+        };
+        for messaging_protocol in messaging_protocols {
+            own_handshake.add(*messaging_protocol, application_protocols.clone());
+        }
+        let mut own_extensions = HandshakeExtensions::new(&application_protocols);
+        own_extensions.observed_addr = Some(observed_addr);
+        (own_handshake, own_extensions)
+    }
 }
 
 /// Upgrade an inbound connection. This means we run a Noise IK handshake for
@@ -214,17 +438,31 @@ async fn upgrade_inbound<T: TSocket>(
     ctxt: Arc<UpgradeContext>,
     fut_socket: impl Future<Output = io::Result<T>>,
     addr: NetworkAddress,
+    lifecycles: PeerLifecycles,
 ) -> io::Result<Connection<NoiseStream<T>>> {
     let origin = ConnectionOrigin::Inbound;
     let socket = fut_socket.await?;
 
     // try authenticating via noise handshake
     let (socket, peer_id) = ctxt.noise.upgrade_inbound(socket).await?;
+    let handshake = lifecycles.handshake_started(peer_id);
     let remote_pubkey = socket.get_remote_static();
+    // the dialer is reported the address its connection came from
+    let (own_handshake, own_extensions) = ctxt.own_handshake(addr.clone());
     let addr = addr.append_prod_protos(remote_pubkey, HANDSHAKE_VERSION);
 
     // try to negotiate common libranet version and supported application protocols
-    perform_handshake(peer_id, socket, addr, origin, &ctxt.own_handshake).await
+    let connection = perform_handshake(
+        peer_id,
+        socket,
+        addr,
+        origin,
+        &own_handshake,
+        &own_extensions,
+    )
+    .await?;
+    handshake.complete();
+    Ok(connection)
 }
 
 /// Upgrade an inbound connection. This means we run a Noise IK handshake for
@@ -233,11 +471,14 @@ async fn upgrade_outbound<T: TSocket>(
     ctxt: Arc<UpgradeContext>,
     fut_socket: impl Future<Output = io::Result<T>>,
     addr: NetworkAddress,
+    base_addr: NetworkAddress,
     remote_peer_id: PeerId,
     remote_pubkey: x25519::PublicKey,
+    lifecycles: PeerLifecycles,
 ) -> io::Result<Connection<NoiseStream<T>>> {
     let origin = ConnectionOrigin::Outbound;
     let socket = fut_socket.await?;
+    let handshake = lifecycles.handshake_started(remote_peer_id);
 
     // noise handshake
     let socket = ctxt.noise.upgrade_outbound(socket, remote_pubkey).await?;
@@ -245,8 +486,21 @@ async fn upgrade_outbound<T: TSocket>(
     // sanity check: Noise IK should always guarantee this is true
     debug_assert_eq!(remote_pubkey, socket.get_remote_static());
 
+    // the listener is reported the address it was dialed at
+    let (own_handshake, own_extensions) = ctxt.own_handshake(base_addr);
+
     // try to negotiate common libranet version and supported application protocols
-    perform_handshake(remote_peer_id, socket, addr, origin, &ctxt.own_handshake).await
+    let connection = perform_handshake(
+        remote_peer_id,
+        socket,
+        addr,
+        origin,
+        &own_handshake,
+        &own_extensions,
+    )
+    .await?;
+    handshake.complete();
+    Ok(connection)
 }
 
 /// The common LibraNet Transport.
@@ -264,7 +518,8 @@ async fn upgrade_outbound<T: TSocket>(
 pub struct LibraNetTransport<TTransport> {
     base_transport: TTransport,
     ctxt: Arc<UpgradeContext>,
-    identity_pubkey: x25519::PublicKey,
+    /// States of the connections with the peers, moved as their handshakes start and end.
+    lifecycles: PeerLifecycles,
 }
 
 impl<TTransport> LibraNetTransport<TTransport>
@@ -283,27 +538,58 @@ where
         handshake_version: u8,
         network_id: NetworkId,
         application_protocols: SupportedProtocols,
+        noise_keylog: Option<Arc<NoiseKeylog>>,
     ) -> Self {
-        let mut own_handshake = HandshakeMsg::new(network_id);
-        own_handshake.add(SUPPORTED_MESSAGING_PROTOCOL, application_protocols);
-        let identity_pubkey = identity_key.public_key();
-
         let auth_mode = match trusted_peers.as_ref() {
             Some(trusted_peers) => HandshakeAuthMode::mutual(trusted_peers.clone()),
             None => HandshakeAuthMode::ServerOnly,
         };
 
+        let mut noise = NoiseUpgrader::new(self_peer_id, identity_key, auth_mode);
+        if let Some(noise_keylog) = noise_keylog {
+            noise = noise.with_keylog(noise_keylog);
+        }
+
         Self {
             ctxt: Arc::new(UpgradeContext {
-                noise: NoiseUpgrader::new(self_peer_id, identity_key, auth_mode),
+                noise,
                 handshake_version,
-                own_handshake,
+                network_id,
+                application_protocols: ApplicationProtocols::new(application_protocols),
+                handshake_extensions: false,
             }),
             base_transport,
-            identity_pubkey,
+            lifecycles: PeerLifecycles::new(),
         }
     }
 
+    /// Advertise `MessagingProtocolVersion::V2` too if `enable`, exchanging the handshake
+    /// extensions with the peers which advertise it. Only enable it once no node of the network
+    /// predates V2.
+    pub fn with_handshake_extensions(mut self, enable: bool) -> Self {
+        Arc::get_mut(&mut self.ctxt)
+            .expect("the upgrade context is only shared once the transport is used")
+            .handshake_extensions = enable;
+        self
+    }
+
+    /// Track the handshakes of the connections of this transport in `lifecycles`, e.g., those
+    /// shared with the PeerManager.
+    pub fn with_peer_lifecycles(mut self, lifecycles: PeerLifecycles) -> Self {
+        self.lifecycles = lifecycles;
+        self
+    }
+
+    /// The application protocols advertised in the handshakes of this transport.
+    pub fn application_protocols(&self) -> ApplicationProtocols {
+        self.ctxt.application_protocols.clone()
+    }
+
+    /// The rotator of the network identity key of this transport.
+    pub fn identity_key_rotator(&self) -> IdentityKeyRotator {
+        IdentityKeyRotator(self.ctxt.clone())
+    }
+
     fn parse_dial_addr(
         addr: &NetworkAddress,
     ) -> io::Result<(NetworkAddress, x25519::PublicKey, u8)> {
@@ -315,16 +601,20 @@ where
         // and leave for the base_transport to actually parse and dial.
         // TODO(philiphayes): protos[..X] is kinda hacky. `Transport` trait
         // should handle this.
-        let (base_transport_protos, base_transport_suffix) = parse_ip_tcp(protos)
-            .map(|x| (&protos[..2], x.1))
+        let (base_transport_protos, base_transport_suffix) = parse_circuit(protos)
+            .map(|x| (&protos[..3], x.1))
+            .or_else(|| parse_ip_tcp_ws(protos).map(|x| (&protos[..3], x.1)))
+            .or_else(|| parse_ip_tcp(protos).map(|x| (&protos[..2], x.1)))
+            .or_else(|| parse_dns_tcp_ws(protos).map(|x| (&protos[..3], x.1)))
             .or_else(|| parse_dns_tcp(protos).map(|x| (&protos[..2], x.1)))
+            .or_else(|| parse_unix(protos).map(|x| (&protos[..1], x.1)))
             .or_else(|| parse_memory(protos).map(|x| (&protos[..1], x.1)))
             .ok_or_else(|| {
                 io::Error::new(
                     io::ErrorKind::InvalidInput,
                     format!(
                         "Unexpected dialing network address: '{}', expected: \
-                         memory, ip+tcp, or dns+tcp",
+                         memory, ip+tcp, ip+tcp+ws, dns+tcp, dns+tcp+ws, unix, or circuit",
                         addr
                     ),
                 )
@@ -370,6 +660,29 @@ where
     /// `/dns/<ipaddr>/tcp/<port>` or
     /// `/dns4/<ipaddr>/tcp/<port>` or
     /// `/dns6/<ipaddr>/tcp/<port>`
+    ///
+    /// If the base transport is `WebSocketTransport`, then `/<base_transport>` is:
+    ///
+    /// `/ip4/<ipaddr>/tcp/<port>/ws` or
+    /// `/ip6/<ipaddr>/tcp/<port>/ws` or
+    /// `/dns/<ipaddr>/tcp/<port>/ws` or
+    /// `/dns4/<ipaddr>/tcp/<port>/ws` or
+    /// `/dns6/<ipaddr>/tcp/<port>/ws`
+    ///
+    /// DNS names are resolved by the base transport on every dial, so that a peer whose IP
+    /// address changes is dialed at its new address without any change to the configuration.
+    ///
+    /// If the base transport is `UdsTransport`, then `/<base_transport>` is:
+    ///
+    /// `/unix/<percent-encoded path>`
+    ///
+    /// If the base transport is `RelayTransport`, then `/<base_transport>` may also be the
+    /// address of a relay followed by `/p2p-circuit`, e.g.:
+    ///
+    /// `/ip4/<ipaddr>/tcp/<port>/p2p-circuit` or
+    /// `/dns/<name>/tcp/<port>/p2p-circuit`
+    ///
+    /// The Noise handshake then runs end-to-end with the peer, through the relay.
     pub fn dial(
         &self,
         peer_id: PeerId,
@@ -393,10 +706,18 @@ where
         }
 
         // try to connect socket
-        let fut_socket = self.base_transport.dial(peer_id, base_addr)?;
+        let fut_socket = self.base_transport.dial(peer_id, base_addr.clone())?;
 
         // outbound dial upgrade task
-        let upgrade_fut = upgrade_outbound(self.ctxt.clone(), fut_socket, addr, peer_id, pubkey);
+        let upgrade_fut = upgrade_outbound(
+            self.ctxt.clone(),
+            fut_socket,
+            addr,
+            base_addr,
+            peer_id,
+            pubkey,
+            self.lifecycles.clone(),
+        );
         let upgrade_fut = timeout_io(TRANSPORT_TIMEOUT, upgrade_fut);
         Ok(upgrade_fut)
     }
@@ -416,6 +737,21 @@ where
     ///
     /// `/ip4/<ipaddr>/tcp/<port>` or
     /// `/ip6/<ipaddr>/tcp/<port>`
+    ///
+    /// If the base transport is `WebSocketTransport`, then we expect:
+    ///
+    /// `/ip4/<ipaddr>/tcp/<port>/ws` or
+    /// `/ip6/<ipaddr>/tcp/<port>/ws`
+    ///
+    /// If the base transport is `UdsTransport`, then we expect:
+    ///
+    /// `/unix/<percent-encoded path>`
+    ///
+    /// If the base transport is `RelayTransport`, then we may also expect the address of a relay
+    /// to accept the connections through:
+    ///
+    /// `/ip4/<ipaddr>/tcp/<port>/p2p-circuit` or
+    /// `/dns/<name>/tcp/<port>/p2p-circuit`
     pub fn listen_on(
         &self,
         addr: NetworkAddress,
@@ -438,16 +774,18 @@ where
         // (e.g., `/memory/<port>` with no trailers), so we don't need to do any
         // parsing here.
         let (listener, listen_addr) = self.base_transport.listen_on(addr)?;
-        let listen_addr =
-            listen_addr.append_prod_protos(self.identity_pubkey, self.ctxt.handshake_version);
+        let listen_addr = listen_addr
+            .append_prod_protos(self.ctxt.noise.public_key(), self.ctxt.handshake_version);
 
         // need to move a ctxt into stream task
         let ctxt = self.ctxt.clone();
+        let lifecycles = self.lifecycles.clone();
 
         // stream of inbound upgrade tasks
         let inbounds = listener.map_ok(move |(fut_socket, addr)| {
             // inbound upgrade task
-            let fut_upgrade = upgrade_inbound(ctxt.clone(), fut_socket, addr.clone());
+            let fut_upgrade =
+                upgrade_inbound(ctxt.clone(), fut_socket, addr.clone(), lifecycles.clone());
             let fut_upgrade = timeout_io(TRANSPORT_TIMEOUT, fut_upgrade);
             (fut_upgrade, addr)
         });
@@ -499,7 +837,7 @@ mod test {
     use super::*;
     use crate::{
         common::NetworkPublicKeys,
-        protocols::wire::handshake::v1::{ProtocolId, SupportedProtocols},
+        protocols::wire::handshake::v1::{CompressionCodec, ProtocolId, SupportedProtocols},
     };
     use bytes::{Bytes, BytesMut};
     use futures::{executor::block_on, future, io::AsyncWriteExt};
@@ -592,6 +930,7 @@ mod test {
             HANDSHAKE_VERSION,
             NetworkId::Validator,
             supported_protocols.clone(),
+            None,
         );
 
         let dialer_transport = LibraNetTransport::new(
@@ -602,6 +941,7 @@ mod test {
             HANDSHAKE_VERSION,
             NetworkId::Validator,
             supported_protocols.clone(),
+            None,
         );
 
         (
@@ -642,6 +982,26 @@ mod test {
         );
     }
 
+    fn expect_ip4_tcp_ws_noise_addr(addr: &NetworkAddress) {
+        assert!(
+            matches!(
+                addr.as_slice(),
+                [Ip4(_), Tcp(_), Ws, NoiseIK(_), Handshake(_)]
+            ),
+            "addr: '{}'",
+            addr
+        );
+    }
+
+    #[cfg(unix)]
+    fn expect_unix_noise_addr(addr: &NetworkAddress) {
+        assert!(
+            matches!(addr.as_slice(), [Unix(_), NoiseIK(_), Handshake(_)]),
+            "addr: '{}'",
+            addr
+        );
+    }
+
     fn test_transport_success<TTransport>(
         base_transport: TTransport,
         auth: Auth,
@@ -661,6 +1021,8 @@ mod test {
             _trusted_peers,
             supported_protocols,
         ) = setup(base_transport, auth);
+        let listener_transport = listener_transport.with_handshake_extensions(true);
+        let dialer_transport = dialer_transport.with_handshake_extensions(true);
 
         let (mut inbounds, listener_addr) = rt.enter(|| {
             listener_transport
@@ -668,6 +1030,7 @@ mod test {
                 .unwrap()
         });
         expect_formatted_addr(&listener_addr);
+        let listener_addr_clone = listener_addr.clone();
         let supported_protocols_clone = supported_protocols.clone();
 
         // we accept the dialer's inbound connection, check the connection metadata,
@@ -684,12 +1047,17 @@ mod test {
             assert_eq!(conn.metadata.origin, ConnectionOrigin::Inbound);
             assert_eq!(
                 conn.metadata.messaging_protocol,
-                MessagingProtocolVersion::V1
+                MessagingProtocolVersion::V2
             );
             assert_eq!(
                 conn.metadata.application_protocols,
                 supported_protocols_clone,
             );
+            // the dialer reports the address it dialed
+            let observed_addr = conn.metadata.observed_addr().unwrap();
+            assert!(listener_addr_clone
+                .as_slice()
+                .starts_with(observed_addr.as_slice()));
 
             // test the socket works
             let msg = write_read_msg(&mut conn.socket, b"foobar").await;
@@ -713,7 +1081,7 @@ mod test {
             assert_eq!(conn.metadata.origin, ConnectionOrigin::Outbound);
             assert_eq!(
                 conn.metadata.messaging_protocol,
-                MessagingProtocolVersion::V1
+                MessagingProtocolVersion::V2
             );
             assert_eq!(conn.metadata.application_protocols, supported_protocols);
 
@@ -810,6 +1178,69 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_memory_transport_v1_only_listener() {
+        let (
+            mut rt,
+            (listener_peer_id, listener_transport),
+            (dialer_peer_id, dialer_transport),
+            _trusted_peers,
+            _supported_protocols,
+        ) = setup(memory::MemoryTransport, Auth::Mutual);
+        // the listener has the default config, as a node which predates V2 during a rolling
+        // upgrade, while the dialer is upgraded
+        let dialer_transport = dialer_transport.with_handshake_extensions(true);
+
+        let (mut inbounds, listener_addr) = rt.enter(|| {
+            listener_transport
+                .listen_on("/memory/0".parse().unwrap())
+                .unwrap()
+        });
+
+        let listener_task = async move {
+            let (inbound, _dialer_addr) = inbounds.next().await.unwrap().unwrap();
+            let conn = inbound.await.unwrap();
+            assert_eq!(conn.metadata.peer_id, dialer_peer_id);
+            conn.metadata
+        };
+        let dialer_task = async move {
+            let conn = dialer_transport
+                .dial(listener_peer_id, listener_addr)
+                .unwrap()
+                .await
+                .unwrap();
+            conn.metadata
+        };
+
+        let (listener_metadata, dialer_metadata) =
+            rt.block_on(future::join(listener_task, dialer_task));
+        for metadata in &[listener_metadata, dialer_metadata] {
+            assert_eq!(metadata.messaging_protocol, MessagingProtocolVersion::V1);
+            assert_eq!(metadata.compression_codecs().preferred(), None);
+            assert_eq!(metadata.observed_addr(), None);
+        }
+    }
+
+    #[test]
+    fn test_default_handshake_advertises_v1_only() {
+        let (_rt, (_listener_peer_id, transport), _dialer, _trusted_peers, protocols) =
+            setup(memory::MemoryTransport, Auth::Mutual);
+        let (handshake, _extensions) = transport.ctxt.own_handshake(NetworkAddress::mock());
+        // the nodes which predate V2 decode the handshake of a node with the default config
+        let mut v1_handshake = HandshakeMsg::new(NetworkId::Validator);
+        v1_handshake.add(MessagingProtocolVersion::V1, protocols.clone());
+        assert_eq!(
+            lcs::to_bytes(&handshake).unwrap(),
+            lcs::to_bytes(&v1_handshake).unwrap()
+        );
+
+        let transport = transport.with_handshake_extensions(true);
+        let (handshake, _extensions) = transport.ctxt.own_handshake(NetworkAddress::mock());
+        assert!(handshake
+            .supported_protocols
+            .contains_key(&MessagingProtocolVersion::V2));
+    }
+
     #[test]
     fn test_memory_transport_rejects_unauthed_dialer() {
         test_transport_rejects_unauthed_dialer(
@@ -852,6 +1283,63 @@ mod test {
         );
     }
 
+    ///////////////////////////////////////////
+    // LibraNetTransport<WebSocketTransport> //
+    ///////////////////////////////////////////
+
+    #[test]
+    fn test_ws_transport_mutual_auth() {
+        test_transport_success(
+            LIBRA_WS_TRANSPORT.clone(),
+            Auth::Mutual,
+            "/ip4/127.0.0.1/tcp/0/ws",
+            expect_ip4_tcp_ws_noise_addr,
+        );
+    }
+
+    #[test]
+    fn test_ws_transport_rejects_unauthed_dialer() {
+        test_transport_rejects_unauthed_dialer(
+            LIBRA_WS_TRANSPORT.clone(),
+            "/ip4/127.0.0.1/tcp/0/ws",
+            expect_ip4_tcp_ws_noise_addr,
+        );
+    }
+
+    /////////////////////////////////////
+    // LibraNetTransport<UdsTransport> //
+    /////////////////////////////////////
+
+    #[cfg(unix)]
+    fn uds_listen_addr(temppath: &libra_temppath::TempPath) -> String {
+        let path = temppath.path().to_str().unwrap().to_owned();
+        let path = libra_network_address::UnixPath::try_from(path).unwrap();
+        NetworkAddress::from(Unix(path)).to_string()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_uds_transport_mutual_auth() {
+        let temppath = libra_temppath::TempPath::new();
+        test_transport_success(
+            netcore::transport::uds::UdsTransport,
+            Auth::Mutual,
+            &uds_listen_addr(&temppath),
+            expect_unix_noise_addr,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_uds_transport_rejects_unauthed_dialer() {
+        let temppath = libra_temppath::TempPath::new();
+        test_transport_rejects_unauthed_dialer(
+            netcore::transport::uds::UdsTransport,
+            &uds_listen_addr(&temppath),
+            expect_unix_noise_addr,
+        );
+    }
+
     ///////////////////////
     // perform_handshake //
     ///////////////////////
@@ -877,6 +1365,7 @@ mod test {
                 NetworkAddress::mock(),
                 ConnectionOrigin::Inbound,
                 &server_handshake,
+                &HandshakeExtensions::default(),
             )
             .await
             .unwrap_err()
@@ -889,6 +1378,7 @@ mod test {
                 NetworkAddress::mock(),
                 ConnectionOrigin::Outbound,
                 &client_handshake,
+                &HandshakeExtensions::default(),
             )
             .await
             .unwrap_err()
@@ -896,4 +1386,239 @@ mod test {
 
         block_on(future::join(server, client));
     }
+
+    #[test]
+    fn handshake_extensions() {
+        let (outbound, inbound) = MemorySocket::new_pair();
+
+        let protocols: SupportedProtocols = [ProtocolId::ConsensusDirectSend].iter().into();
+        let mut server_handshake = HandshakeMsg::new(NetworkId::Validator);
+        server_handshake.add(MessagingProtocolVersion::V1, protocols.clone());
+        server_handshake.add(MessagingProtocolVersion::V2, protocols.clone());
+        let client_handshake = server_handshake.clone();
+        let mut server_extensions = HandshakeExtensions::new(&protocols);
+        server_extensions.observed_addr = Some(NetworkAddress::mock());
+        let mut client_extensions = server_extensions.clone();
+        // The client only supports lz4
+        client_extensions.compression_codecs = [CompressionCodec::Lz4].iter().into();
+
+        let server = async move {
+            perform_handshake(
+                PeerId::random(),
+                inbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Inbound,
+                &server_handshake,
+                &server_extensions,
+            )
+            .await
+            .unwrap()
+        };
+
+        let client = async move {
+            perform_handshake(
+                PeerId::random(),
+                outbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Outbound,
+                &client_handshake,
+                &client_extensions,
+            )
+            .await
+            .unwrap()
+        };
+
+        let (server, client) = block_on(future::join(server, client));
+        for connection in &[server, client] {
+            assert_eq!(
+                connection.metadata.messaging_protocol,
+                MessagingProtocolVersion::V2
+            );
+            let codecs = connection.metadata.compression_codecs();
+            assert_eq!(codecs.preferred(), Some(CompressionCodec::Lz4));
+            assert!(!codecs.contains(CompressionCodec::Zstd));
+            assert_eq!(
+                connection.metadata.observed_addr(),
+                Some(&NetworkAddress::mock())
+            );
+        }
+    }
+
+    #[test]
+    fn handshake_without_extensions() {
+        let (outbound, inbound) = MemorySocket::new_pair();
+
+        let protocols: SupportedProtocols = [ProtocolId::ConsensusDirectSend].iter().into();
+        let mut server_handshake = HandshakeMsg::new(NetworkId::Validator);
+        server_handshake.add(MessagingProtocolVersion::V1, protocols.clone());
+        server_handshake.add(MessagingProtocolVersion::V2, protocols.clone());
+        // The client doesn't exchange the handshake extensions
+        let mut client_handshake = HandshakeMsg::new(NetworkId::Validator);
+        client_handshake.add(MessagingProtocolVersion::V1, protocols.clone());
+        let mut extensions = HandshakeExtensions::new(&protocols);
+        extensions.observed_addr = Some(NetworkAddress::mock());
+        let server_extensions = extensions.clone();
+
+        let server = async move {
+            perform_handshake(
+                PeerId::random(),
+                inbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Inbound,
+                &server_handshake,
+                &server_extensions,
+            )
+            .await
+            .unwrap()
+        };
+
+        let client = async move {
+            perform_handshake(
+                PeerId::random(),
+                outbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Outbound,
+                &client_handshake,
+                &extensions,
+            )
+            .await
+            .unwrap()
+        };
+
+        let (server, client) = block_on(future::join(server, client));
+        for connection in &[server, client] {
+            assert_eq!(
+                connection.metadata.messaging_protocol,
+                MessagingProtocolVersion::V1
+            );
+            assert_eq!(connection.metadata.compression_codecs().preferred(), None);
+            assert_eq!(connection.metadata.observed_addr(), None);
+        }
+    }
+
+    fn handshake_with(
+        server_protocols: &[ProtocolId],
+        client_protocols: &[ProtocolId],
+    ) -> (
+        io::Result<Connection<MemorySocket>>,
+        io::Result<Connection<MemorySocket>>,
+    ) {
+        let (outbound, inbound) = MemorySocket::new_pair();
+
+        let mut server_handshake = HandshakeMsg::new(NetworkId::Validator);
+        server_handshake.add(MessagingProtocolVersion::V1, server_protocols.iter().into());
+        let mut client_handshake = HandshakeMsg::new(NetworkId::Validator);
+        client_handshake.add(MessagingProtocolVersion::V1, client_protocols.iter().into());
+
+        let server = async move {
+            perform_handshake(
+                PeerId::random(),
+                inbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Inbound,
+                &server_handshake,
+                &HandshakeExtensions::default(),
+            )
+            .await
+        };
+
+        let client = async move {
+            perform_handshake(
+                PeerId::random(),
+                outbound,
+                NetworkAddress::mock(),
+                ConnectionOrigin::Outbound,
+                &client_handshake,
+                &HandshakeExtensions::default(),
+            )
+            .await
+        };
+
+        block_on(future::join(server, client))
+    }
+
+    #[test]
+    fn handshake_no_common_application_protocols() {
+        let (server, client) = handshake_with(
+            &[ProtocolId::ConsensusRpc],
+            &[ProtocolId::MempoolDirectSend],
+        );
+        let server_err = server.unwrap_err();
+        match HandshakeError::from_io_error(&server_err) {
+            Some(HandshakeError::NoCommonApplicationProtocols(peer_protocols)) => {
+                assert!(peer_protocols.contains(ProtocolId::MempoolDirectSend))
+            }
+            err => panic!("unexpected handshake error: {:?}", err),
+        }
+        let client_err = client.unwrap_err();
+        assert!(matches!(
+            HandshakeError::from_io_error(&client_err),
+            Some(HandshakeError::NoCommonApplicationProtocols(_))
+        ));
+    }
+
+    #[test]
+    fn handshake_health_check_only() {
+        let (server, client) = handshake_with(
+            &[ProtocolId::ConsensusRpc, ProtocolId::HealthCheckerRpc],
+            &[ProtocolId::MempoolDirectSend, ProtocolId::HealthCheckerRpc],
+        );
+        assert!(server.unwrap().metadata.is_health_check_only());
+        assert!(client.unwrap().metadata.is_health_check_only());
+
+        let (server, _client) = handshake_with(
+            &[ProtocolId::ConsensusRpc, ProtocolId::HealthCheckerRpc],
+            &[ProtocolId::ConsensusRpc, ProtocolId::HealthCheckerRpc],
+        );
+        assert!(!server.unwrap().metadata.is_health_check_only());
+    }
+
+    #[test]
+    fn transport_application_protocols_extended() {
+        let (
+            mut rt,
+            (listener_peer_id, listener_transport),
+            (_dialer_peer_id, dialer_transport),
+            _trusted_peers,
+            supported_protocols,
+        ) = setup(memory::MemoryTransport, Auth::ServerOnly);
+        let (mut inbounds, listener_addr) = rt.enter(|| {
+            listener_transport
+                .listen_on("/memory/0".parse().unwrap())
+                .unwrap()
+        });
+
+        let mut connect = || {
+            let listener_addr = listener_addr.clone();
+            let dial = dialer_transport
+                .dial(listener_peer_id, listener_addr)
+                .unwrap();
+            let accept = async {
+                let (inbound, _) = inbounds.next().await.unwrap().unwrap();
+                inbound.await.unwrap()
+            };
+            let (outbound, inbound) = rt.block_on(future::join(dial, accept));
+            let outbound = outbound.unwrap();
+            assert_eq!(
+                outbound.metadata.application_protocols,
+                inbound.metadata.application_protocols
+            );
+            outbound.metadata.application_protocols
+        };
+
+        // new protocols are only negotiated once both ends advertise them
+        dialer_transport
+            .application_protocols()
+            .extend(&[ProtocolId::MempoolDirectSend]);
+        assert_eq!(connect(), supported_protocols);
+        listener_transport
+            .application_protocols()
+            .extend(&[ProtocolId::MempoolDirectSend]);
+        let protocols = connect();
+        assert!(protocols.contains(ProtocolId::MempoolDirectSend));
+        assert!(protocols.contains(ProtocolId::ConsensusRpc));
+    }
 }
+
+#[cfg(test)]
+mod transcripts;
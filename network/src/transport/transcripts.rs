@@ -0,0 +1,228 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Golden transcripts of the connection upgrades of `LibraNetTransport`.
+//!
+//! A connection is upgraded over the memory transport with fixed keys, peer ids, ephemeral keys
+//! and anti-replay timestamps, then carries a message each way. The bytes written by each end are
+//! compared with the transcripts checked in under `test_data`, so that an unintended change of
+//! the wire format of the Noise handshake, of the Noise stream or of the LibraNet handshake fails
+//! these tests, rather than the connections with the nodes of the previous release.
+//!
+//! When the wire format changes on purpose, e.g., with a new handshake version, re-record the
+//! transcripts with `UPDATE_TRANSCRIPTS=1 cargo test -p network transcripts`, and review their
+//! diff. A missing transcript fails the tests, unless it is recorded that way.
+
+use super::*;
+use futures::{
+    executor::block_on,
+    future,
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::{Context, Poll},
+};
+use libra_crypto::{test_utils::TEST_SEED, traits::Uniform};
+use memsocket::MemorySocket;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// The anti-replay timestamps of the dialer start after this one, in milliseconds.
+const TIMESTAMP: u64 = 1_600_000_000_000;
+
+/// Memory socket recording the bytes written to it.
+#[derive(Debug)]
+struct RecordingSocket {
+    inner: MemorySocket,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl RecordingSocket {
+    fn new(inner: MemorySocket) -> (Self, Arc<Mutex<Vec<u8>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let socket = Self {
+            inner,
+            written: written.clone(),
+        };
+        (socket, written)
+    }
+}
+
+impl AsyncRead for RecordingSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(context, buf)
+    }
+}
+
+impl AsyncWrite for RecordingSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(context, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.written.lock().unwrap().extend_from_slice(&buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(context)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(context)
+    }
+}
+
+/// Upgrades a connection between a dialer and a listener with fixed identities, exchanges a
+/// message each way, and returns the bytes written by each end, as lines of hex.
+fn record_transcript(mutual_auth: bool) -> String {
+    let mut rng = StdRng::from_seed(TEST_SEED);
+    let listener_key = x25519::PrivateKey::generate(&mut rng);
+    let dialer_key = x25519::PrivateKey::generate(&mut rng);
+    let listener_pubkey = listener_key.public_key();
+    let dialer_pubkey = dialer_key.public_key();
+    let listener_peer_id = PeerId::from_identity_public_key(listener_pubkey);
+    let dialer_peer_id = PeerId::from_identity_public_key(dialer_pubkey);
+
+    let auth_mode = || {
+        if mutual_auth {
+            let trusted_peers = vec![
+                (
+                    listener_peer_id,
+                    NetworkPublicKeys {
+                        identity_public_key: listener_pubkey,
+                    },
+                ),
+                (
+                    dialer_peer_id,
+                    NetworkPublicKeys {
+                        identity_public_key: dialer_pubkey,
+                    },
+                ),
+            ];
+            HandshakeAuthMode::mutual(Arc::new(RwLock::new(trusted_peers.into_iter().collect())))
+        } else {
+            HandshakeAuthMode::ServerOnly
+        }
+    };
+    let protocols = SupportedProtocols::from(
+        [ProtocolId::ConsensusRpc, ProtocolId::DiscoveryDirectSend].iter(),
+    );
+    let ctxt = |peer_id, key, seed| {
+        Arc::new(UpgradeContext {
+            noise: NoiseUpgrader::new(peer_id, key, auth_mode())
+                .with_deterministic_source(seed, TIMESTAMP),
+            handshake_version: HANDSHAKE_VERSION,
+            network_id: NetworkId::Validator,
+            application_protocols: ApplicationProtocols::new(protocols.clone()),
+        })
+    };
+    let dialer_ctxt = ctxt(dialer_peer_id, dialer_key, [1u8; 32]);
+    let listener_ctxt = ctxt(listener_peer_id, listener_key, [2u8; 32]);
+
+    let (dialer_socket, listener_socket) = MemorySocket::new_pair();
+    let (dialer_socket, dialer_written) = RecordingSocket::new(dialer_socket);
+    let (listener_socket, listener_written) = RecordingSocket::new(listener_socket);
+    let listen_addr: NetworkAddress = "/memory/6180".parse().unwrap();
+    let dialer_addr: NetworkAddress = "/memory/6181".parse().unwrap();
+
+    let outbound = upgrade_outbound(
+        dialer_ctxt,
+        future::ready(Ok(dialer_socket)),
+        listen_addr
+            .clone()
+            .append_prod_protos(listener_pubkey, HANDSHAKE_VERSION),
+        listen_addr,
+        listener_peer_id,
+        listener_pubkey,
+        PeerLifecycles::new(),
+    );
+    let inbound = upgrade_inbound(
+        listener_ctxt,
+        future::ready(Ok(listener_socket)),
+        dialer_addr,
+        PeerLifecycles::new(),
+    );
+    let (outbound, inbound) = block_on(future::join(outbound, inbound));
+    let (mut outbound, mut inbound) = (outbound.unwrap(), inbound.unwrap());
+    assert_eq!(outbound.metadata.peer_id(), listener_peer_id);
+    assert_eq!(inbound.metadata.peer_id(), dialer_peer_id);
+
+    block_on(async {
+        let mut buf = [0u8; 4];
+        outbound.socket.write_all(b"ping").await.unwrap();
+        outbound.socket.flush().await.unwrap();
+        inbound.socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        inbound.socket.write_all(b"pong").await.unwrap();
+        inbound.socket.flush().await.unwrap();
+        outbound.socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    });
+
+    let mut transcript = String::new();
+    for (end, written) in &[("dialer", dialer_written), ("listener", listener_written)] {
+        transcript.push_str(&format!("# {}\n", end));
+        for line in written.lock().unwrap().chunks(32) {
+            transcript.push_str(&hex::encode(line));
+            transcript.push('\n');
+        }
+    }
+    transcript
+}
+
+fn transcript_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/transport/test_data")
+        .join(format!("{}.txt", name))
+}
+
+/// Compares the transcript with the one checked in as `name`, or records it with
+/// `UPDATE_TRANSCRIPTS`.
+fn check_transcript(name: &str, transcript: &str) {
+    let path = transcript_path(name);
+    if std::env::var("UPDATE_TRANSCRIPTS").is_ok() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, transcript).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "Missing transcript {}: {}. Record it with UPDATE_TRANSCRIPTS=1.",
+            path.display(),
+            e
+        )
+    });
+    assert!(
+        expected == transcript,
+        "The wire format of the connection upgrade changed, see {}. If this is intended, \
+         re-record the transcripts with UPDATE_TRANSCRIPTS=1.\nexpected:\n{}\nactual:\n{}",
+        path.display(),
+        expected,
+        transcript
+    );
+}
+
+#[test]
+fn transcripts_are_reproducible() {
+    assert_eq!(record_transcript(true), record_transcript(true));
+}
+
+#[test]
+fn mutual_auth_transcript() {
+    check_transcript("mutual_auth", &record_transcript(true));
+}
+
+#[test]
+fn server_only_auth_transcript() {
+    check_transcript("server_only_auth", &record_transcript(false));
+}
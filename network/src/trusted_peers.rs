@@ -0,0 +1,183 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistence of the trusted peers of a network, and diffs of their updates.
+//!
+//! The trusted peers of a validator network come from the validator set on chain, which is only
+//! known once storage is open. When the network has a trusted peers file, the connectivity manager
+//! persists every update of the trusted peers to it, along with the epoch of the validator set they
+//! came from, and the network builder loads them back on restart, so that the node authenticates
+//! its validator peers right away.
+//!
+//! Every update is also notified to the connection event listeners of the network as a
+//! [`ConnectionNotification::TrustedPeersUpdated`], listing the peers added, removed, and whose
+//! keys changed.
+//!
+//! [`ConnectionNotification::TrustedPeersUpdated`]:
+//! crate::peer_manager::ConnectionNotification::TrustedPeersUpdated
+
+use crate::common::NetworkPublicKeys;
+use anyhow::{Context, Result};
+use libra_types::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+/// The changes of the trusted peers of a network on an update.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TrustedPeersDiff {
+    /// Epoch of the validator set of the new trusted peers, if they came from the chain.
+    pub epoch: Option<u64>,
+    pub added: HashMap<PeerId, NetworkPublicKeys>,
+    pub removed: HashSet<PeerId>,
+    /// The new keys of the peers whose keys changed.
+    pub key_changed: HashMap<PeerId, NetworkPublicKeys>,
+}
+
+impl TrustedPeersDiff {
+    pub fn new(
+        epoch: Option<u64>,
+        old: &HashMap<PeerId, NetworkPublicKeys>,
+        new: &HashMap<PeerId, NetworkPublicKeys>,
+    ) -> Self {
+        let mut diff = Self {
+            epoch,
+            ..Self::default()
+        };
+        for (peer_id, keys) in new {
+            match old.get(peer_id) {
+                None => {
+                    diff.added.insert(*peer_id, keys.clone());
+                }
+                Some(old_keys) if old_keys != keys => {
+                    diff.key_changed.insert(*peer_id, keys.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|peer_id| !new.contains_key(peer_id))
+            .copied()
+            .collect();
+        diff
+    }
+
+    /// Whether the update left the trusted peers unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.key_changed.is_empty()
+    }
+}
+
+/// The trusted peers of a network, as persisted in its trusted peers file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PersistedTrustedPeers {
+    /// Epoch of the validator set the peers came from, if they came from the chain.
+    pub epoch: Option<u64>,
+    pub peers: HashMap<PeerId, NetworkPublicKeys>,
+}
+
+impl PersistedTrustedPeers {
+    /// Loads the trusted peers persisted in `path`, `None` if nothing was persisted yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read trusted peers from {:?}", path))
+            }
+        };
+        lcs::from_bytes(&bytes)
+            .map(Some)
+            .with_context(|| format!("Failed to parse trusted peers from {:?}", path))
+    }
+
+    /// Persists the trusted peers to `path`, replacing its content atomically, so that a crash
+    /// never leaves a truncated file behind.
+    pub fn store(&self, path: &Path) -> Result<()> {
+        let bytes = lcs::to_bytes(self)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, &bytes)
+            .with_context(|| format!("Failed to write trusted peers to {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to write trusted peers to {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_crypto::{x25519, Uniform};
+    use libra_temppath::TempPath;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn keys(rng: &mut StdRng) -> NetworkPublicKeys {
+        NetworkPublicKeys {
+            identity_public_key: x25519::PrivateKey::generate(rng).public_key(),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let (kept, removed, changed, added) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        let kept_keys = keys(&mut rng);
+        let old: HashMap<_, _> = vec![
+            (kept, kept_keys.clone()),
+            (removed, keys(&mut rng)),
+            (changed, keys(&mut rng)),
+        ]
+        .into_iter()
+        .collect();
+        let (changed_keys, added_keys) = (keys(&mut rng), keys(&mut rng));
+        let new: HashMap<_, _> = vec![
+            (kept, kept_keys),
+            (changed, changed_keys.clone()),
+            (added, added_keys.clone()),
+        ]
+        .into_iter()
+        .collect();
+
+        let diff = TrustedPeersDiff::new(Some(3), &old, &new);
+        assert_eq!(diff.epoch, Some(3));
+        assert_eq!(diff.added, vec![(added, added_keys)].into_iter().collect());
+        assert_eq!(diff.removed, vec![removed].into_iter().collect());
+        assert_eq!(
+            diff.key_changed,
+            vec![(changed, changed_keys)].into_iter().collect()
+        );
+        assert!(TrustedPeersDiff::new(None, &new, &new).is_empty());
+    }
+
+    #[test]
+    fn load_and_store() {
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let path = TempPath::new();
+        assert_eq!(PersistedTrustedPeers::load(path.path()).unwrap(), None);
+
+        let trusted_peers = PersistedTrustedPeers {
+            epoch: Some(7),
+            peers: vec![(PeerId::random(), keys(&mut rng))]
+                .into_iter()
+                .collect(),
+        };
+        trusted_peers.store(path.path()).unwrap();
+        assert_eq!(
+            PersistedTrustedPeers::load(path.path()).unwrap(),
+            Some(trusted_peers)
+        );
+
+        fs::write(path.path(), b"garbage").unwrap();
+        assert!(PersistedTrustedPeers::load(path.path()).is_err());
+    }
+}
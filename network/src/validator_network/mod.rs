@@ -5,3 +5,4 @@
 
 pub use crate::protocols::rpc::error::RpcError;
 pub mod network_builder;
+pub mod network_handle;
@@ -10,41 +10,85 @@
 //! connect to or accept connections from an end-point running in authenticated mode as
 //! long as the latter is in its trusted peers set.
 use crate::{
+    address_book::AddressBook,
+    ban_list::BanList,
+    bandwidth::{BandwidthManager, ThrottledTransport},
     common::NetworkPublicKeys,
-    connectivity_manager::{ConnectivityManager, ConnectivityRequest},
+    connected_peers::ConnectedPeers,
+    connection_limits::{InboundConnectionGate, InboundConnectionLimits},
+    connectivity_manager::{ConnectivityManager, ConnectivityRequest, ReachabilityReporter},
     counters,
+    eviction::{DefaultEvictionPolicy, EvictionPolicy, PeerScores},
+    mdns::{self, MdnsDiscovery},
+    nat::{self, PortMapper},
+    network_events::{NetworkEvent, NetworkEvents},
+    network_info::NetworkInfo,
+    noise::{NoiseKeyProvider, NoiseKeyProviderError, NoiseKeylog, NoiseRelayAuth},
+    observed_addrs::ObservedAddrs,
+    onchain_discovery::ConfigurationChangeListener,
     peer_manager::{
-        conn_notifs_channel, ConnectionRequest, ConnectionRequestSender, PeerManager,
-        PeerManagerNotification, PeerManagerRequest, PeerManagerRequestSender,
+        conn_notifs_channel, lifecycle::PeerLifecycles, ConnectionNotification, ConnectionRequest,
+        ConnectionRequestSender, PeerActivity, PeerEncodings, PeerManager, PeerManagerNotification,
+        PeerManagerRequest, PeerManagerRequestSender,
     },
+    priority::{ProtocolPriorities, ProtocolPriority},
     protocols::{
-        discovery::{self, Discovery},
-        health_checker::{self, HealthChecker},
+        dht::{self, Dht, PeerRecord, SignedPeerRecord},
+        dial_back::{self, DialBack, TransportProber},
+        discovery::{self, Discovery, DiscoveryMetadata, PeerMetadata},
+        health_checker::{self, HealthChecker, PeerRtts, PingPolicy},
+        latency::{self, LatencyMatrix, LatencyProber},
+        pex::{self, PeerExchange},
         wire::handshake::v1::SupportedProtocols,
     },
-    transport::{self, Connection, LibraNetTransport, LIBRA_TCP_TRANSPORT},
+    quota::QuotaLimits,
+    rate_limit::{InboundRateLimits, RateLimit, RateLimitPolicy},
+    transport::{
+        self, ApplicationProtocols, Connection, IdentityKeyRotator, LibraNetTransport,
+        LIBRA_TCP_TRANSPORT,
+    },
+    trusted_peers::{PersistedTrustedPeers, TrustedPeersDiff},
+    validator_network::network_handle::{NetworkHandle, NetworkTask, ProtocolHandlerSenders},
     ProtocolId,
 };
 use channel::{self, libra_channel, message_queues::QueueStyle};
-use futures::stream::StreamExt;
+use futures::{
+    channel::mpsc,
+    stream::{Fuse, StreamExt},
+};
 use libra_config::{
     config::{RoleType, HANDSHAKE_VERSION},
     network_id::NetworkId,
 };
-use libra_crypto::x25519;
+use libra_crypto::{ed25519::Ed25519PrivateKey, x25519, PrivateKey, ValidCryptoMaterial};
 use libra_logger::prelude::*;
 use libra_metrics::IntCounterVec;
-use libra_network_address::NetworkAddress;
-use libra_types::PeerId;
-use netcore::transport::{memory, Transport};
+use libra_network_address::{NetworkAddress, Protocol};
+use libra_types::{on_chain_config::OnChainConfigPayload, PeerId};
+use netcore::transport::{
+    memory,
+    relay::{RelayServer, RelayTransport},
+    tcp::TcpTransport,
+    websocket::WebSocketTransport,
+    Transport, TransportExt,
+};
 use std::{
     clone::Clone,
     collections::HashMap,
+    convert::TryFrom,
+    io, mem,
+    net::{Ipv4Addr, SocketAddrV4},
     num::NonZeroUsize,
+    path::PathBuf,
     sync::{Arc, RwLock},
     time::Duration,
 };
-use tokio::{runtime::Handle, time::interval};
+use thiserror::Error;
+use tokio::{
+    runtime::Handle,
+    sync::broadcast,
+    time::{interval, Interval},
+};
 use tokio_retry::strategy::ExponentialBackoff;
 
 // NB: Almost all of these values are educated guesses, and not determined using any empirical
@@ -64,6 +108,56 @@ pub const PING_FAILURES_TOLERATED: u64 = 10;
 pub const MAX_CONCURRENT_NETWORK_REQS: usize = 100;
 pub const MAX_CONCURRENT_NETWORK_NOTIFS: usize = 100;
 pub const MAX_CONNECTION_DELAY_MS: u64 = 10 * 60 * 1000 /* 10 minutes */;
+pub const DIAL_STAGGER_MS: u64 = 250;
+pub const SHUTDOWN_TIMEOUT_MS: u64 = 10_000;
+// Presets, see `NetworkBuilder::validator_defaults` and `NetworkBuilder::public_fullnode_defaults`.
+pub const VALIDATOR_PING_TIMEOUT_MS: u64 = 5_000;
+pub const VALIDATOR_PING_FAILURES_TOLERATED: u64 = 3;
+pub const PUBLIC_FULLNODE_PING_INTERVAL_MS: u64 = 5_000;
+pub const PUBLIC_FULLNODE_DISCOVERY_INTERVAL_MS: u64 = 30_000;
+pub const PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS: usize = 100;
+pub const PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS_PER_IP: usize = 16;
+
+/// Misconfigurations of a [`NetworkBuilder`], reported when the network is set up.
+#[derive(Debug, Error)]
+pub enum NetworkBuilderError {
+    #[error("Authentication Mode not set")]
+    AuthenticationModeNotSet,
+
+    #[error("ConnectivityManager not enabled")]
+    ConnectivityManagerNotEnabled,
+
+    #[error("The DHT signing key doesn't match the PeerId of the network")]
+    DhtSigningKeyMismatch,
+
+    #[error("The discovery signing key doesn't match the network identity key")]
+    DiscoverySigningKeyMismatch,
+
+    #[error(transparent)]
+    IdentityKeyUnavailable(#[from] NoiseKeyProviderError),
+
+    #[error(
+        "The network requires mutual authentication, but its authentication mode is ServerOnly"
+    )]
+    MutualAuthenticationRequired,
+
+    #[error("Failed to bind the mDNS socket: {0}")]
+    MdnsUnavailable(io::Error),
+
+    #[error("No NAT gateway to map the listen port on")]
+    NatGatewayUnavailable,
+
+    #[error("Failed to bind the relay listener: {0}")]
+    RelayUnavailable(io::Error),
+
+    #[error(
+        "Unsupported listen_address: '{0}', expected '/memory/<port>', \
+         '/ip4/<addr>/tcp/<port>', '/ip6/<addr>/tcp/<port>', \
+         '/ip4/<addr>/tcp/<port>/ws', '/ip6/<addr>/tcp/<port>/ws', \
+         '/<relay>/tcp/<port>/p2p-circuit', or '/unix/<path>'."
+    )]
+    UnsupportedListenAddress(NetworkAddress),
+}
 
 #[derive(Debug)]
 pub enum AuthenticationMode {
@@ -90,6 +184,60 @@ impl AuthenticationMode {
             }
         }
     }
+
+    /// A copy of the inner network identity key, for the handshakes of the reservations on a
+    /// relay.
+    fn relay_key(&self) -> x25519::PrivateKey {
+        match self {
+            AuthenticationMode::ServerOnly(key) | AuthenticationMode::Mutual(key) => {
+                x25519::PrivateKey::try_from(key.to_bytes().as_slice())
+                    .expect("a copy of a valid key is valid")
+            }
+        }
+    }
+}
+
+/// An additional listener of a network, accepting the peers of another network on the same
+/// PeerManager. See [`NetworkBuilder::add_listener`].
+struct AdditionalListener {
+    network_id: NetworkId,
+    listen_address: NetworkAddress,
+    authentication_mode: AuthenticationMode,
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    protocols: Vec<ProtocolId>,
+}
+
+/// Base transport of the connections on a listen address.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum BaseTransport {
+    Tcp,
+    WebSocket,
+    #[cfg(unix)]
+    Unix,
+    Memory,
+}
+
+impl BaseTransport {
+    fn of_listen_address(listen_address: &NetworkAddress) -> Result<Self, NetworkBuilderError> {
+        use libra_network_address::Protocol::*;
+
+        match listen_address.as_slice() {
+            [Ip4(_), Tcp(_)] | [Ip6(_), Tcp(_)] => Ok(BaseTransport::Tcp),
+            // Circuits through a relay, which is dialed over TCP.
+            [Ip4(_), Tcp(_), P2pCircuit]
+            | [Ip6(_), Tcp(_), P2pCircuit]
+            | [Dns(_), Tcp(_), P2pCircuit]
+            | [Dns4(_), Tcp(_), P2pCircuit]
+            | [Dns6(_), Tcp(_), P2pCircuit] => Ok(BaseTransport::Tcp),
+            [Ip4(_), Tcp(_), Ws] | [Ip6(_), Tcp(_), Ws] => Ok(BaseTransport::WebSocket),
+            #[cfg(unix)]
+            [Unix(_)] => Ok(BaseTransport::Unix),
+            [Memory(_)] => Ok(BaseTransport::Memory),
+            _ => Err(NetworkBuilderError::UnsupportedListenAddress(
+                listen_address.clone(),
+            )),
+        }
+    }
 }
 
 /// Build Network module with custom configuration values.
@@ -104,22 +252,71 @@ pub struct NetworkBuilder {
     network_id: NetworkId,
     peer_id: PeerId,
     role: RoleType,
-    // TODO(philiphayes): better support multiple listening addrs
-    listen_address: NetworkAddress,
-    advertised_address: Option<NetworkAddress>,
+    listen_addresses: Vec<NetworkAddress>,
+    listeners: Vec<AdditionalListener>,
+    dual_stack: bool,
+    /// Socket options of the TCP connections, see `tcp_transport`
+    tcp_options: TcpTransport,
+    advertised_addresses: Vec<NetworkAddress>,
     seed_peers: HashMap<PeerId, Vec<NetworkAddress>>,
     trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    /// File to which the connectivity manager persists the trusted peers
+    trusted_peers_file: Option<PathBuf>,
+    /// Updates of the trusted peers by the connectivity manager, for the connection event listeners
+    trusted_peers_updates_rx: Option<channel::Receiver<TrustedPeersDiff>>,
+    /// Store of the addresses at which the connectivity manager reaches the peers, and their
+    /// staleness
+    address_book: Option<(Box<dyn AddressBook>, Duration)>,
     authentication_mode: Option<AuthenticationMode>,
+    /// Provider of the network identity key of the authentication mode, if any
+    key_provider: Option<Arc<dyn NoiseKeyProvider>>,
     channel_size: usize,
     direct_send_protocols: Vec<ProtocolId>,
     rpc_protocols: Vec<ProtocolId>,
     discovery_interval_ms: u64,
+    discovery_metadata: DiscoveryMetadata,
+    /// Whether gossip discovery only advertises the addresses verified by dial-back
+    verify_advertised_addresses: bool,
+    /// Dial-back actor added with gossip discovery, spawned with the base transport on build
+    dial_back: Option<DialBack<Fuse<Interval>>>,
+    /// Mapper of the listen port on the NAT gateway, spawned once the port is bound on build
+    nat_port_mapper: Option<PortMapper>,
+    /// External addresses sent by the port mapper, advertised by gossip discovery
+    external_addrs_rx: Option<mpsc::UnboundedReceiver<Option<NetworkAddress>>>,
+    peer_metadata: PeerMetadata,
+    connected_peers: ConnectedPeers,
+    /// Encodings negotiated with the connected peers, maintained by the peer manager
+    peer_encodings: PeerEncodings,
+    /// Addresses of this node observed by the connected peers, maintained by the peer manager
+    observed_addrs: ObservedAddrs,
+    /// States of the connections with the peers, maintained by the peer manager and the transport
+    peer_lifecycles: PeerLifecycles,
+    /// Publisher of the events of the network, to the subscribers added with
+    /// `add_event_subscriber`
+    network_events: NetworkEvents,
+    /// Round-trip times of the pings of the connected peers, measured by the health checker
+    peer_rtts: PeerRtts,
+    /// Latencies between the validators, measured if the latency protocol was added
+    latency_matrix: LatencyMatrix,
+    /// Bans, shared by the connectivity manager and the peer manager
+    ban_list: BanList,
+    /// Scores of the peers, shared by the applications and the default eviction policy
+    peer_scores: PeerScores,
+    eviction_policy: Option<Arc<dyn EvictionPolicy>>,
     ping_interval_ms: u64,
     ping_timeout_ms: u64,
     ping_failures_tolerated: u64,
+    /// How the health checker adapts its pings to the activity of the peers, if it does
+    ping_policy: Option<PingPolicy>,
+    /// When the last message was received from the connected peers, maintained by the peer manager
+    peer_activity: PeerActivity,
     upstream_handlers:
         HashMap<ProtocolId, libra_channel::Sender<(PeerId, ProtocolId), PeerManagerNotification>>,
     connection_event_handlers: Vec<conn_notifs_channel::Sender>,
+    /// Connection event handlers of the protocol handlers, by protocols handled.
+    protocol_connection_event_handlers: Vec<(Vec<ProtocolId>, conn_notifs_channel::Sender)>,
+    /// Priorities of the outbound messages of the protocols handled, shared with the peer manager
+    protocol_priorities: ProtocolPriorities,
     pm_reqs_tx: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerRequest>,
     pm_reqs_rx: libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerRequest>,
     connection_reqs_tx: libra_channel::Sender<PeerId, ConnectionRequest>,
@@ -129,21 +326,50 @@ pub struct NetworkBuilder {
     max_concurrent_network_reqs: usize,
     max_concurrent_network_notifs: usize,
     max_connection_delay_ms: u64,
+    /// Delay after which the next address of a peer is dialed while the previous dials are
+    /// pending, `None` to dial the addresses one at a time
+    dial_stagger: Option<Duration>,
+    noise_keylog: Option<Arc<NoiseKeylog>>,
+    /// Whether the messaging protocol V2, with the handshake extensions, is advertised
+    handshake_extensions: bool,
+    quota_limits: QuotaLimits,
+    inbound_connection_limits: InboundConnectionLimits,
+    /// Pauses the acceptance of inbound connections, shared with the peer manager
+    inbound_connection_gate: InboundConnectionGate,
+    inbound_rate_limits: InboundRateLimits,
+    max_outbound_bytes_per_sec: Option<u64>,
+    max_inbound_bytes_per_sec: Option<u64>,
+    /// Time given to the network to drain its RPCs in flight and close its connections on shutdown
+    shutdown_timeout_ms: u64,
+    /// Actors using the peer manager, stopped before it on shutdown
+    actors: Vec<NetworkTask>,
+    /// Tasks forwarding the events of the peer manager, stopped after it on shutdown
+    tasks: Vec<NetworkTask>,
+    /// Actors notified of the new public keys of the network identity when it is rotated
+    identity_key_listeners: Vec<mpsc::UnboundedSender<x25519::PublicKey>>,
+    /// Whether `build` fails if the authentication mode isn't `Mutual`, as set by the presets
+    require_mutual_authentication: bool,
 }
 
 impl NetworkBuilder {
-    /// Return a new NetworkBuilder initialized with default configuration values.
+    /// Return a new NetworkBuilder initialized with default configuration values, listening on
+    /// all the `listen_addresses`, e.g., on both an IPv4 and an IPv6 address.
     pub fn new(
         executor: Handle,
         network_id: NetworkId,
         peer_id: PeerId,
         role: RoleType,
-        listen_address: NetworkAddress,
+        listen_addresses: Vec<NetworkAddress>,
     ) -> NetworkBuilder {
         // Setup channel to send requests to peer manager.
-        let (pm_reqs_tx, pm_reqs_rx) = libra_channel::new(
-            QueueStyle::FIFO,
+        // RPC requests carry a deadline, past which they are dropped instead of being forwarded.
+        // The requests of the protocols of higher priority preempt the others.
+        let protocol_priorities = ProtocolPriorities::new();
+        let priorities = protocol_priorities.clone();
+        let (pm_reqs_tx, pm_reqs_rx) = libra_channel::new_with_priorities(
+            QueueStyle::DEADLINE,
             NonZeroUsize::new(NETWORK_CHANNEL_SIZE).unwrap(),
+            move |(_, protocol): &(PeerId, ProtocolId)| priorities.get(*protocol) as u8,
             Some(&counters::PENDING_PEER_MANAGER_REQUESTS),
         );
         // Setup channel to send connection requests to peer manager.
@@ -157,32 +383,142 @@ impl NetworkBuilder {
             network_id,
             peer_id,
             role,
-            listen_address,
-            advertised_address: None,
+            listen_addresses,
+            listeners: Vec::new(),
+            dual_stack: false,
+            tcp_options: LIBRA_TCP_TRANSPORT,
+            advertised_addresses: Vec::new(),
             seed_peers: HashMap::new(),
             trusted_peers: Arc::new(RwLock::new(HashMap::new())),
+            trusted_peers_file: None,
+            trusted_peers_updates_rx: None,
+            address_book: None,
             authentication_mode: None,
+            key_provider: None,
             channel_size: NETWORK_CHANNEL_SIZE,
             direct_send_protocols: vec![],
             rpc_protocols: vec![],
             upstream_handlers: HashMap::new(),
             connection_event_handlers: Vec::new(),
+            protocol_connection_event_handlers: Vec::new(),
+            protocol_priorities,
             pm_reqs_tx,
             pm_reqs_rx,
             connection_reqs_tx,
             connection_reqs_rx,
             conn_mgr_reqs_tx: None,
             discovery_interval_ms: DISCOVERY_INTERVAL_MS,
+            discovery_metadata: DiscoveryMetadata::new(),
+            verify_advertised_addresses: false,
+            dial_back: None,
+            nat_port_mapper: None,
+            external_addrs_rx: None,
+            peer_metadata: PeerMetadata::new(),
+            connected_peers: ConnectedPeers::new(),
+            peer_encodings: PeerEncodings::new(),
+            observed_addrs: ObservedAddrs::new(),
+            peer_lifecycles: PeerLifecycles::new(),
+            network_events: NetworkEvents::new(),
+            peer_rtts: PeerRtts::new(),
+            latency_matrix: LatencyMatrix::new(),
+            ban_list: BanList::new(),
+            peer_scores: PeerScores::new(),
+            eviction_policy: None,
             ping_interval_ms: PING_INTERVAL_MS,
             ping_timeout_ms: PING_TIMEOUT_MS,
             ping_failures_tolerated: PING_FAILURES_TOLERATED,
+            ping_policy: None,
+            peer_activity: PeerActivity::new(),
             connectivity_check_interval_ms: CONNECTIVITY_CHECK_INTERNAL_MS,
             max_concurrent_network_reqs: MAX_CONCURRENT_NETWORK_REQS,
             max_concurrent_network_notifs: MAX_CONCURRENT_NETWORK_NOTIFS,
             max_connection_delay_ms: MAX_CONNECTION_DELAY_MS,
+            dial_stagger: Some(Duration::from_millis(DIAL_STAGGER_MS)),
+            noise_keylog: None,
+            handshake_extensions: false,
+            quota_limits: QuotaLimits::default(),
+            inbound_connection_limits: InboundConnectionLimits::default(),
+            inbound_connection_gate: InboundConnectionGate::new(),
+            inbound_rate_limits: InboundRateLimits::default(),
+            max_outbound_bytes_per_sec: None,
+            max_inbound_bytes_per_sec: None,
+            shutdown_timeout_ms: SHUTDOWN_TIMEOUT_MS,
+            actors: Vec::new(),
+            tasks: Vec::new(),
+            identity_key_listeners: Vec::new(),
+            require_mutual_authentication: false,
         }
     }
 
+    /// Return a new NetworkBuilder for the validator network, which must authenticate its peers
+    /// mutually, and pings them aggressively, so that consensus redials a failed validator within
+    /// seconds. The inbound connections are not limited, since only the validators are accepted.
+    /// The peers are expected to be discovered on chain.
+    pub fn validator_defaults(
+        executor: Handle,
+        peer_id: PeerId,
+        listen_addresses: Vec<NetworkAddress>,
+    ) -> NetworkBuilder {
+        let mut builder = Self::new(
+            executor,
+            NetworkId::Validator,
+            peer_id,
+            RoleType::Validator,
+            listen_addresses,
+        );
+        builder.require_mutual_authentication = true;
+        builder.ping_timeout_ms = VALIDATOR_PING_TIMEOUT_MS;
+        builder.ping_failures_tolerated = VALIDATOR_PING_FAILURES_TOLERATED;
+        builder
+    }
+
+    /// Return a new NetworkBuilder for the network between a validator and the full nodes it
+    /// operates, which must authenticate its peers mutually. The health checks and connection
+    /// limits are the defaults, and the peers are expected to be the seed peers of the config.
+    pub fn vfn_defaults(
+        executor: Handle,
+        peer_id: PeerId,
+        listen_addresses: Vec<NetworkAddress>,
+    ) -> NetworkBuilder {
+        let mut builder = Self::new(
+            executor,
+            NetworkId::vfn_network(),
+            peer_id,
+            RoleType::FullNode,
+            listen_addresses,
+        );
+        builder.require_mutual_authentication = true;
+        builder
+    }
+
+    /// Return a new NetworkBuilder for the public full-node network, which accepts any peer, up
+    /// to [`PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS`], and up to
+    /// [`PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS_PER_IP`] from the same IP address. The peers are
+    /// pinged less often than on the other networks, only while idle and with a backoff, see
+    /// [`PingPolicy`], and are expected to be discovered with
+    /// [`add_dht_discovery`](NetworkBuilder::add_dht_discovery), whose lookups run every
+    /// [`PUBLIC_FULLNODE_DISCOVERY_INTERVAL_MS`].
+    pub fn public_fullnode_defaults(
+        executor: Handle,
+        peer_id: PeerId,
+        listen_addresses: Vec<NetworkAddress>,
+    ) -> NetworkBuilder {
+        let mut builder = Self::new(
+            executor,
+            NetworkId::Public,
+            peer_id,
+            RoleType::FullNode,
+            listen_addresses,
+        );
+        builder.ping_interval_ms = PUBLIC_FULLNODE_PING_INTERVAL_MS;
+        builder.ping_policy = Some(PingPolicy::default());
+        builder.discovery_interval_ms = PUBLIC_FULLNODE_DISCOVERY_INTERVAL_MS;
+        builder
+            .max_inbound_connections(PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS)
+            .max_inbound_connections_per_ip(PUBLIC_FULLNODE_MAX_INBOUND_CONNECTIONS_PER_IP);
+        builder
+    }
+
     pub fn peer_id(&self) -> PeerId {
         self.peer_id
     }
@@ -193,12 +529,88 @@ impl NetworkBuilder {
         self
     }
 
-    /// Set an address to advertise, if different from the listen address
+    /// Set network authentication mode, e.g., `AuthenticationMode::Mutual`, with the network
+    /// identity key fetched from `key_provider`, e.g., secure storage, instead of a key in memory.
+    /// The handle of the network refreshes the key from the provider, see
+    /// [`NetworkHandle::refresh_identity_key`].
+    ///
+    /// Fails if the provider fails to fetch the key.
+    pub fn authentication_mode_from_provider(
+        &mut self,
+        authentication_mode: fn(x25519::PrivateKey) -> AuthenticationMode,
+        key_provider: Arc<dyn NoiseKeyProvider>,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let key = key_provider.private_key()?;
+        self.authentication_mode = Some(authentication_mode(key));
+        self.key_provider = Some(key_provider);
+        Ok(self)
+    }
+
+    /// Add an address to advertise, if different from the listen addresses, e.g., each of the
+    /// IPv4 and IPv6 addresses of a dual-stack listen address.
     pub fn advertised_address(&mut self, advertised_address: NetworkAddress) -> &mut Self {
-        self.advertised_address = Some(advertised_address);
+        self.advertised_addresses.push(advertised_address);
+        self
+    }
+
+    /// Set whether the TCP listen addresses on `::` also accept IPv4 connections, whatever the
+    /// default of the host.
+    pub fn dual_stack(&mut self, dual_stack: bool) -> &mut Self {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// Set whether `TCP_NODELAY` is set on the TCP connections, which it is by default.
+    pub fn tcp_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.tcp_options.nodelay = Some(nodelay);
         self
     }
 
+    /// Enable `SO_KEEPALIVE` on the TCP connections, sending the first probe after `idle` without
+    /// traffic, then every `interval`, or at the interval of the host if `None`. Long links
+    /// through middleboxes dropping idle flows need keepalives shorter than their timeouts.
+    pub fn tcp_keepalive(&mut self, idle: Duration, interval: Option<Duration>) -> &mut Self {
+        self.tcp_options.keepalive = Some(Some(idle));
+        self.tcp_options.keepalive_interval = interval;
+        self
+    }
+
+    /// Set the sizes of the receive and send buffers of the TCP connections, or keep the defaults
+    /// of the host if `None`. The throughput of a connection is bounded by its buffers over its
+    /// round-trip time, so links with a high latency need larger buffers.
+    pub fn tcp_buffer_sizes(
+        &mut self,
+        recv_buffer_size: Option<usize>,
+        send_buffer_size: Option<usize>,
+    ) -> &mut Self {
+        self.tcp_options.recv_buffer_size = recv_buffer_size;
+        self.tcp_options.send_buffer_size = send_buffer_size;
+        self
+    }
+
+    /// Set whether the TCP listeners set `SO_REUSEPORT`, so that a hot-standby process can listen
+    /// on the same port, and take it over when this one exits.
+    pub fn tcp_reuse_port(&mut self, reuse_port: bool) -> &mut Self {
+        self.tcp_options.reuse_port = Some(reuse_port);
+        self
+    }
+
+    /// Bind the TCP listeners to the network interface `device`, e.g., `eth1` on a multi-homed
+    /// host, rather than accepting the connections of any interface. Listening fails on the
+    /// platforms without `SO_BINDTODEVICE`, or without the privilege to set it.
+    pub fn tcp_bind_device(&mut self, device: String) -> &mut Self {
+        self.tcp_options.bind_device = Some(device);
+        self
+    }
+
+    /// The TCP transport of the network, under the WebSocket transport too.
+    fn tcp_transport(&self) -> TcpTransport {
+        TcpTransport {
+            ipv6_only: if self.dual_stack { Some(false) } else { None },
+            ..self.tcp_options.clone()
+        }
+    }
+
     /// Set trusted peers.
     pub fn trusted_peers(
         &mut self,
@@ -208,6 +620,161 @@ impl NetworkBuilder {
         self
     }
 
+    /// Trust the peers persisted in `path` on a previous run, e.g., the validators of the last
+    /// epoch seen, until the connectivity manager updates the trusted peers, persisting them to
+    /// `path`. The trusted peers already set take precedence, so this should be called after
+    /// `trusted_peers`, and before `add_connectivity_manager`.
+    pub fn trusted_peers_file(&mut self, path: PathBuf) -> &mut Self {
+        match PersistedTrustedPeers::load(&path) {
+            Ok(Some(persisted)) => {
+                info!(
+                    "Loaded {} trusted peers of epoch {:?} from {:?}",
+                    persisted.peers.len(),
+                    persisted.epoch,
+                    path
+                );
+                let mut trusted_peers = self.trusted_peers.write().unwrap();
+                for (peer_id, keys) in persisted.peers {
+                    trusted_peers.entry(peer_id).or_insert(keys);
+                }
+            }
+            Ok(None) => {}
+            // The peers are only a cache of the validator set, which is read again once storage
+            // is open.
+            Err(err) => error!("Ignoring the persisted trusted peers: {:?}", err),
+        }
+        self.trusted_peers_file = Some(path);
+        self
+    }
+
+    /// Record the addresses at which the connectivity manager reaches the peers in
+    /// `address_book`, and dial the addresses recorded on previous runs, after the addresses of
+    /// all the other discovery sources. The addresses not reached within `staleness` are pruned.
+    /// Should be called before `add_connectivity_manager`.
+    pub fn address_book(
+        &mut self,
+        address_book: Box<dyn AddressBook>,
+        staleness: Duration,
+    ) -> &mut Self {
+        self.address_book = Some((address_book, staleness));
+        self
+    }
+
+    /// Also accept the peers of the network `network_id` on `listen_address`, authenticated with
+    /// `authentication_mode` (against `trusted_peers` for mutual authentication), sharing the
+    /// PeerManager and runtime of this network instead of running another network for them.
+    ///
+    /// These peers can only use the `protocols` among the ones of this network, and only the
+    /// handlers of these protocols are notified of them. `listen_address` must use one of the
+    /// transports of the listen addresses of this network.
+    pub fn add_listener(
+        &mut self,
+        network_id: NetworkId,
+        listen_address: NetworkAddress,
+        authentication_mode: AuthenticationMode,
+        trusted_peers: HashMap<PeerId, NetworkPublicKeys>,
+        protocols: Vec<ProtocolId>,
+    ) -> &mut Self {
+        assert!(
+            network_id != self.network_id
+                && self
+                    .listeners
+                    .iter()
+                    .all(|listener| listener.network_id != network_id),
+            "Network {:?} already has a listener",
+            network_id
+        );
+        self.listeners.push(AdditionalListener {
+            network_id,
+            listen_address,
+            authentication_mode,
+            trusted_peers: Arc::new(RwLock::new(trusted_peers)),
+            protocols,
+        });
+        self
+    }
+
+    /// Export the keys of every Noise session to a keylog, to debug test networks
+    pub fn noise_keylog(&mut self, noise_keylog: Arc<NoiseKeylog>) -> &mut Self {
+        self.noise_keylog = Some(noise_keylog);
+        self
+    }
+
+    /// Advertise the messaging protocol V2 and exchange the handshake extensions with the peers
+    /// which support them, on the main listener and every additional listener
+    pub fn enable_handshake_extensions(&mut self, enable: bool) -> &mut Self {
+        self.handshake_extensions = enable;
+        self
+    }
+
+    /// Set the limits of the resources the peers of this network, and of every additional
+    /// listener, may use. Every network gets its own quota.
+    pub fn quota_limits(&mut self, quota_limits: QuotaLimits) -> &mut Self {
+        self.quota_limits = quota_limits;
+        self
+    }
+
+    /// Close the inbound connections beyond `max_inbound_connections` on every listen address,
+    /// and on the address of every additional listener, before their handshake. Connections count
+    /// from their acceptance until they close.
+    pub fn max_inbound_connections(&mut self, max_inbound_connections: usize) -> &mut Self {
+        self.inbound_connection_limits.max_inbound_connections = Some(max_inbound_connections);
+        self
+    }
+
+    /// Close the inbound connections from an IP address beyond `max_inbound_connections_per_ip`
+    /// on every listen address, and on the address of every additional listener, before their
+    /// handshake.
+    pub fn max_inbound_connections_per_ip(
+        &mut self,
+        max_inbound_connections_per_ip: usize,
+    ) -> &mut Self {
+        self.inbound_connection_limits
+            .max_inbound_connections_per_ip = Some(max_inbound_connections_per_ip);
+        self
+    }
+
+    /// Limit the rate of the inbound messages of every peer of this network, and of every
+    /// additional listener, for all the protocols.
+    pub fn inbound_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.inbound_rate_limits.default_limit = Some(limit);
+        self
+    }
+
+    /// Limit the rate of the inbound messages of `protocol` of every peer, overriding the limit
+    /// set with [`inbound_rate_limit`](NetworkBuilder::inbound_rate_limit).
+    pub fn protocol_inbound_rate_limit(
+        &mut self,
+        protocol: ProtocolId,
+        limit: RateLimit,
+    ) -> &mut Self {
+        self.inbound_rate_limits
+            .protocol_limits
+            .insert(protocol, limit);
+        self
+    }
+
+    /// Set whether the inbound messages exceeding the rate limit of their peer are dropped, the
+    /// default, or delay the reading of the connection of the peer.
+    pub fn inbound_rate_limit_policy(&mut self, policy: RateLimitPolicy) -> &mut Self {
+        self.inbound_rate_limits.policy = policy;
+        self
+    }
+
+    /// Cap the bytes sent per second by all the connections of this network, and of every
+    /// additional listener.
+    pub fn max_outbound_bytes_per_sec(&mut self, max_outbound_bytes_per_sec: u64) -> &mut Self {
+        self.max_outbound_bytes_per_sec = Some(max_outbound_bytes_per_sec);
+        self
+    }
+
+    /// Cap the bytes received per second by all the connections of this network, and of every
+    /// additional listener.
+    pub fn max_inbound_bytes_per_sec(&mut self, max_inbound_bytes_per_sec: u64) -> &mut Self {
+        self.max_inbound_bytes_per_sec = Some(max_inbound_bytes_per_sec);
+        self
+    }
+
     /// Set seed peers to bootstrap discovery
     pub fn seed_peers(&mut self, seed_peers: HashMap<PeerId, Vec<NetworkAddress>>) -> &mut Self {
         self.seed_peers = seed_peers;
@@ -220,6 +787,92 @@ impl NetworkBuilder {
         self
     }
 
+    /// Only advertise the addresses verified by [`dial_back`] through gossip discovery, i.e.,
+    /// which a connected peer reached.
+    pub fn verify_advertised_addresses(&mut self, verify_advertised_addresses: bool) -> &mut Self {
+        self.verify_advertised_addresses = verify_advertised_addresses;
+        self
+    }
+
+    /// Set the metadata advertised to other peers by discovery
+    pub fn discovery_metadata(&mut self, discovery_metadata: DiscoveryMetadata) -> &mut Self {
+        discovery::verify_metadata(&discovery_metadata).expect("Invalid discovery metadata");
+        self.discovery_metadata = discovery_metadata;
+        self
+    }
+
+    /// Return a handle to the metadata advertised by the peers known to discovery
+    pub fn peer_metadata(&self) -> PeerMetadata {
+        self.peer_metadata.clone()
+    }
+
+    /// Record the peers of this network in `connected_peers`, which may be shared with the other
+    /// networks of the node
+    pub fn connected_peers(&mut self, connected_peers: ConnectedPeers) -> &mut Self {
+        self.connected_peers = connected_peers;
+        self
+    }
+
+    /// Return a handle to the encodings negotiated for the application protocols with the
+    /// connected peers
+    pub fn peer_encodings(&self) -> PeerEncodings {
+        self.peer_encodings.clone()
+    }
+
+    /// Return a handle to the addresses of this node observed by the connected peers
+    pub fn observed_addrs(&self) -> ObservedAddrs {
+        self.observed_addrs.clone()
+    }
+
+    /// Return a handle to the states of the connections with the peers, e.g., to subscribe to
+    /// their transitions
+    pub fn peer_lifecycles(&self) -> PeerLifecycles {
+        self.peer_lifecycles.clone()
+    }
+
+    /// Return a receiver of the structured events of the network, e.g., the failed dials and
+    /// handshakes, the peers connected, disconnected and banned, and the messages dropped as
+    /// their queue was full, see [`network_events`](crate::network_events).
+    pub fn add_event_subscriber(&mut self) -> broadcast::Receiver<NetworkEvent> {
+        self.network_events.subscribe()
+    }
+
+    /// Choose the inbound connections closed to make room for more desirable peers once the
+    /// inbound connection quota of a network is exhausted with `eviction_policy`, instead of the
+    /// [`DefaultEvictionPolicy`], which prefers the trusted peers, then the peers with the highest
+    /// [`peer_scores`](NetworkBuilder::peer_scores).
+    pub fn eviction_policy(&mut self, eviction_policy: Arc<dyn EvictionPolicy>) -> &mut Self {
+        self.eviction_policy = Some(eviction_policy);
+        self
+    }
+
+    /// Return the gate of the inbound connections of this network, which pauses their acceptance
+    /// on all its listeners, e.g., while the node sheds load.
+    pub fn inbound_connection_gate(&self) -> InboundConnectionGate {
+        self.inbound_connection_gate.clone()
+    }
+
+    /// Return the scores of the peers of this network, which the applications may adjust
+    /// according to the usefulness of the peers to rank them for eviction.
+    pub fn peer_scores(&self) -> PeerScores {
+        self.peer_scores.clone()
+    }
+
+    /// Set the time given to the network to drain its RPCs in flight and close its connections
+    /// on [`NetworkHandle::shutdown`].
+    pub fn shutdown_timeout_ms(&mut self, shutdown_timeout_ms: u64) -> &mut Self {
+        self.shutdown_timeout_ms = shutdown_timeout_ms;
+        self
+    }
+
+    /// Set how the health checker adapts its pings to the activity of the peers, sparing the
+    /// peers which sent messages recently and backing off from the idle ones which answer, or
+    /// `None` to ping all the peers alike.
+    pub fn ping_policy(&mut self, ping_policy: Option<PingPolicy>) -> &mut Self {
+        self.ping_policy = ping_policy;
+        self
+    }
+
     /// Set connectivity check ticker interval
     pub fn connectivity_check_interval_ms(
         &mut self,
@@ -229,10 +882,33 @@ impl NetworkBuilder {
         self
     }
 
+    /// Set the delay after which the [`ConnectivityManager`] dials the next address of a peer
+    /// while the previous dials are pending, keeping the first successful connection, or `None`
+    /// to dial the addresses one at a time.
+    pub fn dial_stagger(&mut self, dial_stagger: Option<Duration>) -> &mut Self {
+        self.dial_stagger = dial_stagger;
+        self
+    }
+
+    /// Return a sender of requests to the [`ConnectivityManager`], if one was added, e.g., to
+    /// reload the trusted and seed peers at runtime with [`ConnectivityRequest::ReloadPeers`].
+    /// The trusted peers are shared with the Noise handshake, so inbound connections are
+    /// authenticated against the reloaded set too.
+    ///
+    /// Applications ban misbehaving peers through it too, e.g., consensus after detecting an
+    /// equivocation, with [`ConnectivityRequest::BanPeer`]. The bans are shared with the peer
+    /// manager, which closes the connections of banned peers.
     pub fn conn_mgr_reqs_tx(&self) -> Option<channel::Sender<ConnectivityRequest>> {
         self.conn_mgr_reqs_tx.clone()
     }
 
+    /// A reporter of the peers the applications fail to reach, e.g., on send failures or RPC
+    /// timeouts, to reconnect them earlier than the health checker would. `None` if no
+    /// [`ConnectivityManager`] was added.
+    pub fn reachability_reporter(&self) -> Option<ReachabilityReporter> {
+        self.conn_mgr_reqs_tx().map(ReachabilityReporter::new)
+    }
+
     fn supported_protocols(&self) -> SupportedProtocols {
         self.direct_send_protocols
             .iter()
@@ -240,11 +916,13 @@ impl NetworkBuilder {
             .into()
     }
 
-    /// Add a handler for given protocols using raw bytes.
+    /// Add a handler for given protocols using raw bytes. The outbound messages of the protocols
+    /// are sent with `priority`, preempting the queued messages of lower priority protocols.
     pub fn add_protocol_handler(
         &mut self,
         rpc_protocols: Vec<ProtocolId>,
         direct_send_protocols: Vec<ProtocolId>,
+        priority: ProtocolPriority,
         queue_preference: QueueStyle,
         max_queue_size_per_peer: usize,
         counter: Option<&'static IntCounterVec>,
@@ -269,12 +947,23 @@ impl NetworkBuilder {
         {
             self.upstream_handlers
                 .insert(protocol, network_notifs_tx.clone());
+            self.protocol_priorities.set(protocol, priority);
         }
         let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
         // Auto-subscribe all application level handlers to connection events.
-        self.connection_event_handlers.push(connection_notifs_tx);
+        self.connection_event_handlers
+            .push(connection_notifs_tx.clone());
+        self.protocol_connection_event_handlers.push((
+            rpc_protocols
+                .into_iter()
+                .chain(direct_send_protocols)
+                .collect(),
+            connection_notifs_tx,
+        ));
         (
-            PeerManagerRequestSender::new(self.pm_reqs_tx.clone()),
+            PeerManagerRequestSender::new(self.pm_reqs_tx.clone())
+                .with_events(self.network_events.clone())
+                .with_peer_encodings(self.peer_encodings.clone()),
             network_notifs_rx,
             ConnectionRequestSender::new(self.connection_reqs_tx.clone()),
             connection_notifs_rx,
@@ -306,9 +995,16 @@ impl NetworkBuilder {
         let trusted_peers = self.trusted_peers.clone();
         let seed_peers = self.seed_peers.clone();
         let max_connection_delay_ms = self.max_connection_delay_ms;
+        let dial_stagger = self.dial_stagger;
         let connectivity_check_interval_ms = self.connectivity_check_interval_ms;
+        let ban_list = self.ban_list.clone();
+        let trusted_peers_file = self.trusted_peers_file.clone();
+        let address_book = self.address_book.take();
+        let (trusted_peers_updates_tx, trusted_peers_updates_rx) =
+            channel::new(self.channel_size, &counters::PENDING_TRUSTED_PEERS_UPDATES);
+        self.trusted_peers_updates_rx = Some(trusted_peers_updates_rx);
         let pm_conn_mgr_notifs_rx = self.add_connection_event_listener();
-        let conn_mgr = self.executor.enter(|| {
+        let mut conn_mgr = self.executor.enter(|| {
             ConnectivityManager::new(
                 peer_id,
                 trusted_peers,
@@ -319,9 +1015,23 @@ impl NetworkBuilder {
                 conn_mgr_reqs_rx,
                 ExponentialBackoff::from_millis(2).factor(1000),
                 max_connection_delay_ms,
+                ban_list,
+                trusted_peers_file,
+                Some(trusted_peers_updates_tx),
             )
         });
-        self.executor.spawn(conn_mgr.start());
+        if let Some((address_book, staleness)) = address_book {
+            conn_mgr = conn_mgr.with_address_book(address_book, staleness);
+        }
+        if let Some(dial_stagger) = dial_stagger {
+            conn_mgr = conn_mgr.with_parallel_dials(dial_stagger);
+        }
+        conn_mgr = conn_mgr.with_events(self.network_events.clone());
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "connectivity_manager",
+            conn_mgr.start(),
+        ));
         self
     }
 
@@ -332,53 +1042,358 @@ impl NetworkBuilder {
     /// peers as a network protocol.
     ///
     /// This is for testing purposes only and should not be used in production networks.
-    pub fn add_gossip_discovery(&mut self) -> &mut Self {
+    ///
+    /// The notes of this node are signed with `signing_key`, whose X25519 counterpart must be the
+    /// identity key of the node, see [`discovery`].
+    ///
+    /// Fails if no [`ConnectivityManager`] was added, no authentication mode was set, or
+    /// `signing_key` doesn't match the identity key of the node.
+    pub fn add_gossip_discovery(
+        &mut self,
+        signing_key: Ed25519PrivateKey,
+    ) -> Result<&mut Self, NetworkBuilderError> {
         let peer_id = self.peer_id;
         let conn_mgr_reqs_tx = self
             .conn_mgr_reqs_tx()
-            .expect("ConnectivityManager not enabled");
+            .ok_or(NetworkBuilderError::ConnectivityManagerNotEnabled)?;
+        let pubkey = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .public_key();
+        if x25519::PublicKey::from_ed25519_public_bytes(&signing_key.public_key().to_bytes()).ok()
+            != Some(pubkey)
+        {
+            return Err(NetworkBuilderError::DiscoverySigningKeyMismatch);
+        }
         // Get handles for network events and sender.
         let (discovery_network_tx, discovery_network_rx) = discovery::add_to_network(self);
+        let (dial_back_network_tx, dial_back_network_rx) = dial_back::add_to_network(self);
 
         // TODO(philiphayes): the current setup for gossip discovery doesn't work
-        // when we don't have an `advertised_address` set, since it uses the
-        // `listen_address`, which might not be bound to a port yet. For example,
-        // if our `listen_address` is "/ip6/::1/tcp/0" and `advertised_address` is
-        // `None`, then this will set our `advertised_address` to something like
+        // when we don't have an `advertised_addresses` set, since it uses the
+        // `listen_addresses`, which might not be bound to a port yet. For example,
+        // if our `listen_addresses` are ["/ip6/::1/tcp/0"] and `advertised_addresses` is
+        // empty, then this will set our `advertised_addresses` to something like
         // "/ip6/::1/tcp/0/ln-noise-ik/<pubkey>/ln-handshake/0", which is wrong
         // since the actual bound port will be something > 0.
+        // With `verify_advertised_addresses`, such addresses are never verified by dial-back,
+        // and so never advertised. Without any `advertised_addresses`, the address observed by
+        // the peers replaces them once they agree on it, with the bound port if no peer dialed
+        // this node yet.
 
         // TODO(philiphayes): in network_builder setup, only bind the channels.
         // wait until PeerManager is running to actual setup gossip discovery.
 
-        let advertised_address = self
-            .advertised_address
-            .clone()
-            .unwrap_or_else(|| self.listen_address.clone());
-        let authentication_mode = self
-            .authentication_mode
-            .as_ref()
-            .expect("Authentication Mode not set");
-        let pubkey = authentication_mode.public_key();
-        let advertised_address = advertised_address.append_prod_protos(pubkey, HANDSHAKE_VERSION);
-
-        let addrs = vec![advertised_address];
+        let addrs = self.advertised_prod_addresses(pubkey);
         let role = self.role;
         let discovery_interval_ms = self.discovery_interval_ms;
+        let discovery_metadata = self.discovery_metadata.clone();
+        let peer_metadata = self.peer_metadata.clone();
+        let trusted_peers = self.trusted_peers.clone();
+        let (identity_key_updates_tx, identity_key_updates_rx) = mpsc::unbounded();
+        self.identity_key_listeners.push(identity_key_updates_tx);
+        let connected_peers = self.connected_peers.clone();
+        let mut dial_back = self.executor.enter(|| {
+            DialBack::new(
+                interval(Duration::from_millis(discovery_interval_ms)).fuse(),
+                dial_back_network_tx,
+                dial_back_network_rx,
+                connected_peers,
+            )
+        });
+        // The peers are always served, and this node only advertises the addresses they verified
+        // if enabled.
+        let (verified_addrs_tx, verified_addrs_rx) = mpsc::unbounded();
+        let addrs = if self.verify_advertised_addresses {
+            dial_back = dial_back.verify(addrs, verified_addrs_tx);
+            Vec::new()
+        } else {
+            addrs
+        };
+        self.dial_back = Some(dial_back);
         let discovery = self.executor.enter(|| {
             Discovery::new(
                 peer_id,
                 role,
                 addrs,
+                discovery_metadata,
+                signing_key,
+                trusted_peers,
                 interval(Duration::from_millis(discovery_interval_ms)).fuse(),
                 discovery_network_tx,
                 discovery_network_rx,
                 conn_mgr_reqs_tx,
+                peer_metadata,
             )
+            .identity_key_updates(identity_key_updates_rx)
+            .verified_addrs(verified_addrs_rx)
         });
-        self.executor.spawn(discovery.start());
+        let discovery = match self.external_addrs_rx.take() {
+            Some(external_addrs_rx) => discovery.external_addrs(external_addrs_rx),
+            None => discovery,
+        };
+        let discovery = if self.advertised_addresses.is_empty() {
+            discovery.observed_addrs(self.observed_addrs.clone(), pubkey)
+        } else {
+            discovery
+        };
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "discovery",
+            discovery.start(),
+        ));
         debug!("Started discovery protocol actor");
-        self
+        Ok(self)
+    }
+
+    /// Add the [`Dht`] discovery protocol to the network, which publishes the record of the
+    /// advertised addresses of this node, signed with `signing_key`, and sends the addresses of
+    /// the peers of its routing table to the [`ConnectivityManager`]. Unlike gossip discovery, it
+    /// scales to the size of the public full-node network. The lookups run every discovery
+    /// interval.
+    ///
+    /// The X25519 counterpart of `signing_key` must be the identity key of the node, so that its
+    /// PeerId is bound to the key signing its record, see [`dht`].
+    ///
+    /// Fails if no [`ConnectivityManager`] was added, no authentication mode was set, or
+    /// `signing_key` doesn't match the PeerId of the network.
+    pub fn add_dht_discovery(
+        &mut self,
+        signing_key: Ed25519PrivateKey,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let peer_id = self.peer_id;
+        let conn_mgr_reqs_tx = self
+            .conn_mgr_reqs_tx()
+            .ok_or(NetworkBuilderError::ConnectivityManagerNotEnabled)?;
+        let pubkey = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .public_key();
+        if dht::signing_peer_id(&signing_key.public_key()).ok() != Some(peer_id) {
+            return Err(NetworkBuilderError::DhtSigningKeyMismatch);
+        }
+        let record = PeerRecord {
+            peer_id,
+            addrs: self.advertised_prod_addresses(pubkey),
+            epoch: dht::get_unix_epoch(),
+        };
+        let self_record =
+            SignedPeerRecord::sign(record, &signing_key).expect("Failed to sign the DHT record");
+        let (dht_network_tx, dht_network_rx) = dht::add_to_network(self);
+        let discovery_interval_ms = self.discovery_interval_ms;
+        let dht = self.executor.enter(|| {
+            Dht::new(
+                self_record,
+                interval(Duration::from_millis(discovery_interval_ms)).fuse(),
+                dht_network_tx,
+                dht_network_rx,
+                conn_mgr_reqs_tx,
+            )
+        });
+        self.actors
+            .push(NetworkTask::spawn(&self.executor, "dht", dht.start()));
+        debug!("Started DHT discovery actor");
+        Ok(self)
+    }
+
+    /// Add the [`PeerExchange`] protocol to the network, which serves the addresses known to the
+    /// [`ConnectivityManager`] and those advertised by this node to the connected peers, and asks
+    /// a connected peer for more, only of `role_filter` if any, whenever the
+    /// [`ConnectivityManager`] is left with fewer than `min_outbound_candidates` eligible peers
+    /// to dial, see [`pex`].
+    ///
+    /// Fails if no [`ConnectivityManager`] was added or no authentication mode was set.
+    pub fn add_peer_exchange(
+        &mut self,
+        role_filter: Option<RoleType>,
+        min_outbound_candidates: usize,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let peer_id = self.peer_id;
+        let role = self.role;
+        let mut conn_mgr_reqs_tx = self
+            .conn_mgr_reqs_tx()
+            .ok_or(NetworkBuilderError::ConnectivityManagerNotEnabled)?;
+        let pubkey = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .public_key();
+        let addrs = self.advertised_prod_addresses(pubkey);
+        // A single pending request is enough, the next ones are dropped until it is handled.
+        let (requests_tx, requests_rx) = mpsc::channel(0);
+        conn_mgr_reqs_tx
+            .try_send(ConnectivityRequest::EnablePeerExchange {
+                min_outbound_candidates,
+                requests_tx,
+            })
+            .expect("ConnectivityRequest::EnablePeerExchange send");
+        let (pex_network_tx, pex_network_rx) = pex::add_to_network(self);
+        let peer_exchange = self.executor.enter(|| {
+            PeerExchange::new(
+                peer_id,
+                role,
+                addrs,
+                role_filter,
+                requests_rx,
+                pex_network_tx,
+                pex_network_rx,
+                conn_mgr_reqs_tx,
+            )
+        });
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "pex",
+            peer_exchange.start(),
+        ));
+        debug!("Started peer exchange actor");
+        Ok(self)
+    }
+
+    /// Add the [`MdnsDiscovery`] to the network, which announces the advertised addresses of this
+    /// node over mDNS every discovery interval, and sends the addresses of the peers of the same
+    /// network it discovers to the [`ConnectivityManager`], so that the nodes of a local test
+    /// network, or of a small LAN deployment, find each other without seed peers.
+    ///
+    /// Fails if no [`ConnectivityManager`] was added, no authentication mode was set, or the mDNS
+    /// socket can't be bound.
+    pub fn add_mdns_discovery(&mut self) -> Result<&mut Self, NetworkBuilderError> {
+        let peer_id = self.peer_id;
+        let conn_mgr_reqs_tx = self
+            .conn_mgr_reqs_tx()
+            .ok_or(NetworkBuilderError::ConnectivityManagerNotEnabled)?;
+        let pubkey = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .public_key();
+        let addrs = self.advertised_prod_addresses(pubkey);
+        let socket = self
+            .executor
+            .enter(mdns::bind_socket)
+            .map_err(NetworkBuilderError::MdnsUnavailable)?;
+        let discovery_interval_ms = self.discovery_interval_ms;
+        let mdns_discovery = self.executor.enter(|| {
+            MdnsDiscovery::new(
+                peer_id,
+                &self.network_id,
+                addrs,
+                socket,
+                interval(Duration::from_millis(discovery_interval_ms)).fuse(),
+                conn_mgr_reqs_tx,
+            )
+        });
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "mdns_discovery",
+            mdns_discovery.start(),
+        ));
+        debug!("Started mDNS discovery actor");
+        Ok(self)
+    }
+
+    /// Add the NAT-PMP [`PortMapper`] to the network, which maps the TCP port of the first IPv4
+    /// listen address on `gateway`, or on the gateway of the default route of the host if `None`,
+    /// and keeps the lease refreshed. Gossip discovery advertises the resulting external address
+    /// in addition to the other addresses of this node, so that a node behind a home router is
+    /// dialable without configuring the router. Must be added before gossip discovery.
+    ///
+    /// Fails if no authentication mode was set, or no gateway is known.
+    pub fn add_nat_port_mapping(
+        &mut self,
+        gateway: Option<Ipv4Addr>,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let pubkey = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .public_key();
+        let gateway = gateway
+            .or_else(nat::default_gateway)
+            .ok_or(NetworkBuilderError::NatGatewayUnavailable)?;
+        let (external_addrs_tx, external_addrs_rx) = mpsc::unbounded();
+        self.nat_port_mapper = Some(PortMapper::new(
+            SocketAddrV4::new(gateway, nat::NAT_PMP_PORT),
+            pubkey,
+            HANDSHAKE_VERSION,
+            external_addrs_tx,
+        ));
+        self.external_addrs_rx = Some(external_addrs_rx);
+        Ok(self)
+    }
+
+    /// Add a [`RelayServer`] to the network, listening on the TCP `listen_address` for the peers
+    /// which can't accept inbound connections. Such a peer reserves its `PeerId` on the relay by
+    /// listening on `/<listen_address>/p2p-circuit`, and the dialers of that address are spliced
+    /// with the connections it accepts through the relay. The relay doesn't take part in the
+    /// Noise handshake, which runs end-to-end between the peers.
+    ///
+    /// The reservations are authenticated with a Noise handshake with the identity key of this
+    /// node, so only the peers whose `PeerId` is derived from their identity key can reserve.
+    ///
+    /// Fails if no authentication mode was set or the relay listener can't be bound.
+    pub fn add_relay_server(
+        &mut self,
+        listen_address: NetworkAddress,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let relay_key = self
+            .authentication_mode
+            .as_ref()
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?
+            .relay_key();
+        let relay_auth = NoiseRelayAuth::new(self.peer_id, relay_key);
+        let tcp_transport = self.tcp_transport();
+        let (relay_server, listen_address) = self
+            .executor
+            .enter(|| RelayServer::listen_on(&tcp_transport, listen_address, relay_auth))
+            .map_err(NetworkBuilderError::RelayUnavailable)?;
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "relay_server",
+            relay_server.start(),
+        ));
+        debug!("Started relay server actor on {}", listen_address);
+        Ok(self)
+    }
+
+    /// Add the on-chain discovery to the network: the [`ConfigurationChangeListener`] sends the
+    /// validator set of every reconfiguration received from `reconfig_events_rx`, i.e., the
+    /// addresses and identity keys of the validators, to the [`ConnectivityManager`], which
+    /// replaces the eligible nodes with it. The addresses and keys are those of the validator or
+    /// the full-node network of the validators, depending on the role of the network.
+    ///
+    /// Fails if no [`ConnectivityManager`] was added.
+    pub fn add_onchain_discovery(
+        &mut self,
+        reconfig_events_rx: libra_channel::Receiver<(), OnChainConfigPayload>,
+    ) -> Result<&mut Self, NetworkBuilderError> {
+        let conn_mgr_reqs_tx = self
+            .conn_mgr_reqs_tx()
+            .ok_or(NetworkBuilderError::ConnectivityManagerNotEnabled)?;
+        let listener = ConfigurationChangeListener::new(conn_mgr_reqs_tx, self.peer_id, self.role);
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "onchain_discovery",
+            listener.start(reconfig_events_rx),
+        ));
+        debug!("Started on-chain discovery listener");
+        Ok(self)
+    }
+
+    /// The advertised addresses of this node, or its listen addresses if none was set, for the
+    /// discovery protocols to publish. Every advertised address is published, e.g., both the IPv4
+    /// and IPv6 addresses of a dual-stack node, so that peers can dial whichever they can reach.
+    fn advertised_prod_addresses(&self, pubkey: x25519::PublicKey) -> Vec<NetworkAddress> {
+        let advertised_addresses = if self.advertised_addresses.is_empty() {
+            &self.listen_addresses
+        } else {
+            &self.advertised_addresses
+        };
+        advertised_addresses
+            .iter()
+            .map(|addr| addr.clone().append_prod_protos(pubkey, HANDSHAKE_VERSION))
+            .collect()
     }
 
     pub fn add_connection_monitoring(&mut self) -> &mut Self {
@@ -387,33 +1402,102 @@ impl NetworkBuilder {
         let ping_interval_ms = self.ping_interval_ms;
         let ping_timeout_ms = self.ping_timeout_ms;
         let ping_failures_tolerated = self.ping_failures_tolerated;
+        let connected_peers = self.connected_peers.clone();
+        let peer_rtts = self.peer_rtts.clone();
+        let ping_policy = self.ping_policy;
+        let peer_activity = self.peer_activity.clone();
         let health_checker = self.executor.enter(|| {
-            HealthChecker::new(
+            let health_checker = HealthChecker::new(
                 interval(Duration::from_millis(ping_interval_ms)).fuse(),
                 hc_network_tx,
                 hc_network_rx,
                 Duration::from_millis(ping_timeout_ms),
                 ping_failures_tolerated,
             )
+            .report_rtt(connected_peers)
+            .record_rtts(peer_rtts);
+            match ping_policy {
+                Some(ping_policy) => health_checker.adaptive(ping_policy, peer_activity),
+                None => health_checker,
+            }
         });
-        self.executor.spawn(health_checker.start());
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "health_checker",
+            health_checker.start(),
+        ));
         debug!("Started health checker");
         self
     }
 
-    /// Create the configured transport and start PeerManager.
-    /// Return the actual NetworkAddress over which this peer is listening.
-    pub fn build(mut self) -> NetworkAddress {
-        use libra_network_address::Protocol::*;
+    /// Add the latency protocol to the network, probing every connected peer every
+    /// `probe_interval` to measure the one-way latency and jitter to it, and aggregating the
+    /// latencies measured by the peers into the [`LatencyMatrix`] of the network, see
+    /// [`latency`]. Meant for validator networks, whose peers keep their clocks synchronized.
+    pub fn add_latency_measurement(&mut self, probe_interval: Duration) -> &mut Self {
+        let (latency_network_tx, latency_network_rx) = latency::add_to_network(self);
+        let peer_id = self.peer_id;
+        let probe_timeout_ms = self.ping_timeout_ms;
+        let latency_matrix = self.latency_matrix.clone();
+        let latency_prober = self.executor.enter(|| {
+            LatencyProber::new(
+                peer_id,
+                interval(probe_interval).fuse(),
+                latency_network_tx,
+                latency_network_rx,
+                latency_matrix,
+                Duration::from_millis(probe_timeout_ms),
+            )
+        });
+        self.actors.push(NetworkTask::spawn(
+            &self.executor,
+            "latency_prober",
+            latency_prober.start(),
+        ));
+        debug!("Started latency prober");
+        self
+    }
 
-        let network_id = self.network_id.clone();
-        let protos = self.supported_protocols();
+    /// The round-trip times of the pings of the connected peers, measured once the health checker
+    /// is added, see [`NetworkBuilder::add_connection_monitoring`].
+    pub fn peer_rtts(&self) -> PeerRtts {
+        self.peer_rtts.clone()
+    }
 
+    /// The latencies between the validators, measured once the latency protocol is added, see
+    /// [`NetworkBuilder::add_latency_measurement`].
+    pub fn latency_matrix(&self) -> LatencyMatrix {
+        self.latency_matrix.clone()
+    }
+
+    /// Create the configured transport and start PeerManager.
+    /// Return the handle of the network, with the actual NetworkAddresses over which this peer is
+    /// listening, in the order of the listen addresses.
+    ///
+    /// Fails if no authentication mode was set or a listen address is not supported.
+    pub fn build(mut self) -> Result<NetworkHandle, NetworkBuilderError> {
         let authentication_mode = self
             .authentication_mode
             .take()
-            .expect("Authentication Mode not set");
+            .ok_or(NetworkBuilderError::AuthenticationModeNotSet)?;
+        if self.require_mutual_authentication {
+            if let AuthenticationMode::ServerOnly(_) = authentication_mode {
+                return Err(NetworkBuilderError::MutualAuthenticationRequired);
+            }
+        }
+        let mut base_transports = self
+            .listen_addresses
+            .iter()
+            .map(BaseTransport::of_listen_address)
+            .collect::<Result<Vec<_>, _>>()?;
+        base_transports.sort();
+        base_transports.dedup();
 
+        self.track_connected_peers();
+        self.forward_trusted_peers_updates();
+        let protos = self.supported_protocols();
+
+        let relay_key = authentication_mode.relay_key();
         let (key, maybe_trusted_peers, peer_id) = match authentication_mode {
             // validator-operated full node
             AuthenticationMode::ServerOnly(key) if self.peer_id == PeerId::default() => {
@@ -429,50 +1513,240 @@ impl NetworkBuilder {
             }
         };
 
-        match self.listen_address.as_slice() {
-            [Ip4(_), Tcp(_)] | [Ip6(_), Tcp(_)] => {
-                self.build_with_transport(LibraNetTransport::new(
-                    LIBRA_TCP_TRANSPORT.clone(),
-                    peer_id,
-                    key,
-                    maybe_trusted_peers,
-                    HANDSHAKE_VERSION,
-                    network_id,
-                    protos,
-                ))
-            }
-            [Memory(_)] => self.build_with_transport(LibraNetTransport::new(
+        // TCP addresses may also be reached through a relay, see `add_relay_server`.
+        let relay_auth = NoiseRelayAuth::new(peer_id, relay_key);
+        let tcp_transport = RelayTransport::new(self.tcp_transport(), peer_id, relay_auth);
+
+        let network_handle = match base_transports.as_slice() {
+            [BaseTransport::Tcp] => self.build_with_base_transport(
+                tcp_transport,
+                peer_id,
+                key,
+                maybe_trusted_peers,
+                protos,
+            ),
+            [BaseTransport::WebSocket] => self.build_with_base_transport(
+                WebSocketTransport {
+                    tcp: self.tcp_transport(),
+                },
+                peer_id,
+                key,
+                maybe_trusted_peers,
+                protos,
+            ),
+            #[cfg(unix)]
+            [BaseTransport::Unix] => self.build_with_base_transport(
+                netcore::transport::uds::UdsTransport,
+                peer_id,
+                key,
+                maybe_trusted_peers,
+                protos,
+            ),
+            [BaseTransport::Memory] => self.build_with_base_transport(
                 memory::MemoryTransport,
                 peer_id,
                 key,
                 maybe_trusted_peers,
-                HANDSHAKE_VERSION,
-                network_id,
                 protos,
-            )),
-            _ => panic!(
-                "Unsupported listen_address: '{}', expected '/memory/<port>', \
-                 '/ip4/<addr>/tcp/<port>', or '/ip6/<addr>/tcp/<port>'.",
-                self.listen_address
             ),
+            // Listen addresses of different transports, e.g., TCP and memory in tests, or none.
+            // WebSocket comes first, as TCP would dial the TCP connection of a WebSocket address.
+            _ => {
+                let base_transport = WebSocketTransport {
+                    tcp: self.tcp_transport(),
+                }
+                .or(tcp_transport);
+                #[cfg(unix)]
+                let base_transport = base_transport.or(netcore::transport::uds::UdsTransport);
+                let base_transport = base_transport.or(memory::MemoryTransport);
+                self.build_with_base_transport(
+                    base_transport,
+                    peer_id,
+                    key,
+                    maybe_trusted_peers,
+                    protos,
+                )
+            }
+        };
+        Ok(network_handle)
+    }
+
+    /// Record the peers connecting to and disconnecting from this network in `connected_peers`.
+    fn track_connected_peers(&mut self) {
+        let mut connection_events = self.add_connection_event_listener();
+        let connected_peers = self.connected_peers.clone();
+        let network_id = self.network_id.clone();
+        let role = self.role;
+        let task = NetworkTask::spawn(&self.executor, "connected_peers", async move {
+            while let Some(notification) = connection_events.next().await {
+                match notification {
+                    ConnectionNotification::NewPeer(peer_id, address, _) => {
+                        connected_peers.insert(peer_id, network_id.clone(), role, address)
+                    }
+                    ConnectionNotification::LostPeer(peer_id, address, _reason) => {
+                        connected_peers.remove(&peer_id, &address)
+                    }
+                    ConnectionNotification::TrustedPeersUpdated(_) => {}
+                }
+            }
+        });
+        self.tasks.push(task);
+    }
+
+    /// Notify the connection event listeners of the updates of the trusted peers by the
+    /// connectivity manager, if any.
+    fn forward_trusted_peers_updates(&mut self) {
+        let mut updates = match self.trusted_peers_updates_rx.take() {
+            Some(updates) => updates,
+            None => return,
+        };
+        let mut handlers = self.connection_event_handlers.clone();
+        let peer_id = self.peer_id;
+        let task = NetworkTask::spawn(&self.executor, "trusted_peers_updates", async move {
+            while let Some(diff) = updates.next().await {
+                for handler in &mut handlers {
+                    if let Err(err) = handler.push(
+                        peer_id,
+                        ConnectionNotification::TrustedPeersUpdated(diff.clone()),
+                    ) {
+                        warn!("Failed to notify the trusted peers update: {}", err);
+                    }
+                }
+            }
+        });
+        self.tasks.push(task);
+    }
+
+    /// Given a base transport, build the transports of the network and of its additional
+    /// listeners and launch PeerManager.
+    /// Return the handle of the network.
+    fn build_with_base_transport<TTransport>(
+        mut self,
+        base_transport: TTransport,
+        peer_id: PeerId,
+        key: x25519::PrivateKey,
+        maybe_trusted_peers: Option<Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>>,
+        protos: SupportedProtocols,
+    ) -> NetworkHandle
+    where
+        TTransport: Transport<Error = io::Error> + Clone + Send + Sync + 'static,
+        TTransport::Output: transport::TSocket,
+        TTransport::Outbound: Send + 'static,
+        TTransport::Inbound: Send + 'static,
+        TTransport::Listener: Send + 'static,
+    {
+        // the listeners share the bandwidth of the network
+        let bandwidth = BandwidthManager::new(
+            self.max_outbound_bytes_per_sec,
+            self.max_inbound_bytes_per_sec,
+        );
+        // the probes of dial-back only connect, bypassing the bandwidth limits
+        if let Some(dial_back) = self.dial_back.take() {
+            let prober = TransportProber::new(base_transport.clone());
+            self.actors.push(NetworkTask::spawn(
+                &self.executor,
+                "dial_back",
+                dial_back.start(Box::new(prober)),
+            ));
+            debug!("Started dial-back actor");
         }
+        let base_transport = ThrottledTransport::new(base_transport, bandwidth);
+        let listeners = mem::take(&mut self.listeners)
+            .into_iter()
+            .map(|listener| {
+                let (listener_key, listener_trusted_peers) = match listener.authentication_mode {
+                    AuthenticationMode::ServerOnly(key) => (key, None),
+                    AuthenticationMode::Mutual(key) => (key, Some(listener.trusted_peers)),
+                };
+                let listener_protos = listener
+                    .protocols
+                    .iter()
+                    .filter(|protocol| protos.contains(**protocol))
+                    .into();
+                let connection_event_handlers = self
+                    .protocol_connection_event_handlers
+                    .iter()
+                    .filter(|(protocols, _)| {
+                        protocols
+                            .iter()
+                            .any(|protocol| listener.protocols.contains(protocol))
+                    })
+                    .map(|(_, handler)| handler.clone())
+                    .collect();
+                let listener_transport = LibraNetTransport::new(
+                    base_transport.clone(),
+                    peer_id,
+                    listener_key,
+                    listener_trusted_peers,
+                    HANDSHAKE_VERSION,
+                    listener.network_id.clone(),
+                    listener_protos,
+                    self.noise_keylog.clone(),
+                )
+                .with_handshake_extensions(self.handshake_extensions)
+                .with_peer_lifecycles(self.peer_lifecycles.clone());
+                (
+                    listener.network_id,
+                    listener_transport,
+                    listener.listen_address,
+                    connection_event_handlers,
+                )
+            })
+            .collect();
+        let transport = LibraNetTransport::new(
+            base_transport,
+            peer_id,
+            key,
+            maybe_trusted_peers,
+            HANDSHAKE_VERSION,
+            self.network_id.clone(),
+            protos,
+            self.noise_keylog.clone(),
+        )
+        .with_handshake_extensions(self.handshake_extensions)
+        .with_peer_lifecycles(self.peer_lifecycles.clone());
+        let application_protocols = transport.application_protocols();
+        let identity_key_rotator = transport.identity_key_rotator();
+        self.build_with_transport(
+            transport,
+            listeners,
+            application_protocols,
+            identity_key_rotator,
+        )
     }
 
     /// Given a transport build and launch PeerManager.
-    /// Return the actual NetworkAddress over which this peer is listening.
-    fn build_with_transport<TTransport, TSocket>(self, transport: TTransport) -> NetworkAddress
+    /// Return the handle of the network.
+    fn build_with_transport<TTransport, TSocket>(
+        self,
+        transport: TTransport,
+        listeners: Vec<(
+            NetworkId,
+            TTransport,
+            NetworkAddress,
+            Vec<conn_notifs_channel::Sender>,
+        )>,
+        application_protocols: ApplicationProtocols,
+        identity_key_rotator: IdentityKeyRotator,
+    ) -> NetworkHandle
     where
         TTransport: Transport<Output = Connection<TSocket>> + Send + 'static,
         TSocket: transport::TSocket,
     {
-        let peer_mgr = PeerManager::new(
+        let eviction_policy: Arc<dyn EvictionPolicy> = match self.eviction_policy {
+            Some(eviction_policy) => eviction_policy,
+            None => Arc::new(DefaultEvictionPolicy::new(
+                self.trusted_peers.clone(),
+                self.peer_scores,
+            )),
+        };
+        let network_info = NetworkInfo::new(self.peer_rtts.clone());
+        let mut peer_mgr = PeerManager::new(
             self.executor.clone(),
             transport,
             self.peer_id,
             self.role,
-            // TODO(philiphayes): peer manager should take `Vec<NetworkAddress>`
-            // (which could be empty, like in client use case)
-            self.listen_address,
+            self.listen_addresses,
             self.pm_reqs_rx,
             self.connection_reqs_rx,
             self.upstream_handlers,
@@ -480,12 +1754,197 @@ impl NetworkBuilder {
             self.max_concurrent_network_reqs,
             self.max_concurrent_network_notifs,
             self.channel_size,
+            self.quota_limits,
+            self.inbound_connection_limits,
+            self.inbound_connection_gate,
+            eviction_policy,
+            self.ban_list,
+            self.inbound_rate_limits,
+            self.protocol_priorities.clone(),
+            self.peer_encodings.clone(),
+            self.observed_addrs.clone(),
+            self.peer_activity,
+            network_info.clone(),
+            self.peer_lifecycles,
+            self.network_events.clone(),
         );
-        let listen_addr = peer_mgr.listen_addr().clone();
+        let listen_addrs = peer_mgr.listen_addrs().to_vec();
+        // The port of a circuit address is the one of the relay, not of this node.
+        if let Some(port) = listen_addrs.iter().find_map(|addr| match addr.as_slice() {
+            [_, Protocol::Tcp(_), Protocol::P2pCircuit] => None,
+            [Protocol::Ip4(_), Protocol::Tcp(port), ..]
+            | [Protocol::Ip6(_), Protocol::Tcp(port), ..] => Some(*port),
+            _ => None,
+        }) {
+            self.observed_addrs.set_listen_port(port);
+        }
+        let mut actors = self.actors;
+        if let Some(nat_port_mapper) = self.nat_port_mapper {
+            // The port is only known once bound, e.g., if listening on port 0.
+            let port = listen_addrs.iter().find_map(|addr| match addr.as_slice() {
+                [_, Protocol::Tcp(_), Protocol::P2pCircuit] => None,
+                [Protocol::Ip4(_), Protocol::Tcp(port), ..] => Some(*port),
+                _ => None,
+            });
+            match port {
+                Some(port) => {
+                    actors.push(NetworkTask::spawn(
+                        &self.executor,
+                        "nat_port_mapping",
+                        nat_port_mapper.start(port),
+                    ));
+                    debug!("Started NAT port mapping actor");
+                }
+                None => warn!("No IPv4 TCP listen address to map on the NAT gateway"),
+            }
+        }
+        for (network_id, transport, listen_address, connection_event_handlers) in listeners {
+            peer_mgr.add_listener(
+                network_id,
+                transport,
+                listen_address,
+                connection_event_handlers,
+            );
+        }
 
-        self.executor.spawn(peer_mgr.start());
+        let protocol_handler_senders = ProtocolHandlerSenders {
+            pm_reqs_tx: self.pm_reqs_tx,
+            connection_reqs_tx: self.connection_reqs_tx,
+            protocol_priorities: self.protocol_priorities,
+            protocol_handlers_tx: peer_mgr.protocol_handlers_sender(application_protocols),
+            network_events: self.network_events,
+            peer_encodings: self.peer_encodings,
+        };
+        let peer_manager_shutdown_tx = peer_mgr.shutdown_sender();
+        let peer_manager = self.executor.spawn(peer_mgr.start());
         debug!("Started peer manager");
 
-        listen_addr
+        NetworkHandle::new(
+            self.network_id,
+            listen_addrs,
+            Duration::from_millis(self.shutdown_timeout_ms),
+            peer_manager_shutdown_tx,
+            peer_manager,
+            actors,
+            self.tasks,
+            protocol_handler_senders,
+            identity_key_rotator,
+            self.identity_key_listeners,
+            self.key_provider,
+            network_info,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_crypto::Uniform;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::Mutex;
+    use tokio::runtime::Runtime;
+
+    /// Provides the key of the given bytes, which may be rotated.
+    struct TestKeyProvider(Mutex<[u8; x25519::PRIVATE_KEY_SIZE]>);
+
+    impl NoiseKeyProvider for TestKeyProvider {
+        fn private_key(&self) -> Result<x25519::PrivateKey, NoiseKeyProviderError> {
+            Ok(x25519::PrivateKey::from(*self.0.lock().unwrap()))
+        }
+    }
+
+    fn network_builder(runtime: &Runtime, listen_address: &str) -> NetworkBuilder {
+        NetworkBuilder::new(
+            runtime.handle().clone(),
+            NetworkId::Validator,
+            PeerId::random(),
+            RoleType::Validator,
+            vec![listen_address.parse().unwrap()],
+        )
+    }
+
+    #[test]
+    fn build_misconfigured() {
+        let runtime = Runtime::new().unwrap();
+        let mut rng = StdRng::from_seed([0u8; 32]);
+
+        let builder = network_builder(&runtime, "/memory/0");
+        match builder.build() {
+            Err(NetworkBuilderError::AuthenticationModeNotSet) => {}
+            _ => panic!("Expected the missing authentication mode to be reported"),
+        }
+
+        let mut builder = network_builder(&runtime, "/dns4/example.com/tcp/0");
+        builder.authentication_mode(AuthenticationMode::Mutual(x25519::PrivateKey::generate(
+            &mut rng,
+        )));
+        match builder.add_gossip_discovery(Ed25519PrivateKey::generate(&mut rng)) {
+            Err(NetworkBuilderError::ConnectivityManagerNotEnabled) => {}
+            _ => panic!("Expected the missing connectivity manager to be reported"),
+        }
+        let (_, reconfig_events_rx) =
+            libra_channel::new(QueueStyle::LIFO, NonZeroUsize::new(1).unwrap(), None);
+        match builder.add_onchain_discovery(reconfig_events_rx) {
+            Err(NetworkBuilderError::ConnectivityManagerNotEnabled) => {}
+            _ => panic!("Expected the missing connectivity manager to be reported"),
+        }
+        match builder.add_peer_exchange(None, 1) {
+            Err(NetworkBuilderError::ConnectivityManagerNotEnabled) => {}
+            _ => panic!("Expected the missing connectivity manager to be reported"),
+        }
+        match builder.build() {
+            Err(NetworkBuilderError::UnsupportedListenAddress(_)) => {}
+            _ => panic!("Expected the DNS listen address to be rejected"),
+        }
+
+        let mut builder = NetworkBuilder::validator_defaults(
+            runtime.handle().clone(),
+            PeerId::random(),
+            vec!["/memory/0".parse().unwrap()],
+        );
+        builder.authentication_mode(AuthenticationMode::ServerOnly(
+            x25519::PrivateKey::generate(&mut rng),
+        ));
+        match builder.build() {
+            Err(NetworkBuilderError::MutualAuthenticationRequired) => {}
+            _ => panic!("Expected the validator network to require mutual authentication"),
+        }
+
+        let mut builder = network_builder(&runtime, "/memory/0");
+        builder
+            .authentication_mode(AuthenticationMode::Mutual(x25519::PrivateKey::generate(
+                &mut rng,
+            )))
+            .add_connectivity_manager();
+        match builder.add_gossip_discovery(Ed25519PrivateKey::generate(&mut rng)) {
+            Err(NetworkBuilderError::DiscoverySigningKeyMismatch) => {}
+            _ => panic!("Expected the signing key of another identity to be rejected"),
+        }
+    }
+
+    #[test]
+    fn refresh_identity_key_from_provider() {
+        let runtime = Runtime::new().unwrap();
+        let key_provider = Arc::new(TestKeyProvider(Mutex::new([1; x25519::PRIVATE_KEY_SIZE])));
+        let mut builder = network_builder(&runtime, "/memory/0");
+        builder
+            .authentication_mode_from_provider(AuthenticationMode::Mutual, key_provider.clone())
+            .unwrap();
+        let mut network_handle = builder.build().unwrap();
+        let listen_addr = network_handle.listen_addrs()[0].clone();
+
+        // the key is unchanged until it is rotated in the provider
+        let pubkey = key_provider.private_key().unwrap().public_key();
+        assert_eq!(network_handle.refresh_identity_key().unwrap(), pubkey);
+        assert_eq!(network_handle.listen_addrs(), &[listen_addr.clone()]);
+
+        *key_provider.0.lock().unwrap() = [2; x25519::PRIVATE_KEY_SIZE];
+        let new_pubkey = key_provider.private_key().unwrap().public_key();
+        assert_eq!(network_handle.refresh_identity_key().unwrap(), new_pubkey);
+        assert_eq!(
+            network_handle.listen_addrs(),
+            &[listen_addr.rotate_noise_public_key(&new_pubkey)]
+        );
+        runtime.block_on(network_handle.shutdown());
     }
 }
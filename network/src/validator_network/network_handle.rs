@@ -0,0 +1,288 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Handle of a network started by a [`NetworkBuilder`], to shut it down gracefully instead of
+//! dropping its runtime.
+//!
+//! [`NetworkHandle::shutdown`] stops the actors of the network in order:
+//! 1. the connectivity manager, the discovery and the health checker, so that no more peers are
+//!    dialed, gossiped with nor pinged;
+//! 2. the PeerManager, which closes its listeners, waits for the RPCs in flight to complete, then
+//!    closes the connections with all the peers, until the shutdown deadline;
+//! 3. the tasks forwarding the connection events of the network to the builder's listeners.
+//!
+//! and waits for all of them to terminate. Dropping the handle leaves the network running.
+//!
+//! Protocol handlers can also be added to the running network through its handle, see
+//! [`NetworkHandle::add_protocol_handler`], and its network identity key rotated, see
+//! [`NetworkHandle::rotate_identity_key`] and [`NetworkHandle::refresh_identity_key`].
+//!
+//! The connections of the network can be queried through its handle, see
+//! [`NetworkHandle::network_info`].
+//!
+//! [`NetworkBuilder`]: crate::validator_network::network_builder::NetworkBuilder
+
+use crate::{
+    network_events::NetworkEvents,
+    network_info::NetworkInfo,
+    noise::{NoiseKeyProvider, NoiseKeyProviderError},
+    peer_manager::{
+        conn_notifs_channel, ConnectionRequest, ConnectionRequestSender, PeerEncodings,
+        PeerManagerNotification, PeerManagerRequest, PeerManagerRequestSender,
+        ProtocolHandlerRegistration,
+    },
+    priority::{ProtocolPriorities, ProtocolPriority},
+    transport::IdentityKeyRotator,
+    ProtocolId,
+};
+use channel::{libra_channel, message_queues::QueueStyle};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{abortable, AbortHandle, Aborted, Future},
+};
+use libra_config::network_id::NetworkId;
+use libra_crypto::x25519;
+use libra_logger::prelude::*;
+use libra_metrics::IntCounterVec;
+use libra_network_address::NetworkAddress;
+use libra_types::PeerId;
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Handle, task::JoinHandle};
+
+/// A task spawned for a network, aborted on shutdown.
+pub(crate) struct NetworkTask {
+    name: &'static str,
+    abort_handle: AbortHandle,
+    join_handle: JoinHandle<Result<(), Aborted>>,
+}
+
+impl NetworkTask {
+    pub(crate) fn spawn<F>(executor: &Handle, name: &'static str, future: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (future, abort_handle) = abortable(future);
+        Self {
+            name,
+            abort_handle,
+            join_handle: executor.spawn(future),
+        }
+    }
+
+    /// Aborts the task, and waits for it to terminate.
+    async fn abort(self) {
+        self.abort_handle.abort();
+        if let Err(err) = self.join_handle.await {
+            error!("Network task {} failed: {}", self.name, err);
+        }
+        debug!("Network task {} stopped", self.name);
+    }
+}
+
+/// The senders of the requests of the PeerManager of a network, used by the protocol handlers
+/// added to the running network.
+pub(crate) struct ProtocolHandlerSenders {
+    pub(crate) pm_reqs_tx: libra_channel::Sender<(PeerId, ProtocolId), PeerManagerRequest>,
+    pub(crate) connection_reqs_tx: libra_channel::Sender<PeerId, ConnectionRequest>,
+    /// Priorities of the outbound messages of the protocols, shared with the PeerManager.
+    pub(crate) protocol_priorities: ProtocolPriorities,
+    pub(crate) protocol_handlers_tx: mpsc::UnboundedSender<ProtocolHandlerRegistration>,
+    /// Publisher of the events of the network, shared with the PeerManager.
+    pub(crate) network_events: NetworkEvents,
+    /// Encodings negotiated with the connected peers, shared with the PeerManager.
+    pub(crate) peer_encodings: PeerEncodings,
+}
+
+/// Handle of a running network, returned by [`NetworkBuilder::build`].
+///
+/// [`NetworkBuilder::build`]:
+/// crate::validator_network::network_builder::NetworkBuilder::build
+pub struct NetworkHandle {
+    network_id: NetworkId,
+    listen_addrs: Vec<NetworkAddress>,
+    shutdown_timeout: Duration,
+    /// Sends the PeerManager the deadline of its shutdown.
+    peer_manager_shutdown_tx: oneshot::Sender<Instant>,
+    peer_manager: JoinHandle<()>,
+    /// The actors using the PeerManager, stopped before it.
+    actors: Vec<NetworkTask>,
+    /// The tasks forwarding the events of the PeerManager, stopped after it.
+    tasks: Vec<NetworkTask>,
+    protocol_handler_senders: ProtocolHandlerSenders,
+    identity_key_rotator: IdentityKeyRotator,
+    /// The actors re-advertising the addresses of the network with the new identity keys.
+    identity_key_listeners: Vec<mpsc::UnboundedSender<x25519::PublicKey>>,
+    key_provider: Option<Arc<dyn NoiseKeyProvider>>,
+    /// The connections of the network, maintained by the PeerManager.
+    network_info: NetworkInfo,
+}
+
+impl NetworkHandle {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        network_id: NetworkId,
+        listen_addrs: Vec<NetworkAddress>,
+        shutdown_timeout: Duration,
+        peer_manager_shutdown_tx: oneshot::Sender<Instant>,
+        peer_manager: JoinHandle<()>,
+        actors: Vec<NetworkTask>,
+        tasks: Vec<NetworkTask>,
+        protocol_handler_senders: ProtocolHandlerSenders,
+        identity_key_rotator: IdentityKeyRotator,
+        identity_key_listeners: Vec<mpsc::UnboundedSender<x25519::PublicKey>>,
+        key_provider: Option<Arc<dyn NoiseKeyProvider>>,
+        network_info: NetworkInfo,
+    ) -> Self {
+        Self {
+            network_id,
+            listen_addrs,
+            shutdown_timeout,
+            peer_manager_shutdown_tx,
+            peer_manager,
+            actors,
+            tasks,
+            protocol_handler_senders,
+            identity_key_rotator,
+            identity_key_listeners,
+            key_provider,
+            network_info,
+        }
+    }
+
+    /// The actual NetworkAddresses over which this peer is listening, in the order of the listen
+    /// addresses of the builder.
+    pub fn listen_addrs(&self) -> &[NetworkAddress] {
+        &self.listen_addrs
+    }
+
+    /// Return a handle to the connections of the network: the peers connected, their addresses,
+    /// the origins and negotiated protocols of the connections, their ages, and the round-trip
+    /// times of the pings of the peers if the health checker was added.
+    pub fn network_info(&self) -> NetworkInfo {
+        self.network_info.clone()
+    }
+
+    /// Add a handler for given protocols to the running network, like
+    /// [`NetworkBuilder::add_protocol_handler`] before the network is built, e.g., for a component
+    /// started later than the network. The protocols are advertised in the handshakes of the
+    /// main transport of the network from then on, so only the connections established afterwards
+    /// negotiate them: reconnect to the peers to use the protocols with them. The additional
+    /// listeners of the network never negotiate them.
+    ///
+    /// [`NetworkBuilder::add_protocol_handler`]:
+    /// crate::validator_network::network_builder::NetworkBuilder::add_protocol_handler
+    pub fn add_protocol_handler(
+        &self,
+        rpc_protocols: Vec<ProtocolId>,
+        direct_send_protocols: Vec<ProtocolId>,
+        priority: ProtocolPriority,
+        queue_preference: QueueStyle,
+        max_queue_size_per_peer: usize,
+        counter: Option<&'static IntCounterVec>,
+    ) -> (
+        PeerManagerRequestSender,
+        libra_channel::Receiver<(PeerId, ProtocolId), PeerManagerNotification>,
+        ConnectionRequestSender,
+        conn_notifs_channel::Receiver,
+    ) {
+        let senders = &self.protocol_handler_senders;
+        let protocols: Vec<_> = rpc_protocols
+            .into_iter()
+            .chain(direct_send_protocols)
+            .collect();
+        let (network_notifs_tx, network_notifs_rx) = libra_channel::new(
+            queue_preference,
+            NonZeroUsize::new(max_queue_size_per_peer).unwrap(),
+            counter,
+        );
+        for protocol in &protocols {
+            senders.protocol_priorities.set(*protocol, priority);
+        }
+        let (connection_notifs_tx, connection_notifs_rx) = conn_notifs_channel::new();
+        // The PeerManager has terminated if the send fails, and the receivers are closed then.
+        let _ = senders
+            .protocol_handlers_tx
+            .unbounded_send(ProtocolHandlerRegistration {
+                protocols,
+                handler: network_notifs_tx,
+                connection_event_handler: connection_notifs_tx,
+            });
+        (
+            PeerManagerRequestSender::new(senders.pm_reqs_tx.clone())
+                .with_events(senders.network_events.clone())
+                .with_peer_encodings(senders.peer_encodings.clone()),
+            network_notifs_rx,
+            ConnectionRequestSender::new(senders.connection_reqs_tx.clone()),
+            connection_notifs_rx,
+        )
+    }
+
+    /// Rotate the network identity key of this node to `key`, e.g., periodically. The handshakes
+    /// of the main transport of the network use the new key from then on, while the connections
+    /// already established keep their Noise sessions. The listen addresses of the handle and the
+    /// addresses advertised by the gossip discovery, if any, carry the new public key, which is
+    /// returned. The additional listeners of the network keep their keys.
+    ///
+    /// On mutually authenticated networks, the other peers only accept the new key once it is in
+    /// their trusted peers, e.g., after the validator config of this node is updated on-chain.
+    pub fn rotate_identity_key(&mut self, key: x25519::PrivateKey) -> x25519::PublicKey {
+        let pubkey = key.public_key();
+        self.identity_key_rotator.rotate(key);
+        for addr in &mut self.listen_addrs {
+            *addr = addr.clone().rotate_noise_public_key(&pubkey);
+        }
+        // The discovery has terminated if the send fails.
+        for listener in &self.identity_key_listeners {
+            let _ = listener.unbounded_send(pubkey);
+        }
+        info!(
+            "Network {} rotated its identity key to {}",
+            self.network_id.as_str(),
+            pubkey
+        );
+        pubkey
+    }
+
+    /// Fetch the network identity key from the key provider of the network, see
+    /// [`NetworkBuilder::authentication_mode_from_provider`], and rotate to it if it changed, e.g.,
+    /// after the key was rotated in secure storage. Returns the public key in use, unchanged if
+    /// the network was built without key provider.
+    ///
+    /// [`NetworkBuilder::authentication_mode_from_provider`]:
+    /// crate::validator_network::network_builder::NetworkBuilder::authentication_mode_from_provider
+    pub fn refresh_identity_key(&mut self) -> Result<x25519::PublicKey, NoiseKeyProviderError> {
+        let pubkey = self.identity_key_rotator.public_key();
+        let key = match &self.key_provider {
+            Some(key_provider) => key_provider.private_key()?,
+            None => return Ok(pubkey),
+        };
+        if key.public_key() == pubkey {
+            return Ok(pubkey);
+        }
+        Ok(self.rotate_identity_key(key))
+    }
+
+    /// Stops the network, waiting for its RPCs in flight to complete and for its connections to
+    /// close until the shutdown timeout of the builder elapses, then waits for all its actors to
+    /// terminate.
+    pub async fn shutdown(self) {
+        info!("Shutting down network {}", self.network_id.as_str());
+        let deadline = Instant::now() + self.shutdown_timeout;
+        for actor in self.actors {
+            actor.abort().await;
+        }
+        // The PeerManager has already terminated if the send fails.
+        let _ = self.peer_manager_shutdown_tx.send(deadline);
+        if let Err(err) = self.peer_manager.await {
+            error!("Peer manager failed: {}", err);
+        }
+        for task in self.tasks {
+            task.abort().await;
+        }
+        info!("Network {} shut down", self.network_id.as_str());
+    }
+}
@@ -273,6 +273,7 @@ mod test {
     use libra_config::utils;
     use libra_crypto::{ed25519::Ed25519PrivateKey, HashValue, PrivateKey, Uniform};
     use libra_json_rpc::test_bootstrap;
+    use libra_mempool::MempoolClientRequest;
     use libra_types::{
         account_address::AccountAddress,
         account_config::{AccountResource, BalanceResource},
@@ -421,7 +422,9 @@ mod test {
         if mock_validator {
             // Provide a VMValidator to the runtime.
             server.spawn(async move {
-                while let Some((txn, cb)) = mp_events.next().await {
+                while let Some(MempoolClientRequest::SubmitTransaction(txn, cb)) =
+                    mp_events.next().await
+                {
                     let vm_status = MockVMValidator.validate_transaction(txn).unwrap().status();
                     let result = if vm_status.is_some() {
                         (MempoolStatus::new(MempoolStatusCode::VmError), vm_status)
@@ -33,7 +33,7 @@ use libra_types::{
     account_config::LBR_NAME,
     transaction::{RawTransaction, Script, Transaction, TransactionArgument},
 };
-use std::{str::FromStr, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 use thiserror::Error;
 
 pub mod counters;
@@ -331,5 +331,5 @@ pub fn build_rotation_transaction(
         expiration,
     );
     let signed_txn = raw_txn.sign(signing_key, signing_key.public_key()).unwrap();
-    Transaction::UserTransaction(signed_txn.into_inner())
+    Transaction::UserTransaction(Arc::new(signed_txn.into_inner()))
 }
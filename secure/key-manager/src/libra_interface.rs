@@ -122,7 +122,7 @@ impl LibraInterface for JsonRpcLibraInterface {
     fn submit_transaction(&self, transaction: Transaction) -> Result<(), Error> {
         if let Transaction::UserTransaction(signed_txn) = transaction {
             self.client
-                .submit_signed_transaction(signed_txn)
+                .submit_signed_transaction(signed_txn.as_ref().clone())
                 .map_err(|e| {
                     Error::UnknownError(format!(
                         "Failed to submit signed transaction. Error: {:?}",
@@ -13,6 +13,7 @@ use libra_config::{
 };
 use libra_crypto::{ed25519::Ed25519PrivateKey, HashValue, PrivateKey, Uniform};
 use libra_global_constants::{OPERATOR_ACCOUNT, OPERATOR_KEY};
+use libra_mempool::MempoolClientRequest;
 use libra_secure_storage::{InMemoryStorageInternal, KVStorage, Value};
 use libra_secure_time::{MockTimeService, TimeService};
 use libra_types::{
@@ -414,7 +415,7 @@ fn setup_libra_interface_and_json_server(
 
     // Provide a VMValidator to the runtime.
     server.spawn(async move {
-        while let Some((txn, cb)) = mp_events.next().await {
+        while let Some(MempoolClientRequest::SubmitTransaction(txn, cb)) = mp_events.next().await {
             let vm_status = MockVMValidator.validate_transaction(txn).unwrap().status();
             let result = if vm_status.is_some() {
                 (MempoolStatus::new(MempoolStatusCode::VmError), vm_status)
@@ -2,8 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{BoxedStorage, CryptoStorage, KVStorage};
-use libra_config::config::{Identity, NetworkConfig, WaypointConfig};
-use libra_crypto::x25519;
+use libra_config::{
+    config::{Identity, NetworkConfig, WaypointConfig},
+    keys::KeyPair,
+};
+use libra_crypto::{ed25519::Ed25519PrivateKey, x25519};
 use libra_types::{waypoint::Waypoint, PeerId};
 use std::{convert::TryInto, str::FromStr};
 
@@ -24,6 +27,26 @@ pub fn identity_key(config: &mut NetworkConfig) -> x25519::PrivateKey {
     key.expect("identity key should be present")
 }
 
+/// The Ed25519 key whose X25519 counterpart is the identity key, which signs the notes of gossip
+/// discovery.
+pub fn discovery_signing_key(config: &mut NetworkConfig) -> Ed25519PrivateKey {
+    let key = match &config.identity {
+        Identity::FromConfig(_) => config
+            .discovery_signing_key
+            .as_mut()
+            .and_then(KeyPair::take_private),
+        Identity::FromStorage(config) => {
+            let storage: BoxedStorage = (&config.backend).into();
+            let key = storage
+                .export_private_key(&config.key_name)
+                .expect("Unable to read key");
+            Some(key)
+        }
+        Identity::None => None,
+    };
+    key.expect("discovery signing key should be present")
+}
+
 pub fn peer_id(config: &NetworkConfig) -> PeerId {
     let key = match &config.identity {
         Identity::FromConfig(config) => Some(config.peer_id),
@@ -28,7 +28,7 @@ use libra_types::{
     transaction::{Transaction, TransactionListWithProof, Version},
     waypoint::Waypoint,
 };
-use network::protocols::network::Event;
+use network::{connected_peers::ConnectedPeers, protocols::network::Event};
 use std::{
     collections::HashMap,
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -108,6 +108,8 @@ pub(crate) struct SyncCoordinator<T> {
     // peer will be notified about new chunk of transactions if it's available before expiry time
     subscriptions: HashMap<PeerNetworkId, PendingRequestInfo>,
     executor_proxy: T,
+    // view of the peers connected to this node, in which the versions they report are recorded
+    connected_peers: ConnectedPeers,
 }
 
 impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
@@ -121,6 +123,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         upstream_config: UpstreamConfig,
         executor_proxy: T,
         initial_state: SynchronizerState,
+        connected_peers: ConnectedPeers,
     ) -> Self {
         let retry_timeout_val = match role {
             RoleType::FullNode => config.tick_interval_ms + config.long_poll_timeout_ms,
@@ -141,6 +144,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             sync_request: None,
             initialization_listener: None,
             executor_proxy,
+            connected_peers,
         }
     }
 
@@ -440,6 +444,8 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         peer: PeerNetworkId,
         request: GetChunkRequest,
     ) -> Result<()> {
+        self.connected_peers
+            .set_synced_version(&peer.peer_id(), request.known_version);
         self.sync_state_with_local_storage()?;
         debug!(
             "[state sync] chunk request: peer_id: {:?}, local li version: {}, req: {}",
@@ -622,9 +628,11 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         response: GetChunkResponse,
     ) -> Result<()> {
         counters::RESPONSES_RECEIVED
-            .with_label_values(&[&*peer.peer_id().to_string()])
+            .with_label_values(&[counters::PEER_LABELS.label(&peer.peer_id().to_string())])
             .inc();
         debug!("[state sync] Processing chunk response {}", response);
+        self.connected_peers
+            .set_synced_version(&peer.peer_id(), response.response_li.version());
         let txn_list_with_proof = response.txn_list_with_proof.clone();
         let known_version = self.local_state.highest_version_in_local_storage();
         let chunk_start_version =
@@ -893,7 +901,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         let peer_id = peer.peer_id();
         sender.send_to(peer_id, msg)?;
         counters::REQUESTS_SENT
-            .with_label_values(&[&*peer_id.to_string()])
+            .with_label_values(&[counters::PEER_LABELS.label(&peer_id.to_string())])
             .inc();
         Ok(())
     }
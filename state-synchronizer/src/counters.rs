@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use libra_metrics::{
+    cardinality::{CardinalityGuard, DEFAULT_MAX_LABEL_VALUES},
     register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
     DurationHistogram, IntCounter, IntCounterVec, IntGauge,
 };
@@ -16,6 +17,10 @@ pub static PENDING_STATE_SYNCHRONIZER_NETWORK_EVENTS: Lazy<IntCounterVec> = Lazy
     ).unwrap()
 });
 
+/// Caps the values of the peer labels of `REQUESTS_SENT` and `RESPONSES_RECEIVED`
+pub static PEER_LABELS: Lazy<CardinalityGuard> =
+    Lazy::new(|| CardinalityGuard::new("libra_state_sync_peer_id", DEFAULT_MAX_LABEL_VALUES));
+
 /// Number of sync requests sent from a node
 pub static REQUESTS_SENT: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
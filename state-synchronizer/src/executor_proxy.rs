@@ -10,10 +10,17 @@ use libra_types::{
     contract_event::ContractEvent,
     ledger_info::LedgerInfoWithSignatures,
     move_resource::MoveStorage,
-    on_chain_config::{config_address, OnChainConfigPayload, ON_CHAIN_CONFIG_REGISTRY},
+    on_chain_config::{
+        config_address, OnChainConfigPayload, ON_CHAIN_CONFIG_REGISTRY,
+        OPTIONAL_ON_CHAIN_CONFIG_REGISTRY,
+    },
     transaction::TransactionListWithProof,
 };
-use std::{collections::HashSet, convert::TryFrom, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::Arc,
+};
 use storage_interface::DbReader;
 use subscription_service::ReconfigSubscription;
 
@@ -92,28 +99,26 @@ impl ExecutorProxy {
             .map(|config_id| config_id.access_path())
             .collect();
         let configs = storage.batch_fetch_resources(access_paths)?;
-        let epoch = storage
+        let config_state = storage
             .get_latest_account_state(config_address())?
-            .map(|blob| {
-                AccountState::try_from(&blob).and_then(|state| {
-                    Ok(state
-                        .get_configuration_resource()?
-                        .ok_or_else(|| format_err!("ConfigurationResource does not exist"))?
-                        .epoch())
-                })
-            })
+            .map(|blob| AccountState::try_from(&blob))
             .ok_or_else(|| format_err!("Failed to fetch ConfigurationResource"))??;
+        let epoch = config_state
+            .get_configuration_resource()?
+            .ok_or_else(|| format_err!("ConfigurationResource does not exist"))?
+            .epoch();
 
-        Ok(OnChainConfigPayload::new(
-            epoch,
-            Arc::new(
-                ON_CHAIN_CONFIG_REGISTRY
-                    .iter()
-                    .cloned()
-                    .zip_eq(configs)
-                    .collect(),
-            ),
-        ))
+        let mut configs: HashMap<_, _> = ON_CHAIN_CONFIG_REGISTRY
+            .iter()
+            .cloned()
+            .zip_eq(configs)
+            .collect();
+        for config_id in OPTIONAL_ON_CHAIN_CONFIG_REGISTRY {
+            if let Some(config) = config_state.get(&config_id.access_path().path) {
+                configs.insert(*config_id, config.clone());
+            }
+        }
+        Ok(OnChainConfigPayload::new(epoch, Arc::new(configs)))
     }
 }
 
@@ -205,12 +210,8 @@ impl ExecutorProxyTrait for ExecutorProxy {
             .configs()
             .iter()
             .filter(|(id, cfg)| {
-                &self
-                    .on_chain_configs
-                    .configs()
-                    .get(id)
-                    .expect("missing on-chain config value in local copy")
-                    != cfg
+                // Optional configs are missing from the local copy until they're published.
+                self.on_chain_configs.configs().get(id) != Some(cfg)
             })
             .map(|(id, _)| *id)
             .collect::<HashSet<_>>();
@@ -0,0 +1,87 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzing of the deserialization of the state synchronizer network messages
+
+use crate::{
+    chunk_request::{GetChunkRequest, TargetType},
+    chunk_response::{GetChunkResponse, ResponseLedgerInfo},
+    network::StateSynchronizerMsg,
+};
+use libra_proptest_helpers::ValueGenerator;
+use libra_types::{ledger_info::LedgerInfoWithSignatures, transaction::TransactionListWithProof};
+use proptest::{
+    arbitrary::{any, any_with},
+    option, prop_oneof,
+    strategy::Strategy,
+};
+
+const MAX_NUM_SIGNATURES: usize = 4;
+
+#[test]
+fn test_state_sync_msg_decode_fuzzer() {
+    let mut gen = ValueGenerator::new();
+    let data = generate_corpus(&mut gen);
+    fuzz_decode(&data);
+}
+
+/// Generates a state synchronizer message, mostly chunk responses, as sent over the network.
+pub fn generate_corpus(gen: &mut ValueGenerator) -> Vec<u8> {
+    let message = gen.generate(arb_state_sync_msg());
+    lcs::to_bytes(&message).expect("failed to serialize state sync message")
+}
+
+fn arb_ledger_info() -> impl Strategy<Value = LedgerInfoWithSignatures> {
+    any_with::<LedgerInfoWithSignatures>((0..MAX_NUM_SIGNATURES).into())
+}
+
+fn arb_response_ledger_info() -> impl Strategy<Value = ResponseLedgerInfo> {
+    prop_oneof![
+        arb_ledger_info().prop_map(ResponseLedgerInfo::VerifiableLedgerInfo),
+        (arb_ledger_info(), option::of(arb_ledger_info())).prop_map(
+            |(waypoint_li, end_of_epoch_li)| ResponseLedgerInfo::LedgerInfoForWaypoint {
+                waypoint_li,
+                end_of_epoch_li,
+            }
+        ),
+    ]
+}
+
+fn arb_target_type() -> impl Strategy<Value = TargetType> {
+    prop_oneof![
+        arb_ledger_info().prop_map(TargetType::TargetLedgerInfo),
+        any::<u64>().prop_map(|timeout_ms| TargetType::HighestAvailable { timeout_ms }),
+        any::<u64>().prop_map(TargetType::Waypoint),
+    ]
+}
+
+fn arb_state_sync_msg() -> impl Strategy<Value = StateSynchronizerMsg> {
+    prop_oneof![
+        4 => (arb_response_ledger_info(), any::<TransactionListWithProof>()).prop_map(
+            |(response_li, txn_list_with_proof)| {
+                StateSynchronizerMsg::GetChunkResponse(Box::new(GetChunkResponse::new(
+                    response_li,
+                    txn_list_with_proof,
+                )))
+            }
+        ),
+        1 => (any::<u64>(), any::<u64>(), any::<u64>(), arb_target_type()).prop_map(
+            |(known_version, current_epoch, limit, target)| {
+                StateSynchronizerMsg::GetChunkRequest(Box::new(GetChunkRequest::new(
+                    known_version,
+                    current_epoch,
+                    limit,
+                    target,
+                )))
+            }
+        ),
+    ]
+}
+
+/// Deserializes `data` as a state synchronizer message received from the network.
+pub fn fuzz_decode(data: &[u8]) {
+    if lcs::from_bytes::<StateSynchronizerMsg>(data).is_err() && cfg!(test) {
+        // the corpus should always deserialize
+        panic!();
+    }
+}
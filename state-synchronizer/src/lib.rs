@@ -18,6 +18,8 @@ mod chunk_response;
 mod coordinator;
 mod counters;
 mod executor_proxy;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
 pub mod network;
 mod peer_manager;
 mod synchronizer;
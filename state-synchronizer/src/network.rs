@@ -7,12 +7,13 @@ use crate::{chunk_request::GetChunkRequest, chunk_response::GetChunkResponse, co
 use channel::message_queues::QueueStyle;
 use libra_types::PeerId;
 use network::{
-    error::NetworkError,
     peer_manager::{ConnectionRequestSender, PeerManagerRequestSender},
+    priority::ProtocolPriority,
     protocols::network::{NetworkEvents, NetworkSender},
     validator_network::network_builder::NetworkBuilder,
     ProtocolId,
 };
+use network_api::MessageSender;
 use serde::{Deserialize, Serialize};
 
 /// StateSynchronizer network messages
@@ -38,9 +39,12 @@ pub type StateSynchronizerEvents = NetworkEvents<StateSynchronizerMsg>;
 /// request to remote, to finally receiving the response and deserializing. It
 /// therefore makes the most sense to make the rpc call on a separate async task,
 /// which requires the `StateSynchronizerSender` to be `Clone` and `Send`.
+///
+/// The messages are sent through any [`MessageSender`], e.g., a simulated network in tests, see
+/// [`StateSynchronizerSender::from_sender`].
 #[derive(Clone)]
-pub struct StateSynchronizerSender {
-    inner: NetworkSender<StateSynchronizerMsg>,
+pub struct StateSynchronizerSender<TSender = NetworkSender<StateSynchronizerMsg>> {
+    inner: TSender,
 }
 
 pub fn add_to_network(
@@ -50,6 +54,7 @@ pub fn add_to_network(
         .add_protocol_handler(
             vec![],
             vec![ProtocolId::StateSynchronizerDirectSend],
+            ProtocolPriority::Low,
             QueueStyle::LIFO,
             1,
             Some(&counters::PENDING_STATE_SYNCHRONIZER_NETWORK_EVENTS),
@@ -65,16 +70,23 @@ impl StateSynchronizerSender {
         peer_mgr_reqs_tx: PeerManagerRequestSender,
         connection_reqs_tx: ConnectionRequestSender,
     ) -> Self {
-        Self {
-            inner: NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx),
-        }
+        Self::from_sender(NetworkSender::new(peer_mgr_reqs_tx, connection_reqs_tx))
+    }
+}
+
+impl<TSender> StateSynchronizerSender<TSender>
+where
+    TSender: MessageSender<StateSynchronizerMsg, Protocol = ProtocolId>,
+{
+    pub fn from_sender(inner: TSender) -> Self {
+        Self { inner }
     }
 
     pub fn send_to(
         &mut self,
         recipient: PeerId,
         message: StateSynchronizerMsg,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<(), TSender::Error> {
         let protocol = ProtocolId::StateSynchronizerDirectSend;
         self.inner.send_to(recipient, protocol, message)
     }
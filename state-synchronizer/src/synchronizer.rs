@@ -19,6 +19,7 @@ use libra_types::{
     contract_event::ContractEvent, ledger_info::LedgerInfoWithSignatures, transaction::Transaction,
     waypoint::Waypoint, PeerId,
 };
+use network::connected_peers::ConnectedPeers;
 use std::{
     boxed::Box,
     collections::HashMap,
@@ -47,6 +48,7 @@ impl StateSynchronizer {
         config: &NodeConfig,
         waypoint: Waypoint,
         reconfig_event_subscriptions: Vec<ReconfigSubscription>,
+        connected_peers: ConnectedPeers,
     ) -> Self {
         let runtime = Builder::new()
             .thread_name("state-sync-")
@@ -65,6 +67,7 @@ impl StateSynchronizer {
             &config.state_sync,
             config.upstream.clone(),
             executor_proxy,
+            connected_peers,
         )
     }
 
@@ -77,6 +80,7 @@ impl StateSynchronizer {
         state_sync_config: &StateSyncConfig,
         upstream_config: UpstreamConfig,
         executor_proxy: E,
+        connected_peers: ConnectedPeers,
     ) -> Self {
         let (coordinator_sender, coordinator_receiver) = mpsc::unbounded();
 
@@ -99,6 +103,7 @@ impl StateSynchronizer {
             upstream_config,
             executor_proxy,
             initial_state,
+            connected_peers,
         );
         runtime.spawn(coordinator.start(network));
 
@@ -116,13 +121,15 @@ impl StateSynchronizer {
 
     /// The function returns a future that is fulfilled when the state synchronizer is
     /// caught up with the waypoint specified in the local config.
-    pub async fn wait_until_initialized(&self) -> Result<()> {
+    pub fn wait_until_initialized(&self) -> impl Future<Output = Result<()>> + Send + 'static {
         let mut sender = self.coordinator_sender.clone();
-        let (cb_sender, cb_receiver) = oneshot::channel();
-        sender
-            .send(CoordinatorMessage::WaitInitialize(cb_sender))
-            .await?;
-        cb_receiver.await?
+        async move {
+            let (cb_sender, cb_receiver) = oneshot::channel();
+            sender
+                .send(CoordinatorMessage::WaitInitialize(cb_sender))
+                .await?;
+            cb_receiver.await?
+        }
     }
 }
 
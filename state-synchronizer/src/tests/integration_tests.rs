@@ -12,7 +12,10 @@ use libra_config::{
     config::{PeerNetworkId, RoleType},
     network_id::NetworkId,
 };
-use libra_crypto::{hash::ACCUMULATOR_PLACEHOLDER_HASH, test_utils::TEST_SEED, x25519, Uniform};
+use libra_crypto::{
+    ed25519::Ed25519PrivateKey, hash::ACCUMULATOR_PLACEHOLDER_HASH, test_utils::TEST_SEED, x25519,
+    Uniform,
+};
 use libra_mempool::mocks::MockSharedMempool;
 use libra_network_address::{NetworkAddress, RawNetworkAddress};
 use libra_types::{
@@ -23,6 +26,7 @@ use libra_types::{
     validator_verifier::random_validator_verifier, waypoint::Waypoint,
 };
 use network::{
+    connected_peers::ConnectedPeers,
     validator_network::network_builder::{AuthenticationMode, NetworkBuilder},
     NetworkPublicKeys,
 };
@@ -117,6 +121,7 @@ struct SynchronizerEnv {
     storage_proxies: Vec<Arc<RwLock<MockStorage>>>, // to directly modify peers storage
     signers: Vec<ValidatorSigner>,
     network_keys: Vec<x25519::PrivateKey>,
+    discovery_keys: Vec<Ed25519PrivateKey>,
     public_keys: Vec<ValidatorInfo>,
     network_id: NetworkId,
     peer_ids: Vec<PeerId>,
@@ -125,20 +130,26 @@ struct SynchronizerEnv {
 }
 
 impl SynchronizerEnv {
-    // Returns the initial peers with their signatures
+    // Returns the initial peers with their signatures, identity keys, and the discovery signing
+    // keys from which the identity keys derive
     fn initial_setup(
         count: usize,
     ) -> (
         Vec<ValidatorSigner>,
         Vec<ValidatorInfo>,
         Vec<x25519::PrivateKey>,
+        Vec<Ed25519PrivateKey>,
     ) {
         let (signers, _verifier) = random_validator_verifier(count, None, true);
 
         // Setup identity public keys.
         let mut rng = StdRng::from_seed(TEST_SEED);
-        let network_keys: Vec<_> = (0..count)
-            .map(|_| x25519::PrivateKey::generate(&mut rng))
+        let discovery_keys: Vec<_> = (0..count)
+            .map(|_| Ed25519PrivateKey::generate(&mut rng))
+            .collect();
+        let network_keys: Vec<_> = discovery_keys
+            .iter()
+            .map(|key| x25519::PrivateKey::from_ed25519_private_bytes(&key.to_bytes()).unwrap())
             .collect();
 
         let mut validators_keys = vec![];
@@ -158,7 +169,7 @@ impl SynchronizerEnv {
                 ValidatorInfo::new(signer.author(), voting_power, validator_config);
             validators_keys.push(validator_info);
         }
-        (signers, validators_keys, network_keys)
+        (signers, validators_keys, network_keys, discovery_keys)
     }
 
     // Moves peer 0 to the next epoch. Note that other peers are not going to be able to discover
@@ -196,7 +207,7 @@ impl SynchronizerEnv {
     fn new(num_peers: usize) -> Self {
         ::libra_logger::Logger::new().environment_only(true).init();
         let runtime = Runtime::new().unwrap();
-        let (signers, public_keys, network_keys) = Self::initial_setup(num_peers);
+        let (signers, public_keys, network_keys, discovery_keys) = Self::initial_setup(num_peers);
         let peer_ids = signers.iter().map(|s| s.author()).collect::<Vec<PeerId>>();
 
         Self {
@@ -207,6 +218,7 @@ impl SynchronizerEnv {
             signers,
             network_id: NetworkId::Validator,
             network_keys,
+            discovery_keys,
             public_keys,
             peer_ids,
             peer_addresses: vec![],
@@ -258,7 +270,7 @@ impl SynchronizerEnv {
             self.network_id.clone(),
             self.peer_ids[new_peer_idx],
             RoleType::Validator,
-            addr,
+            vec![addr],
         );
         network_builder
             .authentication_mode(AuthenticationMode::Mutual(
@@ -267,10 +279,11 @@ impl SynchronizerEnv {
             .trusted_peers(trusted_peers)
             .seed_peers(seed_peers)
             .add_connectivity_manager()
-            .add_gossip_discovery();
+            .add_gossip_discovery(self.discovery_keys[new_peer_idx].clone())
+            .unwrap();
 
         let (sender, events) = crate::network::add_to_network(&mut network_builder);
-        let peer_addr = network_builder.build();
+        let peer_addr = network_builder.build().unwrap().listen_addrs()[0].clone();
 
         let mut config = config_builder::test_config().0;
         let network = config.validator_network.unwrap();
@@ -302,6 +315,7 @@ impl SynchronizerEnv {
             &config.state_sync,
             config.upstream,
             MockExecutorProxy::new(handler, storage_proxy.clone()),
+            ConnectedPeers::new(),
         );
         self.mempools
             .push(MockSharedMempool::new(Some(mempool_requests)));
@@ -16,7 +16,10 @@ use libra_types::{
     transaction::{authenticator::AuthenticationKey, SignedTransaction, Transaction},
     validator_signer::ValidatorSigner,
 };
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 use transaction_builder::encode_transfer_with_metadata_script;
 use vm_genesis::GENESIS_KEYPAIR;
 
@@ -148,7 +151,7 @@ impl MockStorage {
             self.add_txns(&mut vec![txn.clone()]);
             committed_txns.push(txn.clone());
             if let Transaction::UserTransaction(signed_txn) = txn {
-                signed_txns.push(signed_txn);
+                signed_txns.push(signed_txn.as_ref().clone());
             }
         }
         self.add_li(None);
@@ -165,13 +168,13 @@ impl MockStorage {
             vec![],
             vec![],
         );
-        Transaction::UserTransaction(get_test_signed_txn(
+        Transaction::UserTransaction(Arc::new(get_test_signed_txn(
             sender,
             0, // sequence number
             &GENESIS_KEYPAIR.0,
             GENESIS_KEYPAIR.1.clone(),
             Some(program),
-        ))
+        )))
     }
 
     // add the LI to the current highest version and sign it
@@ -48,7 +48,9 @@ use crate::{
 };
 use anyhow::{ensure, format_err, Result};
 use itertools::{izip, zip_eq};
-use jellyfish_merkle::{restore::JellyfishMerkleRestore, TreeReader, TreeWriter};
+use jellyfish_merkle::{
+    iterator::JellyfishMerkleIterator, restore::JellyfishMerkleRestore, TreeReader, TreeWriter,
+};
 use libra_crypto::hash::{CryptoHash, HashValue, SPARSE_MERKLE_PLACEHOLDER_HASH};
 use libra_logger::prelude::*;
 use libra_metrics::{
@@ -76,7 +78,9 @@ use libra_types::{
 use once_cell::sync::Lazy;
 use schemadb::{DB, DEFAULT_CF_NAME};
 use std::{iter::Iterator, path::Path, sync::Arc, time::Instant};
-use storage_interface::{DbReader, DbWriter, StartupInfo, TreeState};
+use storage_interface::{
+    AccountStatePage, DbReader, DbWriter, StartupInfo, StatePrunedError, TreeState,
+};
 
 static OP_COUNTER: Lazy<OpMetrics> = Lazy::new(|| OpMetrics::new_and_registered("storage"));
 
@@ -171,7 +175,7 @@ impl LibraDB {
             instant.elapsed().as_millis()
         );
 
-        Ok(LibraDB {
+        let libra_db = LibraDB {
             db: Arc::clone(&db),
             event_store: EventStore::new(Arc::clone(&db)),
             ledger_store: Arc::new(LedgerStore::new(Arc::clone(&db))),
@@ -179,7 +183,16 @@ impl LibraDB {
             transaction_store: Arc::new(TransactionStore::new(Arc::clone(&db))),
             system_store: SystemStore::new(Arc::clone(&db)),
             pruner: prune_window.map(|n| Pruner::new(Arc::clone(&db), n)),
-        })
+        };
+        // The state out of the prune window may have been pruned before the restart.
+        if let Some(pruner) = libra_db.pruner.as_ref() {
+            if let Some((latest_version, _)) =
+                libra_db.ledger_store.get_latest_transaction_info_option()?
+            {
+                pruner.update_least_readable_version(latest_version);
+            }
+        }
+        Ok(libra_db)
     }
 
     /// This opens db in non-readonly mode, without the pruner.
@@ -628,6 +641,34 @@ impl LibraDB {
             pruner.wake(latest_version)
         }
     }
+
+    /// Returns a `StatePrunedError` if the state at `version` may have been pruned.
+    fn error_if_state_pruned(&self, version: Version) -> Result<()> {
+        if let Some(pruner) = self.pruner.as_ref() {
+            let earliest_version = pruner.least_readable_version();
+            if version < earliest_version {
+                return Err(StatePrunedError {
+                    version,
+                    earliest_version,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the state at `version` with `read`, failing with a `StatePrunedError` rather than
+    /// returning partial data if the state is pruned before or while it is read.
+    fn read_unpruned_state<T>(
+        &self,
+        version: Version,
+        read: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        self.error_if_state_pruned(version)?;
+        let result = read();
+        self.error_if_state_pruned(version)?;
+        result
+    }
 }
 
 impl DbReader for LibraDB {
@@ -830,9 +871,11 @@ impl DbReader for LibraDB {
         let txn_info_with_proof = self
             .ledger_store
             .get_transaction_info_with_proof(version, ledger_version)?;
-        let (account_state_blob, sparse_merkle_proof) = self
-            .state_store
-            .get_account_state_with_proof_by_version(address, version)?;
+        let (account_state_blob, sparse_merkle_proof) =
+            self.read_unpruned_state(version, || {
+                self.state_store
+                    .get_account_state_with_proof_by_version(address, version)
+            })?;
         Ok(AccountStateWithProof::new(
             version,
             account_state_blob,
@@ -849,8 +892,49 @@ impl DbReader for LibraDB {
         address: AccountAddress,
         version: Version,
     ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
-        self.state_store
-            .get_account_state_with_proof_by_version(address, version)
+        self.read_unpruned_state(version, || {
+            self.state_store
+                .get_account_state_with_proof_by_version(address, version)
+        })
+    }
+
+    /// Returns up to `limit` account states of the state tree at `version`, in the order of the
+    /// hashes of their addresses, starting from `page_token` or from the first account if it is
+    /// `None`. Following the `next_page_token` of each page iterates over all the accounts.
+    fn get_account_state_page(
+        &self,
+        version: Version,
+        page_token: Option<HashValue>,
+        limit: u64,
+    ) -> Result<AccountStatePage> {
+        error_if_too_many_requested(limit, MAX_LIMIT)?;
+        let latest_version = self.get_latest_version()?;
+        ensure!(
+            version <= latest_version,
+            "The queried version {} is greater than the latest version currently in ledger: {}",
+            version,
+            latest_version
+        );
+
+        let mut account_states = self.read_unpruned_state(version, || {
+            JellyfishMerkleIterator::new(
+                Arc::clone(&self.state_store),
+                version,
+                page_token.unwrap_or_else(HashValue::zero),
+            )?
+            .take(limit as usize + 1)
+            .collect::<Result<Vec<_>>>()
+        })?;
+        // The account after the last one of the page starts the next page.
+        let next_page_token = if account_states.len() as u64 > limit {
+            account_states.pop().map(|(key, _blob)| key)
+        } else {
+            None
+        };
+        Ok(AccountStatePage {
+            account_states,
+            next_page_token,
+        })
     }
 
     fn get_latest_state_root(&self) -> Result<(Version, HashValue)> {
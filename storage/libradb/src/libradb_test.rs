@@ -380,6 +380,111 @@ proptest! {
     }
 }
 
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10))]
+
+    #[test]
+    fn test_get_account_state_page(input in arb_blocks_to_commit(), limit in 1..5u64) {
+        let tmp_dir = TempPath::new();
+        let db = LibraDB::new_for_test(&tmp_dir);
+
+        let mut cur_ver = 0;
+        for (txns_to_commit, ledger_info_with_sigs) in input.iter() {
+            db.save_transactions(&txns_to_commit, cur_ver, Some(ledger_info_with_sigs))
+                .unwrap();
+            cur_ver += txns_to_commit.len() as u64;
+        }
+        let version = cur_ver - 1;
+
+        let expected = db
+            .get_backup_handler()
+            .get_account_iter(version)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        let mut actual = vec![];
+        let mut page_token = None;
+        loop {
+            let page = db.get_account_state_page(version, page_token, limit).unwrap();
+            prop_assert!(page.account_states.len() as u64 <= limit);
+            actual.extend(page.account_states);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        prop_assert_eq!(actual, expected);
+
+        prop_assert!(db.get_account_state_page(version + 1, None, limit).is_err());
+    }
+
+    #[test]
+    fn test_get_account_state_at_past_version(input in arb_blocks_to_commit()) {
+        let tmp_dir = TempPath::new();
+        let db = LibraDB::new_for_test(&tmp_dir);
+
+        let mut cur_ver = 0;
+        for (txns_to_commit, ledger_info_with_sigs) in input.iter() {
+            db.save_transactions(&txns_to_commit, cur_ver, Some(ledger_info_with_sigs))
+                .unwrap();
+            cur_ver += txns_to_commit.len() as u64;
+        }
+        let ledger_version = cur_ver - 1;
+
+        let first_txn_to_commit = &input[0].0[0];
+        for (address, blob) in first_txn_to_commit.account_states() {
+            let account_state_with_proof = db
+                .get_account_state_with_proof(*address, 0, ledger_version)
+                .unwrap();
+            prop_assert_eq!(account_state_with_proof.blob.as_ref(), Some(blob));
+            prop_assert_eq!(
+                db.get_account_state_with_proof_by_version(*address, 0)
+                    .unwrap()
+                    .0
+                    .as_ref(),
+                Some(blob)
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_account_state_out_of_prune_window(input in arb_blocks_to_commit()) {
+        let tmp_dir = TempPath::new();
+        let db = LibraDB::open(&tmp_dir, false /* readonly */, Some(1) /* prune_window */)
+            .unwrap();
+
+        let mut cur_ver = 0;
+        for (txns_to_commit, ledger_info_with_sigs) in input.iter() {
+            db.save_transactions(&txns_to_commit, cur_ver, Some(ledger_info_with_sigs))
+                .unwrap();
+            cur_ver += txns_to_commit.len() as u64;
+        }
+        prop_assume!(cur_ver > 2);
+        let ledger_version = cur_ver - 1;
+        db.pruner
+            .as_ref()
+            .unwrap()
+            .wake_and_wait(ledger_version)
+            .unwrap();
+
+        let address = AccountAddress::random();
+        let err = db
+            .get_account_state_with_proof_by_version(address, 0)
+            .unwrap_err();
+        prop_assert_eq!(
+            err.downcast_ref::<StatePrunedError>(),
+            Some(&StatePrunedError {
+                version: 0,
+                earliest_version: ledger_version - 1,
+            })
+        );
+        prop_assert!(db
+            .get_account_state_with_proof_by_version(address, ledger_version)
+            .is_ok());
+    }
+}
+
 #[test]
 fn test_get_first_seq_num_and_limit() {
     assert!(get_first_seq_num_and_limit(true, 0, 0).is_err());
@@ -414,6 +519,9 @@ fn test_too_many_requested() {
         )
         .is_err());
     assert!(db.get_transactions(0, 1001 /* limit */, 0, true).is_err());
+    assert!(db
+        .get_account_state_page(0, None, 1001 /* limit */)
+        .is_err());
     assert!(db
         .get_events_by_query_path(
             &AccessPath::new_for_sent_event(AccountAddress::random()),
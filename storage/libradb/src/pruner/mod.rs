@@ -46,6 +46,9 @@ pub(crate) struct Pruner {
     /// sets this atomic value to `V`, all versions before `V` can no longer be accessed.
     #[allow(dead_code)]
     worker_progress: Arc<AtomicU64>,
+    /// The state of the versions before this may have been pruned, or be being pruned, so that it
+    /// can no longer be read. It moves before the worker thread is told to prune.
+    least_readable_version: AtomicU64,
 }
 
 impl Pruner {
@@ -65,13 +68,31 @@ impl Pruner {
             worker_thread: Some(worker_thread),
             command_sender: Mutex::new(command_sender),
             worker_progress,
+            least_readable_version: AtomicU64::new(0),
         }
     }
 
-    /// Sends pruning command to the worker thread when necessary.
-    pub fn wake(&self, latest_version: Version) {
+    /// The least version whose state can still be read.
+    pub fn least_readable_version(&self) -> Version {
+        self.least_readable_version.load(Ordering::SeqCst)
+    }
+
+    /// Marks the state of the versions out of the window of `latest_version` as no longer
+    /// readable, e.g., as pruned by a previous run, and returns the least readable version.
+    pub fn update_least_readable_version(&self, latest_version: Version) -> Option<Version> {
         if latest_version > self.historical_versions_to_keep {
             let least_readable_version = latest_version - self.historical_versions_to_keep;
+            self.least_readable_version
+                .store(least_readable_version, Ordering::SeqCst);
+            Some(least_readable_version)
+        } else {
+            None
+        }
+    }
+
+    /// Sends pruning command to the worker thread when necessary.
+    pub fn wake(&self, latest_version: Version) {
+        if let Some(least_readable_version) = self.update_least_readable_version(latest_version) {
             self.command_sender
                 .lock()
                 .expect("command_sender to pruner thread should lock.")
@@ -155,3 +155,19 @@ fn test_worker_quit_eagerly() {
         verify_state_in_store(state_store, address, Some(&value2), 2);
     }
 }
+
+#[test]
+fn test_least_readable_version() {
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new_for_test(&tmp_dir).db;
+    let pruner = Pruner::new(Arc::clone(&db), 2 /* historical_versions_to_keep */);
+    assert_eq!(pruner.least_readable_version(), 0);
+
+    // Nothing is pruned within the window.
+    pruner.wake_and_wait(2 /* latest_version */).unwrap();
+    assert_eq!(pruner.least_readable_version(), 0);
+    pruner.wake_and_wait(5 /* latest_version */).unwrap();
+    assert_eq!(pruner.least_readable_version(), 3);
+    assert_eq!(pruner.update_least_readable_version(7), Some(5));
+    assert_eq!(pruner.least_readable_version(), 5);
+}
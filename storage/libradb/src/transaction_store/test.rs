@@ -7,6 +7,7 @@ use libra_proptest_helpers::Index;
 use libra_temppath::TempPath;
 use libra_types::proptest_types::{AccountInfoUniverse, SignatureCheckedTransactionGen};
 use proptest::{collection::vec, prelude::*};
+use std::sync::Arc;
 
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(10))]
@@ -108,7 +109,9 @@ fn init_store(
     let txns = gens
         .into_iter()
         .map(|(index, gen)| {
-            Transaction::UserTransaction(gen.materialize(index, &mut universe).into_inner())
+            Transaction::UserTransaction(Arc::new(
+                gen.materialize(index, &mut universe).into_inner(),
+            ))
         })
         .collect::<Vec<_>>();
 
@@ -95,6 +95,16 @@ impl TreeState {
     }
 }
 
+/// A page of the account states of a version, in the order of the hashes of the account addresses,
+/// which is the order of the leaves of the state tree.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AccountStatePage {
+    /// The account states of the page, keyed by the hash of their address.
+    pub account_states: Vec<(HashValue, AccountStateBlob)>,
+    /// Token to request the next page with, or `None` if this is the last one.
+    pub next_page_token: Option<HashValue>,
+}
+
 #[derive(Debug, Deserialize, Error, PartialEq, Serialize)]
 pub enum Error {
     #[error("Service error: {:?}", error)]
@@ -104,6 +114,18 @@ pub enum Error {
     SerializationError(String),
 }
 
+/// The error of the reads of the state of a version which has been pruned.
+#[derive(Clone, Debug, Deserialize, Error, Eq, PartialEq, Serialize)]
+#[error(
+    "State at version {} has been pruned, earliest available version is {}",
+    version,
+    earliest_version
+)]
+pub struct StatePrunedError {
+    pub version: Version,
+    pub earliest_version: Version,
+}
+
 impl From<anyhow::Error> for Error {
     fn from(error: anyhow::Error) -> Self {
         Self::ServiceError {
@@ -263,6 +285,20 @@ pub trait DbReader: Send + Sync {
 
     /// Get the ledger info of the epoch that `known_version` belongs to.
     fn get_ledger_info(&self, known_version: u64) -> Result<LedgerInfoWithSignatures>;
+
+    /// See [`LibraDB::get_account_state_page`]. Fails for the readers which don't support it,
+    /// e.g., the storage service client.
+    ///
+    /// [`LibraDB::get_account_state_page`]:
+    /// ../libradb/struct.LibraDB.html#method.get_account_state_page
+    fn get_account_state_page(
+        &self,
+        _version: Version,
+        _page_token: Option<HashValue>,
+        _limit: u64,
+    ) -> Result<AccountStatePage> {
+        Err(format_err!("get_account_state_page is not supported"))
+    }
 }
 
 impl MoveStorage for &dyn DbReader {
@@ -26,6 +26,8 @@ mod dev_commands;
 /// Client wrapper to connect to validator.
 mod libra_client;
 mod query_commands;
+/// Non-interactive mode driven by JSON requests.
+pub mod scripting;
 mod transfer_commands;
 
 /// Struct used to store data for each created account.  We track the sequence number
@@ -10,10 +10,12 @@ use chrono::{
 use cli::{
     client_proxy::ClientProxy,
     commands::{get_commands, parse_cmd, report_error, Command},
+    scripting::{self, ErrorKind, Response},
 };
 use libra_types::waypoint::Waypoint;
 use rustyline::{config::CompletionType, error::ReadlineError, Config, Editor};
 use std::{
+    io, process,
     str::FromStr,
     time::{Duration, UNIX_EPOCH},
 };
@@ -64,6 +66,10 @@ struct Args {
     /// Verbose output.
     #[structopt(short = "v", long = "verbose")]
     pub verbose: bool,
+    /// Non-interactive scripting mode: read one JSON request per line from stdin and write one
+    /// JSON response per line to stdout. See `cli::scripting` for the schemas and exit codes.
+    #[structopt(long = "json")]
+    pub json: bool,
 }
 
 fn main() {
@@ -91,15 +97,18 @@ fn main() {
             })
             .unwrap()
     });
-    let mut client_proxy = ClientProxy::new(
+    let client_proxy = ClientProxy::new(
         &args.url,
         &faucet_account_file,
         args.sync,
         args.faucet_server.clone(),
         mnemonic_file,
         waypoint,
-    )
-    .expect("Failed to construct client.");
+    );
+    if args.json {
+        run_scripting_mode(&args, client_proxy);
+    }
+    let mut client_proxy = client_proxy.expect("Failed to construct client.");
 
     // Test connection to validator
     let block_metadata = client_proxy
@@ -181,6 +190,38 @@ fn main() {
     }
 }
 
+/// Runs the client non-interactively until stdin is exhausted, then exits the process.
+fn run_scripting_mode(args: &Args, client_proxy: anyhow::Result<ClientProxy>) -> ! {
+    let setup = client_proxy.and_then(|mut client_proxy| {
+        client_proxy.test_validator_connection()?;
+        if args.mnemonic_file.is_some() {
+            client_proxy.recover_accounts_in_wallet()?;
+        }
+        Ok(client_proxy)
+    });
+    let stdout = io::stdout();
+    match setup {
+        Ok(mut client_proxy) => {
+            let stdin = io::stdin();
+            process::exit(scripting::run(
+                &mut client_proxy,
+                stdin.lock(),
+                stdout.lock(),
+            ))
+        }
+        Err(e) => {
+            scripting::write_response(
+                &mut stdout.lock(),
+                &Response::error(
+                    ErrorKind::SetupFailed,
+                    format!("Failed to set up client for {}: {}", args.url, e),
+                ),
+            );
+            process::exit(scripting::EXIT_SETUP_FAILED)
+        }
+    }
+}
+
 /// Print the help message for the client and underlying command.
 fn print_help(client_info: &str, commands: &[std::sync::Arc<dyn Command>]) {
     println!("{}", client_info);
@@ -0,0 +1,439 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Non-interactive scripting mode of the client.
+//!
+//! Every line of the input is a JSON request naming a `command` along with its arguments, e.g.,
+//! `{"command": "get_balances", "account": "0"}`, and produces exactly one line of JSON output,
+//! either `{"status": "ok", "result": ...}` or
+//! `{"status": "error", "error": {"kind": ..., "message": ...}}`. Nothing else is written to
+//! stdout. Once the input is exhausted, the process exits with one of the `EXIT_*` codes.
+//!
+//! Accounts are referenced like in the interactive shell: by account_ref_id, address or
+//! authentication key.
+
+use crate::{
+    client_proxy::{AccountEntry, ClientProxy},
+    AccountStatus,
+};
+use anyhow::{bail, Result};
+use libra_json_rpc_client::views::{AccountView, EventView, TransactionView};
+use libra_types::{account_address::AccountAddress, vm_error::StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::{BufRead, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Every request succeeded.
+pub const EXIT_SUCCESS: i32 = 0;
+/// At least one command failed.
+pub const EXIT_COMMAND_FAILED: i32 = 1;
+/// At least one line of the input was not a valid request.
+pub const EXIT_INVALID_REQUEST: i32 = 2;
+/// The client could not be set up, e.g., the node is unreachable.
+pub const EXIT_SETUP_FAILED: i32 = 3;
+
+const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 30;
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A single scripting request.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    /// Creates the next account of the wallet.
+    CreateAccount,
+    /// Lists the accounts known to the client.
+    ListAccounts,
+    /// Gets the balances of an account.
+    GetBalances {
+        /// Account reference.
+        account: String,
+    },
+    /// Gets the sequence number of an account from the node.
+    GetSequenceNumber {
+        /// Account reference.
+        account: String,
+    },
+    /// Gets the latest state of an account.
+    GetAccountState {
+        /// Account reference.
+        account: String,
+    },
+    /// Gets a committed transaction by sender and sequence number.
+    GetTransaction {
+        /// Account reference of the sender.
+        account: String,
+        /// Sequence number of the transaction.
+        sequence_number: u64,
+        /// Whether to include the events emitted by the transaction.
+        #[serde(default)]
+        fetch_events: bool,
+    },
+    /// Gets committed transactions by version range.
+    GetTransactions {
+        /// First version to fetch.
+        start_version: u64,
+        /// Maximum number of transactions.
+        limit: u64,
+        /// Whether to include the events emitted by the transactions.
+        #[serde(default)]
+        fetch_events: bool,
+    },
+    /// Gets the payment events of an account.
+    GetEvents {
+        /// Account reference.
+        account: String,
+        /// Either "sent" or "received".
+        event_type: String,
+        /// Sequence number of the first event.
+        start: u64,
+        /// Maximum number of events.
+        limit: u64,
+    },
+    /// Submits a transfer, without waiting for it to be committed.
+    Transfer {
+        /// Account reference of the sender, which must be managed by the client.
+        sender: String,
+        /// Account reference of the receiver.
+        receiver: String,
+        /// Amount, in whole coins of `currency`.
+        amount: String,
+        /// Currency code, e.g., "LBR".
+        currency: String,
+    },
+    /// Mints coins to an account, without waiting for the mint to be committed.
+    Mint {
+        /// Account reference of the receiver. Must carry its authentication key.
+        receiver: String,
+        /// Amount, in whole coins of `currency`.
+        amount: String,
+        /// Currency code, e.g., "LBR".
+        currency: String,
+    },
+    /// Waits until a transaction is committed and fails unless it executed successfully.
+    WaitForTransaction {
+        /// Address of the sender.
+        sender: AccountAddress,
+        /// Sequence number of the transaction.
+        sequence_number: u64,
+        /// How long to wait, defaults to 30 seconds.
+        timeout_secs: Option<u64>,
+    },
+}
+
+/// An account known to the client, as listed by `list_accounts`.
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+    /// account_ref_id of the account.
+    pub index: usize,
+    /// Address of the account.
+    pub address: AccountAddress,
+    /// Hex-encoded authentication key, if known.
+    pub authentication_key: Option<String>,
+    /// Sequence number maintained by the client.
+    pub sequence_number: u64,
+    /// Whether the account exists on chain.
+    pub status: AccountStatus,
+}
+
+/// Result of `get_account_state`.
+#[derive(Debug, Serialize)]
+pub struct AccountStateResult {
+    /// The account, if it exists.
+    pub account: Option<AccountView>,
+    /// Version at which the account state was read.
+    pub version: u64,
+}
+
+/// Result of `create_account` and `transfer`: the transaction sender, or the created account.
+#[derive(Debug, Serialize)]
+pub struct AccountResult {
+    /// account_ref_id of the account.
+    pub index: usize,
+    /// Address of the account.
+    pub address: AccountAddress,
+    /// Sequence number of the submitted transaction, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence_number: Option<u64>,
+}
+
+/// Kind of a failed request.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The request could not be parsed.
+    InvalidRequest,
+    /// The command failed.
+    CommandFailed,
+    /// The client could not be set up, no request was run.
+    SetupFailed,
+}
+
+/// Error of a failed request.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    /// Kind of error.
+    pub kind: ErrorKind,
+    /// Human readable description of the error. Not stable.
+    pub message: String,
+}
+
+/// Response to a single request.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    /// The command succeeded.
+    Ok {
+        /// Result of the command, whose schema depends on the command.
+        result: Value,
+    },
+    /// The request failed.
+    Error {
+        /// Description of the failure.
+        error: ErrorResponse,
+    },
+}
+
+impl Response {
+    /// Creates an error response.
+    pub fn error(kind: ErrorKind, message: String) -> Self {
+        Response::Error {
+            error: ErrorResponse { kind, message },
+        }
+    }
+}
+
+/// Runs every request read from `input`, writing the responses to `output`. Returns the exit
+/// code of the process.
+pub fn run<R: BufRead, W: Write>(client: &mut ClientProxy, input: R, mut output: W) -> i32 {
+    let mut exit_code = EXIT_SUCCESS;
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                write_response(
+                    &mut output,
+                    &Response::error(ErrorKind::InvalidRequest, e.to_string()),
+                );
+                return EXIT_INVALID_REQUEST;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => match execute(client, request) {
+                Ok(result) => Response::Ok { result },
+                Err(e) => {
+                    exit_code = exit_code.max(EXIT_COMMAND_FAILED);
+                    Response::error(ErrorKind::CommandFailed, e.to_string())
+                }
+            },
+            Err(e) => {
+                exit_code = exit_code.max(EXIT_INVALID_REQUEST);
+                Response::error(ErrorKind::InvalidRequest, e.to_string())
+            }
+        };
+        write_response(&mut output, &response);
+    }
+    exit_code
+}
+
+/// Writes a response on its own line.
+pub fn write_response<W: Write>(output: &mut W, response: &Response) {
+    let line = serde_json::to_string(response).expect("responses are serializable");
+    writeln!(output, "{}", line)
+        .and_then(|_| output.flush())
+        .expect("failed to write to output");
+}
+
+fn execute(client: &mut ClientProxy, request: Request) -> Result<Value> {
+    let result = match request {
+        Request::CreateAccount => {
+            let account = client.create_next_account(true)?;
+            serde_json::to_value(AccountResult {
+                index: account.index,
+                address: account.address,
+                sequence_number: None,
+            })?
+        }
+        Request::ListAccounts => {
+            let accounts: Vec<_> = client
+                .accounts
+                .iter()
+                .enumerate()
+                .map(|(index, account)| AccountSummary {
+                    index,
+                    address: account.address,
+                    authentication_key: account.authentication_key.as_ref().map(hex::encode),
+                    sequence_number: account.sequence_number,
+                    status: account.status.clone(),
+                })
+                .collect();
+            serde_json::to_value(accounts)?
+        }
+        Request::GetBalances { account } => {
+            serde_json::to_value(client.get_balances(&["balance", &account])?)?
+        }
+        Request::GetSequenceNumber { account } => {
+            serde_json::to_value(client.get_sequence_number(&["sequence", &account])?)?
+        }
+        Request::GetAccountState { account } => {
+            let (account, version) = client.get_latest_account_state(&["state", &account])?;
+            serde_json::to_value(AccountStateResult { account, version })?
+        }
+        Request::GetTransaction {
+            account,
+            sequence_number,
+            fetch_events,
+        } => serde_json::to_value(client.get_committed_txn_by_acc_seq(&[
+            "txn_acc_seq",
+            &account,
+            &sequence_number.to_string(),
+            &fetch_events.to_string(),
+        ])?)?,
+        Request::GetTransactions {
+            start_version,
+            limit,
+            fetch_events,
+        } => serde_json::to_value(client.get_committed_txn_by_range(&[
+            "txn_range",
+            &start_version.to_string(),
+            &limit.to_string(),
+            &fetch_events.to_string(),
+        ])?)?,
+        Request::GetEvents {
+            account,
+            event_type,
+            start,
+            limit,
+        } => {
+            let (events, _): (Vec<EventView>, _) = client.get_events_by_account_and_type(&[
+                "event",
+                &account,
+                &event_type,
+                &start.to_string(),
+                &limit.to_string(),
+            ])?;
+            serde_json::to_value(events)?
+        }
+        Request::Transfer {
+            sender,
+            receiver,
+            amount,
+            currency,
+        } => {
+            let submitted = client
+                .transfer_coins(&["transfer", &sender, &receiver, &amount, &currency], false)?;
+            let index = match submitted.account_index {
+                AccountEntry::Index(index) => index,
+                AccountEntry::Address(address) => bail!("{} is not a local account", address),
+            };
+            serde_json::to_value(AccountResult {
+                index,
+                address: client.accounts[index].address,
+                sequence_number: Some(submitted.sequence_number),
+            })?
+        }
+        Request::Mint {
+            receiver,
+            amount,
+            currency,
+        } => {
+            client.mint_coins(&["mint", &receiver, &amount, &currency], false)?;
+            Value::Null
+        }
+        Request::WaitForTransaction {
+            sender,
+            sequence_number,
+            timeout_secs,
+        } => {
+            let timeout = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_WAIT_TIMEOUT_SECS));
+            serde_json::to_value(wait_for_transaction(
+                client,
+                sender,
+                sequence_number,
+                timeout,
+            )?)?
+        }
+    };
+    Ok(result)
+}
+
+/// Polls the node until the transaction is committed. Unlike `ClientProxy::wait_for_transaction`,
+/// it does not print progress, and it fails instead of panicking on timeout.
+fn wait_for_transaction(
+    client: &mut ClientProxy,
+    sender: AccountAddress,
+    sequence_number: u64,
+    timeout: Duration,
+) -> Result<TransactionView> {
+    let start = Instant::now();
+    loop {
+        if let Some(txn) = client
+            .client
+            .get_txn_by_acc_seq(sender, sequence_number, true)?
+        {
+            if txn.vm_status != StatusCode::EXECUTED {
+                bail!("transaction failed to execute; status: {:?}", txn.vm_status);
+            }
+            return Ok(txn);
+        }
+        if start.elapsed() >= timeout {
+            bail!(
+                "transaction {}:{} not committed after {:?}",
+                sender,
+                sequence_number,
+                timeout
+            );
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_requests() {
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command": "create_account"}"#).unwrap(),
+            Request::CreateAccount
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(
+                r#"{"command": "get_transaction", "account": "0", "sequence_number": 3}"#
+            )
+            .unwrap(),
+            Request::GetTransaction {
+                account: "0".to_string(),
+                sequence_number: 3,
+                fetch_events: false,
+            }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command": "unknown"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(
+            r#"{"command": "get_balances", "account": "0", "extra": 1}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_response_schema() {
+        let ok = Response::Ok { result: json!(5) };
+        assert_eq!(
+            serde_json::to_value(&ok).unwrap(),
+            json!({"status": "ok", "result": 5})
+        );
+        let error = Response::error(ErrorKind::CommandFailed, "boom".to_string());
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"status": "error", "error": {"kind": "command_failed", "message": "boom"}})
+        );
+    }
+}
@@ -18,12 +18,16 @@ macro_rules! module_name {
 
 // List fuzz target modules here.
 mod compiled_module;
+mod consensus_msg_decode;
 mod consensus_proposal;
 mod inbound_rpc_protocol;
 mod inner_signed_transaction;
 mod json_rpc_service;
+mod mempool_network_messages;
+mod mempool_sync_msg_decode;
 mod network_noise_initiator;
 mod network_noise_responder;
+mod state_sync_msg_decode;
 //mod storage_save_blocks;
 mod storage_schema_decode;
 mod vm_value;
@@ -32,12 +36,16 @@ static ALL_TARGETS: Lazy<BTreeMap<&'static str, Box<dyn FuzzTargetImpl>>> = Lazy
     let targets: Vec<Box<dyn FuzzTargetImpl>> = vec![
         // List fuzz targets here in this format.
         Box::new(compiled_module::CompiledModuleTarget::default()),
+        Box::new(consensus_msg_decode::ConsensusMsgDecode::default()),
         Box::new(consensus_proposal::ConsensusProposal::default()),
         Box::new(inbound_rpc_protocol::RpcInboundRequest::default()),
         Box::new(inner_signed_transaction::SignedTransactionTarget::default()),
         Box::new(json_rpc_service::JsonRpcSubmitTransactionRequest::default()),
+        Box::new(mempool_network_messages::MempoolNetworkMessages::default()),
+        Box::new(mempool_sync_msg_decode::MempoolSyncMsgDecode::default()),
         Box::new(network_noise_initiator::NetworkNoiseInitiator::default()),
         Box::new(network_noise_responder::NetworkNoiseResponder::default()),
+        Box::new(state_sync_msg_decode::StateSyncMsgDecode::default()),
         //        Box::new(storage_save_blocks::StorageSaveBlocks::default()),
         Box::new(storage_schema_decode::StorageSchemaDecode::default()),
         Box::new(vm_value::ValueTarget::default()),
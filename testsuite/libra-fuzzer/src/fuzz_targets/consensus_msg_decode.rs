@@ -0,0 +1,66 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use consensus::{network_interface::ConsensusMsg, round_manager_fuzzing::generate_corpus_proposal};
+use consensus_types::{
+    block_retrieval::{BlockRetrievalRequest, BlockRetrievalResponse, BlockRetrievalStatus},
+    epoch_retrieval::EpochRetrievalRequest,
+    proposal_msg::ProposalMsg,
+};
+use libra_crypto::HashValue;
+use libra_proptest_helpers::ValueGenerator;
+use libra_types::{epoch_change::EpochChangeProof, ledger_info::LedgerInfoWithSignatures};
+use proptest::{collection::vec, prelude::*};
+
+#[derive(Clone, Debug, Default)]
+pub struct ConsensusMsgDecode;
+
+impl FuzzTargetImpl for ConsensusMsgDecode {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "Consensus network messages (LCS deserializer)"
+    }
+
+    fn generate(&self, idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        let proposal: ProposalMsg = lcs::from_bytes(&generate_corpus_proposal())
+            .expect("generated proposal should deserialize");
+        let value = match idx % 6 {
+            0 => ConsensusMsg::ProposalMsg(Box::new(proposal)),
+            1 => ConsensusMsg::SyncInfo(Box::new(proposal.sync_info().clone())),
+            2 => ConsensusMsg::BlockRetrievalResponse(Box::new(BlockRetrievalResponse::new(
+                BlockRetrievalStatus::Succeeded,
+                vec![proposal.take_proposal()],
+            ))),
+            3 => {
+                let (block_id, num_blocks) = gen.generate((any::<[u8; 32]>(), any::<u64>()));
+                ConsensusMsg::BlockRetrievalRequest(Box::new(BlockRetrievalRequest::new(
+                    HashValue::new(block_id),
+                    num_blocks,
+                )))
+            }
+            4 => {
+                let (start_epoch, end_epoch) = gen.generate((any::<u64>(), any::<u64>()));
+                ConsensusMsg::EpochRetrievalRequest(Box::new(EpochRetrievalRequest {
+                    start_epoch,
+                    end_epoch,
+                }))
+            }
+            _ => {
+                let (ledger_infos, more) = gen.generate((
+                    vec(any_with::<LedgerInfoWithSignatures>((0..4).into()), 0..4),
+                    any::<bool>(),
+                ));
+                ConsensusMsg::EpochChangeProof(Box::new(EpochChangeProof::new(ledger_infos, more)))
+            }
+        };
+        Some(lcs::to_bytes(&value).expect("serialization should work"))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        let _: Result<ConsensusMsg, _> = lcs::from_bytes(&data);
+    }
+}
@@ -0,0 +1,27 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use libra_mempool::fuzzing::{fuzzer, generate_corpus};
+use libra_proptest_helpers::ValueGenerator;
+
+#[derive(Clone, Debug, Default)]
+pub struct MempoolNetworkMessages;
+
+impl FuzzTargetImpl for MempoolNetworkMessages {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "Shared mempool network messages received by a full node"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        Some(generate_corpus(gen))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        fuzzer(data);
+    }
+}
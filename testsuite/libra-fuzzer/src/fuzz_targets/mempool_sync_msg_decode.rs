@@ -0,0 +1,28 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use libra_mempool::{fuzzing::arb_mempool_sync_msg, network::MempoolSyncMsg};
+use libra_proptest_helpers::ValueGenerator;
+
+#[derive(Clone, Debug, Default)]
+pub struct MempoolSyncMsgDecode;
+
+impl FuzzTargetImpl for MempoolSyncMsgDecode {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "Shared mempool network messages (LCS deserializer)"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        let value = gen.generate(arb_mempool_sync_msg());
+        Some(lcs::to_bytes(&value).expect("serialization should work"))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        let _: Result<MempoolSyncMsg, _> = lcs::from_bytes(&data);
+    }
+}
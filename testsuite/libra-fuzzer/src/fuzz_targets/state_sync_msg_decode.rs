@@ -0,0 +1,27 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use libra_proptest_helpers::ValueGenerator;
+use state_synchronizer::fuzzing::{fuzz_decode, generate_corpus};
+
+#[derive(Clone, Debug, Default)]
+pub struct StateSyncMsgDecode;
+
+impl FuzzTargetImpl for StateSyncMsgDecode {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "State synchronizer network messages, e.g., chunk responses (LCS deserializer)"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        Some(generate_corpus(gen))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        fuzz_decode(data);
+    }
+}
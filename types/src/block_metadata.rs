@@ -10,6 +10,8 @@ use anyhow::Result;
 use libra_crypto::HashValue;
 use move_core_types::move_resource::MoveResource;
 use once_cell::sync::Lazy;
+#[cfg(any(test, feature = "fuzzing"))]
+use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
 
 /// Struct that will be persisted on chain to store the information of the current block.
@@ -68,6 +70,68 @@ impl BlockMetadata {
     }
 }
 
+/// Metadata of a block whose proposer attached an extension to it. It has a transaction variant
+/// of its own, so that the encoding, and thus the hash, of the blocks without extension is left
+/// unchanged.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "fuzzing"), derive(Arbitrary))]
+pub struct BlockMetadataWithExtension {
+    metadata: BlockMetadata,
+    extension: BlockMetadataExtension,
+}
+
+impl BlockMetadataWithExtension {
+    pub fn new(metadata: BlockMetadata, extension: BlockMetadataExtension) -> Self {
+        Self {
+            metadata,
+            extension,
+        }
+    }
+
+    pub fn metadata(&self) -> &BlockMetadata {
+        &self.metadata
+    }
+
+    pub fn extension(&self) -> &BlockMetadataExtension {
+        &self.extension
+    }
+
+    pub fn into_inner(self) -> (BlockMetadata, BlockMetadataExtension) {
+        (self.metadata, self.extension)
+    }
+}
+
+/// Small, versioned blob attached to a block by its proposer, e.g. to record the source of its
+/// timestamp, the software version of the proposer or data for an oracle.
+///
+/// The extension is part of the block signed by the proposer, and validators only vote for blocks
+/// whose extension is allowed by the on-chain `BlockMetadataExtensionConfig`. Its interpretation
+/// is left to the Move code reading it from `LibraBlockExtension` after the block prologue.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "fuzzing"), derive(Arbitrary))]
+pub struct BlockMetadataExtension {
+    version: u8,
+    data: Vec<u8>,
+}
+
+impl BlockMetadataExtension {
+    pub fn new(version: u8, data: Vec<u8>) -> Self {
+        Self { version, data }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
 pub fn new_block_event_key() -> EventKey {
     EventKey::new_from_address(&association_address(), 16)
 }
@@ -0,0 +1,132 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{ensure, format_err, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// Well-known chains. Any other chain is identified by its numeric id only.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum NamedChain {
+    MAINNET = 1,
+    TESTNET = 2,
+    DEVNET = 3,
+    TESTING = 4,
+}
+
+impl NamedChain {
+    fn from_id(id: u8) -> Option<NamedChain> {
+        match id {
+            1 => Some(NamedChain::MAINNET),
+            2 => Some(NamedChain::TESTNET),
+            3 => Some(NamedChain::DEVNET),
+            4 => Some(NamedChain::TESTING),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            NamedChain::MAINNET => "mainnet",
+            NamedChain::TESTNET => "testnet",
+            NamedChain::DEVNET => "devnet",
+            NamedChain::TESTING => "testing",
+        }
+    }
+}
+
+/// Identifies the chain a node belongs to. Serialized as its name for well-known chains, and as
+/// its numeric id otherwise.
+#[derive(Clone, Copy, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ChainId(u8);
+
+impl ChainId {
+    pub fn new(id: u8) -> Self {
+        ChainId(id)
+    }
+
+    pub fn id(self) -> u8 {
+        self.0
+    }
+
+    pub fn mainnet() -> Self {
+        ChainId(NamedChain::MAINNET as u8)
+    }
+
+    pub fn test() -> Self {
+        ChainId(NamedChain::TESTING as u8)
+    }
+
+    pub fn is_mainnet(self) -> bool {
+        self.0 == NamedChain::MAINNET as u8
+    }
+}
+
+impl fmt::Debug for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match NamedChain::from_id(self.0) {
+            Some(chain) => write!(f, "{}", chain.name()),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for ChainId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let named = [
+            NamedChain::MAINNET,
+            NamedChain::TESTNET,
+            NamedChain::DEVNET,
+            NamedChain::TESTING,
+        ];
+        if let Some(chain) = named.iter().find(|chain| chain.name() == s.to_lowercase()) {
+            return Ok(ChainId(*chain as u8));
+        }
+        let id = s
+            .parse::<u8>()
+            .map_err(|_| format_err!("Invalid chain id: {}", s))?;
+        ensure!(id > 0, "Chain id 0 is reserved");
+        Ok(ChainId(id))
+    }
+}
+
+impl TryFrom<String> for ChainId {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        ChainId::from_str(&s)
+    }
+}
+
+impl From<ChainId> for String {
+    fn from(chain_id: ChainId) -> Self {
+        chain_id.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(ChainId::from_str("mainnet").unwrap(), ChainId::mainnet());
+        assert_eq!(ChainId::from_str("1").unwrap(), ChainId::mainnet());
+        assert!(ChainId::from_str("1").unwrap().is_mainnet());
+        assert_eq!(ChainId::from_str("42").unwrap(), ChainId::new(42));
+        assert!(ChainId::from_str("0").is_err());
+        assert!(ChainId::from_str("moon").is_err());
+        assert_eq!(ChainId::test().to_string(), "testing");
+        assert_eq!(ChainId::new(42).to_string(), "42");
+    }
+}
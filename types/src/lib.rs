@@ -10,6 +10,7 @@ pub mod account_state;
 pub mod account_state_blob;
 pub mod block_info;
 pub mod block_metadata;
+pub mod chain_id;
 pub mod contract_event;
 pub mod epoch_change;
 pub mod epoch_state;
@@ -0,0 +1,61 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{block_metadata::BlockMetadataExtension, on_chain_config::OnChainConfig};
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+/// Enables the metadata extension of blocks, and bounds what it may contain.
+/// Chains that never published this config don't accept any extension.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BlockMetadataExtensionConfig {
+    pub max_size: u64,
+    pub supported_versions: Vec<u8>,
+}
+
+impl BlockMetadataExtensionConfig {
+    /// Checks that `extension` may be included in a block.
+    pub fn verify(&self, extension: &BlockMetadataExtension) -> Result<()> {
+        ensure!(
+            self.supported_versions.contains(&extension.version()),
+            "Unsupported block metadata extension version {}",
+            extension.version()
+        );
+        ensure!(
+            extension.data().len() as u64 <= self.max_size,
+            "Block metadata extension of {} bytes exceeds the maximum size of {} bytes",
+            extension.data().len(),
+            self.max_size
+        );
+        Ok(())
+    }
+}
+
+impl OnChainConfig for BlockMetadataExtensionConfig {
+    const IDENTIFIER: &'static str = "LibraBlockExtension";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let config = BlockMetadataExtensionConfig {
+            max_size: 4,
+            supported_versions: vec![1, 3],
+        };
+        assert!(config
+            .verify(&BlockMetadataExtension::new(1, vec![0; 4]))
+            .is_ok());
+        assert!(config
+            .verify(&BlockMetadataExtension::new(3, vec![]))
+            .is_ok());
+        assert!(config
+            .verify(&BlockMetadataExtension::new(2, vec![]))
+            .is_err());
+        assert!(config
+            .verify(&BlockMetadataExtension::new(1, vec![0; 5]))
+            .is_err());
+    }
+}
@@ -16,21 +16,28 @@ use move_core_types::{
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
 
+mod block_metadata_extension_config;
 mod libra_version;
 mod registered_currencies;
+mod validator_connectivity_config;
 mod validator_set;
 mod vm_config;
 
 pub use self::{
+    block_metadata_extension_config::BlockMetadataExtensionConfig,
     libra_version::LibraVersion,
     registered_currencies::RegisteredCurrencies,
+    validator_connectivity_config::{
+        ValidatorConnectivityConfig, ValidatorConnectivityPreferences,
+    },
     validator_set::ValidatorSet,
     vm_config::{VMConfig, VMPublishingOption},
 };
 
 /// To register an on-chain config in Rust:
 /// 1. Implement the `OnChainConfig` trait for the Rust representation of the config
-/// 2. Add the config's `ConfigID` to `ON_CHAIN_CONFIG_REGISTRY`
+/// 2. Add the config's `ConfigID` to `ON_CHAIN_CONFIG_REGISTRY`, or to
+///    `OPTIONAL_ON_CHAIN_CONFIG_REGISTRY` if the config may not be published on every chain
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub struct ConfigID(&'static str, &'static str);
@@ -56,6 +63,12 @@ pub const ON_CHAIN_CONFIG_REGISTRY: &[ConfigID] = &[
     RegisteredCurrencies::CONFIG_ID,
 ];
 
+/// Configs that are only part of the on-chain config payload once published
+pub const OPTIONAL_ON_CHAIN_CONFIG_REGISTRY: &[ConfigID] = &[
+    BlockMetadataExtensionConfig::CONFIG_ID,
+    ValidatorConnectivityConfig::CONFIG_ID,
+];
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct OnChainConfigPayload {
     epoch: u64,
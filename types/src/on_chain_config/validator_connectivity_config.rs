@@ -0,0 +1,40 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{account_address::AccountAddress, on_chain_config::OnChainConfig};
+use serde::{Deserialize, Serialize};
+
+/// Preferences of the operator of a validator for the connections of the validator to the other
+/// validators.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ValidatorConnectivityPreferences {
+    pub validator: AccountAddress,
+    pub region: Vec<u8>,
+    /// Regions of the validators dialed first, the most preferred first.
+    pub preferred_regions: Vec<Vec<u8>>,
+    /// Number of connections beyond which no more validators are dialed, 0 for no limit.
+    pub max_connections: u64,
+}
+
+/// Connectivity preferences published by the operators of the validators.
+/// Chains that never published this config have none.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ValidatorConnectivityConfig {
+    pub preferences: Vec<ValidatorConnectivityPreferences>,
+}
+
+impl ValidatorConnectivityConfig {
+    /// Preferences of `validator`, if its operator published any.
+    pub fn preferences_of(
+        &self,
+        validator: &AccountAddress,
+    ) -> Option<&ValidatorConnectivityPreferences> {
+        self.preferences
+            .iter()
+            .find(|preferences| &preferences.validator == validator)
+    }
+}
+
+impl OnChainConfig for ValidatorConnectivityConfig {
+    const IDENTIFIER: &'static str = "ValidatorConnectivity";
+}
@@ -23,6 +23,7 @@ use libra_crypto::{
     },
     HashValue, PrivateKey, Uniform,
 };
+use std::sync::Arc;
 
 #[test]
 fn test_verify_empty_accumulator() {
@@ -347,7 +348,7 @@ fn test_verify_account_state_and_event() {
 
     let privkey = Ed25519PrivateKey::generate_for_testing();
     let pubkey = privkey.public_key();
-    let txn2_hash = Transaction::UserTransaction(
+    let txn2_hash = Transaction::UserTransaction(Arc::new(
         RawTransaction::new_script(
             crate::account_address::from_public_key(&pubkey),
             /* sequence_number = */ 0,
@@ -360,7 +361,7 @@ fn test_verify_account_state_and_event() {
         .sign(&privkey, pubkey)
         .expect("Signing failed.")
         .into_inner(),
-    )
+    ))
     .hash();
 
     let event0_hash = b"event0".test_only_hash();
@@ -41,7 +41,7 @@ use proptest::{
     prelude::*,
 };
 use proptest_derive::Arbitrary;
-use std::{convert::TryFrom, iter::Iterator, time::Duration};
+use std::{convert::TryFrom, iter::Iterator, sync::Arc, time::Duration};
 
 impl WriteOp {
     pub fn value_strategy() -> impl Strategy<Value = Self> {
@@ -513,6 +513,8 @@ impl Arbitrary for StatusCode {
             Just(StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS),
             Just(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND),
             Just(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND),
+            Just(StatusCode::GAS_PAYER_ACCOUNT_DOES_NOT_EXIST),
+            Just(StatusCode::GAS_PAYER_ACCOUNT_FROZEN),
         ]
         .boxed()
     }
@@ -826,7 +828,7 @@ impl TransactionToCommitGen {
             .collect();
 
         TransactionToCommit::new(
-            Transaction::UserTransaction(transaction),
+            Transaction::UserTransaction(Arc::new(transaction)),
             account_states,
             events,
             self.gas_used,
@@ -900,7 +902,7 @@ fn arb_transaction_list_with_proof() -> impl Strategy<Value = TransactionListWit
             let transactions: Vec<_> = transaction_and_events
                 .clone()
                 .into_iter()
-                .map(|(transaction, _event)| Transaction::UserTransaction(transaction))
+                .map(|(transaction, _event)| Transaction::UserTransaction(Arc::new(transaction)))
                 .collect();
             let events: Vec<_> = transaction_and_events
                 .into_iter()
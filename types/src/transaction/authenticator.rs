@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::account_address::AccountAddress;
-use anyhow::{Error, Result};
+use anyhow::{ensure, Error, Result};
 use libra_crypto::{
     ed25519::{Ed25519PublicKey, Ed25519Signature},
     multi_ed25519::{MultiEd25519PublicKey, MultiEd25519Signature},
@@ -59,6 +59,14 @@ pub enum TransactionAuthenticator {
         public_key: MultiEd25519PublicKey,
         signature: MultiEd25519Signature,
     },
+    /// Authenticator of a transaction whose gas is paid by `gas_payer` instead of its sender.
+    /// Both the sender and the gas payer sign the transaction along with the gas payer address,
+    /// see [`SponsoredRawTransaction`](crate::transaction::SponsoredRawTransaction).
+    Sponsored {
+        sender: Box<TransactionAuthenticator>,
+        gas_payer: AccountAddress,
+        gas_payer_authenticator: Box<TransactionAuthenticator>,
+    },
     // ... add more schemes here
 }
 
@@ -68,6 +76,7 @@ impl TransactionAuthenticator {
         match self {
             Self::Ed25519 { .. } => Scheme::Ed25519,
             Self::MultiEd25519 { .. } => Scheme::MultiEd25519,
+            Self::Sponsored { sender, .. } => sender.scheme(),
         }
     }
 
@@ -90,6 +99,31 @@ impl TransactionAuthenticator {
         }
     }
 
+    /// Create the authenticator of a transaction whose gas is paid by `gas_payer`
+    pub fn sponsored(
+        sender: TransactionAuthenticator,
+        gas_payer: AccountAddress,
+        gas_payer_authenticator: TransactionAuthenticator,
+    ) -> Self {
+        Self::Sponsored {
+            sender: Box::new(sender),
+            gas_payer,
+            gas_payer_authenticator: Box::new(gas_payer_authenticator),
+        }
+    }
+
+    /// Return the address and the authenticator of the gas payer, if the transaction is sponsored
+    pub fn gas_payer(&self) -> Option<(AccountAddress, &TransactionAuthenticator)> {
+        match self {
+            Self::Sponsored {
+                gas_payer,
+                gas_payer_authenticator,
+                ..
+            } => Some((*gas_payer, gas_payer_authenticator)),
+            _ => None,
+        }
+    }
+
     /// Return Ok if the authenticator's public key matches its signature, Err otherwise
     pub fn verify_signature(&self, message: &HashValue) -> Result<()> {
         match self {
@@ -101,22 +135,38 @@ impl TransactionAuthenticator {
                 public_key,
                 signature,
             } => signature.verify(message, public_key),
+            Self::Sponsored {
+                sender,
+                gas_payer_authenticator,
+                ..
+            } => {
+                ensure!(
+                    sender.gas_payer().is_none() && gas_payer_authenticator.gas_payer().is_none(),
+                    "Sponsored authenticators cannot be nested"
+                );
+                sender.verify_signature(message)?;
+                gas_payer_authenticator.verify_signature(message)
+            }
         }
     }
 
-    /// Return the raw bytes of `self.public_key`
+    /// Return the raw bytes of `self.public_key`, i.e., of the sender's public key for sponsored
+    /// transactions
     pub fn public_key_bytes(&self) -> Vec<u8> {
         match self {
             Self::Ed25519 { public_key, .. } => public_key.to_bytes().to_vec(),
             Self::MultiEd25519 { public_key, .. } => public_key.to_bytes().to_vec(),
+            Self::Sponsored { sender, .. } => sender.public_key_bytes(),
         }
     }
 
-    /// Return the raw bytes of `self.signature`
+    /// Return the raw bytes of `self.signature`, i.e., of the sender's signature for sponsored
+    /// transactions
     pub fn signature_bytes(&self) -> Vec<u8> {
         match self {
             Self::Ed25519 { signature, .. } => signature.to_bytes().to_vec(),
             Self::MultiEd25519 { signature, .. } => signature.to_bytes().to_vec(),
+            Self::Sponsored { sender, .. } => sender.signature_bytes(),
         }
     }
 
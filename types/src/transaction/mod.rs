@@ -5,7 +5,7 @@ use crate::{
     account_address::AccountAddress,
     account_config::LBR_NAME,
     account_state_blob::AccountStateBlob,
-    block_metadata::BlockMetadata,
+    block_metadata::{BlockMetadata, BlockMetadataWithExtension},
     contract_event::ContractEvent,
     ledger_info::LedgerInfo,
     proof::{accumulator::InMemoryAccumulator, TransactionInfoWithProof, TransactionListProof},
@@ -30,6 +30,7 @@ use std::{
     convert::TryFrom,
     fmt,
     fmt::{Display, Formatter},
+    sync::Arc,
     time::Duration,
 };
 
@@ -38,11 +39,13 @@ mod change_set;
 pub mod helpers;
 mod module;
 mod script;
+mod shared_store;
 mod transaction_argument;
 
 pub use change_set::ChangeSet;
 pub use module::Module;
 pub use script::{ArgumentABI, Script, ScriptABI, TypeArgumentABI, SCRIPT_HASH_LENGTH};
+pub use shared_store::SharedTransactionStore;
 
 use std::ops::Deref;
 pub use transaction_argument::{parse_transaction_argument, TransactionArgument};
@@ -296,6 +299,39 @@ impl RawTransaction {
     pub fn sender(&self) -> AccountAddress {
         self.sender
     }
+
+    /// Return the sequence number of this transaction.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+}
+
+/// The message signed by both the sender and the gas payer of a sponsored transaction, i.e., of a
+/// transaction whose gas is paid by `gas_payer` instead of its sender. Signing the gas payer
+/// along with the raw transaction prevents the signature of the sender from being replayed with
+/// another gas payer.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize, CryptoHasher, LCSCryptoHash)]
+pub struct SponsoredRawTransaction {
+    raw_txn: RawTransaction,
+    gas_payer: AccountAddress,
+}
+
+impl SponsoredRawTransaction {
+    pub fn new(raw_txn: RawTransaction, gas_payer: AccountAddress) -> Self {
+        Self { raw_txn, gas_payer }
+    }
+
+    pub fn raw_txn(&self) -> &RawTransaction {
+        &self.raw_txn
+    }
+
+    pub fn gas_payer(&self) -> AccountAddress {
+        self.gas_payer
+    }
+
+    pub fn into_raw_transaction(self) -> RawTransaction {
+        self.raw_txn
+    }
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
@@ -391,6 +427,25 @@ impl SignedTransaction {
         }
     }
 
+    /// Creates a transaction whose gas is paid by `gas_payer`. Both authenticators must sign the
+    /// hash of the corresponding [`SponsoredRawTransaction`].
+    pub fn new_sponsored(
+        raw_txn: RawTransaction,
+        sender_authenticator: TransactionAuthenticator,
+        gas_payer: AccountAddress,
+        gas_payer_authenticator: TransactionAuthenticator,
+    ) -> SignedTransaction {
+        let authenticator = TransactionAuthenticator::sponsored(
+            sender_authenticator,
+            gas_payer,
+            gas_payer_authenticator,
+        );
+        SignedTransaction {
+            raw_txn,
+            authenticator,
+        }
+    }
+
     pub fn authenticator(&self) -> TransactionAuthenticator {
         self.authenticator.clone()
     }
@@ -399,6 +454,19 @@ impl SignedTransaction {
         self.raw_txn.sender
     }
 
+    /// Returns the account paying the gas of this transaction, i.e., its sender unless it is
+    /// sponsored.
+    pub fn gas_payer(&self) -> AccountAddress {
+        match self.authenticator.gas_payer() {
+            Some((gas_payer, _)) => gas_payer,
+            None => self.raw_txn.sender,
+        }
+    }
+
+    pub fn is_sponsored(&self) -> bool {
+        self.authenticator.gas_payer().is_some()
+    }
+
     pub fn into_raw_transaction(self) -> RawTransaction {
         self.raw_txn
     }
@@ -436,7 +504,13 @@ impl SignedTransaction {
     /// Checks that the signature of given transaction. Returns `Ok(SignatureCheckedTransaction)` if
     /// the signature is valid.
     pub fn check_signature(self) -> Result<SignatureCheckedTransaction> {
-        self.authenticator.verify_signature(&self.raw_txn.hash())?;
+        let message = match self.authenticator.gas_payer() {
+            Some((gas_payer, _)) => {
+                SponsoredRawTransaction::new(self.raw_txn.clone(), gas_payer).hash()
+            }
+            None => self.raw_txn.hash(),
+        };
+        self.authenticator.verify_signature(&message)?;
         Ok(SignatureCheckedTransaction(self))
     }
 
@@ -916,7 +990,9 @@ pub enum Transaction {
     /// transaction, etc.
     /// TODO: We need to rename SignedTransaction to SignedUserTransaction, as well as all the other
     ///       transaction types we had in our codebase.
-    UserTransaction(SignedTransaction),
+    /// The transaction is the copy shared with mempool and consensus, see
+    /// [`SharedTransactionStore`].
+    UserTransaction(Arc<SignedTransaction>),
 
     /// Transaction that applies a WriteSet to the current storage. This should be used for ONLY for
     /// genesis right now.
@@ -924,12 +1000,16 @@ pub enum Transaction {
 
     /// Transaction to update the block metadata resource at the beginning of a block.
     BlockMetadata(BlockMetadata),
+
+    /// Transaction to update the block metadata resource at the beginning of a block, for the
+    /// blocks whose proposer attached a metadata extension.
+    BlockMetadataWithExtension(BlockMetadataWithExtension),
 }
 
 impl Transaction {
     pub fn as_signed_user_txn(&self) -> Result<&SignedTransaction> {
         match self {
-            Transaction::UserTransaction(txn) => Ok(txn),
+            Transaction::UserTransaction(txn) => Ok(txn.as_ref()),
             _ => Err(format_err!("Not a user transaction.")),
         }
     }
@@ -943,6 +1023,10 @@ impl Transaction {
             Transaction::WaypointWriteSet(_write_set) => String::from("genesis"),
             // TODO: display proper information for client
             Transaction::BlockMetadata(_block_metadata) => String::from("block_metadata"),
+            // TODO: display proper information for client
+            Transaction::BlockMetadataWithExtension(_block_metadata) => {
+                String::from("block_metadata_with_extension")
+            }
         }
     }
 }
@@ -952,7 +1036,9 @@ impl TryFrom<Transaction> for SignedTransaction {
 
     fn try_from(txn: Transaction) -> Result<Self> {
         match txn {
-            Transaction::UserTransaction(txn) => Ok(txn),
+            Transaction::UserTransaction(txn) => {
+                Ok(Arc::try_unwrap(txn).unwrap_or_else(|txn| txn.as_ref().clone()))
+            }
             _ => Err(format_err!("Not a user transaction.")),
         }
     }
@@ -0,0 +1,153 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Store of the user transactions shared by mempool, consensus and execution, so that a
+//! transaction is held in memory once, however many blocks and components refer to it.
+//!
+//! The store maps the sender and sequence number of the transactions to weak references: a
+//! transaction lives as long as a component holds it, e.g., until mempool removes it and the blocks
+//! proposing it are pruned, and the entries of the dropped transactions are pruned lazily as new
+//! transactions are interned. Interning compares the transactions of the same sender and sequence
+//! number rather than hashing them, and the entries are spread over shards locked independently so
+//! that mempool and the deserialization of the consensus payloads don't contend on a single lock.
+
+use crate::{account_address::AccountAddress, transaction::SignedTransaction};
+use once_cell::sync::Lazy;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, Weak},
+};
+
+/// Number of shards of the store.
+const NUM_SHARDS: usize = 32;
+
+/// Number of entries of a shard below which its dropped transactions are never pruned.
+const MIN_PRUNE_THRESHOLD: usize = 64;
+
+static GLOBAL_STORE: Lazy<SharedTransactionStore> = Lazy::new(SharedTransactionStore::new);
+
+/// The transactions of a shard. Different transactions of a sender may share a sequence number,
+/// e.g., when a transaction is resubmitted with a higher gas price.
+#[derive(Debug)]
+struct Transactions {
+    by_sequence_number: HashMap<(AccountAddress, u64), Vec<Weak<SignedTransaction>>>,
+    /// Number of entries beyond which the entries of the dropped transactions are pruned.
+    prune_threshold: usize,
+}
+
+impl Transactions {
+    fn new() -> Self {
+        Self {
+            by_sequence_number: HashMap::new(),
+            prune_threshold: MIN_PRUNE_THRESHOLD,
+        }
+    }
+
+    fn get(&self, txn: &SignedTransaction) -> Option<Arc<SignedTransaction>> {
+        self.by_sequence_number
+            .get(&(txn.sender(), txn.sequence_number()))?
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|shared| **shared == *txn)
+    }
+
+    fn insert(&mut self, txn: &Arc<SignedTransaction>) {
+        let copies = self
+            .by_sequence_number
+            .entry((txn.sender(), txn.sequence_number()))
+            .or_insert_with(Vec::new);
+        copies.retain(|copy| copy.strong_count() > 0);
+        copies.push(Arc::downgrade(txn));
+        if self.by_sequence_number.len() >= self.prune_threshold {
+            self.by_sequence_number.retain(|_, copies| {
+                copies.retain(|copy| copy.strong_count() > 0);
+                !copies.is_empty()
+            });
+            self.prune_threshold =
+                std::cmp::max(2 * self.by_sequence_number.len(), MIN_PRUNE_THRESHOLD);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.by_sequence_number
+            .values()
+            .flatten()
+            .filter(|txn| txn.strong_count() > 0)
+            .count()
+    }
+}
+
+/// Deduplicates the copies of the user transactions into shared `Arc`s.
+#[derive(Clone, Debug)]
+pub struct SharedTransactionStore {
+    shards: Arc<Vec<Mutex<Transactions>>>,
+}
+
+impl SharedTransactionStore {
+    pub fn new() -> Self {
+        Self {
+            shards: Arc::new(
+                (0..NUM_SHARDS)
+                    .map(|_| Mutex::new(Transactions::new()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The store shared by all the components of the node.
+    pub fn global() -> &'static Self {
+        &GLOBAL_STORE
+    }
+
+    fn shard(&self, txn: &SignedTransaction) -> &Mutex<Transactions> {
+        let mut hasher = DefaultHasher::new();
+        (txn.sender(), txn.sequence_number()).hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % NUM_SHARDS]
+    }
+
+    /// Returns the shared copy of `txn`, which becomes the shared copy if there is none yet.
+    pub fn intern(&self, txn: SignedTransaction) -> Arc<SignedTransaction> {
+        let mut transactions = self.shard(&txn).lock().unwrap();
+        if let Some(shared) = transactions.get(&txn) {
+            return shared;
+        }
+        let txn = Arc::new(txn);
+        transactions.insert(&txn);
+        txn
+    }
+
+    /// Returns the shared copy of `txn`, which becomes the shared copy if there is none yet, in
+    /// which case `txn` is dropped.
+    pub fn intern_arc(&self, txn: Arc<SignedTransaction>) -> Arc<SignedTransaction> {
+        let mut transactions = self.shard(&txn).lock().unwrap();
+        if let Some(shared) = transactions.get(&txn) {
+            return shared;
+        }
+        transactions.insert(&txn);
+        txn
+    }
+
+    /// Returns the shared copy of `txn`, if any.
+    pub fn get(&self, txn: &SignedTransaction) -> Option<Arc<SignedTransaction>> {
+        self.shard(txn).lock().unwrap().get(txn)
+    }
+
+    /// Number of transactions currently shared.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for SharedTransactionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
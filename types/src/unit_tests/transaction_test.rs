@@ -4,9 +4,10 @@
 use crate::{
     account_address::AccountAddress,
     account_config::LBR_NAME,
+    test_helpers::transaction_test_helpers::get_test_signed_transaction,
     transaction::{
-        RawTransaction, Script, SignedTransaction, Transaction, TransactionInfo,
-        TransactionListWithProof, TransactionPayload, TransactionWithProof,
+        RawTransaction, Script, SharedTransactionStore, SignedTransaction, Transaction,
+        TransactionInfo, TransactionListWithProof, TransactionPayload, TransactionWithProof,
     },
 };
 use lcs::test_helpers::assert_canonical_encode_decode;
@@ -15,7 +16,35 @@ use libra_crypto::{
     PrivateKey, Uniform,
 };
 use proptest::prelude::*;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, sync::Arc};
+
+#[test]
+fn shared_transaction_store_resubmission() {
+    let private_key = Ed25519PrivateKey::generate_for_testing();
+    let sender = AccountAddress::random();
+    let txn = |gas_unit_price| {
+        get_test_signed_transaction(
+            sender,
+            0,
+            &private_key,
+            private_key.public_key(),
+            None,
+            0,
+            gas_unit_price,
+            LBR_NAME.to_owned(),
+            None,
+        )
+    };
+    let store = SharedTransactionStore::new();
+    let shared = store.intern(txn(0));
+    // a transaction resubmitted with another gas price is not the same transaction
+    let resubmitted = store.intern(txn(1));
+    assert!(!Arc::ptr_eq(&shared, &resubmitted));
+    assert_eq!(*resubmitted, txn(1));
+    assert!(Arc::ptr_eq(&shared, &store.intern(txn(0))));
+    assert!(Arc::ptr_eq(&resubmitted, &store.intern(txn(1))));
+    assert_eq!(store.len(), 2);
+}
 
 #[test]
 fn test_invalid_signature() {
@@ -59,6 +88,36 @@ proptest! {
         assert_canonical_encode_decode(signed_txn);
     }
 
+    #[test]
+    fn user_transaction_lcs_roundtrip(signed_txn in any::<SignedTransaction>()) {
+        // the shared copy of a user transaction is encoded like the transaction itself
+        let mut bytes = vec![0];
+        bytes.extend(lcs::to_bytes(&signed_txn).unwrap());
+        let txn = Transaction::UserTransaction(Arc::new(signed_txn));
+        assert_eq!(lcs::to_bytes(&txn).unwrap(), bytes);
+        assert_canonical_encode_decode(txn);
+    }
+
+    #[test]
+    fn shared_transaction_store(txn1 in any::<SignedTransaction>(), txn2 in any::<SignedTransaction>()) {
+        prop_assume!(txn1 != txn2);
+        let store = SharedTransactionStore::new();
+        let shared1 = store.intern(txn1.clone());
+        let shared2 = store.intern(txn2.clone());
+        // the copies of a transaction share the first copy interned
+        assert!(Arc::ptr_eq(&shared1, &store.intern(txn1.clone())));
+        assert!(Arc::ptr_eq(&shared1, &store.intern_arc(Arc::new(txn1.clone()))));
+        assert!(Arc::ptr_eq(&shared1, &store.get(&txn1).unwrap()));
+        assert!(!Arc::ptr_eq(&shared1, &shared2));
+        assert_eq!(store.len(), 2);
+
+        // dropped transactions are no longer shared
+        drop(shared1);
+        assert!(store.get(&txn1).is_none());
+        assert_eq!(store.len(), 1);
+        assert_eq!(*store.intern(txn1.clone()), txn1);
+    }
+
     #[test]
     fn transaction_info_lcs_roundtrip(txn_info in any::<TransactionInfo>()) {
         assert_canonical_encode_decode(txn_info);
@@ -255,6 +255,10 @@ pub enum StatusCode {
     UNABLE_TO_DESERIALIZE_ACCOUNT = 19,
     // The currency info was unable to be found
     CURRENCY_INFO_DOES_NOT_EXIST = 20,
+    // The account paying the gas of a sponsored transaction does not exist
+    GAS_PAYER_ACCOUNT_DOES_NOT_EXIST = 21,
+    // The account paying the gas of a sponsored transaction is frozen
+    GAS_PAYER_ACCOUNT_FROZEN = 22,
 
     // When a code module/script is published it is verified. These are the
     // possible errors that can arise from the verification process.
@@ -496,6 +500,8 @@ impl TryFrom<u64> for StatusCode {
             14 => Ok(StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS),
             15 => Ok(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND),
             16 => Ok(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND),
+            21 => Ok(StatusCode::GAS_PAYER_ACCOUNT_DOES_NOT_EXIST),
+            22 => Ok(StatusCode::GAS_PAYER_ACCOUNT_FROZEN),
             1000 => Ok(StatusCode::UNKNOWN_VERIFICATION_ERROR),
             1001 => Ok(StatusCode::INDEX_OUT_OF_BOUNDS),
             1002 => Ok(StatusCode::RANGE_OUT_OF_BOUNDS),